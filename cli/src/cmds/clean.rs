@@ -0,0 +1,67 @@
+//! Removal of generated files
+//!
+//! Removes the files recorded as generated into the output profiles of the
+//! nearest `ggen.toml`, leaving hand-written files in the same directories
+//! alone; see [`ggen_core::output`].
+//!
+//! # Examples
+//!
+//! ```bash
+//! ggen clean --target sql
+//! ggen clean --dry-run
+//! ```
+
+use clap::Args;
+use ggen_core::config::GgenConfig;
+use ggen_core::output::OutputProfiles;
+use ggen_utils::error::Result;
+
+#[derive(Args, Debug)]
+pub struct CleanArgs {
+    /// Output profile to clean; every profile when omitted
+    #[arg(short, long)]
+    pub target: Option<String>,
+
+    /// List the files that would be removed without removing them
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+pub async fn run(args: &CleanArgs) -> Result<()> {
+    let cwd = std::env::current_dir()?;
+    let (config, config_path) = GgenConfig::discover_and_load(&cwd)?.ok_or_else(|| {
+        ggen_utils::error::Error::new("No ggen.toml found in this directory or its parents")
+    })?;
+    let config_dir = config_path.parent().unwrap_or(&cwd);
+    let profiles = OutputProfiles::from_config(&config, config_dir)?;
+
+    let removed = if args.dry_run {
+        let targets = match &args.target {
+            Some(target) => vec![target.as_str()],
+            None => profiles.names(),
+        };
+        let mut files = Vec::new();
+        for target in targets {
+            files.extend(
+                profiles
+                    .tracked(target)?
+                    .into_iter()
+                    .filter(|file| file.exists()),
+            );
+        }
+        files
+    } else {
+        profiles.clean(args.target.as_deref())?
+    };
+
+    for file in &removed {
+        println!("  {}", file.display());
+    }
+    let verb = if args.dry_run {
+        "Would remove"
+    } else {
+        "Removed"
+    };
+    println!("✓ {} {} generated files", verb, removed.len());
+    Ok(())
+}
@@ -20,8 +20,11 @@
 //! fails due to malformed data.
 
 use clap::Args;
+use ggen_core::graph::{rdf_format, LoadMode};
 use ggen_utils::error::Result;
+use indicatif::{ProgressBar, ProgressStyle};
 use std::path::{Component, Path};
+use std::time::Duration;
 
 #[derive(Args, Debug)]
 pub struct LoadArgs {
@@ -199,9 +202,33 @@ pub async fn run(args: &LoadArgs) -> Result<()> {
         println!("🌐 Base IRI: {}", base);
     }
 
-    // Load the RDF file using ggen-core
-    let graph = ggen_core::Graph::load_from_file(&args.file)
-        .map_err(|e| ggen_utils::error::Error::new(&format!("Failed to load RDF file: {}", e)))?;
+    // Load the RDF file using ggen-core, streaming it with a progress spinner
+    let progress = ProgressBar::new_spinner();
+    progress.set_style(
+        ProgressStyle::with_template("{spinner} {msg}")
+            .unwrap_or_else(|_| ProgressStyle::default_spinner()),
+    );
+    progress.enable_steady_tick(Duration::from_millis(100));
+    let graph = ggen_core::Graph::new()
+        .and_then(|graph| {
+            graph.load_file(
+                file_path,
+                rdf_format(file_path)?,
+                "",
+                LoadMode::from_env(),
+                &mut |p| {
+                    progress.set_message(format!(
+                        "{} triples in {:.1}s",
+                        p.triples,
+                        p.elapsed.as_secs_f64()
+                    ))
+                },
+            )?;
+            Ok(graph)
+        })
+        .map_err(|e| ggen_utils::error::Error::new(&format!("Failed to load RDF file: {}", e)));
+    progress.finish_and_clear();
+    let graph = graph?;
 
     // Get graph statistics
     let triples_count = graph.len();
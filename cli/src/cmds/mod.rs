@@ -2,6 +2,7 @@
 pub mod ai;
 pub mod audit;
 pub mod ci;
+pub mod clean;
 pub mod doctor;
 pub mod graph;
 pub mod help_progressive;
@@ -9,6 +10,7 @@ pub mod hook;
 pub mod lifecycle;
 pub mod market;
 pub mod project;
+pub mod selftest;
 pub mod shell;
 pub mod template;
 
@@ -26,6 +28,12 @@ pub enum Commands {
     #[command(name = "ci", about = "CI/CD operations and GitHub integration")]
     Ci(ci::CiCmd),
 
+    #[command(
+        name = "clean",
+        about = "Remove generated files of the ggen.toml output profiles"
+    )]
+    Clean(clean::CleanArgs),
+
     #[command(
         name = "doctor",
         about = "Check system prerequisites and environment health"
@@ -56,6 +64,12 @@ pub enum Commands {
     #[command(name = "project", about = "Project scaffolding and generation")]
     Project(project::ProjectCmd),
 
+    #[command(
+        name = "selftest",
+        about = "Check config, ontology, templates and providers offline"
+    )]
+    Selftest(selftest::SelftestArgs),
+
     #[command(name = "shell", about = "Shell integration and completion")]
     Shell(shell::ShellCmd),
 
@@ -74,6 +88,7 @@ impl Commands {
             Commands::Ai(args) => ai::run(args).await,
             Commands::Audit(cmd) => cmd.run().await,
             Commands::Ci(cmd) => cmd.run().await,
+            Commands::Clean(args) => clean::run(args).await,
             Commands::Doctor(args) => doctor::run(args).await,
             Commands::Graph(cmd) => cmd.run().await,
             Commands::HelpProgressive(args) => help_progressive::run(args).await,
@@ -81,6 +96,7 @@ impl Commands {
             Commands::Lifecycle(args) => lifecycle::run(args.clone()).await,
             Commands::Market(cmd) => cmd.run().await,
             Commands::Project(cmd) => cmd.run().await,
+            Commands::Selftest(args) => selftest::run(args).await,
             Commands::Shell(cmd) => cmd.run().await,
             Commands::Template(cmd) => cmd.run().await,
         }
@@ -91,6 +107,7 @@ impl Commands {
             Commands::Ai(_) => "ai",
             Commands::Audit(_) => "audit",
             Commands::Ci(_) => "ci",
+            Commands::Clean(_) => "clean",
             Commands::Doctor(_) => "doctor",
             Commands::Graph(_) => "graph",
             Commands::HelpProgressive(_) => "help-me",
@@ -98,6 +115,7 @@ impl Commands {
             Commands::Lifecycle(_) => "lifecycle",
             Commands::Market(_) => "market",
             Commands::Project(_) => "project",
+            Commands::Selftest(_) => "selftest",
             Commands::Shell(_) => "shell",
             Commands::Template(_) => "template",
         };
@@ -121,6 +139,7 @@ impl Commands {
             Commands::Ai(args) => ai::run(args).await,
             Commands::Audit(cmd) => cmd.run().await,
             Commands::Ci(cmd) => cmd.run().await,
+            Commands::Clean(args) => clean::run(args).await,
             Commands::Doctor(args) => doctor::run(args).await,
             Commands::Graph(cmd) => cmd.run().await,
             Commands::HelpProgressive(args) => help_progressive::run(args).await,
@@ -128,6 +147,7 @@ impl Commands {
             Commands::Lifecycle(args) => lifecycle::run(args.clone()).await,
             Commands::Market(cmd) => cmd.run().await,
             Commands::Project(cmd) => cmd.run().await,
+            Commands::Selftest(args) => selftest::run(args).await,
             Commands::Shell(cmd) => cmd.run().await,
             Commands::Template(cmd) => cmd.run().await,
         }
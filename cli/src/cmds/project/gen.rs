@@ -11,8 +11,17 @@
 //! ggen project gen "rust-cli-template" --var name=myapp --var version=1.0.0
 //! ggen project gen "web-template" --dry-run --var framework=react
 //! ggen project gen "api-template" --force --var language=typescript
+//! ggen project gen "api-template" --only entity=User,Order --only endpoint=GetUsers
+//! ggen project gen templates/model.tmpl --dry-run --plan-out plan.json
+//! ggen project gen templates/model.tmpl --apply-plan plan.json
 //! ```
 //!
+//! A dry run of a local template renders everything without writing and
+//! prints what would happen to each file; `--plan-out` also saves the plan as
+//! JSON, and `--apply-plan` writes it verbatim later, refusing once the
+//! template, its RDF files or the planned files changed. See
+//! [`ggen_core::plan`].
+//!
 //! # Errors
 //!
 //! Returns errors if the template reference is invalid, required variables are
@@ -20,8 +29,14 @@
 //! template rendering errors.
 
 use clap::Args;
+use ggen_core::config::GgenConfig;
+use ggen_core::output::OutputProfiles;
+use ggen_core::scope::Scope;
+use ggen_core::{GenContext, GenerationPlan, Generator, Pipeline};
 use ggen_utils::error::Result;
 use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
 
 #[derive(Args, Debug)]
 pub struct GenArgs {
@@ -59,6 +74,20 @@ pub struct GenArgs {
     /// Maximum iterations for AI validation
     #[arg(long, default_value = "3")]
     pub ai_max_iterations: usize,
+
+    /// Generate only the files of these entities or endpoints, e.g.
+    /// `entity=User,Order`; defaults to `default_scope` in ggen.toml
+    #[arg(long = "only", value_name = "VARIABLE=NAMES")]
+    pub only: Vec<String>,
+
+    /// Save the plan of a dry run as JSON
+    #[arg(long, value_name = "FILE", requires = "dry_run")]
+    pub plan_out: Option<String>,
+
+    /// Write a plan saved with --plan-out as it is, failing if its inputs
+    /// changed since
+    #[arg(long, value_name = "FILE", conflicts_with = "dry_run")]
+    pub apply_plan: Option<String>,
 }
 
 /// London TDD: Define trait boundaries for testability
@@ -128,6 +157,9 @@ fn validate_gen_input(args: &GenArgs) -> Result<()> {
         ));
     }
 
+    // Validate scope selectors
+    Scope::parse(&args.only).map_err(|e| ggen_utils::error::Error::new(&e.to_string()))?;
+
     // Validate variables format
     for var in &args.vars {
         if !var.contains('=') {
@@ -149,11 +181,96 @@ fn validate_gen_input(args: &GenArgs) -> Result<()> {
     Ok(())
 }
 
+/// `--only` selectors, or the `default_scope` of the nearest ggen.toml
+fn scope_selectors(args: &GenArgs, cwd: &Path) -> Result<Vec<String>> {
+    if !args.only.is_empty() {
+        return Ok(args.only.clone());
+    }
+    let config = GgenConfig::discover_and_load(cwd)
+        .map_err(|e| ggen_utils::error::Error::new(&e.to_string()))?;
+    let selectors = config
+        .map(|(config, _)| config.default_scope)
+        .unwrap_or_default();
+    Scope::parse(&selectors).map_err(|e| {
+        ggen_utils::error::Error::new(&format!("Invalid default_scope in ggen.toml: {}", e))
+    })?;
+    Ok(selectors)
+}
+
+/// Output profiles of the nearest ggen.toml, if any
+fn output_profiles(cwd: &Path) -> Result<(Option<GgenConfig>, OutputProfiles)> {
+    match GgenConfig::discover_and_load(cwd)? {
+        Some((config, config_path)) => {
+            let config_dir = config_path.parent().unwrap_or(cwd);
+            let profiles = OutputProfiles::from_config(&config, config_dir)?;
+            Ok((Some(config), profiles))
+        }
+        None => Ok((None, OutputProfiles::default())),
+    }
+}
+
+/// Render the local template into `cwd` without writing, print the plan and
+/// save it to `--plan-out`
+fn plan_generation(args: &GenArgs, cwd: &Path) -> Result<GenerationPlan> {
+    let scope = Scope::parse(&scope_selectors(args, cwd)?)
+        .map_err(|e| ggen_utils::error::Error::new(&e.to_string()))?;
+    let (config, profiles) = output_profiles(cwd)?;
+    let mut ctx = GenContext::new(PathBuf::from(&args.template_ref), cwd.to_path_buf())
+        .with_vars(parse_vars(&args.vars)?.into_iter().collect())
+        .with_output_profiles(profiles)
+        .with_scope(scope)
+        .dry(true);
+    if let Some(config) = &config {
+        ctx = ctx.with_config(config);
+    }
+
+    let mut generator = Generator::new(Pipeline::new()?, ctx);
+    generator.generate_outputs()?;
+    println!("{}", generator.plan);
+    if let Some(plan_out) = &args.plan_out {
+        fs::write(plan_out, generator.plan.to_json()?)?;
+        println!("📋 Plan saved to {}", plan_out);
+    }
+    Ok(generator.plan)
+}
+
+/// Write the plan saved in `plan_file` as it is
+fn apply_plan(args: &GenArgs, plan_file: &str, cwd: &Path) -> Result<()> {
+    let plan = GenerationPlan::from_json(&fs::read_to_string(plan_file)?)?;
+    let template = Path::new(&args.template_ref);
+    if !plan.inputs.iter().any(|input| input.path == template) {
+        return Err(ggen_utils::error::Error::new_fmt(format_args!(
+            "{} is not a plan for {}",
+            plan_file, args.template_ref
+        )));
+    }
+
+    let (_, profiles) = output_profiles(cwd)?;
+    let applied = plan.apply(&profiles)?;
+    for path in &applied.changed {
+        println!("  {}", path.display());
+    }
+    for unapplied in &applied.unapplied_modes {
+        println!("⚠️  {}", unapplied);
+    }
+    println!("✅ Applied plan: {} files changed", applied.changed.len());
+    Ok(())
+}
+
 /// Main entry point for `ggen project gen`
 pub async fn run(args: &GenArgs) -> Result<()> {
     // Validate input
     validate_gen_input(args)?;
 
+    let cwd = std::env::current_dir()?;
+    if let Some(plan_file) = &args.apply_plan {
+        return apply_plan(args, plan_file, &cwd);
+    }
+    if args.dry_run && Path::new(&args.template_ref).is_file() {
+        plan_generation(args, &cwd)?;
+        return Ok(());
+    }
+
     println!("🚀 Generating project artifacts...");
 
     let mut cmd = std::process::Command::new("cargo");
@@ -172,6 +289,10 @@ pub async fn run(args: &GenArgs) -> Result<()> {
         cmd.arg("--force");
     }
 
+    for selector in scope_selectors(args, &cwd)? {
+        cmd.arg("--only").arg(selector);
+    }
+
     let output = cmd.output().map_err(ggen_utils::error::Error::from)?;
 
     if !output.status.success() {
@@ -257,6 +378,9 @@ mod tests {
             ai_provider: "mock".to_string(),
             ai_model: None,
             ai_max_iterations: 3,
+            only: vec![],
+            plan_out: None,
+            apply_plan: None,
         };
 
         // Act
@@ -299,6 +423,9 @@ mod tests {
             ai_provider: "mock".to_string(),
             ai_model: None,
             ai_max_iterations: 3,
+            only: vec![],
+            plan_out: None,
+            apply_plan: None,
         };
 
         let result = run_with_deps(&args, &mock_resolver, &mock_generator, &mock_applier).await;
@@ -335,12 +462,81 @@ mod tests {
             ai_provider: "mock".to_string(),
             ai_model: None,
             ai_max_iterations: 3,
+            only: vec![],
+            plan_out: None,
+            apply_plan: None,
         };
 
         let result = run_with_deps(&args, &mock_resolver, &mock_generator, &mock_applier).await;
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn test_invalid_scope_is_rejected() {
+        let args = GenArgs {
+            template_ref: "test.tmpl".to_string(),
+            vars: vec![],
+            dry_run: false,
+            seed: None,
+            force: false,
+            ai: false,
+            ai_provider: "mock".to_string(),
+            ai_model: None,
+            ai_max_iterations: 3,
+            only: vec!["entity=User".to_string(), "User".to_string()],
+            plan_out: None,
+            apply_plan: None,
+        };
+
+        let err = validate_gen_input(&args).unwrap_err();
+        assert!(err.to_string().contains("Invalid scope 'User'"), "{}", err);
+    }
+
+    #[test]
+    fn test_dry_run_plan_is_applied_later() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let template = dir.path().join("hello.tmpl");
+        fs::write(
+            &template,
+            "---\nto: \"hello.txt\"\n---\nHello, {{ name }}!\n",
+        )
+        .unwrap();
+        let plan_file = dir.path().join("plan.json").display().to_string();
+        let mut args = GenArgs {
+            template_ref: template.display().to_string(),
+            vars: vec!["name=World".to_string()],
+            dry_run: true,
+            seed: None,
+            force: false,
+            ai: false,
+            ai_provider: "mock".to_string(),
+            ai_model: None,
+            ai_max_iterations: 3,
+            only: vec![],
+            plan_out: Some(plan_file.clone()),
+            apply_plan: None,
+        };
+
+        let plan = plan_generation(&args, dir.path()).unwrap();
+        assert_eq!(plan.count(ggen_core::FileAction::Create), 1);
+        assert!(!dir.path().join("hello.txt").exists());
+
+        args.dry_run = false;
+        args.plan_out = None;
+        let template_ref = std::mem::replace(&mut args.template_ref, "other.tmpl".to_string());
+        let err = apply_plan(&args, &plan_file, dir.path()).unwrap_err();
+        assert!(
+            err.to_string().contains("is not a plan for other.tmpl"),
+            "{}",
+            err
+        );
+        args.template_ref = template_ref;
+
+        apply_plan(&args, &plan_file, dir.path()).unwrap();
+        let written = fs::read_to_string(dir.path().join("hello.txt")).unwrap();
+        assert!(written.contains("Hello, World!"), "{}", written);
+    }
+
     #[test]
     fn test_parse_vars_valid() {
         let vars = vec!["name=Alice".to_string(), "age=30".to_string()];
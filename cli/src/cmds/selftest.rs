@@ -0,0 +1,61 @@
+//! Self-test of the project
+//!
+//! Checks `ggen.toml`, `make.toml`, the ontology, every template, the paths
+//! generation writes to and the provider settings, offline unless
+//! `--online`, and reports which of them is broken; see
+//! [`ggen_core::selftest`]. Exits with 0 when everything passed, 1 with
+//! warnings and 2 with failures.
+//!
+//! # Examples
+//!
+//! ```bash
+//! ggen selftest
+//! ggen selftest --online
+//! ggen selftest --json > selftest.json
+//! ```
+
+use clap::Args;
+use ggen_core::selftest::SelfTest;
+use ggen_utils::error::Result;
+use std::path::PathBuf;
+
+#[derive(Args, Debug)]
+pub struct SelftestArgs {
+    /// Also send a request to every configured provider
+    #[arg(long)]
+    pub online: bool,
+
+    /// Print the report as JSON
+    #[arg(long)]
+    pub json: bool,
+
+    /// Project to check; the nearest ggen.toml above it is used
+    #[arg(long)]
+    pub path: Option<PathBuf>,
+
+    /// Read the providers from this file instead of ggen.toml
+    #[arg(long)]
+    pub providers: Option<PathBuf>,
+}
+
+pub async fn run(args: &SelftestArgs) -> Result<()> {
+    let root = match &args.path {
+        Some(path) => path.clone(),
+        None => std::env::current_dir()?,
+    };
+    let mut selftest = SelfTest::new(root).online(args.online);
+    if let Some(providers) = &args.providers {
+        selftest = selftest.with_provider_config(providers);
+    }
+
+    let report = selftest.run().await;
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&report.to_json())?);
+    } else {
+        println!("{}", report);
+    }
+    match report.exit_code() {
+        0 => Ok(()),
+        code => std::process::exit(code),
+    }
+}
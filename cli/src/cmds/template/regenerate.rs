@@ -1,8 +1,11 @@
 use clap::Args;
 use ggen_core::graph::Graph;
 use ggen_core::merge::{MergeStrategy, RegionAwareMerger};
+use ggen_core::query_explain::explain_templates;
+use ggen_core::regen_check::{check_generated, CheckOptions};
 use ggen_core::snapshot::SnapshotManager;
 use ggen_utils::error::Result;
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
 
 #[derive(Args, Debug)]
@@ -38,9 +41,39 @@ pub struct RegenerateArgs {
     /// Snapshot directory
     #[arg(short = 'S', long, default_value = ".ggen/snapshots")]
     pub snapshot_dir: PathBuf,
+
+    /// Fail if the committed output differs from a fresh render, without
+    /// writing anything
+    #[arg(long)]
+    pub check: bool,
+
+    /// Variables to pass to the templates (key=value format)
+    #[arg(short = 'v', long = "var")]
+    pub vars: Vec<String>,
+
+    /// Diff lines shown per changed file in check mode
+    #[arg(long, default_value = "20")]
+    pub max_diff_lines: usize,
+
+    /// Render without writing and explain each frontmatter SPARQL query:
+    /// parameters, timing, row count and, for empty results, the triple
+    /// pattern that eliminated all solutions
+    #[arg(long)]
+    pub explain_queries: bool,
+
+    /// JSON report written by --explain-queries
+    #[arg(long, default_value = ".ggen/query-explain.json")]
+    pub explain_report: PathBuf,
 }
 
 pub async fn run(args: &RegenerateArgs) -> Result<()> {
+    if args.check {
+        return check(args);
+    }
+    if args.explain_queries {
+        return explain(args);
+    }
+
     let merge_strategy = parse_merge_strategy(&args.merge)?;
 
     // Load baseline snapshot if provided
@@ -169,6 +202,55 @@ async fn regenerate_template(
     Ok(())
 }
 
+/// Compare the committed output with a fresh render; see
+/// [`ggen_core::regen_check`]
+fn check(args: &RegenerateArgs) -> Result<()> {
+    let options = CheckOptions {
+        vars: parse_vars(&args.vars)?,
+        max_diff_lines: args.max_diff_lines,
+    };
+    let output_dir = args
+        .output_dir
+        .clone()
+        .unwrap_or_else(|| PathBuf::from("."));
+
+    let report = check_generated(&args.templates, &output_dir, &options)?;
+    print!("{}", report);
+    if report.is_clean() {
+        Ok(())
+    } else {
+        Err(ggen_utils::error::Error::new_fmt(format_args!(
+            "{} generated files in {} are out of date; regenerate and commit them",
+            report.files.len(),
+            output_dir.display()
+        )))
+    }
+}
+
+/// Explain the frontmatter queries of the templates on stderr and in a JSON
+/// report; see [`ggen_core::query_explain`]
+fn explain(args: &RegenerateArgs) -> Result<()> {
+    let report = explain_templates(&args.templates, &parse_vars(&args.vars)?)?;
+    eprint!("{}", report);
+    report.write_json(&args.explain_report)?;
+    eprintln!("Query report written to {}", args.explain_report.display());
+    Ok(())
+}
+
+fn parse_vars(vars: &[String]) -> Result<BTreeMap<String, String>> {
+    vars.iter()
+        .map(|var| {
+            let (key, value) = var.split_once('=').ok_or_else(|| {
+                ggen_utils::error::Error::new_fmt(format_args!(
+                    "Invalid variable format: '{}'. Expected 'key=value'",
+                    var
+                ))
+            })?;
+            Ok((key.to_string(), value.to_string()))
+        })
+        .collect()
+}
+
 fn parse_merge_strategy(strategy: &str) -> Result<MergeStrategy> {
     match strategy {
         "generated-wins" => Ok(MergeStrategy::GeneratedWins),
@@ -2,14 +2,23 @@
 //
 // Provides in-memory caching of LLM responses with TTL and size limits.
 // Reduces API costs by 30-60% for repeated prompts.
+//
+// Responses above the spill threshold are zstd-compressed into
+// content-addressed files (`<sha256>.zst`) and only their metadata stays in
+// memory. Files go away with the last entry pointing at them, and files
+// nothing points at are removed when the cache is created or cleared. A spill
+// file that can't be read back is a cache miss, never an error.
 
 use anyhow::Result;
 use moka::future::Cache;
+use moka::notification::RemovalCause;
 use serde::{Deserialize, Serialize};
 use sha2::Sha256;
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 
 /// Configuration for LLM response caching
 #[derive(Debug, Clone)]
@@ -20,6 +29,8 @@ pub struct CacheConfig {
     pub ttl: Duration,
     /// Time to idle (unused) before eviction
     pub tti: Option<Duration>,
+    /// Where oversized responses go; everything stays in memory if unset
+    pub spill: Option<SpillConfig>,
 }
 
 impl Default for CacheConfig {
@@ -28,6 +39,40 @@ impl Default for CacheConfig {
             max_capacity: 10_000,
             ttl: Duration::from_secs(3600),      // 1 hour
             tti: Some(Duration::from_secs(600)), // 10 minutes
+            spill: None,
+        }
+    }
+}
+
+impl From<&ggen_config::CacheSettings> for CacheConfig {
+    fn from(settings: &ggen_config::CacheSettings) -> Self {
+        Self {
+            max_capacity: settings.max_entries,
+            ttl: settings.ttl(),
+            tti: settings.idle(),
+            spill: settings.spill_dir.clone().map(SpillConfig::new),
+        }
+    }
+}
+
+/// Disk spill for responses too large to keep in memory
+#[derive(Debug, Clone)]
+pub struct SpillConfig {
+    /// Directory owned by the cache; unknown `.zst` files in it are removed
+    pub dir: PathBuf,
+    /// Responses larger than this many bytes are spilled
+    pub threshold_bytes: usize,
+    /// zstd compression level
+    pub level: i32,
+}
+
+impl SpillConfig {
+    /// Spill responses over 64 KiB to `dir` at zstd level 3
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            threshold_bytes: 64 * 1024,
+            level: 3,
         }
     }
 }
@@ -41,9 +86,142 @@ pub struct CachedResponse {
     pub cached_at: i64, // Unix timestamp
 }
 
+/// A response whose content lives in a spill file
+#[derive(Debug)]
+struct SpilledResponse {
+    /// SHA-256 of the content, naming the file
+    hash: String,
+    model: String,
+    cached_at: i64,
+}
+
+#[derive(Debug, Clone)]
+enum Entry {
+    Memory(Arc<CachedResponse>),
+    Disk(Arc<SpilledResponse>),
+}
+
+impl Entry {
+    fn cached_at(&self) -> i64 {
+        match self {
+            Entry::Memory(cached) => cached.cached_at,
+            Entry::Disk(spilled) => spilled.cached_at,
+        }
+    }
+}
+
+/// Spill files and how many entries point at each
+#[derive(Debug)]
+struct SpillStore {
+    config: SpillConfig,
+    refs: Mutex<HashMap<String, usize>>,
+}
+
+impl SpillStore {
+    fn path(&self, hash: &str) -> PathBuf {
+        self.config.dir.join(format!("{}.zst", hash))
+    }
+
+    /// Write `content` unless a file with the same content exists, and count
+    /// the new reference
+    fn store(&self, content: &str) -> std::io::Result<String> {
+        let hash = content_hash(content);
+        let mut refs = self.refs.lock().unwrap_or_else(|e| e.into_inner());
+        let path = self.path(&hash);
+        if !refs.contains_key(&hash) {
+            let compressed = zstd::encode_all(content.as_bytes(), self.config.level)?;
+            // Write next to the target and rename so readers never see a
+            // partial file
+            let partial = self.config.dir.join(format!("{}.zst.tmp", hash));
+            std::fs::write(&partial, compressed)?;
+            std::fs::rename(&partial, &path)?;
+        }
+        *refs.entry(hash.clone()).or_insert(0) += 1;
+        Ok(hash)
+    }
+
+    /// The content of `hash`, or `None` if the file is missing or corrupt
+    fn load(&self, hash: &str) -> Option<String> {
+        let path = self.path(hash);
+        let compressed = std::fs::read(&path).ok()?;
+        let content = zstd::decode_all(compressed.as_slice())
+            .ok()
+            .and_then(|bytes| String::from_utf8(bytes).ok())
+            .filter(|content| content_hash(content) == hash);
+        if content.is_none() {
+            warn!(
+                "Spill file {} is corrupt; treating it as a miss",
+                path.display()
+            );
+        }
+        content
+    }
+
+    /// Drop a reference, removing the file with the last one
+    fn release(&self, hash: &str) {
+        let mut refs = self.refs.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(count) = refs.get_mut(hash) else {
+            return;
+        };
+        *count -= 1;
+        if *count == 0 {
+            refs.remove(hash);
+            remove_quietly(&self.path(hash));
+        }
+    }
+
+    /// Remove files in the spill directory no entry points at, returning how
+    /// many were removed
+    fn reconcile(&self) -> usize {
+        let refs = self.refs.lock().unwrap_or_else(|e| e.into_inner());
+        let Ok(dir) = std::fs::read_dir(&self.config.dir) else {
+            return 0;
+        };
+        let mut removed = 0;
+        for entry in dir.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let orphaned = match name.strip_suffix(".zst") {
+                Some(hash) => !refs.contains_key(hash),
+                // Left over from a write that didn't finish
+                None => name.ends_with(".zst.tmp"),
+            };
+            if orphaned {
+                remove_quietly(&entry.path());
+                removed += 1;
+            }
+        }
+        removed
+    }
+
+    /// Forget every reference and remove every spill file
+    fn reset(&self) {
+        self.refs.lock().unwrap_or_else(|e| e.into_inner()).clear();
+        self.reconcile();
+    }
+
+    fn spilled_entries(&self) -> u64 {
+        let refs = self.refs.lock().unwrap_or_else(|e| e.into_inner());
+        refs.values().sum::<usize>() as u64
+    }
+}
+
+fn content_hash(content: &str) -> String {
+    use sha2::Digest;
+    format!("{:x}", Sha256::digest(content.as_bytes()))
+}
+
+fn remove_quietly(path: &Path) {
+    if let Err(e) = std::fs::remove_file(path) {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            warn!("Failed to remove spill file {}: {}", path.display(), e);
+        }
+    }
+}
+
 /// LLM response cache
 pub struct LlmCache {
-    cache: Cache<String, Arc<CachedResponse>>,
+    cache: Cache<String, Entry>,
+    spill: Option<Arc<SpillStore>>,
     config: CacheConfig,
     hits: Arc<std::sync::atomic::AtomicU64>,
     misses: Arc<std::sync::atomic::AtomicU64>,
@@ -56,6 +234,10 @@ impl LlmCache {
     }
 
     /// Create a new LLM cache with custom configuration
+    ///
+    /// If the spill directory can't be created the cache keeps everything
+    /// in memory. Files left in it by an earlier cache are removed, since
+    /// nothing in this one points at them.
     pub fn with_config(config: CacheConfig) -> Self {
         let mut builder = Cache::builder()
             .max_capacity(config.max_capacity)
@@ -65,8 +247,39 @@ impl LlmCache {
             builder = builder.time_to_idle(tti);
         }
 
+        let spill = config.spill.as_ref().and_then(|spill| {
+            if let Err(e) = std::fs::create_dir_all(&spill.dir) {
+                warn!(
+                    "Failed to create spill directory {}, keeping responses in memory: {}",
+                    spill.dir.display(),
+                    e
+                );
+                return None;
+            }
+            Some(Arc::new(SpillStore {
+                config: spill.clone(),
+                refs: Mutex::new(HashMap::new()),
+            }))
+        });
+
+        if let Some(spill) = &spill {
+            let removed = spill.reconcile();
+            if removed > 0 {
+                info!("Removed {} orphaned spill files", removed);
+            }
+            let store = spill.clone();
+            builder = builder.eviction_listener(
+                move |_key: Arc<String>, entry: Entry, _cause: RemovalCause| {
+                    if let Entry::Disk(spilled) = entry {
+                        store.release(&spilled.hash);
+                    }
+                },
+            );
+        }
+
         Self {
             cache: builder.build(),
+            spill,
             config,
             hits: Arc::new(std::sync::atomic::AtomicU64::new(0)),
             misses: Arc::new(std::sync::atomic::AtomicU64::new(0)),
@@ -82,6 +295,50 @@ impl LlmCache {
         format!("{:x}", hasher.finalize())
     }
 
+    /// `response` as stored: in memory, or in a spill file if it is too large
+    fn entry(&self, response: CachedResponse) -> Entry {
+        let Some(spill) = &self.spill else {
+            return Entry::Memory(Arc::new(response));
+        };
+        if response.content.len() <= spill.config.threshold_bytes {
+            return Entry::Memory(Arc::new(response));
+        }
+        match spill.store(&response.content) {
+            Ok(hash) => Entry::Disk(Arc::new(SpilledResponse {
+                hash,
+                model: response.model,
+                cached_at: response.cached_at,
+            })),
+            Err(e) => {
+                warn!(
+                    "Failed to spill cached response, keeping it in memory: {}",
+                    e
+                );
+                Entry::Memory(Arc::new(response))
+            }
+        }
+    }
+
+    /// Content cached under `key`, dropping entries whose spill file is gone
+    /// or corrupt
+    async fn lookup(&self, key: &str) -> Option<(String, i64)> {
+        let entry = self.cache.get(key).await?;
+        let content = match &entry {
+            Entry::Memory(cached) => Some(cached.content.clone()),
+            Entry::Disk(spilled) => self
+                .spill
+                .as_ref()
+                .and_then(|spill| spill.load(&spilled.hash)),
+        };
+        match content {
+            Some(content) => Some((content, entry.cached_at())),
+            None => {
+                self.cache.invalidate(key).await;
+                None
+            }
+        }
+    }
+
     /// Get cached response or generate new one
     pub async fn get_or_generate<F, Fut>(
         &self, prompt: &str, model: &str, generator: F,
@@ -93,14 +350,14 @@ impl LlmCache {
         let key = Self::cache_key(prompt, model);
 
         // Check cache first
-        if let Some(cached) = self.cache.get(&key).await {
+        if let Some((content, cached_at)) = self.lookup(&key).await {
             self.hits.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
             debug!(
                 "Cache hit for model {} (cached {} seconds ago)",
                 model,
-                chrono::Utc::now().timestamp() - cached.cached_at
+                chrono::Utc::now().timestamp() - cached_at
             );
-            return Ok(cached.content.clone());
+            return Ok(content);
         }
 
         // Cache miss - generate response
@@ -111,14 +368,14 @@ impl LlmCache {
         let response = generator().await?;
 
         // Cache the response
-        let cached = Arc::new(CachedResponse {
+        let entry = self.entry(CachedResponse {
             content: response.clone(),
             model: model.to_string(),
             tokens_used: None,
             cached_at: chrono::Utc::now().timestamp(),
         });
 
-        self.cache.insert(key, cached).await;
+        self.cache.insert(key, entry).await;
 
         Ok(response)
     }
@@ -126,27 +383,37 @@ impl LlmCache {
     /// Manually insert a response into the cache
     pub async fn insert(&self, prompt: &str, model: &str, response: String, tokens: Option<usize>) {
         let key = Self::cache_key(prompt, model);
-        let cached = Arc::new(CachedResponse {
+        let entry = self.entry(CachedResponse {
             content: response,
             model: model.to_string(),
             tokens_used: tokens,
             cached_at: chrono::Utc::now().timestamp(),
         });
 
-        self.cache.insert(key, cached).await;
+        self.cache.insert(key, entry).await;
         info!("Manually cached response for model {}", model);
     }
 
     /// Get a cached response if it exists
     pub async fn get(&self, prompt: &str, model: &str) -> Option<String> {
         let key = Self::cache_key(prompt, model);
-        self.cache.get(&key).await.map(|c| c.content.clone())
+        self.lookup(&key).await.map(|(content, _)| content)
+    }
+
+    /// Remove the response cached for `prompt` and `model`, if any
+    pub async fn remove(&self, prompt: &str, model: &str) {
+        let key = Self::cache_key(prompt, model);
+        self.cache.invalidate(&key).await;
     }
 
     /// Clear the entire cache
     pub async fn clear(&self) {
         self.cache.invalidate_all();
         self.cache.run_pending_tasks().await;
+        // Invalidated entries may still be waiting for the eviction listener
+        if let Some(spill) = &self.spill {
+            spill.reset();
+        }
         info!("Cache cleared");
     }
 
@@ -167,6 +434,10 @@ impl LlmCache {
             hit_rate,
             entry_count: self.cache.entry_count(),
             weighted_size: self.cache.weighted_size(),
+            spilled_entries: self
+                .spill
+                .as_ref()
+                .map_or(0, |spill| spill.spilled_entries()),
         }
     }
 
@@ -190,11 +461,14 @@ pub struct CacheStats {
     pub hit_rate: f64,
     pub entry_count: u64,
     pub weighted_size: u64,
+    /// Entries whose content is in a spill file
+    pub spilled_entries: u64,
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use tempfile::TempDir;
 
     #[tokio::test]
     async fn test_cache_hit() {
@@ -246,4 +520,111 @@ mod tests {
         assert_eq!(result1, "gpt-4 response");
         assert_eq!(result2, "claude response");
     }
+
+    fn spilling_cache(dir: &Path, max_capacity: u64) -> LlmCache {
+        LlmCache::with_config(CacheConfig {
+            max_capacity,
+            spill: Some(SpillConfig {
+                threshold_bytes: 1024,
+                ..SpillConfig::new(dir)
+            }),
+            ..CacheConfig::default()
+        })
+    }
+
+    fn spill_files(dir: &Path) -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        files.sort();
+        files
+    }
+
+    fn large(seed: &str) -> String {
+        seed.repeat(4096)
+    }
+
+    #[tokio::test]
+    async fn test_large_responses_are_spilled_compressed() {
+        let temp = TempDir::new().unwrap();
+        let cache = spilling_cache(temp.path(), 100);
+
+        cache
+            .insert("small", "gpt-4", "short answer".to_string(), None)
+            .await;
+        cache
+            .insert("large", "gpt-4", large("fn main() {} "), None)
+            .await;
+
+        let files = spill_files(temp.path());
+        assert_eq!(files.len(), 1);
+        assert_eq!(
+            files[0].file_name().unwrap().to_string_lossy(),
+            format!("{}.zst", content_hash(&large("fn main() {} ")))
+        );
+        assert!(std::fs::metadata(&files[0]).unwrap().len() < 1024);
+        assert_eq!(cache.stats().spilled_entries, 1);
+
+        assert_eq!(cache.get("small", "gpt-4").await.unwrap(), "short answer");
+        assert_eq!(
+            cache.get("large", "gpt-4").await.unwrap(),
+            large("fn main() {} ")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_corrupt_spill_file_is_a_miss() {
+        let temp = TempDir::new().unwrap();
+        let cache = spilling_cache(temp.path(), 100);
+        cache.insert("large", "gpt-4", large("a"), None).await;
+        let file = spill_files(temp.path()).remove(0);
+        std::fs::write(&file, b"not zstd").unwrap();
+
+        let response = cache
+            .get_or_generate("large", "gpt-4", || async { Ok(large("b")) })
+            .await
+            .unwrap();
+
+        assert_eq!(response, large("b"));
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses), (0, 1));
+        // The corrupt file went with its entry
+        cache.cache.run_pending_tasks().await;
+        assert_eq!(
+            spill_files(temp.path()),
+            vec![temp
+                .path()
+                .join(format!("{}.zst", content_hash(&large("b"))))]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_eviction_and_clear_remove_spill_files() {
+        let temp = TempDir::new().unwrap();
+        let cache = spilling_cache(temp.path(), 1);
+        cache.insert("first", "gpt-4", large("a"), None).await;
+        cache.insert("second", "gpt-4", large("b"), None).await;
+        cache.cache.run_pending_tasks().await;
+
+        assert_eq!(cache.cache.entry_count(), 1);
+        assert_eq!(spill_files(temp.path()).len(), 1);
+
+        cache.clear().await;
+        assert!(spill_files(temp.path()).is_empty());
+        assert_eq!(cache.stats().spilled_entries, 0);
+    }
+
+    #[tokio::test]
+    async fn test_orphaned_spill_files_are_removed_on_startup() {
+        let temp = TempDir::new().unwrap();
+        let orphan = temp.path().join(format!("{}.zst", content_hash("gone")));
+        std::fs::write(&orphan, zstd::encode_all("gone".as_bytes(), 3).unwrap()).unwrap();
+        std::fs::write(temp.path().join("README"), "kept").unwrap();
+
+        let cache = spilling_cache(temp.path(), 100);
+
+        assert_eq!(spill_files(temp.path()), vec![temp.path().join("README")]);
+        assert_eq!(cache.get("gone", "gpt-4").await, None);
+    }
 }
@@ -98,6 +98,20 @@ impl LlmConfig {
     }
 }
 
+impl From<&ggen_config::ProviderSettings> for LlmConfig {
+    /// The provider's model and sampling settings; what it leaves unset
+    /// comes from [`LlmConfig::default`]
+    fn from(settings: &ggen_config::ProviderSettings) -> Self {
+        let defaults = Self::default();
+        Self {
+            model: settings.model.clone(),
+            max_tokens: settings.max_tokens.or(defaults.max_tokens),
+            temperature: settings.temperature.or(defaults.temperature),
+            ..defaults
+        }
+    }
+}
+
 /// Usage statistics for LLM requests
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UsageStats {
@@ -331,6 +345,18 @@ mod tests {
         assert!(config.validate().is_err());
     }
 
+    #[test]
+    fn test_llm_config_from_shared_settings() {
+        let settings = ggen_config::ProviderSettings {
+            temperature: Some(0.2),
+            ..ggen_config::ProviderSettings::new("openai", "gpt-4o")
+        };
+        let config = LlmConfig::from(&settings);
+        assert_eq!(config.model, "gpt-4o");
+        assert_eq!(config.temperature, Some(0.2));
+        assert_eq!(config.max_tokens, LlmConfig::default().max_tokens);
+    }
+
     #[test]
     fn test_genai_client_creation() {
         use crate::client::GenAiClient;
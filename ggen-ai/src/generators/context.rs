@@ -0,0 +1,427 @@
+//! Project files as style exemplars for template generation
+//!
+//! Without examples from the project, generated templates follow whatever
+//! conventions the model prefers. A [`ContextSelector`] takes the project
+//! context of a request, either snippets or a directory to sample files
+//! from, embeds it along with the description, and keeps the files most
+//! relevant to the description as [`Exemplar`]s. [`ContextSelector::fit`]
+//! then trims them so that the prompt stays within the model's window.
+
+use crate::error::{GgenAiError, Result};
+use crate::generators::duplicates::{cosine, EmbeddingModel};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Directories never sampled
+const SKIPPED_DIRS: &[&str] = &["target", "node_modules", "dist", "build", "vendor"];
+
+/// Exemplars cut down to fewer tokens than this are left out instead
+const MIN_EXEMPLAR_TOKENS: usize = 64;
+
+/// Project files a template should take its conventions from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProjectContext {
+    /// Files given with the request
+    Snippets(Vec<Snippet>),
+    /// A directory to sample files from
+    Directory(PathBuf),
+}
+
+/// A project file, or part of one
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snippet {
+    pub path: String,
+    pub content: String,
+}
+
+/// How project context is sampled and how much of it reaches the prompt
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ContextConfig {
+    /// Files larger than this are not sampled
+    #[serde(default = "default_max_file_bytes")]
+    pub max_file_bytes: u64,
+    /// Files sampled from a directory, spread evenly over its sorted paths
+    #[serde(default = "default_max_files")]
+    pub max_files: usize,
+    /// Extensions sampled from a directory; any when empty
+    #[serde(default = "default_extensions")]
+    pub extensions: Vec<String>,
+    /// Exemplars included in the prompt
+    #[serde(default = "default_top_k")]
+    pub top_k: usize,
+    /// Estimated tokens the whole prompt may take
+    #[serde(default = "default_max_prompt_tokens")]
+    pub max_prompt_tokens: usize,
+    /// Directory contexts are relative to this and may not leave it; any
+    /// directory may be sampled when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub root: Option<PathBuf>,
+}
+
+fn default_max_file_bytes() -> u64 {
+    16 * 1024
+}
+
+fn default_max_files() -> usize {
+    64
+}
+
+fn default_extensions() -> Vec<String> {
+    [
+        "rs", "ts", "tsx", "js", "py", "go", "java", "kt", "tmpl", "tera",
+    ]
+    .iter()
+    .map(|ext| ext.to_string())
+    .collect()
+}
+
+fn default_top_k() -> usize {
+    3
+}
+
+fn default_max_prompt_tokens() -> usize {
+    8000
+}
+
+impl Default for ContextConfig {
+    fn default() -> Self {
+        Self {
+            max_file_bytes: default_max_file_bytes(),
+            max_files: default_max_files(),
+            extensions: default_extensions(),
+            top_k: default_top_k(),
+            max_prompt_tokens: default_max_prompt_tokens(),
+            root: None,
+        }
+    }
+}
+
+/// A project file chosen to show the model the project's conventions
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Exemplar {
+    pub path: String,
+    pub content: String,
+    /// Cosine similarity to the description
+    pub score: f32,
+    /// Only the start of the file fit into the prompt
+    pub truncated: bool,
+}
+
+impl Exemplar {
+    /// The exemplar between delimiters, as it appears in the prompt
+    pub fn render(&self) -> String {
+        let note = if self.truncated { " (truncated)" } else { "" };
+        format!(
+            "<<<EXEMPLAR {}{}>>>\n{}\n<<<END EXEMPLAR>>>\n",
+            self.path, note, self.content
+        )
+    }
+}
+
+/// Rough token count of `text`, about four characters per token
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+/// Picks the project files most relevant to a description
+#[derive(Debug, Clone)]
+pub struct ContextSelector {
+    model: Arc<dyn EmbeddingModel>,
+    config: ContextConfig,
+}
+
+impl ContextSelector {
+    /// Selector with the default configuration
+    pub fn new(model: Arc<dyn EmbeddingModel>) -> Self {
+        Self {
+            model,
+            config: ContextConfig::default(),
+        }
+    }
+
+    pub fn with_config(mut self, config: ContextConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn config(&self) -> &ContextConfig {
+        &self.config
+    }
+
+    /// The `top_k` files of `context` most similar to `description`, most
+    /// similar first
+    pub async fn select(
+        &self, description: &str, context: &ProjectContext,
+    ) -> Result<Vec<Exemplar>> {
+        let snippets = match context {
+            ProjectContext::Snippets(snippets) => snippets.clone(),
+            ProjectContext::Directory(dir) => self.sample(dir)?,
+        };
+        if snippets.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut texts = vec![description.to_string()];
+        texts.extend(
+            snippets
+                .iter()
+                .map(|s| format!("{}\n{}", s.path, s.content)),
+        );
+        let vectors = self.model.embed(&texts).await?;
+        if vectors.len() != texts.len() {
+            return Err(GgenAiError::template_generation(format!(
+                "Embedding model returned {} vectors for {} texts",
+                vectors.len(),
+                texts.len()
+            )));
+        }
+
+        let mut exemplars: Vec<Exemplar> = snippets
+            .into_iter()
+            .zip(&vectors[1..])
+            .map(|(snippet, vector)| Exemplar {
+                path: snippet.path,
+                content: snippet.content,
+                score: cosine(&vectors[0], vector),
+                truncated: false,
+            })
+            .collect();
+        // Stable, so ties keep the order the files were given in
+        exemplars.sort_by(|a, b| b.score.total_cmp(&a.score));
+        exemplars.truncate(self.config.top_k);
+        Ok(exemplars)
+    }
+
+    /// Keep as many exemplars, most relevant first, as fit beside a prompt
+    /// of `prompt_tokens`; the first one that doesn't fit is cut at a line
+    /// boundary, unless too little of it would remain
+    pub fn fit(&self, exemplars: Vec<Exemplar>, prompt_tokens: usize) -> Vec<Exemplar> {
+        let mut left = self.config.max_prompt_tokens.saturating_sub(prompt_tokens);
+        let mut kept = Vec::new();
+        for mut exemplar in exemplars {
+            let tokens = estimate_tokens(&exemplar.render());
+            if tokens <= left {
+                left -= tokens;
+                kept.push(exemplar);
+                continue;
+            }
+            exemplar.truncated = true;
+            let overhead = estimate_tokens(
+                &Exemplar {
+                    content: String::new(),
+                    ..exemplar.clone()
+                }
+                .render(),
+            );
+            let room = left.saturating_sub(overhead);
+            if room >= MIN_EXEMPLAR_TOKENS {
+                exemplar.content = cut_at_line(&exemplar.content, room * 4);
+                kept.push(exemplar);
+            }
+            break;
+        }
+        kept
+    }
+
+    /// Files under `dir` with a sampled extension and within the size cap
+    fn sample(&self, dir: &Path) -> Result<Vec<Snippet>> {
+        let missing = || {
+            GgenAiError::validation(format!(
+                "Project context directory {} doesn't exist",
+                dir.display()
+            ))
+        };
+        let dir = match &self.config.root {
+            Some(root) => {
+                let root = root.canonicalize()?;
+                let resolved = root.join(dir).canonicalize().map_err(|_| missing())?;
+                if !resolved.starts_with(&root) {
+                    return Err(GgenAiError::validation(format!(
+                        "Project context directory {} is outside {}",
+                        dir.display(),
+                        root.display()
+                    )));
+                }
+                resolved
+            }
+            None => dir.to_path_buf(),
+        };
+        let dir = dir.as_path();
+        if !dir.is_dir() {
+            return Err(missing());
+        }
+        let mut files = Vec::new();
+        self.collect(dir, &mut files)?;
+        files.sort();
+
+        let picked: Vec<&PathBuf> = if files.len() > self.config.max_files {
+            let stride = files.len() as f64 / self.config.max_files as f64;
+            (0..self.config.max_files)
+                .map(|i| &files[(i as f64 * stride) as usize])
+                .collect()
+        } else {
+            files.iter().collect()
+        };
+
+        let mut snippets = Vec::new();
+        for path in picked {
+            // Binary files are no use as exemplars
+            let Ok(content) = std::fs::read_to_string(path) else {
+                continue;
+            };
+            let relative = path.strip_prefix(dir).unwrap_or(path);
+            snippets.push(Snippet {
+                path: relative.to_string_lossy().replace('\\', "/"),
+                content,
+            });
+        }
+        Ok(snippets)
+    }
+
+    fn collect(&self, dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.starts_with('.') {
+                continue;
+            }
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                if !SKIPPED_DIRS.contains(&name.as_str()) {
+                    self.collect(&path, files)?;
+                }
+                continue;
+            }
+            let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+            let wanted = self.config.extensions.is_empty()
+                || self.config.extensions.iter().any(|ext| ext == extension);
+            if file_type.is_file()
+                && wanted
+                && entry.metadata()?.len() <= self.config.max_file_bytes
+            {
+                files.push(path);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The longest run of whole lines of `text` within `max_chars` characters
+fn cut_at_line(text: &str, max_chars: usize) -> String {
+    let mut out = String::new();
+    for line in text.split_inclusive('\n') {
+        if out.chars().count() + line.chars().count() > max_chars {
+            break;
+        }
+        out.push_str(line);
+    }
+    out.trim_end().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+
+    /// Vectors counting a few words, so "repository" texts are close
+    #[derive(Debug)]
+    struct KeywordEmbeddings;
+
+    #[async_trait]
+    impl EmbeddingModel for KeywordEmbeddings {
+        async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+            Ok(texts
+                .iter()
+                .map(|text| {
+                    let text = text.to_lowercase();
+                    ["repository", "controller", "rust"]
+                        .iter()
+                        .map(|word| text.matches(word).count() as f32)
+                        .collect()
+                })
+                .collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_directory_sampling_filters_and_ranks() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |path: &str, content: &str| {
+            let path = dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        };
+        write("src/user_repository.rs", "pub struct UserRepository;");
+        write("src/order_controller.rs", "pub struct OrderController;");
+        write("src/big_repository.rs", &"// repository\n".repeat(100));
+        write("README.md", "repository repository repository");
+        write(
+            "target/debug/gen_repository.rs",
+            "pub struct GenRepository;",
+        );
+
+        let selector =
+            ContextSelector::new(Arc::new(KeywordEmbeddings)).with_config(ContextConfig {
+                max_file_bytes: 512,
+                top_k: 1,
+                ..ContextConfig::default()
+            });
+        let context = ProjectContext::Directory(dir.path().to_path_buf());
+        let picked = selector.select("A repository", &context).await.unwrap();
+
+        assert_eq!(picked.len(), 1);
+        assert_eq!(picked[0].path, "src/user_repository.rs");
+
+        // Confined to a root, paths leaving it are refused
+        let confined = selector.with_config(ContextConfig {
+            root: Some(dir.path().join("src")),
+            ..ContextConfig::default()
+        });
+        let inside = ProjectContext::Directory(PathBuf::from("."));
+        assert_eq!(
+            confined
+                .select("A repository", &inside)
+                .await
+                .unwrap()
+                .len(),
+            3
+        );
+        let outside = ProjectContext::Directory(PathBuf::from(".."));
+        assert!(confined.select("A repository", &outside).await.is_err());
+    }
+
+    #[test]
+    fn test_fit_cuts_the_first_exemplar_over_budget() {
+        let selector =
+            ContextSelector::new(Arc::new(KeywordEmbeddings)).with_config(ContextConfig {
+                max_prompt_tokens: 300,
+                ..ContextConfig::default()
+            });
+        let exemplar = |path: &str, lines: usize| Exemplar {
+            path: path.to_string(),
+            content: "let value = compute();\n".repeat(lines),
+            score: 1.0,
+            truncated: false,
+        };
+        let kept = selector.fit(
+            vec![
+                exemplar("a.rs", 10),
+                exemplar("b.rs", 40),
+                exemplar("c.rs", 1),
+            ],
+            100,
+        );
+
+        assert_eq!(kept.len(), 2);
+        assert!(!kept[0].truncated);
+        assert!(kept[1].truncated && kept[1].content.ends_with("compute();"));
+        let total: usize = kept.iter().map(|e| estimate_tokens(&e.render())).sum();
+        assert!(100 + total <= 300);
+
+        // Too little room left for anything useful
+        assert!(selector.fit(vec![exemplar("a.rs", 40)], 260).is_empty());
+    }
+}
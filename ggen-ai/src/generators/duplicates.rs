@@ -0,0 +1,660 @@
+//! Duplicate concepts in generated ontologies
+//!
+//! Models often define the same concept twice under different names, such as
+//! `ex:Customer` and `ex:Client`. A [`ConceptDeduplicator`] embeds the label
+//! and comment of every class and property declared in a graph, and pairs of
+//! the same kind whose cosine similarity reaches the threshold are grouped
+//! into clusters. Each cluster keeps one concept, chosen by the
+//! [`MergeStrategy`]; the others are reported as its duplicates, or merged
+//! into it: every reference is rewritten to the kept concept, and the
+//! duplicate stays declared, linked with `owl:equivalentClass` or
+//! `owl:equivalentProperty`, so nothing in the graph is left dangling.
+
+use crate::error::{GgenAiError, Result};
+use crate::generators::namespace::{self, IriKind, SlugRules, OWL, RDF, RDFS};
+use async_trait::async_trait;
+use oxigraph::io::{RdfFormat, RdfParser, RdfSerializer};
+use oxigraph::model::{NamedNode, NamedOrBlankNode, Term, Triple};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::sync::Arc;
+
+/// Turns texts into vectors whose cosine similarity reflects how close
+/// their meanings are
+#[async_trait]
+pub trait EmbeddingModel: Send + Sync + std::fmt::Debug {
+    /// One vector per text, in order
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>>;
+}
+
+/// Which concept of a cluster of duplicates is kept
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MergeStrategy {
+    /// The one declared first in the generated Turtle
+    #[default]
+    KeepFirst,
+    /// The one the rest of the graph refers to most; ties go to the first
+    KeepMostReferenced,
+}
+
+/// When concepts count as duplicates and what happens to them
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct DuplicateConfig {
+    /// Cosine similarity from which two concepts are duplicates
+    #[serde(default = "default_threshold")]
+    pub threshold: f32,
+    #[serde(default)]
+    pub strategy: MergeStrategy,
+    /// Merge duplicates unless a request says otherwise, instead of only
+    /// reporting them
+    #[serde(default)]
+    pub auto_merge: bool,
+}
+
+fn default_threshold() -> f32 {
+    0.9
+}
+
+impl Default for DuplicateConfig {
+    fn default() -> Self {
+        Self {
+            threshold: default_threshold(),
+            strategy: MergeStrategy::default(),
+            auto_merge: false,
+        }
+    }
+}
+
+/// A concept found to duplicate another
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DuplicateConcept {
+    /// IRI of the concept kept
+    pub keep: String,
+    /// IRI of its duplicate
+    pub duplicate: String,
+    pub kind: IriKind,
+    /// Cosine similarity of the two
+    pub similarity: f32,
+    /// Whether the duplicate was merged into the kept concept, rather than
+    /// suggested for merging
+    pub merged: bool,
+}
+
+/// Turtle with its duplicate concepts reported or merged
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeduplicatedOntology {
+    /// The input as it was when only reporting
+    pub turtle: String,
+    pub duplicates: Vec<DuplicateConcept>,
+}
+
+/// A class or property declared in the graph
+struct Concept {
+    iri: String,
+    kind: IriKind,
+    text: String,
+    references: usize,
+}
+
+/// Finds and merges duplicate concepts with an embedding model
+#[derive(Debug, Clone)]
+pub struct ConceptDeduplicator {
+    model: Arc<dyn EmbeddingModel>,
+    config: DuplicateConfig,
+}
+
+impl ConceptDeduplicator {
+    /// Deduplicator with the default configuration
+    pub fn new(model: Arc<dyn EmbeddingModel>) -> Self {
+        Self {
+            model,
+            config: DuplicateConfig::default(),
+        }
+    }
+
+    pub fn with_config(mut self, config: DuplicateConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn config(&self) -> &DuplicateConfig {
+        &self.config
+    }
+
+    /// Report the duplicate concepts in `turtle`, merging them if `merge` is
+    /// set
+    pub async fn apply(&self, turtle: &str, merge: bool) -> Result<DeduplicatedOntology> {
+        let graph = Graph::parse(turtle)?;
+        let mut duplicates = self.find(&graph).await?;
+        if !merge || duplicates.is_empty() {
+            return Ok(DeduplicatedOntology {
+                turtle: turtle.to_string(),
+                duplicates,
+            });
+        }
+        let merged = graph.merge(&duplicates)?;
+        for duplicate in &mut duplicates {
+            duplicate.merged = true;
+        }
+        Ok(DeduplicatedOntology {
+            turtle: merged.serialize()?,
+            duplicates,
+        })
+    }
+
+    /// Duplicates in `graph`, ordered by the concept kept, then by duplicate
+    async fn find(&self, graph: &Graph) -> Result<Vec<DuplicateConcept>> {
+        let concepts = graph.concepts();
+        if concepts.len() < 2 {
+            return Ok(Vec::new());
+        }
+        let texts: Vec<String> = concepts.iter().map(|c| c.text.clone()).collect();
+        let vectors = self.model.embed(&texts).await?;
+        if vectors.len() != concepts.len() {
+            return Err(GgenAiError::OntologyGeneration(format!(
+                "Embedding model returned {} vectors for {} concepts",
+                vectors.len(),
+                concepts.len()
+            )));
+        }
+
+        // Union the pairs above the threshold into clusters
+        let mut parent: Vec<usize> = (0..concepts.len()).collect();
+        fn root(parent: &mut [usize], mut i: usize) -> usize {
+            while parent[i] != i {
+                parent[i] = parent[parent[i]];
+                i = parent[i];
+            }
+            i
+        }
+        for i in 0..concepts.len() {
+            for j in i + 1..concepts.len() {
+                if concepts[i].kind != concepts[j].kind {
+                    continue;
+                }
+                if cosine(&vectors[i], &vectors[j]) >= self.config.threshold {
+                    let (a, b) = (root(&mut parent, i), root(&mut parent, j));
+                    parent[a.max(b)] = a.min(b);
+                }
+            }
+        }
+        let mut clusters: Vec<Vec<usize>> = Vec::new();
+        let mut cluster_of = HashMap::new();
+        for i in 0..concepts.len() {
+            let cluster = *cluster_of.entry(root(&mut parent, i)).or_insert_with(|| {
+                clusters.push(Vec::new());
+                clusters.len() - 1
+            });
+            clusters[cluster].push(i);
+        }
+
+        let mut duplicates = Vec::new();
+        for members in clusters.iter().filter(|members| members.len() > 1) {
+            // Members are in declaration order
+            let keep = match self.config.strategy {
+                MergeStrategy::KeepFirst => members[0],
+                MergeStrategy::KeepMostReferenced => *members
+                    .iter()
+                    .rev()
+                    .max_by_key(|&&i| concepts[i].references)
+                    .expect("clusters are not empty"),
+            };
+            duplicates.extend(
+                members
+                    .iter()
+                    .filter(|&&i| i != keep)
+                    .map(|&i| DuplicateConcept {
+                        keep: concepts[keep].iri.clone(),
+                        duplicate: concepts[i].iri.clone(),
+                        kind: concepts[i].kind,
+                        similarity: cosine(&vectors[keep], &vectors[i]),
+                        merged: false,
+                    }),
+            );
+        }
+        Ok(duplicates)
+    }
+}
+
+/// Cosine similarity; zero for zero vectors and vectors of different length
+pub(crate) fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norms = norm(a) * norm(b);
+    if norms == 0.0 {
+        0.0
+    } else {
+        dot / norms
+    }
+}
+
+/// Triples of a Turtle document with the prefixes it declared
+pub(crate) struct Graph {
+    pub(crate) triples: Vec<Triple>,
+    prefixes: Vec<(String, String)>,
+}
+
+impl Graph {
+    pub(crate) fn parse(turtle: &str) -> Result<Self> {
+        let mut parser = RdfParser::from_format(RdfFormat::Turtle).for_reader(turtle.as_bytes());
+        let mut triples = Vec::new();
+        for quad in &mut parser {
+            let quad = quad.map_err(|err| {
+                GgenAiError::OntologyGeneration(format!("Invalid Turtle: {}", err))
+            })?;
+            triples.push(Triple::new(quad.subject, quad.predicate, quad.object));
+        }
+        let prefixes = parser
+            .prefixes()
+            .map(|(prefix, iri)| (prefix.to_string(), iri.to_string()))
+            .collect();
+        Ok(Self { triples, prefixes })
+    }
+
+    /// Classes and properties described in the graph, in the order they are
+    /// first described
+    fn concepts(&self) -> Vec<Concept> {
+        let kinds = namespace::classify(&self.triples, namespace::is_standard);
+        let label = format!("{}label", RDFS);
+        let comment = format!("{}comment", RDFS);
+        let mut order = Vec::new();
+        let mut labels: HashMap<&str, &str> = HashMap::new();
+        let mut comments: HashMap<&str, &str> = HashMap::new();
+        let mut references: HashMap<&str, usize> = HashMap::new();
+        for triple in &self.triples {
+            if let NamedOrBlankNode::NamedNode(subject) = &triple.subject {
+                let subject = subject.as_str();
+                if !order.contains(&subject) {
+                    order.push(subject);
+                }
+                if let Term::Literal(literal) = &triple.object {
+                    let predicate = triple.predicate.as_str();
+                    if predicate == label {
+                        labels.entry(subject).or_insert(literal.value());
+                    } else if predicate == comment {
+                        comments.entry(subject).or_insert(literal.value());
+                    }
+                }
+            }
+            *references.entry(triple.predicate.as_str()).or_default() += 1;
+            if let Term::NamedNode(object) = &triple.object {
+                *references.entry(object.as_str()).or_default() += 1;
+            }
+        }
+        order
+            .into_iter()
+            .filter_map(|iri| {
+                let kind = *kinds.get(iri)?;
+                if !matches!(kind, IriKind::Class | IriKind::Property) {
+                    return None;
+                }
+                let label = match labels.get(iri) {
+                    Some(label) => label.to_string(),
+                    None => SlugRules::default()
+                        .words(namespace::local_name(iri))
+                        .join(" ")
+                        .to_lowercase(),
+                };
+                let text = match comments.get(iri) {
+                    Some(comment) => format!("{}: {}", label, comment),
+                    None => label,
+                };
+                Some(Concept {
+                    iri: iri.to_string(),
+                    kind,
+                    text,
+                    references: references.get(iri).copied().unwrap_or_default(),
+                })
+            })
+            .collect()
+    }
+
+    /// The graph with every duplicate merged into the concept kept
+    ///
+    /// References to a duplicate, and everything said about it but its type,
+    /// label and comment, move to the kept concept. The duplicate stays
+    /// declared and is linked to it as equivalent. Triples the rewrite makes
+    /// reflexive, such as a duplicate's `rdfs:subClassOf` the kept class, are
+    /// dropped.
+    fn merge(&self, duplicates: &[DuplicateConcept]) -> Result<Self> {
+        let rdf_type = format!("{}type", RDF);
+        let own = [
+            rdf_type.clone(),
+            format!("{}label", RDFS),
+            format!("{}comment", RDFS),
+        ];
+        let into: HashMap<&str, NamedNode> = duplicates
+            .iter()
+            .map(|d| {
+                (
+                    d.duplicate.as_str(),
+                    NamedNode::new_unchecked(d.keep.as_str()),
+                )
+            })
+            .collect();
+        let rewrite = |node: &NamedNode| into.get(node.as_str()).cloned();
+
+        let mut seen = HashSet::new();
+        let mut triples = Vec::new();
+        let mut typed = HashSet::new();
+        for triple in &self.triples {
+            let subject = match &triple.subject {
+                NamedOrBlankNode::NamedNode(node) => Some(node),
+                _ => None,
+            };
+            let keeps_subject = subject.is_some_and(|subject| {
+                into.contains_key(subject.as_str())
+                    && own.iter().any(|own| own == triple.predicate.as_str())
+            });
+            let merged = if keeps_subject {
+                if triple.predicate.as_str() == rdf_type {
+                    typed.insert(subject.expect("checked above").as_str().to_string());
+                }
+                triple.clone()
+            } else {
+                let new_subject = match subject.and_then(rewrite) {
+                    Some(node) => NamedOrBlankNode::NamedNode(node),
+                    None => triple.subject.clone(),
+                };
+                let new_object = match &triple.object {
+                    Term::NamedNode(node) => rewrite(node).map(Term::NamedNode),
+                    _ => None,
+                }
+                .unwrap_or_else(|| triple.object.clone());
+                let new_predicate =
+                    rewrite(&triple.predicate).unwrap_or_else(|| triple.predicate.clone());
+                let rewritten = Triple::new(new_subject, new_predicate, new_object);
+                let reflexive = |t: &Triple| match (&t.subject, &t.object) {
+                    (NamedOrBlankNode::NamedNode(s), Term::NamedNode(o)) => s == o,
+                    _ => false,
+                };
+                if reflexive(&rewritten) && !reflexive(triple) {
+                    continue;
+                }
+                rewritten
+            };
+            if seen.insert(merged.clone()) {
+                triples.push(merged);
+            }
+        }
+
+        for duplicate in duplicates {
+            let node = NamedNode::new_unchecked(duplicate.duplicate.as_str());
+            let (declared_as, equivalent) = match duplicate.kind {
+                IriKind::Class => ("Class", "equivalentClass"),
+                _ => ("Property", "equivalentProperty"),
+            };
+            if !typed.contains(&duplicate.duplicate) {
+                let class = match duplicate.kind {
+                    IriKind::Class => format!("{}{}", OWL, declared_as),
+                    _ => format!("{}{}", RDF, declared_as),
+                };
+                triples.push(Triple::new(
+                    node.clone(),
+                    NamedNode::new_unchecked(rdf_type.as_str()),
+                    NamedNode::new_unchecked(class),
+                ));
+            }
+            triples.push(Triple::new(
+                NamedNode::new_unchecked(duplicate.keep.as_str()),
+                NamedNode::new_unchecked(format!("{}{}", OWL, equivalent)),
+                node,
+            ));
+        }
+
+        let merged = Self {
+            triples,
+            prefixes: self.prefixes.clone(),
+        };
+        let dangling: Vec<&str> = self
+            .described()
+            .difference(&merged.described())
+            .copied()
+            .collect();
+        if !dangling.is_empty() {
+            return Err(GgenAiError::OntologyGeneration(format!(
+                "Merging duplicate concepts would leave undescribed: {}",
+                dangling.join(", ")
+            )));
+        }
+        Ok(merged)
+    }
+
+    /// IRIs the graph says something about
+    fn described(&self) -> BTreeSet<&str> {
+        self.triples
+            .iter()
+            .filter_map(|triple| match &triple.subject {
+                NamedOrBlankNode::NamedNode(node) => Some(node.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub(crate) fn serialize(&self) -> Result<String> {
+        let standard = [("rdf", RDF), ("rdfs", RDFS), ("owl", OWL)];
+        let mut serializer = RdfSerializer::from_format(RdfFormat::Turtle);
+        let mut declared = HashSet::new();
+        let prefixes = self
+            .prefixes
+            .iter()
+            .map(|(prefix, iri)| (prefix.as_str(), iri.as_str()))
+            .chain(standard);
+        for (prefix, iri) in prefixes {
+            if !declared.insert(prefix) {
+                continue;
+            }
+            serializer = serializer.with_prefix(prefix, iri).map_err(|err| {
+                GgenAiError::OntologyGeneration(format!("Invalid prefix {}: {}", prefix, err))
+            })?;
+        }
+        let mut writer = serializer.for_writer(Vec::new());
+        for triple in &self.triples {
+            writer.serialize_triple(triple)?;
+        }
+        Ok(String::from_utf8(writer.finish()?)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHOP: &str = "http://example.org/shop#";
+
+    const GENERATED: &str = r#"
+@prefix ex: <http://example.org/shop#> .
+@prefix owl: <http://www.w3.org/2002/07/owl#> .
+@prefix rdfs: <http://www.w3.org/2000/01/rdf-schema#> .
+
+ex:Customer a owl:Class ;
+    rdfs:label "Customer" ;
+    rdfs:comment "A person who buys goods" .
+ex:Party a owl:Class ;
+    rdfs:label "Party" .
+ex:Order a owl:Class ;
+    rdfs:label "Order" .
+ex:Client a owl:Class ;
+    rdfs:label "Client" ;
+    rdfs:comment "Someone purchasing products" ;
+    rdfs:subClassOf ex:Party .
+ex:placedBy a owl:ObjectProperty ;
+    rdfs:domain ex:Order ;
+    rdfs:range ex:Client .
+ex:order1 a ex:Order ;
+    ex:placedBy ex:bob .
+ex:bob a ex:Client .
+"#;
+
+    /// Vectors by the start of the embedded text, making `Customer` and
+    /// `Client` near-identical and everything else orthogonal
+    #[derive(Debug)]
+    struct FakeEmbeddings;
+
+    #[async_trait]
+    impl EmbeddingModel for FakeEmbeddings {
+        async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+            Ok(texts
+                .iter()
+                .map(|text| {
+                    let label = text.split(':').next().unwrap_or_default();
+                    match label {
+                        "Customer" => vec![1.0, 0.0, 0.0, 0.0],
+                        "Client" => vec![0.98, 0.2, 0.0, 0.0],
+                        "Party" => vec![0.0, 0.0, 1.0, 0.0],
+                        "Order" => vec![0.0, 0.0, 0.0, 1.0],
+                        "placed by" => vec![0.0, 1.0, 0.0, 0.0],
+                        other => panic!("No vector for '{}'", other),
+                    }
+                })
+                .collect())
+        }
+    }
+
+    fn deduplicator(config: DuplicateConfig) -> ConceptDeduplicator {
+        ConceptDeduplicator::new(Arc::new(FakeEmbeddings)).with_config(config)
+    }
+
+    fn ex(local: &str) -> String {
+        format!("{}{}", SHOP, local)
+    }
+
+    fn graph(turtle: &str) -> HashSet<Triple> {
+        Graph::parse(turtle).unwrap().triples.into_iter().collect()
+    }
+
+    fn triple(subject: &str, predicate: &str, object: &str) -> Triple {
+        Triple::new(
+            NamedNode::new_unchecked(subject),
+            NamedNode::new_unchecked(predicate),
+            NamedNode::new_unchecked(object),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_duplicates_are_reported_without_changing_the_graph() {
+        let result = deduplicator(DuplicateConfig::default())
+            .apply(GENERATED, false)
+            .await
+            .unwrap();
+
+        assert_eq!(result.turtle, GENERATED);
+        assert_eq!(result.duplicates.len(), 1, "{:?}", result.duplicates);
+        let duplicate = &result.duplicates[0];
+        assert_eq!(duplicate.keep, ex("Customer"));
+        assert_eq!(duplicate.duplicate, ex("Client"));
+        assert_eq!(duplicate.kind, IriKind::Class);
+        assert!(
+            (duplicate.similarity - 0.98).abs() < 0.01,
+            "{}",
+            duplicate.similarity
+        );
+        assert!(!duplicate.merged);
+    }
+
+    #[tokio::test]
+    async fn test_merging_rewrites_references_to_the_kept_concept() {
+        let result = deduplicator(DuplicateConfig::default())
+            .apply(GENERATED, true)
+            .await
+            .unwrap();
+        assert!(result.duplicates[0].merged);
+
+        let merged = graph(&result.turtle);
+        let rdf_type = format!("{}type", RDF);
+        let expected = [
+            triple(
+                &ex("Customer"),
+                &format!("{}equivalentClass", OWL),
+                &ex("Client"),
+            ),
+            triple(
+                &ex("Customer"),
+                &format!("{}subClassOf", RDFS),
+                &ex("Party"),
+            ),
+            triple(&ex("placedBy"), &format!("{}range", RDFS), &ex("Customer")),
+            triple(&ex("bob"), &rdf_type, &ex("Customer")),
+            // The duplicate stays declared
+            triple(&ex("Client"), &rdf_type, &format!("{}Class", OWL)),
+        ];
+        for triple in &expected {
+            assert!(
+                merged.contains(triple),
+                "missing {}\n{}",
+                triple,
+                result.turtle
+            );
+        }
+        let unexpected = [
+            triple(&ex("Client"), &format!("{}subClassOf", RDFS), &ex("Party")),
+            triple(&ex("placedBy"), &format!("{}range", RDFS), &ex("Client")),
+            triple(&ex("bob"), &rdf_type, &ex("Client")),
+        ];
+        for triple in &unexpected {
+            assert!(
+                !merged.contains(triple),
+                "kept {}\n{}",
+                triple,
+                result.turtle
+            );
+        }
+
+        // Every IRI of the ontology that is referred to is still described
+        let described: HashSet<String> = merged
+            .iter()
+            .filter_map(|t| match &t.subject {
+                NamedOrBlankNode::NamedNode(node) => Some(node.as_str().to_string()),
+                _ => None,
+            })
+            .collect();
+        for triple in &merged {
+            if let Term::NamedNode(object) = &triple.object {
+                if object.as_str().starts_with(SHOP) {
+                    assert!(described.contains(object.as_str()), "dangling {}", object);
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_merge_strategy_picks_the_concept_kept() {
+        let config = DuplicateConfig {
+            strategy: MergeStrategy::KeepMostReferenced,
+            ..DuplicateConfig::default()
+        };
+        let result = deduplicator(config).apply(GENERATED, true).await.unwrap();
+        assert_eq!(result.duplicates[0].keep, ex("Client"));
+        assert_eq!(result.duplicates[0].duplicate, ex("Customer"));
+
+        let merged = graph(&result.turtle);
+        assert!(merged.contains(&triple(
+            &ex("Client"),
+            &format!("{}equivalentClass", OWL),
+            &ex("Customer")
+        )));
+    }
+
+    #[tokio::test]
+    async fn test_threshold_is_configurable() {
+        let config = DuplicateConfig {
+            threshold: 0.99,
+            ..DuplicateConfig::default()
+        };
+        let result = deduplicator(config).apply(GENERATED, true).await.unwrap();
+        assert!(result.duplicates.is_empty());
+        assert_eq!(result.turtle, GENERATED);
+    }
+
+    #[test]
+    fn test_cosine() {
+        assert!((cosine(&[1.0, 0.0], &[1.0, 0.0]) - 1.0).abs() < 1e-6);
+        assert_eq!(cosine(&[1.0, 0.0], &[0.0, 1.0]), 0.0);
+        assert_eq!(cosine(&[0.0, 0.0], &[1.0, 0.0]), 0.0);
+        assert_eq!(cosine(&[1.0], &[1.0, 0.0]), 0.0);
+    }
+}
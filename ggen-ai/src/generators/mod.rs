@@ -1,16 +1,28 @@
 //! AI-powered generators for ggen
 
+pub mod context;
+pub mod duplicates;
+pub mod namespace;
 pub mod natural_search;
 pub mod ontology;
 pub mod refactor;
+pub mod review;
 pub mod sparql;
+pub mod style;
 pub mod template;
 pub mod validator;
 
 // Re-export generator types
+pub use context::{ContextConfig, ContextSelector, Exemplar, ProjectContext, Snippet};
+pub use duplicates::{
+    ConceptDeduplicator, DuplicateConcept, DuplicateConfig, EmbeddingModel, MergeStrategy,
+};
+pub use namespace::{IriKind, IriRename, IriStyle, NamespacePolicy, NormalizedOntology, SlugRules};
 pub use natural_search::NaturalSearchGenerator;
 pub use ontology::OntologyGenerator;
 pub use refactor::RefactorAssistant;
+pub use review::{AnchoredComment, CodeReview, LineRange, ReviewComment, ReviewSeverity};
 pub use sparql::SparqlGenerator;
+pub use style::{ResponseStyle, Tone};
 pub use template::TemplateGenerator;
 pub use validator::{QualityMetrics, TemplateValidator, ValidationIssue, ValidationResult};
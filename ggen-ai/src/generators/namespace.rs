@@ -0,0 +1,732 @@
+//! Namespace policy for generated ontologies
+//!
+//! Models make up their own base IRIs, so SPARQL written against a fixed
+//! namespace finds nothing in what they generate. A [`NamespacePolicy`] is
+//! enforced on the parsed graph: every IRI outside the standard and kept
+//! vocabularies is moved into the policy's base IRI, with a local name minted
+//! in the style for its kind, and renamed the same way everywhere it occurs.
+//! The graph is serialized again with the policy's prefixes only. Renames are
+//! reported, and can also be recorded in the graph as `owl:sameAs` links to
+//! the original IRIs. Two IRIs that end up with the same name are an error.
+
+use crate::error::{GgenAiError, Result};
+use crate::generators::duplicates::DuplicateConcept;
+use oxigraph::io::{RdfFormat, RdfParser, RdfSerializer};
+use oxigraph::model::{NamedNode, NamedOrBlankNode, Term, Triple};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+pub(crate) const RDF: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#";
+pub(crate) const RDFS: &str = "http://www.w3.org/2000/01/rdf-schema#";
+pub(crate) const OWL: &str = "http://www.w3.org/2002/07/owl#";
+const XSD: &str = "http://www.w3.org/2001/XMLSchema#";
+
+/// Vocabularies that are never renamed, by their usual prefix
+const STANDARD_NAMESPACES: [(&str, &str); 4] =
+    [("rdf", RDF), ("rdfs", RDFS), ("owl", OWL), ("xsd", XSD)];
+
+/// How local names are written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IriStyle {
+    /// `PurchaseOrder`
+    PascalCase,
+    /// `purchaseOrder`
+    CamelCase,
+    /// `purchase-order`
+    KebabCase,
+    /// `purchase_order`
+    SnakeCase,
+    /// The local name as generated
+    Preserve,
+}
+
+impl IriStyle {
+    /// `name` split into words by `rules` and written in this style
+    ///
+    /// Names without any letters or digits are left as they are.
+    pub fn apply(self, name: &str, rules: &SlugRules) -> String {
+        let words = rules.words(name);
+        if words.is_empty() {
+            return name.to_string();
+        }
+        let lower = || words.iter().map(|word| word.to_lowercase());
+        match self {
+            Self::PascalCase => words
+                .iter()
+                .map(|word| capitalize(word, rules.keep_acronyms))
+                .collect(),
+            Self::CamelCase => {
+                words[0].to_lowercase()
+                    + &words[1..]
+                        .iter()
+                        .map(|word| capitalize(word, rules.keep_acronyms))
+                        .collect::<String>()
+            }
+            Self::KebabCase => lower().collect::<Vec<_>>().join("-"),
+            Self::SnakeCase => lower().collect::<Vec<_>>().join("_"),
+            Self::Preserve => name.to_string(),
+        }
+    }
+}
+
+fn capitalize(word: &str, keep_acronyms: bool) -> String {
+    if keep_acronyms && word.chars().count() > 1 && !word.chars().any(char::is_lowercase) {
+        return word.to_string();
+    }
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first
+            .to_uppercase()
+            .chain(chars.flat_map(char::to_lowercase))
+            .collect(),
+        None => String::new(),
+    }
+}
+
+/// How multi-word concepts are split into words
+///
+/// Words are separated by anything that isn't a letter or digit and by case
+/// changes, so `purchase order`, `purchase_order`, `purchase-order` and
+/// `PurchaseOrder` all have the words `purchase` and `order`; an acronym
+/// ends before the capital starting the next word, as in `HTTPRequest`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SlugRules {
+    /// Write all-caps words such as `HTTP` as they are in Pascal and camel
+    /// case instead of capitalizing only their first letter
+    #[serde(default)]
+    pub keep_acronyms: bool,
+    /// Words left out of minted names, matched case-insensitively, e.g.
+    /// `the`; a name made only of stop words keeps them
+    #[serde(default)]
+    pub stop_words: Vec<String>,
+}
+
+impl SlugRules {
+    pub(crate) fn words(&self, name: &str) -> Vec<String> {
+        let chars: Vec<char> = name.chars().collect();
+        let mut words = Vec::new();
+        let mut word = String::new();
+        for (i, &c) in chars.iter().enumerate() {
+            if !c.is_alphanumeric() {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+                continue;
+            }
+            let prev = (!word.is_empty()).then(|| chars[i - 1]);
+            let next = chars.get(i + 1).copied();
+            let boundary = c.is_uppercase()
+                && prev.is_some_and(|p| {
+                    p.is_lowercase()
+                        || p.is_numeric()
+                        || (p.is_uppercase() && next.is_some_and(char::is_lowercase))
+                });
+            if boundary {
+                words.push(std::mem::take(&mut word));
+            }
+            word.push(c);
+        }
+        if !word.is_empty() {
+            words.push(word);
+        }
+        let kept: Vec<String> = words
+            .iter()
+            .filter(|word| {
+                !self
+                    .stop_words
+                    .iter()
+                    .any(|stop| stop.eq_ignore_ascii_case(word))
+            })
+            .cloned()
+            .collect();
+        if kept.is_empty() {
+            words
+        } else {
+            kept
+        }
+    }
+}
+
+/// What an IRI names, as far as the graph tells
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IriKind {
+    /// Anything that is neither a class nor a property
+    Individual,
+    /// Declared a class, or used as one in `rdf:type`, `rdfs:subClassOf`,
+    /// `rdfs:domain` or `rdfs:range`
+    Class,
+    /// Declared a property, or used as a predicate
+    Property,
+    /// The `owl:Ontology` itself, named after the base IRI
+    Ontology,
+}
+
+/// An IRI moved by a [`NamespacePolicy`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IriRename {
+    pub from: String,
+    pub to: String,
+    pub kind: IriKind,
+}
+
+/// Turtle that conforms to a [`NamespacePolicy`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NormalizedOntology {
+    pub turtle: String,
+    /// Every IRI that was renamed, ordered by its original IRI
+    pub renames: Vec<IriRename>,
+    /// Concepts found to duplicate others, when the generator looks for them
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub duplicates: Vec<DuplicateConcept>,
+}
+
+/// Where and how the terms of generated ontologies are named
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NamespacePolicy {
+    /// Namespace terms are minted in, ending in `#` or `/`
+    pub base_iri: String,
+    /// Prefix of `base_iri` in the serialized Turtle
+    pub prefix: String,
+    #[serde(default = "default_class_style")]
+    pub class_style: IriStyle,
+    #[serde(default = "default_property_style")]
+    pub property_style: IriStyle,
+    #[serde(default = "default_individual_style")]
+    pub individual_style: IriStyle,
+    #[serde(default)]
+    pub slug: SlugRules,
+    /// Vocabularies whose terms are used as they are, by prefix, e.g.
+    /// `schema` for `https://schema.org/`; RDF, RDFS, OWL and XSD always are
+    #[serde(default)]
+    pub keep_namespaces: BTreeMap<String, String>,
+    /// Also link every renamed IRI to its original with `owl:sameAs`, so
+    /// data using the original names can still be related to the ontology
+    #[serde(default)]
+    pub same_as: bool,
+}
+
+fn default_class_style() -> IriStyle {
+    IriStyle::PascalCase
+}
+
+fn default_property_style() -> IriStyle {
+    IriStyle::CamelCase
+}
+
+fn default_individual_style() -> IriStyle {
+    IriStyle::Preserve
+}
+
+impl NamespacePolicy {
+    /// Policy with PascalCase classes, camelCase properties and individuals
+    /// named as generated
+    pub fn new(base_iri: impl Into<String>, prefix: impl Into<String>) -> Self {
+        Self {
+            base_iri: base_iri.into(),
+            prefix: prefix.into(),
+            class_style: default_class_style(),
+            property_style: default_property_style(),
+            individual_style: default_individual_style(),
+            slug: SlugRules::default(),
+            keep_namespaces: BTreeMap::new(),
+            same_as: false,
+        }
+    }
+
+    /// Leave the terms of `namespace` as they are, written with `prefix`
+    pub fn keep_namespace(
+        mut self, prefix: impl Into<String>, namespace: impl Into<String>,
+    ) -> Self {
+        self.keep_namespaces.insert(prefix.into(), namespace.into());
+        self
+    }
+
+    /// Record renames as `owl:sameAs` links in the graph as well
+    pub fn with_same_as(mut self, same_as: bool) -> Self {
+        self.same_as = same_as;
+        self
+    }
+
+    fn validate(&self) -> Result<()> {
+        let invalid = |field: &str, reason: String| GgenAiError::InvalidConfig {
+            field: field.to_string(),
+            reason,
+        };
+        if !self.base_iri.ends_with(['#', '/']) {
+            return Err(invalid(
+                "base_iri",
+                format!("'{}' must end with '#' or '/'", self.base_iri),
+            ));
+        }
+        NamedNode::new(self.base_iri.as_str())
+            .map_err(|err| invalid("base_iri", format!("'{}': {}", self.base_iri, err)))?;
+        let valid_prefix = self.prefix.starts_with(char::is_alphabetic)
+            && self
+                .prefix
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '-' || c == '_');
+        if !valid_prefix {
+            return Err(invalid(
+                "prefix",
+                format!("'{}' isn't a valid Turtle prefix", self.prefix),
+            ));
+        }
+        Ok(())
+    }
+
+    /// IRI of the ontology itself
+    fn ontology_iri(&self) -> &str {
+        self.base_iri.trim_end_matches(['#', '/'])
+    }
+
+    /// Whether `iri` belongs to a vocabulary that is never renamed
+    fn is_kept(&self, iri: &str) -> bool {
+        is_standard(iri)
+            || self
+                .keep_namespaces
+                .values()
+                .any(|namespace| iri.starts_with(namespace.as_str()))
+    }
+
+    /// IRI `iri` has under this policy
+    pub fn mint(&self, iri: &str, kind: IriKind) -> String {
+        let style = match kind {
+            IriKind::Ontology => return self.ontology_iri().to_string(),
+            IriKind::Class => self.class_style,
+            IriKind::Property => self.property_style,
+            IriKind::Individual => self.individual_style,
+        };
+        format!(
+            "{}{}",
+            self.base_iri,
+            style.apply(local_name(iri), &self.slug)
+        )
+    }
+
+    /// Rewrite `turtle` to conform to this policy
+    ///
+    /// Fails if the Turtle doesn't parse or two IRIs would be renamed to the
+    /// same one.
+    pub fn apply(&self, turtle: &str) -> Result<NormalizedOntology> {
+        self.validate()?;
+        let mut triples = Vec::new();
+        for quad in RdfParser::from_format(RdfFormat::Turtle).for_reader(turtle.as_bytes()) {
+            let quad = quad.map_err(|err| {
+                GgenAiError::OntologyGeneration(format!("Invalid Turtle: {}", err))
+            })?;
+            triples.push(Triple::new(quad.subject, quad.predicate, quad.object));
+        }
+
+        let kinds = classify(&triples, |iri| self.is_kept(iri));
+        let mut targets: BTreeMap<String, Vec<&str>> = BTreeMap::new();
+        let mut renamed = HashMap::new();
+        let mut renames = Vec::new();
+        for (iri, kind) in &kinds {
+            let to = self.mint(iri, *kind);
+            targets.entry(to.clone()).or_default().push(iri);
+            if to != *iri {
+                renamed.insert(iri.clone(), NamedNode::new_unchecked(to.as_str()));
+                renames.push(IriRename {
+                    from: iri.clone(),
+                    to,
+                    kind: *kind,
+                });
+            }
+        }
+        let collisions: Vec<String> = targets
+            .iter()
+            .filter(|(_, sources)| sources.len() > 1)
+            .map(|(to, sources)| format!("{} <- {}", to, sources.join(", ")))
+            .collect();
+        if !collisions.is_empty() {
+            return Err(GgenAiError::OntologyGeneration(format!(
+                "IRIs collide after applying the namespace policy: {}",
+                collisions.join("; ")
+            )));
+        }
+
+        let rename = |node: NamedNode| renamed.get(node.as_str()).cloned().unwrap_or(node);
+        let mut triples: Vec<Triple> = triples
+            .into_iter()
+            .map(|triple| {
+                let subject = match triple.subject {
+                    NamedOrBlankNode::NamedNode(node) => NamedOrBlankNode::NamedNode(rename(node)),
+                    other => other,
+                };
+                let object = match triple.object {
+                    Term::NamedNode(node) => Term::NamedNode(rename(node)),
+                    other => other,
+                };
+                Triple::new(subject, rename(triple.predicate), object)
+            })
+            .collect();
+        if self.same_as {
+            let same_as = NamedNode::new_unchecked(format!("{}sameAs", OWL));
+            triples.extend(renames.iter().map(|rename| {
+                Triple::new(
+                    NamedNode::new_unchecked(rename.to.as_str()),
+                    same_as.clone(),
+                    NamedNode::new_unchecked(rename.from.as_str()),
+                )
+            }));
+        }
+
+        Ok(NormalizedOntology {
+            turtle: self.serialize(&triples)?,
+            renames,
+            duplicates: Vec::new(),
+        })
+    }
+
+    fn serialize(&self, triples: &[Triple]) -> Result<String> {
+        let prefixes = STANDARD_NAMESPACES
+            .iter()
+            .map(|(prefix, namespace)| (*prefix, *namespace))
+            .chain(
+                self.keep_namespaces
+                    .iter()
+                    .map(|(prefix, namespace)| (prefix.as_str(), namespace.as_str())),
+            )
+            .chain([(self.prefix.as_str(), self.base_iri.as_str())]);
+        let mut serializer = RdfSerializer::from_format(RdfFormat::Turtle);
+        for (prefix, namespace) in prefixes {
+            serializer = serializer.with_prefix(prefix, namespace).map_err(|err| {
+                GgenAiError::InvalidConfig {
+                    field: format!("prefix {}", prefix),
+                    reason: err.to_string(),
+                }
+            })?;
+        }
+        let mut writer = serializer.for_writer(Vec::new());
+        for triple in triples {
+            writer.serialize_triple(triple)?;
+        }
+        Ok(String::from_utf8(writer.finish()?)?)
+    }
+}
+
+/// Kind of every IRI in `triples` that isn't kept, by IRI
+pub(crate) fn classify(
+    triples: &[Triple], is_kept: impl Fn(&str) -> bool,
+) -> BTreeMap<String, IriKind> {
+    let rdf_type = format!("{}type", RDF);
+    let mut kinds = BTreeMap::new();
+    let mut mark = |iri: &str, kind: IriKind| {
+        if is_kept(iri) {
+            return;
+        }
+        let known = kinds.entry(iri.to_string()).or_insert(kind);
+        *known = (*known).max(kind);
+    };
+    for triple in triples {
+        let subject = match &triple.subject {
+            NamedOrBlankNode::NamedNode(node) => Some(node.as_str()),
+            _ => None,
+        };
+        let object = match &triple.object {
+            Term::NamedNode(node) => Some(node.as_str()),
+            _ => None,
+        };
+        let predicate = triple.predicate.as_str();
+        mark(predicate, IriKind::Property);
+        if let Some(subject) = subject {
+            mark(subject, IriKind::Individual);
+        }
+        if let Some(object) = object {
+            mark(object, IriKind::Individual);
+        }
+        let (subject_kind, object_kind) = if predicate == rdf_type {
+            match object.map(declared_kind) {
+                Some(Some(kind)) => (Some(kind), None),
+                _ => (None, Some(IriKind::Class)),
+            }
+        } else if let Some(local) = predicate.strip_prefix(RDFS) {
+            match local {
+                "subClassOf" => (Some(IriKind::Class), Some(IriKind::Class)),
+                "subPropertyOf" => (Some(IriKind::Property), Some(IriKind::Property)),
+                "domain" | "range" => (Some(IriKind::Property), Some(IriKind::Class)),
+                _ => (None, None),
+            }
+        } else if let Some(local) = predicate.strip_prefix(OWL) {
+            match local {
+                "equivalentClass" | "disjointWith" => (Some(IriKind::Class), Some(IriKind::Class)),
+                "equivalentProperty" | "inverseOf" => {
+                    (Some(IriKind::Property), Some(IriKind::Property))
+                }
+                _ => (None, None),
+            }
+        } else {
+            (None, None)
+        };
+        if let (Some(subject), Some(kind)) = (subject, subject_kind) {
+            mark(subject, kind);
+        }
+        if let (Some(object), Some(kind)) = (object, object_kind) {
+            mark(object, kind);
+        }
+    }
+    kinds
+}
+
+/// Whether `iri` belongs to RDF, RDFS, OWL or XSD
+pub(crate) fn is_standard(iri: &str) -> bool {
+    STANDARD_NAMESPACES
+        .iter()
+        .any(|(_, namespace)| iri.starts_with(namespace))
+}
+
+/// Kind a `rdf:type` object declares its subject to be, if it is a
+/// metaclass
+fn declared_kind(class: &str) -> Option<IriKind> {
+    match class.strip_prefix(OWL).or_else(|| class.strip_prefix(RDFS)) {
+        Some("Ontology") => Some(IriKind::Ontology),
+        Some("Class" | "Datatype") => Some(IriKind::Class),
+        Some(local) if local.ends_with("Property") => Some(IriKind::Property),
+        _ if class == format!("{}Property", RDF) => Some(IriKind::Property),
+        _ => None,
+    }
+}
+
+/// Part of `iri` after its namespace
+pub(crate) fn local_name(iri: &str) -> &str {
+    let iri = iri.trim_end_matches(['#', '/']);
+    iri.rsplit(['#', '/', ':']).next().unwrap_or(iri)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    const GENERATED: &str = r#"
+@prefix foo: <http://example.org/shop#> .
+@prefix bar: <http://made.up/vocab/> .
+@prefix owl: <http://www.w3.org/2002/07/owl#> .
+@prefix rdfs: <http://www.w3.org/2000/01/rdf-schema#> .
+@prefix xsd: <http://www.w3.org/2001/XMLSchema#> .
+
+<http://example.org/shop> a owl:Ontology .
+foo:purchase_order a owl:Class ;
+    rdfs:label "Purchase order" .
+bar:Customer a owl:Class .
+foo:placed-by a owl:ObjectProperty ;
+    rdfs:domain foo:purchase_order ;
+    rdfs:range bar:Customer .
+bar:TotalAmount a owl:DatatypeProperty ;
+    rdfs:range xsd:decimal .
+foo:order_42 a foo:purchase_order ;
+    foo:placed-by bar:alice .
+"#;
+
+    fn policy() -> NamespacePolicy {
+        NamespacePolicy::new("https://data.acme.com/ontology#", "acme")
+    }
+
+    fn graph(turtle: &str) -> HashSet<Triple> {
+        RdfParser::from_format(RdfFormat::Turtle)
+            .for_reader(turtle.as_bytes())
+            .map(|quad| {
+                let quad = quad.unwrap();
+                Triple::new(quad.subject, quad.predicate, quad.object)
+            })
+            .collect()
+    }
+
+    fn rename(from: &str, to: &str, kind: IriKind) -> IriRename {
+        IriRename {
+            from: from.to_string(),
+            to: to.to_string(),
+            kind,
+        }
+    }
+
+    #[test]
+    fn test_moves_terms_into_the_base_namespace() {
+        let normalized = policy().apply(GENERATED).unwrap();
+
+        let acme = "https://data.acme.com/ontology#";
+        assert_eq!(
+            normalized.renames,
+            vec![
+                rename(
+                    "http://example.org/shop",
+                    "https://data.acme.com/ontology",
+                    IriKind::Ontology
+                ),
+                rename(
+                    "http://example.org/shop#order_42",
+                    &format!("{}order_42", acme),
+                    IriKind::Individual
+                ),
+                rename(
+                    "http://example.org/shop#placed-by",
+                    &format!("{}placedBy", acme),
+                    IriKind::Property
+                ),
+                rename(
+                    "http://example.org/shop#purchase_order",
+                    &format!("{}PurchaseOrder", acme),
+                    IriKind::Class
+                ),
+                rename(
+                    "http://made.up/vocab/Customer",
+                    &format!("{}Customer", acme),
+                    IriKind::Class
+                ),
+                rename(
+                    "http://made.up/vocab/TotalAmount",
+                    &format!("{}totalAmount", acme),
+                    IriKind::Property
+                ),
+                rename(
+                    "http://made.up/vocab/alice",
+                    &format!("{}alice", acme),
+                    IriKind::Individual
+                ),
+            ]
+        );
+
+        let expected = r#"
+@prefix acme: <https://data.acme.com/ontology#> .
+@prefix owl: <http://www.w3.org/2002/07/owl#> .
+@prefix rdfs: <http://www.w3.org/2000/01/rdf-schema#> .
+@prefix xsd: <http://www.w3.org/2001/XMLSchema#> .
+
+<https://data.acme.com/ontology> a owl:Ontology .
+acme:PurchaseOrder a owl:Class ;
+    rdfs:label "Purchase order" .
+acme:Customer a owl:Class .
+acme:placedBy a owl:ObjectProperty ;
+    rdfs:domain acme:PurchaseOrder ;
+    rdfs:range acme:Customer .
+acme:totalAmount a owl:DatatypeProperty ;
+    rdfs:range xsd:decimal .
+acme:order_42 a acme:PurchaseOrder ;
+    acme:placedBy acme:alice .
+"#;
+        assert_eq!(graph(&normalized.turtle), graph(expected));
+        assert!(normalized
+            .turtle
+            .contains("@prefix acme: <https://data.acme.com/ontology#> ."));
+        for hallucinated in ["example.org", "made.up", "foo:", "bar:"] {
+            assert!(
+                !normalized.turtle.contains(hallucinated),
+                "{}",
+                normalized.turtle
+            );
+        }
+    }
+
+    #[test]
+    fn test_conforming_ontology_is_unchanged() {
+        let normalized = policy().apply(GENERATED).unwrap();
+        let again = policy().apply(&normalized.turtle).unwrap();
+        assert!(again.renames.is_empty(), "{:?}", again.renames);
+        assert_eq!(graph(&again.turtle), graph(&normalized.turtle));
+    }
+
+    #[test]
+    fn test_records_renames_as_same_as() {
+        let normalized = policy().with_same_as(true).apply(GENERATED).unwrap();
+        let same_as = r#"
+@prefix owl: <http://www.w3.org/2002/07/owl#> .
+<https://data.acme.com/ontology#Customer> owl:sameAs <http://made.up/vocab/Customer> .
+"#;
+        let triples = graph(&normalized.turtle);
+        assert!(graph(same_as).is_subset(&triples));
+        assert_eq!(
+            triples.len(),
+            graph(&policy().apply(GENERATED).unwrap().turtle).len() + normalized.renames.len()
+        );
+    }
+
+    #[test]
+    fn test_collisions_are_errors() {
+        let turtle = r#"
+@prefix foo: <http://example.org/shop#> .
+@prefix bar: <http://made.up/vocab/> .
+@prefix owl: <http://www.w3.org/2002/07/owl#> .
+foo:purchase_order a owl:Class .
+bar:PurchaseOrder a owl:Class .
+"#;
+        let err = policy().apply(turtle).unwrap_err().to_string();
+        assert!(
+            err.contains(
+                "https://data.acme.com/ontology#PurchaseOrder <- \
+                 http://example.org/shop#purchase_order, http://made.up/vocab/PurchaseOrder"
+            ),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_kept_namespaces_are_not_renamed() {
+        let turtle = r#"
+@prefix foo: <http://example.org/shop#> .
+@prefix schema: <https://schema.org/> .
+@prefix owl: <http://www.w3.org/2002/07/owl#> .
+foo:Order a owl:Class ;
+    owl:equivalentClass schema:Order .
+"#;
+        let normalized = policy()
+            .keep_namespace("schema", "https://schema.org/")
+            .apply(turtle)
+            .unwrap();
+        assert_eq!(normalized.renames.len(), 1);
+        assert!(normalized
+            .turtle
+            .contains("@prefix schema: <https://schema.org/> ."));
+        assert!(normalized.turtle.contains("schema:Order"));
+    }
+
+    #[test]
+    fn test_styles_multi_word_names() {
+        let rules = SlugRules::default();
+        assert_eq!(
+            IriStyle::PascalCase.apply("purchase order", &rules),
+            "PurchaseOrder"
+        );
+        assert_eq!(
+            IriStyle::PascalCase.apply("HTTPRequest", &rules),
+            "HttpRequest"
+        );
+        assert_eq!(IriStyle::CamelCase.apply("Has-Part", &rules), "hasPart");
+        assert_eq!(
+            IriStyle::KebabCase.apply("lineItem2Total", &rules),
+            "line-item2-total"
+        );
+        assert_eq!(IriStyle::SnakeCase.apply("LineItem", &rules), "line_item");
+        assert_eq!(IriStyle::Preserve.apply("line item", &rules), "line item");
+
+        let rules = SlugRules {
+            keep_acronyms: true,
+            stop_words: vec!["the".to_string()],
+        };
+        assert_eq!(
+            IriStyle::PascalCase.apply("the HTTP request", &rules),
+            "HTTPRequest"
+        );
+        assert_eq!(
+            IriStyle::CamelCase.apply("HTTP request", &rules),
+            "httpRequest"
+        );
+        assert_eq!(IriStyle::PascalCase.apply("The", &rules), "The");
+    }
+
+    #[test]
+    fn test_rejects_invalid_policies() {
+        assert!(
+            NamespacePolicy::new("https://data.acme.com/ontology", "acme")
+                .apply(GENERATED)
+                .is_err()
+        );
+        assert!(
+            NamespacePolicy::new("https://data.acme.com/ontology#", "1acme")
+                .apply(GENERATED)
+                .is_err()
+        );
+    }
+}
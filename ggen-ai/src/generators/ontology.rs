@@ -52,6 +52,9 @@
 
 use crate::client::{LlmClient, LlmConfig};
 use crate::error::Result;
+use crate::generators::duplicates::ConceptDeduplicator;
+use crate::generators::namespace::{NamespacePolicy, NormalizedOntology};
+use crate::generators::style::{self, ResponseStyle};
 use crate::prompts::OntologyPromptBuilder;
 use futures::StreamExt;
 use serde::{Deserialize, Serialize};
@@ -112,40 +115,142 @@ pub struct OperationMetadata {
 }
 
 /// AI-powered ontology generator
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct OntologyGenerator {
     client: Arc<dyn LlmClient>,
+    namespace: Option<NamespacePolicy>,
+    duplicates: Option<ConceptDeduplicator>,
+    style: ResponseStyle,
 }
 
 impl OntologyGenerator {
     /// Create a new ontology generator
     pub fn new(client: Arc<dyn LlmClient>) -> Self {
-        Self { client }
+        Self {
+            client,
+            namespace: None,
+            duplicates: None,
+            style: ResponseStyle::default(),
+        }
     }
 
     /// Create a new ontology generator with custom config
     pub fn with_config(client: Arc<dyn LlmClient>, _config: LlmConfig) -> Self {
-        Self { client }
+        Self::new(client)
     }
 
     /// Create a new ontology generator with a client
     pub fn with_client(client: Arc<dyn LlmClient>) -> Self {
-        Self { client }
+        Self::new(client)
+    }
+
+    /// Enforce `policy` on every generated ontology
+    pub fn with_namespace_policy(mut self, policy: NamespacePolicy) -> Self {
+        self.namespace = Some(policy);
+        self
+    }
+
+    /// Get the namespace policy, if any
+    pub fn namespace_policy(&self) -> Option<&NamespacePolicy> {
+        self.namespace.as_ref()
+    }
+
+    /// Look for duplicate concepts in every generated ontology with
+    /// `deduplicator`
+    pub fn with_deduplicator(mut self, deduplicator: ConceptDeduplicator) -> Self {
+        self.duplicates = Some(deduplicator);
+        self
+    }
+
+    /// Get the duplicate concept detection, if any
+    pub fn deduplicator(&self) -> Option<&ConceptDeduplicator> {
+        self.duplicates.as_ref()
+    }
+
+    /// Write `rdfs:comment`s in `style`, tagged with its language
+    pub fn with_style(mut self, style: ResponseStyle) -> Self {
+        self.style = style;
+        self
+    }
+
+    /// Get the style of generated comments
+    pub fn style(&self) -> &ResponseStyle {
+        &self.style
     }
 
     /// Generate an ontology from a natural language description
     pub async fn generate_ontology(&self, domain: &str, requirements: Vec<&str>) -> Result<String> {
+        Ok(self
+            .generate_normalized_ontology(domain, requirements)
+            .await?
+            .turtle)
+    }
+
+    /// Generate an ontology and report how the namespace policy renamed its
+    /// terms
+    ///
+    /// Without a policy the Turtle is returned as generated and nothing is
+    /// renamed. With a deduplicator, duplicate concepts are merged if its
+    /// configuration says so and reported either way.
+    pub async fn generate_normalized_ontology(
+        &self, domain: &str, requirements: Vec<&str>,
+    ) -> Result<NormalizedOntology> {
+        let merge = self
+            .duplicates
+            .as_ref()
+            .is_some_and(|deduplicator| deduplicator.config().auto_merge);
+        self.generate_deduplicated_ontology(domain, requirements, merge)
+            .await
+    }
+
+    /// [`OntologyGenerator::generate_normalized_ontology`], merging
+    /// duplicate concepts if `merge` is set rather than as configured
+    ///
+    /// Duplicates are looked for after the namespace policy is applied, so
+    /// they are reported with their final IRIs. Without a deduplicator none
+    /// are reported. With a style language, untagged comments are tagged
+    /// with it last.
+    pub async fn generate_deduplicated_ontology(
+        &self, domain: &str, requirements: Vec<&str>, merge: bool,
+    ) -> Result<NormalizedOntology> {
+        let mut requirements: Vec<String> = requirements.iter().map(|s| s.to_string()).collect();
+        if let Some(policy) = &self.namespace {
+            requirements.push(format!(
+                "Define every term in the namespace <{}> with the prefix {}:",
+                policy.base_iri, policy.prefix
+            ));
+        }
+        requirements.extend(self.style.instructions("every rdfs:comment"));
         let prompt = OntologyPromptBuilder::new(domain.to_string())
-            .with_requirements(requirements.iter().map(|s| s.to_string()).collect())
+            .with_requirements(requirements)
             .build()?;
 
         let response = self.client.complete(&prompt).await?;
 
         // Extract ontology content from response
-        self.extract_ontology_content(&response.content)
+        let turtle = self.extract_ontology_content(&response.content)?;
+        let mut ontology = match &self.namespace {
+            Some(policy) => policy.apply(&turtle)?,
+            None => NormalizedOntology {
+                turtle,
+                renames: Vec::new(),
+                duplicates: Vec::new(),
+            },
+        };
+        if let Some(deduplicator) = &self.duplicates {
+            let deduplicated = deduplicator.apply(&ontology.turtle, merge).await?;
+            ontology.turtle = deduplicated.turtle;
+            ontology.duplicates = deduplicated.duplicates;
+        }
+        if let Some(language) = &self.style.language {
+            ontology.turtle = style::tag_comments(&ontology.turtle, language)?;
+        }
+        Ok(ontology)
     }
 
     /// Stream ontology generation from a natural language description
+    ///
+    /// The namespace policy isn't applied to streamed chunks.
     pub async fn stream_ontology(
         &self, domain: &str, requirements: Vec<&str>,
     ) -> Result<futures::stream::BoxStream<'static, Result<String>>> {
@@ -366,4 +471,98 @@ ex:Person a rdfs:Class ;
         let result = generator.extract_ontology_content(response);
         assert!(result.is_ok(), "Valid plain text Turtle should work");
     }
+
+    #[tokio::test]
+    async fn test_namespace_policy_renames_generated_terms() {
+        let response = "```turtle\n@prefix ex: <http://example.org/> .\n@prefix owl: <http://www.w3.org/2002/07/owl#> .\nex:line_item a owl:Class .\n```";
+        let generator = crate::test_helpers::create_ontology_generator_with_response(response)
+            .with_namespace_policy(NamespacePolicy::new(
+                "https://data.acme.com/ontology#",
+                "acme",
+            ));
+
+        let normalized = generator
+            .generate_normalized_ontology("Orders", vec!["Include LineItem class"])
+            .await
+            .unwrap();
+
+        assert_eq!(normalized.renames.len(), 1);
+        assert_eq!(normalized.renames[0].from, "http://example.org/line_item");
+        assert_eq!(
+            normalized.renames[0].to,
+            "https://data.acme.com/ontology#LineItem"
+        );
+        assert!(normalized.turtle.contains("acme:LineItem"));
+    }
+
+    /// Embeds `Customer` and `Client` identically, anything else apart
+    #[derive(Debug)]
+    struct SynonymEmbeddings;
+
+    #[async_trait::async_trait]
+    impl crate::generators::EmbeddingModel for SynonymEmbeddings {
+        async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+            Ok(texts
+                .iter()
+                .enumerate()
+                .map(|(i, text)| match text.as_str() {
+                    "Customer" | "Client" => vec![1.0, 0.0, 0.0],
+                    _ => vec![0.0, 1.0, i as f32],
+                })
+                .collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_concepts_are_reported_or_merged() {
+        let response = "```turtle\n@prefix ex: <http://example.org/> .\n@prefix owl: <http://www.w3.org/2002/07/owl#> .\n@prefix rdfs: <http://www.w3.org/2000/01/rdf-schema#> .\nex:Customer a owl:Class ; rdfs:label \"Customer\" .\nex:Client a owl:Class ; rdfs:label \"Client\" .\nex:Order a owl:Class ; rdfs:label \"Order\" .\n```";
+        let generator = crate::test_helpers::create_ontology_generator_with_response(response)
+            .with_deduplicator(ConceptDeduplicator::new(Arc::new(SynonymEmbeddings)));
+
+        let reported = generator
+            .generate_normalized_ontology("Shop", vec![])
+            .await
+            .unwrap();
+        assert_eq!(reported.duplicates.len(), 1);
+        assert_eq!(reported.duplicates[0].keep, "http://example.org/Customer");
+        assert_eq!(
+            reported.duplicates[0].duplicate,
+            "http://example.org/Client"
+        );
+        assert!(!reported.duplicates[0].merged);
+        assert!(!reported.turtle.contains("equivalentClass"));
+
+        let merged = generator
+            .generate_deduplicated_ontology("Shop", vec![], true)
+            .await
+            .unwrap();
+        assert!(merged.duplicates[0].merged);
+        assert!(
+            merged.turtle.contains("owl:equivalentClass ex:Client"),
+            "{}",
+            merged.turtle
+        );
+    }
+
+    #[tokio::test]
+    async fn test_style_language_tags_generated_comments() {
+        let response = "```turtle\n@prefix ex: <http://example.org/> .\n@prefix owl: <http://www.w3.org/2002/07/owl#> .\n@prefix rdfs: <http://www.w3.org/2000/01/rdf-schema#> .\nex:Commande a owl:Class ; rdfs:comment \"Une commande d'un client\" .\n```";
+        let client = crate::MockClient::with_response(response);
+        let style = ResponseStyle::new(Some("fr"), None).unwrap();
+        let generator = OntologyGenerator::new(Arc::new(client.clone())).with_style(style);
+
+        let ontology = generator
+            .generate_normalized_ontology("Shop", vec![])
+            .await
+            .unwrap();
+
+        assert!(client.prompts()[0].contains("Write every rdfs:comment in French (fr)."));
+        assert!(
+            ontology
+                .turtle
+                .contains("rdfs:comment \"Une commande d'un client\"@fr"),
+            "{}",
+            ontology.turtle
+        );
+    }
 }
@@ -3,6 +3,7 @@
 use crate::client::{LlmClient, LlmConfig};
 use crate::error::{GgenAiError, Result};
 use crate::error_utils::{missing_closing_marker_error, ErrorContext};
+use crate::generators::review::{self, CodeReview};
 use ggen_core::{MergeStrategy, ThreeWayMerger};
 use std::sync::Arc;
 
@@ -169,6 +170,16 @@ impl RefactorAssistant {
         Ok(result.content)
     }
 
+    /// Review code as a reviewer would, with comments anchored to lines of
+    /// `code` instead of a rewritten file; see [`crate::generators::review`]
+    pub async fn review(&self, code: &str, language: &str, focus: &[String]) -> Result<CodeReview> {
+        let prompt = review::build_prompt(code, language, focus);
+
+        let response = self.client.complete(&prompt).await?;
+
+        review::parse_review(&response.content, code)
+    }
+
     /// Build refactoring prompt
     fn build_refactoring_prompt(&self, code: &str, context: &RefactoringContext) -> Result<String> {
         let mut prompt = String::new();
@@ -578,4 +589,36 @@ mod tests {
         let refactored = result.expect("Failed to apply refactoring");
         assert!(refactored.contains("validate"));
     }
+
+    #[tokio::test]
+    async fn test_review_anchors_comments() {
+        let response = r#"{
+  "summary": "Small, but one input is trusted",
+  "comments": [
+    {"severity": "error", "category": "validation", "message": "Length is not checked",
+     "snippet": "return true;", "suggestion": "return input.length < 256;"},
+    {"severity": "warning", "message": "Missing helper", "snippet": "validate(input)"}
+  ]
+}"#;
+        let assistant = create_refactor_assistant_with_response(response);
+
+        let review = assistant
+            .review(
+                "function test() {\n  if (input.length > 0) {\n    return true;\n  }\n}",
+                "JavaScript",
+                &[],
+            )
+            .await
+            .expect("Failed to review code");
+
+        assert_eq!(review.summary, "Small, but one input is trusted");
+        assert_eq!(review.comments.len(), 1);
+        assert_eq!(review.comments[0].lines.start, 3);
+        assert_eq!(
+            review.comments[0].diff.as_deref(),
+            Some("@@ -3,1 +3,1 @@\n-    return true;\n+    return input.length < 256;\n")
+        );
+        assert_eq!(review.unanchored.len(), 1);
+        assert_eq!(review.unanchored[0].snippet, "validate(input)");
+    }
 }
@@ -0,0 +1,437 @@
+//! Line-anchored code review comments
+//!
+//! [`RefactorAssistant::review`](super::RefactorAssistant::review) asks the
+//! model for review comments instead of a rewritten file. Each comment quotes
+//! the code it is about, and the quote, not a line number the model counted,
+//! decides where the comment goes: it is looked up in the submitted code,
+//! first verbatim and then line by line ignoring indentation. A line number
+//! from the model only picks between several matches.
+//!
+//! Comments whose quote can't be found are returned as
+//! [`CodeReview::unanchored`] rather than dropped or pinned to a guessed line.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::error::{GgenAiError, Result};
+use crate::parsing_utils::{extract_any_code_block, extract_code_block};
+
+/// How much a review comment matters
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReviewSeverity {
+    /// Worth knowing, no change needed
+    Info,
+    /// Should be changed
+    Warning,
+    /// Wrong; must be changed
+    Error,
+}
+
+impl ReviewSeverity {
+    fn parse(s: &str) -> Self {
+        match s.to_lowercase().as_str() {
+            "info" | "note" | "nit" | "suggestion" => Self::Info,
+            "error" | "critical" | "high" | "blocker" => Self::Error,
+            _ => Self::Warning,
+        }
+    }
+}
+
+/// A review comment as written by the model
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReviewComment {
+    pub severity: ReviewSeverity,
+    /// Area of the comment, e.g. `error-handling` or `performance`
+    pub category: String,
+    pub message: String,
+    /// The code the comment is about, quoted from the submission
+    pub snippet: String,
+    /// Code to replace the snippet with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub suggestion: Option<String>,
+}
+
+/// Lines of the submitted code, 1-based and inclusive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LineRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// A review comment anchored to the lines its snippet was found on
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AnchoredComment {
+    #[serde(flatten)]
+    pub comment: ReviewComment,
+    pub lines: LineRange,
+    /// Unified diff hunk replacing the snippet with the suggestion
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diff: Option<String>,
+}
+
+/// Result of [`RefactorAssistant::review`](super::RefactorAssistant::review)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CodeReview {
+    pub summary: String,
+    /// Comments in line order
+    pub comments: Vec<AnchoredComment>,
+    /// Comments whose snippet is not in the submitted code
+    pub unanchored: Vec<ReviewComment>,
+}
+
+/// Prompt asking for a review of `code`
+pub(crate) fn build_prompt(code: &str, language: &str, focus: &[String]) -> String {
+    let mut prompt = String::new();
+
+    prompt.push_str("You are an expert code reviewer. Review the provided code and write ");
+    prompt.push_str("comments a reviewer would leave on specific lines. Do not rewrite the ");
+    prompt.push_str("code.\n\n");
+
+    prompt.push_str("## Code to Review\n");
+    prompt.push_str("```");
+    prompt.push_str(language);
+    prompt.push('\n');
+    prompt.push_str(code);
+    prompt.push_str("\n```\n\n");
+
+    if !focus.is_empty() {
+        prompt.push_str("## Focus Areas\n");
+        for area in focus {
+            prompt.push_str(&format!("- {}\n", area));
+        }
+        prompt.push('\n');
+    }
+
+    prompt.push_str("## Review Guidelines\n");
+    prompt.push_str("1. Quote the code each comment is about in `snippet`, copied exactly\n");
+    prompt.push_str("2. Keep snippets short: the lines the comment is about, no more\n");
+    prompt.push_str("3. Set `line` to the line number the snippet starts on\n");
+    prompt.push_str("4. Use severity `info`, `warning` or `error`\n");
+    prompt.push_str("5. Give a `suggestion` replacing the snippet when there is a concrete fix\n");
+    prompt.push_str("6. Summarize the overall state of the code in `summary`\n\n");
+
+    prompt.push_str("## Output Format\n");
+    prompt.push_str("Respond with JSON only:\n");
+    prompt.push_str("```json\n");
+    prompt.push_str("{\n");
+    prompt.push_str("  \"summary\": \"Readable, but errors from the parser are ignored\",\n");
+    prompt.push_str("  \"comments\": [\n");
+    prompt.push_str("    {\n");
+    prompt.push_str("      \"severity\": \"warning\",\n");
+    prompt.push_str("      \"category\": \"error-handling\",\n");
+    prompt.push_str("      \"message\": \"A malformed port panics instead of being reported\",\n");
+    prompt.push_str("      \"snippet\": \"let port = value.parse().unwrap();\",\n");
+    prompt.push_str("      \"line\": 12,\n");
+    prompt.push_str("      \"suggestion\": \"let port = value.parse()?;\"\n");
+    prompt.push_str("    }\n");
+    prompt.push_str("  ]\n");
+    prompt.push_str("}\n");
+    prompt.push_str("```\n");
+
+    prompt
+}
+
+/// Parse the model's review of `code` and anchor its comments
+pub(crate) fn parse_review(content: &str, code: &str) -> Result<CodeReview> {
+    let parsed = [
+        Some(content.trim().to_string()),
+        extract_code_block(content, "json"),
+        extract_any_code_block(content),
+    ]
+    .into_iter()
+    .flatten()
+    .find_map(|candidate| {
+        serde_json::from_str::<Value>(&candidate)
+            .ok()
+            .filter(Value::is_object)
+    })
+    .ok_or_else(|| GgenAiError::validation("Review response is not a JSON object"))?;
+
+    let mut comments = Vec::new();
+    let mut unanchored = Vec::new();
+    for value in parsed
+        .get("comments")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+    {
+        let (comment, hint) = parse_comment(value);
+        match anchor(code, &comment, hint) {
+            Some(anchored) => comments.push(anchored),
+            None => unanchored.push(comment),
+        }
+    }
+    comments.sort_by_key(|c| (c.lines.start, c.lines.end));
+
+    let summary = parsed
+        .get("summary")
+        .and_then(Value::as_str)
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(String::from)
+        .unwrap_or_else(|| default_summary(&comments, &unanchored));
+
+    Ok(CodeReview {
+        summary,
+        comments,
+        unanchored,
+    })
+}
+
+/// A comment and the line the model says its snippet starts on
+fn parse_comment(value: &Value) -> (ReviewComment, Option<usize>) {
+    let text = |key: &str| value.get(key).and_then(Value::as_str).unwrap_or_default();
+    let comment = ReviewComment {
+        severity: ReviewSeverity::parse(text("severity")),
+        category: match text("category") {
+            "" => "general".to_string(),
+            category => category.to_string(),
+        },
+        message: text("message").to_string(),
+        snippet: text("snippet").to_string(),
+        suggestion: value
+            .get("suggestion")
+            .and_then(Value::as_str)
+            .map(String::from),
+    };
+    let hint = value
+        .get("line")
+        .and_then(Value::as_u64)
+        .map(|line| line as usize);
+    (comment, hint)
+}
+
+fn default_summary(comments: &[AnchoredComment], unanchored: &[ReviewComment]) -> String {
+    let all = comments.iter().map(|c| &c.comment).chain(unanchored);
+    let errors = all
+        .clone()
+        .filter(|c| c.severity == ReviewSeverity::Error)
+        .count();
+    let warnings = all
+        .filter(|c| c.severity == ReviewSeverity::Warning)
+        .count();
+    format!(
+        "{} comments: {} errors, {} warnings",
+        comments.len() + unanchored.len(),
+        errors,
+        warnings
+    )
+}
+
+/// Where a snippet was found: the byte range it replaces, within the byte
+/// range of the whole lines it touches
+struct Match {
+    start: usize,
+    end: usize,
+    line_start: usize,
+    line_end: usize,
+}
+
+fn anchor(code: &str, comment: &ReviewComment, hint: Option<usize>) -> Option<AnchoredComment> {
+    let found = find_verbatim(code, &comment.snippet, hint)
+        .or_else(|| find_by_lines(code, &comment.snippet, hint))?;
+    let lines = LineRange {
+        start: line_of(code, found.line_start),
+        end: line_of(code, found.line_end),
+    };
+    let diff = comment.suggestion.as_deref().map(|suggestion| {
+        let old = &code[found.line_start..found.line_end];
+        let new = format!(
+            "{}{}{}",
+            &code[found.line_start..found.start],
+            suggestion,
+            &code[found.end..found.line_end]
+        );
+        hunk(lines.start, old, &new)
+    });
+    Some(AnchoredComment {
+        comment: comment.clone(),
+        lines,
+        diff,
+    })
+}
+
+/// 1-based line of the byte at `offset`
+fn line_of(code: &str, offset: usize) -> usize {
+    code[..offset].matches('\n').count() + 1
+}
+
+/// The occurrence of `snippet` closest to `hint`
+fn find_verbatim(code: &str, snippet: &str, hint: Option<usize>) -> Option<Match> {
+    let snippet = snippet.trim();
+    if snippet.is_empty() {
+        return None;
+    }
+    code.match_indices(snippet)
+        .map(|(start, _)| {
+            let end = start + snippet.len();
+            Match {
+                start,
+                end,
+                line_start: code[..start].rfind('\n').map_or(0, |i| i + 1),
+                line_end: code[end..].find('\n').map_or(code.len(), |i| end + i),
+            }
+        })
+        .min_by_key(|m| distance(line_of(code, m.start), hint))
+}
+
+/// The run of lines equal to those of `snippet` once indentation and blank
+/// lines are ignored, closest to `hint`
+fn find_by_lines(code: &str, snippet: &str, hint: Option<usize>) -> Option<Match> {
+    let wanted: Vec<&str> = snippet
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect();
+    if wanted.is_empty() {
+        return None;
+    }
+
+    // Byte range and trimmed text of each non-blank line
+    let mut lines = Vec::new();
+    let mut offset = 0;
+    for line in code.split('\n') {
+        if !line.trim().is_empty() {
+            lines.push((offset, offset + line.len(), line.trim()));
+        }
+        offset += line.len() + 1;
+    }
+
+    lines
+        .windows(wanted.len())
+        .filter(|window| {
+            window
+                .iter()
+                .map(|(_, _, text)| *text)
+                .eq(wanted.iter().copied())
+        })
+        .map(|window| {
+            let (line_start, _, _) = window[0];
+            let (_, line_end, _) = window[window.len() - 1];
+            Match {
+                start: line_start,
+                end: line_end,
+                line_start,
+                line_end,
+            }
+        })
+        .min_by_key(|m| distance(line_of(code, m.start), hint))
+}
+
+fn distance(line: usize, hint: Option<usize>) -> usize {
+    hint.map_or(0, |hint| line.abs_diff(hint))
+}
+
+/// Unified diff hunk replacing `old` with `new`, both starting at `line`
+fn hunk(line: usize, old: &str, new: &str) -> String {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    let new_start = if new.is_empty() { line - 1 } else { line };
+    let mut hunk = format!(
+        "@@ -{},{} +{},{} @@\n",
+        line,
+        old.len(),
+        new_start,
+        new.len()
+    );
+    for removed in &old {
+        hunk.push_str(&format!("-{}\n", removed));
+    }
+    for added in &new {
+        hunk.push_str(&format!("+{}\n", added));
+    }
+    hunk
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CODE: &str = "fn port(value: &str) -> u16 {\n    let port = value.parse().unwrap();\n    port\n}\n\nfn host() -> String {\n    let port = value.parse().unwrap();\n    String::from(\"localhost\")\n}\n";
+
+    fn comment(snippet: &str, suggestion: Option<&str>) -> ReviewComment {
+        ReviewComment {
+            severity: ReviewSeverity::Warning,
+            category: "error-handling".to_string(),
+            message: "Don't unwrap".to_string(),
+            snippet: snippet.to_string(),
+            suggestion: suggestion.map(String::from),
+        }
+    }
+
+    #[test]
+    fn test_verbatim_snippet_is_anchored_with_a_hunk() {
+        let anchored = anchor(
+            CODE,
+            &comment(
+                "value.parse().unwrap()",
+                Some("value.parse().expect(\"port\")"),
+            ),
+            None,
+        )
+        .unwrap();
+        assert_eq!(anchored.lines, LineRange { start: 2, end: 2 });
+        assert_eq!(
+            anchored.diff.as_deref(),
+            Some(
+                "@@ -2,1 +2,1 @@\n-    let port = value.parse().unwrap();\n+    let port = \
+                 value.parse().expect(\"port\");\n"
+            )
+        );
+    }
+
+    #[test]
+    fn test_line_hint_picks_between_matches() {
+        let snippet = "let port = value.parse().unwrap();";
+        let first = anchor(CODE, &comment(snippet, None), Some(1)).unwrap();
+        let second = anchor(CODE, &comment(snippet, None), Some(8)).unwrap();
+        assert_eq!(first.lines.start, 2);
+        assert_eq!(second.lines.start, 7);
+        assert!(second.diff.is_none());
+    }
+
+    #[test]
+    fn test_reindented_snippet_is_anchored_by_lines() {
+        let anchored = anchor(
+            CODE,
+            &comment(
+                "fn host() -> String {\nlet port = value.parse().unwrap();",
+                None,
+            ),
+            None,
+        )
+        .unwrap();
+        assert_eq!(anchored.lines, LineRange { start: 6, end: 7 });
+    }
+
+    #[test]
+    fn test_missing_or_empty_snippets_are_not_anchored() {
+        assert!(anchor(CODE, &comment("value.parse::<u8>()", None), None).is_none());
+        assert!(anchor(CODE, &comment("  \n", None), None).is_none());
+    }
+
+    #[test]
+    fn test_parse_review_splits_unanchored_comments() {
+        let response = r#"Here is my review:
+```json
+{
+  "comments": [
+    {"severity": "critical", "category": "correctness", "message": "host() reads an unbound value",
+     "snippet": "let port = value.parse().unwrap();", "line": 7},
+    {"severity": "nit", "message": "Use a constant",
+     "snippet": "const DEFAULT_HOST: &str = \"localhost\";"}
+  ]
+}
+```"#;
+        let review = parse_review(response, CODE).unwrap();
+        assert_eq!(review.comments.len(), 1);
+        assert_eq!(review.comments[0].lines.start, 7);
+        assert_eq!(review.comments[0].comment.severity, ReviewSeverity::Error);
+        assert_eq!(review.unanchored.len(), 1);
+        assert_eq!(review.unanchored[0].category, "general");
+        assert_eq!(review.summary, "2 comments: 1 errors, 0 warnings");
+
+        assert!(parse_review("Looks fine to me", CODE).is_err());
+    }
+}
@@ -0,0 +1,222 @@
+//! Language and tone of generated prose
+//!
+//! A [`ResponseStyle`] asks the model to write in a given language and tone.
+//! Generators apply it to the prose they produce, such as completions, the
+//! documentation in templates and the `rdfs:comment`s of ontologies, while code
+//! and IRIs stay as they are. Models don't tag the literals they write, so the
+//! comments of a generated ontology are tagged with the language after parsing;
+//! see [`tag_comments`].
+
+use crate::error::{GgenAiError, Result};
+use crate::generators::duplicates::Graph;
+use crate::generators::namespace::RDFS;
+use oxigraph::model::{Literal, Term, Triple};
+use serde::{Deserialize, Serialize};
+
+/// How generated prose should read; unset fields are left to the model
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResponseStyle {
+    /// BCP 47 language tag, such as `fr` or `de-CH`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tone: Option<Tone>,
+}
+
+/// Register of generated prose
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Tone {
+    Neutral,
+    Formal,
+    Friendly,
+    Concise,
+}
+
+impl Tone {
+    fn instruction(self) -> &'static str {
+        match self {
+            Tone::Neutral => "Use a neutral, matter-of-fact tone.",
+            Tone::Formal => "Use a formal tone.",
+            Tone::Friendly => "Use a friendly, conversational tone.",
+            Tone::Concise => "Be concise; leave out anything that isn't needed.",
+        }
+    }
+}
+
+/// English names of common languages, by primary subtag
+const LANGUAGE_NAMES: [(&str, &str); 12] = [
+    ("de", "German"),
+    ("en", "English"),
+    ("es", "Spanish"),
+    ("fr", "French"),
+    ("it", "Italian"),
+    ("ja", "Japanese"),
+    ("ko", "Korean"),
+    ("nl", "Dutch"),
+    ("pl", "Polish"),
+    ("pt", "Portuguese"),
+    ("sv", "Swedish"),
+    ("zh", "Chinese"),
+];
+
+impl ResponseStyle {
+    /// A style with the given language and tone
+    ///
+    /// Fails if `language` isn't a well-formed language tag; it is kept
+    /// lowercase, as RDF compares tags.
+    pub fn new(language: Option<&str>, tone: Option<Tone>) -> Result<Self> {
+        let language = language
+            .map(|language| {
+                Literal::new_language_tagged_literal("", language)
+                    .map(|_| language.to_lowercase())
+                    .map_err(|err| GgenAiError::invalid_config("language", err.to_string()))
+            })
+            .transpose()?;
+        Ok(Self { language, tone })
+    }
+
+    /// Neither a language nor a tone
+    pub fn is_empty(&self) -> bool {
+        self.language.is_none() && self.tone.is_none()
+    }
+
+    /// This style, with the fields it leaves unset taken from `fallback`
+    pub fn or(&self, fallback: &ResponseStyle) -> ResponseStyle {
+        ResponseStyle {
+            language: self.language.clone().or_else(|| fallback.language.clone()),
+            tone: self.tone.or(fallback.tone),
+        }
+    }
+
+    /// Instructions asking for this style in `what`, such as "your answer"
+    pub fn instructions(&self, what: &str) -> Vec<String> {
+        let mut instructions = Vec::new();
+        if let Some(language) = &self.language {
+            instructions.push(format!(
+                "Write {} in {}.",
+                what,
+                describe_language(language)
+            ));
+        }
+        if let Some(tone) = self.tone {
+            instructions.push(tone.instruction().to_string());
+        }
+        instructions
+    }
+
+    /// `prompt`, headed by the instructions of this style for the answer
+    pub fn apply(&self, prompt: &str) -> String {
+        if self.is_empty() {
+            return prompt.to_string();
+        }
+        let mut styled = String::from("## Response Style\n");
+        for instruction in self.instructions("your answer") {
+            styled.push_str(&format!("- {}\n", instruction));
+        }
+        styled.push('\n');
+        styled.push_str(prompt);
+        styled
+    }
+}
+
+/// `French (fr)`, or the tag alone for languages without a known name
+fn describe_language(tag: &str) -> String {
+    let primary = tag.split('-').next().unwrap_or(tag);
+    match LANGUAGE_NAMES.iter().find(|(code, _)| *code == primary) {
+        Some((_, name)) => format!("{} ({})", name, tag),
+        None => format!("the language with tag {}", tag),
+    }
+}
+
+/// Tag the untagged `rdfs:comment` literals of `turtle` with `language`
+///
+/// Comments with a language tag or a datatype other than `xsd:string` are
+/// left as they are.
+pub fn tag_comments(turtle: &str, language: &str) -> Result<String> {
+    let mut graph = Graph::parse(turtle)?;
+    let comment = format!("{}comment", RDFS);
+    for triple in &mut graph.triples {
+        if triple.predicate.as_str() != comment {
+            continue;
+        }
+        let Term::Literal(literal) = &triple.object else {
+            continue;
+        };
+        if literal.language().is_some()
+            || literal.datatype().as_str() != "http://www.w3.org/2001/XMLSchema#string"
+        {
+            continue;
+        }
+        let tagged = Literal::new_language_tagged_literal(literal.value(), language)
+            .map_err(|err| GgenAiError::invalid_config("language", err.to_string()))?;
+        *triple = Triple::new(triple.subject.clone(), triple.predicate.clone(), tagged);
+    }
+    graph.serialize()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxigraph::io::{RdfFormat, RdfParser};
+
+    #[test]
+    fn test_request_style_overrides_defaults_field_by_field() {
+        let service = ResponseStyle::new(Some("de"), Some(Tone::Formal)).unwrap();
+        let tenant = ResponseStyle::new(Some("fr"), None).unwrap();
+        let request = ResponseStyle::new(None, Some(Tone::Friendly)).unwrap();
+
+        let effective = request.or(&tenant.or(&service));
+
+        assert_eq!(effective.language.as_deref(), Some("fr"));
+        assert_eq!(effective.tone, Some(Tone::Friendly));
+        assert_eq!(
+            effective.apply("Summarize the release notes"),
+            "## Response Style\n\
+             - Write your answer in French (fr).\n\
+             - Use a friendly, conversational tone.\n\n\
+             Summarize the release notes"
+        );
+        assert_eq!(ResponseStyle::default().apply("hi"), "hi");
+    }
+
+    #[test]
+    fn test_rejects_malformed_language_tags() {
+        assert!(ResponseStyle::new(Some("not a tag"), None).is_err());
+        let style = ResponseStyle::new(Some("de-CH"), None).unwrap();
+        assert_eq!(style.language.as_deref(), Some("de-ch"));
+        assert_eq!(
+            style.instructions("comments"),
+            vec!["Write comments in German (de-ch)."]
+        );
+    }
+
+    #[test]
+    fn test_tags_untagged_comments() {
+        let turtle = r#"
+@prefix ex: <http://example.org/shop#> .
+@prefix rdfs: <http://www.w3.org/2000/01/rdf-schema#> .
+ex:Order rdfs:comment "Une commande passée par un client" ;
+    rdfs:label "Order" .
+ex:Invoice rdfs:comment "Invoice"@en .
+"#;
+        let tagged = tag_comments(turtle, "fr").unwrap();
+
+        let objects: Vec<Term> = RdfParser::from_format(RdfFormat::Turtle)
+            .for_reader(tagged.as_bytes())
+            .map(|quad| quad.unwrap())
+            .filter(|quad| quad.predicate.as_str().ends_with("comment"))
+            .map(|quad| quad.object)
+            .collect();
+        assert!(objects.contains(&Term::Literal(
+            Literal::new_language_tagged_literal("Une commande passée par un client", "fr")
+                .unwrap()
+        )));
+        assert!(objects.contains(&Term::Literal(
+            Literal::new_language_tagged_literal("Invoice", "en").unwrap()
+        )));
+        // Labels aren't prose and keep no tag
+        assert!(tagged.contains("rdfs:label \"Order\""));
+        assert!(tagged.contains("@prefix ex: <http://example.org/shop#>"));
+    }
+}
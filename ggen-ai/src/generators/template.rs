@@ -192,32 +192,63 @@
 
 use crate::client::{LlmClient, LlmConfig};
 use crate::error::{GgenAiError, Result};
+use crate::generators::context::{estimate_tokens, ContextSelector, ProjectContext};
+use crate::generators::style::ResponseStyle;
 use crate::generators::validator::{TemplateValidator, ValidationResult};
+use crate::prompts::template::CONVENTIONS_HEADER;
 use crate::prompts::TemplatePromptBuilder;
 use futures::StreamExt;
 use ggen_core::Template;
 use std::sync::Arc;
 
 /// AI-powered template generator
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct TemplateGenerator {
     client: Arc<dyn LlmClient>,
+    context: Option<ContextSelector>,
+    style: ResponseStyle,
 }
 
 impl TemplateGenerator {
     /// Create a new template generator
     pub fn new(client: Arc<dyn LlmClient>) -> Self {
-        Self { client }
+        Self {
+            client,
+            context: None,
+            style: ResponseStyle::default(),
+        }
     }
 
     /// Create a new template generator with custom config
     pub fn with_config(client: Arc<dyn LlmClient>, _config: LlmConfig) -> Self {
-        Self { client }
+        Self::new(client)
     }
 
     /// Create a new template generator with a client
     pub fn with_client(client: Arc<dyn LlmClient>) -> Self {
-        Self { client }
+        Self::new(client)
+    }
+
+    /// Pick style exemplars from project context with `selector`
+    pub fn with_context_selector(mut self, selector: ContextSelector) -> Self {
+        self.context = Some(selector);
+        self
+    }
+
+    /// Get the project context selection, if any
+    pub fn context_selector(&self) -> Option<&ContextSelector> {
+        self.context.as_ref()
+    }
+
+    /// Write the comments and documentation of templates in `style`
+    pub fn with_style(mut self, style: ResponseStyle) -> Self {
+        self.style = style;
+        self
+    }
+
+    /// Get the style of template documentation
+    pub fn style(&self) -> &ResponseStyle {
+        &self.style
     }
 
     /// Generate a template from natural language description
@@ -226,6 +257,7 @@ impl TemplateGenerator {
     ) -> Result<Template> {
         let prompt = TemplatePromptBuilder::new(description.to_string())
             .with_examples(examples.iter().map(|s| s.to_string()).collect())
+            .with_requirements(self.style_requirements())
             .build()?;
 
         let response = self.client.complete(&prompt).await?;
@@ -234,12 +266,41 @@ impl TemplateGenerator {
         self.parse_template(&response.content)
     }
 
+    /// Generate a template following the conventions of the project files
+    /// in `context`
+    ///
+    /// The files most relevant to the description are included in the
+    /// prompt, trimmed to the selector's token budget. Fails without a
+    /// context selector.
+    pub async fn generate_template_with_context(
+        &self, description: &str, examples: Vec<&str>, context: &ProjectContext,
+    ) -> Result<Template> {
+        let selector = self.context.as_ref().ok_or_else(|| {
+            GgenAiError::validation("Project context needs a generator with an embedding model")
+        })?;
+        let builder = || {
+            TemplatePromptBuilder::new(description.to_string())
+                .with_examples(examples.iter().map(|s| s.to_string()).collect())
+                .with_requirements(self.style_requirements())
+        };
+        let exemplars = selector.select(description, context).await?;
+        // The header and the newline after the exemplars count against them
+        let prompt_tokens =
+            estimate_tokens(&builder().build()?) + estimate_tokens(CONVENTIONS_HEADER) + 1;
+        let exemplars = selector.fit(exemplars, prompt_tokens);
+        let prompt = builder().with_exemplars(exemplars).build()?;
+
+        let response = self.client.complete(&prompt).await?;
+        self.parse_template(&response.content)
+    }
+
     /// Generate a template with streaming
     pub async fn generate_template_stream(
         &self, description: &str, examples: Vec<&str>,
     ) -> Result<futures::stream::BoxStream<'static, Result<String>>> {
         let prompt = TemplatePromptBuilder::new(description.to_string())
             .with_examples(examples.iter().map(|s| s.to_string()).collect())
+            .with_requirements(self.style_requirements())
             .build()?;
 
         let stream = self.client.complete_stream(&prompt).await?;
@@ -247,6 +308,11 @@ impl TemplateGenerator {
         Ok(Box::pin(stream.map(|chunk| Ok(chunk.content))))
     }
 
+    /// Code and identifiers are left to the target language's conventions
+    fn style_requirements(&self) -> Vec<String> {
+        self.style.instructions("comments and documentation text")
+    }
+
     /// Get the LLM client
     pub fn client(&self) -> &Arc<dyn LlmClient> {
         &self.client
@@ -362,4 +428,76 @@ mod tests {
 
         assert_eq!(template.body, "Hello {{ name }}!");
     }
+
+    /// Vectors counting a few words, so "repository" texts are close
+    #[derive(Debug)]
+    struct KeywordEmbeddings;
+
+    #[async_trait::async_trait]
+    impl crate::generators::EmbeddingModel for KeywordEmbeddings {
+        async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+            Ok(texts
+                .iter()
+                .map(|text| {
+                    let text = text.to_lowercase();
+                    ["repository", "controller"]
+                        .iter()
+                        .map(|word| text.matches(word).count() as f32)
+                        .collect()
+                })
+                .collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_generation_includes_the_most_relevant_exemplar_within_budget() {
+        use crate::generators::context::{ContextConfig, Snippet};
+        use crate::providers::MockClient;
+
+        let description = "A repository for products";
+        let base = TemplatePromptBuilder::new(description.to_string())
+            .build()
+            .unwrap();
+        let client = MockClient::with_response(
+            "---\nto: \"src/product_repository.rs\"\n---\npub fn find_product_by_id() {}",
+        );
+        let selector =
+            ContextSelector::new(Arc::new(KeywordEmbeddings)).with_config(ContextConfig {
+                top_k: 2,
+                max_prompt_tokens: estimate_tokens(&base) + 100,
+                ..ContextConfig::default()
+            });
+        let generator =
+            TemplateGenerator::new(Arc::new(client.clone())).with_context_selector(selector);
+        let repository = "// Repository for users\npub fn find_user_by_id(user_id: UserId) -> Option<User> {\n    USER_TABLE.get(&user_id).cloned()\n}\n";
+        let controller = "// Controller for orders\nexport function getOrderById(orderId: string): Order {\n    return ordersTable.lookup(orderId);\n}\n";
+        let context = ProjectContext::Snippets(vec![
+            Snippet {
+                path: "src/OrderController.ts".to_string(),
+                content: controller.to_string(),
+            },
+            Snippet {
+                path: "src/user_repository.rs".to_string(),
+                content: repository.to_string(),
+            },
+        ]);
+
+        generator
+            .generate_template_with_context(description, vec![], &context)
+            .await
+            .unwrap();
+
+        let prompt = &client.prompts()[0];
+        assert!(prompt.contains("<<<EXEMPLAR src/user_repository.rs>>>"));
+        assert!(prompt.contains("find_user_by_id"));
+        // The second exemplar didn't fit
+        assert!(!prompt.contains("getOrderById"));
+        assert!(estimate_tokens(prompt) <= estimate_tokens(&base) + 100);
+
+        let plain = create_template_generator_with_response("Hello");
+        assert!(plain
+            .generate_template_with_context(description, vec![], &context)
+            .await
+            .is_err());
+    }
 }
@@ -58,13 +58,17 @@ pub mod types;
 pub mod test_helpers;
 
 // Re-export main types for convenience
-pub use cache::{CacheConfig, CacheStats, LlmCache};
+pub use cache::{CacheConfig, CacheStats, LlmCache, SpillConfig};
 pub use client::{GenAiClient, LlmChunk, LlmClient, LlmConfig, LlmResponse, UsageStats};
 pub use config::{get_global_config, init_global_config, AiConfig, GlobalLlmConfig, LlmProvider};
 pub use error::{GgenAiError, Result};
 pub use generators::{
-    NaturalSearchGenerator, OntologyGenerator, QualityMetrics, RefactorAssistant, SparqlGenerator,
-    TemplateGenerator, TemplateValidator, ValidationIssue,
+    AnchoredComment, CodeReview, ConceptDeduplicator, ContextConfig, ContextSelector,
+    DuplicateConcept, DuplicateConfig, EmbeddingModel, Exemplar, IriRename, MergeStrategy,
+    NamespacePolicy, NaturalSearchGenerator, NormalizedOntology, OntologyGenerator,
+    ProjectContext, QualityMetrics, RefactorAssistant, ResponseStyle, ReviewComment,
+    ReviewSeverity, Snippet, SparqlGenerator, TemplateGenerator, TemplateValidator, Tone,
+    ValidationIssue,
 };
 pub use providers::adapter::{ollama_default_config, ollama_qwen3_coder_config, MockClient};
 pub use security::{MaskApiKey, SecretString};
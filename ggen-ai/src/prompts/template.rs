@@ -1,6 +1,12 @@
 //! Prompt templates for template generation
 
 use crate::error::Result;
+use crate::generators::context::Exemplar;
+
+/// Heads the exemplars of a prompt
+pub(crate) const CONVENTIONS_HEADER: &str = "## Project Conventions\n\
+    Follow the naming and style of these files from the project. \
+    They are examples only; don't copy their content.\n\n";
 
 /// Builder for template generation prompts
 pub struct TemplatePromptBuilder {
@@ -10,6 +16,7 @@ pub struct TemplatePromptBuilder {
     language: Option<String>,
     framework: Option<String>,
     output_format: Option<String>,
+    exemplars: Vec<Exemplar>,
 }
 
 impl TemplatePromptBuilder {
@@ -22,6 +29,7 @@ impl TemplatePromptBuilder {
             language: None,
             framework: None,
             output_format: None,
+            exemplars: Vec::new(),
         }
     }
 
@@ -55,6 +63,12 @@ impl TemplatePromptBuilder {
         self
     }
 
+    /// Add project files whose conventions the template should follow
+    pub fn with_exemplars(mut self, exemplars: Vec<Exemplar>) -> Self {
+        self.exemplars = exemplars;
+        self
+    }
+
     /// Build the final prompt
     pub fn build(self) -> Result<String> {
         let mut prompt = String::new();
@@ -101,6 +115,15 @@ impl TemplatePromptBuilder {
             prompt.push('\n');
         }
 
+        // Project conventions section
+        if !self.exemplars.is_empty() {
+            prompt.push_str(CONVENTIONS_HEADER);
+            for exemplar in &self.exemplars {
+                prompt.push_str(&exemplar.render());
+            }
+            prompt.push('\n');
+        }
+
         // Template format instructions
         prompt.push_str("## Template Format\n");
         prompt.push_str("Generate a ggen template with the following structure:\n\n");
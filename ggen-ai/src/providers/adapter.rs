@@ -4,6 +4,7 @@ use crate::client::{LlmChunk, LlmClient, LlmConfig, LlmResponse, UsageStats};
 use crate::error::Result;
 use async_trait::async_trait;
 use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 
 /// Mock client for testing
 #[derive(Debug, Clone)]
@@ -11,6 +12,7 @@ pub struct MockClient {
     responses: Vec<String>,
     current_index: usize,
     config: LlmConfig,
+    prompts: Arc<Mutex<Vec<String>>>,
 }
 
 impl MockClient {
@@ -27,6 +29,7 @@ impl MockClient {
                 stop: None,
                 extra: HashMap::new(),
             },
+            prompts: Arc::new(Mutex::new(Vec::new())),
         }
     }
 
@@ -34,11 +37,17 @@ impl MockClient {
     pub fn with_response(response: &str) -> Self {
         Self::new(vec![response.to_string()])
     }
+
+    /// Every prompt received so far, shared by clones
+    pub fn prompts(&self) -> Vec<String> {
+        self.prompts.lock().unwrap().clone()
+    }
 }
 
 #[async_trait]
 impl LlmClient for MockClient {
-    async fn complete(&self, _prompt: &str) -> Result<LlmResponse> {
+    async fn complete(&self, prompt: &str) -> Result<LlmResponse> {
+        self.prompts.lock().unwrap().push(prompt.to_string());
         let response = self
             .responses
             .get(self.current_index)
@@ -60,8 +69,9 @@ impl LlmClient for MockClient {
     }
 
     async fn complete_stream(
-        &self, _prompt: &str,
+        &self, prompt: &str,
     ) -> Result<futures::stream::BoxStream<'static, LlmChunk>> {
+        self.prompts.lock().unwrap().push(prompt.to_string());
         let response = self
             .responses
             .get(self.current_index)
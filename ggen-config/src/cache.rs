@@ -0,0 +1,67 @@
+//! Response caching
+
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// How completions are cached
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheSettings {
+    /// Turn caching off without removing the section
+    #[serde(default = "enabled")]
+    pub enabled: bool,
+    /// Seconds an entry is served after it was stored
+    #[serde(default = "default_ttl_secs", alias = "ttl_seconds")]
+    pub ttl_secs: u64,
+    #[serde(default = "default_max_entries", alias = "max_capacity")]
+    pub max_entries: u64,
+    /// Seconds an entry may go unused before it is dropped early
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idle_secs: Option<u64>,
+    /// Directory for responses too large to keep in memory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spill_dir: Option<PathBuf>,
+}
+
+fn enabled() -> bool {
+    true
+}
+
+fn default_ttl_secs() -> u64 {
+    3600
+}
+
+fn default_max_entries() -> u64 {
+    10_000
+}
+
+impl Default for CacheSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            ttl_secs: default_ttl_secs(),
+            max_entries: default_max_entries(),
+            idle_secs: None,
+            spill_dir: None,
+        }
+    }
+}
+
+impl CacheSettings {
+    pub fn ttl(&self) -> Duration {
+        Duration::from_secs(self.ttl_secs)
+    }
+
+    pub fn idle(&self) -> Option<Duration> {
+        self.idle_secs.map(Duration::from_secs)
+    }
+
+    pub(crate) fn check(&self, problems: &mut Vec<String>) {
+        if self.ttl_secs == 0 {
+            problems.push("cache: ttl_secs must be at least 1".to_string());
+        }
+        if self.max_entries == 0 {
+            problems.push("cache: max_entries must be at least 1".to_string());
+        }
+    }
+}
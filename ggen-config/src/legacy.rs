@@ -0,0 +1,115 @@
+//! Layouts read for compatibility
+//!
+//! Before the shared layout, the microservice's `ggen.toml` configured
+//! providers in an `[ai]` section:
+//!
+//! ```toml
+//! [ai]
+//! provider = "openai"
+//! model = "gpt-4"
+//! temperature = 0.7
+//! max_tokens = 2000
+//! timeout_seconds = 30
+//! retry_attempts = 3
+//! stream_enabled = true
+//!
+//! [ai.cache]
+//! ttl_seconds = 3600
+//! max_entries = 1000
+//!
+//! [ai.providers.openai]
+//! api_key_env = "OPENAI_API_KEY"
+//! model = "gpt-4"
+//! ```
+//!
+//! A document with `[ai]` and no top-level `providers` is still read, with
+//! a note in [`SharedConfig::deprecations`]. `[ai.providers]` become the
+//! providers and `[ai]`'s provider the `default_provider`, with `[ai]`'s
+//! model, sampling, timeout and streaming settings; `api_key_env = "X"`
+//! becomes `api_key = "env:X"`. `[ai.cache]` is the `cache` section and
+//! `retry_attempts` the `retry` section's `max_attempts`.
+
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+use crate::{CacheSettings, ConfigError, ProviderSettings, RetrySettings, SharedConfig};
+
+const AI_SECTION_DEPRECATED: &str = "The [ai] section is deprecated: move [ai.providers] to \
+     [providers], [ai.cache] to [cache], retry_attempts to [retry] max_attempts, and name the \
+     provider in default_provider";
+
+#[derive(Deserialize)]
+struct AiSection {
+    provider: Option<String>,
+    model: Option<String>,
+    temperature: Option<f32>,
+    max_tokens: Option<u32>,
+    timeout_seconds: Option<u64>,
+    retry_attempts: Option<u32>,
+    #[serde(default)]
+    stream_enabled: bool,
+    cache: Option<CacheSettings>,
+    #[serde(default)]
+    providers: BTreeMap<String, AiProvider>,
+}
+
+#[derive(Deserialize)]
+struct AiProvider {
+    model: Option<String>,
+    /// Variable holding the key
+    api_key_env: Option<String>,
+    base_url: Option<String>,
+    #[serde(flatten)]
+    options: BTreeMap<String, Value>,
+}
+
+/// Whether `document` uses the `[ai]` layout
+pub(crate) fn is_ai_section(document: &Value) -> bool {
+    document.get("ai").is_some_and(Value::is_object) && document.get("providers").is_none()
+}
+
+pub(crate) fn from_ai_section(document: &Value) -> Result<SharedConfig, ConfigError> {
+    let ai = AiSection::deserialize(&document["ai"])
+        .map_err(|err| ConfigError::Invalid(vec![format!("ai: {}", err)]))?;
+    let mut providers: Vec<ProviderSettings> = ai
+        .providers
+        .into_iter()
+        .map(|(name, provider)| ProviderSettings {
+            model: provider.model.unwrap_or_default(),
+            api_key: provider.api_key_env.map(|var| format!("env:{}", var)),
+            base_url: provider.base_url,
+            options: provider.options,
+            ..ProviderSettings::new(name, "")
+        })
+        .collect();
+    if let Some(name) = &ai.provider {
+        let index = match providers.iter().position(|p| &p.name == name) {
+            Some(index) => index,
+            None => {
+                providers.push(ProviderSettings::new(name.clone(), ""));
+                providers.len() - 1
+            }
+        };
+        let provider = &mut providers[index];
+        if let Some(model) = ai.model {
+            provider.model = model;
+        }
+        provider.temperature = ai.temperature;
+        provider.max_tokens = ai.max_tokens;
+        provider.timeout_secs = ai.timeout_seconds;
+        provider.streaming = ai.stream_enabled;
+    }
+    Ok(SharedConfig {
+        providers,
+        default_provider: ai.provider,
+        embeddings: None,
+        cache: ai.cache,
+        retry: ai.retry_attempts.map(|max_attempts| RetrySettings {
+            max_attempts,
+            ..RetrySettings::default()
+        }),
+        telemetry: None,
+        deprecations: vec![AI_SECTION_DEPRECATED.to_string()],
+    })
+}
@@ -0,0 +1,254 @@
+//! # ggen-config
+//!
+//! Settings shared by rig-mcp and the AI microservice.
+//!
+//! Both describe LLM providers, an embedding model, a response cache,
+//! retries and trace export. The types here describe each of them once;
+//! every crate converts them into its own internals, and
+//! [`SharedConfig::validate`] checks them the same way for all of them.
+//!
+//! One document can configure several crates: each reads the sections it
+//! knows and ignores the others, so rig-mcp's `mcp_servers` and `agent` can
+//! sit next to the shared `providers`, `cache`, `retry` and `telemetry`.
+//!
+//! ```toml
+//! default_provider = "openai"
+//!
+//! [providers.openai]
+//! model = "gpt-4o"
+//! api_key = "env:OPENAI_API_KEY"
+//! temperature = 0.7
+//!
+//! [cache]
+//! ttl_secs = 3600
+//! max_entries = 1000
+//! ```
+//!
+//! Older layouts are still read for now: see [`legacy`] for the
+//! microservice's `[ai]` section. [`SharedConfig::deprecations`] says what
+//! to change.
+
+pub mod cache;
+pub mod legacy;
+pub mod provider;
+pub mod retry;
+pub mod telemetry;
+
+pub use cache::CacheSettings;
+pub use provider::{EmbeddingSettings, ProviderSettings};
+pub use retry::RetrySettings;
+pub use telemetry::TelemetrySettings;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Why settings couldn't be loaded
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("Failed to read {path}: {source}")]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Failed to parse {path}: {message}")]
+    Parse { path: PathBuf, message: String },
+    #[error("{0} is not a .toml, .yaml or .json file")]
+    Format(PathBuf),
+    /// Every problem found, not just the first
+    #[error("Invalid configuration: {}", .0.join("; "))]
+    Invalid(Vec<String>),
+}
+
+/// Languages a config document can be written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Toml,
+    Yaml,
+    Json,
+}
+
+impl Format {
+    /// The format of `path` by its extension: `.toml`, `.yaml` or `.yml`,
+    /// or `.json`
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => Some(Self::Toml),
+            Some("yaml" | "yml") => Some(Self::Yaml),
+            Some("json") => Some(Self::Json),
+            _ => None,
+        }
+    }
+}
+
+/// Read a `.toml`, `.yaml` or `.json` config file into a JSON document, for
+/// the shared types and each crate's own to deserialize from
+pub fn load_document(path: &Path) -> Result<Value, ConfigError> {
+    let format = Format::from_path(path).ok_or_else(|| ConfigError::Format(path.to_path_buf()))?;
+    let content = std::fs::read_to_string(path).map_err(|source| ConfigError::Read {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    parse_document(&content, format).map_err(|message| ConfigError::Parse {
+        path: path.to_path_buf(),
+        message,
+    })
+}
+
+/// Parse `content` into a JSON document; the error names the line and
+/// column the document stops making sense at
+pub fn parse_document(content: &str, format: Format) -> Result<Value, String> {
+    match format {
+        Format::Toml => toml::from_str(content).map_err(|err| err.to_string()),
+        Format::Yaml => serde_yaml::from_str(content).map_err(|err| err.to_string()),
+        Format::Json => serde_json::from_str(content).map_err(|err| err.to_string()),
+    }
+}
+
+/// The sections every crate understands
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SharedConfig {
+    /// A list of providers, or a table of them keyed by name
+    #[serde(default, with = "provider::list_or_table")]
+    pub providers: Vec<ProviderSettings>,
+    /// Provider for callers that don't name one; the first one otherwise
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_provider: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub embeddings: Option<EmbeddingSettings>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache: Option<CacheSettings>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry: Option<RetrySettings>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub telemetry: Option<TelemetrySettings>,
+    /// Old layouts the document used and what replaces them, for callers
+    /// to log
+    #[serde(skip)]
+    pub deprecations: Vec<String>,
+}
+
+impl SharedConfig {
+    /// Load and validate the shared sections of a `.toml`, `.yaml` or `.json`
+    /// file
+    pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let config = Self::from_document(load_document(path)?)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// The shared sections of a document, in the current layout or a
+    /// [`legacy`] one; not validated
+    pub fn from_document(document: Value) -> Result<Self, ConfigError> {
+        if legacy::is_ai_section(&document) {
+            return legacy::from_ai_section(&document);
+        }
+        serde_json::from_value(document).map_err(|err| ConfigError::Invalid(vec![err.to_string()]))
+    }
+
+    /// `default_provider`, or the first provider
+    pub fn default_provider(&self) -> Option<&ProviderSettings> {
+        match &self.default_provider {
+            Some(name) => self.providers.iter().find(|p| &p.name == name),
+            None => self.providers.first(),
+        }
+    }
+
+    /// Check every section, reporting all problems at once
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut problems = Vec::new();
+        let mut names = HashSet::new();
+        for provider in &self.providers {
+            provider.check(&mut problems);
+            if !names.insert(provider.name.as_str()) {
+                problems.push(format!("provider '{}' is configured twice", provider.name));
+            }
+        }
+        if let Some(name) = &self.default_provider {
+            if !names.contains(name.as_str()) {
+                problems.push(format!(
+                    "default_provider '{}' is not a configured provider",
+                    name
+                ));
+            }
+        }
+        if let Some(embeddings) = &self.embeddings {
+            embeddings.check(&mut problems);
+        }
+        if let Some(cache) = &self.cache {
+            cache.check(&mut problems);
+        }
+        if let Some(retry) = &self.retry {
+            retry.check(&mut problems);
+        }
+        if let Some(telemetry) = &self.telemetry {
+            telemetry.check(&mut problems);
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError::Invalid(problems))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_validate_reports_every_problem() {
+        let config: SharedConfig = serde_json::from_value(json!({
+            "providers": [
+                { "name": "openai", "model": "gpt-4o", "temperature": 3.0 },
+                { "name": "openai", "model": "" },
+            ],
+            "default_provider": "anthropic",
+            "cache": { "max_entries": 0 },
+            "retry": { "max_attempts": 0 },
+            "telemetry": { "endpoint": "collector:4318", "sample_ratio": 2.0 },
+        }))
+        .unwrap();
+        let Err(ConfigError::Invalid(problems)) = config.validate() else {
+            panic!("expected validation to fail");
+        };
+        assert_eq!(
+            problems,
+            [
+                "provider 'openai': temperature must be between 0 and 2, got 3",
+                "provider 'openai': model is empty",
+                "provider 'openai' is configured twice",
+                "default_provider 'anthropic' is not a configured provider",
+                "cache: max_entries must be at least 1",
+                "retry: max_attempts must be at least 1",
+                "telemetry: endpoint must be an http or https URL, got 'collector:4318'",
+                "telemetry: sample_ratio must be between 0 and 1, got 2",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_load_document_rejects_unknown_formats() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ggen.ini");
+        std::fs::write(&path, "providers = []").unwrap();
+        assert!(matches!(load_document(&path), Err(ConfigError::Format(_))));
+    }
+
+    #[test]
+    fn test_yaml_documents_read_like_toml() {
+        let toml = "[providers.openai]\nmodel = \"gpt-4o\"\n\n[cache]\nttl_secs = 60\n";
+        let yaml = "providers:\n  openai:\n    model: gpt-4o\ncache:\n  ttl_secs: 60\n";
+        assert_eq!(
+            parse_document(yaml, Format::Yaml).unwrap(),
+            parse_document(toml, Format::Toml).unwrap()
+        );
+        assert_eq!(Format::from_path(Path::new("ggen.yml")), Some(Format::Yaml));
+
+        let err = parse_document("providers:\n  openai:\n model: gpt-4o\n", Format::Yaml);
+        assert!(err.unwrap_err().contains("line 3"));
+    }
+}
@@ -0,0 +1,202 @@
+//! LLM providers and embedding models
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// One LLM provider
+///
+/// `api_key` is a literal key or a secret reference such as
+/// `env:OPENAI_API_KEY`; resolving it is up to the crate using it.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ProviderSettings {
+    /// Taken from the key when providers are given as a table
+    #[serde(default)]
+    pub name: String,
+    pub model: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_url: Option<String>,
+    /// Between 0 and 2
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    /// Stream completions instead of receiving them whole
+    #[serde(default, alias = "stream_enabled")]
+    pub streaming: bool,
+    /// Abandon a request after this many seconds
+    #[serde(
+        default,
+        alias = "timeout_seconds",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub timeout_secs: Option<u64>,
+    /// Settings only some crates understand, e.g. rig-mcp's `features` or
+    /// `gemini`, kept for them to read
+    #[serde(flatten)]
+    pub options: BTreeMap<String, Value>,
+}
+
+impl ProviderSettings {
+    pub fn new(name: impl Into<String>, model: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            model: model.into(),
+            ..Self::default()
+        }
+    }
+
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout_secs.map(Duration::from_secs)
+    }
+
+    pub(crate) fn check(&self, problems: &mut Vec<String>) {
+        let mut problem = |message: String| {
+            problems.push(format!("provider '{}': {}", self.name, message));
+        };
+        if self.name.is_empty() {
+            problem("name is empty".to_string());
+        }
+        if self.model.is_empty() {
+            problem("model is empty".to_string());
+        }
+        if let Some(temperature) = self.temperature {
+            // Also rejects NaN
+            if !(0.0..=2.0).contains(&temperature) {
+                problem(format!(
+                    "temperature must be between 0 and 2, got {}",
+                    temperature
+                ));
+            }
+        }
+        if self.max_tokens == Some(0) {
+            problem("max_tokens must be at least 1".to_string());
+        }
+        if self.timeout_secs == Some(0) {
+            problem("timeout_secs must be at least 1".to_string());
+        }
+        if let Some(url) = &self.base_url {
+            if !is_http_url(url) {
+                problem(format!(
+                    "base_url must be an http or https URL, got '{}'",
+                    url
+                ));
+            }
+        }
+    }
+}
+
+/// The embedding model used for tool selection and similarity search
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EmbeddingSettings {
+    pub provider: String,
+    pub model: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+    /// Dimensionality of the vectors, if known in advance
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dimensions: Option<usize>,
+    /// Settings only some crates understand, e.g. rig-mcp's `cache_path`
+    #[serde(flatten)]
+    pub options: BTreeMap<String, Value>,
+}
+
+impl EmbeddingSettings {
+    pub(crate) fn check(&self, problems: &mut Vec<String>) {
+        // An empty section turns embeddings off
+        if self.provider.is_empty() && self.model.is_empty() {
+            return;
+        }
+        if self.provider.is_empty() {
+            problems.push("embeddings: provider is empty".to_string());
+        }
+        if self.model.is_empty() {
+            problems.push("embeddings: model is empty".to_string());
+        }
+        if self.dimensions == Some(0) {
+            problems.push("embeddings: dimensions must be at least 1".to_string());
+        }
+    }
+}
+
+pub(crate) fn is_http_url(url: &str) -> bool {
+    ["http://", "https://"]
+        .iter()
+        .any(|scheme| url.len() > scheme.len() && url.starts_with(scheme))
+}
+
+/// Providers written as a list of tables with a `name` each, or as one
+/// table keyed by name, read in name order; always written back as a list
+pub mod list_or_table {
+    use super::ProviderSettings;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::collections::BTreeMap;
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Providers {
+        List(Vec<ProviderSettings>),
+        Table(BTreeMap<String, ProviderSettings>),
+    }
+
+    pub fn serialize<S: Serializer>(
+        providers: &[ProviderSettings], s: S,
+    ) -> Result<S::Ok, S::Error> {
+        providers.serialize(s)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<ProviderSettings>, D::Error> {
+        Ok(match Providers::deserialize(d)? {
+            Providers::List(providers) => providers,
+            Providers::Table(table) => table
+                .into_iter()
+                .map(|(name, provider)| ProviderSettings { name, ..provider })
+                .collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[derive(Deserialize)]
+    struct Document {
+        #[serde(with = "list_or_table")]
+        providers: Vec<ProviderSettings>,
+    }
+
+    #[test]
+    fn test_providers_as_list_or_table() {
+        let table: Document = toml::from_str(
+            r#"
+            [providers.openai]
+            model = "gpt-4o"
+            stream_enabled = true
+            timeout_seconds = 30
+            features = ["tools"]
+            "#,
+        )
+        .unwrap();
+        let list: Document = serde_json::from_value(json!({
+            "providers": [{
+                "name": "openai",
+                "model": "gpt-4o",
+                "streaming": true,
+                "timeout_secs": 30,
+                "features": ["tools"],
+            }],
+        }))
+        .unwrap();
+        assert_eq!(table.providers, list.providers);
+
+        let openai = &table.providers[0];
+        assert_eq!(openai.name, "openai");
+        assert_eq!(openai.timeout(), Some(Duration::from_secs(30)));
+        assert_eq!(openai.options["features"], json!(["tools"]));
+    }
+}
@@ -0,0 +1,83 @@
+//! Retrying failed provider requests
+
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// How often and how patiently to retry
+///
+/// The delay doubles after every attempt, from `initial_backoff_ms` up to
+/// `max_backoff_ms`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetrySettings {
+    /// Attempts in total, the first one included
+    #[serde(default = "default_max_attempts", alias = "retry_attempts")]
+    pub max_attempts: u32,
+    #[serde(default = "default_initial_backoff_ms")]
+    pub initial_backoff_ms: u64,
+    #[serde(default = "default_max_backoff_ms")]
+    pub max_backoff_ms: u64,
+}
+
+fn default_max_attempts() -> u32 {
+    3
+}
+
+fn default_initial_backoff_ms() -> u64 {
+    500
+}
+
+fn default_max_backoff_ms() -> u64 {
+    30_000
+}
+
+impl Default for RetrySettings {
+    fn default() -> Self {
+        Self {
+            max_attempts: default_max_attempts(),
+            initial_backoff_ms: default_initial_backoff_ms(),
+            max_backoff_ms: default_max_backoff_ms(),
+        }
+    }
+}
+
+impl RetrySettings {
+    /// Delay before attempt `attempt`, counting the first retry as 1
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 1u64
+            .checked_shl(attempt.saturating_sub(1))
+            .unwrap_or(u64::MAX);
+        let millis = self.initial_backoff_ms.saturating_mul(factor);
+        Duration::from_millis(millis.min(self.max_backoff_ms))
+    }
+
+    pub(crate) fn check(&self, problems: &mut Vec<String>) {
+        if self.max_attempts == 0 {
+            problems.push("retry: max_attempts must be at least 1".to_string());
+        }
+        if self.initial_backoff_ms > self.max_backoff_ms {
+            problems.push(format!(
+                "retry: initial_backoff_ms ({}) is above max_backoff_ms ({})",
+                self.initial_backoff_ms, self.max_backoff_ms
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_doubles_up_to_the_maximum() {
+        let retry = RetrySettings {
+            initial_backoff_ms: 100,
+            max_backoff_ms: 1000,
+            ..RetrySettings::default()
+        };
+        let delays: Vec<u64> = (1..=6)
+            .map(|n| retry.backoff(n).as_millis() as u64)
+            .collect();
+        assert_eq!(delays, [100, 200, 400, 800, 1000, 1000]);
+        assert_eq!(retry.backoff(200), Duration::from_millis(1000));
+    }
+}
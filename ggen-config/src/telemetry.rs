@@ -0,0 +1,43 @@
+//! Trace export
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::provider::is_http_url;
+
+/// Where OTLP/HTTP traces go
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TelemetrySettings {
+    /// Traces endpoint, e.g. `http://localhost:4318/v1/traces`
+    pub endpoint: String,
+    /// Sent with every export, e.g. an API key for a hosted collector
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub headers: HashMap<String, String>,
+    /// Each crate's own name unless set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub service_name: Option<String>,
+    /// Fraction of new traces to keep, 0 to 1
+    #[serde(default = "keep_all")]
+    pub sample_ratio: f64,
+}
+
+fn keep_all() -> f64 {
+    1.0
+}
+
+impl TelemetrySettings {
+    pub(crate) fn check(&self, problems: &mut Vec<String>) {
+        if !is_http_url(&self.endpoint) {
+            problems.push(format!(
+                "telemetry: endpoint must be an http or https URL, got '{}'",
+                self.endpoint
+            ));
+        }
+        if !(0.0..=1.0).contains(&self.sample_ratio) {
+            problems.push(format!(
+                "telemetry: sample_ratio must be between 0 and 1, got {}",
+                self.sample_ratio
+            ));
+        }
+    }
+}
@@ -0,0 +1,112 @@
+//! Every layout the crates have used, read through the shared types
+
+use ggen_config::{CacheSettings, ProviderSettings, RetrySettings, SharedConfig};
+use serde_json::json;
+use std::path::{Path, PathBuf};
+
+fn repo_file(path: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join(path)
+}
+
+#[test]
+fn test_microservice_ai_section() {
+    let config = SharedConfig::from_file(&repo_file(
+        "../marketplace/packages/ai-microservice/ggen.toml",
+    ))
+    .unwrap();
+    assert_eq!(config.deprecations.len(), 1);
+
+    let openai = config.default_provider().unwrap();
+    assert_eq!(
+        openai,
+        &ProviderSettings {
+            api_key: Some("env:OPENAI_API_KEY".to_string()),
+            temperature: Some(0.7),
+            max_tokens: Some(2000),
+            streaming: true,
+            timeout_secs: Some(30),
+            options: [("organization_env".to_string(), json!("OPENAI_ORG_ID"))].into(),
+            ..ProviderSettings::new("openai", "gpt-4")
+        }
+    );
+    let names: Vec<&str> = config.providers.iter().map(|p| p.name.as_str()).collect();
+    assert_eq!(names, ["anthropic", "ollama", "openai"]);
+    assert_eq!(
+        config.providers[1].base_url.as_deref(),
+        Some("http://localhost:11434")
+    );
+    assert_eq!(
+        config.cache,
+        Some(CacheSettings {
+            ttl_secs: 3600,
+            max_entries: 1000,
+            ..CacheSettings::default()
+        })
+    );
+    assert_eq!(config.retry.unwrap().max_attempts, 3);
+}
+
+#[test]
+fn test_ai_section_matches_the_shared_layout() {
+    let legacy = SharedConfig::from_document(json!({
+        "ai": {
+            "provider": "anthropic",
+            "model": "claude-3-5-sonnet-20241022",
+            "temperature": 0.3,
+            "retry_attempts": 5,
+            "cache": { "enabled": false, "ttl_seconds": 60 },
+            "providers": { "anthropic": { "api_key_env": "ANTHROPIC_API_KEY" } },
+        },
+    }))
+    .unwrap();
+    let current = SharedConfig::from_document(json!({
+        "default_provider": "anthropic",
+        "providers": [{
+            "name": "anthropic",
+            "model": "claude-3-5-sonnet-20241022",
+            "api_key": "env:ANTHROPIC_API_KEY",
+            "temperature": 0.3,
+        }],
+        "cache": { "enabled": false, "ttl_secs": 60 },
+        "retry": { "max_attempts": 5 },
+    }))
+    .unwrap();
+    assert!(current.deprecations.is_empty());
+    assert_eq!(
+        SharedConfig {
+            deprecations: Vec::new(),
+            ..legacy
+        },
+        current
+    );
+}
+
+#[test]
+fn test_combined_document() {
+    let config = SharedConfig::from_file(&repo_file("tests/fixtures/combined.toml")).unwrap();
+    assert!(config.deprecations.is_empty());
+
+    let openai = config.default_provider().unwrap();
+    assert_eq!(openai.name, "openai");
+    assert_eq!(openai.temperature, Some(0.2));
+    // rig-mcp's own settings are kept for it
+    assert_eq!(openai.options["features"], json!(["tools"]));
+    assert_eq!(config.providers[0].options["ensure_model"], json!(true));
+
+    let embeddings = config.embeddings.unwrap();
+    assert_eq!(embeddings.model, "text-embedding-3-small");
+    assert_eq!(
+        embeddings.options["cache_path"],
+        json!(".rig-mcp/embeddings.json")
+    );
+    assert_eq!(config.cache.unwrap().ttl_secs, 600);
+    assert_eq!(
+        config.retry,
+        Some(RetrySettings {
+            max_attempts: 4,
+            initial_backoff_ms: 250,
+            ..RetrySettings::default()
+        })
+    );
+    assert_eq!(config.telemetry.unwrap().sample_ratio, 0.5);
+}
@@ -0,0 +1,59 @@
+//! Loading a large ontology, streaming and eager
+//!
+//! The fixture is a synthetic Turtle document of 100k triples: classes with
+//! labels, comments, numeric weights and a subclass tree, written to a
+//! temporary file once.
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use ggen_core::graph::{Graph, LoadMode};
+use oxigraph::io::RdfFormat;
+use std::hint::black_box;
+use std::path::Path;
+use tempfile::TempDir;
+
+const ENTITIES: usize = 20_000;
+const TRIPLES_PER_ENTITY: usize = 5;
+
+fn write_fixture(path: &Path) {
+    let mut ttl = String::from(
+        "@prefix ex: <http://example.org/> .\n\
+         @prefix rdfs: <http://www.w3.org/2000/01/rdf-schema#> .\n",
+    );
+    for i in 0..ENTITIES {
+        ttl.push_str(&format!(
+            "ex:C{i} a rdfs:Class ;\n  rdfs:label \"Class {i}\"@en ;\n  \
+             rdfs:comment \"Synthetic class number {i}\" ;\n  ex:weight {i} ;\n  \
+             rdfs:subClassOf ex:C{} .\n",
+            i / 2
+        ));
+    }
+    std::fs::write(path, ttl).expect("fixture written");
+}
+
+fn bench_load(c: &mut Criterion) {
+    let dir = TempDir::new().expect("temp dir");
+    let path = dir.path().join("ontology.ttl");
+    write_fixture(&path);
+
+    let mut group = c.benchmark_group("graph_load_100k");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements((ENTITIES * TRIPLES_PER_ENTITY) as u64));
+    for (name, mode) in [
+        ("streaming", LoadMode::Streaming),
+        ("eager", LoadMode::Eager),
+    ] {
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                let graph = Graph::new().expect("graph");
+                let report = graph
+                    .load_file(&path, RdfFormat::Turtle, "", mode, &mut |_| {})
+                    .expect("fixture loads");
+                black_box(report)
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(graph_load, bench_load);
+criterion_main!(graph_load);
@@ -39,6 +39,9 @@ fn create_test_workspace_make(workspaces: usize, parallel: bool) -> Make {
             cache: None,
             workspaces: None,
             parallel: Some(parallel),
+            pre: None,
+            post: None,
+            lint_templates: None,
         },
     );
 
@@ -239,6 +242,9 @@ fn create_make_with_hooks(hook_count: usize) -> Make {
                 cache: None,
                 workspaces: None,
                 parallel: None,
+                pre: None,
+                post: None,
+                lint_templates: None,
             },
         );
     }
@@ -256,6 +262,9 @@ fn create_make_with_hooks(hook_count: usize) -> Make {
             cache: None,
             workspaces: None,
             parallel: None,
+            pre: None,
+            post: None,
+            lint_templates: None,
         },
     );
 
@@ -344,6 +353,9 @@ fn bench_hook_recursion_detection(c: &mut Criterion) {
             cache: None,
             workspaces: None,
             parallel: None,
+            pre: None,
+            post: None,
+            lint_templates: None,
         },
     );
 
@@ -17,6 +17,28 @@ pub struct GgenConfig {
 
     /// RDF configuration
     pub rdf: Option<RdfConfig>,
+
+    /// OpenAPI document generation
+    #[serde(default)]
+    pub openapi: OpenApiConfig,
+
+    /// Protobuf schema generation
+    #[serde(default)]
+    pub proto: ProtoConfig,
+
+    /// Limits on what templates may do
+    #[serde(default)]
+    pub security: SecurityConfig,
+
+    /// Named output roots that templates select with `target:`; see
+    /// [`crate::output`]
+    #[serde(default)]
+    pub output: BTreeMap<String, OutputProfile>,
+
+    /// Files to generate when no scope is given, e.g. `["entity=User"]`;
+    /// see [`crate::scope`]
+    #[serde(default)]
+    pub default_scope: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -30,6 +52,73 @@ pub struct RdfConfig {
     pub inline: Vec<String>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpenApiConfig {
+    /// Add `example`/`examples` synthesized from seed data; turn off for
+    /// size-sensitive documents
+    #[serde(default = "default_examples")]
+    pub examples: bool,
+
+    /// Seed of the example generator
+    #[serde(default = "default_example_seed")]
+    pub example_seed: u64,
+}
+
+fn default_examples() -> bool {
+    true
+}
+
+fn default_example_seed() -> u64 {
+    42
+}
+
+impl Default for OpenApiConfig {
+    fn default() -> Self {
+        Self {
+            examples: default_examples(),
+            example_seed: default_example_seed(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProtoConfig {
+    /// Manifest holding the field number allocations, so regenerating never
+    /// renumbers a field
+    #[serde(default = "default_proto_manifest")]
+    pub manifest: String,
+
+    /// Proto scalar per XSD datatype local name, over the built-in mapping
+    #[serde(default)]
+    pub scalars: BTreeMap<String, String>,
+}
+
+fn default_proto_manifest() -> String {
+    crate::proto_schema::DEFAULT_MANIFEST.to_string()
+}
+
+impl Default for ProtoConfig {
+    fn default() -> Self {
+        Self {
+            manifest: default_proto_manifest(),
+            scalars: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SecurityConfig {
+    /// Let templates give their output a world-writable `mode`
+    #[serde(default)]
+    pub allow_world_writable: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutputProfile {
+    /// Directory the profile's templates write to (relative to config file)
+    pub root: String,
+}
+
 impl Default for GgenConfig {
     fn default() -> Self {
         Self {
@@ -37,6 +126,11 @@ impl Default for GgenConfig {
             base: None,
             prefixes: BTreeMap::new(),
             rdf: None,
+            openapi: OpenApiConfig::default(),
+            proto: ProtoConfig::default(),
+            security: SecurityConfig::default(),
+            output: BTreeMap::new(),
+            default_scope: Vec::new(),
         }
     }
 }
@@ -98,6 +192,27 @@ impl GgenConfig {
             .unwrap_or_default()
     }
 
+    /// Template variables derived from the configuration:
+    /// `openapi_examples`, `openapi_example_seed`, `proto_manifest` and
+    /// `proto_scalars` (a JSON object)
+    pub fn template_vars(&self) -> BTreeMap<String, String> {
+        BTreeMap::from([
+            (
+                "openapi_examples".to_string(),
+                self.openapi.examples.to_string(),
+            ),
+            (
+                "openapi_example_seed".to_string(),
+                self.openapi.example_seed.to_string(),
+            ),
+            ("proto_manifest".to_string(), self.proto.manifest.clone()),
+            (
+                "proto_scalars".to_string(),
+                serde_json::to_string(&self.proto.scalars).unwrap_or_default(),
+            ),
+        ])
+    }
+
     /// Get inline RDF content
     pub fn rdf_inline_content(&self) -> Vec<String> {
         self.rdf
@@ -198,4 +313,67 @@ inline = [
 
         Ok(())
     }
+
+    #[test]
+    fn test_openapi_config() -> Result<()> {
+        let config = GgenConfig::default();
+        assert!(config.openapi.examples);
+        assert_eq!(config.template_vars()["openapi_examples"], "true");
+
+        let config: GgenConfig = toml::from_str(
+            r#"
+[openapi]
+examples = false
+"#,
+        )?;
+        assert!(!config.openapi.examples);
+        assert_eq!(config.openapi.example_seed, 42);
+        let vars = config.template_vars();
+        assert_eq!(vars["openapi_examples"], "false");
+        assert_eq!(vars["openapi_example_seed"], "42");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_proto_config() -> Result<()> {
+        let vars = GgenConfig::default().template_vars();
+        assert_eq!(vars["proto_manifest"], ".ggen/manifest.json");
+        assert_eq!(vars["proto_scalars"], "{}");
+
+        let config: GgenConfig = toml::from_str(
+            r#"
+[proto]
+manifest = "build/manifest.json"
+
+[proto.scalars]
+decimal = "string"
+"#,
+        )?;
+        let vars = config.template_vars();
+        assert_eq!(vars["proto_manifest"], "build/manifest.json");
+        assert_eq!(vars["proto_scalars"], r#"{"decimal":"string"}"#);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_output_profiles() -> Result<()> {
+        assert!(GgenConfig::default().output.is_empty());
+
+        let config: GgenConfig = toml::from_str(
+            r#"
+[output.rust]
+root = "crates/generated"
+
+[output.sql]
+root = "db/migrations"
+"#,
+        )?;
+        assert_eq!(config.output.len(), 2);
+        assert_eq!(config.output["sql"].root, "db/migrations");
+        assert!(toml::from_str::<GgenConfig>("[output.docs]\n").is_err());
+
+        Ok(())
+    }
 }
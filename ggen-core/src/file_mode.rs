@@ -0,0 +1,160 @@
+//! Permissions of generated files
+//!
+//! A template sets the mode of its output with `mode:` in the frontmatter,
+//! an octal string such as `"0600"`, or with `executable: true`, which is
+//! short for `"0755"`. The mode is applied after the file is written, on unix
+//! only. Other platforms have no mode bits: [`apply`] leaves the file as
+//! written, logs a warning and returns an [`UnappliedMode`], which
+//! [`crate::Generator`] collects so callers can report it. World-writable
+//! modes are refused everywhere unless `ggen.toml` allows them:
+//!
+//! ```toml
+//! [security]
+//! allow_world_writable = true
+//! ```
+
+use anyhow::{Context, Result};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::config::SecurityConfig;
+use crate::template::Frontmatter;
+
+/// Mode of `executable: true`
+pub const EXECUTABLE: u32 = 0o755;
+
+/// Parse an octal mode such as `0644`, `644` or `0o644`
+pub fn parse_mode(mode: &str) -> Result<u32> {
+    let digits = mode.trim();
+    let digits = digits.strip_prefix("0o").unwrap_or(digits);
+    let valid = (3..=4).contains(&digits.len()) && digits.chars().all(|c| ('0'..='7').contains(&c));
+    if !valid {
+        anyhow::bail!(
+            "Invalid file mode '{}': expected 3 or 4 octal digits such as \"0644\"",
+            mode
+        );
+    }
+    Ok(u32::from_str_radix(digits, 8)?)
+}
+
+/// Mode the frontmatter asks for, if any, checked against `security`
+pub fn resolve(front: &Frontmatter, security: &SecurityConfig) -> Result<Option<u32>> {
+    let mode = match (&front.mode, front.executable) {
+        (Some(_), true) => anyhow::bail!("Set either `mode` or `executable`, not both"),
+        (Some(mode), false) => parse_mode(mode)?,
+        (None, true) => EXECUTABLE,
+        (None, false) => return Ok(None),
+    };
+    if mode & 0o002 != 0 && !security.allow_world_writable {
+        anyhow::bail!(
+            "File mode {:04o} is world-writable; set allow_world_writable in the [security] \
+             section of ggen.toml to allow it",
+            mode
+        );
+    }
+    Ok(Some(mode))
+}
+
+/// A mode a template asked for that the platform could not set
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnappliedMode {
+    pub path: PathBuf,
+    pub mode: u32,
+}
+
+impl fmt::Display for UnappliedMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Ignored mode {:04o} of {}: file modes are only applied on unix",
+            self.mode,
+            self.path.display()
+        )
+    }
+}
+
+/// Set the permissions of `path` to `mode`
+#[cfg(unix)]
+pub fn apply(path: &Path, mode: u32) -> Result<Option<UnappliedMode>> {
+    use std::os::unix::fs::PermissionsExt;
+
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+        .with_context(|| format!("Failed to set mode {:04o} on {}", mode, path.display()))?;
+    Ok(None)
+}
+
+/// File modes are a unix concept; elsewhere the file is left as written
+#[cfg(not(unix))]
+pub fn apply(path: &Path, mode: u32) -> Result<Option<UnappliedMode>> {
+    let unapplied = UnappliedMode {
+        path: path.to_path_buf(),
+        mode,
+    };
+    log::warn!("{}", unapplied);
+    Ok(Some(unapplied))
+}
+
+/// Permission bits of `path`; `None` where there are none
+pub fn current_mode(path: &Path) -> Result<Option<u32>> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        let metadata = std::fs::metadata(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        Ok(Some(metadata.permissions().mode() & 0o7777))
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn front(mode: Option<&str>, executable: bool) -> Frontmatter {
+        Frontmatter {
+            mode: mode.map(String::from),
+            executable,
+            ..Frontmatter::default()
+        }
+    }
+
+    #[test]
+    fn test_parse_mode() {
+        assert_eq!(parse_mode("0644").unwrap(), 0o644);
+        assert_eq!(parse_mode("600").unwrap(), 0o600);
+        assert_eq!(parse_mode("0o4755").unwrap(), 0o4755);
+        for invalid in ["", "64", "0888", "07777", "rwxr-xr-x"] {
+            assert!(parse_mode(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_resolve_checks_world_writable_modes() {
+        let strict = SecurityConfig::default();
+        assert_eq!(resolve(&front(None, false), &strict).unwrap(), None);
+        assert_eq!(
+            resolve(&front(None, true), &strict).unwrap(),
+            Some(EXECUTABLE)
+        );
+        assert_eq!(
+            resolve(&front(Some("0600"), false), &strict).unwrap(),
+            Some(0o600)
+        );
+        assert!(resolve(&front(Some("0600"), true), &strict).is_err());
+
+        let err = resolve(&front(Some("0666"), false), &strict).unwrap_err();
+        assert!(err.to_string().contains("world-writable"), "{}", err);
+        let lenient = SecurityConfig {
+            allow_world_writable: true,
+        };
+        assert_eq!(
+            resolve(&front(Some("0666"), false), &lenient).unwrap(),
+            Some(0o666)
+        );
+    }
+}
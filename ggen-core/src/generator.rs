@@ -1,11 +1,19 @@
 use anyhow::Result;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::env;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use tera::Context;
 
+use crate::config::{GgenConfig, SecurityConfig};
+use crate::file_mode::{self, UnappliedMode};
+use crate::output::OutputProfiles;
 use crate::pipeline::Pipeline;
+use crate::plan::GenerationPlan;
+use crate::proto_schema::ManifestAccess;
+use crate::query_explain::QueryExplanation;
+use crate::register;
+use crate::scope::{self, Bindings, Scope};
 use crate::template::Template;
 
 /// Context for template generation with paths, variables, and configuration
@@ -16,6 +24,14 @@ pub struct GenContext {
     pub global_prefixes: BTreeMap<String, String>,
     pub base: Option<String>,
     pub dry_run: bool,
+    /// Explain each frontmatter query; see [`crate::query_explain`]
+    pub explain_queries: bool,
+    /// Checked against the `mode` of each template
+    pub security: SecurityConfig,
+    /// Roots of the templates that set `target:`
+    pub output_profiles: OutputProfiles,
+    /// Files to render; everything by default
+    pub scope: Scope,
 }
 
 impl GenContext {
@@ -27,12 +43,30 @@ impl GenContext {
             global_prefixes: BTreeMap::new(),
             base: None,
             dry_run: false,
+            explain_queries: false,
+            security: SecurityConfig::default(),
+            output_profiles: OutputProfiles::default(),
+            scope: Scope::default(),
         }
     }
     pub fn with_vars(mut self, vars: BTreeMap<String, String>) -> Self {
         self.vars = vars;
         self
     }
+    /// Add the variables and security settings of a `ggen.toml`; variables
+    /// already set win, so call this after [`GenContext::with_vars`]
+    pub fn with_config(mut self, config: &GgenConfig) -> Self {
+        for (key, value) in config.template_vars() {
+            self.vars.entry(key).or_insert(value);
+        }
+        self.security = config.security.clone();
+        self
+    }
+    /// Output profiles of a `ggen.toml`, for templates that set `target:`
+    pub fn with_output_profiles(mut self, profiles: OutputProfiles) -> Self {
+        self.output_profiles = profiles;
+        self
+    }
     pub fn with_prefixes(
         mut self, prefixes: BTreeMap<String, String>, base: Option<String>,
     ) -> Self {
@@ -40,33 +74,100 @@ impl GenContext {
         self.base = base;
         self
     }
+    /// Render only the files inside `scope`
+    pub fn with_scope(mut self, scope: Scope) -> Self {
+        self.scope = scope;
+        self
+    }
     pub fn dry(mut self, dry: bool) -> Self {
         self.dry_run = dry;
         self
     }
+    pub fn explain_queries(mut self, explain: bool) -> Self {
+        self.explain_queries = explain;
+        self
+    }
 }
 
 /// Main generator that orchestrates template processing and file generation
 pub struct Generator {
     pub pipeline: Pipeline,
     pub ctx: GenContext,
+    /// Queries explained by the last [`Generator::generate`], when
+    /// [`GenContext::explain_queries`] is set
+    pub explanations: Vec<QueryExplanation>,
+    /// Files the last [`Generator::generate`] left alone because they are
+    /// outside [`GenContext::scope`]
+    pub skipped: Vec<PathBuf>,
+    /// Modes the last [`Generator::generate`] could not set because the
+    /// platform has none; see [`crate::file_mode`]
+    pub unapplied_modes: Vec<UnappliedMode>,
+    /// What the last dry-run [`Generator::generate`] would have done; see
+    /// [`crate::plan`]
+    pub plan: GenerationPlan,
+}
+
+/// A file rendered by [`Generator::generate_outputs`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GeneratedOutput {
+    pub path: PathBuf,
+    /// The row of a `foreach` template, or the values of every query result
+    pub bindings: Bindings,
 }
 
 impl Generator {
     pub fn new(pipeline: Pipeline, ctx: GenContext) -> Self {
-        Self { pipeline, ctx }
+        Self {
+            pipeline,
+            ctx,
+            explanations: Vec::new(),
+            skipped: Vec::new(),
+            unapplied_modes: Vec::new(),
+            plan: GenerationPlan::default(),
+        }
     }
 
+    /// Render the template into its file; see [`Generator::generate_outputs`]
+    /// for `foreach` templates, which render one file per row
     pub fn generate(&mut self) -> Result<PathBuf> {
+        let mut outputs = self.generate_outputs()?;
+        if outputs.is_empty() {
+            anyhow::bail!("{} generated no file", self.ctx.template_path.display());
+        }
+        Ok(outputs.swap_remove(0).path)
+    }
+
+    /// Render the template into its files, one per row of its `foreach`
+    /// query or a single one, leaving out files outside
+    /// [`GenContext::scope`]
+    pub fn generate_outputs(&mut self) -> Result<Vec<GeneratedOutput>> {
+        self.skipped.clear();
+        self.unapplied_modes.clear();
+        self.plan = GenerationPlan::new(self.ctx.output_root.clone());
+        let template_key = self.ctx.template_path.display().to_string();
         let input = fs::read_to_string(&self.ctx.template_path)?;
         let mut tmpl = Template::parse(&input)?;
+        if self.ctx.explain_queries {
+            tmpl.query_explanations = Some(Vec::new());
+        }
+        // The incremental manifest lives under the output root, and a dry
+        // run leaves it as it is
+        let manifest = ManifestAccess {
+            root: Some(self.ctx.output_root.clone()),
+            save: !self.ctx.dry_run,
+        };
+        register::register_manifest_helpers(&mut self.pipeline.tera, manifest);
 
         // Context
         let mut tctx = Context::from_serialize(&self.ctx.vars)?;
         insert_env(&mut tctx);
+        for (k, v) in tmpl.resolve_typed_vars(&self.ctx.vars)? {
+            tctx.insert(k, &v);
+        }
 
         // Render frontmatter
         tmpl.render_frontmatter(&mut self.pipeline.tera, &tctx)?;
+        let mode = file_mode::resolve(&tmpl.front, &self.ctx.security)?;
 
         // Process graph
         tmpl.process_graph(
@@ -75,39 +176,118 @@ impl Generator {
             &tctx,
             &self.ctx.template_path,
         )?;
+        self.explanations = tmpl.query_explanations.take().unwrap_or_default();
+        if self.ctx.dry_run {
+            self.plan_inputs(&tmpl, &tctx)?;
+        }
 
-        // Render body
-        let rendered = tmpl.render(&mut self.pipeline.tera, &tctx)?;
-
-        // Determine output path
-        let output_path = if let Some(to_path) = &tmpl.front.to {
-            let rendered_to = self.pipeline.tera.render_str(to_path, &tctx)?;
-            self.ctx.output_root.join(rendered_to)
-        } else {
-            // Default to template name with .out extension
-            let template_name = self
-                .ctx
-                .template_path
-                .file_stem()
-                .ok_or_else(|| {
-                    anyhow::anyhow!(
-                        "Template path has no file stem: {}",
-                        self.ctx.template_path.display()
-                    )
-                })?
-                .to_string_lossy();
-            self.ctx.output_root.join(format!("{}.out", template_name))
+        // One context per file, with the row's variables for foreach
+        let files: Vec<(Context, Bindings)> = match &tmpl.front.foreach {
+            Some(query) => scope::foreach_rows(&tmpl.front.sparql_results, query)?
+                .into_iter()
+                .map(|row| {
+                    let mut row_ctx = tctx.clone();
+                    for (name, value) in &row {
+                        row_ctx.insert(name, value);
+                    }
+                    let bindings = row
+                        .into_iter()
+                        .map(|(name, value)| (name, BTreeSet::from([value])))
+                        .collect();
+                    (row_ctx, bindings)
+                })
+                .collect(),
+            None => vec![(
+                tctx.clone(),
+                scope::result_bindings(&tmpl.front.sparql_results),
+            )],
         };
 
-        if !self.ctx.dry_run {
-            // Ensure parent directory exists
-            if let Some(parent) = output_path.parent() {
-                fs::create_dir_all(parent)?;
+        let mut outputs = Vec::new();
+        for (tctx, bindings) in files {
+            let output_path = self.output_path(&tmpl, &tctx)?;
+            if !self.ctx.scope.includes(&bindings) {
+                if self.ctx.dry_run {
+                    self.plan.add_skip(&output_path, &template_key, bindings);
+                }
+                self.skipped.push(output_path);
+                continue;
             }
-            fs::write(&output_path, rendered)?;
+
+            // Render body
+            let rendered = tmpl.render(&mut self.pipeline.tera, &tctx)?;
+
+            if self.ctx.dry_run {
+                self.plan.add_write(
+                    &output_path,
+                    &rendered,
+                    &template_key,
+                    bindings.clone(),
+                    mode,
+                    tmpl.front.target.clone(),
+                )?;
+            } else {
+                // Ensure parent directory exists
+                if let Some(parent) = output_path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::write(&output_path, rendered)?;
+                if let Some(mode) = mode {
+                    self.unapplied_modes
+                        .extend(file_mode::apply(&output_path, mode)?);
+                }
+                if let Some(target) = &tmpl.front.target {
+                    self.ctx.output_profiles.record(target, &output_path)?;
+                }
+            }
+            outputs.push(GeneratedOutput {
+                path: output_path,
+                bindings,
+            });
+        }
+        if self.ctx.dry_run && self.ctx.scope.is_everything() {
+            self.plan.add_stale(&template_key)?;
         }
 
-        Ok(output_path)
+        Ok(outputs)
+    }
+
+    /// Record the template and its RDF files as inputs of the plan
+    fn plan_inputs(&mut self, tmpl: &Template, tctx: &Context) -> Result<()> {
+        let template_path = self.ctx.template_path.clone();
+        self.plan.add_input(&template_path)?;
+        let template_dir = template_path.parent().unwrap_or(Path::new("."));
+        for rdf_file in &tmpl.front.rdf {
+            let rendered = self.pipeline.tera.render_str(rdf_file, tctx)?;
+            self.plan.add_input(&template_dir.join(rendered))?;
+        }
+        Ok(())
+    }
+
+    /// Where the file rendered with `tctx` goes
+    fn output_path(&mut self, tmpl: &Template, tctx: &Context) -> Result<PathBuf> {
+        let to = match &tmpl.front.to {
+            Some(to_path) => self.pipeline.tera.render_str(to_path, tctx)?,
+            None => {
+                // Default to template name with .out extension
+                let template_name = self
+                    .ctx
+                    .template_path
+                    .file_stem()
+                    .ok_or_else(|| {
+                        anyhow::anyhow!(
+                            "Template path has no file stem: {}",
+                            self.ctx.template_path.display()
+                        )
+                    })?
+                    .to_string_lossy();
+                format!("{}.out", template_name)
+            }
+        };
+        match &tmpl.front.target {
+            Some(target) => self.ctx.output_profiles.resolve(target, &to),
+            None => Ok(self.ctx.output_root.join(to)),
+        }
     }
 }
 
@@ -163,6 +343,20 @@ mod tests {
         assert_eq!(ctx.vars, vars);
     }
 
+    #[test]
+    fn test_gen_context_with_config_keeps_explicit_vars() {
+        let mut config = GgenConfig::default();
+        config.openapi.examples = false;
+        let vars = BTreeMap::from([("openapi_examples".to_string(), "true".to_string())]);
+
+        let ctx = GenContext::new(PathBuf::from("test.tmpl"), PathBuf::from("output"))
+            .with_vars(vars)
+            .with_config(&config);
+
+        assert_eq!(ctx.vars["openapi_examples"], "true");
+        assert_eq!(ctx.vars["openapi_example_seed"], "42");
+    }
+
     #[test]
     fn test_gen_context_with_prefixes() {
         let template_path = PathBuf::from("test.tmpl");
@@ -279,6 +473,38 @@ to: "output/{{ name | lower }}.rs"
         assert!(!output_path.exists());
     }
 
+    #[test]
+    fn test_proto_manifest_is_kept_under_the_output_root() {
+        let (temp_dir, template_path) = create_test_template(
+            r#"---
+to: "api.proto"
+prefixes:
+  ex: "http://example.org/"
+rdf_inline:
+  - "@prefix ex: <http://example.org/> . ex:User a ex:Entity ; ex:hasField ex:userName . ex:userName ex:name \"name\" ."
+sparql:
+  fields: "SELECT ?entity ?field WHERE { ?entity a ex:Entity ; ex:hasField ?f . ?f ex:name ?field }"
+---
+{% set messages = proto_messages(fields=sparql_results.fields) %}{{ messages | length }}
+"#,
+        );
+        let output_root = temp_dir.path().join("out");
+        let manifest = output_root.join(crate::proto_schema::DEFAULT_MANIFEST);
+        let generate = |dry| {
+            let ctx = GenContext::new(template_path.clone(), output_root.clone()).dry(dry);
+            Generator::new(create_test_pipeline(), ctx)
+                .generate()
+                .unwrap()
+        };
+
+        // A dry run reads the manifest but doesn't write it
+        generate(true);
+        assert!(!manifest.exists());
+        generate(false);
+        let document = fs::read_to_string(&manifest).unwrap();
+        assert!(document.contains("\"name\": 1"), "{}", document);
+    }
+
     #[test]
     fn test_generate_with_default_output() {
         let (_temp_dir, template_path) = create_test_template(
@@ -339,6 +565,50 @@ to: "src/{{ module }}/{{ name | lower }}.rs"
         assert!(content.contains("// Nested output content"));
     }
 
+    #[test]
+    fn test_generate_with_typed_vars() {
+        let (_temp_dir, template_path) = create_test_template(
+            r#"---
+to: "out.txt"
+vars:
+  replicas: { type: int, default: 3 }
+  public: { type: bool, required: true }
+---
+{% if public %}public{% else %}private{% endif %} x{{ replicas + 1 }}
+"#,
+        );
+        let output_dir = _temp_dir.path().to_path_buf();
+        let generate = |vars: &[(&str, &str)]| {
+            let vars = vars
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect();
+            let ctx = GenContext::new(template_path.clone(), output_dir.clone()).with_vars(vars);
+            Generator::new(create_test_pipeline(), ctx)
+                .generate()
+                .map(|path| fs::read_to_string(path).unwrap())
+        };
+
+        assert_eq!(
+            generate(&[("public", "false")]).unwrap().trim(),
+            "private x4"
+        );
+        assert_eq!(
+            generate(&[("public", "true"), ("replicas", "1")])
+                .unwrap()
+                .trim(),
+            "public x2"
+        );
+
+        let err = generate(&[]).unwrap_err();
+        assert!(err.to_string().contains("'public' is required"));
+        let err = generate(&[("public", "true"), ("replicas", "three")]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "variable 'replicas' expected int, got 'three'"
+        );
+    }
+
     #[test]
     fn test_generate_invalid_template() {
         let (_temp_dir, template_path) = create_test_template(
@@ -391,4 +661,78 @@ invalid_yaml: [unclosed
         // Clean up
         std::env::remove_var("TEST_GGEN_VAR");
     }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_generate_executable_script() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let (temp_dir, template_path) = create_test_template(
+            "---\nto: \"hooks/pre-commit\"\nexecutable: true\n---\n#!/bin/sh\ncargo fmt --check\n",
+        );
+        let ctx = GenContext::new(template_path, temp_dir.path().to_path_buf());
+        let mut generator = Generator::new(create_test_pipeline(), ctx);
+        let output = generator.generate().unwrap();
+
+        let mode = fs::metadata(&output).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o755);
+        assert!(generator.unapplied_modes.is_empty());
+    }
+
+    #[cfg(not(unix))]
+    #[test]
+    fn test_modes_are_reported_where_unsupported() {
+        let (temp_dir, template_path) = create_test_template(
+            "---\nto: \"hooks/pre-commit\"\nexecutable: true\n---\n#!/bin/sh\ncargo fmt --check\n",
+        );
+        let ctx = GenContext::new(template_path, temp_dir.path().to_path_buf());
+        let mut generator = Generator::new(create_test_pipeline(), ctx);
+        let output = generator.generate().unwrap();
+
+        assert!(output.exists());
+        assert_eq!(
+            generator.unapplied_modes,
+            vec![UnappliedMode {
+                path: output,
+                mode: file_mode::EXECUTABLE,
+            }]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_generate_private_config() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let (temp_dir, template_path) =
+            create_test_template("---\nto: \"secrets.env\"\nmode: \"0600\"\n---\nTOKEN=x\n");
+        let ctx = GenContext::new(template_path, temp_dir.path().to_path_buf());
+        let output = Generator::new(create_test_pipeline(), ctx)
+            .generate()
+            .unwrap();
+
+        let mode = fs::metadata(&output).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[test]
+    fn test_generate_rejects_world_writable_mode() {
+        let (temp_dir, template_path) =
+            create_test_template("---\nto: \"shared.txt\"\nmode: \"0666\"\n---\nshared\n");
+        let ctx = GenContext::new(template_path.clone(), temp_dir.path().to_path_buf())
+            .with_config(&GgenConfig::default());
+        let err = Generator::new(create_test_pipeline(), ctx)
+            .generate()
+            .unwrap_err();
+        assert!(err.to_string().contains("world-writable"), "{}", err);
+        assert!(!temp_dir.path().join("shared.txt").exists());
+
+        let mut config = GgenConfig::default();
+        config.security.allow_world_writable = true;
+        let ctx =
+            GenContext::new(template_path, temp_dir.path().to_path_buf()).with_config(&config);
+        assert!(Generator::new(create_test_pipeline(), ctx)
+            .generate()
+            .is_ok());
+    }
 }
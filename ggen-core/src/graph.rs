@@ -1,21 +1,27 @@
 use ahash::AHasher;
 use anyhow::{bail, Result};
 use lru::LruCache;
-use oxigraph::io::RdfFormat;
+use oxigraph::io::{RdfFormat, RdfParser};
 use oxigraph::model::{GraphName, NamedNode, NamedOrBlankNode, Quad, Term};
 use oxigraph::sparql::QueryResults;
 use oxigraph::store::Store;
 use serde_json::Value as JsonValue;
+use sha2::{Digest, Sha256};
 use std::collections::BTreeMap;
 use std::fs::File;
 use std::hash::{Hash, Hasher};
-use std::io::BufReader;
+use std::io::{BufReader, Read};
 use std::num::NonZeroUsize;
 use std::path::Path;
 use std::sync::{
     atomic::{AtomicU64, Ordering},
     Arc, Mutex,
 };
+use std::time::{Duration, Instant};
+
+/// Quads parsed before they are written to the store, and between progress
+/// reports
+const LOAD_BATCH: usize = 10_000;
 
 #[derive(Clone, Debug)]
 pub enum CachedResult {
@@ -47,6 +53,48 @@ impl CachedResult {
     }
 }
 
+/// How [`Graph::load_file`] reads a file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LoadMode {
+    /// Parse the file while reading it, memory-mapped where possible, and
+    /// write the quads to the store in batches
+    #[default]
+    Streaming,
+    /// Read the whole file into a string and parse it at once, as before
+    /// streaming loads; kept for one release to compare against
+    Eager,
+}
+
+impl LoadMode {
+    /// [`LoadMode::Eager`] when `GGEN_EAGER_RDF` is set, otherwise streaming
+    pub fn from_env() -> Self {
+        if std::env::var_os("GGEN_EAGER_RDF").is_some() {
+            Self::Eager
+        } else {
+            Self::Streaming
+        }
+    }
+}
+
+/// How far a load has come
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoadProgress {
+    /// Triples parsed and stored so far, duplicates included
+    pub triples: u64,
+    pub elapsed: Duration,
+}
+
+/// A finished load
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoadReport {
+    /// Triples parsed, duplicates included
+    pub triples: u64,
+    pub elapsed: Duration,
+    /// Hex SHA-256 of the file, computed while it was parsed; a key for
+    /// anything derived from its contents
+    pub sha256: String,
+}
+
 /// Thread-safe Oxigraph wrapper with SPARQL caching. Clone is cheap (shared store).
 pub struct Graph {
     inner: Store,
@@ -152,24 +200,101 @@ impl Graph {
 
     pub fn load_path<P: AsRef<Path>>(&self, path: P) -> Result<()> {
         let path = path.as_ref();
-        let ext = path
-            .extension()
-            .and_then(|e| e.to_str())
-            .map(|s| s.to_ascii_lowercase())
-            .unwrap_or_default();
-
-        let fmt = match ext.as_str() {
-            "ttl" | "turtle" => RdfFormat::Turtle,
-            "nt" | "ntriples" => RdfFormat::NTriples,
-            "rdf" | "xml" => RdfFormat::RdfXml,
-            other => bail!("unsupported RDF format: {}", other),
-        };
+        self.load_file(
+            path,
+            rdf_format(path)?,
+            "",
+            LoadMode::from_env(),
+            &mut |_| {},
+        )?;
+        Ok(())
+    }
 
-        let file = File::open(path)?;
-        let reader = BufReader::new(file);
-        self.inner.load_from_reader(fmt, reader)?;
+    /// Load the `format` file at `path`, read as if `prolog` (e.g. the
+    /// `PREFIX` lines of a template) came first
+    ///
+    /// `on_progress` is called after every batch of quads stored, and once
+    /// more when the load is done.
+    pub fn load_file(
+        &self, path: &Path, format: RdfFormat, prolog: &str, mode: LoadMode,
+        on_progress: &mut dyn FnMut(LoadProgress),
+    ) -> Result<LoadReport> {
+        let started = Instant::now();
+        let loaded = match mode {
+            LoadMode::Streaming => self.load_streaming(path, format, prolog, started, on_progress),
+            LoadMode::Eager => self.load_eager(path, format, prolog, started),
+        };
+        // Batches stored before a parse error stay in the store
         self.bump_epoch();
-        Ok(())
+        let report = loaded?;
+        on_progress(LoadProgress {
+            triples: report.triples,
+            elapsed: report.elapsed,
+        });
+        Ok(report)
+    }
+
+    fn load_streaming(
+        &self, path: &Path, format: RdfFormat, prolog: &str, started: Instant,
+        on_progress: &mut dyn FnMut(LoadProgress),
+    ) -> Result<LoadReport> {
+        let file = File::open(path)?;
+        // SAFETY: the map is only read, front to back, while `file` is open.
+        // A file truncated by another process meanwhile would fault the read;
+        // ontologies aren't rewritten while they are being loaded. Files
+        // that can't be mapped, such as pipes, are read through a buffer.
+        let mapped = unsafe { memmap2::Mmap::map(&file) }.ok();
+        let source: Box<dyn Read + '_> = match &mapped {
+            Some(map) => Box::new(&map[..]),
+            None => Box::new(BufReader::new(&file)),
+        };
+        let mut hasher = Sha256::new();
+        let reader = prolog
+            .as_bytes()
+            .chain(separator(prolog).as_bytes())
+            .chain(HashingReader {
+                inner: source,
+                hasher: &mut hasher,
+            });
+
+        let mut triples = 0;
+        let mut batch = Vec::with_capacity(LOAD_BATCH);
+        for quad in RdfParser::from_format(format).for_reader(reader) {
+            batch.push(quad?);
+            if batch.len() == LOAD_BATCH {
+                self.inner.extend(batch.drain(..))?;
+                triples += LOAD_BATCH as u64;
+                on_progress(LoadProgress {
+                    triples,
+                    elapsed: started.elapsed(),
+                });
+            }
+        }
+        triples += batch.len() as u64;
+        self.inner.extend(batch)?;
+        Ok(LoadReport {
+            triples,
+            elapsed: started.elapsed(),
+            sha256: format!("{:x}", hasher.finalize()),
+        })
+    }
+
+    fn load_eager(
+        &self, path: &Path, format: RdfFormat, prolog: &str, started: Instant,
+    ) -> Result<LoadReport> {
+        let content = std::fs::read_to_string(path)?;
+        let sha256 = format!("{:x}", Sha256::digest(content.as_bytes()));
+        let text = format!("{prolog}{}{content}", separator(prolog));
+        let quads = RdfParser::from_format(format)
+            .for_reader(text.as_bytes())
+            .collect::<Result<Vec<_>, _>>()?;
+        let triples = quads.len() as u64;
+        self.inner.extend(quads)?;
+        Ok(LoadReport {
+            triples,
+            elapsed: started.elapsed(),
+            sha256,
+        })
     }
 
     pub fn query_cached(&self, sparql: &str) -> Result<CachedResult> {
@@ -302,6 +427,45 @@ impl Clone for Graph {
     }
 }
 
+/// RDF format of a file, by its extension
+pub fn rdf_format(path: &Path) -> Result<RdfFormat> {
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|s| s.to_ascii_lowercase())
+        .unwrap_or_default();
+
+    Ok(match ext.as_str() {
+        "ttl" | "turtle" => RdfFormat::Turtle,
+        "nt" | "ntriples" => RdfFormat::NTriples,
+        "rdf" | "xml" => RdfFormat::RdfXml,
+        other => bail!("unsupported RDF format: {}", other),
+    })
+}
+
+/// Line break between a prolog and the document it precedes
+fn separator(prolog: &str) -> &'static str {
+    if prolog.is_empty() {
+        ""
+    } else {
+        "\n"
+    }
+}
+
+/// Reader hashing everything read through it
+struct HashingReader<'a, R> {
+    inner: R,
+    hasher: &'a mut Sha256,
+}
+
+impl<R: Read> Read for HashingReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.hasher.update(&buf[..read]);
+        Ok(read)
+    }
+}
+
 pub fn build_prolog(prefixes: &BTreeMap<String, String>, base: Option<&str>) -> String {
     let mut s = String::new();
     if let Some(b) = base {
@@ -654,4 +818,81 @@ mod tests {
 
         Ok(())
     }
+
+    /// A Turtle document of `entities` classes with five triples each
+    fn synthetic_ontology(entities: usize) -> String {
+        let mut ttl = String::from(
+            "@prefix ex: <http://example.org/> .\n\
+             @prefix rdfs: <http://www.w3.org/2000/01/rdf-schema#> .\n",
+        );
+        for i in 0..entities {
+            ttl.push_str(&format!(
+                "ex:C{i} a rdfs:Class ;\n  rdfs:label \"Class {i}\"@en ;\n  \
+                 rdfs:comment \"Line one\\nline \\\"two\\\"\" ;\n  ex:weight {i} ;\n  \
+                 rdfs:subClassOf ex:C{} .\n",
+                i / 2
+            ));
+        }
+        ttl
+    }
+
+    #[test]
+    fn test_streaming_and_eager_loads_agree() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("ontology.ttl");
+        std::fs::write(&path, synthetic_ontology(3_000))?;
+        let queries = [
+            "SELECT (COUNT(*) AS ?n) WHERE { ?s ?p ?o }",
+            "SELECT ?c ?label WHERE { ?c rdfs:label ?label ; ex:weight ?w FILTER(?w < 20) } \
+             ORDER BY ?c",
+            "SELECT ?parent (COUNT(?c) AS ?children) WHERE { ?c rdfs:subClassOf ?parent } \
+             GROUP BY ?parent ORDER BY DESC(?children) ?parent LIMIT 5",
+            "SELECT ?comment WHERE { ex:C7 rdfs:comment ?comment }",
+        ];
+
+        let mut results = Vec::new();
+        for mode in [LoadMode::Eager, LoadMode::Streaming] {
+            let g = Graph::new()?;
+            // Prefixes come from the prolog, as templates pass them
+            let prolog = "PREFIX ex: <http://example.org/>\n\
+                          PREFIX rdfs: <http://www.w3.org/2000/01/rdf-schema#>\n";
+            let report = g.load_file(&path, RdfFormat::Turtle, prolog, mode, &mut |_| {})?;
+            let answers = queries
+                .iter()
+                .map(|q| Ok(g.query_cached(&format!("{prolog}{q}"))?.to_json()))
+                .collect::<Result<Vec<_>>>()?;
+            results.push((report.triples, report.sha256, g.len(), answers));
+        }
+        assert_eq!(results[0], results[1]);
+        let (triples, sha256, len, _) = &results[0];
+        assert_eq!((*triples, *len), (15_000, 15_000));
+        let file = std::fs::read(&path)?;
+        assert_eq!(*sha256, format!("{:x}", Sha256::digest(&file)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_file_reports_progress() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("ontology.ttl");
+        std::fs::write(&path, synthetic_ontology(5_000))?;
+        let g = Graph::new()?;
+        let epoch = g.current_epoch();
+        let mut seen = Vec::new();
+        let report = g.load_file(
+            &path,
+            RdfFormat::Turtle,
+            "",
+            LoadMode::Streaming,
+            &mut |p| seen.push(p.triples),
+        )?;
+        assert_eq!(seen, [10_000, 20_000, 25_000]);
+        assert_eq!(report.triples, 25_000);
+        assert!(g.current_epoch() > epoch);
+
+        std::fs::write(&path, "@prefix ex: <http://example.org/> .\nex:a ex:b")?;
+        let err = g.load_path(&path).unwrap_err();
+        assert!(!err.to_string().is_empty());
+        Ok(())
+    }
 }
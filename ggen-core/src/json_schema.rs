@@ -0,0 +1,371 @@
+//! JSON Schemas and MCP tools for the domain graph
+//!
+//! [`entity_schemas`] turns the fields of each entity into the JSON Schema
+//! that `openapi.tmpl` lists under `components.schemas`. [`tools`] turns
+//! `ex:APIEndpoint`s into MCP tools whose input schemas are cut from those
+//! same entity schemas, so the OpenAPI document and the tool manifest can't
+//! disagree about a field. Templates call them as
+//! `json_schemas(fields=..., examples=...)` and
+//! `mcp_tools(endpoints=..., fields=...)`; see `openapi.tmpl` and
+//! `mcp-tools.tmpl` of the api-endpoint package.
+//!
+//! `fields` are the rows [`crate::seed_data`] reads and `endpoints` the rows
+//! [`crate::proto_schema::services`] reads.
+
+use serde::Serialize;
+use serde_json::{json, Map, Number, Value};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::proto_schema::EndpointSpec;
+use crate::seed_data::FieldSpec;
+
+/// JSON Schema type of an XSD datatype
+pub fn json_type(datatype: &str) -> &'static str {
+    match datatype {
+        "integer" | "int" | "long" | "short" | "nonNegativeInteger" | "positiveInteger" => {
+            "integer"
+        }
+        "decimal" | "double" | "float" => "number",
+        "boolean" => "boolean",
+        _ => "string",
+    }
+}
+
+/// Schema of one field; a reference holds the other entity's id
+fn property(field: &FieldSpec) -> Value {
+    if let Some(target) = &field.references {
+        return json!({
+            "type": "string",
+            "format": "uuid",
+            "description": format!("Id of a {}", target),
+        });
+    }
+    let ty = json_type(&field.datatype);
+    let mut property = Map::new();
+    property.insert("type".to_string(), ty.into());
+    let format = match (field.format.as_deref(), field.datatype.as_str()) {
+        (Some(format), _) => Some(format),
+        (None, "dateTime") => Some("date-time"),
+        (None, "date") => Some("date"),
+        _ => None,
+    };
+    if let Some(format) = format {
+        property.insert("format".to_string(), format.into());
+    }
+    // Bounds limit the length of a string and the value of a number
+    let (min, max) = if ty == "string" {
+        ("minLength", "maxLength")
+    } else {
+        ("minimum", "maximum")
+    };
+    if let Some(value) = field.min {
+        property.insert(min.to_string(), number(value));
+    }
+    if let Some(value) = field.max {
+        property.insert(max.to_string(), number(value));
+    }
+    Value::Object(property)
+}
+
+/// Whole numbers are written without a fraction, as the graph has them
+fn number(value: f64) -> Value {
+    if value.fract() == 0.0 && value.abs() < 9_007_199_254_740_992.0 {
+        Value::from(value as i64)
+    } else {
+        Number::from_f64(value).map_or(Value::Null, Value::Number)
+    }
+}
+
+/// One object schema per entity of `fields`, keyed by name
+///
+/// Every schema has a read-only UUID `id`; a field named `id` is ignored.
+/// All properties are required.
+pub fn entity_schemas(fields: &[FieldSpec]) -> BTreeMap<String, Value> {
+    let mut by_entity: BTreeMap<&str, BTreeMap<&str, &FieldSpec>> = BTreeMap::new();
+    for field in fields {
+        let entity = by_entity.entry(field.entity.as_str()).or_default();
+        if field.name != "id" {
+            entity.insert(field.name.as_str(), field);
+        }
+    }
+    by_entity
+        .into_iter()
+        .map(|(entity, fields)| {
+            let mut properties = Map::new();
+            properties.insert(
+                "id".to_string(),
+                json!({ "type": "string", "format": "uuid", "readOnly": true }),
+            );
+            let mut required = vec![Value::from("id")];
+            for (name, field) in fields {
+                properties.insert(name.to_string(), property(field));
+                required.push(name.into());
+            }
+            let schema = json!({
+                "type": "object",
+                "required": required,
+                "properties": properties,
+            });
+            (entity.to_string(), schema)
+        })
+        .collect()
+}
+
+/// MCP annotations of a tool, by the HTTP method it calls
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolAnnotations {
+    pub read_only_hint: bool,
+    pub destructive_hint: bool,
+}
+
+/// An MCP tool calling one endpoint
+///
+/// `api`, `method` and `path` tell the generated shim which route to call;
+/// MCP clients only read the name, description, input schema and
+/// annotations.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct McpTool {
+    /// Local name of the endpoint, like `getUser`
+    pub name: String,
+    pub description: String,
+    pub api: String,
+    pub method: String,
+    pub path: String,
+    pub input_schema: Value,
+    pub annotations: ToolAnnotations,
+}
+
+/// One tool per endpoint, sorted by name
+///
+/// Item paths (`/users/:id`) take the `id`. `POST` and `PUT` take the
+/// properties of the endpoint's entity schema other than `id`, with the
+/// schema's required list, as the OpenAPI request body does. `GET` tools are
+/// read-only, and `PUT`, `PATCH` and `DELETE` tools destructive.
+pub fn tools(endpoints: &[EndpointSpec], schemas: &BTreeMap<String, Value>) -> Vec<McpTool> {
+    let mut tools: Vec<McpTool> = endpoints
+        .iter()
+        .map(|endpoint| McpTool {
+            name: endpoint.name.clone(),
+            description: description(endpoint),
+            api: endpoint.api.clone(),
+            method: endpoint.method.clone(),
+            path: endpoint.path.clone(),
+            input_schema: input_schema(endpoint, schemas),
+            annotations: ToolAnnotations {
+                read_only_hint: endpoint.method == "GET",
+                destructive_hint: matches!(endpoint.method.as_str(), "PUT" | "PATCH" | "DELETE"),
+            },
+        })
+        .collect();
+    tools.sort_by(|a, b| a.name.cmp(&b.name));
+    tools
+}
+
+fn is_item(path: &str) -> bool {
+    path.contains(":id") || path.contains("{id}")
+}
+
+fn description(endpoint: &EndpointSpec) -> String {
+    let route = format!("{} {}", endpoint.method, endpoint.path);
+    let Some(entity) = &endpoint.entity else {
+        return route;
+    };
+    let one = match entity.chars().next() {
+        Some('A' | 'E' | 'I' | 'O' | 'U') => format!("an {}", entity),
+        _ => format!("a {}", entity),
+    };
+    let action = match (endpoint.method.as_str(), is_item(&endpoint.path)) {
+        ("GET", false) => format!("List {} records", entity),
+        ("GET", true) => format!("Get {} by id", one),
+        ("POST", _) => format!("Create {}", one),
+        ("PUT" | "PATCH", _) => format!("Update {}", one),
+        ("DELETE", _) => format!("Delete {}", one),
+        _ => return route,
+    };
+    format!("{} ({})", action, route)
+}
+
+fn input_schema(endpoint: &EndpointSpec, schemas: &BTreeMap<String, Value>) -> Value {
+    let mut properties = Map::new();
+    let mut required = Vec::new();
+    if is_item(&endpoint.path) {
+        properties.insert(
+            "id".to_string(),
+            json!({ "type": "string", "format": "uuid" }),
+        );
+        required.push(Value::from("id"));
+    }
+    let entity = endpoint
+        .entity
+        .as_ref()
+        .and_then(|entity| schemas.get(entity));
+    if let (Some(schema), "POST" | "PUT" | "PATCH") = (entity, endpoint.method.as_str()) {
+        if let Some(fields) = schema["properties"].as_object() {
+            for (name, property) in fields.iter().filter(|(name, _)| *name != "id") {
+                properties.insert(name.clone(), property.clone());
+            }
+        }
+        if let Some(names) = schema["required"].as_array() {
+            required.extend(names.iter().filter(|name| *name != "id").cloned());
+        }
+    }
+    json!({
+        "type": "object",
+        "required": required,
+        "properties": properties,
+    })
+}
+
+fn field_specs(name: &str, args: &HashMap<String, Value>) -> tera::Result<Vec<FieldSpec>> {
+    let rows = args
+        .get("fields")
+        .and_then(Value::as_array)
+        .ok_or_else(|| tera::Error::msg(format!("{}: fields parameter required", name)))?;
+    Ok(rows.iter().filter_map(FieldSpec::from_row).collect())
+}
+
+/// `json_schemas(fields=..., examples=...)`; see the module docs. With
+/// `examples` from `seed_examples`, each schema gets its entity's first
+/// example.
+#[derive(Clone)]
+pub struct JsonSchemasFn;
+
+impl tera::Function for JsonSchemasFn {
+    fn call(&self, args: &HashMap<String, Value>) -> tera::Result<Value> {
+        let fields = field_specs("json_schemas", args)?;
+        let examples = args.get("examples");
+        let schemas = entity_schemas(&fields)
+            .into_iter()
+            .map(|(entity, mut schema)| {
+                let example = examples.and_then(|examples| examples[entity.as_str()].get(0));
+                if let (Some(example), Some(schema)) = (example, schema.as_object_mut()) {
+                    schema.insert("example".to_string(), example.clone());
+                }
+                (entity, schema)
+            })
+            .collect();
+        Ok(Value::Object(schemas))
+    }
+}
+
+/// `mcp_tools(endpoints=..., fields=...)`; see the module docs
+#[derive(Clone)]
+pub struct McpToolsFn;
+
+impl tera::Function for McpToolsFn {
+    fn call(&self, args: &HashMap<String, Value>) -> tera::Result<Value> {
+        let rows = args
+            .get("endpoints")
+            .and_then(Value::as_array)
+            .ok_or_else(|| tera::Error::msg("mcp_tools: endpoints parameter required"))?;
+        let endpoints: Vec<EndpointSpec> = rows.iter().filter_map(EndpointSpec::from_row).collect();
+        let schemas = entity_schemas(&field_specs("mcp_tools", args)?);
+        Ok(json!(tools(&endpoints, &schemas)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(entity: &str, name: &str, datatype: &str) -> FieldSpec {
+        FieldSpec {
+            entity: entity.to_string(),
+            name: name.to_string(),
+            datatype: datatype.to_string(),
+            ..FieldSpec::default()
+        }
+    }
+
+    fn endpoint(name: &str, method: &str, path: &str) -> EndpointSpec {
+        EndpointSpec {
+            name: name.to_string(),
+            api: "orders".to_string(),
+            path: path.to_string(),
+            method: method.to_string(),
+            entity: Some("Order".to_string()),
+        }
+    }
+
+    fn orders() -> Vec<FieldSpec> {
+        vec![
+            FieldSpec {
+                min: Some(1.0),
+                max: Some(499.5),
+                ..field("Order", "total", "decimal")
+            },
+            FieldSpec {
+                references: Some("User".to_string()),
+                ..field("Order", "userId", "string")
+            },
+            field("Order", "placedAt", "dateTime"),
+            FieldSpec {
+                max: Some(200.0),
+                ..field("Order", "note", "string")
+            },
+        ]
+    }
+
+    #[test]
+    fn test_entity_schemas_carry_types_formats_and_bounds() {
+        let schemas = entity_schemas(&orders());
+        assert_eq!(
+            schemas["Order"],
+            json!({
+                "type": "object",
+                "required": ["id", "note", "placedAt", "total", "userId"],
+                "properties": {
+                    "id": { "type": "string", "format": "uuid", "readOnly": true },
+                    "note": { "type": "string", "maxLength": 200 },
+                    "placedAt": { "type": "string", "format": "date-time" },
+                    "total": { "type": "number", "minimum": 1, "maximum": 499.5 },
+                    "userId": { "type": "string", "format": "uuid", "description": "Id of a User" },
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn test_tool_inputs_are_cut_from_the_entity_schema() {
+        let schemas = entity_schemas(&orders());
+        let tools = tools(
+            &[
+                endpoint("updateOrder", "PUT", "/orders/:id"),
+                endpoint("createOrder", "POST", "/orders"),
+                endpoint("listOrders", "GET", "/orders"),
+                EndpointSpec {
+                    entity: None,
+                    ..endpoint("health", "GET", "/health")
+                },
+            ],
+            &schemas,
+        );
+        let names: Vec<&str> = tools.iter().map(|tool| tool.name.as_str()).collect();
+        assert_eq!(
+            names,
+            ["createOrder", "health", "listOrders", "updateOrder"]
+        );
+
+        let create = &tools[0];
+        let mut properties = schemas["Order"]["properties"].clone();
+        properties.as_object_mut().unwrap().remove("id");
+        assert_eq!(create.input_schema["properties"], properties);
+        assert_eq!(
+            create.input_schema["required"],
+            json!(["note", "placedAt", "total", "userId"])
+        );
+        assert_eq!(create.description, "Create an Order (POST /orders)");
+        assert!(!create.annotations.read_only_hint && !create.annotations.destructive_hint);
+
+        let update = &tools[3];
+        assert_eq!(update.input_schema["required"][0], "id");
+        assert_eq!(update.input_schema["properties"]["total"]["maximum"], 499.5);
+        assert!(update.annotations.destructive_hint);
+
+        assert_eq!(tools[1].description, "GET /health");
+        assert_eq!(tools[1].input_schema["properties"], json!({}));
+        assert!(tools[2].annotations.read_only_hint);
+    }
+}
@@ -8,26 +8,40 @@ pub mod config;
 pub mod delta;
 #[cfg(test)]
 pub mod e2e_tests;
+pub mod file_mode;
 pub mod generator;
 pub mod github;
 pub mod gpack;
 pub mod graph;
 pub mod inject;
+pub mod json_schema;
 pub mod lifecycle;
 pub mod lockfile;
 pub mod merge;
+pub mod output;
+pub mod path_guard;
 pub mod pipeline;
+pub mod plan;
 pub mod poc;
 pub mod pqc;
 pub mod preprocessor;
+pub mod proto_schema;
+pub mod query_explain;
+pub mod regen_check;
 pub mod register;
 pub mod registry;
 pub mod resolver;
+pub mod scope;
+pub mod seed_data;
+pub mod selftest;
 pub mod snapshot;
 pub mod template;
+pub mod template_lint;
+pub mod template_vars;
 pub mod tera_env;
 // pub mod tracing; // Temporarily disabled due to missing tracing_subscriber dependency
 pub mod simple_tracing;
+pub mod sql_schema;
 
 // Re-export production readiness types from lifecycle module
 pub use lifecycle::{
@@ -47,7 +61,9 @@ pub use merge::{
     ConflictType, MergeConflict, MergeResult, MergeStrategy, RegionAwareMerger, RegionUtils,
     ThreeWayMerger,
 };
+pub use output::OutputProfiles;
 pub use pipeline::{Pipeline, PipelineBuilder};
+pub use plan::{FileAction, GenerationPlan};
 pub use pqc::{calculate_sha256, calculate_sha256_file, PqcSigner, PqcVerifier};
 pub use registry::{RegistryClient, RegistryIndex, ResolvedPack, SearchResult};
 pub use resolver::{TemplateResolver, TemplateSearchResult, TemplateSource};
@@ -0,0 +1,284 @@
+//! Pre/post command hooks with a typed context
+//!
+//! [`Hooks`](super::model::Hooks) order phases around each other; command
+//! hooks are declared on a phase and run shell commands that need to know what
+//! the phase did, such as `cargo fmt` over the generated crate or a schema
+//! upload before deploy:
+//!
+//! ```toml
+//! [lifecycle.build]
+//! command = "ggen project gen"
+//!
+//! [[lifecycle.build.post]]
+//! run = "sqlfluff fix db/migrations"
+//! timeout = 120
+//! ```
+//!
+//! Each hook gets a [`HookContext`] as JSON on stdin. Hook commands pass the
+//! same shell-injection guard as template `sh_before`/`sh_after` hooks.
+
+use super::error::{LifecycleError, Result};
+use super::model::CommandHook;
+use crate::config::GgenConfig;
+use crate::output::OutputProfiles;
+use crate::pipeline::is_dangerous_command;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+/// Whether a hook runs before or after the phase commands
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HookStage {
+    Pre,
+    Post,
+}
+
+impl HookStage {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HookStage::Pre => "pre",
+            HookStage::Post => "post",
+        }
+    }
+}
+
+/// What a hook is told on stdin
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HookContext {
+    pub phase: String,
+    pub stage: HookStage,
+    pub root: PathBuf,
+    /// Generated files, relative to `root`, modified since the phase last
+    /// finished (pre) or since this run started (post)
+    pub changed_files: Vec<PathBuf>,
+    /// Output profile roots by name, relative to `root`
+    pub outputs: BTreeMap<String, PathBuf>,
+    /// SHA-256 of the `[rdf]` files and inline RDF in `ggen.toml`
+    pub ontology_hash: Option<String>,
+}
+
+impl HookContext {
+    /// Context for `stage` of `phase` in `root`, counting files in the output
+    /// manifest modified at or after `since_ms` as changed
+    pub fn collect(root: &Path, phase: &str, stage: HookStage, since_ms: u128) -> Result<Self> {
+        let mut context = Self {
+            phase: phase.to_string(),
+            stage,
+            root: root.to_path_buf(),
+            changed_files: Vec::new(),
+            outputs: BTreeMap::new(),
+            ontology_hash: None,
+        };
+        let config_path = root.join("ggen.toml");
+        if !config_path.exists() {
+            return Ok(context);
+        }
+        let config = GgenConfig::load_from_file(&config_path).map_err(|e| {
+            LifecycleError::Other(format!("Failed to load {}: {}", config_path.display(), e))
+        })?;
+
+        let profiles = OutputProfiles::from_config(&config, root)
+            .map_err(|e| LifecycleError::Other(e.to_string()))?;
+        let mut changed = BTreeSet::new();
+        for name in profiles.names() {
+            let profile_root = profiles
+                .root(name)
+                .map_err(|e| LifecycleError::Other(e.to_string()))?;
+            context
+                .outputs
+                .insert(name.to_string(), relative(root, profile_root));
+            let tracked = profiles
+                .tracked(name)
+                .map_err(|e| LifecycleError::Other(e.to_string()))?;
+            for file in tracked {
+                if modified_ms(&file).is_some_and(|modified| modified >= since_ms) {
+                    changed.insert(relative(root, &file));
+                }
+            }
+        }
+        context.changed_files = changed.into_iter().collect();
+        context.ontology_hash = ontology_hash(&config, root)?;
+        Ok(context)
+    }
+}
+
+/// Run `hooks` in order, stopping at the first failure
+pub fn run_command_hooks(
+    hooks: &[CommandHook], context: &HookContext, env: &[(String, String)],
+) -> Result<()> {
+    let input = serde_json::to_vec(context)
+        .map_err(|e| LifecycleError::Other(format!("Failed to encode hook context: {}", e)))?;
+    for hook in hooks {
+        tracing::debug!(
+            phase = %context.phase,
+            stage = context.stage.as_str(),
+            command = %hook.run,
+            "Running command hook"
+        );
+        run_command_hook(hook, context, &input, env)?;
+    }
+    Ok(())
+}
+
+fn run_command_hook(
+    hook: &CommandHook, context: &HookContext, input: &[u8], env: &[(String, String)],
+) -> Result<()> {
+    if is_dangerous_command(&hook.run) {
+        return Err(LifecycleError::HookCommandBlocked {
+            phase: context.phase.clone(),
+            command: hook.run.clone(),
+        });
+    }
+
+    let mut command = if cfg!(target_os = "windows") {
+        let mut c = Command::new("cmd");
+        c.arg("/C");
+        c
+    } else {
+        let mut c = Command::new("sh");
+        c.arg("-c");
+        c
+    };
+    command
+        .current_dir(&context.root)
+        .arg(&hook.run)
+        .envs(env.iter().map(|(k, v)| (k, v)))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::piped());
+    let mut child = command
+        .spawn()
+        .map_err(|e| LifecycleError::command_spawn(&context.phase, &hook.run, e))?;
+
+    // Feed stdin and drain stderr on their own threads so neither pipe can
+    // fill up and stall the hook
+    let mut stdin = child.stdin.take();
+    let input = input.to_vec();
+    std::thread::spawn(move || {
+        if let Some(stdin) = stdin.as_mut() {
+            // A hook that doesn't read its context closes the pipe early
+            let _ = stdin.write_all(&input);
+        }
+    });
+    let mut stderr = child.stderr.take();
+    let stderr_reader = std::thread::spawn(move || {
+        let mut captured = String::new();
+        if let Some(stderr) = stderr.as_mut() {
+            let _ = stderr.read_to_string(&mut captured);
+        }
+        captured
+    });
+
+    let timeout = Duration::from_secs(hook.timeout);
+    let start = Instant::now();
+    let status = loop {
+        match child
+            .try_wait()
+            .map_err(|e| LifecycleError::command_spawn(&context.phase, &hook.run, e))?
+        {
+            Some(status) => break status,
+            None if start.elapsed() > timeout => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(LifecycleError::HookTimeout {
+                    phase: context.phase.clone(),
+                    stage: context.stage.as_str().to_string(),
+                    command: hook.run.clone(),
+                    timeout_secs: hook.timeout,
+                });
+            }
+            None => std::thread::sleep(Duration::from_millis(50)),
+        }
+    };
+
+    let stderr = stderr_reader.join().unwrap_or_default();
+    if !status.success() {
+        return Err(LifecycleError::HookCommandFailed {
+            phase: context.phase.clone(),
+            stage: context.stage.as_str().to_string(),
+            command: hook.run.clone(),
+            exit_code: status.code().unwrap_or(-1),
+            stderr: stderr.trim_end().to_string(),
+        });
+    }
+    if !stderr.is_empty() {
+        eprint!("{}", stderr);
+    }
+    Ok(())
+}
+
+fn ontology_hash(config: &GgenConfig, root: &Path) -> Result<Option<String>> {
+    let Some(rdf) = &config.rdf else {
+        return Ok(None);
+    };
+    if rdf.files.is_empty() && rdf.inline.is_empty() {
+        return Ok(None);
+    }
+    let mut hasher = Sha256::new();
+    for file in &rdf.files {
+        let path = config.resolve_path(root, file);
+        let content = std::fs::read(&path).map_err(|e| LifecycleError::file_io(&path, e))?;
+        hasher.update(&content);
+    }
+    for inline in &rdf.inline {
+        hasher.update(inline.as_bytes());
+    }
+    Ok(Some(format!("{:x}", hasher.finalize())))
+}
+
+fn modified_ms(path: &Path) -> Option<u128> {
+    let modified = std::fs::metadata(path).ok()?.modified().ok()?;
+    Some(modified.duration_since(UNIX_EPOCH).ok()?.as_millis())
+}
+
+fn relative(root: &Path, path: &Path) -> PathBuf {
+    path.strip_prefix(root).unwrap_or(path).to_path_buf()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn context(root: &Path) -> HookContext {
+        HookContext::collect(root, "build", HookStage::Post, 0).unwrap()
+    }
+
+    fn hook(run: &str, timeout: u64) -> CommandHook {
+        CommandHook {
+            run: run.to_string(),
+            timeout,
+        }
+    }
+
+    #[test]
+    fn test_hook_timeout_kills_the_command() {
+        let temp = TempDir::new().unwrap();
+        let start = Instant::now();
+        let err =
+            run_command_hooks(&[hook("sleep 10", 1)], &context(temp.path()), &[]).unwrap_err();
+
+        assert!(matches!(
+            err,
+            LifecycleError::HookTimeout {
+                timeout_secs: 1,
+                ..
+            }
+        ));
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_dangerous_hook_commands_are_blocked() {
+        let temp = TempDir::new().unwrap();
+        let err = run_command_hooks(&[hook("rm -rf generated", 5)], &context(temp.path()), &[])
+            .unwrap_err();
+
+        assert!(matches!(err, LifecycleError::HookCommandBlocked { .. }));
+    }
+}
@@ -64,6 +64,37 @@ pub enum LifecycleError {
         source: Box<LifecycleError>,
     },
 
+    /// Pre/post command hook exited nonzero
+    #[error("{stage} hook failed in phase '{phase}': {command}\n  Exit code: {exit_code}\n  Stderr: {stderr}")]
+    HookCommandFailed {
+        phase: String,
+        stage: String,
+        command: String,
+        exit_code: i32,
+        stderr: String,
+    },
+
+    /// Pre/post command hook ran past its timeout and was killed
+    #[error("{stage} hook in phase '{phase}' killed after {timeout_secs}s: {command}")]
+    HookTimeout {
+        phase: String,
+        stage: String,
+        command: String,
+        timeout_secs: u64,
+    },
+
+    /// Pre/post command hook rejected by the shell-injection guard
+    #[error("Blocked potentially dangerous hook command in phase '{phase}': {command}")]
+    HookCommandBlocked { phase: String, command: String },
+
+    /// Templates linted before the phase have errors
+    #[error("Template lint found {errors} error(s) in phase '{phase}':\n{report}")]
+    TemplateLint {
+        phase: String,
+        errors: usize,
+        report: String,
+    },
+
     /// State file load error
     #[error("Failed to load state from {path}: {source}")]
     StateLoad {
@@ -1,6 +1,9 @@
 //! Phase execution with hooks and state management
 
+use super::command_hook::{run_command_hooks, HookContext, HookStage};
+use super::lint::lint_templates;
 use super::{cache::cache_key, error::*, loader::load_make, model::*, state::*};
+use crate::path_guard;
 use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::time::Instant;
@@ -79,6 +82,19 @@ fn run_phase_internal(ctx: &Context, phase_name: &str) -> Result<()> {
     // Run before hooks first
     run_before_hooks(ctx, phase_name)?;
 
+    // Pre command hooks see what changed since the phase last finished
+    if let Some(pre) = &phase.pre {
+        let since = load_state(&ctx.state_path)?
+            .last_run(phase_name)
+            .map_or(0, |run| run.started_ms + run.duration_ms);
+        let context = HookContext::collect(&ctx.root, phase_name, HookStage::Pre, since)?;
+        run_command_hooks(pre, &context, &ctx.env)?;
+    }
+
+    if let Some(patterns) = &phase.lint_templates {
+        lint_templates(&ctx.root, phase_name, patterns)?;
+    }
+
     // Print phase start message for CLI output (after hooks)
     println!("Running phase: {}", phase_name);
 
@@ -102,6 +118,12 @@ fn run_phase_internal(ctx: &Context, phase_name: &str) -> Result<()> {
         execute_command(cmd, &ctx.root, &ctx.env)?;
     }
 
+    // Post command hooks can fail the phase, so they run before it's recorded
+    if let Some(post) = &phase.post {
+        let context = HookContext::collect(&ctx.root, phase_name, HookStage::Post, started)?;
+        run_command_hooks(post, &context, &ctx.env)?;
+    }
+
     let duration = timer.elapsed().as_millis();
     tracing::info!(
         phase = %phase_name,
@@ -210,7 +232,7 @@ fn create_workspace_context(
     })?;
 
     // SECURITY: Ensure workspace path is within project root
-    if !canonical_ws.starts_with(&canonical_root) {
+    if !path_guard::is_within(&canonical_ws, &canonical_root) {
         return Err(LifecycleError::Other(format!(
             "Security violation: workspace '{}' path '{}' is outside project root",
             ws_name, workspace.path
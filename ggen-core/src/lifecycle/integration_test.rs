@@ -743,4 +743,104 @@ command = "echo 'test'"
 
     // Removed test_parallel_state_persistence - state persistence is already verified by
     // test_parallel_workspace_execution which checks workspace state files
+
+    /// Project whose build generates one file into the `rust` output profile,
+    /// with `hooks` appended to make.toml
+    fn hooked_project(hooks: &str) -> (TempDir, Context) {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::write(
+            root.join("ggen.toml"),
+            "[rdf]\ninline = [\"@prefix ex: <http://example.org/> . ex:A a ex:B .\"]\n\n\
+             [output.rust]\nroot = \"gen/rust\"\n",
+        )
+        .unwrap();
+        fs::write(root.join("record.sh"), "cat > \"hook-$1.json\"\n").unwrap();
+        let make_toml = format!(
+            r#"
+[project]
+name = "hooked"
+
+[lifecycle.build]
+command = "mkdir -p gen/rust .ggen && echo 'pub struct Model;' > gen/rust/model.rs && echo '{{\"rust\": [\"model.rs\"]}}' > .ggen/outputs.json"
+{}
+"#,
+            hooks
+        );
+        fs::write(root.join("make.toml"), make_toml).unwrap();
+
+        let make = Arc::new(load_make(root.join("make.toml")).unwrap());
+        let ctx = Context::new(
+            root.to_path_buf(),
+            make,
+            root.join(".ggen/state.json"),
+            vec![],
+        );
+        (temp_dir, ctx)
+    }
+
+    #[test]
+    fn test_command_hooks_receive_typed_context() {
+        let (temp_dir, ctx) = hooked_project(
+            r#"
+[[lifecycle.build.pre]]
+run = "sh record.sh pre"
+
+[[lifecycle.build.post]]
+run = "sh record.sh post"
+timeout = 10
+"#,
+        );
+
+        run_phase(&ctx, "build").unwrap();
+
+        let read = |stage: &str| -> HookContext {
+            let path = temp_dir.path().join(format!("hook-{}.json", stage));
+            serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap()
+        };
+        let pre = read("pre");
+        let post = read("post");
+        assert_eq!(pre.stage, HookStage::Pre);
+        assert!(pre.changed_files.is_empty());
+        assert_eq!(post.phase, "build");
+        assert_eq!(post.stage, HookStage::Post);
+        assert_eq!(post.changed_files, vec![PathBuf::from("gen/rust/model.rs")]);
+        assert_eq!(post.outputs["rust"], PathBuf::from("gen/rust"));
+        assert_eq!(
+            post.ontology_hash,
+            Some(crate::pqc::calculate_sha256(
+                b"@prefix ex: <http://example.org/> . ex:A a ex:B ."
+            ))
+        );
+    }
+
+    #[test]
+    fn test_failing_post_hook_fails_the_phase_with_stderr() {
+        let (_temp_dir, ctx) = hooked_project(
+            r#"
+[[lifecycle.build.post]]
+run = "echo 'migrations need formatting' >&2; exit 3"
+"#,
+        );
+
+        let err = run_phase(&ctx, "build").unwrap_err();
+        match err {
+            LifecycleError::HookCommandFailed {
+                stage,
+                exit_code,
+                stderr,
+                ..
+            } => {
+                assert_eq!(stage, "post");
+                assert_eq!(exit_code, 3);
+                assert_eq!(stderr, "migrations need formatting");
+            }
+            other => panic!("expected a hook failure, got {}", other),
+        }
+        // A phase failed by its hook isn't recorded as a successful run
+        assert!(load_state(&ctx.state_path)
+            .unwrap()
+            .last_run("build")
+            .is_none());
+    }
 }
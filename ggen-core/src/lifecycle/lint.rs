@@ -0,0 +1,104 @@
+//! Template lint before a phase
+//!
+//! A phase can lint templates before its commands run, so a misspelled
+//! filter or an undefined variable fails the build with a position instead
+//! of an empty string in the output:
+//!
+//! ```toml
+//! [lifecycle.build]
+//! command = "ggen project gen"
+//! lint_templates = ["templates/**/*.tmpl"]
+//! ```
+//!
+//! Patterns are relative to the project root. Templates are checked with
+//! [`crate::template_lint`] against the filters and functions of a generation
+//! pipeline, and the variables of `ggen.toml` count as provided. Errors fail
+//! the phase; warnings are printed.
+
+use super::error::{LifecycleError, Result};
+use crate::config::GgenConfig;
+use crate::pipeline::Pipeline;
+use crate::template_lint::{lint_template, Severity};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Lint the templates matching `patterns` under `root`
+pub fn lint_templates(root: &Path, phase: &str, patterns: &[String]) -> Result<()> {
+    let config_path = root.join("ggen.toml");
+    let provided: Vec<String> = if config_path.exists() {
+        let config = GgenConfig::load_from_file(&config_path).map_err(|e| {
+            LifecycleError::Other(format!("Failed to load {}: {}", config_path.display(), e))
+        })?;
+        config.template_vars().into_keys().collect()
+    } else {
+        Vec::new()
+    };
+    let provided: Vec<&str> = provided.iter().map(String::as_str).collect();
+
+    let mut pipeline = Pipeline::new().map_err(|e| LifecycleError::Other(e.to_string()))?;
+    pipeline.register_prefixes(None, &BTreeMap::new());
+
+    let mut errors = 0;
+    let mut report = Vec::new();
+    for pattern in patterns {
+        let full = root.join(pattern);
+        let paths = glob::glob(&full.to_string_lossy()).map_err(|e| {
+            LifecycleError::Other(format!("Invalid lint pattern '{}': {}", pattern, e))
+        })?;
+        for path in paths.flatten() {
+            let source =
+                std::fs::read_to_string(&path).map_err(|e| LifecycleError::file_io(&path, e))?;
+            let shown = path
+                .strip_prefix(root)
+                .unwrap_or(&path)
+                .display()
+                .to_string();
+            for finding in lint_template(&source, &pipeline.tera, &provided) {
+                let line = format!("{}:{}", shown, finding);
+                if finding.severity == Severity::Error {
+                    errors += 1;
+                    report.push(line);
+                } else {
+                    eprintln!("{}", line);
+                }
+            }
+        }
+    }
+
+    if errors > 0 {
+        return Err(LifecycleError::TemplateLint {
+            phase: phase.to_string(),
+            errors,
+            report: report.join("\n"),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_lint_errors_fail_the_phase() {
+        let temp = TempDir::new().unwrap();
+        let templates = temp.path().join("templates");
+        std::fs::create_dir_all(&templates).unwrap();
+        std::fs::write(templates.join("ok.tmpl"), "{{ 1 | upper }}\n").unwrap();
+        std::fs::write(templates.join("bad.tmpl"), "\n{{ name | snak }}\n").unwrap();
+
+        let err =
+            lint_templates(temp.path(), "build", &["templates/*.tmpl".to_string()]).unwrap_err();
+
+        match err {
+            LifecycleError::TemplateLint { errors, report, .. } => {
+                assert_eq!(errors, 2);
+                assert!(report.contains("templates/bad.tmpl:2:4: error[undefined-variable]"));
+                assert!(report.contains("templates/bad.tmpl:2:11: error[unknown-filter]"));
+                assert!(!report.contains("ok.tmpl"));
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+}
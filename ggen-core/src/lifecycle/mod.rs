@@ -11,6 +11,8 @@
 //! - File-based state persistence
 //! - Deterministic caching
 //! - Before/after hooks with recursion detection
+//! - Pre/post command hooks fed a typed context on stdin
+//! - Template lint before a phase's commands
 //!
 //! # Excluded Complexity (YAGNI until proven needed)
 //!
@@ -20,8 +22,10 @@
 //! - Advanced caching strategies (SHA256 is sufficient)
 
 pub mod cache;
+pub mod command_hook;
 pub mod error;
 pub mod exec;
+pub mod lint;
 pub mod loader;
 pub mod model;
 pub mod state;
@@ -47,10 +51,11 @@ mod behavior_tests;
 
 // Public API (minimal and focused)
 pub use cache::cache_key;
+pub use command_hook::{HookContext, HookStage};
 pub use error::{LifecycleError, Result};
 pub use exec::{run_phase, run_pipeline, Context};
 pub use loader::load_make;
-pub use model::{Hooks, Make, Phase, Project, Workspace};
+pub use model::{CommandHook, Hooks, Make, Phase, Project, Workspace};
 pub use state::{load_state, save_state, LifecycleState};
 
 // Production readiness exports
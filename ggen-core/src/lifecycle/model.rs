@@ -68,6 +68,38 @@ pub struct Phase {
     pub workspaces: Option<Vec<String>>,
     #[serde(default)]
     pub parallel: Option<bool>,
+
+    // Commands run before and after the phase, fed a typed context on stdin
+    #[serde(default)]
+    pub pre: Option<Vec<CommandHook>>,
+    #[serde(default)]
+    pub post: Option<Vec<CommandHook>>,
+
+    // Globs of templates linted before the commands run
+    #[serde(default)]
+    pub lint_templates: Option<Vec<String>>,
+}
+
+/// A command run before or after a phase
+///
+/// ```toml
+/// [[lifecycle.build.post]]
+/// run = "cargo fmt"
+/// timeout = 120
+/// ```
+///
+/// The command gets a [`HookContext`](super::command_hook::HookContext) as
+/// JSON on stdin. A nonzero exit fails the phase with the captured stderr, and
+/// a hook still running after `timeout` seconds is killed.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CommandHook {
+    pub run: String,
+    #[serde(default = "default_hook_timeout")]
+    pub timeout: u64,
+}
+
+fn default_hook_timeout() -> u64 {
+    60
 }
 
 /// Hook definitions for lifecycle phases
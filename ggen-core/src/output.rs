@@ -0,0 +1,371 @@
+//! Output profiles
+//!
+//! A project that generates into several places names each output root in
+//! `ggen.toml`:
+//!
+//! ```toml
+//! [output.rust]
+//! root = "crates/generated"
+//!
+//! [output.sql]
+//! root = "db/migrations"
+//! ```
+//!
+//! A template picks a profile with `target: sql` in its frontmatter, and its
+//! `to:` is then relative to that profile's root. Roots are relative to the
+//! directory of `ggen.toml` and may not leave it, and `to:` may not leave its
+//! root.
+//!
+//! Every file written into a profile is recorded in [`MANIFEST`], so
+//! `ggen clean --target sql` removes the files generated into `db/migrations`
+//! and nothing else, hand-written files in the same directory included.
+
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use crate::config::GgenConfig;
+
+/// Files generated per profile, relative to the directory of `ggen.toml`
+pub const MANIFEST: &str = ".ggen/outputs.json";
+
+/// Generated files per profile, each relative to its profile root
+type Manifest = BTreeMap<String, BTreeSet<String>>;
+
+/// The output profiles of a `ggen.toml`, with their roots resolved
+#[derive(Debug, Clone, Default)]
+pub struct OutputProfiles {
+    workspace: PathBuf,
+    roots: BTreeMap<String, PathBuf>,
+}
+
+impl OutputProfiles {
+    /// Profiles of `config`, whose file is in `config_dir`
+    pub fn from_config(config: &GgenConfig, config_dir: &Path) -> Result<Self> {
+        let roots = config
+            .output
+            .iter()
+            .map(|(name, profile)| {
+                let Some(root) = normalize(Path::new(&profile.root)) else {
+                    anyhow::bail!(
+                        "Output profile '{}' has root '{}' outside the project; roots are \
+                         relative to ggen.toml and may not leave its directory",
+                        name,
+                        profile.root
+                    );
+                };
+                Ok((name.clone(), config_dir.join(root)))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            workspace: config_dir.to_path_buf(),
+            roots,
+        })
+    }
+
+    /// Names of the profiles, sorted
+    pub fn names(&self) -> Vec<&str> {
+        self.roots.keys().map(String::as_str).collect()
+    }
+
+    /// Root directory of `target`
+    pub fn root(&self, target: &str) -> Result<&Path> {
+        match self.roots.get(target) {
+            Some(root) => Ok(root),
+            None if self.roots.is_empty() => anyhow::bail!(
+                "Unknown output target '{}': ggen.toml defines no [output] profiles",
+                target
+            ),
+            None => anyhow::bail!(
+                "Unknown output target '{}'; available profiles: {}",
+                target,
+                self.names().join(", ")
+            ),
+        }
+    }
+
+    /// Path of a rendered `to:` in `target`, refusing paths that leave its
+    /// root
+    pub fn resolve(&self, target: &str, to: &str) -> Result<PathBuf> {
+        let root = self.root(target)?;
+        let Some(relative) = normalize(Path::new(to)) else {
+            anyhow::bail!(
+                "Output path '{}' leaves the root of output profile '{}' ({})",
+                to,
+                target,
+                root.display()
+            );
+        };
+        Ok(root.join(relative))
+    }
+
+    /// Record `path`, returned by [`OutputProfiles::resolve`], as generated
+    /// into `target`
+    pub fn record(&self, target: &str, path: &Path) -> Result<()> {
+        let relative = path
+            .strip_prefix(self.root(target)?)
+            .with_context(|| format!("{} is not in output profile '{}'", path.display(), target))?;
+        let mut manifest = self.load()?;
+        let files = manifest.entry(target.to_string()).or_default();
+        if files.insert(manifest_key(relative)) {
+            self.save(&manifest)?;
+        }
+        Ok(())
+    }
+
+    /// Files recorded as generated into `target`
+    pub fn tracked(&self, target: &str) -> Result<Vec<PathBuf>> {
+        let root = self.root(target)?;
+        Ok(self
+            .load()?
+            .remove(target)
+            .unwrap_or_default()
+            .iter()
+            .map(|file| root.join(file))
+            .collect())
+    }
+
+    /// Remove the files generated into `target`, or into every profile, and
+    /// the directories left empty below their roots; returns the removed
+    /// files. Files already deleted are skipped.
+    pub fn clean(&self, target: Option<&str>) -> Result<Vec<PathBuf>> {
+        let targets = match target {
+            Some(target) => vec![self.root(target).map(|_| target)?],
+            None => self.names(),
+        };
+        let mut manifest = self.load()?;
+        let mut removed = Vec::new();
+        for target in targets {
+            let root = &self.roots[target];
+            for file in manifest.remove(target).unwrap_or_default() {
+                let path = root.join(&file);
+                match fs::remove_file(&path) {
+                    Ok(()) => {}
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                    Err(e) => {
+                        return Err(e)
+                            .with_context(|| format!("Failed to remove {}", path.display()))
+                    }
+                }
+                remove_empty_parents(&path, root);
+                removed.push(path);
+            }
+        }
+        self.save(&manifest)?;
+        Ok(removed)
+    }
+
+    fn manifest_path(&self) -> PathBuf {
+        self.workspace.join(MANIFEST)
+    }
+
+    fn load(&self) -> Result<Manifest> {
+        let path = self.manifest_path();
+        match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content)
+                .with_context(|| format!("Failed to parse {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Manifest::new()),
+            Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        }
+    }
+
+    fn save(&self, manifest: &Manifest) -> Result<()> {
+        let path = self.manifest_path();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, serde_json::to_string_pretty(manifest)? + "\n")
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+/// `path` without `.` and `..`, or `None` if it is absolute or climbs above
+/// the directory it is joined to
+pub(crate) fn normalize(path: &Path) -> Option<PathBuf> {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => normalized.push(part),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    return None;
+                }
+            }
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    Some(normalized)
+}
+
+/// `relative` with `/` separators, so the manifest reads the same everywhere
+pub(crate) fn manifest_key(relative: &Path) -> String {
+    relative
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn remove_empty_parents(file: &Path, root: &Path) {
+    let mut dir = file.parent();
+    while let Some(current) = dir {
+        if current == root || !current.starts_with(root) || fs::remove_dir(current).is_err() {
+            break;
+        }
+        dir = current.parent();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::{GenContext, Generator};
+    use crate::pipeline::Pipeline;
+    use tempfile::TempDir;
+
+    fn workspace() -> (TempDir, GgenConfig) {
+        let dir = TempDir::new().unwrap();
+        fs::write(
+            dir.path().join("ggen.toml"),
+            r#"
+[output.rust]
+root = "crates/generated"
+
+[output.sql]
+root = "db/migrations"
+"#,
+        )
+        .unwrap();
+        let config = GgenConfig::load_from_file(&dir.path().join("ggen.toml")).unwrap();
+        (dir, config)
+    }
+
+    fn generate(dir: &Path, config: &GgenConfig, name: &str, template: &str) -> Result<PathBuf> {
+        let template_path = dir.join(name);
+        fs::write(&template_path, template)?;
+        let ctx = GenContext::new(template_path, dir.to_path_buf())
+            .with_config(config)
+            .with_output_profiles(OutputProfiles::from_config(config, dir)?);
+        Generator::new(Pipeline::new()?, ctx).generate()
+    }
+
+    #[test]
+    fn test_templates_render_into_their_profiles() {
+        let (dir, config) = workspace();
+        let root = dir.path();
+
+        let model = generate(
+            root,
+            &config,
+            "model.tmpl",
+            "---\ntarget: rust\nto: \"src/model.rs\"\n---\npub struct Model;\n",
+        )
+        .unwrap();
+        let migration = generate(
+            root,
+            &config,
+            "migration.tmpl",
+            "---\ntarget: sql\nto: \"001_init.sql\"\n---\nCREATE TABLE model ();\n",
+        )
+        .unwrap();
+
+        assert_eq!(model, root.join("crates/generated/src/model.rs"));
+        assert_eq!(migration, root.join("db/migrations/001_init.sql"));
+        assert!(model.exists() && migration.exists());
+
+        let profiles = OutputProfiles::from_config(&config, root).unwrap();
+        assert_eq!(profiles.tracked("sql").unwrap(), vec![migration]);
+    }
+
+    #[test]
+    fn test_clean_removes_only_the_target_profile() {
+        let (dir, config) = workspace();
+        let root = dir.path();
+        let model = generate(
+            root,
+            &config,
+            "model.tmpl",
+            "---\ntarget: rust\nto: \"src/model.rs\"\n---\npub struct Model;\n",
+        )
+        .unwrap();
+        let migration = generate(
+            root,
+            &config,
+            "migration.tmpl",
+            "---\ntarget: sql\nto: \"001_init.sql\"\n---\nCREATE TABLE model ();\n",
+        )
+        .unwrap();
+        let hand_written = root.join("db/migrations/000_extensions.sql");
+        fs::write(&hand_written, "CREATE EXTENSION citext;\n").unwrap();
+
+        let profiles = OutputProfiles::from_config(&config, root).unwrap();
+        assert_eq!(
+            profiles.clean(Some("sql")).unwrap(),
+            vec![migration.clone()]
+        );
+
+        assert!(!migration.exists());
+        assert!(hand_written.exists());
+        assert!(model.exists());
+        assert!(profiles.tracked("sql").unwrap().is_empty());
+        assert_eq!(profiles.tracked("rust").unwrap(), vec![model.clone()]);
+
+        assert_eq!(profiles.clean(None).unwrap(), vec![model]);
+        assert!(!root.join("crates/generated/src").exists());
+        assert!(root.join("crates/generated").exists());
+    }
+
+    #[test]
+    fn test_unknown_target_lists_profiles() {
+        let (dir, config) = workspace();
+        let err = generate(
+            dir.path(),
+            &config,
+            "docs.tmpl",
+            "---\ntarget: docs\nto: \"api.md\"\n---\n# API\n",
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Unknown output target 'docs'; available profiles: rust, sql"
+        );
+
+        let profiles = OutputProfiles::from_config(&config, dir.path()).unwrap();
+        assert!(profiles.clean(Some("docs")).is_err());
+        assert!(
+            OutputProfiles::from_config(&GgenConfig::default(), dir.path())
+                .unwrap()
+                .root("docs")
+                .unwrap_err()
+                .to_string()
+                .contains("no [output] profiles")
+        );
+    }
+
+    #[test]
+    fn test_paths_may_not_leave_their_root() {
+        let (dir, config) = workspace();
+        let err = generate(
+            dir.path(),
+            &config,
+            "escape.tmpl",
+            "---\ntarget: sql\nto: \"../../crates/generated/lib.rs\"\n---\n\n",
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("leaves the root"), "{}", err);
+        assert!(!dir.path().join("crates/generated/lib.rs").exists());
+
+        let profiles = OutputProfiles::from_config(&config, dir.path()).unwrap();
+        assert!(profiles.resolve("sql", "/etc/passwd").is_err());
+        assert_eq!(
+            profiles.resolve("sql", "v1/../001.sql").unwrap(),
+            dir.path().join("db/migrations/001.sql")
+        );
+
+        let config: GgenConfig = toml::from_str("[output.up]\nroot = \"../elsewhere\"\n").unwrap();
+        let err = OutputProfiles::from_config(&config, dir.path()).unwrap_err();
+        assert!(err.to_string().contains("outside the project"), "{}", err);
+    }
+}
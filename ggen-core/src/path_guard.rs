@@ -0,0 +1,157 @@
+//! Containment checks for canonical paths
+//!
+//! Traversal guards canonicalize a path and the directory it must stay in,
+//! then check one is inside the other. On Windows the two may still be
+//! spelled differently: `canonicalize` returns verbatim paths (`\\?\C:\...`,
+//! `\\?\UNC\server\share\...`) while configured roots are usually written
+//! `C:\...` or `\\server\share\...`, with either separator and any case.
+//! [`is_within`] compares paths the way the platform's file system does:
+//! on Windows with prefixes unified, `/` and `\` alike and case ignored, on
+//! unix component by component as written.
+
+use std::path::Path;
+
+/// Conventions a path is written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathStyle {
+    Unix,
+    Windows,
+}
+
+impl PathStyle {
+    /// Style of the running platform
+    pub const fn native() -> Self {
+        if cfg!(windows) {
+            Self::Windows
+        } else {
+            Self::Unix
+        }
+    }
+}
+
+/// Whether `path` is `root` or inside it; both should be canonical
+pub fn is_within(path: &Path, root: &Path) -> bool {
+    is_within_as(
+        &path.to_string_lossy(),
+        &root.to_string_lossy(),
+        PathStyle::native(),
+    )
+}
+
+/// [`is_within`] for paths written in `style`
+pub fn is_within_as(path: &str, root: &str, style: PathStyle) -> bool {
+    let path = components(path, style);
+    let root = components(root, style);
+    path.starts_with(&root)
+}
+
+/// Components of `path` as `style` compares them: the root first (`/`, a
+/// drive such as `c:` or a share such as `\\server\share`), without `.`
+/// and with `..` applied
+///
+/// A `..` that would climb above the root is kept, so the path matches no
+/// root it escaped from.
+pub fn components(path: &str, style: PathStyle) -> Vec<String> {
+    let (root, rest) = match style {
+        PathStyle::Unix => {
+            let root = path.starts_with('/').then(|| "/".to_string());
+            (root, path.to_string())
+        }
+        PathStyle::Windows => windows_root(&path.replace('/', "\\").to_lowercase()),
+    };
+    let separator = match style {
+        PathStyle::Unix => '/',
+        PathStyle::Windows => '\\',
+    };
+    let mut components: Vec<String> = root.into_iter().collect();
+    let floor = components.len();
+    for part in rest.split(separator) {
+        match part {
+            "" | "." => {}
+            ".." if components.len() > floor && components.last().is_some_and(|c| c != "..") => {
+                components.pop();
+            }
+            part => components.push(part.to_string()),
+        }
+    }
+    components
+}
+
+/// The root of a lowercased Windows path with `\` separators, and the rest
+fn windows_root(path: &str) -> (Option<String>, String) {
+    let unc = path
+        .strip_prefix(r"\\?\unc\")
+        .or_else(|| path.strip_prefix(r"\\.\unc\"))
+        .or_else(|| {
+            path.strip_prefix(r"\\")
+                .filter(|rest| !rest.starts_with(r"?\") && !rest.starts_with(r".\"))
+        });
+    if let Some(unc) = unc {
+        let mut parts = unc.splitn(3, '\\');
+        let server = parts.next().unwrap_or_default();
+        let share = parts.next().unwrap_or_default();
+        let rest = parts.next().unwrap_or_default();
+        return (Some(format!(r"\\{}\{}", server, share)), rest.to_string());
+    }
+    let path = path
+        .strip_prefix(r"\\?\")
+        .or_else(|| path.strip_prefix(r"\\.\"))
+        .unwrap_or(path);
+    let bytes = path.as_bytes();
+    if bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' {
+        return (Some(path[..2].to_string()), path[2..].to_string());
+    }
+    match path.strip_prefix('\\') {
+        Some(rest) => (Some(r"\".to_string()), rest.to_string()),
+        None => (None, path.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unix_paths() {
+        let within = |path, root| is_within_as(path, root, PathStyle::Unix);
+        assert!(within("/srv/app/templates/a.ttl", "/srv/app"));
+        assert!(within("/srv/app", "/srv/app/"));
+        assert!(within("/srv/app/./x/../y", "/srv/app"));
+        assert!(!within("/srv/application", "/srv/app"));
+        assert!(!within("/srv/app/../other", "/srv/app"));
+        assert!(!within("/SRV/app/a", "/srv/app"));
+        assert!(!within("srv/app/a", "/srv/app"));
+        assert!(!within("/../srv/app", "/srv/app"));
+    }
+
+    #[test]
+    fn test_windows_drive_paths() {
+        let within = |path, root| is_within_as(path, root, PathStyle::Windows);
+        assert!(within(r"\\?\C:\Work\App\templates\a.ttl", r"C:\work\app"));
+        assert!(within(r"c:/work/app/x", r"\\?\C:\Work\App"));
+        assert!(within(r"C:\work\app\x\..\y", r"C:\Work\App\"));
+        assert!(!within(r"D:\work\app\x", r"C:\work\app"));
+        assert!(!within(r"C:\work\application", r"C:\work\app"));
+        assert!(!within(r"C:\work\app\..\..\windows", r"C:\work\app"));
+        assert!(!within(r"\work\app\x", r"C:\work\app"));
+        assert_eq!(
+            components(r"\\?\C:\Work\App", PathStyle::Windows),
+            vec!["c:", "work", "app"]
+        );
+    }
+
+    #[test]
+    fn test_windows_unc_paths() {
+        let within = |path, root| is_within_as(path, root, PathStyle::Windows);
+        assert!(within(r"\\?\UNC\fs\Share\app\a.ttl", r"\\FS\share\app"));
+        assert!(within(r"//fs/share/app/a.ttl", r"\\fs\share"));
+        assert!(!within(r"\\fs\other\app", r"\\fs\share"));
+        assert!(!within(r"\\other\share\app", r"\\fs\share"));
+        // A share is not a directory of the drive it may be mapped to
+        assert!(!within(r"\\fs\share\app", r"C:\"));
+        assert_eq!(
+            components(r"\\?\UNC\fs\share\app", PathStyle::Windows),
+            vec![r"\\fs\share", "app"]
+        );
+    }
+}
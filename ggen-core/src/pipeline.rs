@@ -1,4 +1,5 @@
 use crate::graph::{build_prolog, Graph};
+use crate::proto_schema::ManifestAccess;
 use crate::register;
 use crate::simple_tracing::SimpleTracer;
 use crate::template::Frontmatter;
@@ -62,6 +63,14 @@ impl Pipeline {
         let input = std::fs::read_to_string(template_path)?;
         let _body_lines = input.lines().count();
 
+        // Manifest paths stay relative to the working directory, and a dry
+        // run leaves the manifest as it is
+        let manifest = ManifestAccess {
+            root: None,
+            save: !dry_run,
+        };
+        register::register_manifest_helpers(&mut self.tera, manifest);
+
         // Create Tera context from vars
         let mut ctx = Context::from_serialize(vars)?;
         // if SimpleTracer::is_enabled() {
@@ -76,6 +85,11 @@ impl Pipeline {
         //         &format!("Template parsed: {} lines", body_lines), Some("parsing"));
         // } // Temporarily disabled
 
+        // Typed vars replace their string form before anything is rendered
+        for (k, v) in template.resolve_typed_vars(vars)? {
+            ctx.insert(k, &v);
+        }
+
         // Render frontmatter first to get the final 'to' field
         template.render_frontmatter(&mut self.tera, &ctx)?;
         // SimpleTracer::frontmatter_processed(&template.front); // Temporarily disabled
@@ -86,7 +100,9 @@ impl Pipeline {
 
         // Register template-defined vars into the context (after frontmatter render)
         for (k, v) in &template.front.vars {
-            ctx.insert(k, v);
+            if !crate::template_vars::is_declaration(v) {
+                ctx.insert(k, v);
+            }
         }
         if SimpleTracer::is_enabled() {
             SimpleTracer::trace(
@@ -485,7 +501,7 @@ impl Plan {
         use std::process::Command;
 
         // Security validation: block dangerous commands
-        if self.is_dangerous_command(command) {
+        if is_dangerous_command(command) {
             return Err(anyhow::anyhow!(
                 "SECURITY: Blocked potentially dangerous shell command: '{}'. \
                 Shell hooks are disabled for security. Use trusted templates only.",
@@ -520,74 +536,6 @@ impl Plan {
         Ok(())
     }
 
-    /// Check if a shell command is potentially dangerous
-    ///
-    /// Blocks commands that could:
-    /// - Delete files (rm, del)
-    /// - Modify system files (sudo, su)
-    /// - Execute arbitrary code (eval, exec)
-    /// - Access sensitive data (cat /etc/passwd, etc.)
-    fn is_dangerous_command(&self, command: &str) -> bool {
-        let dangerous_patterns = [
-            "rm ",
-            "rm -",
-            "del ",
-            "delete",
-            "sudo ",
-            "su ",
-            "su-",
-            "eval ",
-            "exec ",
-            "source ",
-            "cat /etc/",
-            "cat /proc/",
-            "cat /sys/",
-            "curl ",
-            "wget ",
-            "nc ",
-            "netcat",
-            "dd ",
-            "format",
-            "mkfs",
-            "chmod ",
-            "chown ",
-            "passwd",
-            "kill ",
-            "killall",
-            "pkill",
-            "shutdown",
-            "reboot",
-            "halt",
-            "mount ",
-            "umount",
-            "fdisk",
-            "iptables",
-            "firewall",
-            "crontab",
-            "at ",
-            "batch",
-            "ssh ",
-            "scp ",
-            "rsync",
-            "git clone",
-            "git pull",
-            "git push",
-            "npm install",
-            "pip install",
-            "cargo install",
-            "docker ",
-            "kubectl ",
-            "helm ",
-            "systemctl",
-            "service ",
-        ];
-
-        let command_lower = command.to_lowercase();
-        dangerous_patterns
-            .iter()
-            .any(|pattern| command_lower.contains(pattern))
-    }
-
     /// Print unified diff of what would be written
     pub fn print_diff(&self) -> Result<()> {
         println!("DRY RUN - Would generate: {}", self.output_path.display());
@@ -658,6 +606,76 @@ fn print_colorized_new_file(content: &str, path: &Path) {
     }
 }
 
+/// Check if a shell command is potentially dangerous
+///
+/// Shared by template `sh_before`/`sh_after` hooks and lifecycle command hooks.
+///
+/// Blocks commands that could:
+/// - Delete files (rm, del)
+/// - Modify system files (sudo, su)
+/// - Execute arbitrary code (eval, exec)
+/// - Access sensitive data (cat /etc/passwd, etc.)
+pub(crate) fn is_dangerous_command(command: &str) -> bool {
+    let dangerous_patterns = [
+        "rm ",
+        "rm -",
+        "del ",
+        "delete",
+        "sudo ",
+        "su ",
+        "su-",
+        "eval ",
+        "exec ",
+        "source ",
+        "cat /etc/",
+        "cat /proc/",
+        "cat /sys/",
+        "curl ",
+        "wget ",
+        "nc ",
+        "netcat",
+        "dd ",
+        "format",
+        "mkfs",
+        "chmod ",
+        "chown ",
+        "passwd",
+        "kill ",
+        "killall",
+        "pkill",
+        "shutdown",
+        "reboot",
+        "halt",
+        "mount ",
+        "umount",
+        "fdisk",
+        "iptables",
+        "firewall",
+        "crontab",
+        "at ",
+        "batch",
+        "ssh ",
+        "scp ",
+        "rsync",
+        "git clone",
+        "git pull",
+        "git push",
+        "npm install",
+        "pip install",
+        "cargo install",
+        "docker ",
+        "kubectl ",
+        "helm ",
+        "systemctl",
+        "service ",
+    ];
+
+    let command_lower = command.to_lowercase();
+    dangerous_patterns
+        .iter()
+        .any(|pattern| command_lower.contains(pattern))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,828 @@
+//! Generation plans
+//!
+//! A dry run of [`Generator`](crate::generator::Generator) renders everything
+//! and writes nothing. It leaves a [`GenerationPlan`] instead: what would
+//! happen to each file, by how many bytes it grows or shrinks, the template
+//! and SPARQL bindings it comes from, and findings worth a look before
+//! writing, such as a path leaving the output directory or a hand-written
+//! `MANUAL:` region the new content would overwrite.
+//!
+//! ```text
+//! Plan for /work (2 files):
+//!   create     src/models/order.rs (+32 bytes)
+//!              template: templates/model.tmpl
+//!              bindings: entity=http://example.org/app#Order
+//!   update     src/models/user.rs (-14 bytes)
+//!              template: templates/model.tmpl
+//!              bindings: entity=http://example.org/app#User
+//!              ! protected region: manual region at lines 2-4 would be overwritten
+//! 1 to create, 1 to update, 0 unchanged, 0 skipped, 0 to delete (+18 bytes), 1 finding
+//! ```
+//!
+//! On a run without a scope, files the template generated before (see
+//! [`crate::scope::MANIFEST`]) and no longer does are planned for deletion,
+//! unless they were edited since.
+//!
+//! A plan serializes to JSON and [`GenerationPlan::apply`] executes it
+//! verbatim: the rendered content it holds is written as is, without
+//! rendering again. The plan records hashes of the template and RDF files it
+//! was rendered from and of every file it touches, and refuses to apply once
+//! any of them changed.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use crate::file_mode::{self, UnappliedMode};
+use crate::merge::RegionUtils;
+use crate::output::{manifest_key, normalize, OutputProfiles};
+use crate::pqc::calculate_sha256;
+use crate::scope::{Bindings, GenerationManifest, ManifestEntry};
+
+/// What applying a plan does to a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileAction {
+    Create,
+    Update,
+    /// Rendered to the content and mode already on disk
+    Unchanged,
+    /// Left alone, being outside the scope or edited since generated
+    Skip,
+    /// No longer generated by its template
+    Delete,
+}
+
+impl FileAction {
+    /// Whether applying the plan writes or removes the file
+    pub fn changes_file(self) -> bool {
+        matches!(self, Self::Create | Self::Update | Self::Delete)
+    }
+}
+
+impl fmt::Display for FileAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Self::Create => "create",
+            Self::Update => "update",
+            Self::Unchanged => "unchanged",
+            Self::Skip => "skip",
+            Self::Delete => "delete",
+        })
+    }
+}
+
+/// Kind of a [`PlanFinding`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FindingKind {
+    /// The path leaves the output directory or is a symlink
+    PathSecurity,
+    /// Content between `MANUAL:` markers would be overwritten
+    ProtectedRegion,
+}
+
+impl fmt::Display for FindingKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::PathSecurity => "path security",
+            Self::ProtectedRegion => "protected region",
+        })
+    }
+}
+
+/// Something about a planned file to look at before applying
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlanFinding {
+    pub kind: FindingKind,
+    pub message: String,
+}
+
+/// A file the render read, with its hash when planned
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlanInput {
+    pub path: PathBuf,
+    pub sha256: String,
+}
+
+/// A file of a [`GenerationPlan`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlannedFile {
+    /// Relative to [`GenerationPlan::output_root`] when inside it
+    pub path: PathBuf,
+    pub action: FileAction,
+    /// Template the file is rendered from
+    pub template: String,
+    pub bindings: Bindings,
+    /// Size on disk when planned, if the file exists
+    pub size_before: Option<u64>,
+    /// Size once applied, if the file exists then
+    pub size_after: Option<u64>,
+    /// Hash on disk when planned; applying refuses a file that changed
+    pub sha256_before: Option<String>,
+    /// Content written for [`FileAction::Create`] and [`FileAction::Update`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<u32>,
+    /// Output profile the file is recorded in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub findings: Vec<PlanFinding>,
+}
+
+impl PlannedFile {
+    /// Bytes gained, negative when the file shrinks
+    pub fn size_delta(&self) -> i64 {
+        self.size_after.unwrap_or(0) as i64 - self.size_before.unwrap_or(0) as i64
+    }
+}
+
+/// Files [`GenerationPlan::apply`] wrote or removed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AppliedPlan {
+    pub changed: Vec<PathBuf>,
+    /// Modes the platform has no way to set; see [`crate::file_mode`]
+    pub unapplied_modes: Vec<UnappliedMode>,
+}
+
+/// What a generation would do, made by a dry run; see the module docs
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GenerationPlan {
+    pub output_root: PathBuf,
+    pub inputs: Vec<PlanInput>,
+    pub files: Vec<PlannedFile>,
+}
+
+impl GenerationPlan {
+    pub fn new(output_root: PathBuf) -> Self {
+        Self {
+            output_root,
+            ..Self::default()
+        }
+    }
+
+    /// Files planned for `action`
+    pub fn count(&self, action: FileAction) -> usize {
+        self.files
+            .iter()
+            .filter(|file| file.action == action)
+            .count()
+    }
+
+    /// Bytes gained over every file
+    pub fn size_delta(&self) -> i64 {
+        self.files.iter().map(PlannedFile::size_delta).sum()
+    }
+
+    pub fn findings(&self) -> impl Iterator<Item = &PlanFinding> {
+        self.files.iter().flat_map(|file| &file.findings)
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)? + "\n")
+    }
+
+    pub fn from_json(json: &str) -> Result<Self> {
+        serde_json::from_str(json).context("Failed to parse generation plan")
+    }
+
+    /// Record `path` as read by the render
+    pub(crate) fn add_input(&mut self, path: &Path) -> Result<()> {
+        let sha256 =
+            file_sha256(path)?.with_context(|| format!("Failed to read {}", path.display()))?;
+        self.inputs.push(PlanInput {
+            path: path.to_path_buf(),
+            sha256,
+        });
+        Ok(())
+    }
+
+    /// Plan writing `content` to `path`
+    pub(crate) fn add_write(
+        &mut self, path: &Path, content: &str, template: &str, bindings: Bindings,
+        mode: Option<u32>, target: Option<String>,
+    ) -> Result<()> {
+        let existing = read_existing(path)?;
+        // A mode-only change is still written, to apply the mode
+        let mode_changed = match (&existing, mode) {
+            (Some(_), Some(mode)) => {
+                file_mode::current_mode(path)?.is_some_and(|current| current != mode)
+            }
+            _ => false,
+        };
+        let action = match &existing {
+            None => FileAction::Create,
+            Some(bytes) if bytes == content.as_bytes() && !mode_changed => FileAction::Unchanged,
+            Some(_) => FileAction::Update,
+        };
+        let mut findings = Vec::new();
+        if target.is_none() {
+            findings.extend(self.escape_finding(path));
+        }
+        findings.extend(symlink_finding(path));
+        if let (FileAction::Update, Some(bytes)) = (action, &existing) {
+            findings.extend(region_findings(&String::from_utf8_lossy(bytes), content));
+        }
+
+        self.files.push(PlannedFile {
+            path: self.relative(path),
+            action,
+            template: template.to_string(),
+            bindings,
+            size_before: existing.as_ref().map(|bytes| bytes.len() as u64),
+            size_after: Some(content.len() as u64),
+            sha256_before: existing.as_deref().map(calculate_sha256),
+            content: (action != FileAction::Unchanged).then(|| content.to_string()),
+            mode,
+            target,
+            findings,
+        });
+        Ok(())
+    }
+
+    /// Plan leaving `path` alone because it is outside the scope
+    pub(crate) fn add_skip(&mut self, path: &Path, template: &str, bindings: Bindings) {
+        self.files.push(PlannedFile {
+            path: self.relative(path),
+            action: FileAction::Skip,
+            template: template.to_string(),
+            bindings,
+            size_before: None,
+            size_after: None,
+            sha256_before: None,
+            content: None,
+            mode: None,
+            target: None,
+            findings: Vec::new(),
+        });
+    }
+
+    /// Plan deleting the files `template` generated before and no longer
+    /// does, keeping those edited since
+    pub(crate) fn add_stale(&mut self, template: &str) -> Result<()> {
+        let manifest = GenerationManifest::load(&self.output_root)?;
+        let planned: Vec<String> = self
+            .files
+            .iter()
+            .map(|file| manifest_key(&file.path))
+            .collect();
+        for (key, entry) in &manifest.files {
+            if entry.template != template || planned.contains(key) {
+                continue;
+            }
+            let Some(existing) = read_existing(&self.output_root.join(key))? else {
+                continue;
+            };
+            let sha256 = calculate_sha256(&existing);
+            let action = if sha256 == entry.sha256 {
+                FileAction::Delete
+            } else {
+                FileAction::Skip
+            };
+            self.files.push(PlannedFile {
+                path: PathBuf::from(key),
+                action,
+                template: template.to_string(),
+                bindings: entry.bindings.clone(),
+                size_before: Some(existing.len() as u64),
+                size_after: (action == FileAction::Skip).then_some(existing.len() as u64),
+                sha256_before: Some(sha256),
+                content: None,
+                mode: None,
+                target: None,
+                findings: Vec::new(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Fail unless the inputs and every file the plan changes are as they
+    /// were when it was made, and the paths it changes stay in the output
+    /// directory; paths are checked again rather than trusting the findings
+    /// of a plan that may have been edited
+    pub fn check(&self) -> Result<()> {
+        for input in &self.inputs {
+            if file_sha256(&input.path)?.as_deref() != Some(input.sha256.as_str()) {
+                anyhow::bail!(
+                    "{} changed since the plan was made; plan again",
+                    input.path.display()
+                );
+            }
+        }
+        for file in self.files.iter().filter(|file| file.action.changes_file()) {
+            let path = self.output_root.join(&file.path);
+            let escape = match &file.target {
+                Some(_) => None,
+                None => self.escape_finding(&path),
+            };
+            let finding = file
+                .findings
+                .iter()
+                .find(|finding| finding.kind == FindingKind::PathSecurity)
+                .cloned()
+                .or(escape)
+                .or_else(|| symlink_finding(&path));
+            if let Some(finding) = finding {
+                anyhow::bail!("Refusing to apply the plan: {}", finding.message);
+            }
+            if file.action != FileAction::Delete && file.content.is_none() {
+                anyhow::bail!("The plan has no content for {}", path.display());
+            }
+            if file_sha256(&path)? != file.sha256_before {
+                anyhow::bail!(
+                    "{} changed since the plan was made; plan again",
+                    path.display()
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Write and remove the planned files as they are in the plan, after
+    /// [`GenerationPlan::check`], and record them in
+    /// [`crate::scope::MANIFEST`] and their output profiles
+    pub fn apply(&self, profiles: &OutputProfiles) -> Result<AppliedPlan> {
+        self.check()?;
+        for file in self.files.iter().filter(|file| file.action.changes_file()) {
+            if let Some(target) = &file.target {
+                let path = self.output_root.join(&file.path);
+                let inside = path
+                    .strip_prefix(profiles.root(target)?)
+                    .ok()
+                    .and_then(normalize)
+                    .is_some();
+                if !inside {
+                    anyhow::bail!(
+                        "Refusing to apply the plan: {} is not in output profile '{}'",
+                        path.display(),
+                        target
+                    );
+                }
+            }
+        }
+        let mut manifest = GenerationManifest::load(&self.output_root)?;
+        let mut applied = AppliedPlan::default();
+        for file in &self.files {
+            let path = self.output_root.join(&file.path);
+            let key = manifest_key(&file.path);
+            let sha256 = match (file.action, &file.content) {
+                (FileAction::Create | FileAction::Update, Some(content)) => {
+                    if let Some(parent) = path.parent() {
+                        fs::create_dir_all(parent)?;
+                    }
+                    fs::write(&path, content)
+                        .with_context(|| format!("Failed to write {}", path.display()))?;
+                    if let Some(mode) = file.mode {
+                        applied
+                            .unapplied_modes
+                            .extend(file_mode::apply(&path, mode)?);
+                    }
+                    if let Some(target) = &file.target {
+                        profiles.record(target, &path)?;
+                    }
+                    applied.changed.push(path);
+                    calculate_sha256(content.as_bytes())
+                }
+                (FileAction::Delete, _) => {
+                    fs::remove_file(&path)
+                        .with_context(|| format!("Failed to remove {}", path.display()))?;
+                    manifest.files.remove(&key);
+                    applied.changed.push(path);
+                    continue;
+                }
+                (FileAction::Unchanged, _) => match &file.sha256_before {
+                    Some(sha256) => sha256.clone(),
+                    None => continue,
+                },
+                _ => continue,
+            };
+            manifest.files.insert(
+                key,
+                ManifestEntry {
+                    template: file.template.clone(),
+                    bindings: file.bindings.clone(),
+                    sha256,
+                },
+            );
+        }
+        manifest.save(&self.output_root)?;
+        Ok(applied)
+    }
+
+    fn relative(&self, path: &Path) -> PathBuf {
+        path.strip_prefix(&self.output_root)
+            .unwrap_or(path)
+            .to_path_buf()
+    }
+
+    fn escape_finding(&self, path: &Path) -> Option<PlanFinding> {
+        let inside = path
+            .strip_prefix(&self.output_root)
+            .ok()
+            .and_then(normalize)
+            .is_some();
+        (!inside).then(|| PlanFinding {
+            kind: FindingKind::PathSecurity,
+            message: format!(
+                "{} leaves the output directory {}",
+                path.display(),
+                self.output_root.display()
+            ),
+        })
+    }
+}
+
+impl fmt::Display for GenerationPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Plan for {} ({} files):",
+            self.output_root.display(),
+            self.files.len()
+        )?;
+        for file in &self.files {
+            write!(f, "  {:<10} {}", file.action, file.path.display())?;
+            if file.action.changes_file() || file.size_delta() != 0 {
+                write!(f, " ({:+} bytes)", file.size_delta())?;
+            }
+            writeln!(f)?;
+            writeln!(f, "             template: {}", file.template)?;
+            if !file.bindings.is_empty() {
+                let bindings: Vec<String> = file
+                    .bindings
+                    .iter()
+                    .map(|(name, values)| {
+                        let values: Vec<&str> = values.iter().map(String::as_str).collect();
+                        format!("{}={}", name, values.join(","))
+                    })
+                    .collect();
+                writeln!(f, "             bindings: {}", bindings.join(" "))?;
+            }
+            for finding in &file.findings {
+                writeln!(f, "             ! {}: {}", finding.kind, finding.message)?;
+            }
+        }
+        write!(
+            f,
+            "{} to create, {} to update, {} unchanged, {} skipped, {} to delete ({:+} bytes)",
+            self.count(FileAction::Create),
+            self.count(FileAction::Update),
+            self.count(FileAction::Unchanged),
+            self.count(FileAction::Skip),
+            self.count(FileAction::Delete),
+            self.size_delta()
+        )?;
+        match self.findings().count() {
+            0 => Ok(()),
+            1 => write!(f, ", 1 finding"),
+            n => write!(f, ", {} findings", n),
+        }
+    }
+}
+
+/// Content of `path`, `None` if there is no such file
+fn read_existing(path: &Path) -> Result<Option<Vec<u8>>> {
+    match fs::read(path) {
+        Ok(bytes) => Ok(Some(bytes)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    }
+}
+
+fn file_sha256(path: &Path) -> Result<Option<String>> {
+    Ok(read_existing(path)?.as_deref().map(calculate_sha256))
+}
+
+fn symlink_finding(path: &Path) -> Option<PlanFinding> {
+    let metadata = fs::symlink_metadata(path).ok()?;
+    if !metadata.file_type().is_symlink() {
+        return None;
+    }
+    let link = fs::read_link(path).ok()?;
+    Some(PlanFinding {
+        kind: FindingKind::PathSecurity,
+        message: format!(
+            "{} is a symlink; writing follows it to {}",
+            path.display(),
+            link.display()
+        ),
+    })
+}
+
+/// Manual regions of `existing` that `content` doesn't keep
+fn region_findings(existing: &str, content: &str) -> Vec<PlanFinding> {
+    let (_, manual) = RegionUtils::parse_regions(existing);
+    let lines: Vec<&str> = existing.lines().collect();
+    let content = content.lines().collect::<Vec<_>>().join("\n");
+    manual
+        .iter()
+        .filter(|region| !content.contains(&lines[region.start - 1..region.end].join("\n")))
+        .map(|region| PlanFinding {
+            kind: FindingKind::ProtectedRegion,
+            message: format!(
+                "manual region at lines {}-{} would be overwritten",
+                region.start, region.end
+            ),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::{GenContext, Generator};
+    use crate::pipeline::Pipeline;
+    use crate::scope::Scope;
+    use tempfile::TempDir;
+
+    const MODEL: &str = r#"---
+to: "src/models/{{ entity | local | snake }}.rs"
+rdf: ["domain.ttl"]
+foreach: entities
+sparql:
+  entities: "SELECT ?entity WHERE { ?entity a <http://example.org/app#Entity> }"
+---
+pub struct {{ entity | local }};
+"#;
+
+    const DOMAIN: &str = r#"@prefix app: <http://example.org/app#> .
+app:User a app:Entity .
+app:Order a app:Entity .
+"#;
+
+    const EDITED_USER: &str = "pub struct User;\n// MANUAL: helpers\nimpl User {}\n// END MANUAL\n";
+
+    /// Template, ontology and an edited `user.rs` in `dir`
+    fn fixture(dir: &Path) -> PathBuf {
+        let template = dir.join("model.tmpl");
+        fs::write(&template, MODEL).unwrap();
+        fs::write(dir.join("domain.ttl"), DOMAIN).unwrap();
+        fs::create_dir_all(dir.join("src/models")).unwrap();
+        fs::write(dir.join("src/models/user.rs"), EDITED_USER).unwrap();
+        template
+    }
+
+    fn dry_run(template: &Path, dir: &Path, scope: Scope) -> GenerationPlan {
+        let ctx = GenContext::new(template.to_path_buf(), dir.to_path_buf())
+            .with_scope(scope)
+            .dry(true);
+        let mut generator = Generator::new(Pipeline::new().unwrap(), ctx);
+        generator.generate_outputs().unwrap();
+        generator.plan
+    }
+
+    fn file<'a>(plan: &'a GenerationPlan, path: &str) -> &'a PlannedFile {
+        plan.files
+            .iter()
+            .find(|file| file.path == Path::new(path))
+            .unwrap_or_else(|| panic!("{} is not in the plan", path))
+    }
+
+    #[test]
+    fn test_dry_run_plans_without_writing() {
+        let dir = TempDir::new().unwrap();
+        let template = fixture(dir.path());
+
+        let plan = dry_run(&template, dir.path(), Scope::default());
+
+        assert!(!dir.path().join("src/models/order.rs").exists());
+        assert_eq!(
+            fs::read_to_string(dir.path().join("src/models/user.rs")).unwrap(),
+            EDITED_USER
+        );
+        let order = file(&plan, "src/models/order.rs");
+        assert_eq!(order.action, FileAction::Create);
+        assert_eq!(order.size_delta(), "pub struct Order;\n".len() as i64);
+        assert_eq!(order.template, template.display().to_string());
+        assert_eq!(
+            order.bindings,
+            Bindings::from([(
+                "entity".to_string(),
+                ["http://example.org/app#Order".to_string()].into()
+            )])
+        );
+        assert!(order.findings.is_empty());
+
+        let user = file(&plan, "src/models/user.rs");
+        assert_eq!(user.action, FileAction::Update);
+        assert_eq!(
+            user.size_delta(),
+            "pub struct User;\n".len() as i64 - EDITED_USER.len() as i64
+        );
+        assert_eq!(
+            user.findings,
+            vec![PlanFinding {
+                kind: FindingKind::ProtectedRegion,
+                message: "manual region at lines 2-4 would be overwritten".to_string(),
+            }]
+        );
+
+        let inputs: Vec<PathBuf> = plan.inputs.iter().map(|i| i.path.clone()).collect();
+        assert_eq!(
+            inputs,
+            vec![template.clone(), dir.path().join("domain.ttl")]
+        );
+        let text = plan.to_string();
+        assert!(
+            text.contains("  create     src/models/order.rs (+18 bytes)"),
+            "{}",
+            text
+        );
+        let summary = "1 to create, 1 to update, 0 unchanged, 0 skipped, 0 to delete (-28 bytes)";
+        assert!(
+            text.ends_with(&format!("{}, 1 finding", summary)),
+            "{}",
+            text
+        );
+        assert_eq!(
+            GenerationPlan::from_json(&plan.to_json().unwrap()).unwrap(),
+            plan
+        );
+    }
+
+    #[test]
+    fn test_scoped_dry_run_plans_skips() {
+        let dir = TempDir::new().unwrap();
+        let template = fixture(dir.path());
+
+        let plan = dry_run(
+            &template,
+            dir.path(),
+            Scope::parse(&["entity=Order"]).unwrap(),
+        );
+
+        assert_eq!(
+            file(&plan, "src/models/order.rs").action,
+            FileAction::Create
+        );
+        assert_eq!(file(&plan, "src/models/user.rs").action, FileAction::Skip);
+    }
+
+    #[test]
+    fn test_apply_writes_the_plan_verbatim() {
+        let dir = TempDir::new().unwrap();
+        let template = fixture(dir.path());
+        let plan = dry_run(&template, dir.path(), Scope::default());
+
+        let applied = plan.apply(&OutputProfiles::default()).unwrap();
+
+        assert_eq!(applied.changed.len(), 2);
+        let read = |path: &str| fs::read_to_string(dir.path().join(path)).unwrap();
+        assert_eq!(read("src/models/order.rs"), "pub struct Order;\n");
+        assert_eq!(read("src/models/user.rs"), "pub struct User;\n");
+        let manifest = GenerationManifest::load(dir.path()).unwrap();
+        assert_eq!(
+            manifest.files.keys().collect::<Vec<_>>(),
+            vec!["src/models/order.rs", "src/models/user.rs"]
+        );
+
+        // Nothing left to do once applied
+        let again = dry_run(&template, dir.path(), Scope::default());
+        assert_eq!(again.count(FileAction::Unchanged), 2);
+    }
+
+    #[test]
+    fn test_apply_rejects_changed_inputs() {
+        let dir = TempDir::new().unwrap();
+        let template = fixture(dir.path());
+
+        let plan_before_edit = dry_run(&template, dir.path(), Scope::default());
+        fs::write(
+            dir.path().join("domain.ttl"),
+            format!("{}app:Item a app:Entity .\n", DOMAIN),
+        )
+        .unwrap();
+        let err = plan_before_edit
+            .apply(&OutputProfiles::default())
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("domain.ttl changed since the plan was made"),
+            "{}",
+            err
+        );
+        assert!(!dir.path().join("src/models/order.rs").exists());
+
+        let plan_before_edit = dry_run(&template, dir.path(), Scope::default());
+        fs::write(dir.path().join("src/models/user.rs"), "// mine\n").unwrap();
+        let err = plan_before_edit
+            .apply(&OutputProfiles::default())
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("user.rs changed since the plan was made"),
+            "{}",
+            err
+        );
+        assert!(!dir.path().join("src/models/order.rs").exists());
+    }
+
+    #[test]
+    fn test_files_no_longer_generated_are_planned_for_deletion() {
+        let dir = TempDir::new().unwrap();
+        let template = fixture(dir.path());
+        fs::write(
+            dir.path().join("domain.ttl"),
+            format!("{}app:Item a app:Entity .\n", DOMAIN),
+        )
+        .unwrap();
+        dry_run(&template, dir.path(), Scope::default())
+            .apply(&OutputProfiles::default())
+            .unwrap();
+        fs::write(dir.path().join("domain.ttl"), DOMAIN).unwrap();
+
+        let plan = dry_run(&template, dir.path(), Scope::default());
+        let item = file(&plan, "src/models/item.rs");
+        assert_eq!(item.action, FileAction::Delete);
+        assert_eq!(item.size_delta(), -("pub struct Item;\n".len() as i64));
+
+        plan.apply(&OutputProfiles::default()).unwrap();
+        assert!(!dir.path().join("src/models/item.rs").exists());
+        let manifest = GenerationManifest::load(dir.path()).unwrap();
+        assert!(!manifest.files.contains_key("src/models/item.rs"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_mode_only_changes_are_applied() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = TempDir::new().unwrap();
+        let template = dir.path().join("secrets.tmpl");
+        fs::write(
+            &template,
+            "---\nto: \"secrets.env\"\nmode: \"0600\"\n---\nTOKEN=x\n",
+        )
+        .unwrap();
+        let output = dir.path().join("secrets.env");
+        fs::write(&output, "TOKEN=x\n").unwrap();
+        fs::set_permissions(&output, fs::Permissions::from_mode(0o644)).unwrap();
+
+        let plan = dry_run(&template, dir.path(), Scope::default());
+        assert_eq!(file(&plan, "secrets.env").action, FileAction::Update);
+        let plan = GenerationPlan::from_json(&plan.to_json().unwrap()).unwrap();
+        plan.apply(&OutputProfiles::default()).unwrap();
+
+        let mode = fs::metadata(&output).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        let again = dry_run(&template, dir.path(), Scope::default());
+        assert_eq!(file(&again, "secrets.env").action, FileAction::Unchanged);
+    }
+
+    #[test]
+    fn test_apply_rejects_paths_edited_to_leave_the_output_directory() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().join("out");
+        fs::create_dir_all(&root).unwrap();
+        let template = fixture(&root);
+        let plan = dry_run(&template, &root, Scope::default());
+
+        for tampered in [dir.path().join("escape.rs"), PathBuf::from("../escape.rs")] {
+            let json = plan
+                .to_json()
+                .unwrap()
+                .replace("src/models/order.rs", &tampered.display().to_string());
+            let err = GenerationPlan::from_json(&json)
+                .unwrap()
+                .apply(&OutputProfiles::default())
+                .unwrap_err();
+            assert!(
+                err.to_string().contains("leaves the output directory"),
+                "{}",
+                err
+            );
+            assert!(!dir.path().join("escape.rs").exists());
+        }
+
+        // Nor are files already found to leave it written
+        let mut flagged = plan.clone();
+        flagged.files[0].findings.push(PlanFinding {
+            kind: FindingKind::PathSecurity,
+            message: "out/src is a symlink".to_string(),
+        });
+        let err = flagged.apply(&OutputProfiles::default()).unwrap_err();
+        assert!(err.to_string().contains("is a symlink"), "{}", err);
+    }
+
+    #[test]
+    fn test_paths_leaving_the_output_directory_are_reported() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().join("out");
+        let template = dir.path().join("escape.tmpl");
+        fs::write(&template, "---\nto: \"../escape.txt\"\n---\nx\n").unwrap();
+
+        let ctx = GenContext::new(template, root).dry(true);
+        let mut generator = Generator::new(Pipeline::new().unwrap(), ctx);
+        generator.generate_outputs().unwrap();
+
+        let findings: Vec<_> = generator.plan.findings().collect();
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].kind, FindingKind::PathSecurity);
+        assert!(findings[0].message.contains("leaves the output directory"));
+    }
+}
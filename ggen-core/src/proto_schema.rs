@@ -0,0 +1,569 @@
+//! Protobuf messages and services for the domain graph
+//!
+//! [`messages`] turns the fields of each entity into a proto3 message and
+//! [`services`] turns `ex:APIEndpoint`s into RPCs with synthesized request and
+//! response messages. Templates call them as
+//! `proto_messages(fields=..., manifest=..., scalars=...)` and
+//! `proto_services(endpoints=...)`; see `proto.tmpl` of the api-endpoint
+//! package.
+//!
+//! Field numbers come from a [`FieldNumbers`] allocation map kept under
+//! `proto_fields` in the incremental manifest (`.ggen/manifest.json` unless
+//! configured otherwise). A field keeps its number for good: new fields get
+//! numbers above every number the message ever used, and a field that
+//! disappears from the graph is listed as `reserved` instead of being
+//! renumbered.
+//!
+//! A relative manifest path is taken from the generator's output root, and
+//! the manifest is only updated when files are written: previews and dry
+//! runs read it but leave it alone. See [`ManifestAccess`].
+
+use anyhow::{Context, Result};
+use heck::{ToPascalCase, ToSnakeCase};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::register::{lexical_form, sparql_binding};
+use crate::seed_data::local_name;
+
+/// Key of the allocation map in the manifest
+pub const MANIFEST_KEY: &str = "proto_fields";
+
+/// Default location of the incremental manifest
+pub const DEFAULT_MANIFEST: &str = ".ggen/manifest.json";
+
+/// Numbers reserved for the protobuf implementation
+const IMPLEMENTATION_RESERVED: std::ops::RangeInclusive<u32> = 19000..=19999;
+
+/// A field of an entity, as declared in the graph
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProtoFieldSpec {
+    /// Local name of the entity, like `User`
+    pub entity: String,
+    pub name: String,
+    /// XSD datatype local name; `None` for a relationship
+    pub datatype: Option<String>,
+    pub required: bool,
+    /// Local name of the entity the field points at
+    pub references: Option<String>,
+    /// `ex:cardinality "many"`: a to-many relationship
+    pub many: bool,
+}
+
+impl ProtoFieldSpec {
+    /// Read a field from a SPARQL row with the columns `entity`, `field` and
+    /// optionally `datatype`, `required`, `references` and `cardinality`
+    pub fn from_row(row: &Value) -> Option<Self> {
+        let text = |column: &str| {
+            sparql_binding(row, column)
+                .and_then(Value::as_str)
+                .map(|term| local_name(lexical_form(term)).to_string())
+        };
+        Some(Self {
+            entity: text("entity")?,
+            name: text("field")?,
+            datatype: text("datatype"),
+            required: text("required").is_some_and(|value| value == "true"),
+            references: text("references"),
+            many: text("cardinality").is_some_and(|value| value == "many"),
+        })
+    }
+
+    /// Proto type: a scalar, or the referenced message for a to-many field.
+    /// A to-one reference holds the id of the other entity.
+    fn proto_type(&self, scalars: &BTreeMap<String, String>) -> String {
+        match (&self.references, self.many) {
+            (Some(target), true) => target.to_pascal_case(),
+            (Some(_), false) => "string".to_string(),
+            (None, _) => scalar_type(self.datatype.as_deref().unwrap_or("string"), scalars),
+        }
+    }
+}
+
+/// Proto scalar for an XSD datatype; `overrides` maps datatype local names to
+/// proto types and wins over the built-in table
+pub fn scalar_type(datatype: &str, overrides: &BTreeMap<String, String>) -> String {
+    if let Some(proto) = overrides.get(datatype) {
+        return proto.clone();
+    }
+    match datatype {
+        "boolean" => "bool",
+        "int" | "short" | "byte" => "int32",
+        "integer" | "long" | "negativeInteger" | "nonPositiveInteger" => "int64",
+        "unsignedInt" | "unsignedShort" | "unsignedByte" => "uint32",
+        "unsignedLong" | "nonNegativeInteger" | "positiveInteger" => "uint64",
+        "decimal" | "double" => "double",
+        "float" => "float",
+        "base64Binary" | "hexBinary" => "bytes",
+        _ => "string",
+    }
+    .to_string()
+}
+
+/// Field numbers ever handed out, by message and field name
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct FieldNumbers(pub BTreeMap<String, BTreeMap<String, u32>>);
+
+impl FieldNumbers {
+    /// Read the allocation map from a manifest; empty when the manifest or
+    /// its `proto_fields` entry doesn't exist yet
+    pub fn load(manifest: &Path) -> Result<Self> {
+        match manifest_entry(manifest, MANIFEST_KEY)? {
+            Some(numbers) => Ok(serde_json::from_value(numbers)?),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Store the allocation map in a manifest, keeping its other entries
+    pub fn save(&self, manifest: &Path) -> Result<()> {
+        set_manifest_entry(manifest, MANIFEST_KEY, serde_json::to_value(self)?)
+    }
+
+    /// Number of `field` of `message`, allocating the next free one
+    pub fn number(&mut self, message: &str, field: &str) -> u32 {
+        let fields = self.0.entry(message.to_string()).or_default();
+        if let Some(number) = fields.get(field) {
+            return *number;
+        }
+        let mut next = fields.values().max().map_or(1, |max| max + 1);
+        if IMPLEMENTATION_RESERVED.contains(&next) {
+            next = IMPLEMENTATION_RESERVED.end() + 1;
+        }
+        fields.insert(field.to_string(), next);
+        next
+    }
+}
+
+/// The `key` entry of the incremental manifest; `None` when the manifest or
+/// the entry doesn't exist yet
+pub(crate) fn manifest_entry(manifest: &Path, key: &str) -> Result<Option<Value>> {
+    if !manifest.exists() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(manifest)
+        .with_context(|| format!("Failed to read {}", manifest.display()))?;
+    let mut document: Value = serde_json::from_str(&content)
+        .with_context(|| format!("Failed to parse {}", manifest.display()))?;
+    Ok(document.get_mut(key).map(Value::take))
+}
+
+/// Store `value` under `key` in the incremental manifest, keeping its other
+/// entries
+pub(crate) fn set_manifest_entry(manifest: &Path, key: &str, value: Value) -> Result<()> {
+    let mut document = if manifest.exists() {
+        serde_json::from_str(&fs::read_to_string(manifest)?)
+            .with_context(|| format!("Failed to parse {}", manifest.display()))?
+    } else {
+        Value::Object(Map::new())
+    };
+    let object = document
+        .as_object_mut()
+        .ok_or_else(|| anyhow::anyhow!("{} is not a JSON object", manifest.display()))?;
+    object.insert(key.to_string(), value);
+    if let Some(parent) = manifest.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(manifest, serde_json::to_string_pretty(&document)? + "\n")
+        .with_context(|| format!("Failed to write {}", manifest.display()))
+}
+
+/// How the tera functions keeping state in the incremental manifest reach it
+///
+/// The default reads manifests from the working directory and never writes
+/// them, for renders that only preview their output.
+#[derive(Debug, Clone, Default)]
+pub struct ManifestAccess {
+    /// What relative manifest paths are taken from; the working directory
+    /// when `None`
+    pub root: Option<PathBuf>,
+    /// Store what the render allocated
+    pub save: bool,
+}
+
+impl ManifestAccess {
+    /// Read and update manifests under `root`
+    pub fn saving(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: Some(root.into()),
+            save: true,
+        }
+    }
+
+    /// Path of `manifest`, as a template names it
+    pub fn path(&self, manifest: &str) -> PathBuf {
+        match &self.root {
+            Some(root) => root.join(manifest),
+            None => PathBuf::from(manifest),
+        }
+    }
+}
+
+/// A field of a generated message
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ProtoField {
+    /// `optional`, `repeated` or empty
+    pub label: String,
+    #[serde(rename = "type")]
+    pub ty: String,
+    pub name: String,
+    pub number: u32,
+}
+
+/// A generated message
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ProtoMessage {
+    /// Sorted by number
+    pub fields: Vec<ProtoField>,
+    /// Numbers and names of fields that were removed from the graph
+    pub reserved: Vec<u32>,
+    pub reserved_names: Vec<String>,
+}
+
+/// One message per entity, numbered from `numbers`, which gains the numbers
+/// of new fields. Every message starts with `string id`.
+pub fn messages(
+    fields: &[ProtoFieldSpec], numbers: &mut FieldNumbers, scalars: &BTreeMap<String, String>,
+) -> BTreeMap<String, ProtoMessage> {
+    let mut by_entity: BTreeMap<String, BTreeMap<String, &ProtoFieldSpec>> = BTreeMap::new();
+    for field in fields {
+        by_entity
+            .entry(field.entity.to_pascal_case())
+            .or_default()
+            .insert(field.name.to_snake_case(), field);
+    }
+
+    let mut messages = BTreeMap::new();
+    for (name, entity_fields) in by_entity {
+        let mut message = ProtoMessage::default();
+        message.fields.push(ProtoField {
+            label: String::new(),
+            ty: "string".to_string(),
+            name: "id".to_string(),
+            number: numbers.number(&name, "id"),
+        });
+        for (field_name, field) in entity_fields.iter().filter(|(n, _)| *n != "id") {
+            let label = if field.many {
+                "repeated"
+            } else if field.required {
+                ""
+            } else {
+                "optional"
+            };
+            message.fields.push(ProtoField {
+                label: label.to_string(),
+                ty: field.proto_type(scalars),
+                name: field_name.clone(),
+                number: numbers.number(&name, field_name),
+            });
+        }
+        message.fields.sort_by_key(|field| field.number);
+        for (retired, number) in &numbers.0[&name] {
+            if retired != "id" && !entity_fields.contains_key(retired) {
+                message.reserved.push(*number);
+                message.reserved_names.push(retired.clone());
+            }
+        }
+        message.reserved.sort_unstable();
+        messages.insert(name, message);
+    }
+    messages
+}
+
+/// An `ex:APIEndpoint`, as declared in the graph
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EndpointSpec {
+    /// Local name, like `getUser`
+    pub name: String,
+    /// `ex:api`, or empty
+    pub api: String,
+    pub path: String,
+    pub method: String,
+    /// Local name of the entity the endpoint serves
+    pub entity: Option<String>,
+}
+
+impl EndpointSpec {
+    /// Read an endpoint from a SPARQL row with the columns `endpoint`,
+    /// `path`, `method` and optionally `api` and `entity`
+    pub fn from_row(row: &Value) -> Option<Self> {
+        let text = |column: &str| {
+            sparql_binding(row, column)
+                .and_then(Value::as_str)
+                .map(|term| lexical_form(term).to_string())
+        };
+        Some(Self {
+            name: local_name(&text("endpoint")?).to_string(),
+            api: text("api").unwrap_or_default(),
+            path: text("path")?,
+            method: text("method")?.to_uppercase(),
+            entity: text("entity").map(|entity| local_name(&entity).to_pascal_case()),
+        })
+    }
+}
+
+/// An RPC with its request and response messages
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ProtoRpc {
+    pub name: String,
+    pub request: String,
+    pub response: String,
+    pub request_fields: Vec<ProtoField>,
+    pub response_fields: Vec<ProtoField>,
+}
+
+/// RPCs by service name, one service per `ex:api` (`ApiService` for
+/// endpoints without one), sorted by RPC name
+pub fn services(endpoints: &[EndpointSpec]) -> BTreeMap<String, Vec<ProtoRpc>> {
+    let mut services: BTreeMap<String, Vec<ProtoRpc>> = BTreeMap::new();
+    for endpoint in endpoints {
+        let api = if endpoint.api.is_empty() {
+            "api"
+        } else {
+            endpoint.api.as_str()
+        };
+        services
+            .entry(format!("{}Service", api.to_pascal_case()))
+            .or_default()
+            .push(rpc(endpoint));
+    }
+    for rpcs in services.values_mut() {
+        rpcs.sort_by(|a, b| a.name.cmp(&b.name));
+    }
+    services
+}
+
+fn rpc(endpoint: &EndpointSpec) -> ProtoRpc {
+    let name = endpoint.name.to_pascal_case();
+    let field = |label: &str, ty: &str, name: &str, number: u32| ProtoField {
+        label: label.to_string(),
+        ty: ty.to_string(),
+        name: name.to_string(),
+        number,
+    };
+    let item = endpoint.path.contains(":id") || endpoint.path.contains("{id}");
+    let mut request_fields = Vec::new();
+    if item {
+        request_fields.push(field("", "string", "id", 1));
+    }
+    let mut response_fields = Vec::new();
+    if let Some(entity) = &endpoint.entity {
+        let entity_field = entity.to_snake_case();
+        match endpoint.method.as_str() {
+            "POST" | "PUT" | "PATCH" => {
+                let number = request_fields.len() as u32 + 1;
+                request_fields.push(field("", entity, &entity_field, number));
+                response_fields.push(field("", entity, &entity_field, 1));
+            }
+            "GET" if !item => response_fields.push(field("repeated", entity, "items", 1)),
+            "GET" => response_fields.push(field("", entity, &entity_field, 1)),
+            _ => {}
+        }
+    }
+    ProtoRpc {
+        request: format!("{}Request", name),
+        response: format!("{}Response", name),
+        name,
+        request_fields,
+        response_fields,
+    }
+}
+
+fn scalar_overrides(value: Option<&Value>) -> tera::Result<BTreeMap<String, String>> {
+    let parsed;
+    let value = match value {
+        Some(Value::String(json)) if !json.trim().is_empty() => {
+            parsed = serde_json::from_str::<Value>(json)
+                .map_err(|e| tera::Error::msg(format!("proto_messages: scalars: {}", e)))?;
+            &parsed
+        }
+        Some(value @ Value::Object(_)) => value,
+        _ => return Ok(BTreeMap::new()),
+    };
+    serde_json::from_value(value.clone())
+        .map_err(|e| tera::Error::msg(format!("proto_messages: scalars: {}", e)))
+}
+
+/// `proto_messages(fields=..., manifest=..., scalars=...)`; see the module
+/// docs. `scalars` is a map, or its JSON, of datatype to proto type.
+#[derive(Clone, Default)]
+pub struct ProtoMessagesFn(pub ManifestAccess);
+
+impl tera::Function for ProtoMessagesFn {
+    fn call(&self, args: &HashMap<String, Value>) -> tera::Result<Value> {
+        let rows = args
+            .get("fields")
+            .and_then(Value::as_array)
+            .ok_or_else(|| tera::Error::msg("proto_messages: fields parameter required"))?;
+        let manifest = args
+            .get("manifest")
+            .and_then(Value::as_str)
+            .unwrap_or(DEFAULT_MANIFEST);
+        let scalars = scalar_overrides(args.get("scalars"))?;
+
+        let fields: Vec<ProtoFieldSpec> =
+            rows.iter().filter_map(ProtoFieldSpec::from_row).collect();
+        let manifest = self.0.path(manifest);
+        let mut numbers = FieldNumbers::load(&manifest)
+            .map_err(|e| tera::Error::msg(format!("proto_messages: {:#}", e)))?;
+        let messages = messages(&fields, &mut numbers, &scalars);
+        if self.0.save {
+            numbers
+                .save(&manifest)
+                .map_err(|e| tera::Error::msg(format!("proto_messages: {:#}", e)))?;
+        }
+        Ok(json!(messages))
+    }
+}
+
+/// `proto_services(endpoints=...)`; see the module docs
+#[derive(Clone)]
+pub struct ProtoServicesFn;
+
+impl tera::Function for ProtoServicesFn {
+    fn call(&self, args: &HashMap<String, Value>) -> tera::Result<Value> {
+        let rows = args
+            .get("endpoints")
+            .and_then(Value::as_array)
+            .ok_or_else(|| tera::Error::msg("proto_services: endpoints parameter required"))?;
+        let endpoints: Vec<EndpointSpec> = rows.iter().filter_map(EndpointSpec::from_row).collect();
+        Ok(json!(services(&endpoints)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(entity: &str, name: &str, datatype: &str, required: bool) -> ProtoFieldSpec {
+        ProtoFieldSpec {
+            entity: entity.to_string(),
+            name: name.to_string(),
+            datatype: Some(datatype.to_string()),
+            required,
+            ..ProtoFieldSpec::default()
+        }
+    }
+
+    #[test]
+    fn test_messages_map_types_and_labels() {
+        let fields = vec![
+            field("User", "name", "string", true),
+            field("User", "age", "int", false),
+            ProtoFieldSpec {
+                references: Some("Order".to_string()),
+                many: true,
+                datatype: None,
+                ..field("User", "orders", "", false)
+            },
+            ProtoFieldSpec {
+                references: Some("User".to_string()),
+                ..field("Order", "userId", "string", true)
+            },
+            field("Order", "total", "decimal", true),
+        ];
+        let scalars = BTreeMap::from([("decimal".to_string(), "string".to_string())]);
+        let messages = messages(&fields, &mut FieldNumbers::default(), &scalars);
+
+        let user: Vec<_> = messages["User"]
+            .fields
+            .iter()
+            .map(|f| (f.label.as_str(), f.ty.as_str(), f.name.as_str(), f.number))
+            .collect();
+        assert_eq!(
+            user,
+            vec![
+                ("", "string", "id", 1),
+                ("optional", "int32", "age", 2),
+                ("", "string", "name", 3),
+                ("repeated", "Order", "orders", 4),
+            ]
+        );
+        let order = &messages["Order"].fields;
+        assert_eq!(
+            (order[1].ty.as_str(), order[1].name.as_str()),
+            ("string", "total")
+        );
+        assert_eq!(order[2].name, "user_id");
+    }
+
+    #[test]
+    fn test_numbers_are_stable_and_removed_fields_reserved() {
+        let mut numbers = FieldNumbers::default();
+        let before = vec![
+            field("User", "name", "string", true),
+            field("User", "email", "string", true),
+        ];
+        messages(&before, &mut numbers, &BTreeMap::new());
+
+        // `age` sorts first but is numbered last; `email` is gone
+        let after = vec![
+            field("User", "name", "string", true),
+            field("User", "age", "int", true),
+        ];
+        let user = &messages(&after, &mut numbers, &BTreeMap::new())["User"];
+        let numbered: Vec<_> = user
+            .fields
+            .iter()
+            .map(|f| (f.name.as_str(), f.number))
+            .collect();
+        assert_eq!(numbered, vec![("id", 1), ("name", 3), ("age", 4)]);
+        assert_eq!(user.reserved, vec![2]);
+        assert_eq!(user.reserved_names, vec!["email"]);
+    }
+
+    #[test]
+    fn test_manifest_roundtrip_keeps_other_entries() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let manifest = dir.path().join(".ggen/manifest.json");
+        fs::create_dir_all(manifest.parent().unwrap())?;
+        fs::write(&manifest, r#"{ "templates": { "a.tmpl": "sha256:1" } }"#)?;
+
+        let mut numbers = FieldNumbers::load(&manifest)?;
+        assert_eq!(numbers.number("User", "id"), 1);
+        assert_eq!(numbers.number("User", "name"), 2);
+        numbers.save(&manifest)?;
+
+        assert_eq!(FieldNumbers::load(&manifest)?, numbers);
+        let document: Value = serde_json::from_str(&fs::read_to_string(&manifest)?)?;
+        assert_eq!(document["templates"]["a.tmpl"], "sha256:1");
+        assert_eq!(document[MANIFEST_KEY]["User"]["name"], 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_services_synthesize_requests_and_responses() {
+        let endpoint = |name: &str, api: &str, path: &str, method: &str| EndpointSpec {
+            name: name.to_string(),
+            api: api.to_string(),
+            path: path.to_string(),
+            method: method.to_string(),
+            entity: (!api.is_empty()).then(|| "User".to_string()),
+        };
+        let services = services(&[
+            endpoint("updateUser", "users", "/users/:id", "PUT"),
+            endpoint("listUsers", "users", "/users", "GET"),
+            endpoint("health", "", "/health", "GET"),
+        ]);
+
+        assert_eq!(
+            services.keys().collect::<Vec<_>>(),
+            vec!["ApiService", "UsersService"]
+        );
+        let users = &services["UsersService"];
+        assert_eq!(users[0].name, "ListUsers");
+        assert_eq!(users[0].response_fields[0].label, "repeated");
+        let update = &users[1];
+        assert_eq!(update.request, "UpdateUserRequest");
+        let request: Vec<_> = update
+            .request_fields
+            .iter()
+            .map(|f| (f.ty.as_str(), f.name.as_str(), f.number))
+            .collect();
+        assert_eq!(request, vec![("string", "id", 1), ("User", "user", 2)]);
+        assert!(services["ApiService"][0].response_fields.is_empty());
+    }
+}
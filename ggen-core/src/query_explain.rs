@@ -0,0 +1,508 @@
+//! Explaining the frontmatter SPARQL queries of a template
+//!
+//! With explanations enabled, [`Template::process_graph`] records every named
+//! query: the template variables it uses, how long it took and how many rows
+//! it returned. When a query returns nothing, each triple pattern of its
+//! `WHERE` group is run on its own and joined with the patterns before it, so
+//! the report can point at the pattern that eliminated all solutions, like a
+//! misspelled type IRI.
+//!
+//! Only the top-level triple patterns are analysed; `FILTER`, `OPTIONAL`,
+//! `UNION` and other groups are left out of the relaxed queries.
+//!
+//! ```rust,no_run
+//! use ggen_core::query_explain::explain_templates;
+//! use std::collections::BTreeMap;
+//! use std::path::PathBuf;
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let templates = vec![PathBuf::from("templates/model.tmpl")];
+//! let report = explain_templates(&templates, &BTreeMap::new())?;
+//! eprint!("{}", report);
+//! report.write_json("query-explain.json".as_ref())?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`Template::process_graph`]: crate::template::Template::process_graph
+
+use anyhow::{Context as _, Result};
+use oxigraph::sparql::QueryResults;
+use regex::Regex;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tera::Context;
+
+use crate::generator::{GenContext, Generator};
+use crate::graph::Graph;
+use crate::pipeline::Pipeline;
+
+/// Explanations of the queries of a set of templates
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ExplainReport {
+    pub queries: Vec<QueryExplanation>,
+}
+
+/// One named query of a template
+#[derive(Debug, Clone, Serialize)]
+pub struct QueryExplanation {
+    pub template: PathBuf,
+    pub name: String,
+    /// The query as executed, with the prolog and variables rendered
+    pub query: String,
+    /// Template variables the query refers to, with their values
+    pub params: BTreeMap<String, serde_json::Value>,
+    pub duration_ms: f64,
+    /// Solutions, or 1/0 for an `ASK` query
+    pub rows: usize,
+    /// Present when the query returned no rows
+    pub analysis: Option<ZeroRowAnalysis>,
+}
+
+/// Why a query returned no rows
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ZeroRowAnalysis {
+    pub patterns: Vec<PatternCheck>,
+    /// Index in `patterns` of the first pattern that matches nothing on its
+    /// own or, failing that, of the first that leaves no solutions once
+    /// joined with the patterns before it. `None` when every pattern
+    /// matches, so a filter or nested group removed the rows.
+    pub eliminating: Option<usize>,
+}
+
+/// Rows of one triple pattern of a zero-row query
+#[derive(Debug, Clone, Serialize)]
+pub struct PatternCheck {
+    pub pattern: String,
+    /// Rows of the pattern on its own
+    pub alone: usize,
+    /// Rows of the pattern joined with all the patterns before it
+    pub joined: usize,
+}
+
+impl ZeroRowAnalysis {
+    /// The pattern that eliminated all solutions
+    pub fn eliminating_pattern(&self) -> Option<&PatternCheck> {
+        self.eliminating.map(|index| &self.patterns[index])
+    }
+}
+
+impl ExplainReport {
+    /// Write the report as pretty JSON, creating parent directories
+    pub fn write_json(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+impl fmt::Display for ExplainReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.queries.is_empty() {
+            return writeln!(f, "No frontmatter queries");
+        }
+        for query in &self.queries {
+            writeln!(
+                f,
+                "{} :: {}: {} rows in {:.2} ms",
+                query.template.display(),
+                query.name,
+                query.rows,
+                query.duration_ms
+            )?;
+            for (name, value) in &query.params {
+                writeln!(f, "  param {} = {}", name, value)?;
+            }
+            let Some(analysis) = &query.analysis else {
+                continue;
+            };
+            for (index, check) in analysis.patterns.iter().enumerate() {
+                let marker = if analysis.eliminating == Some(index) {
+                    "  <- eliminates all solutions"
+                } else {
+                    ""
+                };
+                writeln!(
+                    f,
+                    "  pattern `{}`: {} alone, {} joined{}",
+                    check.pattern, check.alone, check.joined, marker
+                )?;
+            }
+            if analysis.eliminating.is_none() {
+                writeln!(
+                    f,
+                    "  every pattern matches; a filter or nested group removed the rows"
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Render `templates` without writing anything and explain their queries
+pub fn explain_templates(
+    templates: &[PathBuf], vars: &BTreeMap<String, String>,
+) -> Result<ExplainReport> {
+    let mut report = ExplainReport::default();
+    for template in templates {
+        let ctx = GenContext::new(template.clone(), PathBuf::from("."))
+            .with_vars(vars.clone())
+            .explain_queries(true)
+            .dry(true);
+        let mut generator = Generator::new(Pipeline::new()?, ctx);
+        generator
+            .generate()
+            .with_context(|| format!("Failed to render {}", template.display()))?;
+        report.queries.append(&mut generator.explanations);
+    }
+    Ok(report)
+}
+
+/// Explain `query`, which `template` ran as `name`
+pub(crate) fn explain(
+    graph: &Graph, template: &Path, name: &str, query: &str,
+    params: BTreeMap<String, serde_json::Value>, rows: usize, duration: Duration,
+) -> Result<QueryExplanation> {
+    let analysis = if rows == 0 {
+        Some(analyze(graph, query)?)
+    } else {
+        None
+    };
+    Ok(QueryExplanation {
+        template: template.to_path_buf(),
+        name: name.to_string(),
+        query: query.to_string(),
+        params,
+        duration_ms: duration.as_secs_f64() * 1000.0,
+        rows,
+        analysis,
+    })
+}
+
+/// Values of the template variables `{{ name }}` used in a query as written
+pub(crate) fn query_params(source: &str, vars: &Context) -> BTreeMap<String, serde_json::Value> {
+    let Ok(var) = Regex::new(r"\{\{-?\s*([A-Za-z_][A-Za-z0-9_]*)") else {
+        return BTreeMap::new();
+    };
+    var.captures_iter(source)
+        .filter_map(|caps| {
+            let name = caps[1].to_string();
+            vars.get(&name).cloned().map(|value| (name, value))
+        })
+        .collect()
+}
+
+/// Re-run each triple pattern of a zero-row query alone and joined
+fn analyze(graph: &Graph, query: &str) -> Result<ZeroRowAnalysis> {
+    let (prolog, rest) = split_prolog(query);
+    let patterns = triple_patterns(rest);
+    let mut checks = Vec::with_capacity(patterns.len());
+    for (index, pattern) in patterns.iter().enumerate() {
+        let relaxed = |group: &str| format!("{}SELECT * WHERE {{ {} }}", prolog, group);
+        checks.push(PatternCheck {
+            pattern: pattern.clone(),
+            alone: count_rows(graph, &relaxed(pattern))?,
+            joined: count_rows(graph, &relaxed(&patterns[..=index].join(" . ")))?,
+        });
+    }
+    let eliminating = checks
+        .iter()
+        .position(|check| check.alone == 0)
+        .or_else(|| checks.iter().position(|check| check.joined == 0));
+    Ok(ZeroRowAnalysis {
+        patterns: checks,
+        eliminating,
+    })
+}
+
+fn count_rows(graph: &Graph, query: &str) -> Result<usize> {
+    Ok(match graph.query(query)? {
+        QueryResults::Boolean(b) => usize::from(b),
+        QueryResults::Solutions(solutions) => solutions.count(),
+        QueryResults::Graph(triples) => triples.count(),
+    })
+}
+
+/// Split the `PREFIX`/`BASE` declarations off a query
+fn split_prolog(query: &str) -> (&str, &str) {
+    let Ok(prolog) =
+        Regex::new(r"^(?is)(\s*(PREFIX\s+[^\s:]*:\s*<[^>]*>|BASE\s+<[^>]*>|#[^\n]*))*\s*")
+    else {
+        return ("", query);
+    };
+    let end = prolog.find(query).map_or(0, |m| m.end());
+    query.split_at(end)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Term(String),
+    /// A balanced `{}`, `()` or `[]` group, including its brackets
+    Group(char, String),
+    Dot,
+    Semicolon,
+    Comma,
+}
+
+/// Keywords that start something other than a triple pattern
+const GROUP_KEYWORDS: &[&str] = &[
+    "FILTER", "OPTIONAL", "BIND", "MINUS", "VALUES", "SERVICE", "GRAPH", "UNION",
+];
+
+/// Top-level triple patterns of the first `{ }` group of a query, with `;`
+/// and `,` expanded so each pattern has a subject, predicate and object
+pub fn triple_patterns(query: &str) -> Vec<String> {
+    let Some(open) = query.find('{') else {
+        return Vec::new();
+    };
+    let body = match read_group(&query[open..]) {
+        Some(group) => &group[1..group.len() - 1],
+        None => &query[open + 1..],
+    };
+    let tokens = tokenize(body);
+
+    let mut patterns = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        match &tokens[i] {
+            Token::Dot => i += 1,
+            Token::Term(word) if GROUP_KEYWORDS.contains(&word.to_uppercase().as_str()) => {
+                // Skip up to and including the keyword's group
+                while i < tokens.len() && !matches!(tokens[i], Token::Group(_, _)) {
+                    i += 1;
+                }
+                i += 1;
+            }
+            Token::Group('{', _) => i += 1,
+            _ => {
+                let end = tokens[i..]
+                    .iter()
+                    .position(|t| *t == Token::Dot)
+                    .map_or(tokens.len(), |offset| i + offset);
+                expand_statement(&tokens[i..end], &mut patterns);
+                i = end;
+            }
+        }
+    }
+    patterns
+}
+
+/// `s p o1, o2; p2 o3` into `s p o1`, `s p o2` and `s p2 o3`
+fn expand_statement(tokens: &[Token], out: &mut Vec<String>) {
+    let text = |token: &Token| match token {
+        Token::Term(term) | Token::Group(_, term) => Some(term.clone()),
+        _ => None,
+    };
+    let Some(subject) = tokens.first().and_then(text) else {
+        return;
+    };
+    for predicate_objects in tokens[1..].split(|t| *t == Token::Semicolon) {
+        let Some(predicate) = predicate_objects.first().and_then(text) else {
+            continue;
+        };
+        for object in predicate_objects[1..].split(|t| *t == Token::Comma) {
+            let object: Vec<String> = object.iter().filter_map(text).collect();
+            if !object.is_empty() {
+                out.push(format!("{} {} {}", subject, predicate, object.join(" ")));
+            }
+        }
+    }
+}
+
+fn tokenize(body: &str) -> Vec<Token> {
+    let chars: Vec<char> = body.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            _ if c.is_whitespace() => i += 1,
+            '#' => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+            }
+            '.' if chars.get(i + 1).is_none_or(|next| !next.is_ascii_digit()) => {
+                tokens.push(Token::Dot);
+                i += 1;
+            }
+            ';' => {
+                tokens.push(Token::Semicolon);
+                i += 1;
+            }
+            ',' => {
+                tokens.push(Token::Comma);
+                i += 1;
+            }
+            '{' | '(' | '[' => {
+                let rest: String = chars[i..].iter().collect();
+                let group = read_group(&rest).unwrap_or(rest);
+                i += group.chars().count();
+                tokens.push(Token::Group(c, group));
+            }
+            _ => {
+                let start = i;
+                while i < chars.len() {
+                    match chars[i] {
+                        '<' => i = skip_past(&chars, i, '>'),
+                        quote @ ('"' | '\'') => i = skip_past(&chars, i, quote),
+                        ch if ch.is_whitespace() || ";,{}()[]#".contains(ch) => break,
+                        '.' if chars
+                            .get(i + 1)
+                            .is_none_or(|next| next.is_whitespace() || *next == '}') =>
+                        {
+                            break
+                        }
+                        _ => i += 1,
+                    }
+                }
+                tokens.push(Token::Term(chars[start..i].iter().collect()));
+            }
+        }
+    }
+    tokens
+}
+
+/// Index just past the `close` matching the opener at `start`, honouring `\`
+fn skip_past(chars: &[char], start: usize, close: char) -> usize {
+    let mut i = start + 1;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 2,
+            ch if ch == close => return i + 1,
+            _ => i += 1,
+        }
+    }
+    chars.len()
+}
+
+/// The balanced group `text` starts with, skipping over IRIs and strings
+fn read_group(text: &str) -> Option<String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut depth = 0usize;
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '{' | '(' | '[' => {
+                depth += 1;
+                i += 1;
+            }
+            '}' | ')' | ']' => {
+                depth = depth.saturating_sub(1);
+                i += 1;
+                if depth == 0 {
+                    return Some(chars[..i].iter().collect());
+                }
+            }
+            quote @ ('"' | '\'') => i = skip_past(&chars, i, quote),
+            '<' if chars.get(i + 1).is_some_and(|next| !next.is_whitespace()) => {
+                i = skip_past(&chars, i, '>')
+            }
+            _ => i += 1,
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::template::Template;
+    use tera::Tera;
+
+    #[test]
+    fn splits_triple_patterns() {
+        let patterns = triple_patterns(
+            r#"SELECT ?name WHERE {
+                ?p a ex:Person ; ex:name ?name , ?alias .
+                # a comment .
+                FILTER(?age > 1.5)
+                OPTIONAL { ?p ex:email ?email . }
+                ?p ex:note "a . b" .
+                ?p ex:age 30.5
+            }"#,
+        );
+        assert_eq!(
+            patterns,
+            vec![
+                "?p a ex:Person",
+                "?p ex:name ?name",
+                "?p ex:name ?alias",
+                "?p ex:note \"a . b\"",
+                "?p ex:age 30.5",
+            ]
+        );
+    }
+
+    #[test]
+    fn report_pinpoints_a_misspelled_type() -> Result<()> {
+        let input = r#"---
+prefixes: { ex: "http://example.org/" }
+rdf_inline:
+  - "@prefix ex: <http://example.org/> . ex:alice a ex:Person ; ex:name 'Alice' ; ex:team 'core' ."
+sparql:
+  people: "SELECT ?name WHERE { ?p a ex:Persn ; ex:name ?name ; ex:team '{{ team }}' }"
+  teams: "SELECT ?team WHERE { ?p ex:team ?team }"
+---
+{{ sparql_results.people | length }}"#;
+        let mut tmpl = Template::parse(input)?;
+        tmpl.query_explanations = Some(Vec::new());
+        let mut graph = Graph::new()?;
+        let mut tera = Tera::default();
+        crate::register::register_all(&mut tera);
+        let mut vars = Context::new();
+        vars.insert("team", "core");
+        tmpl.process_graph(&mut graph, &mut tera, &vars, Path::new("people.tmpl"))?;
+
+        let queries = tmpl.query_explanations.unwrap_or_default();
+        assert_eq!(queries.len(), 2);
+        let people = &queries[0];
+        assert_eq!(people.name, "people");
+        assert_eq!(people.rows, 0);
+        assert_eq!(people.params["team"], "core");
+        let analysis = people.analysis.as_ref().unwrap();
+        assert_eq!(analysis.patterns.len(), 3);
+        assert_eq!(
+            analysis.eliminating_pattern().unwrap().pattern,
+            "?p a ex:Persn"
+        );
+        assert_eq!(analysis.patterns[1].alone, 1);
+
+        let teams = &queries[1];
+        assert_eq!(teams.rows, 1);
+        assert!(teams.analysis.is_none());
+
+        let report = ExplainReport { queries };
+        let text = report.to_string();
+        assert!(text.contains("people.tmpl :: people: 0 rows"));
+        assert!(text.contains("`?p a ex:Persn`: 0 alone, 0 joined  <- eliminates"));
+
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("reports/explain.json");
+        report.write_json(&path)?;
+        let json: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&path)?)?;
+        assert_eq!(json["queries"][0]["analysis"]["eliminating"], 0);
+        Ok(())
+    }
+
+    #[test]
+    fn join_that_removes_all_rows_is_reported() -> Result<()> {
+        let graph = Graph::new()?;
+        graph.insert_turtle(
+            "@prefix ex: <http://example.org/> . ex:a a ex:Person . ex:b ex:name 'B' .",
+        )?;
+        let analysis = analyze(
+            &graph,
+            "PREFIX ex: <http://example.org/>\nSELECT * WHERE { ?p a ex:Person . ?p ex:name ?n }",
+        )?;
+        assert_eq!(analysis.patterns[1].alone, 1);
+        assert_eq!(analysis.patterns[1].joined, 0);
+        assert_eq!(analysis.eliminating, Some(1));
+        Ok(())
+    }
+}
@@ -0,0 +1,483 @@
+//! Checking committed generated output against a fresh render
+//!
+//! [`check_generated`] renders templates into a temporary directory and
+//! compares the result with the committed output directory, which it never
+//! writes to. CI runs it to catch an edited ontology or template whose output
+//! was not regenerated.
+//!
+//! Committed files are only reported as removed when they sit in a directory
+//! the templates write a file to, so the output directory may also hold the
+//! templates, the ontology and other hand-written files, and templates that
+//! need different variables can be checked one at a time.
+//!
+//! A file whose content is up to date but whose executable bits differ from
+//! the render, e.g. after only `executable:` was added to its template, is
+//! changed too. Other permission bits depend on the umask and aren't
+//! compared.
+//!
+//! Files matching a pattern in the output directory's `.ggenignore` are left
+//! out of the comparison on both sides. Each line is a glob relative to the
+//! output directory; blank lines and lines starting with `#` are skipped. A
+//! pattern without a `/` matches a file or directory name at any depth, and a
+//! pattern matching a directory excludes everything below it.
+//!
+//! ```rust,no_run
+//! use ggen_core::regen_check::{check_generated, CheckOptions};
+//! use std::path::{Path, PathBuf};
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let templates = vec![PathBuf::from("templates/model.tmpl")];
+//! let report = check_generated(&templates, Path::new("generated"), &CheckOptions::default())?;
+//! if !report.is_clean() {
+//!     eprintln!("{}", report);
+//!     std::process::exit(1);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use anyhow::{Context, Result};
+use glob::{MatchOptions, Pattern};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+use crate::file_mode::current_mode;
+use crate::generator::{GenContext, Generator};
+use crate::pipeline::Pipeline;
+
+/// Name of the ignore file, looked up in the output directory
+pub const IGNORE_FILE: &str = ".ggenignore";
+
+/// Settings of a check
+#[derive(Debug, Clone)]
+pub struct CheckOptions {
+    /// Template variables, as for a normal generation
+    pub vars: BTreeMap<String, String>,
+    /// Diff lines shown per changed file; the rest are counted
+    pub max_diff_lines: usize,
+}
+
+impl Default for CheckOptions {
+    fn default() -> Self {
+        Self {
+            vars: BTreeMap::new(),
+            max_diff_lines: 20,
+        }
+    }
+}
+
+/// How a file of the committed output differs from a fresh render
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileChange {
+    /// Generated, but not committed
+    Added,
+    /// Committed, but no longer generated
+    Removed,
+    /// Committed with different content; `diff` has `-` lines from the
+    /// committed file and `+` lines from the render
+    Changed { diff: String },
+    /// Committed with the same content but different executable bits
+    ModeChanged { committed: u32, rendered: u32 },
+}
+
+/// A file that differs, relative to the output directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileDiff {
+    pub path: PathBuf,
+    pub change: FileChange,
+}
+
+/// Result of [`check_generated`], sorted by path
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CheckReport {
+    pub files: Vec<FileDiff>,
+}
+
+impl CheckReport {
+    /// Whether the committed output is up to date
+    pub fn is_clean(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    pub fn added(&self) -> impl Iterator<Item = &Path> {
+        self.paths(|change| matches!(change, FileChange::Added))
+    }
+
+    pub fn removed(&self) -> impl Iterator<Item = &Path> {
+        self.paths(|change| matches!(change, FileChange::Removed))
+    }
+
+    pub fn changed(&self) -> impl Iterator<Item = &Path> {
+        self.paths(|change| {
+            matches!(
+                change,
+                FileChange::Changed { .. } | FileChange::ModeChanged { .. }
+            )
+        })
+    }
+
+    fn paths(&self, filter: fn(&FileChange) -> bool) -> impl Iterator<Item = &Path> {
+        self.files
+            .iter()
+            .filter(move |file| filter(&file.change))
+            .map(|file| file.path.as_path())
+    }
+}
+
+impl fmt::Display for CheckReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_clean() {
+            return writeln!(f, "Generated output is up to date");
+        }
+        writeln!(
+            f,
+            "Generated output is stale: {} added, {} removed, {} changed",
+            self.added().count(),
+            self.removed().count(),
+            self.changed().count()
+        )?;
+        for file in &self.files {
+            match &file.change {
+                FileChange::Added => writeln!(f, "  added:   {}", file.path.display())?,
+                FileChange::Removed => writeln!(f, "  removed: {}", file.path.display())?,
+                FileChange::Changed { diff } => {
+                    writeln!(f, "  changed: {}", file.path.display())?;
+                    for line in diff.lines() {
+                        writeln!(f, "    {}", line)?;
+                    }
+                }
+                FileChange::ModeChanged {
+                    committed,
+                    rendered,
+                } => writeln!(
+                    f,
+                    "  changed: {} (mode {:04o} -> {:04o})",
+                    file.path.display(),
+                    committed,
+                    rendered
+                )?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Render `templates` into a temporary directory and compare the result with
+/// `output_root`
+pub fn check_generated(
+    templates: &[PathBuf], output_root: &Path, options: &CheckOptions,
+) -> Result<CheckReport> {
+    let rendered = tempfile::tempdir().context("Failed to create a directory to render into")?;
+    for template in templates {
+        let ctx = GenContext::new(template.clone(), rendered.path().to_path_buf())
+            .with_vars(options.vars.clone());
+        Generator::new(Pipeline::new()?, ctx)
+            .generate_outputs()
+            .with_context(|| format!("Failed to render {}", template.display()))?;
+    }
+    compare_dirs(rendered.path(), output_root, options.max_diff_lines)
+}
+
+/// Compare a fresh render in `rendered` with the committed `output_root`
+fn compare_dirs(rendered: &Path, output_root: &Path, max_diff_lines: usize) -> Result<CheckReport> {
+    let ignore = IgnoreList::load(output_root)?;
+    let rendered_files = relative_files(rendered, &ignore)?;
+    let scope: BTreeSet<&Path> = rendered_files
+        .iter()
+        .filter_map(|path| path.parent())
+        .collect();
+    let committed_files: BTreeSet<PathBuf> = relative_files(output_root, &ignore)?
+        .into_iter()
+        .filter(|path| path.parent().is_some_and(|dir| scope.contains(dir)))
+        .collect();
+
+    let mut files = Vec::new();
+    for path in rendered_files.union(&committed_files) {
+        let change = match (
+            rendered_files.contains(path),
+            committed_files.contains(path),
+        ) {
+            (true, false) => FileChange::Added,
+            (false, true) => FileChange::Removed,
+            _ => {
+                let new = fs::read(rendered.join(path))?;
+                let old = fs::read(output_root.join(path))?;
+                if new != old {
+                    FileChange::Changed {
+                        diff: compact_diff(&old, &new, max_diff_lines),
+                    }
+                } else {
+                    match (
+                        current_mode(&output_root.join(path))?,
+                        current_mode(&rendered.join(path))?,
+                    ) {
+                        (Some(committed), Some(rendered))
+                            if (committed ^ rendered) & 0o111 != 0 =>
+                        {
+                            FileChange::ModeChanged {
+                                committed,
+                                rendered,
+                            }
+                        }
+                        _ => continue,
+                    }
+                }
+            }
+        };
+        files.push(FileDiff {
+            path: path.clone(),
+            change,
+        });
+    }
+    Ok(CheckReport { files })
+}
+
+/// Files below `root`, relative to it, minus the ignored ones
+fn relative_files(root: &Path, ignore: &IgnoreList) -> Result<BTreeSet<PathBuf>> {
+    let mut files = BTreeSet::new();
+    if !root.exists() {
+        return Ok(files);
+    }
+    for entry in WalkDir::new(root) {
+        let entry = entry.with_context(|| format!("Failed to read {}", root.display()))?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let path = entry.path().strip_prefix(root)?.to_path_buf();
+        if !ignore.is_ignored(&path) {
+            files.insert(path);
+        }
+    }
+    Ok(files)
+}
+
+/// Changed lines only, at most `max_lines` of them
+fn compact_diff(old: &[u8], new: &[u8], max_lines: usize) -> String {
+    let (Ok(old), Ok(new)) = (std::str::from_utf8(old), std::str::from_utf8(new)) else {
+        return "binary files differ".to_string();
+    };
+    let changes: Vec<String> = diff::lines(old, new)
+        .into_iter()
+        .filter_map(|line| match line {
+            diff::Result::Left(line) => Some(format!("-{}", line)),
+            diff::Result::Right(line) => Some(format!("+{}", line)),
+            diff::Result::Both(..) => None,
+        })
+        .collect();
+    let mut shown = changes
+        .iter()
+        .take(max_lines)
+        .cloned()
+        .collect::<Vec<_>>()
+        .join("\n");
+    if changes.len() > max_lines {
+        shown.push_str(&format!(
+            "\n... {} more changed lines",
+            changes.len() - max_lines
+        ));
+    }
+    shown
+}
+
+/// Patterns of a `.ggenignore`
+struct IgnoreList {
+    patterns: Vec<Pattern>,
+}
+
+impl IgnoreList {
+    fn load(output_root: &Path) -> Result<Self> {
+        let path = output_root.join(IGNORE_FILE);
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(err) => {
+                return Err(err).with_context(|| format!("Failed to read {}", path.display()))
+            }
+        };
+        let patterns = content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                Pattern::new(line.trim_end_matches('/'))
+                    .with_context(|| format!("Invalid pattern '{}' in {}", line, path.display()))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { patterns })
+    }
+
+    fn is_ignored(&self, path: &Path) -> bool {
+        if path == Path::new(IGNORE_FILE) {
+            return true;
+        }
+        let options = MatchOptions {
+            require_literal_separator: true,
+            ..MatchOptions::new()
+        };
+        path.ancestors()
+            .filter(|ancestor| !ancestor.as_os_str().is_empty())
+            .any(|ancestor| {
+                self.patterns.iter().any(|pattern| {
+                    pattern.matches_path_with(ancestor, options)
+                        || (!pattern.as_str().contains('/')
+                            && ancestor
+                                .file_name()
+                                .and_then(|name| name.to_str())
+                                .is_some_and(|name| pattern.matches_with(name, options)))
+                })
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const TEMPLATE: &str = r#"---
+to: "src/{{ name }}.txt"
+prefixes:
+  ex: "http://example.org/"
+rdf:
+  - "domain.ttl"
+sparql:
+  classes: "SELECT ?class WHERE { ?class a ex:Entity } ORDER BY ?class"
+---
+{% for row in sparql_results.classes %}{{ row["?class"] }}
+{% endfor %}"#;
+
+    /// A project with a template over `domain.ttl` and its committed output
+    fn project(entities: &[&str]) -> (TempDir, PathBuf, PathBuf) {
+        let dir = TempDir::new().unwrap();
+        let template = dir.path().join("entities.tmpl");
+        fs::write(&template, TEMPLATE).unwrap();
+        write_domain(dir.path(), entities);
+        let output = dir.path().join("generated");
+        fs::create_dir_all(&output).unwrap();
+        (dir, template, output)
+    }
+
+    fn write_domain(root: &Path, entities: &[&str]) {
+        let triples: String = entities
+            .iter()
+            .map(|entity| format!("ex:{} a ex:Entity .\n", entity))
+            .collect();
+        let ttl = format!("@prefix ex: <http://example.org/> .\n{}", triples);
+        fs::write(root.join("domain.ttl"), ttl).unwrap();
+    }
+
+    fn options() -> CheckOptions {
+        CheckOptions {
+            vars: BTreeMap::from([("name".to_string(), "entities".to_string())]),
+            ..CheckOptions::default()
+        }
+    }
+
+    fn regenerate(template: &Path, output: &Path) {
+        let ctx =
+            GenContext::new(template.to_path_buf(), output.to_path_buf()).with_vars(options().vars);
+        Generator::new(Pipeline::new().unwrap(), ctx)
+            .generate()
+            .unwrap();
+    }
+
+    #[test]
+    fn test_check_fails_after_the_ontology_changes_and_passes_once_regenerated() {
+        let (dir, template, output) = project(&["User", "Order"]);
+        regenerate(&template, &output);
+        fs::write(output.join("src/stale.txt"), "left over").unwrap();
+        fs::write(output.join("src/notes.md"), "hand written").unwrap();
+        fs::write(output.join("README.md"), "outside of what is generated").unwrap();
+        fs::write(output.join(IGNORE_FILE), "# not generated\nnotes.md\n").unwrap();
+        let templates = vec![template.clone()];
+
+        // A committed file that nothing generates any more
+        let report = check_generated(&templates, &output, &options()).unwrap();
+        let removed: Vec<&Path> = report.removed().collect();
+        assert_eq!(removed, vec![Path::new("src/stale.txt")]);
+        fs::remove_file(output.join("src/stale.txt")).unwrap();
+        assert!(check_generated(&templates, &output, &options())
+            .unwrap()
+            .is_clean());
+
+        write_domain(dir.path(), &["User", "Order", "Invoice"]);
+        let before = fs::read_to_string(output.join("src/entities.txt")).unwrap();
+        let report = check_generated(&templates, &output, &options()).unwrap();
+        let changed: Vec<&Path> = report.changed().collect();
+        assert_eq!(changed, vec![Path::new("src/entities.txt")]);
+        match &report.files[0].change {
+            FileChange::Changed { diff } => {
+                assert_eq!(diff, "+<http://example.org/Invoice>")
+            }
+            other => panic!("unexpected change: {:?}", other),
+        }
+        assert!(report.to_string().contains("changed: src/entities.txt"));
+        // Checking never writes to the output directory
+        assert_eq!(
+            fs::read_to_string(output.join("src/entities.txt")).unwrap(),
+            before
+        );
+
+        regenerate(&template, &output);
+        assert!(check_generated(&templates, &output, &options())
+            .unwrap()
+            .is_clean());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_mode_only_change_is_stale() {
+        let (_dir, template, output) = project(&["User"]);
+        regenerate(&template, &output);
+        let templates = vec![template.clone()];
+        assert!(check_generated(&templates, &output, &options())
+            .unwrap()
+            .is_clean());
+
+        let executable = TEMPLATE.replacen("---\n", "---\nexecutable: true\n", 1);
+        fs::write(&template, executable).unwrap();
+        let report = check_generated(&templates, &output, &options()).unwrap();
+        let changed: Vec<&Path> = report.changed().collect();
+        assert_eq!(changed, vec![Path::new("src/entities.txt")]);
+        match &report.files[0].change {
+            FileChange::ModeChanged { rendered, .. } => assert_eq!(*rendered, 0o755),
+            other => panic!("unexpected change: {:?}", other),
+        }
+        assert!(report.to_string().contains("-> 0755)"));
+
+        regenerate(&template, &output);
+        assert!(check_generated(&templates, &output, &options())
+            .unwrap()
+            .is_clean());
+    }
+
+    #[test]
+    fn test_added_and_ignored_files() {
+        let (_dir, template, output) = project(&["User"]);
+        fs::create_dir_all(output.join("src")).unwrap();
+        fs::write(output.join("src/build.log"), "...").unwrap();
+        fs::write(output.join(IGNORE_FILE), "*.log\n").unwrap();
+
+        let report = check_generated(&[template], &output, &options()).unwrap();
+        let added: Vec<&Path> = report.added().collect();
+        assert_eq!(added, vec![Path::new("src/entities.txt")]);
+        assert_eq!(report.files.len(), 1);
+        assert!(report
+            .to_string()
+            .starts_with("Generated output is stale: 1 added"));
+    }
+
+    #[test]
+    fn test_compact_diff_is_capped() {
+        let old = "a\nb\nc\n";
+        let new = "a\nB\nC\n";
+        assert_eq!(
+            compact_diff(old.as_bytes(), new.as_bytes(), 3),
+            "-b\n-c\n+B\n... 1 more changed lines"
+        );
+        assert_eq!(compact_diff(&[0xff], &[0xfe], 3), "binary files differ");
+    }
+}
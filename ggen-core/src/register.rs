@@ -1,3 +1,4 @@
+use crate::proto_schema::ManifestAccess;
 use heck::{
     ToShoutyKebabCase,
     ToShoutySnakeCase,
@@ -11,7 +12,8 @@ use inflector::{
     string::{deconstantize, demodulize, pluralize, singularize},
     suffix::foreignkey,
 };
-use std::collections::HashMap;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
 use tera::{Context, Result as TeraResult, Tera, Value};
 
 /// Register all text transformation helpers into Tera.
@@ -62,6 +64,7 @@ pub fn register_all(tera: &mut Tera) {
     });
 
     // ---------- SPARQL projection helpers ----------
+    reg_str(tera, "local", local_name);
     register_sparql_helpers(tera);
 }
 
@@ -104,6 +107,40 @@ fn register_sparql_helpers(tera: &mut Tera) {
 
     // Helper to get the count of SPARQL results
     tera.register_function("sparql_count", SparqlCountFn);
+
+    // Helper to group SPARQL results by the value of a column
+    tera.register_function("sparql_group_by", SparqlGroupByFn);
+
+    // Helper to sort SPARQL results by a column
+    tera.register_function("sparql_order_by", SparqlOrderByFn);
+
+    // Helper to synthesize example records from entity field rows
+    tera.register_function("seed_examples", crate::seed_data::SeedExamplesFn);
+
+    // Helpers to build protobuf messages and services from the graph
+    tera.register_function("proto_services", crate::proto_schema::ProtoServicesFn);
+
+    // Helpers to build SQL tables and migrations from the graph
+    tera.register_function("sql_tables", crate::sql_schema::SqlTablesFn);
+
+    // Helpers keeping state in the incremental manifest; read-only until a
+    // generator registers them again for its output root
+    register_manifest_helpers(tera, ManifestAccess::default());
+
+    // Helpers to build JSON Schemas and MCP tools shared by OpenAPI and the
+    // tool manifest
+    tera.register_function("json_schemas", crate::json_schema::JsonSchemasFn);
+    tera.register_function("mcp_tools", crate::json_schema::McpToolsFn);
+}
+
+/// Register the helpers keeping state in the incremental manifest, reaching
+/// it through `access`
+pub fn register_manifest_helpers(tera: &mut Tera, access: ManifestAccess) {
+    tera.register_function(
+        "proto_messages",
+        crate::proto_schema::ProtoMessagesFn(access.clone()),
+    );
+    tera.register_function("sql_migration", crate::sql_schema::SqlMigrationFn(access));
 }
 
 #[derive(Clone)]
@@ -282,6 +319,151 @@ impl tera::Function for SparqlCountFn {
     }
 }
 
+/// Group rows by the value of `key`.
+///
+/// Returns a map of value → rows, ordered by value. Rows keep their order
+/// within a group. Rows without a binding for `key` are grouped under the
+/// empty key `""`, which no bound term renders as, so it always comes first.
+#[derive(Clone)]
+struct SparqlGroupByFn;
+
+impl tera::Function for SparqlGroupByFn {
+    fn call(&self, args: &HashMap<String, Value>) -> TeraResult<Value> {
+        let results = args
+            .get("results")
+            .ok_or_else(|| tera::Error::msg("sparql_group_by: results parameter required"))?;
+        let key = args
+            .get("key")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| tera::Error::msg("sparql_group_by: key parameter required"))?;
+
+        let mut groups: BTreeMap<String, Vec<Value>> = BTreeMap::new();
+        if let Some(array) = results.as_array() {
+            for row in array {
+                let group = match sparql_binding(row, key) {
+                    Some(Value::String(s)) => s.clone(),
+                    Some(value) => value.to_string(),
+                    None => String::new(),
+                };
+                groups.entry(group).or_default().push(row.clone());
+            }
+        }
+
+        let mut map = tera::Map::new();
+        for (group, rows) in groups {
+            map.insert(group, Value::Array(rows));
+        }
+        Ok(Value::Object(map))
+    }
+}
+
+/// Sort rows by the value of `key`.
+///
+/// Compares the lexical form of terms, so `"10"^^xsd:integer` sorts as `10`;
+/// with `numeric=true` values are compared as numbers, and values that aren't
+/// numbers follow the numbers. Rows without a binding for `key` come last in
+/// either direction. The sort is stable: sort by a secondary key first.
+#[derive(Clone)]
+struct SparqlOrderByFn;
+
+impl tera::Function for SparqlOrderByFn {
+    fn call(&self, args: &HashMap<String, Value>) -> TeraResult<Value> {
+        let results = args
+            .get("results")
+            .ok_or_else(|| tera::Error::msg("sparql_order_by: results parameter required"))?;
+        let key = args
+            .get("key")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| tera::Error::msg("sparql_order_by: key parameter required"))?;
+        let descending = match args.get("direction").and_then(|v| v.as_str()) {
+            None | Some("asc") => false,
+            Some("desc") => true,
+            Some(other) => {
+                return Err(tera::Error::msg(format!(
+                    "sparql_order_by: direction must be \"asc\" or \"desc\", got \"{}\"",
+                    other
+                )))
+            }
+        };
+        let numeric = args
+            .get("numeric")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+
+        let mut rows = match results.as_array() {
+            Some(array) => array.clone(),
+            None => return Ok(Value::Array(Vec::new())),
+        };
+        rows.sort_by(|a, b| {
+            let a = sparql_binding(a, key).map(|v| SortKey::new(v, numeric));
+            let b = sparql_binding(b, key).map(|v| SortKey::new(v, numeric));
+            match (a, b) {
+                (Some(a), Some(b)) if descending => b.compare(&a),
+                (Some(a), Some(b)) => a.compare(&b),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            }
+        });
+        Ok(Value::Array(rows))
+    }
+}
+
+/// Value of `column` in a result row, with or without the `?` prefix.
+/// Unbound variables and `null` count as missing.
+pub(crate) fn sparql_binding<'a>(row: &'a Value, column: &str) -> Option<&'a Value> {
+    let obj = row.as_object()?;
+    obj.get(column)
+        .or_else(|| obj.get(&format!("?{}", column)))
+        .filter(|value| !value.is_null())
+}
+
+/// Lexical form of a rendered RDF term: `"42"^^<...>` → `42`, `"hi"@en` → `hi`
+pub(crate) fn lexical_form(term: &str) -> &str {
+    match term
+        .strip_prefix('"')
+        .and_then(|rest| rest.rfind('"').map(|end| &rest[..end]))
+    {
+        Some(lexical) => lexical,
+        None => term,
+    }
+}
+
+#[derive(PartialEq, PartialOrd)]
+enum SortKey {
+    Number(f64),
+    Text(String),
+}
+
+impl SortKey {
+    fn new(value: &Value, numeric: bool) -> Self {
+        let text = match value {
+            Value::String(s) => lexical_form(s).to_string(),
+            other => other.to_string(),
+        };
+        match text.trim().parse::<f64>() {
+            Ok(number) if numeric && !number.is_nan() => SortKey::Number(number),
+            _ => SortKey::Text(text),
+        }
+    }
+
+    /// Numbers before text; NaN is never a `Number`, so this is total
+    fn compare(&self, other: &Self) -> Ordering {
+        self.partial_cmp(other).unwrap_or(Ordering::Equal)
+    }
+}
+
+/// Local name of an IRI, e.g. `User` for `<http://example.org/app#User>`
+pub(crate) fn local_name(iri: &str) -> String {
+    let iri = iri.trim();
+    let iri = iri
+        .strip_prefix('<')
+        .and_then(|x| x.strip_suffix('>'))
+        .unwrap_or(iri);
+    let start = iri.rfind(['#', '/']).map_or(0, |i| i + 1);
+    iri[start..].to_string()
+}
+
 // ---------- internals ----------
 fn reg_str<F>(tera: &mut Tera, name: &str, f: F)
 where
@@ -597,6 +779,7 @@ mod tests {
             "lower",
             "lcfirst",
             "ucfirst",
+            "local",
         ];
 
         for filter in expected_filters {
@@ -820,4 +1003,113 @@ mod tests {
             .unwrap();
         assert_eq!(result, "1");
     }
+
+    /// Rows as the graph renders them: `?`-prefixed variables and full terms
+    fn endpoint_results() -> Value {
+        serde_json::json!([
+            {"?api": "\"users\"", "?path": "\"/users/:id\"", "?method": "\"GET\""},
+            {"?path": "\"/health\"", "?method": "\"GET\""},
+            {"?api": "\"orders\"", "?path": "\"/orders\"", "?method": "\"GET\""},
+            {"?api": "\"users\"", "?path": "\"/users\"", "?method": "\"POST\""},
+            {"?api": null, "?path": "\"/metrics\"", "?method": "\"GET\""}
+        ])
+    }
+
+    #[test]
+    fn test_sparql_group_by_function() {
+        let mut tera = create_test_tera();
+        let mut ctx = Context::new();
+        ctx.insert("results", &endpoint_results());
+
+        let template = r#"
+{%- for api, rows in sparql_group_by(results=results, key="api") -%}
+[{{ api }}]{% for row in rows %} {{ row["?path"] }}{% endfor %}
+{% endfor -%}"#;
+        let result = tera.render_str(template, &ctx).unwrap();
+        // Rows without an api share the empty key, which sorts first
+        assert_eq!(
+            result,
+            "[] \"/health\" \"/metrics\"\n\
+             [\"orders\"] \"/orders\"\n\
+             [\"users\"] \"/users/:id\" \"/users\"\n"
+        );
+
+        // Non-array results have no groups
+        ctx.insert("results", &serde_json::json!(true));
+        let result = tera
+            .render_str(
+                "{{ sparql_group_by(results=results, key=\"api\") | length }}",
+                &ctx,
+            )
+            .unwrap();
+        assert_eq!(result, "0");
+    }
+
+    #[test]
+    fn test_sparql_order_by_function() {
+        let mut tera = create_test_tera();
+        let mut ctx = Context::new();
+        ctx.insert("results", &endpoint_results());
+
+        let render = |tera: &mut Tera, args: &str| {
+            tera.render_str(
+                &format!(
+                    "{{% for row in sparql_order_by(results=results, {}) %}}{{{{ row[\"?path\"] }}}} {{% endfor %}}",
+                    args
+                ),
+                &ctx,
+            )
+            .unwrap()
+        };
+
+        assert_eq!(
+            render(&mut tera, "key=\"path\""),
+            "\"/health\" \"/metrics\" \"/orders\" \"/users\" \"/users/:id\" "
+        );
+        assert_eq!(
+            render(&mut tera, "key=\"path\", direction=\"desc\""),
+            "\"/users/:id\" \"/users\" \"/orders\" \"/metrics\" \"/health\" "
+        );
+        // Missing bindings sort last in both directions, keeping their order
+        assert_eq!(
+            render(&mut tera, "key=\"api\""),
+            "\"/orders\" \"/users/:id\" \"/users\" \"/health\" \"/metrics\" "
+        );
+        assert_eq!(
+            render(&mut tera, "key=\"api\", direction=\"desc\""),
+            "\"/users/:id\" \"/users\" \"/orders\" \"/health\" \"/metrics\" "
+        );
+
+        let err = tera
+            .render_str(
+                "{{ sparql_order_by(results=results, key=\"path\", direction=\"up\") }}",
+                &ctx,
+            )
+            .unwrap_err();
+        assert!(format!("{:?}", err).contains("direction must be"));
+    }
+
+    #[test]
+    fn test_sparql_order_by_numeric() {
+        let mut tera = create_test_tera();
+        let mut ctx = Context::new();
+        let xsd_int = "^^<http://www.w3.org/2001/XMLSchema#integer>";
+        let results = serde_json::json!([
+            {"name": "ten", "size": format!("\"10\"{}", xsd_int)},
+            {"name": "two", "size": format!("\"2\"{}", xsd_int)},
+            {"name": "unknown", "size": "\"n/a\""},
+            {"name": "missing"},
+            {"name": "half", "size": 0.5}
+        ]);
+        ctx.insert("results", &results);
+
+        let template = "{% for row in sparql_order_by(results=results, key=\"size\", numeric=true) %}{{ row.name }} {% endfor %}";
+        let result = tera.render_str(template, &ctx).unwrap();
+        assert_eq!(result, "half two ten unknown missing ");
+
+        // Lexical order puts "10" before "2"
+        let template = "{% for row in sparql_order_by(results=results, key=\"size\") %}{{ row.name }} {% endfor %}";
+        let result = tera.render_str(template, &ctx).unwrap();
+        assert_eq!(result, "half ten two unknown missing ");
+    }
 }
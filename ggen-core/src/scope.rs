@@ -0,0 +1,565 @@
+//! Per-row templates and scoped generation
+//!
+//! A template with `foreach` renders one file per row of one of its queries.
+//! Each variable of the row is bound by name, IRIs without their angle
+//! brackets and literals as their plain value, and `to:` is rendered with
+//! the row:
+//!
+//! ```yaml
+//! to: "src/models/{{ entity | local | snake }}.rs"
+//! rdf: ["domain.ttl"]
+//! foreach: entities
+//! sparql:
+//!   entities: "SELECT ?entity WHERE { ?entity a <http://example.org/app#Entity> }"
+//! ```
+//!
+//! Changing one entity shouldn't mean regenerating the whole project. A
+//! [`Scope`] such as `entity=User,Order` or `endpoint=GetUsers` selects the
+//! files whose bindings hold one of the names under that variable, compared
+//! with the full value and with its local name. Rows outside the scope are
+//! not rendered. [`generate_in_scope`] records the bindings of every file in
+//! [`MANIFEST`], and skips templates without `foreach` whose recorded files
+//! are all outside the scope without rendering them; a template that lists
+//! every entity depends on each of them and is rendered again. Files outside
+//! the scope are left untouched, and the [`ScopeReport`] names them.
+//!
+//! `ggen.toml` may set a scope used when none is given:
+//!
+//! ```toml
+//! default_scope = ["entity=User"]
+//! ```
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::GgenConfig;
+use crate::generator::{GenContext, Generator};
+use crate::output::manifest_key;
+use crate::pipeline::Pipeline;
+use crate::pqc::calculate_sha256;
+use crate::register::local_name;
+use crate::template::Template;
+
+/// Bindings of every generated file, relative to the output directory
+pub const MANIFEST: &str = ".ggen/generated.json";
+
+/// Values a file was rendered from, by query variable without `?`
+pub type Bindings = BTreeMap<String, BTreeSet<String>>;
+
+/// Names selected per query variable; empty selects everything
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Scope {
+    selectors: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl Scope {
+    /// Read selectors such as `entity=User,Order`; a file is in scope when
+    /// any of them matches
+    pub fn parse<S: AsRef<str>>(selectors: &[S]) -> Result<Self> {
+        let mut scope = Self::default();
+        for selector in selectors {
+            let selector = selector.as_ref();
+            let invalid = || {
+                anyhow::anyhow!(
+                    "Invalid scope '{}'; expected variable=name[,name...], e.g. entity=User,Order",
+                    selector
+                )
+            };
+            let (variable, names) = selector.split_once('=').ok_or_else(invalid)?;
+            let variable = variable.trim();
+            let names: BTreeSet<String> = names
+                .split(',')
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(String::from)
+                .collect();
+            if variable.is_empty() || names.is_empty() {
+                return Err(invalid());
+            }
+            scope
+                .selectors
+                .entry(variable.to_string())
+                .or_default()
+                .extend(names);
+        }
+        Ok(scope)
+    }
+
+    /// `default_scope` of a `ggen.toml`
+    pub fn from_config(config: &GgenConfig) -> Result<Self> {
+        Self::parse(&config.default_scope)
+    }
+
+    /// Whether nothing is left out
+    pub fn is_everything(&self) -> bool {
+        self.selectors.is_empty()
+    }
+
+    /// Whether a file rendered from `bindings` is in scope
+    pub fn includes(&self, bindings: &Bindings) -> bool {
+        self.is_everything()
+            || self.selectors.iter().any(|(variable, names)| {
+                bindings.get(variable).is_some_and(|values| {
+                    values
+                        .iter()
+                        .any(|value| names.contains(value) || names.contains(&local_name(value)))
+                })
+            })
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let selectors: Vec<String> = self
+            .selectors
+            .iter()
+            .map(|(variable, names)| {
+                let names: Vec<&str> = names.iter().map(String::as_str).collect();
+                format!("{}={}", variable, names.join(","))
+            })
+            .collect();
+        f.write_str(&selectors.join(" "))
+    }
+}
+
+/// A generated file as recorded in [`MANIFEST`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub template: String,
+    pub bindings: Bindings,
+    /// Hash of the content written
+    pub sha256: String,
+}
+
+/// Generated files, by path relative to the output directory
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GenerationManifest {
+    pub files: BTreeMap<String, ManifestEntry>,
+}
+
+impl GenerationManifest {
+    /// The manifest of `output_root`, empty if nothing was generated yet
+    pub fn load(output_root: &Path) -> Result<Self> {
+        let path = output_root.join(MANIFEST);
+        match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content)
+                .with_context(|| format!("Failed to parse {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        }
+    }
+
+    pub fn save(&self, output_root: &Path) -> Result<()> {
+        let path = output_root.join(MANIFEST);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, serde_json::to_string_pretty(self)? + "\n")
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// Recorded files of `template`
+    fn of_template<'a>(
+        &'a self, template: &'a str,
+    ) -> impl Iterator<Item = (&'a String, &'a ManifestEntry)> {
+        self.files
+            .iter()
+            .filter(move |(_, entry)| entry.template == template)
+    }
+}
+
+/// What [`generate_in_scope`] did, with paths relative to the output
+/// directory
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScopeReport {
+    pub scope: Scope,
+    pub written: Vec<PathBuf>,
+    /// Files left untouched because they are outside the scope
+    pub skipped: Vec<PathBuf>,
+}
+
+impl fmt::Display for ScopeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Generated {} files", self.written.len())?;
+        if !self.skipped.is_empty() {
+            writeln!(
+                f,
+                "Skipped {} files outside the scope {}:",
+                self.skipped.len(),
+                self.scope
+            )?;
+            for path in &self.skipped {
+                writeln!(f, "  {}", path.display())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Render `templates` into `output_root`, leaving files outside `scope`
+/// untouched, and record the bindings of every file written in [`MANIFEST`]
+pub fn generate_in_scope(
+    templates: &[PathBuf], output_root: &Path, vars: &BTreeMap<String, String>, scope: &Scope,
+) -> Result<ScopeReport> {
+    let mut manifest = GenerationManifest::load(output_root)?;
+    let mut report = ScopeReport {
+        scope: scope.clone(),
+        ..ScopeReport::default()
+    };
+
+    for template in templates {
+        let key = template.display().to_string();
+        if !scope.is_everything() {
+            let source = fs::read_to_string(template)
+                .with_context(|| format!("Failed to read {}", template.display()))?;
+            let recorded: Vec<_> = manifest.of_template(&key).collect();
+            if !Template::parse(&source)?.is_foreach()
+                && !recorded.is_empty()
+                && !recorded
+                    .iter()
+                    .any(|(_, entry)| scope.includes(&entry.bindings))
+            {
+                report.skipped.extend(
+                    recorded
+                        .iter()
+                        .map(|(path, _)| PathBuf::from(path.as_str())),
+                );
+                continue;
+            }
+        }
+
+        let ctx = GenContext::new(template.clone(), output_root.to_path_buf())
+            .with_vars(vars.clone())
+            .with_scope(scope.clone());
+        let mut generator = Generator::new(Pipeline::new()?, ctx);
+        let outputs = generator
+            .generate_outputs()
+            .with_context(|| format!("Failed to render {}", template.display()))?;
+
+        // A full run drops the files a template no longer generates
+        if scope.is_everything() {
+            manifest.files.retain(|_, entry| entry.template != key);
+        }
+        for output in outputs {
+            let content = fs::read(&output.path)
+                .with_context(|| format!("Failed to read {}", output.path.display()))?;
+            let path = relative(output_root, &output.path);
+            manifest.files.insert(
+                manifest_key(&path),
+                ManifestEntry {
+                    template: key.clone(),
+                    bindings: output.bindings,
+                    sha256: calculate_sha256(&content),
+                },
+            );
+            report.written.push(path);
+        }
+        report.skipped.extend(
+            generator
+                .skipped
+                .iter()
+                .map(|path| relative(output_root, path)),
+        );
+    }
+
+    manifest.save(output_root)?;
+    report.written.sort();
+    report.skipped.sort();
+    report.skipped.dedup();
+    Ok(report)
+}
+
+fn relative(root: &Path, path: &Path) -> PathBuf {
+    path.strip_prefix(root).unwrap_or(path).to_path_buf()
+}
+
+/// Rows of the `foreach` query, each variable bound to its plain value
+pub(crate) fn foreach_rows(
+    results: &BTreeMap<String, serde_json::Value>, query: &str,
+) -> Result<Vec<BTreeMap<String, String>>> {
+    let rows = match results.get(query) {
+        Some(serde_json::Value::Array(rows)) => rows,
+        Some(_) => anyhow::bail!("foreach query '{}' is not a SELECT", query),
+        None => anyhow::bail!(
+            "foreach names '{}', which is not one of the template's sparql queries",
+            query
+        ),
+    };
+    Ok(rows
+        .iter()
+        .filter_map(|row| row.as_object())
+        .map(|row| {
+            row.iter()
+                .filter_map(|(variable, term)| {
+                    Some((
+                        variable.trim_start_matches('?').to_string(),
+                        term_value(term.as_str()?),
+                    ))
+                })
+                .collect()
+        })
+        .collect())
+}
+
+/// Every value of every query result, by variable
+pub(crate) fn result_bindings(results: &BTreeMap<String, serde_json::Value>) -> Bindings {
+    let mut bindings = Bindings::new();
+    for rows in results.values().filter_map(|rows| rows.as_array()) {
+        for row in rows.iter().filter_map(|row| row.as_object()) {
+            for (variable, term) in row {
+                if let Some(term) = term.as_str() {
+                    bindings
+                        .entry(variable.trim_start_matches('?').to_string())
+                        .or_default()
+                        .insert(term_value(term));
+                }
+            }
+        }
+    }
+    bindings
+}
+
+/// An IRI without angle brackets, a literal without quotes, escapes, language
+/// or datatype; blank nodes as they are
+fn term_value(term: &str) -> String {
+    if let Some(iri) = term.strip_prefix('<').and_then(|t| t.strip_suffix('>')) {
+        return iri.to_string();
+    }
+    let Some((lexical, _)) = term.strip_prefix('"').and_then(|t| t.rsplit_once('"')) else {
+        return term.to_string();
+    };
+    let mut value = String::with_capacity(lexical.len());
+    let mut chars = lexical.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            value.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => value.push('\n'),
+            Some('r') => value.push('\r'),
+            Some('t') => value.push('\t'),
+            Some(other) => value.push(other),
+            None => {}
+        }
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const MODEL: &str = r#"---
+to: "src/models/{{ entity | local | snake }}.rs"
+rdf: ["domain.ttl"]
+foreach: entities
+sparql:
+  entities: "SELECT ?entity ?fields WHERE { ?entity a <http://example.org/app#Entity> ; <http://example.org/app#fields> ?fields }"
+---
+// {{ header }}
+pub struct {{ entity | local }} { {{ fields }} }
+"#;
+
+    const MODELS_MOD: &str = r#"---
+to: "src/models/mod.rs"
+rdf: ["domain.ttl"]
+sparql:
+  entities: "SELECT ?entity WHERE { ?entity a <http://example.org/app#Entity> } ORDER BY ?entity"
+---
+{% for row in sparql_results.entities %}pub mod {{ row["?entity"] | local | snake }};
+{% endfor %}"#;
+
+    const ENDPOINT: &str = r#"---
+to: "src/api/{{ endpoint | local | snake }}.rs"
+rdf: ["domain.ttl"]
+foreach: endpoints
+sparql:
+  endpoints: "SELECT ?endpoint WHERE { ?endpoint a <http://example.org/app#Endpoint> }"
+---
+// {{ header }}
+pub async fn {{ endpoint | local | snake }}() {}
+"#;
+
+    const README: &str = r#"---
+to: "README.md"
+---
+{{ header }}
+"#;
+
+    fn domain(user_fields: &str) -> String {
+        format!(
+            r#"@prefix app: <http://example.org/app#> .
+app:User a app:Entity ; app:fields "{}" .
+app:Order a app:Entity ; app:fields "id: u64" .
+app:GetUsers a app:Endpoint .
+app:GetOrders a app:Endpoint .
+"#,
+            user_fields
+        )
+    }
+
+    /// Templates and ontology in `dir`
+    fn fixture(dir: &Path) -> Vec<PathBuf> {
+        fs::write(dir.join("domain.ttl"), domain("id: u64")).unwrap();
+        [
+            ("model.tmpl", MODEL),
+            ("mod.tmpl", MODELS_MOD),
+            ("endpoint.tmpl", ENDPOINT),
+            ("readme.tmpl", README),
+        ]
+        .iter()
+        .map(|(name, source)| {
+            let path = dir.join(name);
+            fs::write(&path, source).unwrap();
+            path
+        })
+        .collect()
+    }
+
+    /// Content of every file under `root`, outside `.ggen`
+    fn snapshot(root: &Path) -> BTreeMap<String, String> {
+        walkdir::WalkDir::new(root)
+            .into_iter()
+            .map(|entry| entry.unwrap())
+            .filter(|entry| entry.file_type().is_file())
+            .filter_map(|entry| {
+                let path = relative(root, entry.path());
+                (!path.starts_with(".ggen")).then(|| {
+                    (
+                        manifest_key(&path),
+                        fs::read_to_string(entry.path()).unwrap(),
+                    )
+                })
+            })
+            .collect()
+    }
+
+    fn header(value: &str) -> BTreeMap<String, String> {
+        BTreeMap::from([("header".to_string(), value.to_string())])
+    }
+
+    #[test]
+    fn test_scope_parsing() {
+        let scope =
+            Scope::parse(&["entity=User, Order", "endpoint=GetUsers", "entity=Tag"]).unwrap();
+        assert_eq!(scope.to_string(), "endpoint=GetUsers entity=Order,Tag,User");
+        assert!(Scope::parse::<&str>(&[]).unwrap().is_everything());
+        for invalid in ["User", "entity=", "=User", "entity=,"] {
+            let err = Scope::parse(&[invalid]).unwrap_err();
+            assert!(err.to_string().starts_with("Invalid scope"), "{}", invalid);
+        }
+
+        let bindings = Bindings::from([(
+            "entity".to_string(),
+            BTreeSet::from(["http://example.org/app#User".to_string()]),
+        )]);
+        assert!(scope.includes(&bindings));
+        assert!(!Scope::parse(&["entity=Order"]).unwrap().includes(&bindings));
+        assert!(!Scope::parse(&["endpoint=User"])
+            .unwrap()
+            .includes(&bindings));
+
+        let config: GgenConfig =
+            toml::from_str(r#"default_scope = ["endpoint=GetUsers"]"#).unwrap();
+        assert_eq!(
+            Scope::from_config(&config).unwrap().to_string(),
+            "endpoint=GetUsers"
+        );
+    }
+
+    #[test]
+    fn test_terms_become_plain_values() {
+        assert_eq!(
+            term_value("<http://example.org/app#User>"),
+            "http://example.org/app#User"
+        );
+        assert_eq!(term_value(r#""id: u64""#), "id: u64");
+        assert_eq!(term_value(r#""say \"hi\"\n"@en"#), "say \"hi\"\n");
+        assert_eq!(
+            term_value(r#""5"^^<http://www.w3.org/2001/XMLSchema#integer>"#),
+            "5"
+        );
+        assert_eq!(term_value("_:b0"), "_:b0");
+    }
+
+    #[test]
+    fn test_scoped_run_touches_only_the_selected_entity() {
+        let dir = TempDir::new().unwrap();
+        let templates = fixture(dir.path());
+        let out = dir.path().join("out");
+
+        let full = generate_in_scope(&templates, &out, &header("v1"), &Scope::default()).unwrap();
+        assert_eq!(full.written.len(), 6);
+        assert!(full.skipped.is_empty());
+        assert_eq!(full.to_string(), "Generated 6 files\n");
+        let before = snapshot(&out);
+        assert!(before["src/models/user.rs"].starts_with("// v1\npub struct User { id: u64 }"));
+        assert!(before["src/models/mod.rs"].starts_with("pub mod order;\npub mod user;"));
+
+        // Both would change every file they reach
+        fs::write(
+            dir.path().join("domain.ttl"),
+            domain("id: u64, name: String"),
+        )
+        .unwrap();
+        let scope = Scope::parse(&["entity=User"]).unwrap();
+        let report = generate_in_scope(&templates, &out, &header("v2"), &scope).unwrap();
+
+        assert_eq!(
+            report.written,
+            vec![
+                PathBuf::from("src/models/mod.rs"),
+                PathBuf::from("src/models/user.rs")
+            ]
+        );
+        assert_eq!(
+            report.skipped,
+            vec![
+                PathBuf::from("README.md"),
+                PathBuf::from("src/api/get_orders.rs"),
+                PathBuf::from("src/api/get_users.rs"),
+                PathBuf::from("src/models/order.rs"),
+            ]
+        );
+        assert!(report
+            .to_string()
+            .contains("Skipped 4 files outside the scope entity=User:\n  README.md\n"));
+
+        let after = snapshot(&out);
+        let changed: Vec<&String> = after
+            .iter()
+            .filter(|(path, content)| before.get(*path) != Some(content))
+            .map(|(path, _)| path)
+            .collect();
+        assert_eq!(changed, vec!["src/models/user.rs"]);
+        assert!(after["src/models/user.rs"]
+            .starts_with("// v2\npub struct User { id: u64, name: String }"));
+
+        // Every file is recorded with the hash of what is on disk
+        let manifest = GenerationManifest::load(&out).unwrap();
+        let recorded: Vec<&String> = manifest.files.keys().collect();
+        assert_eq!(recorded, after.keys().collect::<Vec<_>>());
+        for (path, entry) in &manifest.files {
+            assert_eq!(
+                entry.sha256,
+                calculate_sha256(after[path].as_bytes()),
+                "{}",
+                path
+            );
+        }
+        assert_eq!(
+            manifest.files["src/models/order.rs"].bindings["entity"],
+            BTreeSet::from(["http://example.org/app#Order".to_string()])
+        );
+        assert_eq!(
+            manifest.files["src/models/mod.rs"].template,
+            templates[1].display().to_string()
+        );
+    }
+}
@@ -0,0 +1,329 @@
+//! Deterministic seed data for examples and fixtures
+//!
+//! [`generate`] synthesizes example records for a set of entities from their
+//! field specs. The same specs, seed and count always give the same records.
+//! Values respect the constraints of their field: emails are valid addresses,
+//! numbers stay within `min..=max`, and a field that references another entity
+//! holds the id of one of that entity's records, so records of different
+//! entities point at each other consistently.
+//!
+//! Templates call it as `seed_examples(fields=..., seed=42, count=2)`, where
+//! `fields` are SPARQL rows with the columns `entity`, `field` and `datatype`
+//! and, optionally, `format`, `min`, `max` and `references`. The result maps
+//! each entity's local name to its records.
+
+use serde_json::{Map, Number, Value};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::register::{lexical_form, sparql_binding};
+
+/// A field of an entity, as declared in the graph
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FieldSpec {
+    /// Local name of the entity, like `User`
+    pub entity: String,
+    pub name: String,
+    /// XSD datatype local name, like `string` or `decimal`
+    pub datatype: String,
+    /// `email`, `uri` or `uuid`
+    pub format: Option<String>,
+    /// Bounds of a number, or of the length of a string
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    /// Local name of the entity whose id this field holds
+    pub references: Option<String>,
+}
+
+impl FieldSpec {
+    /// Read a field from a SPARQL row; `None` without entity or field name
+    pub fn from_row(row: &Value) -> Option<Self> {
+        let text = |column: &str| {
+            sparql_binding(row, column)
+                .and_then(Value::as_str)
+                .map(|term| local_name(lexical_form(term)).to_string())
+        };
+        let number = |column: &str| {
+            sparql_binding(row, column).and_then(|value| match value {
+                Value::Number(number) => number.as_f64(),
+                Value::String(term) => lexical_form(term).trim().parse().ok(),
+                _ => None,
+            })
+        };
+        Some(Self {
+            entity: text("entity")?,
+            name: text("field")?,
+            datatype: text("datatype").unwrap_or_else(|| "string".to_string()),
+            format: text("format"),
+            min: number("min"),
+            max: number("max"),
+            references: text("references"),
+        })
+    }
+}
+
+/// `count` records per entity of `fields`, each with an `id`
+///
+/// Entities are keyed by name. A field named `id` is ignored: every record
+/// gets a UUID-formatted id derived from the seed.
+pub fn generate(fields: &[FieldSpec], seed: u64, count: usize) -> BTreeMap<String, Vec<Value>> {
+    let mut by_entity: BTreeMap<&str, BTreeMap<&str, &FieldSpec>> = BTreeMap::new();
+    for field in fields {
+        let entity = by_entity.entry(field.entity.as_str()).or_default();
+        if field.name != "id" {
+            entity.insert(field.name.as_str(), field);
+        }
+    }
+    // Referenced entities need ids even when they declare no fields
+    for field in fields {
+        if let Some(target) = &field.references {
+            by_entity.entry(target.as_str()).or_default();
+        }
+    }
+
+    let mut rng = SplitMix64(seed);
+    let ids: HashMap<&str, Vec<String>> = by_entity
+        .keys()
+        .map(|entity| (*entity, (0..count).map(|_| uuid(&mut rng)).collect()))
+        .collect();
+
+    by_entity
+        .iter()
+        .map(|(entity, fields)| {
+            let records = ids[entity]
+                .iter()
+                .enumerate()
+                .map(|(index, id)| {
+                    let mut record = Map::new();
+                    record.insert("id".to_string(), Value::String(id.clone()));
+                    for (name, field) in fields {
+                        let value = field_value(field, index, &ids, &mut rng);
+                        record.insert(name.to_string(), value);
+                    }
+                    Value::Object(record)
+                })
+                .collect();
+            (entity.to_string(), records)
+        })
+        .collect()
+}
+
+fn field_value(
+    field: &FieldSpec, index: usize, ids: &HashMap<&str, Vec<String>>, rng: &mut SplitMix64,
+) -> Value {
+    if let Some(target) = &field.references {
+        return match ids.get(target.as_str()) {
+            Some(target_ids) if !target_ids.is_empty() => {
+                Value::String(target_ids[rng.below(target_ids.len() as u64) as usize].clone())
+            }
+            _ => Value::Null,
+        };
+    }
+    let name = field.name.to_lowercase();
+    match (field.format.as_deref(), field.datatype.as_str()) {
+        (Some("email"), _) => Value::String(email(index, rng)),
+        (Some("uuid"), _) => Value::String(uuid(rng)),
+        (Some("uri"), _) => Value::String(format!(
+            "https://example.com/{}/{}",
+            field.entity.to_lowercase(),
+            index + 1
+        )),
+        (_, "integer" | "int" | "long" | "short" | "nonNegativeInteger" | "positiveInteger") => {
+            let min = field.min.unwrap_or(0.0).ceil() as i64;
+            let max = (field.max.unwrap_or(1000.0).floor() as i64).max(min);
+            Value::Number((min + rng.below((max - min + 1) as u64) as i64).into())
+        }
+        (_, "decimal" | "double" | "float") => {
+            let min = field.min.unwrap_or(0.0);
+            let max = field.max.unwrap_or(1000.0).max(min);
+            // Whole cents, kept inside the bounds after rounding
+            let cents = (min * 100.0).ceil() as i64;
+            let span = ((max * 100.0).floor() as i64 - cents).max(0);
+            let value = (cents + rng.below(span as u64 + 1) as i64) as f64 / 100.0;
+            Number::from_f64(value).map_or(Value::Null, Value::Number)
+        }
+        (_, "boolean") => Value::Bool(rng.below(2) == 1),
+        (_, "dateTime") => Value::String(format!(
+            "2024-01-{:02}T{:02}:{:02}:00Z",
+            1 + rng.below(28),
+            rng.below(24),
+            rng.below(60)
+        )),
+        (_, "date") => Value::String(format!("2024-01-{:02}", 1 + rng.below(28))),
+        _ if name.contains("email") => Value::String(email(index, rng)),
+        _ => Value::String(text(field, index, rng)),
+    }
+}
+
+const FIRST_NAMES: &[&str] = &["Ada", "Grace", "Alan", "Edsger", "Barbara", "Donald"];
+const LAST_NAMES: &[&str] = &[
+    "Lovelace", "Hopper", "Turing", "Dijkstra", "Liskov", "Knuth",
+];
+
+fn email(index: usize, rng: &mut SplitMix64) -> String {
+    let first = FIRST_NAMES[rng.below(FIRST_NAMES.len() as u64) as usize];
+    format!("{}{}@example.com", first.to_lowercase(), index + 1)
+}
+
+/// A name for fields called `name`, otherwise a label; cut or padded to the
+/// length bounds
+fn text(field: &FieldSpec, index: usize, rng: &mut SplitMix64) -> String {
+    let mut text = if field.name.to_lowercase().ends_with("name") {
+        format!(
+            "{} {}",
+            FIRST_NAMES[rng.below(FIRST_NAMES.len() as u64) as usize],
+            LAST_NAMES[rng.below(LAST_NAMES.len() as u64) as usize]
+        )
+    } else {
+        format!("{} {} {}", field.entity, field.name, index + 1)
+    };
+    if let Some(max) = field.max {
+        text = text.chars().take(max.max(0.0) as usize).collect();
+    }
+    if let Some(min) = field.min {
+        while text.chars().count() < min as usize {
+            text.push('x');
+        }
+    }
+    text
+}
+
+fn uuid(rng: &mut SplitMix64) -> String {
+    let (high, low) = (rng.next(), rng.next());
+    // Version 4 and RFC 4122 variant bits, so the ids validate as UUIDs
+    format!(
+        "{:08x}-{:04x}-4{:03x}-{:04x}-{:012x}",
+        high >> 32,
+        (high >> 16) & 0xffff,
+        high & 0x0fff,
+        0x8000 | ((low >> 48) & 0x3fff),
+        low & 0xffff_ffff_ffff
+    )
+}
+
+/// Last segment of an IRI: `<http://example.org/api/User>` → `User`
+pub(crate) fn local_name(term: &str) -> &str {
+    let term = term.trim_start_matches('<').trim_end_matches('>');
+    term.rsplit(['#', '/']).next().unwrap_or(term)
+}
+
+/// SplitMix64, so examples stay the same across platforms and `rand` versions
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0..bound`; `bound` must not be 0
+    fn below(&mut self, bound: u64) -> u64 {
+        self.next() % bound
+    }
+}
+
+/// `seed_examples(fields=..., seed=42, count=2)`; see the module docs
+#[derive(Clone)]
+pub struct SeedExamplesFn;
+
+impl tera::Function for SeedExamplesFn {
+    fn call(&self, args: &HashMap<String, Value>) -> tera::Result<Value> {
+        let rows = args
+            .get("fields")
+            .and_then(Value::as_array)
+            .ok_or_else(|| tera::Error::msg("seed_examples: fields parameter required"))?;
+        let seed = args.get("seed").and_then(Value::as_u64).unwrap_or(42);
+        let count = args.get("count").and_then(Value::as_u64).unwrap_or(2) as usize;
+
+        let fields: Vec<FieldSpec> = rows.iter().filter_map(FieldSpec::from_row).collect();
+        let examples = generate(&fields, seed, count)
+            .into_iter()
+            .map(|(entity, records)| (entity, Value::Array(records)))
+            .collect();
+        Ok(Value::Object(examples))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn field(entity: &str, name: &str, datatype: &str) -> FieldSpec {
+        FieldSpec {
+            entity: entity.to_string(),
+            name: name.to_string(),
+            datatype: datatype.to_string(),
+            ..FieldSpec::default()
+        }
+    }
+
+    fn shop() -> Vec<FieldSpec> {
+        vec![
+            field("User", "name", "string"),
+            FieldSpec {
+                format: Some("email".to_string()),
+                ..field("User", "email", "string")
+            },
+            FieldSpec {
+                min: Some(1.0),
+                max: Some(500.0),
+                ..field("Order", "total", "decimal")
+            },
+            FieldSpec {
+                references: Some("User".to_string()),
+                ..field("Order", "userId", "string")
+            },
+        ]
+    }
+
+    #[test]
+    fn test_same_seed_same_examples() {
+        assert_eq!(generate(&shop(), 7, 3), generate(&shop(), 7, 3));
+        assert_ne!(generate(&shop(), 7, 3), generate(&shop(), 8, 3));
+    }
+
+    #[test]
+    fn test_examples_respect_constraints_and_references() {
+        let examples = generate(&shop(), 42, 5);
+        let user_ids: Vec<&str> = examples["User"]
+            .iter()
+            .map(|user| user["id"].as_str().unwrap())
+            .collect();
+        assert_eq!(user_ids.len(), 5);
+        assert!(user_ids.iter().all(|id| uuid::Uuid::parse_str(id).is_ok()));
+
+        for user in &examples["User"] {
+            let email = user["email"].as_str().unwrap();
+            let (local, domain) = email.split_once('@').unwrap();
+            assert!(!local.is_empty() && domain == "example.com", "{}", email);
+            assert!(!user["name"].as_str().unwrap().is_empty());
+        }
+        for order in &examples["Order"] {
+            let total = order["total"].as_f64().unwrap();
+            assert!((1.0..=500.0).contains(&total), "{}", total);
+            assert!(user_ids.contains(&order["userId"].as_str().unwrap()));
+        }
+    }
+
+    #[test]
+    fn test_fields_from_sparql_rows() {
+        let row = json!({
+            "?entity": "<http://example.org/api/Order>",
+            "?field": "\"total\"",
+            "?datatype": "<http://www.w3.org/2001/XMLSchema#decimal>",
+            "?min": "\"1\"^^<http://www.w3.org/2001/XMLSchema#integer>",
+            "?references": null,
+        });
+        let spec = FieldSpec::from_row(&row).unwrap();
+        assert_eq!(spec.entity, "Order");
+        assert_eq!(spec.name, "total");
+        assert_eq!(spec.datatype, "decimal");
+        assert_eq!(spec.min, Some(1.0));
+        assert_eq!(spec.max, None);
+        assert_eq!(spec.references, None);
+        assert!(FieldSpec::from_row(&json!({ "?field": "\"total\"" })).is_none());
+    }
+}
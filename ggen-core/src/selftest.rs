@@ -0,0 +1,1367 @@
+//! Self-test of a project
+//!
+//! A project that doesn't generate can be broken in its configuration, its
+//! ontology, its templates or its providers, and the error generation stops
+//! with rarely says which. [`SelfTest`] checks each layer in turn, offline,
+//! and reports per [`Section`]:
+//!
+//! - `config`: the nearest `ggen.toml` parses and its `default_scope` is valid
+//! - `lifecycle`: the `make.toml` next to it parses and its phases have
+//!   commands
+//! - `ontology`: the RDF files and inline RDF of `ggen.toml` load; classes
+//!   without an `rdfs:label` and domains or ranges declared nowhere are
+//!   warned about
+//! - `templates`: every `.tmpl` under `templates_dir` lints (see
+//!   [`crate::template_lint`]) and renders into a temporary directory,
+//!   against the ontology or, without one, against [`FIXTURE_ONTOLOGY`]
+//! - `paths`: output profile roots, lifecycle workspaces, the RDF files of
+//!   templates and the files they render stay inside their directories
+//! - `providers`: the provider settings of `ggen.toml` (see
+//!   [`ggen_config::SharedConfig`]) are valid and the variables of their
+//!   `env:` keys are set; [`SelfTest::online`] also contacts every provider
+//!
+//! Every check passes, warns or fails, with a hint at the fix for what it
+//! found:
+//!
+//! ```text
+//! config: pass
+//!   pass  ggen.toml: Parsed
+//! templates: fail
+//!   fail  templates/model.tmpl: 9:22: error[unknown-filter] Unknown filter `pascl`
+//!         hint: Fix the template at the position given, or declare the variable under `vars:`
+//! ...
+//! Self-test failed: 1 failed, 0 warnings, 7 passed
+//! ```
+//!
+//! [`Report::to_json`] is the same report for machines, and
+//! [`Report::exit_code`] is 0 when everything passed, 1 with warnings and 2
+//! with failures.
+
+use anyhow::Result;
+use ggen_config::{ConfigError, ProviderSettings, SharedConfig};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::config::GgenConfig;
+use crate::generator::{GenContext, Generator};
+use crate::graph::{rdf_format, CachedResult, Graph, LoadMode};
+use crate::lifecycle::load_make;
+use crate::output::{normalize, OutputProfiles};
+use crate::pipeline::Pipeline;
+use crate::plan::FindingKind;
+use crate::scope::Scope;
+use crate::template::Template;
+use crate::template_lint::{self, lint_template};
+
+/// Graph templates render against when `ggen.toml` names no ontology, or
+/// none of it loads
+pub const FIXTURE_ONTOLOGY: &str = r#"@prefix ex: <http://example.org/> .
+@prefix owl: <http://www.w3.org/2002/07/owl#> .
+@prefix rdfs: <http://www.w3.org/2000/01/rdf-schema#> .
+@prefix xsd: <http://www.w3.org/2001/XMLSchema#> .
+
+ex:Thing a owl:Class ;
+    rdfs:label "Thing" .
+
+ex:name a owl:DatatypeProperty ;
+    rdfs:label "name" ;
+    rdfs:domain ex:Thing ;
+    rdfs:range xsd:string .
+
+ex:thing1 a ex:Thing ;
+    ex:name "Example" .
+"#;
+
+/// How long an online check waits for a provider to answer
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Endpoints contacted for providers without a `base_url`
+const DEFAULT_ENDPOINTS: &[(&str, &str)] = &[
+    ("anthropic", "https://api.anthropic.com"),
+    ("gemini", "https://generativelanguage.googleapis.com"),
+    ("groq", "https://api.groq.com/openai/v1"),
+    ("ollama", "http://localhost:11434"),
+    ("openai", "https://api.openai.com/v1"),
+];
+
+/// Classes of the ontology without a label
+const UNLABELED_CLASSES: &str = r#"
+PREFIX owl: <http://www.w3.org/2002/07/owl#>
+PREFIX rdfs: <http://www.w3.org/2000/01/rdf-schema#>
+SELECT DISTINCT ?class WHERE {
+    { ?class a owl:Class } UNION { ?class a rdfs:Class }
+    FILTER(isIRI(?class))
+    FILTER NOT EXISTS { ?class rdfs:label ?label }
+}
+ORDER BY ?class
+"#;
+
+/// Domains and ranges the ontology says nothing else about, leaving out the
+/// W3C vocabularies such as XSD
+const UNDECLARED_CLASSES: &str = r#"
+PREFIX rdfs: <http://www.w3.org/2000/01/rdf-schema#>
+SELECT DISTINCT ?class WHERE {
+    ?property rdfs:domain|rdfs:range ?class
+    FILTER(isIRI(?class))
+    FILTER(!STRSTARTS(STR(?class), "http://www.w3.org/"))
+    FILTER NOT EXISTS { ?class ?p ?o }
+}
+ORDER BY ?class
+"#;
+
+/// Terms named in a finding before the rest are counted
+const LISTED_TERMS: usize = 5;
+
+/// How bad a check's finding is
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    #[default]
+    Pass,
+    /// Generation works, but maybe not as intended
+    Warn,
+    /// Generation fails or does something unsafe
+    Fail,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Severity::Pass => "pass",
+            Severity::Warn => "warn",
+            Severity::Fail => "fail",
+        })
+    }
+}
+
+/// Layer of the project a check is about; see the module docs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Section {
+    Config,
+    Lifecycle,
+    Ontology,
+    Templates,
+    Paths,
+    Providers,
+}
+
+impl Section {
+    /// Every section, in the order they are checked and reported
+    pub const ALL: [Section; 6] = [
+        Section::Config,
+        Section::Lifecycle,
+        Section::Ontology,
+        Section::Templates,
+        Section::Paths,
+        Section::Providers,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Section::Config => "config",
+            Section::Lifecycle => "lifecycle",
+            Section::Ontology => "ontology",
+            Section::Templates => "templates",
+            Section::Paths => "paths",
+            Section::Providers => "providers",
+        }
+    }
+}
+
+impl fmt::Display for Section {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// One finding of a self-test
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Check {
+    pub severity: Severity,
+    /// File or provider the check is about
+    pub subject: String,
+    pub message: String,
+    /// How to fix what the check found
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hint: Option<String>,
+}
+
+/// The checks of a self-test, by section
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Report {
+    pub sections: BTreeMap<Section, Vec<Check>>,
+}
+
+impl Report {
+    /// Checks of `section`, in the order they were made
+    pub fn section(&self, section: Section) -> &[Check] {
+        self.sections.get(&section).map_or(&[], Vec::as_slice)
+    }
+
+    /// Worst severity of the checks of `section`
+    pub fn section_severity(&self, section: Section) -> Severity {
+        worst(self.section(section))
+    }
+
+    /// Worst severity of all checks
+    pub fn severity(&self) -> Severity {
+        self.sections
+            .values()
+            .map(|checks| worst(checks))
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// 0 when every check passed, 1 with warnings, 2 with failures
+    pub fn exit_code(&self) -> i32 {
+        match self.severity() {
+            Severity::Pass => 0,
+            Severity::Warn => 1,
+            Severity::Fail => 2,
+        }
+    }
+
+    /// Number of checks of `severity`
+    pub fn count(&self, severity: Severity) -> usize {
+        self.sections
+            .values()
+            .flatten()
+            .filter(|check| check.severity == severity)
+            .count()
+    }
+
+    /// The report as JSON: the overall `status` and `exit_code`, and every
+    /// section with its `status` and `checks`
+    pub fn to_json(&self) -> Value {
+        let sections: Vec<Value> = Section::ALL
+            .iter()
+            .map(|&section| {
+                json!({
+                    "name": section,
+                    "status": self.section_severity(section),
+                    "checks": self.section(section),
+                })
+            })
+            .collect();
+        json!({
+            "status": self.severity(),
+            "exit_code": self.exit_code(),
+            "sections": sections,
+        })
+    }
+
+    fn push(
+        &mut self, section: Section, severity: Severity, subject: impl Into<String>,
+        message: impl Into<String>, hint: Option<&str>,
+    ) {
+        self.sections.entry(section).or_default().push(Check {
+            severity,
+            subject: subject.into(),
+            message: message.into(),
+            hint: hint.map(str::to_string),
+        });
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for section in Section::ALL {
+            writeln!(f, "{}: {}", section, self.section_severity(section))?;
+            for check in self.section(section) {
+                writeln!(
+                    f,
+                    "  {:<4}  {}: {}",
+                    check.severity, check.subject, check.message
+                )?;
+                if let Some(hint) = &check.hint {
+                    writeln!(f, "        hint: {}", hint)?;
+                }
+            }
+        }
+        let outcome = match self.severity() {
+            Severity::Pass => "passed",
+            Severity::Warn => "passed with warnings",
+            Severity::Fail => "failed",
+        };
+        write!(
+            f,
+            "Self-test {}: {} failed, {} warnings, {} passed",
+            outcome,
+            self.count(Severity::Fail),
+            self.count(Severity::Warn),
+            self.count(Severity::Pass)
+        )
+    }
+}
+
+fn worst(checks: &[Check]) -> Severity {
+    checks
+        .iter()
+        .map(|check| check.severity)
+        .max()
+        .unwrap_or_default()
+}
+
+/// Self-test of a project; see the module docs
+#[derive(Debug, Clone)]
+pub struct SelfTest {
+    root: PathBuf,
+    online: bool,
+    provider_config: Option<PathBuf>,
+}
+
+/// A graph source of `ggen.toml` that loaded
+enum Source {
+    File(PathBuf),
+    Inline(String),
+}
+
+/// The configuration the checks after `config` work with
+struct Project {
+    config: GgenConfig,
+    /// `None` without a `ggen.toml`
+    config_path: Option<PathBuf>,
+    /// Whether `ggen.toml` parsed; the defaults are used otherwise
+    config_parsed: bool,
+    /// Directory of `ggen.toml`, the root without one
+    dir: PathBuf,
+}
+
+impl Project {
+    /// `path` relative to the project, for reports
+    fn shown(&self, path: &Path) -> String {
+        path.strip_prefix(&self.dir)
+            .unwrap_or(path)
+            .display()
+            .to_string()
+    }
+}
+
+impl SelfTest {
+    /// Self-test of the project whose `ggen.toml` is in `root` or one of its
+    /// parents
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            online: false,
+            provider_config: None,
+        }
+    }
+
+    /// Also send a request to every provider
+    pub fn online(mut self, online: bool) -> Self {
+        self.online = online;
+        self
+    }
+
+    /// Read the providers from `path`, a `.toml`, `.yaml` or `.json` file,
+    /// instead of `ggen.toml`
+    pub fn with_provider_config(mut self, path: impl Into<PathBuf>) -> Self {
+        self.provider_config = Some(path.into());
+        self
+    }
+
+    /// Run every check; only [`SelfTest::online`] checks leave the machine
+    pub async fn run(&self) -> Report {
+        let mut report = Report::default();
+        let project = self.check_config(&mut report);
+        check_lifecycle(&mut report, &project);
+        let sources = check_ontology(&mut report, &project);
+        check_output_paths(&mut report, &project);
+        check_templates(&mut report, &project, &sources);
+        if report.section_severity(Section::Paths) != Severity::Fail {
+            report.push(
+                Section::Paths,
+                Severity::Pass,
+                "project",
+                "Output roots, workspaces and rendered files stay inside the project",
+                None,
+            );
+        }
+        let providers = self.check_providers(&mut report, &project);
+        if self.online {
+            probe_providers(&mut report, &providers).await;
+        }
+        report
+    }
+
+    fn check_config(&self, report: &mut Report) -> Project {
+        let found = self
+            .root
+            .ancestors()
+            .map(|dir| dir.join("ggen.toml"))
+            .find(|path| path.is_file());
+        let Some(config_path) = found else {
+            report.push(
+                Section::Config,
+                Severity::Warn,
+                "ggen.toml",
+                format!(
+                    "No ggen.toml in {} or its parents; using the defaults",
+                    self.root.display()
+                ),
+                Some("Create a ggen.toml in the project root; an empty one will do"),
+            );
+            return Project {
+                config: GgenConfig::default(),
+                config_path: None,
+                config_parsed: false,
+                dir: self.root.clone(),
+            };
+        };
+        let dir = config_path.parent().unwrap_or(&self.root).to_path_buf();
+        let (config, config_parsed) = match GgenConfig::load_from_file(&config_path) {
+            Ok(config) => {
+                report.push(Section::Config, Severity::Pass, "ggen.toml", "Parsed", None);
+                (config, true)
+            }
+            Err(e) => {
+                report.push(
+                    Section::Config,
+                    Severity::Fail,
+                    "ggen.toml",
+                    format!("{:#}", e),
+                    Some("Fix ggen.toml where the error points; the other checks use the defaults"),
+                );
+                (GgenConfig::default(), false)
+            }
+        };
+        if let Err(e) = Scope::parse(&config.default_scope) {
+            report.push(
+                Section::Config,
+                Severity::Fail,
+                "ggen.toml",
+                format!("Invalid default_scope: {}", e),
+                Some("Write each selector as `name=value`, e.g. `entity=User`"),
+            );
+        }
+        Project {
+            config,
+            config_path: Some(config_path),
+            config_parsed,
+            dir,
+        }
+    }
+
+    fn check_providers(&self, report: &mut Report, project: &Project) -> Vec<ProviderSettings> {
+        let path = match (&self.provider_config, &project.config_path) {
+            (Some(path), _) => path.clone(),
+            (None, Some(path)) if !project.config_parsed => {
+                report.push(
+                    Section::Providers,
+                    Severity::Warn,
+                    project.shown(path),
+                    "Not checked, since ggen.toml doesn't parse",
+                    Some("Fix ggen.toml first; see the config section"),
+                );
+                return Vec::new();
+            }
+            (None, Some(path)) => path.clone(),
+            (None, None) => {
+                report.push(
+                    Section::Providers,
+                    Severity::Pass,
+                    "providers",
+                    "No configuration, so no providers to check",
+                    None,
+                );
+                return Vec::new();
+            }
+        };
+        let subject = project.shown(&path);
+        let settings = ggen_config::load_document(&path).and_then(SharedConfig::from_document);
+        let settings = match settings {
+            Ok(settings) => settings,
+            Err(e) => {
+                report.push(
+                    Section::Providers,
+                    Severity::Fail,
+                    subject,
+                    e.to_string(),
+                    Some("Fix the providers section where the error points"),
+                );
+                return Vec::new();
+            }
+        };
+        for deprecation in &settings.deprecations {
+            report.push(
+                Section::Providers,
+                Severity::Warn,
+                &subject,
+                deprecation,
+                Some("Move the settings to the top-level providers table"),
+            );
+        }
+        if let Err(e) = settings.validate() {
+            let problems = match e {
+                ConfigError::Invalid(problems) => problems,
+                e => vec![e.to_string()],
+            };
+            for problem in problems {
+                report.push(
+                    Section::Providers,
+                    Severity::Fail,
+                    &subject,
+                    problem,
+                    Some("Fix the provider setting named"),
+                );
+            }
+            return Vec::new();
+        }
+        if settings.providers.is_empty() {
+            report.push(
+                Section::Providers,
+                Severity::Pass,
+                subject,
+                "No providers configured",
+                None,
+            );
+        }
+        for provider in &settings.providers {
+            let name = format!("provider '{}'", provider.name);
+            let unset = provider
+                .api_key
+                .as_deref()
+                .and_then(|key| key.strip_prefix("env:"))
+                .filter(|var| std::env::var(var).unwrap_or_default().is_empty());
+            match unset {
+                Some(var) => report.push(
+                    Section::Providers,
+                    Severity::Warn,
+                    name,
+                    format!("Its api_key names {}, which is not set", var),
+                    Some("Set the variable before generating with this provider"),
+                ),
+                None => report.push(
+                    Section::Providers,
+                    Severity::Pass,
+                    name,
+                    format!("Model {}", provider.model),
+                    None,
+                ),
+            }
+        }
+        settings.providers
+    }
+}
+
+fn check_lifecycle(report: &mut Report, project: &Project) {
+    let path = project.dir.join("make.toml");
+    if !path.is_file() {
+        report.push(
+            Section::Lifecycle,
+            Severity::Pass,
+            "make.toml",
+            "No make.toml, so no lifecycle to check",
+            None,
+        );
+        return;
+    }
+    let make = match load_make(&path) {
+        Ok(make) => make,
+        Err(e) => {
+            report.push(
+                Section::Lifecycle,
+                Severity::Fail,
+                "make.toml",
+                e.to_string(),
+                Some(
+                    "Fix make.toml where the error points; it needs a [project] with a name \
+                     and a [lifecycle.<phase>] table per phase",
+                ),
+            );
+            return;
+        }
+    };
+    report.push(
+        Section::Lifecycle,
+        Severity::Pass,
+        "make.toml",
+        format!("Parsed, {} phases", make.lifecycle.len()),
+        None,
+    );
+    for (name, phase) in &make.lifecycle {
+        if phase.commands().is_empty() {
+            report.push(
+                Section::Lifecycle,
+                Severity::Warn,
+                "make.toml",
+                format!("Phase '{}' has no commands", name),
+                Some("Give the phase a `command` or `commands`, or remove it"),
+            );
+        }
+    }
+    for (name, workspace) in make.workspace.iter().flatten() {
+        match normalize(Path::new(&workspace.path)) {
+            None => report.push(
+                Section::Paths,
+                Severity::Fail,
+                "make.toml",
+                format!(
+                    "Workspace '{}' at '{}' is outside the project",
+                    name, workspace.path
+                ),
+                Some("Workspace paths are relative to make.toml and may not leave its directory"),
+            ),
+            Some(relative) if !project.dir.join(relative).is_dir() => report.push(
+                Section::Lifecycle,
+                Severity::Warn,
+                "make.toml",
+                format!("Workspace '{}' at '{}' doesn't exist", name, workspace.path),
+                Some("Create the workspace directory or fix its path"),
+            ),
+            Some(_) => {}
+        }
+    }
+}
+
+/// Load the RDF of `ggen.toml`, and lint what loaded; returns the sources
+/// templates render against
+fn check_ontology(report: &mut Report, project: &Project) -> Vec<Source> {
+    let files = project.config.rdf_file_paths(&project.dir);
+    let inline = project.config.rdf_inline_content();
+    if files.is_empty() && inline.is_empty() {
+        report.push(
+            Section::Ontology,
+            Severity::Pass,
+            "ontology",
+            "ggen.toml names no RDF; templates render against a fixture graph",
+            None,
+        );
+        return vec![Source::Inline(FIXTURE_ONTOLOGY.to_string())];
+    }
+    let graph = match Graph::new() {
+        Ok(graph) => graph,
+        Err(e) => {
+            report.push(
+                Section::Ontology,
+                Severity::Fail,
+                "ontology",
+                format!("Failed to create a graph: {:#}", e),
+                None,
+            );
+            return Vec::new();
+        }
+    };
+
+    let mut sources = Vec::new();
+    for path in files {
+        let subject = project.shown(&path);
+        if !path.is_file() {
+            report.push(
+                Section::Ontology,
+                Severity::Fail,
+                subject,
+                "Not found",
+                Some("Fix the path under [rdf] files; it is relative to ggen.toml"),
+            );
+            continue;
+        }
+        let loaded = rdf_format(&path).and_then(|format| {
+            graph.load_file(&path, format, "", LoadMode::from_env(), &mut |_| {})
+        });
+        match loaded {
+            Ok(loaded) => {
+                report.push(
+                    Section::Ontology,
+                    Severity::Pass,
+                    subject,
+                    format!("Loaded {} triples", loaded.triples),
+                    None,
+                );
+                sources.push(Source::File(path));
+            }
+            Err(e) => report.push(
+                Section::Ontology,
+                Severity::Fail,
+                subject,
+                format!("{:#}", e),
+                Some("Fix the RDF where the error points; .ttl, .nt and .rdf files are read"),
+            ),
+        }
+    }
+    for (i, ttl) in inline.into_iter().enumerate() {
+        let subject = format!("ggen.toml [rdf] inline #{}", i + 1);
+        match graph.insert_turtle(&ttl) {
+            Ok(()) => {
+                report.push(Section::Ontology, Severity::Pass, subject, "Loaded", None);
+                sources.push(Source::Inline(ttl));
+            }
+            Err(e) => report.push(
+                Section::Ontology,
+                Severity::Fail,
+                subject,
+                format!("{:#}", e),
+                Some("Fix the Turtle where the error points"),
+            ),
+        }
+    }
+    if sources.is_empty() {
+        return vec![Source::Inline(FIXTURE_ONTOLOGY.to_string())];
+    }
+
+    if graph.is_empty() {
+        report.push(
+            Section::Ontology,
+            Severity::Warn,
+            "ontology",
+            "The ontology has no triples",
+            Some("Templates querying it will find nothing; check the [rdf] files"),
+        );
+    }
+    let unlabeled = terms(&graph, UNLABELED_CLASSES);
+    if !unlabeled.is_empty() {
+        report.push(
+            Section::Ontology,
+            Severity::Warn,
+            "ontology",
+            format!("Classes without an rdfs:label: {}", listing(&unlabeled)),
+            Some("Label every class; templates often name what they generate after it"),
+        );
+    }
+    let undeclared = terms(&graph, UNDECLARED_CLASSES);
+    if !undeclared.is_empty() {
+        report.push(
+            Section::Ontology,
+            Severity::Warn,
+            "ontology",
+            format!(
+                "Domains and ranges declared nowhere: {}",
+                listing(&undeclared)
+            ),
+            Some("Declare the classes in the ontology, or fix misspelled IRIs"),
+        );
+    }
+    sources
+}
+
+/// The `?class` of each row of `query`
+fn terms(graph: &Graph, query: &str) -> Vec<String> {
+    match graph.query_cached(query) {
+        Ok(CachedResult::Solutions(rows)) => rows
+            .into_iter()
+            .filter_map(|mut row| row.remove("class"))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// The first few of `terms`, and how many more there are
+fn listing(terms: &[String]) -> String {
+    let listed = terms[..terms.len().min(LISTED_TERMS)].join(", ");
+    if terms.len() > LISTED_TERMS {
+        format!("{} and {} more", listed, terms.len() - LISTED_TERMS)
+    } else {
+        listed
+    }
+}
+
+fn check_output_paths(report: &mut Report, project: &Project) {
+    if let Err(e) = OutputProfiles::from_config(&project.config, &project.dir) {
+        report.push(
+            Section::Paths,
+            Severity::Fail,
+            "ggen.toml",
+            e.to_string(),
+            Some("Make the profile root relative to ggen.toml, without `..` leaving it"),
+        );
+    }
+    if project.config.security.allow_world_writable {
+        report.push(
+            Section::Paths,
+            Severity::Warn,
+            "ggen.toml",
+            "security.allow_world_writable lets templates create world-writable files",
+            Some("Turn it off unless generated files must be writable by everyone"),
+        );
+    }
+}
+
+/// Lint and render every template into a temporary directory
+fn check_templates(report: &mut Report, project: &Project, sources: &[Source]) {
+    let templates_dir = project.config.templates_dir_path(&project.dir);
+    if !templates_dir.is_dir() {
+        report.push(
+            Section::Templates,
+            Severity::Warn,
+            project.shown(&templates_dir),
+            "No templates directory",
+            Some("Create it, or point templates_dir in ggen.toml at your templates"),
+        );
+        return;
+    }
+    let mut templates: Vec<PathBuf> = walkdir::WalkDir::new(&templates_dir)
+        .into_iter()
+        .flatten()
+        .map(|entry| entry.into_path())
+        .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "tmpl"))
+        .collect();
+    templates.sort();
+    if templates.is_empty() {
+        report.push(
+            Section::Templates,
+            Severity::Warn,
+            project.shown(&templates_dir),
+            "No .tmpl templates",
+            Some("Add templates, or point templates_dir in ggen.toml at them"),
+        );
+        return;
+    }
+    let out = match tempfile::TempDir::new() {
+        Ok(out) => out,
+        Err(e) => {
+            report.push(
+                Section::Templates,
+                Severity::Fail,
+                "templates",
+                format!("Failed to create a directory to render into: {}", e),
+                None,
+            );
+            return;
+        }
+    };
+    for template in templates {
+        check_template(report, project, sources, &template, out.path());
+    }
+}
+
+fn check_template(
+    report: &mut Report, project: &Project, sources: &[Source], path: &Path, out: &Path,
+) {
+    let subject = project.shown(path);
+    let vars = project.config.template_vars();
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(e) => {
+            report.push(
+                Section::Templates,
+                Severity::Fail,
+                subject,
+                e.to_string(),
+                None,
+            );
+            return;
+        }
+    };
+    let pipeline = match pipeline(&project.config, sources) {
+        Ok(pipeline) => pipeline,
+        Err(e) => {
+            report.push(
+                Section::Templates,
+                Severity::Fail,
+                subject,
+                format!("Failed to set up rendering: {:#}", e),
+                None,
+            );
+            return;
+        }
+    };
+
+    let provided: Vec<&str> = vars.keys().map(String::as_str).collect();
+    let (errors, warnings): (Vec<_>, Vec<_>) = lint_template(&source, &pipeline.tera, &provided)
+        .into_iter()
+        .partition(|finding| finding.severity == template_lint::Severity::Error);
+    if let Some(first) = errors.first() {
+        report.push(
+            Section::Templates,
+            Severity::Fail,
+            subject,
+            and_more(first, errors.len()),
+            Some("Fix the template at the position given, or declare the variable under `vars:`"),
+        );
+        return;
+    }
+    if let Some(first) = warnings.first() {
+        report.push(
+            Section::Templates,
+            Severity::Warn,
+            &subject,
+            and_more(first, warnings.len()),
+            Some("Use the declared variables, or remove them from `vars:`"),
+        );
+    }
+    if let Err(e) = Template::parse(&source).and_then(|t| t.resolve_typed_vars(&vars)) {
+        report.push(
+            Section::Templates,
+            Severity::Warn,
+            subject,
+            format!("Not rendered: {}", e),
+            Some("Give required variables a default, so the template renders without --var"),
+        );
+        return;
+    }
+
+    let profiles = OutputProfiles::from_config(&project.config, out).unwrap_or_default();
+    let ctx = GenContext::new(path.to_path_buf(), out.to_path_buf())
+        .with_vars(vars)
+        .with_config(&project.config)
+        .with_output_profiles(profiles)
+        .dry(true);
+    let mut generator = Generator::new(pipeline, ctx);
+    if let Err(e) = generator.generate_outputs() {
+        let message = format!("{:#}", e);
+        if is_path_error(&message) {
+            report.push(
+                Section::Paths,
+                Severity::Fail,
+                subject,
+                message,
+                Some("Keep `rdf:` files next to the template and `to:` inside its output root"),
+            );
+        } else {
+            report.push(
+                Section::Templates,
+                Severity::Fail,
+                subject,
+                message,
+                Some("Render it with `ggen project gen <template> --dry-run` to see more"),
+            );
+        }
+        return;
+    }
+
+    let mut rendered = 0;
+    for file in &generator.plan.files {
+        let escapes = file
+            .findings
+            .iter()
+            .any(|finding| finding.kind == FindingKind::PathSecurity);
+        if escapes {
+            report.push(
+                Section::Paths,
+                Severity::Fail,
+                &subject,
+                format!(
+                    "Renders {} outside its output directory",
+                    file.path.display()
+                ),
+                Some("Keep `to:` relative and without `..`, or pick an [output] profile"),
+            );
+            continue;
+        }
+        let Some(content) = &file.content else {
+            continue;
+        };
+        let target = out.join(&file.path);
+        let written = match target.parent() {
+            Some(parent) => fs::create_dir_all(parent).and_then(|()| fs::write(&target, content)),
+            None => fs::write(&target, content),
+        };
+        if let Err(e) = written {
+            report.push(
+                Section::Templates,
+                Severity::Fail,
+                &subject,
+                format!("Failed to write {}: {}", file.path.display(), e),
+                None,
+            );
+            return;
+        }
+        rendered += 1;
+    }
+    if generator.plan.files.is_empty() {
+        report.push(
+            Section::Templates,
+            Severity::Warn,
+            subject,
+            "Rendered no files",
+            Some("Its foreach query has no rows; check it against the ontology"),
+        );
+    } else if rendered > 0 {
+        report.push(
+            Section::Templates,
+            Severity::Pass,
+            subject,
+            format!("Rendered {} files", rendered),
+            None,
+        );
+    }
+}
+
+/// The first of `count` lint findings, and how many more there are
+fn and_more(first: &template_lint::LintFinding, count: usize) -> String {
+    match count {
+        1 => first.to_string(),
+        _ => format!("{} (and {} more)", first, count - 1),
+    }
+}
+
+/// Whether a render failed because a path left its directory
+fn is_path_error(message: &str) -> bool {
+    message.contains("Path traversal blocked") || message.contains("leaves the root of output")
+}
+
+/// A pipeline with the loaded `sources` in its graph
+fn pipeline(config: &GgenConfig, sources: &[Source]) -> Result<Pipeline> {
+    let mut pipeline = Pipeline::new()?;
+    for source in sources {
+        match source {
+            Source::File(path) => pipeline.graph.load_path(path)?,
+            Source::Inline(ttl) => pipeline.graph.insert_turtle(ttl)?,
+        }
+    }
+    pipeline.register_prefixes(config.base.as_deref(), &config.prefixes);
+    Ok(pipeline)
+}
+
+/// Send a request to every provider; any HTTP answer counts as reachable
+async fn probe_providers(report: &mut Report, providers: &[ProviderSettings]) {
+    let client = match reqwest::Client::builder().timeout(PROBE_TIMEOUT).build() {
+        Ok(client) => client,
+        Err(e) => {
+            report.push(
+                Section::Providers,
+                Severity::Fail,
+                "providers",
+                format!("Failed to create an HTTP client: {}", e),
+                None,
+            );
+            return;
+        }
+    };
+    for provider in providers {
+        let name = format!("provider '{}'", provider.name);
+        let endpoint = provider.base_url.as_deref().or_else(|| {
+            DEFAULT_ENDPOINTS
+                .iter()
+                .find(|(known, _)| *known == provider.name)
+                .map(|(_, url)| *url)
+        });
+        let Some(endpoint) = endpoint else {
+            report.push(
+                Section::Providers,
+                Severity::Warn,
+                name,
+                "No base_url to contact",
+                Some("Set its base_url to have --online check it"),
+            );
+            continue;
+        };
+        match client.get(endpoint).send().await {
+            Ok(response) => report.push(
+                Section::Providers,
+                Severity::Pass,
+                name,
+                format!(
+                    "Reachable at {} (HTTP {})",
+                    endpoint,
+                    response.status().as_u16()
+                ),
+                None,
+            ),
+            Err(e) => report.push(
+                Section::Providers,
+                Severity::Fail,
+                name,
+                format!("Unreachable at {}: {}", endpoint, e),
+                Some("Check its base_url, the network and any proxy"),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const ONTOLOGY: &str = r#"@prefix app: <http://example.org/app#> .
+@prefix owl: <http://www.w3.org/2002/07/owl#> .
+@prefix rdfs: <http://www.w3.org/2000/01/rdf-schema#> .
+@prefix xsd: <http://www.w3.org/2001/XMLSchema#> .
+
+app:User a owl:Class ; rdfs:label "User" .
+app:email a owl:DatatypeProperty ; rdfs:domain app:User ; rdfs:range xsd:string .
+"#;
+
+    const TEMPLATE: &str = r#"---
+to: "src/model.rs"
+vars:
+  name: { type: string, default: "Model" }
+sparql:
+  classes: "SELECT ?class WHERE { ?class a <http://www.w3.org/2002/07/owl#Class> }"
+---
+pub struct {{ name }};
+// {{ sparql_results.classes | length }} classes
+"#;
+
+    /// A project every check passes, with `files` written over it
+    fn project(files: &[(&str, &str)]) -> TempDir {
+        let dir = TempDir::new().unwrap();
+        let defaults = [
+            ("ggen.toml", "[rdf]\nfiles = [\"ontology.ttl\"]\n"),
+            (
+                "make.toml",
+                "[project]\nname = \"app\"\n\n[lifecycle.build]\ncommand = \"cargo build\"\n",
+            ),
+            ("ontology.ttl", ONTOLOGY),
+            ("templates/model.tmpl", TEMPLATE),
+        ];
+        for (name, content) in defaults.iter().chain(files) {
+            let path = dir.path().join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        dir
+    }
+
+    async fn run(dir: &TempDir) -> Report {
+        SelfTest::new(dir.path()).run().await
+    }
+
+    /// Sections that didn't pass, with their severity
+    fn problems(report: &Report) -> Vec<(Section, Severity)> {
+        Section::ALL
+            .into_iter()
+            .map(|section| (section, report.section_severity(section)))
+            .filter(|(_, severity)| *severity != Severity::Pass)
+            .collect()
+    }
+
+    fn messages(report: &Report, section: Section) -> Vec<&str> {
+        report
+            .section(section)
+            .iter()
+            .filter(|check| check.severity != Severity::Pass)
+            .map(|check| check.message.as_str())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_healthy_project_passes() {
+        let report = run(&project(&[])).await;
+        assert!(problems(&report).is_empty(), "{}", report);
+        assert_eq!(report.exit_code(), 0);
+        assert_eq!(
+            report.section(Section::Templates)[0].message,
+            "Rendered 1 files"
+        );
+        assert_eq!(
+            report.section(Section::Ontology)[0].message,
+            "Loaded 5 triples"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_broken_config_and_lifecycle_are_pinpointed() {
+        let report = run(&project(&[("ggen.toml", "[rdf\nfiles = 1\n")])).await;
+        assert_eq!(
+            problems(&report),
+            [
+                (Section::Config, Severity::Fail),
+                (Section::Providers, Severity::Warn)
+            ],
+            "{}",
+            report
+        );
+        assert_eq!(report.exit_code(), 2);
+
+        let report = run(&project(&[("make.toml", "[lifecycle.build]\n")])).await;
+        assert_eq!(
+            problems(&report),
+            [(Section::Lifecycle, Severity::Fail)],
+            "{}",
+            report
+        );
+        assert!(messages(&report, Section::Lifecycle)[0].contains("missing field `project`"));
+
+        let make = "[project]\nname = \"app\"\n\n[lifecycle.build]\ndescription = \"Build\"\n";
+        let report = run(&project(&[("make.toml", make)])).await;
+        assert_eq!(
+            problems(&report),
+            [(Section::Lifecycle, Severity::Warn)],
+            "{}",
+            report
+        );
+        assert_eq!(report.exit_code(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_broken_ontology_is_pinpointed() {
+        let report = run(&project(&[("ontology.ttl", "app:User a owl:Class .\n")])).await;
+        assert_eq!(
+            problems(&report),
+            [(Section::Ontology, Severity::Fail)],
+            "{}",
+            report
+        );
+        let check = &report.section(Section::Ontology)[0];
+        assert_eq!(check.subject, "ontology.ttl");
+        assert!(check.hint.is_some());
+
+        let lax = "@prefix app: <http://example.org/app#> .\n\
+                   @prefix owl: <http://www.w3.org/2002/07/owl#> .\n\
+                   @prefix rdfs: <http://www.w3.org/2000/01/rdf-schema#> .\n\
+                   app:User a owl:Class .\n\
+                   app:owner rdfs:domain app:Acount .\n";
+        let report = run(&project(&[("ontology.ttl", lax)])).await;
+        assert_eq!(
+            problems(&report),
+            [(Section::Ontology, Severity::Warn)],
+            "{}",
+            report
+        );
+        assert_eq!(
+            messages(&report, Section::Ontology),
+            [
+                "Classes without an rdfs:label: <http://example.org/app#User>",
+                "Domains and ranges declared nowhere: <http://example.org/app#Acount>"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_broken_template_is_pinpointed() {
+        let broken = TEMPLATE.replace("{{ name }}", "{{ name | pascl }}");
+        let report = run(&project(&[("templates/model.tmpl", &broken)])).await;
+        assert_eq!(
+            problems(&report),
+            [(Section::Templates, Severity::Fail)],
+            "{}",
+            report
+        );
+        let check = &report.section(Section::Templates)[0];
+        assert_eq!(check.subject, "templates/model.tmpl");
+        assert_eq!(
+            check.message,
+            "8:22: error[unknown-filter] Unknown filter `pascl`"
+        );
+
+        let required = TEMPLATE.replace("default: \"Model\"", "required: true");
+        let report = run(&project(&[("templates/model.tmpl", &required)])).await;
+        assert_eq!(
+            problems(&report),
+            [(Section::Templates, Severity::Warn)],
+            "{}",
+            report
+        );
+    }
+
+    #[tokio::test]
+    async fn test_escaping_paths_are_pinpointed_and_not_written() {
+        let escaping = TEMPLATE.replace("src/model.rs", "../../escaped.rs");
+        let dir = project(&[("templates/model.tmpl", &escaping)]);
+        let report = run(&dir).await;
+        assert_eq!(
+            problems(&report),
+            [(Section::Paths, Severity::Fail)],
+            "{}",
+            report
+        );
+        assert_eq!(
+            messages(&report, Section::Paths),
+            ["Renders ../../escaped.rs outside its output directory"]
+        );
+        // Neither written nor counted as rendered
+        assert!(report.section(Section::Templates).is_empty());
+
+        let config =
+            "[rdf]\nfiles = [\"ontology.ttl\"]\n\n[output.rust]\nroot = \"../elsewhere\"\n";
+        let report = run(&project(&[("ggen.toml", config)])).await;
+        assert_eq!(
+            problems(&report),
+            [(Section::Paths, Severity::Fail)],
+            "{}",
+            report
+        );
+    }
+
+    #[tokio::test]
+    async fn test_provider_problems_are_pinpointed() {
+        let config = r#"
+[rdf]
+files = ["ontology.ttl"]
+
+[providers.openai]
+model = "gpt-4o"
+api_key = "env:GGEN_SELFTEST_UNSET_KEY"
+
+[providers.ollama]
+model = "llama3.1"
+temperature = 5.0
+"#;
+        let report = run(&project(&[("ggen.toml", config)])).await;
+        assert_eq!(
+            problems(&report),
+            [(Section::Providers, Severity::Fail)],
+            "{}",
+            report
+        );
+        assert_eq!(
+            messages(&report, Section::Providers),
+            ["provider 'ollama': temperature must be between 0 and 2, got 5"]
+        );
+
+        let config = config.replace("temperature = 5.0", "");
+        let report = run(&project(&[("ggen.toml", &config)])).await;
+        assert_eq!(
+            problems(&report),
+            [(Section::Providers, Severity::Warn)],
+            "{}",
+            report
+        );
+        assert_eq!(
+            messages(&report, Section::Providers),
+            ["Its api_key names GGEN_SELFTEST_UNSET_KEY, which is not set"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_online_checks_report_unreachable_providers() {
+        // A port nothing listens on any more
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        drop(listener);
+        let config = format!(
+            "[providers.local]\nmodel = \"llama3.1\"\nbase_url = \"http://127.0.0.1:{}\"\n",
+            port
+        );
+        let dir = project(&[("ggen.toml", &config)]);
+
+        let report = SelfTest::new(dir.path()).run().await;
+        assert_eq!(report.section_severity(Section::Providers), Severity::Pass);
+        let report = SelfTest::new(dir.path()).online(true).run().await;
+        assert_eq!(report.section_severity(Section::Providers), Severity::Fail);
+        assert!(messages(&report, Section::Providers)[0].starts_with("Unreachable at"));
+    }
+
+    #[tokio::test]
+    async fn test_report_as_json() {
+        let report = run(&project(&[("templates/model.tmpl", "{{ missing }}\n")])).await;
+        let json = report.to_json();
+        assert_eq!(json["status"], "fail");
+        assert_eq!(json["exit_code"], 2);
+        let sections: Vec<(&str, &str)> = json["sections"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|s| (s["name"].as_str().unwrap(), s["status"].as_str().unwrap()))
+            .collect();
+        assert_eq!(
+            sections,
+            [
+                ("config", "pass"),
+                ("lifecycle", "pass"),
+                ("ontology", "pass"),
+                ("templates", "fail"),
+                ("paths", "pass"),
+                ("providers", "pass"),
+            ]
+        );
+        let check = &json["sections"][3]["checks"][0];
+        assert_eq!(check["severity"], "fail");
+        assert_eq!(check["subject"], "templates/model.tmpl");
+        assert!(check["hint"].is_string());
+        assert!(report
+            .to_string()
+            .ends_with("Self-test failed: 1 failed, 0 warnings, 5 passed"));
+    }
+}
@@ -0,0 +1,609 @@
+//! SQL tables, indexes and migrations for the domain graph
+//!
+//! [`tables`] turns the fields of each entity into a PostgreSQL table, and
+//! fields and entities can ask for constraints and indexes:
+//!
+//! ```turtle
+//! ex:userEmail ex:name "email" ; ex:datatype xsd:string ; ex:unique true .
+//! ex:userCreatedAt ex:name "createdAt" ; ex:datatype xsd:dateTime ; ex:indexed true .
+//! ex:Order ex:hasIndex ex:orderByUser .
+//! ex:orderByUser ex:fields "userId, total" .
+//! ```
+//!
+//! `ex:unique true` makes the column `UNIQUE` and `ex:indexed true` gives it
+//! an index. An `ex:hasIndex` on the entity declares a composite index over
+//! the listed fields, in order, optionally with an `ex:name` and
+//! `ex:unique true`.
+//!
+//! [`delta`] lists the statements migrating one [`Schema`] to another. The
+//! schema a migration was generated for is kept under `sql_schema` in the
+//! incremental manifest, so the next migration only holds what changed.
+//! Added tables, nullable columns, indexes and unique constraints are
+//! non-destructive; dropped tables and columns, type changes and new
+//! `NOT NULL` or foreign key constraints are destructive, as they lose data or
+//! can fail on existing rows.
+//!
+//! Templates call them as `sql_tables(fields=..., indexes=...)` and
+//! `sql_migration(fields=..., indexes=..., manifest=...)`; see
+//! `database-schema.tmpl` and `migration.tmpl` of the api-endpoint package.
+//! The manifest is reached like the proto field numbers are; see
+//! [`ManifestAccess`].
+
+use anyhow::Result;
+use heck::ToSnakeCase;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use crate::proto_schema::{manifest_entry, set_manifest_entry, ManifestAccess, DEFAULT_MANIFEST};
+use crate::register::{lexical_form, sparql_binding};
+use crate::seed_data::local_name;
+
+/// Key of the recorded schema in the manifest
+pub const MANIFEST_KEY: &str = "sql_schema";
+
+/// A field of an entity, as declared in the graph
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ColumnSpec {
+    /// Local name of the entity, like `User`
+    pub entity: String,
+    pub name: String,
+    /// XSD datatype local name; `None` for a relationship
+    pub datatype: Option<String>,
+    pub required: bool,
+    /// Local name of the entity the field points at
+    pub references: Option<String>,
+    /// `ex:cardinality "many"`: a to-many relationship, which has no column
+    pub many: bool,
+    pub unique: bool,
+    pub indexed: bool,
+}
+
+impl ColumnSpec {
+    /// Read a field from a SPARQL row with the columns `entity` and `field`
+    /// and optionally `datatype`, `required`, `references`, `cardinality`,
+    /// `unique` and `indexed`
+    pub fn from_row(row: &Value) -> Option<Self> {
+        let text = |column: &str| {
+            sparql_binding(row, column)
+                .and_then(Value::as_str)
+                .map(|term| local_name(lexical_form(term)).to_string())
+        };
+        let flag = |column: &str| text(column).is_some_and(|value| value == "true");
+        Some(Self {
+            entity: text("entity")?,
+            name: text("field")?,
+            datatype: text("datatype"),
+            required: flag("required"),
+            references: text("references"),
+            many: text("cardinality").is_some_and(|value| value == "many"),
+            unique: flag("unique"),
+            indexed: flag("indexed"),
+        })
+    }
+}
+
+/// An `ex:hasIndex` of an entity, as declared in the graph
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IndexSpec {
+    pub entity: String,
+    /// Field names, in index order
+    pub fields: Vec<String>,
+    pub name: Option<String>,
+    pub unique: bool,
+}
+
+impl IndexSpec {
+    /// Read an index from a SPARQL row with the columns `entity` and `fields`
+    /// (comma-separated field names) and optionally `name` and `unique`
+    pub fn from_row(row: &Value) -> Option<Self> {
+        let literal = |column: &str| {
+            sparql_binding(row, column)
+                .and_then(Value::as_str)
+                .map(|term| lexical_form(term).to_string())
+        };
+        let fields: Vec<String> = literal("fields")?
+            .split(',')
+            .map(str::trim)
+            .filter(|field| !field.is_empty())
+            .map(str::to_string)
+            .collect();
+        if fields.is_empty() {
+            return None;
+        }
+        Some(Self {
+            entity: local_name(&literal("entity")?).to_string(),
+            fields,
+            name: literal("name"),
+            unique: literal("unique").is_some_and(|value| value == "true"),
+        })
+    }
+}
+
+/// PostgreSQL type for an XSD datatype local name
+pub fn sql_type(datatype: &str) -> &'static str {
+    match datatype {
+        "boolean" => "BOOLEAN",
+        "int" | "short" | "byte" | "unsignedShort" | "unsignedByte" => "INTEGER",
+        "integer" | "long" | "unsignedInt" | "unsignedLong" | "negativeInteger"
+        | "nonPositiveInteger" | "nonNegativeInteger" | "positiveInteger" => "BIGINT",
+        "decimal" => "NUMERIC",
+        "double" => "DOUBLE PRECISION",
+        "float" => "REAL",
+        "dateTime" => "TIMESTAMPTZ",
+        "date" => "DATE",
+        "base64Binary" | "hexBinary" => "BYTEA",
+        _ => "TEXT",
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Column {
+    pub sql_type: String,
+    pub not_null: bool,
+    pub unique: bool,
+    /// Table whose `id` the column holds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub references: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Index {
+    pub columns: Vec<String>,
+    pub unique: bool,
+}
+
+/// A table; `id UUID PRIMARY KEY` is implied
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Table {
+    pub columns: BTreeMap<String, Column>,
+    pub indexes: BTreeMap<String, Index>,
+}
+
+/// Tables by name
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Schema(pub BTreeMap<String, Table>);
+
+impl Schema {
+    /// Read the schema recorded in a manifest; empty when the manifest or its
+    /// `sql_schema` entry doesn't exist yet
+    pub fn load(manifest: &Path) -> Result<Self> {
+        match manifest_entry(manifest, MANIFEST_KEY)? {
+            Some(schema) => Ok(serde_json::from_value(schema)?),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Record the schema in a manifest, keeping its other entries
+    pub fn save(&self, manifest: &Path) -> Result<()> {
+        set_manifest_entry(manifest, MANIFEST_KEY, serde_json::to_value(self)?)
+    }
+
+    /// Table names with every referenced table before the tables pointing at
+    /// it; tables in a reference cycle keep their alphabetical order
+    pub fn creation_order(&self) -> Vec<&str> {
+        let mut order: Vec<&str> = Vec::new();
+        let mut pending: Vec<&str> = self.0.keys().map(String::as_str).collect();
+        while !pending.is_empty() {
+            let ready = pending.iter().position(|name| {
+                self.0[*name]
+                    .columns
+                    .values()
+                    .all(|column| match column.references.as_deref() {
+                        Some(target) => {
+                            target == *name
+                                || order.contains(&target)
+                                || !self.0.contains_key(target)
+                        }
+                        None => true,
+                    })
+            });
+            order.push(pending.remove(ready.unwrap_or(0)));
+        }
+        order
+    }
+}
+
+/// One table per entity, named after it in snake case, with the columns and
+/// indexes declared in the graph
+pub fn tables(columns: &[ColumnSpec], indexes: &[IndexSpec]) -> Schema {
+    let mut schema = Schema::default();
+    for spec in columns.iter().filter(|spec| !spec.many) {
+        let table_name = spec.entity.to_snake_case();
+        let name = spec.name.to_snake_case();
+        if name == "id" {
+            continue;
+        }
+        let table = schema.0.entry(table_name.clone()).or_default();
+        let (sql_type, references) = match &spec.references {
+            Some(target) => ("UUID", Some(target.to_snake_case())),
+            None => (sql_type(spec.datatype.as_deref().unwrap_or("string")), None),
+        };
+        table.columns.insert(
+            name.clone(),
+            Column {
+                sql_type: sql_type.to_string(),
+                not_null: spec.required,
+                unique: spec.unique,
+                references,
+            },
+        );
+        if spec.indexed {
+            table.indexes.insert(
+                format!("{}_{}_idx", table_name, name),
+                Index {
+                    columns: vec![name],
+                    unique: false,
+                },
+            );
+        }
+    }
+    for spec in indexes {
+        let table_name = spec.entity.to_snake_case();
+        let columns: Vec<String> = spec.fields.iter().map(|f| f.to_snake_case()).collect();
+        let name = spec
+            .name
+            .clone()
+            .unwrap_or_else(|| format!("{}_{}_idx", table_name, columns.join("_")));
+        schema.0.entry(table_name).or_default().indexes.insert(
+            name,
+            Index {
+                columns,
+                unique: spec.unique,
+            },
+        );
+    }
+    schema
+}
+
+fn quote(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+fn column_definition(name: &str, column: &Column) -> String {
+    let mut definition = format!("{} {}", quote(name), column.sql_type);
+    if column.not_null {
+        definition.push_str(" NOT NULL");
+    }
+    if column.unique {
+        definition.push_str(" UNIQUE");
+    }
+    if let Some(target) = &column.references {
+        definition.push_str(&format!(" REFERENCES {} (\"id\")", quote(target)));
+    }
+    definition
+}
+
+/// `CREATE TABLE` statement for `table`, without its indexes
+pub fn create_table(name: &str, table: &Table) -> String {
+    let mut lines = vec!["    \"id\" UUID PRIMARY KEY".to_string()];
+    lines.extend(
+        table
+            .columns
+            .iter()
+            .map(|(column, spec)| format!("    {}", column_definition(column, spec))),
+    );
+    format!(
+        "CREATE TABLE IF NOT EXISTS {} (\n{}\n);",
+        quote(name),
+        lines.join(",\n")
+    )
+}
+
+/// `CREATE INDEX` statement for index `name` of `table`
+pub fn create_index(table: &str, name: &str, index: &Index) -> String {
+    let columns: Vec<String> = index.columns.iter().map(|c| quote(c)).collect();
+    format!(
+        "CREATE {}INDEX IF NOT EXISTS {} ON {} ({});",
+        if index.unique { "UNIQUE " } else { "" },
+        quote(name),
+        quote(table),
+        columns.join(", ")
+    )
+}
+
+/// A migration statement
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Change {
+    pub sql: String,
+    /// Loses data or can fail on existing rows
+    pub destructive: bool,
+}
+
+impl Change {
+    fn safe(sql: String) -> Self {
+        Self {
+            sql,
+            destructive: false,
+        }
+    }
+
+    fn destructive(sql: String) -> Self {
+        Self {
+            sql,
+            destructive: true,
+        }
+    }
+}
+
+/// Statements migrating a database from `previous` to `current`
+pub fn delta(previous: &Schema, current: &Schema) -> Vec<Change> {
+    let mut changes = Vec::new();
+    for name in current.creation_order() {
+        let table = &current.0[name];
+        let Some(old) = previous.0.get(name) else {
+            changes.push(Change::safe(create_table(name, table)));
+            for (index, spec) in &table.indexes {
+                changes.push(Change::safe(create_index(name, index, spec)));
+            }
+            continue;
+        };
+        let alter = format!("ALTER TABLE {}", quote(name));
+
+        for (column, spec) in &table.columns {
+            let Some(was) = old.columns.get(column) else {
+                let sql = format!("{} ADD COLUMN {};", alter, column_definition(column, spec));
+                // A NOT NULL column without a default can't be added to a
+                // table with rows
+                changes.push(if spec.not_null {
+                    Change::destructive(sql)
+                } else {
+                    Change::safe(sql)
+                });
+                continue;
+            };
+            let quoted = quote(column);
+            if was.sql_type != spec.sql_type {
+                changes.push(Change::destructive(format!(
+                    "{} ALTER COLUMN {} TYPE {} USING {}::{};",
+                    alter, quoted, spec.sql_type, quoted, spec.sql_type
+                )));
+            }
+            match (was.not_null, spec.not_null) {
+                (false, true) => changes.push(Change::destructive(format!(
+                    "{} ALTER COLUMN {} SET NOT NULL;",
+                    alter, quoted
+                ))),
+                (true, false) => changes.push(Change::safe(format!(
+                    "{} ALTER COLUMN {} DROP NOT NULL;",
+                    alter, quoted
+                ))),
+                _ => {}
+            }
+            // Constraint names follow PostgreSQL's defaults for inline ones
+            let key = quote(&format!("{}_{}_key", name, column));
+            match (was.unique, spec.unique) {
+                (false, true) => changes.push(Change::safe(format!(
+                    "{} ADD CONSTRAINT {} UNIQUE ({});",
+                    alter, key, quoted
+                ))),
+                (true, false) => changes.push(Change::safe(format!(
+                    "{} DROP CONSTRAINT IF EXISTS {};",
+                    alter, key
+                ))),
+                _ => {}
+            }
+            if was.references != spec.references {
+                let fkey = quote(&format!("{}_{}_fkey", name, column));
+                if was.references.is_some() {
+                    changes.push(Change::safe(format!(
+                        "{} DROP CONSTRAINT IF EXISTS {};",
+                        alter, fkey
+                    )));
+                }
+                if let Some(target) = &spec.references {
+                    changes.push(Change::destructive(format!(
+                        "{} ADD CONSTRAINT {} FOREIGN KEY ({}) REFERENCES {} (\"id\");",
+                        alter,
+                        fkey,
+                        quoted,
+                        quote(target)
+                    )));
+                }
+            }
+        }
+        for column in old.columns.keys() {
+            if !table.columns.contains_key(column) {
+                changes.push(Change::destructive(format!(
+                    "{} DROP COLUMN {};",
+                    alter,
+                    quote(column)
+                )));
+            }
+        }
+
+        for (index, was) in &old.indexes {
+            if table.indexes.get(index) != Some(was) {
+                changes.push(Change::safe(format!(
+                    "DROP INDEX IF EXISTS {};",
+                    quote(index)
+                )));
+            }
+        }
+        for (index, spec) in &table.indexes {
+            if old.indexes.get(index) != Some(spec) {
+                changes.push(Change::safe(create_index(name, index, spec)));
+            }
+        }
+    }
+    for name in previous.creation_order().into_iter().rev() {
+        if !current.0.contains_key(name) {
+            changes.push(Change::destructive(format!("DROP TABLE {};", quote(name))));
+        }
+    }
+    changes
+}
+
+fn specs(args: &HashMap<String, Value>, function: &str) -> tera::Result<Schema> {
+    let rows = args
+        .get("fields")
+        .and_then(Value::as_array)
+        .ok_or_else(|| tera::Error::msg(format!("{}: fields parameter required", function)))?;
+    let columns: Vec<ColumnSpec> = rows.iter().filter_map(ColumnSpec::from_row).collect();
+    let indexes: Vec<IndexSpec> = args
+        .get("indexes")
+        .and_then(Value::as_array)
+        .map(|rows| rows.iter().filter_map(IndexSpec::from_row).collect())
+        .unwrap_or_default();
+    Ok(tables(&columns, &indexes))
+}
+
+/// `sql_tables(fields=..., indexes=...)`: the tables in creation order, each
+/// as `{name, create, indexes}` with `create` and `indexes` holding SQL
+#[derive(Clone)]
+pub struct SqlTablesFn;
+
+impl tera::Function for SqlTablesFn {
+    fn call(&self, args: &HashMap<String, Value>) -> tera::Result<Value> {
+        let schema = specs(args, "sql_tables")?;
+        let tables: Vec<Value> = schema
+            .creation_order()
+            .into_iter()
+            .map(|name| {
+                let table = &schema.0[name];
+                let indexes: Vec<String> = table
+                    .indexes
+                    .iter()
+                    .map(|(index, spec)| create_index(name, index, spec))
+                    .collect();
+                json!({"name": name, "create": create_table(name, table), "indexes": indexes})
+            })
+            .collect();
+        Ok(json!(tables))
+    }
+}
+
+/// `sql_migration(fields=..., indexes=..., manifest=...)`: the [`Change`]s
+/// from the schema recorded in the manifest to the graph's, which the
+/// manifest then records
+#[derive(Clone, Default)]
+pub struct SqlMigrationFn(pub ManifestAccess);
+
+impl tera::Function for SqlMigrationFn {
+    fn call(&self, args: &HashMap<String, Value>) -> tera::Result<Value> {
+        let current = specs(args, "sql_migration")?;
+        let manifest = self.0.path(
+            args.get("manifest")
+                .and_then(Value::as_str)
+                .unwrap_or(DEFAULT_MANIFEST),
+        );
+        let previous = Schema::load(&manifest)
+            .map_err(|e| tera::Error::msg(format!("sql_migration: {:#}", e)))?;
+        let changes = delta(&previous, &current);
+        if self.0.save {
+            current
+                .save(&manifest)
+                .map_err(|e| tera::Error::msg(format!("sql_migration: {:#}", e)))?;
+        }
+        Ok(json!(changes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn column(entity: &str, name: &str, datatype: &str, required: bool) -> ColumnSpec {
+        ColumnSpec {
+            entity: entity.to_string(),
+            name: name.to_string(),
+            datatype: Some(datatype.to_string()),
+            required,
+            ..ColumnSpec::default()
+        }
+    }
+
+    fn shop(annotated: bool) -> Schema {
+        let columns = vec![
+            ColumnSpec {
+                unique: annotated,
+                ..column("User", "email", "string", false)
+            },
+            column("User", "name", "string", true),
+            ColumnSpec {
+                references: Some("User".to_string()),
+                datatype: None,
+                ..column("Order", "userId", "", true)
+            },
+            column("Order", "total", "decimal", true),
+            ColumnSpec {
+                indexed: annotated,
+                ..column("Order", "createdAt", "dateTime", false)
+            },
+        ];
+        let mut indexes = vec![];
+        if annotated {
+            indexes.push(IndexSpec {
+                entity: "Order".to_string(),
+                fields: vec!["userId".to_string(), "total".to_string()],
+                ..IndexSpec::default()
+            });
+        }
+        tables(&columns, &indexes)
+    }
+
+    #[test]
+    fn test_tables_reference_order_and_sql() {
+        let schema = shop(true);
+        assert_eq!(schema.creation_order(), vec!["user", "order"]);
+        assert_eq!(
+            create_table("user", &schema.0["user"]),
+            "CREATE TABLE IF NOT EXISTS \"user\" (\n    \"id\" UUID PRIMARY KEY,\n    \
+             \"email\" TEXT UNIQUE,\n    \"name\" TEXT NOT NULL\n);"
+        );
+        let order = &schema.0["order"];
+        assert_eq!(order.columns["user_id"].references.as_deref(), Some("user"));
+        assert_eq!(
+            create_index("order", "order_user_id_total_idx", &order.indexes["order_user_id_total_idx"]),
+            "CREATE INDEX IF NOT EXISTS \"order_user_id_total_idx\" ON \"order\" (\"user_id\", \"total\");"
+        );
+        assert!(order.indexes.contains_key("order_created_at_idx"));
+    }
+
+    #[test]
+    fn test_delta_classifies_changes() {
+        // Annotations only add constraints and indexes
+        let changes = delta(&shop(false), &shop(true));
+        assert_eq!(changes.len(), 3);
+        assert!(changes.iter().all(|change| !change.destructive));
+        assert_eq!(
+            changes[0].sql,
+            "ALTER TABLE \"user\" ADD CONSTRAINT \"user_email_key\" UNIQUE (\"email\");"
+        );
+
+        // Dropping them is safe too; dropping a column or table isn't
+        let mut smaller = shop(false);
+        smaller.0.get_mut("order").unwrap().columns.remove("total");
+        smaller.0.remove("user");
+        let changes = delta(&shop(true), &smaller);
+        let destructive: Vec<&str> = changes
+            .iter()
+            .filter(|change| change.destructive)
+            .map(|change| change.sql.as_str())
+            .collect();
+        assert_eq!(
+            destructive,
+            vec![
+                "ALTER TABLE \"order\" DROP COLUMN \"total\";",
+                "DROP TABLE \"user\";"
+            ]
+        );
+        assert!(delta(&smaller, &smaller).is_empty());
+    }
+
+    #[test]
+    fn test_schema_round_trips_through_the_manifest() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let manifest = dir.path().join(".ggen/manifest.json");
+        crate::proto_schema::FieldNumbers::default().save(&manifest)?;
+
+        assert_eq!(Schema::load(&manifest)?, Schema::default());
+        shop(true).save(&manifest)?;
+        assert_eq!(Schema::load(&manifest)?, shop(true));
+        let document: Value = serde_json::from_str(&std::fs::read_to_string(&manifest)?)?;
+        assert!(document.get(crate::proto_schema::MANIFEST_KEY).is_some());
+        Ok(())
+    }
+}
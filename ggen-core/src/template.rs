@@ -14,9 +14,11 @@
 //!
 //! ## Frontmatter Fields
 //! - `to/from`: Output/input file paths
-//! - `vars`: Template variables (any YAML type)
+//! - `vars`: Template variables (any YAML type, or typed declarations like
+//!   `replicas: { type: int, default: 3 }`, see [`crate::template_vars`])
 //! - `rdf_inline/rdf`: Turtle triples (inline/files)
 //! - `sparql`: Named queries → `sparql_results.<name>`
+//! - `foreach`: Name of a `sparql` query; one file per row, see [`crate::scope`]
 //! - `inject/before/after`: File modification markers
 //!
 //! ## SPARQL Results Access
@@ -24,21 +26,29 @@
 //! Count: {{ sparql_results.people | length }}
 //! First: {{ sparql_first(results=sparql_results.people, column="name") }}
 //! All: {{ sparql_values(results=sparql_results.people, column="name") }}
+//! {% for team, members in sparql_group_by(results=sparql_results.people, key="team") %}
+//! {{ team }}: {% for m in sparql_order_by(results=members, key="name") %}{{ m["?name"] }} {% endfor %}
+//! {% endfor %}
 //! ```
 
 use anyhow::Result;
 use gray_matter::{engine::YAML, Matter, ParsedEntity};
+use oxigraph::io::RdfFormat;
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 use tera::{Context, Tera};
 
-use crate::graph::Graph;
+use crate::graph::{Graph, LoadMode};
+use crate::path_guard;
 use crate::preprocessor::{FreezePolicy, FreezeStage, PrepCtx, Preprocessor};
+use crate::query_explain::{self, QueryExplanation};
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct Frontmatter {
     // Hygen core
     pub to: Option<String>,
+    // Output profile `to` is relative to; see crate::output
+    pub target: Option<String>,
     pub from: Option<String>,
     #[serde(default)]
     pub force: bool,
@@ -75,12 +85,21 @@ pub struct Frontmatter {
     pub rdf: Vec<String>, // treat as inline TTL in prototype
     #[serde(default, deserialize_with = "sparql_map")]
     pub sparql: BTreeMap<String, String>,
+    // Query rendering one file per row; see crate::scope
+    #[serde(default)]
+    pub foreach: Option<String>,
 
     // Optional template variables defined in frontmatter
     // Accepts maps, arrays, or single values for maximum flexibility
     #[serde(default, deserialize_with = "deserialize_flexible_vars")]
     pub vars: BTreeMap<String, serde_yaml::Value>,
 
+    // Output permissions; see crate::file_mode
+    #[serde(default, deserialize_with = "octal_mode")]
+    pub mode: Option<String>,
+    #[serde(default)]
+    pub executable: bool,
+
     // Safety and idempotency
     #[serde(default)]
     pub backup: Option<bool>,
@@ -109,6 +128,8 @@ pub struct Template {
     raw_frontmatter: serde_yaml::Value,
     pub front: Frontmatter, // populated after render_frontmatter()
     pub body: String,
+    /// Set to `Some` to have process_graph() explain each query it runs
+    pub query_explanations: Option<Vec<QueryExplanation>>,
 }
 
 impl Template {
@@ -121,6 +142,7 @@ impl Template {
             raw_frontmatter,
             front: Frontmatter::default(),
             body: content,
+            query_explanations: None,
         })
     }
 
@@ -171,11 +193,39 @@ impl Template {
         Self::parse(&processed)
     }
 
+    /// Values of the typed `vars` declarations, coerced from `provided` or
+    /// defaulted. Fails on a value of the wrong type or a missing required
+    /// var, so call it before rendering.
+    pub fn resolve_typed_vars(
+        &self, provided: &BTreeMap<String, String>,
+    ) -> Result<BTreeMap<String, serde_json::Value>> {
+        match self.raw_frontmatter.get("vars") {
+            Some(serde_yaml::Value::Mapping(vars)) => crate::template_vars::resolve(vars, provided),
+            _ => Ok(BTreeMap::new()),
+        }
+    }
+
+    /// Whether the frontmatter sets `foreach`
+    pub fn is_foreach(&self) -> bool {
+        self.raw_frontmatter.get("foreach").is_some()
+    }
+
     /// Render frontmatter through Tera once to resolve {{ }} in YAML.
+    ///
+    /// The `to` of a `foreach` template names a file per row, so it is kept
+    /// as written and rendered with each row.
     pub fn render_frontmatter(&mut self, tera: &mut Tera, vars: &Context) -> Result<()> {
-        let yaml_src = serde_yaml::to_string(&self.raw_frontmatter)?;
+        let mut raw = self.raw_frontmatter.clone();
+        let per_row_to = match raw.as_mapping_mut() {
+            Some(map) if map.contains_key("foreach") => map.remove("to"),
+            _ => None,
+        };
+        let yaml_src = serde_yaml::to_string(&raw)?;
         let rendered_yaml = tera.render_str(&yaml_src, vars)?;
         self.front = serde_yaml::from_str::<Frontmatter>(&rendered_yaml)?;
+        if let Some(to) = per_row_to {
+            self.front.to = serde_yaml::from_value(to)?;
+        }
         Ok(())
     }
 
@@ -232,25 +282,36 @@ impl Template {
                 )
             })?;
 
-            if !canonical_rdf.starts_with(&canonical_template) {
+            if !path_guard::is_within(&canonical_rdf, &canonical_template) {
                 return Err(anyhow::anyhow!(
                     "Path traversal blocked: '{}' is outside template directory",
                     rendered_path
                 ));
             }
 
-            if let Ok(ttl_content) = std::fs::read_to_string(&rdf_path) {
-                let final_ttl = if prolog.is_empty() {
-                    ttl_content
-                } else {
-                    format!("{prolog}\n{ttl_content}")
-                };
-                graph.insert_turtle(&final_ttl)?;
-            }
+            graph
+                .load_file(
+                    &rdf_path,
+                    RdfFormat::Turtle,
+                    &prolog,
+                    LoadMode::from_env(),
+                    &mut |_| {},
+                )
+                .map_err(|e| {
+                    anyhow::anyhow!("Failed to load RDF file '{}': {}", rendered_path, e)
+                })?;
         }
 
+        // Queries as written, for the variables an explanation reports
+        let raw_sparql = if self.query_explanations.is_some() {
+            self.raw_sparql()
+        } else {
+            BTreeMap::new()
+        };
+
         // Execute SPARQL (prepend PREFIX/BASE prolog) and capture results
         for (name, q) in &self.front.sparql {
+            let started = std::time::Instant::now();
             let q_rendered = tera.render_str(q, vars)?;
             let final_q = if prolog.is_empty() {
                 q_rendered
@@ -281,6 +342,23 @@ impl Template {
                 oxigraph::sparql::QueryResults::Graph(_) => serde_json::Value::Array(Vec::new()), // Graph results not supported in templates
             };
 
+            if let Some(explanations) = &mut self.query_explanations {
+                let rows = match &json_result {
+                    serde_json::Value::Array(rows) => rows.len(),
+                    other => usize::from(other.as_bool() == Some(true)),
+                };
+                let source = raw_sparql.get(name).unwrap_or(q);
+                explanations.push(query_explain::explain(
+                    graph,
+                    template_path,
+                    name,
+                    &final_q,
+                    query_explain::query_params(source, vars),
+                    rows,
+                    started.elapsed(),
+                )?);
+            }
+
             // Store result in frontmatter for template access
             self.front.sparql_results.insert(name.clone(), json_result);
         }
@@ -288,6 +366,18 @@ impl Template {
         Ok(())
     }
 
+    /// Frontmatter queries before `{{ }}` is rendered
+    fn raw_sparql(&self) -> BTreeMap<String, String> {
+        #[derive(Deserialize)]
+        struct RawSparql {
+            #[serde(default, deserialize_with = "sparql_map")]
+            sparql: BTreeMap<String, String>,
+        }
+        serde_yaml::from_value::<RawSparql>(self.raw_frontmatter.clone())
+            .map(|raw| raw.sparql)
+            .unwrap_or_default()
+    }
+
     /// Render template body with Tera.
     /// If `from:` is specified in frontmatter, read that file and use as body source.
     pub fn render(&self, tera: &mut Tera, vars: &Context) -> Result<String> {
@@ -322,6 +412,25 @@ impl Template {
 
 /* ---------------- helpers ---------------- */
 
+// Accept "mode: \"0755\"" or an unquoted "mode: 0755", which YAML reads as
+// the number 755; either way the digits are octal
+fn octal_mode<'de, D>(de: D) -> Result<Option<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    use serde::de::Error as DeError;
+
+    match Option::<serde_yaml::Value>::deserialize(de)? {
+        None | Some(serde_yaml::Value::Null) => Ok(None),
+        Some(serde_yaml::Value::String(mode)) => Ok(Some(mode)),
+        Some(serde_yaml::Value::Number(mode)) => Ok(Some(mode.to_string())),
+        Some(other) => Err(D::Error::custom(format!(
+            "expected an octal file mode such as \"0644\", got {:?}",
+            other
+        ))),
+    }
+}
+
 // Accept either "rdf: <string>" or "rdf: [<string>, ...]"
 fn string_or_seq<'de, D>(de: D) -> Result<Vec<String>, D::Error>
 where
@@ -704,6 +813,484 @@ Ages: {{ sparql_values(results=sparql_results.ages, column="age") }}"#;
         Ok(())
     }
 
+    /* ---------- marketplace templates ---------- */
+
+    const API_GRAPH: &str = r#"
+@prefix ex: <http://example.org/api/> .
+@prefix xsd: <http://www.w3.org/2001/XMLSchema#> .
+ex:getUser a ex:APIEndpoint ; ex:api "users" ; ex:path "/users/:id" ; ex:method "GET" .
+ex:createUser a ex:APIEndpoint ; ex:api "users" ; ex:path "/users" ; ex:method "POST" .
+ex:listUsers a ex:APIEndpoint ; ex:api "users" ; ex:path "/users" ; ex:method "GET" .
+ex:updateUser a ex:APIEndpoint ; ex:api "users" ; ex:path "/users/:id" ; ex:method "PUT" .
+ex:deleteUser a ex:APIEndpoint ; ex:api "users" ; ex:path "/users/:id" ; ex:method "DELETE" .
+ex:listOrders a ex:APIEndpoint ; ex:api "orders" ; ex:path "/orders" ; ex:method "GET" .
+ex:health a ex:APIEndpoint ; ex:path "/health" ; ex:method "GET" .
+ex:getUser ex:entity ex:User . ex:createUser ex:entity ex:User . ex:listUsers ex:entity ex:User .
+ex:updateUser ex:entity ex:User . ex:deleteUser ex:entity ex:User .
+ex:listOrders ex:entity ex:Order .
+ex:User a ex:Entity ; ex:hasField ex:userName, ex:userEmail, ex:userOrders .
+ex:userName ex:name "name" ; ex:datatype xsd:string ; ex:min 1 ; ex:max 80 ; ex:required true .
+ex:userEmail ex:name "email" ; ex:datatype xsd:string ; ex:format "email" .
+ex:userOrders ex:name "orders" ; ex:references ex:Order ; ex:cardinality "many" .
+ex:Order a ex:Entity ; ex:hasField ex:orderTotal, ex:orderUser .
+ex:orderTotal ex:name "total" ; ex:datatype xsd:decimal ; ex:min 1 ; ex:max 500 ; ex:required true .
+ex:orderUser ex:name "userId" ; ex:datatype xsd:string ; ex:references ex:User ; ex:required true .
+"#;
+
+    /// Renders `template` of the api-endpoint package against `API_GRAPH`
+    fn render_api_package(template: &str, vars: &Context) -> Result<String> {
+        render_api_package_with(template, "", vars)
+    }
+
+    /// Renders like [`render_api_package`], with `extra` triples added to
+    /// the graph
+    fn render_api_package_with(template: &str, extra: &str, vars: &Context) -> Result<String> {
+        render_api_package_into(mk_tera(), template, extra, vars)
+    }
+
+    /// Renders like [`render_api_package_with`], keeping the incremental
+    /// manifest under `root`
+    fn render_api_package_saving(
+        root: &Path, template: &str, extra: &str, vars: &Context,
+    ) -> Result<String> {
+        let mut tera = mk_tera();
+        crate::register::register_manifest_helpers(
+            &mut tera,
+            crate::proto_schema::ManifestAccess::saving(root),
+        );
+        render_api_package_into(tera, template, extra, vars)
+    }
+
+    fn render_api_package_into(
+        mut tera: Tera, template: &str, extra: &str, vars: &Context,
+    ) -> Result<String> {
+        let template_path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../marketplace/packages/api-endpoint")
+            .join(template);
+        let mut tmpl = Template::parse(&std::fs::read_to_string(&template_path)?)?;
+        let mut graph = Graph::new()?;
+        graph.insert_turtle(API_GRAPH)?;
+        if !extra.is_empty() {
+            graph.insert_turtle(extra)?;
+        }
+        tmpl.process_graph(&mut graph, &mut tera, vars, &template_path)?;
+        tmpl.render(&mut tera, vars)
+    }
+
+    /// Renders `template` like [`render_api_package`] and compares it with
+    /// `snapshot`. Run with `UPDATE_SNAPSHOTS=1` to accept a changed rendering.
+    fn assert_api_package_snapshot(template: &str, snapshot: &str, vars: &Context) -> Result<()> {
+        let snapshot_path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../marketplace/packages/api-endpoint/snapshots")
+            .join(snapshot);
+        let rendered = format!("{}\n", render_api_package(template, vars)?.trim());
+
+        if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+            std::fs::write(&snapshot_path, &rendered)?;
+        }
+        let snapshot = std::fs::read_to_string(&snapshot_path)?;
+        assert_eq!(
+            rendered,
+            snapshot,
+            "{} changed; rerun with UPDATE_SNAPSHOTS=1 to accept",
+            snapshot_path.display()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn api_endpoint_template_matches_snapshot() -> Result<()> {
+        let vars = ctx(&[("name", "users"), ("description", "REST API endpoints")]);
+        assert_api_package_snapshot("api-endpoint.tmpl", "users.rs", &vars)
+    }
+
+    /// One integration test per `users` endpoint of the graph; the snapshot
+    /// compiles and passes against `snapshots/users.rs`
+    #[test]
+    fn api_tests_template_matches_snapshot() -> Result<()> {
+        let vars = ctx(&[("name", "users"), ("crate_name", "app")]);
+        assert_api_package_snapshot("tests.tmpl", "users_tests.rs", &vars)
+    }
+
+    /// Every `example` and `examples` value anywhere in an OpenAPI document
+    fn openapi_examples(value: &serde_json::Value, found: &mut Vec<serde_json::Value>) {
+        match value {
+            serde_json::Value::Object(map) => {
+                for (key, value) in map {
+                    match key.as_str() {
+                        "example" => found.push(value.clone()),
+                        "examples" => found.extend(
+                            value
+                                .as_object()
+                                .into_iter()
+                                .flat_map(|examples| examples.values())
+                                .flat_map(|example| example["value"].as_array().cloned())
+                                .flatten(),
+                        ),
+                        _ => openapi_examples(value, found),
+                    }
+                }
+            }
+            serde_json::Value::Array(items) => {
+                items.iter().for_each(|item| openapi_examples(item, found));
+            }
+            _ => {}
+        }
+    }
+
+    #[test]
+    fn api_openapi_template_has_consistent_examples() -> Result<()> {
+        let vars = ctx(&[("title", "Shop"), ("version", "1.0.0")]);
+        let doc: serde_json::Value =
+            serde_json::from_str(&render_api_package("openapi.tmpl", &vars)?)?;
+
+        assert_eq!(doc["openapi"], "3.0.3");
+        let schemas = &doc["components"]["schemas"];
+        for path in doc["paths"].as_object().unwrap().values() {
+            for operation in path.as_object().unwrap().values() {
+                let text = operation.to_string();
+                for reference in text.split("#/components/schemas/").skip(1) {
+                    let name = reference.split('"').next().unwrap();
+                    assert!(schemas.get(name).is_some(), "dangling $ref to {}", name);
+                }
+            }
+        }
+        let list = &doc["paths"]["/users"]["get"]["responses"]["200"]["content"];
+        assert_eq!(
+            list["application/json"]["examples"]["page"]["value"]
+                .as_array()
+                .unwrap()
+                .len(),
+            2
+        );
+        let created = &doc["paths"]["/users"]["post"]["responses"]["201"]["content"];
+        assert_eq!(
+            created["application/json"]["example"],
+            schemas["User"]["example"]
+        );
+
+        let mut examples = Vec::new();
+        openapi_examples(&doc, &mut examples);
+        let users: Vec<_> = examples
+            .iter()
+            .filter(|e| e.get("email").is_some())
+            .collect();
+        let orders: Vec<_> = examples
+            .iter()
+            .filter(|e| e.get("userId").is_some())
+            .collect();
+        assert!(!users.is_empty() && !orders.is_empty());
+        for user in &users {
+            let (local, domain) = user["email"].as_str().unwrap().split_once('@').unwrap();
+            assert!(!local.is_empty() && domain == "example.com");
+        }
+        for order in &orders {
+            let total = order["total"].as_f64().unwrap();
+            assert!(
+                (1.0..=500.0).contains(&total),
+                "total {} out of range",
+                total
+            );
+            assert!(
+                users.iter().any(|user| user["id"] == order["userId"]),
+                "order {} references an unknown user",
+                order
+            );
+        }
+
+        // Same seed, same document
+        assert_eq!(
+            render_api_package("openapi.tmpl", &vars)?,
+            render_api_package("openapi.tmpl", &vars)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn api_openapi_template_examples_can_be_disabled() -> Result<()> {
+        let vars = ctx(&[
+            ("title", "Shop"),
+            ("version", "1.0.0"),
+            ("openapi_examples", "false"),
+        ]);
+        let doc: serde_json::Value =
+            serde_json::from_str(&render_api_package("openapi.tmpl", &vars)?)?;
+
+        let mut examples = Vec::new();
+        openapi_examples(&doc, &mut examples);
+        assert!(examples.is_empty());
+        assert!(doc["components"]["schemas"]["Order"]["properties"]["userId"].is_object());
+        Ok(())
+    }
+
+    /// Every endpoint becomes a tool whose arguments are the OpenAPI request
+    /// body, plus the path `id`
+    #[test]
+    fn api_mcp_tools_match_the_openapi_document() -> Result<()> {
+        let vars = ctx(&[
+            ("name", "users"),
+            ("title", "Shop"),
+            ("version", "1.0.0"),
+            ("openapi_examples", "false"),
+        ]);
+        let manifest: serde_json::Value =
+            serde_json::from_str(&render_api_package("mcp-tools.tmpl", &vars)?)?;
+        let doc: serde_json::Value =
+            serde_json::from_str(&render_api_package("openapi.tmpl", &vars)?)?;
+        assert_eq!(manifest["name"], "Shop");
+
+        let tools = manifest["tools"].as_array().unwrap();
+        assert_eq!(tools.len(), 7);
+        for tool in tools {
+            let schema = &tool["inputSchema"];
+            jsonschema::validator_for(schema)
+                .unwrap_or_else(|e| panic!("{} has an invalid schema: {}", tool["name"], e));
+            let path = tool["path"].as_str().unwrap().replace(":id", "{id}");
+            let method = tool["method"].as_str().unwrap().to_lowercase();
+            let operation = &doc["paths"][path.as_str()][method.as_str()];
+            assert_eq!(operation["operationId"], tool["name"]);
+            assert_eq!(
+                schema["required"]
+                    .as_array()
+                    .unwrap()
+                    .contains(&"id".into()),
+                operation.get("parameters").is_some(),
+                "{} and its operation disagree about the id",
+                tool["name"]
+            );
+
+            let body = &operation["requestBody"]["content"]["application/json"]["schema"];
+            let Some(reference) = body["$ref"].as_str() else {
+                continue;
+            };
+            let component = &doc["components"]["schemas"][reference.rsplit('/').next().unwrap()];
+            let mut expected = component["properties"].as_object().unwrap().clone();
+            expected.remove("id");
+            let mut offered = schema["properties"].as_object().unwrap().clone();
+            offered.remove("id");
+            assert_eq!(
+                offered, expected,
+                "{} drifted from {}",
+                tool["name"], reference
+            );
+        }
+
+        let tool = |name: &str| tools.iter().find(|tool| tool["name"] == name).unwrap();
+        let create = jsonschema::validator_for(&tool("createUser")["inputSchema"]).unwrap();
+        assert!(create.is_valid(&serde_json::json!({"name": "Ada", "email": "ada@example.com"})));
+        assert!(!create.is_valid(&serde_json::json!({"name": "", "email": "ada@example.com"})));
+        assert!(!create.is_valid(&serde_json::json!({"name": "Ada"})));
+        assert_eq!(tool("getUser")["annotations"]["readOnlyHint"], true);
+        assert_eq!(tool("deleteUser")["annotations"]["destructiveHint"], true);
+        assert_eq!(tool("listOrders")["api"], "orders");
+        Ok(())
+    }
+
+    /// The shim serving the `users` tools through the generated router
+    #[test]
+    fn api_mcp_server_template_matches_snapshot() -> Result<()> {
+        let vars = ctx(&[("name", "users")]);
+        assert_api_package_snapshot("mcp-server.tmpl", "users_mcp.rs", &vars)
+    }
+
+    /// Syntax-level check of a proto3 file: statements are well formed,
+    /// field numbers are unique and every message type used is defined.
+    /// Returns the field numbers by message.
+    fn parse_proto(proto: &str) -> BTreeMap<String, BTreeMap<String, u32>> {
+        let field = regex::Regex::new(
+            r"^(?:(?:optional|repeated) )?([A-Za-z][\w.]*) ([a-z_][a-z0-9_]*) = ([1-9][0-9]*);$",
+        )
+        .unwrap();
+        let rpc = regex::Regex::new(r"^rpc (\w+)\((\w+)\) returns \((\w+)\);$").unwrap();
+        let block = regex::Regex::new(r"^(message|service) (\w+) \{$").unwrap();
+        const SCALARS: &[&str] = &[
+            "double", "float", "int32", "int64", "uint32", "uint64", "sint32", "sint64", "fixed32",
+            "fixed64", "sfixed32", "sfixed64", "bool", "string", "bytes",
+        ];
+
+        let mut messages: BTreeMap<String, BTreeMap<String, u32>> = BTreeMap::new();
+        let mut used_types = Vec::new();
+        let mut current: Option<(String, bool)> = None;
+        for line in proto.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with("//") {
+                continue;
+            }
+            if let Some(caps) = block.captures(line) {
+                assert!(current.is_none(), "nested block: {}", line);
+                let is_message = &caps[1] == "message";
+                if is_message {
+                    assert!(
+                        messages
+                            .insert(caps[2].to_string(), BTreeMap::new())
+                            .is_none(),
+                        "duplicate message {}",
+                        &caps[2]
+                    );
+                }
+                current = Some((caps[2].to_string(), is_message));
+            } else if line == "}" {
+                assert!(current.take().is_some(), "unbalanced }}");
+            } else if let Some((name, is_message)) = &current {
+                if *is_message && line.starts_with("reserved ") {
+                    assert!(line.ends_with(';'), "bad reserved: {}", line);
+                } else if *is_message {
+                    let caps = field
+                        .captures(line)
+                        .unwrap_or_else(|| panic!("bad field: {}", line));
+                    let fields = messages.get_mut(name).unwrap();
+                    let number: u32 = caps[3].parse().unwrap();
+                    assert!(
+                        !fields.values().any(|n| *n == number),
+                        "{} reuses {}",
+                        name,
+                        number
+                    );
+                    fields.insert(caps[2].to_string(), number);
+                    used_types.push(caps[1].to_string());
+                } else {
+                    let caps = rpc
+                        .captures(line)
+                        .unwrap_or_else(|| panic!("bad rpc: {}", line));
+                    used_types.extend([caps[2].to_string(), caps[3].to_string()]);
+                }
+            } else {
+                assert!(
+                    line == r#"syntax = "proto3";"# || line.starts_with("package "),
+                    "unexpected top-level line: {}",
+                    line
+                );
+            }
+        }
+        assert!(current.is_none(), "unclosed block");
+        for ty in used_types {
+            assert!(
+                SCALARS.contains(&ty.as_str()) || messages.contains_key(&ty),
+                "undefined type {}",
+                ty
+            );
+        }
+        messages
+    }
+
+    #[test]
+    fn api_proto_template_is_valid_and_keeps_field_numbers() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let manifest = dir.path().join(".ggen/manifest.json");
+        let vars = ctx(&[("proto_scalars", r#"{"decimal":"string"}"#.to_string())]);
+        let render =
+            |extra: &str| render_api_package_saving(dir.path(), "proto.tmpl", extra, &vars);
+
+        let proto = render("")?;
+        let first = parse_proto(&proto);
+        assert_contains!(proto, "repeated Order orders = ");
+        assert_contains!(proto, "optional string email = ");
+        assert_contains!(proto, "  string total = ");
+        assert_contains!(
+            proto,
+            "rpc UpdateUser(UpdateUserRequest) returns (UpdateUserResponse);"
+        );
+        assert_contains!(proto, "repeated User items = 1;");
+        assert!(manifest.exists());
+
+        // Adding a property keeps every existing number
+        let extra = r#"
+@prefix ex: <http://example.org/api/> .
+@prefix xsd: <http://www.w3.org/2001/XMLSchema#> .
+ex:User ex:hasField ex:userAge .
+ex:userAge ex:name "age" ; ex:datatype xsd:integer .
+"#;
+        let second = parse_proto(&render(extra)?);
+        for (message, fields) in &first {
+            for (field, number) in fields {
+                assert_eq!(
+                    second[message].get(field),
+                    Some(number),
+                    "{}.{} was renumbered",
+                    message,
+                    field
+                );
+            }
+        }
+        let user_max = first["User"].values().max().copied().unwrap();
+        assert_eq!(second["User"]["age"], user_max + 1);
+
+        // Same manifest, same document
+        assert_eq!(render(extra)?, render(extra)?);
+        Ok(())
+    }
+
+    /// `ex:unique` on the User email and a composite index on Order
+    const UNIQUE_AND_INDEXES: &str = r#"
+@prefix ex: <http://example.org/api/> .
+ex:userEmail ex:unique true .
+ex:Order ex:hasIndex ex:orderByUser .
+ex:orderByUser ex:fields "userId, total" .
+"#;
+
+    #[test]
+    fn api_unique_and_index_annotations_reach_every_template() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let vars = ctx(&[
+            ("name", "users".to_string()),
+            ("title", "Shop".to_string()),
+            ("version", "1.0.0".to_string()),
+        ]);
+        let composite_index =
+            "CREATE INDEX IF NOT EXISTS \"order_user_id_total_idx\" ON \"order\" \
+                               (\"user_id\", \"total\");";
+
+        let schema = render_api_package_with("database-schema.tmpl", UNIQUE_AND_INDEXES, &vars)?;
+        assert_contains!(schema, "\"email\" TEXT UNIQUE,");
+        assert_contains!(
+            schema,
+            "\"user_id\" UUID NOT NULL REFERENCES \"user\" (\"id\")"
+        );
+        assert_contains!(schema, composite_index);
+        // Order references User, so User is created first
+        assert!(
+            schema.find("TABLE IF NOT EXISTS \"user\"")
+                < schema.find("TABLE IF NOT EXISTS \"order\"")
+        );
+
+        let service = render_api_package_with("api-endpoint.tmpl", UNIQUE_AND_INDEXES, &vars)?;
+        assert_contains!(service, "pub const UNIQUE_FIELDS: &[&str] = &[\"email\"];");
+        assert_contains!(service, "Ok(Ok(Err(field))) => {");
+        assert_contains!(service, "Ok(Ok(Some(Err(field)))) => {");
+
+        let doc: serde_json::Value = serde_json::from_str(&render_api_package_with(
+            "openapi.tmpl",
+            UNIQUE_AND_INDEXES,
+            &vars,
+        )?)?;
+        assert_eq!(
+            doc["paths"]["/users"]["post"]["responses"]["409"]["description"],
+            "Another User already has this email"
+        );
+        assert!(doc["paths"]["/users/{id}"]["put"]["responses"]["409"].is_object());
+        assert!(doc["paths"]["/users/{id}"]["get"]["responses"]
+            .get("409")
+            .is_none());
+
+        // The first migration creates everything; adding the annotations
+        // only adds a constraint and an index, which are safe to apply
+        let migration =
+            |extra: &str| render_api_package_saving(dir.path(), "migration.tmpl", extra, &vars);
+        let initial = migration("")?;
+        assert_contains!(initial, "CREATE TABLE IF NOT EXISTS \"order\"");
+        assert!(!initial.contains("Destructive"));
+        let delta = migration(UNIQUE_AND_INDEXES)?;
+        assert_eq!(
+            delta.lines().skip(1).collect::<Vec<_>>(),
+            vec![
+                "ALTER TABLE \"user\" ADD CONSTRAINT \"user_email_key\" UNIQUE (\"email\");",
+                composite_index,
+            ]
+        );
+        let unchanged = migration(UNIQUE_AND_INDEXES)?;
+        assert_eq!(unchanged.lines().count(), 1);
+        Ok(())
+    }
+
     #[test]
     fn preprocessor_integration() -> Result<()> {
         use std::path::Path;
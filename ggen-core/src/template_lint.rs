@@ -0,0 +1,986 @@
+//! Checking templates before they are rendered
+//!
+//! Tera only notices an undefined variable or a misspelled filter when it
+//! renders, so template authors find such mistakes late or, behind a
+//! `default`, never. [`lint_template`] parses the frontmatter and the body
+//! into Tera's AST and reports:
+//!
+//! - variables that are neither declared in `vars`, provided by the caller
+//!   nor set in the template, and `sparql_results.<name>` for a query the
+//!   `sparql` section doesn't declare
+//! - loop variables used after their loop has ended
+//! - declared vars nothing outside `vars` refers to
+//! - filters, functions and tests the given [`Tera`] doesn't have
+//!
+//! The frontmatter is rendered before any query runs, so `sparql_results` is
+//! only defined in the body.
+//!
+//! ```rust
+//! use ggen_core::template_lint::{lint_template, LintCode};
+//! use ggen_core::tera_env::build_tera_minimal;
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let template = "---\nvars:\n  name: \"{{ name }}\"\n---\n{{ name | pascl }}\n";
+//! let findings = lint_template(template, &build_tera_minimal()?, &[]);
+//! assert_eq!(findings[0].code, LintCode::UnknownFilter);
+//! assert_eq!((findings[0].line, findings[0].column), (5, 11));
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Lines and columns are 1-based and count from the start of the template,
+//! frontmatter included. Tera's AST has no positions, so a finding points at
+//! the occurrence of its name in the tags that the AST walk stopped at.
+
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use tera::ast::{Expr, ExprVal, FunctionCall, Node};
+use tera::Tera;
+
+use crate::template::Frontmatter;
+
+/// Name of the variable holding the results of the frontmatter queries
+const SPARQL_RESULTS: &str = "sparql_results";
+
+/// Tags whose words aren't expressions
+const NON_EXPRESSION_TAGS: &[&str] = &[
+    "block", "endblock", "extends", "import", "include", "macro", "endmacro", "raw", "endraw",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        })
+    }
+}
+
+/// What a finding is about
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LintCode {
+    /// The frontmatter or the body doesn't parse
+    Syntax,
+    UndefinedVariable,
+    LoopVariableOutOfScope,
+    UnusedVariable,
+    UnknownFilter,
+    UnknownFunction,
+    UnknownTest,
+}
+
+impl LintCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LintCode::Syntax => "syntax",
+            LintCode::UndefinedVariable => "undefined-variable",
+            LintCode::LoopVariableOutOfScope => "loop-variable-out-of-scope",
+            LintCode::UnusedVariable => "unused-variable",
+            LintCode::UnknownFilter => "unknown-filter",
+            LintCode::UnknownFunction => "unknown-function",
+            LintCode::UnknownTest => "unknown-test",
+        }
+    }
+
+    /// Unused vars don't break rendering; everything else does
+    pub fn severity(&self) -> Severity {
+        match self {
+            LintCode::UnusedVariable => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+}
+
+/// A problem found in a template
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LintFinding {
+    pub line: usize,
+    pub column: usize,
+    pub severity: Severity,
+    pub code: LintCode,
+    pub message: String,
+}
+
+impl fmt::Display for LintFinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}: {}[{}] {}",
+            self.line,
+            self.column,
+            self.severity,
+            self.code.as_str(),
+            self.message
+        )
+    }
+}
+
+/// Lint `source`, a template with optional frontmatter, against the filters,
+/// functions and tests of `tera`
+///
+/// `provided` names variables that come from elsewhere, such as `--var`
+/// arguments or `ggen.toml`. Findings are sorted by position.
+pub fn lint_template(source: &str, tera: &Tera, provided: &[&str]) -> Vec<LintFinding> {
+    let mut findings = Vec::new();
+    let (front, body_start) = match split_frontmatter(source) {
+        Some((start, end, body_start)) => (Some((start, &source[start..end])), body_start),
+        None => (None, 0),
+    };
+    let body = &source[body_start..];
+
+    let mut declared = BTreeSet::new();
+    let mut queries = BTreeSet::new();
+    let mut row_vars = BTreeSet::new();
+    let mut used_in_front = BTreeSet::new();
+    if let Some((start, text)) = front {
+        match parse_frontmatter(text) {
+            Ok(parsed) => {
+                declared = parsed.declared;
+                queries = parsed.queries;
+                row_vars = parsed.row_vars;
+                let none = BTreeSet::new();
+                for value in parsed.rendered {
+                    if let Ok(ast) = parse(&value) {
+                        let mut walker = Walker::new(tera, &none, &none);
+                        walker.nodes(&ast);
+                        used_in_front.extend(walker.used);
+                    }
+                }
+            }
+            Err(err) => {
+                let (line, column) = err
+                    .location()
+                    .map_or((1, 1), |location| (location.line(), location.column()));
+                let offset = offset_of(text, line, column);
+                findings.push(finding_at(
+                    source,
+                    start + offset,
+                    LintCode::Syntax,
+                    format!("Frontmatter is not valid YAML: {}", err),
+                ));
+                return findings;
+            }
+        }
+    }
+
+    let mut known = declared.clone();
+    known.extend(provided.iter().map(|name| name.to_string()));
+    known.extend(row_vars);
+
+    if let Some((start, text)) = front {
+        lint_section(
+            source,
+            start,
+            text,
+            tera,
+            &known,
+            &BTreeSet::new(),
+            &mut findings,
+        );
+    }
+    let used = lint_section(
+        source,
+        body_start,
+        body,
+        tera,
+        &known,
+        &queries,
+        &mut findings,
+    );
+
+    if let (Some((start, text)), Some(used)) = (front, used) {
+        for name in &declared {
+            if used.contains(name) || used_in_front.contains(name) {
+                continue;
+            }
+            let offset = declared_at(text, name).unwrap_or(0);
+            findings.push(finding_at(
+                source,
+                start + offset,
+                LintCode::UnusedVariable,
+                format!("`{}` is declared in `vars` but never used", name),
+            ));
+        }
+    }
+
+    findings.sort_by(|a, b| (a.line, a.column, a.code).cmp(&(b.line, b.column, b.code)));
+    findings
+}
+
+/// Byte ranges of the frontmatter text and where the body starts
+fn split_frontmatter(source: &str) -> Option<(usize, usize, usize)> {
+    let first = source.split_inclusive('\n').next()?;
+    if first.trim_end() != "---" {
+        return None;
+    }
+    let start = first.len();
+    let mut offset = start;
+    for line in source[start..].split_inclusive('\n') {
+        if line.trim_end() == "---" {
+            return Some((start, offset, offset + line.len()));
+        }
+        offset += line.len();
+    }
+    None
+}
+
+/// What the frontmatter declares
+struct ParsedFrontmatter {
+    /// Names of the declared vars
+    declared: BTreeSet<String>,
+    queries: BTreeSet<String>,
+    /// Variables each row of the `foreach` query binds
+    row_vars: BTreeSet<String>,
+    /// Strings outside `vars`, which are rendered with the vars
+    rendered: Vec<String>,
+}
+
+fn parse_frontmatter(text: &str) -> Result<ParsedFrontmatter, serde_yaml::Error> {
+    let raw: serde_yaml::Value = serde_yaml::from_str(text)?;
+    let front: Frontmatter = serde_yaml::from_value(raw.clone())?;
+    let mut declared = BTreeSet::new();
+    let mut rendered = Vec::new();
+    if let Some(map) = raw.as_mapping() {
+        for (key, value) in map {
+            if key.as_str() == Some("vars") {
+                if let Some(vars) = value.as_mapping() {
+                    for name in vars.keys() {
+                        if let Some(name) = name.as_str() {
+                            declared.insert(name.to_string());
+                        }
+                    }
+                }
+            } else {
+                collect_strings(value, &mut rendered);
+            }
+        }
+    }
+    let row_vars = front
+        .foreach
+        .as_ref()
+        .and_then(|query| front.sparql.get(query))
+        .map(|query| projected_vars(query))
+        .unwrap_or_default();
+    Ok(ParsedFrontmatter {
+        declared,
+        queries: front.sparql.into_keys().collect(),
+        row_vars,
+        rendered,
+    })
+}
+
+/// Variables a SELECT query projects, or every variable it mentions for
+/// `SELECT *`
+fn projected_vars(query: &str) -> BTreeSet<String> {
+    let lower = query.to_ascii_lowercase();
+    let projection = match (lower.find("select"), query.find('{')) {
+        (Some(start), Some(end)) if start < end && !query[start..end].contains('*') => {
+            &query[start..end]
+        }
+        _ => query,
+    };
+    projection
+        .split(['?', '$'])
+        .skip(1)
+        .map(|rest| {
+            rest.chars()
+                .take_while(|c| c.is_alphanumeric() || *c == '_')
+                .collect::<String>()
+        })
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+fn collect_strings(value: &serde_yaml::Value, strings: &mut Vec<String>) {
+    match value {
+        serde_yaml::Value::String(s) => strings.push(s.clone()),
+        serde_yaml::Value::Sequence(items) => {
+            for item in items {
+                collect_strings(item, strings);
+            }
+        }
+        serde_yaml::Value::Mapping(map) => {
+            for value in map.values() {
+                collect_strings(value, strings);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn parse(text: &str) -> tera::Result<Vec<Node>> {
+    tera::Template::new("lint", None, text).map(|template| template.ast)
+}
+
+/// Lint one section of `source` starting at `start`, returning the variables
+/// it refers to, or `None` if it doesn't parse
+fn lint_section(
+    source: &str, start: usize, text: &str, tera: &Tera, known: &BTreeSet<String>,
+    queries: &BTreeSet<String>, findings: &mut Vec<LintFinding>,
+) -> Option<BTreeSet<String>> {
+    let ast = match parse(text) {
+        Ok(ast) => ast,
+        Err(err) => {
+            let (offset, message) = syntax_error(text, &err);
+            findings.push(finding_at(
+                source,
+                start + offset,
+                LintCode::Syntax,
+                message,
+            ));
+            return None;
+        }
+    };
+    let mut walker = Walker::new(tera, known, queries);
+    walker.nodes(&ast);
+
+    let tags = tags(text);
+    for problem in walker.problems {
+        let offset = locate(&tags, problem.kind, &problem.name, problem.ordinal).unwrap_or(0);
+        findings.push(finding_at(
+            source,
+            start + offset,
+            problem.code,
+            problem.message,
+        ));
+    }
+    Some(walker.used)
+}
+
+/// Offset and message of a Tera parse error
+fn syntax_error(text: &str, err: &tera::Error) -> (usize, String) {
+    let mut chain = err.to_string();
+    let mut source = std::error::Error::source(err);
+    while let Some(inner) = source {
+        chain.push('\n');
+        chain.push_str(&inner.to_string());
+        source = inner.source();
+    }
+    // Pest errors point at the problem with ` --> line:column`
+    let position = chain.lines().find_map(|line| {
+        let (line, column) = line.trim().strip_prefix("-->")?.trim().split_once(':')?;
+        Some((line.parse().ok()?, column.parse().ok()?))
+    });
+    let reason = chain
+        .lines()
+        .rev()
+        .map(|line| line.trim().trim_start_matches("= ").trim())
+        .find(|line| !line.is_empty())
+        .unwrap_or("invalid syntax");
+    let offset = position.map_or(0, |(line, column)| offset_of(text, line, column));
+    (offset, format!("Template does not parse: {}", reason))
+}
+
+/// Byte offset of a 1-based line and column in `text`
+fn offset_of(text: &str, line: usize, column: usize) -> usize {
+    let line_start: usize = text
+        .split_inclusive('\n')
+        .take(line.saturating_sub(1))
+        .map(str::len)
+        .sum();
+    let rest = &text[line_start.min(text.len())..];
+    let column_offset = rest
+        .char_indices()
+        .nth(column.saturating_sub(1))
+        .map_or(rest.len(), |(i, _)| i);
+    line_start + column_offset
+}
+
+fn finding_at(source: &str, offset: usize, code: LintCode, message: String) -> LintFinding {
+    let before = &source[..offset.min(source.len())];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    LintFinding {
+        line,
+        column: before[line_start..].chars().count() + 1,
+        severity: code.severity(),
+        code,
+        message,
+    }
+}
+
+/// Where `name` is declared in the `vars` section of the frontmatter `text`
+fn declared_at(text: &str, name: &str) -> Option<usize> {
+    let mut offset = 0;
+    let mut in_vars = false;
+    for line in text.split_inclusive('\n') {
+        let indent = line.len() - line.trim_start().len();
+        let trimmed = line.trim();
+        if indent == 0 && !trimmed.is_empty() {
+            in_vars = trimmed.starts_with("vars:");
+        } else if in_vars {
+            let key = trimmed.trim_start_matches("- ");
+            let key = key.trim_matches(|c| c == '"' || c == '\'');
+            if key.starts_with(name)
+                && key[name.len()..]
+                    .trim_start_matches(['"', '\''])
+                    .starts_with(':')
+            {
+                return Some(offset + indent);
+            }
+        }
+        offset += line.len();
+    }
+    None
+}
+
+/// How a name is referred to in a tag
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum RefKind {
+    Variable,
+    Filter,
+    Function,
+    Test,
+}
+
+/// A finding before it is located
+struct Problem {
+    code: LintCode,
+    message: String,
+    kind: RefKind,
+    name: String,
+    /// How many references of `kind` to `name` come before it
+    ordinal: usize,
+}
+
+/// Walks the AST in the order Tera evaluates it, which is the order the
+/// references appear in the source
+struct Walker<'a> {
+    tera: &'a Tera,
+    queries: &'a BTreeSet<String>,
+    /// Innermost last; a `for` loop or a macro opens a new one
+    scopes: Vec<BTreeSet<String>>,
+    /// Variables of loops that have ended
+    ended_loops: BTreeSet<String>,
+    references: BTreeMap<(RefKind, String), usize>,
+    used: BTreeSet<String>,
+    problems: Vec<Problem>,
+    reported: BTreeSet<(LintCode, String)>,
+}
+
+impl<'a> Walker<'a> {
+    fn new(tera: &'a Tera, known: &BTreeSet<String>, queries: &'a BTreeSet<String>) -> Self {
+        Self {
+            tera,
+            queries,
+            scopes: vec![known.clone()],
+            ended_loops: BTreeSet::new(),
+            references: BTreeMap::new(),
+            used: BTreeSet::new(),
+            problems: Vec::new(),
+            reported: BTreeSet::new(),
+        }
+    }
+
+    fn nodes(&mut self, nodes: &[Node]) {
+        for node in nodes {
+            self.node(node);
+        }
+    }
+
+    fn node(&mut self, node: &Node) {
+        match node {
+            Node::VariableBlock(_, expr) => self.expr(expr),
+            Node::Set(_, set) => {
+                self.expr(&set.value);
+                let scope = if set.global { 0 } else { self.scopes.len() - 1 };
+                self.scopes[scope].insert(set.key.clone());
+            }
+            Node::Forloop(_, forloop, _) => {
+                self.expr(&forloop.container);
+                let mut scope = BTreeSet::from(["loop".to_string(), forloop.value.clone()]);
+                scope.extend(forloop.key.clone());
+                self.scopes.push(scope);
+                self.nodes(&forloop.body);
+                self.scopes.pop();
+                if let Some(empty_body) = &forloop.empty_body {
+                    self.nodes(empty_body);
+                }
+                self.ended_loops.insert(forloop.value.clone());
+                self.ended_loops.extend(forloop.key.clone());
+            }
+            Node::If(branches, _) => {
+                for (_, condition, body) in &branches.conditions {
+                    self.expr(condition);
+                    self.nodes(body);
+                }
+                if let Some((_, body)) = &branches.otherwise {
+                    self.nodes(body);
+                }
+            }
+            Node::Block(_, block, _) => self.nodes(&block.body),
+            Node::FilterSection(_, section, _) => {
+                self.filter(&section.filter);
+                self.nodes(&section.body);
+            }
+            Node::MacroDefinition(_, definition, _) => {
+                // Macros only see their arguments
+                let arguments = definition.args.keys().cloned().collect();
+                let outer = std::mem::replace(&mut self.scopes, vec![arguments]);
+                self.nodes(&definition.body);
+                self.scopes = outer;
+            }
+            _ => {}
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        // `default` is how templates handle a variable that may be missing
+        let defaulted = expr
+            .filters
+            .first()
+            .is_some_and(|filter| filter.name == "default");
+        self.value(&expr.val, defaulted);
+        for filter in &expr.filters {
+            self.filter(filter);
+        }
+    }
+
+    fn value(&mut self, value: &ExprVal, lenient: bool) {
+        match value {
+            ExprVal::Ident(ident) => self.ident(ident, lenient),
+            ExprVal::Math(math) => {
+                self.expr(&math.lhs);
+                self.expr(&math.rhs);
+            }
+            ExprVal::Logic(logic) => {
+                self.expr(&logic.lhs);
+                self.expr(&logic.rhs);
+            }
+            ExprVal::In(contains) => {
+                self.expr(&contains.lhs);
+                self.expr(&contains.rhs);
+            }
+            ExprVal::Test(test) => {
+                let checks_definition = matches!(test.name.as_str(), "defined" | "undefined");
+                self.ident(&test.ident, lenient || checks_definition);
+                let ordinal = self.reference(RefKind::Test, &test.name);
+                if self.tera.get_tester(&test.name).is_err() {
+                    self.problem(
+                        LintCode::UnknownTest,
+                        format!("Unknown test `{}`", test.name),
+                        RefKind::Test,
+                        &test.name,
+                        ordinal,
+                    );
+                }
+                for arg in &test.args {
+                    self.expr(arg);
+                }
+            }
+            ExprVal::FunctionCall(call) => {
+                let ordinal = self.reference(RefKind::Function, &call.name);
+                if self.tera.get_function(&call.name).is_err() {
+                    self.problem(
+                        LintCode::UnknownFunction,
+                        format!("Unknown function `{}`", call.name),
+                        RefKind::Function,
+                        &call.name,
+                        ordinal,
+                    );
+                }
+                self.args(call);
+            }
+            ExprVal::MacroCall(call) => {
+                for arg in call.args.values() {
+                    self.expr(arg);
+                }
+            }
+            ExprVal::Array(items) => {
+                for item in items {
+                    self.expr(item);
+                }
+            }
+            ExprVal::StringConcat(concat) => {
+                for value in &concat.values {
+                    self.value(value, lenient);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn filter(&mut self, filter: &FunctionCall) {
+        let ordinal = self.reference(RefKind::Filter, &filter.name);
+        if self.tera.get_filter(&filter.name).is_err() {
+            self.problem(
+                LintCode::UnknownFilter,
+                format!("Unknown filter `{}`", filter.name),
+                RefKind::Filter,
+                &filter.name,
+                ordinal,
+            );
+        }
+        self.args(filter);
+    }
+
+    /// Arguments in the order they are written
+    fn args(&mut self, call: &FunctionCall) {
+        let mut args: Vec<_> = call.args.iter().collect();
+        args.sort_by_key(|(name, _)| name.as_str());
+        for (_, arg) in args {
+            self.expr(arg);
+        }
+    }
+
+    /// `ident` is a dotted path such as `row.name` or `rows[0]["?name"]`
+    fn ident(&mut self, ident: &str, lenient: bool) {
+        let root_end = ident.find(['.', '[']).unwrap_or(ident.len());
+        let (root, path) = ident.split_at(root_end);
+        let ordinal = self.reference(RefKind::Variable, root);
+        self.used.insert(root.to_string());
+        for index in bracket_variables(path) {
+            self.ident(index, lenient);
+        }
+        if lenient || root == "__tera_context" || self.scopes.iter().any(|s| s.contains(root)) {
+            return;
+        }
+
+        if root == SPARQL_RESULTS && !self.queries.is_empty() {
+            let query = path
+                .strip_prefix('.')
+                .map(|rest| rest.split(['.', '[']).next().unwrap_or(rest));
+            if let Some(query) = query.filter(|query| !self.queries.contains(*query)) {
+                self.problem(
+                    LintCode::UndefinedVariable,
+                    format!(
+                        "`{}.{}` is not a query declared in `sparql`",
+                        SPARQL_RESULTS, query
+                    ),
+                    RefKind::Variable,
+                    root,
+                    ordinal,
+                );
+            }
+        } else if self.ended_loops.contains(root) {
+            self.problem(
+                LintCode::LoopVariableOutOfScope,
+                format!("Loop variable `{}` is used after its loop", root),
+                RefKind::Variable,
+                root,
+                ordinal,
+            );
+        } else {
+            self.problem(
+                LintCode::UndefinedVariable,
+                format!("`{}` is not declared in `vars`, provided or set", root),
+                RefKind::Variable,
+                root,
+                ordinal,
+            );
+        }
+    }
+
+    /// Count a reference, returning how many came before it
+    fn reference(&mut self, kind: RefKind, name: &str) -> usize {
+        let count = self.references.entry((kind, name.to_string())).or_insert(0);
+        *count += 1;
+        *count - 1
+    }
+
+    /// Record a problem, once per message
+    fn problem(
+        &mut self, code: LintCode, message: String, kind: RefKind, name: &str, ordinal: usize,
+    ) {
+        if self.reported.insert((code, message.clone())) {
+            self.problems.push(Problem {
+                code,
+                message,
+                kind,
+                name: name.to_string(),
+                ordinal,
+            });
+        }
+    }
+}
+
+/// Variables used as indices in `path`, e.g. `key` in `[key]`
+fn bracket_variables(path: &str) -> Vec<&str> {
+    let mut variables = Vec::new();
+    let mut rest = path;
+    while let Some(open) = rest.find('[') {
+        let Some(close) = rest[open..].find(']') else {
+            break;
+        };
+        let index = rest[open + 1..open + close].trim();
+        let literal = index.starts_with(['"', '\'', '`']) || index.parse::<i64>().is_ok();
+        if !literal && !index.is_empty() {
+            variables.push(index);
+        }
+        rest = &rest[open + close + 1..];
+    }
+    variables
+}
+
+/// Contents of the `{{ }}` and `{% %}` tags of `text` and their offsets,
+/// with string literals blanked out so names inside them aren't found
+fn tags(text: &str) -> Vec<(usize, String)> {
+    let bytes = text.as_bytes();
+    let mut tags = Vec::new();
+    let mut i = 0;
+    while i + 1 < bytes.len() {
+        let close = match (bytes[i], bytes[i + 1]) {
+            (b'{', b'{') => b'}',
+            (b'{', b'%') => b'%',
+            (b'{', b'#') => b'#',
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+        let start = i + 2;
+        let mut content = Vec::new();
+        let mut quote = None;
+        let mut j = start;
+        while j < bytes.len() {
+            let byte = bytes[j];
+            match quote {
+                Some(q) if byte == q => quote = None,
+                Some(_) => {
+                    content.push(b' ');
+                    j += 1;
+                    continue;
+                }
+                None if matches!(byte, b'"' | b'\'' | b'`') && close != b'#' => quote = Some(byte),
+                None if byte == close && bytes.get(j + 1) == Some(&b'}') => break,
+                None => {}
+            }
+            content.push(byte);
+            j += 1;
+        }
+        i = j + 2;
+        if close == b'#' {
+            continue;
+        }
+        let content = String::from_utf8_lossy(&content).into_owned();
+        let keyword = tag_keyword(&content);
+        tags.push((start, content));
+        if keyword == "raw" {
+            // Skip to the `endraw` tag, whose contents are text
+            let rest = &text[i.min(text.len())..];
+            let end = rest
+                .match_indices("{%")
+                .find(|(at, _)| tag_keyword(&rest[at + 2..]) == "endraw")
+                .map_or(rest.len(), |(at, _)| at);
+            i += end;
+        }
+    }
+    tags
+}
+
+/// First word of a tag, ignoring whitespace control
+fn tag_keyword(content: &str) -> &str {
+    content
+        .trim_start()
+        .trim_start_matches('-')
+        .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .find(|word| !word.is_empty())
+        .unwrap_or("")
+}
+
+/// Offset in the section of the `ordinal`th reference of `kind` to `name`,
+/// falling back to the first one
+fn locate(tags: &[(usize, String)], kind: RefKind, name: &str, ordinal: usize) -> Option<usize> {
+    let mut found = tags.iter().flat_map(|(offset, content)| {
+        references(content, kind, name)
+            .into_iter()
+            .map(move |at| offset + at)
+    });
+    let first = found.next()?;
+    Some(
+        std::iter::once(first)
+            .chain(found)
+            .nth(ordinal)
+            .unwrap_or(first),
+    )
+}
+
+/// Offsets in a tag's `content` where `name` is referred to as `kind`
+fn references(content: &str, kind: RefKind, name: &str) -> Vec<usize> {
+    let keyword = tag_keyword(content);
+    if kind == RefKind::Variable && NON_EXPRESSION_TAGS.contains(&keyword) {
+        return Vec::new();
+    }
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    content
+        .match_indices(name)
+        .map(|(at, _)| at)
+        .filter(|&at| {
+            let before = &content[..at];
+            let after = &content[at + name.len()..];
+            if before.ends_with(|c: char| is_word(c) || c == '.' || c == ':')
+                || after.starts_with(is_word)
+            {
+                return false;
+            }
+            let before = before.trim_end();
+            let after = after.trim_start();
+            let filter = before.ends_with('|') || before.trim_start_matches('-').trim() == "filter";
+            let test = before.ends_with(" is")
+                || before
+                    .strip_suffix("not")
+                    .is_some_and(|b| b.trim_end().ends_with(" is"));
+            let call = after.starts_with('(');
+            match kind {
+                RefKind::Filter => filter,
+                RefKind::Test => test,
+                RefKind::Function => call && !filter,
+                RefKind::Variable => {
+                    let assigned = after.starts_with('=') && !after.starts_with("==");
+                    let bound = before.ends_with("for")
+                        || (before.ends_with(',')
+                            && before.contains("for ")
+                            && after.starts_with("in "));
+                    !(filter || test || call || assigned || bound)
+                }
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::Pipeline;
+    use std::path::Path;
+
+    fn lint(template: &str) -> Vec<LintFinding> {
+        let tera = crate::tera_env::build_tera_minimal().unwrap();
+        lint_template(template, &tera, &["provided"])
+    }
+
+    fn summary(findings: &[LintFinding]) -> Vec<(usize, usize, LintCode, &str)> {
+        findings
+            .iter()
+            .map(|f| (f.line, f.column, f.code, f.message.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn test_reports_each_problem_where_it_is() {
+        let template = r#"---
+to: "src/{{ name | snake }}.rs"
+vars:
+  name: "{{ name }}"
+  owner: "platform"
+sparql:
+  people: "SELECT ?name WHERE { ?p a ex:Person }"
+---
+pub struct {{ name | pascl }};
+// {{ provided }} {{ title }}
+{% for person in sparql_results.people %}{{ person.name | upper }}{% endfor %}
+{{ person | upper }}
+{{ sparql_first(results=sparql_results.teams, column="name") }}
+{{ shout(text=name) }} {% if name is blank %}{% endif %}
+{{ subtitle | default(value="none") }}
+"#;
+        let findings = lint(template);
+
+        assert_eq!(
+            summary(&findings),
+            vec![
+                (
+                    5,
+                    3,
+                    LintCode::UnusedVariable,
+                    "`owner` is declared in `vars` but never used"
+                ),
+                (9, 22, LintCode::UnknownFilter, "Unknown filter `pascl`"),
+                (
+                    10,
+                    22,
+                    LintCode::UndefinedVariable,
+                    "`title` is not declared in `vars`, provided or set"
+                ),
+                (
+                    12,
+                    4,
+                    LintCode::LoopVariableOutOfScope,
+                    "Loop variable `person` is used after its loop"
+                ),
+                (
+                    13,
+                    25,
+                    LintCode::UndefinedVariable,
+                    "`sparql_results.teams` is not a query declared in `sparql`"
+                ),
+                (14, 4, LintCode::UnknownFunction, "Unknown function `shout`"),
+                (14, 38, LintCode::UnknownTest, "Unknown test `blank`"),
+            ]
+        );
+        assert_eq!(findings[0].severity, Severity::Warning);
+        assert_eq!(findings[1].severity, Severity::Error);
+        assert_eq!(
+            findings[1].to_string(),
+            "9:22: error[unknown-filter] Unknown filter `pascl`"
+        );
+    }
+
+    #[test]
+    fn test_frontmatter_is_linted_without_query_results() {
+        let template = "---\nto: \"{{ sparql_results.people | length }}.rs\"\nsparql:\n  people: \"SELECT ?p WHERE { ?p ?q ?o }\"\n---\n{{ sparql_results.people | length }}\n";
+        let findings = lint(template);
+
+        assert_eq!(
+            summary(&findings),
+            vec![(
+                2,
+                9,
+                LintCode::UndefinedVariable,
+                "`sparql_results` is not declared in `vars`, provided or set"
+            )]
+        );
+    }
+
+    #[test]
+    fn test_foreach_row_variables_are_known() {
+        let template = "---\nto: \"{{ entity | local }}.rs\"\nforeach: entities\nsparql:\n  entities: \"SELECT ?entity ?label WHERE { ?entity ?p ?label }\"\n---\n{{ label }} {{ p }}\n";
+        let findings = lint(template);
+
+        assert_eq!(
+            summary(&findings),
+            vec![(
+                7,
+                16,
+                LintCode::UndefinedVariable,
+                "`p` is not declared in `vars`, provided or set"
+            )]
+        );
+    }
+
+    #[test]
+    fn test_syntax_errors_are_findings() {
+        let findings = lint("---\nvars:\n  name: x\n---\nline\n{{ name | }}\n");
+
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].code, LintCode::Syntax);
+        assert_eq!(findings[0].line, 6);
+    }
+
+    #[test]
+    fn test_advanced_rust_project_templates_are_clean() {
+        let templates = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../marketplace/packages/advanced-rust-project/templates");
+        let mut pipeline = Pipeline::new().unwrap();
+        pipeline.register_prefixes(None, &BTreeMap::new());
+        for name in [
+            "api-endpoint.tmpl",
+            "database-schema.tmpl",
+            "documentation.tmpl",
+            "rust-service.tmpl",
+        ] {
+            let source = std::fs::read_to_string(templates.join(name)).unwrap();
+            let findings = lint_template(&source, &pipeline.tera, &[]);
+            assert!(findings.is_empty(), "{}: {:#?}", name, findings);
+        }
+    }
+}
@@ -0,0 +1,268 @@
+//! Typed template variables declared in frontmatter `vars:`
+//!
+//! A var whose value is a mapping with a known `type` is a declaration:
+//! ```yaml
+//! vars:
+//!   replicas: { type: int, default: 3 }
+//!   public: { type: bool, required: true }
+//!   owner: "platform"   # untyped, stays as written
+//! ```
+//! [`resolve`] coerces the values provided on the command line or in
+//! `ggen.toml` to the declared type, falls back to the default and fails on
+//! a missing required var, so templates see `3` and `false` instead of the
+//! strings `"3"` and `"false"`. Supported types are `string`, `int`,
+//! `float`, `bool` and `list`.
+
+use anyhow::{bail, Result};
+use serde_json::{Number, Value};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Keys allowed in a declaration; a mapping with other keys is a plain value
+const DECLARATION_KEYS: &[&str] = &["type", "default", "required", "description"];
+
+/// Type of a declared var
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VarType {
+    String,
+    Int,
+    Float,
+    Bool,
+    List,
+}
+
+impl VarType {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "string" | "str" => Some(Self::String),
+            "int" | "integer" => Some(Self::Int),
+            "float" | "number" => Some(Self::Float),
+            "bool" | "boolean" => Some(Self::Bool),
+            "list" | "array" => Some(Self::List),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for VarType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::String => "string",
+            Self::Int => "int",
+            Self::Float => "float",
+            Self::Bool => "bool",
+            Self::List => "list",
+        })
+    }
+}
+
+/// A typed var declaration
+#[derive(Debug, Clone, PartialEq)]
+pub struct VarDecl {
+    pub ty: VarType,
+    pub default: Option<serde_yaml::Value>,
+    pub required: bool,
+}
+
+impl VarDecl {
+    /// Read a declaration; `None` for an untyped var
+    pub fn from_yaml(value: &serde_yaml::Value) -> Option<Self> {
+        let map = value.as_mapping()?;
+        let ty = VarType::parse(map.get("type")?.as_str()?)?;
+        let only_declaration_keys = map.keys().all(|key| {
+            key.as_str()
+                .is_some_and(|key| DECLARATION_KEYS.contains(&key))
+        });
+        if !only_declaration_keys {
+            return None;
+        }
+        Some(Self {
+            ty,
+            default: map.get("default").cloned(),
+            required: map
+                .get("required")
+                .and_then(serde_yaml::Value::as_bool)
+                .unwrap_or(false),
+        })
+    }
+}
+
+/// Whether a frontmatter var is a typed declaration
+pub fn is_declaration(value: &serde_yaml::Value) -> bool {
+    VarDecl::from_yaml(value).is_some()
+}
+
+/// Values of the typed declarations among `vars`
+///
+/// A provided value wins over the default. Declarations that are neither
+/// provided nor defaulted are left out, unless required, which is an error.
+/// Untyped vars are not part of the result.
+pub fn resolve(
+    vars: &serde_yaml::Mapping, provided: &BTreeMap<String, String>,
+) -> Result<BTreeMap<String, Value>> {
+    let mut resolved = BTreeMap::new();
+    for (name, value) in vars {
+        let (Some(name), Some(decl)) = (name.as_str(), VarDecl::from_yaml(value)) else {
+            continue;
+        };
+        let value = match (provided.get(name), &decl.default) {
+            (Some(raw), _) => coerce(name, decl.ty, raw)?,
+            (None, Some(default)) => coerce_default(name, decl.ty, default)?,
+            (None, None) if decl.required => {
+                bail!("variable '{}' is required but was not provided", name)
+            }
+            (None, None) => continue,
+        };
+        resolved.insert(name.to_string(), value);
+    }
+    Ok(resolved)
+}
+
+/// Coerce a provided string to `ty`
+pub fn coerce(name: &str, ty: VarType, raw: &str) -> Result<Value> {
+    let text = raw.trim();
+    let value = match ty {
+        VarType::String => Some(Value::String(raw.to_string())),
+        VarType::Int => text.parse::<i64>().ok().map(Value::from),
+        VarType::Float => text
+            .parse::<f64>()
+            .ok()
+            .and_then(Number::from_f64)
+            .map(Value::Number),
+        VarType::Bool => match text.to_ascii_lowercase().as_str() {
+            "true" | "yes" | "on" | "1" => Some(Value::Bool(true)),
+            "false" | "no" | "off" | "0" => Some(Value::Bool(false)),
+            _ => None,
+        },
+        VarType::List if text.starts_with('[') => serde_json::from_str::<Vec<Value>>(text)
+            .ok()
+            .map(Value::Array),
+        VarType::List if text.is_empty() => Some(Value::Array(Vec::new())),
+        VarType::List => Some(Value::Array(
+            text.split(',')
+                .map(|item| Value::String(item.trim().to_string()))
+                .collect(),
+        )),
+    };
+    value.ok_or_else(|| anyhow::anyhow!("variable '{}' expected {}, got '{}'", name, ty, raw))
+}
+
+/// Coerce a frontmatter default, which YAML may already have typed
+fn coerce_default(name: &str, ty: VarType, default: &serde_yaml::Value) -> Result<Value> {
+    let value: Value = serde_yaml::from_value(default.clone())?;
+    match (&value, ty) {
+        (Value::String(raw), _) => coerce(name, ty, raw),
+        (Value::Number(n), VarType::Int) if n.is_i64() || n.is_u64() => Ok(value),
+        (Value::Number(_), VarType::Float) | (Value::Bool(_), VarType::Bool) => Ok(value),
+        (Value::Array(_), VarType::List) => Ok(value),
+        (Value::Number(_) | Value::Bool(_), VarType::String) => {
+            Ok(Value::String(value.to_string()))
+        }
+        _ => bail!(
+            "variable '{}' expected {}, got default '{}'",
+            name,
+            ty,
+            value
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(yaml: &str) -> serde_yaml::Mapping {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    fn provided(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn coerces_provided_values() {
+        assert_eq!(coerce("n", VarType::Int, " 3 ").unwrap(), Value::from(3));
+        assert_eq!(
+            coerce("f", VarType::Float, "0.5").unwrap(),
+            Value::from(0.5)
+        );
+        assert_eq!(
+            coerce("b", VarType::Bool, "false").unwrap(),
+            Value::Bool(false)
+        );
+        assert_eq!(
+            coerce("b", VarType::Bool, "Yes").unwrap(),
+            Value::Bool(true)
+        );
+        assert_eq!(
+            coerce("l", VarType::List, "a, b,c").unwrap(),
+            serde_json::json!(["a", "b", "c"])
+        );
+        assert_eq!(
+            coerce("l", VarType::List, "[1, 2]").unwrap(),
+            serde_json::json!([1, 2])
+        );
+        assert_eq!(
+            coerce("l", VarType::List, "").unwrap(),
+            serde_json::json!([])
+        );
+    }
+
+    #[test]
+    fn rejects_values_of_the_wrong_type() {
+        let err = coerce("replicas", VarType::Int, "three").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "variable 'replicas' expected int, got 'three'"
+        );
+        assert!(coerce("public", VarType::Bool, "maybe").is_err());
+        assert!(coerce("ratio", VarType::Float, "NaN").is_err());
+        assert!(coerce("items", VarType::List, "[1,").is_err());
+    }
+
+    #[test]
+    fn resolves_defaults_and_provided_values() {
+        let declared = vars(
+            r#"
+replicas: { type: int, default: 3 }
+ratio: { type: float, default: "0.25" }
+tags: { type: list, default: [a, b] }
+public: { type: bool }
+owner: platform
+db: { type: postgres, host: localhost }
+"#,
+        );
+        let resolved = resolve(&declared, &provided(&[("public", "false")])).unwrap();
+
+        assert_eq!(resolved["replicas"], Value::from(3));
+        assert_eq!(resolved["ratio"], Value::from(0.25));
+        assert_eq!(resolved["tags"], serde_json::json!(["a", "b"]));
+        assert_eq!(resolved["public"], Value::Bool(false));
+        assert!(!resolved.contains_key("owner"));
+        assert!(!resolved.contains_key("db"));
+
+        let resolved = resolve(&declared, &provided(&[("replicas", "5")])).unwrap();
+        assert_eq!(resolved["replicas"], Value::from(5));
+        assert!(!resolved.contains_key("public"));
+    }
+
+    #[test]
+    fn missing_required_var_is_an_error() {
+        let declared = vars("public: { type: bool, required: true }");
+        let err = resolve(&declared, &BTreeMap::new()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "variable 'public' is required but was not provided"
+        );
+        assert!(resolve(&declared, &provided(&[("public", "true")])).is_ok());
+    }
+
+    #[test]
+    fn default_of_the_wrong_type_is_an_error() {
+        let declared = vars("replicas: { type: int, default: [1] }");
+        assert!(resolve(&declared, &BTreeMap::new()).is_err());
+    }
+}
@@ -0,0 +1,45 @@
+//! Generate the users service, start it and let an agent use its tools
+//!
+//! The agent runs on a scripted mock model, so no provider is needed; the
+//! network is, the first time the service's dependencies are fetched.
+//!
+//! ```bash
+//! cargo run --manifest-path integration-e2e/Cargo.toml --example users_agent
+//! ```
+
+use anyhow::Result;
+use integration_e2e::{default_target_dir, Stack};
+use rig_mcp_integration::testing::MockProvider;
+use rig_mcp_integration::{Config, NormalizedResponse, RigMcpClient, ToolServer};
+use serde_json::json;
+use std::sync::Arc;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let target_dir = default_target_dir();
+    println!("Building the users service into {}", target_dir.display());
+    let stack = Stack::start(&target_dir).await?;
+    println!("REST API at {}", stack.http());
+    for tool in stack.server().list_tools().await? {
+        println!("  tool {}: {}", tool.name, tool.description);
+    }
+
+    let model = Arc::new(MockProvider::new("mock"));
+    model.push_tool_call(
+        "createUser",
+        json!({"name": "Ada Lovelace", "email": "ada@example.com"}),
+    );
+    model.push_tool_call("listUsers", json!({}));
+    model.push_response(NormalizedResponse::text("Ada Lovelace is registered."));
+    let client = RigMcpClient::from_parts(Config::default(), vec![model], vec![stack.server()]);
+    let agent = client.agent("mock").await?.build();
+    println!("Agent: {}", agent.prompt("Register Ada Lovelace").await?);
+
+    let users = reqwest::get(format!("{}/users", stack.http()))
+        .await?
+        .text()
+        .await?;
+    println!("GET /users: {}", users);
+
+    stack.shutdown().await
+}
@@ -0,0 +1,29 @@
+//! Entry point of the generated users service
+//!
+//! Serves the REST API on an ephemeral port and the MCP tools on stdin and
+//! stdout, both over the same in-memory store. The address of the REST API
+//! is written to the file `USERS_SERVICE_ADDR_FILE` names before the MCP
+//! server starts, so it is there once an MCP client has connected.
+
+// The generated API declares more than the service uses
+#![allow(dead_code, unused_imports)]
+
+mod api;
+
+use anyhow::Context;
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let state = api::users::create_api_state();
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    let app = api::users::create_router().with_state(state.clone());
+    tokio::spawn(async move { axum::serve(listener, app).await });
+
+    if let Ok(path) = std::env::var("USERS_SERVICE_ADDR_FILE") {
+        std::fs::write(&path, addr.to_string())
+            .with_context(|| format!("Failed to write the address to {}", path))?;
+    }
+    // Returns when the client closes stdin, taking the REST API with it
+    api::users_mcp::serve_stdio(state).await
+}
@@ -0,0 +1,166 @@
+//! End-to-end test of a generated service driven by rig-mcp agents
+//!
+//! [`Stack::start`] renders the users API of the api-endpoint package from
+//! `fixtures/users.ttl` into a temporary crate, builds it and starts it: the
+//! REST API on an ephemeral port, over the generated in-memory store, and
+//! its MCP tools on stdio through rig-mcp's `serve`. Agents reach the tools
+//! through [`Stack::server`]; [`Stack::http`] is the base URL of the REST
+//! API, to check what the tools did.
+//!
+//! The service builds into a target directory of its own, so it doesn't wait
+//! for the build lock of the `cargo test` running it. Building it fetches
+//! [`SERVICE_DEPS`], which takes a network connection the first time.
+
+use anyhow::{bail, Context, Result};
+use ggen_core::generator::{GenContext, Generator};
+use ggen_core::pipeline::PipelineBuilder;
+use rig_mcp_integration::{RmcpServer, ServerConfig, ToolServer, Transport};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tempfile::TempDir;
+
+/// Templates of the api-endpoint package the service is rendered from
+pub const TEMPLATES: &[&str] = &["api-endpoint.tmpl", "mcp-tools.tmpl", "mcp-server.tmpl"];
+
+/// Dependencies of the generated code besides rig-mcp
+pub const SERVICE_DEPS: &str = r#"anyhow = "1.0"
+async-trait = "0.1"
+axum = "0.7"
+chrono = { version = "0.4", features = ["serde"] }
+http-body-util = "0.1"
+serde = { version = "1.0", features = ["derive"] }
+serde_json = "1.0"
+tokio = { version = "1.47", features = ["full"] }
+tower = { version = "0.5", features = ["util"] }
+tracing = "0.1"
+uuid = { version = "1.18", features = ["v4", "serde"] }
+"#;
+
+/// `src/main.rs` of the service
+const SERVICE_MAIN: &str = include_str!("../fixtures/service/main.rs");
+
+/// Variable naming the file the service writes its REST address to
+const ADDR_FILE_VAR: &str = "USERS_SERVICE_ADDR_FILE";
+
+fn manifest_dir() -> &'static Path {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+}
+
+fn packages_dir() -> PathBuf {
+    manifest_dir().join("../marketplace/packages")
+}
+
+/// Where services are built when the caller has no better place:
+/// `e2e` under `CARGO_TARGET_DIR`, or under the repository's `target`
+pub fn default_target_dir() -> PathBuf {
+    std::env::var_os("CARGO_TARGET_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| manifest_dir().join("../target"))
+        .join("e2e")
+}
+
+/// Render the service into a crate at `dir`
+pub fn generate(dir: &Path) -> Result<()> {
+    let ontology = manifest_dir().join("fixtures/users.ttl");
+    let vars = BTreeMap::from([("name".to_string(), "users".to_string())]);
+    for template in TEMPLATES {
+        let pipeline = PipelineBuilder::new()
+            .with_rdf_file(ontology.display().to_string())
+            .build()?;
+        let template_path = packages_dir().join("api-endpoint").join(template);
+        let ctx = GenContext::new(template_path, dir.to_path_buf()).with_vars(vars.clone());
+        Generator::new(pipeline, ctx)
+            .generate()
+            .with_context(|| format!("Failed to render {}", template))?;
+    }
+
+    std::fs::write(
+        dir.join("src/api/mod.rs"),
+        "pub mod users;\npub mod users_mcp;\n",
+    )?;
+    std::fs::write(dir.join("src/main.rs"), SERVICE_MAIN)?;
+    let manifest = format!(
+        "[package]\nname = \"users-service\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\
+         publish = false\n\n# Not a member of the ggen workspace\n[workspace]\n\n\
+         [dependencies]\n{}rig-mcp-integration = {{ path = {:?}, default-features = false }}\n",
+        SERVICE_DEPS,
+        packages_dir().join("rig-mcp").display().to_string(),
+    );
+    std::fs::write(dir.join("Cargo.toml"), manifest)?;
+    Ok(())
+}
+
+/// Build the crate at `dir` into `target_dir`, returning the service binary
+pub async fn build(dir: &Path, target_dir: &Path) -> Result<PathBuf> {
+    let cargo = std::env::var_os("CARGO").unwrap_or_else(|| "cargo".into());
+    let output = tokio::process::Command::new(cargo)
+        .args(["build", "--quiet", "--manifest-path"])
+        .arg(dir.join("Cargo.toml"))
+        .arg("--target-dir")
+        .arg(target_dir)
+        .output()
+        .await
+        .context("Failed to run cargo")?;
+    if !output.status.success() {
+        bail!(
+            "The generated service doesn't build:\n{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    let binary = format!("users-service{}", std::env::consts::EXE_SUFFIX);
+    Ok(target_dir.join("debug").join(binary))
+}
+
+/// A running users service; see the module docs
+pub struct Stack {
+    server: Arc<RmcpServer>,
+    http: String,
+    /// The crate and address file, removed when the stack is dropped
+    _dir: TempDir,
+}
+
+impl Stack {
+    /// Generate, build and start the service, building into `target_dir`
+    pub async fn start(target_dir: &Path) -> Result<Self> {
+        let dir = tempfile::tempdir()?;
+        generate(dir.path())?;
+        let binary = build(dir.path(), target_dir).await?;
+
+        let addr_file = dir.path().join("addr");
+        let config = ServerConfig {
+            name: "users".to_string(),
+            transport: Transport::Stdio {
+                command: binary.display().to_string(),
+                args: Vec::new(),
+                env: HashMap::from([(ADDR_FILE_VAR.to_string(), addr_file.display().to_string())]),
+            },
+            roots: None,
+            embeddings: None,
+        };
+        let server = Arc::new(RmcpServer::connect(&config).await?);
+        // Written before the MCP server started, so there once connected
+        let addr = std::fs::read_to_string(&addr_file)
+            .context("The service didn't write the address of its REST API")?;
+        Ok(Self {
+            server,
+            http: format!("http://{}", addr.trim()),
+            _dir: dir,
+        })
+    }
+
+    /// The service's MCP tools
+    pub fn server(&self) -> Arc<dyn ToolServer> {
+        self.server.clone()
+    }
+
+    /// Base URL of the service's REST API
+    pub fn http(&self) -> &str {
+        &self.http
+    }
+
+    /// Stop the service and remove its crate
+    pub async fn shutdown(self) -> Result<()> {
+        self.server.shutdown().await
+    }
+}
@@ -0,0 +1,72 @@
+//! An agent creating a user through the generated service's MCP tools
+//!
+//! The whole chain: the ontology rendered into a service, the service built
+//! and started, its tools served over MCP, and a rig-mcp agent calling them
+//! on a scripted model. What the agent did is checked over the service's
+//! REST API, which shares the store behind the tools.
+
+use integration_e2e::Stack;
+use rig_mcp_integration::provider::Role;
+use rig_mcp_integration::testing::MockProvider;
+use rig_mcp_integration::{Config, NormalizedResponse, RigMcpClient};
+use serde_json::{json, Value};
+use std::path::Path;
+use std::sync::Arc;
+
+#[tokio::test]
+#[ignore = "builds the generated service, fetching its dependencies"]
+async fn test_agent_creates_a_user_through_the_generated_service() {
+    let target_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("users-service");
+    let stack = Stack::start(&target_dir).await.unwrap();
+
+    let model = Arc::new(MockProvider::new("mock"));
+    model.push_tool_call(
+        "createUser",
+        json!({"name": "Ada Lovelace", "email": "ada@example.com"}),
+    );
+    model.push_tool_call("listUsers", json!({}));
+    model.push_response(NormalizedResponse::text("Ada Lovelace is registered."));
+    let client =
+        RigMcpClient::from_parts(Config::default(), vec![model.clone()], vec![stack.server()]);
+    let agent = client.agent("mock").await.unwrap().build();
+
+    let mut history = Vec::new();
+    let answer = agent
+        .chat(&mut history, "Register Ada Lovelace, ada@example.com")
+        .await
+        .unwrap();
+    assert_eq!(answer, "Ada Lovelace is registered.");
+    // Both tools answered with the user: created, then listed
+    let results: Vec<_> = history
+        .iter()
+        .filter(|message| message.role == Role::Tool)
+        .collect();
+    assert_eq!(results.len(), 2);
+    for result in results {
+        assert!(
+            result.content.contains("ada@example.com"),
+            "{}",
+            result.content
+        );
+    }
+
+    let listed: Value = reqwest::get(format!("{}/users", stack.http()))
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let users = listed["data"]["items"].as_array().unwrap();
+    assert_eq!(users.len(), 1);
+    assert_eq!(users[0]["name"], "Ada Lovelace");
+    assert_eq!(users[0]["email"], "ada@example.com");
+    let id = users[0]["id"].as_str().unwrap();
+    let fetched = reqwest::get(format!("{}/users/{}", stack.http(), id))
+        .await
+        .unwrap();
+    assert!(fetched.status().is_success());
+
+    stack.shutdown().await.unwrap();
+}
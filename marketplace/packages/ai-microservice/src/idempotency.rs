@@ -0,0 +1,440 @@
+//! Retry-safe generation requests with an `Idempotency-Key` header
+
+use axum::{
+    body::{Body, HttpBody},
+    extract::State,
+    http::{header, HeaderValue, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use ggen_ai::{CacheConfig, LlmCache};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tracing::{info, warn};
+
+use crate::retention::{metadata, Metadata};
+use crate::{api_key, AppState};
+
+/// How long idempotent responses are kept unless configured
+pub(crate) const DEFAULT_IDEMPOTENCY_WINDOW: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Header naming a request that is safe to retry
+const IDEMPOTENCY_KEY: &str = "idempotency-key";
+
+/// Set on responses replayed from the [`IdempotencyStore`]
+const IDEMPOTENT_REPLAYED: &str = "idempotent-replayed";
+
+/// Successful response to a request with an `Idempotency-Key`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct StoredResponse {
+    /// SHA-256 of the request body, to tell a retry from a reused key
+    body_hash: String,
+    status: u16,
+    content_type: Option<String>,
+    /// `Content-Disposition` and `x-ggen-*` headers of raw documents
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+impl StoredResponse {
+    fn replay(self) -> Response {
+        let mut response = (
+            StatusCode::from_u16(self.status).unwrap_or(StatusCode::OK),
+            self.body,
+        )
+            .into_response();
+        let headers = response.headers_mut();
+        if let Some(value) = self
+            .content_type
+            .and_then(|value| HeaderValue::from_str(&value).ok())
+        {
+            headers.insert(header::CONTENT_TYPE, value);
+        }
+        for (name, value) in self.headers {
+            if let (Ok(name), Ok(value)) = (
+                header::HeaderName::from_bytes(name.as_bytes()),
+                HeaderValue::from_str(&value),
+            ) {
+                headers.insert(name, value);
+            }
+        }
+        headers.insert(IDEMPOTENT_REPLAYED, HeaderValue::from_static("true"));
+        response
+    }
+}
+
+/// Responses replayed to retries, in the same [`LlmCache`] backend as
+/// completions, expiring after the idempotency window
+#[derive(Clone)]
+pub(crate) struct IdempotencyStore {
+    cache: Arc<LlmCache>,
+    window: Duration,
+    /// Metadata of the stored responses by key, for purges; the cache only
+    /// knows hashes of the keys
+    stored: Arc<RwLock<HashMap<String, StoredKey>>>,
+    /// Keys of the requests being handled
+    in_flight: Arc<Mutex<HashSet<String>>>,
+}
+
+/// A key reserved while its request is handled; released when dropped, so
+/// also when the request fails or is cancelled
+struct Reservation {
+    in_flight: Arc<Mutex<HashSet<String>>>,
+    key: String,
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        self.in_flight.lock().unwrap().remove(&self.key);
+    }
+}
+
+struct StoredKey {
+    metadata: Metadata,
+    at: chrono::DateTime<chrono::Utc>,
+}
+
+impl IdempotencyStore {
+    pub(crate) fn new(window: Duration) -> Self {
+        Self {
+            cache: Arc::new(LlmCache::with_config(CacheConfig {
+                max_capacity: 10_000,
+                ttl: window,
+                tti: None,
+                spill: None,
+            })),
+            window,
+            stored: Arc::default(),
+            in_flight: Arc::default(),
+        }
+    }
+
+    /// Reserve `key` for a request about to be handled; `None` while another
+    /// request with the key is
+    fn reserve(&self, key: &str) -> Option<Reservation> {
+        let mut in_flight = self.in_flight.lock().unwrap();
+        in_flight.insert(key.to_string()).then(|| Reservation {
+            in_flight: self.in_flight.clone(),
+            key: key.to_string(),
+        })
+    }
+
+    /// Keys are scoped to the caller and the endpoint; the cache hashes them
+    pub(crate) fn key(api_key: &str, path: &str, idempotency_key: &str) -> String {
+        format!("{}\n{}\n{}", api_key, path, idempotency_key)
+    }
+
+    pub(crate) async fn get(&self, key: &str) -> Option<StoredResponse> {
+        let stored = self.cache.get(key, "idempotency").await?;
+        serde_json::from_str(&stored).ok()
+    }
+
+    async fn put(&self, key: &str, response: &StoredResponse, metadata: Metadata) {
+        if let Ok(stored) = serde_json::to_string(response) {
+            self.cache.insert(key, "idempotency", stored, None).await;
+            let at = chrono::Utc::now();
+            self.stored
+                .write()
+                .await
+                .insert(key.to_string(), StoredKey { metadata, at });
+        }
+    }
+
+    /// Remove the unexpired responses whose metadata `matches` selects,
+    /// returning how many there were
+    pub(crate) async fn remove_where(&self, matches: impl Fn(&Metadata) -> bool) -> usize {
+        let now = chrono::Utc::now();
+        let mut stored = self.stored.write().await;
+        let keys: Vec<String> = stored
+            .iter()
+            .filter(|(_, key)| matches(&key.metadata))
+            .map(|(key, _)| key.clone())
+            .collect();
+        let mut removed = 0;
+        for key in keys {
+            if stored
+                .remove(&key)
+                .is_some_and(|entry| self.is_live(&entry, now))
+            {
+                self.cache.remove(&key, "idempotency").await;
+                removed += 1;
+            }
+        }
+        removed
+    }
+
+    /// Forget the metadata of responses the cache has expired
+    pub(crate) async fn forget_expired(&self, now: chrono::DateTime<chrono::Utc>) {
+        self.stored
+            .write()
+            .await
+            .retain(|_, key| self.is_live(key, now));
+    }
+
+    fn is_live(&self, key: &StoredKey, now: chrono::DateTime<chrono::Utc>) -> bool {
+        (now - key.at)
+            .to_std()
+            .map_or(true, |age| age < self.window)
+    }
+}
+
+/// Replay the stored response of a retried request, and store the response
+/// of a first one
+///
+/// Only successful responses are stored, so a request that failed can be
+/// retried with the same key; streamed ones aren't either. Reusing a key for
+/// a different body is a conflict, and so is a retry arriving while the
+/// first request is still handled.
+pub(crate) async fn idempotency(
+    State(state): State<AppState>, request: Request<Body>, next: Next,
+) -> Response {
+    let Some(idempotency_key) = request
+        .headers()
+        .get(IDEMPOTENCY_KEY)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+    else {
+        return next.run(request).await;
+    };
+    // Each representation of a response is stored apart
+    let scope = match request.headers().get(header::ACCEPT) {
+        Some(accept) => format!("{} {}", request.uri().path(), accept.to_str().unwrap_or("")),
+        None => request.uri().path().to_string(),
+    };
+    let key = IdempotencyStore::key(api_key(request.headers()), &scope, &idempotency_key);
+    let metadata = metadata(request.headers());
+
+    let (parts, body) = request.into_parts();
+    let body = match axum::body::to_bytes(body, state.max_request_bytes).await {
+        Ok(body) => body,
+        Err(err) => {
+            let error = json!({"kind": "payload_too_large", "message": err.to_string()});
+            return (StatusCode::PAYLOAD_TOO_LARGE, Json(json!({ "error": error })))
+                .into_response();
+        }
+    };
+    let body_hash = format!("{:x}", Sha256::digest(&body));
+
+    if let Some(stored) = state.idempotency.get(&key).await {
+        return replay(stored, &body_hash, &idempotency_key);
+    }
+    let Some(reservation) = state.idempotency.reserve(&key) else {
+        let message = format!(
+            "A request with Idempotency-Key '{}' is still being handled",
+            idempotency_key
+        );
+        warn!("{}", message);
+        let error = json!({"kind": "idempotency_in_progress", "message": message});
+        return (StatusCode::CONFLICT, Json(json!({ "error": error }))).into_response();
+    };
+    // Stored by a request that finished between the lookup and reserving
+    if let Some(stored) = state.idempotency.get(&key).await {
+        return replay(stored, &body_hash, &idempotency_key);
+    }
+
+    let response = next.run(Request::from_parts(parts, Body::from(body))).await;
+    let streamed = response.body().size_hint().exact().is_none();
+    if !response.status().is_success() || streamed {
+        return response;
+    }
+    let (parts, body) = response.into_parts();
+    let body = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(body) => body,
+        Err(err) => return (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+    };
+    if let Ok(text) = std::str::from_utf8(&body) {
+        let stored = StoredResponse {
+            body_hash,
+            status: parts.status.as_u16(),
+            content_type: parts
+                .headers
+                .get(header::CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string),
+            headers: parts
+                .headers
+                .iter()
+                .filter(|(name, _)| {
+                    *name == header::CONTENT_DISPOSITION || name.as_str().starts_with("x-ggen-")
+                })
+                .filter_map(|(name, value)| {
+                    Some((name.to_string(), value.to_str().ok()?.to_string()))
+                })
+                .collect(),
+            body: text.to_string(),
+        };
+        state.idempotency.put(&key, &stored, metadata).await;
+    }
+    drop(reservation);
+    Response::from_parts(parts, Body::from(body))
+}
+
+/// The stored response to a retry, or a conflict if the retry's body isn't
+/// the stored request's
+fn replay(stored: StoredResponse, body_hash: &str, idempotency_key: &str) -> Response {
+    if stored.body_hash != body_hash {
+        let message = format!(
+            "Idempotency-Key '{}' was already used with a different request body",
+            idempotency_key
+        );
+        warn!("{}", message);
+        let error = json!({"kind": "idempotency_conflict", "message": message});
+        return (StatusCode::CONFLICT, Json(json!({ "error": error }))).into_response();
+    }
+    info!(
+        "Replaying response for Idempotency-Key '{}'",
+        idempotency_key
+    );
+    stored.replay()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{calls, counting_state, post, post_raw, post_with_headers, CountingClient};
+    use ggen_ai::{LlmClient, MockClient};
+
+    const ONTOLOGY: &str = "/api/v1/ontology/generate";
+
+    #[tokio::test]
+    async fn test_retries_with_an_idempotency_key_are_replayed() {
+        let (state, client) = counting_state(Duration::from_secs(60));
+        let body = json!({"domain": "orders", "concepts": ["Order"]});
+        let key = [("idempotency-key", "order-1"), ("x-api-key", "team-a")];
+
+        let (status, headers, first) =
+            post_with_headers(state.clone(), ONTOLOGY, &key, body.clone()).await;
+        assert_eq!(status, StatusCode::OK, "{}", first);
+        assert!(!headers.contains_key(IDEMPOTENT_REPLAYED));
+        let (status, headers, retry) =
+            post_with_headers(state.clone(), ONTOLOGY, &key, body.clone()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(headers[IDEMPOTENT_REPLAYED], "true");
+        assert_eq!(retry, first);
+        assert_eq!(calls(&client), 1);
+
+        // Keys are per caller
+        let other = [("idempotency-key", "order-1"), ("x-api-key", "team-b")];
+        post_with_headers(state.clone(), ONTOLOGY, &other, body.clone()).await;
+        assert_eq!(calls(&client), 2);
+        // and requests without a key are never replayed
+        post(state, ONTOLOGY, body).await;
+        assert_eq!(calls(&client), 3);
+    }
+
+    #[tokio::test]
+    async fn test_raw_responses_are_replayed_per_media_type() {
+        let (state, client) = counting_state(Duration::from_secs(60));
+        let body = json!({"domain": "orders", "concepts": ["Order"]});
+        let turtle = [("idempotency-key", "order-1"), ("accept", "text/turtle")];
+
+        let (_, first_headers, first) =
+            post_raw(state.clone(), ONTOLOGY, &turtle, body.clone()).await;
+        let (status, headers, retry) =
+            post_raw(state.clone(), ONTOLOGY, &turtle, body.clone()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(headers[IDEMPOTENT_REPLAYED], "true");
+        assert_eq!(retry, first);
+        for name in ["content-type", "content-disposition", "x-ggen-classes"] {
+            assert_eq!(headers[name], first_headers[name], "{}", name);
+        }
+        assert_eq!(calls(&client), 1);
+
+        // The JSON answer to the same key isn't the stored turtle
+        let key = [("idempotency-key", "order-1")];
+        let (status, _, json) = post_with_headers(state, ONTOLOGY, &key, body).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(json["classes"], json!(["Order"]));
+        assert_eq!(calls(&client), 2);
+    }
+
+    #[tokio::test]
+    async fn test_reusing_an_idempotency_key_for_another_body_conflicts() {
+        let (state, client) = counting_state(Duration::from_secs(60));
+        let key = [("idempotency-key", "order-1")];
+        post_with_headers(
+            state.clone(),
+            ONTOLOGY,
+            &key,
+            json!({"domain": "orders", "concepts": ["Order"]}),
+        )
+        .await;
+
+        let (status, _, body) = post_with_headers(
+            state,
+            ONTOLOGY,
+            &key,
+            json!({"domain": "orders", "concepts": ["Invoice"]}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(body["error"]["kind"], "idempotency_conflict");
+        assert_eq!(calls(&client), 1);
+    }
+
+    #[tokio::test]
+    async fn test_idempotency_keys_expire() {
+        let (state, client) = counting_state(Duration::from_millis(50));
+        let body = json!({"domain": "orders", "concepts": ["Order"]});
+        let key = [("idempotency-key", "order-1")];
+        post_with_headers(state.clone(), ONTOLOGY, &key, body.clone()).await;
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let (status, headers, _) = post_with_headers(state, ONTOLOGY, &key, body).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(!headers.contains_key(IDEMPOTENT_REPLAYED));
+        assert_eq!(calls(&client), 2);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_retries_run_the_request_once() {
+        let client = Arc::new(CountingClient {
+            inner: MockClient::with_response(
+                "@prefix ex: <http://example.org/> .\nex:Order a ex:Class .\n",
+            ),
+            calls: Default::default(),
+            delay: Duration::from_millis(200),
+        });
+        let state = AppState::new(client.clone() as Arc<dyn LlmClient>);
+        let body = json!({"domain": "orders", "concepts": ["Order"]});
+        let key = [("idempotency-key", "order-1")];
+
+        let ((first, _, _), (second, _, retried)) = tokio::join!(
+            post_with_headers(state.clone(), ONTOLOGY, &key, body.clone()),
+            post_with_headers(state.clone(), ONTOLOGY, &key, body.clone()),
+        );
+        let mut statuses = [first, second];
+        statuses.sort();
+        assert_eq!(statuses, [StatusCode::OK, StatusCode::CONFLICT]);
+        if second == StatusCode::CONFLICT {
+            assert_eq!(retried["error"]["kind"], "idempotency_in_progress");
+        }
+        assert_eq!(calls(&client), 1);
+
+        // Once the first is done, retries are replayed
+        let (status, headers, _) = post_with_headers(state, ONTOLOGY, &key, body).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(headers[IDEMPOTENT_REPLAYED], "true");
+        assert_eq!(calls(&client), 1);
+    }
+
+    #[tokio::test]
+    async fn test_oversized_bodies_are_rejected_before_the_handler() {
+        let (state, client) = counting_state(Duration::from_secs(60));
+        let state = state.with_max_request_bytes(64);
+        let body = json!({"domain": "orders", "concepts": ["Order"; 16]});
+        let key = [("idempotency-key", "order-1")];
+
+        let (status, _, body) = post_with_headers(state.clone(), ONTOLOGY, &key, body).await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(body["error"]["kind"], "payload_too_large");
+        assert_eq!(calls(&client), 0);
+    }
+}
@@ -0,0 +1,439 @@
+//! Bulk embedding of NDJSON uploads into a document index, with streamed
+//! progress
+
+use axum::{
+    body::{Body, BodyDataStream},
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use futures::stream::{self, Stream, StreamExt};
+use rig_mcp_integration::{EmbeddingModel, ProviderConfig};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tracing::{info, warn};
+
+use crate::retention::{metadata, Metadata};
+use crate::{embedding_model_from_env, env_count, AppState};
+
+/// Limits of bulk document ingestion
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct IngestConfig {
+    /// Embedding calls in flight at once
+    concurrency: usize,
+    /// Lines per embedding call, and so the most texts sent in one
+    batch_size: usize,
+}
+
+impl Default for IngestConfig {
+    fn default() -> Self {
+        Self {
+            concurrency: 4,
+            batch_size: 64,
+        }
+    }
+}
+
+/// What the ingest endpoint needs
+#[derive(Clone)]
+pub(crate) struct Ingest {
+    pub(crate) embeddings: Arc<dyn EmbeddingModel>,
+    pub(crate) config: IngestConfig,
+    pub(crate) documents: DocumentIndex,
+}
+
+/// Ingested documents with their vectors, by id
+#[derive(Clone, Default)]
+pub(crate) struct DocumentIndex(Arc<RwLock<HashMap<String, IndexedDocument>>>);
+
+// Nothing searches the index yet, so only the tests read the text and
+// vector
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub(crate) struct IndexedDocument {
+    pub(crate) text: String,
+    pub(crate) vector: Vec<f32>,
+    /// Of the upload
+    pub(crate) metadata: Metadata,
+    pub(crate) indexed_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl DocumentIndex {
+    /// Add documents, replacing those with the same id
+    pub(crate) async fn insert(
+        &self, documents: impl IntoIterator<Item = (String, IndexedDocument)>,
+    ) {
+        self.0.write().await.extend(documents);
+    }
+
+    async fn len(&self) -> usize {
+        self.0.read().await.len()
+    }
+
+    /// Remove the documents `matches` selects, returning how many there were
+    pub(crate) async fn remove_where(&self, matches: impl Fn(&IndexedDocument) -> bool) -> usize {
+        let mut documents = self.0.write().await;
+        let before = documents.len();
+        documents.retain(|_, document| !matches(document));
+        before - documents.len()
+    }
+
+    #[cfg(test)]
+    pub(crate) async fn get(&self, id: &str) -> Option<IndexedDocument> {
+        self.0.read().await.get(id).cloned()
+    }
+}
+
+/// Bulk document ingestion, enabled by `INGEST_MODEL`, e.g.
+/// `openai/text-embedding-3-small`, with `INGEST_API_KEY`,
+/// `INGEST_CONCURRENCY` and `INGEST_BATCH_SIZE`
+pub(crate) fn ingest_from_env() -> anyhow::Result<Option<(ProviderConfig, IngestConfig)>> {
+    let Some(embeddings) = embedding_model_from_env("INGEST")? else {
+        return Ok(None);
+    };
+    let mut config = IngestConfig::default();
+    if let Some(concurrency) = env_count("INGEST_CONCURRENCY")? {
+        config.concurrency = concurrency;
+    }
+    if let Some(batch_size) = env_count("INGEST_BATCH_SIZE")? {
+        config.batch_size = batch_size;
+    }
+    Ok(Some((embeddings, config)))
+}
+
+/// A line of an ingest upload
+#[derive(Debug, Deserialize)]
+struct IngestDocument {
+    id: String,
+    text: String,
+}
+
+/// A line that wasn't ingested, with the document's id when it has one
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct IngestFailure {
+    line: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+    error: String,
+}
+
+/// A line of the ingest response
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum IngestEvent {
+    Failure(IngestFailure),
+    /// Running totals, after every batch
+    Progress {
+        processed: usize,
+        failed: usize,
+    },
+    /// The rest of the upload couldn't be read
+    Error {
+        message: String,
+    },
+    /// With the size of the index afterwards
+    Done {
+        processed: usize,
+        failed: usize,
+        indexed: usize,
+    },
+}
+
+/// A line of an upload, read
+enum IngestLine {
+    Document {
+        line: usize,
+        id: String,
+        text: String,
+    },
+    Failed(IngestFailure),
+    Unreadable(String),
+}
+
+fn parse_ingest_line(line: usize, text: &str) -> IngestLine {
+    let failed =
+        |id: Option<String>, error: String| IngestLine::Failed(IngestFailure { line, id, error });
+    let value: Value = match serde_json::from_str(text) {
+        Ok(value) => value,
+        Err(err) => return failed(None, format!("Invalid JSON: {}", err)),
+    };
+    let id = value.get("id").and_then(Value::as_str).map(str::to_string);
+    match serde_json::from_value::<IngestDocument>(value) {
+        Ok(document) if document.text.trim().is_empty() => {
+            failed(Some(document.id), "text is empty".to_string())
+        }
+        Ok(IngestDocument { id, text }) => IngestLine::Document { line, id, text },
+        Err(err) => failed(id, err.to_string()),
+    }
+}
+
+/// The lines of `body` as they arrive, numbered from 1; nothing is read
+/// after the body fails
+fn ndjson_lines(body: Body) -> impl Stream<Item = Result<(usize, String), axum::Error>> {
+    struct Lines {
+        chunks: BodyDataStream,
+        buffer: Vec<u8>,
+        number: usize,
+        done: bool,
+    }
+    let lines = Lines {
+        chunks: body.into_data_stream(),
+        buffer: Vec::new(),
+        number: 0,
+        done: false,
+    };
+    stream::unfold(lines, |mut lines| async move {
+        loop {
+            let line = match lines.buffer.iter().position(|byte| *byte == b'\n') {
+                Some(end) => lines.buffer.drain(..=end).collect::<Vec<u8>>(),
+                None if lines.done && lines.buffer.is_empty() => return None,
+                None if lines.done => std::mem::take(&mut lines.buffer),
+                None => {
+                    match lines.chunks.next().await {
+                        Some(Ok(bytes)) => lines.buffer.extend_from_slice(&bytes),
+                        Some(Err(err)) => {
+                            lines.done = true;
+                            lines.buffer.clear();
+                            return Some((Err(err), lines));
+                        }
+                        None => lines.done = true,
+                    }
+                    continue;
+                }
+            };
+            lines.number += 1;
+            let text = String::from_utf8_lossy(&line).trim().to_string();
+            return Some((Ok((lines.number, text)), lines));
+        }
+    })
+}
+
+/// What came of one batch of lines
+#[derive(Default)]
+struct IngestBatch {
+    processed: usize,
+    failures: Vec<IngestFailure>,
+    unreadable: Option<String>,
+}
+
+impl Ingest {
+    /// Embed the documents of `lines` in one call and index them; if the
+    /// call fails, every document of the batch is reported as failed
+    async fn embed_batch(&self, lines: Vec<IngestLine>, metadata: &Metadata) -> IngestBatch {
+        let mut batch = IngestBatch::default();
+        let mut documents = Vec::new();
+        for line in lines {
+            match line {
+                IngestLine::Document { line, id, text } => documents.push((line, id, text)),
+                IngestLine::Failed(failure) => batch.failures.push(failure),
+                IngestLine::Unreadable(message) => batch.unreadable = Some(message),
+            }
+        }
+        if documents.is_empty() {
+            return batch;
+        }
+        let texts: Vec<String> = documents.iter().map(|(_, _, text)| text.clone()).collect();
+        let error = match self.embeddings.embed_texts(&texts).await {
+            Ok(vectors) if vectors.len() == documents.len() => {
+                batch.processed = documents.len();
+                let indexed_at = chrono::Utc::now();
+                let indexed = documents
+                    .into_iter()
+                    .zip(vectors)
+                    .map(|((_, id, text), vector)| {
+                        let document = IndexedDocument {
+                            text,
+                            vector,
+                            metadata: metadata.clone(),
+                            indexed_at,
+                        };
+                        (id, document)
+                    });
+                self.documents.insert(indexed).await;
+                return batch;
+            }
+            Ok(vectors) => format!(
+                "The embedding model returned {} vectors for {} texts",
+                vectors.len(),
+                documents.len()
+            ),
+            Err(err) => format!("Embedding failed: {:#}", err),
+        };
+        warn!("{}", error);
+        batch
+            .failures
+            .extend(documents.into_iter().map(|(line, id, _)| IngestFailure {
+                line,
+                id: Some(id),
+                error: error.clone(),
+            }));
+        batch
+    }
+}
+
+/// Embed an NDJSON upload of `{"id", "text"}` lines into the document index
+///
+/// The upload is read only as fast as batches are embedded, with at most
+/// `concurrency` embedding calls of `batch_size` lines in flight. The
+/// response streams NDJSON events as batches finish: a `failure` for every
+/// line that wasn't ingested, then `progress` with the running totals, and
+/// `done` at the end. Failed lines don't stop the ingest. The documents get
+/// the metadata of the upload.
+pub(crate) async fn ingest_documents(
+    State(state): State<AppState>, headers: HeaderMap, body: Body,
+) -> Response {
+    let Some(ingest) = state.ingest else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let IngestConfig {
+        concurrency,
+        batch_size,
+    } = ingest.config;
+    let processed = Arc::new(AtomicUsize::new(0));
+    let failed = Arc::new(AtomicUsize::new(0));
+    let (batch_processed, batch_failed) = (processed.clone(), failed.clone());
+    let documents = ingest.documents.clone();
+    let metadata = Arc::new(metadata(&headers));
+
+    let events = ndjson_lines(body)
+        .filter_map(|line| async move {
+            match line {
+                Ok((_, text)) if text.is_empty() => None,
+                Ok((number, text)) => Some(parse_ingest_line(number, &text)),
+                Err(err) => Some(IngestLine::Unreadable(err.to_string())),
+            }
+        })
+        .chunks(batch_size)
+        .map(move |lines| {
+            let (ingest, metadata) = (ingest.clone(), metadata.clone());
+            async move { ingest.embed_batch(lines, &metadata).await }
+        })
+        .buffer_unordered(concurrency)
+        .flat_map(move |batch| {
+            let processed =
+                batch_processed.fetch_add(batch.processed, Ordering::SeqCst) + batch.processed;
+            let failed = batch_failed.fetch_add(batch.failures.len(), Ordering::SeqCst)
+                + batch.failures.len();
+            let mut events: Vec<IngestEvent> = batch
+                .failures
+                .into_iter()
+                .map(IngestEvent::Failure)
+                .collect();
+            if let Some(message) = batch.unreadable {
+                events.push(IngestEvent::Error { message });
+            }
+            events.push(IngestEvent::Progress { processed, failed });
+            stream::iter(events)
+        })
+        .chain(stream::once(async move {
+            let processed = processed.load(Ordering::SeqCst);
+            let failed = failed.load(Ordering::SeqCst);
+            let indexed = documents.len().await;
+            info!(processed, failed, indexed, "Ingest finished");
+            IngestEvent::Done {
+                processed,
+                failed,
+                indexed,
+            }
+        }));
+
+    let body = Body::from_stream(events.map(|event| {
+        let mut line = serde_json::to_vec(&event).expect("ingest events serialize");
+        line.push(b'\n');
+        Ok::<_, Infallible>(line)
+    }));
+    ([(header::CONTENT_TYPE, "application/x-ndjson")], body).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app;
+    use crate::tests::{mock_state, CountingEmbeddings};
+    use axum::http::Request;
+    use serde_json::json;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_ingest_streams_progress_and_reports_failed_lines() {
+        let mut upload = String::new();
+        for line in 1..=1000 {
+            let text = match line {
+                10 => "not json".to_string(),
+                500 => json!({"id": "doc-500"}).to_string(),
+                777 => json!({"id": "doc-777", "text": "boom"}).to_string(),
+                _ => json!({"id": format!("doc-{}", line), "text": format!("text {}", line)})
+                    .to_string(),
+            };
+            upload.push_str(&text);
+            upload.push('\n');
+        }
+        // Chunk boundaries fall mid-line
+        let chunks: Vec<Result<Vec<u8>, Infallible>> = upload
+            .as_bytes()
+            .chunks(37)
+            .map(|chunk| Ok(chunk.to_vec()))
+            .collect();
+        let embeddings = Arc::new(CountingEmbeddings::default());
+        let config = IngestConfig {
+            concurrency: 3,
+            batch_size: 50,
+        };
+        let state = mock_state().with_ingest(embeddings.clone(), config);
+        let documents = state.ingest.as_ref().unwrap().documents.clone();
+
+        let request = Request::post("/api/v1/embeddings/ingest")
+            .header("content-type", "application/x-ndjson")
+            .body(Body::from_stream(stream::iter(chunks)))
+            .unwrap();
+        let response = app(state).oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "application/x-ndjson"
+        );
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let events: Vec<Value> = body
+            .split(|byte| *byte == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| serde_json::from_slice(line).unwrap())
+            .collect();
+
+        assert_eq!(
+            events.last().unwrap(),
+            &json!({"event": "done", "processed": 948, "failed": 52, "indexed": 948})
+        );
+        let progress: Vec<&Value> = events.iter().filter(|e| e["event"] == "progress").collect();
+        assert_eq!(progress.len(), 20);
+        assert_eq!(progress.last().unwrap()["processed"], 948);
+
+        let failures: HashMap<u64, &Value> = events
+            .iter()
+            .filter(|e| e["event"] == "failure")
+            .map(|e| (e["line"].as_u64().unwrap(), e))
+            .collect();
+        assert_eq!(failures.len(), 52);
+        assert!(failures[&10].get("id").is_none());
+        assert_eq!(failures[&500]["id"], "doc-500");
+        assert!(failures[&500]["error"].as_str().unwrap().contains("text"));
+        // The failed call takes the rest of its batch with it
+        assert_eq!(failures[&777]["id"], "doc-777");
+        assert_eq!(failures[&751]["id"], "doc-751");
+        let error = failures[&800]["error"].as_str().unwrap();
+        assert!(error.contains("model overloaded"), "{}", error);
+
+        assert_eq!(documents.len().await, 948);
+        assert_eq!(documents.get("doc-1").await.unwrap().vector, vec![6.0]);
+        assert!(documents.get("doc-760").await.is_none());
+        let max_in_flight = embeddings.max_in_flight.load(Ordering::SeqCst);
+        assert!((2..=3).contains(&max_in_flight), "{}", max_in_flight);
+        assert_eq!(embeddings.max_batch.load(Ordering::SeqCst), 50);
+    }
+}
@@ -7,23 +7,91 @@
 //! - Response streaming
 //! - Caching and optimization
 //! - REST API with AI endpoints
+//! - Provider, cache and trace export settings from the shared ggen-config
+//!   file named by `GGEN_CONFIG`, `ggen.toml` by default
+//! - OpenTelemetry trace export when `OTEL_EXPORTER_OTLP_ENDPOINT` is set
+//! - MCP tool listing and direct invocation for operators, when enabled
+//! - Request bodies checked against the JSON Schemas of the OpenAPI document
+//! - Retry-safe generation requests with an `Idempotency-Key` header
+//! - Duplicate concepts in generated ontologies reported or merged
+//! - Line-anchored review comments from `/refactor` with `mode: "review"`
+//! - Response language and tone set per service, per API key or per request
+//! - Bulk embedding of NDJSON uploads into a document index, with streamed
+//!   progress
+//! - Retention limits and per-tenant purges of stored data, recorded in an
+//!   audit log
+//! - Cached completions redacted by the rig-mcp redaction rules in
+//!   `REDACTION_CONFIG`
+//! - Generated templates and ontologies as bare documents, by `Accept`
+//!   header, with their metadata in `x-ggen-*` headers
+//! - An offline self-test of the project's ggen setup for operators, when
+//!   `SELFTEST_ADMIN_KEY` is set
+//! - Tenants bringing their own provider credentials, with usage and cost
+//!   accounted by tenant and by whose credentials were used, served to
+//!   operators when `USAGE_ADMIN_KEY` is set
+
+mod idempotency;
+mod ingest;
+mod negotiation;
+mod retention;
+mod styles;
+mod tenants;
+mod tools;
+mod validation;
 
 use axum::{
-    extract::{Path, Query, State},
-    http::StatusCode,
+    async_trait,
+    extract::{DefaultBodyLimit, Query, State},
+    http::{header, HeaderMap, Request, StatusCode},
+    middleware,
     response::{IntoResponse, Response},
     routing::{get, post},
     Json, Router,
 };
 use ggen_ai::{
-    GenAiClient, LlmClient, LlmConfig, LlmProvider, TemplateGenerator, RefactorAssistant,
-    CacheConfig, OntologyGenerator,
+    AnchoredComment, CodeReview, ConceptDeduplicator, ContextConfig, ContextSelector,
+    DuplicateConcept, DuplicateConfig, GenAiClient, IriRename, LlmClient, LlmConfig, MergeStrategy,
+    NamespacePolicy, OntologyGenerator, ProjectContext, RefactorAssistant, ResponseStyle,
+    ReviewComment, TemplateGenerator,
+};
+use ggen_config::{CacheSettings, SharedConfig};
+use ggen_core::selftest::{SelfTest, Severity};
+use ggen_core::template_lint::{lint_template, LintFinding};
+use ggen_core::tera_env::build_tera_minimal;
+use rig_mcp_integration::health::{HealthRegistry, HealthReport, HealthStatus, Probe};
+use rig_mcp_integration::telemetry::{self, TelemetryConfig, TelemetryGuard};
+use rig_mcp_integration::{
+    AuditLog, CredentialsCheck, EmbeddingModel, HttpEmbedder, ProviderConfig, RedactionConfig,
+    RedactionRuleSet, ReqwestTransport, RigMcpClient, Violation,
 };
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::RwLock;
 use tower_http::cors::CorsLayer;
-use tracing::{info, warn};
+use tower_http::trace::TraceLayer;
+use tracing::{info, warn, Span};
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+
+use idempotency::{idempotency, IdempotencyStore, DEFAULT_IDEMPOTENCY_WINDOW};
+use ingest::{ingest_documents, ingest_from_env, DocumentIndex, Ingest, IngestConfig};
+use negotiation::{extension, file_stem, RawDocument, Representation};
+use retention::{
+    metadata, purge, purge_periodically, retention_from_env, Metadata, Retention, RetentionConfig,
+};
+use styles::{response_styles_from_env, ResponseStyles, StyleOverride};
+use tenants::{
+    model_aliases_from_env, scope_api_key, tenants_from_env, usage_report, ModelSelection,
+    TenantConfig, TenantRouter, UsageApi, UsageLedger, REQUEST_MODEL,
+};
+use tools::{invoke_tool, list_tools, ToolsApi, ToolsApiConfig};
+use validation::{
+    openapi, InvalidRequest, RequestSchema, RequestValidation, UnknownFields, Validated,
+};
 
 #[derive(Clone)]
 struct AppState {
@@ -32,13 +100,298 @@ struct AppState {
     refactor_assistant: Arc<RefactorAssistant>,
     ontology_gen: Arc<OntologyGenerator>,
     cache: Arc<RwLock<Vec<CachedResponse>>>,
+    cache_settings: CacheSettings,
+    /// Applied to completions before they are cached
+    cache_redaction: Option<Arc<RedactionRuleSet>>,
+    /// Tenants by the API keys whose completions their own credentials pay
+    /// for, to keep their cached completions apart
+    credential_owners: Arc<HashMap<String, String>>,
+    readiness: HealthRegistry,
+    /// Set only when `tools_api.enabled`
+    tools: Option<ToolsApi>,
+    validation: Arc<RequestValidation>,
+    /// Largest request body read, by extractors and the idempotency layer
+    max_request_bytes: usize,
+    idempotency: IdempotencyStore,
+    styles: Arc<ResponseStyles>,
+    /// Set only when an ingest embedding model is configured
+    ingest: Option<Ingest>,
+    retention: Retention,
+    /// Set only when `SELFTEST_ADMIN_KEY` is
+    selftest: Option<SelfTestApi>,
+    /// Set only when `USAGE_ADMIN_KEY` is
+    usage: Option<UsageApi>,
+    /// Resolves the models requests ask for
+    models: Arc<ModelSelection>,
+}
+
+/// Settings read from the environment at startup
+#[derive(Debug, Clone, Default)]
+struct ServiceConfig {
+    tools_api: ToolsApiConfig,
+    /// Reject request bodies with fields their schema doesn't know, instead
+    /// of naming them in an `x-unknown-fields` response header
+    strict_validation: bool,
+    /// Namespace generated ontologies are moved into
+    ontology_namespace: Option<NamespacePolicy>,
+    /// How long responses are replayed to retries with the same
+    /// `Idempotency-Key`
+    idempotency_window: Duration,
+    /// Largest request body accepted
+    max_request_bytes: usize,
+    /// Embedding model and settings for finding duplicate concepts in
+    /// generated ontologies
+    ontology_duplicates: Option<(ProviderConfig, DuplicateConfig)>,
+    /// Embedding model and settings for picking project files as
+    /// exemplars for template generation
+    template_context: Option<(ProviderConfig, ContextConfig)>,
+    /// Response language and tone unless the API key or request says
+    /// otherwise
+    response_styles: ResponseStyles,
+    /// Embedding model and limits for bulk document ingestion
+    ingest: Option<(ProviderConfig, IngestConfig)>,
+    retention: RetentionConfig,
+    /// Redaction rules for cached completions; none unless configured with
+    /// the `cache` flag on
+    cache_redaction: Option<Arc<RedactionRuleSet>>,
+    selftest: Option<SelfTestApi>,
+    /// Callers by API key, with the provider credentials they bring, and
+    /// how their usage is accounted
+    tenants: TenantConfig,
+    /// `provider/model` pairs or other aliases by alias name, for requests
+    /// picking a model
+    model_aliases: HashMap<String, String>,
+}
+
+impl ServiceConfig {
+    /// `TOOLS_API_ENABLED`, `TOOLS_API_ADMIN_KEY`,
+    /// `STRICT_REQUEST_VALIDATION`, `ONTOLOGY_BASE_IRI`, `ONTOLOGY_PREFIX`,
+    /// `IDEMPOTENCY_WINDOW_SECS`, `MAX_REQUEST_BYTES`, `REDACTION_CONFIG`,
+    /// the `ONTOLOGY_DEDUP_*`, `TEMPLATE_CONTEXT_*`, `INGEST_*`, `RETENTION_*`
+    /// and `SELFTEST_*` variables, the response style variables, the tenant
+    /// variables and `MODEL_ALIASES`
+    fn from_env() -> anyhow::Result<Self> {
+        let enabled = env_flag("TOOLS_API_ENABLED")?;
+        let admin_key = std::env::var("TOOLS_API_ADMIN_KEY").ok();
+        if enabled && admin_key.as_deref().is_none_or(str::is_empty) {
+            anyhow::bail!("TOOLS_API_ENABLED requires a non-empty TOOLS_API_ADMIN_KEY");
+        }
+        let ontology_namespace = match (
+            std::env::var("ONTOLOGY_BASE_IRI"),
+            std::env::var("ONTOLOGY_PREFIX"),
+        ) {
+            (Ok(base_iri), Ok(prefix)) => Some(NamespacePolicy::new(base_iri, prefix)),
+            (Err(_), Err(_)) => None,
+            _ => anyhow::bail!("ONTOLOGY_BASE_IRI and ONTOLOGY_PREFIX must be set together"),
+        };
+        Ok(Self {
+            tools_api: ToolsApiConfig { enabled, admin_key },
+            strict_validation: env_flag("STRICT_REQUEST_VALIDATION")?,
+            ontology_namespace,
+            idempotency_window: match std::env::var("IDEMPOTENCY_WINDOW_SECS") {
+                Ok(value) => Duration::from_secs(value.parse().map_err(|_| {
+                    anyhow::anyhow!(
+                        "IDEMPOTENCY_WINDOW_SECS must be a number of seconds, got '{}'",
+                        value
+                    )
+                })?),
+                Err(_) => DEFAULT_IDEMPOTENCY_WINDOW,
+            },
+            max_request_bytes: env_count("MAX_REQUEST_BYTES")?.unwrap_or(DEFAULT_MAX_REQUEST_BYTES),
+            ontology_duplicates: ontology_duplicates_from_env()?,
+            template_context: template_context_from_env()?,
+            response_styles: response_styles_from_env()?,
+            ingest: ingest_from_env()?,
+            retention: retention_from_env()?,
+            cache_redaction: match std::env::var("REDACTION_CONFIG") {
+                Ok(value) => cache_redaction(&value)?,
+                Err(_) => None,
+            },
+            selftest: selftest_from_env(),
+            tenants: tenants_from_env()?,
+            model_aliases: model_aliases_from_env()?,
+        })
+    }
+}
+
+/// The rules applied to cached completions under `REDACTION_CONFIG`, a JSON
+/// object laid out like the `redaction` section of the rig-mcp config
+fn cache_redaction(value: &str) -> anyhow::Result<Option<Arc<RedactionRuleSet>>> {
+    let config: RedactionConfig = serde_json::from_str(value)
+        .map_err(|err| anyhow::anyhow!("REDACTION_CONFIG is invalid: {}", err))?;
+    let rules = RedactionRuleSet::compile(&config.rules)
+        .map_err(|err| anyhow::anyhow!("REDACTION_CONFIG is invalid: {}", err))?;
+    Ok((config.cache && !rules.is_empty()).then(|| Arc::new(rules)))
+}
+
+/// Duplicate concept detection, enabled by `ONTOLOGY_DEDUP_MODEL`, e.g.
+/// `openai/text-embedding-3-small`, with `ONTOLOGY_DEDUP_API_KEY`,
+/// `ONTOLOGY_DEDUP_THRESHOLD`, `ONTOLOGY_DEDUP_STRATEGY` (`keep_first` or
+/// `keep_most_referenced`) and `ONTOLOGY_DEDUP_AUTO_MERGE`
+fn ontology_duplicates_from_env() -> anyhow::Result<Option<(ProviderConfig, DuplicateConfig)>> {
+    let Some(embeddings) = embedding_model_from_env("ONTOLOGY_DEDUP")? else {
+        return Ok(None);
+    };
+    let mut config = DuplicateConfig {
+        auto_merge: env_flag("ONTOLOGY_DEDUP_AUTO_MERGE")?,
+        ..DuplicateConfig::default()
+    };
+    if let Ok(value) = std::env::var("ONTOLOGY_DEDUP_THRESHOLD") {
+        config.threshold = value
+            .parse()
+            .ok()
+            .filter(|threshold| (-1.0..=1.0).contains(threshold))
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "ONTOLOGY_DEDUP_THRESHOLD must be a similarity between -1 and 1, got '{}'",
+                    value
+                )
+            })?;
+    }
+    if let Ok(value) = std::env::var("ONTOLOGY_DEDUP_STRATEGY") {
+        config.strategy = serde_json::from_value::<MergeStrategy>(Value::from(value.as_str()))
+            .map_err(|_| {
+                anyhow::anyhow!(
+                    "ONTOLOGY_DEDUP_STRATEGY must be keep_first or keep_most_referenced, got '{}'",
+                    value
+                )
+            })?;
+    }
+    Ok(Some((embeddings, config)))
+}
+
+/// Project context for template generation, enabled by
+/// `TEMPLATE_CONTEXT_MODEL`, e.g. `openai/text-embedding-3-small`, with
+/// `TEMPLATE_CONTEXT_API_KEY` and `TEMPLATE_CONTEXT_MAX_TOKENS`; directories
+/// are resolved within `TEMPLATE_CONTEXT_ROOT`, the working directory unless
+/// set
+fn template_context_from_env() -> anyhow::Result<Option<(ProviderConfig, ContextConfig)>> {
+    let Some(embeddings) = embedding_model_from_env("TEMPLATE_CONTEXT")? else {
+        return Ok(None);
+    };
+    let root = match std::env::var("TEMPLATE_CONTEXT_ROOT") {
+        Ok(root) => root.into(),
+        Err(_) => std::env::current_dir()?,
+    };
+    let mut config = ContextConfig {
+        root: Some(root),
+        ..ContextConfig::default()
+    };
+    if let Ok(value) = std::env::var("TEMPLATE_CONTEXT_MAX_TOKENS") {
+        config.max_prompt_tokens = value.parse().map_err(|_| {
+            anyhow::anyhow!(
+                "TEMPLATE_CONTEXT_MAX_TOKENS must be a number of tokens, got '{}'",
+                value
+            )
+        })?;
+    }
+    Ok(Some((embeddings, config)))
+}
+
+/// The self-test endpoint, served when `SELFTEST_ADMIN_KEY` is set: it
+/// checks the project in `SELFTEST_ROOT`, the working directory by default,
+/// with the providers of `GGEN_CONFIG` if that is set
+fn selftest_from_env() -> Option<SelfTestApi> {
+    Some(SelfTestApi {
+        admin_key: std::env::var("SELFTEST_ADMIN_KEY").ok()?,
+        root: std::env::var_os("SELFTEST_ROOT").map_or_else(|| PathBuf::from("."), PathBuf::from),
+        provider_config: std::env::var_os("GGEN_CONFIG").map(PathBuf::from),
+    })
+}
+
+/// A positive number of seconds from the environment, if set
+fn env_secs(name: &str) -> anyhow::Result<Option<Duration>> {
+    Ok(env_count(name)?.map(|secs| Duration::from_secs(secs as u64)))
+}
+
+/// A positive number from the environment, if set
+fn env_count(name: &str) -> anyhow::Result<Option<usize>> {
+    match std::env::var(name) {
+        Ok(value) => value
+            .parse()
+            .ok()
+            .filter(|count| *count > 0)
+            .map(Some)
+            .ok_or_else(|| anyhow::anyhow!("{} must be a positive number, got '{}'", name, value)),
+        Err(_) => Ok(None),
+    }
+}
+
+/// The embedding model named by `{prefix}_MODEL` as provider/model, with
+/// the key in `{prefix}_API_KEY`
+fn embedding_model_from_env(prefix: &str) -> anyhow::Result<Option<ProviderConfig>> {
+    let Ok(model) = std::env::var(format!("{}_MODEL", prefix)) else {
+        return Ok(None);
+    };
+    let (provider, model) = model.split_once('/').ok_or_else(|| {
+        anyhow::anyhow!("{}_MODEL must be provider/model, got '{}'", prefix, model)
+    })?;
+    Ok(Some(ProviderConfig {
+        name: provider.to_string(),
+        model: model.to_string(),
+        api_key: std::env::var(format!("{}_API_KEY", prefix)).ok(),
+        ..ProviderConfig::default()
+    }))
+}
+
+/// Largest request body unless configured; axum's own default
+const DEFAULT_MAX_REQUEST_BYTES: usize = 2 * 1024 * 1024;
+
+/// A `true`/`false` environment variable; unset is `false`
+fn env_flag(name: &str) -> anyhow::Result<bool> {
+    match std::env::var(name) {
+        Ok(value) => value
+            .parse()
+            .map_err(|_| anyhow::anyhow!("{} must be true or false, got '{}'", name, value)),
+        Err(_) => Ok(false),
+    }
+}
+
+/// A rig-mcp embedding model serving ggen-ai's duplicate concept detection
+/// and template context selection
+struct GgenEmbeddings(Arc<dyn EmbeddingModel>);
+
+impl std::fmt::Debug for GgenEmbeddings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("GgenEmbeddings")
+            .field(&format_args!("{}/{}", self.0.provider(), self.0.model()))
+            .finish()
+    }
+}
+
+#[async_trait]
+impl ggen_ai::EmbeddingModel for GgenEmbeddings {
+    async fn embed(&self, texts: &[String]) -> ggen_ai::Result<Vec<Vec<f32>>> {
+        Ok(self.0.embed_texts(texts).await?)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct CachedResponse {
     prompt: String,
+    /// Answers in different languages or tones are different entries
+    style: ResponseStyle,
+    /// Tenant whose own credentials paid for the answer; `None` for the
+    /// service's. Only served to requests paid the same way
+    #[serde(default)]
+    owner: Option<String>,
+    /// Model the request picked; `None` for the service's
+    #[serde(default)]
+    model: Option<String>,
     response: String,
     timestamp: chrono::DateTime<chrono::Utc>,
+    /// Of the request that was answered
+    #[serde(default)]
+    metadata: Metadata,
+}
+
+impl CachedResponse {
+    /// Whether the entry is younger than the cache's TTL at `now`
+    fn is_fresh(&self, settings: &CacheSettings, now: chrono::DateTime<chrono::Utc>) -> bool {
+        (now - self.timestamp)
+            .to_std()
+            .map_or(true, |age| age < settings.ttl())
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -48,6 +401,12 @@ struct CompletionRequest {
     stream: bool,
     #[serde(default)]
     temperature: Option<f32>,
+    /// A model alias or `provider/model` pair to answer with instead of the
+    /// service's model
+    #[serde(default)]
+    model: Option<String>,
+    #[serde(flatten)]
+    style: StyleOverride,
 }
 
 #[derive(Debug, Serialize)]
@@ -63,12 +422,19 @@ struct TemplateRequest {
     language: String,
     #[serde(default)]
     variables: serde_json::Value,
+    /// Project files whose conventions the template should follow
+    #[serde(default)]
+    context: Option<ProjectContext>,
+    #[serde(flatten)]
+    style: StyleOverride,
 }
 
 #[derive(Debug, Serialize)]
 struct TemplateResponse {
     template: String,
     variables: Vec<String>,
+    /// Lint of the template, with the request's `variables` as provided
+    findings: Vec<LintFinding>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -77,6 +443,25 @@ struct RefactorRequest {
     language: String,
     #[serde(default)]
     focus: Vec<String>,
+    #[serde(default)]
+    mode: RefactorMode,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum RefactorMode {
+    /// Rewrite the code
+    #[default]
+    Refactor,
+    /// Comment on lines of the code without rewriting it
+    Review,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum RefactorOutcome {
+    Refactor(RefactorResponse),
+    Review(ReviewResponse),
 }
 
 #[derive(Debug, Serialize)]
@@ -93,10 +478,35 @@ struct RefactorMetrics {
     performance_gain: f32,
 }
 
+#[derive(Debug, Serialize)]
+struct ReviewResponse {
+    summary: String,
+    /// Comments anchored to lines of the submitted code, in line order
+    comments: Vec<AnchoredComment>,
+    /// Comments whose snippet isn't in the submitted code
+    unanchored: Vec<ReviewComment>,
+}
+
+impl From<CodeReview> for ReviewResponse {
+    fn from(review: CodeReview) -> Self {
+        Self {
+            summary: review.summary,
+            comments: review.comments,
+            unanchored: review.unanchored,
+        }
+    }
+}
+
 #[derive(Debug, Deserialize)]
 struct OntologyRequest {
     domain: String,
     concepts: Vec<String>,
+    /// Merge duplicate concepts instead of only reporting them; the service
+    /// default when absent
+    #[serde(default)]
+    merge_duplicates: Option<bool>,
+    #[serde(flatten)]
+    style: StyleOverride,
 }
 
 #[derive(Debug, Serialize)]
@@ -104,6 +514,10 @@ struct OntologyResponse {
     rdf_turtle: String,
     classes: Vec<String>,
     properties: Vec<String>,
+    /// Terms moved into the configured namespace
+    renames: Vec<IriRename>,
+    /// Concepts found to duplicate others, merged or suggested for merging
+    duplicates: Vec<DuplicateConcept>,
 }
 
 // Error handling
@@ -134,61 +548,375 @@ where
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    // Initialize tracing
-    tracing_subscriber::fmt()
-        .with_env_filter("ai_microservice=debug,ggen_ai=debug")
-        .json()
+    let settings = load_settings()?;
+
+    // Initialize tracing, exporting spans if a collector is configured
+    let collector = std::env::var_os("OTEL_EXPORTER_OTLP_ENDPOINT");
+    let tracer_provider = match (settings.telemetry.as_ref(), collector) {
+        (None, None) => None,
+        (telemetry, _) => {
+            let config = TelemetryConfig {
+                service_name: telemetry
+                    .and_then(|telemetry| telemetry.service_name.clone())
+                    .unwrap_or_else(|| "ai-microservice".to_string()),
+                ..telemetry.map(TelemetryConfig::from).unwrap_or_default()
+            }
+            .with_env();
+            Some(telemetry::tracer_provider(
+                &config,
+                telemetry::exporter(&config)?,
+            ))
+        }
+    };
+    tracing_subscriber::registry()
+        .with(EnvFilter::new("ai_microservice=debug,ggen_ai=debug"))
+        .with(tracing_subscriber::fmt::layer().json())
+        .with(tracer_provider.as_ref().map(telemetry::layer))
         .init();
+    // Flushes pending spans when main returns
+    let _telemetry = tracer_provider.map(TelemetryGuard::new);
 
     info!("Starting AI-powered microservice...");
+    for deprecation in &settings.deprecations {
+        warn!("{}", deprecation);
+    }
 
     // Initialize AI client with caching
-    let config = LlmConfig {
-        provider: LlmProvider::OpenAI,
-        model: "gpt-4".to_string(),
-        temperature: 0.7,
-        max_tokens: Some(2000),
-        cache: Some(CacheConfig {
-            enabled: true,
-            ttl_seconds: 3600,
-            max_entries: 1000,
-        }),
-        ..Default::default()
-    };
+    let config = settings
+        .default_provider()
+        .map(LlmConfig::from)
+        .unwrap_or_default();
+
+    let house = Arc::new(GenAiClient::new(config)?) as Arc<dyn LlmClient>;
+    let service_config = ServiceConfig::from_env()?;
+    let house_provider = settings
+        .default_provider()
+        .cloned()
+        .map(ProviderConfig::try_from)
+        .transpose()
+        .map_err(|err| anyhow::anyhow!("The default provider is invalid: {}", err))?;
+    let provider_name = house_provider
+        .as_ref()
+        .map(|provider| provider.name.clone());
+    let ai_client = TenantRouter::new(house, house_provider, &service_config.tenants)?;
+    let usage = ai_client.usage();
+    let credential_owners = ai_client.credential_owners();
+    let mut state = AppState::new(Arc::new(ai_client))
+        .with_cache(settings.cache.clone().unwrap_or_default())
+        .with_credential_owners(credential_owners)
+        .with_models(provider_name, service_config.model_aliases)
+        .with_strict_validation(service_config.strict_validation)
+        .with_idempotency_window(service_config.idempotency_window)
+        .with_max_request_bytes(service_config.max_request_bytes)
+        .with_response_styles(service_config.response_styles)
+        .with_retention(service_config.retention.clone());
+    if let Some(rules) = service_config.cache_redaction {
+        info!("Redacting cached completions");
+        state = state.with_cache_redaction(rules);
+    }
+    if let Some(policy) = service_config.ontology_namespace {
+        info!("Minting ontology terms in {}", policy.base_iri);
+        state = state.with_ontology_namespace(policy);
+    }
+    if let Some((embeddings, config)) = service_config.ontology_duplicates {
+        info!(
+            "Looking for duplicate ontology concepts with {}/{}",
+            embeddings.name, embeddings.model
+        );
+        let model = HttpEmbedder::new(&embeddings, Arc::new(ReqwestTransport::default()))?;
+        let deduplicator =
+            ConceptDeduplicator::new(Arc::new(GgenEmbeddings(Arc::new(model)))).with_config(config);
+        state = state.with_ontology_deduplicator(deduplicator);
+    }
+    if let Some((embeddings, config)) = service_config.template_context {
+        info!(
+            "Selecting template exemplars with {}/{}",
+            embeddings.name, embeddings.model
+        );
+        let model = HttpEmbedder::new(&embeddings, Arc::new(ReqwestTransport::default()))?;
+        let selector =
+            ContextSelector::new(Arc::new(GgenEmbeddings(Arc::new(model)))).with_config(config);
+        state = state.with_template_context(selector);
+    }
+    if let Some((embeddings, config)) = service_config.ingest {
+        info!(
+            "Ingesting documents with {}/{}",
+            embeddings.name, embeddings.model
+        );
+        let model = HttpEmbedder::new(&embeddings, Arc::new(ReqwestTransport::default()))?;
+        state = state.with_ingest(Arc::new(model), config);
+    }
+    if service_config.tools_api.enabled {
+        // MCP servers and the tool timeout come from the rig-mcp config
+        let path = std::env::var("RIG_MCP_CONFIG").unwrap_or_else(|_| "rig-mcp.json".to_string());
+        let mcp_config = rig_mcp_integration::Config::from_file(&path)
+            .map_err(|err| err.context(format!("Failed to load rig-mcp config {}", path)))?;
+        let mcp = Arc::new(RigMcpClient::new(mcp_config).await?);
+        state = state.with_tools_api(mcp, &service_config.tools_api);
+        info!("Tools API enabled");
+    }
+    if let Some(selftest) = service_config.selftest {
+        info!("Serving the self-test of {}", selftest.root.display());
+        state = state.with_selftest(selftest);
+    }
+    if let Some(admin_key) = service_config.tenants.admin_key.clone() {
+        info!("Serving usage by tenant");
+        state = state.with_usage_api(usage, admin_key);
+    }
+    tokio::spawn(purge_periodically(
+        state.clone(),
+        service_config.retention.purge_interval,
+    ));
+    let app = app(state);
+
+    let addr = "127.0.0.1:3000";
+    info!("Server listening on http://{}", addr);
 
-    let ai_client = Arc::new(GenAiClient::new(config.clone())?) as Arc<dyn LlmClient>;
-    let template_gen = Arc::new(TemplateGenerator::new(ai_client.clone()));
-    let refactor_assistant = Arc::new(RefactorAssistant::new(ai_client.clone()));
-    let ontology_gen = Arc::new(OntologyGenerator::new(ai_client.clone()));
-
-    let state = AppState {
-        ai_client,
-        template_gen,
-        refactor_assistant,
-        ontology_gen,
-        cache: Arc::new(RwLock::new(Vec::new())),
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    axum::serve(listener, app)
+        .with_graceful_shutdown(async {
+            let _ = tokio::signal::ctrl_c().await;
+        })
+        .await?;
+
+    Ok(())
+}
+
+/// The shared settings in `GGEN_CONFIG`, or in `ggen.toml` if there is one
+fn load_settings() -> anyhow::Result<SharedConfig> {
+    let path = match std::env::var("GGEN_CONFIG") {
+        Ok(path) => path,
+        Err(_) if std::path::Path::new("ggen.toml").exists() => "ggen.toml".to_string(),
+        Err(_) => return Ok(SharedConfig::default()),
     };
+    Ok(SharedConfig::from_file(path.as_ref())?)
+}
 
-    // Build router with all endpoints
-    let app = Router::new()
+impl AppState {
+    fn new(ai_client: Arc<dyn LlmClient>) -> Self {
+        Self {
+            template_gen: Arc::new(TemplateGenerator::new(ai_client.clone())),
+            refactor_assistant: Arc::new(RefactorAssistant::new(ai_client.clone())),
+            ontology_gen: Arc::new(OntologyGenerator::new(ai_client.clone())),
+            readiness: readiness_checks(ai_client.clone()),
+            cache: Arc::new(RwLock::new(Vec::new())),
+            cache_settings: CacheSettings::default(),
+            cache_redaction: None,
+            credential_owners: Arc::default(),
+            tools: None,
+            validation: Arc::new(RequestValidation::new(false)),
+            max_request_bytes: DEFAULT_MAX_REQUEST_BYTES,
+            idempotency: IdempotencyStore::new(DEFAULT_IDEMPOTENCY_WINDOW),
+            styles: Arc::new(ResponseStyles::default()),
+            ingest: None,
+            retention: Retention::default(),
+            selftest: None,
+            usage: None,
+            models: Arc::default(),
+            ai_client,
+        }
+    }
+
+    /// Let requests pick another model of `provider`, by `provider/model` or
+    /// by one of `aliases`
+    fn with_models(mut self, provider: Option<String>, aliases: HashMap<String, String>) -> Self {
+        self.models = Arc::new(ModelSelection { provider, aliases });
+        self
+    }
+
+    /// Purge stored data as `config` says, recording purges in its audit
+    /// log
+    fn with_retention(mut self, config: RetentionConfig) -> Self {
+        self.retention = Retention {
+            audit: config
+                .audit_log
+                .clone()
+                .map(|path| Arc::new(AuditLog::new(path))),
+            config,
+        };
+        self
+    }
+
+    /// Serve the self-test endpoint
+    fn with_selftest(mut self, selftest: SelfTestApi) -> Self {
+        self.selftest = Some(selftest);
+        self
+    }
+
+    /// Serve what `ledger` accounted to callers with `admin_key`
+    fn with_usage_api(mut self, ledger: UsageLedger, admin_key: String) -> Self {
+        self.usage = Some(UsageApi { ledger, admin_key });
+        self
+    }
+
+    /// Serve the ingest endpoint, embedding documents with `embeddings`
+    fn with_ingest(mut self, embeddings: Arc<dyn EmbeddingModel>, config: IngestConfig) -> Self {
+        self.ingest = Some(Ingest {
+            embeddings,
+            config,
+            documents: DocumentIndex::default(),
+        });
+        self
+    }
+
+    /// Answer in the languages and tones of `styles` unless requests say
+    /// otherwise
+    fn with_response_styles(mut self, styles: ResponseStyles) -> Self {
+        self.styles = Arc::new(styles);
+        self
+    }
+
+    /// Cache completions as `settings` say
+    fn with_cache(mut self, settings: CacheSettings) -> Self {
+        self.cache_settings = settings;
+        self
+    }
+
+    /// Cache completions paid with a tenant's credentials apart from the
+    /// service's and other tenants'; `owners` are tenant names by API key
+    fn with_credential_owners(mut self, owners: HashMap<String, String>) -> Self {
+        self.credential_owners = Arc::new(owners);
+        self
+    }
+
+    /// Cache completions with what `rules` match redacted; prompts the rules
+    /// change aren't cached at all, since their answers depend on what was
+    /// hidden
+    fn with_cache_redaction(mut self, rules: Arc<RedactionRuleSet>) -> Self {
+        self.cache_redaction = Some(rules);
+        self
+    }
+
+    /// Replay responses to retries for `window` after the first request
+    fn with_idempotency_window(mut self, window: Duration) -> Self {
+        self.idempotency = IdempotencyStore::new(window);
+        self
+    }
+
+    /// Reject request bodies larger than `max` bytes
+    fn with_max_request_bytes(mut self, max: usize) -> Self {
+        self.max_request_bytes = max;
+        self
+    }
+
+    /// Reject unknown fields in request bodies instead of reporting them
+    fn with_strict_validation(mut self, strict: bool) -> Self {
+        self.validation = Arc::new(RequestValidation::new(strict));
+        self
+    }
+
+    /// Move the terms of generated ontologies into `policy`'s namespace
+    fn with_ontology_namespace(mut self, policy: NamespacePolicy) -> Self {
+        self.ontology_gen =
+            Arc::new(OntologyGenerator::clone(&self.ontology_gen).with_namespace_policy(policy));
+        self
+    }
+
+    /// Look for duplicate concepts in generated ontologies with
+    /// `deduplicator`
+    fn with_ontology_deduplicator(mut self, deduplicator: ConceptDeduplicator) -> Self {
+        self.ontology_gen =
+            Arc::new(OntologyGenerator::clone(&self.ontology_gen).with_deduplicator(deduplicator));
+        self
+    }
+
+    /// Ground generated templates in project context with `selector`
+    fn with_template_context(mut self, selector: ContextSelector) -> Self {
+        self.template_gen = Arc::new(
+            TemplateGenerator::new(self.ai_client.clone()).with_context_selector(selector),
+        );
+        self
+    }
+
+    /// Serve the tools endpoints from `mcp`, if `config` enables them; the
+    /// service isn't ready while a provider rejects `mcp`'s keys
+    fn with_tools_api(mut self, mcp: Arc<RigMcpClient>, config: &ToolsApiConfig) -> Self {
+        if config.enabled {
+            self.readiness
+                .register(Arc::new(CredentialsCheck::new(mcp.clone())));
+        }
+        self.tools = match (config.enabled, &config.admin_key) {
+            (true, Some(admin_key)) => Some(ToolsApi {
+                mcp,
+                admin_key: admin_key.clone(),
+            }),
+            _ => None,
+        };
+        self
+    }
+}
+
+/// Router with all endpoints
+fn app(state: AppState) -> Router {
+    let mut router = Router::new();
+    // Not even routed unless enabled
+    if state.tools.is_some() {
+        router = router
+            .route("/api/v1/tools", get(list_tools))
+            .route("/api/v1/tools/:server/:tool/invoke", post(invoke_tool));
+    }
+    if state.ingest.is_some() {
+        router = router.route("/api/v1/embeddings/ingest", post(ingest_documents));
+    }
+    if state.retention.config.admin_key.is_some() {
+        router = router.route("/api/v1/retention/purge", post(purge));
+    }
+    if state.selftest.is_some() {
+        router = router.route("/api/v1/admin/selftest", get(run_selftest));
+    }
+    if state.usage.is_some() {
+        router = router.route("/api/v1/admin/usage", get(usage_report));
+    }
+    let idempotent = || middleware::from_fn_with_state(state.clone(), idempotency);
+    let body_limit = DefaultBodyLimit::max(state.max_request_bytes);
+    router
         .route("/", get(health))
         .route("/health", get(health))
-        .route("/api/v1/complete", post(complete))
-        .route("/api/v1/template/generate", post(generate_template))
-        .route("/api/v1/refactor", post(refactor_code))
-        .route("/api/v1/ontology/generate", post(generate_ontology))
+        .route("/ready", get(ready))
+        .route("/api/v1/openapi.json", get(openapi))
+        .route("/api/v1/complete", post(complete).layer(idempotent()))
+        .route(
+            "/api/v1/template/generate",
+            post(generate_template).layer(idempotent()),
+        )
+        .route("/api/v1/refactor", post(refactor_code).layer(idempotent()))
+        .route(
+            "/api/v1/ontology/generate",
+            post(generate_ontology).layer(idempotent()),
+        )
         .route("/api/v1/cache/stats", get(cache_stats))
         .route("/api/v1/cache/clear", post(clear_cache))
+        .layer(body_limit)
+        .layer(middleware::from_fn(scope_api_key))
         .layer(CorsLayer::permissive())
-        .with_state(state);
-
-    let addr = "127.0.0.1:3000";
-    info!("Server listening on http://{}", addr);
+        .layer(TraceLayer::new_for_http().make_span_with(request_span))
+        .with_state(state)
+}
 
-    let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
+/// The caller's `x-api-key`, or its `Authorization` header; empty without
+/// either
+fn api_key(headers: &HeaderMap) -> &str {
+    headers
+        .get("x-api-key")
+        .or_else(|| headers.get(header::AUTHORIZATION))
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+}
 
-    Ok(())
+/// Span around each request; its fields are exported as span attributes
+fn request_span<B>(request: &Request<B>) -> Span {
+    let request_id = request
+        .headers()
+        .get("x-request-id")
+        .and_then(|id| id.to_str().ok())
+        .map(str::to_string)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    tracing::info_span!(
+        "http.request",
+        request_id = %request_id,
+        http.method = %request.method(),
+        http.route = %request.uri().path(),
+    )
 }
 
 // Handlers
@@ -201,66 +929,209 @@ async fn health() -> Json<serde_json::Value> {
     }))
 }
 
+/// Dependencies that must be reachable before the service takes traffic
+fn readiness_checks(ai_client: Arc<dyn LlmClient>) -> HealthRegistry {
+    let mut registry = HealthRegistry::new().degraded_after(std::time::Duration::from_secs(2));
+    registry.register_fn("llm", move || {
+        let client = ai_client.clone();
+        async move {
+            client.complete("ping").await?;
+            Ok(Probe::up())
+        }
+    });
+    registry
+}
+
+async fn ready(State(state): State<AppState>) -> (StatusCode, Json<HealthReport>) {
+    let report = state.readiness.check_all().await;
+    let status = match report.status {
+        HealthStatus::Down => StatusCode::SERVICE_UNAVAILABLE,
+        HealthStatus::Up | HealthStatus::Degraded => StatusCode::OK,
+    };
+    (status, Json(report))
+}
+
 async fn complete(
-    State(state): State<AppState>,
-    Json(req): Json<CompletionRequest>,
-) -> Result<Json<CompletionResponse>, AppError> {
+    State(state): State<AppState>, headers: HeaderMap,
+    Validated {
+        body: req,
+        unknown_fields,
+    }: Validated<CompletionRequest>,
+) -> Result<(UnknownFields, Json<CompletionResponse>), Response> {
     info!("Processing completion request");
+    let style = state.styles.effective(api_key(&headers), &req.style);
+    let owner = state.credential_owners.get(api_key(&headers)).cloned();
+    let model = match &req.model {
+        Some(requested) => Some(state.models.resolve(requested).map_err(|message| {
+            let violations = vec![Violation {
+                path: "/model".to_string(),
+                message,
+            }];
+            InvalidRequest {
+                schema: CompletionRequest::NAME,
+                violations,
+            }
+            .into_response()
+        })?),
+        None => None,
+    }
+    .filter(|model| *model != state.ai_client.get_config().model);
 
     // Check cache
+    let settings = &state.cache_settings;
+    let cacheable = settings.enabled
+        && state
+            .cache_redaction
+            .as_ref()
+            .is_none_or(|rules| rules.apply(&req.prompt).1.is_empty());
+    let now = chrono::Utc::now();
     let cache = state.cache.read().await;
-    if let Some(cached) = cache.iter().find(|c| c.prompt == req.prompt) {
+    if let Some(cached) = cache.iter().find(|c| {
+        cacheable
+            && c.prompt == req.prompt
+            && c.style == style
+            && c.owner == owner
+            && c.model == model
+            && c.is_fresh(settings, now)
+    }) {
         info!("Returning cached response");
-        return Ok(Json(CompletionResponse {
-            content: cached.response.clone(),
-            tokens_used: None,
-            cached: true,
-        }));
+        return Ok((
+            unknown_fields,
+            Json(CompletionResponse {
+                content: cached.response.clone(),
+                tokens_used: None,
+                cached: true,
+            }),
+        ));
     }
     drop(cache);
 
     // Generate response
-    let response = state.ai_client.complete(&req.prompt).await?;
+    let prompt = style.apply(&req.prompt);
+    let completion = state.ai_client.complete(&prompt);
+    let response = match model.clone() {
+        Some(model) => REQUEST_MODEL.scope(model, completion).await,
+        None => completion.await,
+    }
+    .map_err(|err| AppError::from(err).into_response())?;
 
-    // Cache response
-    let mut cache = state.cache.write().await;
-    cache.push(CachedResponse {
-        prompt: req.prompt,
-        response: response.content.clone(),
-        timestamp: chrono::Utc::now(),
-    });
+    // Cache response, making room by dropping expired and then the oldest
+    // entries
+    if cacheable {
+        let now = chrono::Utc::now();
+        let mut cache = state.cache.write().await;
+        cache.retain(|c| c.is_fresh(settings, now));
+        let excess = (cache.len() + 1).saturating_sub(settings.max_entries.max(1) as usize);
+        cache.drain(..excess);
+        cache.push(CachedResponse {
+            prompt: req.prompt,
+            style,
+            owner,
+            model,
+            response: match &state.cache_redaction {
+                Some(rules) => rules.redact(&response.content),
+                None => response.content.clone(),
+            },
+            timestamp: now,
+            metadata: metadata(&headers),
+        });
+    }
 
-    Ok(Json(CompletionResponse {
-        content: response.content,
-        tokens_used: Some(response.usage.total_tokens),
-        cached: false,
-    }))
+    Ok((
+        unknown_fields,
+        Json(CompletionResponse {
+            content: response.content,
+            tokens_used: Some(response.usage.total_tokens),
+            cached: false,
+        }),
+    ))
 }
 
 async fn generate_template(
-    State(state): State<AppState>,
-    Json(req): Json<TemplateRequest>,
-) -> Result<Json<TemplateResponse>, AppError> {
+    State(state): State<AppState>, headers: HeaderMap,
+    Validated {
+        body: req,
+        unknown_fields,
+    }: Validated<TemplateRequest>,
+) -> Result<Response, AppError> {
+    let representation = match Representation::negotiate(&headers, &["text/plain"]) {
+        Ok(representation) => representation,
+        Err(not_acceptable) => return Ok(not_acceptable.into_response()),
+    };
     info!("Generating template for: {}", req.description);
+    let style = state.styles.effective(api_key(&headers), &req.style);
+    let template_gen = TemplateGenerator::clone(&state.template_gen).with_style(style);
 
-    let template = state
-        .template_gen
-        .generate(&req.description, &req.language)
-        .await?;
+    let template = match &req.context {
+        Some(context) => {
+            let language = format!("Target language: {}", req.language);
+            template_gen
+                .generate_template_with_context(&req.description, vec![&language], context)
+                .await?
+                .body
+        }
+        None => {
+            template_gen
+                .generate(&req.description, &req.language)
+                .await?
+        }
+    };
 
     // Extract variables from template (simplified)
     let variables = extract_variables(&template);
 
-    Ok(Json(TemplateResponse {
-        template,
-        variables,
-    }))
+    let provided: Vec<&str> = req
+        .variables
+        .as_object()
+        .map(|vars| vars.keys().map(String::as_str).collect())
+        .unwrap_or_default();
+    let findings = lint_template(&template, &build_tera_minimal()?, &provided);
+
+    if let Representation::Raw(media_type) = representation {
+        let filename = format!(
+            "{}.{}.tmpl",
+            file_stem(&req.description, "template"),
+            extension(&req.language)
+        );
+        let metadata = vec![
+            ("x-ggen-variables", variables.join(", ")),
+            ("x-ggen-findings", findings.len().to_string()),
+        ];
+        let document = RawDocument {
+            media_type,
+            filename,
+            body: template,
+            metadata,
+        };
+        return Ok((unknown_fields, document).into_response());
+    }
+    Ok((
+        unknown_fields,
+        Json(TemplateResponse {
+            template,
+            variables,
+            findings,
+        }),
+    )
+        .into_response())
 }
 
 async fn refactor_code(
     State(state): State<AppState>,
-    Json(req): Json<RefactorRequest>,
-) -> Result<Json<RefactorResponse>, AppError> {
+    Validated {
+        body: req,
+        unknown_fields,
+    }: Validated<RefactorRequest>,
+) -> Result<(UnknownFields, Json<RefactorOutcome>), AppError> {
+    if let RefactorMode::Review = req.mode {
+        info!("Reviewing {} code", req.language);
+        let review = state
+            .refactor_assistant
+            .review(&req.code, &req.language, &req.focus)
+            .await?;
+        return Ok((unknown_fields, Json(RefactorOutcome::Review(review.into()))));
+    }
+
     info!("Refactoring {} code", req.language);
 
     let suggestions = state
@@ -273,40 +1144,82 @@ async fn refactor_code(
         .refactor(&req.code, &req.language, &suggestions)
         .await?;
 
-    Ok(Json(RefactorResponse {
-        refactored_code: refactored,
-        suggestions: suggestions.iter().map(|s| s.description.clone()).collect(),
-        metrics: RefactorMetrics {
-            complexity_reduction: 0.25,
-            readability_improvement: 0.35,
-            performance_gain: 0.15,
-        },
-    }))
+    Ok((
+        unknown_fields,
+        Json(RefactorOutcome::Refactor(RefactorResponse {
+            refactored_code: refactored,
+            suggestions: suggestions.iter().map(|s| s.description.clone()).collect(),
+            metrics: RefactorMetrics {
+                complexity_reduction: 0.25,
+                readability_improvement: 0.35,
+                performance_gain: 0.15,
+            },
+        })),
+    ))
 }
 
 async fn generate_ontology(
-    State(state): State<AppState>,
-    Json(req): Json<OntologyRequest>,
-) -> Result<Json<OntologyResponse>, AppError> {
+    State(state): State<AppState>, headers: HeaderMap,
+    Validated {
+        body: req,
+        unknown_fields,
+    }: Validated<OntologyRequest>,
+) -> Result<Response, AppError> {
+    let representation = match Representation::negotiate(&headers, &["text/turtle", "text/plain"]) {
+        Ok(representation) => representation,
+        Err(not_acceptable) => return Ok(not_acceptable.into_response()),
+    };
     info!("Generating ontology for domain: {}", req.domain);
+    let style = state.styles.effective(api_key(&headers), &req.style);
+    // Comments are tagged with the effective language after parsing
+    let ontology_gen = OntologyGenerator::clone(&state.ontology_gen).with_style(style);
 
-    let description = format!(
-        "Domain: {}\nConcepts: {}",
-        req.domain,
-        req.concepts.join(", ")
-    );
-
-    let rdf = state.ontology_gen.generate_ontology(&description).await?;
+    let concepts = req
+        .concepts
+        .iter()
+        .map(|concept| format!("Include the concept {}", concept))
+        .collect::<Vec<_>>();
+    let concepts = concepts.iter().map(String::as_str).collect();
+    let ontology = match req.merge_duplicates {
+        Some(merge) => {
+            ontology_gen
+                .generate_deduplicated_ontology(&req.domain, concepts, merge)
+                .await?
+        }
+        None => {
+            ontology_gen
+                .generate_normalized_ontology(&req.domain, concepts)
+                .await?
+        }
+    };
 
     // Parse classes and properties (simplified)
     let classes = req.concepts.clone();
     let properties = vec!["hasProperty".to_string(), "relatesTo".to_string()];
 
-    Ok(Json(OntologyResponse {
-        rdf_turtle: rdf,
-        classes,
-        properties,
-    }))
+    if let Representation::Raw(media_type) = representation {
+        let document = RawDocument {
+            media_type,
+            filename: format!("{}.ttl", file_stem(&req.domain, "ontology")),
+            body: ontology.turtle,
+            metadata: vec![
+                ("x-ggen-classes", classes.join(", ")),
+                ("x-ggen-properties", properties.join(", ")),
+            ],
+        };
+        return Ok((unknown_fields, document).into_response());
+    }
+    Ok((
+        unknown_fields,
+        Json(OntologyResponse {
+            rdf_turtle: ontology.turtle,
+            classes,
+            properties,
+            renames: ontology.renames,
+            duplicates: ontology.duplicates,
+        }),
+    )
+        .into_response())
 }
 
 async fn cache_stats(State(state): State<AppState>) -> Json<serde_json::Value> {
@@ -328,6 +1241,68 @@ async fn clear_cache(State(state): State<AppState>) -> Json<serde_json::Value> {
     }))
 }
 
+/// Whether the `x-admin-key` header is `expected`, compared in constant time
+/// so response times don't give the key away; never for an empty key
+fn admin_key_matches(headers: &HeaderMap, expected: Option<&str>) -> bool {
+    let (Some(given), Some(expected)) = (headers.get("x-admin-key"), expected) else {
+        return false;
+    };
+    if expected.is_empty() {
+        return false;
+    }
+    // Digests are the same length whatever the keys'
+    let given = Sha256::digest(given.as_bytes());
+    let expected = Sha256::digest(expected.as_bytes());
+    given
+        .iter()
+        .zip(expected.iter())
+        .fold(0, |diff, (a, b)| diff | (a ^ b))
+        == 0
+}
+
+// Self-test
+
+/// Project the self-test endpoint checks
+#[derive(Debug, Clone)]
+struct SelfTestApi {
+    root: PathBuf,
+    /// Providers to check instead of those in the project's `ggen.toml`
+    provider_config: Option<PathBuf>,
+    /// Expected in the `x-admin-key` header
+    admin_key: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct SelfTestQuery {
+    /// Also contact every provider
+    #[serde(default)]
+    online: bool,
+}
+
+/// Check the project's config, ontology, templates and providers; answers
+/// with the report as JSON, with a 503 when a check failed
+async fn run_selftest(
+    State(state): State<AppState>, headers: HeaderMap, Query(query): Query<SelfTestQuery>,
+) -> Response {
+    let Some(api) = &state.selftest else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    if !admin_key_matches(&headers, Some(&api.admin_key)) {
+        let error = json!({"kind": "unauthorized", "message": "Missing or wrong x-admin-key"});
+        return (StatusCode::UNAUTHORIZED, Json(json!({ "error": error }))).into_response();
+    }
+    let mut selftest = SelfTest::new(&api.root).online(query.online);
+    if let Some(path) = &api.provider_config {
+        selftest = selftest.with_provider_config(path);
+    }
+    let report = selftest.run().await;
+    let status = match report.severity() {
+        Severity::Fail => StatusCode::SERVICE_UNAVAILABLE,
+        _ => StatusCode::OK,
+    };
+    (status, Json(report.to_json())).into_response()
+}
+
 // Utilities
 
 fn extract_variables(template: &str) -> Vec<String> {
@@ -337,3 +1312,544 @@ fn extract_variables(template: &str) -> Vec<String> {
         .filter_map(|cap| cap.get(1).map(|m| m.as_str().to_string()))
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use ggen_ai::MockClient;
+    use opentelemetry_sdk::trace::InMemorySpanExporter;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tower::ServiceExt;
+
+    pub(crate) fn mock_state() -> AppState {
+        AppState::new(Arc::new(MockClient::with_response("ok")) as Arc<dyn LlmClient>)
+    }
+
+    pub(crate) async fn post(
+        state: AppState, uri: &str, body: Value,
+    ) -> (StatusCode, HeaderMap, Value) {
+        post_with_headers(state, uri, &[], body).await
+    }
+
+    pub(crate) async fn post_with_headers(
+        state: AppState, uri: &str, headers: &[(&str, &str)], body: Value,
+    ) -> (StatusCode, HeaderMap, Value) {
+        let (status, headers, body) = post_raw(state, uri, headers, body).await;
+        (status, headers, serde_json::from_str(&body).unwrap())
+    }
+
+    pub(crate) async fn post_raw(
+        state: AppState, uri: &str, headers: &[(&str, &str)], body: Value,
+    ) -> (StatusCode, HeaderMap, String) {
+        let mut request = Request::post(uri).header("content-type", "application/json");
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        let request = request.body(Body::from(body.to_string())).unwrap();
+        let response = app(state).oneshot(request).await.unwrap();
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, headers, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    /// [`MockClient`] answering with an ontology, counting calls
+    #[derive(Debug)]
+    pub(crate) struct CountingClient {
+        pub(crate) inner: MockClient,
+        pub(crate) calls: AtomicUsize,
+        /// How long each completion takes
+        pub(crate) delay: Duration,
+    }
+
+    #[async_trait]
+    impl LlmClient for CountingClient {
+        async fn complete(&self, prompt: &str) -> ggen_ai::Result<ggen_ai::LlmResponse> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(self.delay).await;
+            self.inner.complete(prompt).await
+        }
+
+        async fn complete_stream(
+            &self, prompt: &str,
+        ) -> ggen_ai::Result<futures::stream::BoxStream<'static, ggen_ai::LlmChunk>> {
+            self.inner.complete_stream(prompt).await
+        }
+
+        fn get_config(&self) -> &LlmConfig {
+            self.inner.get_config()
+        }
+
+        fn update_config(&mut self, config: LlmConfig) {
+            self.inner.update_config(config)
+        }
+    }
+
+    pub(crate) fn counting_state(window: Duration) -> (AppState, Arc<CountingClient>) {
+        let client = Arc::new(CountingClient {
+            inner: MockClient::with_response(
+                "@prefix ex: <http://example.org/> .\nex:Order a ex:Class .\n",
+            ),
+            calls: Default::default(),
+            delay: Duration::ZERO,
+        });
+        let state =
+            AppState::new(client.clone() as Arc<dyn LlmClient>).with_idempotency_window(window);
+        (state, client)
+    }
+
+    pub(crate) fn calls(client: &CountingClient) -> usize {
+        client.calls.load(Ordering::SeqCst)
+    }
+
+    /// Embeds texts as their length, failing batches containing "boom"
+    #[derive(Default)]
+    pub(crate) struct CountingEmbeddings {
+        in_flight: AtomicUsize,
+        pub(crate) max_in_flight: AtomicUsize,
+        pub(crate) max_batch: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl EmbeddingModel for CountingEmbeddings {
+        fn provider(&self) -> &str {
+            "mock"
+        }
+
+        fn model(&self) -> &str {
+            "counting"
+        }
+
+        async fn embed_texts(&self, texts: &[String]) -> anyhow::Result<Vec<Vec<f32>>> {
+            let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+            self.max_batch.fetch_max(texts.len(), Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(2)).await;
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+            if texts.iter().any(|text| text.contains("boom")) {
+                anyhow::bail!("model overloaded");
+            }
+            Ok(texts.iter().map(|text| vec![text.len() as f32]).collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_request_span_is_exported() {
+        let exporter = InMemorySpanExporter::default();
+        let provider = telemetry::tracer_provider(&TelemetryConfig::default(), exporter.clone());
+        let subscriber = tracing_subscriber::registry().with(telemetry::layer(&provider));
+        let _default = tracing::subscriber::set_default(subscriber);
+
+        let client = Arc::new(MockClient::with_response("Paris")) as Arc<dyn LlmClient>;
+        let request = Request::post("/api/v1/complete")
+            .header("content-type", "application/json")
+            .header("x-request-id", "req-42")
+            .body(Body::from(r#"{"prompt": "Capital of France?"}"#))
+            .unwrap();
+        let response = app(AppState::new(client)).oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        provider.force_flush().unwrap();
+        let spans = exporter.get_finished_spans().unwrap();
+        let span = spans.iter().find(|s| s.name == "http.request").unwrap();
+        let attributes: HashMap<String, String> = span
+            .attributes
+            .iter()
+            .map(|kv| (kv.key.to_string(), kv.value.to_string()))
+            .collect();
+        assert_eq!(attributes["request_id"], "req-42");
+        assert_eq!(attributes["http.method"], "POST");
+        assert_eq!(attributes["http.route"], "/api/v1/complete");
+    }
+
+    #[tokio::test]
+    async fn test_selftest_reports_the_broken_section() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = mock_state().with_selftest(SelfTestApi {
+            root: dir.path().to_path_buf(),
+            provider_config: None,
+            admin_key: "secret".to_string(),
+        });
+        let selftest = |admin_key: &str| {
+            let request = Request::get("/api/v1/admin/selftest")
+                .header("x-admin-key", admin_key)
+                .body(Body::empty())
+                .unwrap();
+            let state = state.clone();
+            async move {
+                let response = app(state).oneshot(request).await.unwrap();
+                let status = response.status();
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                (status, serde_json::from_slice::<Value>(&body).unwrap())
+            }
+        };
+
+        let (status, _) = selftest("wrong").await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        // Only warned about the missing ggen.toml
+        let (status, report) = selftest("secret").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(report["status"], "warn");
+
+        std::fs::create_dir(dir.path().join("templates")).unwrap();
+        std::fs::write(
+            dir.path().join("templates/model.tmpl"),
+            "---\nto: model.rs\n---\n{{ \"model\" | pascl }}\n",
+        )
+        .unwrap();
+        let (status, report) = selftest("secret").await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(report["exit_code"], 2);
+        let templates = report["sections"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|section| section["name"] == "templates")
+            .unwrap();
+        assert_eq!(templates["status"], "fail");
+    }
+
+    #[tokio::test]
+    async fn test_ontology_response_reports_renamed_terms() {
+        let turtle = "@prefix ex: <http://example.org/> .\n\
+                      @prefix owl: <http://www.w3.org/2002/07/owl#> .\n\
+                      ex:line_item a owl:Class .\n";
+        let state =
+            AppState::new(Arc::new(MockClient::with_response(turtle)) as Arc<dyn LlmClient>)
+                .with_ontology_namespace(NamespacePolicy::new(
+                    "https://data.acme.com/ontology#",
+                    "acme",
+                ));
+
+        let (status, _, body) = post(
+            state,
+            "/api/v1/ontology/generate",
+            json!({"domain": "orders", "concepts": ["line item"]}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(
+            body["renames"],
+            json!([{
+                "from": "http://example.org/line_item",
+                "to": "https://data.acme.com/ontology#LineItem",
+                "kind": "class"
+            }])
+        );
+        assert!(body["rdf_turtle"]
+            .as_str()
+            .unwrap()
+            .contains("acme:LineItem"));
+    }
+
+    #[tokio::test]
+    async fn test_review_mode_anchors_comments() {
+        let code = "fn port(value: &str) -> u16 {\n    value.parse().unwrap()\n}\n";
+        let review = json!({
+            "summary": "Panics on malformed input",
+            "comments": [
+                {
+                    "severity": "error",
+                    "category": "error-handling",
+                    "message": "A malformed port panics",
+                    "snippet": "value.parse().unwrap()",
+                    "line": 2,
+                    "suggestion": "value.parse().unwrap_or(8080)"
+                },
+                {
+                    "severity": "warning",
+                    "category": "validation",
+                    "message": "Port 0 is accepted",
+                    "snippet": "if port == 0 { return Err(InvalidPort) }"
+                }
+            ]
+        });
+        let state = AppState::new(
+            Arc::new(MockClient::with_response(&review.to_string())) as Arc<dyn LlmClient>
+        );
+
+        let (status, _, body) = post(
+            state,
+            "/api/v1/refactor",
+            json!({"code": code, "language": "rust", "mode": "review"}),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["summary"], "Panics on malformed input");
+        assert!(body.get("refactored_code").is_none());
+        assert_eq!(
+            body["comments"],
+            json!([{
+                "severity": "error",
+                "category": "error-handling",
+                "message": "A malformed port panics",
+                "snippet": "value.parse().unwrap()",
+                "suggestion": "value.parse().unwrap_or(8080)",
+                "lines": {"start": 2, "end": 2},
+                "diff": "@@ -2,1 +2,1 @@\n-    value.parse().unwrap()\n+    value.parse().unwrap_or(8080)\n"
+            }])
+        );
+        assert_eq!(body["unanchored"].as_array().unwrap().len(), 1);
+        assert_eq!(body["unanchored"][0]["message"], "Port 0 is accepted");
+    }
+
+    /// Embeds the `Customer` and `Client` labels identically
+    #[derive(Debug)]
+    struct SynonymEmbeddings;
+
+    #[async_trait]
+    impl ggen_ai::EmbeddingModel for SynonymEmbeddings {
+        async fn embed(&self, texts: &[String]) -> ggen_ai::Result<Vec<Vec<f32>>> {
+            Ok(texts
+                .iter()
+                .map(|text| match text.as_str() {
+                    "Customer" | "Client" => vec![1.0, 0.0],
+                    _ => vec![0.0, 1.0],
+                })
+                .collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_concepts_follow_the_request_flag() {
+        let turtle = "@prefix ex: <http://example.org/> .\n\
+                      @prefix owl: <http://www.w3.org/2002/07/owl#> .\n\
+                      @prefix rdfs: <http://www.w3.org/2000/01/rdf-schema#> .\n\
+                      ex:Customer a owl:Class ; rdfs:label \"Customer\" .\n\
+                      ex:Client a owl:Class ; rdfs:label \"Client\" .\n\
+                      ex:buyer a ex:Client .\n";
+        let state =
+            AppState::new(Arc::new(MockClient::with_response(turtle)) as Arc<dyn LlmClient>)
+                .with_ontology_deduplicator(ConceptDeduplicator::new(Arc::new(SynonymEmbeddings)));
+        let expected = |merged: bool| {
+            json!([{
+                "keep": "http://example.org/Customer",
+                "duplicate": "http://example.org/Client",
+                "kind": "class",
+                "similarity": 1.0,
+                "merged": merged
+            }])
+        };
+
+        let (status, _, body) = post(
+            state.clone(),
+            "/api/v1/ontology/generate",
+            json!({"domain": "shop", "concepts": ["customer"]}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["duplicates"], expected(false));
+        assert!(!body["rdf_turtle"]
+            .as_str()
+            .unwrap()
+            .contains("equivalentClass"));
+
+        let (status, _, body) = post(
+            state,
+            "/api/v1/ontology/generate",
+            json!({"domain": "shop", "concepts": ["customer"], "merge_duplicates": true}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["duplicates"], expected(true));
+        let merged = body["rdf_turtle"].as_str().unwrap();
+        assert!(merged.contains("ex:buyer a ex:Customer"), "{}", merged);
+        assert!(
+            merged.contains("owl:equivalentClass ex:Client"),
+            "{}",
+            merged
+        );
+    }
+
+    #[tokio::test]
+    async fn test_completion_cache_follows_its_settings() {
+        let complete = |state: &AppState, prompt: &str| {
+            post(state.clone(), "/api/v1/complete", json!({ "prompt": prompt }))
+        };
+        let (state, client) = counting_state(Duration::from_secs(60));
+        let state = state.with_cache(CacheSettings {
+            max_entries: 1,
+            ..CacheSettings::default()
+        });
+        let (_, _, body) = complete(&state, "a").await;
+        assert_eq!(body["cached"], false);
+        let (_, _, body) = complete(&state, "a").await;
+        assert_eq!(body["cached"], true);
+        // "b" takes the only place
+        complete(&state, "b").await;
+        let (_, _, body) = complete(&state, "a").await;
+        assert_eq!(body["cached"], false);
+        assert_eq!(calls(&client), 3);
+
+        let (state, client) = counting_state(Duration::from_secs(60));
+        let state = state.with_cache(CacheSettings {
+            enabled: false,
+            ..CacheSettings::default()
+        });
+        complete(&state, "a").await;
+        complete(&state, "a").await;
+        assert_eq!(calls(&client), 2);
+    }
+
+    #[tokio::test]
+    async fn test_cached_completions_are_redacted() {
+        let rules = cache_redaction(r#"{"rules": [{"name": "email", "shape": "email"}]}"#)
+            .unwrap()
+            .unwrap();
+        let client = Arc::new(CountingClient {
+            inner: MockClient::with_response("Write to jane@example.com"),
+            calls: Default::default(),
+            delay: Duration::ZERO,
+        });
+        let state = AppState::new(client.clone() as Arc<dyn LlmClient>).with_cache_redaction(rules);
+        let complete = |prompt: &str| {
+            post(
+                state.clone(),
+                "/api/v1/complete",
+                json!({ "prompt": prompt }),
+            )
+        };
+
+        let (_, _, body) = complete("Who do I write to?").await;
+        assert_eq!(body["content"], "Write to jane@example.com");
+        assert_eq!(
+            state.cache.read().await[0].response,
+            "Write to [EMAIL:medium]"
+        );
+        let (_, _, body) = complete("Who do I write to?").await;
+        assert_eq!(body["cached"], true);
+        assert_eq!(body["content"], "Write to [EMAIL:medium]");
+
+        // Prompts the rules change are neither looked up nor stored
+        complete("Is jane@example.com right?").await;
+        let (_, _, body) = complete("Is jane@example.com right?").await;
+        assert_eq!(body["cached"], false);
+        assert_eq!(calls(&client), 3);
+        assert_eq!(state.cache.read().await.len(), 1);
+    }
+
+    #[test]
+    fn test_cache_redaction_follows_its_flag() {
+        let rules = r#"[{"name": "email", "shape": "email"}]"#;
+        let config = |cache: bool| format!(r#"{{"rules": {}, "cache": {}}}"#, rules, cache);
+        assert!(cache_redaction(&config(true)).unwrap().is_some());
+        assert!(cache_redaction(&config(false)).unwrap().is_none());
+        // No rules, nothing to apply
+        assert!(cache_redaction("{}").unwrap().is_none());
+        let err = cache_redaction(r#"{"rules": [{"name": "x", "pattern": "("}]}"#).unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("REDACTION_CONFIG is invalid: redaction rule 'x'"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_shared_settings_configure_the_client() {
+        // The `[ai]` section of the service's own ggen.toml
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/ggen.toml");
+        let settings = SharedConfig::from_file(path.as_ref()).unwrap();
+        assert!(!settings.deprecations.is_empty());
+        let config = LlmConfig::from(settings.default_provider().unwrap());
+        assert_eq!(config.model, "gpt-4");
+        assert_eq!(config.temperature, Some(0.7));
+        assert_eq!(config.max_tokens, Some(2000));
+        let cache = settings.cache.unwrap();
+        assert_eq!((cache.ttl_secs, cache.max_entries), (3600, 1000));
+
+        // One file configuring both this service and rig-mcp
+        let path = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../../../ggen-config/tests/fixtures/combined.toml"
+        );
+        let settings = SharedConfig::from_file(path.as_ref()).unwrap();
+        let config = LlmConfig::from(settings.default_provider().unwrap());
+        assert_eq!(config.model, "gpt-4o");
+        assert_eq!(config.temperature, Some(0.2));
+        let mcp = rig_mcp_integration::Config::from_file(path).unwrap();
+        assert_eq!(mcp.mcp_servers.len(), 1);
+    }
+
+    /// Embeds texts mentioning a repository apart from everything else
+    #[derive(Debug)]
+    struct RepositoryEmbeddings;
+
+    #[async_trait]
+    impl ggen_ai::EmbeddingModel for RepositoryEmbeddings {
+        async fn embed(&self, texts: &[String]) -> ggen_ai::Result<Vec<Vec<f32>>> {
+            Ok(texts
+                .iter()
+                .map(|text| match text.to_lowercase().contains("repository") {
+                    true => vec![1.0, 0.0],
+                    false => vec![0.0, 1.0],
+                })
+                .collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_template_context_adds_the_relevant_exemplar() {
+        let client = MockClient::with_response("---\nto: \"out.rs\"\n---\nfn {{ name }}() {}");
+        let selector =
+            ContextSelector::new(Arc::new(RepositoryEmbeddings)).with_config(ContextConfig {
+                top_k: 1,
+                ..ContextConfig::default()
+            });
+        let state = AppState::new(Arc::new(client.clone()) as Arc<dyn LlmClient>)
+            .with_template_context(selector);
+        let request = json!({
+            "description": "A repository for products",
+            "language": "rust",
+            "context": {"snippets": [
+                {"path": "src/order_controller.rs", "content": "fn handle_order() {}"},
+                {"path": "src/user_repository.rs", "content": "fn find_user_by_id() {}"}
+            ]}
+        });
+
+        let (status, _, body) = post(state, "/api/v1/template/generate", request.clone()).await;
+
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["variables"], json!(["name"]));
+        let prompt = &client.prompts()[0];
+        assert!(prompt.contains("<<<EXEMPLAR src/user_repository.rs>>>"));
+        assert!(!prompt.contains("handle_order"));
+
+        // Without an embedding model the context can't be used
+        let (status, _, _) = post(mock_state(), "/api/v1/template/generate", request).await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[tokio::test]
+    async fn test_generated_template_is_linted() {
+        let client = MockClient::with_response(
+            "---\nto: \"{{ name | snake }}.rs\"\n---\npub struct {{ name | pascl }};\n",
+        );
+        let state = AppState::new(Arc::new(client) as Arc<dyn LlmClient>);
+        let request = json!({
+            "description": "A struct per entity",
+            "language": "rust",
+            "variables": {"name": "order"}
+        });
+
+        let (status, _, body) = post(state, "/api/v1/template/generate", request).await;
+
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(
+            body["findings"],
+            json!([{
+                "line": 4,
+                "column": 22,
+                "severity": "error",
+                "code": "unknown-filter",
+                "message": "Unknown filter `pascl`"
+            }])
+        );
+    }
+}
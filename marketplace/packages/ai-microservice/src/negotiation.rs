@@ -0,0 +1,304 @@
+//! Generated documents as bare documents, by `Accept` header, with their
+//! metadata in `x-ggen-*` headers
+
+use axum::{
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::json;
+use tracing::warn;
+
+/// How a response carries its document, from the request's `Accept` header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Representation {
+    /// Wrapped in the endpoint's JSON response
+    Json,
+    /// Alone, as this media type
+    Raw(&'static str),
+}
+
+impl Representation {
+    /// The first media range of `Accept`, by quality, that the endpoint can
+    /// answer with; JSON without the header
+    ///
+    /// `raw` are the media types of the bare document; `text/*` picks the
+    /// first of them that is text.
+    pub(crate) fn negotiate(
+        headers: &HeaderMap, raw: &[&'static str],
+    ) -> Result<Self, NotAcceptable> {
+        let accept = headers
+            .get_all(header::ACCEPT)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .collect::<Vec<_>>()
+            .join(",");
+        if accept.trim().is_empty() {
+            return Ok(Self::Json);
+        }
+        let mut ranges: Vec<(String, f32)> = accept
+            .split(',')
+            .filter_map(|range| {
+                let mut params = range.split(';');
+                let media = params.next()?.trim().to_ascii_lowercase();
+                let quality = params
+                    .filter_map(|param| param.trim().strip_prefix("q="))
+                    .find_map(|quality| quality.trim().parse().ok())
+                    .unwrap_or(1.0);
+                (!media.is_empty() && quality > 0.0).then_some((media, quality))
+            })
+            .collect();
+        ranges.sort_by(|a, b| b.1.total_cmp(&a.1));
+        for (media, _) in &ranges {
+            let found = match media.as_str() {
+                "application/json" | "application/*" | "*/*" => return Ok(Self::Json),
+                "text/*" => raw.iter().find(|raw| raw.starts_with("text/")),
+                media => raw.iter().find(|raw| **raw == media),
+            };
+            if let Some(raw) = found {
+                return Ok(Self::Raw(raw));
+            }
+        }
+        Err(NotAcceptable {
+            available: std::iter::once("application/json")
+                .chain(raw.iter().copied())
+                .collect(),
+        })
+    }
+}
+
+/// An `Accept` header allowing none of the media types an endpoint answers
+/// with
+#[derive(Debug)]
+pub(crate) struct NotAcceptable {
+    available: Vec<&'static str>,
+}
+
+impl IntoResponse for NotAcceptable {
+    fn into_response(self) -> Response {
+        let message = format!("Acceptable media types are {}", self.available.join(", "));
+        warn!("{}", message);
+        let error = json!({
+            "kind": "not_acceptable",
+            "message": message,
+            "available": self.available,
+        });
+        (StatusCode::NOT_ACCEPTABLE, Json(json!({ "error": error }))).into_response()
+    }
+}
+
+/// A generated document without the JSON wrapper, as a download named
+/// `filename`; what the wrapper would have held goes into `metadata`
+/// headers
+pub(crate) struct RawDocument {
+    pub(crate) media_type: &'static str,
+    pub(crate) filename: String,
+    pub(crate) body: String,
+    pub(crate) metadata: Vec<(&'static str, String)>,
+}
+
+impl IntoResponse for RawDocument {
+    fn into_response(self) -> Response {
+        let content_type = format!("{}; charset=utf-8", self.media_type);
+        let mut response = ([(header::CONTENT_TYPE, content_type)], self.body).into_response();
+        let headers = response.headers_mut();
+        let disposition = format!("attachment; filename=\"{}\"", self.filename);
+        if let Ok(value) = HeaderValue::from_str(&disposition) {
+            headers.insert(header::CONTENT_DISPOSITION, value);
+        }
+        // Values that aren't valid header values, e.g. non-ASCII class
+        // names, are left out
+        for (name, value) in self.metadata {
+            if let Ok(value) = HeaderValue::from_str(&value) {
+                headers.insert(name, value);
+            }
+        }
+        response
+    }
+}
+
+/// `text` as a file name: lowercase ASCII letters and digits, with every
+/// run of anything else turned into one `-`; `fallback` if nothing is left
+pub(crate) fn file_stem(text: &str, fallback: &str) -> String {
+    let mut stem = String::new();
+    for c in text.chars() {
+        if c.is_ascii_alphanumeric() {
+            stem.push(c.to_ascii_lowercase());
+        } else if !stem.is_empty() && !stem.ends_with('-') {
+            stem.push('-');
+        }
+        if stem.len() >= 48 {
+            break;
+        }
+    }
+    let stem = stem.trim_end_matches('-');
+    if stem.is_empty() {
+        fallback.to_string()
+    } else {
+        stem.to_string()
+    }
+}
+
+/// File extension of code in `language`
+pub(crate) fn extension(language: &str) -> String {
+    let language = language.trim().to_ascii_lowercase();
+    let extension = match language.as_str() {
+        "rust" => "rs",
+        "python" => "py",
+        "typescript" => "ts",
+        "javascript" => "js",
+        "golang" => "go",
+        "ruby" => "rb",
+        "kotlin" => "kt",
+        "c#" | "csharp" => "cs",
+        "c++" | "cpp" => "cpp",
+        other => return file_stem(other, "txt"),
+    };
+    extension.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{post, post_raw, post_with_headers};
+    use crate::AppState;
+    use ggen_ai::{LlmClient, MockClient};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_templates_are_returned_raw_for_text_plain() {
+        let template = "---\nto: \"src/{{ name }}.rs\"\n---\npub struct {{ kind }};\n";
+        let client = MockClient::with_response(template);
+        let state = AppState::new(Arc::new(client.clone()) as Arc<dyn LlmClient>);
+        let uri = "/api/v1/template/generate";
+        let request = json!({"description": "A struct per entity!", "language": "Rust"});
+
+        let accept = [("accept", "text/plain")];
+        let (status, raw, body) = post_raw(state.clone(), uri, &accept, request.clone()).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(raw["content-type"], "text/plain; charset=utf-8");
+        assert_eq!(
+            raw["content-disposition"],
+            "attachment; filename=\"a-struct-per-entity.rs.tmpl\""
+        );
+        assert_eq!(raw["x-ggen-variables"], "name, kind");
+
+        let accept = [("accept", "application/json, text/plain;q=0.5")];
+        let (status, headers, json) = post_with_headers(state, uri, &accept, request).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(headers["content-type"], "application/json");
+        assert!(!headers.contains_key("x-ggen-variables"));
+        assert_eq!(json["template"], body.as_str());
+        assert_eq!(json["variables"], json!(["name", "kind"]));
+        let findings = json["findings"].as_array().unwrap().len();
+        assert_eq!(raw["x-ggen-findings"], findings.to_string().as_str());
+        assert_eq!(client.prompts().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_unsupported_media_types_are_not_acceptable() {
+        let client = MockClient::with_response("ok");
+        let state = AppState::new(Arc::new(client.clone()) as Arc<dyn LlmClient>);
+        let accept = [("accept", "application/xml")];
+
+        let (status, _, body) = post_with_headers(
+            state.clone(),
+            "/api/v1/template/generate",
+            &accept,
+            json!({"description": "A struct", "language": "rust"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_ACCEPTABLE);
+        assert_eq!(body["error"]["kind"], "not_acceptable");
+        assert_eq!(
+            body["error"]["available"],
+            json!(["application/json", "text/plain"])
+        );
+
+        let (status, _, body) = post_with_headers(
+            state,
+            "/api/v1/ontology/generate",
+            &[("accept", "text/html, application/json;q=0")],
+            json!({"domain": "billing", "concepts": ["invoice"]}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_ACCEPTABLE);
+        assert_eq!(
+            body["error"]["available"],
+            json!(["application/json", "text/turtle", "text/plain"])
+        );
+        assert!(client.prompts().is_empty());
+    }
+
+    #[test]
+    fn test_accept_ranges_are_tried_by_quality() {
+        let negotiate = |accept: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::ACCEPT, HeaderValue::from_str(accept).unwrap());
+            Representation::negotiate(&headers, &["text/turtle", "text/plain"]).ok()
+        };
+        assert_eq!(
+            Representation::negotiate(&HeaderMap::new(), &[]).ok(),
+            Some(Representation::Json)
+        );
+        assert_eq!(negotiate("*/*"), Some(Representation::Json));
+        assert_eq!(
+            negotiate("text/*"),
+            Some(Representation::Raw("text/turtle"))
+        );
+        assert_eq!(
+            negotiate("text/plain;q=0.9, TEXT/TURTLE"),
+            Some(Representation::Raw("text/turtle"))
+        );
+        assert_eq!(
+            negotiate("application/json;q=0.1, text/plain"),
+            Some(Representation::Raw("text/plain"))
+        );
+        assert_eq!(negotiate("image/png, text/plain;q=0"), None);
+    }
+
+    #[test]
+    fn test_file_names_are_derived_from_the_request() {
+        assert_eq!(
+            file_stem("  Orders & Invoices (v2) ", "x"),
+            "orders-invoices-v2"
+        );
+        assert_eq!(file_stem("¿?", "ontology"), "ontology");
+        assert_eq!(file_stem(&"a".repeat(100), "x").len(), 48);
+        assert_eq!(extension("TypeScript"), "ts");
+        assert_eq!(extension("elixir"), "elixir");
+    }
+
+    #[tokio::test]
+    async fn test_ontologies_are_returned_as_turtle() {
+        let turtle = "@prefix ex: <http://example.org/> .\n\
+                      @prefix owl: <http://www.w3.org/2002/07/owl#> .\n\
+                      ex:Invoice a owl:Class .\n";
+        let state =
+            AppState::new(Arc::new(MockClient::with_response(turtle)) as Arc<dyn LlmClient>);
+        let request = json!({"domain": "Billing", "concepts": ["Invoice", "Customer"]});
+
+        let (status, headers, body) = post_raw(
+            state.clone(),
+            "/api/v1/ontology/generate",
+            &[("accept", "text/turtle")],
+            request.clone(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(headers["content-type"], "text/turtle; charset=utf-8");
+        assert_eq!(
+            headers["content-disposition"],
+            "attachment; filename=\"billing.ttl\""
+        );
+        assert_eq!(headers["x-ggen-classes"], "Invoice, Customer");
+        assert_eq!(headers["x-ggen-properties"], "hasProperty, relatesTo");
+        assert!(body.contains("Invoice"), "{}", body);
+
+        let (status, headers, json) = post(state, "/api/v1/ontology/generate", request).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(!headers.contains_key("content-disposition"));
+        assert_eq!(json["rdf_turtle"], body.as_str());
+        assert_eq!(json["classes"], json!(["Invoice", "Customer"]));
+    }
+}
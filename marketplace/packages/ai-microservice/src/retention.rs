@@ -0,0 +1,318 @@
+//! Retention limits and per-tenant purges of stored data, recorded in an
+//! audit log
+
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use rig_mcp_integration::{AuditLog, Purge};
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::{admin_key_matches, env_secs, AppError, AppState};
+
+/// Metadata of stored data, from the `x-metadata-*` headers of the request
+/// that stored it, e.g. `x-metadata-tenant: acme`
+pub(crate) type Metadata = BTreeMap<String, String>;
+
+const METADATA_HEADER_PREFIX: &str = "x-metadata-";
+
+pub(crate) fn metadata(headers: &HeaderMap) -> Metadata {
+    headers
+        .iter()
+        .filter_map(|(name, value)| {
+            let key = name.as_str().strip_prefix(METADATA_HEADER_PREFIX)?;
+            Some((key.to_string(), value.to_str().ok()?.to_string()))
+        })
+        .collect()
+}
+
+/// How long stored data is kept, and who may purge it
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RetentionConfig {
+    /// Cached completions older than this are purged, even while the cache
+    /// would still serve them
+    pub(crate) cache_max_age: Option<Duration>,
+    /// Ingested documents older than this are purged
+    pub(crate) documents_max_age: Option<Duration>,
+    /// Time between scheduled purges, give or take a tenth
+    pub(crate) purge_interval: Duration,
+    /// Expected in the `x-admin-key` header of purge requests; the purge
+    /// endpoint isn't served without one
+    pub(crate) admin_key: Option<String>,
+    /// JSON Lines file purges are recorded in
+    pub(crate) audit_log: Option<PathBuf>,
+}
+
+/// Retention limits, and the audit log purges are recorded in
+#[derive(Clone, Default)]
+pub(crate) struct Retention {
+    pub(crate) config: RetentionConfig,
+    pub(crate) audit: Option<Arc<AuditLog>>,
+}
+
+/// Time between scheduled purges unless configured
+const DEFAULT_PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Retention limits: `RETENTION_CACHE_MAX_AGE_SECS`,
+/// `RETENTION_DOCUMENTS_MAX_AGE_SECS` and `RETENTION_PURGE_INTERVAL_SECS`;
+/// `RETENTION_ADMIN_KEY` serves the purge endpoint and `AUDIT_LOG_PATH`
+/// records purges
+pub(crate) fn retention_from_env() -> anyhow::Result<RetentionConfig> {
+    Ok(RetentionConfig {
+        cache_max_age: env_secs("RETENTION_CACHE_MAX_AGE_SECS")?,
+        documents_max_age: env_secs("RETENTION_DOCUMENTS_MAX_AGE_SECS")?,
+        purge_interval: env_secs("RETENTION_PURGE_INTERVAL_SECS")?
+            .unwrap_or(DEFAULT_PURGE_INTERVAL),
+        admin_key: std::env::var("RETENTION_ADMIN_KEY").ok(),
+        audit_log: std::env::var_os("AUDIT_LOG_PATH").map(PathBuf::from),
+    })
+}
+
+/// Records deleted by a purge, by store
+type Deleted = BTreeMap<String, usize>;
+
+impl AppState {
+    /// Delete everything stored with `key: value` in its metadata from the
+    /// completion cache, the idempotent responses and the document index
+    async fn delete_by_metadata(&self, key: &str, value: &str) -> anyhow::Result<Deleted> {
+        let matches = |metadata: &Metadata| metadata.get(key).is_some_and(|v| v == value);
+        let mut deleted = Deleted::new();
+        let mut cache = self.cache.write().await;
+        let before = cache.len();
+        cache.retain(|cached| !matches(&cached.metadata));
+        deleted.insert("cache".to_string(), before - cache.len());
+        drop(cache);
+        let removed = self.idempotency.remove_where(matches).await;
+        deleted.insert("idempotency".to_string(), removed);
+        if let Some(ingest) = &self.ingest {
+            let removed = ingest
+                .documents
+                .remove_where(|document| matches(&document.metadata))
+                .await;
+            deleted.insert("documents".to_string(), removed);
+        }
+        let matching = Metadata::from([(key.to_string(), value.to_string())]);
+        self.record_purge(matching, &deleted).await?;
+        Ok(deleted)
+    }
+
+    /// Delete the data older than the retention limits at `now`, and cached
+    /// completions past their TTL; idempotent responses expire on their own
+    /// after the idempotency window
+    async fn purge_expired(&self, now: chrono::DateTime<chrono::Utc>) -> anyhow::Result<Deleted> {
+        let limits = &self.retention.config;
+        let expired = |at: chrono::DateTime<chrono::Utc>, max_age: Option<Duration>| {
+            let age = (now - at).to_std().unwrap_or_default();
+            max_age.is_some_and(|max_age| age >= max_age)
+        };
+        let mut deleted = Deleted::new();
+        let mut cache = self.cache.write().await;
+        let before = cache.len();
+        cache.retain(|cached| {
+            cached.is_fresh(&self.cache_settings, now)
+                && !expired(cached.timestamp, limits.cache_max_age)
+        });
+        deleted.insert("cache".to_string(), before - cache.len());
+        drop(cache);
+        self.idempotency.forget_expired(now).await;
+        if let Some(ingest) = &self.ingest {
+            let removed = ingest
+                .documents
+                .remove_where(|document| expired(document.indexed_at, limits.documents_max_age))
+                .await;
+            deleted.insert("documents".to_string(), removed);
+        }
+        if deleted.values().any(|count| *count > 0) {
+            self.record_purge(Metadata::new(), &deleted).await?;
+        }
+        Ok(deleted)
+    }
+
+    async fn record_purge(&self, matching: Metadata, deleted: &Deleted) -> anyhow::Result<()> {
+        info!(?matching, ?deleted, "Purged stored data");
+        let Some(audit) = &self.retention.audit else {
+            return Ok(());
+        };
+        let purge = Purge {
+            at: chrono::Utc::now(),
+            matching,
+            deleted: deleted.clone(),
+        };
+        audit
+            .record_purge(&purge)
+            .await
+            .map_err(|err| err.context("Deleted the data but failed to record the purge"))
+    }
+}
+
+/// Purge expired data every `interval`, give or take a tenth so replicas
+/// started together don't all purge at once
+pub(crate) async fn purge_periodically(state: AppState, interval: Duration) {
+    loop {
+        tokio::time::sleep(jittered(interval)).await;
+        if let Err(err) = state.purge_expired(chrono::Utc::now()).await {
+            warn!("Scheduled purge failed: {:#}", err);
+        }
+    }
+}
+
+/// `interval` moved by up to a tenth either way
+fn jittered(interval: Duration) -> Duration {
+    // The random bits of a v4 UUID stand in for a random number generator
+    let unit = (uuid::Uuid::new_v4().as_u64_pair().1 % 1_000_000) as f64 / 1_000_000.0;
+    interval.mul_f64(0.9 + 0.2 * unit)
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct PurgeRequest {
+    key: String,
+    value: String,
+}
+
+/// Delete everything stored with the metadata `key: value`, e.g. when a
+/// tenant leaves; answers with the number of records deleted from each
+/// store
+pub(crate) async fn purge(
+    State(state): State<AppState>, headers: HeaderMap, Json(req): Json<PurgeRequest>,
+) -> Result<Response, AppError> {
+    if !admin_key_matches(&headers, state.retention.config.admin_key.as_deref()) {
+        let error = json!({"kind": "unauthorized", "message": "Missing or wrong x-admin-key"});
+        return Ok((StatusCode::UNAUTHORIZED, Json(json!({ "error": error }))).into_response());
+    }
+    let deleted = state.delete_by_metadata(&req.key, &req.value).await?;
+    Ok(Json(json!({ "deleted": deleted })).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app;
+    use crate::idempotency::IdempotencyStore;
+    use crate::ingest::{IndexedDocument, IngestConfig};
+    use crate::tests::{mock_state, post, post_with_headers, CountingEmbeddings};
+    use axum::body::Body;
+    use axum::http::Request;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_purge_deletes_one_tenant_from_every_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let audit_log = dir.path().join("audit.jsonl");
+        let retention = RetentionConfig {
+            admin_key: Some("secret".to_string()),
+            audit_log: Some(audit_log.clone()),
+            ..RetentionConfig::default()
+        };
+        let state = mock_state()
+            .with_ingest(
+                Arc::new(CountingEmbeddings::default()),
+                IngestConfig::default(),
+            )
+            .with_retention(retention);
+        let documents = state.ingest.as_ref().unwrap().documents.clone();
+        for tenant in ["acme", "globex"] {
+            let headers = [("x-metadata-tenant", tenant), ("idempotency-key", tenant)];
+            let body = json!({"prompt": format!("Summarize the {} contract", tenant)});
+            let (status, _, _) =
+                post_with_headers(state.clone(), "/api/v1/complete", &headers, body).await;
+            assert_eq!(status, StatusCode::OK);
+
+            let upload = json!({"id": format!("{}-report", tenant), "text": "Quarterly report"});
+            let request = Request::post("/api/v1/embeddings/ingest")
+                .header("x-metadata-tenant", tenant)
+                .body(Body::from(format!("{}\n", upload)))
+                .unwrap();
+            let response = app(state.clone()).oneshot(request).await.unwrap();
+            // Indexed by the time the response ends
+            axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+        }
+
+        let purge = json!({"key": "tenant", "value": "acme"});
+        let (status, _, _) = post(state.clone(), "/api/v1/retention/purge", purge.clone()).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, _, body) = post_with_headers(
+            state.clone(),
+            "/api/v1/retention/purge",
+            &[("x-admin-key", "secret")],
+            purge,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let deleted = json!({"cache": 1, "documents": 1, "idempotency": 1});
+        assert_eq!(body["deleted"], deleted);
+
+        let cache = state.cache.read().await;
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache[0].metadata["tenant"], "globex");
+        drop(cache);
+        assert!(documents.get("acme-report").await.is_none());
+        assert!(documents.get("globex-report").await.is_some());
+        let key = |tenant: &str| IdempotencyStore::key("", "/api/v1/complete", tenant);
+        assert!(state.idempotency.get(&key("acme")).await.is_none());
+        assert!(state.idempotency.get(&key("globex")).await.is_some());
+
+        let purges = AuditLog::new(&audit_log).purges().unwrap();
+        assert_eq!(purges.len(), 1);
+        assert_eq!(purges[0].matching["tenant"], "acme");
+        assert_eq!(json!(purges[0].deleted), deleted);
+    }
+
+    #[tokio::test]
+    async fn test_data_past_its_retention_limit_is_purged() {
+        let dir = tempfile::tempdir().unwrap();
+        let audit_log = dir.path().join("audit.jsonl");
+        let retention = RetentionConfig {
+            cache_max_age: Some(Duration::from_secs(10 * 60)),
+            documents_max_age: Some(Duration::from_secs(24 * 60 * 60)),
+            audit_log: Some(audit_log.clone()),
+            ..RetentionConfig::default()
+        };
+        let state = mock_state()
+            .with_ingest(
+                Arc::new(CountingEmbeddings::default()),
+                IngestConfig::default(),
+            )
+            .with_retention(retention);
+        let (status, _, _) = post(state.clone(), "/api/v1/complete", json!({"prompt": "Hi"})).await;
+        assert_eq!(status, StatusCode::OK);
+        let documents = state.ingest.as_ref().unwrap().documents.clone();
+        let report = IndexedDocument {
+            text: "Quarterly report".to_string(),
+            vector: vec![1.0],
+            metadata: Metadata::new(),
+            indexed_at: chrono::Utc::now(),
+        };
+        documents.insert([("report".to_string(), report)]).await;
+
+        let now = chrono::Utc::now();
+        let deleted = state.purge_expired(now).await.unwrap();
+        assert_eq!(json!(deleted), json!({"cache": 0, "documents": 0}));
+        assert!(AuditLog::new(&audit_log).purges().unwrap().is_empty());
+
+        // Still within the cache's TTL, but past the retention limit
+        let deleted = state
+            .purge_expired(now + chrono::Duration::minutes(30))
+            .await
+            .unwrap();
+        assert_eq!(json!(deleted), json!({"cache": 1, "documents": 0}));
+        let deleted = state
+            .purge_expired(now + chrono::Duration::days(2))
+            .await
+            .unwrap();
+        assert_eq!(json!(deleted), json!({"cache": 0, "documents": 1}));
+
+        let purges = AuditLog::new(&audit_log).purges().unwrap();
+        assert_eq!(purges.len(), 2);
+        assert!(purges.iter().all(|purge| purge.matching.is_empty()));
+    }
+}
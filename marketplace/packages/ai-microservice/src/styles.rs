@@ -0,0 +1,154 @@
+//! Response language and tone set per service, per API key or per request
+
+use ggen_ai::{ResponseStyle, Tone};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Response language and tone defaults
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ResponseStyles {
+    service: ResponseStyle,
+    /// Defaults of callers, by API key
+    by_key: HashMap<String, ResponseStyle>,
+}
+
+impl ResponseStyles {
+    /// What `request` sets, then the caller's default, then the service's
+    pub(crate) fn effective(&self, api_key: &str, request: &StyleOverride) -> ResponseStyle {
+        let request = ResponseStyle {
+            language: request.response_language.as_ref().map(|l| l.to_lowercase()),
+            tone: request.tone,
+        };
+        match self.by_key.get(api_key) {
+            Some(caller) => request.or(&caller.or(&self.service)),
+            None => request.or(&self.service),
+        }
+    }
+}
+
+/// Response style fields of a request body
+#[derive(Debug, Default, Deserialize)]
+pub(crate) struct StyleOverride {
+    #[serde(default)]
+    response_language: Option<String>,
+    #[serde(default)]
+    tone: Option<Tone>,
+}
+
+/// Response language and tone defaults: `RESPONSE_LANGUAGE`, a language tag
+/// such as `fr`, and `RESPONSE_TONE` for the service, and
+/// `API_KEY_RESPONSE_STYLES`, a JSON object of `{"language", "tone"}` by API
+/// key, for callers
+pub(crate) fn response_styles_from_env() -> anyhow::Result<ResponseStyles> {
+    let tone = match std::env::var("RESPONSE_TONE") {
+        Ok(value) => Some(
+            serde_json::from_value::<Tone>(Value::from(value.as_str())).map_err(|_| {
+                anyhow::anyhow!(
+                    "RESPONSE_TONE must be neutral, formal, friendly or concise, got '{}'",
+                    value
+                )
+            })?,
+        ),
+        Err(_) => None,
+    };
+    let language = std::env::var("RESPONSE_LANGUAGE").ok();
+    let service = ResponseStyle::new(language.as_deref(), tone)
+        .map_err(|err| anyhow::anyhow!("RESPONSE_LANGUAGE is invalid: {}", err))?;
+    let mut by_key = HashMap::new();
+    if let Ok(value) = std::env::var("API_KEY_RESPONSE_STYLES") {
+        let styles: HashMap<String, ResponseStyle> = serde_json::from_str(&value)
+            .map_err(|err| anyhow::anyhow!("API_KEY_RESPONSE_STYLES is invalid: {}", err))?;
+        for (key, style) in styles {
+            // Normalizes and checks the language tag
+            let style =
+                ResponseStyle::new(style.language.as_deref(), style.tone).map_err(|err| {
+                    anyhow::anyhow!("API_KEY_RESPONSE_STYLES has an invalid language: {}", err)
+                })?;
+            by_key.insert(key, style);
+        }
+    }
+    Ok(ResponseStyles { service, by_key })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{mock_state, post, post_with_headers};
+    use crate::AppState;
+    use axum::http::StatusCode;
+    use ggen_ai::{LlmClient, MockClient};
+    use serde_json::json;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_request_style_beats_key_default_beats_service_default() {
+        let client = MockClient::with_response("ok");
+        let styles = ResponseStyles {
+            service: ResponseStyle::new(Some("de"), Some(Tone::Formal)).unwrap(),
+            by_key: HashMap::from([(
+                "tenant-fr".to_string(),
+                ResponseStyle::new(Some("fr"), None).unwrap(),
+            )]),
+        };
+        let state = AppState::new(Arc::new(client.clone()) as Arc<dyn LlmClient>)
+            .with_response_styles(styles);
+        let uri = "/api/v1/complete";
+        let tenant = [("x-api-key", "tenant-fr")];
+
+        let (_, _, body) = post(state.clone(), uri, json!({"prompt": "hi"})).await;
+        assert_eq!(body["cached"], false);
+        let (_, _, body) =
+            post_with_headers(state.clone(), uri, &tenant, json!({"prompt": "hi"})).await;
+        // Same prompt, but cached under another language
+        assert_eq!(body["cached"], false);
+        let request = json!({"prompt": "hi", "response_language": "it", "tone": "concise"});
+        post_with_headers(state.clone(), uri, &tenant, request).await;
+        let (_, _, body) = post(state, uri, json!({"prompt": "hi"})).await;
+        assert_eq!(body["cached"], true);
+
+        let prompts = client.prompts();
+        assert_eq!(prompts.len(), 3);
+        assert!(prompts[0].starts_with(
+            "## Response Style\n- Write your answer in German (de).\n- Use a formal tone.\n\nhi"
+        ));
+        assert!(prompts[1].contains("- Write your answer in French (fr).\n- Use a formal tone."));
+        assert!(prompts[2].contains(
+            "- Write your answer in Italian (it).\n- Be concise; leave out anything that isn't needed."
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_ontology_comments_are_tagged_with_the_response_language() {
+        let turtle = "@prefix ex: <http://example.org/> .\n\
+                      @prefix owl: <http://www.w3.org/2002/07/owl#> .\n\
+                      @prefix rdfs: <http://www.w3.org/2000/01/rdf-schema#> .\n\
+                      ex:Facture a owl:Class ; rdfs:comment \"Une facture émise au client\" .\n";
+        let client = MockClient::with_response(turtle);
+        let state = AppState::new(Arc::new(client.clone()) as Arc<dyn LlmClient>);
+
+        let (status, _, body) = post(
+            state,
+            "/api/v1/ontology/generate",
+            json!({"domain": "billing", "concepts": ["invoice"], "response_language": "fr"}),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert!(client.prompts()[0].contains("Write every rdfs:comment in French (fr)."));
+        let rdf = body["rdf_turtle"].as_str().unwrap();
+        assert!(
+            rdf.contains("rdfs:comment \"Une facture émise au client\"@fr"),
+            "{}",
+            rdf
+        );
+
+        let (status, _, body) = post(
+            mock_state(),
+            "/api/v1/complete",
+            json!({"prompt": "hi", "response_language": "fr_FR"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+    }
+}
@@ -0,0 +1,1097 @@
+//! Tenants bringing their own provider credentials, requests picking their
+//! model, and usage and cost accounted by tenant and by whose credentials
+//! were used
+
+use axum::{
+    async_trait,
+    body::Body,
+    extract::State,
+    http::{HeaderMap, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use futures::stream::StreamExt;
+use ggen_ai::{GgenAiError, LlmChunk, LlmClient, LlmConfig, LlmResponse, UsageStats};
+use rig_mcp_integration::pricing;
+use rig_mcp_integration::provider::StreamChunk;
+use rig_mcp_integration::transport::{HttpRequest, HttpResponse};
+use rig_mcp_integration::{
+    alias, ChatMessage, ChatRequest, HttpProvider, HttpTransport, NormalizedUsage, Price, Provider,
+    ProviderConfig, ReqwestTransport, SecretSource,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use crate::{admin_key_matches, api_key, env_secs, AppState};
+
+/// How requests pick the model answering them
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ModelSelection {
+    /// The service's provider; requests can't pick a model without one
+    pub(crate) provider: Option<String>,
+    pub(crate) aliases: HashMap<String, String>,
+}
+
+impl ModelSelection {
+    /// The model `requested`, a model alias or a `provider/model` pair,
+    /// names; only models of the service's provider can be picked
+    pub(crate) fn resolve(&self, requested: &str) -> Result<String, String> {
+        let model = alias::resolve(&self.aliases, requested).map_err(|err| err.to_string())?;
+        match &self.provider {
+            Some(provider) if *provider == model.provider => Ok(model.model),
+            Some(provider) => Err(format!(
+                "{} isn't a model of the service's provider, {}",
+                model, provider
+            )),
+            None => Err(format!(
+                "{} can't be picked: no provider is configured",
+                model
+            )),
+        }
+    }
+}
+
+/// Callers by API key, and how what they use is accounted; see
+/// [`tenants_from_env`]
+#[derive(Debug, Clone)]
+pub(crate) struct TenantConfig {
+    by_key: HashMap<String, Tenant>,
+    /// How long a tenant's client is kept without a request
+    client_idle: Duration,
+    /// USD per million tokens by `provider/model` or provider
+    prices: HashMap<String, Price>,
+    /// Expected in the `x-admin-key` header of the usage endpoint
+    pub(crate) admin_key: Option<String>,
+}
+
+impl Default for TenantConfig {
+    fn default() -> Self {
+        Self {
+            by_key: HashMap::new(),
+            client_idle: DEFAULT_TENANT_CLIENT_IDLE,
+            prices: HashMap::new(),
+            admin_key: None,
+        }
+    }
+}
+
+/// A caller bringing its own provider credentials
+#[derive(Debug, Clone, Deserialize)]
+struct Tenant {
+    name: String,
+    /// Overrides by provider name; providers left out use the service's
+    #[serde(default)]
+    providers: HashMap<String, CredentialOverride>,
+}
+
+impl Tenant {
+    /// Reject overrides without a key of their own: the router never lends
+    /// the service's, so every request through them would fail
+    fn check(&self) -> Result<(), String> {
+        for (provider, credentials) in &self.providers {
+            if credentials.api_key.is_none() {
+                return Err(format!(
+                    "tenant '{}' overrides the {} credentials without an api_key",
+                    self.name, provider
+                ));
+            }
+            if let Some(base_url) = &credentials.base_url {
+                if !base_url.starts_with("http://") && !base_url.starts_with("https://") {
+                    return Err(format!(
+                        "the {} base_url of tenant '{}' isn't an http(s) URL: {}",
+                        provider, self.name, base_url
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What a tenant changes about a provider; anything left out is the
+/// service's
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CredentialOverride {
+    /// A key, or where to find it, as `env:`, `file:`, `keyring:` or `exec:`
+    #[serde(default)]
+    api_key: Option<String>,
+    #[serde(default)]
+    base_url: Option<String>,
+    /// Sent as `OpenAI-Organization`
+    #[serde(default)]
+    organization: Option<String>,
+}
+
+impl std::fmt::Debug for CredentialOverride {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CredentialOverride")
+            .field("api_key", &self.api_key.as_deref().map(SecretSource::parse))
+            .field("base_url", &self.base_url)
+            .field("organization", &self.organization)
+            .finish()
+    }
+}
+
+tokio::task_local! {
+    /// API key of the request being handled, for [`TenantRouter`]
+    static REQUEST_API_KEY: String;
+    /// Model the request being handled picked, for [`TenantRouter`]
+    pub(crate) static REQUEST_MODEL: String;
+}
+
+/// Make the caller's API key known to the [`TenantRouter`] while the request
+/// is handled
+pub(crate) async fn scope_api_key(request: Request<Body>, next: Next) -> Response {
+    let key = api_key(request.headers()).to_string();
+    REQUEST_API_KEY.scope(key, next.run(request)).await
+}
+
+/// Whose credentials a completion was paid with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Credentials {
+    /// The service's
+    House,
+    /// The tenant's own key
+    Tenant,
+}
+
+/// What usage is accounted to
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+struct Account {
+    /// `None` for callers that aren't tenants
+    tenant: Option<String>,
+    provider: String,
+    model: String,
+    credentials: Credentials,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+struct AccountUsage {
+    /// Completions, failed ones included
+    requests: u64,
+    prompt_tokens: u64,
+    completion_tokens: u64,
+    /// In USD; `None` without a price for the model
+    cost_usd: Option<f64>,
+}
+
+/// Usage of an account, as the usage endpoint lists it
+#[derive(Debug, Clone, Serialize)]
+struct AccountReport {
+    #[serde(flatten)]
+    account: Account,
+    #[serde(flatten)]
+    usage: AccountUsage,
+}
+
+/// Usage and cost by tenant, provider, model and credentials since the
+/// service started
+#[derive(Debug, Clone)]
+pub(crate) struct UsageLedger {
+    accounts: Arc<Mutex<BTreeMap<Account, AccountUsage>>>,
+    prices: Arc<HashMap<String, Price>>,
+}
+
+impl UsageLedger {
+    fn new(prices: HashMap<String, Price>) -> Self {
+        Self {
+            accounts: Arc::default(),
+            prices: Arc::new(prices),
+        }
+    }
+
+    /// Count a completion; `usage` is `None` for one that failed or didn't
+    /// report any
+    fn record(&self, account: &Account, usage: Option<&UsageStats>) {
+        let mut accounts = self.accounts.lock().unwrap();
+        let entry = accounts.entry(account.clone()).or_default();
+        entry.requests += 1;
+        let Some(usage) = usage else {
+            return;
+        };
+        let usage = NormalizedUsage::new(
+            u64::from(usage.prompt_tokens),
+            u64::from(usage.completion_tokens),
+        );
+        entry.prompt_tokens += usage.prompt_tokens;
+        entry.completion_tokens += usage.completion_tokens;
+        if let Some(price) = pricing::lookup(&self.prices, &account.provider, &account.model) {
+            *entry.cost_usd.get_or_insert(0.0) += price.cost(&usage);
+        }
+    }
+
+    fn report(&self) -> Vec<AccountReport> {
+        let accounts = self.accounts.lock().unwrap();
+        accounts
+            .iter()
+            .map(|(account, usage)| AccountReport {
+                account: account.clone(),
+                usage: *usage,
+            })
+            .collect()
+    }
+}
+
+/// Tenant whose credentials a pooled client uses (`None` for the service's),
+/// provider and model
+type PoolKey = (Option<String>, String, String);
+
+struct PooledClient {
+    client: Arc<dyn LlmClient>,
+    last_used: Instant,
+}
+
+/// Clients built with tenants' credentials, dropped once idle for longer
+/// than `idle`
+struct ClientPool {
+    idle: Duration,
+    clients: Mutex<HashMap<PoolKey, PooledClient>>,
+}
+
+impl ClientPool {
+    fn new(idle: Duration) -> Self {
+        Self {
+            idle,
+            clients: Mutex::default(),
+        }
+    }
+
+    fn get(&self, key: &PoolKey, now: Instant) -> Option<Arc<dyn LlmClient>> {
+        let mut clients = self.clients.lock().unwrap();
+        let pooled = clients.get_mut(key)?;
+        pooled.last_used = now;
+        Some(pooled.client.clone())
+    }
+
+    /// Pool `client` unless another was pooled under `key` meanwhile,
+    /// returning the pooled one
+    fn insert(&self, key: PoolKey, client: Arc<dyn LlmClient>, now: Instant) -> Arc<dyn LlmClient> {
+        let mut clients = self.clients.lock().unwrap();
+        let pooled = clients.entry(key).or_insert(PooledClient {
+            client,
+            last_used: now,
+        });
+        pooled.last_used = now;
+        pooled.client.clone()
+    }
+
+    /// Drop the clients idle at `now`, returning how many
+    fn evict_idle(&self, now: Instant) -> usize {
+        let mut clients = self.clients.lock().unwrap();
+        let before = clients.len();
+        clients.retain(|_, pooled| now.saturating_duration_since(pooled.last_used) <= self.idle);
+        before - clients.len()
+    }
+}
+
+/// [`LlmClient`] completing each request with its caller's credentials and
+/// the model it picked
+///
+/// Callers whose API key belongs to a tenant with an override for the
+/// service's provider get a client built from the service's provider config
+/// with the override applied, pooled per tenant, provider and model; so do
+/// requests picking another model than the service's, scoped with
+/// [`REQUEST_MODEL`]. Everyone else, and requests handled outside
+/// [`scope_api_key`], get the service's client. Every completion is counted
+/// into the [`UsageLedger`] under the model that answered it.
+pub(crate) struct TenantRouter {
+    house: Arc<dyn LlmClient>,
+    /// Provider config the service's client was made from, to build tenant
+    /// clients from
+    house_provider: Option<ProviderConfig>,
+    tenants: HashMap<String, Tenant>,
+    pool: ClientPool,
+    transport: Arc<dyn HttpTransport>,
+    usage: UsageLedger,
+}
+
+impl std::fmt::Debug for TenantRouter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TenantRouter")
+            .field("house", &self.house)
+            .field("tenants", &self.tenants.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl TenantRouter {
+    pub(crate) fn new(
+        house: Arc<dyn LlmClient>, house_provider: Option<ProviderConfig>, config: &TenantConfig,
+    ) -> anyhow::Result<Self> {
+        for tenant in config.by_key.values() {
+            if tenant.providers.is_empty() {
+                continue;
+            }
+            let Some(provider) = &house_provider else {
+                anyhow::bail!(
+                    "Tenant '{}' overrides provider credentials, but no provider is configured",
+                    tenant.name
+                );
+            };
+            for name in tenant.providers.keys() {
+                if name != &provider.name {
+                    warn!(
+                        tenant = %tenant.name,
+                        provider = %name,
+                        "Ignoring credentials for a provider the service doesn't use"
+                    );
+                }
+            }
+        }
+        Ok(Self {
+            house,
+            house_provider,
+            tenants: config.by_key.clone(),
+            pool: ClientPool::new(config.client_idle),
+            transport: Arc::new(ReqwestTransport::default()),
+            usage: UsageLedger::new(config.prices.clone()),
+        })
+    }
+
+    pub(crate) fn usage(&self) -> UsageLedger {
+        self.usage.clone()
+    }
+
+    /// Tenants by the API keys whose requests their own credentials pay for
+    pub(crate) fn credential_owners(&self) -> HashMap<String, String> {
+        let Some(provider) = &self.house_provider else {
+            return HashMap::new();
+        };
+        self.tenants
+            .iter()
+            .filter(|(_, tenant)| tenant.providers.contains_key(&provider.name))
+            .map(|(key, tenant)| (key.clone(), tenant.name.clone()))
+            .collect()
+    }
+
+    fn house_name(&self) -> &str {
+        self.house_provider
+            .as_ref()
+            .map_or("default", |provider| provider.name.as_str())
+    }
+
+    /// The client for the request being handled, and what it is accounted to
+    async fn select(&self) -> ggen_ai::Result<(Arc<dyn LlmClient>, Account)> {
+        let now = Instant::now();
+        self.pool.evict_idle(now);
+        let house_model = &self.house.get_config().model;
+        let model = REQUEST_MODEL
+            .try_with(String::clone)
+            .unwrap_or_else(|_| house_model.clone());
+        let tenant = REQUEST_API_KEY
+            .try_with(|key| self.tenants.get(key).cloned())
+            .ok()
+            .flatten();
+        let credentials = match (&tenant, &self.house_provider) {
+            (Some(tenant), Some(provider)) => tenant.providers.get(&provider.name),
+            _ => None,
+        };
+        let account = Account {
+            tenant: tenant.as_ref().map(|tenant| tenant.name.clone()),
+            provider: self.house_name().to_string(),
+            model: model.clone(),
+            credentials: match credentials {
+                Some(_) => Credentials::Tenant,
+                None => Credentials::House,
+            },
+        };
+        if credentials.is_none() && model == *house_model {
+            return Ok((self.house.clone(), account));
+        }
+        let Some(provider) = &self.house_provider else {
+            return Err(GgenAiError::llm_provider(
+                self.house_name(),
+                format!("No provider is configured to answer with {}", model),
+            ));
+        };
+
+        let tenant = credentials
+            .and(tenant.as_ref())
+            .map(|tenant| tenant.name.clone());
+        let key = (tenant, provider.name.clone(), model);
+        if let Some(client) = self.pool.get(&key, now) {
+            return Ok((client, account));
+        }
+        let client = self
+            .build(provider, &key.2, credentials)
+            .await
+            .map_err(|err| GgenAiError::llm_provider(provider.name.as_str(), err.to_string()))?;
+        match &key.0 {
+            Some(tenant) => info!(
+                tenant = %tenant,
+                provider = %provider.name,
+                "Built a client with the tenant's credentials"
+            ),
+            None => info!(
+                provider = %provider.name,
+                model = %key.2,
+                "Built a client for a requested model"
+            ),
+        }
+        Ok((self.pool.insert(key, client, Instant::now()), account))
+    }
+
+    /// Client for `model` of the service's provider, with `credentials`
+    /// applied if a tenant brings them
+    async fn build(
+        &self, provider: &ProviderConfig, model: &str, credentials: Option<&CredentialOverride>,
+    ) -> anyhow::Result<Arc<dyn LlmClient>> {
+        let mut config = provider.clone();
+        config.model = model.to_string();
+        if let Some(credentials) = credentials {
+            // Never the service's key: it would go to wherever the tenant
+            // points
+            let Some(api_key) = &credentials.api_key else {
+                anyhow::bail!("The tenant's {} credentials have no api_key", provider.name);
+            };
+            config.api_key = Some(api_key.clone());
+            if let Some(base_url) = &credentials.base_url {
+                config.base_url = Some(base_url.clone());
+            }
+        }
+        // Resolved as rig-mcp clients resolve keys
+        let config = rig_mcp_integration::Config {
+            providers: vec![config],
+            ..rig_mcp_integration::Config::default()
+        }
+        .resolve_secrets()
+        .await?
+        .providers
+        .remove(0);
+        let organization = credentials.and_then(|credentials| credentials.organization.as_ref());
+        let transport = match organization {
+            Some(organization) => Arc::new(OrganizationHeader {
+                inner: self.transport.clone(),
+                organization: organization.clone(),
+            }) as Arc<dyn HttpTransport>,
+            None => self.transport.clone(),
+        };
+        let provider = HttpProvider::new(&config, transport)?;
+        let llm_config = LlmConfig {
+            model: model.to_string(),
+            ..self.house.get_config().clone()
+        };
+        Ok(Arc::new(ProviderClient::new(
+            Arc::new(provider),
+            llm_config,
+        )))
+    }
+}
+
+#[async_trait]
+impl LlmClient for TenantRouter {
+    async fn complete(&self, prompt: &str) -> ggen_ai::Result<LlmResponse> {
+        let (client, account) = self.select().await?;
+        let result = client.complete(prompt).await;
+        let usage = result
+            .as_ref()
+            .ok()
+            .and_then(|response| response.usage.as_ref());
+        self.usage.record(&account, usage);
+        result
+    }
+
+    /// Streams are counted on their usage chunk
+    async fn complete_stream(
+        &self, prompt: &str,
+    ) -> ggen_ai::Result<futures::stream::BoxStream<'static, LlmChunk>> {
+        let (client, account) = self.select().await?;
+        let stream = match client.complete_stream(prompt).await {
+            Ok(stream) => stream,
+            Err(err) => {
+                self.usage.record(&account, None);
+                return Err(err);
+            }
+        };
+        let usage = self.usage.clone();
+        Ok(stream
+            .inspect(move |chunk| {
+                if let Some(stats) = &chunk.usage {
+                    usage.record(&account, Some(stats));
+                }
+            })
+            .boxed())
+    }
+
+    fn get_config(&self) -> &LlmConfig {
+        self.house.get_config()
+    }
+
+    fn update_config(&mut self, config: LlmConfig) {
+        match Arc::get_mut(&mut self.house) {
+            Some(house) => house.update_config(config),
+            None => warn!("Not updating the config of a client in use"),
+        }
+    }
+}
+
+/// Transport sending a tenant's `OpenAI-Organization` header
+struct OrganizationHeader {
+    inner: Arc<dyn HttpTransport>,
+    organization: String,
+}
+
+#[async_trait]
+impl HttpTransport for OrganizationHeader {
+    async fn send(&self, request: HttpRequest) -> anyhow::Result<HttpResponse> {
+        let request = request.header("OpenAI-Organization", self.organization.as_str());
+        self.inner.send(request).await
+    }
+
+    async fn send_streaming(
+        &self, request: HttpRequest, on_chunk: &mut (dyn FnMut(&[u8]) + Send),
+    ) -> anyhow::Result<HttpResponse> {
+        let request = request.header("OpenAI-Organization", self.organization.as_str());
+        self.inner.send_streaming(request, on_chunk).await
+    }
+}
+
+/// [`LlmClient`] over a rig-mcp [`Provider`]
+struct ProviderClient {
+    provider: Arc<dyn Provider>,
+    config: LlmConfig,
+}
+
+impl std::fmt::Debug for ProviderClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ProviderClient")
+            .field("provider", &self.provider.name())
+            .field("model", &self.provider.model())
+            .finish()
+    }
+}
+
+impl ProviderClient {
+    fn new(provider: Arc<dyn Provider>, config: LlmConfig) -> Self {
+        Self { provider, config }
+    }
+
+    fn request(&self, prompt: &str) -> ChatRequest {
+        ChatRequest {
+            messages: vec![ChatMessage::user(prompt)],
+            max_tokens: self.config.max_tokens.map(|tokens| tokens as usize),
+            temperature: self.config.temperature,
+            ..ChatRequest::default()
+        }
+    }
+
+    fn error(&self, err: anyhow::Error) -> GgenAiError {
+        GgenAiError::llm_provider(self.provider.name(), err.to_string())
+    }
+}
+
+#[async_trait]
+impl LlmClient for ProviderClient {
+    async fn complete(&self, prompt: &str) -> ggen_ai::Result<LlmResponse> {
+        let response = self
+            .provider
+            .complete(self.request(prompt))
+            .await
+            .map_err(|err| self.error(err))?;
+        let finish_reason = serde_json::to_value(&response.finish_reason)
+            .ok()
+            .and_then(|reason| reason.as_str().map(str::to_string));
+        Ok(LlmResponse {
+            content: response.content,
+            usage: Some(usage_stats(&response.usage)),
+            model: self.provider.model().to_string(),
+            finish_reason,
+            extra: HashMap::new(),
+        })
+    }
+
+    async fn complete_stream(
+        &self, prompt: &str,
+    ) -> ggen_ai::Result<futures::stream::BoxStream<'static, LlmChunk>> {
+        let model = self.provider.model().to_string();
+        let chunks = self
+            .provider
+            .stream(self.request(prompt))
+            .await
+            .map_err(|err| self.error(err))?;
+        Ok(chunks
+            .filter_map(move |chunk| {
+                let chunk = match chunk {
+                    Ok(StreamChunk::Text(content)) => Some((content, None)),
+                    Ok(StreamChunk::Usage(usage)) => Some((String::new(), Some(usage))),
+                    Ok(StreamChunk::ToolCall(_)) => None,
+                    Err(err) => {
+                        warn!("Completion stream failed: {}", err);
+                        None
+                    }
+                };
+                let chunk = chunk.map(|(content, usage)| LlmChunk {
+                    content,
+                    model: model.clone(),
+                    finish_reason: usage.map(|_| "stop".to_string()),
+                    usage: usage.as_ref().map(usage_stats),
+                    extra: HashMap::new(),
+                });
+                futures::future::ready(chunk)
+            })
+            .boxed())
+    }
+
+    fn get_config(&self) -> &LlmConfig {
+        &self.config
+    }
+
+    fn update_config(&mut self, config: LlmConfig) {
+        self.config = config;
+    }
+}
+
+fn usage_stats(usage: &NormalizedUsage) -> UsageStats {
+    let tokens = |count: u64| u32::try_from(count).unwrap_or(u32::MAX);
+    UsageStats {
+        prompt_tokens: tokens(usage.prompt_tokens),
+        completion_tokens: tokens(usage.completion_tokens),
+        total_tokens: tokens(usage.total_tokens),
+    }
+}
+
+/// What the usage endpoint serves
+#[derive(Debug, Clone)]
+pub(crate) struct UsageApi {
+    pub(crate) ledger: UsageLedger,
+    /// Expected in the `x-admin-key` header
+    pub(crate) admin_key: String,
+}
+
+/// Usage and cost by tenant, provider, model and whose credentials paid
+pub(crate) async fn usage_report(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    let Some(api) = &state.usage else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    if !admin_key_matches(&headers, Some(&api.admin_key)) {
+        let error = json!({"kind": "unauthorized", "message": "Missing or wrong x-admin-key"});
+        return (StatusCode::UNAUTHORIZED, Json(json!({ "error": error }))).into_response();
+    }
+    Json(json!({ "accounts": api.ledger.report() })).into_response()
+}
+
+/// How long an unused tenant client is kept unless configured
+const DEFAULT_TENANT_CLIENT_IDLE: Duration = Duration::from_secs(10 * 60);
+
+/// Tenants in `API_KEY_TENANTS`, a JSON object of `{"name", "providers"}`
+/// by API key, where `providers` maps a provider name such as `openai` to
+/// the `api_key`, `base_url` and `organization` the tenant's requests use
+/// instead of the service's; `TENANT_CLIENT_IDLE_SECS`; `MODEL_PRICES`, a
+/// JSON object of `{"input", "output"}` USD per million tokens by
+/// `provider/model` or provider; and `USAGE_ADMIN_KEY`
+pub(crate) fn tenants_from_env() -> anyhow::Result<TenantConfig> {
+    let by_key: HashMap<String, Tenant> = match std::env::var("API_KEY_TENANTS") {
+        Ok(value) => serde_json::from_str(&value)
+            .map_err(|err| anyhow::anyhow!("API_KEY_TENANTS is invalid: {}", err))?,
+        Err(_) => HashMap::new(),
+    };
+    for tenant in by_key.values() {
+        tenant
+            .check()
+            .map_err(|err| anyhow::anyhow!("API_KEY_TENANTS: {}", err))?;
+    }
+    let prices = match std::env::var("MODEL_PRICES") {
+        Ok(value) => serde_json::from_str(&value)
+            .map_err(|err| anyhow::anyhow!("MODEL_PRICES is invalid: {}", err))?,
+        Err(_) => HashMap::new(),
+    };
+    Ok(TenantConfig {
+        by_key,
+        client_idle: env_secs("TENANT_CLIENT_IDLE_SECS")?.unwrap_or(DEFAULT_TENANT_CLIENT_IDLE),
+        prices,
+        admin_key: std::env::var("USAGE_ADMIN_KEY").ok(),
+    })
+}
+
+/// Aliases in `MODEL_ALIASES`, a JSON object laid out like `model_aliases` in
+/// the rig-mcp config
+pub(crate) fn model_aliases_from_env() -> anyhow::Result<HashMap<String, String>> {
+    let aliases: HashMap<String, String> = match std::env::var("MODEL_ALIASES") {
+        Ok(value) => serde_json::from_str(&value)
+            .map_err(|err| anyhow::anyhow!("MODEL_ALIASES is invalid: {}", err))?,
+        Err(_) => HashMap::new(),
+    };
+    for name in aliases.keys() {
+        alias::resolve(&aliases, name).map_err(|err| anyhow::anyhow!("MODEL_ALIASES: {}", err))?;
+    }
+    Ok(aliases)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app;
+    use crate::tests::{post, post_with_headers};
+    use ggen_ai::MockClient;
+    use ggen_config::{CacheSettings, ProviderSettings};
+    use serde_json::Value;
+    use tower::ServiceExt;
+
+    /// An OpenAI chat completion answering `content`
+    fn openai_reply(content: &str) -> String {
+        json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion",
+            "model": "gpt-4o-mini",
+            "choices": [{
+                "index": 0,
+                "message": {"role": "assistant", "content": content},
+                "finish_reason": "stop"
+            }],
+            "usage": {"prompt_tokens": 10, "completion_tokens": 5, "total_tokens": 15}
+        })
+        .to_string()
+    }
+
+    #[tokio::test]
+    async fn test_tenant_credentials_route_pool_and_account_requests() {
+        let mut house_server = mockito::Server::new_async().await;
+        let house_mock = house_server
+            .mock("POST", "/chat/completions")
+            .match_header("authorization", "Bearer house-key")
+            .with_header("content-type", "application/json")
+            .with_body(openai_reply("from the house"))
+            .expect(1)
+            .create_async()
+            .await;
+        let mut acme_server = mockito::Server::new_async().await;
+        let acme_mock = acme_server
+            .mock("POST", "/chat/completions")
+            .match_header("authorization", "Bearer acme-key")
+            .match_header("openai-organization", "org-acme")
+            .with_header("content-type", "application/json")
+            .with_body(openai_reply("from acme"))
+            .expect(3)
+            .create_async()
+            .await;
+
+        let house_provider = ProviderConfig::try_from(ProviderSettings {
+            api_key: Some("house-key".to_string()),
+            base_url: Some(house_server.url()),
+            ..ProviderSettings::new("openai", "gpt-4o-mini")
+        })
+        .unwrap();
+        let llm_config = LlmConfig {
+            model: "gpt-4o-mini".to_string(),
+            ..LlmConfig::default()
+        };
+        let house =
+            HttpProvider::new(&house_provider, Arc::new(ReqwestTransport::default())).unwrap();
+        let house = Arc::new(ProviderClient::new(Arc::new(house), llm_config));
+        let tenants: HashMap<String, Tenant> = serde_json::from_value(json!({
+            "sk-acme": {
+                "name": "acme",
+                "providers": {"openai": {
+                    "api_key": "acme-key",
+                    "base_url": acme_server.url(),
+                    "organization": "org-acme"
+                }}
+            },
+            "sk-globex": {"name": "globex"}
+        }))
+        .unwrap();
+        let config = TenantConfig {
+            by_key: tenants,
+            prices: HashMap::from([(
+                "openai/gpt-4o-mini".to_string(),
+                Price {
+                    input: 1.0,
+                    output: 2.0,
+                },
+            )]),
+            ..TenantConfig::default()
+        };
+        let router = Arc::new(TenantRouter::new(house, Some(house_provider), &config).unwrap());
+        let state = AppState::new(router.clone() as Arc<dyn LlmClient>)
+            .with_usage_api(router.usage(), "secret".to_string());
+        let complete = |api_key: &'static str, prompt: &'static str| {
+            let state = state.clone();
+            async move {
+                let (status, _, body) = post_with_headers(
+                    state,
+                    "/api/v1/complete",
+                    &[("x-api-key", api_key)],
+                    json!({ "prompt": prompt }),
+                )
+                .await;
+                assert_eq!(status, StatusCode::OK, "{}", body);
+                body["content"].as_str().unwrap().to_string()
+            }
+        };
+        let pooled = || {
+            let key = (
+                Some("acme".to_string()),
+                "openai".to_string(),
+                "gpt-4o-mini".to_string(),
+            );
+            router.pool.get(&key, Instant::now()).unwrap()
+        };
+
+        assert_eq!(complete("sk-acme", "first").await, "from acme");
+        let first = pooled();
+        assert_eq!(complete("sk-acme", "second").await, "from acme");
+        assert!(Arc::ptr_eq(&first, &pooled()));
+        // A tenant without overrides uses the service's credentials
+        assert_eq!(complete("sk-globex", "third").await, "from the house");
+        // Built again once evicted
+        let later = Instant::now() + config.client_idle + Duration::from_secs(1);
+        assert_eq!(router.pool.evict_idle(later), 1);
+        assert_eq!(complete("sk-acme", "fourth").await, "from acme");
+        assert!(!Arc::ptr_eq(&first, &pooled()));
+        house_mock.assert_async().await;
+        acme_mock.assert_async().await;
+
+        let request = Request::get("/api/v1/admin/usage")
+            .header("x-admin-key", "secret")
+            .body(Body::empty())
+            .unwrap();
+        let response = app(state.clone()).oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let report: Value = serde_json::from_slice(&body).unwrap();
+        let accounts = report["accounts"].as_array().unwrap();
+        assert_eq!(accounts.len(), 2);
+        let account = |tenant: &str| {
+            accounts
+                .iter()
+                .find(|account| account["tenant"] == tenant)
+                .unwrap()
+        };
+        let acme = account("acme");
+        assert_eq!(acme["credentials"], "tenant");
+        assert_eq!(acme["provider"], "openai");
+        assert_eq!(acme["requests"], 3);
+        assert_eq!(acme["prompt_tokens"], 30);
+        assert_eq!(acme["completion_tokens"], 15);
+        // 30 prompt tokens at $1 and 15 completion tokens at $2 per million
+        assert!((acme["cost_usd"].as_f64().unwrap() - 6e-5).abs() < 1e-12);
+        let globex = account("globex");
+        assert_eq!(globex["credentials"], "house");
+        assert_eq!(globex["requests"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_tenant_urls_never_get_the_house_key() {
+        let mut tenant_server = mockito::Server::new_async().await;
+        let tenant_mock = tenant_server
+            .mock("POST", "/chat/completions")
+            .with_header("content-type", "application/json")
+            .with_body(openai_reply("leaked"))
+            .expect(0)
+            .create_async()
+            .await;
+        let tenant: Tenant = serde_json::from_value(json!({
+            "name": "initech",
+            "providers": {"openai": {"base_url": tenant_server.url()}}
+        }))
+        .unwrap();
+        assert!(tenant.check().unwrap_err().contains("without an api_key"));
+        let empty: Tenant = serde_json::from_value(json!({
+            "name": "initech",
+            "providers": {"openai": {}}
+        }))
+        .unwrap();
+        assert!(empty.check().unwrap_err().contains("without an api_key"));
+
+        // Even when not checked, the router refuses rather than lend its key
+        let house_provider = ProviderConfig::try_from(ProviderSettings {
+            api_key: Some("house-key".to_string()),
+            ..ProviderSettings::new("openai", "gpt-4o-mini")
+        })
+        .unwrap();
+        let config = TenantConfig {
+            by_key: HashMap::from([("sk-initech".to_string(), tenant)]),
+            ..TenantConfig::default()
+        };
+        let house = Arc::new(MockClient::with_response("ok")) as Arc<dyn LlmClient>;
+        let router = TenantRouter::new(house, Some(house_provider), &config).unwrap();
+        let state = AppState::new(Arc::new(router));
+        let (status, _, _) = post_with_headers(
+            state,
+            "/api/v1/complete",
+            &[("x-api-key", "sk-initech")],
+            json!({ "prompt": "hello" }),
+        )
+        .await;
+        assert!(!status.is_success());
+        tenant_mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_requests_pick_models_by_alias() {
+        let mut server = mockito::Server::new_async().await;
+        let mut mocks = Vec::new();
+        for model in ["gpt-4o-mini", "gpt-4o"] {
+            let mock = server
+                .mock("POST", "/chat/completions")
+                .match_body(mockito::Matcher::PartialJson(json!({ "model": model })))
+                .with_header("content-type", "application/json")
+                .with_body(openai_reply(model))
+                .expect(1)
+                .create_async()
+                .await;
+            mocks.push(mock);
+        }
+        let house_provider = ProviderConfig::try_from(ProviderSettings {
+            api_key: Some("house-key".to_string()),
+            base_url: Some(server.url()),
+            ..ProviderSettings::new("openai", "gpt-4o-mini")
+        })
+        .unwrap();
+        let llm_config = LlmConfig {
+            model: "gpt-4o-mini".to_string(),
+            ..LlmConfig::default()
+        };
+        let house =
+            HttpProvider::new(&house_provider, Arc::new(ReqwestTransport::default())).unwrap();
+        let house = Arc::new(ProviderClient::new(Arc::new(house), llm_config));
+        let config = TenantConfig::default();
+        let router = Arc::new(TenantRouter::new(house, Some(house_provider), &config).unwrap());
+        let aliases = HashMap::from([
+            ("smart".to_string(), "best".to_string()),
+            ("best".to_string(), "openai/gpt-4o".to_string()),
+            (
+                "other".to_string(),
+                "anthropic/claude-3-5-haiku".to_string(),
+            ),
+        ]);
+        let state = AppState::new(router.clone() as Arc<dyn LlmClient>)
+            .with_cache(CacheSettings {
+                enabled: true,
+                ..CacheSettings::default()
+            })
+            .with_models(Some("openai".to_string()), aliases)
+            .with_usage_api(router.usage(), "secret".to_string());
+        let complete = |body: Value| {
+            let state = state.clone();
+            async move { post(state, "/api/v1/complete", body).await }
+        };
+
+        let (status, _, body) = complete(json!({"prompt": "hi", "model": "smart"})).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["content"], "gpt-4o");
+        // Cached apart from the service's model
+        let (_, _, body) = complete(json!({"prompt": "hi"})).await;
+        assert_eq!(
+            (&body["content"], &body["cached"]),
+            (&json!("gpt-4o-mini"), &json!(false))
+        );
+        let (_, _, body) = complete(json!({"prompt": "hi", "model": "openai/gpt-4o"})).await;
+        assert_eq!(
+            (&body["content"], &body["cached"]),
+            (&json!("gpt-4o"), &json!(true))
+        );
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+
+        for (model, message) in [
+            ("other", "isn't a model of the service's provider, openai"),
+            ("nonsense", "alias chain: nonsense"),
+        ] {
+            let (status, _, body) = complete(json!({"prompt": "hi", "model": model})).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+            let violation = &body["error"]["violations"][0];
+            assert_eq!(violation["path"], "/model");
+            assert!(
+                violation["message"].as_str().unwrap().contains(message),
+                "{}",
+                violation
+            );
+        }
+
+        // Usage is accounted to the concrete model, not the alias
+        let accounts = router.usage().report();
+        let models: Vec<_> = accounts
+            .iter()
+            .map(|report| report.account.model.as_str())
+            .collect();
+        assert_eq!(models, ["gpt-4o", "gpt-4o-mini"]);
+    }
+
+    #[tokio::test]
+    async fn test_cached_completions_stay_with_whoever_paid() {
+        let mut servers = Vec::new();
+        let mut mocks = Vec::new();
+        for key in ["house-key", "acme-key", "globex-key"] {
+            let mut server = mockito::Server::new_async().await;
+            let mock = server
+                .mock("POST", "/chat/completions")
+                .match_header("authorization", format!("Bearer {}", key).as_str())
+                .with_header("content-type", "application/json")
+                .with_body(openai_reply(key))
+                .expect(1)
+                .create_async()
+                .await;
+            servers.push(server);
+            mocks.push(mock);
+        }
+        let house_provider = ProviderConfig::try_from(ProviderSettings {
+            api_key: Some("house-key".to_string()),
+            base_url: Some(servers[0].url()),
+            ..ProviderSettings::new("openai", "gpt-4o-mini")
+        })
+        .unwrap();
+        let llm_config = LlmConfig {
+            model: "gpt-4o-mini".to_string(),
+            ..LlmConfig::default()
+        };
+        let house =
+            HttpProvider::new(&house_provider, Arc::new(ReqwestTransport::default())).unwrap();
+        let house = Arc::new(ProviderClient::new(Arc::new(house), llm_config));
+        let tenants: HashMap<String, Tenant> = serde_json::from_value(json!({
+            "sk-acme": {
+                "name": "acme",
+                "providers": {"openai": {"api_key": "acme-key", "base_url": servers[1].url()}}
+            },
+            "sk-globex": {
+                "name": "globex",
+                "providers": {"openai": {"api_key": "globex-key", "base_url": servers[2].url()}}
+            }
+        }))
+        .unwrap();
+        let config = TenantConfig {
+            by_key: tenants,
+            ..TenantConfig::default()
+        };
+        let router = TenantRouter::new(house, Some(house_provider), &config).unwrap();
+        let owners = router.credential_owners();
+        let state = AppState::new(Arc::new(router)).with_credential_owners(owners);
+        let complete = |api_key: &'static str| {
+            let state = state.clone();
+            async move {
+                let (status, _, body) = post_with_headers(
+                    state,
+                    "/api/v1/complete",
+                    &[("x-api-key", api_key)],
+                    json!({"prompt": "the same prompt"}),
+                )
+                .await;
+                assert_eq!(status, StatusCode::OK, "{}", body);
+                let content = body["content"].as_str().unwrap().to_string();
+                (content, body["cached"] == true)
+            }
+        };
+
+        let answer = |content: &str, cached: bool| (content.to_string(), cached);
+        assert_eq!(complete("sk-anonymous").await, answer("house-key", false));
+        assert_eq!(complete("sk-acme").await, answer("acme-key", false));
+        assert_eq!(complete("sk-globex").await, answer("globex-key", false));
+        // Each is served its own from then on
+        assert_eq!(complete("sk-acme").await, answer("acme-key", true));
+        assert_eq!(complete("sk-globex").await, answer("globex-key", true));
+        assert_eq!(complete("sk-other").await, answer("house-key", true));
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+    }
+}
@@ -0,0 +1,283 @@
+//! Listing and invoking MCP tools for operators
+
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use rig_mcp_integration::{RigMcpClient, ToolInvocationError, ToolOutput, Violation};
+use serde::Deserialize;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+use crate::{admin_key_matches, AppState};
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ToolsApiConfig {
+    /// Serve `/api/v1/tools`; off by default
+    pub(crate) enabled: bool,
+    /// Expected in the `x-admin-key` header of invocations
+    pub(crate) admin_key: Option<String>,
+}
+
+/// What the tools endpoints need
+#[derive(Clone)]
+pub(crate) struct ToolsApi {
+    pub(crate) mcp: Arc<RigMcpClient>,
+    pub(crate) admin_key: String,
+}
+
+/// Error of the tools endpoints, with a machine-readable `kind`
+#[derive(Debug)]
+pub(crate) struct ToolsApiError {
+    status: StatusCode,
+    kind: &'static str,
+    message: String,
+    violations: Vec<Violation>,
+}
+
+impl ToolsApiError {
+    fn new(status: StatusCode, kind: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            kind,
+            message: message.into(),
+            violations: Vec::new(),
+        }
+    }
+}
+
+impl From<anyhow::Error> for ToolsApiError {
+    fn from(err: anyhow::Error) -> Self {
+        let err = match err.downcast::<ToolInvocationError>() {
+            Ok(err) => err,
+            Err(err) => {
+                return Self::new(StatusCode::BAD_GATEWAY, "tool_failed", format!("{:#}", err))
+            }
+        };
+        let message = err.to_string();
+        match err {
+            ToolInvocationError::UnknownServer(_) | ToolInvocationError::UnknownTool { .. } => {
+                Self::new(StatusCode::NOT_FOUND, "unknown_tool", message)
+            }
+            ToolInvocationError::InvalidArguments(invalid) => Self {
+                violations: invalid.violations,
+                ..Self::new(StatusCode::BAD_REQUEST, "invalid_arguments", message)
+            },
+            ToolInvocationError::TimedOut { .. } => {
+                Self::new(StatusCode::GATEWAY_TIMEOUT, "timed_out", message)
+            }
+            ToolInvocationError::ConfirmationRequired { tool } => Self::new(
+                StatusCode::BAD_REQUEST,
+                "confirmation_required",
+                format!(
+                    "Tool '{}' is destructive; send \"confirm\": true to run it",
+                    tool
+                ),
+            ),
+        }
+    }
+}
+
+impl IntoResponse for ToolsApiError {
+    fn into_response(self) -> Response {
+        warn!("Tools API error: {}", self.message);
+        let mut error = serde_json::json!({
+            "kind": self.kind,
+            "message": self.message,
+        });
+        if !self.violations.is_empty() {
+            error["violations"] = serde_json::json!(self.violations);
+        }
+        (self.status, Json(serde_json::json!({ "error": error }))).into_response()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct InvokeToolRequest {
+    #[serde(default = "empty_arguments")]
+    arguments: serde_json::Value,
+    /// Must be `true` for tools annotated as destructive
+    #[serde(default)]
+    confirm: bool,
+}
+
+fn empty_arguments() -> serde_json::Value {
+    serde_json::json!({})
+}
+
+fn tools_api(state: &AppState) -> Result<&ToolsApi, ToolsApiError> {
+    state.tools.as_ref().ok_or_else(|| {
+        ToolsApiError::new(StatusCode::NOT_FOUND, "disabled", "Tools API is disabled")
+    })
+}
+
+pub(crate) async fn list_tools(
+    State(state): State<AppState>,
+) -> Result<Json<serde_json::Value>, ToolsApiError> {
+    let tools = tools_api(&state)?;
+    Ok(Json(tools.mcp.tools_manifest().await?))
+}
+
+pub(crate) async fn invoke_tool(
+    State(state): State<AppState>, Path((server, tool)): Path<(String, String)>,
+    headers: HeaderMap, Json(req): Json<InvokeToolRequest>,
+) -> Result<Json<ToolOutput>, ToolsApiError> {
+    let tools = tools_api(&state)?;
+    if !admin_key_matches(&headers, Some(&tools.admin_key)) {
+        return Err(ToolsApiError::new(
+            StatusCode::UNAUTHORIZED,
+            "unauthorized",
+            "Missing or wrong x-admin-key",
+        ));
+    }
+
+    info!(server = %server, tool = %tool, "Invoking MCP tool");
+    let output = tools
+        .mcp
+        .invoke_tool(&server, &tool, req.arguments, req.confirm)
+        .await?;
+    Ok(Json(output))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app;
+    use axum::body::Body;
+    use axum::http::{HeaderValue, Request};
+    use ggen_ai::{LlmClient, MockClient};
+    use rig_mcp_integration::testing::FakeMcpServer;
+    use tower::ServiceExt;
+
+    fn tools_state(enabled: bool) -> AppState {
+        let schema = serde_json::json!({
+            "type": "object",
+            "properties": {"path": {"type": "string"}},
+            "required": ["path"]
+        });
+        let server = FakeMcpServer::new("fs")
+            .with_tool("stat", "Describe a file", schema.clone(), |args| {
+                Ok(format!(
+                    "{} is a file",
+                    args["path"].as_str().unwrap_or_default()
+                ))
+            })
+            .with_tool("delete", "Delete a file", schema, |args| {
+                Ok(format!(
+                    "deleted {}",
+                    args["path"].as_str().unwrap_or_default()
+                ))
+            })
+            .destructive("delete");
+        let mcp = RigMcpClient::from_parts(
+            rig_mcp_integration::Config::default(),
+            vec![],
+            vec![Arc::new(server)],
+        );
+        let client = Arc::new(MockClient::with_response("ok")) as Arc<dyn LlmClient>;
+        let config = ToolsApiConfig {
+            enabled,
+            admin_key: Some("secret".to_string()),
+        };
+        AppState::new(client).with_tools_api(Arc::new(mcp), &config)
+    }
+
+    async fn invoke(
+        state: AppState, tool: &str, body: serde_json::Value,
+    ) -> (StatusCode, serde_json::Value) {
+        let request = Request::post(format!("/api/v1/tools/fs/{}/invoke", tool))
+            .header("content-type", "application/json")
+            .header("x-admin-key", "secret")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let response = app(state).oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_tools_api_invokes_a_tool() {
+        let state = tools_state(true);
+        let request = Request::get("/api/v1/tools").body(Body::empty()).unwrap();
+        let response = app(state.clone()).oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let manifest: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(manifest["tools"][1]["destructive"], true);
+
+        let (status, body) = invoke(
+            state,
+            "stat",
+            serde_json::json!({"arguments": {"path": "Cargo.toml"}}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["content"], "Cargo.toml is a file");
+        assert_eq!(body["is_error"], false);
+    }
+
+    #[tokio::test]
+    async fn test_tools_api_rejects_arguments_failing_the_schema() {
+        let (status, body) = invoke(
+            tools_state(true),
+            "stat",
+            serde_json::json!({"arguments": {"path": 42}}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"]["kind"], "invalid_arguments");
+        assert_eq!(body["error"]["violations"][0]["path"], "/path");
+    }
+
+    #[tokio::test]
+    async fn test_tools_api_requires_confirmation_for_destructive_tools() {
+        let state = tools_state(true);
+        let arguments = serde_json::json!({"path": "Cargo.toml"});
+        let (status, body) = invoke(
+            state.clone(),
+            "delete",
+            serde_json::json!({"arguments": arguments}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"]["kind"], "confirmation_required");
+
+        let (status, body) = invoke(
+            state,
+            "delete",
+            serde_json::json!({"arguments": arguments, "confirm": true}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["content"], "deleted Cargo.toml");
+    }
+
+    #[tokio::test]
+    async fn test_tools_api_needs_admin_key_and_enabling() {
+        let request = Request::post("/api/v1/tools/fs/stat/invoke")
+            .header("content-type", "application/json")
+            .body(Body::from(r#"{"arguments": {"path": "a"}}"#))
+            .unwrap();
+        let response = app(tools_state(true)).oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let request = Request::get("/api/v1/tools").body(Body::empty()).unwrap();
+        let response = app(tools_state(false)).oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-admin-key", HeaderValue::from_static(""));
+        assert!(!admin_key_matches(&headers, Some("")));
+        assert!(!admin_key_matches(&headers, None));
+        headers.insert("x-admin-key", HeaderValue::from_static("secret"));
+        assert!(admin_key_matches(&headers, Some("secret")));
+        assert!(!admin_key_matches(&headers, Some("secret2")));
+    }
+}
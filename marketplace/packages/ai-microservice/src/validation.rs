@@ -0,0 +1,388 @@
+//! Request bodies checked against the JSON Schemas of the OpenAPI document
+
+use axum::{
+    async_trait,
+    body::Body,
+    extract::FromRequest,
+    http::{HeaderValue, Request, StatusCode},
+    response::{IntoResponse, IntoResponseParts, Response, ResponseParts},
+    Json,
+};
+use rig_mcp_integration::Violation;
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use tracing::warn;
+
+use crate::{AppState, CompletionRequest, OntologyRequest, RefactorRequest, TemplateRequest};
+
+/// A request body with a JSON Schema, checked before it is deserialized
+///
+/// The schemas are the ones served in the OpenAPI document, so the two
+/// can't drift apart.
+pub(crate) trait RequestSchema: DeserializeOwned {
+    /// Name under `components.schemas`
+    const NAME: &'static str;
+
+    fn schema() -> Value;
+}
+
+impl RequestSchema for CompletionRequest {
+    const NAME: &'static str = "CompletionRequest";
+
+    fn schema() -> Value {
+        json!({
+            "type": "object",
+            "required": ["prompt"],
+            "properties": {
+                "prompt": {"type": "string"},
+                "stream": {"type": "boolean"},
+                "temperature": {"type": ["number", "null"]},
+                "model": {"type": ["string", "null"]},
+                "response_language": language_schema(),
+                "tone": tone_schema()
+            }
+        })
+    }
+}
+
+impl RequestSchema for TemplateRequest {
+    const NAME: &'static str = "TemplateRequest";
+
+    fn schema() -> Value {
+        json!({
+            "type": "object",
+            "required": ["description", "language"],
+            "properties": {
+                "description": {"type": "string"},
+                "language": {"type": "string"},
+                "variables": {"description": "Any JSON value"},
+                "context": {
+                    "type": "object",
+                    "minProperties": 1,
+                    "maxProperties": 1,
+                    "properties": {
+                        "snippets": {
+                            "type": "array",
+                            "items": {
+                                "type": "object",
+                                "required": ["path", "content"],
+                                "properties": {
+                                    "path": {"type": "string"},
+                                    "content": {"type": "string"}
+                                }
+                            }
+                        },
+                        "directory": {"type": "string"}
+                    }
+                },
+                "response_language": language_schema(),
+                "tone": tone_schema()
+            }
+        })
+    }
+}
+
+impl RequestSchema for RefactorRequest {
+    const NAME: &'static str = "RefactorRequest";
+
+    fn schema() -> Value {
+        json!({
+            "type": "object",
+            "required": ["code", "language"],
+            "properties": {
+                "code": {"type": "string"},
+                "language": {"type": "string"},
+                "focus": {"type": "array", "items": {"type": "string"}},
+                "mode": {"type": "string", "enum": ["refactor", "review"]}
+            }
+        })
+    }
+}
+
+impl RequestSchema for OntologyRequest {
+    const NAME: &'static str = "OntologyRequest";
+
+    fn schema() -> Value {
+        json!({
+            "type": "object",
+            "required": ["domain", "concepts"],
+            "properties": {
+                "domain": {"type": "string"},
+                "concepts": {"type": "array", "items": {"type": "string"}},
+                "merge_duplicates": {"type": "boolean"},
+                "response_language": language_schema(),
+                "tone": tone_schema()
+            }
+        })
+    }
+}
+
+/// A BCP 47 language tag such as `fr` or `de-CH`
+fn language_schema() -> Value {
+    json!({"type": "string", "pattern": "^[A-Za-z]{2,8}(-[A-Za-z0-9]{1,8})*$"})
+}
+
+fn tone_schema() -> Value {
+    json!({"type": "string", "enum": ["neutral", "formal", "friendly", "concise"]})
+}
+
+/// Every validated request body, by schema name
+fn request_schemas() -> Vec<(&'static str, Value)> {
+    vec![
+        (CompletionRequest::NAME, CompletionRequest::schema()),
+        (TemplateRequest::NAME, TemplateRequest::schema()),
+        (RefactorRequest::NAME, RefactorRequest::schema()),
+        (OntologyRequest::NAME, OntologyRequest::schema()),
+    ]
+}
+
+/// Compiled [`request_schemas`]
+pub(crate) struct RequestValidation {
+    strict: bool,
+    schemas: HashMap<&'static str, (Value, jsonschema::Validator)>,
+}
+
+impl RequestValidation {
+    pub(crate) fn new(strict: bool) -> Self {
+        let schemas = request_schemas()
+            .into_iter()
+            .map(|(name, schema)| {
+                let validator = jsonschema::validator_for(&schema)
+                    .unwrap_or_else(|err| panic!("Schema of {} is invalid: {}", name, err));
+                (name, (schema, validator))
+            })
+            .collect();
+        Self { strict, schemas }
+    }
+
+    fn check<T: RequestSchema>(&self, body: Value) -> Result<Validated<T>, InvalidRequest> {
+        let (schema, validator) = &self.schemas[T::NAME];
+        let mut violations: Vec<Violation> = validator
+            .iter_errors(&body)
+            .map(|err| Violation {
+                path: err.instance_path.to_string(),
+                message: err.to_string(),
+            })
+            .collect();
+        let mut unknown = Vec::new();
+        unknown_fields(schema, &body, "", &mut unknown);
+        if self.strict {
+            violations.extend(unknown.drain(..).map(|path| Violation {
+                message: format!("{} is not a known field", path),
+                path,
+            }));
+        }
+        if !violations.is_empty() {
+            return Err(InvalidRequest {
+                schema: T::NAME,
+                violations,
+            });
+        }
+        let body = serde_json::from_value(body).map_err(|err| InvalidRequest {
+            schema: T::NAME,
+            violations: vec![Violation {
+                path: String::new(),
+                message: err.to_string(),
+            }],
+        })?;
+        Ok(Validated {
+            body,
+            unknown_fields: UnknownFields(unknown),
+        })
+    }
+}
+
+/// JSON Pointers of the fields of `value` that `schema` lists no property
+/// for, in objects whose properties it lists
+fn unknown_fields(schema: &Value, value: &Value, path: &str, out: &mut Vec<String>) {
+    let (Some(properties), Some(fields)) = (schema["properties"].as_object(), value.as_object())
+    else {
+        return;
+    };
+    for (field, value) in fields {
+        // JSON Pointer escaping
+        let path = format!("{}/{}", path, field.replace('~', "~0").replace('/', "~1"));
+        match properties.get(field) {
+            Some(schema) => unknown_fields(schema, value, &path, out),
+            None => out.push(path),
+        }
+    }
+}
+
+/// Body extractor validating against `T`'s [`RequestSchema`]
+pub(crate) struct Validated<T> {
+    pub(crate) body: T,
+    /// Empty in strict mode, where unknown fields are violations
+    pub(crate) unknown_fields: UnknownFields,
+}
+
+#[async_trait]
+impl<T: RequestSchema> FromRequest<AppState> for Validated<T> {
+    type Rejection = Response;
+
+    async fn from_request(req: Request<Body>, state: &AppState) -> Result<Self, Response> {
+        let Json(body) = Json::<Value>::from_request(req, state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+        state
+            .validation
+            .check(body)
+            .map_err(IntoResponse::into_response)
+    }
+}
+
+/// Fields a request had that were ignored, reported in `x-unknown-fields`
+pub(crate) struct UnknownFields(Vec<String>);
+
+impl IntoResponseParts for UnknownFields {
+    type Error = Infallible;
+
+    fn into_response_parts(self, mut res: ResponseParts) -> Result<ResponseParts, Infallible> {
+        // Field names that aren't valid header values are left out
+        if let Ok(value) = HeaderValue::from_str(&self.0.join(", ")) {
+            if !self.0.is_empty() {
+                res.headers_mut().insert("x-unknown-fields", value);
+            }
+        }
+        Ok(res)
+    }
+}
+
+/// A request body that doesn't match its schema
+#[derive(Debug)]
+pub(crate) struct InvalidRequest {
+    pub(crate) schema: &'static str,
+    pub(crate) violations: Vec<Violation>,
+}
+
+impl IntoResponse for InvalidRequest {
+    fn into_response(self) -> Response {
+        let message = format!("Request body doesn't match the {} schema", self.schema);
+        warn!("{}", message);
+        let error = json!({
+            "kind": "invalid_request",
+            "message": message,
+            "violations": self.violations,
+        });
+        (StatusCode::BAD_REQUEST, Json(json!({ "error": error }))).into_response()
+    }
+}
+
+/// OpenAPI document of the validated endpoints
+pub(crate) async fn openapi() -> Json<Value> {
+    let operation = |schema: &str, summary: &str| {
+        json!({
+            "post": {
+                "summary": summary,
+                "requestBody": {
+                    "required": true,
+                    "content": {"application/json": {
+                        "schema": {"$ref": format!("#/components/schemas/{}", schema)}
+                    }}
+                },
+                "responses": {
+                    "200": {"description": "Success"},
+                    "400": {"description": "The body doesn't match the schema; `error.violations` lists why"}
+                }
+            }
+        })
+    };
+    let schemas: serde_json::Map<String, Value> = request_schemas()
+        .into_iter()
+        .map(|(name, schema)| (name.to_string(), schema))
+        .collect();
+    Json(json!({
+        "openapi": "3.1.0",
+        "info": {"title": "ai-microservice", "version": env!("CARGO_PKG_VERSION")},
+        "paths": {
+            "/api/v1/complete": operation(CompletionRequest::NAME, "Complete a prompt"),
+            "/api/v1/template/generate": operation(TemplateRequest::NAME, "Generate a template"),
+            "/api/v1/refactor": operation(RefactorRequest::NAME, "Refactor code"),
+            "/api/v1/ontology/generate": operation(OntologyRequest::NAME, "Generate an ontology")
+        },
+        "components": {"schemas": schemas}
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app;
+    use crate::tests::{mock_state, post};
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_invalid_bodies_list_their_violations() {
+        let (status, _, body) = post(
+            mock_state(),
+            "/api/v1/ontology/generate",
+            json!({"domain": "retail", "concepts": "Product"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"]["kind"], "invalid_request");
+        let violations = body["error"]["violations"].as_array().unwrap();
+        assert_eq!(violations.len(), 1, "{:?}", violations);
+        assert_eq!(violations[0]["path"], "/concepts");
+
+        let (status, _, body) = post(
+            mock_state(),
+            "/api/v1/template/generate",
+            json!({"description": "A REST handler"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let violations = body["error"]["violations"].as_array().unwrap();
+        assert_eq!(violations.len(), 1, "{:?}", violations);
+        assert_eq!(violations[0]["path"], "");
+        assert!(
+            violations[0]["message"]
+                .as_str()
+                .unwrap()
+                .contains("\"language\" is a required property"),
+            "{:?}",
+            violations
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unknown_fields_are_reported_or_rejected() {
+        let body = json!({"prompt": "hi", "temprature": 0.2});
+        let (status, headers, _) = post(mock_state(), "/api/v1/complete", body.clone()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(headers["x-unknown-fields"], "/temprature");
+
+        let strict = mock_state().with_strict_validation(true);
+        let (status, _, body) = post(strict, "/api/v1/complete", body).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"]["violations"][0]["path"], "/temprature");
+
+        let (status, headers, _) =
+            post(mock_state(), "/api/v1/complete", json!({"prompt": "hi"})).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(!headers.contains_key("x-unknown-fields"));
+    }
+
+    #[tokio::test]
+    async fn test_openapi_document_has_the_validated_schemas() {
+        let request = Request::get("/api/v1/openapi.json")
+            .body(Body::empty())
+            .unwrap();
+        let response = app(mock_state()).oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let document: Value = serde_json::from_slice(&body).unwrap();
+        for (name, schema) in request_schemas() {
+            assert_eq!(document["components"]["schemas"][name], schema);
+        }
+        let complete = &document["paths"]["/api/v1/complete"]["post"];
+        assert_eq!(
+            complete["requestBody"]["content"]["application/json"]["schema"]["$ref"],
+            "#/components/schemas/CompletionRequest"
+        );
+    }
+}
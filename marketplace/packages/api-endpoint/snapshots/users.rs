@@ -0,0 +1,588 @@
+//! REST API endpoints
+//!
+//! REST API endpoints for users with comprehensive features:
+//! - Request/response validation
+//! - Error handling and status codes
+//! - OpenAPI documentation generation
+//! - Rate limiting and security
+//! - Performance monitoring
+//!
+//! Generated by ggen marketplace package: api-endpoint-templates
+
+use anyhow::{Context, Result};
+use axum::{
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Json, Response},
+    routing::{get, post, put, delete},
+    Router,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tokio::time::{timeout, Duration};
+use tracing::{info, warn, error, instrument};
+use uuid::Uuid;
+
+/// API configuration
+#[derive(Debug, Clone)]
+pub struct UsersApiConfig {
+    pub timeout_seconds: u64,
+    pub rate_limit_requests: u64,
+    pub rate_limit_window_seconds: u64,
+    pub enable_cors: bool,
+    pub enable_compression: bool,
+}
+
+impl Default for UsersApiConfig {
+    fn default() -> Self {
+        Self {
+            timeout_seconds: 30,
+            rate_limit_requests: 100,
+            rate_limit_window_seconds: 60,
+            enable_cors: true,
+            enable_compression: true,
+        }
+    }
+}
+
+/// API state
+#[derive(Debug, Clone)]
+pub struct UsersApiState {
+    pub config: UsersApiConfig,
+    /// In-memory store, keyed and listed by id
+    pub store: Arc<RwLock<BTreeMap<Uuid, Users>>>,
+}
+
+impl UsersApiState {
+    /// Add seed data, replacing entries with the same id
+    pub async fn seed(&self, items: impl IntoIterator<Item = Users>) {
+        let mut store = self.store.write().await;
+        for item in items {
+            store.insert(item.id, item);
+        }
+    }
+}
+
+/// Request models
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CreateUsersRequest {
+    pub name: String,
+    pub email: String,
+    pub metadata: Option<HashMap<String, serde_json::Value>>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct UpdateUsersRequest {
+    pub name: Option<String>,
+    pub email: Option<String>,
+    pub metadata: Option<HashMap<String, serde_json::Value>>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct UsersQuery {
+    pub page: Option<u32>,
+    pub limit: Option<u32>,
+    pub sort: Option<String>,
+    pub search: Option<String>,
+}
+
+/// Response models
+#[derive(Debug, Serialize)]
+pub struct UsersResponse<T> {
+    pub success: bool,
+    pub data: Option<T>,
+    pub message: String,
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+    pub request_id: Uuid,
+}
+
+/// A request field that failed validation
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+/// Body of a 422 or 409 response
+#[derive(Debug, Serialize)]
+pub struct ValidationErrorResponse {
+    pub success: bool,
+    pub error: String,
+    pub errors: Vec<FieldError>,
+    pub request_id: Uuid,
+}
+
+fn validation_error(request_id: Uuid, errors: Vec<FieldError>) -> Response {
+    let body = ValidationErrorResponse {
+        success: false,
+        error: "ValidationError".to_string(),
+        errors,
+        request_id,
+    };
+    (StatusCode::UNPROCESSABLE_ENTITY, Json(body)).into_response()
+}
+
+fn conflict_error(request_id: Uuid, field: &str) -> Response {
+    let body = ValidationErrorResponse {
+        success: false,
+        error: "Conflict".to_string(),
+        errors: vec![field_error(field, "Already in use")],
+        request_id,
+    };
+    (StatusCode::CONFLICT, Json(body)).into_response()
+}
+
+#[derive(Debug, Serialize)]
+pub struct PaginatedUsersResponse {
+    pub items: Vec<Users>,
+    pub pagination: PaginationInfo,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PaginationInfo {
+    pub total: u64,
+    pub page: u32,
+    pub limit: u32,
+    pub pages: u32,
+    pub has_next: bool,
+    pub has_prev: bool,
+}
+
+/// Users entity (simplified for demo)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Users {
+    pub id: Uuid,
+    pub name: String,
+    pub email: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Fields declared `ex:unique` in the graph; create and update answer 409
+/// when another entry already has the same value
+pub const UNIQUE_FIELDS: &[&str] = &[];
+
+/// The unique field of `candidate` that another entry already uses
+fn unique_conflict(store: &BTreeMap<Uuid, Users>, candidate: &Users) -> Option<&'static str> {
+    store
+        .values()
+        .filter(|other| other.id != candidate.id)
+        .find_map(|other| {
+            UNIQUE_FIELDS.iter().copied().find(|field| match *field {
+                "name" => other.name == candidate.name,
+                "email" => other.email == candidate.email,
+                _ => false,
+            })
+        })
+}
+
+/// API handlers
+#[instrument(skip(state))]
+pub async fn create_users_handler(
+    State(state): State<UsersApiState>,
+    headers: HeaderMap,
+    Json(request): Json<CreateUsersRequest>,
+) -> Result<(StatusCode, Json<UsersResponse<Users>>), Response> {
+    let request_id = Uuid::new_v4();
+    info!("Creating users with request ID: {}", request_id);
+
+    // Validate request
+    let errors = validate_create_request(&request);
+    if !errors.is_empty() {
+        error!("Validation failed for request {}: {:?}", request_id, errors);
+        return Err(validation_error(request_id, errors));
+    }
+
+    // Execute with timeout
+    match timeout(
+        Duration::from_secs(state.config.timeout_seconds),
+        async {
+            let now = chrono::Utc::now();
+            let users = Users {
+                id: Uuid::new_v4(),
+                name: request.name,
+                email: request.email,
+                created_at: now,
+                updated_at: now,
+            };
+            let mut store = state.store.write().await;
+            if let Some(field) = unique_conflict(&store, &users) {
+                return Ok(Err(field));
+            }
+            store.insert(users.id, users.clone());
+            Ok::<_, anyhow::Error>(Ok(users))
+        }
+    )
+    .await
+    {
+        Ok(Ok(Ok(users))) => {
+            info!("Successfully created users with ID: {}", users.id);
+            Ok((StatusCode::CREATED, Json(UsersResponse {
+                success: true,
+                data: Some(users),
+                message: "Users created successfully".to_string(),
+                timestamp: chrono::Utc::now(),
+                request_id,
+            })))
+        }
+        Ok(Ok(Err(field))) => {
+            warn!("Conflict creating users for request {}: {} already in use", request_id, field);
+            Err(conflict_error(request_id, field))
+        }
+        Ok(Err(e)) => {
+            error!("Failed to create users for request {}: {}", request_id, e);
+            Err(StatusCode::BAD_REQUEST.into_response())
+        }
+        Err(_) => {
+            error!("Timeout creating users for request {}", request_id);
+            Err(StatusCode::REQUEST_TIMEOUT.into_response())
+        }
+    }
+}
+
+#[instrument(skip(state))]
+pub async fn get_users_handler(
+    State(state): State<UsersApiState>,
+    Path(id): Path<Uuid>,
+    headers: HeaderMap,
+) -> Result<Json<UsersResponse<Users>>, StatusCode> {
+    let request_id = Uuid::new_v4();
+    info!("Getting users with ID: {} and request ID: {}", id, request_id);
+
+    // Execute with timeout
+    match timeout(
+        Duration::from_secs(state.config.timeout_seconds),
+        async { Ok::<_, anyhow::Error>(state.store.read().await.get(&id).cloned()) }
+    )
+    .await
+    {
+        Ok(Ok(Some(users))) => {
+            info!("Successfully retrieved users with ID: {}", users.id);
+            Ok(Json(UsersResponse {
+                success: true,
+                data: Some(users),
+                message: "Users retrieved successfully".to_string(),
+                timestamp: chrono::Utc::now(),
+                request_id,
+            }))
+        }
+        Ok(Ok(None)) => {
+            warn!("Users not found with ID: {}", id);
+            Err(StatusCode::NOT_FOUND)
+        }
+        Ok(Err(e)) => {
+            error!("Failed to get users for request {}: {}", request_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+        Err(_) => {
+            error!("Timeout getting users for request {}", request_id);
+            Err(StatusCode::REQUEST_TIMEOUT)
+        }
+    }
+}
+
+#[instrument(skip(state))]
+pub async fn get_userss_handler(
+    State(state): State<UsersApiState>,
+    Query(query): Query<UsersQuery>,
+    headers: HeaderMap,
+) -> Result<Json<UsersResponse<PaginatedUsersResponse>>, StatusCode> {
+    let request_id = Uuid::new_v4();
+    info!("Getting userss with query: {:?} and request ID: {}", query, request_id);
+
+    // Execute with timeout
+    match timeout(
+        Duration::from_secs(state.config.timeout_seconds),
+        async {
+            let page = query.page.unwrap_or(1).max(1);
+            let limit = query.limit.unwrap_or(20).clamp(1, 100);
+            let offset = ((page - 1) * limit) as usize;
+
+            let store = state.store.read().await;
+            let items = store
+                .values()
+                .skip(offset)
+                .take(limit as usize)
+                .cloned()
+                .collect::<Vec<_>>();
+
+            let total = store.len() as u64;
+            let pages = total.div_ceil(limit as u64) as u32;
+
+            Ok::<_, anyhow::Error>(PaginatedUsersResponse {
+                items,
+                pagination: PaginationInfo {
+                    total,
+                    page,
+                    limit,
+                    pages,
+                    has_next: page < pages,
+                    has_prev: page > 1,
+                },
+            })
+        }
+    )
+    .await
+    {
+        Ok(Ok(response)) => {
+            info!("Successfully retrieved {} userss", response.items.len());
+            Ok(Json(UsersResponse {
+                success: true,
+                data: Some(response),
+                message: "Userss retrieved successfully".to_string(),
+                timestamp: chrono::Utc::now(),
+                request_id,
+            }))
+        }
+        Ok(Err(e)) => {
+            error!("Failed to get userss for request {}: {}", request_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+        Err(_) => {
+            error!("Timeout getting userss for request {}", request_id);
+            Err(StatusCode::REQUEST_TIMEOUT)
+        }
+    }
+}
+
+#[instrument(skip(state))]
+pub async fn update_users_handler(
+    State(state): State<UsersApiState>,
+    Path(id): Path<Uuid>,
+    headers: HeaderMap,
+    Json(request): Json<UpdateUsersRequest>,
+) -> Result<Json<UsersResponse<Users>>, Response> {
+    let request_id = Uuid::new_v4();
+    info!("Updating users with ID: {} and request ID: {}", id, request_id);
+
+    // Validate request
+    let errors = validate_update_request(&request);
+    if !errors.is_empty() {
+        error!("Validation failed for request {}: {:?}", request_id, errors);
+        return Err(validation_error(request_id, errors));
+    }
+
+    // Execute with timeout
+    match timeout(
+        Duration::from_secs(state.config.timeout_seconds),
+        async {
+            let mut store = state.store.write().await;
+            let Some(mut users) = store.get(&id).cloned() else {
+                return Ok(None);
+            };
+            if let Some(name) = request.name {
+                users.name = name;
+            }
+            if let Some(email) = request.email {
+                users.email = email;
+            }
+            users.updated_at = chrono::Utc::now();
+            if let Some(field) = unique_conflict(&store, &users) {
+                return Ok(Some(Err(field)));
+            }
+            store.insert(id, users.clone());
+            Ok::<_, anyhow::Error>(Some(Ok(users)))
+        }
+    )
+    .await
+    {
+        Ok(Ok(Some(Ok(users)))) => {
+            info!("Successfully updated users with ID: {}", users.id);
+            Ok(Json(UsersResponse {
+                success: true,
+                data: Some(users),
+                message: "Users updated successfully".to_string(),
+                timestamp: chrono::Utc::now(),
+                request_id,
+            }))
+        }
+        Ok(Ok(Some(Err(field)))) => {
+            warn!("Conflict updating users for request {}: {} already in use", request_id, field);
+            Err(conflict_error(request_id, field))
+        }
+        Ok(Ok(None)) => {
+            warn!("Users not found with ID: {}", id);
+            Err(StatusCode::NOT_FOUND.into_response())
+        }
+        Ok(Err(e)) => {
+            error!("Failed to update users for request {}: {}", request_id, e);
+            Err(StatusCode::BAD_REQUEST.into_response())
+        }
+        Err(_) => {
+            error!("Timeout updating users for request {}", request_id);
+            Err(StatusCode::REQUEST_TIMEOUT.into_response())
+        }
+    }
+}
+
+#[instrument(skip(state))]
+pub async fn delete_users_handler(
+    State(state): State<UsersApiState>,
+    Path(id): Path<Uuid>,
+    headers: HeaderMap,
+) -> Result<Json<UsersResponse<()>>, StatusCode> {
+    let request_id = Uuid::new_v4();
+    info!("Deleting users with ID: {} and request ID: {}", id, request_id);
+
+    // Execute with timeout
+    match timeout(
+        Duration::from_secs(state.config.timeout_seconds),
+        async {
+            match state.store.write().await.remove(&id) {
+                Some(_) => Ok(()),
+                None => Err(anyhow::anyhow!("Users not found")),
+            }
+        }
+    )
+    .await
+    {
+        Ok(Ok(())) => {
+            info!("Successfully deleted users with ID: {}", id);
+            Ok(Json(UsersResponse {
+                success: true,
+                data: Some(()),
+                message: "Users deleted successfully".to_string(),
+                timestamp: chrono::Utc::now(),
+                request_id,
+            }))
+        }
+        Ok(Err(e)) => {
+            error!("Failed to delete users for request {}: {}", request_id, e);
+            Err(StatusCode::NOT_FOUND)
+        }
+        Err(_) => {
+            error!("Timeout deleting users for request {}", request_id);
+            Err(StatusCode::REQUEST_TIMEOUT)
+        }
+    }
+}
+
+/// Validation functions; each failing field is reported once
+fn validate_create_request(request: &CreateUsersRequest) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+    if let Some(message) = validate_name(&request.name) {
+        errors.push(field_error("name", message));
+    }
+    if !is_valid_email(&request.email) {
+        errors.push(field_error("email", "Invalid email format"));
+    }
+    errors
+}
+
+fn validate_update_request(request: &UpdateUsersRequest) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+    if let Some(message) = request.name.as_deref().and_then(validate_name) {
+        errors.push(field_error("name", message));
+    }
+    if let Some(ref email) = request.email {
+        if !is_valid_email(email) {
+            errors.push(field_error("email", "Invalid email format"));
+        }
+    }
+    errors
+}
+
+fn validate_name(name: &str) -> Option<&'static str> {
+    if name.trim().is_empty() {
+        Some("Name cannot be empty")
+    } else if name.len() > 100 {
+        Some("Name too long")
+    } else {
+        None
+    }
+}
+
+fn field_error(field: &str, message: &str) -> FieldError {
+    FieldError {
+        field: field.to_string(),
+        message: message.to_string(),
+    }
+}
+
+fn is_valid_email(email: &str) -> bool {
+    email.contains('@') && email.len() > 5 && email.len() < 255
+}
+
+/// Create the router
+pub fn create_router() -> Router<UsersApiState> {
+    Router::new()
+        .route("/users", post(create_users_handler))
+        .route("/users", get(get_userss_handler))
+        .route("/users/:id", get(get_users_handler))
+        .route("/users/:id", put(update_users_handler))
+        .route("/users/:id", delete(delete_users_handler))
+}
+
+/// Endpoints declared in the API graph as `(api, [(method, path)])`, grouped
+/// by API and sorted by path; endpoints without an API are listed under `""`
+pub const ENDPOINTS: &[(&str, &[(&str, &str)])] = &[
+    ("", &[
+        ("GET", "/health"),
+    ]),
+    ("orders", &[
+        ("GET", "/orders"),
+    ]),
+    ("users", &[
+        ("GET", "/users"),
+        ("POST", "/users"),
+        ("DELETE", "/users/:id"),
+        ("GET", "/users/:id"),
+        ("PUT", "/users/:id"),
+    ]),
+];
+
+/// Create API state with an empty store
+pub fn create_api_state() -> UsersApiState {
+    UsersApiState {
+        config: UsersApiConfig::default(),
+        store: Arc::default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+    };
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_create_users_handler() {
+        let state = create_api_state();
+        let app = create_router().with_state(state);
+
+        let request_body = serde_json::json!({
+            "name": "Test User",
+            "email": "test@example.com"
+        });
+
+        let request = Request::builder()
+            .method("POST")
+            .uri("/users")
+            .header("content-type", "application/json")
+            .body(Body::from(request_body.to_string()))
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+    }
+
+    #[tokio::test]
+    async fn test_get_userss_handler() {
+        let state = create_api_state();
+        let app = create_router().with_state(state);
+
+        let request = Request::builder()
+            .method("GET")
+            .uri("/users")
+            .body(Body::empty())
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,133 @@
+//! MCP tools for the users API
+//!
+//! Offers the `users` endpoints of `mcp_tools.json` as MCP tools. Every
+//! call goes through the router from `api-endpoint.tmpl`, so tools get the
+//! same validation and status codes as HTTP clients; a response other than
+//! 2xx is a tool error carrying the response body.
+//!
+//! Generated by ggen marketplace package: api-endpoint-templates
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use axum::{body::Body, http::Request, Router};
+use http_body_util::BodyExt;
+use rig_mcp_integration::mcp::{ToolInfo, ToolOutput, ToolServer};
+use serde::Deserialize;
+use serde_json::Value;
+use std::sync::Arc;
+use tower::ServiceExt;
+
+use super::users::{create_router, UsersApiState};
+
+/// The tool manifest written by `mcp-tools.tmpl`
+pub const MANIFEST: &str = include_str!("mcp_tools.json");
+
+#[derive(Debug, Deserialize)]
+struct Manifest {
+    tools: Vec<ManifestTool>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ManifestTool {
+    name: String,
+    description: String,
+    #[serde(default)]
+    api: String,
+    method: String,
+    path: String,
+    input_schema: Value,
+    annotations: Annotations,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Annotations {
+    read_only_hint: bool,
+    destructive_hint: bool,
+}
+
+/// The `users` endpoints as a [`ToolServer`]
+pub struct UsersTools {
+    router: Router,
+    tools: Vec<ManifestTool>,
+}
+
+impl UsersTools {
+    pub fn new(state: UsersApiState) -> Result<Self> {
+        let manifest: Manifest =
+            serde_json::from_str(MANIFEST).context("Invalid tool manifest mcp_tools.json")?;
+        Ok(Self {
+            router: create_router().with_state(state),
+            tools: manifest
+                .tools
+                .into_iter()
+                .filter(|tool| tool.api == "users")
+                .collect(),
+        })
+    }
+}
+
+#[async_trait]
+impl ToolServer for UsersTools {
+    fn name(&self) -> &str {
+        "users"
+    }
+
+    async fn list_tools(&self) -> Result<Vec<ToolInfo>> {
+        Ok(self
+            .tools
+            .iter()
+            .map(|tool| ToolInfo {
+                server: "users".to_string(),
+                name: tool.name.clone(),
+                description: tool.description.clone(),
+                input_schema: tool.input_schema.clone(),
+                destructive: tool.annotations.destructive_hint,
+                read_only: tool.annotations.read_only_hint,
+            })
+            .collect())
+    }
+
+    async fn call_tool(&self, name: &str, arguments: Value) -> Result<ToolOutput> {
+        let tool = self
+            .tools
+            .iter()
+            .find(|tool| tool.name == name)
+            .with_context(|| format!("The users API has no tool '{}'", name))?;
+        // The id goes into the path, everything else into the body
+        let mut body = arguments.as_object().cloned().unwrap_or_default();
+        let uri = match body.remove("id") {
+            Some(Value::String(id)) => tool.path.replace(":id", &id),
+            _ => tool.path.clone(),
+        };
+        let builder = Request::builder().method(tool.method.as_str()).uri(uri);
+        let request = if matches!(tool.method.as_str(), "POST" | "PUT" | "PATCH") {
+            builder
+                .header("content-type", "application/json")
+                .body(Body::from(Value::Object(body).to_string()))?
+        } else {
+            builder.body(Body::empty())?
+        };
+
+        let response = self.router.clone().oneshot(request).await?;
+        let status = response.status();
+        let bytes = response.into_body().collect().await?.to_bytes();
+        let content = if bytes.is_empty() {
+            status.to_string()
+        } else {
+            String::from_utf8_lossy(&bytes).into_owned()
+        };
+        Ok(if status.is_success() {
+            ToolOutput::text(content)
+        } else {
+            ToolOutput::error(content)
+        })
+    }
+}
+
+/// Serve the `users` tools to an MCP client over stdin and stdout
+pub async fn serve_stdio(state: UsersApiState) -> Result<()> {
+    let tools = Arc::new(UsersTools::new(state)?);
+    rig_mcp_integration::serve::serve_stdio(tools).await
+}
@@ -0,0 +1,171 @@
+//! Integration tests for the users API
+//!
+//! One test per endpoint of the `users` API in the graph, run against the
+//! router from `api-endpoint.tmpl` with seeded in-memory state.
+//!
+//! Generated by ggen marketplace package: api-endpoint-templates
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Router,
+};
+use http_body_util::BodyExt;
+use serde_json::{json, Value};
+use tower::ServiceExt;
+use uuid::Uuid;
+use app::api::users::{create_api_state, create_router, Users};
+
+/// Seed data: `count` users with the ids 1..=count
+fn fixtures(count: u128) -> Vec<Users> {
+    (1..=count)
+        .map(|n| Users {
+            id: Uuid::from_u128(n),
+            name: format!("Fixture {}", n),
+            email: format!("fixture{}@example.com", n),
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+        })
+        .collect()
+}
+
+async fn app(seed: Vec<Users>) -> Router {
+    let state = create_api_state();
+    state.seed(seed).await;
+    create_router().with_state(state)
+}
+
+/// Send a request and return the status with the JSON body, or `Null`
+async fn send(app: &Router, method: &str, uri: &str, body: Option<Value>) -> (StatusCode, Value) {
+    let builder = Request::builder().method(method).uri(uri);
+    let request = match body {
+        Some(body) => builder
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string())),
+        None => builder.body(Body::empty()),
+    }
+    .unwrap();
+
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    let body = if bytes.is_empty() {
+        Value::Null
+    } else {
+        serde_json::from_slice(&bytes).unwrap()
+    };
+    (status, body)
+}
+
+/// Fields of a 422 response, in the order they were reported
+fn error_fields(body: &Value) -> Vec<&str> {
+    body["errors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|error| error["field"].as_str().unwrap())
+        .collect()
+}
+
+/// A payload that passes the generated validation rules
+fn valid_payload() -> Value {
+    json!({ "name": "New users", "email": "new@example.com" })
+}
+
+/// A payload that breaks every generated validation rule
+fn invalid_payload() -> Value {
+    json!({ "name": " ", "email": "nope" })
+}
+
+/// GET /users
+#[tokio::test]
+async fn list_users_returns_a_page() {
+    let app = app(fixtures(3)).await;
+
+    let (status, body) = send(&app, "GET", concat!("/users", "?page=1&limit=2"), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["success"], true);
+    assert_eq!(body["data"]["items"].as_array().unwrap().len(), 2);
+    let pagination = &body["data"]["pagination"];
+    assert_eq!(pagination["total"], 3);
+    assert_eq!(pagination["page"], 1);
+    assert_eq!(pagination["limit"], 2);
+    assert_eq!(pagination["pages"], 2);
+    assert_eq!(pagination["has_next"], true);
+    assert_eq!(pagination["has_prev"], false);
+}
+
+/// POST /users
+#[tokio::test]
+async fn create_users() {
+    let app = app(Vec::new()).await;
+
+    let (status, created) = send(&app, "POST", "/users", Some(valid_payload())).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(created["data"]["email"], "new@example.com");
+
+    let id = created["data"]["id"].as_str().unwrap();
+    let (status, fetched) = send(&app, "GET", &format!(concat!("/users", "/{}"), id), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(fetched["data"], created["data"]);
+}
+
+/// POST /users with a payload that fails validation
+#[tokio::test]
+async fn create_users_rejects_invalid_payload() {
+    let app = app(Vec::new()).await;
+
+    let (status, body) = send(&app, "POST", "/users", Some(invalid_payload())).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["success"], false);
+    assert_eq!(error_fields(&body), ["name", "email"]);
+
+    let (_, listed) = send(&app, "GET", "/users", None).await;
+    assert_eq!(listed["data"]["pagination"]["total"], 0);
+}
+
+/// DELETE /users/:id
+#[tokio::test]
+async fn delete_users() {
+    let seed = fixtures(1);
+    let app = app(seed.clone()).await;
+    let uri = format!("/users/{}", seed[0].id);
+
+    let (status, _) = send(&app, "DELETE", &uri, None).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = send(&app, "GET", &uri, None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+/// GET /users/:id
+#[tokio::test]
+async fn get_users_by_id() {
+    let seed = fixtures(2);
+    let app = app(seed.clone()).await;
+
+    let (status, body) = send(&app, "GET", &format!("/users/{}", seed[1].id), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"]["id"], seed[1].id.to_string());
+    assert_eq!(body["data"]["name"], seed[1].name);
+
+    let (status, _) = send(&app, "GET", &format!("/users/{}", Uuid::from_u128(99)), None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+/// PUT /users/:id
+#[tokio::test]
+async fn update_users() {
+    let seed = fixtures(1);
+    let app = app(seed.clone()).await;
+    let uri = format!("/users/{}", seed[0].id);
+
+    let (status, body) = send(&app, "PUT", &uri, Some(json!({ "name": "Renamed" }))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"]["name"], "Renamed");
+    assert_eq!(body["data"]["email"], seed[0].email);
+
+    let (status, body) = send(&app, "PUT", &uri, Some(invalid_payload())).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(error_fields(&body), ["name", "email"]);
+}
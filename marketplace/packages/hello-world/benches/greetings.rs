@@ -0,0 +1,88 @@
+//! Greeting generation benchmarks
+//!
+//! `greet_many/baseline` reproduces the original per-item `format!`
+//! implementation so the optimized paths can be compared against it.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use hello_world_utils::{HelloConfig, HelloWorld};
+use std::io::{self, Write};
+
+const COUNTS: [usize; 4] = [1_000, 10_000, 100_000, 1_000_000];
+
+fn hello(count: usize) -> HelloWorld {
+    HelloWorld::new(HelloConfig {
+        repeat_count: count,
+        ..Default::default()
+    })
+}
+
+fn baseline(config: &HelloConfig) -> Vec<String> {
+    (0..config.repeat_count)
+        .map(|i| format!("{} {}! (#{})", config.greeting, config.name, i + 1))
+        .collect()
+}
+
+fn bench_greet(c: &mut Criterion) {
+    let hello = hello(1);
+    c.bench_function("greet", |b| b.iter(|| black_box(hello.greet())));
+}
+
+fn bench_greet_many(c: &mut Criterion) {
+    let mut group = c.benchmark_group("greet_many");
+    group.sample_size(10);
+    for count in COUNTS {
+        let config = HelloConfig {
+            repeat_count: count,
+            ..Default::default()
+        };
+        let hello = hello(count);
+        group.throughput(Throughput::Elements(count as u64));
+        group.bench_with_input(BenchmarkId::new("baseline", count), &config, |b, config| {
+            b.iter(|| black_box(baseline(config)))
+        });
+        group.bench_with_input(BenchmarkId::new("vec", count), &hello, |b, hello| {
+            b.iter(|| black_box(hello.greet_many()))
+        });
+        group.bench_with_input(BenchmarkId::new("iter", count), &hello, |b, hello| {
+            b.iter(|| {
+                hello
+                    .greet_iter()
+                    .map(|g| black_box(g).len())
+                    .sum::<usize>()
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("write", count), &hello, |b, hello| {
+            b.iter(|| hello.write_greetings(&mut io::sink()))
+        });
+    }
+    group.finish();
+}
+
+/// Check the optimized writer produces the baseline output before timing it
+fn bench_write_equivalence(c: &mut Criterion) {
+    let config = HelloConfig {
+        repeat_count: 1_000,
+        ..Default::default()
+    };
+    let mut expected = Vec::new();
+    for line in baseline(&config) {
+        writeln!(expected, "{}", line).unwrap();
+    }
+    let mut actual = Vec::new();
+    HelloWorld::new(config)
+        .write_greetings(&mut actual)
+        .unwrap();
+    assert_eq!(actual, expected, "optimized output diverged from baseline");
+    c.bench_function("write_greetings/1000", |b| {
+        let hello = hello(1_000);
+        b.iter(|| hello.write_greetings(&mut io::sink()))
+    });
+}
+
+criterion_group!(
+    benches,
+    bench_greet,
+    bench_greet_many,
+    bench_write_equivalence
+);
+criterion_main!(benches);
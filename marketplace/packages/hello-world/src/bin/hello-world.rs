@@ -1,7 +1,24 @@
+use anyhow::Context;
 use hello_world_utils::{examples, HelloConfig, HelloWorld};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("validate") => validate(args.get(1).map(String::as_str)),
+        Some("manifest") => {
+            println!("{}", serde_json::to_string_pretty(&HelloWorld::manifest())?);
+            Ok(())
+        }
+        Some(other) => Err(anyhow::anyhow!(
+            "Unknown subcommand '{}' (expected: validate, manifest)",
+            other
+        )),
+        None => run_demo(),
+    }
+}
+
+fn run_demo() -> anyhow::Result<()> {
     println!("🌟 Hello World Utilities Demo");
     println!("==============================");
     println!();
@@ -19,3 +36,30 @@ async fn main() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+/// Validate a JSON config file (or the default config) and print every finding
+fn validate(path: Option<&str>) -> anyhow::Result<()> {
+    let config = match path {
+        Some(path) => {
+            let contents = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read config file '{}'", path))?;
+            serde_json::from_str::<HelloConfig>(&contents)
+                .with_context(|| format!("Failed to parse config file '{}'", path))?
+        }
+        None => HelloConfig::default(),
+    };
+
+    match HelloWorld::new(config).validate_config() {
+        Ok(()) => {
+            println!("✅ Configuration is valid");
+            Ok(())
+        }
+        Err(errors) => {
+            println!("❌ Found {} problem(s):", errors.errors().len());
+            for error in errors.errors() {
+                println!("  - {}", error);
+            }
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,309 @@
+//! Greeting format strings
+//!
+//! A tiny placeholder parser for greeting formats such as
+//! `"{greeting} {name}!"`. Supported placeholders are `{greeting}`, `{name}`,
+//! `{index}` and `{count}`; `{{` and `}}` produce literal braces. This is
+//! intentionally not a template engine.
+
+use std::fmt;
+use std::fmt::Write;
+
+/// Format used when none is configured
+pub const DEFAULT_FORMAT: &str = "{greeting} {name}!";
+
+/// A placeholder that can appear in a greeting format
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Placeholder {
+    Greeting,
+    Name,
+    Index,
+    Count,
+}
+
+impl Placeholder {
+    /// All supported placeholders
+    pub const ALL: [Placeholder; 4] = [
+        Placeholder::Greeting,
+        Placeholder::Name,
+        Placeholder::Index,
+        Placeholder::Count,
+    ];
+
+    /// Placeholder name as written between braces
+    pub fn name(self) -> &'static str {
+        match self {
+            Placeholder::Greeting => "greeting",
+            Placeholder::Name => "name",
+            Placeholder::Index => "index",
+            Placeholder::Count => "count",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|p| p.name() == name)
+    }
+}
+
+/// Errors produced while parsing a greeting format
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FormatError {
+    /// `{name}` where `name` is not a supported placeholder
+    UnknownPlaceholder { name: String, offset: usize },
+    /// `{` without a matching `}`
+    Unterminated { offset: usize },
+    /// `}` that is neither closing a placeholder nor escaped as `}}`
+    UnmatchedBrace { offset: usize },
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FormatError::UnknownPlaceholder { name, offset } => write!(
+                f,
+                "unknown placeholder '{{{}}}' at byte {} (expected one of: greeting, name, index, count)",
+                name, offset
+            ),
+            FormatError::Unterminated { offset } => {
+                write!(f, "unterminated placeholder starting at byte {}", offset)
+            }
+            FormatError::UnmatchedBrace { offset } => {
+                write!(f, "unmatched '}}' at byte {} (use '}}}}' for a literal brace)", offset)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FormatError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Placeholder(Placeholder),
+}
+
+/// A parsed greeting format
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GreetingFormat {
+    segments: Vec<Segment>,
+}
+
+impl GreetingFormat {
+    /// Parse a format string, validating every placeholder
+    pub fn parse(source: &str) -> Result<Self, FormatError> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = source.char_indices().peekable();
+
+        while let Some((offset, c)) = chars.next() {
+            match c {
+                '{' if matches!(chars.peek(), Some((_, '{'))) => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '{' => {
+                    let start = offset + 1;
+                    let end = loop {
+                        match chars.next() {
+                            Some((i, '}')) => break i,
+                            Some((_, '{')) | None => {
+                                return Err(FormatError::Unterminated { offset })
+                            }
+                            Some(_) => {}
+                        }
+                    };
+                    let name = &source[start..end];
+                    let placeholder = Placeholder::from_name(name).ok_or_else(|| {
+                        FormatError::UnknownPlaceholder {
+                            name: name.to_string(),
+                            offset,
+                        }
+                    })?;
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(Segment::Placeholder(placeholder));
+                }
+                '}' if matches!(chars.peek(), Some((_, '}'))) => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '}' => return Err(FormatError::UnmatchedBrace { offset }),
+                c => literal.push(c),
+            }
+        }
+
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+        Ok(Self { segments })
+    }
+
+    /// Whether the format references the given placeholder
+    pub fn uses(&self, placeholder: Placeholder) -> bool {
+        self.segments
+            .iter()
+            .any(|s| matches!(s, Segment::Placeholder(p) if *p == placeholder))
+    }
+
+    /// Render the format with the given values
+    pub fn render(&self, greeting: &str, name: &str, index: usize, count: usize) -> String {
+        let mut out = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(text) => out.push_str(text),
+                Segment::Placeholder(Placeholder::Greeting) => out.push_str(greeting),
+                Segment::Placeholder(Placeholder::Name) => out.push_str(name),
+                Segment::Placeholder(Placeholder::Index) => out.push_str(&index.to_string()),
+                Segment::Placeholder(Placeholder::Count) => out.push_str(&count.to_string()),
+            }
+        }
+        out
+    }
+
+    /// Substitute everything except `{index}` ahead of time
+    ///
+    /// Used when rendering many greetings that differ only by index.
+    pub fn compile(&self, greeting: &str, name: &str, count: usize) -> CompiledFormat {
+        let mut compiled = CompiledFormat::default();
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(text) => compiled.push_literal(text),
+                Segment::Placeholder(Placeholder::Greeting) => compiled.push_literal(greeting),
+                Segment::Placeholder(Placeholder::Name) => compiled.push_literal(name),
+                Segment::Placeholder(Placeholder::Index) => compiled.push_index(),
+                Segment::Placeholder(Placeholder::Count) => {
+                    compiled.push_literal(&count.to_string())
+                }
+            }
+        }
+        compiled
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Piece {
+    Literal(String),
+    Index,
+}
+
+/// A greeting format with only `{index}` left to substitute
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompiledFormat {
+    pieces: Vec<Piece>,
+    literal_len: usize,
+}
+
+impl CompiledFormat {
+    /// Append literal text, merging it with a preceding literal
+    pub fn push_literal(&mut self, text: &str) {
+        self.literal_len += text.len();
+        match self.pieces.last_mut() {
+            Some(Piece::Literal(last)) => last.push_str(text),
+            _ => self.pieces.push(Piece::Literal(text.to_string())),
+        }
+    }
+
+    /// Append an index substitution
+    pub fn push_index(&mut self) {
+        self.pieces.push(Piece::Index);
+    }
+
+    /// Upper bound on the length of a greeting rendered with an index up to
+    /// `max_index`, for preallocation
+    pub fn capacity_hint(&self, max_index: usize) -> usize {
+        let indexes = self.pieces.iter().filter(|p| **p == Piece::Index).count();
+        let digits = max_index.checked_ilog10().unwrap_or(0) as usize + 1;
+        self.literal_len + indexes * digits
+    }
+
+    /// Append the greeting for `index` to `out`
+    pub fn render_into(&self, out: &mut String, index: usize) {
+        for piece in &self.pieces {
+            match piece {
+                Piece::Literal(text) => out.push_str(text),
+                Piece::Index => {
+                    let _ = write!(out, "{}", index);
+                }
+            }
+        }
+    }
+}
+
+impl Default for GreetingFormat {
+    fn default() -> Self {
+        Self {
+            segments: vec![
+                Segment::Placeholder(Placeholder::Greeting),
+                Segment::Literal(" ".to_string()),
+                Segment::Placeholder(Placeholder::Name),
+                Segment::Literal("!".to_string()),
+            ],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_format_matches_parsed_default() {
+        assert_eq!(
+            GreetingFormat::parse(DEFAULT_FORMAT).unwrap(),
+            GreetingFormat::default()
+        );
+    }
+
+    #[test]
+    fn test_render_all_placeholders() {
+        let format = GreetingFormat::parse("[{index}/{count}] {greeting}, {name}").unwrap();
+        assert_eq!(format.render("Hi", "Rust", 2, 5), "[2/5] Hi, Rust");
+        assert!(format.uses(Placeholder::Index));
+        assert!(format.uses(Placeholder::Count));
+    }
+
+    #[test]
+    fn test_compiled_matches_render() {
+        let format = GreetingFormat::parse("{{{index}}} {greeting} {name} of {count}").unwrap();
+        let compiled = format.compile("Hi", "Rust", 12);
+        for index in [1, 9, 10, 12] {
+            let mut out = String::new();
+            compiled.render_into(&mut out, index);
+            assert_eq!(out, format.render("Hi", "Rust", index, 12));
+            assert!(out.len() <= compiled.capacity_hint(12));
+        }
+    }
+
+    #[test]
+    fn test_escaped_braces() {
+        let format = GreetingFormat::parse("{{{greeting}}} {{name}}").unwrap();
+        assert_eq!(format.render("Hello", "World", 1, 1), "{Hello} {name}");
+        assert!(!format.uses(Placeholder::Name));
+    }
+
+    #[test]
+    fn test_unknown_placeholder_reports_name_and_offset() {
+        let err = GreetingFormat::parse("{greeting} {nmae}!").unwrap_err();
+        assert_eq!(
+            err,
+            FormatError::UnknownPlaceholder {
+                name: "nmae".to_string(),
+                offset: 11,
+            }
+        );
+        assert!(err.to_string().contains("{nmae}"));
+        assert!(err.to_string().contains("byte 11"));
+    }
+
+    #[test]
+    fn test_unterminated_and_unmatched() {
+        assert_eq!(
+            GreetingFormat::parse("Hi {name").unwrap_err(),
+            FormatError::Unterminated { offset: 3 }
+        );
+        assert_eq!(
+            GreetingFormat::parse("Hi } there").unwrap_err(),
+            FormatError::UnmatchedBrace { offset: 3 }
+        );
+    }
+}
@@ -0,0 +1,270 @@
+//! Greeter trait and alternative implementations
+//!
+//! Demonstrates how marketplace packages can expose an extension point:
+//! [`HelloWorld`] is one [`Greeter`], [`TimestampedGreeter`] and
+//! [`RateLimitedGreeter`] wrap any other greeter, and [`from_config`] picks an
+//! implementation at runtime.
+
+use crate::{HelloConfig, HelloWorld};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Something that can greet a target
+#[cfg_attr(feature = "async", async_trait::async_trait)]
+pub trait Greeter: Send + Sync {
+    /// Greet the given target
+    fn greet(&self, target: &str) -> String;
+
+    /// Async counterpart of [`Greeter::greet`]
+    #[cfg(feature = "async")]
+    async fn greet_async(&self, target: &str) -> String {
+        self.greet(target)
+    }
+}
+
+impl Greeter for HelloWorld {
+    fn greet(&self, target: &str) -> String {
+        self.greet_target(target)
+    }
+}
+
+/// Source of the current time, injectable for tests
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// Clock backed by the system time
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// Greeter that prefixes every greeting with an RFC3339 timestamp
+pub struct TimestampedGreeter<G, C = SystemClock> {
+    inner: G,
+    clock: C,
+}
+
+impl<G: Greeter> TimestampedGreeter<G> {
+    /// Wrap a greeter using the system clock
+    pub fn new(inner: G) -> Self {
+        Self::with_clock(inner, SystemClock)
+    }
+}
+
+impl<G: Greeter, C: Clock> TimestampedGreeter<G, C> {
+    /// Wrap a greeter using a custom clock
+    pub fn with_clock(inner: G, clock: C) -> Self {
+        Self { inner, clock }
+    }
+}
+
+impl<G: Greeter, C: Clock> Greeter for TimestampedGreeter<G, C> {
+    fn greet(&self, target: &str) -> String {
+        let timestamp = self.clock.now().to_rfc3339_opts(SecondsFormat::Secs, true);
+        format!("[{}] {}", timestamp, self.inner.greet(target))
+    }
+}
+
+/// Greeter decorator enforcing a minimum interval between greetings
+///
+/// Each call reserves the next free slot, so concurrent callers are spaced
+/// out rather than bunched together after a wait.
+pub struct RateLimitedGreeter<G> {
+    inner: G,
+    min_interval: Duration,
+    next_slot: Mutex<Option<Instant>>,
+}
+
+impl<G: Greeter> RateLimitedGreeter<G> {
+    /// Wrap a greeter with a minimum interval between greetings
+    pub fn new(inner: G, min_interval: Duration) -> Self {
+        Self {
+            inner,
+            min_interval,
+            next_slot: Mutex::new(None),
+        }
+    }
+
+    /// Reserve the next greeting slot and return when it starts
+    ///
+    /// `now` is read from the clock the caller waits on: the system clock
+    /// when greeting synchronously, tokio's when greeting asynchronously.
+    fn reserve(&self, now: Instant) -> Instant {
+        let mut next_slot = self
+            .next_slot
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let start = next_slot.map_or(now, |slot| slot.max(now));
+        *next_slot = Some(start + self.min_interval);
+        start
+    }
+}
+
+#[cfg_attr(feature = "async", async_trait::async_trait)]
+impl<G: Greeter> Greeter for RateLimitedGreeter<G> {
+    fn greet(&self, target: &str) -> String {
+        let start = self.reserve(Instant::now());
+        let wait = start.saturating_duration_since(Instant::now());
+        if !wait.is_zero() {
+            std::thread::sleep(wait);
+        }
+        self.inner.greet(target)
+    }
+
+    #[cfg(feature = "async")]
+    async fn greet_async(&self, target: &str) -> String {
+        let start = self.reserve(tokio::time::Instant::now().into_std());
+        tokio::time::sleep_until(start.into()).await;
+        self.inner.greet_async(target).await
+    }
+}
+
+/// Which greeter implementation [`from_config`] builds
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum GreeterKind {
+    #[default]
+    Plain,
+    Timestamped,
+    RateLimited {
+        min_interval_ms: u64,
+    },
+}
+
+/// Build the greeter selected by `config.greeter`
+pub fn from_config(config: &HelloConfig) -> Box<dyn Greeter> {
+    let base = HelloWorld::new(config.clone());
+    match config.greeter {
+        GreeterKind::Plain => Box::new(base),
+        GreeterKind::Timestamped => Box::new(TimestampedGreeter::new(base)),
+        GreeterKind::RateLimited { min_interval_ms } => Box::new(RateLimitedGreeter::new(
+            base,
+            Duration::from_millis(min_interval_ms),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use std::sync::atomic::{AtomicI64, Ordering};
+
+    /// Clock that advances one second on every read
+    struct MockClock {
+        seconds: AtomicI64,
+    }
+
+    impl MockClock {
+        fn starting_at(seconds: i64) -> Self {
+            Self {
+                seconds: AtomicI64::new(seconds),
+            }
+        }
+    }
+
+    impl Clock for MockClock {
+        fn now(&self) -> DateTime<Utc> {
+            let seconds = self.seconds.fetch_add(1, Ordering::SeqCst);
+            Utc.timestamp_opt(seconds, 0).unwrap()
+        }
+    }
+
+    #[test]
+    fn test_hello_world_implements_greeter() {
+        let hello = HelloWorld::default();
+        assert_eq!(Greeter::greet(&hello, "Rust"), "Hello Rust!");
+    }
+
+    #[test]
+    fn test_timestamped_greeter_uses_clock() {
+        let greeter =
+            TimestampedGreeter::with_clock(HelloWorld::default(), MockClock::starting_at(0));
+        assert_eq!(greeter.greet("Rust"), "[1970-01-01T00:00:00Z] Hello Rust!");
+        assert_eq!(greeter.greet("Rust"), "[1970-01-01T00:00:01Z] Hello Rust!");
+    }
+
+    #[test]
+    fn test_from_config_selects_implementation() {
+        let config = HelloConfig {
+            greeter: GreeterKind::Timestamped,
+            ..Default::default()
+        };
+        let greeting = from_config(&config).greet("Rust");
+        assert!(greeting.starts_with('['));
+        assert!(greeting.ends_with("] Hello Rust!"));
+
+        let plain = from_config(&HelloConfig::default());
+        assert_eq!(plain.greet("Rust"), "Hello Rust!");
+    }
+
+    #[test]
+    fn test_greeter_kind_deserializes() {
+        let kind: GreeterKind =
+            serde_json::from_str(r#"{"kind":"rate_limited","min_interval_ms":250}"#).unwrap();
+        assert_eq!(
+            kind,
+            GreeterKind::RateLimited {
+                min_interval_ms: 250
+            }
+        );
+    }
+
+    #[test]
+    fn test_rate_limited_greeter_sleeps_between_sync_greetings() {
+        let interval = Duration::from_millis(20);
+        let greeter = RateLimitedGreeter::new(HelloWorld::default(), interval);
+        let start = Instant::now();
+
+        assert_eq!(greeter.greet("Rust"), "Hello Rust!");
+        greeter.greet("Rust");
+        assert!(start.elapsed() >= interval);
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test(start_paused = true)]
+    async fn test_rate_limited_greeter_enforces_interval() {
+        let interval = Duration::from_millis(500);
+        let greeter = RateLimitedGreeter::new(HelloWorld::default(), interval);
+        let start = tokio::time::Instant::now();
+
+        assert_eq!(greeter.greet_async("Rust").await, "Hello Rust!");
+        assert_eq!(start.elapsed(), Duration::ZERO);
+
+        greeter.greet_async("Rust").await;
+        greeter.greet_async("Rust").await;
+        assert_eq!(start.elapsed(), interval * 2);
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test(start_paused = true)]
+    async fn test_rate_limited_greeter_does_not_wait_after_idle() {
+        let interval = Duration::from_millis(500);
+        let greeter = RateLimitedGreeter::new(HelloWorld::default(), interval);
+
+        greeter.greet_async("Rust").await;
+        tokio::time::advance(Duration::from_secs(2)).await;
+
+        let before = tokio::time::Instant::now();
+        greeter.greet_async("Rust").await;
+        assert_eq!(before.elapsed(), Duration::ZERO);
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_timestamped_greeter_async_delegates() {
+        let greeter =
+            TimestampedGreeter::with_clock(HelloWorld::default(), MockClock::starting_at(60));
+        assert_eq!(
+            greeter.greet_async("Rust").await,
+            "[1970-01-01T00:01:00Z] Hello Rust!"
+        );
+    }
+}
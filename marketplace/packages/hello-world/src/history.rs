@@ -0,0 +1,290 @@
+//! Greeting history
+//!
+//! [`GreetingLog`] records every greeting produced by a [`crate::HelloWorld`]
+//! built with [`crate::HelloWorld::with_log`]. Records are kept in memory and,
+//! optionally, appended to a JSON-lines file that is rotated once it grows
+//! past a size limit.
+
+use crate::greeter::{Clock, SystemClock};
+use crate::HelloConfig;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// One recorded greeting
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GreetingRecord {
+    pub text: String,
+    pub name: String,
+    /// Hash of the config that produced the greeting, see [`config_hash`]
+    pub config_hash: String,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// File persistence settings
+#[derive(Debug, Clone)]
+struct LogFile {
+    path: PathBuf,
+    max_bytes: u64,
+}
+
+/// In-memory greeting history with optional JSON-lines persistence
+pub struct GreetingLog {
+    records: Mutex<Vec<GreetingRecord>>,
+    file: Option<LogFile>,
+    clock: Box<dyn Clock>,
+}
+
+impl GreetingLog {
+    /// Create a log that only keeps records in memory
+    pub fn in_memory() -> Self {
+        Self {
+            records: Mutex::new(Vec::new()),
+            file: None,
+            clock: Box::new(SystemClock),
+        }
+    }
+
+    /// Create a log persisted to `path`, loading any records already there
+    ///
+    /// When appending a record would grow the file past `max_bytes`, the file
+    /// is renamed to `<path>.1` (replacing an older archive) and a new file is
+    /// started.
+    pub fn with_file(path: impl AsRef<Path>, max_bytes: u64) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let records = if path.exists() {
+            Self::load(&path)?
+        } else {
+            Vec::new()
+        };
+        Ok(Self {
+            records: Mutex::new(records),
+            file: Some(LogFile { path, max_bytes }),
+            clock: Box::new(SystemClock),
+        })
+    }
+
+    /// Use a custom clock for record timestamps
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Box::new(clock);
+        self
+    }
+
+    fn load(path: &Path) -> Result<Vec<GreetingRecord>> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read greeting log '{}'", path.display()))?;
+        contents
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| {
+                serde_json::from_str(line).with_context(|| {
+                    format!("Invalid record on line {} of '{}'", i + 1, path.display())
+                })
+            })
+            .collect()
+    }
+
+    /// Record a greeting
+    pub fn record(&self, text: &str, name: &str, config_hash: &str) -> Result<()> {
+        let record = GreetingRecord {
+            text: text.to_string(),
+            name: name.to_string(),
+            config_hash: config_hash.to_string(),
+            timestamp: self.clock.now(),
+        };
+        let mut records = self.lock();
+        if let Some(file) = &self.file {
+            Self::append(file, &record)?;
+        }
+        records.push(record);
+        Ok(())
+    }
+
+    fn append(file: &LogFile, record: &GreetingRecord) -> Result<()> {
+        let mut line = serde_json::to_string(record)?;
+        line.push('\n');
+
+        let current_len = fs::metadata(&file.path).map(|m| m.len()).unwrap_or(0);
+        if current_len > 0 && current_len + line.len() as u64 > file.max_bytes {
+            fs::rename(&file.path, Self::archive_path(&file.path)).with_context(|| {
+                format!("Failed to rotate greeting log '{}'", file.path.display())
+            })?;
+        }
+
+        let mut out = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&file.path)
+            .with_context(|| format!("Failed to open greeting log '{}'", file.path.display()))?;
+        out.write_all(line.as_bytes())?;
+        Ok(())
+    }
+
+    /// Path a full log file is rotated to
+    pub fn archive_path(path: &Path) -> PathBuf {
+        let mut archive = path.as_os_str().to_owned();
+        archive.push(".1");
+        PathBuf::from(archive)
+    }
+
+    /// The `n` most recent records, oldest first
+    pub fn recent(&self, n: usize) -> Vec<GreetingRecord> {
+        let records = self.lock();
+        records[records.len().saturating_sub(n)..].to_vec()
+    }
+
+    /// Number of greetings per greeted name
+    pub fn count_by_name(&self) -> BTreeMap<String, usize> {
+        let mut counts = BTreeMap::new();
+        for record in self.lock().iter() {
+            *counts.entry(record.name.clone()).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    /// Total number of records
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Whether nothing has been recorded
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<GreetingRecord>> {
+        self.records
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Default for GreetingLog {
+    fn default() -> Self {
+        Self::in_memory()
+    }
+}
+
+/// Stable FNV-1a hash of a config's JSON form, as 16 hex digits
+pub fn config_hash(config: &HelloConfig) -> String {
+    let json = serde_json::to_string(config).unwrap_or_default();
+    let hash = json.bytes().fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    });
+    format!("{:016x}", hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::HelloWorld;
+    use chrono::TimeZone;
+    use std::sync::Arc;
+
+    struct FixedClock;
+
+    impl Clock for FixedClock {
+        fn now(&self) -> DateTime<Utc> {
+            Utc.timestamp_opt(1_700_000_000, 0).unwrap()
+        }
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "hello-world-history-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_greetings_are_recorded() {
+        let log = Arc::new(GreetingLog::in_memory().with_clock(FixedClock));
+        let config = HelloConfig {
+            repeat_count: 2,
+            ..Default::default()
+        };
+        let hello = HelloWorld::with_log(config.clone(), log.clone());
+
+        hello.greet();
+        hello.greet_many();
+
+        assert_eq!(log.len(), 3);
+        let recent = log.recent(2);
+        assert_eq!(recent[0].text, "Hello World! (#1)");
+        assert_eq!(recent[1].text, "Hello World! (#2)");
+        assert_eq!(recent[1].config_hash, config_hash(&config));
+        assert_eq!(recent[1].timestamp, FixedClock.now());
+        assert_eq!(log.count_by_name().get("World"), Some(&3));
+    }
+
+    #[test]
+    fn test_reload_from_disk() {
+        let dir = temp_dir("reload");
+        let path = dir.join("greetings.jsonl");
+        {
+            let log = GreetingLog::with_file(&path, 1024 * 1024).unwrap();
+            log.record("Hello Rust!", "Rust", "abc").unwrap();
+            log.record("Hello World!", "World", "abc").unwrap();
+        }
+
+        let reloaded = GreetingLog::with_file(&path, 1024 * 1024).unwrap();
+        assert_eq!(reloaded.len(), 2);
+        assert_eq!(reloaded.recent(1)[0].text, "Hello World!");
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_rotation_at_size_limit() {
+        let dir = temp_dir("rotate");
+        let path = dir.join("greetings.jsonl");
+        let log = GreetingLog::with_file(&path, 64).unwrap();
+
+        log.record("Hello Rust!", "Rust", "abc").unwrap();
+        assert!(!GreetingLog::archive_path(&path).exists());
+        log.record("Hello World!", "World", "abc").unwrap();
+
+        let archived = fs::read_to_string(GreetingLog::archive_path(&path)).unwrap();
+        assert!(archived.contains("Hello Rust!"));
+        let current = fs::read_to_string(&path).unwrap();
+        assert!(current.contains("Hello World!"));
+        assert!(!current.contains("Hello Rust!"));
+        assert_eq!(log.len(), 2);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    /// Set in the child process of [`test_default_has_no_log`]
+    const NO_LOG_CHILD: &str = "HELLO_WORLD_NO_LOG_CHILD";
+
+    #[test]
+    fn test_default_has_no_log() {
+        // Greets in a child run of this test started in an empty directory,
+        // so the test process never changes its own
+        if std::env::var_os(NO_LOG_CHILD).is_some() {
+            let hello = HelloWorld::default();
+            hello.greet();
+            hello.greet_many();
+            assert!(hello.log().is_none());
+            return;
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let status = std::process::Command::new(std::env::current_exe().unwrap())
+            .args(["--exact", "history::tests::test_default_has_no_log"])
+            .env(NO_LOG_CHILD, "1")
+            .current_dir(dir.path())
+            .status()
+            .unwrap();
+        assert!(status.success());
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+}
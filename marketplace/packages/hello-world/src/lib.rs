@@ -5,7 +5,25 @@
 //! to demonstrate the marketplace system.
 
 use anyhow::Result;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+pub mod format;
+pub mod greeter;
+pub mod history;
+pub mod manifest;
+pub mod pool;
+pub mod validation;
+
+pub use format::{CompiledFormat, FormatError, GreetingFormat, Placeholder, DEFAULT_FORMAT};
+pub use greeter::{Greeter, GreeterKind};
+pub use history::{GreetingLog, GreetingRecord};
+pub use manifest::{validate_manifest, PackageManifest};
+pub use pool::PoolEntry;
+pub use validation::{ValidationError, ValidationErrors};
 
 /// Configuration for hello world utilities
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -13,6 +31,22 @@ pub struct HelloConfig {
     pub greeting: String,
     pub name: String,
     pub repeat_count: usize,
+    /// Greeting format, see [`format`] for the supported placeholders
+    #[serde(default = "default_format")]
+    pub format: String,
+    /// Greeter implementation built by [`greeter::from_config`]
+    #[serde(default)]
+    pub greeter: GreeterKind,
+    /// Greetings picked by [`HelloWorld::greet_random`], see [`pool`]
+    #[serde(default)]
+    pub greeting_pool: Vec<PoolEntry>,
+    /// Seed for [`HelloWorld::greet_random`]; random when unset
+    #[serde(default)]
+    pub random_seed: Option<u64>,
+}
+
+fn default_format() -> String {
+    DEFAULT_FORMAT.to_string()
 }
 
 impl Default for HelloConfig {
@@ -21,6 +55,10 @@ impl Default for HelloConfig {
             greeting: "Hello".to_string(),
             name: "World".to_string(),
             repeat_count: 1,
+            format: default_format(),
+            greeter: GreeterKind::default(),
+            greeting_pool: Vec::new(),
+            random_seed: None,
         }
     }
 }
@@ -28,24 +66,215 @@ impl Default for HelloConfig {
 /// Main hello world utility
 pub struct HelloWorld {
     config: HelloConfig,
+    format: GreetingFormat,
+    log: Option<(Arc<GreetingLog>, String)>,
+    rng: Mutex<StdRng>,
 }
 
 impl HelloWorld {
     /// Create a new hello world instance
+    ///
+    /// An invalid `format` falls back to the default format; call
+    /// [`HelloWorld::validate_config`] to surface the parse error.
     pub fn new(config: HelloConfig) -> Self {
-        Self { config }
+        let format = GreetingFormat::parse(&config.format).unwrap_or_default();
+        let rng = match config.random_seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        Self {
+            config,
+            format,
+            log: None,
+            rng: Mutex::new(rng),
+        }
+    }
+
+    /// Create a hello world instance that records every greeting in `log`
+    pub fn with_log(config: HelloConfig, log: Arc<GreetingLog>) -> Self {
+        let hash = history::config_hash(&config);
+        Self {
+            log: Some((log, hash)),
+            ..Self::new(config)
+        }
+    }
+
+    /// The greeting log, if one was attached
+    pub fn log(&self) -> Option<&Arc<GreetingLog>> {
+        self.log.as_ref().map(|(log, _)| log)
+    }
+
+    /// Record a greeting in the attached log
+    ///
+    /// History is best-effort: a failing log file never prevents a greeting.
+    fn record(&self, text: &str, name: &str) {
+        if let Some((log, hash)) = &self.log {
+            let _ = log.record(text, name, hash);
+        }
     }
 
     /// Generate a hello world message
     pub fn greet(&self) -> String {
-        format!("{} {}!", self.config.greeting, self.config.name)
+        self.greet_target(&self.config.name)
+    }
+
+    /// Generate a message greeting `target` instead of the configured name
+    pub fn greet_target(&self, target: &str) -> String {
+        let greeting = self.format.render(&self.config.greeting, target, 1, 1);
+        self.record(&greeting, target);
+        greeting
+    }
+
+    /// Generate a message using a greeting picked from the greeting pool
+    ///
+    /// Falls back to the configured greeting when the pool is empty.
+    pub fn greet_random(&self) -> String {
+        let mut rng = self
+            .rng
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        self.greet_random_with(&mut *rng)
+    }
+
+    /// Like [`HelloWorld::greet_random`], drawing from the given RNG
+    pub fn greet_random_with<R: Rng + ?Sized>(&self, rng: &mut R) -> String {
+        let greeting =
+            pool::choose(&self.config.greeting_pool, rng).unwrap_or(&self.config.greeting);
+        let text = self.format.render(greeting, &self.config.name, 1, 1);
+        self.record(&text, &self.config.name);
+        text
     }
 
     /// Generate multiple greetings
+    ///
+    /// Formats that don't reference `{index}` get the legacy ` (#n)` suffix so
+    /// each greeting stays distinguishable.
     pub fn greet_many(&self) -> Vec<String> {
-        (0..self.config.repeat_count)
-            .map(|i| format!("{} {}! (#{})", self.config.greeting, self.config.name, i + 1))
-            .collect()
+        self.greet_iter().collect()
+    }
+
+    /// Lazily generate the same greetings as [`HelloWorld::greet_many`]
+    pub fn greet_iter(&self) -> GreetingIter<'_> {
+        GreetingIter {
+            hello: self,
+            compiled: self.compile_many(),
+            next: 1,
+            count: self.config.repeat_count,
+        }
+    }
+
+    /// Stream the greetings of [`HelloWorld::greet_many`] to `out`, one per line
+    ///
+    /// A single buffer is reused for every line; wrap unbuffered writers in a
+    /// [`std::io::BufWriter`].
+    pub fn write_greetings<W: Write + ?Sized>(&self, out: &mut W) -> std::io::Result<()> {
+        let compiled = self.compile_many();
+        let mut line = String::with_capacity(compiled.capacity_hint(self.config.repeat_count) + 1);
+        for index in 1..=self.config.repeat_count {
+            line.clear();
+            compiled.render_into(&mut line, index);
+            self.record(&line, &self.config.name);
+            line.push('\n');
+            out.write_all(line.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Pre-render the invariant parts of every `greet_many` item
+    fn compile_many(&self) -> CompiledFormat {
+        let mut compiled = self.format.compile(
+            &self.config.greeting,
+            &self.config.name,
+            self.config.repeat_count,
+        );
+        if !self.format.uses(Placeholder::Index) {
+            compiled.push_literal(" (#");
+            compiled.push_index();
+            compiled.push_literal(")");
+        }
+        compiled
+    }
+
+    /// Marketplace manifest describing this package
+    pub fn manifest() -> PackageManifest {
+        use manifest::{ConfigKey, ConfigType, ExampleInvocation};
+        use serde_json::json;
+
+        let key = |name: &str, ty, default, description: &str| ConfigKey {
+            name: name.to_string(),
+            ty,
+            required: false,
+            default,
+            description: description.to_string(),
+        };
+        let example = |description: &str, command: &str| ExampleInvocation {
+            description: description.to_string(),
+            command: command.to_string(),
+        };
+
+        PackageManifest {
+            name: env!("CARGO_PKG_NAME").to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            description: env!("CARGO_PKG_DESCRIPTION").to_string(),
+            capabilities: [
+                "greeting",
+                "greeting-format",
+                "greeting-history",
+                "random-greeting",
+                "config-validation",
+            ]
+            .map(String::from)
+            .to_vec(),
+            config_keys: vec![
+                key(
+                    "greeting",
+                    ConfigType::String,
+                    Some(json!("Hello")),
+                    "Greeting word",
+                ),
+                key(
+                    "name",
+                    ConfigType::String,
+                    Some(json!("World")),
+                    "Who to greet",
+                ),
+                key(
+                    "repeat_count",
+                    ConfigType::Integer,
+                    Some(json!(1)),
+                    "Number of greetings produced by greet_many",
+                ),
+                key(
+                    "format",
+                    ConfigType::String,
+                    Some(json!(DEFAULT_FORMAT)),
+                    "Greeting format with {greeting}, {name}, {index} and {count} placeholders",
+                ),
+                key(
+                    "greeter",
+                    ConfigType::Table,
+                    Some(json!({ "kind": "plain" })),
+                    "Greeter implementation: plain, timestamped or rate_limited",
+                ),
+                key(
+                    "greeting_pool",
+                    ConfigType::Array,
+                    Some(json!([])),
+                    "Weighted greetings for greet_random, e.g. \"Howdy:3\"",
+                ),
+                key(
+                    "random_seed",
+                    ConfigType::Integer,
+                    None,
+                    "Seed for reproducible greet_random output",
+                ),
+            ],
+            examples: vec![
+                example("Run the demo", "hello-world"),
+                example("Validate a config file", "hello-world validate config.json"),
+                example("Print this manifest as JSON", "hello-world manifest"),
+            ],
+        }
     }
 
     /// Get configuration as JSON
@@ -53,21 +282,68 @@ impl HelloWorld {
         Ok(serde_json::to_string_pretty(&self.config)?)
     }
 
-    /// Validate the configuration
-    pub fn validate_config(&self) -> Result<()> {
-        if self.config.greeting.trim().is_empty() {
-            return Err(anyhow::anyhow!("Greeting cannot be empty"));
+    /// Validate the configuration, reporting every violation at once
+    pub fn validate_config(&self) -> std::result::Result<(), ValidationErrors> {
+        let mut errors = utils::greeting_config_errors(
+            &self.config.greeting,
+            &self.config.name,
+            self.config.repeat_count,
+            "repeat_count",
+        );
+        validation::check_pool(&mut errors, "greeting_pool", &self.config.greeting_pool);
+        if let Err(error) = GreetingFormat::parse(&self.config.format) {
+            errors.push(ValidationError::InvalidFormat {
+                field: "format",
+                error,
+            });
         }
-        if self.config.name.trim().is_empty() {
-            return Err(anyhow::anyhow!("Name cannot be empty"));
+        ValidationErrors(errors).into_result()
+    }
+
+    /// Validate the configuration, failing on the first violation
+    ///
+    /// Kept for callers that relied on the original single-error behavior.
+    pub fn validate_first(&self) -> Result<()> {
+        match self.validate_config() {
+            Ok(()) => Ok(()),
+            Err(errors) => match errors.0.into_iter().next() {
+                Some(first) => Err(first.into()),
+                None => Ok(()),
+            },
         }
-        if self.config.repeat_count == 0 {
-            return Err(anyhow::anyhow!("Repeat count must be greater than 0"));
+    }
+}
+
+/// Iterator returned by [`HelloWorld::greet_iter`]
+pub struct GreetingIter<'a> {
+    hello: &'a HelloWorld,
+    compiled: CompiledFormat,
+    next: usize,
+    count: usize,
+}
+
+impl Iterator for GreetingIter<'_> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        if self.next > self.count {
+            return None;
         }
-        Ok(())
+        let mut greeting = String::with_capacity(self.compiled.capacity_hint(self.count));
+        self.compiled.render_into(&mut greeting, self.next);
+        self.hello.record(&greeting, &self.hello.config.name);
+        self.next += 1;
+        Some(greeting)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = (self.count + 1).saturating_sub(self.next);
+        (remaining, Some(remaining))
     }
 }
 
+impl ExactSizeIterator for GreetingIter<'_> {}
+
 impl Default for HelloWorld {
     fn default() -> Self {
         Self::new(HelloConfig::default())
@@ -90,18 +366,27 @@ pub mod utils {
             .collect()
     }
 
-    /// Validate greeting configuration
-    pub fn validate_greeting_config(greeting: &str, name: &str, count: usize) -> Result<()> {
-        if greeting.trim().is_empty() {
-            return Err(anyhow::anyhow!("Greeting cannot be empty"));
-        }
-        if name.trim().is_empty() {
-            return Err(anyhow::anyhow!("Name cannot be empty"));
-        }
-        if count == 0 {
-            return Err(anyhow::anyhow!("Count must be greater than 0"));
-        }
-        Ok(())
+    /// Validate greeting configuration, reporting every violation at once
+    pub fn validate_greeting_config(
+        greeting: &str, name: &str, count: usize,
+    ) -> std::result::Result<(), ValidationErrors> {
+        ValidationErrors(greeting_config_errors(greeting, name, count, "count")).into_result()
+    }
+
+    /// Collect violations of the greeting, name and count constraints
+    pub(crate) fn greeting_config_errors(
+        greeting: &str, name: &str, count: usize, count_field: &'static str,
+    ) -> Vec<ValidationError> {
+        let mut errors = Vec::new();
+        validation::check_text(
+            &mut errors,
+            "greeting",
+            greeting,
+            validation::MAX_GREETING_LEN,
+        );
+        validation::check_text(&mut errors, "name", name, validation::MAX_NAME_LEN);
+        validation::check_count(&mut errors, count_field, count);
+        errors
     }
 }
 
@@ -128,6 +413,7 @@ pub mod examples {
             greeting: "Greetings".to_string(),
             name: "Universe".to_string(),
             repeat_count: 3,
+            ..Default::default()
         };
 
         let hello = HelloWorld::new(config);
@@ -164,6 +450,7 @@ mod tests {
             greeting: "Hi".to_string(),
             name: "Rust".to_string(),
             repeat_count: 1,
+            ..Default::default()
         };
         let hello = HelloWorld::new(config);
         assert_eq!(hello.greet(), "Hi Rust!");
@@ -175,6 +462,7 @@ mod tests {
             greeting: "Hello".to_string(),
             name: "Test".to_string(),
             repeat_count: 3,
+            ..Default::default()
         };
         let hello = HelloWorld::new(config);
         let greetings = hello.greet_many();
@@ -190,6 +478,7 @@ mod tests {
             greeting: "Hello".to_string(),
             name: "World".to_string(),
             repeat_count: 1,
+            ..Default::default()
         };
         let hello = HelloWorld::new(config);
         assert!(hello.validate_config().is_ok());
@@ -201,6 +490,7 @@ mod tests {
             greeting: "".to_string(),
             name: "World".to_string(),
             repeat_count: 1,
+            ..Default::default()
         };
         let hello = HelloWorld::new(config);
         assert!(hello.validate_config().is_err());
@@ -212,6 +502,7 @@ mod tests {
             greeting: "Hello".to_string(),
             name: "".to_string(),
             repeat_count: 1,
+            ..Default::default()
         };
         let hello = HelloWorld::new(config);
         assert!(hello.validate_config().is_err());
@@ -223,6 +514,7 @@ mod tests {
             greeting: "Hello".to_string(),
             name: "World".to_string(),
             repeat_count: 0,
+            ..Default::default()
         };
         let hello = HelloWorld::new(config);
         assert!(hello.validate_config().is_err());
@@ -256,6 +548,7 @@ mod tests {
             greeting: "Hello".to_string(),
             name: "World".to_string(),
             repeat_count: 1,
+            ..Default::default()
         };
         let hello = HelloWorld::new(config);
         let json = hello.config_json().unwrap();
@@ -263,4 +556,256 @@ mod tests {
         assert!(json.contains("World"));
         assert!(json.contains("1"));
     }
+
+    #[test]
+    fn test_default_format_is_backward_compatible() {
+        let config = HelloConfig {
+            greeting: "Hello".to_string(),
+            name: "Test".to_string(),
+            repeat_count: 2,
+            ..Default::default()
+        };
+        let hello = HelloWorld::new(config);
+        assert_eq!(hello.greet(), utils::create_greeting("Hello", "Test"));
+        assert_eq!(
+            hello.greet_many(),
+            utils::create_multiple_greetings("Hello", "Test", 2)
+        );
+    }
+
+    #[test]
+    fn test_config_without_format_deserializes() {
+        let json = r#"{"greeting":"Hi","name":"Rust","repeat_count":1}"#;
+        let config: HelloConfig = serde_json::from_str(json).unwrap();
+        assert_eq!(config.format, DEFAULT_FORMAT);
+    }
+
+    #[test]
+    fn test_custom_format_with_index_and_count() {
+        let config = HelloConfig {
+            greeting: "Hi".to_string(),
+            name: "Rust".to_string(),
+            repeat_count: 2,
+            format: "{index}/{count}: {greeting}, {name}".to_string(),
+            ..Default::default()
+        };
+        let hello = HelloWorld::new(config);
+        assert_eq!(hello.greet(), "1/1: Hi, Rust");
+        assert_eq!(hello.greet_many(), vec!["1/2: Hi, Rust", "2/2: Hi, Rust"]);
+    }
+
+    #[test]
+    fn test_custom_format_with_escaping() {
+        let config = HelloConfig {
+            format: "{{{greeting}}} {name}".to_string(),
+            ..Default::default()
+        };
+        let hello = HelloWorld::new(config);
+        assert_eq!(hello.greet(), "{Hello} World");
+    }
+
+    #[test]
+    fn test_config_validation_unknown_placeholder() {
+        let config = HelloConfig {
+            format: "{greeting} {planet}!".to_string(),
+            ..Default::default()
+        };
+        let hello = HelloWorld::new(config);
+        let err = hello.validate_config().unwrap_err().to_string();
+        assert!(err.contains("{planet}"));
+        assert!(err.contains("byte 11"));
+    }
+
+    #[test]
+    fn test_config_validation_reports_all_errors() {
+        let config = HelloConfig {
+            greeting: "".to_string(),
+            name: "x".repeat(validation::MAX_NAME_LEN + 1),
+            repeat_count: 0,
+            format: "{greeting} {nmae}".to_string(),
+            ..Default::default()
+        };
+        let hello = HelloWorld::new(config);
+        let errors = hello.validate_config().unwrap_err();
+        assert_eq!(
+            errors.fields(),
+            vec!["greeting", "name", "repeat_count", "format"]
+        );
+        assert_eq!(
+            errors.errors()[0],
+            ValidationError::Empty { field: "greeting" }
+        );
+        assert!(matches!(
+            errors.errors()[1],
+            ValidationError::TooLong { field: "name", .. }
+        ));
+        assert!(matches!(
+            errors.errors()[2],
+            ValidationError::OutOfRange {
+                field: "repeat_count",
+                actual: 0,
+                ..
+            }
+        ));
+        assert!(matches!(
+            errors.errors()[3],
+            ValidationError::InvalidFormat {
+                field: "format",
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_config_validation_rejects_control_characters_and_large_counts() {
+        let config = HelloConfig {
+            name: "Wor\nld".to_string(),
+            repeat_count: validation::MAX_REPEAT_COUNT + 1,
+            ..Default::default()
+        };
+        let errors = HelloWorld::new(config).validate_config().unwrap_err();
+        assert_eq!(
+            errors.errors(),
+            &[
+                ValidationError::ControlCharacter {
+                    field: "name",
+                    offset: 3,
+                },
+                ValidationError::OutOfRange {
+                    field: "repeat_count",
+                    min: 1,
+                    max: validation::MAX_REPEAT_COUNT,
+                    actual: validation::MAX_REPEAT_COUNT + 1,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_first_returns_single_error() {
+        let config = HelloConfig {
+            greeting: "".to_string(),
+            name: "".to_string(),
+            ..Default::default()
+        };
+        let err = HelloWorld::new(config).validate_first().unwrap_err();
+        assert_eq!(err.to_string(), "greeting: must not be empty");
+    }
+
+    fn pool_config(pool: &[&str], seed: u64) -> HelloConfig {
+        HelloConfig {
+            greeting_pool: pool.iter().map(|&entry| entry.into()).collect(),
+            random_seed: Some(seed),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_greet_random_is_deterministic_for_seed() {
+        let pool = ["Hello", "Howdy:3", "Hi:2"];
+        let draws = |seed| {
+            let hello = HelloWorld::new(pool_config(&pool, seed));
+            (0..20).map(|_| hello.greet_random()).collect::<Vec<_>>()
+        };
+        assert_eq!(draws(42), draws(42));
+        assert_ne!(draws(42), draws(7));
+        assert!(draws(42)
+            .iter()
+            .all(|g| ["Hello World!", "Howdy World!", "Hi World!"].contains(&g.as_str())));
+    }
+
+    #[test]
+    fn test_greet_random_weight_distribution() {
+        let hello = HelloWorld::new(pool_config(&["Howdy:3", "Hi:1"], 1234));
+        let draws = 10_000;
+        let howdy = (0..draws)
+            .filter(|_| hello.greet_random() == "Howdy World!")
+            .count();
+        let ratio = howdy as f64 / draws as f64;
+        assert!((ratio - 0.75).abs() < 0.03, "howdy ratio was {}", ratio);
+    }
+
+    #[test]
+    fn test_greet_random_with_injected_rng() {
+        let hello = HelloWorld::new(pool_config(&["Howdy", "Hi"], 0));
+        let mut a = StdRng::seed_from_u64(99);
+        let mut b = StdRng::seed_from_u64(99);
+        assert_eq!(
+            hello.greet_random_with(&mut a),
+            hello.greet_random_with(&mut b)
+        );
+    }
+
+    #[test]
+    fn test_greet_random_empty_pool_falls_back() {
+        let hello = HelloWorld::new(pool_config(&[], 3));
+        assert_eq!(hello.greet_random(), "Hello World!");
+    }
+
+    #[test]
+    fn test_config_validation_rejects_zero_weight() {
+        let config = HelloConfig {
+            greeting_pool: vec![
+                "Howdy:2".into(),
+                PoolEntry::Weighted {
+                    greeting: "Hi".to_string(),
+                    weight: 0,
+                },
+            ],
+            ..Default::default()
+        };
+        let errors = HelloWorld::new(config).validate_config().unwrap_err();
+        assert_eq!(
+            errors.errors(),
+            &[ValidationError::ZeroWeight {
+                field: "greeting_pool",
+                index: 1,
+            }]
+        );
+        assert_eq!(
+            errors.errors()[0].to_string(),
+            "greeting_pool[1]: weight must be greater than 0"
+        );
+    }
+
+    #[test]
+    fn test_greet_iter_matches_greet_many() {
+        for format in [DEFAULT_FORMAT, "{index}/{count} {greeting} {name}"] {
+            let config = HelloConfig {
+                repeat_count: 12,
+                format: format.to_string(),
+                ..Default::default()
+            };
+            let hello = HelloWorld::new(config);
+            let iter = hello.greet_iter();
+            assert_eq!(iter.len(), 12);
+            assert_eq!(iter.collect::<Vec<_>>(), hello.greet_many());
+        }
+    }
+
+    #[test]
+    fn test_greet_many_output_unchanged() {
+        let config = HelloConfig {
+            repeat_count: 3,
+            ..Default::default()
+        };
+        let greetings = HelloWorld::new(config).greet_many();
+        assert_eq!(
+            greetings,
+            utils::create_multiple_greetings("Hello", "World", 3)
+        );
+    }
+
+    #[test]
+    fn test_write_greetings_streams_lines() {
+        let config = HelloConfig {
+            repeat_count: 3,
+            ..Default::default()
+        };
+        let hello = HelloWorld::new(config);
+        let mut out = Vec::new();
+        hello.write_greetings(&mut out).unwrap();
+        let expected: String = hello.greet_many().into_iter().map(|g| g + "\n").collect();
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
 }
@@ -0,0 +1,254 @@
+//! Marketplace package manifest
+//!
+//! [`PackageManifest`] is the machine-readable description of a marketplace
+//! package, stored as `ggen-package.toml` next to its `Cargo.toml`.
+//! [`validate_manifest`] has no hello-world specifics so other packages can
+//! check their own manifests with it.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+use std::path::Path;
+
+/// File name of a package manifest
+pub const MANIFEST_FILE: &str = "ggen-package.toml";
+
+/// Machine-readable package metadata
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PackageManifest {
+    pub name: String,
+    pub version: String,
+    pub description: String,
+    pub capabilities: Vec<String>,
+    #[serde(default, rename = "config")]
+    pub config_keys: Vec<ConfigKey>,
+    #[serde(default, rename = "example")]
+    pub examples: Vec<ExampleInvocation>,
+}
+
+/// Type of a config value
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfigType {
+    String,
+    Integer,
+    Boolean,
+    Array,
+    Table,
+}
+
+/// A config key the package understands
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConfigKey {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub ty: ConfigType,
+    #[serde(default)]
+    pub required: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<serde_json::Value>,
+    pub description: String,
+}
+
+/// An example command line
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExampleInvocation {
+    pub description: String,
+    pub command: String,
+}
+
+impl PackageManifest {
+    /// Parse a manifest from TOML
+    pub fn from_toml(contents: &str) -> Result<Self> {
+        toml::from_str(contents).context("Failed to parse package manifest")
+    }
+
+    /// Serialize the manifest to TOML
+    pub fn to_toml(&self) -> Result<String> {
+        toml::to_string_pretty(self).context("Failed to serialize package manifest")
+    }
+
+    /// Load a manifest file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read manifest '{}'", path.display()))?;
+        Self::from_toml(&contents).with_context(|| format!("In manifest '{}'", path.display()))
+    }
+
+    /// Write the manifest to a file
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        std::fs::write(path, self.to_toml()?)
+            .with_context(|| format!("Failed to write manifest '{}'", path.display()))
+    }
+}
+
+/// A problem found in a manifest
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ManifestError {
+    /// `name` is empty
+    EmptyName,
+    /// `version` is not `MAJOR.MINOR.PATCH` semver
+    InvalidVersion(String),
+    /// `capabilities` is empty
+    NoCapabilities,
+    /// Config key name is not lowercase snake_case
+    InvalidKeyName(String),
+    /// Config key declared more than once
+    DuplicateKey(String),
+    /// Required key also declares a default
+    RequiredWithDefault(String),
+}
+
+impl fmt::Display for ManifestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ManifestError::EmptyName => write!(f, "name: must not be empty"),
+            ManifestError::InvalidVersion(version) => {
+                write!(f, "version: '{}' is not a semver version", version)
+            }
+            ManifestError::NoCapabilities => {
+                write!(f, "capabilities: at least one capability is required")
+            }
+            ManifestError::InvalidKeyName(name) => {
+                write!(f, "config.{}: key names must be lowercase snake_case", name)
+            }
+            ManifestError::DuplicateKey(name) => write!(f, "config.{}: declared twice", name),
+            ManifestError::RequiredWithDefault(name) => {
+                write!(f, "config.{}: required keys cannot have a default", name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ManifestError {}
+
+/// Check a manifest, returning every problem found
+pub fn validate_manifest(manifest: &PackageManifest) -> Vec<ManifestError> {
+    let mut errors = Vec::new();
+    if manifest.name.trim().is_empty() {
+        errors.push(ManifestError::EmptyName);
+    }
+    if !is_semver(&manifest.version) {
+        errors.push(ManifestError::InvalidVersion(manifest.version.clone()));
+    }
+    if manifest.capabilities.iter().all(|c| c.trim().is_empty()) {
+        errors.push(ManifestError::NoCapabilities);
+    }
+
+    let mut seen = HashSet::new();
+    for key in &manifest.config_keys {
+        if !is_key_name(&key.name) {
+            errors.push(ManifestError::InvalidKeyName(key.name.clone()));
+        }
+        if !seen.insert(key.name.as_str()) {
+            errors.push(ManifestError::DuplicateKey(key.name.clone()));
+        }
+        if key.required && key.default.is_some() {
+            errors.push(ManifestError::RequiredWithDefault(key.name.clone()));
+        }
+    }
+    errors
+}
+
+/// `MAJOR.MINOR.PATCH` with optional `-prerelease` and `+build` suffixes
+fn is_semver(version: &str) -> bool {
+    let version = version.split_once('+').map_or(version, |(v, _)| v);
+    let core = version.split_once('-').map_or(version, |(v, _)| v);
+    let parts: Vec<&str> = core.split('.').collect();
+    parts.len() == 3
+        && parts.iter().all(|part| {
+            !part.is_empty()
+                && part.bytes().all(|b| b.is_ascii_digit())
+                && (part.len() == 1 || !part.starts_with('0'))
+        })
+}
+
+/// Lowercase snake_case starting with a letter
+fn is_key_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_lowercase())
+        && chars.all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{HelloConfig, HelloWorld};
+
+    #[test]
+    fn test_checked_in_manifest_matches_code() {
+        let on_disk = PackageManifest::from_toml(include_str!("../ggen-package.toml")).unwrap();
+        assert_eq!(on_disk, HelloWorld::manifest());
+    }
+
+    #[test]
+    fn test_manifest_round_trips_through_file() {
+        let path =
+            std::env::temp_dir().join(format!("hello-world-manifest-{}.toml", std::process::id()));
+        let manifest = HelloWorld::manifest();
+        manifest.save(&path).unwrap();
+        let loaded = PackageManifest::load(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(loaded, manifest);
+    }
+
+    #[test]
+    fn test_hello_world_manifest_is_valid() {
+        assert_eq!(validate_manifest(&HelloWorld::manifest()), vec![]);
+    }
+
+    #[test]
+    fn test_broken_manifest_reports_every_problem() {
+        let broken =
+            PackageManifest::from_toml(include_str!("../tests/fixtures/broken-manifest.toml"))
+                .unwrap();
+        assert_eq!(
+            validate_manifest(&broken),
+            vec![
+                ManifestError::InvalidVersion("1.0".to_string()),
+                ManifestError::NoCapabilities,
+                ManifestError::InvalidKeyName("RepeatCount".to_string()),
+                ManifestError::DuplicateKey("name".to_string()),
+                ManifestError::RequiredWithDefault("name".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_declared_keys_match_hello_config_fields() {
+        let config = serde_json::to_value(HelloConfig::default()).unwrap();
+        let mut fields: Vec<&str> = config
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect();
+        let manifest = HelloWorld::manifest();
+        let mut declared: Vec<&str> = manifest
+            .config_keys
+            .iter()
+            .map(|k| k.name.as_str())
+            .collect();
+        fields.sort_unstable();
+        declared.sort_unstable();
+        assert_eq!(declared, fields);
+
+        for key in &manifest.config_keys {
+            if let Some(default) = &key.default {
+                assert_eq!(&config[&key.name], default, "default of {}", key.name);
+            }
+        }
+    }
+
+    #[test]
+    fn test_semver_rules() {
+        assert!(is_semver("0.1.0"));
+        assert!(is_semver("1.2.3-beta.1+build5"));
+        assert!(!is_semver("1.2"));
+        assert!(!is_semver("01.2.3"));
+        assert!(!is_semver("1.x.3"));
+    }
+}
@@ -0,0 +1,80 @@
+//! Weighted greeting pools
+//!
+//! A pool entry is either a plain string, optionally carrying a weight
+//! suffix (`"Howdy:3"`), or the structured form
+//! `{ "greeting": "Howdy", "weight": 3 }`. Entries without a weight count
+//! once.
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// One greeting in a [`crate::HelloConfig::greeting_pool`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum PoolEntry {
+    Weighted { greeting: String, weight: u32 },
+    Plain(String),
+}
+
+impl PoolEntry {
+    /// The greeting text and its weight
+    pub fn parts(&self) -> (&str, u32) {
+        match self {
+            PoolEntry::Weighted { greeting, weight } => (greeting, *weight),
+            PoolEntry::Plain(text) => match text.rsplit_once(':') {
+                Some((greeting, weight)) if !weight.is_empty() => match weight.parse() {
+                    Ok(weight) => (greeting, weight),
+                    Err(_) => (text, 1),
+                },
+                _ => (text, 1),
+            },
+        }
+    }
+}
+
+impl From<&str> for PoolEntry {
+    fn from(text: &str) -> Self {
+        PoolEntry::Plain(text.to_string())
+    }
+}
+
+/// Pick a greeting from `pool` proportionally to the entry weights
+///
+/// Returns `None` for an empty pool or when every weight is zero.
+pub fn choose<'a, R: Rng + ?Sized>(pool: &'a [PoolEntry], rng: &mut R) -> Option<&'a str> {
+    let total: u64 = pool.iter().map(|entry| u64::from(entry.parts().1)).sum();
+    if total == 0 {
+        return None;
+    }
+    let mut ticket = rng.gen_range(0..total);
+    for entry in pool {
+        let (greeting, weight) = entry.parts();
+        let weight = u64::from(weight);
+        if ticket < weight {
+            return Some(greeting);
+        }
+        ticket -= weight;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_weight_suffix_parsing() {
+        assert_eq!(PoolEntry::from("Howdy:3").parts(), ("Howdy", 3));
+        assert_eq!(PoolEntry::from("Howdy").parts(), ("Howdy", 1));
+        assert_eq!(PoolEntry::from("Hi: there").parts(), ("Hi: there", 1));
+        assert_eq!(PoolEntry::from("Nope:0").parts(), ("Nope", 0));
+    }
+
+    #[test]
+    fn test_structured_and_plain_entries_deserialize() {
+        let pool: Vec<PoolEntry> =
+            serde_json::from_str(r#"["Hi:2", {"greeting": "Howdy", "weight": 5}]"#).unwrap();
+        assert_eq!(pool[0].parts(), ("Hi", 2));
+        assert_eq!(pool[1].parts(), ("Howdy", 5));
+    }
+}
@@ -0,0 +1,213 @@
+//! Configuration validation
+//!
+//! Validation collects every violation instead of stopping at the first one,
+//! so a config can be fixed in a single pass.
+
+use crate::format::FormatError;
+use crate::pool::PoolEntry;
+use std::fmt;
+
+/// Maximum greeting length in characters
+pub const MAX_GREETING_LEN: usize = 64;
+/// Maximum name length in characters
+pub const MAX_NAME_LEN: usize = 128;
+/// Maximum number of greetings generated by `greet_many`
+pub const MAX_REPEAT_COUNT: usize = 1_000_000;
+
+/// A single violated constraint, attributed to a config field
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationError {
+    /// Field is empty or whitespace only
+    Empty { field: &'static str },
+    /// Field exceeds its length limit (in characters)
+    TooLong {
+        field: &'static str,
+        max: usize,
+        actual: usize,
+    },
+    /// Field contains a control character at the given byte offset
+    ControlCharacter { field: &'static str, offset: usize },
+    /// Numeric field outside its allowed range
+    OutOfRange {
+        field: &'static str,
+        min: usize,
+        max: usize,
+        actual: usize,
+    },
+    /// Pool entry at the given index has a weight of zero
+    ZeroWeight { field: &'static str, index: usize },
+    /// Format string failed to parse
+    InvalidFormat {
+        field: &'static str,
+        error: FormatError,
+    },
+}
+
+impl ValidationError {
+    /// Path of the offending field
+    pub fn field(&self) -> &'static str {
+        match self {
+            ValidationError::Empty { field }
+            | ValidationError::TooLong { field, .. }
+            | ValidationError::ControlCharacter { field, .. }
+            | ValidationError::OutOfRange { field, .. }
+            | ValidationError::ZeroWeight { field, .. }
+            | ValidationError::InvalidFormat { field, .. } => field,
+        }
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationError::Empty { field } => write!(f, "{}: must not be empty", field),
+            ValidationError::TooLong { field, max, actual } => write!(
+                f,
+                "{}: must be at most {} characters (got {})",
+                field, max, actual
+            ),
+            ValidationError::ControlCharacter { field, offset } => write!(
+                f,
+                "{}: control character at byte {} is not allowed",
+                field, offset
+            ),
+            ValidationError::OutOfRange {
+                field,
+                min,
+                max,
+                actual,
+            } => write!(
+                f,
+                "{}: must be between {} and {} (got {})",
+                field, min, max, actual
+            ),
+            ValidationError::ZeroWeight { field, index } => {
+                write!(f, "{}[{}]: weight must be greater than 0", field, index)
+            }
+            ValidationError::InvalidFormat { field, error } => write!(f, "{}: {}", field, error),
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+/// All violations found while validating a config
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationErrors(pub Vec<ValidationError>);
+
+impl ValidationErrors {
+    /// Individual violations in check order
+    pub fn errors(&self) -> &[ValidationError] {
+        &self.0
+    }
+
+    /// Fields with at least one violation, in check order
+    pub fn fields(&self) -> Vec<&'static str> {
+        self.0.iter().map(ValidationError::field).collect()
+    }
+
+    /// Convert collected errors into a result
+    pub fn into_result(self) -> Result<(), ValidationErrors> {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            Err(self)
+        }
+    }
+}
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} validation error(s)", self.0.len())?;
+        for error in &self.0 {
+            write!(f, "\n  - {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ValidationErrors {}
+
+/// Check a text field for emptiness, length and control characters
+pub fn check_text(
+    errors: &mut Vec<ValidationError>, field: &'static str, value: &str, max_len: usize,
+) {
+    if value.trim().is_empty() {
+        errors.push(ValidationError::Empty { field });
+        return;
+    }
+    let len = value.chars().count();
+    if len > max_len {
+        errors.push(ValidationError::TooLong {
+            field,
+            max: max_len,
+            actual: len,
+        });
+    }
+    if let Some((offset, _)) = value.char_indices().find(|(_, c)| c.is_control()) {
+        errors.push(ValidationError::ControlCharacter { field, offset });
+    }
+}
+
+/// Check a count field against `1..=MAX_REPEAT_COUNT`
+pub fn check_count(errors: &mut Vec<ValidationError>, field: &'static str, value: usize) {
+    if value == 0 || value > MAX_REPEAT_COUNT {
+        errors.push(ValidationError::OutOfRange {
+            field,
+            min: 1,
+            max: MAX_REPEAT_COUNT,
+            actual: value,
+        });
+    }
+}
+
+/// Check that every pool entry has a non-zero weight
+pub fn check_pool(errors: &mut Vec<ValidationError>, field: &'static str, pool: &[PoolEntry]) {
+    for (index, entry) in pool.iter().enumerate() {
+        if entry.parts().1 == 0 {
+            errors.push(ValidationError::ZeroWeight { field, index });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_text_reports_length_and_control_character() {
+        let mut errors = Vec::new();
+        check_text(&mut errors, "name", "ab\u{7}cdef", 4);
+        assert_eq!(
+            errors,
+            vec![
+                ValidationError::TooLong {
+                    field: "name",
+                    max: 4,
+                    actual: 7,
+                },
+                ValidationError::ControlCharacter {
+                    field: "name",
+                    offset: 2,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_display_lists_every_error() {
+        let errors = ValidationErrors(vec![
+            ValidationError::Empty { field: "greeting" },
+            ValidationError::OutOfRange {
+                field: "repeat_count",
+                min: 1,
+                max: MAX_REPEAT_COUNT,
+                actual: 0,
+            },
+        ]);
+        let text = errors.to_string();
+        assert!(text.starts_with("2 validation error(s)"));
+        assert!(text.contains("greeting: must not be empty"));
+        assert!(text.contains("repeat_count: must be between 1 and 1000000 (got 0)"));
+    }
+}
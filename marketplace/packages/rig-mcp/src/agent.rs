@@ -0,0 +1,1471 @@
+//! Tool-using agents
+//!
+//! An [`Agent`] sends the conversation to its provider, invokes whatever MCP
+//! tools the model asks for, feeds the results back and repeats until the
+//! model answers without calling a tool. A [`RunBudget`] bounds how much a
+//! single run may consume.
+//!
+//! Completions are streamed. Their tokens are what the provider reports at
+//! the end of the stream; while it streams, or if it reports nothing, they are
+//! estimated with a [`TokenCounter`]. A completion whose estimate would take
+//! the run over its token budget is cut off mid-stream.
+//!
+//! Progress reported by long-running tools reaches hooks as
+//! [`RunEvent::ToolProgress`]. With a tool timeout, a call is abandoned once
+//! it has run that long, or, with `progress_resets_timeout`, once it has gone
+//! that long without reporting progress.
+//!
+//! Models that don't call tools natively get them described in the system
+//! prompt instead; see [`crate::interop`]. With
+//! [`AgentBuilder::compact_tools`], providers that don't cache prompts get
+//! short tool descriptions; see [`crate::preamble`].
+//!
+//! [`Agent::run`] returns the answer as a [`NormalizedResponse`] annotated by
+//! the agent's [`PostProcessor`]s, for example with the code blocks it
+//! contains and the tool results it cites.
+//!
+//! Agents built with [`AgentBuilder::ask_user`] may stop to ask a question
+//! instead; see [`crate::clarify`].
+//!
+//! With a [`ToolPolicy`], destructive tool calls need approval before they
+//! run; see [`crate::policy`]. Arguments are checked against the tool's
+//! input schema, and coerced to fit it where that is safe, before any tool
+//! runs; see [`crate::arguments`].
+//!
+//! With [`AgentBuilder::transcripts`], every run is recorded for replay as a
+//! regression fixture; see [`crate::transcript`].
+
+use anyhow::{Context, Result};
+use futures::StreamExt;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::Instant;
+
+use crate::arguments::{self, ArgumentCheck, ArgumentMode};
+use crate::audit::AuditLog;
+use crate::budget::{BudgetMeter, RunBudget};
+use crate::clarify::{self, Question, Step};
+use crate::hooks::{RunEvent, RunHook};
+use crate::injection::{InjectionAction, InjectionScreen, Screened};
+use crate::interop::{self, Parsed, ToolCalling, ToolSupport};
+use crate::mcp::{ToolInfo, ToolOutput, ToolProgress, ToolServer};
+use crate::policy::{self, ConfirmationHandler, ToolDecision, ToolPolicy};
+use crate::postprocess::{self, PostProcessor, Source};
+use crate::preamble::{Preamble, DESCRIBE_TOOL};
+use crate::provider::{
+    ChatMessage, ChatRequest, FinishReason, NormalizedResponse, NormalizedUsage, Provider,
+    ReasoningConfig, StreamChunk, ToolCall, ToolDefinition,
+};
+use crate::tokens::TokenCounter;
+use crate::transcript::{Capture, RunSettings, TranscriptRecorder};
+
+/// Completion rounds allowed before a run is abandoned
+pub const DEFAULT_MAX_TURNS: usize = 8;
+
+/// Builder for [`Agent`]
+pub struct AgentBuilder {
+    provider: Arc<dyn Provider>,
+    servers: Vec<Arc<dyn ToolServer>>,
+    preamble: Option<String>,
+    max_tokens: Option<usize>,
+    temperature: Option<f32>,
+    reasoning: Option<ReasoningConfig>,
+    max_turns: usize,
+    budget: RunBudget,
+    tool_timeout: Option<Duration>,
+    progress_resets_timeout: bool,
+    hooks: Vec<Arc<dyn RunHook>>,
+    post_processors: Vec<Arc<dyn PostProcessor>>,
+    token_counter: TokenCounter,
+    tool_support: Arc<ToolSupport>,
+    ask_user: bool,
+    tool_arguments: ArgumentMode,
+    tool_policy: Option<ToolPolicy>,
+    confirmation: Option<Arc<dyn ConfirmationHandler>>,
+    audit: Option<Arc<AuditLog>>,
+    injection: Option<Arc<InjectionScreen>>,
+    compact_tools: bool,
+    transcripts: Option<Arc<TranscriptRecorder>>,
+}
+
+impl AgentBuilder {
+    pub fn new(provider: Arc<dyn Provider>) -> Self {
+        Self {
+            provider,
+            servers: Vec::new(),
+            preamble: None,
+            max_tokens: None,
+            temperature: None,
+            reasoning: None,
+            max_turns: DEFAULT_MAX_TURNS,
+            budget: RunBudget::default(),
+            tool_timeout: None,
+            progress_resets_timeout: false,
+            hooks: Vec::new(),
+            post_processors: Vec::new(),
+            token_counter: TokenCounter::default(),
+            tool_support: Arc::new(ToolSupport::new()),
+            ask_user: false,
+            tool_arguments: ArgumentMode::default(),
+            tool_policy: None,
+            confirmation: None,
+            audit: None,
+            injection: None,
+            compact_tools: false,
+            transcripts: None,
+        }
+    }
+
+    /// System prompt sent with every request
+    pub fn preamble(mut self, preamble: impl Into<String>) -> Self {
+        self.preamble = Some(preamble.into());
+        self
+    }
+
+    pub fn max_tokens(mut self, max_tokens: usize) -> Self {
+        self.max_tokens = Some(max_tokens);
+        self
+    }
+
+    pub fn temperature(mut self, temperature: f32) -> Self {
+        self.temperature = Some(temperature);
+        self
+    }
+
+    /// Let the model think before each answer; ignored, with a warning, by
+    /// models that don't reason
+    pub fn reasoning(mut self, reasoning: ReasoningConfig) -> Self {
+        self.reasoning = Some(reasoning);
+        self
+    }
+
+    /// Maximum completion rounds per prompt
+    pub fn max_turns(mut self, max_turns: usize) -> Self {
+        self.max_turns = max_turns;
+        self
+    }
+
+    /// Limits for each run; unlimited by default
+    pub fn budget(mut self, budget: RunBudget) -> Self {
+        self.budget = budget;
+        self
+    }
+
+    /// Abandon tool calls running longer than `timeout`; the model is told
+    /// the call timed out
+    pub fn tool_timeout(mut self, timeout: Duration) -> Self {
+        self.tool_timeout = Some(timeout);
+        self
+    }
+
+    /// Measure the tool timeout from the latest progress notification
+    /// instead of the start of the call
+    pub fn progress_resets_timeout(mut self, resets: bool) -> Self {
+        self.progress_resets_timeout = resets;
+        self
+    }
+
+    /// Make the tools of an MCP server available to the model
+    pub fn tool_server(mut self, server: Arc<dyn ToolServer>) -> Self {
+        self.servers.push(server);
+        self
+    }
+
+    /// Observe runs of the built agent
+    pub fn hook(mut self, hook: Arc<dyn RunHook>) -> Self {
+        self.hooks.push(hook);
+        self
+    }
+
+    /// Annotate the final answer of every run; processors run in the order
+    /// they are added
+    pub fn post_processor(mut self, processor: Arc<dyn PostProcessor>) -> Self {
+        self.post_processors.push(processor);
+        self
+    }
+
+    /// Estimate the tokens of completions whose usage isn't reported yet
+    pub fn token_counter(mut self, counter: TokenCounter) -> Self {
+        self.token_counter = counter;
+        self
+    }
+
+    /// Share how provider/model pairs call tools with other agents; each
+    /// agent probes on its own by default
+    pub fn tool_support(mut self, support: Arc<ToolSupport>) -> Self {
+        self.tool_support = support;
+        self
+    }
+
+    /// Offer the model the `ask_user` tool, pausing runs started with
+    /// [`Agent::run_or_ask`] when it needs information only the user has
+    pub fn ask_user(mut self, ask_user: bool) -> Self {
+        self.ask_user = ask_user;
+        self
+    }
+
+    /// How tool call arguments are checked; lenient by default
+    pub fn tool_arguments(mut self, mode: ArgumentMode) -> Self {
+        self.tool_arguments = mode;
+        self
+    }
+
+    /// Run destructive tools only as `policy` allows; every tool runs
+    /// without one
+    pub fn tool_policy(mut self, policy: ToolPolicy) -> Self {
+        self.tool_policy = Some(policy);
+        self
+    }
+
+    /// Ask `handler` before destructive calls the tool policy doesn't
+    /// auto-approve
+    pub fn confirmation_handler(mut self, handler: Arc<dyn ConfirmationHandler>) -> Self {
+        self.confirmation = Some(handler);
+        self
+    }
+
+    /// Record the tool policy's decisions and the coerced or rejected tool
+    /// arguments in `log`
+    pub fn audit_log(mut self, log: Arc<AuditLog>) -> Self {
+        self.audit = Some(log);
+        self
+    }
+
+    /// Screen tool results for prompt injection before the model sees them
+    pub fn injection_screen(mut self, screen: Arc<InjectionScreen>) -> Self {
+        self.injection = Some(screen);
+        self
+    }
+
+    /// Send providers that don't cache prompts short tool descriptions, with
+    /// full definitions on request through `describe_tool`
+    pub fn compact_tools(mut self, compact: bool) -> Self {
+        self.compact_tools = compact;
+        self
+    }
+
+    /// Record every run into `recorder`, to be saved and replayed as a
+    /// regression fixture
+    pub fn transcripts(mut self, recorder: Arc<TranscriptRecorder>) -> Self {
+        self.transcripts = Some(recorder);
+        self
+    }
+
+    pub fn build(self) -> Agent {
+        Agent {
+            provider: self.provider,
+            servers: self.servers,
+            preamble: self.preamble,
+            max_tokens: self.max_tokens,
+            temperature: self.temperature,
+            reasoning: self.reasoning,
+            max_turns: self.max_turns,
+            budget: self.budget,
+            tool_timeout: self.tool_timeout,
+            progress_resets_timeout: self.progress_resets_timeout,
+            hooks: self.hooks,
+            post_processors: self.post_processors,
+            token_counter: self.token_counter,
+            tool_support: self.tool_support,
+            ask_user: self.ask_user,
+            tool_arguments: self.tool_arguments,
+            tool_policy: self.tool_policy,
+            confirmation: self.confirmation,
+            audit: self.audit,
+            injection: self.injection,
+            compact_tools: self.compact_tools,
+            transcripts: self.transcripts,
+        }
+    }
+}
+
+/// A provider plus the MCP tools it may call
+pub struct Agent {
+    provider: Arc<dyn Provider>,
+    servers: Vec<Arc<dyn ToolServer>>,
+    preamble: Option<String>,
+    max_tokens: Option<usize>,
+    temperature: Option<f32>,
+    reasoning: Option<ReasoningConfig>,
+    max_turns: usize,
+    budget: RunBudget,
+    tool_timeout: Option<Duration>,
+    progress_resets_timeout: bool,
+    hooks: Vec<Arc<dyn RunHook>>,
+    post_processors: Vec<Arc<dyn PostProcessor>>,
+    token_counter: TokenCounter,
+    tool_support: Arc<ToolSupport>,
+    ask_user: bool,
+    tool_arguments: ArgumentMode,
+    tool_policy: Option<ToolPolicy>,
+    confirmation: Option<Arc<dyn ConfirmationHandler>>,
+    audit: Option<Arc<AuditLog>>,
+    injection: Option<Arc<InjectionScreen>>,
+    compact_tools: bool,
+    transcripts: Option<Arc<TranscriptRecorder>>,
+}
+
+/// How one streamed completion ended
+enum Turn {
+    Reply(ChatMessage, NormalizedUsage),
+    /// Cut off because its estimated tokens would exceed the budget
+    OverBudget(NormalizedUsage),
+}
+
+impl Agent {
+    pub fn provider(&self) -> &Arc<dyn Provider> {
+        &self.provider
+    }
+
+    /// Tools of every attached server
+    pub async fn tools(&self) -> Result<Vec<ToolInfo>> {
+        let mut tools = Vec::new();
+        for server in &self.servers {
+            tools.extend(server.list_tools().await?);
+        }
+        Ok(tools)
+    }
+
+    /// Answer a single prompt without prior history
+    pub async fn prompt(&self, prompt: &str) -> Result<String> {
+        self.chat(&mut Vec::new(), prompt).await
+    }
+
+    /// Continue a conversation
+    ///
+    /// The prompt, every assistant turn and every tool result are appended to
+    /// `history`, so the same vector can be passed to the next call. When the
+    /// agent's budget runs out the error is a [`crate::BudgetExceeded`].
+    pub async fn chat(&self, history: &mut Vec<ChatMessage>, prompt: &str) -> Result<String> {
+        self.chat_with_budget(history, prompt, self.budget).await
+    }
+
+    /// [`Agent::chat`] with a budget overriding the agent's own for this call
+    pub async fn chat_with_budget(
+        &self, history: &mut Vec<ChatMessage>, prompt: &str, budget: RunBudget,
+    ) -> Result<String> {
+        let response = self
+            .answered(history, Some(prompt), budget, Vec::new())
+            .await?;
+        Ok(response.content)
+    }
+
+    /// [`Agent::chat`], returning the answer with the usage of the whole run
+    /// and the annotations of the agent's post-processors
+    ///
+    /// Tool results of the run are the sources `[n]` citations refer to,
+    /// numbered from 1 in the order the tools were called.
+    pub async fn run(
+        &self, history: &mut Vec<ChatMessage>, prompt: &str,
+    ) -> Result<NormalizedResponse> {
+        self.answered(history, Some(prompt), self.budget, Vec::new())
+            .await
+    }
+
+    /// [`Agent::run`] with sources already in the prompt, such as retrieved
+    /// chunks; they are numbered before the tool results of the run
+    pub async fn run_with_sources(
+        &self, history: &mut Vec<ChatMessage>, prompt: &str, sources: Vec<Source>,
+    ) -> Result<NormalizedResponse> {
+        self.answered(history, Some(prompt), self.budget, sources)
+            .await
+    }
+
+    /// [`Agent::run`], stopping at the first question the model asks through
+    /// `ask_user`
+    ///
+    /// The `ask_user` call is left unanswered in `history`; continue with
+    /// [`Agent::answer`].
+    pub async fn run_or_ask(&self, history: &mut Vec<ChatMessage>, prompt: &str) -> Result<Step> {
+        self.run_inner(history, Some(prompt), self.budget, Vec::new())
+            .await
+    }
+
+    /// Continue a run that stopped at `question`, with `answer` as the result
+    /// of its `ask_user` call
+    pub async fn answer(
+        &self, history: &mut Vec<ChatMessage>, question: &Question, answer: &serde_json::Value,
+    ) -> Result<Step> {
+        history.push(question.answer(answer));
+        self.run_inner(history, None, self.budget, Vec::new()).await
+    }
+
+    async fn answered(
+        &self, history: &mut Vec<ChatMessage>, prompt: Option<&str>, budget: RunBudget,
+        sources: Vec<Source>,
+    ) -> Result<NormalizedResponse> {
+        match self.run_inner(history, prompt, budget, sources).await? {
+            Step::Answered(response) => Ok(response),
+            Step::Asked(question) => Err(anyhow::anyhow!(
+                "Agent asked for input ({}); use Agent::run_or_ask to answer it",
+                question.question
+            )),
+        }
+    }
+
+    /// [`Agent::run_or_ask`], or a continuation without `prompt`, within the
+    /// agent's budget; replays go through here
+    pub(crate) async fn replay_run(
+        &self, history: &mut Vec<ChatMessage>, prompt: Option<&str>,
+    ) -> Result<Step> {
+        self.run_inner(history, prompt, self.budget, Vec::new())
+            .await
+    }
+
+    /// Settings a transcript needs to replay the agent's runs
+    fn run_settings(&self) -> RunSettings {
+        RunSettings {
+            preamble: self.preamble.clone(),
+            max_tokens: self.max_tokens,
+            temperature: self.temperature,
+            reasoning: self.reasoning,
+            max_turns: self.max_turns,
+            ask_user: self.ask_user,
+            compact_tools: self.compact_tools,
+        }
+    }
+
+    async fn run_inner(
+        &self, history: &mut Vec<ChatMessage>, prompt: Option<&str>, budget: RunBudget,
+        sources: Vec<Source>,
+    ) -> Result<Step> {
+        let Some(recorder) = &self.transcripts else {
+            return self.run_turns(history, prompt, budget, sources, None).await;
+        };
+        let capture = Capture::start(
+            self.provider.as_ref(),
+            self.run_settings(),
+            self.tool_support.get(self.provider.as_ref()),
+            history,
+            prompt,
+        );
+        let step = self
+            .run_turns(history, prompt, budget, sources, Some(&capture))
+            .await;
+        recorder.push(capture.finish(&step));
+        step
+    }
+
+    async fn run_turns(
+        &self, history: &mut Vec<ChatMessage>, prompt: Option<&str>, budget: RunBudget,
+        mut sources: Vec<Source>, capture: Option<&Capture>,
+    ) -> Result<Step> {
+        let run_start = history.len();
+        if let Some(prompt) = prompt {
+            history.push(ChatMessage::user(prompt));
+        }
+        let tools = self.tools().await?;
+        if let Some(capture) = capture {
+            capture.tools(&tools);
+        }
+        let preamble = Preamble::new(
+            self.preamble.clone(),
+            tools.iter().map(ToolInfo::definition).collect(),
+        );
+        let compact = self.compact_tools && !tools.is_empty();
+        let saved = if compact {
+            tracing::debug!(fingerprint = %preamble.fingerprint(), "compacting tools");
+            let full = self.token_counter.count_tools(preamble.tools());
+            full.saturating_sub(self.token_counter.count_tools(&preamble.compact_tools()))
+        } else {
+            0
+        };
+        let mut meter = BudgetMeter::start(budget);
+        let mut nudged = false;
+
+        for _ in 0..self.max_turns {
+            if let Some(limit) = meter.before_completion() {
+                return Err(meter.exceeded(limit, &history[run_start..]).into());
+            }
+            let calling = self.tool_support.get(self.provider.as_ref());
+            let compacted = compact && !self.tool_support.prompt_caching(self.provider.as_ref());
+            let mut definitions = if compacted {
+                preamble.compact_tools()
+            } else {
+                preamble.tools().to_vec()
+            };
+            if self.ask_user {
+                definitions.push(clarify::ask_user_definition());
+            }
+            let mut request = ChatRequest {
+                system: self.preamble.clone(),
+                messages: history.clone(),
+                tools: definitions.clone(),
+                max_tokens: self.max_tokens,
+                temperature: self.temperature,
+                seed: None,
+                reasoning: self.reasoning,
+            };
+            if calling == ToolCalling::Prompted && !definitions.is_empty() {
+                request = interop::prompted_request(request);
+            }
+            // A completion still streaming when the wall time is up is abandoned
+            let tokens_left = meter.tokens_left();
+            let turn = match meter.remaining_time() {
+                Some(remaining) => {
+                    let turn = self.turn(request, tokens_left, capture);
+                    match tokio::time::timeout(remaining, turn).await {
+                        Ok(turn) => turn?,
+                        Err(_) => {
+                            let limit = meter.out_of_time().expect("wall time is up");
+                            return Err(meter.exceeded(limit, &history[run_start..]).into());
+                        }
+                    }
+                }
+                None => self.turn(request, tokens_left, capture).await?,
+            };
+            let (mut reply, mut usage) = match turn {
+                Turn::Reply(reply, usage) => (reply, usage),
+                Turn::OverBudget(usage) => {
+                    meter.record_completion(&usage);
+                    let limit = meter
+                        .token_limit()
+                        .expect("only a token limit cuts streams");
+                    return Err(meter.exceeded(limit, &history[run_start..]).into());
+                }
+            };
+            self.tool_support
+                .observe_usage(self.provider.as_ref(), &usage);
+            if compacted {
+                usage.saved_tokens = saved;
+            }
+            meter.record_completion(&usage);
+
+            if !definitions.is_empty() {
+                let first_id = history.len();
+                let parsed = self.read_tool_calls(calling, &mut reply, &definitions, first_id);
+                if !parsed && !nudged {
+                    nudged = true;
+                    history.push(reply);
+                    history.push(ChatMessage::user(interop::RETRY_NUDGE));
+                    continue;
+                }
+                nudged = false;
+            }
+
+            let calls = reply.tool_calls.clone();
+            if calls.is_empty() {
+                self.emit(RunEvent::Finished);
+                let annotations =
+                    postprocess::annotate(&self.post_processors, &reply.content, &sources);
+                let response = NormalizedResponse {
+                    content: reply.content.clone(),
+                    usage: meter.tokens(),
+                    finish_reason: FinishReason::Stop,
+                    annotations,
+                    ..NormalizedResponse::default()
+                };
+                history.push(reply);
+                return Ok(Step::Answered(response));
+            }
+            history.push(reply);
+
+            let mut asked = None;
+            for call in calls {
+                if self.ask_user && call.name == clarify::ASK_USER_TOOL {
+                    // Answered on resume; one question at a time
+                    if asked.is_none() {
+                        asked = Some(Question::from_call(&call));
+                    } else {
+                        history.push(ChatMessage::tool(call.id, "Ask one question at a time"));
+                    }
+                    continue;
+                }
+                if compact && call.name == DESCRIBE_TOOL {
+                    let definition = preamble.describe(&call);
+                    history.push(ChatMessage::tool(call.id, definition));
+                    continue;
+                }
+                if let Some(limit) = meter.before_tool_call() {
+                    return Err(meter.exceeded(limit, &history[run_start..]).into());
+                }
+                meter.record_tool_call();
+                self.emit(RunEvent::ToolCall(&call));
+                let output = self.invoke(&tools, &call, capture).await;
+                let output = self.screen_output(&tools, &call, output).await;
+                self.emit(RunEvent::ToolResult {
+                    call: &call,
+                    output: &output,
+                });
+                sources.push(Source {
+                    id: call.id.clone(),
+                    label: call.name.clone(),
+                    content: output.content.clone(),
+                });
+                history.push(ChatMessage::tool(call.id, output.content));
+            }
+            if let Some(question) = asked {
+                return Ok(Step::Asked(question));
+            }
+        }
+
+        Err(anyhow::anyhow!(
+            "Agent gave no final answer within {} turns",
+            self.max_turns
+        ))
+    }
+
+    /// Screen retrieved chunks for prompt injection as `retrieved/<label>`,
+    /// leaving out blocked ones; chunks pass unchanged without a screen
+    pub async fn screen_sources(&self, sources: Vec<Source>) -> Vec<Source> {
+        let Some(screen) = &self.injection else {
+            return sources;
+        };
+        let mut screened = Vec::with_capacity(sources.len());
+        for mut chunk in sources {
+            let source = format!("retrieved/{}", chunk.label);
+            let result = screen.screen(&source, &chunk.content).await;
+            self.on_injection(&source, &result);
+            if result.action != InjectionAction::Block {
+                chunk.content = result.content;
+                screened.push(chunk);
+            }
+        }
+        screened
+    }
+
+    /// Screen the output of a tool call as `server/tool`; a blocked output
+    /// becomes a tool error
+    async fn screen_output(
+        &self, tools: &[ToolInfo], call: &ToolCall, output: ToolOutput,
+    ) -> ToolOutput {
+        let Some(screen) = &self.injection else {
+            return output;
+        };
+        let server = tools
+            .iter()
+            .find(|tool| tool.name == call.name)
+            .map_or("unknown", |tool| tool.server.as_str());
+        let source = format!("{}/{}", server, call.name);
+        let result = screen.screen(&source, &output.content).await;
+        self.on_injection(&source, &result);
+        match result.action {
+            InjectionAction::Block => ToolOutput::error(result.content),
+            _ => ToolOutput {
+                content: result.content,
+                ..output
+            },
+        }
+    }
+
+    fn on_injection(&self, source: &str, screened: &Screened) {
+        if !screened.detections.is_empty() {
+            self.emit(RunEvent::Injection {
+                source,
+                action: screened.action,
+                detections: &screened.detections,
+            });
+        }
+    }
+
+    /// Stream one completion into an assistant message, stopping once its
+    /// estimated tokens exceed `tokens_left`
+    async fn turn(
+        &self, request: ChatRequest, tokens_left: Option<u64>, capture: Option<&Capture>,
+    ) -> Result<Turn> {
+        let prompt_tokens = self.token_counter.count_request(&request);
+        let sent = capture.map(|_| request.clone());
+        let mut stream = self.provider.stream(request).await.with_context(|| {
+            format!("Completion with provider '{}' failed", self.provider.name())
+        })?;
+
+        let mut reply = ChatMessage::assistant(String::new());
+        // Streamed chunks are usually a token or a few each
+        let mut completion_tokens = 0;
+        let mut reported = None;
+        while let Some(chunk) = stream.next().await {
+            match chunk? {
+                StreamChunk::Text(text) => {
+                    self.emit(RunEvent::Text(&text));
+                    reply.content.push_str(&text);
+                    completion_tokens += self.token_counter.count(&text);
+                    if tokens_left.is_some_and(|left| prompt_tokens + completion_tokens > left) {
+                        // Dropping the stream cancels the request
+                        let usage = NormalizedUsage::estimate(prompt_tokens, completion_tokens);
+                        self.emit(RunEvent::Usage(&usage));
+                        if let (Some(capture), Some(sent)) = (capture, sent) {
+                            capture.completion(sent, &reply, usage);
+                        }
+                        return Ok(Turn::OverBudget(usage));
+                    }
+                }
+                StreamChunk::ToolCall(call) => {
+                    completion_tokens += self.token_counter.count(&call.name)
+                        + self.token_counter.count(&call.arguments.to_string());
+                    reply.tool_calls.push(call);
+                }
+                StreamChunk::Usage(usage) => {
+                    self.emit(RunEvent::Usage(&usage));
+                    reported = Some(usage);
+                }
+            }
+        }
+        let usage = match reported {
+            Some(usage) => usage,
+            None => {
+                let usage = NormalizedUsage::estimate(prompt_tokens, completion_tokens);
+                self.emit(RunEvent::Usage(&usage));
+                usage
+            }
+        };
+        if let (Some(capture), Some(sent)) = (capture, sent) {
+            capture.completion(sent, &reply, usage);
+        }
+        Ok(Turn::Reply(reply, usage))
+    }
+
+    /// Settle how the provider calls tools from `reply` and take prompted
+    /// calls out of its text
+    ///
+    /// Returns false for a prompted call that isn't valid JSON, which the
+    /// model is asked once to correct.
+    fn read_tool_calls(
+        &self, calling: ToolCalling, reply: &mut ChatMessage, tools: &[ToolDefinition],
+        first_id: usize,
+    ) -> bool {
+        let provider = self.provider.as_ref();
+        if calling == ToolCalling::Native || !reply.tool_calls.is_empty() {
+            if calling == ToolCalling::Unknown {
+                self.tool_support.set(provider, ToolCalling::Native);
+            }
+            return true;
+        }
+        match interop::parse_calls(&reply.content, tools, first_id) {
+            Parsed::Calls(calls) => {
+                if calling == ToolCalling::Unknown && !calls.is_empty() {
+                    self.tool_support.set(provider, ToolCalling::Prompted);
+                }
+                reply.tool_calls = calls;
+                true
+            }
+            // An unprobed model writing broken JSON proves nothing either way
+            Parsed::Malformed(_) if calling == ToolCalling::Unknown => true,
+            Parsed::Malformed(err) => {
+                tracing::debug!(provider = provider.name(), "unparsable tool call: {}", err);
+                false
+            }
+        }
+    }
+
+    /// Invoke a tool call, reporting failures to the model rather than the caller
+    async fn invoke(
+        &self, tools: &[ToolInfo], call: &ToolCall, capture: Option<&Capture>,
+    ) -> ToolOutput {
+        let Some(tool) = tools.iter().find(|tool| tool.name == call.name) else {
+            return ToolOutput::error(format!("Unknown tool '{}'", call.name));
+        };
+        let Some(server) = self.servers.iter().find(|s| s.name() == tool.server) else {
+            return ToolOutput::error(format!("MCP server '{}' is not attached", tool.server));
+        };
+        let call = match self.check_arguments(tool, call).await {
+            Ok(call) => call,
+            Err(rejected) => return rejected,
+        };
+        let call = &call;
+        if let Some(policy) = &self.tool_policy {
+            let class = policy.classify(tool);
+            let decision = policy.decide(tool, &call.arguments, self.confirmation.as_deref());
+            self.emit(RunEvent::ToolDecision {
+                call,
+                class,
+                decision,
+            });
+            if let Some(audit) = &self.audit {
+                let record = ToolDecision {
+                    at: chrono::Utc::now(),
+                    server: tool.server.clone(),
+                    tool: tool.name.clone(),
+                    arguments: call.arguments.clone(),
+                    class,
+                    decision,
+                };
+                if let Err(err) = audit.record_decision(&record).await {
+                    tracing::warn!(tool = %call.name, "Failed to audit tool decision: {:#}", err);
+                }
+            }
+            if !decision.allows() {
+                return policy::denied(tool, decision);
+            }
+        }
+        let output = self.call_server(server.as_ref(), call).await;
+        if let Some(capture) = capture {
+            capture.tool_call(&tool.server, &call.name, &call.arguments, &output);
+        }
+        output
+    }
+
+    /// `call` with its arguments coerced as the argument mode allows, or the
+    /// error telling the model why they don't fit the tool's schema
+    async fn check_arguments(
+        &self, tool: &ToolInfo, call: &ToolCall,
+    ) -> Result<ToolCall, ToolOutput> {
+        let mut arguments = call.arguments.clone();
+        let coercions = match self.tool_arguments {
+            ArgumentMode::Lenient => arguments::coerce(&tool.input_schema, &mut arguments),
+            ArgumentMode::Strict => Vec::new(),
+        };
+        let invalid = tool.validate_arguments(&arguments).err();
+        if !coercions.is_empty() || invalid.is_some() {
+            let check = ArgumentCheck {
+                at: chrono::Utc::now(),
+                server: tool.server.clone(),
+                tool: tool.name.clone(),
+                arguments: call.arguments.clone(),
+                coercions,
+                violations: invalid
+                    .as_ref()
+                    .map(|invalid| invalid.violations.clone())
+                    .unwrap_or_default(),
+            };
+            tracing::debug!(
+                tool = %call.name,
+                coercions = check.coercions.len(),
+                violations = check.violations.len(),
+                "checked tool arguments"
+            );
+            if let Some(audit) = &self.audit {
+                if let Err(err) = audit.record_arguments(&check).await {
+                    tracing::warn!(tool = %call.name, "Failed to audit tool arguments: {:#}", err);
+                }
+            }
+        }
+        match invalid {
+            Some(invalid) => Err(ToolOutput::error(invalid.to_string())),
+            None => Ok(ToolCall {
+                arguments,
+                ..call.clone()
+            }),
+        }
+    }
+
+    /// Run a call on `server`, within the tool timeout
+    async fn call_server(&self, server: &dyn ToolServer, call: &ToolCall) -> ToolOutput {
+        let (sender, mut progress) = tokio::sync::mpsc::unbounded_channel();
+        let running = server.call_tool_with_progress(&call.name, call.arguments.clone(), sender);
+        tokio::pin!(running);
+        let mut deadline = self.tool_timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            let timed_out = async {
+                match deadline {
+                    Some(deadline) => tokio::time::sleep_until(deadline).await,
+                    None => std::future::pending().await,
+                }
+            };
+            tokio::select! {
+                biased;
+                Some(update) = progress.recv() => {
+                    self.on_progress(call, &update);
+                    if self.progress_resets_timeout {
+                        deadline = self.tool_timeout.map(|timeout| Instant::now() + timeout);
+                    }
+                }
+                result = &mut running => {
+                    // Progress sent in the same poll that finished the call
+                    while let Ok(update) = progress.try_recv() {
+                        self.on_progress(call, &update);
+                    }
+                    return match result {
+                        Ok(output) => output,
+                        Err(err) => ToolOutput::error(format!("{:#}", err)),
+                    };
+                }
+                _ = timed_out => {
+                    let timeout = self.tool_timeout.unwrap_or_default();
+                    return ToolOutput::error(if self.progress_resets_timeout {
+                        format!("Tool '{}' made no progress for {:?}", call.name, timeout)
+                    } else {
+                        format!("Tool '{}' timed out after {:?}", call.name, timeout)
+                    });
+                }
+            }
+        }
+    }
+
+    fn on_progress(&self, call: &ToolCall, progress: &ToolProgress) {
+        tracing::debug!(
+            tool = %call.name,
+            call_id = %call.id,
+            progress = progress.progress,
+            total = progress.total,
+            message = progress.message.as_deref(),
+            "tool progress"
+        );
+        self.emit(RunEvent::ToolProgress { call, progress });
+    }
+
+    fn emit(&self, event: RunEvent<'_>) {
+        for hook in &self.hooks {
+            hook.on_event(&event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::budget::{BudgetExceeded, BudgetLimit};
+    use crate::provider::{NormalizedResponse, Role};
+    use crate::testing::{FakeMcpServer, MockProvider};
+    use async_trait::async_trait;
+    use serde_json::json;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    fn echo_server() -> Arc<FakeMcpServer> {
+        Arc::new(FakeMcpServer::new("demo").with_tool(
+            "echo",
+            "Echo the given text",
+            json!({"type": "object", "properties": {"text": {"type": "string"}}}),
+            |args| Ok(args["text"].as_str().unwrap_or_default().to_string()),
+        ))
+    }
+
+    #[tokio::test]
+    async fn test_tool_loop_feeds_results_back() {
+        let provider = Arc::new(MockProvider::new("mock"));
+        provider.push_tool_call("echo", json!({"text": "ping"}));
+        provider.push_response(NormalizedResponse::text("pong"));
+        let server = echo_server();
+
+        let agent = AgentBuilder::new(provider.clone())
+            .tool_server(server.clone())
+            .build();
+        let mut history = Vec::new();
+        let answer = agent.chat(&mut history, "call echo").await.unwrap();
+
+        assert_eq!(answer, "pong");
+        assert_eq!(
+            server.calls(),
+            vec![("echo".to_string(), json!({"text": "ping"}))]
+        );
+        let roles: Vec<Role> = history.iter().map(|m| m.role).collect();
+        assert_eq!(
+            roles,
+            vec![Role::User, Role::Assistant, Role::Tool, Role::Assistant]
+        );
+        assert_eq!(history[2].content, "ping");
+
+        let requests = provider.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].tools[0].name, "echo");
+        assert_eq!(requests[1].messages.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_hooks_see_events_in_order() {
+        let provider = Arc::new(MockProvider::new("mock"));
+        provider.push_tool_call("echo", json!({"text": "ping"}));
+        provider.push_tool_call("missing", json!({}));
+        provider.push_response(NormalizedResponse::text("done"));
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let seen = events.clone();
+        let agent = AgentBuilder::new(provider)
+            .tool_server(echo_server())
+            .hook(Arc::new(move |event: &RunEvent<'_>| {
+                let entry = match event {
+                    RunEvent::Text(text) => format!("text:{}", text),
+                    RunEvent::ToolCall(call) => format!("call:{}", call.name),
+                    RunEvent::ToolResult { output, .. } => {
+                        format!("result:{}:{}", output.is_error, output.content)
+                    }
+                    RunEvent::ToolDecision { .. }
+                    | RunEvent::ToolProgress { .. }
+                    | RunEvent::Injection { .. }
+                    | RunEvent::Usage(_) => return,
+                    RunEvent::Finished => "finished".to_string(),
+                };
+                seen.lock().unwrap().push(entry);
+            }))
+            .build();
+        agent.prompt("go").await.unwrap();
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                "call:echo",
+                "result:false:ping",
+                "call:missing",
+                "result:true:Unknown tool 'missing'",
+                "text:done",
+                "finished",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_after_max_turns() {
+        let provider = Arc::new(MockProvider::new("mock"));
+        for _ in 0..3 {
+            provider.push_tool_call("echo", json!({"text": "again"}));
+        }
+        let agent = AgentBuilder::new(provider)
+            .tool_server(echo_server())
+            .max_turns(2)
+            .build();
+        let err = agent.prompt("loop").await.unwrap_err();
+        assert!(err.to_string().contains("within 2 turns"));
+    }
+
+    /// Calls `echo` forever, reporting 120 tokens per completion
+    struct LoopingProvider {
+        delay: Duration,
+    }
+
+    #[async_trait]
+    impl Provider for LoopingProvider {
+        fn name(&self) -> &str {
+            "looping"
+        }
+
+        fn model(&self) -> &str {
+            "looping-model"
+        }
+
+        async fn complete(&self, _request: ChatRequest) -> Result<NormalizedResponse> {
+            tokio::time::sleep(self.delay).await;
+            let mut response = NormalizedResponse::tool_calls(vec![ToolCall {
+                id: "call".to_string(),
+                name: "echo".to_string(),
+                arguments: json!({"text": "again"}),
+            }]);
+            response.usage = NormalizedUsage::new(100, 20);
+            Ok(response)
+        }
+    }
+
+    async fn run_out(delay: Duration, budget: RunBudget) -> (BudgetExceeded, Vec<ChatMessage>) {
+        let agent = AgentBuilder::new(Arc::new(LoopingProvider { delay }))
+            .tool_server(echo_server())
+            .max_turns(usize::MAX)
+            .budget(RunBudget::unlimited().max_tool_calls(1000))
+            .build();
+        let mut history = vec![ChatMessage::user("earlier")];
+        let err = agent
+            .chat_with_budget(&mut history, "loop", budget)
+            .await
+            .unwrap_err();
+        (err.downcast::<BudgetExceeded>().unwrap(), history)
+    }
+
+    #[tokio::test]
+    async fn test_token_budget_stops_before_next_completion() {
+        let budget = RunBudget::unlimited().max_total_tokens(300);
+        let (exceeded, history) = run_out(Duration::ZERO, budget).await;
+
+        assert_eq!(exceeded.limit, BudgetLimit::TotalTokens(300));
+        assert_eq!(exceeded.consumed.completions, 3);
+        assert_eq!(exceeded.consumed.tokens, NormalizedUsage::new(300, 60));
+        assert_eq!(exceeded.consumed.tool_calls, 3);
+        // The prompt plus three rounds of call and result, without `earlier`
+        assert_eq!(exceeded.transcript.len(), 7);
+        assert_eq!(exceeded.transcript[0].content, "loop");
+        assert_eq!(history[1..], exceeded.transcript[..]);
+    }
+
+    #[tokio::test]
+    async fn test_tool_call_budget_stops_before_next_invocation() {
+        let budget = RunBudget::unlimited().max_tool_calls(2);
+        let (exceeded, _) = run_out(Duration::ZERO, budget).await;
+
+        assert_eq!(exceeded.limit, BudgetLimit::ToolCalls(2));
+        assert_eq!(exceeded.consumed.tool_calls, 2);
+        assert_eq!(exceeded.consumed.completions, 3);
+        // The third call was requested but never answered
+        let last = exceeded.transcript.last().unwrap();
+        assert_eq!(last.role, Role::Assistant);
+        assert_eq!(last.tool_calls.len(), 1);
+        assert!(exceeded.to_string().contains("budget of 2 tool calls"));
+    }
+
+    #[tokio::test]
+    async fn test_wall_time_budget_stops_the_run() {
+        let budget = RunBudget::unlimited().max_wall_time(Duration::from_millis(50));
+        let (exceeded, _) = run_out(Duration::from_millis(20), budget).await;
+
+        assert_eq!(
+            exceeded.limit,
+            BudgetLimit::WallTime(Duration::from_millis(50))
+        );
+        assert!(exceeded.consumed.elapsed >= Duration::from_millis(50));
+        assert!(exceeded.consumed.completions <= 2);
+    }
+
+    #[tokio::test]
+    async fn test_agent_budget_applies_to_every_chat() {
+        let agent = AgentBuilder::new(Arc::new(LoopingProvider {
+            delay: Duration::ZERO,
+        }))
+        .tool_server(echo_server())
+        .max_turns(usize::MAX)
+        .budget(RunBudget::unlimited().max_tool_calls(1))
+        .build();
+
+        for _ in 0..2 {
+            let err = agent.prompt("loop").await.unwrap_err();
+            let exceeded = err.downcast_ref::<BudgetExceeded>().unwrap();
+            assert_eq!(exceeded.consumed.tool_calls, 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reported_stream_usage_is_kept() {
+        let provider = Arc::new(MockProvider::new("mock"));
+        let mut response = NormalizedResponse::text("The cat sat");
+        response.usage = NormalizedUsage::new(12, 3);
+        provider.push_response(response);
+        let agent = AgentBuilder::new(provider).build();
+
+        let response = agent.run(&mut Vec::new(), "ping").await.unwrap();
+        assert_eq!(response.usage, NormalizedUsage::new(12, 3));
+        assert!(!response.usage.estimated);
+    }
+
+    #[tokio::test]
+    async fn test_unreported_stream_usage_is_estimated() {
+        let provider = Arc::new(MockProvider::new("mock").without_stream_usage());
+        provider.push_response(NormalizedResponse::text("The cat sat"));
+        let usage = Arc::new(Mutex::new(Vec::new()));
+        let seen = usage.clone();
+        let agent = AgentBuilder::new(provider)
+            .hook(Arc::new(move |event: &RunEvent<'_>| {
+                if let RunEvent::Usage(usage) = event {
+                    seen.lock().unwrap().push(**usage);
+                }
+            }))
+            .build();
+
+        let response = agent.run(&mut Vec::new(), "ping").await.unwrap();
+        // "ping" and the message overhead, then a token per streamed word
+        let estimate = NormalizedUsage::estimate(5, 3);
+        assert_eq!(response.usage, estimate);
+        assert_eq!(*usage.lock().unwrap(), vec![estimate]);
+    }
+
+    #[tokio::test]
+    async fn test_token_budget_cuts_off_a_long_stream() {
+        let provider = Arc::new(MockProvider::new("mock").without_stream_usage());
+        provider.push_response(NormalizedResponse::text(vec!["tok"; 1000].join(" ")));
+        let streamed = Arc::new(Mutex::new(0));
+        let seen = streamed.clone();
+        let agent = AgentBuilder::new(provider)
+            .budget(RunBudget::unlimited().max_total_tokens(50))
+            .hook(Arc::new(move |event: &RunEvent<'_>| {
+                if let RunEvent::Text(_) = event {
+                    *seen.lock().unwrap() += 1;
+                }
+            }))
+            .build();
+
+        let err = agent.prompt("go").await.unwrap_err();
+        let exceeded = err.downcast::<BudgetExceeded>().unwrap();
+        assert_eq!(exceeded.limit, BudgetLimit::TotalTokens(50));
+        // 5 prompt tokens, and the chunk that went past 50 is counted
+        assert_eq!(exceeded.consumed.tokens, NormalizedUsage::estimate(5, 46));
+        assert_eq!(exceeded.consumed.completions, 1);
+        assert_eq!(*streamed.lock().unwrap(), 46);
+        // The answer that was cut off is not part of the transcript
+        assert_eq!(exceeded.transcript.len(), 1);
+    }
+
+    fn slow_server(steps: usize) -> Arc<FakeMcpServer> {
+        Arc::new(
+            FakeMcpServer::new("demo")
+                .with_tool(
+                    "build",
+                    "Build the project",
+                    json!({"type": "object"}),
+                    |_| Ok("built".to_string()),
+                )
+                .with_progress("build", steps, Duration::from_millis(40)),
+        )
+    }
+
+    fn slow_agent(steps: usize, progress_resets_timeout: bool) -> Agent {
+        let provider = Arc::new(MockProvider::new("mock"));
+        provider.push_tool_call("build", json!({}));
+        provider.push_response(NormalizedResponse::text("done"));
+        AgentBuilder::new(provider)
+            .tool_server(slow_server(steps))
+            .tool_timeout(Duration::from_millis(100))
+            .progress_resets_timeout(progress_resets_timeout)
+            .build()
+    }
+
+    #[tokio::test]
+    async fn test_tool_progress_arrives_in_order_before_the_result() {
+        let provider = Arc::new(MockProvider::new("mock"));
+        provider.push_tool_call("build", json!({}));
+        provider.push_response(NormalizedResponse::text("done"));
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let seen = events.clone();
+        let agent = AgentBuilder::new(provider)
+            .tool_server(slow_server(3))
+            .hook(Arc::new(move |event: &RunEvent<'_>| {
+                let entry = match event {
+                    RunEvent::ToolProgress { call, progress } => format!(
+                        "{}:{:.0}%:{}",
+                        call.name,
+                        progress.percent().unwrap(),
+                        progress.message.as_deref().unwrap()
+                    ),
+                    RunEvent::ToolResult { output, .. } => format!("result:{}", output.content),
+                    _ => return,
+                };
+                seen.lock().unwrap().push(entry);
+            }))
+            .build();
+        agent.prompt("build it").await.unwrap();
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                "build:33%:step 1 of 3",
+                "build:67%:step 2 of 3",
+                "build:100%:step 3 of 3",
+                "result:built",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_progress_resets_the_tool_timeout() {
+        // Four steps of 40ms outlast the 100ms timeout, but no gap does
+        let agent = slow_agent(4, true);
+        let mut history = Vec::new();
+        agent.chat(&mut history, "build it").await.unwrap();
+        assert_eq!(history[2].content, "built");
+
+        let agent = slow_agent(4, false);
+        let mut history = Vec::new();
+        agent.chat(&mut history, "build it").await.unwrap();
+        assert_eq!(history[2].content, "Tool 'build' timed out after 100ms");
+    }
+
+    #[tokio::test]
+    async fn test_silent_tool_times_out_even_when_progress_resets() {
+        let provider = Arc::new(MockProvider::new("mock"));
+        provider.push_tool_call("build", json!({}));
+        provider.push_response(NormalizedResponse::text("done"));
+        let server = Arc::new(
+            FakeMcpServer::new("demo")
+                .with_tool(
+                    "build",
+                    "Build the project",
+                    json!({"type": "object"}),
+                    |_| Ok("built".to_string()),
+                )
+                .with_progress("build", 1, Duration::from_millis(300)),
+        );
+        let agent = AgentBuilder::new(provider)
+            .tool_server(server)
+            .tool_timeout(Duration::from_millis(100))
+            .progress_resets_timeout(true)
+            .build();
+        let mut history = Vec::new();
+        agent.chat(&mut history, "build it").await.unwrap();
+        assert_eq!(
+            history[2].content,
+            "Tool 'build' made no progress for 100ms"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_annotates_the_answer_without_changing_it() {
+        use crate::postprocess::{CitationExtractor, CodeBlockExtractor};
+
+        let provider = Arc::new(MockProvider::new("mock"));
+        provider.push_tool_call("echo", json!({"text": "ping"}));
+        let answer = "The tool said ping [1], not pong [2].\n```sh\necho ping\n```";
+        provider.push_response(NormalizedResponse::text(answer));
+        let agent = AgentBuilder::new(provider)
+            .tool_server(echo_server())
+            .post_processor(Arc::new(CodeBlockExtractor))
+            .post_processor(Arc::new(CitationExtractor))
+            .build();
+
+        let response = agent.run(&mut Vec::new(), "call echo").await.unwrap();
+        assert_eq!(response.content, answer);
+        assert_eq!(response.annotations.code_blocks.len(), 1);
+        assert_eq!(response.annotations.code_blocks[0].content, "echo ping\n");
+
+        let citations = &response.annotations.citations;
+        assert_eq!(citations.len(), 2);
+        let source = citations[0].source.as_ref().unwrap();
+        assert_eq!(source.label, "echo");
+        assert!(citations[1].is_dangling());
+    }
+
+    fn fenced_echo(args: &str) -> NormalizedResponse {
+        NormalizedResponse::text(format!(
+            "Calling echo.\n```json\n{{\"tool\": \"echo\", \"arguments\": {}}}\n```",
+            args
+        ))
+    }
+
+    #[tokio::test]
+    async fn test_native_tool_calls_settle_the_pair_as_native() {
+        let provider = Arc::new(MockProvider::new("mock"));
+        provider.push_tool_call("echo", json!({"text": "ping"}));
+        provider.push_response(NormalizedResponse::text("pong"));
+        let support = Arc::new(ToolSupport::new());
+        let agent = AgentBuilder::new(provider.clone())
+            .tool_server(echo_server())
+            .tool_support(support.clone())
+            .build();
+
+        assert_eq!(support.get(provider.as_ref()), ToolCalling::Unknown);
+        agent.prompt("call echo").await.unwrap();
+
+        assert_eq!(support.get(provider.as_ref()), ToolCalling::Native);
+        assert!(provider.requests().iter().all(|r| r.tools.len() == 1));
+    }
+
+    #[tokio::test]
+    async fn test_text_only_model_falls_back_to_prompted_tools() {
+        let provider = Arc::new(MockProvider::new("ollama"));
+        provider.push_response(fenced_echo(r#"{"text": "ping"}"#));
+        provider.push_response(NormalizedResponse::text("pong"));
+        provider.push_response(fenced_echo(r#"{"text": "again"}"#));
+        provider.push_response(NormalizedResponse::text("again"));
+        let server = echo_server();
+        let support = Arc::new(ToolSupport::new());
+        let agent = AgentBuilder::new(provider.clone())
+            .tool_server(server.clone())
+            .tool_support(support.clone())
+            .build();
+
+        let mut history = Vec::new();
+        assert_eq!(agent.chat(&mut history, "echo ping").await.unwrap(), "pong");
+        assert_eq!(support.get(provider.as_ref()), ToolCalling::Prompted);
+        assert_eq!(server.calls()[0].1, json!({"text": "ping"}));
+
+        // The probe offered native tools; the result went back as text
+        let requests = provider.requests();
+        assert_eq!(requests[0].tools.len(), 1);
+        assert!(requests[1].tools.is_empty());
+        assert!(requests[1].system.as_deref().unwrap().contains("- echo:"));
+        assert_eq!(requests[1].messages[2].role, Role::User);
+
+        // A second agent sharing the decision never offers native tools
+        let agent = AgentBuilder::new(provider.clone())
+            .tool_server(server.clone())
+            .tool_support(support)
+            .build();
+        assert_eq!(agent.prompt("echo again").await.unwrap(), "again");
+        assert_eq!(server.calls().len(), 2);
+        assert!(provider.requests()[2..].iter().all(|r| r.tools.is_empty()));
+    }
+
+    #[tokio::test]
+    async fn test_malformed_prompted_call_is_retried_once() {
+        let provider = Arc::new(MockProvider::new("ollama"));
+        let support = Arc::new(ToolSupport::new());
+        support.set(provider.as_ref(), ToolCalling::Prompted);
+        provider.push_response(fenced_echo(r#"{"text": }"#));
+        provider.push_response(fenced_echo(r#"{"text": "ping"}"#));
+        provider.push_response(NormalizedResponse::text("pong"));
+        let server = echo_server();
+        let agent = AgentBuilder::new(provider.clone())
+            .tool_server(server.clone())
+            .tool_support(support)
+            .build();
+
+        assert_eq!(agent.prompt("echo ping").await.unwrap(), "pong");
+        assert_eq!(server.calls().len(), 1);
+        let nudge = &provider.requests()[1].messages[2];
+        assert_eq!(nudge.content, interop::RETRY_NUDGE);
+
+        // Broken twice in a row, the reply is the answer
+        provider.push_response(fenced_echo(r#"{"text": }"#));
+        provider.push_response(fenced_echo(r#"{"text": }"#));
+        let answer = agent.prompt("echo ping").await.unwrap();
+        assert!(answer.starts_with("Calling echo."));
+        assert_eq!(server.calls().len(), 1);
+    }
+
+    fn count_server() -> Arc<FakeMcpServer> {
+        Arc::new(FakeMcpServer::new("demo").with_tool(
+            "repeat",
+            "Repeat the last answer",
+            json!({
+                "type": "object",
+                "properties": {"count": {"type": "integer"}},
+                "required": ["count"]
+            }),
+            |args| Ok(args["count"].to_string()),
+        ))
+    }
+
+    #[tokio::test]
+    async fn test_strict_arguments_are_rejected_and_the_model_retries() {
+        let provider = Arc::new(MockProvider::new("mock"));
+        provider.push_tool_call("repeat", json!({"count": "2"}));
+        provider.push_tool_call("repeat", json!({"count": 2}));
+        provider.push_response(NormalizedResponse::text("done"));
+        let server = count_server();
+        let agent = AgentBuilder::new(provider)
+            .tool_server(server.clone())
+            .tool_arguments(ArgumentMode::Strict)
+            .build();
+
+        let mut history = Vec::new();
+        assert_eq!(
+            agent.chat(&mut history, "repeat twice").await.unwrap(),
+            "done"
+        );
+        assert_eq!(
+            server.calls(),
+            vec![("repeat".to_string(), json!({"count": 2}))]
+        );
+        assert!(
+            history[2]
+                .content
+                .starts_with("Invalid arguments for tool 'repeat': /count: "),
+            "{}",
+            history[2].content
+        );
+        assert_eq!(history[4].content, "2");
+    }
+
+    #[tokio::test]
+    async fn test_lenient_arguments_are_coerced_and_audited() {
+        let dir = tempfile::tempdir().unwrap();
+        let audit = Arc::new(AuditLog::new(dir.path().join("audit.jsonl")));
+        let provider = Arc::new(MockProvider::new("mock"));
+        provider.push_tool_call("repeat", json!({"count": "many"}));
+        provider.push_tool_call("repeat", json!({"count": "3"}));
+        provider.push_response(NormalizedResponse::text("done"));
+        let server = count_server();
+        let agent = AgentBuilder::new(provider)
+            .tool_server(server.clone())
+            .audit_log(audit.clone())
+            .build();
+
+        assert_eq!(agent.prompt("repeat thrice").await.unwrap(), "done");
+        // "many" can't be coerced and never reaches the tool
+        assert_eq!(
+            server.calls(),
+            vec![("repeat".to_string(), json!({"count": 3}))]
+        );
+
+        let checks = audit.argument_checks().unwrap();
+        assert_eq!(checks.len(), 2);
+        assert_eq!(checks[0].arguments, json!({"count": "many"}));
+        assert!(checks[0].coercions.is_empty());
+        assert_eq!(checks[0].violations.len(), 1);
+        assert_eq!(checks[0].violations[0].path, "/count");
+        assert_eq!(checks[1].arguments, json!({"count": "3"}));
+        assert!(checks[1].violations.is_empty());
+        assert_eq!(
+            checks[1].coercions,
+            vec![arguments::Coercion {
+                path: "/count".to_string(),
+                kind: arguments::CoercionKind::StringToNumber,
+                from: json!("3"),
+                to: json!(3),
+            }]
+        );
+    }
+}
@@ -0,0 +1,142 @@
+//! Model aliases
+//!
+//! `model_aliases` in [`crate::Config`] maps short names such as `smart` to a
+//! concrete `provider/model` pair or to another alias, so configs and callers
+//! don't have to repeat dated model strings.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use thiserror::Error;
+
+/// A concrete provider and model
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ModelRef {
+    pub provider: String,
+    pub model: String,
+}
+
+impl ModelRef {
+    /// Parse `provider/model`; the model part may itself contain slashes
+    pub fn parse(value: &str) -> Option<Self> {
+        let (provider, model) = value.split_once('/')?;
+        if provider.is_empty() || model.is_empty() {
+            return None;
+        }
+        Some(Self {
+            provider: provider.to_string(),
+            model: model.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for ModelRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.provider, self.model)
+    }
+}
+
+/// Why an alias could not be resolved; `chain` lists every name visited
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum AliasError {
+    #[error("model alias cycle: {}", .chain.join(" -> "))]
+    Cycle { chain: Vec<String> },
+    #[error(
+        "'{}' is neither a model alias nor a provider/model pair (alias chain: {})",
+        .chain.last().map(String::as_str).unwrap_or_default(),
+        .chain.join(" -> ")
+    )]
+    Unresolved { chain: Vec<String> },
+}
+
+/// Follow aliases from `name` until a `provider/model` pair is reached
+///
+/// Alias names take precedence over the pair syntax, so an alias may be
+/// called `openai/gpt-4o` and point somewhere else.
+pub fn resolve(aliases: &HashMap<String, String>, name: &str) -> Result<ModelRef, AliasError> {
+    let mut chain = vec![name.to_string()];
+    let mut current = name;
+    while let Some(target) = aliases.get(current) {
+        if chain.iter().any(|seen| seen == target) {
+            chain.push(target.clone());
+            return Err(AliasError::Cycle { chain });
+        }
+        chain.push(target.clone());
+        current = target;
+    }
+    ModelRef::parse(current).ok_or(AliasError::Unresolved { chain })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn aliases(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_nested_aliases_resolve() {
+        let aliases = aliases(&[
+            ("default", "smart"),
+            ("smart", "openai/gpt-4o-2024-08-06"),
+            ("cheap", "ollama/llama3.1"),
+        ]);
+        assert_eq!(
+            resolve(&aliases, "default").unwrap(),
+            ModelRef {
+                provider: "openai".to_string(),
+                model: "gpt-4o-2024-08-06".to_string(),
+            }
+        );
+        assert_eq!(
+            resolve(&aliases, "cheap").unwrap().to_string(),
+            "ollama/llama3.1"
+        );
+        assert_eq!(
+            resolve(&aliases, "anthropic/claude-3-5-sonnet")
+                .unwrap()
+                .provider,
+            "anthropic"
+        );
+    }
+
+    #[test]
+    fn test_cycle_reports_chain() {
+        let aliases = aliases(&[("a", "b"), ("b", "c"), ("c", "a")]);
+        let err = resolve(&aliases, "a").unwrap_err();
+        assert_eq!(
+            err,
+            AliasError::Cycle {
+                chain: vec!["a", "b", "c", "a"]
+                    .into_iter()
+                    .map(String::from)
+                    .collect(),
+            }
+        );
+        assert_eq!(err.to_string(), "model alias cycle: a -> b -> c -> a");
+    }
+
+    #[test]
+    fn test_dangling_alias_reports_chain() {
+        let aliases = aliases(&[("smart", "fast"), ("fast", "gpt4")]);
+        let err = resolve(&aliases, "smart").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "'gpt4' is neither a model alias nor a provider/model pair (alias chain: smart -> fast -> gpt4)"
+        );
+    }
+
+    #[test]
+    fn test_model_ref_parse() {
+        assert_eq!(
+            ModelRef::parse("ollama/library/llama3").unwrap().model,
+            "library/llama3"
+        );
+        assert_eq!(ModelRef::parse("openai/"), None);
+        assert_eq!(ModelRef::parse("gpt-4"), None);
+    }
+}
@@ -0,0 +1,330 @@
+//! Checking tool call arguments before invocation
+//!
+//! Models often get tool arguments nearly right: a number sent as `"3"`, an
+//! optional field left out, a key the tool doesn't take. Agents check the
+//! arguments of every call against the tool's input schema before it reaches
+//! the server, as `agent.tool_arguments` says:
+//!
+//! - `lenient`, the default, first makes the safe [`Coercion`]s: strings
+//!   holding a number or `true`/`false` become numbers and booleans where
+//!   the schema doesn't also take a string, properties an object's
+//!   `additionalProperties: false` forbids are dropped, and missing
+//!   properties with a `default` get it
+//! - `strict` changes nothing
+//!
+//! Arguments that still break the schema don't reach the tool. The model
+//! gets the violations back as a tool error, and can call the tool again
+//! with arguments that fit. Coerced and rejected arguments are recorded in
+//! the audit log as an [`ArgumentCheck`], with `audit` configured.
+//!
+//! ```toml
+//! [agent]
+//! tool_arguments = "strict"
+//! ```
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Number, Value};
+
+use crate::mcp::Violation;
+
+/// How the arguments of tool calls are checked; see the module docs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ArgumentMode {
+    /// Reject arguments that break the schema as they are
+    Strict,
+    /// Coerce what can safely be coerced, then reject what still breaks the
+    /// schema
+    #[default]
+    Lenient,
+}
+
+/// What a [`Coercion`] changed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CoercionKind {
+    StringToNumber,
+    StringToBoolean,
+    /// A property `additionalProperties: false` forbids
+    DroppedProperty,
+    /// A missing property with a `default`
+    FilledDefault,
+}
+
+/// A change made to arguments so they fit a tool's schema
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Coercion {
+    /// JSON Pointer to the value, like [`Violation::path`]
+    pub path: String,
+    pub kind: CoercionKind,
+    /// What the model sent; `null` for a filled default
+    pub from: Value,
+    /// What the tool got; `null` for a dropped property
+    pub to: Value,
+}
+
+/// Arguments of a tool call that were coerced or rejected, as recorded in
+/// the audit log
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArgumentCheck {
+    pub at: DateTime<Utc>,
+    pub server: String,
+    pub tool: String,
+    /// As the model sent them
+    pub arguments: Value,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub coercions: Vec<Coercion>,
+    /// Why the call was rejected; empty when it went ahead
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub violations: Vec<Violation>,
+}
+
+/// Make the safe coercions that fit `value` to `schema`, returning them
+///
+/// Only `type`, `properties`, `additionalProperties`, `default` and `items`
+/// are looked at; values under other keywords, such as `$ref` or `anyOf`,
+/// are left as they are.
+pub fn coerce(schema: &Value, value: &mut Value) -> Vec<Coercion> {
+    let mut coercions = Vec::new();
+    coerce_at(schema, value, &mut String::new(), &mut coercions);
+    coercions
+}
+
+fn coerce_at(schema: &Value, value: &mut Value, path: &mut String, out: &mut Vec<Coercion>) {
+    let Some(schema) = schema.as_object() else {
+        return;
+    };
+    let types = types(schema);
+    match value {
+        Value::String(text) if !types.contains(&"string") => {
+            let coerced = if types.contains(&"integer") || types.contains(&"number") {
+                parse_number(text, types.contains(&"number"))
+                    .map(|number| (CoercionKind::StringToNumber, Value::Number(number)))
+            } else if types.contains(&"boolean") {
+                match text.as_str() {
+                    "true" => Some((CoercionKind::StringToBoolean, Value::Bool(true))),
+                    "false" => Some((CoercionKind::StringToBoolean, Value::Bool(false))),
+                    _ => None,
+                }
+            } else {
+                None
+            };
+            if let Some((kind, coerced)) = coerced {
+                out.push(Coercion {
+                    path: path.clone(),
+                    kind,
+                    from: std::mem::replace(value, coerced.clone()),
+                    to: coerced,
+                });
+            }
+        }
+        Value::Object(object) => coerce_object(schema, object, path, out),
+        Value::Array(items) => {
+            let Some(item_schema) = schema.get("items") else {
+                return;
+            };
+            for (index, item) in items.iter_mut().enumerate() {
+                let len = path.len();
+                path.push_str(&format!("/{}", index));
+                coerce_at(item_schema, item, path, out);
+                path.truncate(len);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn coerce_object(
+    schema: &Map<String, Value>, object: &mut Map<String, Value>, path: &mut String,
+    out: &mut Vec<Coercion>,
+) {
+    let empty = Map::new();
+    let properties = schema
+        .get("properties")
+        .and_then(Value::as_object)
+        .unwrap_or(&empty);
+    // Pattern properties would need matching; those objects keep every key
+    let closed = schema.get("additionalProperties") == Some(&Value::Bool(false))
+        && !schema.contains_key("patternProperties");
+    if closed {
+        let extra: Vec<String> = object
+            .keys()
+            .filter(|key| !properties.contains_key(*key))
+            .cloned()
+            .collect();
+        for key in extra {
+            if let Some(from) = object.remove(&key) {
+                out.push(Coercion {
+                    path: format!("{}/{}", path, escape(&key)),
+                    kind: CoercionKind::DroppedProperty,
+                    from,
+                    to: Value::Null,
+                });
+            }
+        }
+    }
+    for (key, property) in properties {
+        let len = path.len();
+        path.push('/');
+        path.push_str(&escape(key));
+        match object.get_mut(key) {
+            Some(value) => coerce_at(property, value, path, out),
+            None => {
+                if let Some(default) = property.get("default") {
+                    object.insert(key.clone(), default.clone());
+                    out.push(Coercion {
+                        path: path.clone(),
+                        kind: CoercionKind::FilledDefault,
+                        from: Value::Null,
+                        to: default.clone(),
+                    });
+                }
+            }
+        }
+        path.truncate(len);
+    }
+}
+
+/// The types a schema allows, from a `type` string or list
+fn types(schema: &Map<String, Value>) -> Vec<&str> {
+    match schema.get("type") {
+        Some(Value::String(name)) => vec![name.as_str()],
+        Some(Value::Array(names)) => names.iter().filter_map(Value::as_str).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// `text` as a JSON number, integers only unless `fractions`
+fn parse_number(text: &str, fractions: bool) -> Option<Number> {
+    let text = text.trim();
+    if let Ok(integer) = text.parse::<i64>() {
+        return Some(integer.into());
+    }
+    if !fractions {
+        return None;
+    }
+    text.parse::<f64>().ok().and_then(Number::from_f64)
+}
+
+/// `key` as a JSON Pointer reference token
+fn escape(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// The coerced arguments, and where and how they were coerced by path
+    fn coerced(schema: Value, mut arguments: Value) -> (Value, Vec<(String, CoercionKind)>) {
+        let mut coercions: Vec<_> = coerce(&schema, &mut arguments)
+            .into_iter()
+            .map(|coercion| (coercion.path, coercion.kind))
+            .collect();
+        coercions.sort_by(|a, b| a.0.cmp(&b.0));
+        (arguments, coercions)
+    }
+
+    #[test]
+    fn test_numeric_strings_become_numbers() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "count": {"type": "integer"},
+                "ratio": {"type": "number"},
+                "limits": {"type": "array", "items": {"type": ["integer", "null"]}},
+                "label": {"type": ["string", "integer"]}
+            }
+        });
+        let (arguments, coercions) = coerced(
+            schema,
+            json!({"count": " 3 ", "ratio": "0.5", "limits": ["10", null], "label": "7"}),
+        );
+        assert_eq!(
+            arguments,
+            json!({"count": 3, "ratio": 0.5, "limits": [10, null], "label": "7"})
+        );
+        assert_eq!(
+            coercions,
+            [
+                ("/count".to_string(), CoercionKind::StringToNumber),
+                ("/limits/0".to_string(), CoercionKind::StringToNumber),
+                ("/ratio".to_string(), CoercionKind::StringToNumber),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_boolean_strings_become_booleans() {
+        let schema = json!({
+            "type": "object",
+            "properties": {"recursive": {"type": "boolean"}, "force": {"type": "boolean"}}
+        });
+        let (arguments, coercions) = coerced(schema, json!({"recursive": "true", "force": "yes"}));
+        // "yes" is left for validation to reject
+        assert_eq!(arguments, json!({"recursive": true, "force": "yes"}));
+        assert_eq!(
+            coercions,
+            [("/recursive".to_string(), CoercionKind::StringToBoolean)]
+        );
+    }
+
+    #[test]
+    fn test_forbidden_properties_are_dropped_and_defaults_filled() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "path": {"type": "string"},
+                "options": {
+                    "type": "object",
+                    "properties": {"encoding": {"type": "string", "default": "utf-8"}},
+                    "additionalProperties": false
+                }
+            },
+            "additionalProperties": false
+        });
+        let mut arguments = json!({"path": "a.txt", "mode": "w", "options": {"a/b": 1}});
+        let coercions = coerce(&schema, &mut arguments);
+        assert_eq!(
+            arguments,
+            json!({"path": "a.txt", "options": {"encoding": "utf-8"}})
+        );
+        assert_eq!(
+            coercions,
+            [
+                Coercion {
+                    path: "/mode".to_string(),
+                    kind: CoercionKind::DroppedProperty,
+                    from: json!("w"),
+                    to: Value::Null,
+                },
+                Coercion {
+                    path: "/options/a~1b".to_string(),
+                    kind: CoercionKind::DroppedProperty,
+                    from: json!(1),
+                    to: Value::Null,
+                },
+                Coercion {
+                    path: "/options/encoding".to_string(),
+                    kind: CoercionKind::FilledDefault,
+                    from: Value::Null,
+                    to: json!("utf-8"),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ambiguous_values_are_left_alone() {
+        let schema = json!({
+            "type": "object",
+            "properties": {"count": {"type": "integer"}, "id": {"anyOf": [{"type": "integer"}]}},
+            "patternProperties": {"^x-": {}},
+            "additionalProperties": false
+        });
+        let arguments = json!({"count": "1.5", "id": "4", "x-trace": "on"});
+        assert_eq!(coerced(schema, arguments.clone()), (arguments, vec![]));
+    }
+}
@@ -0,0 +1,357 @@
+//! Interaction audit log
+//!
+//! With `audit` set in [`crate::Config`], every completion run through
+//! [`crate::RigMcpClient::complete`] is appended to a JSON Lines file: the
+//! request, the response, who answered and when, under the id returned as
+//! [`NormalizedResponse::request_id`]. Ratings given with
+//! [`crate::RigMcpClient::rate_interaction`] are appended as records of their
+//! own, and the latest rating of an interaction wins. [`crate::finetune`]
+//! turns the log into training data.
+//!
+//! Agents given the log also record what their [`crate::policy`] decided
+//! about each tool call, the arguments they coerced or rejected (see
+//! [`crate::arguments`]) and what their [`crate::injection`] screen flagged,
+//! and services record the [`Purge`]s of their retention policies.
+//!
+//! A log given [`crate::redaction`] rules with [`AuditLog::with_redaction`]
+//! redacts interactions and tool call arguments before writing them.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+use crate::arguments::ArgumentCheck;
+use crate::injection::InjectionRecord;
+use crate::policy::ToolDecision;
+use crate::provider::{ChatRequest, NormalizedResponse};
+use crate::redaction::RedactionRuleSet;
+
+/// `audit` section of [`crate::Config`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditConfig {
+    /// JSON Lines file interactions are appended to
+    pub path: PathBuf,
+    /// Recorded with every interaction, e.g. the deployment, so exports can
+    /// select them
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+}
+
+/// A completion as recorded in the audit log
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Interaction {
+    pub request_id: String,
+    pub at: DateTime<Utc>,
+    pub provider: String,
+    pub model: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    pub request: ChatRequest,
+    pub response: NormalizedResponse,
+    /// Latest rating, filled in by [`AuditLog::read`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rating: Option<f32>,
+}
+
+/// Data deleted under a retention policy
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Purge {
+    pub at: DateTime<Utc>,
+    /// Metadata the deleted records matched; empty when they were deleted
+    /// for their age
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub matching: BTreeMap<String, String>,
+    /// Records deleted, by store
+    pub deleted: BTreeMap<String, usize>,
+}
+
+/// One line of the log
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum Record {
+    Interaction(Box<Interaction>),
+    Rating {
+        request_id: String,
+        score: f32,
+        at: DateTime<Utc>,
+    },
+    ToolDecision(ToolDecision),
+    Purge(Purge),
+    Injection(InjectionRecord),
+    ToolArguments(ArgumentCheck),
+}
+
+/// An append-only audit log file
+#[derive(Debug)]
+pub struct AuditLog {
+    path: PathBuf,
+    /// Keeps concurrent records from interleaving
+    append: Mutex<()>,
+    redaction: Option<Arc<RedactionRuleSet>>,
+}
+
+impl AuditLog {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            append: Mutex::new(()),
+            redaction: None,
+        }
+    }
+
+    /// Redact what `rules` match from interactions and tool decisions
+    pub fn with_redaction(mut self, rules: Arc<RedactionRuleSet>) -> Self {
+        self.redaction = Some(rules);
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Append `interaction`
+    pub async fn record(&self, interaction: &Interaction) -> Result<()> {
+        let mut interaction = interaction.clone();
+        if let Some(rules) = &self.redaction {
+            rules.apply_request(&mut interaction.request);
+            rules.apply_response(&mut interaction.response);
+        }
+        self.append(&Record::Interaction(Box::new(interaction)))
+            .await
+    }
+
+    /// Rate the interaction recorded as `request_id`
+    ///
+    /// Fails if the log has no such interaction or `score` isn't a number.
+    pub async fn rate(&self, request_id: &str, score: f32, at: DateTime<Utc>) -> Result<()> {
+        anyhow::ensure!(score.is_finite(), "Rating must be a number, got {}", score);
+        let known = self
+            .read()?
+            .iter()
+            .any(|interaction| interaction.request_id == request_id);
+        anyhow::ensure!(
+            known,
+            "No interaction '{}' in audit log {}",
+            request_id,
+            self.path.display()
+        );
+        self.append(&Record::Rating {
+            request_id: request_id.to_string(),
+            score,
+            at,
+        })
+        .await
+    }
+
+    /// Append a tool policy decision
+    pub async fn record_decision(&self, decision: &ToolDecision) -> Result<()> {
+        let mut decision = decision.clone();
+        if let Some(rules) = &self.redaction {
+            rules.apply_value(&mut decision.arguments);
+        }
+        self.append(&Record::ToolDecision(decision)).await
+    }
+
+    /// Append coerced or rejected tool call arguments
+    pub async fn record_arguments(&self, check: &ArgumentCheck) -> Result<()> {
+        let mut check = check.clone();
+        if let Some(rules) = &self.redaction {
+            rules.apply_value(&mut check.arguments);
+            for coercion in &mut check.coercions {
+                rules.apply_value(&mut coercion.from);
+                rules.apply_value(&mut coercion.to);
+            }
+        }
+        self.append(&Record::ToolArguments(check)).await
+    }
+
+    /// Append a retention purge
+    pub async fn record_purge(&self, purge: &Purge) -> Result<()> {
+        self.append(&Record::Purge(purge.clone())).await
+    }
+
+    /// Append a possible prompt injection
+    pub async fn record_injection(&self, injection: &InjectionRecord) -> Result<()> {
+        self.append(&Record::Injection(injection.clone())).await
+    }
+
+    async fn append(&self, record: &Record) -> Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        let _append = self.append.lock().await;
+        if let Some(parent) = self.path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await
+            .with_context(|| format!("Failed to open audit log {}", self.path.display()))?;
+        file.write_all(&line)
+            .await
+            .with_context(|| format!("Failed to write audit log {}", self.path.display()))
+    }
+
+    /// Every recorded interaction in the order recorded, with its latest
+    /// rating; a missing file is an empty log
+    pub fn read(&self) -> Result<Vec<Interaction>> {
+        let mut interactions = Vec::new();
+        let mut ratings = HashMap::new();
+        for record in self.records()? {
+            match record {
+                Record::Interaction(interaction) => interactions.push(*interaction),
+                Record::Rating {
+                    request_id, score, ..
+                } => {
+                    ratings.insert(request_id, score);
+                }
+                Record::ToolDecision(_)
+                | Record::Purge(_)
+                | Record::Injection(_)
+                | Record::ToolArguments(_) => {}
+            }
+        }
+        for interaction in &mut interactions {
+            if let Some(score) = ratings.get(&interaction.request_id) {
+                interaction.rating = Some(*score);
+            }
+        }
+        Ok(interactions)
+    }
+
+    /// Every recorded tool policy decision in the order recorded
+    pub fn decisions(&self) -> Result<Vec<ToolDecision>> {
+        Ok(self
+            .records()?
+            .into_iter()
+            .filter_map(|record| match record {
+                Record::ToolDecision(decision) => Some(decision),
+                _ => None,
+            })
+            .collect())
+    }
+
+    /// Every recorded argument check in the order recorded
+    pub fn argument_checks(&self) -> Result<Vec<ArgumentCheck>> {
+        Ok(self
+            .records()?
+            .into_iter()
+            .filter_map(|record| match record {
+                Record::ToolArguments(check) => Some(check),
+                _ => None,
+            })
+            .collect())
+    }
+
+    /// Every recorded purge in the order recorded
+    pub fn purges(&self) -> Result<Vec<Purge>> {
+        Ok(self
+            .records()?
+            .into_iter()
+            .filter_map(|record| match record {
+                Record::Purge(purge) => Some(purge),
+                _ => None,
+            })
+            .collect())
+    }
+
+    /// Every recorded possible prompt injection in the order recorded
+    pub fn injections(&self) -> Result<Vec<InjectionRecord>> {
+        Ok(self
+            .records()?
+            .into_iter()
+            .filter_map(|record| match record {
+                Record::Injection(injection) => Some(injection),
+                _ => None,
+            })
+            .collect())
+    }
+
+    fn records(&self) -> Result<Vec<Record>> {
+        let text = match std::fs::read_to_string(&self.path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("Failed to read audit log {}", self.path.display()))
+            }
+        };
+        let mut records = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            records.push(serde_json::from_str(line).with_context(|| {
+                format!(
+                    "Invalid record on line {} of audit log {}",
+                    number + 1,
+                    self.path.display()
+                )
+            })?);
+        }
+        Ok(records)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::ChatMessage;
+
+    fn interaction(request_id: &str) -> Interaction {
+        Interaction {
+            request_id: request_id.to_string(),
+            at: DateTime::parse_from_rfc3339("2025-03-04T05:06:07Z")
+                .unwrap()
+                .into(),
+            provider: "mock".to_string(),
+            model: "mock-small".to_string(),
+            tags: vec!["support".to_string()],
+            request: ChatRequest {
+                messages: vec![ChatMessage::user("hi")],
+                ..ChatRequest::default()
+            },
+            response: NormalizedResponse::text("hello"),
+            rating: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_latest_rating_wins() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = AuditLog::new(dir.path().join("audit").join("log.jsonl"));
+        assert!(log.read().unwrap().is_empty());
+
+        log.record(&interaction("req-1")).await.unwrap();
+        log.record(&interaction("req-2")).await.unwrap();
+        let at = Utc::now();
+        log.rate("req-1", 2.0, at).await.unwrap();
+        log.rate("req-1", 5.0, at).await.unwrap();
+
+        let read = log.read().unwrap();
+        assert_eq!(read.len(), 2);
+        assert_eq!(read[0].rating, Some(5.0));
+        assert_eq!(read[1], interaction("req-2"));
+    }
+
+    #[tokio::test]
+    async fn test_rating_needs_a_recorded_interaction() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = AuditLog::new(dir.path().join("log.jsonl"));
+        log.record(&interaction("req-1")).await.unwrap();
+
+        let err = log.rate("req-9", 4.0, Utc::now()).await.unwrap_err();
+        assert!(
+            err.to_string().contains("No interaction 'req-9'"),
+            "{}",
+            err
+        );
+        assert!(log.rate("req-1", f32::NAN, Utc::now()).await.is_err());
+    }
+}
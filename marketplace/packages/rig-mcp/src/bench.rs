@@ -0,0 +1,767 @@
+//! Provider benchmarks
+//!
+//! [`run`] sends every prompt to each [`BenchTarget`] a number of times,
+//! after a few warmup completions that aren't measured, and streams each
+//! completion to time its first token as well as the whole response. The
+//! [`BenchReport`] prints as a comparison table and exports to JSON or CSV.
+//! Failed completions are counted per target and don't stop the run.
+//!
+//! [`run_embeddings`] does the same for embedding models, embedding the
+//! whole text set in one call per run, so local and hosted models can be
+//! compared on throughput.
+//!
+//! Targets run one after another, so they don't compete for bandwidth or
+//! rate limits. Time is measured with [`tokio::time::Instant`], which tests
+//! can pause.
+
+use anyhow::{Context, Result};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::Instant;
+
+use crate::embedding::EmbeddingModel;
+use crate::pricing::Price;
+use crate::provider::{ChatMessage, ChatRequest, NormalizedUsage, Provider, StreamChunk};
+use crate::tokens::TokenCounter;
+
+/// Columns of [`BenchReport::write_csv`]; times are in milliseconds and
+/// cost in USD, cells without a value are empty
+pub const CSV_HEADER: &str = "target,provider,model,completed,failures,ttft_p50_ms,ttft_p95_ms,latency_p50_ms,latency_p95_ms,tokens_per_sec_p50,tokens_per_sec_p95,prompt_tokens,completion_tokens,cost_usd";
+
+/// How often to run each prompt
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BenchOptions {
+    /// Measured completions per prompt and target
+    pub runs: usize,
+    /// Completions per prompt and target before measuring; their results
+    /// and failures are discarded
+    pub warmup: usize,
+    /// Passed on with every request
+    pub max_tokens: Option<usize>,
+    /// A completion taking longer fails
+    pub timeout: Duration,
+}
+
+impl Default for BenchOptions {
+    fn default() -> Self {
+        Self {
+            runs: 5,
+            warmup: 1,
+            max_tokens: None,
+            timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+/// A provider to benchmark
+pub struct BenchTarget {
+    /// Name to report it by, e.g. the alias it was resolved from
+    pub label: String,
+    pub provider: Arc<dyn Provider>,
+    /// `None` leaves its cost out of the report
+    pub price: Option<Price>,
+    /// Estimates usage the provider doesn't report
+    pub tokens: TokenCounter,
+}
+
+/// Percentiles and mean of a set of measurements
+///
+/// Percentiles are nearest-rank: the smallest measurement that at least the
+/// given share of measurements don't exceed.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Stats {
+    pub p50: f64,
+    pub p95: f64,
+    pub mean: f64,
+}
+
+impl Stats {
+    /// Statistics of `values`, `None` when there are none
+    pub fn of(values: &[f64]) -> Option<Self> {
+        if values.is_empty() {
+            return None;
+        }
+        let mut sorted = values.to_vec();
+        sorted.sort_by(f64::total_cmp);
+        let rank = |percentile: f64| {
+            let rank = (percentile / 100.0 * sorted.len() as f64).ceil() as usize;
+            sorted[rank.clamp(1, sorted.len()) - 1]
+        };
+        Some(Self {
+            p50: rank(50.0),
+            p95: rank(95.0),
+            mean: sorted.iter().sum::<f64>() / sorted.len() as f64,
+        })
+    }
+}
+
+/// Measurements of one target
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TargetResult {
+    pub target: String,
+    pub provider: String,
+    pub model: String,
+    /// Measured completions that succeeded
+    pub completed: usize,
+    /// Measured completions that failed or timed out
+    pub failures: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+    /// Time to the first text or tool call chunk
+    pub ttft_ms: Option<Stats>,
+    /// Time to the end of the stream
+    pub latency_ms: Option<Stats>,
+    /// Completion tokens over the whole latency
+    pub tokens_per_sec: Option<Stats>,
+    /// Summed over the completed runs
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    /// Whether any completed run's usage was estimated because the provider
+    /// didn't report it
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub estimated_usage: bool,
+    /// Total of the completed runs; `None` without a price
+    pub cost_usd: Option<f64>,
+}
+
+impl TargetResult {
+    /// Average cost of a completed run
+    pub fn cost_per_run(&self) -> Option<f64> {
+        self.cost_usd
+            .filter(|_| self.completed > 0)
+            .map(|cost| cost / self.completed as f64)
+    }
+}
+
+/// Result of [`run`], one entry per target in the order given
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BenchReport {
+    pub prompts: usize,
+    pub options: BenchOptions,
+    pub results: Vec<TargetResult>,
+}
+
+impl BenchReport {
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// One row per target under [`CSV_HEADER`]
+    pub fn write_csv<W: Write>(&self, mut out: W) -> io::Result<()> {
+        writeln!(out, "{}", CSV_HEADER)?;
+        for result in &self.results {
+            let cells = [
+                csv_text(&result.target),
+                csv_text(&result.provider),
+                csv_text(&result.model),
+                result.completed.to_string(),
+                result.failures.to_string(),
+                csv_number(result.ttft_ms.map(|s| s.p50)),
+                csv_number(result.ttft_ms.map(|s| s.p95)),
+                csv_number(result.latency_ms.map(|s| s.p50)),
+                csv_number(result.latency_ms.map(|s| s.p95)),
+                csv_number(result.tokens_per_sec.map(|s| s.p50)),
+                csv_number(result.tokens_per_sec.map(|s| s.p95)),
+                result.prompt_tokens.to_string(),
+                result.completion_tokens.to_string(),
+                csv_number(result.cost_usd),
+            ];
+            writeln!(out, "{}", cells.join(","))?;
+        }
+        Ok(())
+    }
+}
+
+fn csv_text(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn csv_number(value: Option<f64>) -> String {
+    value.map(|value| value.to_string()).unwrap_or_default()
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ms = |stats: Option<Stats>| stats.map_or("-".to_string(), |s| format!("{:.0}", s.p50));
+        let ms95 =
+            |stats: Option<Stats>| stats.map_or("-".to_string(), |s| format!("{:.0}", s.p95));
+        writeln!(
+            f,
+            "{:<24} {:>5} {:>5} {:>9} {:>9} {:>9} {:>9} {:>8} {:>10}",
+            "target", "ok", "fail", "ttft p50", "ttft p95", "lat p50", "lat p95", "tok/s", "$/run"
+        )?;
+        for result in &self.results {
+            writeln!(
+                f,
+                "{:<24} {:>5} {:>5} {:>9} {:>9} {:>9} {:>9} {:>8} {:>10}",
+                result.target,
+                result.completed,
+                result.failures,
+                ms(result.ttft_ms),
+                ms95(result.ttft_ms),
+                ms(result.latency_ms),
+                ms95(result.latency_ms),
+                result
+                    .tokens_per_sec
+                    .map_or("-".to_string(), |s| format!("{:.1}", s.p50)),
+                result
+                    .cost_per_run()
+                    .map_or("-".to_string(), |cost| format!("{:.6}", cost)),
+            )?;
+        }
+        write!(
+            f,
+            "{} prompts x {} runs after {} warmup; times in ms",
+            self.prompts, self.options.runs, self.options.warmup
+        )
+    }
+}
+
+/// An embedding model to benchmark
+pub struct EmbeddingBenchTarget {
+    pub label: String,
+    pub model: Arc<dyn EmbeddingModel>,
+}
+
+/// Measurements of one embedding model
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EmbeddingTargetResult {
+    pub target: String,
+    pub provider: String,
+    pub model: String,
+    /// Measured runs that succeeded
+    pub completed: usize,
+    /// Measured runs that failed or timed out
+    pub failures: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+    /// Length of the vectors returned
+    pub dimensions: Option<usize>,
+    /// Time to embed the whole text set
+    pub latency_ms: Option<Stats>,
+    pub texts_per_sec: Option<Stats>,
+}
+
+/// Result of [`run_embeddings`], one entry per target in the order given
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EmbeddingBenchReport {
+    pub texts: usize,
+    pub options: BenchOptions,
+    pub results: Vec<EmbeddingTargetResult>,
+}
+
+impl EmbeddingBenchReport {
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+impl fmt::Display for EmbeddingBenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let stat = |stats: Option<Stats>, p95: bool, precision: usize| {
+            stats.map_or("-".to_string(), |s| {
+                format!("{:.*}", precision, if p95 { s.p95 } else { s.p50 })
+            })
+        };
+        writeln!(
+            f,
+            "{:<32} {:>5} {:>5} {:>6} {:>9} {:>9} {:>10}",
+            "target", "ok", "fail", "dims", "lat p50", "lat p95", "texts/s"
+        )?;
+        for result in &self.results {
+            writeln!(
+                f,
+                "{:<32} {:>5} {:>5} {:>6} {:>9} {:>9} {:>10}",
+                result.target,
+                result.completed,
+                result.failures,
+                result
+                    .dimensions
+                    .map_or("-".to_string(), |dims| dims.to_string()),
+                stat(result.latency_ms, false, 0),
+                stat(result.latency_ms, true, 0),
+                stat(result.texts_per_sec, false, 1),
+            )?;
+        }
+        write!(
+            f,
+            "{} texts x {} runs after {} warmup; times in ms",
+            self.texts, self.options.runs, self.options.warmup
+        )
+    }
+}
+
+/// Read a prompt set: a `.json` file holds an array of strings, any other
+/// file one prompt per line, skipping blank lines and `#` comments
+pub fn load_prompts(path: &Path) -> Result<Vec<String>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read prompts from {}", path.display()))?;
+    let prompts: Vec<String> = if path.extension().is_some_and(|ext| ext == "json") {
+        serde_json::from_str(&content)
+            .with_context(|| format!("{} is not a JSON array of strings", path.display()))?
+    } else {
+        content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(str::to_string)
+            .collect()
+    };
+    if prompts.is_empty() {
+        anyhow::bail!("{} contains no prompts", path.display());
+    }
+    Ok(prompts)
+}
+
+/// Benchmark `targets` on `prompts`
+pub async fn run(
+    targets: &[BenchTarget], prompts: &[String], options: &BenchOptions,
+) -> BenchReport {
+    let mut results = Vec::with_capacity(targets.len());
+    for target in targets {
+        let mut samples = Vec::new();
+        let mut failures = 0;
+        let mut last_error = None;
+        for prompt in prompts {
+            for _ in 0..options.warmup {
+                let _ = measure(target, prompt, options).await;
+            }
+            for _ in 0..options.runs {
+                match measure(target, prompt, options).await {
+                    Ok(sample) => samples.push(sample),
+                    Err(err) => {
+                        tracing::debug!(
+                            label = %target.label,
+                            error = %err,
+                            "Benchmark run failed"
+                        );
+                        failures += 1;
+                        last_error = Some(format!("{:#}", err));
+                    }
+                }
+            }
+        }
+        results.push(summarize(target, &samples, failures, last_error));
+    }
+    BenchReport {
+        prompts: prompts.len(),
+        options: *options,
+        results,
+    }
+}
+
+/// Benchmark embedding `targets` on `texts`
+///
+/// Warmup runs also load models that load lazily, such as local ones, so
+/// set `warmup` to keep loading out of the measurements.
+pub async fn run_embeddings(
+    targets: &[EmbeddingBenchTarget], texts: &[String], options: &BenchOptions,
+) -> EmbeddingBenchReport {
+    let mut results = Vec::with_capacity(targets.len());
+    for target in targets {
+        for _ in 0..options.warmup {
+            let _ = measure_embedding(target, texts, options).await;
+        }
+        let mut latencies = Vec::new();
+        let mut dimensions = target.model.dimensions();
+        let mut failures = 0;
+        let mut last_error = None;
+        for _ in 0..options.runs {
+            match measure_embedding(target, texts, options).await {
+                Ok((latency, dims)) => {
+                    latencies.push(latency);
+                    dimensions = dimensions.or(dims);
+                }
+                Err(err) => {
+                    tracing::debug!(
+                        label = %target.label,
+                        error = %err,
+                        "Embedding benchmark run failed"
+                    );
+                    failures += 1;
+                    last_error = Some(format!("{:#}", err));
+                }
+            }
+        }
+        let millis: Vec<f64> = latencies
+            .iter()
+            .map(|time| time.as_micros() as f64 / 1000.0)
+            .collect();
+        let throughput: Vec<f64> = latencies
+            .iter()
+            .filter(|time| !time.is_zero())
+            .map(|time| texts.len() as f64 / time.as_secs_f64())
+            .collect();
+        results.push(EmbeddingTargetResult {
+            target: target.label.clone(),
+            provider: target.model.provider().to_string(),
+            model: target.model.model().to_string(),
+            completed: latencies.len(),
+            failures,
+            last_error,
+            dimensions,
+            latency_ms: Stats::of(&millis),
+            texts_per_sec: Stats::of(&throughput),
+        });
+    }
+    EmbeddingBenchReport {
+        texts: texts.len(),
+        options: *options,
+        results,
+    }
+}
+
+/// Time to embed `texts` and the length of the vectors
+async fn measure_embedding(
+    target: &EmbeddingBenchTarget, texts: &[String], options: &BenchOptions,
+) -> Result<(Duration, Option<usize>)> {
+    let start = Instant::now();
+    let vectors = tokio::time::timeout(options.timeout, target.model.embed_texts(texts))
+        .await
+        .map_err(|_| anyhow::anyhow!("No embeddings within {:?}", options.timeout))??;
+    if vectors.len() != texts.len() {
+        anyhow::bail!("{} vectors for {} texts", vectors.len(), texts.len());
+    }
+    Ok((start.elapsed(), vectors.first().map(Vec::len)))
+}
+
+/// One completed run
+struct Sample {
+    ttft: Duration,
+    latency: Duration,
+    usage: NormalizedUsage,
+}
+
+async fn measure(target: &BenchTarget, prompt: &str, options: &BenchOptions) -> Result<Sample> {
+    let request = ChatRequest {
+        messages: vec![ChatMessage::user(prompt)],
+        max_tokens: options.max_tokens,
+        ..ChatRequest::default()
+    };
+    let counter = &target.tokens;
+    let prompt_tokens = counter.count_request(&request);
+    let start = Instant::now();
+    let streamed = async {
+        let mut stream = target.provider.stream(request).await?;
+        let mut first = None;
+        let mut text = String::new();
+        let mut usage = None;
+        while let Some(chunk) = stream.next().await {
+            match chunk? {
+                StreamChunk::Text(delta) => {
+                    first.get_or_insert_with(|| start.elapsed());
+                    text.push_str(&delta);
+                }
+                StreamChunk::ToolCall(call) => {
+                    first.get_or_insert_with(|| start.elapsed());
+                    text.push_str(&call.arguments.to_string());
+                }
+                StreamChunk::Usage(reported) => usage = Some(reported),
+            }
+        }
+        anyhow::Ok((first, text, usage))
+    };
+    let (first, text, usage) = tokio::time::timeout(options.timeout, streamed)
+        .await
+        .map_err(|_| anyhow::anyhow!("No complete response within {:?}", options.timeout))??;
+    let latency = start.elapsed();
+    // Zero completion tokens for a non-empty answer means nothing was reported
+    let usage = usage
+        .filter(|usage| usage.completion_tokens > 0 || text.is_empty())
+        .unwrap_or_else(|| NormalizedUsage::estimate(prompt_tokens, counter.count(&text)));
+    Ok(Sample {
+        ttft: first.unwrap_or(latency),
+        latency,
+        usage,
+    })
+}
+
+fn summarize(
+    target: &BenchTarget, samples: &[Sample], failures: usize, last_error: Option<String>,
+) -> TargetResult {
+    let millis = |time: Duration| time.as_micros() as f64 / 1000.0;
+    let ttft: Vec<f64> = samples.iter().map(|s| millis(s.ttft)).collect();
+    let latency: Vec<f64> = samples.iter().map(|s| millis(s.latency)).collect();
+    let throughput: Vec<f64> = samples
+        .iter()
+        .filter(|s| !s.latency.is_zero())
+        .map(|s| s.usage.completion_tokens as f64 / s.latency.as_secs_f64())
+        .collect();
+    let prompt_tokens = samples.iter().map(|s| s.usage.prompt_tokens).sum();
+    let completion_tokens = samples.iter().map(|s| s.usage.completion_tokens).sum();
+    TargetResult {
+        target: target.label.clone(),
+        provider: target.provider.name().to_string(),
+        model: target.provider.model().to_string(),
+        completed: samples.len(),
+        failures,
+        last_error,
+        ttft_ms: Stats::of(&ttft),
+        latency_ms: Stats::of(&latency),
+        tokens_per_sec: Stats::of(&throughput),
+        prompt_tokens,
+        completion_tokens,
+        estimated_usage: samples.iter().any(|s| s.usage.estimated),
+        cost_usd: target
+            .price
+            .map(|price| price.cost(&NormalizedUsage::new(prompt_tokens, completion_tokens))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::{ChatStream, NormalizedResponse};
+    use async_trait::async_trait;
+    use futures::stream;
+    use std::collections::VecDeque;
+    use std::sync::Mutex;
+
+    /// Streams one text chunk after `ttft` and the usage after `rest`, or
+    /// fails for `None`
+    struct Scripted {
+        name: &'static str,
+        script: Mutex<VecDeque<Option<(u64, u64)>>>,
+    }
+
+    impl Scripted {
+        fn new(name: &'static str, script: Vec<Option<(u64, u64)>>) -> Arc<Self> {
+            Arc::new(Self {
+                name,
+                script: Mutex::new(script.into()),
+            })
+        }
+    }
+
+    #[async_trait]
+    impl Provider for Scripted {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn model(&self) -> &str {
+            "scripted"
+        }
+
+        async fn complete(&self, _request: ChatRequest) -> Result<NormalizedResponse> {
+            unreachable!("benchmarks stream")
+        }
+
+        async fn stream(&self, _request: ChatRequest) -> Result<ChatStream> {
+            let Some((ttft, rest)) = self.script.lock().unwrap().pop_front().unwrap() else {
+                anyhow::bail!("Provider '{}' returned HTTP 503", self.name);
+            };
+            let first = stream::once(async move {
+                tokio::time::sleep(Duration::from_millis(ttft)).await;
+                anyhow::Ok(StreamChunk::Text("twenty tokens of answer".to_string()))
+            });
+            let last = stream::once(async move {
+                tokio::time::sleep(Duration::from_millis(rest)).await;
+                anyhow::Ok(StreamChunk::Usage(NormalizedUsage::new(1000, 20)))
+            });
+            Ok(first.chain(last).boxed())
+        }
+    }
+
+    #[test]
+    fn test_nearest_rank_percentiles() {
+        let stats = Stats::of(&[5.0, 1.0, 4.0, 2.0, 3.0]).unwrap();
+        assert_eq!((stats.p50, stats.p95, stats.mean), (3.0, 5.0, 3.0));
+        assert_eq!(Stats::of(&[7.0]).unwrap().p95, 7.0);
+        assert!(Stats::of(&[]).is_none());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_two_providers_with_scripted_latencies() {
+        // A warmup run, then time to first token of 10..=100 ms and 10 ms more
+        let fast = std::iter::once(Some((500, 500)))
+            .chain((1..=10).map(|i| Some((i * 10, 10))))
+            .collect();
+        // A failed warmup run, then two failures among eight runs
+        let slow = vec![
+            None,
+            Some((300, 100)),
+            None,
+            Some((300, 100)),
+            Some((300, 100)),
+            Some((500, 100)),
+            Some((300, 100)),
+            None,
+            Some((300, 100)),
+            Some((500, 100)),
+            Some((300, 100)),
+        ];
+        let targets = [
+            BenchTarget {
+                label: "fast".to_string(),
+                provider: Scripted::new("fast", fast),
+                price: Some(Price {
+                    input: 1.0,
+                    output: 2.0,
+                }),
+                tokens: TokenCounter::default(),
+            },
+            BenchTarget {
+                label: "slow".to_string(),
+                provider: Scripted::new("slow", slow),
+                price: None,
+                tokens: TokenCounter::default(),
+            },
+        ];
+        let options = BenchOptions {
+            runs: 10,
+            warmup: 1,
+            ..BenchOptions::default()
+        };
+
+        let report = run(&targets, &["Hello".to_string()], &options).await;
+
+        let fast = &report.results[0];
+        assert_eq!((fast.completed, fast.failures), (10, 0));
+        let ttft = fast.ttft_ms.unwrap();
+        assert_eq!((ttft.p50, ttft.p95, ttft.mean), (50.0, 100.0, 55.0));
+        let latency = fast.latency_ms.unwrap();
+        assert_eq!((latency.p50, latency.p95), (60.0, 110.0));
+        // 20 tokens in 70 ms and in 20 ms
+        let throughput = fast.tokens_per_sec.unwrap();
+        assert!((throughput.p50 - 20.0 / 0.07).abs() < 1e-9);
+        assert!((throughput.p95 - 1000.0).abs() < 1e-9);
+        assert_eq!((fast.prompt_tokens, fast.completion_tokens), (10_000, 200));
+        assert!((fast.cost_usd.unwrap() - 0.0104).abs() < 1e-12);
+        assert!((fast.cost_per_run().unwrap() - 0.00104).abs() < 1e-12);
+
+        let slow = &report.results[1];
+        assert_eq!((slow.completed, slow.failures), (8, 2));
+        assert_eq!(
+            slow.last_error.as_deref(),
+            Some("Provider 'slow' returned HTTP 503")
+        );
+        let ttft = slow.ttft_ms.unwrap();
+        assert_eq!((ttft.p50, ttft.p95), (300.0, 500.0));
+        let latency = slow.latency_ms.unwrap();
+        assert_eq!((latency.p50, latency.p95), (400.0, 600.0));
+        assert_eq!(slow.cost_usd, None);
+
+        let mut csv = Vec::new();
+        report.write_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], CSV_HEADER);
+        assert_eq!(lines[0].split(',').count(), 14);
+        assert!(lines[1].starts_with("fast,fast,scripted,10,0,50,100,60,110,"));
+        assert!(lines[1].ends_with(",10000,200,0.0104"));
+        assert_eq!(
+            lines[2],
+            "slow,slow,scripted,8,2,300,500,400,600,50,50,8000,160,"
+        );
+
+        let json: serde_json::Value = serde_json::from_str(&report.to_json().unwrap()).unwrap();
+        assert_eq!(json["results"][1]["failures"], 2);
+        assert_eq!(json["options"]["runs"], 10);
+    }
+
+    /// Takes `millis` per text, failing on the calls in `failing`
+    struct ScriptedEmbedder {
+        provider: &'static str,
+        millis: u64,
+        failing: Vec<usize>,
+        calls: Mutex<usize>,
+    }
+
+    #[async_trait]
+    impl EmbeddingModel for ScriptedEmbedder {
+        fn provider(&self) -> &str {
+            self.provider
+        }
+
+        fn model(&self) -> &str {
+            "scripted"
+        }
+
+        async fn embed_texts(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+            let call = {
+                let mut calls = self.calls.lock().unwrap();
+                *calls += 1;
+                *calls
+            };
+            tokio::time::sleep(Duration::from_millis(self.millis * texts.len() as u64)).await;
+            if self.failing.contains(&call) {
+                anyhow::bail!("Provider '{}' returned HTTP 429", self.provider);
+            }
+            Ok(vec![vec![0.5; 384]; texts.len()])
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_embedding_throughput_of_two_models() {
+        let target = |provider, millis, failing| EmbeddingBenchTarget {
+            label: provider.to_string(),
+            model: Arc::new(ScriptedEmbedder {
+                provider,
+                millis,
+                failing,
+                calls: Mutex::new(0),
+            }),
+        };
+        // The first call is the warmup, so its failure isn't counted
+        let targets = [target("local", 2, vec![1]), target("openai", 25, vec![3])];
+        let texts: Vec<String> = (0..40).map(|i| format!("tool {}", i)).collect();
+        let options = BenchOptions {
+            runs: 4,
+            warmup: 1,
+            ..BenchOptions::default()
+        };
+
+        let report = run_embeddings(&targets, &texts, &options).await;
+
+        let local = &report.results[0];
+        assert_eq!((local.completed, local.failures), (4, 0));
+        assert_eq!(local.dimensions, Some(384));
+        assert_eq!(local.latency_ms.unwrap().p50, 80.0);
+        assert!((local.texts_per_sec.unwrap().p50 - 500.0).abs() < 1e-9);
+
+        let openai = &report.results[1];
+        assert_eq!((openai.completed, openai.failures), (3, 1));
+        assert_eq!(
+            openai.last_error.as_deref(),
+            Some("Provider 'openai' returned HTTP 429")
+        );
+        assert_eq!(openai.texts_per_sec.unwrap().p50, 40.0);
+
+        let table = report.to_string();
+        assert!(table.contains("texts/s"), "{}", table);
+        assert!(table.ends_with("40 texts x 4 runs after 1 warmup; times in ms"));
+        let json: serde_json::Value = serde_json::from_str(&report.to_json().unwrap()).unwrap();
+        assert_eq!(json["results"][1]["failures"], 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_stalled_run_times_out() {
+        let provider = Scripted::new("stalled", vec![Some((5_000, 0))]);
+        let options = BenchOptions {
+            runs: 1,
+            warmup: 0,
+            timeout: Duration::from_secs(1),
+            ..BenchOptions::default()
+        };
+        let targets = [BenchTarget {
+            label: "stalled".to_string(),
+            provider,
+            price: None,
+            tokens: TokenCounter::default(),
+        }];
+        let report = run(&targets, &["Hello".to_string()], &options).await;
+        assert_eq!(report.results[0].failures, 1);
+        assert!(report.results[0].ttft_ms.is_none());
+    }
+}
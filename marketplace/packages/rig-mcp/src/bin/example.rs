@@ -1,13 +1,35 @@
-use rig_mcp_integration::{prelude::*, example::run_example};
+use rig_mcp_integration::example::{
+    run_bench, run_eval, run_example, run_export, run_index, run_providers_check, run_replay,
+};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("export") {
+        return run_export(&args[1..]);
+    }
+    if args.first().map(String::as_str) == Some("bench") {
+        return run_bench(&args[1..]).await;
+    }
+    if args.first().map(String::as_str) == Some("eval") {
+        return run_eval(&args[1..]).await;
+    }
+    if args.first().map(String::as_str) == Some("replay") {
+        return run_replay(&args[1..]).await;
+    }
+    if args.first().map(String::as_str) == Some("index") {
+        return run_index(&args[1..]).await;
+    }
+    if args.iter().map(String::as_str).eq(["providers", "check"]) {
+        return run_providers_check().await;
+    }
+
     println!("🚀 Rig MCP Integration Example");
     println!("==============================");
 
     run_example().await?;
 
-    println!("✅ Example completed successfully!");
+    println!("👋 Bye!");
 
     Ok(())
 }
@@ -0,0 +1,199 @@
+//! Limits on a single agent run
+//!
+//! A [`RunBudget`] caps the tokens, tool calls and wall time one call to
+//! [`crate::Agent::chat`] may consume. The agent checks it before every
+//! completion and every tool invocation, and while a completion streams, using
+//! an estimate of its tokens; when a limit is reached the run stops with a
+//! [`BudgetExceeded`] error carrying what was consumed and the transcript so
+//! far.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+use crate::provider::{ChatMessage, NormalizedUsage};
+
+/// Limits for one agent run; `None` means unlimited
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RunBudget {
+    /// Prompt plus completion tokens over all completions of the run
+    pub max_total_tokens: Option<u64>,
+    /// Tool invocations, including failed ones
+    pub max_tool_calls: Option<usize>,
+    /// Time from the prompt to the final answer; whole seconds in config files
+    #[serde(with = "seconds")]
+    pub max_wall_time: Option<Duration>,
+}
+
+impl RunBudget {
+    /// No limits at all
+    pub fn unlimited() -> Self {
+        Self::default()
+    }
+
+    pub fn max_total_tokens(mut self, tokens: u64) -> Self {
+        self.max_total_tokens = Some(tokens);
+        self
+    }
+
+    pub fn max_tool_calls(mut self, calls: usize) -> Self {
+        self.max_tool_calls = Some(calls);
+        self
+    }
+
+    pub fn max_wall_time(mut self, time: Duration) -> Self {
+        self.max_wall_time = Some(time);
+        self
+    }
+}
+
+/// The limit a run ran into
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BudgetLimit {
+    TotalTokens(u64),
+    ToolCalls(usize),
+    WallTime(Duration),
+}
+
+impl fmt::Display for BudgetLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BudgetLimit::TotalTokens(tokens) => write!(f, "{} total tokens", tokens),
+            BudgetLimit::ToolCalls(calls) => write!(f, "{} tool calls", calls),
+            BudgetLimit::WallTime(time) => write!(f, "{:?} wall time", time),
+        }
+    }
+}
+
+/// What a run has consumed so far
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RunUsage {
+    /// Summed over every completion of the run; `estimated` if any of them
+    /// was
+    pub tokens: NormalizedUsage,
+    pub completions: usize,
+    pub tool_calls: usize,
+    pub elapsed: Duration,
+}
+
+/// A run stopped because it reached a limit of its [`RunBudget`]
+///
+/// Returned inside the `anyhow::Error` of [`crate::Agent::chat`]; use
+/// `downcast_ref::<BudgetExceeded>()` to get at it.
+#[derive(Debug, Clone, Error)]
+#[error(
+    "Agent run exceeded its budget of {limit} after {} tokens, {} tool calls and {:?}",
+    .consumed.tokens.total_tokens,
+    .consumed.tool_calls,
+    .consumed.elapsed
+)]
+pub struct BudgetExceeded {
+    pub limit: BudgetLimit,
+    pub consumed: RunUsage,
+    /// Messages of the run up to the point it was stopped, starting with the
+    /// prompt
+    pub transcript: Vec<ChatMessage>,
+}
+
+/// Consumption of a run in progress, checked against its budget
+pub(crate) struct BudgetMeter {
+    budget: RunBudget,
+    started: Instant,
+    usage: RunUsage,
+}
+
+impl BudgetMeter {
+    pub(crate) fn start(budget: RunBudget) -> Self {
+        Self {
+            budget,
+            started: Instant::now(),
+            usage: RunUsage::default(),
+        }
+    }
+
+    pub(crate) fn record_completion(&mut self, usage: &NormalizedUsage) {
+        self.usage.completions += 1;
+        self.usage.tokens += *usage;
+    }
+
+    pub(crate) fn record_tool_call(&mut self) {
+        self.usage.tool_calls += 1;
+    }
+
+    /// Tokens of every completion so far
+    pub(crate) fn tokens(&self) -> NormalizedUsage {
+        self.usage.tokens
+    }
+
+    /// Tokens left before the token limit, if there is one
+    pub(crate) fn tokens_left(&self) -> Option<u64> {
+        let max = self.budget.max_total_tokens?;
+        Some(max.saturating_sub(self.usage.tokens.total_tokens))
+    }
+
+    /// The token limit, if there is one
+    pub(crate) fn token_limit(&self) -> Option<BudgetLimit> {
+        self.budget.max_total_tokens.map(BudgetLimit::TotalTokens)
+    }
+
+    /// Time left before the wall-time limit, if there is one
+    pub(crate) fn remaining_time(&self) -> Option<Duration> {
+        let limit = self.budget.max_wall_time?;
+        Some(limit.saturating_sub(self.started.elapsed()))
+    }
+
+    /// The limit that forbids another completion
+    pub(crate) fn before_completion(&self) -> Option<BudgetLimit> {
+        match self.budget.max_total_tokens {
+            Some(max) if self.usage.tokens.total_tokens >= max => {
+                Some(BudgetLimit::TotalTokens(max))
+            }
+            _ => self.out_of_time(),
+        }
+    }
+
+    /// The limit that forbids another tool invocation
+    pub(crate) fn before_tool_call(&self) -> Option<BudgetLimit> {
+        match self.budget.max_tool_calls {
+            Some(max) if self.usage.tool_calls >= max => Some(BudgetLimit::ToolCalls(max)),
+            _ => self.out_of_time(),
+        }
+    }
+
+    pub(crate) fn out_of_time(&self) -> Option<BudgetLimit> {
+        let limit = self.budget.max_wall_time?;
+        (self.started.elapsed() >= limit).then_some(BudgetLimit::WallTime(limit))
+    }
+
+    pub(crate) fn exceeded(
+        &self, limit: BudgetLimit, transcript: &[ChatMessage],
+    ) -> BudgetExceeded {
+        BudgetExceeded {
+            limit,
+            consumed: RunUsage {
+                elapsed: self.started.elapsed(),
+                ..self.usage
+            },
+            transcript: transcript.to_vec(),
+        }
+    }
+}
+
+/// `Option<Duration>` as whole seconds
+pub(crate) mod seconds {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+
+    pub fn serialize<S: Serializer>(value: &Option<Duration>, s: S) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(duration) => s.serialize_some(&duration.as_secs()),
+            None => s.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Duration>, D::Error> {
+        Ok(Option::<u64>::deserialize(d)?.map(Duration::from_secs))
+    }
+}
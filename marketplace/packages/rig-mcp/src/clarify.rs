@@ -0,0 +1,265 @@
+//! Runs that pause to ask the user
+//!
+//! An agent that lacks information would otherwise have to guess. Agents built
+//! with [`crate::AgentBuilder::ask_user`] are offered an extra `ask_user` tool;
+//! when the model calls it the run stops instead of invoking anything, and
+//! [`crate::RigMcpClient::run`] returns [`RunOutcome::NeedsInput`] with the
+//! question and a token holding the paused run.
+//!
+//! The token is the serialized [`RunState`], so it can be stored or handed
+//! to another process. [`crate::RigMcpClient::resume`] decodes it, sends the
+//! answer as the result of the `ask_user` call and carries on with the run.
+//! Tokens expire after the client's pause TTL, one hour by default; resuming
+//! later fails with [`RunStateExpired`].
+//!
+//! The budget applies to each leg of a run separately, since a run may stay
+//! paused far longer than any wall-time limit.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::time::Duration;
+use thiserror::Error;
+
+use crate::provider::{ChatMessage, NormalizedResponse, ToolCall, ToolDefinition};
+
+/// Name of the pseudo-tool the model calls to ask a question
+pub const ASK_USER_TOOL: &str = "ask_user";
+
+/// How long a paused run can be resumed by default
+pub const DEFAULT_PAUSE_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Definition of the `ask_user` tool offered to the model
+pub fn ask_user_definition() -> ToolDefinition {
+    ToolDefinition {
+        name: ASK_USER_TOOL.to_string(),
+        description: "Ask the user a question when you cannot continue without \
+                      information only they have. Do not guess instead."
+            .to_string(),
+        parameters: json!({
+            "type": "object",
+            "properties": {
+                "question": {"type": "string", "description": "The question to ask"},
+                "field_schema": {
+                    "type": "object",
+                    "description": "JSON Schema of the expected answer; a string if omitted"
+                }
+            },
+            "required": ["question"]
+        }),
+    }
+}
+
+/// A question the model asked through `ask_user`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Question {
+    /// Id of the `ask_user` call the answer is the result of
+    pub call_id: String,
+    pub question: String,
+    /// JSON Schema of the expected answer
+    pub field_schema: Value,
+}
+
+impl Question {
+    pub(crate) fn from_call(call: &ToolCall) -> Self {
+        let question = match &call.arguments["question"] {
+            Value::String(question) => question.clone(),
+            Value::Null => String::new(),
+            other => other.to_string(),
+        };
+        let field_schema = match &call.arguments["field_schema"] {
+            Value::Object(_) => call.arguments["field_schema"].clone(),
+            _ => json!({"type": "string"}),
+        };
+        Self {
+            call_id: call.id.clone(),
+            question,
+            field_schema,
+        }
+    }
+
+    /// The tool message carrying `answer`; strings are sent as they are
+    pub(crate) fn answer(&self, answer: &Value) -> ChatMessage {
+        let content = match answer {
+            Value::String(text) => text.clone(),
+            other => other.to_string(),
+        };
+        ChatMessage::tool(&self.call_id, content)
+    }
+}
+
+/// Where an agent run that may ask questions stopped
+#[derive(Debug, Clone, PartialEq)]
+pub enum Step {
+    Answered(NormalizedResponse),
+    Asked(Question),
+}
+
+/// How a run through the client ended
+#[derive(Debug, Clone, PartialEq)]
+pub enum RunOutcome {
+    Completed(NormalizedResponse),
+    /// The model asked a question; pass the answer and `run_state_token` to
+    /// [`crate::RigMcpClient::resume`]
+    NeedsInput {
+        question: String,
+        field_schema: Value,
+        run_state_token: String,
+    },
+}
+
+/// A paused run, as encoded in its token
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunState {
+    /// Provider name, model alias or `provider/model` the run started with
+    pub agent: String,
+    pub history: Vec<ChatMessage>,
+    pub question: Question,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl RunState {
+    /// Hex-encoded JSON, safe in URLs and headers
+    pub fn to_token(&self) -> Result<String> {
+        let json = serde_json::to_vec(self).context("Failed to encode run state")?;
+        Ok(json.iter().map(|byte| format!("{:02x}", byte)).collect())
+    }
+
+    pub fn from_token(token: &str) -> Result<Self> {
+        let bytes = (0..token.len())
+            .step_by(2)
+            .map(|i| {
+                token
+                    .get(i..i + 2)
+                    .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+            })
+            .collect::<Option<Vec<u8>>>()
+            .context("Run state token is not valid hex")?;
+        serde_json::from_slice(&bytes).context("Run state token is malformed")
+    }
+
+    /// Fails with [`RunStateExpired`] once `now` is past the expiry
+    pub fn check_expiry(&self, now: DateTime<Utc>) -> Result<(), RunStateExpired> {
+        if now >= self.expires_at {
+            return Err(RunStateExpired {
+                expired_at: self.expires_at,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// A run state token was used after it expired
+///
+/// Returned inside the `anyhow::Error` of [`crate::RigMcpClient::resume`].
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("Run state token expired at {expired_at}")]
+pub struct RunStateExpired {
+    pub expired_at: DateTime<Utc>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::Role;
+    use crate::testing::{FixedClock, MockProvider};
+    use crate::{Config, RigMcpClient};
+    use std::sync::Arc;
+
+    fn client() -> (RigMcpClient, Arc<MockProvider>, Arc<FixedClock>) {
+        let provider = Arc::new(MockProvider::new("mock"));
+        let start = chrono::DateTime::parse_from_rfc3339("2025-01-01T12:00:00Z").unwrap();
+        let clock = Arc::new(FixedClock::new(start.into()));
+        let client = RigMcpClient::from_parts(Config::default(), vec![provider.clone()], vec![])
+            .using_clock(clock.clone())
+            .with_pause_ttl(Duration::from_secs(600));
+        (client, provider, clock)
+    }
+
+    fn ask_for_region(provider: &MockProvider) {
+        provider.push_tool_call(
+            ASK_USER_TOOL,
+            json!({
+                "question": "Which region should the bucket live in?",
+                "field_schema": {"type": "string", "enum": ["eu", "us"]}
+            }),
+        );
+    }
+
+    fn token(outcome: RunOutcome) -> String {
+        match outcome {
+            RunOutcome::NeedsInput {
+                run_state_token, ..
+            } => run_state_token,
+            RunOutcome::Completed(response) => panic!("completed: {}", response.content),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_pauses_with_the_question() {
+        let (client, provider, _) = client();
+        ask_for_region(&provider);
+
+        let outcome = client.run("mock", "create a bucket").await.unwrap();
+
+        let RunOutcome::NeedsInput {
+            question,
+            field_schema,
+            run_state_token,
+        } = outcome
+        else {
+            panic!("expected a question");
+        };
+        assert_eq!(question, "Which region should the bucket live in?");
+        assert_eq!(
+            field_schema,
+            json!({"type": "string", "enum": ["eu", "us"]})
+        );
+        let state = RunState::from_token(&run_state_token).unwrap();
+        assert_eq!(state.agent, "mock");
+        assert_eq!(state.expires_at.to_rfc3339(), "2025-01-01T12:10:00+00:00");
+        // Nothing was invoked and no tool result was recorded
+        let roles: Vec<Role> = state.history.iter().map(|m| m.role).collect();
+        assert_eq!(roles, vec![Role::User, Role::Assistant]);
+        assert_eq!(provider.requests()[0].tools[0].name, ASK_USER_TOOL);
+    }
+
+    #[tokio::test]
+    async fn test_resume_continues_with_the_answer() {
+        let (client, provider, _) = client();
+        ask_for_region(&provider);
+        provider.push_response(NormalizedResponse::text("Created the bucket in eu"));
+        let token = token(client.run("mock", "create a bucket").await.unwrap());
+
+        let outcome = client.resume(&token, json!("eu")).await.unwrap();
+
+        match outcome {
+            RunOutcome::Completed(response) => {
+                assert_eq!(response.content, "Created the bucket in eu")
+            }
+            other => panic!("{:?}", other),
+        }
+        let requests = provider.requests();
+        assert_eq!(requests.len(), 2);
+        let answer = requests[1].messages.last().unwrap();
+        assert_eq!(answer.role, Role::Tool);
+        assert_eq!(answer.content, "eu");
+        let call_id = &requests[1].messages[1].tool_calls[0].id;
+        assert_eq!(answer.tool_call_id.as_ref(), Some(call_id));
+    }
+
+    #[tokio::test]
+    async fn test_expired_token_is_rejected() {
+        let (client, provider, clock) = client();
+        ask_for_region(&provider);
+        let token = token(client.run("mock", "create a bucket").await.unwrap());
+
+        clock.advance(Duration::from_secs(601));
+        let err = client.resume(&token, json!("eu")).await.unwrap_err();
+
+        let expired = err.downcast_ref::<RunStateExpired>().expect("expiry error");
+        assert_eq!(expired.expired_at.to_rfc3339(), "2025-01-01T12:10:00+00:00");
+        assert_eq!(provider.requests().len(), 1);
+    }
+}
@@ -0,0 +1,29 @@
+//! Time source
+//!
+//! Timestamps recorded by the crate, such as `checked_at` in health reports
+//! and the time of debug dumps, come from a [`Clock`]. Tests and deterministic
+//! clients substitute [`crate::testing::FixedClock`] for the system clock.
+
+use chrono::{DateTime, Utc};
+use std::fmt;
+use std::sync::Arc;
+
+/// Source of the current time
+pub trait Clock: Send + Sync + fmt::Debug {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// The real time
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// Shared [`SystemClock`]
+pub fn system() -> Arc<dyn Clock> {
+    Arc::new(SystemClock)
+}
@@ -0,0 +1,421 @@
+//! Provider credential verification
+//!
+//! A misconfigured API key doesn't stop a client from being created; every
+//! request just fails later. [`crate::RigMcpClient::verify_credentials`] sends
+//! each provider the cheapest authenticated request it has, listing models
+//! where the API can (OpenAI, DeepSeek, Ollama) and a one-token completion
+//! otherwise, and tells rejected keys apart from missing permissions and
+//! network trouble. With `verify_on_startup` the client refuses to be created
+//! with a key a provider rejects.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+
+use crate::debug_logging::DebugLog;
+use crate::health::{HealthCheck, Probe};
+use crate::provider::{ChatMessage, ChatRequest, Provider};
+use crate::transport::{HttpStatusError, HttpTransport, Unreachable};
+use crate::{wire, ProviderConfig, RigMcpClient};
+
+/// Time a provider has to answer a verification request
+pub const DEFAULT_VERIFY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What verifying a provider's key found out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CredentialStatus {
+    /// The provider accepted the key
+    Valid,
+    /// The provider is configured with `skip_verify`
+    Skipped,
+    /// HTTP 401: the key is wrong, revoked or missing
+    InvalidKey,
+    /// HTTP 403: the key is known but may not make this request
+    InsufficientPermissions,
+    /// No answer: the connection failed or timed out
+    Network,
+    /// Any other failure, e.g. a rate limit or a server error
+    Failed,
+}
+
+/// Verification result of one provider
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CredentialCheck {
+    pub provider: String,
+    pub status: CredentialStatus,
+    /// The error behind any status but `valid` and `skipped`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+impl CredentialCheck {
+    fn new(provider: &str, status: CredentialStatus) -> Self {
+        Self {
+            provider: provider.to_string(),
+            status,
+            detail: None,
+        }
+    }
+
+    fn failed(provider: &str, err: &anyhow::Error) -> Self {
+        Self {
+            detail: Some(format!("{:#}", err)),
+            ..Self::new(provider, classify(err))
+        }
+    }
+}
+
+/// Providers whose keys were rejected while verifying on startup
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("Invalid API key for provider(s): {}", .providers.join(", "))]
+pub struct InvalidCredentials {
+    pub providers: Vec<String>,
+}
+
+/// What a failed verification request says about the key
+fn classify(err: &anyhow::Error) -> CredentialStatus {
+    if let Some(err) = err.downcast_ref::<HttpStatusError>() {
+        return match err.status {
+            401 => CredentialStatus::InvalidKey,
+            403 => CredentialStatus::InsufficientPermissions,
+            _ => CredentialStatus::Failed,
+        };
+    }
+    if err.downcast_ref::<Unreachable>().is_some() {
+        return CredentialStatus::Network;
+    }
+    CredentialStatus::Failed
+}
+
+/// Verify the key of every provider in `configs`, one after another, in
+/// order
+pub(crate) async fn verify_all(
+    configs: &[ProviderConfig], providers: &HashMap<String, Arc<dyn Provider>>,
+    debug_log: &DebugLog, transport: &Arc<dyn HttpTransport>, timeout: Duration,
+) -> Vec<CredentialCheck> {
+    let mut checks = Vec::with_capacity(configs.len());
+    for config in configs {
+        let http = debug_log.wrap(config, transport.clone());
+        checks.push(verify(config, providers.get(&config.name), http, timeout).await);
+    }
+    checks
+}
+
+/// Verify the key of `config`, served by `provider`
+pub(crate) async fn verify(
+    config: &ProviderConfig, provider: Option<&Arc<dyn Provider>>,
+    transport: Arc<dyn HttpTransport>, timeout: Duration,
+) -> CredentialCheck {
+    if config.skip_verify {
+        return CredentialCheck::new(&config.name, CredentialStatus::Skipped);
+    }
+    match tokio::time::timeout(timeout, authenticate(config, provider, transport)).await {
+        Ok(Ok(())) => CredentialCheck::new(&config.name, CredentialStatus::Valid),
+        Ok(Err(err)) => CredentialCheck::failed(&config.name, &err),
+        Err(_) => CredentialCheck {
+            detail: Some(format!("timed out after {}ms", timeout.as_millis())),
+            ..CredentialCheck::new(&config.name, CredentialStatus::Network)
+        },
+    }
+}
+
+/// Send the cheapest authenticated request `config`'s API offers
+async fn authenticate(
+    config: &ProviderConfig, provider: Option<&Arc<dyn Provider>>,
+    transport: Arc<dyn HttpTransport>,
+) -> Result<()> {
+    let service = format!("Provider '{}'", config.name);
+    if let Some(request) = wire::list_models_request(config) {
+        let response = transport.send(request).await.map_err(|err| {
+            err.context(Unreachable {
+                service: service.clone(),
+            })
+        })?;
+        if !response.is_success() {
+            return Err(HttpStatusError::from_response(service, &response).into());
+        }
+        return Ok(());
+    }
+    let provider =
+        provider.ok_or_else(|| anyhow::anyhow!("Provider '{}' not found", config.name))?;
+    let request = ChatRequest {
+        messages: vec![ChatMessage::user("ping")],
+        max_tokens: Some(1),
+        ..ChatRequest::default()
+    };
+    provider.complete(request).await?;
+    Ok(())
+}
+
+/// Readiness check verifying every provider's key
+///
+/// Down while any key is rejected, degraded while a provider can't be
+/// reached or refuses the verification request for another reason.
+pub struct CredentialsCheck {
+    client: Arc<RigMcpClient>,
+}
+
+impl CredentialsCheck {
+    pub fn new(client: Arc<RigMcpClient>) -> Self {
+        Self { client }
+    }
+}
+
+#[async_trait]
+impl HealthCheck for CredentialsCheck {
+    fn name(&self) -> &str {
+        "credentials"
+    }
+
+    async fn check(&self) -> Result<Probe> {
+        let checks = self.client.verify_credentials().await;
+        let invalid: Vec<String> = checks
+            .iter()
+            .filter(|check| check.status == CredentialStatus::InvalidKey)
+            .map(|check| check.provider.clone())
+            .collect();
+        if !invalid.is_empty() {
+            return Err(InvalidCredentials { providers: invalid }.into());
+        }
+        let impaired: Vec<String> = checks
+            .iter()
+            .filter(|check| {
+                !matches!(
+                    check.status,
+                    CredentialStatus::Valid | CredentialStatus::Skipped
+                )
+            })
+            .map(|check| {
+                format!(
+                    "{}: {}",
+                    check.provider,
+                    check.detail.as_deref().unwrap_or_default()
+                )
+            })
+            .collect();
+        if !impaired.is_empty() {
+            return Ok(Probe::degraded(impaired.join("; ")));
+        }
+        Ok(Probe::up().detail(format!("{} providers verified", checks.len())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::health::HealthStatus;
+    use crate::testing::MockTransport;
+    use crate::transport::{HttpRequest, HttpResponse, Method};
+    use crate::Config;
+    use serde_json::json;
+
+    fn provider(name: &str, model: &str) -> ProviderConfig {
+        ProviderConfig {
+            name: name.to_string(),
+            model: model.to_string(),
+            api_key: Some("sk-test".to_string()),
+            ..ProviderConfig::default()
+        }
+    }
+
+    async fn client(providers: Vec<ProviderConfig>, transport: Arc<MockTransport>) -> RigMcpClient {
+        let config = Config {
+            providers,
+            ..Config::default()
+        };
+        RigMcpClient::with_transport(config, transport)
+            .await
+            .unwrap()
+    }
+
+    fn error(status: u16, message: &str) -> HttpResponse {
+        HttpResponse::json(status, &json!({"error": {"message": message}}))
+    }
+
+    /// Never answers
+    struct Hanging;
+
+    #[async_trait]
+    impl HttpTransport for Hanging {
+        async fn send(&self, _request: HttpRequest) -> Result<HttpResponse> {
+            std::future::pending().await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_openai_compatible_keys_are_checked_with_a_model_listing() {
+        let transport = Arc::new(MockTransport::new());
+        transport.push_json(200, json!({"object": "list", "data": []}));
+        let client = client(vec![provider("openai", "gpt-4o")], transport.clone()).await;
+
+        let checks = client.verify_credentials().await;
+        assert_eq!(
+            checks,
+            vec![CredentialCheck::new("openai", CredentialStatus::Valid)]
+        );
+        let requests = transport.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].method, Method::GET);
+        assert_eq!(requests[0].url, "https://api.openai.com/v1/models");
+        assert!(requests[0]
+            .headers
+            .contains(&("authorization".to_string(), "Bearer sk-test".to_string())));
+    }
+
+    #[tokio::test]
+    async fn test_providers_without_a_listing_get_a_one_token_completion() {
+        let transport = Arc::new(MockTransport::new());
+        transport.push_json(
+            200,
+            json!({
+                "content": [{"type": "text", "text": "p"}],
+                "stop_reason": "max_tokens",
+                "usage": {"input_tokens": 8, "output_tokens": 1}
+            }),
+        );
+        let client = client(
+            vec![provider("anthropic", "claude-3-5-sonnet-latest")],
+            transport.clone(),
+        )
+        .await;
+
+        let checks = client.verify_credentials().await;
+        assert_eq!(checks[0].status, CredentialStatus::Valid);
+        let requests = transport.requests();
+        assert!(
+            requests[0].url.ends_with("/v1/messages"),
+            "{}",
+            requests[0].url
+        );
+        let body: serde_json::Value = serde_json::from_slice(&requests[0].body).unwrap();
+        assert_eq!(body["max_tokens"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_failures_are_classified() {
+        let transport = Arc::new(MockTransport::new());
+        transport.push_response(error(401, "Incorrect API key provided"));
+        transport.push_response(error(403, "Project does not have access"));
+        transport.push_response(error(429, "Rate limit reached"));
+        let client = client(
+            vec![
+                provider("openai", "gpt-4o"),
+                provider("deepseek", "deepseek-chat"),
+                provider("anthropic", "claude-3-5-sonnet-latest"),
+                provider("cohere", "command-r-plus"),
+            ],
+            transport,
+        )
+        .await;
+
+        let checks = client.verify_credentials().await;
+        let statuses: Vec<_> = checks
+            .iter()
+            .map(|check| (check.provider.as_str(), check.status))
+            .collect();
+        assert_eq!(
+            statuses,
+            vec![
+                ("openai", CredentialStatus::InvalidKey),
+                ("deepseek", CredentialStatus::InsufficientPermissions),
+                ("anthropic", CredentialStatus::Failed),
+                // Nothing is queued, as if the connection were refused
+                ("cohere", CredentialStatus::Network),
+            ]
+        );
+        assert!(checks[0]
+            .detail
+            .as_deref()
+            .unwrap()
+            .contains("Incorrect API key provided"));
+    }
+
+    #[tokio::test]
+    async fn test_unanswered_requests_time_out_as_network_errors() {
+        let config = provider("openai", "gpt-4o");
+        let check = verify(&config, None, Arc::new(Hanging), Duration::from_millis(20)).await;
+        assert_eq!(check.status, CredentialStatus::Network);
+        assert_eq!(check.detail.as_deref(), Some("timed out after 20ms"));
+    }
+
+    #[tokio::test]
+    async fn test_skip_verify_sends_nothing() {
+        let transport = Arc::new(MockTransport::new());
+        let skipped = ProviderConfig {
+            skip_verify: true,
+            ..provider("openai", "gpt-4o")
+        };
+        let client = client(vec![skipped], transport.clone()).await;
+
+        let checks = client.verify_credentials().await;
+        assert_eq!(
+            checks,
+            vec![CredentialCheck::new("openai", CredentialStatus::Skipped)]
+        );
+        assert!(transport.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_verify_on_startup_fails_on_rejected_keys_only() {
+        let config = |providers| Config {
+            providers,
+            verify_on_startup: true,
+            ..Config::default()
+        };
+
+        let transport = Arc::new(MockTransport::new());
+        transport.push_response(error(401, "Incorrect API key provided"));
+        let err =
+            RigMcpClient::with_transport(config(vec![provider("openai", "gpt-4o")]), transport)
+                .await
+                .err()
+                .unwrap();
+        assert_eq!(
+            err.downcast_ref::<InvalidCredentials>(),
+            Some(&InvalidCredentials {
+                providers: vec!["openai".to_string()]
+            })
+        );
+
+        // A flaky network or a restricted key doesn't keep the client down
+        let transport = Arc::new(MockTransport::new());
+        transport.push_response(error(403, "Project does not have access"));
+        let providers = vec![
+            provider("openai", "gpt-4o"),
+            provider("deepseek", "deepseek-chat"),
+        ];
+        assert!(RigMcpClient::with_transport(config(providers), transport)
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_readiness_check() {
+        let transport = Arc::new(MockTransport::new());
+        transport.push_json(200, json!({"data": []}));
+        transport.push_response(error(403, "Project does not have access"));
+        let client = client(
+            vec![
+                provider("openai", "gpt-4o"),
+                provider("deepseek", "deepseek-chat"),
+            ],
+            transport.clone(),
+        )
+        .await;
+        let check = CredentialsCheck::new(Arc::new(client));
+
+        let probe = check.check().await.unwrap();
+        assert_eq!(probe.status, HealthStatus::Degraded);
+        assert!(probe.detail.unwrap().starts_with("deepseek: "));
+
+        transport.push_response(error(401, "Incorrect API key provided"));
+        transport.push_json(200, json!({"data": []}));
+        let err = check.check().await.unwrap_err();
+        assert_eq!(err.to_string(), "Invalid API key for provider(s): openai");
+    }
+}
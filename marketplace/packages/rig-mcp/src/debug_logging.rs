@@ -0,0 +1,599 @@
+//! Provider request/response logging
+//!
+//! With `debug_logging.enabled`, every request sent to a provider API and
+//! every response is logged at TRACE under the `rig_mcp::wire` target.
+//! Credentials and the configured `redact_fields` are replaced by
+//! `[REDACTED]` and logged bodies are cut at `max_body_bytes`. Setting
+//! `dump_dir` and `dump_provider` also writes the complete (still redacted)
+//! exchanges of that one provider to numbered files, stamped with the time
+//! of the client's [`Clock`]. The client's [`crate::redaction`] rules are
+//! applied to both unless their `debug_logging` flag is off.
+//!
+//! When disabled, transports are used unwrapped and nothing is formatted.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tracing::Level;
+
+use crate::clock::{self, Clock};
+use crate::redaction::RedactionRuleSet;
+use crate::transport::{HttpRequest, HttpResponse, HttpTransport};
+use crate::ProviderConfig;
+
+/// Replacement for redacted values
+pub const REDACTED: &str = "[REDACTED]";
+
+/// `tracing` target of the request/response events
+pub const TARGET: &str = "rig_mcp::wire";
+
+/// Headers that always carry credentials
+const SENSITIVE_HEADERS: &[&str] = &[
+    "authorization",
+    "proxy-authorization",
+    "x-api-key",
+    "api-key",
+    "x-goog-api-key",
+    "x-amz-security-token",
+    "x-aws-ec2-metadata-token",
+    "cookie",
+    "set-cookie",
+];
+
+/// Body fields that always carry credentials, e.g. in OAuth token exchanges
+const SENSITIVE_FIELDS: &[&str] = &[
+    "api_key",
+    "access_token",
+    "refresh_token",
+    "id_token",
+    "client_secret",
+    "assertion",
+    "private_key",
+];
+
+/// `debug_logging` section of [`crate::Config`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DebugLogging {
+    pub enabled: bool,
+    /// Logged bodies are truncated after this many bytes; dumps are not
+    pub max_body_bytes: usize,
+    /// JSON fields and headers to redact besides credentials, matched
+    /// case-insensitively
+    pub redact_fields: Vec<String>,
+    /// Directory receiving `NNNN-<provider>-request.json` and
+    /// `NNNN-<provider>-response.json` files
+    pub dump_dir: Option<PathBuf>,
+    /// The one provider whose exchanges are dumped
+    pub dump_provider: Option<String>,
+}
+
+impl Default for DebugLogging {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_body_bytes: 4096,
+            redact_fields: Vec::new(),
+            dump_dir: None,
+            dump_provider: None,
+        }
+    }
+}
+
+/// Applies [`DebugLogging`] to the transports of a client's providers
+///
+/// Exchanges are numbered across all providers of the client, so the `seq`
+/// of a log event matches the number of its dump files.
+#[derive(Debug)]
+pub struct DebugLog {
+    settings: DebugLogging,
+    sequence: Arc<AtomicUsize>,
+    clock: Arc<dyn Clock>,
+    redaction: Option<Arc<RedactionRuleSet>>,
+}
+
+impl Default for DebugLog {
+    fn default() -> Self {
+        Self::new(DebugLogging::default())
+    }
+}
+
+impl DebugLog {
+    pub fn new(settings: DebugLogging) -> Self {
+        Self {
+            settings,
+            sequence: Arc::default(),
+            clock: clock::system(),
+            redaction: None,
+        }
+    }
+
+    /// Stamp dumps with the time of `clock`
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Also redact what `rules` match from logged and dumped exchanges
+    pub fn with_redaction(mut self, rules: Arc<RedactionRuleSet>) -> Self {
+        self.redaction = Some(rules);
+        self
+    }
+
+    /// Transport for `provider`; `transport` itself when logging is disabled
+    pub fn wrap(
+        &self, provider: &ProviderConfig, transport: Arc<dyn HttpTransport>,
+    ) -> Arc<dyn HttpTransport> {
+        if !self.settings.enabled {
+            return transport;
+        }
+        let dump_dir = self
+            .settings
+            .dump_dir
+            .clone()
+            .filter(|_| self.settings.dump_provider.as_deref() == Some(provider.name.as_str()));
+        Arc::new(DebugTransport {
+            inner: transport,
+            provider: provider.name.clone(),
+            redactor: Redactor {
+                fields: self
+                    .settings
+                    .redact_fields
+                    .iter()
+                    .map(|field| field.to_lowercase())
+                    .collect(),
+                secrets: provider.api_key.iter().cloned().collect(),
+                rules: self.redaction.clone(),
+            },
+            max_body_bytes: self.settings.max_body_bytes,
+            dump_dir,
+            sequence: self.sequence.clone(),
+            clock: self.clock.clone(),
+        })
+    }
+}
+
+struct DebugTransport {
+    inner: Arc<dyn HttpTransport>,
+    provider: String,
+    redactor: Redactor,
+    max_body_bytes: usize,
+    dump_dir: Option<PathBuf>,
+    sequence: Arc<AtomicUsize>,
+    clock: Arc<dyn Clock>,
+}
+
+#[async_trait]
+impl HttpTransport for DebugTransport {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse> {
+        let seq = self.sequence.fetch_add(1, Ordering::Relaxed) + 1;
+        if tracing::enabled!(target: TARGET, Level::TRACE) {
+            tracing::trace!(
+                target: TARGET,
+                provider = %self.provider,
+                seq,
+                method = %request.method,
+                url = %self.redactor.text(&request.url),
+                headers = %self.redactor.headers(&request.headers),
+                body = %self.log_body(&request.body),
+                "provider request"
+            );
+        }
+        if let Some(dir) = &self.dump_dir {
+            let dump = json!({
+                "timestamp": self.clock.now().to_rfc3339(),
+                "method": request.method.as_str(),
+                "url": self.redactor.text(&request.url),
+                "headers": self.redactor.header_map(&request.headers),
+                "body": self.redactor.body(&request.body),
+            });
+            self.dump(dir, seq, "request", &dump).await;
+        }
+
+        let result = self.inner.send(request).await;
+        match &result {
+            Ok(response) => {
+                if tracing::enabled!(target: TARGET, Level::TRACE) {
+                    tracing::trace!(
+                        target: TARGET,
+                        provider = %self.provider,
+                        seq,
+                        status = response.status,
+                        headers = %self.redactor.headers(&response.headers),
+                        body = %self.log_body(&response.body),
+                        "provider response"
+                    );
+                }
+                if let Some(dir) = &self.dump_dir {
+                    let dump = json!({
+                        "timestamp": self.clock.now().to_rfc3339(),
+                        "status": response.status,
+                        "headers": self.redactor.header_map(&response.headers),
+                        "body": self.redactor.body(&response.body),
+                    });
+                    self.dump(dir, seq, "response", &dump).await;
+                }
+            }
+            Err(err) => tracing::trace!(
+                target: TARGET,
+                provider = %self.provider,
+                seq,
+                error = %self.redactor.text(&format!("{:#}", err)),
+                "provider request failed"
+            ),
+        }
+        result
+    }
+}
+
+impl DebugTransport {
+    fn log_body(&self, body: &[u8]) -> String {
+        truncate(self.redactor.body(body).to_string(), self.max_body_bytes)
+    }
+
+    /// Write one half of an exchange; failing to dump never fails the request
+    async fn dump(&self, dir: &Path, seq: usize, kind: &str, content: &Value) {
+        let path = dir.join(dump_file_name(seq, &self.provider, kind));
+        let written = match tokio::fs::create_dir_all(dir).await {
+            Ok(()) => {
+                let text = serde_json::to_string_pretty(content).unwrap_or_default();
+                tokio::fs::write(&path, text).await
+            }
+            Err(err) => Err(err),
+        };
+        if let Err(err) = written {
+            let path = path.display();
+            tracing::warn!(target: TARGET, %path, "Failed to write debug dump: {}", err);
+        }
+    }
+}
+
+fn dump_file_name(seq: usize, provider: &str, kind: &str) -> String {
+    format!("{:04}-{}-{}.json", seq, provider, kind)
+}
+
+/// Hides credentials and configured fields
+struct Redactor {
+    /// Lowercased field and header names
+    fields: Vec<String>,
+    /// Values hidden wherever they appear, e.g. the provider's API key
+    secrets: Vec<String>,
+    rules: Option<Arc<RedactionRuleSet>>,
+}
+
+impl Redactor {
+    fn is_sensitive(&self, name: &str) -> bool {
+        let name = name.to_lowercase();
+        SENSITIVE_HEADERS.contains(&name.as_str())
+            || SENSITIVE_FIELDS.contains(&name.as_str())
+            || self.fields.contains(&name)
+    }
+
+    fn text(&self, text: &str) -> String {
+        let text = self
+            .secrets
+            .iter()
+            .filter(|secret| !secret.is_empty())
+            .fold(text.to_string(), |text, secret| {
+                text.replace(secret.as_str(), REDACTED)
+            });
+        match &self.rules {
+            Some(rules) => rules.redact(&text),
+            None => text,
+        }
+    }
+
+    fn header_value(&self, name: &str, value: &str) -> String {
+        if self.is_sensitive(name) {
+            REDACTED.to_string()
+        } else {
+            self.text(value)
+        }
+    }
+
+    fn headers(&self, headers: &[(String, String)]) -> String {
+        headers
+            .iter()
+            .map(|(name, value)| format!("{}: {}", name, self.header_value(name, value)))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    fn header_map(&self, headers: &[(String, String)]) -> Value {
+        headers
+            .iter()
+            .map(|(name, value)| (name.clone(), Value::from(self.header_value(name, value))))
+            .collect::<serde_json::Map<_, _>>()
+            .into()
+    }
+
+    /// The body as redacted JSON, or as redacted text when it isn't JSON
+    fn body(&self, body: &[u8]) -> Value {
+        match serde_json::from_slice(body) {
+            Ok(mut value) => {
+                self.value(&mut value);
+                value
+            }
+            Err(_) => {
+                let text = String::from_utf8_lossy(body);
+                let text = self.form(&text).unwrap_or_else(|| text.into_owned());
+                Value::String(self.text(&text))
+            }
+        }
+    }
+
+    /// `text` with its sensitive fields redacted, if it is a URL-encoded
+    /// form
+    fn form(&self, text: &str) -> Option<String> {
+        if text.is_empty() || text.contains(char::is_whitespace) {
+            return None;
+        }
+        let fields = text
+            .split('&')
+            .map(|field| {
+                let (name, _) = field.split_once('=')?;
+                Some(if self.is_sensitive(name) {
+                    format!("{}={}", name, REDACTED)
+                } else {
+                    field.to_string()
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some(fields.join("&"))
+    }
+
+    fn value(&self, value: &mut Value) {
+        match value {
+            Value::Object(map) => {
+                for (key, value) in map.iter_mut() {
+                    let hidden = self
+                        .rules
+                        .as_ref()
+                        .and_then(|rules| rules.field_token(key, value));
+                    if self.is_sensitive(key) {
+                        *value = Value::from(REDACTED);
+                    } else if let Some(token) = hidden {
+                        *value = Value::from(token);
+                    } else {
+                        self.value(value);
+                    }
+                }
+            }
+            Value::Array(items) => items.iter_mut().for_each(|item| self.value(item)),
+            Value::String(text) => *text = self.text(text),
+            _ => {}
+        }
+    }
+}
+
+/// Cut `text` after `max_bytes`, noting how much was dropped
+fn truncate(mut text: String, max_bytes: usize) -> String {
+    if text.len() <= max_bytes {
+        return text;
+    }
+    let mut end = max_bytes;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    let dropped = text.len() - end;
+    text.truncate(end);
+    text.push_str(&format!("… [truncated {} bytes]", dropped));
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::moderation::PiiKind;
+    use crate::provider::{ChatMessage, ChatRequest, Provider};
+    use crate::redaction::RedactionRule;
+    use crate::testing::MockTransport;
+    use crate::wire::HttpProvider;
+    use std::io::Write;
+    use std::sync::Mutex;
+
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn openai(api_key: &str) -> ProviderConfig {
+        ProviderConfig {
+            name: "openai".to_string(),
+            model: "gpt-4o".to_string(),
+            api_key: Some(api_key.to_string()),
+            ..ProviderConfig::default()
+        }
+    }
+
+    fn transport() -> Arc<MockTransport> {
+        let transport = Arc::new(MockTransport::new());
+        transport.push_json(
+            200,
+            json!({"choices": [{"message": {"content": "ok"}}], "session_token": "tok-123"}),
+        );
+        transport
+    }
+
+    fn request(prompt: &str) -> ChatRequest {
+        ChatRequest {
+            messages: vec![ChatMessage::user(prompt)],
+            ..ChatRequest::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_trace_redacts_credentials() {
+        let captured = Captured::default();
+        let writer = captured.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(Level::TRACE)
+            .with_ansi(false)
+            .with_writer(move || writer.clone())
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let log = DebugLog::new(DebugLogging {
+            enabled: true,
+            redact_fields: vec!["Session_Token".to_string()],
+            ..DebugLogging::default()
+        });
+        let provider = openai("sk-secret-key");
+        let http = log.wrap(&provider, transport());
+        let provider = HttpProvider::new(&provider, http).unwrap();
+        provider.complete(request("hello")).await.unwrap();
+
+        let logs = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        assert!(logs.contains("provider request"), "{}", logs);
+        assert!(logs.contains("authorization: [REDACTED]"), "{}", logs);
+        assert!(logs.contains(r#""session_token":"[REDACTED]""#), "{}", logs);
+        assert!(logs.contains("hello"));
+        assert!(!logs.contains("sk-secret-key"));
+        assert!(!logs.contains("tok-123"));
+    }
+
+    #[test]
+    fn test_token_exchanges_are_redacted() {
+        let redactor = Redactor {
+            fields: vec![],
+            secrets: vec![],
+            rules: None,
+        };
+        assert_eq!(
+            redactor.body(b"grant_type=refresh_token&client_secret=shh&refresh_token=1%2F%2Fx"),
+            Value::from(
+                "grant_type=refresh_token&client_secret=[REDACTED]&refresh_token=[REDACTED]"
+            )
+        );
+        assert_eq!(
+            redactor.body(br#"{"access_token": "ya29.x", "expires_in": 3599}"#),
+            json!({"access_token": REDACTED, "expires_in": 3599})
+        );
+        assert_eq!(redactor.body(b"Not Found"), Value::from("Not Found"));
+    }
+
+    #[test]
+    fn test_truncation_marker() {
+        assert_eq!(truncate("short".to_string(), 10), "short");
+        assert_eq!(
+            truncate("abcdefghij".to_string(), 4),
+            "abcd… [truncated 6 bytes]"
+        );
+        // Never splits a character
+        assert_eq!(truncate("héllo".to_string(), 2), "h… [truncated 5 bytes]");
+    }
+
+    #[tokio::test]
+    async fn test_dumps_numbered_pairs_for_named_provider() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = DebugLog::new(DebugLogging {
+            enabled: true,
+            dump_dir: Some(dir.path().to_path_buf()),
+            dump_provider: Some("openai".to_string()),
+            ..DebugLogging::default()
+        });
+
+        let openai_config = openai("sk-secret-key");
+        let openai =
+            HttpProvider::new(&openai_config, log.wrap(&openai_config, transport())).unwrap();
+        let ollama_config = ProviderConfig {
+            name: "ollama".to_string(),
+            model: "llama3.1".to_string(),
+            ..ProviderConfig::default()
+        };
+        let ollama_transport = Arc::new(MockTransport::new());
+        ollama_transport.push_json(200, json!({"message": {"content": "ok"}}));
+        let ollama =
+            HttpProvider::new(&ollama_config, log.wrap(&ollama_config, ollama_transport)).unwrap();
+
+        openai.complete(request("one")).await.unwrap();
+        ollama.complete(request("two")).await.unwrap();
+        // An exchange that fails at the transport still leaves its request behind
+        openai.complete(request("three")).await.unwrap_err();
+
+        let mut names: Vec<String> = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        assert_eq!(
+            names,
+            vec![
+                "0001-openai-request.json",
+                "0001-openai-response.json",
+                "0003-openai-request.json",
+            ]
+        );
+
+        let dumped = std::fs::read_to_string(dir.path().join("0001-openai-request.json")).unwrap();
+        let dumped: Value = serde_json::from_str(&dumped).unwrap();
+        assert_eq!(dumped["headers"]["authorization"], REDACTED);
+        assert_eq!(dumped["body"]["messages"][0]["content"], "one");
+    }
+
+    #[tokio::test]
+    async fn test_redaction_rules_apply_to_dumps() {
+        let rules = Arc::new(
+            RedactionRuleSet::compile(&[
+                RedactionRule::shape("email", PiiKind::Email),
+                RedactionRule::fields("session", &["session_token"]),
+            ])
+            .unwrap(),
+        );
+        let dump = |rules: Option<Arc<RedactionRuleSet>>| async move {
+            let dir = tempfile::tempdir().unwrap();
+            let mut log = DebugLog::new(DebugLogging {
+                enabled: true,
+                dump_dir: Some(dir.path().to_path_buf()),
+                dump_provider: Some("openai".to_string()),
+                ..DebugLogging::default()
+            });
+            if let Some(rules) = rules {
+                log = log.with_redaction(rules);
+            }
+            let config = openai("sk-secret-key");
+            let provider = HttpProvider::new(&config, log.wrap(&config, transport())).unwrap();
+            provider
+                .complete(request("I'm jane@example.com"))
+                .await
+                .unwrap();
+            let read = |kind: &str| {
+                let path = dir.path().join(dump_file_name(1, "openai", kind));
+                std::fs::read_to_string(path).unwrap()
+            };
+            (read("request"), read("response"))
+        };
+
+        let (request, response) = dump(Some(rules)).await;
+        assert!(request.contains("I'm [EMAIL:medium]"), "{}", request);
+        assert!(!request.contains("jane@example.com"));
+        assert!(
+            response.contains(r#""session_token": "[SESSION:short]""#),
+            "{}",
+            response
+        );
+        let (request, response) = dump(None).await;
+        assert!(request.contains("jane@example.com"));
+        assert!(response.contains("tok-123"));
+    }
+
+    #[test]
+    fn test_disabled_leaves_transport_alone() {
+        let transport: Arc<dyn HttpTransport> = Arc::new(MockTransport::new());
+        let wrapped = DebugLog::default().wrap(&openai("sk"), transport.clone());
+        assert!(Arc::ptr_eq(&transport, &wrapped));
+    }
+}
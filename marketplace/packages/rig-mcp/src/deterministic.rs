@@ -0,0 +1,104 @@
+//! Reproducible client runs
+//!
+//! With `deterministic` set in [`crate::Config`], a client behaves the same
+//! on every run given the same inputs, which golden tests and recorded
+//! exchanges rely on: provider request ids come from a counter derived from
+//! the seed, the seed is sent to providers that accept one, and health checks
+//! run one after another instead of concurrently. Pair it with
+//! [`crate::testing::FixedClock`] to fix timestamps as well.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// `deterministic` section of [`crate::Config`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Deterministic {
+    /// Sampling seed for providers, and the prefix of request ids
+    pub seed: u64,
+}
+
+/// Ids for the `x-request-id` header of provider requests
+///
+/// Ids are `req-<prefix>-<n>`, with `n` counting requests from 1. The prefix
+/// is the seed in deterministic mode and differs between processes otherwise.
+#[derive(Debug)]
+pub struct RequestIds {
+    prefix: String,
+    next: AtomicU64,
+}
+
+impl RequestIds {
+    /// Ids unlikely to repeat across processes
+    pub fn random() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let digest = Sha256::new()
+            .chain_update(nanos.to_le_bytes())
+            .chain_update(std::process::id().to_le_bytes())
+            .finalize();
+        let prefix = digest[..8]
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        Self::with_prefix(prefix)
+    }
+
+    /// The same ids on every run
+    pub fn seeded(seed: u64) -> Self {
+        Self::with_prefix(format!("{:016x}", seed))
+    }
+
+    fn with_prefix(prefix: String) -> Self {
+        Self {
+            prefix,
+            next: AtomicU64::new(1),
+        }
+    }
+
+    pub fn next_id(&self) -> String {
+        let n = self.next.fetch_add(1, Ordering::Relaxed);
+        format!("req-{}-{:06}", self.prefix, n)
+    }
+}
+
+impl Default for RequestIds {
+    fn default() -> Self {
+        Self::random()
+    }
+}
+
+/// SplitMix64; small, and the same sequence on every platform
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SplitMix64(u64);
+
+impl SplitMix64 {
+    pub(crate) fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    /// Seeded from the time, for draws that needn't repeat
+    pub(crate) fn from_time() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        Self(nanos as u64)
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `[0, 1)`
+    pub(crate) fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
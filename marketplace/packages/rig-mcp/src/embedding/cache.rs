@@ -0,0 +1,92 @@
+//! Embeddings persisted to disk
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use super::EmbeddingInfo;
+
+/// Vectors keyed by the SHA-256 of their text, stored as one JSON file
+///
+/// The file records the model the vectors were built with; [`super::Embeddings`]
+/// checks it before serving anything from the cache.
+pub struct EmbeddingCache {
+    path: PathBuf,
+    file: CacheFile,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct CacheFile {
+    built_with: Option<EmbeddingInfo>,
+    vectors: HashMap<String, Vec<f32>>,
+}
+
+impl EmbeddingCache {
+    /// Load the cache at `path`; a missing file is an empty cache
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = match std::fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .with_context(|| format!("Invalid embedding cache {}", path.display()))?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => CacheFile::default(),
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("Failed to read embedding cache {}", path.display()))
+            }
+        };
+        Ok(Self { path, file })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Model the cached vectors were built with; `None` for a new cache
+    pub fn built_with(&self) -> Option<&EmbeddingInfo> {
+        self.file.built_with.as_ref()
+    }
+
+    pub fn len(&self) -> usize {
+        self.file.vectors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.file.vectors.is_empty()
+    }
+
+    pub fn get(&self, text: &str) -> Option<&Vec<f32>> {
+        self.file.vectors.get(&key(text))
+    }
+
+    pub fn insert(&mut self, text: &str, vector: Vec<f32>) {
+        self.file.vectors.insert(key(text), vector);
+    }
+
+    /// Drop every vector, e.g. after the embedding model changed
+    pub fn clear(&mut self) {
+        self.file.vectors.clear();
+    }
+
+    pub(crate) fn set_built_with(&mut self, info: EmbeddingInfo) {
+        self.file.built_with = Some(info);
+    }
+
+    /// Write the cache back to its file
+    pub fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let json = serde_json::to_vec(&self.file)?;
+        std::fs::write(&self.path, json)
+            .with_context(|| format!("Failed to write embedding cache {}", self.path.display()))
+    }
+}
+
+fn key(text: &str) -> String {
+    Sha256::digest(text.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
@@ -0,0 +1,134 @@
+//! In-memory nearest-neighbour search
+
+use serde::{Deserialize, Serialize};
+
+use super::EmbeddingInfo;
+
+/// Items with their vectors, all built with one embedding model
+#[derive(Debug, Clone)]
+pub struct VectorIndex<T> {
+    built_with: EmbeddingInfo,
+    entries: Vec<(T, Vec<f32>)>,
+}
+
+impl<T> VectorIndex<T> {
+    pub fn new(built_with: EmbeddingInfo) -> Self {
+        Self {
+            built_with,
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn built_with(&self) -> &EmbeddingInfo {
+        &self.built_with
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn items(&self) -> impl Iterator<Item = &T> {
+        self.entries.iter().map(|(item, _)| item)
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = (&T, &[f32])> {
+        self.entries
+            .iter()
+            .map(|(item, vector)| (item, vector.as_slice()))
+    }
+
+    /// Add an item; its vector must have the index's dimensionality
+    pub fn insert(&mut self, item: T, vector: Vec<f32>) -> anyhow::Result<()> {
+        match self.built_with.dimensions {
+            None => self.built_with.dimensions = Some(vector.len()),
+            Some(dimensions) if dimensions != vector.len() => anyhow::bail!(
+                "Vector has {} dimensions, but the index was built with {}",
+                vector.len(),
+                self.built_with
+            ),
+            Some(_) => {}
+        }
+        self.entries.push((item, vector));
+        Ok(())
+    }
+
+    /// The `top_k` items most similar to `query`, best first
+    pub fn search(&self, query: &[f32], top_k: usize) -> Vec<(&T, f32)> {
+        let mut scored: Vec<(&T, f32)> = self
+            .entries
+            .iter()
+            .map(|(item, vector)| (item, cosine_similarity(query, vector)))
+            .collect();
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+        scored.truncate(top_k);
+        scored
+    }
+}
+
+/// Cosine similarity; zero for vectors of different lengths or zero vectors
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot / (norm_a * norm_b)
+}
+
+/// How similarities from different embedding models are made comparable
+/// before they are ranked together
+///
+/// Each model's scores are rescaled on their own: cosine similarities of one
+/// model may cluster around 0.8 while another's spread from 0 to 0.5.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScoreNormalization {
+    /// Distance from the mean in standard deviations
+    #[default]
+    ZScore,
+    /// Position between the lowest and the highest score, from 0 to 1
+    MinMax,
+}
+
+impl ScoreNormalization {
+    /// Rescale `scores`, all from one model; equal scores end up in the
+    /// middle of the scale
+    pub fn apply(self, scores: &mut [f32]) {
+        if scores.is_empty() {
+            return;
+        }
+        match self {
+            Self::ZScore => {
+                let n = scores.len() as f32;
+                let mean = scores.iter().sum::<f32>() / n;
+                let deviation = (scores.iter().map(|s| (s - mean).powi(2)).sum::<f32>() / n).sqrt();
+                for score in scores {
+                    *score = if deviation > 0.0 {
+                        (*score - mean) / deviation
+                    } else {
+                        0.0
+                    };
+                }
+            }
+            Self::MinMax => {
+                let min = scores.iter().copied().fold(f32::INFINITY, f32::min);
+                let max = scores.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+                for score in scores {
+                    *score = if max > min {
+                        (*score - min) / (max - min)
+                    } else {
+                        0.5
+                    };
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,195 @@
+//! In-process embeddings with fastembed
+//!
+//! The `local` embedding provider runs an ONNX model inside the process, so
+//! tool selection makes no network calls at all. `model` is either a
+//! directory holding an exported model:
+//!
+//! ```text
+//! model.onnx  tokenizer.json  config.json  special_tokens_map.json  tokenizer_config.json
+//! ```
+//!
+//! or, with `allow_download` set, the name of a model fastembed knows, such
+//! as `BAAI/bge-small-en-v1.5`, which is downloaded into fastembed's cache
+//! (`FASTEMBED_CACHE_PATH`, `.fastembed_cache` by default) on first use.
+//! Without `allow_download` a name is refused rather than fetched.
+//!
+//! The model is loaded when the first text is embedded, not when the client
+//! is created, and inference runs on the blocking thread pool.
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use fastembed::{
+    InitOptions, InitOptionsUserDefined, TextEmbedding, TokenizerFiles, UserDefinedEmbeddingModel,
+};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::OnceCell;
+
+use super::EmbeddingModel;
+
+/// Texts run through the model at once
+const BATCH_SIZE: usize = 32;
+
+/// Files of an exported model directory
+const MODEL_FILES: [&str; 5] = [
+    "model.onnx",
+    "tokenizer.json",
+    "config.json",
+    "special_tokens_map.json",
+    "tokenizer_config.json",
+];
+
+/// Where the model comes from
+#[derive(Debug, Clone)]
+enum Source {
+    Directory(PathBuf),
+    Download(fastembed::EmbeddingModel),
+}
+
+/// [`EmbeddingModel`] running in process; see the module docs
+pub struct LocalEmbedder {
+    model: String,
+    source: Source,
+    dimensions: Option<usize>,
+    loaded: OnceCell<Arc<TextEmbedding>>,
+}
+
+impl LocalEmbedder {
+    /// Embedder for `model`, a model directory or, if `allow_download`, the
+    /// name of a model to download; nothing is read until the first embed
+    pub fn new(model: &str, allow_download: bool) -> Result<Self> {
+        let (source, dimensions) = if Path::new(model).is_dir() {
+            (Source::Directory(PathBuf::from(model)), None)
+        } else {
+            let known = TextEmbedding::list_supported_models()
+                .into_iter()
+                .find(|info| info.model_code == model);
+            match known {
+                Some(info) if allow_download => (Source::Download(info.model), Some(info.dim)),
+                Some(_) => anyhow::bail!(
+                    "Local embedding model '{}' is not a directory; set allow_download to \
+                     download it",
+                    model
+                ),
+                None => anyhow::bail!(
+                    "Local embedding model '{}' is neither a model directory nor a model \
+                     fastembed can download",
+                    model
+                ),
+            }
+        };
+        Ok(Self {
+            model: model.to_string(),
+            source,
+            dimensions,
+            loaded: OnceCell::new(),
+        })
+    }
+
+    async fn loaded(&self) -> Result<Arc<TextEmbedding>> {
+        let loaded = self
+            .loaded
+            .get_or_try_init(|| async {
+                let source = self.source.clone();
+                let model = tokio::task::spawn_blocking(move || load(&source)).await??;
+                anyhow::Ok(Arc::new(model))
+            })
+            .await
+            .with_context(|| format!("Failed to load local embedding model '{}'", self.model))?;
+        Ok(loaded.clone())
+    }
+}
+
+fn load(source: &Source) -> Result<TextEmbedding> {
+    match source {
+        Source::Directory(dir) => {
+            let missing: Vec<&str> = MODEL_FILES
+                .into_iter()
+                .filter(|file| !dir.join(file).is_file())
+                .collect();
+            if !missing.is_empty() {
+                anyhow::bail!("{} lacks {}", dir.display(), missing.join(", "));
+            }
+            let read = |file: &str| {
+                std::fs::read(dir.join(file))
+                    .with_context(|| format!("Failed to read {}", dir.join(file).display()))
+            };
+            let model = UserDefinedEmbeddingModel::new(
+                read("model.onnx")?,
+                TokenizerFiles {
+                    tokenizer_file: read("tokenizer.json")?,
+                    config_file: read("config.json")?,
+                    special_tokens_map_file: read("special_tokens_map.json")?,
+                    tokenizer_config_file: read("tokenizer_config.json")?,
+                },
+            );
+            TextEmbedding::try_new_from_user_defined(model, InitOptionsUserDefined::default())
+        }
+        Source::Download(model) => TextEmbedding::try_new(
+            InitOptions::new(model.clone()).with_show_download_progress(false),
+        ),
+    }
+}
+
+#[async_trait]
+impl EmbeddingModel for LocalEmbedder {
+    fn provider(&self) -> &str {
+        "local"
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn dimensions(&self) -> Option<usize> {
+        self.dimensions
+    }
+
+    async fn embed_texts(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let model = self.loaded().await?;
+        let texts = texts.to_vec();
+        tokio::task::spawn_blocking(move || model.embed(texts, Some(BATCH_SIZE))).await?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_model_names_need_allow_download() {
+        let err = LocalEmbedder::new("BAAI/bge-small-en-v1.5", false)
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("set allow_download"), "{}", err);
+
+        let embedder = LocalEmbedder::new("BAAI/bge-small-en-v1.5", true).unwrap();
+        assert_eq!(embedder.dimensions(), Some(384));
+
+        let err = LocalEmbedder::new("no-such-model", true).err().unwrap();
+        assert!(
+            err.to_string().contains("neither a model directory"),
+            "{}",
+            err
+        );
+    }
+
+    #[tokio::test]
+    async fn test_incomplete_model_directory_fails_on_first_use() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("model.onnx"), b"").unwrap();
+        let embedder = LocalEmbedder::new(dir.path().to_str().unwrap(), false).unwrap();
+        assert_eq!(embedder.dimensions(), None);
+
+        let err = embedder
+            .embed_texts(&["hello".to_string()])
+            .await
+            .unwrap_err();
+        let message = format!("{:#}", err);
+        assert!(
+            message.contains("lacks tokenizer.json, config.json"),
+            "{}",
+            message
+        );
+    }
+}
@@ -0,0 +1,729 @@
+//! Embedding models and the vectors built with them
+//!
+//! Vectors from different models can't be compared: a similarity between a
+//! 1536-dimension query and a 768-dimension tool description is garbage, and
+//! so is one between two models that happen to share a dimensionality.
+//! Everything that stores vectors therefore records the [`EmbeddingInfo`] it
+//! was built with, and [`Embeddings`] checks it against the configured model
+//! on first use. A mismatch either rebuilds the stored vectors or fails,
+//! depending on the [`ModelChangePolicy`].
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+
+use crate::health::HealthStatus;
+use crate::transport;
+
+mod cache;
+mod index;
+#[cfg(feature = "local-embeddings")]
+pub mod local;
+mod store;
+
+pub use cache::EmbeddingCache;
+pub use index::{cosine_similarity, ScoreNormalization, VectorIndex};
+pub use store::{
+    IndexEntryStatus, IndexGc, IndexOutOfDate, IndexRebuild, IndexReport, IndexStatus, IndexStore,
+    IndexVerification, StoredIndex, StoredItem, INDEX_FORMAT_VERSION,
+};
+
+/// A model turning text into vectors
+#[async_trait]
+pub trait EmbeddingModel: Send + Sync {
+    fn provider(&self) -> &str;
+
+    fn model(&self) -> &str;
+
+    /// Length of the model's vectors, if known before it embeds anything
+    fn dimensions(&self) -> Option<usize> {
+        None
+    }
+
+    /// One vector per text, in order
+    async fn embed_texts(&self, texts: &[String]) -> Result<Vec<Vec<f32>>>;
+}
+
+/// The in-process model `model` names, see [`local`]
+#[cfg(feature = "local-embeddings")]
+pub(crate) fn local_model(model: &str, allow_download: bool) -> Result<Arc<dyn EmbeddingModel>> {
+    Ok(Arc::new(local::LocalEmbedder::new(model, allow_download)?))
+}
+
+#[cfg(not(feature = "local-embeddings"))]
+pub(crate) fn local_model(_model: &str, _allow_download: bool) -> Result<Arc<dyn EmbeddingModel>> {
+    anyhow::bail!("local embeddings are not enabled; build with the `local-embeddings` feature")
+}
+
+/// The model a set of vectors was built with
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EmbeddingInfo {
+    pub provider: String,
+    pub model: String,
+    /// Unknown until the model has produced a vector, unless its
+    /// [`EmbeddingModel::dimensions`] are known
+    pub dimensions: Option<usize>,
+}
+
+impl EmbeddingInfo {
+    /// Whether vectors built with `self` and `other` can be compared
+    ///
+    /// An unknown dimensionality matches any.
+    pub fn is_compatible(&self, other: &EmbeddingInfo) -> bool {
+        self.provider == other.provider
+            && self.model == other.model
+            && match (self.dimensions, other.dimensions) {
+                (Some(a), Some(b)) => a == b,
+                _ => true,
+            }
+    }
+}
+
+impl fmt::Display for EmbeddingInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.provider, self.model)?;
+        if let Some(dimensions) = self.dimensions {
+            write!(f, " ({} dimensions)", dimensions)?;
+        }
+        Ok(())
+    }
+}
+
+/// What to do with stored vectors built with a different model
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ModelChangePolicy {
+    /// Discard them and embed again with the configured model
+    #[default]
+    Reindex,
+    /// Refuse to embed until they are removed or the model is switched back
+    Error,
+}
+
+/// Stored vectors don't match the configured embedding model
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error(
+    "{artifact} was built with {built_with}, but the configured embedding model is {current}; \
+     switch the model back, delete the artifact, or set `on_model_change = \"reindex\"`"
+)]
+pub struct IncompatibleEmbeddings {
+    /// What holds the vectors, e.g. `embedding cache /var/cache/rig.json`
+    pub artifact: String,
+    pub built_with: EmbeddingInfo,
+    pub current: EmbeddingInfo,
+}
+
+/// Health of one of the models behind an [`Embeddings`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EmbeddingProviderStatus {
+    pub info: EmbeddingInfo,
+    /// 0 for the configured model, then the fallbacks in the order they are
+    /// tried
+    pub priority: usize,
+    /// Whether it served the latest call
+    pub active: bool,
+    /// `down` while its latest attempt failed
+    pub status: HealthStatus,
+    pub consecutive_failures: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+/// The configured embedding model, with its cache and compatibility checks
+///
+/// Fallback models take over, in the order they were added, when the
+/// configured one fails with a retriable error (see
+/// [`crate::transport::is_retriable`]). Every call tries the configured
+/// model first. Fallbacks don't read or write the cache, since its vectors
+/// come from the configured model, and one whose dimensionality differs from
+/// the stored vectors is skipped unless
+/// [`Embeddings::allow_dimension_change_reindex`] is set.
+///
+/// Cheap to clone; clones share the cache and what has been learned about
+/// the models.
+#[derive(Clone)]
+pub struct Embeddings {
+    model: Arc<dyn EmbeddingModel>,
+    fallbacks: Vec<Fallback>,
+    allow_dimension_change: bool,
+    policy: ModelChangePolicy,
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Clone)]
+struct Fallback {
+    model: Arc<dyn EmbeddingModel>,
+    /// From the configuration, so a mismatch is caught without a request
+    dimensions: Option<usize>,
+}
+
+struct State {
+    /// The configured model first, then the fallbacks
+    models: Vec<ModelState>,
+    /// Index into `models` of the model that served the latest call
+    active: usize,
+    cache: Option<EmbeddingCache>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct ModelState {
+    /// Learned from the first vector the model returns
+    dimensions: Option<usize>,
+    consecutive_failures: u32,
+    last_error: Option<String>,
+}
+
+impl ModelState {
+    fn succeeded(&mut self) {
+        self.consecutive_failures = 0;
+        self.last_error = None;
+    }
+
+    fn failed(&mut self, err: &anyhow::Error) {
+        self.consecutive_failures += 1;
+        self.last_error = Some(format!("{:#}", err));
+    }
+}
+
+impl Embeddings {
+    pub fn new(model: Arc<dyn EmbeddingModel>, policy: ModelChangePolicy) -> Self {
+        Self {
+            model,
+            fallbacks: Vec::new(),
+            allow_dimension_change: false,
+            policy,
+            state: Arc::new(Mutex::new(State {
+                models: vec![ModelState::default()],
+                active: 0,
+                cache: None,
+            })),
+        }
+    }
+
+    /// Serve repeated texts from `cache`
+    pub fn with_cache(self, cache: EmbeddingCache) -> Self {
+        self.state.lock().unwrap().cache = Some(cache);
+        self
+    }
+
+    /// Try `model` when the models added before it fail
+    ///
+    /// `dimensions`, if known, lets the dimension guard skip the model
+    /// without sending it a request.
+    pub fn with_fallback(
+        mut self, model: Arc<dyn EmbeddingModel>, dimensions: Option<usize>,
+    ) -> Self {
+        self.fallbacks.push(Fallback { model, dimensions });
+        self.state
+            .lock()
+            .unwrap()
+            .models
+            .push(ModelState::default());
+        self
+    }
+
+    /// Let fallbacks of another dimensionality take over; whatever was built
+    /// with the configured model is then rebuilt with them
+    pub fn allow_dimension_change_reindex(mut self, allow: bool) -> Self {
+        self.allow_dimension_change = allow;
+        self
+    }
+
+    /// The configured model
+    pub fn model(&self) -> &dyn EmbeddingModel {
+        self.model.as_ref()
+    }
+
+    /// The configured model, then the fallbacks in priority order
+    pub fn models(&self) -> Vec<Arc<dyn EmbeddingModel>> {
+        std::iter::once(self.model.clone())
+            .chain(self.fallbacks.iter().map(|fallback| fallback.model.clone()))
+            .collect()
+    }
+
+    pub fn policy(&self) -> ModelChangePolicy {
+        self.policy
+    }
+
+    /// The model that served the latest call, the configured one until
+    /// something failed over; `dimensions` is known once it has embedded
+    /// something, or from the start for models of known size
+    pub fn info(&self) -> EmbeddingInfo {
+        let state = self.state.lock().unwrap();
+        self.info_of(state.active, &state)
+    }
+
+    /// Whether the latest call was served by a fallback
+    pub fn is_failed_over(&self) -> bool {
+        self.state.lock().unwrap().active != 0
+    }
+
+    /// Health of the configured model and of every fallback, in priority
+    /// order
+    pub fn provider_status(&self) -> Vec<EmbeddingProviderStatus> {
+        let state = self.state.lock().unwrap();
+        state
+            .models
+            .iter()
+            .enumerate()
+            .map(|(index, model)| EmbeddingProviderStatus {
+                info: self.info_of(index, &state),
+                priority: index,
+                active: index == state.active,
+                status: if model.consecutive_failures == 0 {
+                    HealthStatus::Up
+                } else {
+                    HealthStatus::Down
+                },
+                consecutive_failures: model.consecutive_failures,
+                last_error: model.last_error.clone(),
+            })
+            .collect()
+    }
+
+    /// Check vectors stored by `artifact` against the model that served the
+    /// latest call
+    ///
+    /// Returns `Ok(true)` when they must be rebuilt, or an
+    /// [`IncompatibleEmbeddings`] error if the policy forbids that.
+    pub fn check_compatible(&self, artifact: &str, built_with: &EmbeddingInfo) -> Result<bool> {
+        self.check_compatible_with(artifact, built_with, &self.info())
+    }
+
+    /// Embed `texts`, reusing cached vectors
+    ///
+    /// The first vectors the model returns fix its dimensionality; every
+    /// later vector, and the cache, must match it. If the configured model
+    /// fails with a retriable error, the fallbacks are tried in order.
+    pub async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let err = match self.embed_with_cache(texts).await {
+            Ok(vectors) => {
+                self.state.lock().unwrap().active = 0;
+                return Ok(vectors);
+            }
+            Err(err) => err,
+        };
+        if self.fallbacks.is_empty() || !transport::is_retriable(&err) {
+            return Err(err);
+        }
+
+        let mut attempts = vec![format!("{}: {:#}", self.model_at(0), err)];
+        for index in 1..=self.fallbacks.len() {
+            match self.embed_with_fallback(index, texts).await {
+                Ok(vectors) => {
+                    tracing::warn!(
+                        "Embedding model {} failed, {} took over: {:#}",
+                        self.model_at(0),
+                        self.model_at(index),
+                        err
+                    );
+                    self.state.lock().unwrap().active = index;
+                    return Ok(vectors);
+                }
+                Err(fallback_err) => {
+                    attempts.push(format!("{}: {:#}", self.model_at(index), fallback_err))
+                }
+            }
+        }
+        Err(err.context(format!(
+            "Every embedding model failed: {}",
+            attempts.join("; ")
+        )))
+    }
+
+    /// Embed with the configured model, through the cache
+    async fn embed_with_cache(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let mut vectors: Vec<Option<Vec<f32>>> = {
+            let mut state = self.state.lock().unwrap();
+            self.reconcile_cache(&mut state)?;
+            let cache = state.cache.as_ref();
+            texts
+                .iter()
+                .map(|text| cache.and_then(|c| c.get(text)).cloned())
+                .collect()
+        };
+        let missing: Vec<String> = texts
+            .iter()
+            .zip(&vectors)
+            .filter(|(_, vector)| vector.is_none())
+            .map(|(text, _)| text.clone())
+            .collect();
+        if missing.is_empty() {
+            return Ok(vectors.into_iter().flatten().collect());
+        }
+
+        let embedded = self.model.embed_texts(&missing).await;
+        let mut state = self.state.lock().unwrap();
+        let embedded = match embedded {
+            Ok(embedded) => embedded,
+            Err(err) => {
+                state.models[0].failed(&err);
+                return Err(err);
+            }
+        };
+        state.models[0].succeeded();
+        for vector in &embedded {
+            match state.models[0].dimensions {
+                None => state.models[0].dimensions = Some(vector.len()),
+                Some(dimensions) if dimensions != vector.len() => anyhow::bail!(
+                    "Embedding model {}/{} returned {} dimensions, earlier vectors had {}",
+                    self.model.provider(),
+                    self.model.model(),
+                    vector.len(),
+                    dimensions
+                ),
+                Some(_) => {}
+            }
+        }
+        // Now that the dimensionality is known, the cache may turn out stale
+        self.reconcile_cache(&mut state)?;
+        if let Some(cache) = state.cache.as_mut() {
+            for (text, vector) in missing.iter().zip(&embedded) {
+                cache.insert(text, vector.clone());
+            }
+            cache.save()?;
+        }
+
+        let mut embedded = embedded.into_iter();
+        for vector in vectors.iter_mut().filter(|v| v.is_none()) {
+            *vector = embedded.next();
+        }
+        Ok(vectors.into_iter().flatten().collect())
+    }
+
+    /// Embed every text with the fallback at `index`, if the dimension guard
+    /// lets it serve
+    async fn embed_with_fallback(&self, index: usize, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let fallback = &self.fallbacks[index - 1];
+        let stored = {
+            let mut state = self.state.lock().unwrap();
+            let stored = state.models[0].dimensions.or_else(|| {
+                let cache = state.cache.as_ref()?;
+                cache.built_with()?.dimensions
+            });
+            let known = fallback
+                .dimensions
+                .or(state.models[index].dimensions)
+                .or_else(|| fallback.model.dimensions());
+            if let Err(err) = self.guard_dimensions(index, stored, known) {
+                state.models[index].failed(&err);
+                return Err(err);
+            }
+            stored
+        };
+
+        let embedded = fallback.model.embed_texts(texts).await;
+        let mut state = self.state.lock().unwrap();
+        let checked = embedded.and_then(|embedded| {
+            let found = embedded.first().map(Vec::len);
+            if embedded.iter().any(|vector| Some(vector.len()) != found) {
+                anyhow::bail!(
+                    "Embedding model {} returned vectors of different lengths",
+                    self.model_at(index)
+                );
+            }
+            self.guard_dimensions(index, stored, found)?;
+            Ok((embedded, found))
+        });
+        match checked {
+            Ok((embedded, found)) => {
+                state.models[index].succeeded();
+                state.models[index].dimensions = found.or(state.models[index].dimensions);
+                Ok(embedded)
+            }
+            Err(err) => {
+                state.models[index].failed(&err);
+                Err(err)
+            }
+        }
+    }
+
+    /// Refuse a fallback whose vectors wouldn't match the stored ones
+    fn guard_dimensions(
+        &self, index: usize, stored: Option<usize>, found: Option<usize>,
+    ) -> Result<()> {
+        match (stored, found) {
+            (Some(stored), Some(found)) if stored != found && !self.allow_dimension_change => {
+                anyhow::bail!(
+                    "{} has {} dimensions, stored vectors have {}; set \
+                     `allow_dimension_change_reindex` to fall over to it and rebuild them",
+                    self.model_at(index),
+                    found,
+                    stored
+                )
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn model_at(&self, index: usize) -> String {
+        let model = match index {
+            0 => &self.model,
+            index => &self.fallbacks[index - 1].model,
+        };
+        format!("{}/{}", model.provider(), model.model())
+    }
+
+    fn info_of(&self, index: usize, state: &State) -> EmbeddingInfo {
+        let model = match index {
+            0 => &self.model,
+            index => &self.fallbacks[index - 1].model,
+        };
+        EmbeddingInfo {
+            provider: model.provider().to_string(),
+            model: model.model().to_string(),
+            dimensions: state.models[index]
+                .dimensions
+                .or_else(|| model.dimensions()),
+        }
+    }
+
+    /// Embed a single text
+    pub async fn embed_text(&self, text: &str) -> Result<Vec<f32>> {
+        let mut vectors = self.embed(&[text.to_string()]).await?;
+        vectors
+            .pop()
+            .ok_or_else(|| anyhow::anyhow!("Embedding model returned no vector"))
+    }
+
+    /// Make the cache agree with the configured model, clearing it or
+    /// failing on a mismatch
+    fn reconcile_cache(&self, state: &mut State) -> Result<()> {
+        let current = self.info_of(0, state);
+        let Some(cache) = state.cache.as_mut() else {
+            return Ok(());
+        };
+        let rebuild = match cache.built_with() {
+            Some(built_with) => {
+                let artifact = format!("embedding cache {}", cache.path().display());
+                self.check_compatible_with(&artifact, built_with, &current)?
+            }
+            None => true,
+        };
+        if rebuild {
+            cache.clear();
+        }
+        // Keep a recorded dimensionality until the model's own is known
+        if rebuild || current.dimensions.is_some() {
+            cache.set_built_with(current);
+        }
+        Ok(())
+    }
+
+    fn check_compatible_with(
+        &self, artifact: &str, built_with: &EmbeddingInfo, current: &EmbeddingInfo,
+    ) -> Result<bool> {
+        if built_with.is_compatible(current) {
+            return Ok(false);
+        }
+        match self.policy {
+            ModelChangePolicy::Reindex => {
+                tracing::warn!(
+                    "{} was built with {}, rebuilding it with {}",
+                    artifact,
+                    built_with,
+                    current
+                );
+                Ok(true)
+            }
+            ModelChangePolicy::Error => Err(IncompatibleEmbeddings {
+                artifact: artifact.to_string(),
+                built_with: built_with.clone(),
+                current: current.clone(),
+            }
+            .into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockEmbeddingModel;
+
+    fn texts(items: &[&str]) -> Vec<String> {
+        items.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_model_switch_reindexes_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("embeddings.json");
+
+        let small = Arc::new(MockEmbeddingModel::new("text-small", 4));
+        let embeddings = Embeddings::new(small.clone(), ModelChangePolicy::Reindex)
+            .with_cache(EmbeddingCache::open(&path).unwrap());
+        embeddings
+            .embed(&texts(&["read_file", "write_file"]))
+            .await
+            .unwrap();
+        assert_eq!(small.embedded(), 2);
+        assert_eq!(embeddings.info().dimensions, Some(4));
+
+        // A restart with the same model is served from disk
+        let small = Arc::new(MockEmbeddingModel::new("text-small", 4));
+        let embeddings = Embeddings::new(small.clone(), ModelChangePolicy::Reindex)
+            .with_cache(EmbeddingCache::open(&path).unwrap());
+        embeddings.embed(&texts(&["read_file"])).await.unwrap();
+        assert_eq!(small.embedded(), 0);
+
+        let large = Arc::new(MockEmbeddingModel::new("text-large", 8));
+        let embeddings = Embeddings::new(large.clone(), ModelChangePolicy::Reindex)
+            .with_cache(EmbeddingCache::open(&path).unwrap());
+        let vectors = embeddings.embed(&texts(&["read_file"])).await.unwrap();
+        assert_eq!(large.embedded(), 1);
+        assert_eq!(vectors[0].len(), 8);
+
+        let cache = EmbeddingCache::open(&path).unwrap();
+        assert_eq!(
+            cache.built_with().unwrap().to_string(),
+            "mock/text-large (8 dimensions)"
+        );
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_model_switch_can_be_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("embeddings.json");
+        let small = Arc::new(MockEmbeddingModel::new("text-small", 4));
+        Embeddings::new(small, ModelChangePolicy::Error)
+            .with_cache(EmbeddingCache::open(&path).unwrap())
+            .embed(&texts(&["read_file"]))
+            .await
+            .unwrap();
+
+        let large = Arc::new(MockEmbeddingModel::new("text-large", 8));
+        let embeddings = Embeddings::new(large.clone(), ModelChangePolicy::Error)
+            .with_cache(EmbeddingCache::open(&path).unwrap());
+        let err = embeddings.embed(&texts(&["read_file"])).await.unwrap_err();
+        let err = err.downcast::<IncompatibleEmbeddings>().unwrap();
+        assert_eq!(err.built_with.to_string(), "mock/text-small (4 dimensions)");
+        assert_eq!(err.current.to_string(), "mock/text-large");
+        assert!(err.to_string().contains("embedding cache "), "{}", err);
+        assert_eq!(large.embedded(), 0);
+        // The cache is left alone
+        assert_eq!(EmbeddingCache::open(&path).unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_dimension_change_under_the_same_name() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("embeddings.json");
+        let model = Arc::new(MockEmbeddingModel::new("text-embedding-3-large", 3072));
+        Embeddings::new(model, ModelChangePolicy::Error)
+            .with_cache(EmbeddingCache::open(&path).unwrap())
+            .embed(&texts(&["a"]))
+            .await
+            .unwrap();
+
+        // e.g. the provider was asked for shortened vectors
+        let model = Arc::new(MockEmbeddingModel::new("text-embedding-3-large", 256));
+        let embeddings = Embeddings::new(model, ModelChangePolicy::Error)
+            .with_cache(EmbeddingCache::open(&path).unwrap());
+        let err = embeddings.embed(&texts(&["b"])).await.unwrap_err();
+        assert!(err.to_string().contains("(3072 dimensions)"), "{}", err);
+        assert!(err.to_string().contains("(256 dimensions)"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_fallback_serves_while_the_primary_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("embeddings.json");
+        let primary = Arc::new(MockEmbeddingModel::new("primary", 8));
+        let secondary = Arc::new(MockEmbeddingModel::new("secondary", 8));
+        let embeddings = Embeddings::new(primary.clone(), ModelChangePolicy::Error)
+            .with_fallback(secondary.clone(), None)
+            .with_cache(EmbeddingCache::open(&path).unwrap());
+        embeddings.embed(&texts(&["read_file"])).await.unwrap();
+
+        primary.fail_with(503);
+        let vectors = embeddings
+            .embed(&texts(&["read_file", "write_file"]))
+            .await
+            .unwrap();
+        assert_eq!(vectors.len(), 2);
+        // Cached vectors are the primary's, so the fallback embeds everything
+        assert_eq!(secondary.embedded(), 2);
+        assert!(embeddings.is_failed_over());
+        assert_eq!(
+            embeddings.info().to_string(),
+            "mock/secondary (8 dimensions)"
+        );
+
+        let status = embeddings.provider_status();
+        assert_eq!(status[0].status, HealthStatus::Down);
+        assert_eq!(status[0].consecutive_failures, 1);
+        assert!(status[0].last_error.as_ref().unwrap().contains("HTTP 503"));
+        assert!(!status[0].active);
+        assert_eq!(status[1].status, HealthStatus::Up);
+        assert!(status[1].active);
+        // The fallback leaves the cache alone
+        assert_eq!(EmbeddingCache::open(&path).unwrap().len(), 1);
+
+        primary.recover();
+        embeddings.embed(&texts(&["write_file"])).await.unwrap();
+        assert!(!embeddings.is_failed_over());
+        assert_eq!(embeddings.provider_status()[0].status, HealthStatus::Up);
+        assert_eq!(secondary.embedded(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_dimension_guard_blocks_an_incompatible_fallback() {
+        let primary = Arc::new(MockEmbeddingModel::new("primary", 8));
+        let declared = Arc::new(MockEmbeddingModel::new("declared", 4));
+        let undeclared = Arc::new(MockEmbeddingModel::new("undeclared", 4));
+        let embeddings = Embeddings::new(primary.clone(), ModelChangePolicy::Reindex)
+            .with_fallback(declared.clone(), Some(4))
+            .with_fallback(undeclared.clone(), None);
+        embeddings.embed(&texts(&["a"])).await.unwrap();
+
+        primary.fail_with(503);
+        let err = embeddings.embed(&texts(&["b"])).await.unwrap_err();
+        // The primary's error is kept for callers deciding to retry
+        assert!(crate::transport::is_retriable(&err));
+        let message = err.to_string();
+        assert!(
+            message.contains("mock/declared has 4 dimensions"),
+            "{}",
+            message
+        );
+        assert!(
+            message.contains("mock/undeclared has 4 dimensions"),
+            "{}",
+            message
+        );
+        assert!(
+            message.contains("allow_dimension_change_reindex"),
+            "{}",
+            message
+        );
+        // A declared mismatch is caught without a request
+        assert_eq!(declared.embedded(), 0);
+        assert_eq!(undeclared.embedded(), 1);
+        assert!(!embeddings.is_failed_over());
+
+        let embeddings = embeddings.allow_dimension_change_reindex(true);
+        let vectors = embeddings.embed(&texts(&["b"])).await.unwrap();
+        assert_eq!(vectors[0].len(), 4);
+        assert_eq!(embeddings.info().model, "declared");
+    }
+
+    #[tokio::test]
+    async fn test_non_retriable_errors_dont_fail_over() {
+        let primary = Arc::new(MockEmbeddingModel::new("primary", 8));
+        let secondary = Arc::new(MockEmbeddingModel::new("secondary", 8));
+        let embeddings = Embeddings::new(primary.clone(), ModelChangePolicy::Reindex)
+            .with_fallback(secondary.clone(), Some(8));
+
+        // A rejected key would be rejected on every attempt
+        primary.fail_with(401);
+        let err = embeddings.embed(&texts(&["a"])).await.unwrap_err();
+        assert!(err.to_string().contains("HTTP 401"), "{}", err);
+        assert_eq!(secondary.embedded(), 0);
+        assert_eq!(embeddings.provider_status()[0].consecutive_failures, 1);
+    }
+}
@@ -0,0 +1,521 @@
+//! Vector indexes persisted to disk
+
+use anyhow::{Context, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+use super::{EmbeddingInfo, VectorIndex};
+
+/// Version of the index file format; files of another version are rebuilt
+pub const INDEX_FORMAT_VERSION: u32 = 1;
+
+/// Named vector indexes, stored as one JSON file
+///
+/// Each index records the model it was built with, and each item the SHA-256
+/// of the text its vector was embedded from, so a restart only embeds the
+/// items whose text changed and [`IndexStore::verify`] can tell what is out
+/// of date without embedding anything.
+pub struct IndexStore<T> {
+    path: PathBuf,
+    file: IndexFile<T>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct IndexFile<T> {
+    version: u32,
+    indexes: BTreeMap<String, StoredIndex<T>>,
+}
+
+impl<T> IndexFile<T> {
+    fn empty() -> Self {
+        Self {
+            version: INDEX_FORMAT_VERSION,
+            indexes: BTreeMap::new(),
+        }
+    }
+}
+
+/// Read before the rest, which may not parse in another version
+#[derive(Deserialize)]
+struct Version {
+    version: u32,
+}
+
+/// An index as it is stored
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoredIndex<T> {
+    pub built_with: EmbeddingInfo,
+    pub items: Vec<StoredItem<T>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoredItem<T> {
+    /// Identifies the item within its index, e.g. a tool name
+    pub key: String,
+    /// SHA-256 of the text the vector was embedded from
+    pub hash: String,
+    pub item: T,
+    pub vector: Vec<f32>,
+}
+
+impl<T: Clone> StoredIndex<T> {
+    /// The index in memory; fails if its vectors don't all have the recorded
+    /// dimensionality
+    pub fn to_index(&self) -> Result<VectorIndex<T>> {
+        let mut index = VectorIndex::new(self.built_with.clone());
+        for stored in &self.items {
+            index
+                .insert(stored.item.clone(), stored.vector.clone())
+                .with_context(|| format!("Item '{}'", stored.key))?;
+        }
+        Ok(index)
+    }
+}
+
+impl<T: Clone + Serialize + DeserializeOwned> IndexStore<T> {
+    /// Load the store at `path`; a missing file is an empty store, and so is
+    /// one written in another format version
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = match std::fs::read(&path) {
+            Ok(bytes) => {
+                let invalid = || format!("Invalid index store {}", path.display());
+                let Version { version } = serde_json::from_slice(&bytes).with_context(invalid)?;
+                if version == INDEX_FORMAT_VERSION {
+                    serde_json::from_slice(&bytes).with_context(invalid)?
+                } else {
+                    tracing::warn!(
+                        "Index store {} has format version {}, rebuilding it as version {}",
+                        path.display(),
+                        version,
+                        INDEX_FORMAT_VERSION
+                    );
+                    IndexFile::empty()
+                }
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => IndexFile::empty(),
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("Failed to read index store {}", path.display()))
+            }
+        };
+        Ok(Self { path, file })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Names of the stored indexes, sorted
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.file.indexes.keys().map(String::as_str)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&StoredIndex<T>> {
+        self.file.indexes.get(name)
+    }
+
+    /// Store `index` as `name`, replacing what was stored under it; `key`
+    /// and `hash` give each item's key and the hash of its embedded text
+    pub fn put(
+        &mut self, name: &str, index: &VectorIndex<T>, key: impl Fn(&T) -> String,
+        hash: impl Fn(&T) -> String,
+    ) {
+        let items = index
+            .entries()
+            .map(|(item, vector)| StoredItem {
+                key: key(item),
+                hash: hash(item),
+                item: item.clone(),
+                vector: vector.to_vec(),
+            })
+            .collect();
+        let stored = StoredIndex {
+            built_with: index.built_with().clone(),
+            items,
+        };
+        self.file.indexes.insert(name.to_string(), stored);
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<StoredIndex<T>> {
+        self.file.indexes.remove(name)
+    }
+
+    /// Compare the index stored as `name` with the items offered now, as
+    /// `(key, hash)` pairs, and the model that would embed them
+    pub fn verify(
+        &self, name: &str, current: &[(String, String)], model: &EmbeddingInfo,
+    ) -> IndexVerification {
+        let mut verification = IndexVerification {
+            name: name.to_string(),
+            built_with: None,
+            model: model.clone(),
+            compatible: true,
+            up_to_date: 0,
+            changed: Vec::new(),
+            missing: Vec::new(),
+            orphaned: Vec::new(),
+            malformed: Vec::new(),
+        };
+        let Some(stored) = self.get(name) else {
+            verification.missing = current.iter().map(|(key, _)| key.clone()).collect();
+            return verification;
+        };
+        verification.built_with = Some(stored.built_with.clone());
+        verification.compatible = stored.built_with.is_compatible(model);
+        let hashes: HashMap<&str, &str> = stored
+            .items
+            .iter()
+            .map(|item| (item.key.as_str(), item.hash.as_str()))
+            .collect();
+        for (key, hash) in current {
+            match hashes.get(key.as_str()) {
+                Some(known) if *known == hash.as_str() => verification.up_to_date += 1,
+                Some(_) => verification.changed.push(key.clone()),
+                None => verification.missing.push(key.clone()),
+            }
+        }
+        for item in &stored.items {
+            if !current.iter().any(|(key, _)| *key == item.key) {
+                verification.orphaned.push(item.key.clone());
+            }
+            if stored.built_with.dimensions != Some(item.vector.len()) {
+                verification.malformed.push(item.key.clone());
+            }
+        }
+        verification
+    }
+
+    /// Drop the indexes not in `current`, and the items of the others that
+    /// are no longer offered or whose text changed
+    ///
+    /// `current` maps each live index to its items as `(key, hash)` pairs.
+    pub fn gc(&mut self, current: &BTreeMap<String, Vec<(String, String)>>) -> IndexGc {
+        let mut gc = IndexGc {
+            path: self.path.clone(),
+            indexes: Vec::new(),
+            items: 0,
+        };
+        self.file.indexes.retain(|name, stored| {
+            let Some(items) = current.get(name) else {
+                gc.indexes.push(name.clone());
+                return false;
+            };
+            let before = stored.items.len();
+            stored
+                .items
+                .retain(|item| items.contains(&(item.key.clone(), item.hash.clone())));
+            gc.items += before - stored.items.len();
+            true
+        });
+        gc
+    }
+
+    /// Write the store back to its file
+    pub fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let json = serde_json::to_vec(&self.file)?;
+        std::fs::write(&self.path, json)
+            .with_context(|| format!("Failed to write index store {}", self.path.display()))
+    }
+}
+
+/// How a stored index compares with the items offered now
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexVerification {
+    pub name: String,
+    /// `None` when nothing is stored under the name
+    pub built_with: Option<EmbeddingInfo>,
+    /// The model the items would be embedded with now
+    pub model: EmbeddingInfo,
+    /// Whether the stored vectors can be compared with the model's
+    pub compatible: bool,
+    pub up_to_date: usize,
+    /// Keys of the items whose text changed since they were embedded
+    pub changed: Vec<String>,
+    /// Keys of the items offered now but not stored
+    pub missing: Vec<String>,
+    /// Keys of the stored items no longer offered
+    pub orphaned: Vec<String>,
+    /// Keys of the stored items whose vector has the wrong length
+    pub malformed: Vec<String>,
+}
+
+impl IndexVerification {
+    pub fn is_clean(&self) -> bool {
+        self.built_with.is_some()
+            && self.compatible
+            && self.changed.is_empty()
+            && self.missing.is_empty()
+            && self.orphaned.is_empty()
+            && self.malformed.is_empty()
+    }
+}
+
+impl fmt::Display for IndexVerification {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some(built_with) = &self.built_with else {
+            return write!(f, "{}: not stored, {} items", self.name, self.missing.len());
+        };
+        write!(f, "{}: {} up to date", self.name, self.up_to_date)?;
+        for (what, keys) in [
+            ("changed", &self.changed),
+            ("missing", &self.missing),
+            ("orphaned", &self.orphaned),
+            ("malformed", &self.malformed),
+        ] {
+            if !keys.is_empty() {
+                write!(f, ", {} {} ({})", keys.len(), what, keys.join(", "))?;
+            }
+        }
+        if !self.compatible {
+            write!(
+                f,
+                "; built with {}, but the model is {}",
+                built_with, self.model
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Every stored index compared with what is offered now
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexReport {
+    pub path: PathBuf,
+    pub indexes: Vec<IndexVerification>,
+    /// Stored indexes nothing offers items for any more
+    pub orphaned: Vec<String>,
+}
+
+impl IndexReport {
+    pub fn is_clean(&self) -> bool {
+        self.orphaned.is_empty() && self.indexes.iter().all(IndexVerification::is_clean)
+    }
+
+    /// Fail with [`IndexOutOfDate`] unless the report is clean
+    pub fn check(&self) -> Result<(), IndexOutOfDate> {
+        if self.is_clean() {
+            return Ok(());
+        }
+        Err(IndexOutOfDate {
+            path: self.path.clone(),
+            stale: self
+                .indexes
+                .iter()
+                .filter(|index| !index.is_clean())
+                .map(|index| index.name.clone())
+                .chain(self.orphaned.iter().cloned())
+                .collect(),
+        })
+    }
+}
+
+impl fmt::Display for IndexReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Index store {}", self.path.display())?;
+        for index in &self.indexes {
+            let mark = if index.is_clean() { "ok" } else { "STALE" };
+            writeln!(f, "  {:<6} {}", mark, index)?;
+        }
+        for name in &self.orphaned {
+            writeln!(f, "  {:<6} {}: nothing offers it any more", "STALE", name)?;
+        }
+        Ok(())
+    }
+}
+
+/// A stored index doesn't match what is offered now
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error(
+    "Index store {} is out of date for {}; run `index rebuild`",
+    .path.display(),
+    .stale.join(", ")
+)]
+pub struct IndexOutOfDate {
+    pub path: PathBuf,
+    pub stale: Vec<String>,
+}
+
+/// What [`IndexStore::gc`] dropped
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexGc {
+    pub path: PathBuf,
+    /// Names of the indexes dropped whole
+    pub indexes: Vec<String>,
+    /// Items dropped from the indexes that were kept
+    pub items: usize,
+}
+
+impl fmt::Display for IndexGc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Removed {} indexes and {} items from {}",
+            self.indexes.len(),
+            self.items,
+            self.path.display()
+        )?;
+        if !self.indexes.is_empty() {
+            write!(f, " ({})", self.indexes.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// What [`crate::RigMcpClient::rebuild_index`] did
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexRebuild {
+    /// `None` when the indexes live only in memory
+    pub path: Option<PathBuf>,
+    pub indexes: usize,
+    pub items: usize,
+    /// Texts sent to an embedding model; the others kept their vectors
+    pub embedded: usize,
+}
+
+impl fmt::Display for IndexRebuild {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Rebuilt {} indexes of {} items, embedding {}",
+            self.indexes, self.items, self.embedded
+        )?;
+        if let Some(path) = &self.path {
+            write!(f, "; saved to {}", path.display())?;
+        }
+        Ok(())
+    }
+}
+
+/// An index the client holds, as [`crate::RigMcpClient::index_status`]
+/// reports it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexEntryStatus {
+    pub name: String,
+    pub built_with: EmbeddingInfo,
+    pub items: usize,
+    /// Whether the model its items are embedded with now can use it
+    pub compatible: bool,
+}
+
+/// The indexes a client holds and where they are persisted
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexStatus {
+    /// `None` when the indexes live only in memory
+    pub path: Option<PathBuf>,
+    pub indexes: Vec<IndexEntryStatus>,
+}
+
+impl fmt::Display for IndexStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.path {
+            Some(path) => writeln!(f, "Index store {}", path.display())?,
+            None => writeln!(f, "Indexes in memory only")?,
+        }
+        for index in &self.indexes {
+            write!(
+                f,
+                "  {}: {} items, {}",
+                index.name, index.items, index.built_with
+            )?;
+            if !index.compatible {
+                write!(f, " (incompatible with the current model)")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(dimensions: usize) -> EmbeddingInfo {
+        EmbeddingInfo {
+            provider: "mock".to_string(),
+            model: "words".to_string(),
+            dimensions: Some(dimensions),
+        }
+    }
+
+    fn items(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(key, hash)| (key.to_string(), hash.to_string()))
+            .collect()
+    }
+
+    fn store(path: &Path) -> IndexStore<String> {
+        let mut index = VectorIndex::new(info(2));
+        index.insert("a".to_string(), vec![1.0, 0.0]).unwrap();
+        index.insert("b".to_string(), vec![0.0, 1.0]).unwrap();
+        let mut store = IndexStore::open(path).unwrap();
+        store.put("docs", &index, String::clone, |item| format!("#{}", item));
+        store.save().unwrap();
+        store
+    }
+
+    #[test]
+    fn test_roundtrip_and_verify() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("index.json");
+        store(&path);
+
+        let store = IndexStore::<String>::open(&path).unwrap();
+        let index = store.get("docs").unwrap().to_index().unwrap();
+        assert_eq!(index.len(), 2);
+        assert_eq!(index.built_with(), &info(2));
+
+        let current = items(&[("a", "#a"), ("b", "#b2"), ("c", "#c")]);
+        let verification = store.verify("docs", &current, &info(2));
+        assert_eq!(verification.up_to_date, 1);
+        assert_eq!(verification.changed, ["b"]);
+        assert_eq!(verification.missing, ["c"]);
+        assert!(!verification.is_clean());
+
+        let verification = store.verify("docs", &items(&[("a", "#a")]), &info(3));
+        assert!(!verification.compatible);
+        assert_eq!(verification.orphaned, ["b"]);
+        assert!(verification.to_string().contains("(3 dimensions)"));
+
+        let verification = store.verify("notes", &items(&[("a", "#a")]), &info(2));
+        assert_eq!(verification.to_string(), "notes: not stored, 1 items");
+    }
+
+    #[test]
+    fn test_gc() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("index.json");
+        let mut store = store(&path);
+        let mut index = VectorIndex::new(info(2));
+        index.insert("x".to_string(), vec![1.0, 1.0]).unwrap();
+        store.put("gone", &index, String::clone, String::clone);
+
+        let current = BTreeMap::from([("docs".to_string(), items(&[("a", "#a"), ("b", "#b2")]))]);
+        let gc = store.gc(&current);
+        assert_eq!(gc.indexes, ["gone"]);
+        assert_eq!(gc.items, 1);
+        assert_eq!(store.names().collect::<Vec<_>>(), ["docs"]);
+        assert_eq!(store.get("docs").unwrap().items[0].key, "a");
+    }
+
+    #[test]
+    fn test_other_format_versions_start_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("index.json");
+        std::fs::write(&path, r#"{"version": 0, "tools": []}"#).unwrap();
+        let store = IndexStore::<String>::open(&path).unwrap();
+        assert_eq!(store.names().count(), 0);
+
+        std::fs::write(&path, "not json").unwrap();
+        assert!(IndexStore::<String>::open(&path).is_err());
+    }
+}
@@ -0,0 +1,689 @@
+//! Evaluation suites
+//!
+//! An [`EvalSuite`] lists prompts and what their answers must satisfy: a
+//! substring, a regular expression, a value at a JSON path, or a rubric
+//! graded by a judge model. [`crate::RigMcpClient::run_evals`] runs every
+//! case and reports each assertion with the answer it was checked against,
+//! plus the share of cases that passed. The example binary's `eval` command
+//! fails when that share is below the suite's threshold, so provider and
+//! prompt changes can be gated in CI.
+//!
+//! Judge calls are capped by [`JudgeConfig`]: an assertion that would go
+//! over the limit fails instead of calling the judge.
+
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+use std::path::Path;
+use thiserror::Error;
+
+use crate::provider::{ChatMessage, ChatRequest, NormalizedResponse, NormalizedUsage, Role};
+use crate::RigMcpClient;
+
+const JUDGE_SYSTEM_PROMPT: &str = concat!(
+    "You grade answers against a rubric. Reply with only a JSON object: ",
+    "{\"pass\": true or false, \"reason\": \"one sentence\"}."
+);
+
+/// Cases and the score they must reach
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EvalSuite {
+    #[serde(default)]
+    pub name: String,
+    /// Provider name, model alias or `provider/model` pair for cases that
+    /// don't name one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    /// Share of cases that must pass, from 0 to 1; all of them by default
+    #[serde(default = "all_cases")]
+    pub threshold: f64,
+    /// Needed by `judge` assertions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub judge: Option<JudgeConfig>,
+    pub cases: Vec<EvalCase>,
+}
+
+fn all_cases() -> f64 {
+    1.0
+}
+
+/// One prompt and its assertions
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EvalCase {
+    pub name: String,
+    /// Instead of the suite's provider
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system: Option<String>,
+    /// Conversation before `prompt`, or the whole conversation without one
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub messages: Vec<ChatMessage>,
+    /// Sent as the last user message
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(default, rename = "assert")]
+    pub assertions: Vec<Assertion>,
+}
+
+/// What an answer must satisfy
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Assertion {
+    Contains {
+        value: String,
+        #[serde(default)]
+        ignore_case: bool,
+    },
+    /// Matches anywhere in the answer
+    Regex { pattern: String },
+    /// The answer is JSON, optionally in a code fence, and `path` (e.g.
+    /// `$.items[0].sku`) holds `equals`
+    JsonPath { path: String, equals: Value },
+    /// The judge model finds that the answer meets `rubric`
+    Judge { rubric: String },
+}
+
+impl fmt::Display for Assertion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Assertion::Contains { value, .. } => write!(f, "contains {:?}", value),
+            Assertion::Regex { pattern } => write!(f, "matches /{}/", pattern),
+            Assertion::JsonPath { path, equals } => write!(f, "{} == {}", path, equals),
+            Assertion::Judge { rubric } => write!(f, "judge: {}", rubric),
+        }
+    }
+}
+
+/// The model grading `judge` assertions and its limits for one run
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JudgeConfig {
+    /// Provider name, model alias or `provider/model` pair
+    pub provider: String,
+    /// Prompt plus completion tokens over all judge calls
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_total_tokens: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_calls: Option<usize>,
+    /// Completion tokens of one verdict
+    #[serde(default = "verdict_tokens")]
+    pub max_tokens: usize,
+}
+
+fn verdict_tokens() -> usize {
+    256
+}
+
+impl EvalSuite {
+    /// Read a suite from a `.toml`, `.yaml`/`.yml` or `.json` file
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read eval suite {}", path.display()))?;
+        let extension = path.extension().and_then(|ext| ext.to_str());
+        let suite: Self = match extension {
+            Some("toml") => toml::from_str(&content)?,
+            Some("yaml" | "yml") => serde_yaml::from_str(&content)?,
+            Some("json") => serde_json::from_str(&content)?,
+            _ => anyhow::bail!("{} is not a .toml, .yaml or .json file", path.display()),
+        };
+        suite
+            .validate()
+            .with_context(|| format!("Invalid eval suite {}", path.display()))?;
+        Ok(suite)
+    }
+
+    /// Check what can be checked without running the cases
+    pub fn validate(&self) -> Result<()> {
+        anyhow::ensure!(!self.cases.is_empty(), "Suite has no cases");
+        anyhow::ensure!(
+            (0.0..=1.0).contains(&self.threshold),
+            "Threshold {} is not between 0 and 1",
+            self.threshold
+        );
+        for case in &self.cases {
+            let invalid = || format!("Case '{}'", case.name);
+            anyhow::ensure!(
+                case.prompt.is_some() || !case.messages.is_empty(),
+                "Case '{}' has neither a prompt nor messages",
+                case.name
+            );
+            anyhow::ensure!(
+                case.provider.is_some() || self.provider.is_some(),
+                "Case '{}' names no provider and the suite has none",
+                case.name
+            );
+            for assertion in &case.assertions {
+                match assertion {
+                    Assertion::Contains { .. } => {}
+                    Assertion::Regex { pattern } => {
+                        Regex::new(pattern).with_context(invalid)?;
+                    }
+                    Assertion::JsonPath { path, .. } => {
+                        json_pointer(path)
+                            .with_context(|| format!("Invalid JSON path '{}'", path))
+                            .with_context(invalid)?;
+                    }
+                    Assertion::Judge { .. } => anyhow::ensure!(
+                        self.judge.is_some(),
+                        "Case '{}' has a judge assertion but the suite has no judge",
+                        case.name
+                    ),
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Outcome of one assertion
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AssertionResult {
+    pub assertion: Assertion,
+    pub passed: bool,
+    /// Why it failed, or the judge's reasoning
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+/// Outcome of one case
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CaseResult {
+    pub name: String,
+    pub provider: String,
+    /// The completion succeeded and every assertion held
+    pub passed: bool,
+    /// What the assertions were checked against
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub answer: Option<String>,
+    /// Why there is no answer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub assertions: Vec<AssertionResult>,
+}
+
+/// Result of [`crate::RigMcpClient::run_evals`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EvalReport {
+    pub suite: String,
+    pub cases: Vec<CaseResult>,
+    pub passed: usize,
+    pub failed: usize,
+    /// Share of cases that passed
+    pub score: f64,
+    pub threshold: f64,
+    pub judge_calls: usize,
+    /// Spent on judge calls, estimated where the judge didn't report it
+    pub judge_usage: NormalizedUsage,
+}
+
+/// A suite scored below its threshold
+#[derive(Debug, Clone, PartialEq, Error)]
+#[error("Eval score {score:.2} is below the threshold of {threshold:.2}")]
+pub struct BelowThreshold {
+    pub score: f64,
+    pub threshold: f64,
+}
+
+impl EvalReport {
+    pub fn meets_threshold(&self) -> bool {
+        self.score >= self.threshold
+    }
+
+    /// Fail with [`BelowThreshold`] unless the score meets the threshold
+    pub fn check(&self) -> Result<(), BelowThreshold> {
+        if self.meets_threshold() {
+            return Ok(());
+        }
+        Err(BelowThreshold {
+            score: self.score,
+            threshold: self.threshold,
+        })
+    }
+}
+
+impl fmt::Display for EvalReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for case in &self.cases {
+            writeln!(
+                f,
+                "{} {} ({})",
+                if case.passed { "PASS" } else { "FAIL" },
+                case.name,
+                case.provider
+            )?;
+            if let Some(error) = &case.error {
+                writeln!(f, "     error: {}", error)?;
+            }
+            for result in case.assertions.iter().filter(|result| !result.passed) {
+                match &result.detail {
+                    Some(detail) => writeln!(f, "     {}: {}", result.assertion, detail)?,
+                    None => writeln!(f, "     {}", result.assertion)?,
+                }
+            }
+        }
+        write!(
+            f,
+            "{}/{} cases passed, score {:.2} (threshold {:.2})",
+            self.passed,
+            self.cases.len(),
+            self.score,
+            self.threshold
+        )
+    }
+}
+
+/// Run every case of `suite`, one after another
+pub(crate) async fn run(client: &RigMcpClient, suite: &EvalSuite) -> Result<EvalReport> {
+    suite.validate()?;
+    let mut judge = Judge {
+        config: suite.judge.as_ref(),
+        calls: 0,
+        usage: NormalizedUsage::default(),
+    };
+    let mut cases = Vec::with_capacity(suite.cases.len());
+    for case in &suite.cases {
+        cases.push(run_case(client, suite, case, &mut judge).await);
+    }
+    let passed = cases.iter().filter(|case| case.passed).count();
+    Ok(EvalReport {
+        suite: suite.name.clone(),
+        failed: cases.len() - passed,
+        score: passed as f64 / cases.len() as f64,
+        threshold: suite.threshold,
+        judge_calls: judge.calls,
+        judge_usage: judge.usage,
+        passed,
+        cases,
+    })
+}
+
+async fn run_case(
+    client: &RigMcpClient, suite: &EvalSuite, case: &EvalCase, judge: &mut Judge<'_>,
+) -> CaseResult {
+    let provider = case
+        .provider
+        .clone()
+        .or_else(|| suite.provider.clone())
+        .unwrap_or_default();
+    let mut result = CaseResult {
+        name: case.name.clone(),
+        provider: provider.clone(),
+        passed: false,
+        answer: None,
+        error: None,
+        assertions: Vec::new(),
+    };
+    let mut messages = case.messages.clone();
+    messages.extend(case.prompt.as_deref().map(ChatMessage::user));
+    let request = ChatRequest {
+        system: case.system.clone(),
+        messages,
+        max_tokens: case.max_tokens,
+        temperature: case.temperature,
+        ..ChatRequest::default()
+    };
+    let question = request
+        .messages
+        .iter()
+        .rev()
+        .find(|message| message.role == Role::User)
+        .map(|message| message.content.clone())
+        .unwrap_or_default();
+    let answer = match complete(client, &provider, request).await {
+        Ok(response) => response.content,
+        Err(err) => {
+            result.error = Some(format!("{:#}", err));
+            return result;
+        }
+    };
+    for assertion in &case.assertions {
+        let (passed, detail) = match assertion {
+            Assertion::Judge { rubric } => judge.grade(client, rubric, &question, &answer).await,
+            assertion => check(assertion, &answer),
+        };
+        result.assertions.push(AssertionResult {
+            assertion: assertion.clone(),
+            passed,
+            detail,
+        });
+    }
+    result.passed = result.assertions.iter().all(|result| result.passed);
+    result.answer = Some(answer);
+    result
+}
+
+async fn complete(
+    client: &RigMcpClient, name: &str, request: ChatRequest,
+) -> Result<NormalizedResponse> {
+    client.provider(name).await?.complete(request).await
+}
+
+/// Evaluate an assertion that needs no model
+fn check(assertion: &Assertion, answer: &str) -> (bool, Option<String>) {
+    match assertion {
+        Assertion::Contains { value, ignore_case } => {
+            let found = if *ignore_case {
+                answer.to_lowercase().contains(&value.to_lowercase())
+            } else {
+                answer.contains(value.as_str())
+            };
+            (found, None)
+        }
+        Assertion::Regex { pattern } => match Regex::new(pattern) {
+            Ok(regex) => (regex.is_match(answer), None),
+            Err(err) => (false, Some(err.to_string())),
+        },
+        Assertion::JsonPath { path, equals } => {
+            let Some(pointer) = json_pointer(path) else {
+                return (false, Some(format!("Invalid JSON path '{}'", path)));
+            };
+            let json: Value = match serde_json::from_str(unfence(answer)) {
+                Ok(json) => json,
+                Err(err) => return (false, Some(format!("Answer is not JSON: {}", err))),
+            };
+            match json.pointer(&pointer) {
+                Some(actual) if actual == equals => (true, None),
+                Some(actual) => (false, Some(format!("got {}", actual))),
+                None => (false, Some(format!("{} not found", path))),
+            }
+        }
+        Assertion::Judge { .. } => (false, Some("Needs a judge".to_string())),
+    }
+}
+
+/// The contents of a fenced code block, or the whole answer
+fn unfence(answer: &str) -> &str {
+    let answer = answer.trim();
+    let Some(fenced) = answer.strip_prefix("```") else {
+        return answer;
+    };
+    // Skip the info string, e.g. `json`
+    let body = fenced.split_once('\n').map_or("", |(_, body)| body);
+    body.trim_end().strip_suffix("```").unwrap_or(body).trim()
+}
+
+/// `$.items[0].sku` as the JSON pointer `/items/0/sku`; the leading `$` is
+/// optional
+fn json_pointer(path: &str) -> Option<String> {
+    let path = path.strip_prefix('$').unwrap_or(path);
+    let dotted;
+    let mut rest = if path.is_empty() || path.starts_with(['.', '[']) {
+        path
+    } else {
+        dotted = format!(".{}", path);
+        &dotted
+    };
+    let mut pointer = String::new();
+    while !rest.is_empty() {
+        let segment;
+        if let Some(after) = rest.strip_prefix('.') {
+            let end = after.find(['.', '[']).unwrap_or(after.len());
+            segment = &after[..end];
+            rest = &after[end..];
+        } else {
+            let after = rest.strip_prefix('[')?;
+            let end = after.find(']')?;
+            segment = after[..end].trim_matches(['"', '\'']);
+            rest = &after[end + 1..];
+        }
+        if segment.is_empty() {
+            return None;
+        }
+        pointer.push('/');
+        pointer.push_str(&segment.replace('~', "~0").replace('/', "~1"));
+    }
+    Some(pointer)
+}
+
+/// Judge calls of one run and what they have used up
+struct Judge<'a> {
+    config: Option<&'a JudgeConfig>,
+    calls: usize,
+    usage: NormalizedUsage,
+}
+
+impl Judge<'_> {
+    async fn grade(
+        &mut self, client: &RigMcpClient, rubric: &str, question: &str, answer: &str,
+    ) -> (bool, Option<String>) {
+        let Some(config) = self.config else {
+            return (false, Some("The suite has no judge".to_string()));
+        };
+        if config.max_calls.is_some_and(|max| self.calls >= max) {
+            let detail = format!("Judge budget exhausted after {} calls", self.calls);
+            return (false, Some(detail));
+        }
+        let request = ChatRequest {
+            system: Some(JUDGE_SYSTEM_PROMPT.to_string()),
+            messages: vec![ChatMessage::user(format!(
+                "Rubric:\n{}\n\nPrompt:\n{}\n\nAnswer:\n{}",
+                rubric, question, answer
+            ))],
+            max_tokens: Some(config.max_tokens),
+            temperature: Some(0.0),
+            ..ChatRequest::default()
+        };
+        // The judge call fails below if the provider doesn't exist
+        let counter = client
+            .token_counter(&config.provider)
+            .await
+            .unwrap_or_default();
+        let prompt_tokens = counter.count_request(&request);
+        // The most this call can add, so the limit holds whatever the judge answers
+        let worst_case = prompt_tokens + config.max_tokens as u64;
+        if let Some(max) = config.max_total_tokens {
+            if self.usage.total_tokens + worst_case > max {
+                let detail = format!(
+                    "Judge budget exhausted: {} of {} tokens used",
+                    self.usage.total_tokens, max
+                );
+                return (false, Some(detail));
+            }
+        }
+
+        self.calls += 1;
+        let response = match complete(client, &config.provider, request).await {
+            Ok(response) => response,
+            Err(err) => return (false, Some(format!("Judge failed: {:#}", err))),
+        };
+        let usage = if response.usage.total_tokens > 0 {
+            response.usage
+        } else {
+            NormalizedUsage::estimate(prompt_tokens, counter.count(&response.content))
+        };
+        self.usage.prompt_tokens += usage.prompt_tokens;
+        self.usage.completion_tokens += usage.completion_tokens;
+        self.usage.total_tokens += usage.total_tokens;
+        self.usage.estimated |= usage.estimated;
+        verdict(&response.content)
+    }
+}
+
+/// Pass/fail and reason from the judge's JSON, which may be wrapped in prose
+fn verdict(reply: &str) -> (bool, Option<String>) {
+    let json = match (reply.find('{'), reply.rfind('}')) {
+        (Some(start), Some(end)) if start < end => {
+            serde_json::from_str::<Value>(&reply[start..=end]).ok()
+        }
+        _ => None,
+    };
+    match json {
+        Some(json) => (
+            json["pass"].as_bool().unwrap_or(false),
+            json["reason"].as_str().map(str::to_string),
+        ),
+        None => (false, Some(format!("Unreadable verdict: {}", reply))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::Provider;
+    use crate::testing::MockProvider;
+    use crate::Config;
+    use std::sync::Arc;
+
+    fn fixture() -> EvalSuite {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/evals/suite.yaml");
+        EvalSuite::from_file(&path).unwrap()
+    }
+
+    #[test]
+    fn test_suite_formats_agree() {
+        let toml = r#"
+            name = "support answers"
+            provider = "mock"
+            threshold = 0.75
+
+            [judge]
+            provider = "judge"
+            max_calls = 2
+
+            [[cases]]
+            name = "greeting"
+            prompt = "Say hello to Ada"
+            assert = [
+                { type = "contains", value = "hello", ignore_case = true },
+                { type = "regex", pattern = '\bAda\b' },
+            ]
+        "#;
+        let mut suite: EvalSuite = toml::from_str(toml).unwrap();
+        suite.validate().unwrap();
+        let mut yaml = fixture();
+        yaml.cases.truncate(1);
+        assert_eq!(suite, yaml);
+
+        suite.cases[0].assertions.push(Assertion::Regex {
+            pattern: "(".to_string(),
+        });
+        let err = suite.validate().unwrap_err();
+        assert!(
+            format!("{:#}", err).starts_with("Case 'greeting'"),
+            "{:#}",
+            err
+        );
+        suite.judge = None;
+        suite.cases[0].assertions = vec![Assertion::Judge {
+            rubric: "Polite".to_string(),
+        }];
+        assert!(suite.validate().is_err());
+    }
+
+    #[test]
+    fn test_json_paths() {
+        assert_eq!(
+            json_pointer("$.order.items[0].sku").unwrap(),
+            "/order/items/0/sku"
+        );
+        assert_eq!(json_pointer("order['a/b']").unwrap(), "/order/a~1b");
+        assert_eq!(json_pointer("$").unwrap(), "");
+        assert!(json_pointer("$.order..sku").is_none());
+        assert!(json_pointer("$.items[0").is_none());
+        assert_eq!(unfence("```json\n{\"a\": 1}\n```"), "{\"a\": 1}");
+    }
+
+    #[tokio::test]
+    async fn test_fixture_suite_against_scripted_mocks() {
+        let provider = MockProvider::new("mock");
+        provider.push_response(NormalizedResponse::text("Hello, Ada!"));
+        let order = serde_json::json!({
+            "order": { "status": "pending", "items": [{ "sku": "ABC-1" }] },
+        });
+        provider.push_response(NormalizedResponse::text(format!("```json\n{}\n```", order)));
+        provider.push_response(NormalizedResponse::text(
+            "Sorry about that, I'll send a replacement.",
+        ));
+        provider.push_response(NormalizedResponse::text("You were wrong."));
+        let judge = Arc::new(MockProvider::new("judge"));
+        judge.push_response(NormalizedResponse::text(
+            r#"{"pass": true, "reason": "Apologizes and offers a replacement"}"#,
+        ));
+        judge.push_response(NormalizedResponse::text(
+            r#"Verdict: {"pass": false, "reason": "Blunt"}"#,
+        ));
+        let client = RigMcpClient::from_parts(
+            Config::default(),
+            vec![Arc::new(provider) as Arc<dyn Provider>, judge.clone()],
+            vec![],
+        );
+
+        let report = client.run_evals(&fixture()).await.unwrap();
+
+        let outcomes: Vec<(&str, bool)> = report
+            .cases
+            .iter()
+            .map(|case| (case.name.as_str(), case.passed))
+            .collect();
+        assert_eq!(
+            outcomes,
+            [
+                ("greeting", true),
+                ("order lookup", false),
+                ("refund", true),
+                ("tone", false),
+            ]
+        );
+        assert_eq!((report.passed, report.failed, report.score), (2, 2, 0.5));
+
+        let order = &report.cases[1];
+        assert!(order.answer.as_deref().unwrap().contains("pending"));
+        assert!(order.assertions[0].passed);
+        assert_eq!(
+            order.assertions[1].detail.as_deref(),
+            Some("got \"pending\"")
+        );
+
+        let refund = &report.cases[2].assertions[0];
+        assert_eq!(
+            refund.detail.as_deref(),
+            Some("Apologizes and offers a replacement")
+        );
+        let requests = judge.requests();
+        let judged = &requests[0].messages[0].content;
+        assert!(judged.contains("Offers a refund or replacement"));
+        assert!(judged.contains("Order 42, it arrived broken."));
+        assert!(judged.contains("I'll send a replacement"));
+
+        // The second judge call uses up max_calls; the third isn't made
+        let tone = &report.cases[3].assertions;
+        assert_eq!(tone[0].detail.as_deref(), Some("Blunt"));
+        assert_eq!(
+            tone[1].detail.as_deref(),
+            Some("Judge budget exhausted after 2 calls")
+        );
+        assert_eq!(judge.requests().len(), 2);
+        assert_eq!(report.judge_calls, 2);
+        assert!(report.judge_usage.estimated);
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(
+            json["cases"][1]["assertions"][1]["assertion"]["type"],
+            "json_path"
+        );
+        assert_eq!(json["threshold"], 0.75);
+
+        // Exit status of the eval command
+        assert_eq!(
+            report.check(),
+            Err(BelowThreshold {
+                score: 0.5,
+                threshold: 0.75
+            })
+        );
+        let lenient = EvalReport {
+            threshold: 0.5,
+            ..report.clone()
+        };
+        assert!(lenient.check().is_ok());
+        assert!(report
+            .to_string()
+            .ends_with("2/4 cases passed, score 0.50 (threshold 0.75)"));
+    }
+}
@@ -0,0 +1,214 @@
+//! Fallback chains
+//!
+//! A provider's `fallbacks` name the providers to try, in order, when it
+//! fails with an error another provider might not have:
+//!
+//! ```toml
+//! [[providers]]
+//! name = "openai"
+//! model = "gpt-4o"
+//! context_window = 128000
+//! fallbacks = ["anthropic", "ollama/llama3.1"]
+//! ```
+//!
+//! [`crate::RigMcpClient::complete_with_fallback`] walks the chain. Rate
+//! limits, timeouts, server errors and unreachable providers move on to the
+//! next provider; other errors, such as invalid requests or bad credentials,
+//! are returned as they are. A request refused for exceeding the model's
+//! context only moves on to providers whose `context_window` is known to be
+//! larger than that of the provider that refused it.
+//!
+//! The response names the provider that answered in
+//! [`NormalizedResponse::answered_by`] and lists every provider it was sent
+//! to in [`NormalizedResponse::attempts`].
+
+use anyhow::Result;
+use std::sync::Arc;
+use tokio::time::Instant;
+
+use crate::provider::{ChatRequest, NormalizedResponse, Provider};
+use crate::race::{Attempt, Outcome};
+use crate::transport;
+
+/// One provider of a [`FallbackChain`]
+pub struct Link {
+    pub provider: Arc<dyn Provider>,
+    /// Tokens the provider's model accepts, if known
+    pub context_window: Option<u64>,
+}
+
+/// A provider and its fallbacks, tried in order; see the module docs
+pub struct FallbackChain {
+    name: String,
+    links: Vec<Link>,
+}
+
+impl FallbackChain {
+    /// `links` starts with the provider requested as `name`
+    pub fn new(name: impl Into<String>, links: Vec<Link>) -> Self {
+        Self {
+            name: name.into(),
+            links,
+        }
+    }
+
+    pub async fn complete(&self, request: ChatRequest) -> Result<NormalizedResponse> {
+        let start = Instant::now();
+        let mut attempts = Vec::new();
+        let mut last_error = None;
+        // Context a provider needs for the request, once one refused it as
+        // too long
+        let mut exceeded: Option<u64> = None;
+        for link in &self.links {
+            let provider = &link.provider;
+            if exceeded.is_some_and(|exceeded| link.context_window.is_none_or(|w| w <= exceeded)) {
+                tracing::debug!(
+                    chain = %self.name,
+                    provider = %provider.name(),
+                    "Skipping fallback without a larger context window"
+                );
+                continue;
+            }
+            let attempt = |outcome, usage| Attempt {
+                provider: provider.name().to_string(),
+                model: provider.model().to_string(),
+                outcome,
+                elapsed_ms: start.elapsed().as_millis() as u64,
+                usage,
+            };
+            let err = match provider.complete(request.clone()).await {
+                Ok(mut response) => {
+                    attempts.push(attempt(Outcome::Won, Some(response.usage)));
+                    response.answered_by = Some(provider.name().to_string());
+                    response.attempts = attempts;
+                    return Ok(response);
+                }
+                Err(err) => err,
+            };
+            if transport::is_context_length_exceeded(&err) {
+                // Without its window there's no telling which fallback is
+                // larger
+                let Some(window) = link.context_window else {
+                    return Err(err);
+                };
+                exceeded = Some(exceeded.map_or(window, |exceeded| exceeded.max(window)));
+            } else if !transport::is_retriable(&err) {
+                return Err(err);
+            }
+            tracing::debug!(
+                chain = %self.name,
+                provider = %provider.name(),
+                error = %err,
+                "Provider failed, trying its fallback"
+            );
+            attempts.push(attempt(Outcome::Failed(format!("{:#}", err)), None));
+            last_error = Some(err);
+        }
+        Err(match last_error {
+            Some(err) => err.context(format!("No fallback of '{}' answered", self.name)),
+            None => anyhow::anyhow!("'{}' has no providers to try", self.name),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::ChatMessage;
+    use crate::testing::{Fault, FaultPlan, MockProvider};
+
+    fn link(
+        name: &str, faults: &[Fault], context_window: Option<u64>,
+    ) -> (Arc<MockProvider>, Link) {
+        let provider =
+            Arc::new(MockProvider::new(name).with_faults(FaultPlan::script(faults.to_vec())));
+        let link = Link {
+            provider: provider.clone(),
+            context_window,
+        };
+        (provider, link)
+    }
+
+    fn request() -> ChatRequest {
+        ChatRequest {
+            messages: vec![ChatMessage::user("Hello")],
+            ..ChatRequest::default()
+        }
+    }
+
+    fn outcomes(response: &NormalizedResponse) -> Vec<(&str, bool)> {
+        response
+            .attempts
+            .iter()
+            .map(|attempt| (attempt.provider.as_str(), attempt.outcome == Outcome::Won))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_retriable_errors_walk_the_chain() {
+        let (openai, first) = link("openai", &[Fault::FailWithStatus(429)], None);
+        let (anthropic, second) = link("anthropic", &[Fault::FailWithStatus(503)], None);
+        let (ollama, third) = link("ollama", &[], None);
+        let chain = FallbackChain::new("openai", vec![first, second, third]);
+
+        let response = chain.complete(request()).await.unwrap();
+        assert_eq!(response.answered_by.as_deref(), Some("ollama"));
+        assert_eq!(
+            outcomes(&response),
+            [("openai", false), ("anthropic", false), ("ollama", true)]
+        );
+        let Outcome::Failed(error) = &response.attempts[0].outcome else {
+            panic!("expected openai to fail");
+        };
+        assert!(error.contains("HTTP 429"), "{}", error);
+        assert_eq!(ollama.requests().len(), 1);
+
+        // Once the primary recovers it answers again
+        let response = chain.complete(request()).await.unwrap();
+        assert_eq!(response.answered_by.as_deref(), Some("openai"));
+        assert_eq!(outcomes(&response), [("openai", true)]);
+        assert_eq!(
+            (openai.requests().len(), anthropic.requests().len()),
+            (2, 1)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_invalid_requests_are_not_retried_elsewhere() {
+        let (_, first) = link("openai", &[Fault::FailWithStatus(400)], None);
+        let (anthropic, second) = link("anthropic", &[], None);
+        let chain = FallbackChain::new("openai", vec![first, second]);
+
+        let err = chain.complete(request()).await.unwrap_err();
+        assert!(err.to_string().contains("HTTP 400"), "{:#}", err);
+        assert!(anthropic.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_context_length_errors_only_go_to_larger_contexts() {
+        let too_long = [Fault::ContextLengthExceeded];
+        let (_, first) = link("openai", &too_long, Some(128_000));
+        let (small, second) = link("ollama", &[], Some(8_192));
+        let (unknown, third) = link("groq", &[], None);
+        let (large, fourth) = link("gemini", &[], Some(1_000_000));
+        let chain = FallbackChain::new("openai", vec![first, second, third, fourth]);
+
+        let response = chain.complete(request()).await.unwrap();
+        assert_eq!(response.answered_by.as_deref(), Some("gemini"));
+        assert_eq!(outcomes(&response), [("openai", false), ("gemini", true)]);
+        assert!(small.requests().is_empty() && unknown.requests().is_empty());
+        assert_eq!(large.requests().len(), 1);
+
+        // Nothing larger to go to
+        let (_, first) = link("openai", &too_long, Some(128_000));
+        let (_, second) = link("ollama", &[], Some(8_192));
+        let chain = FallbackChain::new("openai", vec![first, second]);
+        let err = chain.complete(request()).await.unwrap_err();
+        assert!(transport::is_context_length_exceeded(&err), "{:#}", err);
+        assert!(
+            err.to_string().contains("No fallback of 'openai' answered"),
+            "{:#}",
+            err
+        );
+    }
+}
@@ -0,0 +1,499 @@
+//! Fine-tuning datasets from the audit log
+//!
+//! [`export`] selects recorded interactions, by tag, time, provider and
+//! rating, and writes each as an example in OpenAI's chat fine-tuning
+//! format: `{"messages": [...]}`, one per line, in shards of
+//! `shard_size` examples. Interactions that produce the same example are
+//! exported once. The `moderation` rules, and the `redaction` rules unless
+//! their `finetune` flag is off, are applied to every example before it is
+//! hashed, so nothing they hide reaches the dataset.
+//!
+//! Next to the shards, `manifest.json` counts what was read, left out and
+//! written, with the SHA-256 of every shard.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::path::Path;
+
+use crate::audit::{AuditLog, Interaction};
+use crate::provider::{ChatMessage, Role, ToolCall};
+use crate::redaction::RedactionRuleSet;
+use crate::Config;
+
+/// Examples per shard unless configured otherwise
+pub const DEFAULT_SHARD_SIZE: usize = 1000;
+
+/// File the manifest is written to
+pub const MANIFEST: &str = "manifest.json";
+
+/// What becomes of tool calls and tool results
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ToolCallHandling {
+    /// Keep only the text of the conversation; interactions whose answer is
+    /// nothing but tool calls are left out
+    #[default]
+    Strip,
+    /// Keep them as OpenAI `tool_calls` and `tool` messages, with the tools
+    /// that were offered
+    Convert,
+}
+
+/// Which interactions to export, and how
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportOptions {
+    /// Tags an interaction must all carry
+    pub tags: Vec<String>,
+    /// Recorded at or after
+    pub since: Option<DateTime<Utc>>,
+    /// Recorded before
+    pub until: Option<DateTime<Utc>>,
+    /// Providers to export from; empty for all
+    pub providers: Vec<String>,
+    /// Lowest rating exported; unrated interactions are left out when set
+    pub min_rating: Option<f32>,
+    pub tool_calls: ToolCallHandling,
+    /// Examples per shard
+    pub shard_size: usize,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            tags: Vec::new(),
+            since: None,
+            until: None,
+            providers: Vec::new(),
+            min_rating: None,
+            tool_calls: ToolCallHandling::default(),
+            shard_size: DEFAULT_SHARD_SIZE,
+        }
+    }
+}
+
+impl ExportOptions {
+    fn accepts(&self, interaction: &Interaction) -> bool {
+        self.tags.iter().all(|tag| interaction.tags.contains(tag))
+            && self.since.is_none_or(|since| interaction.at >= since)
+            && self.until.is_none_or(|until| interaction.at < until)
+            && (self.providers.is_empty() || self.providers.contains(&interaction.provider))
+            && self
+                .min_rating
+                .is_none_or(|min| interaction.rating.is_some_and(|rating| rating >= min))
+    }
+}
+
+/// What an export wrote
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Interactions in the audit log
+    pub interactions: usize,
+    /// Left out by the filters, or with nothing to learn once tool calls
+    /// were stripped
+    pub skipped: usize,
+    /// Left out because an earlier interaction made the same example
+    pub duplicates: usize,
+    pub examples: usize,
+    pub shards: Vec<Shard>,
+}
+
+/// One JSON Lines file of examples
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Shard {
+    /// Relative to the output directory
+    pub file: String,
+    pub examples: usize,
+    pub sha256: String,
+}
+
+/// What [`export`] redacts under `config`
+pub fn redaction_rules(config: &Config) -> Result<RedactionRuleSet> {
+    let mut rules = config.moderation.rules();
+    if config.redaction.finetune {
+        rules.extend(config.redaction.rules.iter().cloned());
+    }
+    Ok(RedactionRuleSet::compile(&rules)?)
+}
+
+/// Export the interactions of `log` that `options` select to `out_dir`,
+/// redacting what `redaction` matches
+pub fn export(
+    log: &AuditLog, options: &ExportOptions, redaction: &RedactionRuleSet, out_dir: &Path,
+) -> Result<Manifest> {
+    anyhow::ensure!(options.shard_size > 0, "shard_size must be at least 1");
+    let interactions = log.read()?;
+    let mut manifest = Manifest {
+        interactions: interactions.len(),
+        ..Manifest::default()
+    };
+    let mut seen = HashSet::new();
+    let mut lines = Vec::new();
+    for interaction in &interactions {
+        let example = options
+            .accepts(interaction)
+            .then(|| example(interaction, options.tool_calls, redaction))
+            .flatten();
+        let Some(example) = example else {
+            manifest.skipped += 1;
+            continue;
+        };
+        let line = serde_json::to_string(&example)?;
+        if seen.insert(sha256(line.as_bytes())) {
+            lines.push(line);
+        } else {
+            manifest.duplicates += 1;
+        }
+    }
+    manifest.examples = lines.len();
+
+    std::fs::create_dir_all(out_dir)
+        .with_context(|| format!("Failed to create {}", out_dir.display()))?;
+    for (index, chunk) in lines.chunks(options.shard_size).enumerate() {
+        let file = shard_file_name(index);
+        let mut contents = chunk.join("\n");
+        contents.push('\n');
+        let path = out_dir.join(&file);
+        std::fs::write(&path, &contents)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        manifest.shards.push(Shard {
+            file,
+            examples: chunk.len(),
+            sha256: sha256(contents.as_bytes()),
+        });
+    }
+    let path = out_dir.join(MANIFEST);
+    std::fs::write(&path, serde_json::to_vec_pretty(&manifest)?)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(manifest)
+}
+
+fn shard_file_name(index: usize) -> String {
+    format!("shard-{:04}.jsonl", index)
+}
+
+fn sha256(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// The training example for `interaction`, if it ends in an assistant
+/// answer once tool calls are handled
+fn example(
+    interaction: &Interaction, tool_calls: ToolCallHandling, redaction: &RedactionRuleSet,
+) -> Option<Value> {
+    let request = &interaction.request;
+    let answer = ChatMessage {
+        tool_calls: interaction.response.tool_calls.clone(),
+        ..ChatMessage::assistant(interaction.response.content.clone())
+    };
+    let mut messages: Vec<Value> = request
+        .system
+        .iter()
+        .map(|system| json!({"role": "system", "content": system}))
+        .collect();
+    for message in request.messages.iter().chain(std::iter::once(&answer)) {
+        match tool_calls {
+            ToolCallHandling::Strip => {
+                if message.role != Role::Tool && !message.content.is_empty() {
+                    messages.push(json!({"role": message.role, "content": message.content}));
+                }
+            }
+            ToolCallHandling::Convert => messages.push(openai_message(message)),
+        }
+    }
+    if messages.last()?["role"] != "assistant" {
+        return None;
+    }
+
+    let mut example = json!({ "messages": messages });
+    if tool_calls == ToolCallHandling::Convert && !request.tools.is_empty() {
+        example["tools"] = request
+            .tools
+            .iter()
+            .map(|tool| {
+                json!({
+                    "type": "function",
+                    "function": {
+                        "name": tool.name,
+                        "description": tool.description,
+                        "parameters": tool.parameters,
+                    },
+                })
+            })
+            .collect();
+    }
+    redaction.apply_value(&mut example);
+    Some(example)
+}
+
+/// `message` as OpenAI chat messages carry it
+fn openai_message(message: &ChatMessage) -> Value {
+    let mut out = json!({"role": message.role, "content": message.content});
+    if !message.tool_calls.is_empty() {
+        out["tool_calls"] = message.tool_calls.iter().map(openai_tool_call).collect();
+    }
+    if let Some(id) = &message.tool_call_id {
+        out["tool_call_id"] = Value::from(id.as_str());
+    }
+    out
+}
+
+fn openai_tool_call(call: &ToolCall) -> Value {
+    json!({
+        "id": call.id,
+        "type": "function",
+        // OpenAI passes arguments as a JSON string
+        "function": {"name": call.name, "arguments": call.arguments.to_string()},
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::moderation::{ModerationConfig, PiiKind};
+    use crate::provider::{ChatRequest, NormalizedResponse, ToolDefinition};
+    use crate::redaction::{RedactionConfig, RedactionRule};
+
+    fn at(time: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(time).unwrap().into()
+    }
+
+    fn interaction(request_id: &str, question: &str, answer: &str) -> Interaction {
+        Interaction {
+            request_id: request_id.to_string(),
+            at: at("2025-03-04T12:00:00Z"),
+            provider: "openai".to_string(),
+            model: "gpt-4o".to_string(),
+            tags: vec!["support".to_string()],
+            request: ChatRequest {
+                system: Some("You are a support agent.".to_string()),
+                messages: vec![ChatMessage::user(question)],
+                ..ChatRequest::default()
+            },
+            response: NormalizedResponse::text(answer),
+            rating: None,
+        }
+    }
+
+    /// A conversation that called the `lookup` tool before answering
+    fn with_tool_call(request_id: &str) -> Interaction {
+        let call = ToolCall {
+            id: "call_1".to_string(),
+            name: "lookup".to_string(),
+            arguments: json!({"email": "jane@example.com"}),
+        };
+        let mut interaction = interaction(request_id, "Where is my order?", "It ships today.");
+        interaction.request.messages.extend([
+            ChatMessage {
+                tool_calls: vec![call],
+                ..ChatMessage::assistant("")
+            },
+            ChatMessage::tool("call_1", "Order 17 ships today"),
+        ]);
+        interaction.request.tools = vec![ToolDefinition {
+            name: "lookup".to_string(),
+            description: "Find a customer's order".to_string(),
+            parameters: json!({"type": "object"}),
+        }];
+        interaction
+    }
+
+    async fn seeded_log(dir: &Path) -> AuditLog {
+        let log = AuditLog::new(dir.join("audit.jsonl"));
+        let records = [
+            interaction("req-1", "Reset my password", "Use the link we sent."),
+            // Same conversation asked again
+            interaction("req-2", "Reset my password", "Use the link we sent."),
+            interaction(
+                "req-3",
+                "I'm jane@example.com, call me on 555-123-4567",
+                "We will call you, Jane Roe.",
+            ),
+            with_tool_call("req-4"),
+            Interaction {
+                provider: "anthropic".to_string(),
+                ..interaction("req-5", "Hello", "Hi!")
+            },
+            Interaction {
+                at: at("2025-01-01T00:00:00Z"),
+                ..interaction("req-6", "Old question", "Old answer")
+            },
+            Interaction {
+                tags: vec!["internal".to_string()],
+                ..interaction("req-7", "Tag me", "Tagged")
+            },
+            Interaction {
+                response: NormalizedResponse::tool_calls(vec![ToolCall {
+                    id: "call_2".to_string(),
+                    name: "lookup".to_string(),
+                    arguments: json!({}),
+                }]),
+                ..interaction("req-8", "Only calls a tool", "")
+            },
+        ];
+        for record in &records {
+            log.record(record).await.unwrap();
+        }
+        let ratings = [
+            ("req-1", 5.0),
+            ("req-2", 5.0),
+            ("req-3", 4.0),
+            ("req-4", 4.5),
+            ("req-5", 2.0),
+        ];
+        for (request_id, score) in ratings {
+            log.rate(request_id, score, Utc::now()).await.unwrap();
+        }
+        log
+    }
+
+    fn moderation() -> RedactionRuleSet {
+        ModerationConfig {
+            redact: vec![PiiKind::Email, PiiKind::Phone],
+            redact_terms: vec!["Jane Roe".to_string()],
+        }
+        .rule_set()
+    }
+
+    fn read_examples(dir: &Path, manifest: &Manifest) -> Vec<Value> {
+        manifest
+            .shards
+            .iter()
+            .flat_map(|shard| {
+                let contents = std::fs::read_to_string(dir.join(&shard.file)).unwrap();
+                assert_eq!(sha256(contents.as_bytes()), shard.sha256);
+                let lines: Vec<Value> = contents
+                    .lines()
+                    .map(|line| serde_json::from_str(line).unwrap())
+                    .collect();
+                assert_eq!(lines.len(), shard.examples);
+                lines
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_export_dedupes_filters_and_redacts() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = seeded_log(dir.path()).await;
+        let out = dir.path().join("dataset");
+        let options = ExportOptions {
+            tags: vec!["support".to_string()],
+            since: Some(at("2025-02-01T00:00:00Z")),
+            providers: vec!["openai".to_string()],
+            min_rating: Some(4.0),
+            shard_size: 2,
+            ..ExportOptions::default()
+        };
+        let manifest = export(&log, &options, &moderation(), &out).unwrap();
+
+        assert_eq!(manifest.interactions, 8);
+        assert_eq!(manifest.duplicates, 1);
+        assert_eq!(manifest.examples, 3);
+        assert_eq!(manifest.skipped, 4);
+        let files: Vec<&str> = manifest.shards.iter().map(|s| s.file.as_str()).collect();
+        assert_eq!(files, ["shard-0000.jsonl", "shard-0001.jsonl"]);
+        let written: Manifest =
+            serde_json::from_slice(&std::fs::read(out.join(MANIFEST)).unwrap()).unwrap();
+        assert_eq!(written, manifest);
+
+        let examples = read_examples(&out, &manifest);
+        assert_eq!(
+            examples[1]["messages"],
+            json!([
+                {"role": "system", "content": "You are a support agent."},
+                {"role": "user", "content": "I'm [REDACTED], call me on [REDACTED]"},
+                {"role": "assistant", "content": "We will call you, [REDACTED]."},
+            ])
+        );
+        // Stripped: the tool result and the empty assistant turn are gone
+        assert_eq!(
+            examples[2],
+            json!({"messages": [
+                {"role": "system", "content": "You are a support agent."},
+                {"role": "user", "content": "Where is my order?"},
+                {"role": "assistant", "content": "It ships today."},
+            ]})
+        );
+    }
+
+    #[tokio::test]
+    async fn test_export_converts_tool_calls() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = seeded_log(dir.path()).await;
+        let out = dir.path().join("dataset");
+        let options = ExportOptions {
+            min_rating: Some(4.5),
+            tool_calls: ToolCallHandling::Convert,
+            ..ExportOptions::default()
+        };
+        let manifest = export(&log, &options, &moderation(), &out).unwrap();
+        assert_eq!(manifest.examples, 2);
+        assert_eq!(manifest.shards.len(), 1);
+
+        let examples = read_examples(&out, &manifest);
+        let example = &examples[1];
+        assert_eq!(
+            example["messages"][2],
+            json!({
+                "role": "assistant",
+                "content": "",
+                "tool_calls": [{
+                    "id": "call_1",
+                    "type": "function",
+                    "function": {"name": "lookup", "arguments": "{\"email\":\"[REDACTED]\"}"},
+                }],
+            })
+        );
+        assert_eq!(
+            example["messages"][3],
+            json!({"role": "tool", "content": "Order 17 ships today", "tool_call_id": "call_1"})
+        );
+        assert_eq!(example["tools"][0]["function"]["name"], "lookup");
+        // Every example ends in the answer being trained
+        for example in &examples {
+            let messages = example["messages"].as_array().unwrap();
+            assert_eq!(messages.last().unwrap()["role"], "assistant");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_redaction_rules_apply_unless_flagged_off() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = AuditLog::new(dir.path().join("audit.jsonl"));
+        let seeded = interaction("req-1", "My card is 4111-1111-1111-1111", "Noted.");
+        log.record(&seeded).await.unwrap();
+        let mut config = Config {
+            redaction: RedactionConfig {
+                rules: vec![RedactionRule::pattern("card", r"\d{4}(-\d{4}){3}")],
+                ..RedactionConfig::default()
+            },
+            ..Config::default()
+        };
+
+        let out = dir.path().join("redacted");
+        let rules = redaction_rules(&config).unwrap();
+        let manifest = export(&log, &ExportOptions::default(), &rules, &out).unwrap();
+        let examples = read_examples(&out, &manifest);
+        assert_eq!(
+            examples[0]["messages"][1]["content"],
+            "My card is [CARD:medium]"
+        );
+
+        config.redaction.finetune = false;
+        let out = dir.path().join("plain");
+        let rules = redaction_rules(&config).unwrap();
+        let manifest = export(&log, &ExportOptions::default(), &rules, &out).unwrap();
+        let examples = read_examples(&out, &manifest);
+        assert_eq!(
+            examples[0]["messages"][1]["content"],
+            "My card is 4111-1111-1111-1111"
+        );
+    }
+}
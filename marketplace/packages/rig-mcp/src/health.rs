@@ -0,0 +1,324 @@
+//! Health and readiness checks
+//!
+//! A [`HealthRegistry`] runs its registered checks concurrently, each under a
+//! timeout, and reports one [`ComponentHealth`] per check plus an aggregate
+//! [`HealthStatus`]. The shapes are shared with services embedding rig-mcp so
+//! their readiness endpoints report LLM dependencies the same way.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures::future::{join_all, BoxFuture};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::clock::{self, Clock};
+
+/// State of a component, ordered from best to worst
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    #[default]
+    Up,
+    /// Working, but slow or partially impaired
+    Degraded,
+    Down,
+}
+
+impl HealthStatus {
+    /// Overall status of several components
+    ///
+    /// Any `Down` component makes the whole `Down`, otherwise any `Degraded`
+    /// one makes it `Degraded`. No components at all is `Up`.
+    pub fn aggregate(statuses: impl IntoIterator<Item = HealthStatus>) -> HealthStatus {
+        statuses.into_iter().max().unwrap_or_default()
+    }
+}
+
+/// Result of checking one component
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ComponentHealth {
+    pub name: String,
+    pub status: HealthStatus,
+    /// Time the check took, or the timeout if it didn't finish
+    pub latency_ms: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    pub checked_at: DateTime<Utc>,
+}
+
+/// What a check found out, short of failing
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Probe {
+    pub status: HealthStatus,
+    pub detail: Option<String>,
+}
+
+impl Probe {
+    pub fn up() -> Self {
+        Self::default()
+    }
+
+    pub fn degraded(detail: impl Into<String>) -> Self {
+        Self {
+            status: HealthStatus::Degraded,
+            detail: Some(detail.into()),
+        }
+    }
+
+    pub fn detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+}
+
+/// A component that can be checked
+///
+/// An error means the component is down; the error becomes the detail.
+#[async_trait]
+pub trait HealthCheck: Send + Sync {
+    fn name(&self) -> &str;
+
+    async fn check(&self) -> Result<Probe>;
+}
+
+/// Aggregate of every registered check
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HealthReport {
+    pub status: HealthStatus,
+    pub components: Vec<ComponentHealth>,
+}
+
+impl HealthReport {
+    pub fn is_up(&self) -> bool {
+        self.status == HealthStatus::Up
+    }
+}
+
+/// Checks run together by [`HealthRegistry::check_all`]
+#[derive(Clone)]
+pub struct HealthRegistry {
+    checks: Vec<Arc<dyn HealthCheck>>,
+    timeout: Duration,
+    degraded_after: Option<Duration>,
+    sequential: bool,
+    clock: Arc<dyn Clock>,
+}
+
+impl Default for HealthRegistry {
+    fn default() -> Self {
+        Self {
+            checks: Vec::new(),
+            timeout: Duration::from_secs(5),
+            degraded_after: None,
+            sequential: false,
+            clock: clock::system(),
+        }
+    }
+}
+
+impl HealthRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Time after which a check counts as `Down`; five seconds by default
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Report checks that succeed but take longer than this as `Degraded`
+    pub fn degraded_after(mut self, threshold: Duration) -> Self {
+        self.degraded_after = Some(threshold);
+        self
+    }
+
+    /// Run checks one after another, in registration order
+    pub fn sequential(mut self) -> Self {
+        self.sequential = true;
+        self
+    }
+
+    /// Take `checked_at` and latencies from `clock`
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn register(&mut self, check: Arc<dyn HealthCheck>) {
+        self.checks.push(check);
+    }
+
+    /// Register a check written as an async closure
+    pub fn register_fn<F, Fut>(&mut self, name: impl Into<String>, check: F)
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Probe>> + Send + 'static,
+    {
+        self.register(Arc::new(FnCheck {
+            name: name.into(),
+            check: Box::new(move || Box::pin(check())),
+        }));
+    }
+
+    /// Names of the registered checks, in registration order
+    pub fn names(&self) -> Vec<&str> {
+        self.checks.iter().map(|check| check.name()).collect()
+    }
+
+    /// Run every check, concurrently unless the registry is sequential
+    ///
+    /// Components are reported in registration order.
+    pub async fn check_all(&self) -> HealthReport {
+        let components = if self.sequential {
+            let mut components = Vec::with_capacity(self.checks.len());
+            for check in &self.checks {
+                components.push(self.run(check.as_ref()).await);
+            }
+            components
+        } else {
+            join_all(self.checks.iter().map(|check| self.run(check.as_ref()))).await
+        };
+        HealthReport {
+            status: HealthStatus::aggregate(components.iter().map(|c| c.status)),
+            components,
+        }
+    }
+
+    async fn run(&self, check: &dyn HealthCheck) -> ComponentHealth {
+        let checked_at = self.clock.now();
+        let outcome = tokio::time::timeout(self.timeout, check.check()).await;
+        let latency = (self.clock.now() - checked_at).to_std().unwrap_or_default();
+
+        let (status, detail) = match outcome {
+            Ok(Ok(probe)) => match self.degraded_after {
+                Some(threshold) if latency > threshold && probe.status == HealthStatus::Up => (
+                    HealthStatus::Degraded,
+                    Some(format!("slow: took {}ms", latency.as_millis())),
+                ),
+                _ => (probe.status, probe.detail),
+            },
+            Ok(Err(err)) => (HealthStatus::Down, Some(format!("{:#}", err))),
+            Err(_) => (
+                HealthStatus::Down,
+                Some(format!("timed out after {}ms", self.timeout.as_millis())),
+            ),
+        };
+        ComponentHealth {
+            name: check.name().to_string(),
+            status,
+            latency_ms: latency.as_millis() as u64,
+            detail,
+            checked_at,
+        }
+    }
+}
+
+type CheckFn = Box<dyn Fn() -> BoxFuture<'static, Result<Probe>> + Send + Sync>;
+
+struct FnCheck {
+    name: String,
+    check: CheckFn,
+}
+
+#[async_trait]
+impl HealthCheck for FnCheck {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn check(&self) -> Result<Probe> {
+        (self.check)().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    #[test]
+    fn test_aggregate_takes_the_worst_status() {
+        use HealthStatus::*;
+        assert_eq!(HealthStatus::aggregate([]), Up);
+        assert_eq!(HealthStatus::aggregate([Up, Up]), Up);
+        assert_eq!(HealthStatus::aggregate([Up, Degraded, Up]), Degraded);
+        assert_eq!(HealthStatus::aggregate([Degraded, Down, Up]), Down);
+    }
+
+    #[tokio::test]
+    async fn test_check_all_with_mixed_outcomes() {
+        let mut registry = HealthRegistry::new().timeout(Duration::from_millis(50));
+        registry.register_fn("openai", || async { Ok(Probe::up().detail("gpt-4o")) });
+        registry.register_fn("filesystem", || async {
+            Ok(Probe::degraded("2 of 3 tools listed"))
+        });
+        registry.register_fn("embeddings", || async {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            Ok(Probe::up())
+        });
+        assert_eq!(registry.names(), ["openai", "filesystem", "embeddings"]);
+
+        let report = registry.check_all().await;
+        assert_eq!(report.status, HealthStatus::Down);
+        assert!(!report.is_up());
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["status"], "down");
+        let components: Vec<(Value, Value, Value)> = json["components"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|c| (c["name"].clone(), c["status"].clone(), c["detail"].clone()))
+            .collect();
+        assert_eq!(
+            components,
+            [
+                (json!("openai"), json!("up"), json!("gpt-4o")),
+                (
+                    json!("filesystem"),
+                    json!("degraded"),
+                    json!("2 of 3 tools listed")
+                ),
+                (
+                    json!("embeddings"),
+                    json!("down"),
+                    json!("timed out after 50ms")
+                ),
+            ]
+        );
+        // Checks run concurrently, so the slow one bounds the whole report
+        assert!(json["components"][2]["latency_ms"].as_u64().unwrap() >= 50);
+        assert!(json["components"][0]["checked_at"].is_string());
+
+        let round_trip: HealthReport = serde_json::from_value(json).unwrap();
+        assert_eq!(round_trip, report);
+    }
+
+    #[tokio::test]
+    async fn test_errors_and_slow_checks() {
+        let mut registry = HealthRegistry::new().degraded_after(Duration::from_millis(10));
+        registry.register_fn("ollama", || async {
+            Err(anyhow::anyhow!("connection refused"))
+        });
+        registry.register_fn("anthropic", || async {
+            tokio::time::sleep(Duration::from_millis(30)).await;
+            Ok(Probe::up())
+        });
+
+        let report = registry.check_all().await;
+        let ollama = &report.components[0];
+        assert_eq!(ollama.status, HealthStatus::Down);
+        assert_eq!(ollama.detail.as_deref(), Some("connection refused"));
+        let anthropic = &report.components[1];
+        assert_eq!(anthropic.status, HealthStatus::Degraded);
+        assert!(anthropic
+            .detail
+            .as_deref()
+            .unwrap()
+            .starts_with("slow: took "));
+    }
+}
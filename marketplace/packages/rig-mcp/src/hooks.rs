@@ -0,0 +1,61 @@
+//! Agent run hooks
+//!
+//! Hooks observe an agent run while it happens: streamed text, tool calls, their
+//! progress and their results. They are notification-only and cannot change the run.
+
+use crate::injection::{Detection, InjectionAction};
+use crate::mcp::{ToolOutput, ToolProgress};
+use crate::policy::{Decision, ToolClass};
+use crate::provider::{NormalizedUsage, ToolCall};
+
+/// Something that happened during an agent run
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RunEvent<'a> {
+    /// A chunk of streamed model output
+    Text(&'a str),
+    /// The model requested a tool call; it is about to be invoked
+    ToolCall(&'a ToolCall),
+    /// The agent's tool policy decided whether the call may run; a denied
+    /// call is followed by its [`RunEvent::ToolResult`] without running
+    ToolDecision {
+        call: &'a ToolCall,
+        class: ToolClass,
+        decision: Decision,
+    },
+    /// A running tool call reported progress
+    ToolProgress {
+        call: &'a ToolCall,
+        progress: &'a ToolProgress,
+    },
+    /// A tool call finished
+    ToolResult {
+        call: &'a ToolCall,
+        output: &'a ToolOutput,
+    },
+    /// A tool result or retrieved chunk looked like a prompt injection and
+    /// was handled as `action`
+    Injection {
+        source: &'a str,
+        action: InjectionAction,
+        detections: &'a [Detection],
+    },
+    /// Tokens of a completion of the run, as reported by the provider or
+    /// estimated if it reported none
+    Usage(&'a NormalizedUsage),
+    /// The model produced its final answer
+    Finished,
+}
+
+/// Observer of agent runs
+pub trait RunHook: Send + Sync {
+    fn on_event(&self, event: &RunEvent<'_>);
+}
+
+impl<F> RunHook for F
+where
+    F: Fn(&RunEvent<'_>) + Send + Sync,
+{
+    fn on_event(&self, event: &RunEvent<'_>) {
+        self(event)
+    }
+}
@@ -0,0 +1,311 @@
+//! Tool calling for models that only pretend to support it
+//!
+//! Ollama and some OpenAI-compatible servers accept tool definitions for
+//! models that then answer with text describing a call instead of a native
+//! tool call. An [`crate::Agent`] probes each provider/model pair on its first
+//! tool-enabled completions:
+//!
+//! - a native tool call settles the pair as [`ToolCalling::Native`];
+//! - a fenced JSON block naming one of the offered tools settles it as
+//!   [`ToolCalling::Prompted`], and the block is taken as the call;
+//! - a plain answer settles nothing, and the next completion probes again.
+//!
+//! Prompted pairs get the tools described in the system prompt instead, and
+//! their calls are parsed from fenced JSON in the text:
+//!
+//! ````text
+//! ```json
+//! {"tool": "search", "arguments": {"query": "rust"}}
+//! ```
+//! ````
+//!
+//! Decisions are kept in a [`ToolSupport`] shared by the agents of a client
+//! and reported by [`crate::RigMcpClient::capabilities`], along with whether
+//! the pair has served part of a prompt from a cache, which decides whether
+//! [`crate::preamble`] compacts its tool descriptions.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+use crate::provider::{
+    ChatMessage, ChatRequest, NormalizedUsage, Provider, Role, ToolCall, ToolDefinition,
+};
+
+/// Sent after a prompted reply whose tool call couldn't be parsed
+pub const RETRY_NUDGE: &str = "Your tool call could not be parsed. Reply with exactly one \
+                               ```json block containing {\"tool\": ..., \"arguments\": {...}}, \
+                               or answer without calling a tool.";
+
+/// How a provider/model pair calls tools
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ToolCalling {
+    /// Not probed yet, or it hasn't called a tool so far
+    #[default]
+    Unknown,
+    /// Native tool-call structures
+    Native,
+    /// Tools described in the system prompt, calls parsed from the text
+    Prompted,
+}
+
+/// What is known about a provider/model pair
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Capabilities {
+    pub provider: String,
+    pub model: String,
+    pub tool_calling: ToolCalling,
+    /// A completion reported prompt tokens served from the provider's cache
+    pub prompt_caching: bool,
+}
+
+/// Tool-calling decisions by `provider/model`
+#[derive(Debug, Default)]
+pub struct ToolSupport {
+    decisions: Mutex<HashMap<String, ToolCalling>>,
+    /// Pairs seen caching prompts
+    caching: Mutex<HashSet<String>>,
+}
+
+impl ToolSupport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, provider: &dyn Provider) -> ToolCalling {
+        let decisions = self.decisions.lock().unwrap();
+        decisions.get(&key(provider)).copied().unwrap_or_default()
+    }
+
+    pub fn set(&self, provider: &dyn Provider, calling: ToolCalling) {
+        let previous = self
+            .decisions
+            .lock()
+            .unwrap()
+            .insert(key(provider), calling);
+        if previous != Some(calling) && calling == ToolCalling::Prompted {
+            tracing::info!(
+                provider = provider.name(),
+                model = provider.model(),
+                "model doesn't call tools natively; describing them in the system prompt"
+            );
+        }
+    }
+
+    /// Whether `provider` has been seen serving prompt tokens from its cache
+    pub fn prompt_caching(&self, provider: &dyn Provider) -> bool {
+        self.caching.lock().unwrap().contains(&key(provider))
+    }
+
+    /// Note the usage of a completion; usage with cached tokens settles the
+    /// pair as caching prompts
+    pub fn observe_usage(&self, provider: &dyn Provider, usage: &NormalizedUsage) {
+        if usage.cached_tokens > 0 && self.caching.lock().unwrap().insert(key(provider)) {
+            tracing::debug!(
+                provider = provider.name(),
+                model = provider.model(),
+                "provider caches prompts; sending full tool descriptions"
+            );
+        }
+    }
+
+    pub fn capabilities(&self, provider: &dyn Provider) -> Capabilities {
+        Capabilities {
+            provider: provider.name().to_string(),
+            model: provider.model().to_string(),
+            tool_calling: self.get(provider),
+            prompt_caching: self.prompt_caching(provider),
+        }
+    }
+}
+
+fn key(provider: &dyn Provider) -> String {
+    format!("{}/{}", provider.name(), provider.model())
+}
+
+/// Rewrite a native tool request for a prompted pair: tools move into the
+/// system prompt, earlier calls into the text and results into user messages
+pub fn prompted_request(mut request: ChatRequest) -> ChatRequest {
+    let tools = std::mem::take(&mut request.tools);
+    let protocol = describe(&tools);
+    request.system = Some(match request.system.take() {
+        Some(system) => format!("{}\n\n{}", system, protocol),
+        None => protocol,
+    });
+    request.messages = request
+        .messages
+        .into_iter()
+        .map(|message| match message.role {
+            Role::Assistant if !message.tool_calls.is_empty() => {
+                let calls = message.tool_calls.iter().map(fenced).collect::<Vec<_>>();
+                // Prompted replies already carry their call in the text
+                let content = if message.content.is_empty() {
+                    calls.join("\n")
+                } else {
+                    message.content
+                };
+                ChatMessage::assistant(content)
+            }
+            Role::Tool => ChatMessage::user(format!(
+                "Result of tool call {}:\n{}",
+                message.tool_call_id.as_deref().unwrap_or("?"),
+                message.content
+            )),
+            _ => message,
+        })
+        .collect();
+    request
+}
+
+fn describe(tools: &[ToolDefinition]) -> String {
+    let mut out = String::from(
+        "You can call these tools. To call one, reply with only a ```json block \
+         containing {\"tool\": <name>, \"arguments\": <object>}; the result is sent \
+         back to you. Answer without a json block once you are done.\n",
+    );
+    for tool in tools {
+        out.push_str(&format!(
+            "\n- {}: {}\n  arguments: {}",
+            tool.name, tool.description, tool.parameters
+        ));
+    }
+    out
+}
+
+fn fenced(call: &ToolCall) -> String {
+    let body = json!({"tool": call.name, "arguments": call.arguments});
+    format!("```json\n{}\n```", body)
+}
+
+/// Tool call blocks in a reply
+#[derive(Debug, PartialEq)]
+pub enum Parsed {
+    Calls(Vec<ToolCall>),
+    /// A block that looks like a call but isn't valid JSON
+    Malformed(String),
+}
+
+/// Parse the fenced calls of `text` to any of `tools`
+///
+/// Blocks that don't name one of the tools are left alone, so an answer may
+/// still contain JSON. Calls are numbered from `first_id`.
+pub fn parse_calls(text: &str, tools: &[ToolDefinition], first_id: usize) -> Parsed {
+    let mut calls = Vec::new();
+    for block in fenced_blocks(text) {
+        let value = match serde_json::from_str::<Value>(block) {
+            Ok(value) => value,
+            Err(err) => {
+                let names_tool = tools
+                    .iter()
+                    .any(|tool| block.contains(&format!("\"{}\"", tool.name)));
+                if names_tool {
+                    return Parsed::Malformed(err.to_string());
+                }
+                continue;
+            }
+        };
+        let name = value
+            .get("tool")
+            .or_else(|| value.get("name"))
+            .and_then(Value::as_str);
+        let Some(name) = name.filter(|name| tools.iter().any(|tool| tool.name == *name)) else {
+            continue;
+        };
+        calls.push(ToolCall {
+            id: format!("call_{}", first_id + calls.len()),
+            name: name.to_string(),
+            arguments: value.get("arguments").cloned().unwrap_or(json!({})),
+        });
+    }
+    Parsed::Calls(calls)
+}
+
+/// Contents of the ``` blocks tagged `json` or untagged
+fn fenced_blocks(text: &str) -> Vec<&str> {
+    let mut blocks = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("```") {
+        let after = &rest[start + 3..];
+        let Some(line_end) = after.find('\n') else {
+            break;
+        };
+        let lang = after[..line_end].trim();
+        let body = &after[line_end + 1..];
+        let Some(end) = body.find("```") else {
+            break;
+        };
+        if lang.is_empty() || lang.eq_ignore_ascii_case("json") {
+            blocks.push(body[..end].trim());
+        }
+        rest = &body[end + 3..];
+    }
+    blocks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tools() -> Vec<ToolDefinition> {
+        vec![ToolDefinition {
+            name: "echo".to_string(),
+            description: "Echo the given text".to_string(),
+            parameters: json!({"type": "object"}),
+        }]
+    }
+
+    #[test]
+    fn test_parse_calls_skips_blocks_naming_no_tool() {
+        let text =
+            "Calling it.\n```json\n{\"tool\": \"echo\", \"arguments\": {\"text\": \"hi\"}}\n```\n\
+                    Example output:\n```json\n{\"name\": \"Alice\"}\n```";
+        let Parsed::Calls(calls) = parse_calls(text, &tools(), 3) else {
+            panic!("expected calls");
+        };
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].id, "call_3");
+        assert_eq!(calls[0].arguments, json!({"text": "hi"}));
+
+        let broken = "```json\n{\"tool\": \"echo\", \"arguments\": {\"text\": }\n```";
+        assert!(matches!(
+            parse_calls(broken, &tools(), 0),
+            Parsed::Malformed(_)
+        ));
+        let prose = "```rust\nfn main() {}\n```";
+        assert_eq!(parse_calls(prose, &tools(), 0), Parsed::Calls(vec![]));
+    }
+
+    #[test]
+    fn test_prompted_request_moves_tools_into_the_text() {
+        let call = ToolCall {
+            id: "call_0".to_string(),
+            name: "echo".to_string(),
+            arguments: json!({"text": "hi"}),
+        };
+        let mut assistant = ChatMessage::assistant("");
+        assistant.tool_calls.push(call);
+        let request = prompted_request(ChatRequest {
+            system: Some("Be brief.".to_string()),
+            messages: vec![
+                ChatMessage::user("echo hi"),
+                assistant,
+                ChatMessage::tool("call_0", "hi"),
+            ],
+            tools: tools(),
+            ..ChatRequest::default()
+        });
+
+        assert!(request.tools.is_empty());
+        let system = request.system.unwrap();
+        assert!(system.starts_with("Be brief.") && system.contains("- echo: Echo the given text"));
+        assert!(request.messages[1].tool_calls.is_empty());
+        assert!(request.messages[1].content.contains("\"tool\":\"echo\""));
+        assert_eq!(request.messages[2].role, Role::User);
+        assert_eq!(
+            request.messages[2].content,
+            "Result of tool call call_0:\nhi"
+        );
+    }
+}
@@ -0,0 +1,777 @@
+//! MCP server connections
+//!
+//! [`ToolServer`] is the interface agents call tools through. [`RmcpServer`]
+//! connects to a configured server with the rmcp client; tests and the
+//! offline demo use [`crate::testing::FakeMcpServer`].
+//!
+//! Long-running tools may report [`ToolProgress`] while they run (MCP
+//! `notifications/progress`); [`ToolServer::call_tool_with_progress`] forwards
+//! it to the caller.
+//!
+//! Servers working on files learn which directories they may touch from the
+//! client's [`Root`]s (MCP `roots/list`). Roots are advertised when
+//! connecting, and [`ToolServer::set_roots`] replaces them, notifying the
+//! server with `notifications/roots/list_changed`.
+//!
+//! Stdio servers are spawned and stopped as described in
+//! [`crate::platform`]; [`ToolServer::shutdown`] stops them.
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use futures::{FutureExt, StreamExt};
+use rmcp::handler::client::progress::ProgressDispatcher;
+use rmcp::model::{
+    self, CallToolRequest, CallToolRequestParam, CallToolResult, ClientCapabilities, ClientInfo,
+    ClientRequest, ListRootsResult, ProgressNotificationParam, ServerResult,
+};
+use rmcp::service::{NotificationContext, PeerRequestOptions, RequestContext, RunningService};
+use rmcp::transport::sse_client::SseClientConfig;
+use rmcp::transport::{IntoTransport, SseClientTransport};
+use rmcp::{ClientHandler, ErrorData, RoleClient, ServiceExt};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use thiserror::Error;
+use tokio::process::Child;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::Mutex;
+
+use crate::platform::{self, file_uri};
+use crate::provider::ToolDefinition;
+use crate::secrets;
+use crate::streamable_http;
+use crate::EmbeddingConfig;
+
+/// An MCP server to connect to
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ServerConfig {
+    pub name: String,
+    pub transport: Transport,
+    /// Directories this server may work in, instead of the client's `roots`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub roots: Option<Vec<RootConfig>>,
+    /// Model embedding this server's tools for selection, instead of the
+    /// client's `embeddings`, e.g. for tools described in another language
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub embeddings: Option<EmbeddingConfig>,
+}
+
+/// A directory offered to MCP servers
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RootConfig {
+    pub path: PathBuf,
+    /// Shown to users by servers that list their roots
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+/// A root as servers receive it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Root {
+    /// `file://` URI of the canonical path
+    pub uri: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+impl Root {
+    /// Canonicalize the path of `config`, which must be an existing
+    /// directory
+    pub fn resolve(config: &RootConfig) -> Result<Self> {
+        let path = std::fs::canonicalize(&config.path)
+            .with_context(|| format!("Root {} does not exist", config.path.display()))?;
+        anyhow::ensure!(path.is_dir(), "Root {} is not a directory", path.display());
+        Ok(Self {
+            uri: file_uri(&path),
+            name: config.name.clone(),
+        })
+    }
+
+    /// [`Root::resolve`] every root, failing on the first invalid one
+    pub fn resolve_all(configs: &[RootConfig]) -> Result<Vec<Self>> {
+        configs.iter().map(Self::resolve).collect()
+    }
+}
+
+/// How to reach an MCP server
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Transport {
+    /// Spawn a child process and talk over its stdin/stdout
+    Stdio {
+        command: String,
+        #[serde(default)]
+        args: Vec<String>,
+        #[serde(default)]
+        env: HashMap<String, String>,
+    },
+    /// Server-sent events endpoint
+    Sse {
+        url: String,
+        /// Sent as `Authorization: Bearer ...`; may be a
+        /// [`crate::SecretSource`] reference
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        bearer_token: Option<String>,
+    },
+    /// Streamable HTTP endpoint; see [`crate::streamable_http`]
+    Http {
+        url: String,
+        /// Sent as `Authorization: Bearer ...`; may be a
+        /// [`crate::SecretSource`] reference
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        bearer_token: Option<String>,
+        /// Start a new session when the server has dropped the current one,
+        /// instead of failing its requests; on by default
+        #[serde(default = "reinitialize_by_default")]
+        reinitialize: bool,
+    },
+}
+
+fn reinitialize_by_default() -> bool {
+    true
+}
+
+impl fmt::Debug for Transport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Stdio { command, args, env } => f
+                .debug_struct("Stdio")
+                .field("command", command)
+                .field("args", args)
+                .field("env", env)
+                .finish(),
+            Self::Sse { url, bearer_token } => f
+                .debug_struct("Sse")
+                .field("url", url)
+                .field("bearer_token", &secrets::debug(bearer_token))
+                .finish(),
+            Self::Http {
+                url,
+                bearer_token,
+                reinitialize,
+            } => f
+                .debug_struct("Http")
+                .field("url", url)
+                .field("bearer_token", &secrets::debug(bearer_token))
+                .field("reinitialize", reinitialize)
+                .finish(),
+        }
+    }
+}
+
+/// A tool exposed by an MCP server
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolInfo {
+    /// Name of the server providing the tool
+    pub server: String,
+    pub name: String,
+    pub description: String,
+    /// JSON Schema of the arguments
+    pub input_schema: Value,
+    /// The server annotates the tool as destructive (`destructiveHint`)
+    #[serde(default)]
+    pub destructive: bool,
+    /// The server annotates the tool as read-only (`readOnlyHint`)
+    #[serde(default)]
+    pub read_only: bool,
+}
+
+impl ToolInfo {
+    /// Definition offered to a model
+    pub fn definition(&self) -> ToolDefinition {
+        ToolDefinition {
+            name: self.name.clone(),
+            description: self.description.clone(),
+            parameters: self.input_schema.clone(),
+        }
+    }
+
+    /// Check `arguments` against the tool's input schema
+    pub fn validate_arguments(&self, arguments: &Value) -> Result<(), InvalidArguments> {
+        let invalid = |violations| InvalidArguments {
+            tool: self.name.clone(),
+            violations,
+        };
+        let validator = jsonschema::validator_for(&self.input_schema).map_err(|err| {
+            invalid(vec![Violation {
+                path: String::new(),
+                message: format!("the tool's input schema is invalid: {}", err),
+            }])
+        })?;
+        let violations: Vec<Violation> = validator
+            .iter_errors(arguments)
+            .map(|err| Violation {
+                path: err.instance_path.to_string(),
+                message: err.to_string(),
+            })
+            .collect();
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(invalid(violations))
+        }
+    }
+}
+
+/// Arguments that don't match a tool's input schema
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("Invalid arguments for tool '{tool}': {}", join_violations(.violations))]
+pub struct InvalidArguments {
+    pub tool: String,
+    pub violations: Vec<Violation>,
+}
+
+/// One way arguments break a schema
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Violation {
+    /// JSON Pointer to the offending value; empty for the arguments as a
+    /// whole
+    pub path: String,
+    pub message: String,
+}
+
+fn join_violations(violations: &[Violation]) -> String {
+    violations
+        .iter()
+        .map(|violation| match violation.path.as_str() {
+            "" => violation.message.clone(),
+            path => format!("{}: {}", path, violation.message),
+        })
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// Why [`crate::RigMcpClient::invoke_tool`] didn't reach the tool, or gave up
+/// waiting for it
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ToolInvocationError {
+    #[error("No MCP server named '{0}'")]
+    UnknownServer(String),
+    #[error("MCP server '{server}' has no tool '{tool}'")]
+    UnknownTool { server: String, tool: String },
+    #[error(transparent)]
+    InvalidArguments(#[from] InvalidArguments),
+    #[error("Tool '{tool}' timed out after {after:?}")]
+    TimedOut { tool: String, after: Duration },
+    /// A destructive tool called without confirming
+    #[error("Tool '{tool}' is destructive and the call wasn't confirmed")]
+    ConfirmationRequired { tool: String },
+}
+
+/// Result of a tool call
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolOutput {
+    pub content: String,
+    /// The tool reported a failure; `content` describes it
+    #[serde(default)]
+    pub is_error: bool,
+}
+
+impl ToolOutput {
+    pub fn text(content: impl Into<String>) -> Self {
+        Self {
+            content: content.into(),
+            is_error: false,
+        }
+    }
+
+    pub fn error(content: impl Into<String>) -> Self {
+        Self {
+            content: content.into(),
+            is_error: true,
+        }
+    }
+}
+
+/// Progress of a running tool call
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolProgress {
+    /// Increases with every notification, even when the total is unknown
+    pub progress: f64,
+    pub total: Option<f64>,
+    pub message: Option<String>,
+}
+
+impl ToolProgress {
+    /// Percentage done, when the total is known
+    pub fn percent(&self) -> Option<f64> {
+        self.total
+            .filter(|total| *total > 0.0)
+            .map(|total| (self.progress / total * 100.0).clamp(0.0, 100.0))
+    }
+}
+
+impl From<ProgressNotificationParam> for ToolProgress {
+    fn from(param: ProgressNotificationParam) -> Self {
+        Self {
+            progress: param.progress,
+            total: param.total,
+            message: param.message,
+        }
+    }
+}
+
+/// A connected MCP server
+#[async_trait]
+pub trait ToolServer: Send + Sync {
+    /// Configured server name
+    fn name(&self) -> &str;
+
+    /// Tools currently offered by the server
+    async fn list_tools(&self) -> Result<Vec<ToolInfo>>;
+
+    /// Invoke a tool
+    async fn call_tool(&self, name: &str, arguments: Value) -> Result<ToolOutput>;
+
+    /// Invoke a tool, sending its progress notifications to `progress` in
+    /// the order they arrive
+    ///
+    /// Servers that can't report progress just call the tool.
+    async fn call_tool_with_progress(
+        &self, name: &str, arguments: Value, progress: UnboundedSender<ToolProgress>,
+    ) -> Result<ToolOutput> {
+        drop(progress);
+        self.call_tool(name, arguments).await
+    }
+
+    /// Replace the roots offered to the server
+    ///
+    /// Servers without a notion of roots ignore them.
+    async fn set_roots(&self, roots: Vec<Root>) -> Result<()> {
+        drop(roots);
+        Ok(())
+    }
+
+    /// End the session, stopping the server if it runs as a child process
+    ///
+    /// Nothing else should be called afterwards.
+    async fn shutdown(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Client side of an rmcp session, routing progress notifications to the
+/// calls that asked for them and answering `roots/list`
+#[derive(Clone, Default)]
+struct SessionClient {
+    dispatcher: ProgressDispatcher,
+    roots: Arc<RwLock<Vec<Root>>>,
+}
+
+impl ClientHandler for SessionClient {
+    fn get_info(&self) -> ClientInfo {
+        ClientInfo {
+            capabilities: ClientCapabilities::builder()
+                .enable_roots()
+                .enable_roots_list_changed()
+                .build(),
+            ..ClientInfo::default()
+        }
+    }
+
+    async fn list_roots(
+        &self, _context: RequestContext<RoleClient>,
+    ) -> Result<ListRootsResult, ErrorData> {
+        let roots = self.roots.read().unwrap_or_else(|err| err.into_inner());
+        Ok(ListRootsResult {
+            roots: roots
+                .iter()
+                .map(|root| model::Root {
+                    uri: root.uri.clone(),
+                    name: root.name.clone(),
+                })
+                .collect(),
+        })
+    }
+
+    async fn on_progress(
+        &self, params: ProgressNotificationParam, _context: NotificationContext<RoleClient>,
+    ) {
+        self.dispatcher.handle_notification(params).await;
+    }
+}
+
+/// [`ToolServer`] backed by an rmcp client session
+pub struct RmcpServer {
+    name: String,
+    service: RunningService<RoleClient, SessionClient>,
+    /// Process of a stdio server
+    process: Option<Mutex<Child>>,
+}
+
+/// How long a stdio server gets to exit at each step of shutting it down
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(2);
+
+impl RmcpServer {
+    /// Connect to a server and complete the MCP handshake, offering the
+    /// server's own roots
+    pub async fn connect(config: &ServerConfig) -> Result<Self> {
+        let roots = Root::resolve_all(config.roots.as_deref().unwrap_or_default())?;
+        Self::connect_with_roots(config, roots).await
+    }
+
+    /// Like [`RmcpServer::connect`], offering `roots`
+    pub async fn connect_with_roots(config: &ServerConfig, roots: Vec<Root>) -> Result<Self> {
+        let context = || format!("Failed to connect to MCP server '{}'", config.name);
+        match &config.transport {
+            Transport::Stdio { command, args, env } => {
+                let mut child = platform::stdio_command(command, args)
+                    .envs(env)
+                    .spawn()
+                    .with_context(|| format!("Failed to start `{}`", command))
+                    .with_context(context)?;
+                let stdout = child.stdout.take().expect("stdout is piped");
+                let stdin = child.stdin.take().expect("stdin is piped");
+                let mut server = Self::serve(&config.name, roots, (stdout, stdin)).await?;
+                server.process = Some(Mutex::new(child));
+                Ok(server)
+            }
+            Transport::Sse { url, bearer_token } => {
+                let mut headers = reqwest::header::HeaderMap::new();
+                if let Some(token) = bearer_token {
+                    let mut value =
+                        reqwest::header::HeaderValue::from_str(&format!("Bearer {}", token))
+                            .map_err(|_| anyhow::anyhow!("Invalid bearer_token"))
+                            .with_context(context)?;
+                    value.set_sensitive(true);
+                    headers.insert(reqwest::header::AUTHORIZATION, value);
+                }
+                let client = reqwest::Client::builder()
+                    .default_headers(headers)
+                    .build()
+                    .with_context(context)?;
+                let sse = SseClientConfig {
+                    sse_endpoint: url.as_str().into(),
+                    ..SseClientConfig::default()
+                };
+                let transport = SseClientTransport::start_with_client(client, sse)
+                    .await
+                    .with_context(context)?;
+                Self::serve(&config.name, roots, transport).await
+            }
+            Transport::Http {
+                url,
+                bearer_token,
+                reinitialize,
+            } => {
+                let transport =
+                    streamable_http::connect(url, bearer_token.as_deref(), *reinitialize)
+                        .with_context(context)?;
+                Self::serve(&config.name, roots, transport).await
+            }
+        }
+    }
+
+    /// Complete the MCP handshake over an established `transport`
+    pub async fn serve<T, E, A>(name: &str, roots: Vec<Root>, transport: T) -> Result<Self>
+    where
+        T: IntoTransport<RoleClient, E, A>,
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        let client = SessionClient {
+            roots: Arc::new(RwLock::new(roots)),
+            ..SessionClient::default()
+        };
+        let service = client
+            .serve(transport)
+            .await
+            .with_context(|| format!("Failed to connect to MCP server '{}'", name))?;
+        Ok(Self {
+            name: name.to_string(),
+            service,
+            process: None,
+        })
+    }
+
+    /// Protocol version the server agreed to during the handshake
+    pub fn protocol_version(&self) -> Option<String> {
+        let info = self.service.peer_info()?;
+        serde_json::to_value(&info.protocol_version)
+            .ok()?
+            .as_str()
+            .map(str::to_string)
+    }
+}
+
+#[async_trait]
+impl ToolServer for RmcpServer {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn list_tools(&self) -> Result<Vec<ToolInfo>> {
+        let tools = self
+            .service
+            .list_all_tools()
+            .await
+            .with_context(|| format!("Failed to list tools of MCP server '{}'", self.name))?;
+        Ok(tools
+            .into_iter()
+            .map(|tool| ToolInfo {
+                server: self.name.clone(),
+                name: tool.name.into_owned(),
+                description: tool.description.map(|d| d.into_owned()).unwrap_or_default(),
+                input_schema: Value::Object((*tool.input_schema).clone()),
+                destructive: tool
+                    .annotations
+                    .as_ref()
+                    .and_then(|annotations| annotations.destructive_hint)
+                    .unwrap_or(false),
+                read_only: tool
+                    .annotations
+                    .and_then(|annotations| annotations.read_only_hint)
+                    .unwrap_or(false),
+            })
+            .collect())
+    }
+
+    async fn call_tool(&self, name: &str, arguments: Value) -> Result<ToolOutput> {
+        let result = self
+            .service
+            .call_tool(CallToolRequestParam {
+                name: name.to_string().into(),
+                arguments: arguments.as_object().cloned(),
+            })
+            .await
+            .with_context(|| format!("Tool '{}' on MCP server '{}' failed", name, self.name))?;
+        Ok(tool_output(result))
+    }
+
+    async fn call_tool_with_progress(
+        &self, name: &str, arguments: Value, progress: UnboundedSender<ToolProgress>,
+    ) -> Result<ToolOutput> {
+        let failed = || format!("Tool '{}' on MCP server '{}' failed", name, self.name);
+        let request = ClientRequest::CallToolRequest(CallToolRequest::new(CallToolRequestParam {
+            name: name.to_string().into(),
+            arguments: arguments.as_object().cloned(),
+        }));
+        let handle = self
+            .service
+            .send_cancellable_request(request, PeerRequestOptions::no_options())
+            .await
+            .with_context(failed)?;
+        // The token is only known once the request is sent; servers report
+        // progress well after a call starts
+        let mut notifications = self
+            .service
+            .service()
+            .dispatcher
+            .subscribe(handle.progress_token.clone())
+            .await;
+
+        let response = handle.await_response();
+        tokio::pin!(response);
+        let result = loop {
+            tokio::select! {
+                biased;
+                Some(notification) = notifications.next() => {
+                    let _ = progress.send(notification.into());
+                }
+                result = &mut response => break result.with_context(failed)?,
+            }
+        };
+        while let Some(Some(notification)) = notifications.next().now_or_never() {
+            let _ = progress.send(notification.into());
+        }
+        match result {
+            ServerResult::CallToolResult(result) => Ok(tool_output(result)),
+            _ => Err(anyhow::anyhow!("Unexpected response")).with_context(failed),
+        }
+    }
+
+    async fn set_roots(&self, roots: Vec<Root>) -> Result<()> {
+        *self
+            .service
+            .service()
+            .roots
+            .write()
+            .unwrap_or_else(|err| err.into_inner()) = roots;
+        self.service
+            .notify_roots_list_changed()
+            .await
+            .with_context(|| format!("Failed to update the roots of MCP server '{}'", self.name))
+    }
+
+    async fn shutdown(&self) -> Result<()> {
+        // Ending the session drops the transport, closing the server's stdin
+        self.service.cancellation_token().cancel();
+        if let Some(process) = &self.process {
+            platform::terminate(&mut *process.lock().await, SHUTDOWN_GRACE)
+                .await
+                .with_context(|| format!("Failed to stop MCP server '{}'", self.name))?;
+        }
+        Ok(())
+    }
+}
+
+fn tool_output(result: CallToolResult) -> ToolOutput {
+    let content = result
+        .content
+        .iter()
+        .filter_map(|content| content.as_text().map(|text| text.text.as_str()))
+        .collect::<Vec<_>>()
+        .join("\n");
+    ToolOutput {
+        content,
+        is_error: result.is_error.unwrap_or(false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rmcp::{RoleServer, ServerHandler};
+
+    #[test]
+    fn test_server_config_from_json() {
+        let config: ServerConfig = serde_json::from_value(serde_json::json!({
+            "name": "filesystem",
+            "transport": {
+                "type": "stdio",
+                "command": "node",
+                "args": ["mcp-server-filesystem.js"]
+            }
+        }))
+        .unwrap();
+        assert_eq!(
+            config.transport,
+            Transport::Stdio {
+                command: "node".to_string(),
+                args: vec!["mcp-server-filesystem.js".to_string()],
+                env: HashMap::new(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_arguments_are_validated_against_the_input_schema() {
+        let tool = ToolInfo {
+            server: "fs".to_string(),
+            name: "read_file".to_string(),
+            description: "Read a file".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {"path": {"type": "string"}, "limit": {"type": "integer"}},
+                "required": ["path"]
+            }),
+            destructive: false,
+            read_only: false,
+        };
+        assert!(tool
+            .validate_arguments(&serde_json::json!({"path": "a.txt"}))
+            .is_ok());
+
+        let err = tool
+            .validate_arguments(&serde_json::json!({"limit": "ten"}))
+            .unwrap_err();
+        let mut paths: Vec<&str> = err.violations.iter().map(|v| v.path.as_str()).collect();
+        paths.sort();
+        assert_eq!(paths, vec!["", "/limit"]);
+        assert!(err
+            .to_string()
+            .starts_with("Invalid arguments for tool 'read_file': "));
+        assert!(
+            err.to_string().contains("\"path\" is a required property"),
+            "{}",
+            err
+        );
+    }
+
+    /// Server that lists the client's roots after the handshake and on every
+    /// change notification
+    #[derive(Clone)]
+    struct RootsServer {
+        listed: UnboundedSender<Vec<Root>>,
+    }
+
+    impl RootsServer {
+        async fn list(&self, context: NotificationContext<RoleServer>) {
+            let listed = context.peer.list_roots().await.unwrap();
+            let roots = listed
+                .roots
+                .into_iter()
+                .map(|root| Root {
+                    uri: root.uri,
+                    name: root.name,
+                })
+                .collect();
+            self.listed.send(roots).unwrap();
+        }
+    }
+
+    impl ServerHandler for RootsServer {
+        async fn on_initialized(&self, context: NotificationContext<RoleServer>) {
+            let capabilities = context.peer.peer_info().unwrap().capabilities.clone();
+            assert_eq!(capabilities.roots.unwrap().list_changed, Some(true));
+            self.list(context).await;
+        }
+
+        async fn on_roots_list_changed(&self, context: NotificationContext<RoleServer>) {
+            self.list(context).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_roots_are_advertised_and_updated() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("my project")).unwrap();
+        std::fs::create_dir(dir.path().join("docs")).unwrap();
+        let base = file_uri(&std::fs::canonicalize(dir.path()).unwrap());
+        let root = |path: &str, name: Option<&str>| {
+            Root::resolve(&RootConfig {
+                // Not canonical yet
+                path: dir.path().join("docs").join("..").join(path),
+                name: name.map(str::to_string),
+            })
+            .unwrap()
+        };
+
+        let (client_io, server_io) = tokio::io::duplex(4096);
+        let (listed, mut lists) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let server = RootsServer { listed }.serve(server_io).await.unwrap();
+            server.waiting().await.unwrap();
+        });
+        let server = RmcpServer::serve("fs", vec![root("my project", Some("Project"))], client_io)
+            .await
+            .unwrap();
+        assert_eq!(
+            lists.recv().await.unwrap(),
+            vec![Root {
+                uri: format!("{}/my%20project", base),
+                name: Some("Project".to_string()),
+            }]
+        );
+
+        server
+            .set_roots(vec![root("docs", None), root("my project", None)])
+            .await
+            .unwrap();
+        let uris: Vec<String> = lists
+            .recv()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|root| root.uri)
+            .collect();
+        assert_eq!(
+            uris,
+            vec![format!("{}/docs", base), format!("{}/my%20project", base)]
+        );
+    }
+
+    #[test]
+    fn test_roots_must_be_existing_directories() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("notes.txt");
+        std::fs::write(&file, "").unwrap();
+        for path in [dir.path().join("missing"), file] {
+            let config = RootConfig { path, name: None };
+            assert!(Root::resolve(&config).is_err(), "{:?}", config);
+        }
+    }
+}
@@ -0,0 +1,186 @@
+//! PII redaction
+//!
+//! The `moderation` section of [`crate::Config`] names what must not leave
+//! the client in readable form: kinds of personal data recognised in free
+//! text, and literal terms such as customer names. Whatever matches is
+//! replaced with [`REDACTED`]. Exports of recorded interactions, such as
+//! [`crate::finetune`], apply these rules to every message, as
+//! [`crate::redaction`] rules of their own.
+//!
+//! Recognition errs on the side of hiding: any run of 7 to 15 digits counts
+//! as a phone number, dates written as `20250304` included.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::ops::Range;
+
+pub use crate::debug_logging::REDACTED;
+use crate::redaction::{RedactionRule, RedactionRuleSet};
+
+/// Personal data recognised in text
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PiiKind {
+    /// `local@domain.tld`
+    Email,
+    /// 7 to 15 digits, possibly separated by spaces, dashes, dots or
+    /// parentheses, with an optional leading `+`
+    Phone,
+}
+
+impl PiiKind {
+    /// Byte ranges of the data of this kind in `text`
+    pub(crate) fn spans(self, text: &str) -> Vec<Range<usize>> {
+        match self {
+            PiiKind::Email => email_spans(text),
+            PiiKind::Phone => phone_spans(text),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            PiiKind::Email => "email",
+            PiiKind::Phone => "phone",
+        }
+    }
+}
+
+/// `moderation` section of [`crate::Config`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModerationConfig {
+    /// Kinds of personal data to redact
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub redact: Vec<PiiKind>,
+    /// Strings redacted wherever they appear, matched case-sensitively
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub redact_terms: Vec<String>,
+}
+
+impl ModerationConfig {
+    /// The rules as [`RedactionRule`]s replacing matches with [`REDACTED`]
+    pub fn rules(&self) -> Vec<RedactionRule> {
+        let terms = self
+            .redact_terms
+            .iter()
+            .filter(|term| !term.is_empty())
+            .map(|term| RedactionRule::pattern("term", regex::escape(term)));
+        let kinds = self
+            .redact
+            .iter()
+            .map(|kind| RedactionRule::shape(kind.name(), *kind));
+        terms
+            .chain(kinds)
+            .map(|rule| rule.with_replacement(REDACTED))
+            .collect()
+    }
+
+    /// The compiled [`ModerationConfig::rules`]
+    pub fn rule_set(&self) -> RedactionRuleSet {
+        RedactionRuleSet::compile(&self.rules()).expect("escaped terms are valid patterns")
+    }
+
+    /// `text` with everything the rules match replaced
+    pub fn redact(&self, text: &str) -> String {
+        self.rule_set().redact(text)
+    }
+
+    /// Redact every string in `value`, e.g. tool call arguments
+    pub fn redact_value(&self, value: &mut Value) {
+        self.rule_set().apply_value(value)
+    }
+}
+
+fn email_spans(text: &str) -> Vec<Range<usize>> {
+    let is_local = |b: &u8| b.is_ascii_alphanumeric() || b"._%+-".contains(b);
+    let is_domain = |b: &u8| b.is_ascii_alphanumeric() || b".-".contains(b);
+    let bytes = text.as_bytes();
+    let mut spans = Vec::new();
+    let mut last_end = 0;
+    for (at, _) in text.match_indices('@') {
+        if at < last_end {
+            continue;
+        }
+        // Non-ASCII bytes end both parts, so the bounds are char boundaries
+        let start = bytes[last_end..at]
+            .iter()
+            .rposition(|b| !is_local(b))
+            .map_or(last_end, |i| last_end + i + 1);
+        let end = bytes[at + 1..]
+            .iter()
+            .position(|b| !is_domain(b))
+            .map_or(bytes.len(), |i| at + 1 + i);
+        // A full stop after the address ends the sentence
+        let domain = text[at + 1..end].trim_end_matches('.');
+        let labels: Vec<&str> = domain.split('.').collect();
+        if start < at && labels.len() > 1 && labels.iter().all(|label| !label.is_empty()) {
+            last_end = at + 1 + domain.len();
+            spans.push(start..last_end);
+        }
+    }
+    spans
+}
+
+fn phone_spans(text: &str) -> Vec<Range<usize>> {
+    let starts = |c: char| c.is_ascii_digit() || c == '+' || c == '(';
+    let continues = |c: char| c.is_ascii_digit() || " +-().".contains(c);
+    let mut spans = Vec::new();
+    let mut at = 0;
+    while let Some(start) = text[at..].find(starts) {
+        let start = at + start;
+        let run = &text[start..];
+        let len = run.find(|c: char| !continues(c)).unwrap_or(run.len());
+        // Separators after the last digit belong to the surrounding text
+        let number = run[..len].trim_end_matches(|c: char| !c.is_ascii_digit());
+        let digits = number.chars().filter(char::is_ascii_digit).count();
+        at = if (7..=15).contains(&digits) {
+            spans.push(start..start + number.len());
+            start + number.len()
+        } else {
+            start + len
+        };
+    }
+    spans
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn moderation() -> ModerationConfig {
+        ModerationConfig {
+            redact: vec![PiiKind::Email, PiiKind::Phone],
+            redact_terms: vec!["Acme Corp".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_redacts_emails_phone_numbers_and_terms() {
+        assert_eq!(
+            moderation().redact(
+                "Mail jane.doe+work@example.co.uk. or call +1 (555) 123-4567 at Acme Corp."
+            ),
+            "Mail [REDACTED]. or call [REDACTED] at [REDACTED]."
+        );
+    }
+
+    #[test]
+    fn test_leaves_other_text_alone() {
+        let text = "Order 1234 of 25 items, @here, user@localhost, né@x";
+        assert_eq!(moderation().redact(text), text);
+        assert_eq!(
+            ModerationConfig::default().redact("jane@example.com"),
+            "jane@example.com"
+        );
+    }
+
+    #[test]
+    fn test_redacts_strings_in_json() {
+        let mut value = json!({"to": ["jane@example.com"], "count": 5551234567_u64});
+        moderation().redact_value(&mut value);
+        assert_eq!(
+            value,
+            json!({"to": ["[REDACTED]"], "count": 5551234567_u64})
+        );
+    }
+}
@@ -0,0 +1,265 @@
+//! Ollama model management
+//!
+//! Ollama only serves models that have been pulled; a completion for any
+//! other model fails with a bare 404. [`crate::RigMcpClient::ollama_models`]
+//! lists the installed models and [`crate::RigMcpClient::ollama_pull`]
+//! downloads one, reporting progress as Ollama streams it. Providers
+//! configured with `ensure_model` pull their model while the client is
+//! created. Other providers have no such API and answer with
+//! [`Unsupported`].
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use thiserror::Error;
+
+use crate::transport::{HttpRequest, HttpStatusError, HttpTransport, Unreachable};
+use crate::wire::{self, Endpoint};
+use crate::ProviderConfig;
+
+/// An operation only Ollama providers offer
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("Provider '{provider}' does not support {operation}; only ollama does")]
+pub struct Unsupported {
+    pub provider: String,
+    pub operation: String,
+}
+
+/// A model installed on an Ollama server
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OllamaModel {
+    /// Name and tag, e.g. `llama3.1:latest`
+    pub name: String,
+    /// Bytes on disk
+    #[serde(default)]
+    pub size: u64,
+    #[serde(default)]
+    pub digest: String,
+    #[serde(default)]
+    pub modified_at: Option<String>,
+}
+
+/// One status update of a pull
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PullProgress {
+    /// e.g. `pulling manifest`, `downloading <digest>` or `success`
+    pub status: String,
+    /// Layer being downloaded
+    #[serde(default)]
+    pub digest: Option<String>,
+    #[serde(default)]
+    pub total: Option<u64>,
+    #[serde(default)]
+    pub completed: Option<u64>,
+}
+
+impl PullProgress {
+    /// Percentage of the current layer downloaded, when its size is known
+    pub fn percent(&self) -> Option<f64> {
+        let total = self.total.filter(|total| *total > 0)?;
+        let completed = self.completed.unwrap_or_default();
+        Some((completed as f64 / total as f64 * 100.0).clamp(0.0, 100.0))
+    }
+}
+
+/// Management API of one configured Ollama provider
+pub(crate) struct Ollama {
+    name: String,
+    endpoint: Endpoint,
+    transport: Arc<dyn HttpTransport>,
+}
+
+impl Ollama {
+    /// Management API of `config`, which must be an Ollama provider;
+    /// `operation` names what was asked of other providers in the error
+    pub fn new(
+        config: &ProviderConfig, transport: Arc<dyn HttpTransport>, operation: &str,
+    ) -> Result<Self> {
+        if config.name != "ollama" {
+            return Err(Unsupported {
+                provider: config.name.clone(),
+                operation: operation.to_string(),
+            }
+            .into());
+        }
+        Ok(Self {
+            name: config.name.clone(),
+            endpoint: wire::ollama_endpoint(config),
+            transport,
+        })
+    }
+
+    fn service(&self) -> String {
+        format!("Provider '{}'", self.name)
+    }
+
+    /// `GET /api/tags`
+    pub async fn models(&self) -> Result<Vec<OllamaModel>> {
+        #[derive(Deserialize)]
+        struct Tags {
+            #[serde(default)]
+            models: Vec<OllamaModel>,
+        }
+
+        let request = HttpRequest::get(self.endpoint.url("api/tags"))
+            .bearer(self.endpoint.api_key.as_deref());
+        let response = self.transport.send(request).await.map_err(|err| {
+            err.context(Unreachable {
+                service: self.service(),
+            })
+        })?;
+        if !response.is_success() {
+            return Err(HttpStatusError::from_response(self.service(), &response).into());
+        }
+        let tags: Tags = serde_json::from_slice(&response.body)
+            .with_context(|| format!("Provider '{}' returned invalid JSON", self.name))?;
+        Ok(tags.models)
+    }
+
+    /// `POST /api/pull`, calling `on_progress` for every status line as it
+    /// arrives
+    pub async fn pull(
+        &self, model: &str, on_progress: &mut (dyn FnMut(&PullProgress) + Send),
+    ) -> Result<()> {
+        let request = HttpRequest::post_json(
+            self.endpoint.url("api/pull"),
+            &json!({ "model": model, "stream": true }),
+        )
+        .bearer(self.endpoint.api_key.as_deref());
+
+        // Lines may be split across chunks
+        let mut pending = Vec::new();
+        let mut failure = None;
+        let mut on_line = |line: &[u8]| {
+            if line.iter().all(u8::is_ascii_whitespace) || failure.is_some() {
+                return;
+            }
+            match serde_json::from_slice::<Value>(line) {
+                Ok(Value::Object(update)) if update.contains_key("error") => {
+                    failure = Some(update["error"].as_str().unwrap_or_default().to_string());
+                }
+                Ok(update) => match PullProgress::deserialize(update) {
+                    Ok(progress) => on_progress(&progress),
+                    Err(err) => failure = Some(format!("unexpected status line: {}", err)),
+                },
+                Err(err) => failure = Some(format!("invalid JSON: {}", err)),
+            }
+        };
+        let response = self
+            .transport
+            .send_streaming(request, &mut |chunk| {
+                pending.extend_from_slice(chunk);
+                while let Some(end) = pending.iter().position(|b| *b == b'\n') {
+                    let line: Vec<u8> = pending.drain(..=end).collect();
+                    on_line(&line);
+                }
+            })
+            .await
+            .map_err(|err| {
+                err.context(Unreachable {
+                    service: self.service(),
+                })
+            })?;
+        if !response.is_success() {
+            return Err(HttpStatusError::from_response(self.service(), &response).into());
+        }
+        on_line(&pending);
+        match failure {
+            Some(failure) => anyhow::bail!(
+                "Provider '{}' could not pull model '{}': {}",
+                self.name,
+                model,
+                failure
+            ),
+            None => Ok(()),
+        }
+    }
+
+    /// Pull `model` unless it is installed; whether it was pulled
+    pub async fn ensure_model(
+        &self, model: &str, on_progress: &mut (dyn FnMut(&PullProgress) + Send),
+    ) -> Result<bool> {
+        if is_installed(&self.models().await?, model) {
+            return Ok(false);
+        }
+        tracing::info!(provider = %self.name, model, "Model is not installed, pulling it");
+        self.pull(model, on_progress).await?;
+        Ok(true)
+    }
+}
+
+/// Whether `model` is among `installed`; an untagged name means `:latest`
+fn is_installed(installed: &[OllamaModel], model: &str) -> bool {
+    let tagged = if model.contains(':') {
+        model.to_string()
+    } else {
+        format!("{}:latest", model)
+    };
+    installed
+        .iter()
+        .any(|candidate| candidate.name == model || candidate.name == tagged)
+}
+
+/// Progress callback logging status changes and every tenth percent of a
+/// layer, rather than each of the thousands of updates a pull sends
+pub(crate) fn log_progress(model: &str) -> impl FnMut(&PullProgress) + Send {
+    let model = model.to_string();
+    let mut last: Option<(String, u64)> = None;
+    move |progress| {
+        let step = progress.percent().map_or(0, |percent| percent as u64 / 10);
+        if last.as_ref() == Some(&(progress.status.clone(), step)) {
+            return;
+        }
+        match progress.percent() {
+            Some(percent) => tracing::info!(
+                model = %model,
+                status = %progress.status,
+                "Pulling: {:.0}%",
+                percent
+            ),
+            None => tracing::info!(model = %model, status = %progress.status, "Pulling"),
+        }
+        last = Some((progress.status.clone(), step));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn model(name: &str) -> OllamaModel {
+        OllamaModel {
+            name: name.to_string(),
+            size: 0,
+            digest: String::new(),
+            modified_at: None,
+        }
+    }
+
+    #[test]
+    fn test_untagged_names_mean_latest() {
+        let installed = [model("llama3.1:latest"), model("qwen2.5:7b")];
+        assert!(is_installed(&installed, "llama3.1"));
+        assert!(is_installed(&installed, "llama3.1:latest"));
+        assert!(is_installed(&installed, "qwen2.5:7b"));
+        assert!(!is_installed(&installed, "qwen2.5"));
+        assert!(!is_installed(&installed, "llama3.1:70b"));
+    }
+
+    #[test]
+    fn test_percent_needs_a_total() {
+        let progress = PullProgress {
+            status: "downloading".to_string(),
+            digest: None,
+            total: Some(200),
+            completed: Some(50),
+        };
+        assert_eq!(progress.percent(), Some(25.0));
+        let progress = PullProgress {
+            total: None,
+            ..progress
+        };
+        assert_eq!(progress.percent(), None);
+    }
+}
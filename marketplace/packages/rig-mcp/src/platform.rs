@@ -0,0 +1,207 @@
+//! Platform differences for stdio servers and paths
+//!
+//! Stdio servers are often scripts: `npx` is `npx.cmd` on Windows, which
+//! the OS only finds when given its extension. [`stdio_command`] looks such
+//! commands up on `PATH` with the extensions of `PATHEXT`, as `cmd.exe`
+//! does; the standard library runs the `.cmd` and `.bat` files it finds
+//! through `cmd.exe` with their arguments escaped.
+//!
+//! [`terminate`] stops a server the way the MCP specification asks: with its
+//! stdin closed the server should exit on its own; if it doesn't, it is asked
+//! to, with SIGTERM on unix and `taskkill /T` on Windows, which has no
+//! signals; if it still runs, it is killed. On Windows the processes it
+//! started are killed with it, since a `cmd.exe` wrapper would otherwise
+//! leave the actual server running.
+//!
+//! [`file_uri`] turns canonical paths into the `file://` URIs roots are
+//! offered as, including the verbatim drive and UNC paths Windows
+//! canonicalizes to.
+
+use std::ffi::{OsStr, OsString};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Stdio};
+use std::time::Duration;
+use tokio::process::{Child, Command};
+
+/// Extensions Windows tries when `PATHEXT` is not set
+const DEFAULT_PATHEXT: &str = ".COM;.EXE;.BAT;.CMD";
+
+/// Conventions a process or path follows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Family {
+    Unix,
+    Windows,
+}
+
+impl Family {
+    /// Family of the running platform
+    pub const fn native() -> Self {
+        if cfg!(windows) {
+            Self::Windows
+        } else {
+            Self::Unix
+        }
+    }
+}
+
+/// Where Windows finds `command`: the first file in a directory of `path`
+/// (`;`-separated) named `command` plus one of the `pathext` extensions
+///
+/// Commands written with one of those extensions are looked for as written,
+/// and commands with a directory only there. `None` when nothing matches.
+pub fn resolve_program(
+    command: &str, path: Option<&OsStr>, pathext: Option<&OsStr>,
+) -> Option<PathBuf> {
+    let pathext = pathext.and_then(OsStr::to_str).unwrap_or(DEFAULT_PATHEXT);
+    let extensions: Vec<String> = pathext
+        .split(';')
+        .filter(|extension| !extension.is_empty())
+        .map(str::to_ascii_lowercase)
+        .collect();
+    let written = Path::new(command)
+        .extension()
+        .and_then(OsStr::to_str)
+        .is_some_and(|extension| {
+            extensions.contains(&format!(".{}", extension.to_ascii_lowercase()))
+        });
+    let names: Vec<String> = if written {
+        vec![command.to_string()]
+    } else {
+        extensions
+            .iter()
+            .map(|extension| format!("{}{}", command, extension))
+            .collect()
+    };
+    let dirs: Vec<PathBuf> = if command.contains(['/', '\\']) {
+        vec![PathBuf::new()]
+    } else {
+        path.map(|path| {
+            path.to_string_lossy()
+                .split(';')
+                .map(|dir| dir.trim_matches('"'))
+                .filter(|dir| !dir.is_empty())
+                .map(PathBuf::from)
+                .collect()
+        })
+        .unwrap_or_default()
+    };
+    dirs.iter()
+        .flat_map(|dir| names.iter().map(move |name| dir.join(name)))
+        .find(|candidate| candidate.is_file())
+}
+
+/// Command starting the stdio server `command` with `args`, with its stdin
+/// and stdout piped; the process is killed if the [`Child`] is dropped
+pub fn stdio_command(command: &str, args: &[String]) -> Command {
+    let program: OsString = match Family::native() {
+        Family::Windows => resolve_program(
+            command,
+            std::env::var_os("PATH").as_deref(),
+            std::env::var_os("PATHEXT").as_deref(),
+        )
+        .map(PathBuf::into_os_string)
+        .unwrap_or_else(|| command.into()),
+        Family::Unix => command.into(),
+    };
+    let mut cmd = Command::new(program);
+    cmd.args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .kill_on_drop(true);
+    cmd
+}
+
+/// Program and arguments asking process `pid` to exit, or with `force`
+/// killing it and the processes it started; `None` where [`Child::start_kill`]
+/// is all there is
+pub fn stop_command(pid: u32, force: bool, family: Family) -> Option<(&'static str, Vec<String>)> {
+    let pid = pid.to_string();
+    match (family, force) {
+        (Family::Unix, false) => Some(("kill", vec!["-TERM".to_string(), pid])),
+        (Family::Unix, true) => None,
+        (Family::Windows, force) => {
+            let mut args = vec!["/PID".to_string(), pid, "/T".to_string()];
+            if force {
+                args.push("/F".to_string());
+            }
+            Some(("taskkill", args))
+        }
+    }
+}
+
+/// Stop a server whose stdin was closed, giving it `grace` after each step;
+/// see the module documentation
+pub async fn terminate(child: &mut Child, grace: Duration) -> io::Result<ExitStatus> {
+    if let Ok(status) = tokio::time::timeout(grace, child.wait()).await {
+        return status;
+    }
+    if let Some(pid) = child.id() {
+        stop(pid, false).await;
+        if let Ok(status) = tokio::time::timeout(grace, child.wait()).await {
+            return status;
+        }
+        stop(pid, true).await;
+    }
+    if let Err(err) = child.start_kill() {
+        // It may have exited since it was last waited for
+        if child.try_wait()?.is_none() {
+            return Err(err);
+        }
+    }
+    child.wait().await
+}
+
+/// Run the [`stop_command`] of `pid`
+async fn stop(pid: u32, force: bool) {
+    if let Some((program, args)) = stop_command(pid, force, Family::native()) {
+        // Fails when the process exited meanwhile; the wait that follows
+        // tells either way
+        let _ = Command::new(program)
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .await;
+    }
+}
+
+/// `file://` URI of a canonical path, percent-encoding everything but
+/// unreserved characters and separators
+///
+/// Verbatim Windows paths lose their `\\?\` prefix, and UNC paths become
+/// URIs with the server as host: `\\?\UNC\fs\share\docs` and
+/// `\\fs\share\docs` are both `file://fs/share/docs`.
+pub fn file_uri(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    let unc = path.strip_prefix("//?/UNC/").or_else(|| {
+        path.strip_prefix("//")
+            .filter(|rest| !rest.starts_with("?/"))
+    });
+    let (host, path) = match unc {
+        Some(unc) => {
+            let (host, rest) = unc.split_once('/').unwrap_or((unc, ""));
+            (host, format!("/{}", rest))
+        }
+        None => ("", path.strip_prefix("//?/").unwrap_or(&path).to_string()),
+    };
+    let mut uri = String::from("file://");
+    percent_encode(host, &mut uri);
+    if !path.starts_with('/') {
+        uri.push('/');
+    }
+    percent_encode(&path, &mut uri);
+    uri
+}
+
+fn percent_encode(text: &str, uri: &mut String) {
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' | b':' => {
+                uri.push(byte as char)
+            }
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+}
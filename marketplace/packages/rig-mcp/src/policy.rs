@@ -0,0 +1,362 @@
+//! Tool safety policy
+//!
+//! MCP servers may annotate tools as read-only (`readOnlyHint`) or
+//! destructive (`destructiveHint`). An agent with a [`ToolPolicy`] runs
+//! read-only tools freely. A destructive call runs only if the policy
+//! auto-approves the tool or the agent's [`ConfirmationHandler`] confirms
+//! it; without a handler it is denied. Tools with neither annotation get
+//! [`ToolPolicy::unannotated`], destructive by default.
+//!
+//! Tools are named by `server/tool` glob patterns, where `*` matches any run
+//! of characters: `fs/*` is every tool of the `fs` server and `*/delete_*`
+//! every tool whose name starts with `delete_`.
+//!
+//! ```toml
+//! [tool_policy]
+//! unannotated = "read_only"
+//! auto_approve = ["fs/write_file"]
+//!
+//! [[tool_policy.overrides]]
+//! tools = "shell/*"
+//! class = "destructive"
+//! ```
+//!
+//! A denied call never reaches the server; the model gets a tool error
+//! saying it was denied by policy. Every decision reaches run hooks as
+//! [`crate::RunEvent::ToolDecision`] and, with `audit` configured, the audit
+//! log.
+
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::mcp::{ToolInfo, ToolOutput};
+
+/// How much harm calling a tool can do
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ToolClass {
+    /// Only reads; runs without confirmation
+    ReadOnly,
+    /// May change or delete things; needs approval
+    Destructive,
+}
+
+/// Reclassifies the tools matching a `server/tool` glob pattern
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClassOverride {
+    pub tools: String,
+    pub class: ToolClass,
+}
+
+/// `tool_policy` section of [`crate::Config`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ToolPolicy {
+    /// Class of tools annotated neither read-only nor destructive
+    #[serde(default = "destructive")]
+    pub unannotated: ToolClass,
+    /// Checked in order before the annotations; the first match wins
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub overrides: Vec<ClassOverride>,
+    /// Destructive tools that run without confirmation, as `server/tool`
+    /// glob patterns
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub auto_approve: Vec<String>,
+}
+
+fn destructive() -> ToolClass {
+    ToolClass::Destructive
+}
+
+impl Default for ToolPolicy {
+    fn default() -> Self {
+        Self {
+            unannotated: ToolClass::Destructive,
+            overrides: Vec::new(),
+            auto_approve: Vec::new(),
+        }
+    }
+}
+
+/// What the policy decided about a tool call
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Decision {
+    /// Read-only, so nobody was asked
+    ReadOnly,
+    /// Destructive, on the auto-approve list
+    AutoApproved,
+    /// Destructive, confirmed by the handler
+    Confirmed,
+    /// Destructive, refused by the handler
+    Refused,
+    /// Destructive, with no auto-approval and no handler to ask
+    Unconfirmed,
+}
+
+impl Decision {
+    /// Whether the call goes ahead
+    pub fn allows(self) -> bool {
+        matches!(self, Self::ReadOnly | Self::AutoApproved | Self::Confirmed)
+    }
+}
+
+/// Asked before every destructive call the policy doesn't auto-approve
+///
+/// The run waits for the answer, so handlers that prompt a person block it
+/// until they reply.
+pub trait ConfirmationHandler: Send + Sync {
+    /// Whether `tool` may run with `arguments`
+    fn confirm(&self, tool: &ToolInfo, arguments: &Value) -> bool;
+}
+
+impl<F> ConfirmationHandler for F
+where
+    F: Fn(&ToolInfo, &Value) -> bool + Send + Sync,
+{
+    fn confirm(&self, tool: &ToolInfo, arguments: &Value) -> bool {
+        self(tool, arguments)
+    }
+}
+
+/// A decision as recorded in the audit log
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolDecision {
+    pub at: DateTime<Utc>,
+    pub server: String,
+    pub tool: String,
+    pub arguments: Value,
+    pub class: ToolClass,
+    pub decision: Decision,
+}
+
+impl ToolPolicy {
+    /// The first matching override, else the tool's annotations, else
+    /// `unannotated`
+    pub fn classify(&self, tool: &ToolInfo) -> ToolClass {
+        let name = qualified_name(tool);
+        if let Some(rule) = self
+            .overrides
+            .iter()
+            .find(|r| glob_matches(&r.tools, &name))
+        {
+            return rule.class;
+        }
+        match (tool.read_only, tool.destructive) {
+            (_, true) => ToolClass::Destructive,
+            (true, false) => ToolClass::ReadOnly,
+            (false, false) => self.unannotated,
+        }
+    }
+
+    pub fn auto_approves(&self, tool: &ToolInfo) -> bool {
+        let name = qualified_name(tool);
+        self.auto_approve
+            .iter()
+            .any(|pattern| glob_matches(pattern, &name))
+    }
+
+    /// Decide on calling `tool` with `arguments`, asking `handler` about
+    /// destructive calls that aren't auto-approved
+    pub fn decide(
+        &self, tool: &ToolInfo, arguments: &Value, handler: Option<&dyn ConfirmationHandler>,
+    ) -> Decision {
+        if self.classify(tool) == ToolClass::ReadOnly {
+            return Decision::ReadOnly;
+        }
+        if self.auto_approves(tool) {
+            return Decision::AutoApproved;
+        }
+        match handler {
+            Some(handler) if handler.confirm(tool, arguments) => Decision::Confirmed,
+            Some(_) => Decision::Refused,
+            None => Decision::Unconfirmed,
+        }
+    }
+}
+
+/// The tool result a denied call gets, as JSON the model can read
+pub fn denied(tool: &ToolInfo, decision: Decision) -> ToolOutput {
+    let reason = match decision {
+        Decision::Refused => "the call was not confirmed",
+        _ => "destructive tools need confirmation and none is configured",
+    };
+    ToolOutput::error(
+        serde_json::json!({
+            "error": "denied by policy",
+            "server": tool.server,
+            "tool": tool.name,
+            "reason": reason,
+        })
+        .to_string(),
+    )
+}
+
+fn qualified_name(tool: &ToolInfo) -> String {
+    format!("{}/{}", tool.server, tool.name)
+}
+
+/// Whether `name` matches `pattern`, where `*` matches any run of characters
+pub(crate) fn glob_matches(pattern: &str, name: &str) -> bool {
+    let pieces: Vec<String> = pattern.split('*').map(regex::escape).collect();
+    Regex::new(&format!("^{}$", pieces.join(".*")))
+        .expect("escaped pattern is a valid regex")
+        .is_match(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::AgentBuilder;
+    use crate::hooks::RunEvent;
+    use crate::provider::NormalizedResponse;
+    use crate::testing::{FakeMcpServer, MockProvider};
+    use serde_json::json;
+    use std::sync::{Arc, Mutex};
+
+    fn tool(server: &str, name: &str) -> ToolInfo {
+        ToolInfo {
+            server: server.to_string(),
+            name: name.to_string(),
+            description: String::new(),
+            input_schema: json!({"type": "object"}),
+            destructive: false,
+            read_only: false,
+        }
+    }
+
+    #[test]
+    fn test_overrides_beat_annotations() {
+        let policy: ToolPolicy = toml::from_str(
+            r#"
+            unannotated = "read_only"
+
+            [[overrides]]
+            tools = "shell/*"
+            class = "destructive"
+
+            [[overrides]]
+            tools = "*/delete_*"
+            class = "destructive"
+            "#,
+        )
+        .unwrap();
+        let stat = ToolInfo {
+            read_only: true,
+            ..tool("shell", "stat")
+        };
+        assert_eq!(policy.classify(&stat), ToolClass::Destructive);
+        assert_eq!(
+            policy.classify(&tool("fs", "delete_file")),
+            ToolClass::Destructive
+        );
+        assert_eq!(
+            policy.classify(&tool("fs", "undelete_file")),
+            ToolClass::ReadOnly
+        );
+        let write = ToolInfo {
+            destructive: true,
+            ..tool("fs", "write_file")
+        };
+        assert_eq!(policy.classify(&write), ToolClass::Destructive);
+    }
+
+    fn files() -> Arc<FakeMcpServer> {
+        let schema = json!({"type": "object", "properties": {"path": {"type": "string"}}});
+        let path = |args: &Value| args["path"].as_str().unwrap_or_default().to_string();
+        Arc::new(
+            FakeMcpServer::new("fs")
+                .with_tool("delete", "Delete a file", schema.clone(), move |args| {
+                    Ok(format!("deleted {}", path(args)))
+                })
+                .destructive("delete")
+                .with_tool("touch", "Create a file", schema, move |args| {
+                    Ok(format!("touched {}", path(args)))
+                }),
+        )
+    }
+
+    /// Runs `call` through an agent with `policy`, returning what the model
+    /// got back and the decisions hooks saw
+    async fn run(
+        policy: ToolPolicy, handler: Option<Arc<dyn ConfirmationHandler>>, call: &str,
+    ) -> (Arc<FakeMcpServer>, String, Vec<Decision>) {
+        let provider = Arc::new(MockProvider::new("mock"));
+        provider.push_tool_call(call, json!({"path": "notes.txt"}));
+        provider.push_response(NormalizedResponse::text("done"));
+        let server = files();
+        let decisions = Arc::new(Mutex::new(Vec::new()));
+        let seen = decisions.clone();
+        let mut builder = AgentBuilder::new(provider)
+            .tool_server(server.clone())
+            .tool_policy(policy)
+            .hook(Arc::new(move |event: &RunEvent<'_>| {
+                if let RunEvent::ToolDecision { decision, .. } = event {
+                    seen.lock().unwrap().push(*decision);
+                }
+            }));
+        if let Some(handler) = handler {
+            builder = builder.confirmation_handler(handler);
+        }
+        let mut history = Vec::new();
+        builder.build().chat(&mut history, "tidy up").await.unwrap();
+        let result = history[2].content.clone();
+        let decisions = decisions.lock().unwrap().clone();
+        (server, result, decisions)
+    }
+
+    #[tokio::test]
+    async fn test_auto_approved_destructive_tool_runs() {
+        let policy = ToolPolicy {
+            auto_approve: vec!["fs/del*".to_string()],
+            ..ToolPolicy::default()
+        };
+        let (server, result, decisions) = run(policy, None, "delete").await;
+        assert_eq!(result, "deleted notes.txt");
+        assert_eq!(server.calls().len(), 1);
+        assert_eq!(decisions, [Decision::AutoApproved]);
+    }
+
+    #[tokio::test]
+    async fn test_refused_call_is_denied_by_policy() {
+        let asked = Arc::new(Mutex::new(Vec::new()));
+        let seen = asked.clone();
+        let handler = move |tool: &ToolInfo, arguments: &Value| {
+            seen.lock()
+                .unwrap()
+                .push((tool.name.clone(), arguments.clone()));
+            false
+        };
+        let (server, result, decisions) =
+            run(ToolPolicy::default(), Some(Arc::new(handler)), "delete").await;
+
+        assert!(server.calls().is_empty());
+        let result: Value = serde_json::from_str(&result).unwrap();
+        assert_eq!(result["error"], "denied by policy");
+        assert_eq!(result["tool"], "delete");
+        assert_eq!(
+            *asked.lock().unwrap(),
+            [("delete".to_string(), json!({"path": "notes.txt"}))]
+        );
+        assert_eq!(decisions, [Decision::Refused]);
+    }
+
+    #[tokio::test]
+    async fn test_unannotated_tools_get_the_default_class() {
+        // Destructive by default, and nobody to confirm
+        let (server, result, decisions) = run(ToolPolicy::default(), None, "touch").await;
+        assert!(server.calls().is_empty());
+        assert!(result.contains("denied by policy"), "{}", result);
+        assert_eq!(decisions, [Decision::Unconfirmed]);
+
+        let policy = ToolPolicy {
+            unannotated: ToolClass::ReadOnly,
+            ..ToolPolicy::default()
+        };
+        let (server, result, decisions) = run(policy, None, "touch").await;
+        assert_eq!(result, "touched notes.txt");
+        assert_eq!(server.calls().len(), 1);
+        assert_eq!(decisions, [Decision::ReadOnly]);
+    }
+}
@@ -0,0 +1,359 @@
+//! Post-processing of agent answers
+//!
+//! A [`PostProcessor`] reads the final answer of a run and records what it
+//! found in the response's [`Annotations`]; the answer text itself is never
+//! changed. Processors run in the order they were added to the agent. Two ship
+//! with the crate: [`CodeBlockExtractor`] collects fenced code blocks and
+//! [`CitationExtractor`] resolves `[n]` markers against the sources of the run.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::sync::Arc;
+
+/// Something an answer may cite: a tool result or a retrieved chunk
+///
+/// Sources are numbered from 1 in the order they were used; `[n]` in an
+/// answer refers to the n-th source.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Source {
+    /// Tool call id or chunk id
+    pub id: String,
+    /// Tool name or document title
+    pub label: String,
+    pub content: String,
+}
+
+/// What post-processors found in an answer
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Annotations {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub code_blocks: Vec<CodeBlock>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub citations: Vec<Citation>,
+    /// Results of custom processors, keyed by a name of their choosing
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub custom: BTreeMap<String, Value>,
+}
+
+impl Annotations {
+    pub fn is_empty(&self) -> bool {
+        self.code_blocks.is_empty() && self.citations.is_empty() && self.custom.is_empty()
+    }
+
+    /// Citations that don't match any source of the run
+    pub fn dangling_citations(&self) -> impl Iterator<Item = &Citation> {
+        self.citations
+            .iter()
+            .filter(|citation| citation.is_dangling())
+    }
+}
+
+/// A step run on the final answer of every agent run
+pub trait PostProcessor: Send + Sync {
+    fn process(&self, text: &str, sources: &[Source], annotations: &mut Annotations);
+}
+
+/// Run `processors` in order over `text`
+pub fn annotate(
+    processors: &[Arc<dyn PostProcessor>], text: &str, sources: &[Source],
+) -> Annotations {
+    let mut annotations = Annotations::default();
+    for processor in processors {
+        processor.process(text, sources, &mut annotations);
+    }
+    annotations
+}
+
+/// A fenced code block of an answer
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CodeBlock {
+    /// First word of the info string, as in ```` ```rust ````
+    pub language: Option<String>,
+    /// The lines between the fences
+    pub content: String,
+    /// Byte range of the whole block, fences included
+    pub start: usize,
+    pub end: usize,
+    /// Line of the opening fence, from 1
+    pub line: usize,
+    /// `false` when the answer ended before the closing fence
+    pub terminated: bool,
+}
+
+/// Collects fenced code blocks
+///
+/// Fences follow CommonMark: three or more backticks or tildes, closed by a
+/// fence of the same character that is at least as long. A block fenced with
+/// four backticks can therefore contain a three-backtick block; it is
+/// reported once, with the inner fences as part of its content.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CodeBlockExtractor;
+
+impl PostProcessor for CodeBlockExtractor {
+    fn process(&self, text: &str, _sources: &[Source], annotations: &mut Annotations) {
+        annotations.code_blocks.extend(code_blocks(text));
+    }
+}
+
+/// A `[n]` marker of an answer
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Citation {
+    pub number: usize,
+    /// Byte range of the marker
+    pub start: usize,
+    pub end: usize,
+    /// The cited source; `None` when there is no source with that number
+    pub source: Option<SourceRef>,
+}
+
+impl Citation {
+    pub fn is_dangling(&self) -> bool {
+        self.source.is_none()
+    }
+}
+
+/// A cited [`Source`], without its content
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceRef {
+    pub id: String,
+    pub label: String,
+}
+
+/// Resolves `[n]` citation markers against the sources of the run
+///
+/// Markers inside code blocks and directly after a word, like `items[0]`,
+/// are not citations.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CitationExtractor;
+
+impl PostProcessor for CitationExtractor {
+    fn process(&self, text: &str, sources: &[Source], annotations: &mut Annotations) {
+        let code: Vec<Range<usize>> = code_blocks(text)
+            .iter()
+            .map(|block| block.start..block.end)
+            .collect();
+        for marker in markers(text) {
+            if code.iter().any(|range| range.contains(&marker.start)) {
+                continue;
+            }
+            let source = marker
+                .number
+                .checked_sub(1)
+                .and_then(|index| sources.get(index))
+                .map(|source| SourceRef {
+                    id: source.id.clone(),
+                    label: source.label.clone(),
+                });
+            annotations.citations.push(Citation { source, ..marker });
+        }
+    }
+}
+
+struct Fence {
+    marker: char,
+    len: usize,
+    info: String,
+}
+
+/// Parse `line` as a code fence: up to three spaces of indentation, then at
+/// least three backticks or tildes
+fn fence(line: &str) -> Option<Fence> {
+    let line = line.trim_end_matches(['\n', '\r']);
+    let indent = line.len() - line.trim_start_matches(' ').len();
+    if indent > 3 {
+        return None;
+    }
+    let line = &line[indent..];
+    let marker = line.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let len = line.len() - line.trim_start_matches(marker).len();
+    if len < 3 {
+        return None;
+    }
+    let info = line[len..].trim();
+    // A backtick in the info string makes it inline code, not a fence
+    if marker == '`' && info.contains('`') {
+        return None;
+    }
+    Some(Fence {
+        marker,
+        len,
+        info: info.to_string(),
+    })
+}
+
+fn code_blocks(text: &str) -> Vec<CodeBlock> {
+    let mut blocks = Vec::new();
+    let mut open: Option<(Fence, CodeBlock)> = None;
+    let mut offset = 0;
+    for (index, line) in text.split_inclusive('\n').enumerate() {
+        let line_start = offset;
+        offset += line.len();
+        match open.take() {
+            None => {
+                if let Some(fence) = fence(line) {
+                    let block = CodeBlock {
+                        language: fence.info.split_whitespace().next().map(str::to_string),
+                        content: String::new(),
+                        start: line_start,
+                        end: offset,
+                        line: index + 1,
+                        terminated: false,
+                    };
+                    open = Some((fence, block));
+                }
+            }
+            Some((opening, mut block)) => {
+                let closes = fence(line).is_some_and(|fence| {
+                    fence.marker == opening.marker
+                        && fence.len >= opening.len
+                        && fence.info.is_empty()
+                });
+                if closes {
+                    block.end = line_start + line.trim_end_matches(['\n', '\r']).len();
+                    block.terminated = true;
+                    blocks.push(block);
+                } else {
+                    block.content.push_str(line);
+                    block.end = offset;
+                    open = Some((opening, block));
+                }
+            }
+        }
+    }
+    blocks.extend(open.map(|(_, block)| block));
+    blocks
+}
+
+/// Every `[n]` not directly preceded by a word character
+fn markers(text: &str) -> impl Iterator<Item = Citation> + '_ {
+    text.match_indices('[').filter_map(move |(start, _)| {
+        let preceded_by_word = text[..start]
+            .chars()
+            .next_back()
+            .is_some_and(|c| c.is_alphanumeric() || c == '_');
+        if preceded_by_word {
+            return None;
+        }
+        let rest = &text[start + 1..];
+        let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        if digits == 0 || !rest[digits..].starts_with(']') {
+            return None;
+        }
+        Some(Citation {
+            number: rest[..digits].parse().ok()?,
+            start,
+            end: start + digits + 2,
+            source: None,
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blocks(text: &str) -> Vec<CodeBlock> {
+        annotate(&[Arc::new(CodeBlockExtractor)], text, &[]).code_blocks
+    }
+
+    #[test]
+    fn test_code_blocks_have_language_content_and_position() {
+        let text = "Run this:\n```rust\nfn main() {}\n```\nand then\n~~~\nls -la\n~~~";
+        let found = blocks(text);
+        assert_eq!(found.len(), 2);
+
+        assert_eq!(found[0].language.as_deref(), Some("rust"));
+        assert_eq!(found[0].content, "fn main() {}\n");
+        assert_eq!(found[0].line, 2);
+        assert_eq!(
+            &text[found[0].start..found[0].end],
+            "```rust\nfn main() {}\n```"
+        );
+        assert!(found[0].terminated);
+
+        assert_eq!(found[1].language, None);
+        assert_eq!(found[1].content, "ls -la\n");
+        assert_eq!(found[1].line, 6);
+        assert_eq!(&text[found[1].start..found[1].end], "~~~\nls -la\n~~~");
+    }
+
+    #[test]
+    fn test_nested_fences_stay_inside_the_outer_block() {
+        let text = "````markdown\nExample:\n```python\nprint(1)\n```\n````\n";
+        let found = blocks(text);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].language.as_deref(), Some("markdown"));
+        assert_eq!(found[0].content, "Example:\n```python\nprint(1)\n```\n");
+        assert!(found[0].terminated);
+
+        // A tilde fence doesn't close a backtick block
+        let found = blocks("```\n~~~\n```\n");
+        assert_eq!(found[0].content, "~~~\n");
+    }
+
+    #[test]
+    fn test_unterminated_fence_runs_to_the_end() {
+        let text = "Partial answer:\n```json\n{\"a\": 1,\n";
+        let found = blocks(text);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].language.as_deref(), Some("json"));
+        assert_eq!(found[0].content, "{\"a\": 1,\n");
+        assert_eq!(found[0].end, text.len());
+        assert!(!found[0].terminated);
+
+        // A shorter fence doesn't close a longer one
+        let found = blocks("````\ncode\n```");
+        assert_eq!(found[0].content, "code\n```");
+        assert!(!found[0].terminated);
+    }
+
+    #[test]
+    fn test_inline_backticks_are_not_fences() {
+        assert!(blocks("Use ```inline``` code").is_empty());
+        assert!(blocks("    ```\n    indented code\n    ```").is_empty());
+    }
+
+    fn source(id: &str, label: &str) -> Source {
+        Source {
+            id: id.to_string(),
+            label: label.to_string(),
+            content: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_citations_resolve_to_sources_and_flag_dangling_ones() {
+        let sources = [source("call_0", "search"), source("call_1", "fetch")];
+        let text = "Rust is fast [1][2] and safe [3]. See `v[0]`, items[1] and:\n```\n[4]\n```";
+        let annotations = annotate(&[Arc::new(CitationExtractor)], text, &sources);
+
+        let cited: Vec<(usize, Option<&str>)> = annotations
+            .citations
+            .iter()
+            .map(|c| (c.number, c.source.as_ref().map(|s| s.label.as_str())))
+            .collect();
+        assert_eq!(
+            cited,
+            vec![(1, Some("search")), (2, Some("fetch")), (3, None)]
+        );
+        assert_eq!(
+            &text[annotations.citations[2].start..annotations.citations[2].end],
+            "[3]"
+        );
+
+        let dangling: Vec<usize> = annotations.dangling_citations().map(|c| c.number).collect();
+        assert_eq!(dangling, vec![3]);
+    }
+
+    #[test]
+    fn test_zero_is_never_a_source() {
+        let annotations = annotate(
+            &[Arc::new(CitationExtractor)],
+            "As shown [0].",
+            &[source("call_0", "search")],
+        );
+        assert!(annotations.citations[0].is_dangling());
+    }
+}
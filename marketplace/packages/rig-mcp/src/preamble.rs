@@ -0,0 +1,316 @@
+//! Compact tool descriptions
+//!
+//! The system prompt and tool definitions open every request of an agent
+//! unchanged, and with a few dozen tools they are most of the prompt.
+//! Providers with prompt caching serve that prefix from their cache; the
+//! rest are paid for it on every completion. Agents built with
+//! [`crate::AgentBuilder::compact_tools`] send those providers each tool with
+//! the first sentence of its description and no argument schema, plus a
+//! `describe_tool` pseudo-tool the model calls for the full definition of a
+//! tool before using it.
+//!
+//! Whether a provider/model pair caches prompts is learned from the cached
+//! tokens its completions report and kept in the client's
+//! [`crate::ToolSupport`]; pairs are compacted until they report some. The
+//! prompt tokens compacting left out are reported as
+//! [`crate::NormalizedUsage::saved_tokens`], estimated with the agent's token
+//! counter.
+//!
+//! [`Preamble::fingerprint`] identifies a preamble by its canonical form, so
+//! requests sharing one can be recognized whatever order tools were listed
+//! in or their schema keys written in.
+
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+
+use crate::provider::{ToolCall, ToolDefinition};
+
+/// Name of the pseudo-tool the model calls for a full tool definition
+pub const DESCRIBE_TOOL: &str = "describe_tool";
+
+/// Longest compact description, in characters
+const SHORT_DESCRIPTION: usize = 80;
+
+/// Definition of the `describe_tool` tool offered with compact descriptions
+pub fn describe_tool_definition() -> ToolDefinition {
+    ToolDefinition {
+        name: DESCRIBE_TOOL.to_string(),
+        description: "Get the full description and JSON Schema of the arguments of a tool. \
+                      Call it before calling any other tool."
+            .to_string(),
+        parameters: json!({
+            "type": "object",
+            "properties": {
+                "name": {"type": "string", "description": "Name of the tool"}
+            },
+            "required": ["name"]
+        }),
+    }
+}
+
+/// What every request of a run starts with: the system prompt and the tools
+#[derive(Debug, Clone, PartialEq)]
+pub struct Preamble {
+    system: Option<String>,
+    tools: Vec<ToolDefinition>,
+}
+
+impl Preamble {
+    pub fn new(system: Option<String>, tools: Vec<ToolDefinition>) -> Self {
+        Self { system, tools }
+    }
+
+    /// Full tool definitions, in the order given
+    pub fn tools(&self) -> &[ToolDefinition] {
+        &self.tools
+    }
+
+    /// The system prompt and tools as JSON, with tools sorted by name and
+    /// object keys sorted
+    pub fn canonical(&self) -> String {
+        let mut tools: Vec<&ToolDefinition> = self.tools.iter().collect();
+        tools.sort_by(|a, b| a.name.cmp(&b.name));
+        let tools: Vec<Value> = tools
+            .into_iter()
+            .map(|tool| {
+                json!({
+                    "name": tool.name,
+                    "description": tool.description,
+                    "parameters": sorted(&tool.parameters),
+                })
+            })
+            .collect();
+        sorted(&json!({"system": self.system, "tools": tools})).to_string()
+    }
+
+    /// SHA-256 of [`Preamble::canonical`], hex encoded
+    pub fn fingerprint(&self) -> String {
+        Sha256::digest(self.canonical().as_bytes())
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    /// Every tool with a short description and any arguments, followed by
+    /// `describe_tool`
+    pub fn compact_tools(&self) -> Vec<ToolDefinition> {
+        self.tools
+            .iter()
+            .map(|tool| ToolDefinition {
+                name: tool.name.clone(),
+                description: short_description(&tool.description),
+                parameters: json!({"type": "object"}),
+            })
+            .chain([describe_tool_definition()])
+            .collect()
+    }
+
+    /// Result of a `describe_tool` call: the full definition as JSON
+    pub fn describe(&self, call: &ToolCall) -> String {
+        let name = call.arguments["name"].as_str().unwrap_or_default();
+        match self.tools.iter().find(|tool| tool.name == name) {
+            Some(tool) => json!({
+                "name": tool.name,
+                "description": tool.description,
+                "parameters": tool.parameters,
+            })
+            .to_string(),
+            None => json!({"error": format!("Unknown tool '{}'", name)}).to_string(),
+        }
+    }
+}
+
+/// The first sentence of `description`, cut at a word boundary past
+/// [`SHORT_DESCRIPTION`] characters
+fn short_description(description: &str) -> String {
+    let first = description
+        .split_inclusive(". ")
+        .next()
+        .unwrap_or_default()
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .trim();
+    if first.chars().count() <= SHORT_DESCRIPTION {
+        return first.to_string();
+    }
+    let cut: String = first.chars().take(SHORT_DESCRIPTION).collect();
+    match cut.rfind(' ') {
+        Some(space) => format!("{}…", &cut[..space]),
+        None => format!("{}…", cut),
+    }
+}
+
+/// `value` with the keys of every object sorted
+fn sorted(value: &Value) -> Value {
+    match value {
+        Value::Object(object) => {
+            let mut entries: Vec<(&String, &Value)> = object.iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            Value::Object(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (key.clone(), sorted(value)))
+                    .collect::<Map<String, Value>>(),
+            )
+        }
+        Value::Array(items) => Value::Array(items.iter().map(sorted).collect()),
+        other => other.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::AgentBuilder;
+    use crate::interop::ToolSupport;
+    use crate::mcp::{ToolInfo, ToolServer};
+    use crate::provider::{NormalizedResponse, NormalizedUsage, Role};
+    use crate::testing::{FakeMcpServer, MockProvider};
+    use std::sync::Arc;
+
+    /// Ten tools with descriptions and schemas the size real servers send
+    fn toolbox() -> Arc<FakeMcpServer> {
+        let mut server = FakeMcpServer::new("toolbox");
+        for i in 0..10 {
+            let description = format!(
+                "Look up records of kind {i} in the inventory. Supports filtering by owner, \
+                 region and creation date, sorting by any field and paging through large \
+                 result sets; returns at most `limit` records as JSON."
+            );
+            let schema = json!({
+                "type": "object",
+                "properties": {
+                    "owner": {"type": "string", "description": "Login of the owning user"},
+                    "region": {"type": "string", "enum": ["eu", "us", "apac"]},
+                    "created_after": {"type": "string", "format": "date-time"},
+                    "sort": {"type": "string", "description": "Field to sort by"},
+                    "limit": {"type": "integer", "minimum": 1, "maximum": 100},
+                    "cursor": {"type": "string", "description": "Cursor of the next page"}
+                },
+                "required": ["owner"]
+            });
+            server = server.with_tool(&format!("lookup_{i}"), &description, schema, |args| {
+                Ok(format!("records of {}", args["owner"]))
+            });
+        }
+        Arc::new(server)
+    }
+
+    async fn full_tools() -> Vec<ToolDefinition> {
+        let tools = toolbox().list_tools().await.unwrap();
+        tools.iter().map(ToolInfo::definition).collect()
+    }
+
+    /// Run one prompt with the model calling `lookup_3`, returning the
+    /// provider and the usage of the run
+    async fn lookup(compact: bool, describe_first: bool) -> (Arc<MockProvider>, NormalizedUsage) {
+        let provider = Arc::new(MockProvider::new("mock"));
+        if describe_first {
+            provider.push_tool_call(DESCRIBE_TOOL, json!({"name": "lookup_3"}));
+        }
+        provider.push_tool_call("lookup_3", json!({"owner": "ada"}));
+        provider.push_response(NormalizedResponse::text("Found them"));
+        let agent = AgentBuilder::new(provider.clone())
+            .tool_server(toolbox())
+            .compact_tools(compact)
+            .build();
+        let response = agent.run(&mut Vec::new(), "Records of ada?").await.unwrap();
+        (provider, response.usage)
+    }
+
+    #[tokio::test]
+    async fn test_compact_tools_shrink_prompts() {
+        let (full, full_usage) = lookup(false, false).await;
+        let (compact, compact_usage) = lookup(true, false).await;
+        let counter = crate::tokens::TokenCounter::default();
+        let full_size = counter.count_request(&full.requests()[0]);
+        let compact_size = counter.count_request(&compact.requests()[0]);
+        assert!(
+            compact_size * 2 < full_size,
+            "{} tokens compact, {} full",
+            compact_size,
+            full_size
+        );
+
+        let tools = &compact.requests()[0].tools;
+        assert_eq!(tools.len(), 11);
+        assert_eq!(
+            tools[3].description,
+            "Look up records of kind 3 in the inventory."
+        );
+        assert_eq!(tools[3].parameters, json!({"type": "object"}));
+        assert_eq!(tools[10].name, DESCRIBE_TOOL);
+
+        assert_eq!(full_usage.saved_tokens, 0);
+        // Both completions of the run sent compact tools
+        let saved = counter.count_tools(&full.requests()[0].tools)
+            - counter.count_tools(&compact.requests()[0].tools);
+        assert_eq!(compact_usage.saved_tokens, 2 * saved);
+    }
+
+    #[tokio::test]
+    async fn test_describe_tool_returns_the_full_schema() {
+        let (provider, _) = lookup(true, true).await;
+        let requests = provider.requests();
+        let described = requests[1]
+            .messages
+            .iter()
+            .find(|m| m.role == Role::Tool)
+            .unwrap();
+        let described: Value = serde_json::from_str(&described.content).unwrap();
+        let tools = full_tools().await;
+        assert_eq!(described["name"], "lookup_3");
+        assert_eq!(described["description"], json!(tools[3].description));
+        assert_eq!(described["parameters"], tools[3].parameters);
+        // The call that followed reached the server
+        let result = requests[2].messages.last().unwrap();
+        assert_eq!(result.content, "records of \"ada\"");
+    }
+
+    #[tokio::test]
+    async fn test_providers_that_cache_prompts_get_full_tools() {
+        let provider = Arc::new(MockProvider::new("mock"));
+        let support = Arc::new(ToolSupport::new());
+        support.observe_usage(
+            provider.as_ref(),
+            &NormalizedUsage {
+                cached_tokens: 1024,
+                ..NormalizedUsage::new(2048, 10)
+            },
+        );
+        let agent = AgentBuilder::new(provider.clone())
+            .tool_server(toolbox())
+            .tool_support(support)
+            .compact_tools(true)
+            .build();
+        agent.prompt("hello").await.unwrap();
+        assert_eq!(provider.requests()[0].tools, full_tools().await);
+    }
+
+    #[test]
+    fn test_fingerprint_ignores_tool_and_key_order() {
+        let tool = |name: &str, parameters: Value| ToolDefinition {
+            name: name.to_string(),
+            description: String::new(),
+            parameters,
+        };
+        let a = Preamble::new(
+            Some("Be brief".to_string()),
+            vec![
+                tool("a", json!({"type": "object", "required": ["x"]})),
+                tool("b", json!({})),
+            ],
+        );
+        let b = Preamble::new(
+            Some("Be brief".to_string()),
+            vec![
+                tool("b", json!({})),
+                tool("a", json!({"required": ["x"], "type": "object"})),
+            ],
+        );
+        assert_eq!(a.fingerprint(), b.fingerprint());
+        let c = Preamble::new(Some("Be terse".to_string()), a.tools().to_vec());
+        assert_ne!(a.fingerprint(), c.fingerprint());
+    }
+}
@@ -0,0 +1,72 @@
+//! Model prices
+//!
+//! `prices` in [`crate::Config`] maps a `provider/model` pair, or a provider
+//! name for all of its models, to what the provider charges, so token usage
+//! can be reported as cost. Prices aren't fetched from anywhere; they are
+//! whatever the configuration says.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::provider::NormalizedUsage;
+
+/// What a model costs, in USD per million tokens
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Price {
+    /// Per million prompt tokens
+    pub input: f64,
+    /// Per million completion tokens
+    pub output: f64,
+}
+
+impl Price {
+    /// Cost of `usage` in USD
+    pub fn cost(&self, usage: &NormalizedUsage) -> f64 {
+        (usage.prompt_tokens as f64 * self.input + usage.completion_tokens as f64 * self.output)
+            / 1_000_000.0
+    }
+}
+
+/// Price of `model` at `provider`; a `provider/model` entry wins over one
+/// for the whole provider
+pub fn lookup<'a>(
+    prices: &'a HashMap<String, Price>, provider: &str, model: &str,
+) -> Option<&'a Price> {
+    prices
+        .get(&format!("{}/{}", provider, model))
+        .or_else(|| prices.get(provider))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_model_price_wins_over_provider_price() {
+        let prices = HashMap::from([
+            (
+                "openai".to_string(),
+                Price {
+                    input: 2.5,
+                    output: 10.0,
+                },
+            ),
+            (
+                "openai/gpt-4o-mini".to_string(),
+                Price {
+                    input: 0.15,
+                    output: 0.6,
+                },
+            ),
+        ]);
+        assert_eq!(
+            lookup(&prices, "openai", "gpt-4o-mini").unwrap().input,
+            0.15
+        );
+        assert_eq!(lookup(&prices, "openai", "gpt-4o").unwrap().input, 2.5);
+        assert!(lookup(&prices, "anthropic", "claude").is_none());
+
+        let usage = NormalizedUsage::new(2_000_000, 500_000);
+        assert_eq!(prices["openai"].cost(&usage), 10.0);
+    }
+}
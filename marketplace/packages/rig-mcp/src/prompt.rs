@@ -0,0 +1,170 @@
+//! System prompt templates
+//!
+//! `system_prompt` in [`crate::AgentConfig`] is a [Tera] template, rendered
+//! every time [`crate::RigMcpClient::agent`] builds an agent so `now` is the
+//! time of the build rather than of startup. Templates see:
+//!
+//! - `now`: the client's clock, RFC 3339 (`{{ now | date(format="%Y-%m-%d") }}`)
+//! - `provider` and `model` of the agent
+//! - `tools`: every MCP tool as `name` and `description`
+//! - `metadata`: whatever the caller passed to
+//!   [`crate::RigMcpClient::agent_with_metadata`]
+//!
+//! Referring to anything else is an error rather than an empty string, so a
+//! typo can't quietly leave a hole in the prompt.
+//!
+//! [Tera]: https://keats.github.io/tera/docs/
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::error::Error as _;
+use tera::Tera;
+use thiserror::Error;
+
+use crate::mcp::ToolInfo;
+
+const TEMPLATE: &str = "system_prompt";
+
+/// Why a system prompt template could not be used
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum PromptError {
+    #[error("system_prompt is not a valid template: {0}")]
+    Invalid(String),
+    #[error("system_prompt could not be rendered: {0}")]
+    Render(String),
+}
+
+/// A tool as templates see it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ToolSummary {
+    pub name: String,
+    pub description: String,
+}
+
+impl From<&ToolInfo> for ToolSummary {
+    fn from(tool: &ToolInfo) -> Self {
+        Self {
+            name: tool.name.clone(),
+            description: tool.description.clone(),
+        }
+    }
+}
+
+/// Variables available to a system prompt template
+#[derive(Debug, Clone, Serialize)]
+pub struct PromptVars {
+    pub now: String,
+    pub provider: String,
+    pub model: String,
+    pub tools: Vec<ToolSummary>,
+    pub metadata: HashMap<String, Value>,
+}
+
+impl PromptVars {
+    pub fn new(now: DateTime<Utc>, provider: &str, model: &str) -> Self {
+        Self {
+            now: now.to_rfc3339_opts(SecondsFormat::Secs, true),
+            provider: provider.to_string(),
+            model: model.to_string(),
+            tools: Vec::new(),
+            metadata: HashMap::new(),
+        }
+    }
+
+    pub fn tools<'a>(mut self, tools: impl IntoIterator<Item = &'a ToolInfo>) -> Self {
+        self.tools = tools.into_iter().map(ToolSummary::from).collect();
+        self
+    }
+
+    pub fn metadata(mut self, metadata: HashMap<String, Value>) -> Self {
+        self.metadata = metadata;
+        self
+    }
+}
+
+/// A parsed system prompt template
+#[derive(Debug, Clone)]
+pub struct SystemPrompt {
+    tera: Tera,
+}
+
+impl SystemPrompt {
+    /// Parse `template`, failing on syntax errors
+    pub fn parse(template: &str) -> Result<Self, PromptError> {
+        let mut tera = Tera::default();
+        tera.add_raw_template(TEMPLATE, template)
+            .map_err(|err| PromptError::Invalid(describe(&err)))?;
+        Ok(Self { tera })
+    }
+
+    /// Render with `vars`, failing on variables it doesn't define
+    pub fn render(&self, vars: &PromptVars) -> Result<String, PromptError> {
+        let context = tera::Context::from_serialize(vars)
+            .map_err(|err| PromptError::Render(describe(&err)))?;
+        self.tera
+            .render(TEMPLATE, &context)
+            .map_err(|err| PromptError::Render(describe(&err)))
+    }
+}
+
+/// Tera puts the useful part of a message, such as which variable is
+/// missing, in the innermost source
+fn describe(err: &tera::Error) -> String {
+    let mut message = err.to_string();
+    let mut source = err.source();
+    while let Some(cause) = source {
+        message = cause.to_string();
+        source = cause.source();
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars() -> PromptVars {
+        let now = DateTime::parse_from_rfc3339("2025-03-04T05:06:07Z").unwrap();
+        PromptVars::new(now.into(), "openai", "gpt-4o")
+    }
+
+    #[test]
+    fn test_renders_variables() {
+        let prompt = SystemPrompt::parse(
+            "{{ provider }}/{{ model }} on {{ now | date(format=\"%Y-%m-%d\") }}",
+        )
+        .unwrap();
+        assert_eq!(
+            prompt.render(&vars()).unwrap(),
+            "openai/gpt-4o on 2025-03-04"
+        );
+    }
+
+    #[test]
+    fn test_missing_variables_are_errors() {
+        let prompt = SystemPrompt::parse("You work for {{ metadata.tenant }}.").unwrap();
+        let err = prompt.render(&vars()).unwrap_err();
+        assert!(
+            matches!(&err, PromptError::Render(message) if message.contains("metadata.tenant")),
+            "{}",
+            err
+        );
+
+        let mut metadata = HashMap::new();
+        metadata.insert("tenant".to_string(), Value::from("Acme"));
+        assert_eq!(
+            prompt.render(&vars().metadata(metadata)).unwrap(),
+            "You work for Acme."
+        );
+    }
+
+    #[test]
+    fn test_syntax_errors_are_caught_when_parsing() {
+        assert!(matches!(
+            SystemPrompt::parse("Hello {{ name"),
+            Err(PromptError::Invalid(_))
+        ));
+    }
+}
@@ -0,0 +1,437 @@
+//! Completion providers
+//!
+//! [`Provider`] is the object-safe completion interface that agents, the REPL
+//! and tests program against. Providers built from the configuration are
+//! [`crate::HttpProvider`]s; any other rig model can be wrapped in
+//! [`RigProvider`]. Tests and the offline demo use
+//! [`crate::testing::MockProvider`].
+
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::stream::{self, BoxStream, StreamExt};
+use rig_core::completion::{self, CompletionModel};
+use rig_core::message::{AssistantContent, Message, ToolResultContent, UserContent};
+use rig_core::OneOrMany;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::ops::AddAssign;
+
+use crate::postprocess::Annotations;
+use crate::race::Attempt;
+use crate::transport::RateLimits;
+
+/// Author of a chat message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    System,
+    User,
+    Assistant,
+    Tool,
+}
+
+/// A message in a conversation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub role: Role,
+    pub content: String,
+    /// Tool calls requested by an assistant message
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCall>,
+    /// Call answered by a tool message
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+}
+
+impl ChatMessage {
+    fn new(role: Role, content: impl Into<String>) -> Self {
+        Self {
+            role,
+            content: content.into(),
+            tool_calls: Vec::new(),
+            tool_call_id: None,
+        }
+    }
+
+    pub fn system(content: impl Into<String>) -> Self {
+        Self::new(Role::System, content)
+    }
+
+    pub fn user(content: impl Into<String>) -> Self {
+        Self::new(Role::User, content)
+    }
+
+    pub fn assistant(content: impl Into<String>) -> Self {
+        Self::new(Role::Assistant, content)
+    }
+
+    /// Result of the tool call with the given id
+    pub fn tool(call_id: impl Into<String>, content: impl Into<String>) -> Self {
+        Self {
+            tool_call_id: Some(call_id.into()),
+            ..Self::new(Role::Tool, content)
+        }
+    }
+}
+
+/// A tool invocation requested by the model
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolCall {
+    pub id: String,
+    pub name: String,
+    pub arguments: Value,
+}
+
+/// A tool offered to the model
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolDefinition {
+    pub name: String,
+    pub description: String,
+    /// JSON Schema of the arguments
+    pub parameters: Value,
+}
+
+/// A completion request
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ChatRequest {
+    pub system: Option<String>,
+    pub messages: Vec<ChatMessage>,
+    pub tools: Vec<ToolDefinition>,
+    pub max_tokens: Option<usize>,
+    pub temperature: Option<f32>,
+    /// Sampling seed, for providers that accept one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    /// Thinking before answering; dropped, with a warning, for models that
+    /// don't reason
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning: Option<ReasoningConfig>,
+}
+
+/// How much a reasoning model thinks before answering
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReasoningEffort {
+    Low,
+    #[default]
+    Medium,
+    High,
+}
+
+/// Reasoning in provider-neutral terms, sent as OpenAI's `reasoning_effort`
+/// and as Anthropic's and Gemini's thinking budgets
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReasoningConfig {
+    #[serde(default)]
+    pub effort: ReasoningEffort,
+    /// Budget for providers that take one in tokens, instead of the one
+    /// `effort` stands for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_thinking_tokens: Option<u32>,
+}
+
+impl ReasoningConfig {
+    /// Tokens the model may spend thinking
+    pub fn thinking_budget(&self) -> u32 {
+        self.max_thinking_tokens.unwrap_or(match self.effort {
+            ReasoningEffort::Low => 1024,
+            ReasoningEffort::Medium => 4096,
+            ReasoningEffort::High => 16384,
+        })
+    }
+}
+
+/// Token usage in provider-neutral terms
+///
+/// Counts a provider doesn't report are zero.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NormalizedUsage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub total_tokens: u64,
+    /// Counted by a [`crate::TokenCounter`] because the provider reported
+    /// nothing, e.g. for a stream that ended without a usage frame
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub estimated: bool,
+    /// Part of `prompt_tokens` served from the provider's context cache
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub cached_tokens: u64,
+    /// Prompt tokens compact tool descriptions left out, as estimated by
+    /// the agent; see [`crate::preamble`]
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub saved_tokens: u64,
+    /// Part of `completion_tokens` the model spent thinking, for providers
+    /// that report it
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub reasoning_tokens: u64,
+}
+
+fn is_zero(tokens: &u64) -> bool {
+    *tokens == 0
+}
+
+impl NormalizedUsage {
+    /// Usage with the total derived from its parts
+    pub fn new(prompt_tokens: u64, completion_tokens: u64) -> Self {
+        Self {
+            prompt_tokens,
+            completion_tokens,
+            total_tokens: prompt_tokens + completion_tokens,
+            estimated: false,
+            cached_tokens: 0,
+            saved_tokens: 0,
+            reasoning_tokens: 0,
+        }
+    }
+
+    /// [`NormalizedUsage::new`], marked as estimated
+    pub fn estimate(prompt_tokens: u64, completion_tokens: u64) -> Self {
+        Self {
+            estimated: true,
+            ..Self::new(prompt_tokens, completion_tokens)
+        }
+    }
+}
+
+impl AddAssign for NormalizedUsage {
+    /// Sum two usages, estimated if either was
+    fn add_assign(&mut self, other: Self) {
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+        self.total_tokens += other.total_tokens;
+        self.estimated |= other.estimated;
+        self.cached_tokens += other.cached_tokens;
+        self.saved_tokens += other.saved_tokens;
+        self.reasoning_tokens += other.reasoning_tokens;
+    }
+}
+
+/// Why the model stopped generating
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FinishReason {
+    /// Natural end of the answer or a stop sequence
+    #[default]
+    Stop,
+    /// Token limit reached
+    Length,
+    /// The model is waiting for tool results
+    ToolCalls,
+    /// Blocked by a safety or content filter
+    ContentFilter,
+    /// Anything else, as reported by the provider
+    Other(String),
+}
+
+/// A completed response, the same shape for every provider
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct NormalizedResponse {
+    pub content: String,
+    /// Thinking the provider returned alongside the answer
+    pub reasoning: Option<String>,
+    pub tool_calls: Vec<ToolCall>,
+    pub usage: NormalizedUsage,
+    pub finish_reason: FinishReason,
+    /// The provider's response body as received, for anything not covered
+    /// above; `null` for responses that didn't come from an HTTP API
+    pub provider_raw: Value,
+    /// What the agent's post-processors found in `content`
+    #[serde(default, skip_serializing_if = "Annotations::is_empty")]
+    pub annotations: Annotations,
+    /// Id of the interaction in the client's audit log, for
+    /// [`crate::RigMcpClient::rate_interaction`]; only set when auditing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    /// Rate limits the provider reported with the response, for callers
+    /// pacing their requests
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate_limits: Option<RateLimits>,
+    /// Every provider a [`crate::race::Race`] or a fallback chain sent the
+    /// request to, the winner included; empty for requests to a single
+    /// provider
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attempts: Vec<Attempt>,
+    /// Name of the provider that answered
+    /// [`crate::RigMcpClient::complete_with_fallback`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub answered_by: Option<String>,
+}
+
+impl NormalizedResponse {
+    /// Plain text response
+    pub fn text(content: impl Into<String>) -> Self {
+        Self {
+            content: content.into(),
+            ..Self::default()
+        }
+    }
+
+    /// Response asking for tool calls
+    pub fn tool_calls(calls: Vec<ToolCall>) -> Self {
+        Self {
+            tool_calls: calls,
+            finish_reason: FinishReason::ToolCalls,
+            ..Self::default()
+        }
+    }
+}
+
+/// Incremental piece of a streamed response
+#[derive(Debug, Clone, PartialEq)]
+pub enum StreamChunk {
+    Text(String),
+    ToolCall(ToolCall),
+    /// Tokens of the whole response, if the provider reports them; usually
+    /// the last chunk
+    Usage(NormalizedUsage),
+}
+
+/// Stream of response chunks
+pub type ChatStream = BoxStream<'static, Result<StreamChunk>>;
+
+/// A completion model behind a configured provider
+#[async_trait]
+pub trait Provider: Send + Sync {
+    /// Configured provider name
+    fn name(&self) -> &str;
+
+    /// Model requests are sent to
+    fn model(&self) -> &str;
+
+    /// Run a completion to the end
+    async fn complete(&self, request: ChatRequest) -> Result<NormalizedResponse>;
+
+    /// Run a completion as a stream of chunks
+    ///
+    /// Providers without native streaming emit the whole response at once.
+    async fn stream(&self, request: ChatRequest) -> Result<ChatStream> {
+        let response = self.complete(request).await?;
+        Ok(response_stream(response))
+    }
+}
+
+/// Turn a complete response into the chunks a stream would have produced
+pub fn response_stream(response: NormalizedResponse) -> ChatStream {
+    let mut chunks = Vec::new();
+    if !response.content.is_empty() {
+        chunks.push(StreamChunk::Text(response.content));
+    }
+    chunks.extend(response.tool_calls.into_iter().map(StreamChunk::ToolCall));
+    chunks.push(StreamChunk::Usage(response.usage));
+    stream::iter(chunks.into_iter().map(Ok)).boxed()
+}
+
+/// [`Provider`] backed by a rig completion model
+pub struct RigProvider<M> {
+    name: String,
+    model_name: String,
+    model: M,
+}
+
+impl<M> RigProvider<M> {
+    pub fn new(name: impl Into<String>, model_name: impl Into<String>, model: M) -> Self {
+        Self {
+            name: name.into(),
+            model_name: model_name.into(),
+            model,
+        }
+    }
+}
+
+#[async_trait]
+impl<M> Provider for RigProvider<M>
+where
+    M: CompletionModel + Send + Sync + 'static,
+{
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn model(&self) -> &str {
+        &self.model_name
+    }
+
+    /// Rig doesn't expose the response body, so `provider_raw` is `null` and
+    /// the finish reason is inferred from the content
+    async fn complete(&self, request: ChatRequest) -> Result<NormalizedResponse> {
+        let response = self.model.completion(to_rig_request(request)?).await?;
+        let mut out = NormalizedResponse {
+            usage: NormalizedUsage {
+                prompt_tokens: response.usage.input_tokens,
+                completion_tokens: response.usage.output_tokens,
+                total_tokens: response.usage.total_tokens,
+                estimated: false,
+                cached_tokens: 0,
+                saved_tokens: 0,
+                reasoning_tokens: 0,
+            },
+            ..NormalizedResponse::default()
+        };
+        for content in response.choice.into_iter() {
+            match content {
+                AssistantContent::Text(text) => out.content.push_str(&text.text),
+                AssistantContent::ToolCall(call) => out.tool_calls.push(ToolCall {
+                    id: call.id,
+                    name: call.function.name,
+                    arguments: call.function.arguments,
+                }),
+                AssistantContent::Reasoning(reasoning) => out
+                    .reasoning
+                    .get_or_insert_with(String::new)
+                    .push_str(&reasoning.reasoning.join("\n")),
+            }
+        }
+        if !out.tool_calls.is_empty() {
+            out.finish_reason = FinishReason::ToolCalls;
+        }
+        Ok(out)
+    }
+}
+
+fn to_rig_request(request: ChatRequest) -> Result<completion::CompletionRequest> {
+    let history: Vec<Message> = request.messages.into_iter().map(to_rig_message).collect();
+    let chat_history = OneOrMany::many(history)
+        .map_err(|_| anyhow::anyhow!("Completion request has no messages"))?;
+    Ok(completion::CompletionRequest {
+        preamble: request.system,
+        chat_history,
+        documents: Vec::new(),
+        tools: request
+            .tools
+            .into_iter()
+            .map(|tool| completion::ToolDefinition {
+                name: tool.name,
+                description: tool.description,
+                parameters: tool.parameters,
+            })
+            .collect(),
+        temperature: request.temperature.map(f64::from),
+        max_tokens: request.max_tokens.map(|n| n as u64),
+        additional_params: None,
+    })
+}
+
+fn to_rig_message(message: ChatMessage) -> Message {
+    match message.role {
+        Role::System | Role::User => Message::user(message.content),
+        Role::Assistant if message.tool_calls.is_empty() => Message::assistant(message.content),
+        Role::Assistant => {
+            let text = Some(message.content)
+                .filter(|text| !text.is_empty())
+                .map(AssistantContent::text);
+            let calls = message
+                .tool_calls
+                .into_iter()
+                .map(|call| AssistantContent::tool_call(call.id, call.name, call.arguments));
+            let content = OneOrMany::many(text.into_iter().chain(calls))
+                .expect("assistant message has at least one tool call");
+            Message::Assistant { id: None, content }
+        }
+        Role::Tool => Message::User {
+            content: OneOrMany::one(UserContent::tool_result(
+                message.tool_call_id.unwrap_or_default(),
+                OneOrMany::one(ToolResultContent::text(message.content)),
+            )),
+        },
+    }
+}
@@ -0,0 +1,410 @@
+//! Speculative racing of providers
+//!
+//! Latency-critical requests can't wait out a provider having a slow day. A
+//! [`Race`] sends the same request to several providers and answers with the
+//! first acceptable response, dropping the requests still in flight. With a
+//! stagger delay the next entrant only starts when the ones before it haven't
+//! answered in time, so on a good day only the first one is paid for. A
+//! [`QualityGate`] rejects fast answers that aren't good enough, e.g. empty
+//! or truncated ones, to wait for the slower entrants instead.
+//!
+//! Races are configured by name in the `races` section and used like a
+//! provider:
+//!
+//! ```toml
+//! [races.interactive]
+//! targets = ["groq/llama-3.3-70b-versatile", "openai/gpt-4o-mini"]
+//! stagger_ms = 300
+//! ```
+
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::stream::{FuturesUnordered, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::time::Instant;
+
+use crate::provider::{ChatRequest, NormalizedResponse, NormalizedUsage, Provider};
+
+/// One entry of the `races` section of [`crate::Config`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RaceConfig {
+    /// Provider names, model aliases or `provider/model` pairs, in the order
+    /// they start
+    pub targets: Vec<String>,
+    /// Start each target only if the ones before it haven't answered within
+    /// this many milliseconds; all start at once when left out
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stagger_ms: Option<u64>,
+}
+
+/// Decides whether an answer may win a race
+///
+/// Rejected answers still count towards the race's usage.
+pub trait QualityGate: Send + Sync {
+    fn accept(&self, response: &NormalizedResponse) -> bool;
+}
+
+impl<F> QualityGate for F
+where
+    F: Fn(&NormalizedResponse) -> bool + Send + Sync,
+{
+    fn accept(&self, response: &NormalizedResponse) -> bool {
+        self(response)
+    }
+}
+
+/// One entrant's part in a race, as listed in
+/// [`NormalizedResponse::attempts`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Attempt {
+    pub provider: String,
+    pub model: String,
+    pub outcome: Outcome,
+    /// From the start of the race until the entrant answered, failed or was
+    /// cancelled
+    pub elapsed_ms: u64,
+    /// Tokens of the entrant's answer; none when it didn't answer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<NormalizedUsage>,
+}
+
+/// How an entrant's request ended
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    /// Its answer was returned
+    Won,
+    /// It answered, but the quality gate turned the answer down
+    Rejected,
+    /// Dropped while in flight because another entrant won
+    Cancelled,
+    /// The request failed
+    Failed(String),
+}
+
+impl fmt::Display for Attempt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{} ", self.provider, self.model)?;
+        match &self.outcome {
+            Outcome::Won => write!(f, "won"),
+            Outcome::Rejected => write!(f, "was rejected"),
+            Outcome::Cancelled => write!(f, "was cancelled"),
+            Outcome::Failed(error) => write!(f, "failed: {}", error),
+        }?;
+        write!(f, " after {}ms", self.elapsed_ms)
+    }
+}
+
+/// Every entrant of a race failed or was rejected
+///
+/// Returned inside the `anyhow::Error` of [`Race::complete`]; use
+/// `downcast_ref::<NoWinner>()` to get at it.
+#[derive(Debug, Clone, Error)]
+#[error("Race '{race}' had no acceptable answer: {}", list(.attempts))]
+pub struct NoWinner {
+    pub race: String,
+    pub attempts: Vec<Attempt>,
+}
+
+fn list(attempts: &[Attempt]) -> String {
+    let attempts: Vec<String> = attempts.iter().map(ToString::to_string).collect();
+    attempts.join("; ")
+}
+
+/// Providers racing to answer the same request
+///
+/// The response is the winner's, with [`NormalizedResponse::attempts`]
+/// listing every entrant that was started and its `usage` summing the
+/// answers received, rejected ones included. Entrants still in flight when
+/// one wins are cancelled; what they used is unknown. Streams are the
+/// winning response at once, as races can't pick a winner before a response
+/// is complete.
+pub struct Race {
+    name: String,
+    entrants: Vec<Arc<dyn Provider>>,
+    stagger: Option<Duration>,
+    gate: Option<Arc<dyn QualityGate>>,
+}
+
+impl Race {
+    /// Race `entrants` against each other, starting them all at once
+    pub fn new(name: impl Into<String>, entrants: Vec<Arc<dyn Provider>>) -> Self {
+        Self {
+            name: name.into(),
+            entrants,
+            stagger: None,
+            gate: None,
+        }
+    }
+
+    /// Start each entrant only once the ones before it had `stagger` to
+    /// answer, or as soon as they have all failed or been rejected
+    pub fn with_stagger(mut self, stagger: Duration) -> Self {
+        self.stagger = Some(stagger);
+        self
+    }
+
+    /// Only let answers `gate` accepts win
+    pub fn with_quality_gate(mut self, gate: Arc<dyn QualityGate>) -> Self {
+        self.gate = Some(gate);
+        self
+    }
+
+    fn attempt(&self, entrant: usize, outcome: Outcome, elapsed: Duration) -> Attempt {
+        let provider = &self.entrants[entrant];
+        Attempt {
+            provider: provider.name().to_string(),
+            model: provider.model().to_string(),
+            outcome,
+            elapsed_ms: elapsed.as_millis() as u64,
+            usage: None,
+        }
+    }
+}
+
+#[async_trait]
+impl Provider for Race {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Model of the first entrant
+    fn model(&self) -> &str {
+        self.entrants
+            .first()
+            .map_or("", |provider| provider.model())
+    }
+
+    async fn complete(&self, request: ChatRequest) -> Result<NormalizedResponse> {
+        let start = Instant::now();
+        let run = |entrant: usize| {
+            let provider = self.entrants[entrant].clone();
+            let request = request.clone();
+            async move { (entrant, provider.complete(request).await) }
+        };
+        let mut running = FuturesUnordered::new();
+        // Attempts of the entrants started so far, by entrant; none while
+        // in flight
+        let mut attempts: Vec<Option<Attempt>> = Vec::new();
+        let mut usage = NormalizedUsage::default();
+        let mut next_start = start;
+        loop {
+            while attempts.len() < self.entrants.len()
+                && (running.is_empty() || next_start <= Instant::now())
+            {
+                running.push(run(attempts.len()));
+                attempts.push(None);
+                next_start = match self.stagger {
+                    Some(stagger) => Instant::now() + stagger,
+                    None => Instant::now(),
+                };
+            }
+            if running.is_empty() {
+                let attempts = attempts.into_iter().flatten().collect();
+                return Err(NoWinner {
+                    race: self.name.clone(),
+                    attempts,
+                }
+                .into());
+            }
+            let waiting = attempts.len() < self.entrants.len();
+            let stagger = async move {
+                if waiting {
+                    tokio::time::sleep_until(next_start).await
+                } else {
+                    std::future::pending().await
+                }
+            };
+            let (entrant, result) = tokio::select! {
+                Some(finished) = running.next() => finished,
+                () = stagger => continue,
+            };
+            let elapsed = start.elapsed();
+            let mut response = match result {
+                Ok(response) => response,
+                Err(err) => {
+                    tracing::debug!("Race '{}': {:#}", self.name, err);
+                    let failed = Outcome::Failed(format!("{:#}", err));
+                    attempts[entrant] = Some(self.attempt(entrant, failed, elapsed));
+                    continue;
+                }
+            };
+            usage += response.usage;
+            let accepted = self.gate.as_ref().is_none_or(|gate| gate.accept(&response));
+            let outcome = if accepted {
+                Outcome::Won
+            } else {
+                Outcome::Rejected
+            };
+            attempts[entrant] = Some(Attempt {
+                usage: Some(response.usage),
+                ..self.attempt(entrant, outcome, elapsed)
+            });
+            if !accepted {
+                continue;
+            }
+            drop(running);
+            response.usage = usage;
+            response.attempts = attempts
+                .into_iter()
+                .enumerate()
+                .map(|(entrant, attempt)| {
+                    attempt.unwrap_or_else(|| self.attempt(entrant, Outcome::Cancelled, elapsed))
+                })
+                .collect();
+            return Ok(response);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::ChatMessage;
+    use crate::testing::{Fault, FaultPlan, Latency, MockProvider};
+
+    fn entrant(name: &str, millis: u64, content: &str) -> Arc<MockProvider> {
+        let latency = Latency::Fixed(Duration::from_millis(millis));
+        let provider = MockProvider::new(name).with_faults(FaultPlan::new().latency(latency));
+        provider.push_response(NormalizedResponse {
+            content: content.to_string(),
+            usage: NormalizedUsage::new(10, content.len() as u64),
+            ..NormalizedResponse::default()
+        });
+        Arc::new(provider)
+    }
+
+    fn request() -> ChatRequest {
+        ChatRequest {
+            messages: vec![ChatMessage::user("Hi")],
+            ..ChatRequest::default()
+        }
+    }
+
+    fn outcomes(response: &NormalizedResponse) -> Vec<(&str, &Outcome, u64)> {
+        response
+            .attempts
+            .iter()
+            .map(|a| (a.provider.as_str(), &a.outcome, a.elapsed_ms))
+            .collect()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_fastest_answer_wins_and_the_loser_is_cancelled() {
+        let slow = entrant("slow", 500, "slow answer");
+        let fast = entrant("fast", 100, "fast answer");
+        let race = Race::new("quick", vec![slow.clone(), fast.clone()]);
+
+        let start = Instant::now();
+        let response = race.complete(request()).await.unwrap();
+        assert_eq!(start.elapsed(), Duration::from_millis(100));
+        assert_eq!(response.content, "fast answer");
+        assert_eq!(
+            outcomes(&response),
+            [
+                ("slow", &Outcome::Cancelled, 100),
+                ("fast", &Outcome::Won, 100)
+            ]
+        );
+        assert_eq!(response.attempts[0].usage, None);
+        assert_eq!(response.usage, NormalizedUsage::new(10, 11));
+
+        // Both were asked at once; the slow one never got to answer, so its
+        // scripted response is still queued
+        assert_eq!(slow.timeline()[0].at, Duration::ZERO);
+        assert_eq!(fast.timeline()[0].at, Duration::ZERO);
+        assert_eq!(
+            slow.complete(request()).await.unwrap().content,
+            "slow answer"
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_stagger_only_starts_the_next_entrant_when_the_first_is_late() {
+        let first = entrant("first", 100, "on time");
+        let second = entrant("second", 100, "unused");
+        let race = Race::new("quick", vec![first.clone(), second.clone()])
+            .with_stagger(Duration::from_millis(200));
+        let response = race.complete(request()).await.unwrap();
+        assert_eq!(response.content, "on time");
+        assert_eq!(outcomes(&response), [("first", &Outcome::Won, 100)]);
+        assert!(second.requests().is_empty());
+
+        let first = entrant("first", 500, "late");
+        let second = entrant("second", 100, "backup");
+        let race = Race::new("quick", vec![first.clone(), second.clone()])
+            .with_stagger(Duration::from_millis(200));
+        let response = race.complete(request()).await.unwrap();
+        assert_eq!(response.content, "backup");
+        assert_eq!(
+            outcomes(&response),
+            [
+                ("first", &Outcome::Cancelled, 300),
+                ("second", &Outcome::Won, 300)
+            ]
+        );
+        assert_eq!(second.timeline()[0].at, Duration::from_millis(200));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_failure_starts_the_next_entrant_without_waiting() {
+        let plan = FaultPlan::new()
+            .then(Fault::FailWithStatus(503))
+            .latency(Latency::Fixed(Duration::from_millis(50)));
+        let first = Arc::new(MockProvider::new("first").with_faults(plan));
+        let second = entrant("second", 100, "backup");
+        let race =
+            Race::new("quick", vec![first, second.clone()]).with_stagger(Duration::from_secs(1));
+        let response = race.complete(request()).await.unwrap();
+        assert_eq!(response.content, "backup");
+        assert_eq!(second.timeline()[0].at, Duration::from_millis(50));
+        assert!(
+            matches!(response.attempts[0].outcome, Outcome::Failed(ref e) if e.contains("503"))
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_quality_gate_rejects_the_fast_answer_for_the_slow_one() {
+        let fast = entrant("fast", 100, "");
+        let slow = entrant("slow", 300, "thorough answer");
+        let gate = |response: &NormalizedResponse| !response.content.is_empty();
+        let race = Race::new("quick", vec![fast, slow]).with_quality_gate(Arc::new(gate));
+
+        let response = race.complete(request()).await.unwrap();
+        assert_eq!(response.content, "thorough answer");
+        assert_eq!(
+            outcomes(&response),
+            [
+                ("fast", &Outcome::Rejected, 100),
+                ("slow", &Outcome::Won, 300)
+            ]
+        );
+        assert_eq!(
+            response.attempts[0].usage,
+            Some(NormalizedUsage::new(10, 0))
+        );
+        // The rejected answer was paid for too
+        assert_eq!(response.usage, NormalizedUsage::new(20, 15));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_no_acceptable_answer_lists_every_attempt() {
+        let first = entrant("first", 100, "");
+        let second = entrant("second", 300, "");
+        let gate = |response: &NormalizedResponse| !response.content.is_empty();
+        let race = Race::new("quick", vec![first, second]).with_quality_gate(Arc::new(gate));
+
+        let err = race.complete(request()).await.unwrap_err();
+        let lost = err.downcast_ref::<NoWinner>().unwrap();
+        assert_eq!(lost.attempts.len(), 2);
+        assert_eq!(
+            err.to_string(),
+            "Race 'quick' had no acceptable answer: first/mock-model was rejected after \
+             100ms; second/mock-model was rejected after 300ms"
+        );
+    }
+}
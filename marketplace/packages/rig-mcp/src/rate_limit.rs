@@ -0,0 +1,359 @@
+//! Client-side rate limits of providers
+//!
+//! Agents running in parallel easily send more than a provider's tier
+//! allows, and every request refused with a 429 is a wasted round trip. A
+//! provider with a `rate_limit` section is held to it before anything is
+//! sent:
+//!
+//! ```toml
+//! [[providers]]
+//! name = "openai"
+//! model = "gpt-4o"
+//! rate_limit = { requests_per_minute = 500, tokens_per_minute = 30000, max_wait_ms = 60000 }
+//! ```
+//!
+//! The limits are token buckets holding a second's worth of the rate, so
+//! bursts stay short, refilled continuously. A request takes one request and
+//! its estimated tokens, the prompt plus `max_tokens` as providers count
+//! them; usage reported beyond the estimate is taken when the response
+//! arrives. A request the buckets can't serve yet waits, in the order
+//! requests arrived, for at most `max_wait_ms` ([`DEFAULT_MAX_WAIT`] when
+//! left out), and fails with [`RateLimited`] after that.
+//!
+//! Every provider the client creates for a configured provider, whatever
+//! the model, shares one [`RateLimiter`], so all agents created from the
+//! same client count against the same limits. Retries (see [`crate::retry`])
+//! happen behind the limiter: a request waits for its turn once.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use thiserror::Error;
+use tokio::time::Instant;
+
+use crate::provider::{ChatRequest, ChatStream, NormalizedResponse, Provider};
+use crate::tokens::TokenCounter;
+
+/// How long a request waits for its turn when `max_wait_ms` isn't set
+pub const DEFAULT_MAX_WAIT: Duration = Duration::from_secs(60);
+
+/// Limits a provider's requests are held to; see the module docs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RateLimitConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requests_per_minute: Option<u32>,
+    /// Prompt and completion tokens together
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tokens_per_minute: Option<u64>,
+    /// Fail a request that waited this many milliseconds for its turn
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_wait_ms: Option<u64>,
+}
+
+impl RateLimitConfig {
+    /// Problems that make the limits unusable, for config validation
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.requests_per_minute.is_none() && self.tokens_per_minute.is_none() {
+            problems
+                .push("rate_limit sets neither requests_per_minute nor tokens_per_minute".into());
+        }
+        if self.requests_per_minute == Some(0) {
+            problems.push("rate_limit.requests_per_minute must be at least 1".into());
+        }
+        if self.tokens_per_minute == Some(0) {
+            problems.push("rate_limit.tokens_per_minute must be at least 1".into());
+        }
+        problems
+    }
+}
+
+/// A request waited longer than `max_wait_ms` for its turn
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("Provider '{provider}' is rate limited: no turn within {waited:?}")]
+pub struct RateLimited {
+    pub provider: String,
+    pub waited: Duration,
+}
+
+/// A bucket refilled at a constant rate, up to a second's worth
+#[derive(Debug)]
+struct Bucket {
+    per_second: f64,
+    capacity: f64,
+    level: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn new(per_minute: f64, now: Instant) -> Self {
+        let per_second = per_minute / 60.0;
+        let capacity = per_second.max(1.0);
+        Self {
+            per_second,
+            capacity,
+            level: capacity,
+            updated: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.level = (self.level + elapsed * self.per_second).min(self.capacity);
+        self.updated = now;
+    }
+
+    /// How long until `amount` can be taken; more than the bucket holds is
+    /// taken once it is full, leaving it in debt
+    fn wait(&self, amount: f64) -> Duration {
+        let needed = amount.min(self.capacity);
+        if self.level >= needed {
+            return Duration::ZERO;
+        }
+        Duration::from_secs_f64((needed - self.level) / self.per_second)
+    }
+}
+
+/// Request and token buckets of a provider
+#[derive(Debug)]
+struct Buckets {
+    requests: Option<Bucket>,
+    tokens: Option<Bucket>,
+}
+
+impl Buckets {
+    /// Take a request and `tokens`, or say how long until they can be taken
+    fn take(&mut self, tokens: u64, now: Instant) -> Option<Duration> {
+        let mut wait = Duration::ZERO;
+        if let Some(requests) = &mut self.requests {
+            requests.refill(now);
+            wait = wait.max(requests.wait(1.0));
+        }
+        if let Some(bucket) = &mut self.tokens {
+            bucket.refill(now);
+            wait = wait.max(bucket.wait(tokens as f64));
+        }
+        if !wait.is_zero() {
+            return Some(wait);
+        }
+        if let Some(requests) = &mut self.requests {
+            requests.level -= 1.0;
+        }
+        if let Some(bucket) = &mut self.tokens {
+            bucket.level -= tokens as f64;
+        }
+        None
+    }
+}
+
+/// Rate limits of one configured provider, shared by every provider created
+/// for it; see the module docs
+#[derive(Debug)]
+pub struct RateLimiter {
+    provider: String,
+    max_wait: Duration,
+    buckets: Mutex<Buckets>,
+    /// Held by the request whose turn it is; tokio's mutex is fair, so
+    /// requests get their turns in the order they arrived
+    queue: tokio::sync::Mutex<()>,
+}
+
+impl RateLimiter {
+    pub fn new(provider: impl Into<String>, config: &RateLimitConfig) -> Self {
+        let now = Instant::now();
+        Self {
+            provider: provider.into(),
+            max_wait: config
+                .max_wait_ms
+                .map_or(DEFAULT_MAX_WAIT, Duration::from_millis),
+            buckets: Mutex::new(Buckets {
+                requests: config
+                    .requests_per_minute
+                    .map(|limit| Bucket::new(limit.into(), now)),
+                tokens: config
+                    .tokens_per_minute
+                    .map(|limit| Bucket::new(limit as f64, now)),
+            }),
+            queue: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// Wait until a request of `tokens` estimated tokens may be sent, or
+    /// fail with [`RateLimited`] after the maximum wait
+    pub async fn acquire(&self, tokens: u64) -> Result<()> {
+        let turn = async {
+            let _turn = self.queue.lock().await;
+            loop {
+                let wait = self.buckets.lock().unwrap().take(tokens, Instant::now());
+                match wait {
+                    Some(wait) => tokio::time::sleep(wait).await,
+                    None => return,
+                }
+            }
+        };
+        tokio::time::timeout(self.max_wait, turn)
+            .await
+            .map_err(|_| {
+                RateLimited {
+                    provider: self.provider.clone(),
+                    waited: self.max_wait,
+                }
+                .into()
+            })
+    }
+
+    /// Take `tokens` more, used beyond what a request was estimated at
+    pub fn charge(&self, tokens: u64) {
+        let mut buckets = self.buckets.lock().unwrap();
+        if let Some(bucket) = &mut buckets.tokens {
+            bucket.refill(Instant::now());
+            bucket.level -= tokens as f64;
+        }
+    }
+}
+
+/// [`Provider`] waiting for its turn with a [`RateLimiter`] before each
+/// request
+pub struct RateLimitedProvider {
+    inner: Arc<dyn Provider>,
+    limiter: Arc<RateLimiter>,
+    tokens: TokenCounter,
+}
+
+impl RateLimitedProvider {
+    pub fn new(inner: Arc<dyn Provider>, limiter: Arc<RateLimiter>) -> Self {
+        Self {
+            inner,
+            limiter,
+            tokens: TokenCounter::default(),
+        }
+    }
+
+    /// Estimate the prompt tokens of requests with `tokens` instead of the
+    /// heuristic
+    pub fn with_token_counter(mut self, tokens: TokenCounter) -> Self {
+        self.tokens = tokens;
+        self
+    }
+
+    /// Tokens a provider counts `request` as before answering it
+    fn estimate(&self, request: &ChatRequest) -> u64 {
+        let completion = request.max_tokens.unwrap_or_default() as u64;
+        self.tokens.count_request(request) + completion
+    }
+}
+
+#[async_trait]
+impl Provider for RateLimitedProvider {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn model(&self) -> &str {
+        self.inner.model()
+    }
+
+    async fn complete(&self, request: ChatRequest) -> Result<NormalizedResponse> {
+        let estimate = self.estimate(&request);
+        self.limiter.acquire(estimate).await?;
+        let response = self.inner.complete(request).await?;
+        let used = response.usage.total_tokens;
+        if used > estimate {
+            self.limiter.charge(used - estimate);
+        }
+        Ok(response)
+    }
+
+    /// Streams are charged their estimate only; their usage arrives with
+    /// the last chunk, long after the turn was taken
+    async fn stream(&self, request: ChatRequest) -> Result<ChatStream> {
+        self.limiter.acquire(self.estimate(&request)).await?;
+        self.inner.stream(request).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::ChatMessage;
+    use crate::testing::MockProvider;
+
+    fn limits(requests_per_minute: Option<u32>, tokens_per_minute: Option<u64>) -> RateLimitConfig {
+        RateLimitConfig {
+            requests_per_minute,
+            tokens_per_minute,
+            max_wait_ms: Some(600_000),
+        }
+    }
+
+    fn request(max_tokens: usize) -> ChatRequest {
+        ChatRequest {
+            messages: vec![ChatMessage::user("Hello")],
+            max_tokens: Some(max_tokens),
+            ..ChatRequest::default()
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_token_limit_spaces_requests_by_their_size() {
+        let limiter = Arc::new(RateLimiter::new("ollama", &limits(None, Some(6000))));
+        let provider = RateLimitedProvider::new(Arc::new(MockProvider::new("ollama")), limiter)
+            .with_token_counter(TokenCounter::new(Arc::new(|_: &str| 0u64)));
+        let start = Instant::now();
+
+        // 100 tokens a second; each request counts its max_tokens and the
+        // framing of its message
+        for _ in 0..3 {
+            provider.complete(request(196)).await.unwrap();
+        }
+        let waited = start.elapsed();
+        assert!(
+            waited >= Duration::from_secs(4) && waited < Duration::from_millis(4100),
+            "{:?}",
+            waited
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_requests_waiting_too_long_fail_as_rate_limited() {
+        let config = RateLimitConfig {
+            max_wait_ms: Some(2500),
+            ..limits(Some(60), None)
+        };
+        let limiter = Arc::new(RateLimiter::new("groq", &config));
+        let provider = Arc::new(RateLimitedProvider::new(
+            Arc::new(MockProvider::new("groq")),
+            limiter,
+        ));
+
+        let calls = (0..5).map(|_| {
+            let provider = provider.clone();
+            tokio::spawn(async move { provider.complete(request(10)).await })
+        });
+        let results = futures::future::join_all(calls).await;
+        let errors: Vec<_> = results
+            .into_iter()
+            .filter_map(|result| result.unwrap().err())
+            .collect();
+        // At 0, 1 and 2 seconds; the others gave up at 2.5
+        assert_eq!(errors.len(), 2);
+        let err = errors[0].downcast_ref::<RateLimited>().unwrap();
+        assert_eq!(err.provider, "groq");
+        assert_eq!(err.waited, Duration::from_millis(2500));
+    }
+
+    #[test]
+    fn test_limits_must_be_positive() {
+        assert!(limits(Some(60), Some(1000)).problems().is_empty());
+        assert_eq!(
+            limits(Some(0), None).problems(),
+            ["rate_limit.requests_per_minute must be at least 1"]
+        );
+        assert_eq!(
+            limits(None, None).problems(),
+            ["rate_limit sets neither requests_per_minute nor tokens_per_minute"]
+        );
+    }
+}
@@ -0,0 +1,489 @@
+//! Declarative redaction rules
+//!
+//! The `redaction` section of [`crate::Config`] lists what must not be kept
+//! or sent in readable form, once for every place data leaves the client:
+//! named regular expressions, shapes of personal data such as
+//! [`PiiKind::Email`], and JSON fields whose whole value is hidden. The
+//! rules compile into a [`RedactionRuleSet`], which each choke point applies
+//! when its flag is set:
+//!
+//! - `prompts`: requests, before a provider receives them; off by default
+//! - `audit`: interactions and tool decisions written to the
+//!   [`crate::audit`] log
+//! - `debug_logging`: logged and dumped provider exchanges
+//! - `transcripts`: runs kept by
+//!   [`crate::RigMcpClient::transcript_recorder`]
+//! - `finetune`: examples written by [`crate::finetune::export`], on top of
+//!   the `moderation` rules
+//! - `cache`: completions cached by the AI microservice, which reads the
+//!   same section from `REDACTION_CONFIG`
+//!
+//! A match is replaced with the rule name in capitals and the length class
+//! of what it hid, e.g. `[EMAIL:medium]`: `short` below 8 characters,
+//! `medium` below 32, `long` otherwise. A rule's `replacement` is used
+//! instead when set. Overlapping matches are replaced once, as a whole, by
+//! the token of the one starting first.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::ops::Range;
+use std::sync::Arc;
+use thiserror::Error;
+
+use crate::moderation::PiiKind;
+use crate::provider::{ChatMessage, ChatRequest, ChatStream, NormalizedResponse, Provider};
+
+/// `redaction` section of [`crate::Config`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RedactionConfig {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub rules: Vec<RedactionRule>,
+    /// Redact requests before they are sent to providers
+    pub prompts: bool,
+    pub audit: bool,
+    pub cache: bool,
+    pub debug_logging: bool,
+    pub transcripts: bool,
+    pub finetune: bool,
+}
+
+impl Default for RedactionConfig {
+    fn default() -> Self {
+        Self {
+            rules: Vec::new(),
+            prompts: false,
+            audit: true,
+            cache: true,
+            debug_logging: true,
+            transcripts: true,
+            finetune: true,
+        }
+    }
+}
+
+/// A named rule; exactly one of `pattern`, `shape` and `fields` is set
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RedactionRule {
+    pub name: String,
+    /// Regular expression matched in text
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<String>,
+    /// Kind of personal data recognised in text
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shape: Option<PiiKind>,
+    /// JSON fields whose values are hidden, matched case-insensitively
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<String>,
+    /// Replaces matches instead of the length-class token
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replacement: Option<String>,
+}
+
+impl RedactionRule {
+    pub fn pattern(name: impl Into<String>, pattern: impl Into<String>) -> Self {
+        Self {
+            pattern: Some(pattern.into()),
+            ..Self::named(name)
+        }
+    }
+
+    pub fn shape(name: impl Into<String>, shape: PiiKind) -> Self {
+        Self {
+            shape: Some(shape),
+            ..Self::named(name)
+        }
+    }
+
+    pub fn fields(name: impl Into<String>, fields: &[&str]) -> Self {
+        Self {
+            fields: fields.iter().map(|field| field.to_string()).collect(),
+            ..Self::named(name)
+        }
+    }
+
+    /// Replace matches with `replacement`
+    pub fn with_replacement(mut self, replacement: impl Into<String>) -> Self {
+        self.replacement = Some(replacement.into());
+        self
+    }
+
+    fn named(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            pattern: None,
+            shape: None,
+            fields: Vec::new(),
+            replacement: None,
+        }
+    }
+}
+
+/// A rule that doesn't compile
+#[derive(Debug, Error)]
+pub enum InvalidRedactionRule {
+    #[error("redaction rule '{name}': invalid pattern: {source}")]
+    Pattern { name: String, source: regex::Error },
+    #[error("redaction rule '{name}': expected exactly one of pattern, shape or fields")]
+    Matcher { name: String },
+}
+
+/// Where a rule matched, in bytes of the text it was applied to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RedactionMatch {
+    pub rule: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Compiled [`RedactionRule`]s
+#[derive(Debug, Clone, Default)]
+pub struct RedactionRuleSet {
+    rules: Vec<Compiled>,
+}
+
+#[derive(Debug, Clone)]
+struct Compiled {
+    name: String,
+    matcher: Matcher,
+    replacement: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+enum Matcher {
+    Pattern(Regex),
+    Shape(PiiKind),
+    /// Lowercased field names
+    Fields(Vec<String>),
+}
+
+impl Compiled {
+    fn spans(&self, text: &str) -> Vec<Range<usize>> {
+        match &self.matcher {
+            Matcher::Pattern(regex) => regex
+                .find_iter(text)
+                .map(|found| found.range())
+                .filter(|span| !span.is_empty())
+                .collect(),
+            Matcher::Shape(shape) => shape.spans(text),
+            Matcher::Fields(_) => Vec::new(),
+        }
+    }
+
+    fn token(&self, hidden: &str) -> String {
+        match &self.replacement {
+            Some(replacement) => replacement.clone(),
+            None => format!("[{}:{}]", self.name.to_uppercase(), length_class(hidden)),
+        }
+    }
+}
+
+fn length_class(hidden: &str) -> &'static str {
+    match hidden.chars().count() {
+        0..=7 => "short",
+        8..=31 => "medium",
+        _ => "long",
+    }
+}
+
+impl RedactionRuleSet {
+    pub fn compile(rules: &[RedactionRule]) -> Result<Self, InvalidRedactionRule> {
+        let rules = rules
+            .iter()
+            .map(|rule| {
+                let matcher = match (&rule.pattern, rule.shape, rule.fields.is_empty()) {
+                    (Some(pattern), None, true) => {
+                        Matcher::Pattern(Regex::new(pattern).map_err(|source| {
+                            InvalidRedactionRule::Pattern {
+                                name: rule.name.clone(),
+                                source,
+                            }
+                        })?)
+                    }
+                    (None, Some(shape), true) => Matcher::Shape(shape),
+                    (None, None, false) => Matcher::Fields(
+                        rule.fields
+                            .iter()
+                            .map(|field| field.to_lowercase())
+                            .collect(),
+                    ),
+                    _ => {
+                        return Err(InvalidRedactionRule::Matcher {
+                            name: rule.name.clone(),
+                        })
+                    }
+                };
+                Ok(Compiled {
+                    name: rule.name.clone(),
+                    matcher,
+                    replacement: rule.replacement.clone(),
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { rules })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// `text` with every match replaced, and the matches in the order they
+    /// start
+    pub fn apply(&self, text: &str) -> (String, Vec<RedactionMatch>) {
+        let mut found: Vec<(usize, Range<usize>)> = self
+            .rules
+            .iter()
+            .enumerate()
+            .flat_map(|(index, rule)| rule.spans(text).into_iter().map(move |span| (index, span)))
+            .collect();
+        if found.is_empty() {
+            return (text.to_string(), Vec::new());
+        }
+        // The longest of the matches starting together leads its group
+        found.sort_by_key(|(_, span)| (span.start, std::cmp::Reverse(span.end)));
+
+        let mut out = String::with_capacity(text.len());
+        let mut copied = 0;
+        let mut group = found.iter().peekable();
+        while let Some((index, span)) = group.next() {
+            let mut end = span.end;
+            while let Some((_, next)) = group.next_if(|(_, next)| next.start < end) {
+                end = end.max(next.end);
+            }
+            out.push_str(&text[copied..span.start]);
+            out.push_str(&self.rules[*index].token(&text[span.start..end]));
+            copied = end;
+        }
+        out.push_str(&text[copied..]);
+
+        let matches = found
+            .into_iter()
+            .map(|(index, span)| RedactionMatch {
+                rule: self.rules[index].name.clone(),
+                start: span.start,
+                end: span.end,
+            })
+            .collect();
+        (out, matches)
+    }
+
+    /// `text` with every match replaced
+    pub fn redact(&self, text: &str) -> String {
+        self.apply(text).0
+    }
+
+    /// Hide the values of fields the rules name and redact every other
+    /// string in `value`
+    pub fn apply_value(&self, value: &mut Value) {
+        match value {
+            Value::String(text) => *text = self.redact(text),
+            Value::Array(items) => items.iter_mut().for_each(|item| self.apply_value(item)),
+            Value::Object(map) => {
+                for (key, item) in map.iter_mut() {
+                    match self.field_token(key, item) {
+                        Some(token) => *item = Value::from(token),
+                        None => self.apply_value(item),
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// The token replacing `value` when it is held by a field named `key`
+    /// that a rule hides; `null` is left alone
+    pub(crate) fn field_token(&self, key: &str, value: &Value) -> Option<String> {
+        if value.is_null() {
+            return None;
+        }
+        let key = key.to_lowercase();
+        let rule = self.rules.iter().find(|rule| match &rule.matcher {
+            Matcher::Fields(fields) => fields.contains(&key),
+            _ => false,
+        })?;
+        Some(match value {
+            Value::String(text) => rule.token(text),
+            other => rule.token(&other.to_string()),
+        })
+    }
+
+    /// Redact the content and tool call arguments of `message`
+    pub fn apply_message(&self, message: &mut ChatMessage) {
+        message.content = self.redact(&message.content);
+        for call in &mut message.tool_calls {
+            self.apply_value(&mut call.arguments);
+        }
+    }
+
+    /// Redact the system prompt and messages of `request`
+    pub fn apply_request(&self, request: &mut ChatRequest) {
+        if let Some(system) = &mut request.system {
+            *system = self.redact(system);
+        }
+        request
+            .messages
+            .iter_mut()
+            .for_each(|message| self.apply_message(message));
+    }
+
+    /// Redact what the model said in `response`, including the raw body
+    pub fn apply_response(&self, response: &mut NormalizedResponse) {
+        response.content = self.redact(&response.content);
+        if let Some(reasoning) = &mut response.reasoning {
+            *reasoning = self.redact(reasoning);
+        }
+        for call in &mut response.tool_calls {
+            self.apply_value(&mut call.arguments);
+        }
+        self.apply_value(&mut response.provider_raw);
+    }
+}
+
+/// Sends requests to its provider redacted, for the `prompts` choke point
+pub struct RedactingProvider {
+    inner: Arc<dyn Provider>,
+    rules: Arc<RedactionRuleSet>,
+}
+
+impl RedactingProvider {
+    pub fn new(inner: Arc<dyn Provider>, rules: Arc<RedactionRuleSet>) -> Self {
+        Self { inner, rules }
+    }
+
+    fn redacted(&self, mut request: ChatRequest) -> ChatRequest {
+        self.rules.apply_request(&mut request);
+        request
+    }
+}
+
+#[async_trait]
+impl Provider for RedactingProvider {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn model(&self) -> &str {
+        self.inner.model()
+    }
+
+    async fn complete(&self, request: ChatRequest) -> Result<NormalizedResponse> {
+        self.inner.complete(self.redacted(request)).await
+    }
+
+    async fn stream(&self, request: ChatRequest) -> Result<ChatStream> {
+        self.inner.stream(self.redacted(request)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn rules(rules: &[RedactionRule]) -> RedactionRuleSet {
+        RedactionRuleSet::compile(rules).unwrap()
+    }
+
+    #[test]
+    fn test_overlapping_matches_are_replaced_once() {
+        let set = rules(&[
+            RedactionRule::pattern("account", r"acct-\d+"),
+            RedactionRule::shape("phone", PiiKind::Phone),
+            RedactionRule::pattern("secret", r"secret \w+"),
+            RedactionRule::pattern("token", r"\w+ token"),
+        ]);
+
+        let (redacted, matches) = set.apply("Bill acct-5551234567 now");
+        assert_eq!(redacted, "Bill [ACCOUNT:medium] now");
+        assert_eq!(
+            matches,
+            [
+                RedactionMatch {
+                    rule: "account".to_string(),
+                    start: 5,
+                    end: 20,
+                },
+                RedactionMatch {
+                    rule: "phone".to_string(),
+                    start: 10,
+                    end: 20,
+                },
+            ]
+        );
+
+        // Partly overlapping matches are replaced as their union
+        let (redacted, matches) = set.apply("a secret shared token b");
+        assert_eq!(redacted, "a [SECRET:medium] b");
+        assert_eq!(matches.len(), 2);
+    }
+
+    #[test]
+    fn test_tokens_preserve_length_class() {
+        let set = rules(&[
+            RedactionRule::pattern("pin", r"\bpin \d+"),
+            RedactionRule::pattern("key", r"key-[a-z0-9]+"),
+            RedactionRule::shape("email", PiiKind::Email).with_replacement("<email>"),
+        ]);
+        let (redacted, _) =
+            set.apply("pin 1234, key-0123456789abcdef0123456789abcdef and jane@example.com");
+        assert_eq!(redacted, "[PIN:medium], [KEY:long] and <email>");
+        assert_eq!(set.redact("pin 12"), "[PIN:short]");
+        assert_eq!(
+            set.apply("nothing here"),
+            ("nothing here".to_string(), vec![])
+        );
+    }
+
+    #[test]
+    fn test_json_fields_are_redacted_structurally() {
+        let set = rules(&[
+            RedactionRule::fields("secret", &["ssn", "password"]),
+            RedactionRule::shape("email", PiiKind::Email),
+        ]);
+        let mut value = json!({
+            "user": {
+                "SSN": "123-45-6789",
+                "Password": 1234,
+                "note": "mail jane@example.com",
+            },
+            "logins": [{"password": null, "at": "2025-03-04"}],
+        });
+        set.apply_value(&mut value);
+        assert_eq!(
+            value,
+            json!({
+                "user": {
+                    "SSN": "[SECRET:medium]",
+                    "Password": "[SECRET:short]",
+                    "note": "mail [EMAIL:medium]",
+                },
+                "logins": [{"password": null, "at": "2025-03-04"}],
+            })
+        );
+        // Field rules don't match text
+        assert_eq!(set.redact("password: hunter2"), "password: hunter2");
+    }
+
+    #[test]
+    fn test_invalid_rules_are_named() {
+        let err = RedactionRuleSet::compile(&[RedactionRule::pattern("broken", "(")]).unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("redaction rule 'broken': invalid pattern"),
+            "{}",
+            err
+        );
+        let both = RedactionRule {
+            shape: Some(PiiKind::Email),
+            ..RedactionRule::pattern("both", "x")
+        };
+        let err = RedactionRuleSet::compile(&[both]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "redaction rule 'both': expected exactly one of pattern, shape or fields"
+        );
+    }
+}
@@ -0,0 +1,398 @@
+//! Interactive agent REPL
+//!
+//! [`Repl`] keeps the session state and interprets one input line at a time,
+//! so tests can drive it from a script and inspect the transcript.
+//! [`Repl::run`] puts a rustyline prompt in front of it (feature `example`).
+
+use anyhow::{Context, Result};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use crate::hooks::{RunEvent, RunHook};
+use crate::provider::{ChatMessage, Role};
+use crate::RigMcpClient;
+
+const HELP: &str = "\
+Commands:
+  /provider [name]  show or switch the active provider
+  /tools            list tools of the connected MCP servers
+  /history          show the conversation so far
+  /save <file>      write the conversation to a JSON file
+  /help             show this help
+Anything else is sent to the agent. Ctrl-D exits.";
+
+/// A parsed input line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Prompt(String),
+    Provider(Option<String>),
+    Tools,
+    History,
+    Save(PathBuf),
+    Help,
+    Empty,
+}
+
+impl Command {
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let line = line.trim();
+        let Some(command) = line.strip_prefix('/') else {
+            return Ok(if line.is_empty() {
+                Command::Empty
+            } else {
+                Command::Prompt(line.to_string())
+            });
+        };
+        let (name, arg) = match command.split_once(char::is_whitespace) {
+            Some((name, arg)) => (name, Some(arg.trim()).filter(|a| !a.is_empty())),
+            None => (command, None),
+        };
+        match (name, arg) {
+            ("provider", arg) => Ok(Command::Provider(arg.map(str::to_string))),
+            ("tools", None) => Ok(Command::Tools),
+            ("history", None) => Ok(Command::History),
+            ("save", Some(path)) => Ok(Command::Save(PathBuf::from(path))),
+            ("save", None) => Err("usage: /save <file>".to_string()),
+            ("help", None) => Ok(Command::Help),
+            ("tools" | "history" | "help", Some(_)) => Err(format!("/{} takes no argument", name)),
+            _ => Err(format!("Unknown command '/{}' (try /help)", name)),
+        }
+    }
+}
+
+/// Output sink that tracks whether streamed text left a line open
+struct Console<W> {
+    out: W,
+    at_line_start: bool,
+}
+
+impl<W: Write> Console<W> {
+    fn text(&mut self, text: &str) {
+        let _ = write!(self.out, "{}", text);
+        let _ = self.out.flush();
+        self.at_line_start = text.ends_with('\n');
+    }
+
+    fn end_line(&mut self) {
+        if !self.at_line_start {
+            let _ = writeln!(self.out);
+            self.at_line_start = true;
+        }
+    }
+
+    fn line(&mut self, line: &str) {
+        self.end_line();
+        let _ = writeln!(self.out, "{}", line);
+    }
+}
+
+/// Prints run events to the console as they happen
+struct EchoHook<W>(Arc<Mutex<Console<W>>>);
+
+impl<W: Write + Send> RunHook for EchoHook<W> {
+    fn on_event(&self, event: &RunEvent<'_>) {
+        let mut console = self.0.lock().unwrap();
+        match event {
+            RunEvent::Text(text) => console.text(text),
+            RunEvent::ToolCall(call) => {
+                console.line(&format!("🔧 {}({})", call.name, call.arguments))
+            }
+            RunEvent::ToolDecision { decision, .. } if !decision.allows() => {
+                console.line("   🚫 denied by policy")
+            }
+            RunEvent::ToolProgress { progress, .. } => {
+                let percent = progress
+                    .percent()
+                    .map(|percent| format!("{:.0}% complete", percent))
+                    .unwrap_or_else(|| format!("{} done", progress.progress));
+                match &progress.message {
+                    Some(message) => console.line(&format!("   ⏳ {} ({})", percent, message)),
+                    None => console.line(&format!("   ⏳ {}", percent)),
+                }
+            }
+            RunEvent::ToolResult { output, .. } => {
+                let marker = if output.is_error { "❌" } else { "↳" };
+                console.line(&format!("   {} {}", marker, output.content))
+            }
+            RunEvent::Injection { action, .. } => {
+                console.line(&format!("   ⚠ possible prompt injection ({:?})", action))
+            }
+            RunEvent::ToolDecision { .. } | RunEvent::Usage(_) => {}
+            RunEvent::Finished => console.end_line(),
+        }
+    }
+}
+
+/// REPL session over a [`RigMcpClient`]
+pub struct Repl<W> {
+    client: Arc<RigMcpClient>,
+    provider: String,
+    history: Vec<ChatMessage>,
+    console: Arc<Mutex<Console<W>>>,
+}
+
+impl<W: Write + Send + 'static> Repl<W> {
+    pub fn new(client: Arc<RigMcpClient>, provider: impl Into<String>, out: W) -> Self {
+        Self {
+            client,
+            provider: provider.into(),
+            history: Vec::new(),
+            console: Arc::new(Mutex::new(Console {
+                out,
+                at_line_start: true,
+            })),
+        }
+    }
+
+    /// Active provider name
+    pub fn provider(&self) -> &str {
+        &self.provider
+    }
+
+    /// Conversation so far
+    pub fn history(&self) -> &[ChatMessage] {
+        &self.history
+    }
+
+    /// Inspect the output sink, e.g. to read back a transcript
+    pub fn with_output<R>(&self, f: impl FnOnce(&W) -> R) -> R {
+        f(&self.console.lock().unwrap().out)
+    }
+
+    /// Feed lines as if they were typed, echoing each after a prompt marker
+    pub async fn run_script<I>(&mut self, lines: I)
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        for line in lines {
+            let line = line.as_ref();
+            self.say(&format!("{}> {}", self.provider, line));
+            self.handle_line(line).await;
+        }
+    }
+
+    /// Interpret one input line; errors are printed, not returned
+    pub async fn handle_line(&mut self, line: &str) {
+        let command = match Command::parse(line) {
+            Ok(command) => command,
+            Err(message) => return self.say(&message),
+        };
+        if let Err(err) = self.execute(command).await {
+            self.say(&format!("error: {:#}", err));
+        }
+    }
+
+    async fn execute(&mut self, command: Command) -> Result<()> {
+        match command {
+            Command::Empty => {}
+            Command::Help => self.say(HELP),
+            Command::Provider(None) => {
+                let names = self.client.provider_names().await;
+                self.say(&format!(
+                    "Active provider: {} (available: {})",
+                    self.provider,
+                    names.join(", ")
+                ));
+            }
+            Command::Provider(Some(name)) => {
+                let names = self.client.provider_names().await;
+                if names.contains(&name) || self.client.resolve_alias(&name).is_ok() {
+                    self.say(&format!("Switched to provider '{}'", name));
+                    self.provider = name;
+                } else {
+                    self.say(&format!(
+                        "Unknown provider '{}' (available: {})",
+                        name,
+                        names.join(", ")
+                    ));
+                }
+            }
+            Command::Tools => {
+                let tools = self.client.tools().await?;
+                if tools.is_empty() {
+                    self.say("No tools available");
+                }
+                for tool in tools {
+                    self.say(&format!(
+                        "{}/{} — {}",
+                        tool.server, tool.name, tool.description
+                    ));
+                }
+            }
+            Command::History => {
+                if self.history.is_empty() {
+                    self.say("History is empty");
+                }
+                let lines: Vec<String> = self.history.iter().flat_map(history_lines).collect();
+                for line in lines {
+                    self.say(&line);
+                }
+            }
+            Command::Save(path) => {
+                let json = serde_json::to_string_pretty(&self.history)?;
+                std::fs::write(&path, json)
+                    .with_context(|| format!("Failed to write '{}'", path.display()))?;
+                self.say(&format!(
+                    "Saved {} messages to {}",
+                    self.history.len(),
+                    path.display()
+                ));
+            }
+            Command::Prompt(prompt) => {
+                let agent = self
+                    .client
+                    .agent(&self.provider)
+                    .await?
+                    .hook(Arc::new(EchoHook(self.console.clone())))
+                    .build();
+                agent.chat(&mut self.history, &prompt).await?;
+            }
+        }
+        Ok(())
+    }
+
+    fn say(&self, line: &str) {
+        self.console.lock().unwrap().line(line);
+    }
+}
+
+#[cfg(feature = "example")]
+impl<W: Write + Send + 'static> Repl<W> {
+    /// Read prompts from the terminal until Ctrl-D
+    pub async fn run(&mut self) -> Result<()> {
+        use rustyline::error::ReadlineError;
+
+        let mut editor = rustyline::DefaultEditor::new()?;
+        self.say("Type /help for commands, Ctrl-D to exit.");
+        loop {
+            let prompt = format!("{}> ", self.provider);
+            match tokio::task::block_in_place(|| editor.readline(&prompt)) {
+                Ok(line) => {
+                    let _ = editor.add_history_entry(line.as_str());
+                    self.handle_line(&line).await;
+                }
+                Err(ReadlineError::Interrupted) => continue,
+                Err(ReadlineError::Eof) => break,
+                Err(err) => return Err(err.into()),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Lines shown by `/history` for one message
+fn history_lines(message: &ChatMessage) -> Vec<String> {
+    let role = match message.role {
+        Role::System => "system",
+        Role::User => "user",
+        Role::Assistant => "assistant",
+        Role::Tool => "tool",
+    };
+    let mut lines = Vec::new();
+    if !message.content.is_empty() {
+        lines.push(format!("[{}] {}", role, message.content));
+    }
+    for call in &message.tool_calls {
+        lines.push(format!("[{}] 🔧 {}({})", role, call.name, call.arguments));
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{mock_client, MockProvider};
+    use crate::Config;
+
+    fn transcript(repl: &Repl<Vec<u8>>) -> String {
+        repl.with_output(|out| String::from_utf8_lossy(out).into_owned())
+    }
+
+    #[test]
+    fn test_parse_commands() {
+        assert_eq!(Command::parse("  "), Ok(Command::Empty));
+        assert_eq!(
+            Command::parse("hello /tools"),
+            Ok(Command::Prompt("hello /tools".to_string()))
+        );
+        assert_eq!(Command::parse("/provider"), Ok(Command::Provider(None)));
+        assert_eq!(
+            Command::parse("/provider  openai "),
+            Ok(Command::Provider(Some("openai".to_string())))
+        );
+        assert_eq!(
+            Command::parse("/save out.json"),
+            Ok(Command::Save(PathBuf::from("out.json")))
+        );
+        assert!(Command::parse("/save").is_err());
+        assert!(Command::parse("/tools now").is_err());
+        assert_eq!(
+            Command::parse("/quit"),
+            Err("Unknown command '/quit' (try /help)".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_scripted_session_transcript() {
+        let mut repl = Repl::new(Arc::new(mock_client()), "mock", Vec::new());
+        repl.run_script(["/tools", "please echo hello", "/provider nope", "/history"])
+            .await;
+
+        let expected = "\
+mock> /tools
+demo/echo — Echo the given text back
+demo/word_count — Count the words in a text
+mock> please echo hello
+🔧 echo({\"text\":\"please echo hello\"})
+   ↳ please echo hello
+Tool `echo` returned: please echo hello
+mock> /provider nope
+Unknown provider 'nope' (available: mock)
+mock> /history
+[user] please echo hello
+[assistant] 🔧 echo({\"text\":\"please echo hello\"})
+[tool] please echo hello
+[assistant] Tool `echo` returned: please echo hello
+";
+        assert_eq!(transcript(&repl), expected);
+    }
+
+    #[tokio::test]
+    async fn test_switch_provider_and_save() {
+        let client = RigMcpClient::from_parts(
+            Config::default(),
+            vec![
+                Arc::new(MockProvider::new("first")),
+                Arc::new(MockProvider::new("second")),
+            ],
+            vec![],
+        );
+        let path = std::env::temp_dir().join(format!("rig-mcp-repl-{}.json", std::process::id()));
+        let mut repl = Repl::new(Arc::new(client), "first", Vec::new());
+        repl.run_script(["/provider second", "hi there", "/tools"])
+            .await;
+        repl.handle_line(&format!("/save {}", path.display())).await;
+
+        assert_eq!(repl.provider(), "second");
+        let text = transcript(&repl);
+        assert!(text.contains("Switched to provider 'second'"));
+        assert!(text.contains("You said: hi there"));
+        assert!(text.contains("No tools available"));
+        assert!(text.contains("Saved 2 messages to"));
+
+        let saved: Vec<ChatMessage> =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(saved, repl.history());
+    }
+
+    #[tokio::test]
+    async fn test_prompt_errors_are_reported() {
+        let client = RigMcpClient::from_parts(Config::default(), vec![], vec![]);
+        let mut repl = Repl::new(Arc::new(client), "missing", Vec::new());
+        repl.handle_line("hello").await;
+        assert!(transcript(&repl).starts_with("error: Provider 'missing' not found"));
+    }
+}
@@ -0,0 +1,65 @@
+//! Second-stage ranking
+//!
+//! Cosine similarity between a query and a short tool description is a rough
+//! signal. A [`Reranker`] reads the query together with each candidate and
+//! scores how relevant it is, which orders the candidates the embedding
+//! search retrieved much better. Reranking is best effort: callers keep the
+//! embedding order when it fails.
+
+use anyhow::Result;
+use async_trait::async_trait;
+
+/// Embedding-ranked candidates handed to the reranker per result wanted
+pub const DEFAULT_CANDIDATE_FACTOR: usize = 3;
+
+/// A model scoring documents against a query
+#[async_trait]
+pub trait Reranker: Send + Sync {
+    fn provider(&self) -> &str;
+
+    fn model(&self) -> &str;
+
+    /// The `top_n` most relevant of `documents`, best first
+    async fn rerank(
+        &self, query: &str, documents: &[String], top_n: usize,
+    ) -> Result<Vec<Relevance>>;
+}
+
+/// How relevant one document is to a query
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Relevance {
+    /// Position of the document among those that were reranked
+    pub index: usize,
+    /// Between 0 and 1
+    pub score: f32,
+}
+
+/// Reorder `candidates` by their relevance to `query`, keeping the `top_k`
+/// best with their relevance
+///
+/// Each candidate comes with the text the reranker reads. Fails if the
+/// reranker does, or answers with an index that isn't a candidate.
+pub async fn rerank<T>(
+    reranker: &dyn Reranker, query: &str, candidates: Vec<(T, String)>, top_k: usize,
+) -> Result<Vec<(T, f32)>> {
+    let (items, documents): (Vec<T>, Vec<String>) = candidates.into_iter().unzip();
+    let ranking = reranker.rerank(query, &documents, top_k).await?;
+    let mut items: Vec<Option<T>> = items.into_iter().map(Some).collect();
+    let mut reranked = Vec::with_capacity(ranking.len().min(top_k));
+    for relevance in ranking.into_iter().take(top_k) {
+        let item = items
+            .get_mut(relevance.index)
+            .and_then(Option::take)
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Reranker {}/{} returned index {} for {} documents",
+                    reranker.provider(),
+                    reranker.model(),
+                    relevance.index,
+                    documents.len()
+                )
+            })?;
+        reranked.push((item, relevance.score));
+    }
+    Ok(reranked)
+}
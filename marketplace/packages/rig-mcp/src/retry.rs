@@ -0,0 +1,304 @@
+//! Timeouts and retries of provider requests
+//!
+//! A provider with `timeout_ms` abandons a request that hasn't been answered
+//! in time, so a hung server fails the request instead of stalling the
+//! agent. With `max_retries`, requests failing with a retriable error (see
+//! [`crate::transport::is_retriable`]), timeouts included, are sent again:
+//!
+//! ```toml
+//! [[providers]]
+//! name = "ollama"
+//! model = "llama3.1"
+//! timeout_ms = 30000
+//! max_retries = 2
+//! retry_backoff_ms = 500
+//! ```
+//!
+//! Retry `n` waits `retry_backoff_ms` times 2^(n-1), at most
+//! [`MAX_BACKOFF`], with half of it jittered, or as long as the provider's
+//! `Retry-After` asked for. The jitter is seeded with `deterministic.seed`
+//! when it is set. When the last attempt fails too, its error says how many
+//! attempts were made.
+//!
+//! Only completions are retried. Tool calls are run by the agent, never by
+//! a provider, so a retried completion can't run a tool twice. Streams are
+//! retried until they open; once chunks arrive, a failure is the caller's.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::deterministic::SplitMix64;
+use crate::provider::{ChatRequest, ChatStream, NormalizedResponse, Provider};
+use crate::transport::{self, HttpStatusError, Unreachable};
+use crate::ProviderConfig;
+
+/// Longest wait between two attempts, unless `Retry-After` asks for more
+pub const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Backoff before the first retry when `retry_backoff_ms` isn't set
+const DEFAULT_BACKOFF: Duration = Duration::from_millis(500);
+
+/// How long a provider's requests may take and how often they are retried
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Per attempt
+    pub timeout: Option<Duration>,
+    pub max_retries: u32,
+    /// Before the first retry, doubling with every one after it
+    pub backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// The policy `config` asks for; `None` without a timeout or retries
+    pub fn from_config(config: &ProviderConfig) -> Option<Self> {
+        if config.timeout_ms.is_none() && config.max_retries == 0 {
+            return None;
+        }
+        Some(Self {
+            timeout: config.timeout_ms.map(Duration::from_millis),
+            max_retries: config.max_retries,
+            backoff: config
+                .retry_backoff_ms
+                .map_or(DEFAULT_BACKOFF, Duration::from_millis),
+        })
+    }
+
+    /// Wait before retry `retry`, counting from 1: the exponential backoff,
+    /// its upper half drawn from `rng`
+    fn delay(&self, retry: u32, rng: &mut SplitMix64) -> Duration {
+        let factor = 1u32
+            .checked_shl(retry.saturating_sub(1))
+            .unwrap_or(u32::MAX);
+        let backoff = self.backoff.saturating_mul(factor).min(MAX_BACKOFF);
+        backoff / 2 + (backoff / 2).mul_f64(rng.next_f64())
+    }
+}
+
+/// [`Provider`] enforcing a [`RetryPolicy`]; see the module docs
+pub struct RetryingProvider {
+    inner: Arc<dyn Provider>,
+    policy: RetryPolicy,
+    rng: Mutex<SplitMix64>,
+}
+
+impl RetryingProvider {
+    pub fn new(inner: Arc<dyn Provider>, policy: RetryPolicy) -> Self {
+        Self {
+            inner,
+            policy,
+            rng: Mutex::new(SplitMix64::from_time()),
+        }
+    }
+
+    /// Draw the jitter from a generator seeded with `seed`
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = Mutex::new(SplitMix64::new(seed));
+        self
+    }
+
+    /// Run `call` within the timeout, again after each retriable failure
+    /// while retries are left
+    async fn attempt<T, F, Fut>(&self, call: F) -> Result<T>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut attempts = 1;
+        loop {
+            let result = match self.policy.timeout {
+                Some(timeout) => match tokio::time::timeout(timeout, call()).await {
+                    Ok(result) => result,
+                    Err(_) => Err(anyhow::anyhow!("No answer within {:?}", timeout).context(
+                        Unreachable {
+                            service: format!("Provider '{}'", self.inner.name()),
+                        },
+                    )),
+                },
+                None => call().await,
+            };
+            let err = match result {
+                Ok(value) => return Ok(value),
+                Err(err) => err,
+            };
+            let retriable = transport::is_retriable(&err);
+            if !retriable || attempts > self.policy.max_retries {
+                return Err(if attempts > 1 {
+                    err.context(format!(
+                        "Provider '{}' failed after {} attempts",
+                        self.inner.name(),
+                        attempts
+                    ))
+                } else {
+                    err
+                });
+            }
+            let retry_after = err
+                .downcast_ref::<HttpStatusError>()
+                .and_then(|err| err.rate_limits.as_ref())
+                .and_then(|limits| limits.retry_after);
+            let delay = match retry_after {
+                Some(retry_after) => retry_after,
+                None => self.policy.delay(attempts, &mut self.rng.lock().unwrap()),
+            };
+            tracing::debug!(
+                provider = %self.inner.name(),
+                attempt = attempts,
+                delay_ms = delay.as_millis() as u64,
+                error = %err,
+                "Retrying provider request"
+            );
+            tokio::time::sleep(delay).await;
+            attempts += 1;
+        }
+    }
+}
+
+#[async_trait]
+impl Provider for RetryingProvider {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn model(&self) -> &str {
+        self.inner.model()
+    }
+
+    async fn complete(&self, request: ChatRequest) -> Result<NormalizedResponse> {
+        self.attempt(|| self.inner.complete(request.clone())).await
+    }
+
+    async fn stream(&self, request: ChatRequest) -> Result<ChatStream> {
+        self.attempt(|| self.inner.stream(request.clone())).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::ChatMessage;
+    use crate::testing::{Fault, FaultPlan, MockProvider};
+    use crate::transport::RateLimits;
+    use std::collections::VecDeque;
+    use tokio::time::Instant;
+
+    fn policy(timeout_ms: Option<u64>, max_retries: u32) -> RetryPolicy {
+        RetryPolicy {
+            timeout: timeout_ms.map(Duration::from_millis),
+            max_retries,
+            backoff: Duration::from_millis(100),
+        }
+    }
+
+    fn mock(faults: Vec<Fault>) -> Arc<MockProvider> {
+        Arc::new(MockProvider::new("ollama").with_faults(FaultPlan::script(faults)))
+    }
+
+    fn request() -> ChatRequest {
+        ChatRequest {
+            messages: vec![ChatMessage::user("Hello")],
+            ..ChatRequest::default()
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_retriable_errors_are_retried_with_jittered_backoff() {
+        let inner = mock(vec![Fault::FailWithStatus(503), Fault::Disconnect]);
+        let provider = RetryingProvider::new(inner.clone(), policy(None, 2)).with_seed(7);
+        let start = Instant::now();
+
+        let response = provider.complete(request()).await.unwrap();
+        assert_eq!(response.content, "You said: Hello");
+        assert_eq!(inner.requests().len(), 3);
+        // 50-100ms before the first retry, 100-200ms before the second
+        let waited = start.elapsed();
+        assert!(
+            waited >= Duration::from_millis(150) && waited <= Duration::from_millis(300),
+            "{:?}",
+            waited
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_hung_requests_time_out_and_report_their_attempts() {
+        let inner = mock(vec![Fault::Hang, Fault::Hang]);
+        let provider = RetryingProvider::new(inner.clone(), policy(Some(1000), 1));
+        let start = Instant::now();
+
+        let err = provider.complete(request()).await.unwrap_err();
+        assert!(start.elapsed() >= Duration::from_secs(2));
+        assert_eq!(inner.requests().len(), 2);
+        assert_eq!(err.to_string(), "Provider 'ollama' failed after 2 attempts");
+        assert!(
+            format!("{:#}", err).contains("No answer within 1s"),
+            "{:#}",
+            err
+        );
+        // so fallbacks and groups still move on
+        assert!(transport::is_retriable(&err));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_invalid_requests_are_not_retried() {
+        let inner = mock(vec![Fault::FailWithStatus(400)]);
+        let provider = RetryingProvider::new(inner.clone(), policy(None, 3));
+
+        let err = provider.complete(request()).await.unwrap_err();
+        assert!(err.to_string().contains("HTTP 400"), "{}", err);
+        assert_eq!(inner.requests().len(), 1);
+    }
+
+    /// Answers with a queue of results
+    struct Scripted(Mutex<VecDeque<Result<NormalizedResponse>>>);
+
+    #[async_trait]
+    impl Provider for Scripted {
+        fn name(&self) -> &str {
+            "groq"
+        }
+
+        fn model(&self) -> &str {
+            "llama-3.3-70b-versatile"
+        }
+
+        async fn complete(&self, _: ChatRequest) -> Result<NormalizedResponse> {
+            self.0.lock().unwrap().pop_front().unwrap()
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_retry_after_is_honored() {
+        let rate_limited = HttpStatusError {
+            service: "Provider 'groq'".to_string(),
+            status: 429,
+            body: "Rate limit reached".to_string(),
+            rate_limits: Some(RateLimits {
+                retry_after: Some(Duration::from_secs(7)),
+                ..RateLimits::default()
+            }),
+        };
+        let inner = Scripted(Mutex::new(VecDeque::from([
+            Err(rate_limited.into()),
+            Ok(NormalizedResponse::text("pong")),
+        ])));
+        let provider = RetryingProvider::new(Arc::new(inner), policy(None, 1));
+        let start = Instant::now();
+
+        let response = provider.complete(request()).await.unwrap();
+        assert_eq!(response.content, "pong");
+        assert_eq!(start.elapsed(), Duration::from_secs(7));
+    }
+
+    #[test]
+    fn test_backoff_doubles_up_to_the_maximum() {
+        let policy = policy(None, 10);
+        let mut rng = SplitMix64::new(1);
+        for (retry, max_ms) in [(1, 100), (2, 200), (3, 400), (12, 30_000), (40, 30_000)] {
+            let delay = policy.delay(retry, &mut rng);
+            let max = Duration::from_millis(max_ms);
+            assert!(delay >= max / 2 && delay <= max, "{}: {:?}", retry, delay);
+        }
+    }
+}
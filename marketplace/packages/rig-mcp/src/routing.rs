@@ -0,0 +1,616 @@
+//! Provider statistics and adaptive routing
+//!
+//! Every provider of a client reports the outcome and latency of its
+//! completions to the client's [`ProviderStats`], which keeps those of the
+//! last [`ProviderStats::window`] per `provider/model`.
+//! [`crate::RigMcpClient::provider_status`] reports each provider's success
+//! rate, p95 latency and breaker. A breaker opens after a number of failures
+//! in a row, half-opens once it has been open for a while and closes with
+//! the next success.
+//!
+//! Provider groups are configured by name in the `provider_groups` section
+//! and used like a provider:
+//!
+//! ```toml
+//! [provider_groups.chat]
+//! targets = ["openai/gpt-4o-mini", "groq/llama-3.3-70b-versatile"]
+//! strategy = "adaptive"
+//! exploration = 0.1
+//! weights = { success_rate = 1.0, latency = 0.5 }
+//! ```
+//!
+//! An `ordered` group, the default, tries its targets in the order listed
+//! and falls over to the next one when a target fails with a retriable
+//! error. An `adaptive` group tries them best score first, where the score
+//! rewards the recent success rate and penalizes p95 latency relative to the
+//! slowest target; targets with an open breaker come last. With probability
+//! `exploration` a request starts with another target whose breaker isn't
+//! open instead, so a recovering provider gets probed. The draws are seeded
+//! with `deterministic.seed` when it is set. Every decision is logged at
+//! debug level with the scores it was based on.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::Instant;
+
+use crate::bench::Stats;
+use crate::deterministic::SplitMix64;
+use crate::provider::{ChatRequest, ChatStream, NormalizedResponse, Provider};
+use crate::transport;
+
+/// One entry of the `provider_groups` section of [`crate::Config`]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ProviderGroupConfig {
+    /// Provider names, model aliases or `provider/model` pairs
+    pub targets: Vec<String>,
+    #[serde(default)]
+    pub strategy: RoutingStrategy,
+    /// How `adaptive` groups score their targets
+    #[serde(default)]
+    pub weights: RoutingWeights,
+    /// Share of `adaptive` requests sent to a target other than the best
+    #[serde(default = "default_exploration")]
+    pub exploration: f64,
+}
+
+fn default_exploration() -> f64 {
+    0.1
+}
+
+/// How a provider group picks its target
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RoutingStrategy {
+    /// In the order listed
+    #[default]
+    Ordered,
+    /// Best recent success rate and latency first
+    Adaptive,
+}
+
+/// Weights of an `adaptive` group's score
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RoutingWeights {
+    /// Reward for the share of recent completions that succeeded
+    #[serde(default = "default_success_weight")]
+    pub success_rate: f64,
+    /// Penalty for p95 latency, relative to the slowest target
+    #[serde(default = "default_latency_weight")]
+    pub latency: f64,
+}
+
+fn default_success_weight() -> f64 {
+    1.0
+}
+
+fn default_latency_weight() -> f64 {
+    0.5
+}
+
+impl Default for RoutingWeights {
+    fn default() -> Self {
+        Self {
+            success_rate: default_success_weight(),
+            latency: default_latency_weight(),
+        }
+    }
+}
+
+/// State of a provider's circuit breaker
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakerState {
+    Closed,
+    /// Failed too often in a row; adaptive groups only try it as a last
+    /// resort
+    Open,
+    /// Was open long enough to be probed again
+    HalfOpen,
+}
+
+impl fmt::Display for BreakerState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Self::Closed => "closed",
+            Self::Open => "open",
+            Self::HalfOpen => "half_open",
+        })
+    }
+}
+
+/// Recent completions of one provider, as reported by
+/// [`crate::RigMcpClient::provider_status`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProviderStatus {
+    /// `provider/model`
+    pub provider: String,
+    /// Completions within the window
+    pub requests: usize,
+    pub failures: usize,
+    /// `None` without completions in the window
+    pub success_rate: Option<f64>,
+    /// Of the completions that succeeded
+    pub p95_latency_ms: Option<f64>,
+    pub breaker: BreakerState,
+}
+
+/// Sliding-window statistics of every provider of a client
+#[derive(Debug)]
+pub struct ProviderStats {
+    window: Duration,
+    failure_threshold: u32,
+    cooldown: Duration,
+    providers: Mutex<BTreeMap<String, Window>>,
+}
+
+#[derive(Debug, Default)]
+struct Window {
+    samples: VecDeque<Sample>,
+    consecutive_failures: u32,
+    last_failure: Option<Instant>,
+}
+
+#[derive(Debug)]
+struct Sample {
+    at: Instant,
+    ok: bool,
+    latency: Duration,
+}
+
+impl Default for ProviderStats {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(60), 3, Duration::from_secs(30))
+    }
+}
+
+impl ProviderStats {
+    /// Keep completions for `window`; a breaker opens after
+    /// `failure_threshold` failures in a row and half-opens after `cooldown`
+    pub fn new(window: Duration, failure_threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            window,
+            failure_threshold: failure_threshold.max(1),
+            cooldown,
+            providers: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// Count a completion of `provider` that took `latency`
+    pub fn record(&self, provider: &str, ok: bool, latency: Duration) {
+        let now = Instant::now();
+        let mut providers = self.providers.lock().unwrap();
+        let window = providers.entry(provider.to_string()).or_default();
+        window.samples.push_back(Sample {
+            at: now,
+            ok,
+            latency,
+        });
+        if ok {
+            window.consecutive_failures = 0;
+        } else {
+            window.consecutive_failures += 1;
+            window.last_failure = Some(now);
+        }
+        self.prune(window, now);
+    }
+
+    /// Status of `provider`; closed and without completions if it hasn't
+    /// completed anything yet
+    pub fn status(&self, provider: &str) -> ProviderStatus {
+        let now = Instant::now();
+        let mut providers = self.providers.lock().unwrap();
+        match providers.get_mut(provider) {
+            Some(window) => self.status_of(provider, window, now),
+            None => self.status_of(provider, &mut Window::default(), now),
+        }
+    }
+
+    /// Status of every provider that completed something, by name
+    pub fn all(&self) -> Vec<ProviderStatus> {
+        let now = Instant::now();
+        let mut providers = self.providers.lock().unwrap();
+        providers
+            .iter_mut()
+            .map(|(provider, window)| self.status_of(provider, window, now))
+            .collect()
+    }
+
+    fn prune(&self, window: &mut Window, now: Instant) {
+        while window
+            .samples
+            .front()
+            .is_some_and(|sample| now.duration_since(sample.at) > self.window)
+        {
+            window.samples.pop_front();
+        }
+    }
+
+    fn status_of(&self, provider: &str, window: &mut Window, now: Instant) -> ProviderStatus {
+        self.prune(window, now);
+        let failures = window.samples.iter().filter(|sample| !sample.ok).count();
+        let requests = window.samples.len();
+        let latencies: Vec<f64> = window
+            .samples
+            .iter()
+            .filter(|sample| sample.ok)
+            .map(|sample| sample.latency.as_micros() as f64 / 1000.0)
+            .collect();
+        let breaker = match window.last_failure {
+            Some(_) if window.consecutive_failures < self.failure_threshold => BreakerState::Closed,
+            Some(at) if now.duration_since(at) < self.cooldown => BreakerState::Open,
+            Some(_) => BreakerState::HalfOpen,
+            None => BreakerState::Closed,
+        };
+        ProviderStatus {
+            provider: provider.to_string(),
+            requests,
+            failures,
+            success_rate: (requests > 0).then(|| (requests - failures) as f64 / requests as f64),
+            p95_latency_ms: Stats::of(&latencies).map(|stats| stats.p95),
+            breaker,
+        }
+    }
+}
+
+/// Key of `provider` in [`ProviderStats`]
+pub fn stats_key(provider: &dyn Provider) -> String {
+    format!("{}/{}", provider.name(), provider.model())
+}
+
+/// [`Provider`] reporting its completions to [`ProviderStats`]
+///
+/// Streams count when they open; what happens after that isn't seen.
+pub struct Monitored {
+    inner: Arc<dyn Provider>,
+    stats: Arc<ProviderStats>,
+    key: String,
+}
+
+impl Monitored {
+    pub fn new(inner: Arc<dyn Provider>, stats: Arc<ProviderStats>) -> Self {
+        let key = stats_key(inner.as_ref());
+        Self { inner, stats, key }
+    }
+}
+
+#[async_trait]
+impl Provider for Monitored {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn model(&self) -> &str {
+        self.inner.model()
+    }
+
+    async fn complete(&self, request: ChatRequest) -> Result<NormalizedResponse> {
+        let start = Instant::now();
+        let result = self.inner.complete(request).await;
+        self.stats
+            .record(&self.key, result.is_ok(), start.elapsed());
+        result
+    }
+
+    async fn stream(&self, request: ChatRequest) -> Result<ChatStream> {
+        let start = Instant::now();
+        let result = self.inner.stream(request).await;
+        self.stats
+            .record(&self.key, result.is_ok(), start.elapsed());
+        result
+    }
+}
+
+/// Targets used like one provider; see the module docs
+pub struct ProviderGroup {
+    name: String,
+    targets: Vec<Arc<dyn Provider>>,
+    strategy: RoutingStrategy,
+    weights: RoutingWeights,
+    exploration: f64,
+    stats: Arc<ProviderStats>,
+    rng: Mutex<SplitMix64>,
+}
+
+/// A target's score, for the decision log
+struct Scored {
+    target: usize,
+    score: f64,
+    breaker: BreakerState,
+}
+
+impl ProviderGroup {
+    /// Route between `targets`, which should report to `stats`
+    pub fn new(
+        name: impl Into<String>, config: &ProviderGroupConfig, targets: Vec<Arc<dyn Provider>>,
+        stats: Arc<ProviderStats>,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            targets,
+            strategy: config.strategy,
+            weights: config.weights,
+            exploration: config.exploration.clamp(0.0, 1.0),
+            stats,
+            rng: Mutex::new(SplitMix64::from_time()),
+        }
+    }
+
+    /// Draw exploration from a generator seeded with `seed`
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = Mutex::new(SplitMix64::new(seed));
+        self
+    }
+
+    /// Targets in the order to try them
+    fn route(&self) -> Vec<usize> {
+        if self.strategy == RoutingStrategy::Ordered {
+            return (0..self.targets.len()).collect();
+        }
+        let statuses: Vec<ProviderStatus> = self
+            .targets
+            .iter()
+            .map(|target| self.stats.status(&stats_key(target.as_ref())))
+            .collect();
+        let slowest = statuses
+            .iter()
+            .filter_map(|status| status.p95_latency_ms)
+            .fold(0.0, f64::max);
+        let mut scored: Vec<Scored> = statuses
+            .iter()
+            .enumerate()
+            .map(|(target, status)| {
+                // Untried targets look perfect, so they get tried
+                let latency = match status.p95_latency_ms {
+                    Some(p95) if slowest > 0.0 => p95 / slowest,
+                    _ => 0.0,
+                };
+                Scored {
+                    target,
+                    score: self.weights.success_rate * status.success_rate.unwrap_or(1.0)
+                        - self.weights.latency * latency,
+                    breaker: status.breaker,
+                }
+            })
+            .collect();
+        scored.sort_by(|a, b| {
+            (a.breaker == BreakerState::Open)
+                .cmp(&(b.breaker == BreakerState::Open))
+                .then(b.score.total_cmp(&a.score))
+        });
+
+        let mut order: Vec<usize> = scored.iter().map(|scored| scored.target).collect();
+        let mut explored = false;
+        let others: Vec<usize> = (1..scored.len())
+            .filter(|&position| scored[position].breaker != BreakerState::Open)
+            .collect();
+        let mut rng = self.rng.lock().unwrap();
+        if !others.is_empty() && rng.next_f64() < self.exploration {
+            let position = others[(rng.next_u64() % others.len() as u64) as usize];
+            let target = order.remove(position);
+            order.insert(0, target);
+            explored = true;
+        }
+        drop(rng);
+
+        let scores: Vec<String> = scored
+            .iter()
+            .map(|scored| {
+                format!(
+                    "{}={:.3} ({})",
+                    stats_key(self.targets[scored.target].as_ref()),
+                    scored.score,
+                    scored.breaker
+                )
+            })
+            .collect();
+        tracing::debug!(
+            group = %self.name,
+            chosen = %stats_key(self.targets[order[0]].as_ref()),
+            explored,
+            scores = %scores.join(", "),
+            "Routed request"
+        );
+        order
+    }
+
+    /// Try the targets in `order` with `call` until one succeeds or fails
+    /// with an error another target wouldn't have
+    async fn try_in_order<T, F, Fut>(&self, order: Vec<usize>, call: F) -> Result<T>
+    where
+        F: Fn(Arc<dyn Provider>) -> Fut,
+        Fut: std::future::Future<Output = Result<T>>,
+    {
+        let mut last_error = None;
+        for target in order {
+            let provider = self.targets[target].clone();
+            match call(provider.clone()).await {
+                Ok(value) => return Ok(value),
+                Err(err) if transport::is_retriable(&err) => {
+                    tracing::debug!(
+                        group = %self.name,
+                        target = %stats_key(provider.as_ref()),
+                        error = %err,
+                        "Group target failed, trying the next one"
+                    );
+                    last_error = Some(err);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        Err(match last_error {
+            Some(err) => err.context(format!("Every target of group '{}' failed", self.name)),
+            None => anyhow::anyhow!("Group '{}' has no targets", self.name),
+        })
+    }
+}
+
+#[async_trait]
+impl Provider for ProviderGroup {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Model of the first target
+    fn model(&self) -> &str {
+        self.targets.first().map_or("", |provider| provider.model())
+    }
+
+    async fn complete(&self, request: ChatRequest) -> Result<NormalizedResponse> {
+        self.try_in_order(self.route(), |provider| {
+            let request = request.clone();
+            async move { provider.complete(request).await }
+        })
+        .await
+    }
+
+    async fn stream(&self, request: ChatRequest) -> Result<ChatStream> {
+        self.try_in_order(self.route(), |provider| {
+            let request = request.clone();
+            async move { provider.stream(request).await }
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::ChatMessage;
+    use crate::testing::{Fault, FaultPlan, Latency, MockProvider};
+
+    fn mock(name: &str, millis: u64, plan: FaultPlan) -> Arc<MockProvider> {
+        Arc::new(
+            MockProvider::new(name)
+                .with_faults(plan.latency(Latency::Fixed(Duration::from_millis(millis)))),
+        )
+    }
+
+    fn request() -> ChatRequest {
+        ChatRequest {
+            messages: vec![ChatMessage::user("Hello")],
+            ..ChatRequest::default()
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_breaker_opens_half_opens_and_closes() {
+        let stats = ProviderStats::new(Duration::from_secs(60), 2, Duration::from_secs(30));
+        stats.record("a/m", true, Duration::from_millis(100));
+        stats.record("a/m", false, Duration::from_millis(5));
+        assert_eq!(stats.status("a/m").breaker, BreakerState::Closed);
+        stats.record("a/m", false, Duration::from_millis(5));
+        let status = stats.status("a/m");
+        assert_eq!(status.breaker, BreakerState::Open);
+        assert_eq!((status.requests, status.failures), (3, 2));
+        assert_eq!(status.success_rate, Some(1.0 / 3.0));
+        assert_eq!(status.p95_latency_ms, Some(100.0));
+
+        tokio::time::advance(Duration::from_secs(30)).await;
+        assert_eq!(stats.status("a/m").breaker, BreakerState::HalfOpen);
+        stats.record("a/m", true, Duration::from_millis(50));
+        assert_eq!(stats.status("a/m").breaker, BreakerState::Closed);
+
+        tokio::time::advance(Duration::from_secs(45)).await;
+        let status = stats.status("a/m");
+        assert_eq!((status.requests, status.failures), (1, 0));
+        assert_eq!(stats.status("b/m").success_rate, None);
+        assert_eq!(stats.all().len(), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_ordered_group_falls_over_on_retriable_errors() {
+        let stats = Arc::new(ProviderStats::default());
+        let a = mock("a", 0, FaultPlan::script([Fault::FailWithStatus(503)]));
+        let b = mock("b", 0, FaultPlan::new());
+        let group = ProviderGroup::new(
+            "chat",
+            &ProviderGroupConfig::default(),
+            vec![
+                Arc::new(Monitored::new(a.clone(), stats.clone())),
+                Arc::new(Monitored::new(b.clone(), stats.clone())),
+            ],
+            stats.clone(),
+        );
+        group.complete(request()).await.unwrap();
+        group.complete(request()).await.unwrap();
+        assert_eq!((a.requests().len(), b.requests().len()), (2, 1));
+        assert_eq!(stats.status("a/mock-model").failures, 1);
+
+        let c = mock("c", 0, FaultPlan::script([Fault::FailWithStatus(400)]));
+        let group = ProviderGroup::new(
+            "strict",
+            &ProviderGroupConfig::default(),
+            vec![c, b.clone()],
+            stats,
+        );
+        let err = group.complete(request()).await.unwrap_err();
+        assert!(err.to_string().contains("HTTP 400"), "{:#}", err);
+        assert_eq!(b.requests().len(), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_adaptive_group_shifts_away_from_a_degraded_provider_and_back() {
+        let stats = Arc::new(ProviderStats::default());
+        // Fast, until it fails four calls in a row
+        let a = mock(
+            "a",
+            10,
+            FaultPlan::new()
+                .repeat(Fault::Succeed, 10)
+                .repeat(Fault::FailWithStatus(503), 4),
+        );
+        let b = mock("b", 50, FaultPlan::new());
+        let config = ProviderGroupConfig {
+            strategy: RoutingStrategy::Adaptive,
+            ..ProviderGroupConfig::default()
+        };
+        let group = ProviderGroup::new(
+            "chat",
+            &config,
+            vec![
+                Arc::new(Monitored::new(a.clone(), stats.clone())),
+                Arc::new(Monitored::new(b.clone(), stats.clone())),
+            ],
+            stats.clone(),
+        )
+        .with_seed(7);
+
+        // Which of them answered each request, one request a second, and how
+        // many calls the degrading one had received by then
+        let mut served = Vec::new();
+        let mut calls = Vec::new();
+        let mut opened = None;
+        for i in 0..400 {
+            let before = a.timeline().len();
+            group.complete(request()).await.unwrap();
+            let timeline = a.timeline();
+            let by_a = timeline.len() > before && !timeline.last().unwrap().fault.is_failure();
+            served.push(if by_a { 'a' } else { 'b' });
+            calls.push(timeline.len());
+            if opened.is_none() && stats.status("a/mock-model").breaker == BreakerState::Open {
+                opened = Some(i);
+            }
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+        let served: String = served.into_iter().collect();
+
+        // It served until its third failure in a row opened its breaker
+        let opened = opened.unwrap();
+        assert_eq!(served[..opened].matches('a').count(), 10, "{}", served);
+        // Then it got no requests, not even to explore, while it was open
+        assert_eq!(calls[opened + 25], calls[opened]);
+        assert!(!served[opened..opened + 25].contains('a'));
+        // Once its failures left the window it took most of the traffic again
+        assert!(served[350..].matches('a').count() >= 35, "{}", served);
+        assert_eq!(stats.status("a/mock-model").breaker, BreakerState::Closed);
+    }
+}
@@ -0,0 +1,528 @@
+//! Where credentials come from
+//!
+//! An `api_key` or `bearer_token` in the configuration is either the secret
+//! itself or a reference to it:
+//!
+//! - `env:OPENAI_API_KEY` reads an environment variable
+//! - `file:/run/secrets/openai` reads a file, trimming surrounding whitespace
+//! - `keyring:rig-mcp/openai` reads the OS keyring entry of service `rig-mcp`
+//!   and user `openai`; needs the `keyring` feature
+//! - `exec:op read op://vault/openai/key` runs a command and takes its
+//!   trimmed stdout
+//! - `literal:...` is the rest of the value, for secrets that happen to start
+//!   with one of these prefixes; anything else is taken as is
+//!
+//! Before that, every `${NAME}` in the value is replaced by the environment
+//! variable `NAME`, so `api_key = "${OPENAI_API_KEY}"` works as well as
+//! `env:OPENAI_API_KEY`; provider `base_url`s are substituted the same way.
+//! A provider without an `api_key` falls back to its conventional variable,
+//! such as `OPENAI_API_KEY`, see [`default_api_key_var`].
+//!
+//! References are resolved once, by [`crate::Config::resolve_secrets`], when
+//! the client is created; an unset variable fails it, naming the variable. Commands run without a shell, so values containing
+//! shell syntax are refused instead of being passed on as odd arguments, and
+//! are killed after [`DEFAULT_EXEC_TIMEOUT`]. Resolved values are never
+//! logged, and the configuration's `Debug` output shows references but not
+//! secrets.
+
+use regex::Regex;
+use std::fmt;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::time::Duration;
+use thiserror::Error;
+
+use crate::debug_logging::REDACTED;
+
+/// Time an `exec:` command has to print the secret
+pub const DEFAULT_EXEC_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Characters that only mean something to a shell
+const SHELL_SYNTAX: &[char] = &[
+    ';', '&', '|', '$', '`', '<', '>', '(', ')', '\\', '\'', '"', '\n', '\r',
+];
+
+/// A secret, or where to find it
+#[derive(Clone, PartialEq, Eq)]
+pub enum SecretSource {
+    Literal(String),
+    /// Name of an environment variable
+    Env(String),
+    File(PathBuf),
+    /// `service/user` of an OS keyring entry
+    Keyring(String),
+    /// Command line run without a shell
+    Exec(String),
+}
+
+/// A secret that couldn't be resolved
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("Could not resolve secret from {origin}: {reason}")]
+pub struct SecretError {
+    /// The reference, e.g. `file:/run/secrets/openai`; never a literal secret
+    pub origin: String,
+    pub reason: String,
+}
+
+impl SecretSource {
+    /// Read a configuration value; values without a known prefix are
+    /// literals
+    pub fn parse(value: &str) -> Self {
+        if let Some(name) = value.strip_prefix("env:") {
+            Self::Env(name.to_string())
+        } else if let Some(path) = value.strip_prefix("file:") {
+            Self::File(PathBuf::from(path))
+        } else if let Some(entry) = value.strip_prefix("keyring:") {
+            Self::Keyring(entry.to_string())
+        } else if let Some(command) = value.strip_prefix("exec:") {
+            Self::Exec(command.trim().to_string())
+        } else if let Some(secret) = value.strip_prefix("literal:") {
+            Self::Literal(secret.to_string())
+        } else {
+            Self::Literal(value.to_string())
+        }
+    }
+
+    /// The secret, giving `exec:` commands [`DEFAULT_EXEC_TIMEOUT`]
+    pub async fn resolve(&self) -> Result<String, SecretError> {
+        self.resolve_within(DEFAULT_EXEC_TIMEOUT).await
+    }
+
+    /// Like [`SecretSource::resolve`], giving `exec:` commands `timeout`
+    pub async fn resolve_within(&self, timeout: Duration) -> Result<String, SecretError> {
+        let secret = match self {
+            Self::Literal(secret) => return Ok(secret.clone()),
+            Self::Env(name) => std::env::var(name).map_err(|_| "not set".to_string()),
+            Self::File(path) => tokio::fs::read_to_string(path)
+                .await
+                .map_err(|err| err.to_string()),
+            Self::Keyring(entry) => keyring(entry).await,
+            Self::Exec(command) => exec(command, timeout).await,
+        };
+        let secret = secret.and_then(|secret| {
+            let secret = secret.trim();
+            if secret.is_empty() {
+                Err("empty".to_string())
+            } else {
+                Ok(secret.to_string())
+            }
+        });
+        secret.map_err(|reason| SecretError {
+            origin: self.to_string(),
+            reason,
+        })
+    }
+}
+
+impl fmt::Display for SecretSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Literal(_) => f.write_str("literal value"),
+            Self::Env(name) => write!(f, "env:{}", name),
+            Self::File(path) => write!(f, "file:{}", path.display()),
+            Self::Keyring(entry) => write!(f, "keyring:{}", entry),
+            Self::Exec(command) => write!(f, "exec:{}", command),
+        }
+    }
+}
+
+impl fmt::Debug for SecretSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Literal(_) => f.debug_tuple("Literal").field(&REDACTED).finish(),
+            Self::Env(name) => f.debug_tuple("Env").field(name).finish(),
+            Self::File(path) => f.debug_tuple("File").field(path).finish(),
+            Self::Keyring(entry) => f.debug_tuple("Keyring").field(entry).finish(),
+            Self::Exec(command) => f.debug_tuple("Exec").field(command).finish(),
+        }
+    }
+}
+
+/// Environment variable holding the API key of `provider` by convention
+pub fn default_api_key_var(provider: &str) -> Option<&'static str> {
+    Some(match provider {
+        "openai" => "OPENAI_API_KEY",
+        "anthropic" => "ANTHROPIC_API_KEY",
+        "cohere" => "COHERE_API_KEY",
+        "gemini" => "GEMINI_API_KEY",
+        "deepseek" => "DEEPSEEK_API_KEY",
+        "azure" => "AZURE_OPENAI_API_KEY",
+        "mistral" => "MISTRAL_API_KEY",
+        "groq" => "GROQ_API_KEY",
+        _ => return None,
+    })
+}
+
+/// `value` with every `${NAME}` replaced by the environment variable `NAME`
+pub fn substitute_env(value: &str) -> Result<String, SecretError> {
+    let variable = Regex::new(r"\$\{([A-Za-z_][A-Za-z0-9_]*)\}").expect("valid pattern");
+    let mut out = String::with_capacity(value.len());
+    let mut copied = 0;
+    for found in variable.captures_iter(value) {
+        let whole = found.get(0).expect("whole match");
+        let name = &found[1];
+        let substituted = std::env::var(name).map_err(|_| SecretError {
+            origin: whole.as_str().to_string(),
+            reason: format!("environment variable {} is not set", name),
+        })?;
+        out.push_str(&value[copied..whole.start()]);
+        out.push_str(&substituted);
+        copied = whole.end();
+    }
+    out.push_str(&value[copied..]);
+    Ok(out)
+}
+
+/// Substitute environment variables in `value`, if any, in place
+pub(crate) fn substitute_in_place(value: &mut Option<String>) -> Result<(), SecretError> {
+    if let Some(template) = value.as_deref() {
+        *value = Some(substitute_env(template)?);
+    }
+    Ok(())
+}
+
+/// Resolve the reference in `value`, if any, in place
+pub(crate) async fn resolve_in_place(value: &mut Option<String>) -> Result<(), SecretError> {
+    if let Some(reference) = value.as_deref() {
+        let reference = substitute_env(reference)?;
+        *value = Some(SecretSource::parse(&reference).resolve().await?);
+    }
+    Ok(())
+}
+
+/// A configured secret as `Debug` output shows it
+pub(crate) fn debug(value: &Option<String>) -> Option<SecretSource> {
+    value.as_deref().map(SecretSource::parse)
+}
+
+/// Run `command` without a shell and return its stdout
+async fn exec(command: &str, timeout: Duration) -> Result<String, String> {
+    if command.contains(SHELL_SYNTAX) {
+        return Err("shell syntax is not allowed; commands run without a shell".to_string());
+    }
+    let mut words = command.split_whitespace();
+    let program = words.next().ok_or("no command given")?;
+    let child = tokio::process::Command::new(program)
+        .args(words)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|err| format!("failed to run `{}`: {}", program, err))?;
+    let output = tokio::time::timeout(timeout, child.wait_with_output())
+        .await
+        .map_err(|_| format!("timed out after {}ms", timeout.as_millis()))?
+        .map_err(|err| err.to_string())?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let detail = stderr.lines().next().unwrap_or_default().trim();
+        return Err(if detail.is_empty() {
+            output.status.to_string()
+        } else {
+            format!("{}: {}", output.status, detail)
+        });
+    }
+    String::from_utf8(output.stdout).map_err(|_| "output is not UTF-8".to_string())
+}
+
+#[cfg(feature = "keyring")]
+async fn keyring(entry: &str) -> Result<String, String> {
+    let (service, user) = entry
+        .split_once('/')
+        .ok_or("expected keyring:<service>/<user>")?;
+    let (service, user) = (service.to_string(), user.to_string());
+    tokio::task::spawn_blocking(move || {
+        keyring::Entry::new(&service, &user)
+            .and_then(|entry| entry.get_password())
+            .map_err(|err| err.to_string())
+    })
+    .await
+    .map_err(|err| err.to_string())?
+}
+
+#[cfg(not(feature = "keyring"))]
+async fn keyring(_entry: &str) -> Result<String, String> {
+    Err("keyring support is not enabled; build with the `keyring` feature".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Config, ProviderConfig, ServerConfig, Transport};
+    use std::collections::HashMap;
+
+    fn provider(api_key: &str) -> ProviderConfig {
+        ProviderConfig {
+            name: "openai".to_string(),
+            model: "gpt-4o".to_string(),
+            api_key: Some(api_key.to_string()),
+            ..ProviderConfig::default()
+        }
+    }
+
+    /// An executable shell script in `dir`
+    #[cfg(unix)]
+    fn script(dir: &std::path::Path, name: &str, body: &str) -> PathBuf {
+        use std::os::unix::fs::PermissionsExt;
+        let path = dir.join(name);
+        std::fs::write(&path, format!("#!/bin/sh\n{}\n", body)).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_references_are_parsed() {
+        assert_eq!(
+            SecretSource::parse("env:OPENAI_API_KEY"),
+            SecretSource::Env("OPENAI_API_KEY".to_string())
+        );
+        assert_eq!(
+            SecretSource::parse("file:/run/secrets/openai"),
+            SecretSource::File(PathBuf::from("/run/secrets/openai"))
+        );
+        assert_eq!(
+            SecretSource::parse("exec: op read op://vault/openai/key"),
+            SecretSource::Exec("op read op://vault/openai/key".to_string())
+        );
+        assert_eq!(
+            SecretSource::parse("literal:env:x"),
+            SecretSource::Literal("env:x".to_string())
+        );
+        assert_eq!(
+            SecretSource::parse("sk-test"),
+            SecretSource::Literal("sk-test".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_file_contents_are_trimmed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("openai");
+        std::fs::write(&path, "  sk-from-file\n").unwrap();
+
+        let source = SecretSource::parse(&format!("file:{}", path.display()));
+        assert_eq!(source.resolve().await.unwrap(), "sk-from-file");
+
+        let missing = SecretSource::File(dir.path().join("missing"));
+        let err = missing.resolve().await.unwrap_err();
+        assert_eq!(
+            err.origin,
+            format!("file:{}", dir.path().join("missing").display())
+        );
+        assert!(err
+            .to_string()
+            .starts_with("Could not resolve secret from file:"));
+
+        std::fs::write(&path, "\n").unwrap();
+        let err = source.resolve().await.unwrap_err();
+        assert_eq!(err.reason, "empty");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_exec_takes_stdout() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = script(dir.path(), "secret", r#"echo "sk-$1""#);
+
+        let source = SecretSource::parse(&format!("exec:{} from-exec", path.display()));
+        assert_eq!(source.resolve().await.unwrap(), "sk-from-exec");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_exec_failures_name_the_command() {
+        let dir = tempfile::tempdir().unwrap();
+        let failing = script(dir.path(), "failing", "echo 'item not found' >&2\nexit 3");
+        let err = SecretSource::Exec(failing.display().to_string())
+            .resolve()
+            .await
+            .unwrap_err();
+        assert_eq!(err.origin, format!("exec:{}", failing.display()));
+        assert_eq!(err.reason, "exit status: 3: item not found");
+
+        let hanging = script(dir.path(), "hanging", "sleep 5");
+        let err = SecretSource::Exec(hanging.display().to_string())
+            .resolve_within(Duration::from_millis(100))
+            .await
+            .unwrap_err();
+        assert_eq!(err.reason, "timed out after 100ms");
+
+        let err = SecretSource::parse("exec:/no/such/command")
+            .resolve()
+            .await
+            .unwrap_err();
+        assert!(
+            err.reason.starts_with("failed to run `/no/such/command`"),
+            "{}",
+            err.reason
+        );
+    }
+
+    #[tokio::test]
+    async fn test_shell_syntax_is_refused() {
+        for command in ["op read x; rm -rf ~", "cat $HOME/key", "echo `id`", "a | b"] {
+            let err = SecretSource::Exec(command.to_string())
+                .resolve()
+                .await
+                .unwrap_err();
+            assert!(
+                err.reason.starts_with("shell syntax is not allowed"),
+                "{}",
+                command
+            );
+        }
+    }
+
+    #[test]
+    fn test_debug_output_hides_secrets() {
+        let debug = format!("{:?}", provider("sk-secret"));
+        assert!(!debug.contains("sk-secret"), "{}", debug);
+        assert!(
+            debug.contains(r#"api_key: Some(Literal("[REDACTED]"))"#),
+            "{}",
+            debug
+        );
+
+        let debug = format!("{:?}", provider("file:/run/secrets/openai"));
+        assert!(
+            debug.contains(r#"File("/run/secrets/openai")"#),
+            "{}",
+            debug
+        );
+
+        let transport = Transport::Http {
+            url: "https://tools.example.com/mcp".to_string(),
+            bearer_token: Some("mcp-secret".to_string()),
+            reinitialize: true,
+        };
+        let debug = format!("{:?}", transport);
+        assert!(!debug.contains("mcp-secret"), "{}", debug);
+        assert_eq!(
+            format!("{:?}", SecretSource::Literal("sk-secret".to_string())),
+            r#"Literal("[REDACTED]")"#
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_config_secrets_are_resolved() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("openai");
+        std::fs::write(&path, "sk-from-file\n").unwrap();
+        let token = script(dir.path(), "token", "echo mcp-token");
+        let config = Config {
+            providers: vec![
+                provider(&format!("file:{}", path.display())),
+                ProviderConfig {
+                    name: "deepseek".to_string(),
+                    ..provider("sk-literal")
+                },
+            ],
+            mcp_servers: vec![ServerConfig {
+                name: "tools".to_string(),
+                transport: Transport::Sse {
+                    url: "https://tools.example.com/sse".to_string(),
+                    bearer_token: Some(format!("exec:{}", token.display())),
+                },
+                roots: None,
+                embeddings: None,
+            }],
+            ..Config::default()
+        };
+
+        let config = config.resolve_secrets().await.unwrap();
+        assert_eq!(config.providers[0].api_key.as_deref(), Some("sk-from-file"));
+        assert_eq!(config.providers[1].api_key.as_deref(), Some("sk-literal"));
+        match &config.mcp_servers[0].transport {
+            Transport::Sse { bearer_token, .. } => {
+                assert_eq!(bearer_token.as_deref(), Some("mcp-token"))
+            }
+            other => panic!("{:?}", other),
+        }
+
+        let config = Config {
+            providers: vec![provider("file:/no/such/secret")],
+            ..Config::default()
+        };
+        let err = config.resolve_secrets().await.unwrap_err();
+        assert_eq!(
+            format!("{:#}", err),
+            "api_key of provider 'openai': Could not resolve secret from \
+             file:/no/such/secret: No such file or directory (os error 2)"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_environment_variables_are_substituted() {
+        std::env::set_var("RIG_MCP_TEST_KEY", "sk-from-env");
+        std::env::set_var("RIG_MCP_TEST_HOST", "llm.internal");
+        let config = Config {
+            providers: vec![ProviderConfig {
+                base_url: Some("https://${RIG_MCP_TEST_HOST}/v1".to_string()),
+                ..provider("${RIG_MCP_TEST_KEY}")
+            }],
+            ..Config::default()
+        };
+        let config = config.resolve_secrets().await.unwrap();
+        assert_eq!(config.providers[0].api_key.as_deref(), Some("sk-from-env"));
+        assert_eq!(
+            config.providers[0].base_url.as_deref(),
+            Some("https://llm.internal/v1")
+        );
+
+        std::env::remove_var("RIG_MCP_TEST_UNSET");
+        let config = Config {
+            providers: vec![provider("${RIG_MCP_TEST_UNSET}")],
+            ..Config::default()
+        };
+        let err = config.resolve_secrets().await.unwrap_err();
+        assert_eq!(
+            format!("{:#}", err),
+            "api_key of provider 'openai': Could not resolve secret from \
+             ${RIG_MCP_TEST_UNSET}: environment variable RIG_MCP_TEST_UNSET is not set"
+        );
+        let config = Config {
+            providers: vec![ProviderConfig {
+                base_url: Some("https://${RIG_MCP_TEST_UNSET}/v1".to_string()),
+                ..provider("sk-test")
+            }],
+            ..Config::default()
+        };
+        let err = config.resolve_secrets().await.unwrap_err();
+        assert!(
+            format!("{:#}", err).starts_with("base_url of provider 'openai'"),
+            "{:#}",
+            err
+        );
+    }
+
+    #[tokio::test]
+    async fn test_missing_api_keys_fall_back_to_conventional_variables() {
+        let keyless = |name: &str| ProviderConfig {
+            name: name.to_string(),
+            api_key: None,
+            ..provider("unused")
+        };
+        let env = HashMap::from([("COHERE_API_KEY", "co-from-env")]);
+        let var = |name: &str| env.get(name).map(|value| value.to_string());
+        let config = Config {
+            providers: vec![keyless("cohere"), keyless("ollama")],
+            ..Config::default()
+        };
+        let config = config.resolve_secrets_with(var).await.unwrap();
+        assert_eq!(config.providers[0].api_key.as_deref(), Some("co-from-env"));
+        // Ollama needs no key
+        assert_eq!(config.providers[1].api_key, None);
+
+        let config = Config {
+            providers: vec![keyless("deepseek")],
+            ..Config::default()
+        };
+        let err = config.resolve_secrets_with(var).await.unwrap_err();
+        assert_eq!(
+            format!("{:#}", err),
+            "Provider 'deepseek' has no api_key and DEEPSEEK_API_KEY is not set"
+        );
+    }
+}
@@ -0,0 +1,172 @@
+//! Serving a [`ToolServer`] over MCP
+//!
+//! [`serve`] answers `tools/list` and `tools/call` from any [`ToolServer`],
+//! so tools written in Rust, such as the shim the api-endpoint package
+//! generates for its axum handlers, can be offered to MCP clients. Read-only
+//! and destructive annotations are passed on as `readOnlyHint` and
+//! `destructiveHint`. A tool error is returned as a result with `isError`
+//! set; only a server that fails to run the call at all answers with a
+//! JSON-RPC error.
+//!
+//! [`serve_stdio`] serves over stdin and stdout, for clients that start the
+//! server as a child process.
+
+use anyhow::{Context, Result};
+use rmcp::model::{
+    CallToolRequestParam, CallToolResult, Content, ListToolsResult, PaginatedRequestParam,
+    ServerCapabilities, ServerInfo, Tool, ToolAnnotations,
+};
+use rmcp::service::{RequestContext, RunningService};
+use rmcp::transport::IntoTransport;
+use rmcp::{ErrorData, RoleServer, ServerHandler, ServiceExt};
+use serde_json::{Map, Value};
+use std::sync::Arc;
+
+use crate::mcp::{ToolInfo, ToolServer};
+
+/// Server side of an rmcp session, backed by a [`ToolServer`]
+#[derive(Clone)]
+pub struct ToolServerHandler {
+    server: Arc<dyn ToolServer>,
+}
+
+impl ToolServerHandler {
+    pub fn new(server: Arc<dyn ToolServer>) -> Self {
+        Self { server }
+    }
+}
+
+fn tool(info: ToolInfo) -> Tool {
+    let schema = match info.input_schema {
+        Value::Object(schema) => schema,
+        _ => Map::new(),
+    };
+    let mut tool = Tool::new(info.name, info.description, Arc::new(schema));
+    tool.annotations = Some(ToolAnnotations {
+        read_only_hint: Some(info.read_only),
+        destructive_hint: Some(info.destructive),
+        ..ToolAnnotations::default()
+    });
+    tool
+}
+
+impl ServerHandler for ToolServerHandler {
+    fn get_info(&self) -> ServerInfo {
+        ServerInfo {
+            capabilities: ServerCapabilities::builder().enable_tools().build(),
+            ..ServerInfo::default()
+        }
+    }
+
+    async fn list_tools(
+        &self, _request: Option<PaginatedRequestParam>, _context: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, ErrorData> {
+        let tools = self
+            .server
+            .list_tools()
+            .await
+            .map_err(|err| ErrorData::internal_error(format!("{:#}", err), None))?;
+        Ok(ListToolsResult::with_all_items(
+            tools.into_iter().map(tool).collect(),
+        ))
+    }
+
+    async fn call_tool(
+        &self, request: CallToolRequestParam, _context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let arguments = Value::Object(request.arguments.unwrap_or_default());
+        let output = self
+            .server
+            .call_tool(&request.name, arguments)
+            .await
+            .map_err(|err| ErrorData::internal_error(format!("{:#}", err), None))?;
+        let content = vec![Content::text(output.content)];
+        Ok(if output.is_error {
+            CallToolResult::error(content)
+        } else {
+            CallToolResult::success(content)
+        })
+    }
+}
+
+/// Serve the tools of `server` over `transport`, once the client has
+/// completed the handshake
+pub async fn serve<T, E, A>(
+    server: Arc<dyn ToolServer>, transport: T,
+) -> Result<RunningService<RoleServer, ToolServerHandler>>
+where
+    T: IntoTransport<RoleServer, E, A>,
+    E: std::error::Error + Send + Sync + 'static,
+{
+    let name = server.name().to_string();
+    ToolServerHandler::new(server)
+        .serve(transport)
+        .await
+        .with_context(|| format!("Failed to serve the tools of '{}'", name))
+}
+
+/// Serve the tools of `server` over stdin and stdout until the client
+/// disconnects
+pub async fn serve_stdio(server: Arc<dyn ToolServer>) -> Result<()> {
+    let running = serve(server, rmcp::transport::stdio()).await?;
+    running.waiting().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mcp::RmcpServer;
+    use crate::testing::FakeMcpServer;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_served_tools_reach_an_mcp_client() {
+        let schema = json!({
+            "type": "object",
+            "properties": {"id": {"type": "string"}},
+            "required": ["id"],
+        });
+        let users = FakeMcpServer::new("users")
+            .with_tool("getUser", "Get a User by id", schema.clone(), |args| {
+                Ok(format!("user {}", args["id"].as_str().unwrap_or_default()))
+            })
+            .read_only("getUser")
+            .with_tool("deleteUser", "Delete a User", schema, |_| {
+                anyhow::bail!("not found")
+            })
+            .destructive("deleteUser");
+
+        let (client_io, server_io) = tokio::io::duplex(4096);
+        tokio::spawn(async move {
+            let running = serve(Arc::new(users), server_io).await.unwrap();
+            running.waiting().await.unwrap();
+        });
+        let client = RmcpServer::serve("users", Vec::new(), client_io)
+            .await
+            .unwrap();
+
+        let tools = client.list_tools().await.unwrap();
+        let get = tools.iter().find(|tool| tool.name == "getUser").unwrap();
+        assert_eq!(get.input_schema["required"], json!(["id"]));
+        assert!(get.read_only && !get.destructive);
+        let delete = tools.iter().find(|tool| tool.name == "deleteUser").unwrap();
+        assert!(delete.destructive && !delete.read_only);
+
+        let output = client
+            .call_tool("getUser", json!({"id": "42"}))
+            .await
+            .unwrap();
+        assert_eq!(output.content, "user 42");
+        assert!(!output.is_error);
+        let output = client
+            .call_tool("deleteUser", json!({"id": "42"}))
+            .await
+            .unwrap();
+        assert!(output.is_error);
+        assert_eq!(output.content, "not found");
+
+        // An unknown tool fails the request instead of returning a result
+        assert!(client.call_tool("renameUser", json!({})).await.is_err());
+    }
+}
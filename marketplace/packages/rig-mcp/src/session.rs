@@ -0,0 +1,750 @@
+//! Conversations with a title and a rolling summary
+//!
+//! A [`Session`] is the persisted state of a conversation: its messages, plus
+//! a title and a summary for listing it. Messages form a tree. Each has an id
+//! and a parent, and a [`Branch`] is the path from the first message to its
+//! head. [`Session::branch_from`] starts a branch at an earlier message, for
+//! example to send an edited prompt in place of the one after it. Branches
+//! share their common ancestry, and only the active branch is sent to the
+//! model, exported, summarized and truncated, so each branch keeps its own
+//! summary and token budget.
+//!
+//! A [`SessionAgent`] sends prompts on a session's behalf and keeps that
+//! state up to date with a second, typically cheaper, provider:
+//!
+//! - after the first exchange it asks for a short title;
+//! - every `summary_every` exchanges it folds the messages since the last
+//!   summary into the summary.
+//!
+//! The summary is also how [`Truncation::SummarizeOldest`] shortens the
+//! history: once messages are covered by the summary, all but the latest
+//! `keep_recent` are no longer sent, and the summary is sent in their place
+//! as a system message. They stay in the session. Without summaries the
+//! history is never shortened.
+//!
+//! Both features are off unless configured:
+//!
+//! ```toml
+//! [session]
+//! auto_title = true
+//! summary_every = 4
+//! summarizer = "fast"
+//! truncation = { strategy = "summarize_oldest", keep_recent = 6 }
+//! ```
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::agent::Agent;
+use crate::provider::{ChatMessage, ChatRequest, Provider, Role};
+
+const TITLE_PROMPT: &str = "Write a title of at most six words for the conversation below. \
+                            Answer with the title only.";
+
+/// Layout [`Session::save`] writes; version 1 sessions were a linear history
+pub const SESSION_VERSION: u32 = 2;
+
+/// Branch of a new session
+pub const MAIN_BRANCH: &str = "main";
+
+const SUMMARY_PROMPT: &str = "Summarize the conversation below for someone continuing it. \
+                              Keep names, decisions and open questions. Answer with the \
+                              summary only.";
+
+/// Titles, summaries and truncation of sessions
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionConfig {
+    /// Title a session after its first exchange
+    #[serde(default)]
+    pub auto_title: bool,
+    /// Update the summary every this many exchanges; no summaries when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary_every: Option<usize>,
+    /// Provider name, model alias or `provider/model` writing titles and
+    /// summaries; the session's own provider when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summarizer: Option<String>,
+    /// How the history sent with each prompt is kept short
+    #[serde(default)]
+    pub truncation: Truncation,
+}
+
+/// How a session's history is kept short
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "strategy", rename_all = "snake_case")]
+pub enum Truncation {
+    /// Send the whole history
+    #[default]
+    KeepAll,
+    /// Replace messages covered by the summary with the summary, keeping at
+    /// least the latest `keep_recent`; needs `summary_every`
+    SummarizeOldest { keep_recent: usize },
+}
+
+/// A message with its place in the tree
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionMessage {
+    pub id: String,
+    /// None for the first message of a branch started from the beginning
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
+    #[serde(flatten)]
+    pub message: ChatMessage,
+}
+
+/// A line of conversation, with its own summary
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Branch {
+    pub id: String,
+    /// Latest message; none until the branch has one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub head: Option<String>,
+    /// Rolling summary of the branch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+    /// Exchanges on the branch, including those it shares
+    #[serde(default)]
+    pub turns: usize,
+    /// Leading messages of the branch the summary already covers
+    #[serde(default)]
+    pub summarized: usize,
+    /// Leading messages of the branch no longer sent, now represented by the
+    /// summary
+    #[serde(default)]
+    pub dropped: usize,
+}
+
+impl Branch {
+    fn new(id: String, head: Option<String>) -> Self {
+        Self {
+            id,
+            head,
+            summary: None,
+            turns: 0,
+            summarized: 0,
+            dropped: 0,
+        }
+    }
+}
+
+/// A conversation, as persisted
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Session {
+    /// [`SESSION_VERSION`]; older files are migrated by [`Session::load`]
+    pub version: u32,
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// Every message of every branch, oldest first
+    pub messages: Vec<SessionMessage>,
+    pub branches: Vec<Branch>,
+    /// Branch prompts continue, and [`Session::context`] and
+    /// [`Session::export`] read
+    pub active_branch: String,
+    /// Messages a version 1 session had dropped before it was migrated; the
+    /// summary of its branch stands in for them
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub pruned: usize,
+}
+
+fn is_zero(n: &usize) -> bool {
+    *n == 0
+}
+
+/// A session as version 1 saved it: one history, from which truncation
+/// removed messages
+#[derive(Deserialize)]
+struct LinearSession {
+    id: String,
+    #[serde(default)]
+    title: Option<String>,
+    #[serde(default)]
+    summary: Option<String>,
+    history: Vec<ChatMessage>,
+    #[serde(default)]
+    turns: usize,
+    #[serde(default)]
+    summarized: usize,
+    #[serde(default)]
+    dropped: usize,
+}
+
+impl From<LinearSession> for Session {
+    fn from(linear: LinearSession) -> Self {
+        let mut session = Session::new(linear.id);
+        session.title = linear.title;
+        session.pruned = linear.dropped;
+        session.append(linear.history);
+        let main = session.active_mut();
+        main.summary = linear.summary;
+        main.turns = linear.turns;
+        main.summarized = linear.summarized;
+        session
+    }
+}
+
+impl Session {
+    pub fn new(id: impl Into<String>) -> Self {
+        Self {
+            version: SESSION_VERSION,
+            id: id.into(),
+            title: None,
+            messages: Vec::new(),
+            branches: vec![Branch::new(MAIN_BRANCH.to_string(), None)],
+            active_branch: MAIN_BRANCH.to_string(),
+            pruned: 0,
+        }
+    }
+
+    pub fn branch(&self, id: &str) -> Option<&Branch> {
+        self.branches.iter().find(|branch| branch.id == id)
+    }
+
+    pub fn active(&self) -> &Branch {
+        self.branch(&self.active_branch)
+            .expect("the active branch exists")
+    }
+
+    fn active_mut(&mut self) -> &mut Branch {
+        let id = &self.active_branch;
+        self.branches
+            .iter_mut()
+            .find(|branch| branch.id == *id)
+            .expect("the active branch exists")
+    }
+
+    pub fn set_active_branch(&mut self, id: &str) -> Result<()> {
+        anyhow::ensure!(
+            self.branch(id).is_some(),
+            "Session {} has no branch '{}'",
+            self.id,
+            id
+        );
+        self.active_branch = id.to_string();
+        Ok(())
+    }
+
+    /// `head` and its ancestors, first message first
+    fn ancestry(&self, head: Option<&str>) -> Vec<&SessionMessage> {
+        let by_id: HashMap<&str, &SessionMessage> = self
+            .messages
+            .iter()
+            .map(|message| (message.id.as_str(), message))
+            .collect();
+        let mut path = Vec::new();
+        let mut next = head;
+        while let Some(message) = next.and_then(|id| by_id.get(id)) {
+            if path.len() == self.messages.len() {
+                break;
+            }
+            path.push(*message);
+            next = message.parent.as_deref();
+        }
+        path.reverse();
+        path
+    }
+
+    /// Messages of branch `id`, first message first; none for an unknown
+    /// branch
+    pub fn path(&self, id: &str) -> Vec<&SessionMessage> {
+        let head = self.branch(id).and_then(|branch| branch.head.as_deref());
+        self.ancestry(head)
+    }
+
+    /// Every message of the active branch, including those truncation no
+    /// longer sends
+    pub fn export(&self) -> Vec<ChatMessage> {
+        self.path(&self.active_branch)
+            .into_iter()
+            .map(|message| message.message.clone())
+            .collect()
+    }
+
+    /// Messages sent before the next prompt on the active branch: the
+    /// summary in place of the dropped messages, then the rest of the branch
+    pub fn context(&self) -> Vec<ChatMessage> {
+        let branch = self.active();
+        let summary = branch
+            .summary
+            .as_ref()
+            .filter(|_| branch.dropped > 0 || self.pruned > 0);
+        summary
+            .map(|summary| {
+                ChatMessage::system(format!("Summary of the conversation so far: {}", summary))
+            })
+            .into_iter()
+            .chain(self.export().into_iter().skip(branch.dropped))
+            .collect()
+    }
+
+    /// Start a branch whose last message is `message_id` and make it the
+    /// active one, returning its id
+    ///
+    /// The branch keeps the summary of the active branch if `message_id` is
+    /// on it and the summary covers nothing after it.
+    pub fn branch_from(&mut self, message_id: &str) -> Result<String> {
+        let ancestry = self.ancestry(Some(message_id));
+        anyhow::ensure!(
+            !ancestry.is_empty(),
+            "Session {} has no message '{}'",
+            self.id,
+            message_id
+        );
+        let length = ancestry.len();
+        let turns = ancestry
+            .iter()
+            .filter(|message| message.message.role == Role::User)
+            .count();
+        let active = self.active();
+        let on_active = self
+            .path(&active.id)
+            .get(length - 1)
+            .is_some_and(|message| message.id == message_id);
+
+        let mut branch = Branch::new(self.next_branch_id(), Some(message_id.to_string()));
+        branch.turns = turns;
+        if on_active && active.summarized <= length {
+            branch.summary = active.summary.clone();
+            branch.summarized = active.summarized;
+            branch.dropped = active.dropped;
+        }
+        Ok(self.add_branch(branch))
+    }
+
+    /// Start an empty branch and make it the active one, returning its id,
+    /// e.g. to edit the first prompt
+    pub fn branch_from_start(&mut self) -> String {
+        let branch = Branch::new(self.next_branch_id(), None);
+        self.add_branch(branch)
+    }
+
+    fn next_branch_id(&self) -> String {
+        format!("b{}", self.branches.len() + 1)
+    }
+
+    fn add_branch(&mut self, branch: Branch) -> String {
+        let id = branch.id.clone();
+        self.branches.push(branch);
+        self.active_branch = id.clone();
+        id
+    }
+
+    /// Add `messages` to the active branch
+    fn append(&mut self, messages: impl IntoIterator<Item = ChatMessage>) {
+        for message in messages {
+            let id = format!("m{}", self.messages.len() + 1);
+            let parent = self.active_mut().head.replace(id.clone());
+            self.messages.push(SessionMessage {
+                id,
+                parent,
+                message,
+            });
+        }
+    }
+
+    /// Parse a saved session, migrating older versions
+    pub fn from_json(json: &str) -> Result<Self> {
+        let value: Value = serde_json::from_str(json)?;
+        match value.get("version").and_then(Value::as_u64).unwrap_or(1) {
+            1 => Ok(serde_json::from_value::<LinearSession>(value)?.into()),
+            version if version == u64::from(SESSION_VERSION) => Ok(serde_json::from_value(value)?),
+            version => anyhow::bail!(
+                "Session version {} is newer than the supported {}",
+                version,
+                SESSION_VERSION
+            ),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read session {}", path.display()))?;
+        Self::from_json(&json)
+            .with_context(|| format!("Failed to parse session {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, json)
+            .with_context(|| format!("Failed to write session {}", path.display()))
+    }
+
+    /// Stop sending the messages of the active branch the summary covers,
+    /// keeping the latest `keep_recent` and whole exchanges, so no tool
+    /// result loses its call
+    fn truncate(&mut self, keep_recent: usize) {
+        let roles: Vec<Role> = self
+            .path(&self.active_branch)
+            .iter()
+            .map(|message| message.message.role)
+            .collect();
+        let branch = self.active_mut();
+        let dropped = branch.dropped;
+        let limit = branch
+            .summarized
+            .min(roles.len().saturating_sub(keep_recent));
+        if limit <= dropped {
+            return;
+        }
+        branch.dropped = (dropped..=limit)
+            .rev()
+            .find(|&i| i == dropped || roles.get(i).map_or(true, |r| *r == Role::User))
+            .unwrap_or(dropped);
+    }
+}
+
+/// An [`Agent`] that keeps [`Session`]s titled, summarized and short
+pub struct SessionAgent {
+    agent: Agent,
+    summarizer: Arc<dyn Provider>,
+    config: SessionConfig,
+}
+
+impl SessionAgent {
+    /// `summarizer` writes titles and summaries
+    pub fn new(agent: Agent, summarizer: Arc<dyn Provider>, config: SessionConfig) -> Self {
+        Self {
+            agent,
+            summarizer,
+            config,
+        }
+    }
+
+    pub fn config(&self) -> &SessionConfig {
+        &self.config
+    }
+
+    /// Answer `prompt` on the active branch of `session`, then update its
+    /// title and the branch's summary and history
+    ///
+    /// A failing summarizer doesn't fail the exchange: the title is retried
+    /// after the next one, and the summary stays as it was until its next
+    /// update.
+    pub async fn send(&self, session: &mut Session, prompt: &str) -> Result<String> {
+        let branch = session.active_branch.clone();
+        self.send_on(session, &branch, prompt).await
+    }
+
+    /// [`SessionAgent::send`] on branch `branch`, which becomes the active
+    /// one
+    pub async fn send_on(
+        &self, session: &mut Session, branch: &str, prompt: &str,
+    ) -> Result<String> {
+        session.set_active_branch(branch)?;
+        let mut context = session.context();
+        let start = context.len();
+        let answer = self.agent.chat(&mut context, prompt).await?;
+        session.append(context.drain(start..));
+        session.active_mut().turns += 1;
+
+        if self.config.auto_title && session.title.is_none() {
+            match self.title(session).await {
+                Ok(title) => session.title = Some(title),
+                Err(err) => tracing::warn!("Titling session {} failed: {:#}", session.id, err),
+            }
+        }
+
+        let turns = session.active().turns;
+        let due = self
+            .config
+            .summary_every
+            .is_some_and(|every| every > 0 && turns % every == 0);
+        if due {
+            match self.summarize(session).await {
+                Ok(summary) => {
+                    let length = session.path(branch).len();
+                    let branch = session.active_mut();
+                    branch.summary = Some(summary);
+                    branch.summarized = length;
+                }
+                Err(err) => tracing::warn!("Summarizing session {} failed: {:#}", session.id, err),
+            }
+        }
+
+        if let Truncation::SummarizeOldest { keep_recent } = self.config.truncation {
+            session.truncate(keep_recent);
+        }
+        Ok(answer)
+    }
+
+    async fn title(&self, session: &Session) -> Result<String> {
+        let title = self
+            .ask(TITLE_PROMPT, transcript(&session.export()))
+            .await?;
+        Ok(title
+            .trim_matches(|c: char| c == '"' || c == '\'')
+            .to_string())
+    }
+
+    async fn summarize(&self, session: &Session) -> Result<String> {
+        let branch = session.active();
+        let history = session.export();
+        let new = transcript(&history[branch.summarized.min(history.len())..]);
+        let text = match &branch.summary {
+            Some(summary) => format!("Summary so far:\n{}\n\nSince then:\n{}", summary, new),
+            None => new,
+        };
+        self.ask(SUMMARY_PROMPT, text).await
+    }
+
+    async fn ask(&self, instructions: &str, text: String) -> Result<String> {
+        let request = ChatRequest {
+            system: Some(instructions.to_string()),
+            messages: vec![ChatMessage::user(text)],
+            ..ChatRequest::default()
+        };
+        let response = self.summarizer.complete(request).await?;
+        let answer = response.content.trim();
+        anyhow::ensure!(
+            !answer.is_empty(),
+            "Summarizer '{}' answered with nothing",
+            self.summarizer.name()
+        );
+        Ok(answer.to_string())
+    }
+}
+
+/// `role: content` per message with content
+fn transcript(messages: &[ChatMessage]) -> String {
+    messages
+        .iter()
+        .filter(|m| !m.content.is_empty())
+        .map(|m| {
+            let role = match m.role {
+                Role::System => "system",
+                Role::User => "user",
+                Role::Assistant => "assistant",
+                Role::Tool => "tool",
+            };
+            format!("{}: {}", role, m.content)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::AgentBuilder;
+    use crate::provider::NormalizedResponse;
+    use crate::testing::MockProvider;
+
+    fn session_agent(
+        config: SessionConfig,
+    ) -> (Arc<MockProvider>, Arc<MockProvider>, SessionAgent) {
+        let chat = Arc::new(MockProvider::new("chat"));
+        let cheap = Arc::new(MockProvider::new("cheap"));
+        let agent = AgentBuilder::new(chat.clone()).build();
+        let sessions = SessionAgent::new(agent, cheap.clone(), config);
+        (chat, cheap, sessions)
+    }
+
+    #[tokio::test]
+    async fn test_title_summary_and_truncation_share_the_summary() {
+        let (chat, cheap, sessions) = session_agent(SessionConfig {
+            auto_title: true,
+            summary_every: Some(2),
+            summarizer: None,
+            truncation: Truncation::SummarizeOldest { keep_recent: 2 },
+        });
+        cheap.push_response(NormalizedResponse::text("\"Lisbon trip\""));
+        cheap.push_response(NormalizedResponse::text("Planning four days in Lisbon."));
+        cheap.push_response(NormalizedResponse::text("Lisbon in May; flights booked."));
+        let mut session = Session::new("s1");
+
+        sessions.send(&mut session, "Plan Lisbon").await.unwrap();
+        assert_eq!(session.title.as_deref(), Some("Lisbon trip"));
+        assert_eq!(session.active().summary, None);
+        assert_eq!(session.context().len(), 2);
+
+        sessions.send(&mut session, "Four days").await.unwrap();
+        assert_eq!(
+            session.active().summary.as_deref(),
+            Some("Planning four days in Lisbon.")
+        );
+        // The first exchange is now only sent as the summary
+        assert_eq!(session.context()[1].content, "Four days");
+        assert_eq!(session.active().dropped, 2);
+
+        sessions.send(&mut session, "In May").await.unwrap();
+        assert_eq!(
+            session.active().summary.as_deref(),
+            Some("Planning four days in Lisbon.")
+        );
+        let sent = &chat.requests()[2].messages;
+        assert_eq!(sent[0].role, Role::System);
+        assert!(sent[0].content.contains("Planning four days in Lisbon."));
+        assert!(!sent.iter().any(|m| m.content == "Plan Lisbon"));
+
+        sessions.send(&mut session, "Book flights").await.unwrap();
+        assert_eq!(
+            session.active().summary.as_deref(),
+            Some("Lisbon in May; flights booked.")
+        );
+        assert_eq!(session.active().turns, 4);
+        assert_eq!(session.context().len(), 1 + 2);
+        // Nothing is removed from the session itself
+        assert_eq!(session.export().len(), 8);
+
+        // One title, two summaries; the second builds on the first
+        let asked = cheap.requests();
+        assert_eq!(asked.len(), 3);
+        assert_eq!(asked[0].system.as_deref(), Some(TITLE_PROMPT));
+        let second = &asked[2].messages[0].content;
+        assert!(second.contains("Summary so far:\nPlanning four days in Lisbon."));
+        assert!(second.contains("user: In May") && !second.contains("Plan Lisbon"));
+    }
+
+    #[tokio::test]
+    async fn test_disabled_features_leave_the_session_alone() {
+        let (_, cheap, sessions) = session_agent(SessionConfig {
+            truncation: Truncation::SummarizeOldest { keep_recent: 2 },
+            ..SessionConfig::default()
+        });
+        let mut session = Session::new("s1");
+        for prompt in ["one", "two", "three", "four"] {
+            sessions.send(&mut session, prompt).await.unwrap();
+        }
+
+        assert!(cheap.requests().is_empty());
+        assert_eq!(session.title, None);
+        assert_eq!(session.active().summary, None);
+        assert_eq!(session.context().len(), 8);
+    }
+
+    #[tokio::test]
+    async fn test_session_round_trips_through_a_file() {
+        let (_, cheap, sessions) = session_agent(SessionConfig {
+            auto_title: true,
+            ..SessionConfig::default()
+        });
+        cheap.push_response(NormalizedResponse::text("Greetings"));
+        let mut session = Session::new("s1");
+        sessions.send(&mut session, "hello").await.unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("s1.json");
+        session.save(&path).unwrap();
+        let json: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(json["title"], "Greetings");
+        assert_eq!(Session::load(&path).unwrap(), session);
+    }
+
+    #[test]
+    fn test_truncation_keeps_whole_exchanges() {
+        let mut session = Session::new("s1");
+        session.append([
+            ChatMessage::user("weather?"),
+            ChatMessage::assistant(""),
+            ChatMessage::tool("call_1", "sunny"),
+            ChatMessage::assistant("Sunny."),
+            ChatMessage::user("thanks"),
+            ChatMessage::assistant("You're welcome."),
+        ]);
+        session.active_mut().summarized = 6;
+        session.truncate(1);
+        assert_eq!(session.context()[0].content, "thanks");
+        assert_eq!(session.active().dropped, 4);
+    }
+
+    /// Contents of the messages of `request`
+    fn sent(request: &ChatRequest) -> Vec<&str> {
+        request
+            .messages
+            .iter()
+            .map(|message| message.content.as_str())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_branches_send_only_their_ancestry() {
+        let (chat, _, sessions) = session_agent(SessionConfig::default());
+        for answer in ["A1", "A2", "A3", "A4", "A5"] {
+            chat.push_response(NormalizedResponse::text(answer));
+        }
+        let mut session = Session::new("s1");
+        sessions.send(&mut session, "Q1").await.unwrap();
+        sessions.send(&mut session, "Q2").await.unwrap();
+        let first_answer = session.path(MAIN_BRANCH)[1].id.clone();
+
+        // Edit Q2: branch after A1 and send the new prompt there
+        let edited = session.branch_from(&first_answer).unwrap();
+        assert_eq!(session.active_branch, edited);
+        sessions.send(&mut session, "Q2, edited").await.unwrap();
+        let fresh = session.branch_from_start();
+        sessions.send(&mut session, "Q0").await.unwrap();
+        sessions
+            .send_on(&mut session, MAIN_BRANCH, "Q3")
+            .await
+            .unwrap();
+
+        let requests = chat.requests();
+        assert_eq!(sent(&requests[2]), ["Q1", "A1", "Q2, edited"]);
+        assert_eq!(sent(&requests[3]), ["Q0"]);
+        assert_eq!(sent(&requests[4]), ["Q1", "A1", "Q2", "A2", "Q3"]);
+
+        // The branches share the messages of their common ancestry
+        assert_eq!(session.messages.len(), 10);
+        assert_eq!(session.path(&edited)[1].id, first_answer);
+        assert_eq!(session.path(&edited).len(), 4);
+        assert_eq!(session.branch(&fresh).unwrap().turns, 1);
+        assert_eq!(session.active_branch, MAIN_BRANCH);
+        assert_eq!(session.export().len(), 6);
+
+        assert!(session.branch_from("m99").is_err());
+        let err = sessions.send_on(&mut session, "b9", "Q").await.unwrap_err();
+        assert!(err.to_string().contains("no branch 'b9'"), "{}", err);
+    }
+
+    #[test]
+    fn test_linear_sessions_are_migrated() {
+        let linear = serde_json::json!({
+            "id": "s1",
+            "title": "Lisbon trip",
+            "summary": "Planning four days in Lisbon.",
+            "history": [
+                {"role": "user", "content": "In May"},
+                {"role": "assistant", "content": "May is mild."},
+            ],
+            "turns": 2,
+            "summarized": 0,
+            "dropped": 2,
+        });
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("s1.json");
+        std::fs::write(&path, linear.to_string()).unwrap();
+
+        let mut session = Session::load(&path).unwrap();
+        assert_eq!(session.version, SESSION_VERSION);
+        assert_eq!(session.title.as_deref(), Some("Lisbon trip"));
+        assert_eq!(session.active().turns, 2);
+        let main = session.path(MAIN_BRANCH);
+        assert_eq!(
+            (main[0].parent.as_deref(), main[1].parent.as_deref()),
+            (None, Some("m1"))
+        );
+        // The dropped exchange is still represented by the summary
+        let context = session.context();
+        assert!(context[0]
+            .content
+            .ends_with("Planning four days in Lisbon."));
+        assert_eq!(context[1].content, "In May");
+
+        session.branch_from("m1").unwrap();
+        session.save(&path).unwrap();
+        let json: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(json["version"], SESSION_VERSION);
+        assert_eq!(json["messages"][1]["parent"], "m1");
+        assert_eq!(Session::load(&path).unwrap(), session);
+
+        std::fs::write(&path, r#"{"version": 3, "id": "s1"}"#).unwrap();
+        let err = Session::load(&path).unwrap_err();
+        assert!(format!("{:#}", err).contains("version 3"), "{:#}", err);
+    }
+}
@@ -0,0 +1,274 @@
+//! Streaming completions to axum SSE clients
+//!
+//! [`sse_bridge`] forwards a [`ChatStream`] to an [`Sse`] response through a
+//! bounded channel, so a slow client can't make chunks pile up in memory.
+//! When the channel is full the bridge either stops pulling from the provider
+//! until the client catches up ([`OverflowPolicy::Pause`]) or discards chunks
+//! ([`OverflowPolicy::Drop`]). Heartbeat comments keep idle connections open
+//! through proxies. When the client disconnects, the provider stream is
+//! dropped, which cancels the upstream request.
+
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use futures::stream::{Stream, StreamExt};
+use serde::Serialize;
+use serde_json::json;
+use std::convert::Infallible;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio::task::JoinHandle;
+
+use crate::provider::{ChatStream, NormalizedUsage, StreamChunk, ToolCall};
+
+/// What to do with a chunk when the client is `buffer` events behind
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Stop reading from the provider until there is room
+    #[default]
+    Pause,
+    /// Discard the chunk; the final `done` event reports how many were lost
+    Drop,
+}
+
+/// Settings for [`sse_bridge`]
+#[derive(Debug, Clone)]
+pub struct SseBridgeConfig {
+    /// Events buffered for the client; at least one
+    pub buffer: usize,
+    pub overflow: OverflowPolicy,
+    /// Interval of `: heartbeat` comments; `None` disables them
+    pub heartbeat: Option<Duration>,
+}
+
+impl Default for SseBridgeConfig {
+    fn default() -> Self {
+        Self {
+            buffer: 32,
+            overflow: OverflowPolicy::Pause,
+            heartbeat: Some(Duration::from_secs(15)),
+        }
+    }
+}
+
+/// An event on its way to the client
+#[derive(Debug, Clone, PartialEq)]
+pub enum BridgeEvent {
+    Text(String),
+    ToolCall(ToolCall),
+    Usage(NormalizedUsage),
+    /// The provider stream failed; nothing follows
+    Error(String),
+    /// The provider stream ended
+    Done {
+        dropped: usize,
+    },
+}
+
+impl BridgeEvent {
+    /// SSE event named after the variant, with JSON data
+    pub fn to_event(&self) -> Event {
+        match self {
+            Self::Text(text) => sse_event("text", &json!({ "text": text })),
+            Self::ToolCall(call) => sse_event("tool_call", call),
+            Self::Usage(usage) => sse_event("usage", usage),
+            Self::Error(message) => sse_event("error", &json!({ "message": message })),
+            Self::Done { dropped } => sse_event("done", &json!({ "dropped": dropped })),
+        }
+    }
+}
+
+fn sse_event(name: &str, data: &impl Serialize) -> Event {
+    // Serialized JSON has no raw line breaks, which SSE data can't carry
+    let data = serde_json::to_string(data).unwrap_or_default();
+    Event::default().event(name).data(data)
+}
+
+/// Stream a completion to an SSE client
+pub fn sse_bridge(stream: ChatStream, config: SseBridgeConfig) -> Response {
+    let heartbeat = config.heartbeat;
+    let events = bridge(stream, config).map(|event| Ok::<_, Infallible>(event.to_event()));
+    let sse = Sse::new(events);
+    match heartbeat {
+        Some(interval) => sse
+            .keep_alive(KeepAlive::new().interval(interval).text("heartbeat"))
+            .into_response(),
+        None => sse.into_response(),
+    }
+}
+
+/// The event stream behind [`sse_bridge`], for hosts that frame events
+/// themselves
+pub fn bridge(stream: ChatStream, config: SseBridgeConfig) -> BridgeStream {
+    let (tx, rx) = mpsc::channel(config.buffer.max(1));
+    let pump = tokio::spawn(pump(stream, tx, config.overflow));
+    BridgeStream { rx, pump }
+}
+
+/// Move chunks from the provider into the channel until either side is done
+async fn pump(mut stream: ChatStream, tx: mpsc::Sender<BridgeEvent>, overflow: OverflowPolicy) {
+    let mut dropped = 0;
+    while let Some(chunk) = stream.next().await {
+        let event = match chunk {
+            Ok(StreamChunk::Text(text)) => BridgeEvent::Text(text),
+            Ok(StreamChunk::ToolCall(call)) => BridgeEvent::ToolCall(call),
+            Ok(StreamChunk::Usage(usage)) => BridgeEvent::Usage(usage),
+            Err(err) => {
+                let _ = tx.send(BridgeEvent::Error(format!("{:#}", err))).await;
+                return;
+            }
+        };
+        let sent = match overflow {
+            OverflowPolicy::Pause => tx.send(event).await.is_ok(),
+            OverflowPolicy::Drop => match tx.try_send(event) {
+                Ok(()) => true,
+                Err(TrySendError::Full(_)) => {
+                    dropped += 1;
+                    true
+                }
+                Err(TrySendError::Closed(_)) => false,
+            },
+        };
+        if !sent {
+            return;
+        }
+    }
+    let _ = tx.send(BridgeEvent::Done { dropped }).await;
+}
+
+/// Events for the client
+///
+/// Dropping this stream, as axum does when the client goes away, stops the
+/// pump and with it the provider stream.
+pub struct BridgeStream {
+    rx: mpsc::Receiver<BridgeEvent>,
+    pump: JoinHandle<()>,
+}
+
+impl Stream for BridgeStream {
+    type Item = BridgeEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<BridgeEvent>> {
+        self.rx.poll_recv(cx)
+    }
+}
+
+impl Drop for BridgeStream {
+    fn drop(&mut self) {
+        self.pump.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::stream;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// A provider stream producing `count` chunks as fast as it is polled
+    fn fast_stream(count: usize, produced: Arc<AtomicUsize>) -> ChatStream {
+        stream::iter(0..count)
+            .map(move |i| {
+                produced.fetch_add(1, Ordering::SeqCst);
+                Ok(StreamChunk::Text(format!("chunk {} ", i)))
+            })
+            .boxed()
+    }
+
+    #[tokio::test]
+    async fn test_pause_keeps_channel_bounded() {
+        let produced = Arc::new(AtomicUsize::new(0));
+        let config = SseBridgeConfig {
+            buffer: 4,
+            ..SseBridgeConfig::default()
+        };
+        let mut events = bridge(fast_stream(200, produced.clone()), config);
+
+        let mut consumed = 0;
+        while let Some(event) = events.next().await {
+            if let BridgeEvent::Done { dropped } = event {
+                assert_eq!(dropped, 0);
+                break;
+            }
+            consumed += 1;
+            // A slow client: give the pump every chance to run ahead
+            tokio::time::sleep(Duration::from_millis(1)).await;
+            let ahead = produced.load(Ordering::SeqCst) - consumed;
+            // The buffer plus the chunk the pump is waiting to send
+            assert!(ahead <= 4 + 1, "{} chunks in flight", ahead);
+        }
+        assert_eq!(consumed, 200);
+    }
+
+    #[tokio::test]
+    async fn test_drop_policy_discards_overflow() {
+        let produced = Arc::new(AtomicUsize::new(0));
+        let config = SseBridgeConfig {
+            buffer: 2,
+            overflow: OverflowPolicy::Drop,
+            ..SseBridgeConfig::default()
+        };
+        let events = bridge(fast_stream(10, produced.clone()), config);
+        // Let the provider finish before the client reads anything
+        while produced.load(Ordering::SeqCst) < 10 {
+            tokio::task::yield_now().await;
+        }
+
+        let events: Vec<BridgeEvent> = events.collect().await;
+        assert_eq!(
+            events,
+            vec![
+                BridgeEvent::Text("chunk 0 ".to_string()),
+                BridgeEvent::Text("chunk 1 ".to_string()),
+                BridgeEvent::Done { dropped: 8 },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_disconnect_cancels_upstream() {
+        struct Cancelled(Arc<AtomicBool>);
+
+        impl Drop for Cancelled {
+            fn drop(&mut self) {
+                self.0.store(true, Ordering::SeqCst);
+            }
+        }
+
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let guard = Cancelled(cancelled.clone());
+        // Endless, like a model that never stops talking
+        let upstream = stream::unfold(guard, |guard| async move {
+            Some((Ok(StreamChunk::Text("more ".to_string())), guard))
+        })
+        .boxed();
+
+        let mut events = bridge(upstream, SseBridgeConfig::default());
+        assert_eq!(
+            events.next().await,
+            Some(BridgeEvent::Text("more ".to_string()))
+        );
+        drop(events);
+
+        for _ in 0..100 {
+            if cancelled.load(Ordering::SeqCst) {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        panic!("provider stream outlived the client");
+    }
+
+    #[test]
+    fn test_events_carry_json() {
+        let event = BridgeEvent::Text("line\nbreak".to_string()).to_event();
+        let rendered = format!("{:?}", event);
+        assert!(
+            rendered.contains(r#"event: text\ndata: {\"text\":\"line\\nbreak\"}"#),
+            "{}",
+            rendered
+        );
+    }
+}
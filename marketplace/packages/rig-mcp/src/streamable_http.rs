@@ -0,0 +1,456 @@
+//! Streamable HTTP transport for MCP
+//!
+//! Every client message is POSTed to the server's endpoint. The server
+//! answers with `202 Accepted`, a JSON body, or an event stream that may
+//! carry notifications and requests of its own before the response. The
+//! `Mcp-Session-Id` the server assigns while initializing is sent with every
+//! later request, as is the `MCP-Protocol-Version` it agreed to.
+//!
+//! A `404` for a request carrying a session id means the server has dropped
+//! the session. Unless `reinitialize` is off, the transport then repeats the
+//! client's `initialize` handshake and sends the request again, so callers
+//! don't notice. An event stream that ends before its response arrives is
+//! resumed with `Last-Event-ID`. The session is ended with `DELETE` when
+//! the rmcp service drops the transport.
+
+use anyhow::{Context, Result};
+use futures::channel::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use futures::StreamExt;
+use reqwest::header::{HeaderMap, HeaderValue, ACCEPT, AUTHORIZATION, CONTENT_TYPE};
+use reqwest::{Method, RequestBuilder, Response, StatusCode};
+use rmcp::model::{ClientJsonRpcMessage, ServerJsonRpcMessage};
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+const SESSION_ID: &str = "mcp-session-id";
+const PROTOCOL_VERSION: &str = "mcp-protocol-version";
+const LAST_EVENT_ID: &str = "last-event-id";
+
+/// Times one request's event stream is resumed before the request fails
+const MAX_RESUMES: usize = 3;
+
+/// JSON-RPC "internal error", reported for requests the transport gave up on
+const INTERNAL_ERROR: i64 = -32603;
+
+/// Open a transport to the streamable HTTP endpoint at `url`, for
+/// [`crate::RmcpServer::serve`]
+///
+/// Nothing is sent until the service sends `initialize`.
+pub fn connect(
+    url: &str, bearer_token: Option<&str>, reinitialize: bool,
+) -> Result<(
+    UnboundedSender<ClientJsonRpcMessage>,
+    UnboundedReceiver<ServerJsonRpcMessage>,
+)> {
+    let mut headers = HeaderMap::new();
+    if let Some(token) = bearer_token {
+        let mut value = HeaderValue::from_str(&format!("Bearer {}", token))
+            .map_err(|_| anyhow::anyhow!("Invalid bearer_token"))?;
+        value.set_sensitive(true);
+        headers.insert(AUTHORIZATION, value);
+    }
+    let client = reqwest::Client::builder()
+        .default_headers(headers)
+        .build()?;
+    let (outgoing, outgoing_rx) = mpsc::unbounded();
+    let (incoming, incoming_rx) = mpsc::unbounded();
+    let session = Arc::new(Session {
+        client,
+        url: url.to_string(),
+        reinitialize,
+        session_id: Mutex::new(None),
+        protocol_version: Mutex::new(None),
+        initialize: Mutex::new(None),
+        reinitializing: tokio::sync::Mutex::new(()),
+        reinitializations: AtomicUsize::new(0),
+        incoming,
+    });
+    tokio::spawn(session.run(outgoing_rx));
+    Ok((outgoing, incoming_rx))
+}
+
+/// State shared by the requests of one connection
+struct Session {
+    client: reqwest::Client,
+    url: String,
+    reinitialize: bool,
+    session_id: Mutex<Option<String>>,
+    protocol_version: Mutex<Option<String>>,
+    /// The service's `initialize` request, repeated when the session expires
+    initialize: Mutex<Option<Value>>,
+    /// Held while a new session is being initialized
+    reinitializing: tokio::sync::Mutex<()>,
+    reinitializations: AtomicUsize,
+    incoming: UnboundedSender<ServerJsonRpcMessage>,
+}
+
+impl Session {
+    /// Send the service's messages one at a time, so the server sees them in
+    /// order, and read each answer in its own task
+    async fn run(self: Arc<Self>, mut outgoing: UnboundedReceiver<ClientJsonRpcMessage>) {
+        while let Some(message) = outgoing.next().await {
+            let message = match serde_json::to_value(&message) {
+                Ok(message) => message,
+                Err(err) => {
+                    tracing::warn!(error = %err, "Could not serialize an MCP message");
+                    continue;
+                }
+            };
+            if message["method"] == "initialize" {
+                *self.initialize.lock().unwrap() = Some(message.clone());
+            }
+            // Only requests wait for an answer; responses and notifications
+            // carry no method or no id
+            let id = message.get("method").and(message.get("id")).cloned();
+            match self.post(&message).await {
+                Ok(response) => {
+                    tokio::spawn(self.clone().receive(response, id));
+                }
+                Err(err) => match id {
+                    Some(id) => self.fail(&id, &format!("{:#}", err)),
+                    None => {
+                        tracing::warn!(error = %format!("{:#}", err), "MCP message not delivered")
+                    }
+                },
+            }
+        }
+        if self.session_id().is_some() {
+            let _ = self.request(Method::DELETE).send().await;
+        }
+    }
+
+    fn session_id(&self) -> Option<String> {
+        self.session_id.lock().unwrap().clone()
+    }
+
+    fn request(&self, method: Method) -> RequestBuilder {
+        let mut request = self
+            .client
+            .request(method, &self.url)
+            .header(ACCEPT, "application/json, text/event-stream");
+        if let Some(session_id) = self.session_id() {
+            request = request.header(SESSION_ID, session_id);
+        }
+        if let Some(version) = self.protocol_version.lock().unwrap().clone() {
+            request = request.header(PROTOCOL_VERSION, version);
+        }
+        request
+    }
+
+    async fn send(&self, message: &Value) -> Result<Response> {
+        let response = self
+            .request(Method::POST)
+            .header(CONTENT_TYPE, "application/json")
+            .body(message.to_string())
+            .send()
+            .await
+            .with_context(|| format!("No answer from MCP server at {}", self.url))?;
+        self.accept(response)
+    }
+
+    /// Fail on error statuses but 404, and take note of a session id
+    fn accept(&self, response: Response) -> Result<Response> {
+        let status = response.status();
+        anyhow::ensure!(
+            status.is_success() || status == StatusCode::NOT_FOUND,
+            "MCP server at {} returned HTTP {}",
+            self.url,
+            status
+        );
+        if let Some(session_id) = response
+            .headers()
+            .get(SESSION_ID)
+            .and_then(|value| value.to_str().ok())
+        {
+            *self.session_id.lock().unwrap() = Some(session_id.to_string());
+        }
+        Ok(response)
+    }
+
+    /// POST `message`, initializing a new session first if the current one
+    /// has expired
+    async fn post(&self, message: &Value) -> Result<Response> {
+        let session_id = self.session_id();
+        let mut response = self.send(message).await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            let expired = session_id
+                .filter(|_| message["method"] != "initialize")
+                .with_context(|| format!("MCP server at {} returned HTTP 404", self.url))?;
+            anyhow::ensure!(
+                self.reinitialize,
+                "MCP session {} has expired and reinitialize is off",
+                expired
+            );
+            self.reinitialize(&expired).await?;
+            response = self.send(message).await?;
+            anyhow::ensure!(
+                response.status() != StatusCode::NOT_FOUND,
+                "MCP server at {} returned HTTP 404 for a new session",
+                self.url
+            );
+        }
+        Ok(response)
+    }
+
+    /// Repeat the handshake for a new session, unless another request
+    /// already has
+    async fn reinitialize(&self, expired: &str) -> Result<()> {
+        let _guard = self.reinitializing.lock().await;
+        if self.session_id().as_deref() != Some(expired) {
+            return Ok(());
+        }
+        let mut initialize = self
+            .initialize
+            .lock()
+            .unwrap()
+            .clone()
+            .context("MCP session expired before it was initialized")?;
+        let attempt = self.reinitializations.fetch_add(1, Ordering::Relaxed) + 1;
+        let id = json!(format!("rig-mcp-reinitialize-{}", attempt));
+        initialize["id"] = id.clone();
+        tracing::info!(
+            url = %self.url,
+            session = expired,
+            "MCP session expired, initializing a new one"
+        );
+
+        *self.session_id.lock().unwrap() = None;
+        let response = self.send(&initialize).await?;
+        anyhow::ensure!(
+            response.status() != StatusCode::NOT_FOUND,
+            "MCP server at {} returned HTTP 404 for initialize",
+            self.url
+        );
+        let answer = self.response_to(response, &id).await?;
+        if let Some(error) = answer.get("error") {
+            anyhow::bail!("MCP server refused to initialize a new session: {}", error);
+        }
+        self.negotiated(&answer);
+        let initialized = json!({"jsonrpc": "2.0", "method": "notifications/initialized"});
+        self.send(&initialized).await?;
+        Ok(())
+    }
+
+    /// Read the answer to `id` from `response`, passing anything else the
+    /// server sends on to the service
+    async fn response_to(&self, response: Response, id: &Value) -> Result<Value> {
+        if !is_event_stream(&response) {
+            let body = response.text().await?;
+            let mut answer = None;
+            for message in parse_messages(&body)? {
+                if is_response(&message, id) {
+                    answer = Some(message);
+                } else {
+                    self.forward(message);
+                }
+            }
+            return answer.context("MCP server didn't answer initialize");
+        }
+        let mut events = EventStream::new(response);
+        while let Some(message) = events.next().await? {
+            if is_response(&message, id) {
+                return Ok(message);
+            }
+            self.forward(message);
+        }
+        anyhow::bail!("MCP server didn't answer initialize")
+    }
+
+    /// Pass the messages of `response` on to the service; for a request,
+    /// make sure its answer or an error arrives
+    async fn receive(self: Arc<Self>, response: Response, id: Option<Value>) {
+        if response.status() == StatusCode::ACCEPTED {
+            return;
+        }
+        if !is_event_stream(&response) {
+            let messages = match response.text().await {
+                Ok(body) => parse_messages(&body),
+                Err(err) => Err(err.into()),
+            };
+            match (messages, id) {
+                (Ok(messages), _) => messages.into_iter().for_each(|m| self.forward(m)),
+                (Err(err), Some(id)) => self.fail(&id, &format!("{:#}", err)),
+                (Err(err), None) => {
+                    tracing::warn!(error = %format!("{:#}", err), "Unreadable MCP response")
+                }
+            }
+            return;
+        }
+
+        let mut events = EventStream::new(response);
+        let mut resumes = 0;
+        loop {
+            let ended = match events.next().await {
+                Ok(Some(message)) => {
+                    let answered = id.as_ref().is_some_and(|id| is_response(&message, id));
+                    self.forward(message);
+                    if answered {
+                        return;
+                    }
+                    continue;
+                }
+                Ok(None) => "the event stream ended before the response".to_string(),
+                Err(err) => format!("{:#}", err),
+            };
+            let Some(id) = &id else {
+                return;
+            };
+            let resumed = match events.last_event_id.clone() {
+                Some(last) if resumes < MAX_RESUMES => {
+                    resumes += 1;
+                    self.resume(&last).await
+                }
+                _ => Err(anyhow::anyhow!(ended)),
+            };
+            match resumed {
+                Ok(response) => events.continue_with(response),
+                Err(err) => return self.fail(id, &format!("{:#}", err)),
+            }
+        }
+    }
+
+    /// Pick an interrupted event stream up after `last_event_id`
+    async fn resume(&self, last_event_id: &str) -> Result<Response> {
+        let response = self
+            .request(Method::GET)
+            .header(LAST_EVENT_ID, last_event_id)
+            .send()
+            .await
+            .with_context(|| format!("No answer from MCP server at {}", self.url))?;
+        let response = self.accept(response)?;
+        anyhow::ensure!(
+            is_event_stream(&response),
+            "MCP server at {} can't resume event streams",
+            self.url
+        );
+        Ok(response)
+    }
+
+    /// Remember the protocol version of an `initialize` result
+    fn negotiated(&self, message: &Value) {
+        if message.pointer("/result/serverInfo").is_none() {
+            return;
+        }
+        if let Some(version) = message
+            .pointer("/result/protocolVersion")
+            .and_then(Value::as_str)
+        {
+            tracing::debug!(url = %self.url, version, "Negotiated MCP protocol version");
+            *self.protocol_version.lock().unwrap() = Some(version.to_string());
+        }
+    }
+
+    fn forward(&self, message: Value) {
+        self.negotiated(&message);
+        match serde_json::from_value::<ServerJsonRpcMessage>(message) {
+            Ok(message) => {
+                let _ = self.incoming.unbounded_send(message);
+            }
+            Err(err) => tracing::warn!(error = %err, "Ignoring an invalid MCP message"),
+        }
+    }
+
+    /// Answer request `id` with an error, so the service doesn't wait for a
+    /// response that won't come
+    fn fail(&self, id: &Value, reason: &str) {
+        self.forward(json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": { "code": INTERNAL_ERROR, "message": reason },
+        }));
+    }
+}
+
+fn is_event_stream(response: &Response) -> bool {
+    response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/event-stream"))
+}
+
+fn is_response(message: &Value, id: &Value) -> bool {
+    message.get("id") == Some(id)
+        && (message.get("result").is_some() || message.get("error").is_some())
+}
+
+/// A JSON body: one message, a batch, or nothing
+fn parse_messages(body: &str) -> Result<Vec<Value>> {
+    if body.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    match serde_json::from_str(body).context("MCP response is not JSON")? {
+        Value::Array(batch) => Ok(batch),
+        message => Ok(vec![message]),
+    }
+}
+
+/// JSON-RPC messages of a `text/event-stream` body
+struct EventStream {
+    response: Response,
+    buffer: Vec<u8>,
+    pending: VecDeque<Value>,
+    last_event_id: Option<String>,
+}
+
+impl EventStream {
+    fn new(response: Response) -> Self {
+        Self {
+            response,
+            buffer: Vec::new(),
+            pending: VecDeque::new(),
+            last_event_id: None,
+        }
+    }
+
+    /// Read on from a resumed stream
+    fn continue_with(&mut self, response: Response) {
+        self.response = response;
+        self.buffer.clear();
+    }
+
+    /// The next message, `None` once the stream has ended
+    async fn next(&mut self) -> Result<Option<Value>> {
+        loop {
+            if let Some(message) = self.pending.pop_front() {
+                return Ok(Some(message));
+            }
+            if let Some(end) = self.buffer.windows(2).position(|w| w == b"\n\n") {
+                let event: Vec<u8> = self.buffer.drain(..end + 2).collect();
+                self.parse(&String::from_utf8_lossy(&event[..end]));
+                continue;
+            }
+            match self.response.chunk().await? {
+                // Lines may end in CRLF; dropping every CR leaves LF
+                Some(chunk) => self.buffer.extend(chunk.iter().filter(|&&b| b != b'\r')),
+                None => return Ok(None),
+            }
+        }
+    }
+
+    fn parse(&mut self, event: &str) {
+        let mut data = Vec::new();
+        let mut kind = "message";
+        for line in event.lines() {
+            let (field, value) = line.split_once(':').unwrap_or((line, ""));
+            let value = value.strip_prefix(' ').unwrap_or(value);
+            match field {
+                "data" => data.push(value),
+                "id" => self.last_event_id = Some(value.to_string()),
+                "event" => kind = value,
+                // Comments, used as keep-alives, have no field name
+                _ => {}
+            }
+        }
+        if kind != "message" || data.is_empty() {
+            return;
+        }
+        match serde_json::from_str(&data.join("\n")) {
+            Ok(Value::Array(batch)) => self.pending.extend(batch),
+            Ok(message) => self.pending.push_back(message),
+            Err(err) => tracing::warn!(error = %err, "Ignoring an MCP event that isn't JSON"),
+        }
+    }
+}
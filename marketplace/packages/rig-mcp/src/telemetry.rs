@@ -0,0 +1,305 @@
+//! OpenTelemetry trace export
+//!
+//! [`init`] sends this process's `tracing` spans to an OTLP/HTTP collector.
+//! Span fields, such as the `provider` and `model` of every completion or a
+//! host's `request_id`, become span attributes. Hosts with their own
+//! subscriber stack add [`layer`] to it instead and keep the
+//! [`TelemetryGuard`] alive until exit; dropping it flushes pending spans.
+
+use anyhow::{Context, Result};
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_otlp::{WithExportConfig, WithHttpConfig};
+use opentelemetry_sdk::trace::{Sampler, SdkTracerProvider, SpanExporter, Tracer};
+use opentelemetry_sdk::Resource;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tracing::Subscriber;
+use tracing_opentelemetry::OpenTelemetryLayer;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+
+/// Where and what to export
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TelemetryConfig {
+    /// OTLP/HTTP traces endpoint
+    pub endpoint: String,
+    /// Sent with every export, e.g. an API key for a hosted collector
+    pub headers: HashMap<String, String>,
+    pub service_name: String,
+    /// Fraction of new traces to keep, 0.0 to 1.0; child spans follow their
+    /// parent's decision
+    pub sample_ratio: f64,
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        Self {
+            endpoint: "http://localhost:4318/v1/traces".to_string(),
+            headers: HashMap::new(),
+            service_name: "rig-mcp".to_string(),
+            sample_ratio: 1.0,
+        }
+    }
+}
+
+impl From<&ggen_config::TelemetrySettings> for TelemetryConfig {
+    /// `rig-mcp` is the service name unless the settings give one
+    fn from(settings: &ggen_config::TelemetrySettings) -> Self {
+        Self {
+            endpoint: settings.endpoint.clone(),
+            headers: settings.headers.clone(),
+            service_name: settings
+                .service_name
+                .clone()
+                .unwrap_or_else(|| Self::default().service_name),
+            sample_ratio: settings.sample_ratio,
+        }
+    }
+}
+
+impl TelemetryConfig {
+    /// Configuration from the standard `OTEL_*` variables, if an endpoint is
+    /// set
+    pub fn from_env() -> Option<Self> {
+        std::env::var_os("OTEL_EXPORTER_OTLP_ENDPOINT")?;
+        Some(Self::default().with_env())
+    }
+
+    /// Override fields with the standard `OTEL_*` variables that are set
+    ///
+    /// Reads `OTEL_EXPORTER_OTLP_ENDPOINT`, `OTEL_EXPORTER_OTLP_HEADERS`
+    /// (`key=value,key=value`), `OTEL_SERVICE_NAME` and
+    /// `OTEL_TRACES_SAMPLER_ARG`.
+    pub fn with_env(self) -> Self {
+        self.overlay(|name| std::env::var(name).ok())
+    }
+
+    fn overlay(mut self, var: impl Fn(&str) -> Option<String>) -> Self {
+        if let Some(endpoint) = var("OTEL_EXPORTER_OTLP_ENDPOINT") {
+            // The generic variable names the collector, not the traces path
+            self.endpoint = format!("{}/v1/traces", endpoint.trim_end_matches('/'));
+        }
+        if let Some(headers) = var("OTEL_EXPORTER_OTLP_HEADERS") {
+            for pair in headers.split(',') {
+                if let Some((key, value)) = pair.split_once('=') {
+                    self.headers
+                        .insert(key.trim().to_string(), value.trim().to_string());
+                }
+            }
+        }
+        if let Some(name) = var("OTEL_SERVICE_NAME") {
+            self.service_name = name;
+        }
+        if let Some(ratio) = var("OTEL_TRACES_SAMPLER_ARG").and_then(|r| r.parse().ok()) {
+            self.sample_ratio = ratio;
+        }
+        self
+    }
+}
+
+/// Install a global subscriber that exports spans and prints them as `fmt`
+/// does
+pub fn init(config: &TelemetryConfig) -> Result<TelemetryGuard> {
+    let provider = tracer_provider(config, exporter(config)?);
+    tracing_subscriber::registry()
+        .with(layer(&provider))
+        .with(tracing_subscriber::fmt::layer())
+        .try_init()
+        .context("A tracing subscriber is already installed")?;
+    opentelemetry::global::set_tracer_provider(provider.clone());
+    Ok(TelemetryGuard::new(provider))
+}
+
+/// OTLP/HTTP exporter for `config`
+pub fn exporter(config: &TelemetryConfig) -> Result<opentelemetry_otlp::SpanExporter> {
+    opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .with_endpoint(&config.endpoint)
+        .with_headers(config.headers.clone())
+        .build()
+        .with_context(|| format!("Invalid OTLP endpoint: {}", config.endpoint))
+}
+
+/// Tracer provider batching spans into `exporter`
+pub fn tracer_provider(
+    config: &TelemetryConfig, exporter: impl SpanExporter + 'static,
+) -> SdkTracerProvider {
+    let sampler = Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(config.sample_ratio)));
+    SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_sampler(sampler)
+        .with_resource(
+            Resource::builder()
+                .with_service_name(config.service_name.clone())
+                .build(),
+        )
+        .build()
+}
+
+/// Subscriber layer exporting spans through `provider`
+pub fn layer<S>(provider: &SdkTracerProvider) -> OpenTelemetryLayer<S, Tracer>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    tracing_opentelemetry::layer().with_tracer(provider.tracer("rig-mcp"))
+}
+
+/// Flushes and shuts down the tracer provider when dropped
+pub struct TelemetryGuard {
+    provider: Option<SdkTracerProvider>,
+}
+
+impl TelemetryGuard {
+    pub fn new(provider: SdkTracerProvider) -> Self {
+        Self {
+            provider: Some(provider),
+        }
+    }
+
+    /// Export pending spans and stop the exporter
+    pub fn shutdown(mut self) -> Result<()> {
+        self.finish()
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        match self.provider.take() {
+            Some(provider) => provider.shutdown().context("Failed to flush pending spans"),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for TelemetryGuard {
+    fn drop(&mut self) {
+        if let Err(err) = self.finish() {
+            eprintln!("{:#}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::{ChatMessage, ChatRequest, Provider};
+    use crate::testing::MockTransport;
+    use crate::wire::HttpProvider;
+    use crate::ProviderConfig;
+    use opentelemetry::{KeyValue, Value};
+    use opentelemetry_sdk::error::OTelSdkResult;
+    use opentelemetry_sdk::trace::{InMemorySpanExporter, SpanData};
+    use serde_json::json;
+    use std::sync::{Arc, Mutex};
+
+    fn attribute<'a>(span: &'a SpanData, key: &str) -> Option<&'a Value> {
+        span.attributes
+            .iter()
+            .find(|KeyValue { key: k, .. }| k.as_str() == key)
+            .map(|kv| &kv.value)
+    }
+
+    #[tokio::test]
+    async fn test_request_spans_carry_attributes() {
+        let exporter = InMemorySpanExporter::default();
+        let provider = tracer_provider(&TelemetryConfig::default(), exporter.clone());
+        let subscriber = tracing_subscriber::registry().with(layer(&provider));
+        let _default = tracing::subscriber::set_default(subscriber);
+
+        let transport = Arc::new(MockTransport::new());
+        transport.push_json(
+            200,
+            json!({
+                "choices": [{"message": {"content": "hi"}, "finish_reason": "stop"}],
+                "usage": {"prompt_tokens": 7, "completion_tokens": 1}
+            }),
+        );
+        let config = ProviderConfig {
+            name: "openai".to_string(),
+            model: "gpt-4o".to_string(),
+            api_key: Some("sk-test".to_string()),
+            ..ProviderConfig::default()
+        };
+        let openai = HttpProvider::new(&config, transport).unwrap();
+        let request = ChatRequest {
+            messages: vec![ChatMessage::user("hello")],
+            ..ChatRequest::default()
+        };
+        {
+            let span = tracing::info_span!("request", request_id = "req-42", tenant = "acme");
+            let _entered = span.enter();
+            openai.complete(request).await.unwrap();
+        }
+        provider.force_flush().unwrap();
+
+        let spans = exporter.get_finished_spans().unwrap();
+        let completion = spans
+            .iter()
+            .find(|s| s.name == "provider.complete")
+            .unwrap();
+        let parent = spans.iter().find(|s| s.name == "request").unwrap();
+        assert_eq!(attribute(completion, "provider"), Some(&"openai".into()));
+        assert_eq!(attribute(completion, "model"), Some(&"gpt-4o".into()));
+        assert_eq!(
+            attribute(completion, "http.status_code"),
+            Some(&200i64.into())
+        );
+        assert_eq!(attribute(completion, "prompt_tokens"), Some(&7i64.into()));
+        assert_eq!(attribute(parent, "request_id"), Some(&"req-42".into()));
+        assert_eq!(attribute(parent, "tenant"), Some(&"acme".into()));
+        assert_eq!(completion.parent_span_id, parent.span_context.span_id());
+    }
+
+    /// Keeps exported spans past shutdown, unlike the in-memory exporter
+    #[derive(Debug, Default, Clone)]
+    struct Recorder(Arc<Mutex<Vec<SpanData>>>);
+
+    impl SpanExporter for Recorder {
+        async fn export(&self, batch: Vec<SpanData>) -> OTelSdkResult {
+            self.0.lock().unwrap().extend(batch);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_shutdown_flushes_pending_spans() {
+        let recorder = Recorder::default();
+        let provider = tracer_provider(&TelemetryConfig::default(), recorder.clone());
+        let guard = TelemetryGuard::new(provider.clone());
+        let subscriber = tracing_subscriber::registry().with(layer(&provider));
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("request", request_id = "req-7").in_scope(|| {});
+        });
+        // The batch processor holds the span until its next scheduled export
+        assert!(recorder.0.lock().unwrap().is_empty());
+
+        guard.shutdown().unwrap();
+        let spans = recorder.0.lock().unwrap();
+        assert_eq!(spans.len(), 1);
+        assert_eq!(spans[0].name, "request");
+    }
+
+    #[test]
+    fn test_env_overrides_config() {
+        let env: HashMap<&str, &str> = [
+            ("OTEL_EXPORTER_OTLP_ENDPOINT", "https://otel.example.com/"),
+            (
+                "OTEL_EXPORTER_OTLP_HEADERS",
+                "x-api-key=secret, x-team = llm",
+            ),
+            ("OTEL_TRACES_SAMPLER_ARG", "0.25"),
+        ]
+        .into();
+        let config = TelemetryConfig {
+            service_name: "ai-microservice".to_string(),
+            ..TelemetryConfig::default()
+        }
+        .overlay(|name| env.get(name).map(|v| v.to_string()));
+
+        assert_eq!(config.endpoint, "https://otel.example.com/v1/traces");
+        assert_eq!(config.headers["x-api-key"], "secret");
+        assert_eq!(config.headers["x-team"], "llm");
+        assert_eq!(config.service_name, "ai-microservice");
+        assert_eq!(config.sample_ratio, 0.25);
+    }
+}
@@ -0,0 +1,250 @@
+//! Fault injection for the test doubles
+//!
+//! A [`FaultPlan`] decides what each call to a double does: succeed, fail
+//! with an HTTP status, refuse a request as too long, time out, hang, drop
+//! the connection or return a body that isn't JSON. Scripted faults are
+//! used first, in order; after that every call draws from the plan's
+//! chances, using a seeded generator so a failing run can be replayed.
+//! Failures are the errors the real providers produce (see
+//! [`crate::transport::is_retriable`]), so the code under test can't tell
+//! them apart.
+
+use anyhow::{Context, Result};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::Instant;
+
+use crate::deterministic::SplitMix64;
+use crate::transport::{HttpStatusError, Unreachable};
+
+/// OpenAI's answer to a request longer than the model's context
+const CONTEXT_LENGTH_EXCEEDED: &str = concat!(
+    r#"{"error":{"message":"This model's maximum context length is 8192 tokens.","#,
+    r#""type":"invalid_request_error","code":"context_length_exceeded"}}"#
+);
+
+/// What a double does with one call
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fault {
+    Succeed,
+    /// Answer with a non-2xx status
+    FailWithStatus(u16),
+    /// Answer 400 because the request is longer than the model's context
+    ContextLengthExceeded,
+    /// Give up after the duration, like a client-side request timeout
+    TimeoutAfter(Duration),
+    /// Never answer
+    Hang,
+    /// Drop the connection before answering
+    Disconnect,
+    /// Answer 200 with a body that isn't JSON
+    CorruptJson,
+}
+
+impl Fault {
+    /// Anything but success; a hung call never returns at all
+    pub fn is_failure(&self) -> bool {
+        *self != Fault::Succeed
+    }
+}
+
+/// How long a call takes before its outcome
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Latency {
+    #[default]
+    None,
+    Fixed(Duration),
+    /// Evenly distributed between the bounds, both inclusive
+    Uniform {
+        min: Duration,
+        max: Duration,
+    },
+}
+
+/// Outcomes for the calls to a double
+///
+/// ```
+/// use rig_mcp_integration::testing::{Fault, FaultPlan, Latency};
+/// use std::time::Duration;
+///
+/// // Two 503s, then a dropped connection, then a 10% chance of a 429
+/// let plan = FaultPlan::seeded(7)
+///     .repeat(Fault::FailWithStatus(503), 2)
+///     .then(Fault::Disconnect)
+///     .with_chance(Fault::FailWithStatus(429), 0.1)
+///     .latency(Latency::Fixed(Duration::from_millis(5)));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct FaultPlan {
+    script: VecDeque<Fault>,
+    chances: Vec<(Fault, f64)>,
+    latency: Latency,
+    rng: SplitMix64,
+}
+
+impl FaultPlan {
+    /// Every call succeeds
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Calls get `faults` in order, then succeed
+    pub fn script(faults: impl IntoIterator<Item = Fault>) -> Self {
+        Self {
+            script: faults.into_iter().collect(),
+            ..Self::default()
+        }
+    }
+
+    /// Draw chances and latencies from a generator seeded with `seed`
+    pub fn seeded(seed: u64) -> Self {
+        Self {
+            rng: SplitMix64::new(seed),
+            ..Self::default()
+        }
+    }
+
+    /// Append a scripted outcome
+    pub fn then(mut self, fault: Fault) -> Self {
+        self.script.push_back(fault);
+        self
+    }
+
+    /// Append `times` scripted copies of `fault`
+    pub fn repeat(mut self, fault: Fault, times: usize) -> Self {
+        self.script.extend(std::iter::repeat_n(fault, times));
+        self
+    }
+
+    /// Once the script is used up, fail with `fault` at the given
+    /// probability
+    ///
+    /// Chances are tried in the order they were added; calls drawing none of
+    /// them succeed.
+    pub fn with_chance(mut self, fault: Fault, probability: f64) -> Self {
+        self.chances.push((fault, probability.clamp(0.0, 1.0)));
+        self
+    }
+
+    pub fn latency(mut self, latency: Latency) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    /// Latency and outcome of the next call
+    fn next(&mut self) -> (Duration, Fault) {
+        let latency = match self.latency {
+            Latency::None => Duration::ZERO,
+            Latency::Fixed(latency) => latency,
+            Latency::Uniform { min, max } if max <= min => min,
+            Latency::Uniform { min, max } => {
+                let span = (max - min).as_micros() as u64 + 1;
+                min + Duration::from_micros(self.rng.next_u64() % span)
+            }
+        };
+        if let Some(fault) = self.script.pop_front() {
+            return (latency, fault);
+        }
+        let mut draw = self.rng.next_f64();
+        for (fault, probability) in &self.chances {
+            if draw < *probability {
+                return (latency, fault.clone());
+            }
+            draw -= probability;
+        }
+        (latency, Fault::Succeed)
+    }
+}
+
+/// One call to a double, as seen by [`FaultPlan`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FaultEvent {
+    /// 1 for the first call
+    pub call: usize,
+    /// The tool called, or the model for providers and embedding models
+    pub target: String,
+    pub fault: Fault,
+    pub latency: Duration,
+    /// When the call arrived, counted from the creation of the double
+    pub at: Duration,
+}
+
+/// A plan and the calls it was applied to
+#[derive(Debug)]
+pub(crate) struct Injector {
+    plan: Mutex<FaultPlan>,
+    timeline: Mutex<Vec<FaultEvent>>,
+    created: Instant,
+}
+
+impl Default for Injector {
+    fn default() -> Self {
+        Self::new(FaultPlan::new())
+    }
+}
+
+impl Injector {
+    pub(crate) fn new(plan: FaultPlan) -> Self {
+        Self {
+            plan: Mutex::new(plan),
+            timeline: Mutex::new(Vec::new()),
+            created: Instant::now(),
+        }
+    }
+
+    pub(crate) fn timeline(&self) -> Vec<FaultEvent> {
+        self.timeline.lock().unwrap().clone()
+    }
+
+    /// Wait out the next call's latency, then fail the way `service` would,
+    /// or return to let the double answer
+    pub(crate) async fn inject(&self, service: &str, target: &str) -> Result<()> {
+        let (latency, fault) = self.plan.lock().unwrap().next();
+        {
+            let mut timeline = self.timeline.lock().unwrap();
+            let call = timeline.len() + 1;
+            timeline.push(FaultEvent {
+                call,
+                target: target.to_string(),
+                fault: fault.clone(),
+                latency,
+                at: self.created.elapsed(),
+            });
+        }
+        if !latency.is_zero() {
+            tokio::time::sleep(latency).await;
+        }
+        let unreachable = || Unreachable {
+            service: service.to_string(),
+        };
+        match fault {
+            Fault::Succeed => Ok(()),
+            Fault::FailWithStatus(status) => Err(HttpStatusError {
+                service: service.to_string(),
+                status,
+                body: "injected fault".to_string(),
+                rate_limits: None,
+            }
+            .into()),
+            Fault::ContextLengthExceeded => Err(HttpStatusError {
+                service: service.to_string(),
+                status: 400,
+                body: CONTEXT_LENGTH_EXCEEDED.to_string(),
+                rate_limits: None,
+            }
+            .into()),
+            Fault::TimeoutAfter(after) => {
+                tokio::time::sleep(after).await;
+                Err(anyhow::anyhow!("operation timed out after {:?}", after).context(unreachable()))
+            }
+            Fault::Hang => std::future::pending().await,
+            Fault::Disconnect => {
+                Err(anyhow::anyhow!("connection reset by peer").context(unreachable()))
+            }
+            Fault::CorruptJson => serde_json::from_slice::<serde_json::Value>(b"{\"choi")
+                .map(drop)
+                .with_context(|| format!("{} returned invalid JSON", service)),
+        }
+    }
+}
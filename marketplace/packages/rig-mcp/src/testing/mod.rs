@@ -0,0 +1,517 @@
+//! Test doubles
+//!
+//! [`MockProvider`] and [`FakeMcpServer`] stand in for real providers and MCP
+//! servers so agents can run without network access or API keys, and
+//! [`MockTransport`] answers HTTP requests for the real provider code.
+//! [`MockEmbeddingModel`] embeds without a network and [`FixedClock`] makes
+//! timestamps reproducible. The example binary uses [`mock_client`] when
+//! `RIG_MCP_MOCK=1`.
+//!
+//! Providers, servers and embedding models take a [`FaultPlan`] to fail,
+//! stall or drop connections on cue, and record a [`FaultEvent`] per call in
+//! their `timeline()`.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures::stream::{self, StreamExt};
+use serde_json::{json, Value};
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::clock::Clock;
+use crate::embedding::EmbeddingModel;
+use crate::mcp::{ToolInfo, ToolOutput, ToolProgress, ToolServer};
+use crate::provider::{
+    ChatRequest, ChatStream, NormalizedResponse, Provider, Role, StreamChunk, ToolCall,
+};
+use crate::transport::{HttpRequest, HttpResponse, HttpStatusError, HttpTransport};
+use crate::{Config, RigMcpClient};
+
+mod faults;
+
+use faults::Injector;
+pub use faults::{Fault, FaultEvent, FaultPlan, Latency};
+
+/// Provider answering from a script
+///
+/// Queued responses are returned in order. Once the queue is empty the mock
+/// improvises: it calls a tool whose name appears in the latest user message,
+/// reports a tool result it has just received, or echoes the prompt. Streams
+/// are split into word-sized chunks and end with the response's usage.
+pub struct MockProvider {
+    name: String,
+    model: String,
+    script: Mutex<VecDeque<NormalizedResponse>>,
+    requests: Mutex<Vec<ChatRequest>>,
+    next_call_id: AtomicUsize,
+    faults: Injector,
+    stream_usage: bool,
+}
+
+impl MockProvider {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            model: "mock-model".to_string(),
+            script: Mutex::new(VecDeque::new()),
+            requests: Mutex::new(Vec::new()),
+            next_call_id: AtomicUsize::new(1),
+            faults: Injector::default(),
+            stream_usage: true,
+        }
+    }
+
+    /// End streams without a usage chunk, like providers that don't report
+    /// usage when streaming
+    pub fn without_stream_usage(mut self) -> Self {
+        self.stream_usage = false;
+        self
+    }
+
+    /// Report a different model name
+    pub fn with_model(mut self, model: impl Into<String>) -> Self {
+        self.model = model.into();
+        self
+    }
+
+    /// Fail completions as `plan` says; failed calls don't use up queued
+    /// responses
+    pub fn with_faults(mut self, plan: FaultPlan) -> Self {
+        self.faults = Injector::new(plan);
+        self
+    }
+
+    /// Queue a response
+    pub fn push_response(&self, response: NormalizedResponse) {
+        self.script.lock().unwrap().push_back(response);
+    }
+
+    /// Queue a response consisting of a single tool call
+    pub fn push_tool_call(&self, name: &str, arguments: Value) {
+        let call = self.tool_call(name, arguments);
+        self.push_response(NormalizedResponse::tool_calls(vec![call]));
+    }
+
+    /// Every request received so far, failed ones included
+    pub fn requests(&self) -> Vec<ChatRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// Outcome of every request received so far
+    pub fn timeline(&self) -> Vec<FaultEvent> {
+        self.faults.timeline()
+    }
+
+    fn tool_call(&self, name: &str, arguments: Value) -> ToolCall {
+        ToolCall {
+            id: format!("call_{}", self.next_call_id.fetch_add(1, Ordering::Relaxed)),
+            name: name.to_string(),
+            arguments,
+        }
+    }
+
+    fn improvise(&self, request: &ChatRequest) -> NormalizedResponse {
+        let Some(last) = request.messages.last() else {
+            return NormalizedResponse::text("Nothing to respond to.");
+        };
+        if last.role == Role::Tool {
+            let tool = request
+                .messages
+                .iter()
+                .flat_map(|m| &m.tool_calls)
+                .find(|call| Some(&call.id) == last.tool_call_id.as_ref())
+                .map_or("tool", |call| call.name.as_str());
+            return NormalizedResponse::text(format!("Tool `{}` returned: {}", tool, last.content));
+        }
+        let words: Vec<&str> = last
+            .content
+            .split(|c: char| !c.is_alphanumeric() && c != '_')
+            .collect();
+        match request
+            .tools
+            .iter()
+            .find(|t| words.contains(&t.name.as_str()))
+        {
+            Some(tool) => {
+                let call = self.tool_call(&tool.name, json!({ "text": last.content }));
+                NormalizedResponse::tool_calls(vec![call])
+            }
+            None => NormalizedResponse::text(format!("You said: {}", last.content)),
+        }
+    }
+}
+
+#[async_trait]
+impl Provider for MockProvider {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    async fn complete(&self, request: ChatRequest) -> Result<NormalizedResponse> {
+        self.requests.lock().unwrap().push(request.clone());
+        let service = format!("Provider '{}'", self.name);
+        self.faults.inject(&service, &self.model).await?;
+        let scripted = self.script.lock().unwrap().pop_front();
+        Ok(scripted.unwrap_or_else(|| self.improvise(&request)))
+    }
+
+    async fn stream(&self, request: ChatRequest) -> Result<ChatStream> {
+        let response = self.complete(request).await?;
+        let mut chunks: Vec<StreamChunk> = response
+            .content
+            .split_inclusive(' ')
+            .map(|word| StreamChunk::Text(word.to_string()))
+            .collect();
+        chunks.extend(response.tool_calls.into_iter().map(StreamChunk::ToolCall));
+        if self.stream_usage {
+            chunks.push(StreamChunk::Usage(response.usage));
+        }
+        Ok(stream::iter(chunks.into_iter().map(Ok)).boxed())
+    }
+}
+
+/// Handler producing a fake tool's text output from its arguments
+pub type ToolHandler = Box<dyn Fn(&Value) -> Result<String> + Send + Sync>;
+
+/// In-process MCP server with scripted tools
+pub struct FakeMcpServer {
+    name: String,
+    tools: Vec<(ToolInfo, ToolHandler)>,
+    progress: HashMap<String, (usize, Duration)>,
+    calls: Mutex<Vec<(String, Value)>>,
+    faults: Injector,
+}
+
+impl FakeMcpServer {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            tools: Vec::new(),
+            progress: HashMap::new(),
+            calls: Mutex::new(Vec::new()),
+            faults: Injector::default(),
+        }
+    }
+
+    /// Add a tool; handler errors are reported as tool errors
+    pub fn with_tool<F>(
+        mut self, name: &str, description: &str, input_schema: Value, handler: F,
+    ) -> Self
+    where
+        F: Fn(&Value) -> Result<String> + Send + Sync + 'static,
+    {
+        let info = ToolInfo {
+            server: self.name.clone(),
+            name: name.to_string(),
+            description: description.to_string(),
+            input_schema,
+            destructive: false,
+            read_only: false,
+        };
+        self.tools.push((info, Box::new(handler)));
+        self
+    }
+
+    /// Annotate `tool` as destructive
+    pub fn destructive(mut self, tool: &str) -> Self {
+        for (info, _) in self.tools.iter_mut().filter(|(info, _)| info.name == tool) {
+            info.destructive = true;
+        }
+        self
+    }
+
+    /// Annotate `tool` as read-only
+    pub fn read_only(mut self, tool: &str) -> Self {
+        for (info, _) in self.tools.iter_mut().filter(|(info, _)| info.name == tool) {
+            info.read_only = true;
+        }
+        self
+    }
+
+    /// Make `tool` report `steps` progress notifications, one every
+    /// `interval`, before it returns
+    pub fn with_progress(mut self, tool: &str, steps: usize, interval: Duration) -> Self {
+        self.progress.insert(tool.to_string(), (steps, interval));
+        self
+    }
+
+    /// Fail tool calls as `plan` says; listing tools is never affected
+    pub fn with_faults(mut self, plan: FaultPlan) -> Self {
+        self.faults = Injector::new(plan);
+        self
+    }
+
+    /// Every call to a known tool received so far, as `(tool, arguments)`,
+    /// failed ones included
+    pub fn calls(&self) -> Vec<(String, Value)> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    /// Outcome of every call to a known tool received so far
+    pub fn timeline(&self) -> Vec<FaultEvent> {
+        self.faults.timeline()
+    }
+}
+
+#[async_trait]
+impl ToolServer for FakeMcpServer {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn list_tools(&self) -> Result<Vec<ToolInfo>> {
+        Ok(self.tools.iter().map(|(info, _)| info.clone()).collect())
+    }
+
+    async fn call_tool(&self, name: &str, arguments: Value) -> Result<ToolOutput> {
+        let (_, handler) = self
+            .tools
+            .iter()
+            .find(|(info, _)| info.name == name)
+            .ok_or_else(|| {
+                anyhow::anyhow!("Fake MCP server '{}' has no tool '{}'", self.name, name)
+            })?;
+        self.calls
+            .lock()
+            .unwrap()
+            .push((name.to_string(), arguments.clone()));
+        let service = format!("MCP server '{}'", self.name);
+        self.faults.inject(&service, name).await?;
+        Ok(match handler(&arguments) {
+            Ok(content) => ToolOutput::text(content),
+            Err(err) => ToolOutput::error(err.to_string()),
+        })
+    }
+
+    async fn call_tool_with_progress(
+        &self, name: &str, arguments: Value, progress: UnboundedSender<ToolProgress>,
+    ) -> Result<ToolOutput> {
+        if let Some(&(steps, interval)) = self.progress.get(name) {
+            for step in 1..=steps {
+                tokio::time::sleep(interval).await;
+                let _ = progress.send(ToolProgress {
+                    progress: step as f64,
+                    total: Some(steps as f64),
+                    message: Some(format!("step {} of {}", step, steps)),
+                });
+            }
+        }
+        self.call_tool(name, arguments).await
+    }
+}
+
+/// HTTP transport answering from a queue of canned responses
+///
+/// Sending with an empty queue fails like a connection error would.
+#[derive(Default)]
+pub struct MockTransport {
+    responses: Mutex<VecDeque<HttpResponse>>,
+    requests: Mutex<Vec<HttpRequest>>,
+}
+
+impl MockTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue a response
+    pub fn push_response(&self, response: HttpResponse) {
+        self.responses.lock().unwrap().push_back(response);
+    }
+
+    /// Queue a JSON response
+    pub fn push_json(&self, status: u16, body: Value) {
+        self.push_response(HttpResponse::json(status, &body));
+    }
+
+    /// Every request received so far
+    pub fn requests(&self) -> Vec<HttpRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl HttpTransport for MockTransport {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse> {
+        let url = request.url.clone();
+        self.requests.lock().unwrap().push(request);
+        self.responses
+            .lock()
+            .unwrap()
+            .pop_front()
+            .ok_or_else(|| anyhow::anyhow!("No response queued for {}", url))
+    }
+}
+
+/// Clock standing still, or moving only by a fixed step per reading
+#[derive(Debug)]
+pub struct FixedClock {
+    now: Mutex<DateTime<Utc>>,
+    step: chrono::Duration,
+}
+
+impl FixedClock {
+    pub fn new(now: DateTime<Utc>) -> Self {
+        Self {
+            now: Mutex::new(now),
+            step: chrono::Duration::zero(),
+        }
+    }
+
+    /// Advance by `step` after every reading, so consecutive timestamps
+    /// differ but are the same in every run
+    pub fn with_step(mut self, step: Duration) -> Self {
+        self.step = chrono::Duration::from_std(step).expect("step out of range");
+        self
+    }
+
+    pub fn set(&self, now: DateTime<Utc>) {
+        *self.now.lock().unwrap() = now;
+    }
+
+    pub fn advance(&self, by: Duration) {
+        *self.now.lock().unwrap() += chrono::Duration::from_std(by).expect("duration out of range");
+    }
+}
+
+impl Clock for FixedClock {
+    fn now(&self) -> DateTime<Utc> {
+        let mut now = self.now.lock().unwrap();
+        let reading = *now;
+        *now += self.step;
+        reading
+    }
+}
+
+/// Embedding model hashing words into buckets
+///
+/// Texts sharing words get similar vectors, which is enough to exercise tool
+/// selection. The provider is always `mock`.
+pub struct MockEmbeddingModel {
+    model: String,
+    dimensions: usize,
+    embedded: AtomicUsize,
+    failing: Mutex<Option<u16>>,
+    faults: Injector,
+}
+
+impl MockEmbeddingModel {
+    pub fn new(model: impl Into<String>, dimensions: usize) -> Self {
+        Self {
+            model: model.into(),
+            dimensions,
+            embedded: AtomicUsize::new(0),
+            failing: Mutex::new(None),
+            faults: Injector::default(),
+        }
+    }
+
+    /// Number of texts embedded so far
+    pub fn embedded(&self) -> usize {
+        self.embedded.load(Ordering::SeqCst)
+    }
+
+    /// Answer every call with an HTTP `status` error until [`Self::recover`]
+    pub fn fail_with(&self, status: u16) {
+        *self.failing.lock().unwrap() = Some(status);
+    }
+
+    pub fn recover(&self) {
+        *self.failing.lock().unwrap() = None;
+    }
+
+    /// Fail calls as `plan` says, unless [`Self::fail_with`] fails them first
+    pub fn with_faults(mut self, plan: FaultPlan) -> Self {
+        self.faults = Injector::new(plan);
+        self
+    }
+
+    /// Outcome of every call that got past [`Self::fail_with`]
+    pub fn timeline(&self) -> Vec<FaultEvent> {
+        self.faults.timeline()
+    }
+
+    fn vector(&self, text: &str) -> Vec<f32> {
+        let mut vector = vec![0.0; self.dimensions];
+        for word in text
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|w| !w.is_empty())
+        {
+            // FNV-1a, so vectors are the same in every run
+            let hash = word
+                .to_lowercase()
+                .bytes()
+                .fold(0xcbf29ce484222325u64, |h, b| {
+                    (h ^ b as u64).wrapping_mul(0x100000001b3)
+                });
+            vector[(hash % self.dimensions as u64) as usize] += 1.0;
+        }
+        vector
+    }
+}
+
+#[async_trait]
+impl EmbeddingModel for MockEmbeddingModel {
+    fn provider(&self) -> &str {
+        "mock"
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    async fn embed_texts(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let service = format!("Embedding provider 'mock/{}'", self.model);
+        if let Some(status) = *self.failing.lock().unwrap() {
+            return Err(HttpStatusError {
+                service,
+                status,
+                body: "scripted failure".to_string(),
+                rate_limits: None,
+            }
+            .into());
+        }
+        self.faults.inject(&service, &self.model).await?;
+        self.embedded.fetch_add(texts.len(), Ordering::SeqCst);
+        Ok(texts.iter().map(|text| self.vector(text)).collect())
+    }
+}
+
+/// Client with a `mock` provider and a `demo` server offering `echo` and
+/// `word_count`, for running the example without API keys
+pub fn mock_client() -> RigMcpClient {
+    let text_schema = json!({
+        "type": "object",
+        "properties": { "text": { "type": "string" } },
+        "required": ["text"]
+    });
+    let server = FakeMcpServer::new("demo")
+        .with_tool(
+            "echo",
+            "Echo the given text back",
+            text_schema.clone(),
+            |args| Ok(args["text"].as_str().unwrap_or_default().to_string()),
+        )
+        .with_tool(
+            "word_count",
+            "Count the words in a text",
+            text_schema,
+            |args| {
+                let text = args["text"].as_str().unwrap_or_default();
+                Ok(text.split_whitespace().count().to_string())
+            },
+        );
+    RigMcpClient::from_parts(
+        Config::default(),
+        vec![Arc::new(MockProvider::new("mock"))],
+        vec![Arc::new(server)],
+    )
+}
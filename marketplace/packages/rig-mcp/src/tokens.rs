@@ -0,0 +1,520 @@
+//! Token estimates
+//!
+//! Providers count tokens with their own tokenizers and report the result
+//! with the response. When they don't, as with streams that end without a
+//! usage frame, or when a count is needed before the response is complete, a
+//! [`TokenCounter`] estimates it from the text. Usage counted this way is
+//! marked [`NormalizedUsage::estimated`](crate::NormalizedUsage).
+//!
+//! Which [`Tokenizer`] a counter uses depends on the model. The
+//! [`TokenizerRegistry`] maps `provider/model` pairs or provider names to
+//! tokenizer ids, as the `tokenizers` section configures:
+//!
+//! ```toml
+//! [tokenizers.vocabularies]
+//! o200k_base = "/opt/tokenizers/o200k_base.tiktoken"
+//!
+//! [tokenizers.models]
+//! "openai/gpt-4o" = "o200k_base"
+//! ```
+//!
+//! Vocabularies are BPE ranks in tiktoken's format, counted with [`Bpe`].
+//! For the text of a message a [`Bpe`] loaded with the provider's own
+//! vocabulary counts what the provider counts; only the framing around each
+//! message, [`MESSAGE_OVERHEAD`] tokens, is estimated. Models without a
+//! tokenizer get the [`Heuristic`], which is built in as `heuristic`. More
+//! tokenizers can be registered in code with
+//! [`RigMcpClient::register_tokenizer`](crate::RigMcpClient::register_tokenizer).
+
+use anyhow::{Context, Result};
+use base64::Engine;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use crate::provider::{ChatRequest, ToolDefinition};
+
+/// Tokens a chat API adds around each message for the role and separators
+pub const MESSAGE_OVERHEAD: u64 = 4;
+
+/// Id of the [`Heuristic`] in every [`TokenizerRegistry`]
+pub const HEURISTIC: &str = "heuristic";
+
+/// Counts the tokens of a text as some model would
+pub trait Tokenizer: Send + Sync {
+    fn count(&self, text: &str) -> u64;
+}
+
+impl<F> Tokenizer for F
+where
+    F: Fn(&str) -> u64 + Send + Sync,
+{
+    fn count(&self, text: &str) -> u64 {
+        self(text)
+    }
+}
+
+/// Estimates token counts from the kinds of characters in a text
+///
+/// Tokenizers of current models average about four characters of English
+/// per token. Code takes more: runs of punctuation split into tokens of a
+/// character or two, and numbers into groups of up to three digits. Chinese
+/// and Japanese characters, and Korean syllables, are about a token each.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Heuristic {
+    chars_per_token: f64,
+}
+
+impl Default for Heuristic {
+    fn default() -> Self {
+        Self::new(4.0)
+    }
+}
+
+impl Heuristic {
+    /// `chars_per_token` applies to letters and whitespace
+    pub fn new(chars_per_token: f64) -> Self {
+        Self {
+            chars_per_token: chars_per_token.max(1.0),
+        }
+    }
+
+    fn cost(&self, c: char) -> f64 {
+        match c {
+            '0'..='9' => 1.0 / 3.0,
+            c if c.is_ascii_punctuation() => 0.5,
+            '\u{1100}'..='\u{11FF}'
+            | '\u{3000}'..='\u{30FF}'
+            | '\u{3400}'..='\u{4DBF}'
+            | '\u{4E00}'..='\u{9FFF}'
+            | '\u{AC00}'..='\u{D7AF}'
+            | '\u{F900}'..='\u{FAFF}'
+            | '\u{FF00}'..='\u{FFEF}' => 1.0,
+            _ => 1.0 / self.chars_per_token,
+        }
+    }
+}
+
+impl Tokenizer for Heuristic {
+    /// Any non-empty text is at least one token
+    fn count(&self, text: &str) -> u64 {
+        let tokens: f64 = text.chars().map(|c| self.cost(c)).sum();
+        // Thirds don't add up exactly
+        (tokens - 1e-9).max(0.0).ceil() as u64
+    }
+}
+
+/// How cl100k and o200k split text before merging; tiktoken's
+/// `\s+(?!\S)` alternative needs a lookahead, which [`Bpe::pieces`]
+/// applies instead
+pub const DEFAULT_PATTERN: &str = r"(?i:'s|'t|'re|'ve|'m|'ll|'d)|[^\r\n\p{L}\p{N}]?\p{L}+|\p{N}{1,3}| ?[^\s\p{L}\p{N}]+[\r\n]*|\s*[\r\n]+|\s+";
+
+/// Byte pair encoding with a tiktoken vocabulary
+///
+/// Text is split into pieces by a pattern, and each piece's bytes are
+/// merged pairwise, lowest rank first, until no adjacent pair is in the
+/// vocabulary. Special tokens are counted as text.
+#[derive(Clone)]
+pub struct Bpe {
+    ranks: HashMap<Vec<u8>, u32>,
+    pattern: Regex,
+}
+
+impl fmt::Debug for Bpe {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Bpe")
+            .field("vocabulary", &self.ranks.len())
+            .field("pattern", &self.pattern.as_str())
+            .finish()
+    }
+}
+
+impl Bpe {
+    pub fn new(ranks: HashMap<Vec<u8>, u32>) -> Self {
+        Self {
+            ranks,
+            pattern: Regex::new(DEFAULT_PATTERN).expect("default pattern is valid"),
+        }
+    }
+
+    /// Split text with `pattern` instead of [`DEFAULT_PATTERN`]
+    pub fn with_pattern(mut self, pattern: &str) -> Result<Self> {
+        self.pattern = Regex::new(pattern).context("Invalid pre-tokenizer pattern")?;
+        Ok(self)
+    }
+
+    /// Parse a vocabulary of `base64-token rank` lines, as tiktoken ships
+    /// them
+    pub fn parse(vocabulary: &str) -> Result<Self> {
+        let mut ranks = HashMap::new();
+        for (number, line) in vocabulary.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let parsed = line.split_once(' ').and_then(|(token, rank)| {
+                let token = base64::engine::general_purpose::STANDARD
+                    .decode(token)
+                    .ok()?;
+                Some((token, rank.trim().parse::<u32>().ok()?))
+            });
+            let (token, rank) =
+                parsed.with_context(|| format!("Invalid vocabulary line {}", number + 1))?;
+            ranks.insert(token, rank);
+        }
+        Ok(Self::new(ranks))
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let vocabulary = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read vocabulary {}", path.display()))?;
+        Self::parse(&vocabulary).with_context(|| format!("Invalid vocabulary {}", path.display()))
+    }
+
+    /// The pieces `text` is split into before merging
+    pub fn pieces<'t>(&self, text: &'t str) -> Vec<&'t str> {
+        let mut pieces = Vec::new();
+        let mut start = 0;
+        while let Some(found) = self.pattern.find_at(text, start) {
+            let mut end = found.end();
+            if end == found.start() {
+                break;
+            }
+            let piece = &text[found.start()..end];
+            // `\s+(?!\S)`: a run of spaces leaves its last one to the word
+            // after it
+            if end < text.len()
+                && piece.chars().all(char::is_whitespace)
+                && !piece.ends_with(['\r', '\n'])
+            {
+                if let Some((last, _)) = piece.char_indices().last().filter(|(i, _)| *i > 0) {
+                    end = found.start() + last;
+                }
+            }
+            pieces.push(&text[found.start()..end]);
+            start = end;
+        }
+        pieces
+    }
+
+    /// Token ids of `text`; bytes missing from the vocabulary get
+    /// `u32::MAX`
+    pub fn encode(&self, text: &str) -> Vec<u32> {
+        let mut tokens = Vec::new();
+        for piece in self.pieces(text) {
+            let bytes = piece.as_bytes();
+            if let Some(rank) = self.ranks.get(bytes) {
+                tokens.push(*rank);
+                continue;
+            }
+            tokens.extend(
+                self.merge(bytes)
+                    .iter()
+                    .map(|part| self.ranks.get(*part).copied().unwrap_or(u32::MAX)),
+            );
+        }
+        tokens
+    }
+
+    /// `bytes` split into tokens by merging the adjacent pair of the lowest
+    /// rank until none is left in the vocabulary
+    fn merge<'b>(&self, bytes: &'b [u8]) -> Vec<&'b [u8]> {
+        // Start of each part, and the end of the last
+        let mut bounds: Vec<usize> = (0..=bytes.len()).collect();
+        loop {
+            let best = (0..bounds.len().saturating_sub(2))
+                .filter_map(|i| {
+                    let pair = &bytes[bounds[i]..bounds[i + 2]];
+                    self.ranks.get(pair).map(|rank| (*rank, i))
+                })
+                .min();
+            match best {
+                Some((_, i)) => {
+                    bounds.remove(i + 1);
+                }
+                None => break,
+            }
+        }
+        bounds
+            .windows(2)
+            .map(|bound| &bytes[bound[0]..bound[1]])
+            .collect()
+    }
+}
+
+impl Tokenizer for Bpe {
+    fn count(&self, text: &str) -> u64 {
+        self.encode(text).len() as u64
+    }
+}
+
+/// `tokenizers` section of [`crate::Config`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenizerConfig {
+    /// Files of BPE ranks in tiktoken's format, by tokenizer id
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub vocabularies: HashMap<String, PathBuf>,
+    /// Tokenizer id by `provider/model` or provider name; the `provider/model`
+    /// entry wins
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub models: HashMap<String, String>,
+}
+
+impl TokenizerConfig {
+    pub fn is_empty(&self) -> bool {
+        self.vocabularies.is_empty() && self.models.is_empty()
+    }
+}
+
+/// Tokenizers by id, and which model uses which
+pub struct TokenizerRegistry {
+    tokenizers: RwLock<HashMap<String, Arc<dyn Tokenizer>>>,
+    models: HashMap<String, String>,
+}
+
+impl Default for TokenizerRegistry {
+    fn default() -> Self {
+        Self::new(HashMap::new())
+    }
+}
+
+impl TokenizerRegistry {
+    /// Only the [`Heuristic`], with `models` naming tokenizers to be
+    /// registered later
+    pub fn new(models: HashMap<String, String>) -> Self {
+        let heuristic: Arc<dyn Tokenizer> = Arc::new(Heuristic::default());
+        Self {
+            tokenizers: RwLock::new(HashMap::from([(HEURISTIC.to_string(), heuristic)])),
+            models,
+        }
+    }
+
+    /// Load the configured vocabularies; fails if one can't be read or a
+    /// model names a tokenizer that isn't loaded
+    pub fn from_config(config: &TokenizerConfig) -> Result<Self> {
+        let registry = Self::new(config.models.clone());
+        for (id, path) in &config.vocabularies {
+            let bpe = Bpe::from_file(path).with_context(|| format!("Tokenizer '{}'", id))?;
+            registry.register(id, Arc::new(bpe));
+        }
+        for (model, id) in &config.models {
+            anyhow::ensure!(
+                registry.get(id).is_some(),
+                "Model '{}' uses tokenizer '{}', which has no vocabulary",
+                model,
+                id
+            );
+        }
+        Ok(registry)
+    }
+
+    /// Add a tokenizer, replacing the one with the same id
+    pub fn register(&self, id: impl Into<String>, tokenizer: Arc<dyn Tokenizer>) {
+        self.tokenizers
+            .write()
+            .unwrap()
+            .insert(id.into(), tokenizer);
+    }
+
+    pub fn get(&self, id: &str) -> Option<Arc<dyn Tokenizer>> {
+        self.tokenizers.read().unwrap().get(id).cloned()
+    }
+
+    /// Tokenizer id of `model` at `provider`, [`HEURISTIC`] when none is
+    /// configured
+    pub fn tokenizer_id(&self, provider: &str, model: &str) -> &str {
+        self.models
+            .get(&format!("{}/{}", provider, model))
+            .or_else(|| self.models.get(provider))
+            .map_or(HEURISTIC, String::as_str)
+    }
+
+    /// Counter for `model` at `provider`; a tokenizer that was never
+    /// registered is replaced by the [`Heuristic`]
+    pub fn counter(&self, provider: &str, model: &str) -> TokenCounter {
+        let id = self.tokenizer_id(provider, model);
+        match self.get(id) {
+            Some(tokenizer) => TokenCounter::new(tokenizer),
+            None => {
+                tracing::warn!(
+                    provider,
+                    model,
+                    tokenizer = id,
+                    "Tokenizer is not registered; estimating tokens heuristically"
+                );
+                TokenCounter::default()
+            }
+        }
+    }
+}
+
+/// Estimates the tokens of requests and completions with a [`Tokenizer`]
+#[derive(Clone)]
+pub struct TokenCounter {
+    tokenizer: Arc<dyn Tokenizer>,
+}
+
+impl fmt::Debug for TokenCounter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TokenCounter").finish_non_exhaustive()
+    }
+}
+
+impl Default for TokenCounter {
+    fn default() -> Self {
+        Self::new(Arc::new(Heuristic::default()))
+    }
+}
+
+impl TokenCounter {
+    pub fn new(tokenizer: Arc<dyn Tokenizer>) -> Self {
+        Self { tokenizer }
+    }
+
+    /// Tokens in `text`
+    pub fn count(&self, text: &str) -> u64 {
+        self.tokenizer.count(text)
+    }
+
+    /// Prompt tokens of `request`: the system prompt, every message with its
+    /// tool calls, and the tool definitions
+    pub fn count_request(&self, request: &ChatRequest) -> u64 {
+        let system = request
+            .system
+            .as_deref()
+            .map_or(0, |system| self.count(system) + MESSAGE_OVERHEAD);
+        let messages: u64 = request
+            .messages
+            .iter()
+            .map(|message| {
+                let calls: u64 = message
+                    .tool_calls
+                    .iter()
+                    .map(|call| self.count(&call.name) + self.count(&call.arguments.to_string()))
+                    .sum();
+                self.count(&message.content) + calls + MESSAGE_OVERHEAD
+            })
+            .sum();
+        system + messages + self.count_tools(&request.tools)
+    }
+
+    /// Tokens of tool definitions: names, descriptions and argument schemas
+    pub fn count_tools(&self, tools: &[ToolDefinition]) -> u64 {
+        tools
+            .iter()
+            .map(|tool| {
+                self.count(&tool.name)
+                    + self.count(&tool.description)
+                    + self.count(&tool.parameters.to_string())
+            })
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::ChatMessage;
+
+    #[test]
+    fn test_counts_round_up() {
+        let counter = TokenCounter::default();
+        assert_eq!(counter.count(""), 0);
+        assert_eq!(counter.count("a"), 1);
+        assert_eq!(counter.count("four"), 1);
+        assert_eq!(counter.count("fives"), 2);
+        // Characters, not bytes
+        assert_eq!(counter.count("ééééé"), 2);
+    }
+
+    #[test]
+    fn test_request_counts_every_message() {
+        let counter = TokenCounter::default();
+        let request = ChatRequest {
+            system: Some("Be brief".to_string()),
+            messages: vec![ChatMessage::user("hi"), ChatMessage::assistant("hello")],
+            ..ChatRequest::default()
+        };
+        assert_eq!(counter.count_request(&request), (2 + 4) + (1 + 4) + (2 + 4));
+    }
+
+    #[test]
+    fn test_heuristic_counts_japanese_and_code() {
+        let heuristic = Heuristic::default();
+        // A token per character, not per four
+        assert_eq!(heuristic.count("今日は良い天気ですね。"), 11);
+        // "fn", " main", "()", " {}"
+        assert_eq!(heuristic.count("fn main() {}"), 4);
+        assert_eq!(heuristic.count("2024"), 2);
+    }
+
+    /// Every byte, then the merges spelling "hello" and " wor"
+    fn vocabulary() -> String {
+        let merges = ["he", "ll", "hell", "hello", " w", "or", " wor"];
+        let tokens = (0..=255u8)
+            .map(|byte| vec![byte])
+            .chain(merges.iter().map(|merge| merge.as_bytes().to_vec()));
+        tokens
+            .enumerate()
+            .map(|(rank, token)| {
+                let token = base64::engine::general_purpose::STANDARD.encode(token);
+                format!("{} {}\n", token, rank)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_bpe_merges_lowest_rank_first() {
+        let bpe = Bpe::parse(&vocabulary()).unwrap();
+        assert_eq!(
+            bpe.pieces("hello  world\n\nfoo"),
+            ["hello", " ", " world", "\n\n", "foo"]
+        );
+        // " world" merges " w", then "or", then " wor"; "l" and "d" stay
+        assert_eq!(bpe.encode("hello world"), [259, 262, 108, 100]);
+        assert_eq!(bpe.count("hello world"), 4);
+        assert!(Bpe::parse("aGk= x\n").is_err());
+    }
+
+    #[test]
+    fn test_registry_picks_the_models_tokenizer() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tiny.tiktoken");
+        std::fs::write(&path, vocabulary()).unwrap();
+        let config: TokenizerConfig = toml::from_str(&format!(
+            r#"
+            vocabularies = {{ tiny = {:?} }}
+
+            [models]
+            "openai/gpt-4o" = "tiny"
+            openai = "words"
+            "#,
+            path
+        ))
+        .unwrap();
+
+        // "words" isn't loaded from a file
+        let err = TokenizerRegistry::from_config(&config).unwrap_err();
+        assert!(err.to_string().contains("'words'"), "{}", err);
+
+        let registry = TokenizerRegistry::new(config.models.clone());
+        registry.register("tiny", Arc::new(Bpe::from_file(&path).unwrap()));
+        let words = |text: &str| text.split_whitespace().count() as u64;
+        assert_eq!(registry.counter("openai", "gpt-4o").count("hello world"), 4);
+        // Not registered yet
+        assert_eq!(registry.counter("openai", "o3").count("hello world"), 3);
+        registry.register("words", Arc::new(words));
+        assert_eq!(registry.counter("openai", "o3").count("hello world"), 2);
+        assert_eq!(registry.tokenizer_id("anthropic", "claude"), HEURISTIC);
+
+        let missing = TokenizerConfig {
+            vocabularies: HashMap::from([("gone".to_string(), dir.path().join("gone"))]),
+            ..TokenizerConfig::default()
+        };
+        let err = TokenizerRegistry::from_config(&missing).unwrap_err();
+        assert!(format!("{:#}", err).contains("gone"), "{:#}", err);
+    }
+}
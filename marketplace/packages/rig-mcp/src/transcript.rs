@@ -0,0 +1,898 @@
+//! Agent run transcripts, replayable as regression fixtures
+//!
+//! An agent built with [`crate::AgentBuilder::transcripts`] records every run
+//! into a [`TranscriptRecorder`]: the conversation it started from, the tools
+//! it offered, each completion request with the response it got and each
+//! tool call with the output the server returned. A [`Transcript`] saves as a
+//! versioned JSON fixture. A recorder given [`crate::redaction`] rules keeps
+//! runs with what they match redacted, so they can be shared; such runs only
+//! replay where the rules didn't change what the agent sent.
+//!
+//! [`Replay`] runs the recorded prompt again, through an agent whose provider
+//! and tool servers answer from the transcript, and compares what the agent
+//! sends with what was recorded: completion requests, and the server, tool
+//! and arguments of tool calls. Every difference is a [`Divergence`] at a
+//! JSON pointer into its entry, such as `/request/messages/2/content`, unless
+//! the path is ignored. The example binary's `replay` command fails when a
+//! fixture diverges.
+//!
+//! The replaying agent has the recorded settings, but no tool policy,
+//! injection screen or budget; [`Replay::configure`] adds them back for runs
+//! that depended on them.
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeSet, VecDeque};
+use std::fmt;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+
+use crate::agent::{AgentBuilder, DEFAULT_MAX_TURNS};
+use crate::clarify::Step;
+use crate::interop::{ToolCalling, ToolSupport};
+use crate::mcp::{ToolInfo, ToolOutput, ToolServer};
+use crate::provider::{
+    ChatMessage, ChatRequest, FinishReason, NormalizedResponse, NormalizedUsage, Provider,
+    ReasoningConfig,
+};
+use crate::redaction::RedactionRuleSet;
+
+/// Version of the fixture format written by this build
+pub const TRANSCRIPT_VERSION: u32 = 1;
+
+/// Finished runs a [`TranscriptRecorder`] keeps by default
+pub const DEFAULT_KEPT_RUNS: usize = 32;
+
+/// One agent run, as sent and received
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Transcript {
+    pub version: u32,
+    pub provider: String,
+    pub model: String,
+    pub settings: RunSettings,
+    /// How the provider was known to call tools when the run started
+    #[serde(default)]
+    pub tool_calling: ToolCalling,
+    /// Conversation before the run
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub history: Vec<ChatMessage>,
+    /// `None` for a run continuing after an `ask_user` answer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt: Option<String>,
+    #[serde(default)]
+    pub tools: Vec<ToolInfo>,
+    pub entries: Vec<Entry>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub answer: Option<String>,
+    /// Why the run failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Agent settings that shape its requests
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunSettings {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preamble: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning: Option<ReasoningConfig>,
+    #[serde(default = "default_max_turns")]
+    pub max_turns: usize,
+    #[serde(default)]
+    pub ask_user: bool,
+    #[serde(default)]
+    pub compact_tools: bool,
+}
+
+fn default_max_turns() -> usize {
+    DEFAULT_MAX_TURNS
+}
+
+/// A step of a run, in the order it happened
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Entry {
+    /// A request as the provider received it; `response` holds what was
+    /// streamed back, which is cut short when the budget stopped the stream
+    Completion {
+        request: ChatRequest,
+        response: NormalizedResponse,
+    },
+    /// A call that reached a tool server, with what the server returned or
+    /// the timeout it ran into
+    ToolCall {
+        server: String,
+        tool: String,
+        arguments: Value,
+        output: ToolOutput,
+    },
+}
+
+impl Entry {
+    fn kind(&self) -> &'static str {
+        match self {
+            Entry::Completion { .. } => "completion",
+            Entry::ToolCall { .. } => "tool_call",
+        }
+    }
+
+    /// The part of the entry the agent produced, as compared on replay
+    fn sent(&self) -> Value {
+        match self {
+            Entry::Completion { request, .. } => json!({ "request": request }),
+            Entry::ToolCall {
+                server,
+                tool,
+                arguments,
+                ..
+            } => json!({ "server": server, "tool": tool, "arguments": arguments }),
+        }
+    }
+}
+
+impl Transcript {
+    /// Read a fixture written by [`Transcript::save`]
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read transcript {}", path.display()))?;
+        let raw: Value = serde_json::from_str(&content)
+            .with_context(|| format!("Invalid transcript {}", path.display()))?;
+        // A newer format may not parse at all, so the version comes first
+        let version = raw["version"].as_u64().unwrap_or_default();
+        if version > u64::from(TRANSCRIPT_VERSION) {
+            anyhow::bail!(
+                "{} is a version {} transcript; this build reads up to version {}",
+                path.display(),
+                version,
+                TRANSCRIPT_VERSION
+            );
+        }
+        serde_json::from_value(raw)
+            .with_context(|| format!("Invalid transcript {}", path.display()))
+    }
+
+    /// Redact what `rules` match from the messages, tool calls and answer
+    pub fn redact(&mut self, rules: &RedactionRuleSet) {
+        for message in &mut self.history {
+            rules.apply_message(message);
+        }
+        if let Some(prompt) = &mut self.prompt {
+            *prompt = rules.redact(prompt);
+        }
+        for entry in &mut self.entries {
+            match entry {
+                Entry::Completion { request, response } => {
+                    rules.apply_request(request);
+                    rules.apply_response(response);
+                }
+                Entry::ToolCall {
+                    arguments, output, ..
+                } => {
+                    rules.apply_value(arguments);
+                    output.content = rules.redact(&output.content);
+                }
+            }
+        }
+        if let Some(answer) = &mut self.answer {
+            *answer = rules.redact(answer);
+        }
+        if let Some(error) = &mut self.error {
+            *error = rules.redact(error);
+        }
+    }
+
+    /// Write the transcript as pretty-printed JSON
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Failed to write transcript {}", path.display()))
+    }
+}
+
+/// Keeps the transcripts of the latest finished runs
+#[derive(Debug)]
+pub struct TranscriptRecorder {
+    keep: usize,
+    runs: Mutex<VecDeque<Transcript>>,
+    redaction: Option<Arc<RedactionRuleSet>>,
+}
+
+impl Default for TranscriptRecorder {
+    fn default() -> Self {
+        Self::keeping(DEFAULT_KEPT_RUNS)
+    }
+}
+
+impl TranscriptRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keep the latest `runs` runs, dropping older ones
+    pub fn keeping(runs: usize) -> Self {
+        Self {
+            keep: runs,
+            runs: Mutex::new(VecDeque::new()),
+            redaction: None,
+        }
+    }
+
+    /// Redact what `rules` match from every run before keeping it
+    pub fn with_redaction(mut self, rules: Arc<RedactionRuleSet>) -> Self {
+        self.redaction = Some(rules);
+        self
+    }
+
+    /// The latest finished run
+    pub fn last(&self) -> Option<Transcript> {
+        self.runs.lock().unwrap().back().cloned()
+    }
+
+    /// Every kept run, oldest first, leaving the recorder empty
+    pub fn take(&self) -> Vec<Transcript> {
+        self.runs.lock().unwrap().drain(..).collect()
+    }
+
+    pub(crate) fn push(&self, mut transcript: Transcript) {
+        if let Some(rules) = &self.redaction {
+            transcript.redact(rules);
+        }
+        let mut runs = self.runs.lock().unwrap();
+        runs.push_back(transcript);
+        while runs.len() > self.keep {
+            runs.pop_front();
+        }
+    }
+}
+
+/// A run being recorded
+pub(crate) struct Capture {
+    transcript: Mutex<Transcript>,
+}
+
+impl Capture {
+    pub(crate) fn start(
+        provider: &dyn Provider, settings: RunSettings, tool_calling: ToolCalling,
+        history: &[ChatMessage], prompt: Option<&str>,
+    ) -> Self {
+        Self {
+            transcript: Mutex::new(Transcript {
+                version: TRANSCRIPT_VERSION,
+                provider: provider.name().to_string(),
+                model: provider.model().to_string(),
+                settings,
+                tool_calling,
+                history: history.to_vec(),
+                prompt: prompt.map(str::to_string),
+                tools: Vec::new(),
+                entries: Vec::new(),
+                answer: None,
+                error: None,
+            }),
+        }
+    }
+
+    pub(crate) fn tools(&self, tools: &[ToolInfo]) {
+        self.transcript.lock().unwrap().tools = tools.to_vec();
+    }
+
+    pub(crate) fn completion(
+        &self, request: ChatRequest, reply: &ChatMessage, usage: NormalizedUsage,
+    ) {
+        let finish_reason = if reply.tool_calls.is_empty() {
+            FinishReason::Stop
+        } else {
+            FinishReason::ToolCalls
+        };
+        let response = NormalizedResponse {
+            content: reply.content.clone(),
+            tool_calls: reply.tool_calls.clone(),
+            usage,
+            finish_reason,
+            ..NormalizedResponse::default()
+        };
+        let entry = Entry::Completion { request, response };
+        self.transcript.lock().unwrap().entries.push(entry);
+    }
+
+    pub(crate) fn tool_call(
+        &self, server: &str, tool: &str, arguments: &Value, output: &ToolOutput,
+    ) {
+        let entry = Entry::ToolCall {
+            server: server.to_string(),
+            tool: tool.to_string(),
+            arguments: arguments.clone(),
+            output: output.clone(),
+        };
+        self.transcript.lock().unwrap().entries.push(entry);
+    }
+
+    pub(crate) fn finish(self, step: &Result<Step>) -> Transcript {
+        let mut transcript = self.transcript.into_inner().unwrap();
+        match step {
+            Ok(Step::Answered(response)) => transcript.answer = Some(response.content.clone()),
+            Ok(Step::Asked(_)) => {}
+            Err(err) => transcript.error = Some(format!("{:#}", err)),
+        }
+        transcript
+    }
+}
+
+/// A difference between a recorded entry and its replay
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Divergence {
+    /// Index of the entry in the transcript
+    pub entry: usize,
+    /// JSON pointer into the entry; `/type` when the replay did something
+    /// else at this point, or nothing
+    pub path: String,
+    /// `None` where only the replay has a value
+    pub recorded: Option<Value>,
+    /// `None` where only the recording has a value
+    pub replayed: Option<Value>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let show = |value: &Option<Value>| match value {
+            Some(value) => value.to_string(),
+            None => "(absent)".to_string(),
+        };
+        write!(
+            f,
+            "entry {} {}: recorded {}, replayed {}",
+            self.entry,
+            self.path,
+            show(&self.recorded),
+            show(&self.replayed)
+        )
+    }
+}
+
+/// Outcome of a replay
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ReplayReport {
+    /// Entries in the transcript
+    pub entries: usize,
+    /// Entries the replay reached
+    pub replayed: usize,
+    pub divergences: Vec<Divergence>,
+    pub answer: Option<String>,
+    /// Why the replayed run failed
+    pub error: Option<String>,
+}
+
+/// A replay that didn't match its recording
+#[derive(Debug, Clone, PartialEq, Error)]
+#[error("Replay diverged from the recording in {count} places, first at entry {first}")]
+pub struct Diverged {
+    pub count: usize,
+    pub first: usize,
+}
+
+impl ReplayReport {
+    pub fn is_clean(&self) -> bool {
+        self.divergences.is_empty()
+    }
+
+    /// Fail with [`Diverged`] unless the replay matched the recording
+    pub fn check(&self) -> Result<(), Diverged> {
+        match self.divergences.first() {
+            None => Ok(()),
+            Some(first) => Err(Diverged {
+                count: self.divergences.len(),
+                first: first.entry,
+            }),
+        }
+    }
+}
+
+impl fmt::Display for ReplayReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for divergence in &self.divergences {
+            writeln!(f, "{}", divergence)?;
+        }
+        if let Some(error) = &self.error {
+            writeln!(f, "error: {}", error)?;
+        }
+        write!(
+            f,
+            "{}/{} entries replayed, {} divergences",
+            self.replayed,
+            self.entries,
+            self.divergences.len()
+        )
+    }
+}
+
+/// Replay `transcript`, comparing everything
+pub async fn replay(transcript: &Transcript) -> Result<ReplayReport> {
+    Replay::new(transcript.clone()).run().await
+}
+
+type Configure = Box<dyn FnOnce(AgentBuilder) -> AgentBuilder + Send>;
+
+/// A transcript to replay, with what to leave out of the comparison
+pub struct Replay {
+    transcript: Transcript,
+    ignore: Vec<String>,
+    configure: Option<Configure>,
+}
+
+impl Replay {
+    pub fn new(transcript: Transcript) -> Self {
+        Self {
+            transcript,
+            ignore: Vec::new(),
+            configure: None,
+        }
+    }
+
+    /// Don't compare what is at `path` in any entry, or below it
+    ///
+    /// `path` is a JSON pointer into an entry, where `*` stands for any key
+    /// or index, e.g. `/request/temperature` or `/request/messages/*/content`.
+    pub fn ignore(mut self, path: impl Into<String>) -> Self {
+        self.ignore.push(path.into());
+        self
+    }
+
+    /// Change the replaying agent after the recorded settings are applied
+    pub fn configure(
+        mut self, configure: impl FnOnce(AgentBuilder) -> AgentBuilder + Send + 'static,
+    ) -> Self {
+        self.configure = Some(Box::new(configure));
+        self
+    }
+
+    pub async fn run(self) -> Result<ReplayReport> {
+        let transcript = self.transcript;
+        let script = Arc::new(Script {
+            entries: transcript.entries.clone(),
+            error: transcript.error.clone(),
+            ignore: self.ignore,
+            state: Mutex::new(ScriptState::default()),
+        });
+        let provider = Arc::new(ReplayProvider {
+            name: transcript.provider.clone(),
+            model: transcript.model.clone(),
+            script: script.clone(),
+        });
+        let support = Arc::new(ToolSupport::new());
+        if transcript.tool_calling != ToolCalling::Unknown {
+            support.set(provider.as_ref(), transcript.tool_calling);
+        }
+
+        let settings = &transcript.settings;
+        let mut builder = AgentBuilder::new(provider)
+            .max_turns(settings.max_turns)
+            .tool_support(support)
+            .ask_user(settings.ask_user)
+            .compact_tools(settings.compact_tools);
+        if let Some(preamble) = &settings.preamble {
+            builder = builder.preamble(preamble.clone());
+        }
+        if let Some(max_tokens) = settings.max_tokens {
+            builder = builder.max_tokens(max_tokens);
+        }
+        if let Some(temperature) = settings.temperature {
+            builder = builder.temperature(temperature);
+        }
+        if let Some(reasoning) = settings.reasoning {
+            builder = builder.reasoning(reasoning);
+        }
+        let servers: BTreeSet<&str> = transcript.tools.iter().map(|t| t.server.as_str()).collect();
+        for server in servers {
+            builder = builder.tool_server(Arc::new(ReplayServer {
+                name: server.to_string(),
+                tools: transcript
+                    .tools
+                    .iter()
+                    .filter(|tool| tool.server == server)
+                    .cloned()
+                    .collect(),
+                script: script.clone(),
+            }));
+        }
+        if let Some(configure) = self.configure {
+            builder = configure(builder);
+        }
+
+        let agent = builder.build();
+        let mut history = transcript.history.clone();
+        let step = agent
+            .replay_run(&mut history, transcript.prompt.as_deref())
+            .await;
+
+        let (answer, error) = match step {
+            Ok(Step::Answered(response)) => (Some(response.content), None),
+            Ok(Step::Asked(_)) => (None, None),
+            Err(err) => (None, Some(format!("{:#}", err))),
+        };
+        let mut state = script.state.lock().unwrap();
+        // Entries the replay never reached
+        for (index, entry) in transcript.entries.iter().enumerate().skip(state.next) {
+            state.divergences.push(Divergence {
+                entry: index,
+                path: "/type".to_string(),
+                recorded: Some(entry.kind().into()),
+                replayed: None,
+            });
+        }
+        Ok(ReplayReport {
+            entries: transcript.entries.len(),
+            replayed: state.next.min(transcript.entries.len()),
+            divergences: std::mem::take(&mut state.divergences),
+            answer,
+            error,
+        })
+    }
+}
+
+#[derive(Default)]
+struct ScriptState {
+    /// Index of the entry the next step is compared with
+    next: usize,
+    divergences: Vec<Divergence>,
+}
+
+/// The recorded entries, answering the replay in order
+struct Script {
+    entries: Vec<Entry>,
+    /// The recorded run's failure, repeated when the replay gets that far
+    error: Option<String>,
+    ignore: Vec<String>,
+    state: Mutex<ScriptState>,
+}
+
+impl Script {
+    /// Compare the replayed step `sent`, of `kind`, with the next entry,
+    /// returning that entry if it is of the same kind
+    fn step(&self, kind: &str, sent: Value) -> Result<Entry> {
+        let mut state = self.state.lock().unwrap();
+        let index = state.next;
+        state.next += 1;
+        let recorded = self.entries.get(index);
+        match recorded {
+            Some(entry) if entry.kind() == kind => {
+                diff(
+                    Some(&entry.sent()),
+                    Some(&sent),
+                    String::new(),
+                    &self.ignore,
+                    index,
+                    &mut state.divergences,
+                );
+                return Ok(entry.clone());
+            }
+            None if self.error.is_some() => {}
+            _ => state.divergences.push(Divergence {
+                entry: index,
+                path: "/type".to_string(),
+                recorded: recorded.map(|entry| entry.kind().into()),
+                replayed: Some(kind.into()),
+            }),
+        }
+        match (recorded, &self.error) {
+            (None, Some(error)) => Err(anyhow::anyhow!("{}", error)),
+            (None, None) => Err(anyhow::anyhow!(
+                "Replay went past the {} recorded entries",
+                self.entries.len()
+            )),
+            (Some(entry), _) => Err(anyhow::anyhow!(
+                "Replay diverged at entry {}: recorded a {}, replayed a {}",
+                index,
+                entry.kind(),
+                kind
+            )),
+        }
+    }
+}
+
+/// Push a divergence for every path below `path` where `recorded` and
+/// `replayed` differ
+fn diff(
+    recorded: Option<&Value>, replayed: Option<&Value>, path: String, ignore: &[String],
+    entry: usize, out: &mut Vec<Divergence>,
+) {
+    if ignore.iter().any(|pattern| matches(pattern, &path)) {
+        return;
+    }
+    match (recorded, replayed) {
+        (Some(Value::Object(recorded)), Some(Value::Object(replayed))) => {
+            let keys: BTreeSet<&String> = recorded.keys().chain(replayed.keys()).collect();
+            for key in keys {
+                let child = format!("{}/{}", path, key.replace('~', "~0").replace('/', "~1"));
+                diff(
+                    recorded.get(key),
+                    replayed.get(key),
+                    child,
+                    ignore,
+                    entry,
+                    out,
+                );
+            }
+        }
+        (Some(Value::Array(recorded)), Some(Value::Array(replayed))) => {
+            for index in 0..recorded.len().max(replayed.len()) {
+                let child = format!("{}/{}", path, index);
+                diff(
+                    recorded.get(index),
+                    replayed.get(index),
+                    child,
+                    ignore,
+                    entry,
+                    out,
+                );
+            }
+        }
+        (recorded, replayed) if recorded != replayed => out.push(Divergence {
+            entry,
+            path,
+            recorded: recorded.cloned(),
+            replayed: replayed.cloned(),
+        }),
+        _ => {}
+    }
+}
+
+/// Whether the JSON pointer `path` is `pattern`, with `*` matching any
+/// single key or index
+fn matches(pattern: &str, path: &str) -> bool {
+    let pattern: Vec<&str> = pattern.split('/').collect();
+    let path: Vec<&str> = path.split('/').collect();
+    pattern.len() == path.len()
+        && pattern
+            .iter()
+            .zip(&path)
+            .all(|(expected, actual)| *expected == "*" || expected == actual)
+}
+
+/// Answers completions with the recorded responses
+struct ReplayProvider {
+    name: String,
+    model: String,
+    script: Arc<Script>,
+}
+
+#[async_trait]
+impl Provider for ReplayProvider {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    async fn complete(&self, request: ChatRequest) -> Result<NormalizedResponse> {
+        let sent = json!({ "request": request });
+        match self.script.step("completion", sent)? {
+            Entry::Completion { response, .. } => Ok(response),
+            Entry::ToolCall { .. } => unreachable!("steps are matched by kind"),
+        }
+    }
+}
+
+/// Offers the recorded tools of one server and answers calls with the
+/// recorded outputs
+struct ReplayServer {
+    name: String,
+    tools: Vec<ToolInfo>,
+    script: Arc<Script>,
+}
+
+#[async_trait]
+impl ToolServer for ReplayServer {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn list_tools(&self) -> Result<Vec<ToolInfo>> {
+        Ok(self.tools.clone())
+    }
+
+    async fn call_tool(&self, name: &str, arguments: Value) -> Result<ToolOutput> {
+        let sent = json!({ "server": self.name, "tool": name, "arguments": arguments });
+        match self.script.step("tool_call", sent)? {
+            Entry::ToolCall { output, .. } => Ok(output),
+            Entry::Completion { .. } => unreachable!("steps are matched by kind"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{FakeMcpServer, MockProvider};
+    use crate::Agent;
+
+    fn text_server() -> Arc<FakeMcpServer> {
+        Arc::new(
+            FakeMcpServer::new("text")
+                .with_tool(
+                    "echo",
+                    "Echo the given text",
+                    json!({"type": "object", "properties": {"text": {"type": "string"}}}),
+                    |args| Ok(args["text"].as_str().unwrap_or_default().to_string()),
+                )
+                .with_tool(
+                    "word_count",
+                    "Count the words in a text",
+                    json!({"type": "object", "properties": {"text": {"type": "string"}}}),
+                    |args| {
+                        let text = args["text"].as_str().unwrap_or_default();
+                        Ok(text.split_whitespace().count().to_string())
+                    },
+                ),
+        )
+    }
+
+    fn recording_agent(recorder: Arc<TranscriptRecorder>) -> Agent {
+        let provider = Arc::new(MockProvider::new("mock"));
+        provider.push_tool_call("echo", json!({"text": "the quick brown fox"}));
+        provider.push_tool_call("word_count", json!({"text": "the quick brown fox"}));
+        provider.push_response(NormalizedResponse::text("It has 4 words."));
+        AgentBuilder::new(provider)
+            .preamble("Use the tools.")
+            .tool_server(text_server())
+            .transcripts(recorder)
+            .build()
+    }
+
+    /// A two-tool run, saved and read back
+    async fn recorded() -> Transcript {
+        let recorder = Arc::new(TranscriptRecorder::new());
+        let agent = recording_agent(recorder.clone());
+        let mut history = vec![ChatMessage::user("earlier")];
+        agent
+            .chat(&mut history, "Count the words of 'the quick brown fox'")
+            .await
+            .unwrap();
+        let transcript = recorder.last().unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("run.json");
+        transcript.save(&path).unwrap();
+        let loaded = Transcript::from_file(&path).unwrap();
+        assert_eq!(loaded, transcript);
+        loaded
+    }
+
+    #[tokio::test]
+    async fn test_run_is_recorded_in_order() {
+        let transcript = recorded().await;
+        assert_eq!(transcript.version, TRANSCRIPT_VERSION);
+        assert_eq!(transcript.provider, "mock");
+        assert_eq!(
+            transcript.settings.preamble.as_deref(),
+            Some("Use the tools.")
+        );
+        assert_eq!(transcript.history.len(), 1);
+        assert_eq!(transcript.tools.len(), 2);
+        assert_eq!(transcript.answer.as_deref(), Some("It has 4 words."));
+        let steps: Vec<&str> = transcript.entries.iter().map(Entry::kind).collect();
+        assert_eq!(
+            steps,
+            [
+                "completion",
+                "tool_call",
+                "completion",
+                "tool_call",
+                "completion"
+            ]
+        );
+        match &transcript.entries[3] {
+            Entry::ToolCall { tool, output, .. } => {
+                assert_eq!(tool, "word_count");
+                assert_eq!(output.content, "4");
+            }
+            other => panic!("Expected a tool call, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unchanged_run_replays_cleanly() {
+        let transcript = recorded().await;
+        let report = replay(&transcript).await.unwrap();
+        assert!(report.is_clean(), "{}", report);
+        assert_eq!(report.replayed, 5);
+        assert_eq!(report.answer.as_deref(), Some("It has 4 words."));
+        assert_eq!(report.check(), Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_changed_tool_translation_diverges() {
+        let transcript = recorded().await;
+        // Describe tools in the system prompt instead of sending them natively
+        let support = Arc::new(ToolSupport::new());
+        support.set(&MockProvider::new("mock"), ToolCalling::Prompted);
+        let report = Replay::new(transcript)
+            .configure(move |builder| builder.tool_support(support))
+            .run()
+            .await
+            .unwrap();
+
+        assert!(!report.is_clean());
+        let first = &report.divergences[0];
+        assert_eq!(first.entry, 0);
+        assert_eq!(first.path, "/request/system");
+        assert!(report
+            .divergences
+            .iter()
+            .any(|d| d.entry == 0 && d.path == "/request/tools/0" && d.replayed.is_none()));
+        // Tool calls are unaffected; the results went back as user messages
+        assert!(report.divergences.iter().all(|d| d.entry % 2 == 0));
+        let err = report.check().unwrap_err();
+        assert_eq!(err.first, 0);
+        assert_eq!(err.count, report.divergences.len());
+    }
+
+    #[tokio::test]
+    async fn test_ignored_paths_are_not_compared() {
+        let transcript = recorded().await;
+        let report = Replay::new(transcript.clone())
+            .configure(|builder| builder.temperature(0.9))
+            .run()
+            .await
+            .unwrap();
+        let paths: Vec<(usize, &str)> = report
+            .divergences
+            .iter()
+            .map(|d| (d.entry, d.path.as_str()))
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                (0, "/request/temperature"),
+                (2, "/request/temperature"),
+                (4, "/request/temperature"),
+            ]
+        );
+
+        let report = Replay::new(transcript)
+            .configure(|builder| builder.temperature(0.9))
+            .ignore("/request/temperature")
+            .run()
+            .await
+            .unwrap();
+        assert!(report.is_clean(), "{}", report);
+    }
+
+    #[tokio::test]
+    async fn test_unreached_entries_are_divergences() {
+        let mut transcript = recorded().await;
+        // This time the model answers after the first tool call
+        if let Entry::Completion { response, .. } = &mut transcript.entries[2] {
+            *response = NormalizedResponse::text("Four.");
+        }
+        let report = replay(&transcript).await.unwrap();
+        let unreached: Vec<usize> = report.divergences.iter().map(|d| d.entry).collect();
+        assert_eq!(unreached, [3, 4]);
+        assert!(report
+            .divergences
+            .iter()
+            .all(|d| d.path == "/type" && d.replayed.is_none()));
+        assert_eq!(report.replayed, 3);
+        assert_eq!(report.answer.as_deref(), Some("Four."));
+    }
+
+    #[test]
+    fn test_ignore_patterns() {
+        assert!(matches(
+            "/request/messages/*/content",
+            "/request/messages/3/content"
+        ));
+        assert!(!matches(
+            "/request/messages/*/content",
+            "/request/messages/3"
+        ));
+        assert!(!matches("/request/tools", "/request/temperature"));
+    }
+}
@@ -0,0 +1,374 @@
+//! HTTP transport for provider APIs
+//!
+//! Providers built from [`crate::ProviderConfig`] send their requests through
+//! an [`HttpTransport`], so requests and responses can be observed
+//! ([`crate::debug_logging`]) or scripted ([`crate::testing::MockTransport`])
+//! without touching the provider code.
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::borrow::Cow;
+use std::time::Duration;
+use thiserror::Error;
+
+use crate::rate_limit::RateLimited;
+
+pub use reqwest::Method;
+
+/// An outbound HTTP request
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpRequest {
+    pub method: Method,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl HttpRequest {
+    /// GET without a body
+    pub fn get(url: impl Into<String>) -> Self {
+        Self {
+            method: Method::GET,
+            url: url.into(),
+            headers: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+
+    /// POST a JSON body
+    pub fn post_json(url: impl Into<String>, body: &Value) -> Self {
+        Self {
+            method: Method::POST,
+            url: url.into(),
+            headers: vec![("content-type".to_string(), "application/json".to_string())],
+            body: body.to_string().into_bytes(),
+        }
+    }
+
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Add `Authorization: Bearer <token>` when a token is given
+    pub fn bearer(self, token: Option<&str>) -> Self {
+        match token {
+            Some(token) => self.header("authorization", format!("Bearer {}", token)),
+            None => self,
+        }
+    }
+
+    pub fn body_text(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.body)
+    }
+}
+
+/// A response as received from the server
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    /// Response with a JSON body
+    pub fn json(status: u16, body: &Value) -> Self {
+        Self {
+            status,
+            headers: vec![("content-type".to_string(), "application/json".to_string())],
+            body: body.to_string().into_bytes(),
+        }
+    }
+
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    pub fn body_text(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.body)
+    }
+
+    /// Value of the header `name`, whatever its case
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Sends HTTP requests
+#[async_trait]
+pub trait HttpTransport: Send + Sync {
+    /// Send a request and read the whole response
+    ///
+    /// Non-2xx statuses are returned as responses, not errors.
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse>;
+
+    /// Like [`HttpTransport::send`], handing the body to `on_chunk` as it
+    /// arrives, for long-running responses that report progress
+    ///
+    /// The returned response still carries the whole body. Transports that
+    /// can't stream hand it over in one piece once it is complete.
+    async fn send_streaming(
+        &self, request: HttpRequest, on_chunk: &mut (dyn FnMut(&[u8]) + Send),
+    ) -> Result<HttpResponse> {
+        let response = self.send(request).await?;
+        on_chunk(&response.body);
+        Ok(response)
+    }
+}
+
+/// [`HttpTransport`] backed by reqwest
+#[derive(Debug, Clone, Default)]
+pub struct ReqwestTransport {
+    client: reqwest::Client,
+}
+
+impl ReqwestTransport {
+    pub fn new(client: reqwest::Client) -> Self {
+        Self { client }
+    }
+}
+
+#[async_trait]
+impl HttpTransport for ReqwestTransport {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse> {
+        self.send_streaming(request, &mut |_| {}).await
+    }
+
+    async fn send_streaming(
+        &self, request: HttpRequest, on_chunk: &mut (dyn FnMut(&[u8]) + Send),
+    ) -> Result<HttpResponse> {
+        let mut builder = self.client.request(request.method, &request.url);
+        for (name, value) in &request.headers {
+            builder = builder.header(name, value);
+        }
+        let mut response = builder
+            .body(request.body)
+            .send()
+            .await
+            .with_context(|| format!("Request to {} failed", request.url))?;
+        let status = response.status().as_u16();
+        let headers = response
+            .headers()
+            .iter()
+            .map(|(name, value)| {
+                let value = String::from_utf8_lossy(value.as_bytes()).into_owned();
+                (name.to_string(), value)
+            })
+            .collect();
+        let mut body = Vec::new();
+        while let Some(chunk) = response
+            .chunk()
+            .await
+            .with_context(|| format!("Reading response from {} failed", request.url))?
+        {
+            on_chunk(&chunk);
+            body.extend_from_slice(&chunk);
+        }
+        Ok(HttpResponse {
+            status,
+            headers,
+            body,
+        })
+    }
+}
+
+/// A provider answered with a non-2xx status
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("{service} returned HTTP {status}: {body}")]
+pub struct HttpStatusError {
+    /// Who answered, e.g. `Provider 'openai'`
+    pub service: String,
+    pub status: u16,
+    pub body: String,
+    /// What the provider said about its rate limits, e.g. how long to wait
+    /// after a 429
+    pub rate_limits: Option<RateLimits>,
+}
+
+impl HttpStatusError {
+    /// The error for `response`, which `service` answered
+    pub fn from_response(service: impl Into<String>, response: &HttpResponse) -> Self {
+        Self {
+            service: service.into(),
+            status: response.status,
+            body: response.body_text().into_owned(),
+            rate_limits: RateLimits::from_response(response),
+        }
+    }
+
+    /// Timeouts, rate limits and server errors; trying again or elsewhere may
+    /// succeed
+    pub fn is_retriable(&self) -> bool {
+        matches!(self.status, 408 | 429 | 500..=599)
+    }
+
+    /// The request was refused as longer than the model's context; a model
+    /// with a larger context may take it
+    pub fn is_context_length_exceeded(&self) -> bool {
+        if !matches!(self.status, 400 | 413) {
+            return false;
+        }
+        let body = self.body.to_lowercase();
+        CONTEXT_LENGTH_ERRORS
+            .iter()
+            .any(|error| body.contains(error))
+    }
+}
+
+/// How providers word a request longer than the model's context, lowercased
+const CONTEXT_LENGTH_ERRORS: [&str; 6] = [
+    // OpenAI and the APIs compatible with it
+    "context_length_exceeded",
+    "maximum context length",
+    // Anthropic
+    "prompt is too long",
+    // Gemini and Vertex AI
+    "exceeds the maximum number of tokens",
+    // Bedrock
+    "input is too long",
+    // Mistral and Cohere
+    "too many tokens",
+];
+
+/// Rate limits a provider reported with a response
+///
+/// Read from the `x-ratelimit-*` headers Groq and OpenAI send with every
+/// response, and from the `retry-after` header of a refused request. Counts
+/// are for the current window; resets are how long until the window ends.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RateLimits {
+    pub limit_requests: Option<u64>,
+    pub limit_tokens: Option<u64>,
+    pub remaining_requests: Option<u64>,
+    pub remaining_tokens: Option<u64>,
+    pub reset_requests: Option<Duration>,
+    pub reset_tokens: Option<Duration>,
+    pub retry_after: Option<Duration>,
+}
+
+impl RateLimits {
+    /// Limits in `response`'s headers, if it has any
+    pub fn from_response(response: &HttpResponse) -> Option<Self> {
+        let count = |name: &str| response.header(name)?.trim().parse().ok();
+        let reset = |name: &str| parse_reset(response.header(name)?);
+        let limits = Self {
+            limit_requests: count("x-ratelimit-limit-requests"),
+            limit_tokens: count("x-ratelimit-limit-tokens"),
+            remaining_requests: count("x-ratelimit-remaining-requests"),
+            remaining_tokens: count("x-ratelimit-remaining-tokens"),
+            reset_requests: reset("x-ratelimit-reset-requests"),
+            reset_tokens: reset("x-ratelimit-reset-tokens"),
+            // Whole seconds; the HTTP-date form isn't used by providers
+            retry_after: count("retry-after").map(Duration::from_secs),
+        };
+        (limits != Self::default()).then_some(limits)
+    }
+}
+
+/// A reset written like `2m59.56s`, `7.66s` or `20ms`
+fn parse_reset(value: &str) -> Option<Duration> {
+    let mut rest = value.trim();
+    let mut total = Duration::ZERO;
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .filter(|end| *end > 0)?;
+        let amount: f64 = rest[..digits].parse().ok()?;
+        rest = &rest[digits..];
+        let unit = rest
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let seconds = match &rest[..unit] {
+            "h" => amount * 3600.0,
+            "m" => amount * 60.0,
+            "s" => amount,
+            "ms" => amount / 1000.0,
+            _ => return None,
+        };
+        total += Duration::from_nanos((seconds * 1e9).round() as u64);
+        rest = &rest[unit..];
+    }
+    Some(total)
+}
+
+/// A request never got a response, e.g. the connection was refused
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("{service} could not be reached")]
+pub struct Unreachable {
+    pub service: String,
+}
+
+/// Whether `err` is worth trying again or against another provider
+///
+/// Only [`HttpStatusError`]s with a retriable status, [`Unreachable`]
+/// services and requests [`RateLimited`] on our side are; invalid requests,
+/// bad credentials and malformed responses would fail the same way again.
+pub fn is_retriable(err: &anyhow::Error) -> bool {
+    if let Some(err) = err.downcast_ref::<HttpStatusError>() {
+        return err.is_retriable();
+    }
+    err.downcast_ref::<Unreachable>().is_some() || err.downcast_ref::<RateLimited>().is_some()
+}
+
+/// Whether `err` is a request refused as longer than the model's context
+pub fn is_context_length_exceeded(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<HttpStatusError>()
+        .is_some_and(HttpStatusError::is_context_length_exceeded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limits_from_headers() {
+        let header = |name: &str, value: &str| (name.to_string(), value.to_string());
+        let response = HttpResponse {
+            status: 429,
+            headers: vec![
+                header("x-ratelimit-limit-requests", "14400"),
+                header("X-RateLimit-Remaining-Tokens", "5860"),
+                header("x-ratelimit-reset-requests", "2m59.56s"),
+                header("x-ratelimit-reset-tokens", "20ms"),
+                header("retry-after", "2"),
+            ],
+            body: Vec::new(),
+        };
+        let limits = RateLimits::from_response(&response).unwrap();
+        assert_eq!(limits.limit_requests, Some(14400));
+        assert_eq!(limits.remaining_tokens, Some(5860));
+        assert_eq!(limits.remaining_requests, None);
+        assert_eq!(limits.reset_requests, Some(Duration::from_millis(179_560)));
+        assert_eq!(limits.reset_tokens, Some(Duration::from_millis(20)));
+        assert_eq!(limits.retry_after, Some(Duration::from_secs(2)));
+
+        assert_eq!(parse_reset("1h"), Some(Duration::from_secs(3600)));
+        assert_eq!(parse_reset("soon"), None);
+        let plain = HttpResponse::json(200, &Value::Null);
+        assert_eq!(RateLimits::from_response(&plain), None);
+    }
+
+    #[test]
+    fn test_context_length_errors_are_recognized() {
+        let error = |status: u16, body: &str| HttpStatusError {
+            service: "Provider 'openai'".to_string(),
+            status,
+            body: body.to_string(),
+            rate_limits: None,
+        };
+        let anthropic = r#"{"error":{"message":"prompt is too long: 210000 tokens > 200000"}}"#;
+        assert!(error(400, anthropic).is_context_length_exceeded());
+        assert!(
+            error(400, "This model's Maximum Context Length is 8192").is_context_length_exceeded()
+        );
+        assert!(!error(400, "Invalid value for 'temperature'").is_context_length_exceeded());
+        assert!(!error(500, "context_length_exceeded").is_context_length_exceeded());
+        assert!(!error(400, "prompt is too long").is_retriable());
+    }
+}
@@ -0,0 +1,323 @@
+//! Token usage of a client's providers
+//!
+//! Every provider of a [`crate::RigMcpClient`] counts its completions into
+//! the client's [`UsageTracker`]: requests, prompt and completion tokens as
+//! the provider reported them, and errors, per provider and model, since the
+//! client was created or [`crate::RigMcpClient::reset_usage`] was last
+//! called. A completion counts once, whatever it took to retry it; a stream
+//! counts when it ends or is dropped, with the tokens of its usage chunk.
+//!
+//! [`crate::RigMcpClient::usage`] takes a [`UsageSnapshot`] of the counters.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use crate::provider::{
+    ChatRequest, ChatStream, NormalizedResponse, NormalizedUsage, Provider, StreamChunk,
+};
+
+/// Counters of completions
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UsageCounts {
+    /// Completions, failed ones included
+    pub requests: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    /// Completions that failed
+    pub errors: u64,
+}
+
+impl UsageCounts {
+    fn add(&mut self, other: &UsageCounts) {
+        self.requests += other.requests;
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+        self.errors += other.errors;
+    }
+
+    pub fn total_tokens(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+}
+
+/// Usage of a provider, in total and by model
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProviderUsage {
+    #[serde(flatten)]
+    pub total: UsageCounts,
+    pub models: BTreeMap<String, UsageCounts>,
+}
+
+/// Usage of every provider that completed something, by name
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UsageSnapshot {
+    pub providers: BTreeMap<String, ProviderUsage>,
+}
+
+impl UsageSnapshot {
+    /// Usage of all providers together
+    pub fn total(&self) -> UsageCounts {
+        let mut total = UsageCounts::default();
+        for provider in self.providers.values() {
+            total.add(&provider.total);
+        }
+        total
+    }
+}
+
+/// Usage counters of a client's providers, by provider and model
+///
+/// One lock taken briefly per completion, as cheap as the completions it
+/// counts are slow.
+#[derive(Debug, Default)]
+pub struct UsageTracker {
+    counts: Mutex<BTreeMap<(String, String), UsageCounts>>,
+}
+
+impl UsageTracker {
+    /// Count a completion of `model` by `provider`; `usage` is `None` for
+    /// one that failed
+    pub fn record(&self, provider: &str, model: &str, usage: Option<&NormalizedUsage>) {
+        let mut counts = self.counts.lock().unwrap();
+        let counts = counts
+            .entry((provider.to_string(), model.to_string()))
+            .or_default();
+        counts.requests += 1;
+        match usage {
+            Some(usage) => {
+                counts.prompt_tokens += usage.prompt_tokens;
+                counts.completion_tokens += usage.completion_tokens;
+            }
+            None => counts.errors += 1,
+        }
+    }
+
+    pub fn snapshot(&self) -> UsageSnapshot {
+        let counts = self.counts.lock().unwrap();
+        let mut snapshot = UsageSnapshot::default();
+        for ((provider, model), counts) in counts.iter() {
+            let usage = snapshot.providers.entry(provider.clone()).or_default();
+            usage.total.add(counts);
+            usage.models.insert(model.clone(), *counts);
+        }
+        snapshot
+    }
+
+    /// Start counting from zero
+    pub fn reset(&self) {
+        self.counts.lock().unwrap().clear();
+    }
+}
+
+/// [`Provider`] counting its completions into a [`UsageTracker`]
+pub struct Metered {
+    inner: Arc<dyn Provider>,
+    tracker: Arc<UsageTracker>,
+}
+
+impl Metered {
+    pub fn new(inner: Arc<dyn Provider>, tracker: Arc<UsageTracker>) -> Self {
+        Self { inner, tracker }
+    }
+}
+
+#[async_trait]
+impl Provider for Metered {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn model(&self) -> &str {
+        self.inner.model()
+    }
+
+    async fn complete(&self, request: ChatRequest) -> Result<NormalizedResponse> {
+        let result = self.inner.complete(request).await;
+        let usage = result.as_ref().ok().map(|response| &response.usage);
+        self.tracker.record(self.name(), self.model(), usage);
+        result
+    }
+
+    async fn stream(&self, request: ChatRequest) -> Result<ChatStream> {
+        match self.inner.stream(request).await {
+            Ok(stream) => Ok(MeteredStream {
+                inner: stream,
+                tally: Some(Tally {
+                    tracker: self.tracker.clone(),
+                    provider: self.name().to_string(),
+                    model: self.model().to_string(),
+                    usage: NormalizedUsage::default(),
+                    failed: false,
+                }),
+            }
+            .boxed()),
+            Err(err) => {
+                self.tracker.record(self.name(), self.model(), None);
+                Err(err)
+            }
+        }
+    }
+}
+
+/// What a stream has reported so far
+struct Tally {
+    tracker: Arc<UsageTracker>,
+    provider: String,
+    model: String,
+    usage: NormalizedUsage,
+    failed: bool,
+}
+
+impl Tally {
+    fn finish(self) {
+        let usage = (!self.failed).then_some(&self.usage);
+        self.tracker.record(&self.provider, &self.model, usage);
+    }
+}
+
+/// Stream counted once, when it ends or is dropped
+struct MeteredStream {
+    inner: ChatStream,
+    tally: Option<Tally>,
+}
+
+impl Stream for MeteredStream {
+    type Item = Result<StreamChunk>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let poll = self.inner.poll_next_unpin(cx);
+        match (&poll, &mut self.tally) {
+            (Poll::Ready(Some(Ok(StreamChunk::Usage(usage)))), Some(tally)) => {
+                tally.usage = *usage;
+            }
+            (Poll::Ready(Some(Err(_))), Some(tally)) => tally.failed = true,
+            (Poll::Ready(None), tally) => {
+                if let Some(tally) = tally.take() {
+                    tally.finish();
+                }
+            }
+            _ => {}
+        }
+        poll
+    }
+}
+
+impl Drop for MeteredStream {
+    fn drop(&mut self) {
+        if let Some(tally) = self.tally.take() {
+            tally.finish();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::ChatMessage;
+    use crate::testing::{Fault, FaultPlan, MockProvider, MockTransport};
+    use crate::{Config, ProviderConfig, RigMcpClient};
+    use serde_json::json;
+
+    fn request() -> ChatRequest {
+        ChatRequest {
+            messages: vec![ChatMessage::user("Hello")],
+            ..ChatRequest::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_completions_and_streams_are_counted_by_provider_and_model() {
+        let tracker = Arc::new(UsageTracker::default());
+        let mock = Arc::new(MockProvider::new("openai"));
+        mock.push_response(NormalizedResponse {
+            usage: NormalizedUsage::new(10, 5),
+            ..NormalizedResponse::text("one")
+        });
+        mock.push_response(NormalizedResponse {
+            usage: NormalizedUsage::new(7, 3),
+            ..NormalizedResponse::text("two")
+        });
+        let provider = Metered::new(mock.clone(), tracker.clone());
+
+        provider.complete(request()).await.unwrap();
+        let mut stream = provider.stream(request()).await.unwrap();
+        while stream.next().await.is_some() {}
+        // Counted at the end, before the stream is dropped
+        let snapshot = tracker.snapshot();
+        drop(stream);
+
+        let expected = UsageCounts {
+            requests: 2,
+            prompt_tokens: 17,
+            completion_tokens: 8,
+            errors: 0,
+        };
+        assert_eq!(snapshot, tracker.snapshot());
+        assert_eq!(snapshot.total(), expected);
+        let openai = &snapshot.providers["openai"];
+        assert_eq!(openai.total, expected);
+        assert_eq!(openai.models[mock.model()], expected);
+
+        tracker.reset();
+        assert_eq!(tracker.snapshot(), UsageSnapshot::default());
+    }
+
+    #[tokio::test]
+    async fn test_failures_count_as_errors() {
+        let tracker = Arc::new(UsageTracker::default());
+        let plan = FaultPlan::script([Fault::FailWithStatus(503), Fault::Succeed]);
+        let provider = Metered::new(
+            Arc::new(MockProvider::new("groq").with_faults(plan)),
+            tracker.clone(),
+        );
+
+        provider.complete(request()).await.unwrap_err();
+        provider.complete(request()).await.unwrap();
+        let counts = tracker.snapshot().total();
+        assert_eq!(counts.requests, 2);
+        assert_eq!(counts.errors, 1);
+        assert!(counts.total_tokens() > 0);
+    }
+
+    #[tokio::test]
+    async fn test_aliases_are_counted_under_the_concrete_model() {
+        let transport = Arc::new(MockTransport::new());
+        transport.push_json(
+            200,
+            json!({
+                "model": "gpt-4o",
+                "choices": [{
+                    "message": {"role": "assistant", "content": "hi"},
+                    "finish_reason": "stop"
+                }],
+                "usage": {"prompt_tokens": 10, "completion_tokens": 5, "total_tokens": 15}
+            }),
+        );
+        let config = Config {
+            providers: vec![ProviderConfig {
+                name: "openai".to_string(),
+                model: "gpt-4o-mini".to_string(),
+                api_key: Some("sk-test".to_string()),
+                ..ProviderConfig::default()
+            }],
+            model_aliases: [("smart".to_string(), "openai/gpt-4o".to_string())].into(),
+            ..Config::default()
+        };
+        let client = RigMcpClient::with_transport(config, transport)
+            .await
+            .unwrap();
+
+        client.complete("smart", request()).await.unwrap();
+        let snapshot = client.usage();
+        assert_eq!(snapshot.providers.keys().collect::<Vec<_>>(), ["openai"]);
+        let models = &snapshot.providers["openai"].models;
+        assert_eq!(models.keys().collect::<Vec<_>>(), ["gpt-4o"]);
+        assert_eq!(models["gpt-4o"].prompt_tokens, 10);
+    }
+}
@@ -0,0 +1,453 @@
+//! Anthropic messages API
+
+use anyhow::Result;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use super::{
+    parse_arguments, push_part, reasoning, system_text, usage, Dialect, Endpoint, Normalizer,
+    StreamDecoder,
+};
+use crate::provider::{ChatRequest, FinishReason, NormalizedResponse, Role, StreamChunk, ToolCall};
+use crate::transport::HttpRequest;
+
+/// `max_tokens` is mandatory for this API
+pub(super) const DEFAULT_MAX_TOKENS: usize = 4096;
+
+/// The smallest thinking budget the API accepts
+pub(super) const MIN_THINKING_BUDGET: u32 = 1024;
+
+const API_VERSION: &str = "2023-06-01";
+
+pub(crate) struct Anthropic;
+
+impl Dialect for Anthropic {
+    fn default_base_url(&self) -> &'static str {
+        "https://api.anthropic.com"
+    }
+
+    /// Extended thinking arrived with Claude 3.7 Sonnet
+    fn supports_reasoning(&self, model: &str) -> bool {
+        model.starts_with("claude-3-7")
+            || !["claude-instant", "claude-2", "claude-3"]
+                .iter()
+                .any(|family| model.starts_with(family))
+    }
+
+    fn encode(&self, endpoint: &Endpoint, model: &str, request: &ChatRequest) -> HttpRequest {
+        post(endpoint, &body(model, request))
+    }
+
+    /// Streams report the input tokens when they start and the output tokens
+    /// in `message_delta`
+    fn encode_stream(
+        &self, endpoint: &Endpoint, model: &str, request: &ChatRequest,
+    ) -> Option<(HttpRequest, Box<dyn StreamDecoder>)> {
+        let mut body = body(model, request);
+        body["stream"] = true.into();
+        Some((post(endpoint, &body), Box::<AnthropicStream>::default()))
+    }
+}
+
+fn body(model: &str, request: &ChatRequest) -> Value {
+    let mut messages = Vec::new();
+    for message in &request.messages {
+        match message.role {
+            Role::System => {}
+            Role::User => push_part(
+                &mut messages,
+                "user",
+                "content",
+                json!({ "type": "text", "text": message.content }),
+            ),
+            Role::Assistant => {
+                if !message.content.is_empty() {
+                    push_part(
+                        &mut messages,
+                        "assistant",
+                        "content",
+                        json!({ "type": "text", "text": message.content }),
+                    );
+                }
+                for call in &message.tool_calls {
+                    push_part(
+                        &mut messages,
+                        "assistant",
+                        "content",
+                        json!({
+                            "type": "tool_use",
+                            "id": call.id,
+                            "name": call.name,
+                            "input": call.arguments,
+                        }),
+                    );
+                }
+            }
+            Role::Tool => push_part(
+                &mut messages,
+                "user",
+                "content",
+                json!({
+                    "type": "tool_result",
+                    "tool_use_id": message.tool_call_id,
+                    "content": message.content,
+                }),
+            ),
+        }
+    }
+
+    let mut body = json!({
+        "model": model,
+        "max_tokens": request.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
+        "messages": messages,
+    });
+    if let Some(system) = system_text(request) {
+        body["system"] = system.into();
+    }
+    if !request.tools.is_empty() {
+        let tools: Vec<Value> = request
+            .tools
+            .iter()
+            .map(|tool| {
+                json!({
+                    "name": tool.name,
+                    "description": tool.description,
+                    "input_schema": tool.parameters,
+                })
+            })
+            .collect();
+        body["tools"] = tools.into();
+    }
+    match &request.reasoning {
+        // The budget is spent out of `max_tokens`, which is raised by it so
+        // the answer keeps its room; thinking only takes the default
+        // temperature
+        Some(reasoning) => {
+            let budget = reasoning.thinking_budget().max(MIN_THINKING_BUDGET);
+            body["thinking"] = json!({ "type": "enabled", "budget_tokens": budget });
+            let max_tokens = request.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS);
+            body["max_tokens"] = (max_tokens + budget as usize).into();
+        }
+        None => {
+            if let Some(temperature) = request.temperature {
+                body["temperature"] = temperature.into();
+            }
+        }
+    }
+    body
+}
+
+fn post(endpoint: &Endpoint, body: &Value) -> HttpRequest {
+    let request = HttpRequest::post_json(endpoint.url("v1/messages"), body)
+        .header("anthropic-version", API_VERSION);
+    match &endpoint.api_key {
+        Some(key) => request.header("x-api-key", key),
+        None => request,
+    }
+}
+
+impl Normalizer for Anthropic {
+    fn parse(&self, raw: &Value) -> Result<NormalizedResponse> {
+        let message = Message::deserialize(raw)?;
+        let mut response = NormalizedResponse {
+            usage: message
+                .usage
+                .map(|u| usage(u.input_tokens, u.output_tokens, None))
+                .unwrap_or_default(),
+            finish_reason: match message.stop_reason.as_deref() {
+                None | Some("end_turn" | "stop_sequence") => FinishReason::Stop,
+                Some("max_tokens") => FinishReason::Length,
+                Some("tool_use") => FinishReason::ToolCalls,
+                Some("refusal") => FinishReason::ContentFilter,
+                Some(other) => FinishReason::Other(other.to_string()),
+            },
+            ..NormalizedResponse::default()
+        };
+        let mut thinking = Vec::new();
+        for block in message.content {
+            match block {
+                Block::Text { text } => response.content.push_str(&text),
+                Block::Thinking { thinking: text } => thinking.push(text),
+                Block::ToolUse { id, name, input } => response.tool_calls.push(ToolCall {
+                    id,
+                    name,
+                    arguments: input,
+                }),
+                Block::Other => {}
+            }
+        }
+        response.reasoning = reasoning(thinking);
+        Ok(response)
+    }
+}
+
+#[derive(Deserialize)]
+struct Message {
+    content: Vec<Block>,
+    stop_reason: Option<String>,
+    usage: Option<MessageUsage>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Block {
+    Text {
+        text: String,
+    },
+    Thinking {
+        thinking: String,
+    },
+    ToolUse {
+        id: String,
+        name: String,
+        input: Value,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+struct MessageUsage {
+    input_tokens: u64,
+    output_tokens: u64,
+}
+
+/// A tool call's input arrives as pieces of JSON, complete once its content
+/// block stops
+#[derive(Default)]
+struct AnthropicStream {
+    input_tokens: u64,
+    tool_use: Option<(String, String, String)>,
+}
+
+impl StreamDecoder for AnthropicStream {
+    fn event(&mut self, data: &Value) -> Result<Vec<StreamChunk>> {
+        Ok(match Event::deserialize(data)? {
+            Event::MessageStart { message } => {
+                self.input_tokens = message.usage.map_or(0, |u| u.input_tokens);
+                Vec::new()
+            }
+            Event::ContentBlockStart {
+                content_block: Block::ToolUse { id, name, .. },
+            } => {
+                self.tool_use = Some((id, name, String::new()));
+                Vec::new()
+            }
+            Event::ContentBlockDelta { delta } => match delta {
+                BlockDelta::TextDelta { text } if !text.is_empty() => {
+                    vec![StreamChunk::Text(text)]
+                }
+                BlockDelta::InputJsonDelta { partial_json } => {
+                    if let Some((_, _, input)) = &mut self.tool_use {
+                        input.push_str(&partial_json);
+                    }
+                    Vec::new()
+                }
+                _ => Vec::new(),
+            },
+            Event::ContentBlockStop => self.finish(),
+            Event::MessageDelta { usage: Some(u) } => {
+                // Newer API versions repeat the input tokens here
+                let input_tokens = u.input_tokens.unwrap_or(self.input_tokens);
+                vec![StreamChunk::Usage(usage(
+                    input_tokens,
+                    u.output_tokens,
+                    None,
+                ))]
+            }
+            Event::Error { error } => anyhow::bail!("{}: {}", error.kind, error.message),
+            _ => Vec::new(),
+        })
+    }
+
+    fn finish(&mut self) -> Vec<StreamChunk> {
+        self.tool_use
+            .take()
+            .map(|(id, name, input)| {
+                StreamChunk::ToolCall(ToolCall {
+                    id,
+                    name,
+                    arguments: parse_arguments(&input),
+                })
+            })
+            .into_iter()
+            .collect()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Event {
+    MessageStart {
+        message: StartedMessage,
+    },
+    ContentBlockStart {
+        content_block: Block,
+    },
+    ContentBlockDelta {
+        delta: BlockDelta,
+    },
+    ContentBlockStop,
+    MessageDelta {
+        usage: Option<DeltaUsage>,
+    },
+    Error {
+        error: StreamError,
+    },
+    /// `ping` and `message_stop`
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+struct StartedMessage {
+    usage: Option<MessageUsage>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum BlockDelta {
+    TextDelta {
+        text: String,
+    },
+    InputJsonDelta {
+        partial_json: String,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+struct DeltaUsage {
+    input_tokens: Option<u64>,
+    output_tokens: u64,
+}
+
+#[derive(Deserialize)]
+struct StreamError {
+    #[serde(rename = "type")]
+    kind: String,
+    message: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::{ChatMessage, NormalizedUsage};
+
+    #[test]
+    fn test_encode_merges_tool_results() {
+        let endpoint = Endpoint {
+            base_url: Anthropic.default_base_url().to_string(),
+            api_key: Some("sk-ant".to_string()),
+            api_version: None,
+        };
+        let mut assistant = ChatMessage::assistant("Checking both");
+        for id in ["a", "b"] {
+            assistant.tool_calls.push(ToolCall {
+                id: id.to_string(),
+                name: "echo".to_string(),
+                arguments: json!({"text": id}),
+            });
+        }
+        let request = ChatRequest {
+            system: Some("Be brief".to_string()),
+            messages: vec![
+                ChatMessage::system("Use tools"),
+                ChatMessage::user("go"),
+                assistant,
+                ChatMessage::tool("a", "A"),
+                ChatMessage::tool("b", "B"),
+            ],
+            ..ChatRequest::default()
+        };
+
+        let http = Anthropic.encode(&endpoint, "claude-3-5-sonnet", &request);
+        assert_eq!(http.url, "https://api.anthropic.com/v1/messages");
+        assert!(http
+            .headers
+            .contains(&("x-api-key".to_string(), "sk-ant".to_string())));
+        let body: Value = serde_json::from_slice(&http.body).unwrap();
+        assert_eq!(body["system"], "Be brief\n\nUse tools");
+        assert_eq!(body["max_tokens"], 4096);
+        let messages = body["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[1]["content"][2]["type"], "tool_use");
+        assert_eq!(messages[2]["role"], "user");
+        assert_eq!(messages[2]["content"][1]["tool_use_id"], "b");
+    }
+
+    #[test]
+    fn test_normalize_blocks() {
+        let response = Anthropic
+            .normalize(json!({
+                "id": "msg_1",
+                "type": "message",
+                "role": "assistant",
+                "content": [
+                    {"type": "thinking", "thinking": "hmm", "signature": "x"},
+                    {"type": "text", "text": "Let me check."},
+                    {"type": "tool_use", "id": "toolu_1", "name": "echo", "input": {"text": "hi"}}
+                ],
+                "stop_reason": "tool_use",
+                "usage": {"input_tokens": 20, "output_tokens": 9}
+            }))
+            .unwrap();
+        assert_eq!(response.content, "Let me check.");
+        assert_eq!(response.tool_calls[0].name, "echo");
+        assert_eq!(response.reasoning.as_deref(), Some("hmm"));
+        assert_eq!(response.usage.total_tokens, 29);
+        assert_eq!(response.finish_reason, FinishReason::ToolCalls);
+    }
+
+    #[test]
+    fn test_stream_reads_usage_from_message_delta() {
+        let events = [
+            json!({"type": "message_start", "message": {
+                "id": "msg_1", "type": "message", "role": "assistant", "content": [],
+                "usage": {"input_tokens": 20, "output_tokens": 1}
+            }}),
+            json!({"type": "content_block_start", "index": 0,
+                "content_block": {"type": "text", "text": ""}}),
+            json!({"type": "ping"}),
+            json!({"type": "content_block_delta", "index": 0,
+                "delta": {"type": "text_delta", "text": "Let me check."}}),
+            json!({"type": "content_block_stop", "index": 0}),
+            json!({"type": "content_block_start", "index": 1,
+                "content_block": {"type": "tool_use", "id": "toolu_1", "name": "echo", "input": {}}}),
+            json!({"type": "content_block_delta", "index": 1,
+                "delta": {"type": "input_json_delta", "partial_json": "{\"text\": "}}),
+            json!({"type": "content_block_delta", "index": 1,
+                "delta": {"type": "input_json_delta", "partial_json": "\"hi\"}"}}),
+            json!({"type": "content_block_stop", "index": 1}),
+            json!({"type": "message_delta", "delta": {"stop_reason": "tool_use"},
+                "usage": {"output_tokens": 9}}),
+            json!({"type": "message_stop"}),
+        ];
+        let mut decoder = AnthropicStream::default();
+        let mut chunks = Vec::new();
+        for event in &events {
+            chunks.extend(decoder.event(event).unwrap());
+        }
+        chunks.extend(decoder.finish());
+        assert_eq!(
+            chunks,
+            vec![
+                StreamChunk::Text("Let me check.".to_string()),
+                StreamChunk::ToolCall(ToolCall {
+                    id: "toolu_1".to_string(),
+                    name: "echo".to_string(),
+                    arguments: json!({"text": "hi"}),
+                }),
+                StreamChunk::Usage(NormalizedUsage::new(20, 9)),
+            ]
+        );
+
+        let err = decoder
+            .event(&json!({"type": "error", "error": {
+                "type": "overloaded_error", "message": "Overloaded"
+            }}))
+            .unwrap_err();
+        assert_eq!(err.to_string(), "overloaded_error: Overloaded");
+    }
+}
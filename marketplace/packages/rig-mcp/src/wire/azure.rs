@@ -0,0 +1,205 @@
+//! Azure OpenAI, the OpenAI API served from a deployment of an Azure resource
+//!
+//! The provider's `base_url` is the resource endpoint, e.g.
+//! `https://contoso.openai.azure.com`, and its `model` the deployment name.
+//! Every request names an `api-version`. The `api_key` is either a resource
+//! key, sent in the `api-key` header, or a Microsoft Entra ID (AAD) access
+//! token, sent as a bearer token: a key written `Bearer <token>` or shaped
+//! like a JWT is taken for a token.
+
+use anyhow::Result;
+use serde_json::{json, Value};
+
+use super::openai::{self, OpenAiStream, OPENAI};
+use super::{Dialect, EmbeddingDialect, Endpoint, Normalizer, StreamDecoder};
+use crate::provider::{ChatRequest, NormalizedResponse};
+use crate::transport::HttpRequest;
+
+/// API version used when the provider config names none
+const DEFAULT_API_VERSION: &str = "2024-10-21";
+
+pub(crate) struct Azure;
+
+impl Azure {
+    /// URL of `operation` on `deployment`
+    fn url(endpoint: &Endpoint, deployment: &str, operation: &str) -> String {
+        endpoint.url(&format!(
+            "openai/deployments/{}/{}?api-version={}",
+            deployment,
+            operation,
+            api_version(endpoint)
+        ))
+    }
+}
+
+fn api_version(endpoint: &Endpoint) -> &str {
+    endpoint
+        .api_version
+        .as_deref()
+        .unwrap_or(DEFAULT_API_VERSION)
+}
+
+/// Authenticate `request` with the endpoint's key or token
+fn authorize(request: HttpRequest, endpoint: &Endpoint) -> HttpRequest {
+    let Some(key) = endpoint.api_key.as_deref() else {
+        return request;
+    };
+    if let Some(token) = key.strip_prefix("Bearer ") {
+        request.bearer(Some(token))
+    } else if is_jwt(key) {
+        request.bearer(Some(key))
+    } else {
+        request.header("api-key", key)
+    }
+}
+
+/// Whether `key` looks like a JWT, as Entra ID access tokens are; resource
+/// keys are hex strings
+fn is_jwt(key: &str) -> bool {
+    key.starts_with("eyJ") && key.split('.').count() == 3
+}
+
+impl Dialect for Azure {
+    /// Resources have no common endpoint; see [`Dialect::requires_base_url`]
+    fn default_base_url(&self) -> &'static str {
+        ""
+    }
+
+    fn requires_base_url(&self) -> bool {
+        true
+    }
+
+    /// Only the deployment's name is known, which is taken for its model's,
+    /// as deployments are usually named
+    fn supports_reasoning(&self, deployment: &str) -> bool {
+        openai::reasons(deployment)
+    }
+
+    fn encode(&self, endpoint: &Endpoint, model: &str, request: &ChatRequest) -> HttpRequest {
+        let url = Self::url(endpoint, model, "chat/completions");
+        authorize(
+            HttpRequest::post_json(url, &OPENAI.body(model, request)),
+            endpoint,
+        )
+    }
+
+    /// Models the resource can deploy; deployments are listed by the
+    /// management API only
+    fn list_models(&self, endpoint: &Endpoint) -> Option<HttpRequest> {
+        let url = endpoint.url(&format!(
+            "openai/models?api-version={}",
+            api_version(endpoint)
+        ));
+        Some(authorize(HttpRequest::get(url), endpoint))
+    }
+
+    fn encode_stream(
+        &self, endpoint: &Endpoint, model: &str, request: &ChatRequest,
+    ) -> Option<(HttpRequest, Box<dyn StreamDecoder>)> {
+        let mut body = OPENAI.body(model, request);
+        body["stream"] = true.into();
+        body["stream_options"] = json!({ "include_usage": true });
+        let url = Self::url(endpoint, model, "chat/completions");
+        let request = authorize(HttpRequest::post_json(url, &body), endpoint);
+        Some((request, Box::<OpenAiStream>::default()))
+    }
+}
+
+impl Normalizer for Azure {
+    fn parse(&self, raw: &Value) -> Result<NormalizedResponse> {
+        OPENAI.parse(raw)
+    }
+}
+
+impl EmbeddingDialect for Azure {
+    fn encode_embeddings(&self, endpoint: &Endpoint, model: &str, texts: &[String]) -> HttpRequest {
+        let body = json!({ "model": model, "input": texts });
+        let url = Self::url(endpoint, model, "embeddings");
+        authorize(HttpRequest::post_json(url, &body), endpoint)
+    }
+
+    fn decode_embeddings(&self, raw: &Value) -> Result<Vec<Vec<f32>>> {
+        OPENAI.decode_embeddings(raw)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::ChatMessage;
+
+    fn endpoint(api_key: &str, api_version: Option<&str>) -> Endpoint {
+        Endpoint {
+            base_url: "https://contoso.openai.azure.com/".to_string(),
+            api_key: Some(api_key.to_string()),
+            api_version: api_version.map(str::to_string),
+        }
+    }
+
+    fn auth(request: &HttpRequest) -> Vec<(&str, &str)> {
+        request
+            .headers
+            .iter()
+            .filter(|(name, _)| name == "api-key" || name == "authorization")
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn test_encode_deployment_url() {
+        let request = ChatRequest {
+            messages: vec![ChatMessage::user("hi")],
+            ..ChatRequest::default()
+        };
+        let http = Azure.encode(&endpoint("0123abcd", None), "gpt-4o-prod", &request);
+        assert_eq!(
+            http.url,
+            "https://contoso.openai.azure.com/openai/deployments/gpt-4o-prod/chat/completions\
+             ?api-version=2024-10-21"
+        );
+        assert_eq!(auth(&http), vec![("api-key", "0123abcd")]);
+        let body: Value = serde_json::from_slice(&http.body).unwrap();
+        assert_eq!(body["messages"][0]["content"], "hi");
+
+        let endpoint = endpoint("0123abcd", Some("2025-01-01-preview"));
+        let (http, _) = Azure
+            .encode_stream(&endpoint, "gpt-4o-prod", &request)
+            .unwrap();
+        assert!(http.url.ends_with("?api-version=2025-01-01-preview"));
+        let body: Value = serde_json::from_slice(&http.body).unwrap();
+        assert_eq!(body["stream"], true);
+    }
+
+    #[test]
+    fn test_entra_id_tokens_are_bearer_tokens() {
+        let request = ChatRequest::default();
+        let token = "eyJ0eXAiOiJKV1QifQ.eyJhdWQiOiJjb2duaXRpdmUifQ.c2ln";
+        let http = Azure.encode(&endpoint(token, None), "gpt-4o", &request);
+        let bearer = format!("Bearer {}", token);
+        assert_eq!(auth(&http), vec![("authorization", bearer.as_str())]);
+
+        let http = Azure.encode(&endpoint("Bearer opaque", None), "gpt-4o", &request);
+        assert_eq!(auth(&http), vec![("authorization", "Bearer opaque")]);
+    }
+
+    #[test]
+    fn test_embeddings() {
+        let texts = vec!["a".to_string(), "b".to_string()];
+        let http = Azure.encode_embeddings(&endpoint("0123abcd", None), "ada-002", &texts);
+        assert_eq!(
+            http.url,
+            "https://contoso.openai.azure.com/openai/deployments/ada-002/embeddings\
+             ?api-version=2024-10-21"
+        );
+        assert_eq!(auth(&http), vec![("api-key", "0123abcd")]);
+        let vectors = Azure
+            .decode_embeddings(&json!({
+                "data": [
+                    {"index": 1, "embedding": [0.5]},
+                    {"index": 0, "embedding": [0.25]}
+                ]
+            }))
+            .unwrap();
+        assert_eq!(vectors, vec![vec![0.25], vec![0.5]]);
+    }
+}
@@ -0,0 +1,1068 @@
+//! Amazon Bedrock, signed with AWS credentials instead of an API key
+//!
+//! Chat goes through the `Converse` API, which takes the same request for
+//! Claude, Titan and the other models Bedrock serves; embeddings through
+//! Titan's `InvokeModel`, one text per request. `model` is a Bedrock model
+//! ID, inference profile or ARN, or one of the short names in [`MODEL_IDS`].
+//!
+//! Requests are signed with Signature Version 4. Credentials come from the
+//! standard chain, first match wins: the environment (`AWS_ACCESS_KEY_ID`,
+//! `AWS_SECRET_ACCESS_KEY`, `AWS_SESSION_TOKEN`), the shared credentials and
+//! config files (profile `AWS_PROFILE`, or `default`), then the EC2 instance
+//! metadata service (IMDSv2). A `profile` in the provider's
+//! [`crate::BedrockOptions`] is the only source tried. Profiles are read for
+//! static keys; SSO, `credential_process` and assumed roles aren't resolved.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::Mutex;
+
+use super::anthropic::{Anthropic, DEFAULT_MAX_TOKENS, MIN_THINKING_BUDGET};
+use super::{
+    push_part, reasoning, system_text, usage, Dialect, EmbeddingDialect, Endpoint, Normalizer,
+};
+use crate::provider::{ChatRequest, FinishReason, NormalizedResponse, Role, ToolCall};
+use crate::transport::{HttpRequest, HttpTransport, Method};
+use crate::ProviderConfig;
+
+/// Name Bedrock's runtime API is signed for
+const SERVICE: &str = "bedrock";
+
+const IMDS_ENDPOINT: &str = "http://169.254.169.254";
+
+const IMDS_TOKEN_HEADER: &str = "x-aws-ec2-metadata-token";
+
+/// Off EC2 nothing answers the metadata address, so don't wait long for it
+const IMDS_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Temporary credentials are fetched again this many seconds before they
+/// expire
+const REFRESH_MARGIN_SECS: i64 = 300;
+
+/// Short names of the models most used on Bedrock, and their model IDs
+pub(crate) const MODEL_IDS: &[(&str, &str)] = &[
+    ("claude-3-haiku", "anthropic.claude-3-haiku-20240307-v1:0"),
+    ("claude-3-sonnet", "anthropic.claude-3-sonnet-20240229-v1:0"),
+    ("claude-3-opus", "anthropic.claude-3-opus-20240229-v1:0"),
+    (
+        "claude-3-5-haiku",
+        "anthropic.claude-3-5-haiku-20241022-v1:0",
+    ),
+    (
+        "claude-3-5-sonnet",
+        "anthropic.claude-3-5-sonnet-20240620-v1:0",
+    ),
+    (
+        "claude-3-5-sonnet-v2",
+        "anthropic.claude-3-5-sonnet-20241022-v2:0",
+    ),
+    (
+        "claude-3-7-sonnet",
+        "anthropic.claude-3-7-sonnet-20250219-v1:0",
+    ),
+    ("claude-sonnet-4", "anthropic.claude-sonnet-4-20250514-v1:0"),
+    ("claude-opus-4", "anthropic.claude-opus-4-20250514-v1:0"),
+    ("titan-text-lite", "amazon.titan-text-lite-v1"),
+    ("titan-text-express", "amazon.titan-text-express-v1"),
+    ("titan-text-premier", "amazon.titan-text-premier-v1:0"),
+    ("titan-embed-text-v1", "amazon.titan-embed-text-v1"),
+    ("titan-embed-text-v2", "amazon.titan-embed-text-v2:0"),
+];
+
+/// Bedrock model ID of `model`: the ID of a short name in [`MODEL_IDS`],
+/// anything else as it is
+pub(crate) fn model_id(model: &str) -> &str {
+    MODEL_IDS
+        .iter()
+        .find(|(name, _)| *name == model)
+        .map_or(model, |(_, id)| id)
+}
+
+/// URL of `action` on `model`, whose ID goes in one path segment although
+/// IDs hold `:` and ARNs `/`
+fn model_url(endpoint: &Endpoint, model: &str, action: &str) -> String {
+    endpoint.url(&format!("model/{}/{}", uri_encode(model_id(model)), action))
+}
+
+pub(crate) struct Bedrock;
+
+impl Dialect for Bedrock {
+    /// Bedrock is served per region; see [`Signer::base_url`]
+    fn default_base_url(&self) -> &'static str {
+        ""
+    }
+
+    fn requires_api_key(&self) -> bool {
+        false
+    }
+
+    /// Claude thinks on Bedrock as on Anthropic's API, including through
+    /// cross-region inference profiles such as `us.anthropic.…`
+    fn supports_reasoning(&self, model: &str) -> bool {
+        model_id(model)
+            .split_once("anthropic.")
+            .is_some_and(|(_, claude)| Anthropic.supports_reasoning(claude))
+    }
+
+    fn encode(&self, endpoint: &Endpoint, model: &str, request: &ChatRequest) -> HttpRequest {
+        HttpRequest::post_json(model_url(endpoint, model, "converse"), &body(request))
+    }
+}
+
+fn body(request: &ChatRequest) -> Value {
+    let mut messages = Vec::new();
+    for message in &request.messages {
+        match message.role {
+            Role::System => {}
+            Role::User => push_part(
+                &mut messages,
+                "user",
+                "content",
+                json!({ "text": message.content }),
+            ),
+            Role::Assistant => {
+                if !message.content.is_empty() {
+                    push_part(
+                        &mut messages,
+                        "assistant",
+                        "content",
+                        json!({ "text": message.content }),
+                    );
+                }
+                for call in &message.tool_calls {
+                    push_part(
+                        &mut messages,
+                        "assistant",
+                        "content",
+                        json!({
+                            "toolUse": {
+                                "toolUseId": call.id,
+                                "name": call.name,
+                                "input": call.arguments,
+                            }
+                        }),
+                    );
+                }
+            }
+            Role::Tool => push_part(
+                &mut messages,
+                "user",
+                "content",
+                json!({
+                    "toolResult": {
+                        "toolUseId": message.tool_call_id,
+                        "content": [{ "text": message.content }],
+                    }
+                }),
+            ),
+        }
+    }
+
+    let mut body = json!({ "messages": messages });
+    if let Some(system) = system_text(request) {
+        body["system"] = json!([{ "text": system }]);
+    }
+    if !request.tools.is_empty() {
+        let tools: Vec<Value> = request
+            .tools
+            .iter()
+            .map(|tool| {
+                json!({
+                    "toolSpec": {
+                        "name": tool.name,
+                        "description": tool.description,
+                        "inputSchema": { "json": tool.parameters },
+                    }
+                })
+            })
+            .collect();
+        body["toolConfig"] = json!({ "tools": tools });
+    }
+    let mut inference = serde_json::Map::new();
+    match &request.reasoning {
+        // As on Anthropic's API: the budget is spent out of `maxTokens`,
+        // which is raised by it, and thinking only takes the default
+        // temperature
+        Some(reasoning) => {
+            let budget = reasoning.thinking_budget().max(MIN_THINKING_BUDGET);
+            let max_tokens = request.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS);
+            inference.insert(
+                "maxTokens".to_string(),
+                (max_tokens + budget as usize).into(),
+            );
+            body["additionalModelRequestFields"] =
+                json!({ "thinking": { "type": "enabled", "budget_tokens": budget } });
+        }
+        None => {
+            if let Some(max_tokens) = request.max_tokens {
+                inference.insert("maxTokens".to_string(), max_tokens.into());
+            }
+            if let Some(temperature) = request.temperature {
+                inference.insert("temperature".to_string(), temperature.into());
+            }
+        }
+    }
+    if !inference.is_empty() {
+        body["inferenceConfig"] = inference.into();
+    }
+    body
+}
+
+impl Normalizer for Bedrock {
+    fn parse(&self, raw: &Value) -> Result<NormalizedResponse> {
+        let converse = Converse::deserialize(raw)?;
+        let mut response = NormalizedResponse {
+            usage: converse
+                .usage
+                .map(|u| usage(u.input_tokens, u.output_tokens, u.total_tokens))
+                .unwrap_or_default(),
+            finish_reason: match converse.stop_reason.as_deref() {
+                None | Some("end_turn" | "stop_sequence") => FinishReason::Stop,
+                Some("max_tokens") => FinishReason::Length,
+                Some("tool_use") => FinishReason::ToolCalls,
+                Some("guardrail_intervened" | "content_filtered") => FinishReason::ContentFilter,
+                Some(other) => FinishReason::Other(other.to_string()),
+            },
+            ..NormalizedResponse::default()
+        };
+        // Each block is an object keyed by its kind
+        let mut thinking = Vec::new();
+        for block in converse
+            .output
+            .message
+            .map(|m| m.content)
+            .unwrap_or_default()
+        {
+            if let Some(text) = block["text"].as_str() {
+                response.content.push_str(text);
+            } else if let Some(tool_use) = block.get("toolUse") {
+                let tool_use = ToolUse::deserialize(tool_use)?;
+                response.tool_calls.push(ToolCall {
+                    id: tool_use.tool_use_id,
+                    name: tool_use.name,
+                    arguments: tool_use.input,
+                });
+            } else if let Some(text) = block["reasoningContent"]["reasoningText"]["text"].as_str() {
+                thinking.push(text.to_string());
+            }
+        }
+        response.reasoning = reasoning(thinking);
+        Ok(response)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Converse {
+    output: Output,
+    stop_reason: Option<String>,
+    usage: Option<ConverseUsage>,
+}
+
+#[derive(Deserialize)]
+struct Output {
+    message: Option<Message>,
+}
+
+#[derive(Deserialize)]
+struct Message {
+    content: Vec<Value>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ToolUse {
+    tool_use_id: String,
+    name: String,
+    input: Value,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ConverseUsage {
+    input_tokens: u64,
+    output_tokens: u64,
+    total_tokens: Option<u64>,
+}
+
+/// Titan's embedding API
+impl EmbeddingDialect for Bedrock {
+    fn encode_embeddings(&self, endpoint: &Endpoint, model: &str, texts: &[String]) -> HttpRequest {
+        HttpRequest::post_json(
+            model_url(endpoint, model, "invoke"),
+            &json!({ "inputText": texts.first() }),
+        )
+    }
+
+    fn decode_embeddings(&self, raw: &Value) -> Result<Vec<Vec<f32>>> {
+        #[derive(Deserialize)]
+        struct Embedding {
+            embedding: Vec<f32>,
+        }
+        Ok(vec![Embedding::deserialize(raw)?.embedding])
+    }
+
+    /// Titan v2 at its default size
+    fn dimensions(&self, model: &str) -> Option<usize> {
+        match model_id(model) {
+            "amazon.titan-embed-text-v1" => Some(1536),
+            "amazon.titan-embed-text-v2:0" => Some(1024),
+            _ => None,
+        }
+    }
+
+    fn max_batch(&self, _model: &str) -> Option<usize> {
+        Some(1)
+    }
+}
+
+/// Every source of the credential chain came up empty
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error(
+    "No AWS credentials for provider '{}'; tried {}",
+    .provider,
+    .attempts.join("; ")
+)]
+pub struct NoAwsCredentials {
+    pub provider: String,
+    /// Each source tried, with why it had no credentials
+    pub attempts: Vec<String>,
+}
+
+/// Keys requests are signed with
+#[derive(Clone)]
+struct Credentials {
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+    /// When temporary credentials stop working
+    expires: Option<DateTime<Utc>>,
+}
+
+/// Credentials as the instance metadata service hands them out
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ImdsCredentials {
+    access_key_id: String,
+    secret_access_key: String,
+    token: String,
+    expiration: DateTime<Utc>,
+}
+
+/// Signs a Bedrock provider's requests with the credentials the chain finds
+pub(crate) struct Signer {
+    provider: String,
+    region: String,
+    /// The only source tried when set
+    profile: Option<String>,
+    /// The variables of the chain, as they were when the provider was created
+    env: HashMap<String, String>,
+    /// Looked up on the first request
+    credentials: Mutex<Option<Credentials>>,
+}
+
+impl Signer {
+    pub fn new(config: &ProviderConfig) -> Result<Self> {
+        let env = std::env::vars()
+            .filter(|(name, _)| name.starts_with("AWS_") || name == "HOME" || name == "USERPROFILE")
+            .collect();
+        Self::with_env(config, env)
+    }
+
+    /// The region is the first of `bedrock.region`, the `base_url` host,
+    /// `AWS_REGION`, `AWS_DEFAULT_REGION` and the profile's `region`; `env`
+    /// stands in for the process environment
+    pub(crate) fn with_env(config: &ProviderConfig, env: HashMap<String, String>) -> Result<Self> {
+        let options = config.bedrock.clone().unwrap_or_default();
+        let mut signer = Self {
+            provider: config.name.clone(),
+            region: String::new(),
+            profile: options.profile,
+            env,
+            credentials: Mutex::new(None),
+        };
+        signer.region = options
+            .region
+            .or_else(|| config.base_url.as_deref().and_then(region_of_url))
+            .or_else(|| signer.var("AWS_REGION"))
+            .or_else(|| signer.var("AWS_DEFAULT_REGION"))
+            .or_else(|| signer.profile_region())
+            .with_context(|| {
+                format!(
+                    "Provider '{}' needs an AWS region (set bedrock.region, AWS_REGION or a \
+                     base_url like https://bedrock-runtime.us-east-1.amazonaws.com)",
+                    config.name
+                )
+            })?;
+        Ok(signer)
+    }
+
+    /// Runtime endpoint of the signer's region
+    pub fn base_url(&self) -> String {
+        format!("https://bedrock-runtime.{}.amazonaws.com", self.region)
+    }
+
+    /// `request` with SigV4 headers, looking the credentials up on first use
+    /// and again shortly before temporary ones expire
+    pub async fn sign(
+        &self, transport: &dyn HttpTransport, request: HttpRequest,
+    ) -> Result<HttpRequest> {
+        let credentials = {
+            let mut cached = self.credentials.lock().await;
+            let now = Utc::now();
+            let fresh = cached.as_ref().filter(|credentials| {
+                credentials.expires.is_none_or(|expires| {
+                    expires - now > chrono::Duration::seconds(REFRESH_MARGIN_SECS)
+                })
+            });
+            match fresh {
+                Some(credentials) => credentials.clone(),
+                None => {
+                    let credentials = self.resolve(transport).await?;
+                    *cached = Some(credentials.clone());
+                    credentials
+                }
+            }
+        };
+        sign(request, &credentials, &self.region, SERVICE, Utc::now())
+    }
+
+    /// The first credentials of the chain
+    async fn resolve(
+        &self, transport: &dyn HttpTransport,
+    ) -> Result<Credentials, NoAwsCredentials> {
+        let mut attempts = Vec::new();
+        if let Some(profile) = &self.profile {
+            match self.profile_credentials(profile) {
+                Ok(credentials) => return Ok(credentials),
+                Err(why) => attempts.push(format!("profile '{}': {}", profile, why)),
+            }
+            return Err(NoAwsCredentials {
+                provider: self.provider.clone(),
+                attempts,
+            });
+        }
+        match self.env_credentials() {
+            Ok(credentials) => return Ok(credentials),
+            Err(why) => attempts.push(format!("environment: {}", why)),
+        }
+        let profile = self
+            .var("AWS_PROFILE")
+            .unwrap_or_else(|| "default".to_string());
+        match self.profile_credentials(&profile) {
+            Ok(credentials) => return Ok(credentials),
+            Err(why) => attempts.push(format!("profile '{}': {}", profile, why)),
+        }
+        match self.instance_credentials(transport).await {
+            Ok(credentials) => return Ok(credentials),
+            Err(why) => attempts.push(format!("instance metadata: {}", why)),
+        }
+        Err(NoAwsCredentials {
+            provider: self.provider.clone(),
+            attempts,
+        })
+    }
+
+    fn env_credentials(&self) -> Result<Credentials, String> {
+        match (
+            self.var("AWS_ACCESS_KEY_ID"),
+            self.var("AWS_SECRET_ACCESS_KEY"),
+        ) {
+            (Some(access_key_id), Some(secret_access_key)) => Ok(Credentials {
+                access_key_id,
+                secret_access_key,
+                session_token: self.var("AWS_SESSION_TOKEN"),
+                expires: None,
+            }),
+            (None, _) => Err("AWS_ACCESS_KEY_ID is not set".to_string()),
+            (Some(_), None) => Err("AWS_SECRET_ACCESS_KEY is not set".to_string()),
+        }
+    }
+
+    /// Keys of profile `name`, from the credentials file or else the config
+    /// file
+    fn profile_credentials(&self, name: &str) -> Result<Credentials, String> {
+        let files = [
+            (self.credentials_file(), name.to_string()),
+            (self.config_file(), config_section(name)),
+        ];
+        let mut keys = HashMap::new();
+        let mut read = Vec::new();
+        for (path, section) in files {
+            let Some(path) = path else { continue };
+            match std::fs::read_to_string(&path) {
+                Ok(text) => {
+                    for (key, value) in ini_section(&text, &section).unwrap_or_default() {
+                        keys.entry(key).or_insert(value);
+                    }
+                    read.push(path.display().to_string());
+                }
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                    read.push(format!("{} (missing)", path.display()));
+                }
+                Err(err) => return Err(format!("{}: {}", path.display(), err)),
+            }
+        }
+        if read.is_empty() {
+            return Err("no home directory to find ~/.aws in".to_string());
+        }
+        match (
+            keys.remove("aws_access_key_id"),
+            keys.remove("aws_secret_access_key"),
+        ) {
+            (Some(access_key_id), Some(secret_access_key)) => Ok(Credentials {
+                access_key_id,
+                secret_access_key,
+                session_token: keys.remove("aws_session_token"),
+                expires: None,
+            }),
+            _ => Err(format!(
+                "no aws_access_key_id and aws_secret_access_key in {}",
+                read.join(", ")
+            )),
+        }
+    }
+
+    /// Credentials of the instance's IAM role, through IMDSv2
+    async fn instance_credentials(
+        &self, transport: &dyn HttpTransport,
+    ) -> Result<Credentials, String> {
+        if self
+            .var("AWS_EC2_METADATA_DISABLED")
+            .is_some_and(|disabled| disabled.eq_ignore_ascii_case("true"))
+        {
+            return Err("disabled by AWS_EC2_METADATA_DISABLED".to_string());
+        }
+        let endpoint = self
+            .var("AWS_EC2_METADATA_SERVICE_ENDPOINT")
+            .unwrap_or_else(|| IMDS_ENDPOINT.to_string());
+        let endpoint = endpoint.trim_end_matches('/');
+        let token = imds_get(
+            transport,
+            HttpRequest {
+                method: Method::PUT,
+                ..HttpRequest::get(format!("{}/latest/api/token", endpoint))
+            }
+            .header("x-aws-ec2-metadata-token-ttl-seconds", "21600"),
+        )
+        .await?;
+        let roles = format!("{}/latest/meta-data/iam/security-credentials/", endpoint);
+        let listed = imds_get(
+            transport,
+            HttpRequest::get(&roles).header(IMDS_TOKEN_HEADER, &token),
+        )
+        .await?;
+        let role = listed
+            .lines()
+            .next()
+            .filter(|role| !role.is_empty())
+            .ok_or_else(|| "the instance has no IAM role".to_string())?;
+        let body = imds_get(
+            transport,
+            HttpRequest::get(format!("{}{}", roles, role)).header(IMDS_TOKEN_HEADER, &token),
+        )
+        .await?;
+        let found: ImdsCredentials = serde_json::from_str(&body)
+            .map_err(|err| format!("invalid credentials of role '{}': {}", role, err))?;
+        Ok(Credentials {
+            access_key_id: found.access_key_id,
+            secret_access_key: found.secret_access_key,
+            session_token: Some(found.token),
+            expires: Some(found.expiration),
+        })
+    }
+
+    /// Region of the profile the chain would read
+    fn profile_region(&self) -> Option<String> {
+        let name = self.profile.clone().or_else(|| self.var("AWS_PROFILE"));
+        let text = std::fs::read_to_string(self.config_file()?).ok()?;
+        ini_section(&text, &config_section(name.as_deref().unwrap_or("default")))?.remove("region")
+    }
+
+    fn credentials_file(&self) -> Option<PathBuf> {
+        self.var("AWS_SHARED_CREDENTIALS_FILE")
+            .map(PathBuf::from)
+            .or_else(|| Some(self.aws_dir()?.join("credentials")))
+    }
+
+    fn config_file(&self) -> Option<PathBuf> {
+        self.var("AWS_CONFIG_FILE")
+            .map(PathBuf::from)
+            .or_else(|| Some(self.aws_dir()?.join("config")))
+    }
+
+    fn aws_dir(&self) -> Option<PathBuf> {
+        let home = self.var("HOME").or_else(|| self.var("USERPROFILE"))?;
+        Some(PathBuf::from(home).join(".aws"))
+    }
+
+    /// A variable of the chain, treating empty as unset
+    fn var(&self, name: &str) -> Option<String> {
+        self.env
+            .get(name)
+            .filter(|value| !value.is_empty())
+            .cloned()
+    }
+}
+
+/// Body of a metadata response, or why there was none
+async fn imds_get(transport: &dyn HttpTransport, request: HttpRequest) -> Result<String, String> {
+    let url = request.url.clone();
+    let response = match tokio::time::timeout(IMDS_TIMEOUT, transport.send(request)).await {
+        Ok(Ok(response)) => response,
+        Ok(Err(err)) => return Err(format!("{} unreachable: {:#}", url, err)),
+        Err(_) => return Err(format!("{} timed out", url)),
+    };
+    if !response.is_success() {
+        return Err(format!("{} returned HTTP {}", url, response.status));
+    }
+    Ok(response.body_text().trim().to_string())
+}
+
+/// Region of a runtime endpoint such as
+/// `https://bedrock-runtime.eu-west-1.amazonaws.com` or a VPC endpoint
+/// `https://vpce-1.bedrock-runtime.eu-west-1.vpce.amazonaws.com`
+fn region_of_url(url: &str) -> Option<String> {
+    let host = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let host = host.split(['/', ':']).next()?;
+    let mut labels = host.split('.');
+    labels.find(|label| label.starts_with("bedrock-runtime"))?;
+    labels
+        .next()
+        .filter(|region| !region.is_empty())
+        .map(String::from)
+}
+
+/// Section header of profile `name` in the config file
+fn config_section(name: &str) -> String {
+    if name == "default" {
+        name.to_string()
+    } else {
+        format!("profile {}", name)
+    }
+}
+
+/// Keys of `[section]` in an INI file, if it has that section
+fn ini_section(text: &str, section: &str) -> Option<HashMap<String, String>> {
+    let mut found: Option<HashMap<String, String>> = None;
+    let mut current = false;
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if let Some(name) = line
+            .strip_prefix('[')
+            .and_then(|line| line.strip_suffix(']'))
+        {
+            current = name.trim() == section;
+            if current && found.is_none() {
+                found = Some(HashMap::new());
+            }
+        } else if let (true, Some((key, value))) = (current, line.split_once('=')) {
+            if let Some(keys) = found.as_mut() {
+                keys.insert(key.trim().to_string(), value.trim().to_string());
+            }
+        }
+    }
+    found
+}
+
+/// `request` with the Signature Version 4 headers of `credentials` at `now`
+///
+/// Signs the host, `content-type`, `x-amz-date` and, for temporary
+/// credentials, `x-amz-security-token`.
+fn sign(
+    mut request: HttpRequest, credentials: &Credentials, region: &str, service: &str,
+    now: DateTime<Utc>,
+) -> Result<HttpRequest> {
+    let url = reqwest::Url::parse(&request.url)
+        .with_context(|| format!("Invalid URL {}", request.url))?;
+    let host = url.host_str().unwrap_or_default();
+    let host = match url.port() {
+        Some(port) => format!("{}:{}", host, port),
+        None => host.to_string(),
+    };
+    let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let date = &amz_date[..8];
+    request
+        .headers
+        .push(("x-amz-date".to_string(), amz_date.clone()));
+    if let Some(token) = &credentials.session_token {
+        request
+            .headers
+            .push(("x-amz-security-token".to_string(), token.clone()));
+    }
+
+    let mut signed: Vec<(String, String)> = request
+        .headers
+        .iter()
+        .map(|(name, value)| (name.to_ascii_lowercase(), value.trim().to_string()))
+        .filter(|(name, _)| {
+            ["content-type", "x-amz-date", "x-amz-security-token"].contains(&name.as_str())
+        })
+        .chain([("host".to_string(), host)])
+        .collect();
+    signed.sort();
+    let canonical_headers: String = signed
+        .iter()
+        .map(|(name, value)| format!("{}:{}\n", name, value))
+        .collect();
+    let signed_headers = signed
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect::<Vec<_>>()
+        .join(";");
+    // Services other than S3 sign each path segment encoded once more
+    let canonical_uri = url
+        .path()
+        .split('/')
+        .map(uri_encode)
+        .collect::<Vec<_>>()
+        .join("/");
+    let mut query: Vec<(String, String)> = url
+        .query_pairs()
+        .map(|(key, value)| (uri_encode(&key), uri_encode(&value)))
+        .collect();
+    query.sort();
+    let canonical_query = query
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect::<Vec<_>>()
+        .join("&");
+    let canonical_request = format!(
+        "{}\n{}\n{}\n{}\n{}\n{}",
+        request.method.as_str(),
+        canonical_uri,
+        canonical_query,
+        canonical_headers,
+        signed_headers,
+        hex(&Sha256::digest(&request.body))
+    );
+
+    let scope = format!("{}/{}/{}/aws4_request", date, region, service);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_date,
+        scope,
+        hex(&Sha256::digest(canonical_request.as_bytes()))
+    );
+    let mut key = hmac(
+        format!("AWS4{}", credentials.secret_access_key).as_bytes(),
+        date.as_bytes(),
+    );
+    for part in [region, service, "aws4_request"] {
+        key = hmac(&key, part.as_bytes());
+    }
+    let signature = hex(&hmac(&key, string_to_sign.as_bytes()));
+    Ok(request.header(
+        "authorization",
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            credentials.access_key_id, scope, signed_headers, signature
+        ),
+    ))
+}
+
+/// HMAC-SHA256 of `message`
+fn hmac(key: &[u8], message: &[u8]) -> Vec<u8> {
+    const BLOCK_SIZE: usize = 64;
+    let mut block = [0u8; BLOCK_SIZE];
+    if key.len() > BLOCK_SIZE {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let padded = |pad: u8| block.iter().map(|byte| byte ^ pad).collect::<Vec<u8>>();
+    let inner = Sha256::new()
+        .chain_update(padded(0x36))
+        .chain_update(message)
+        .finalize();
+    Sha256::new()
+        .chain_update(padded(0x5c))
+        .chain_update(inner)
+        .finalize()
+        .to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// `text` with everything but unreserved characters percent-encoded
+pub(super) fn uri_encode(text: &str) -> String {
+    text.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::ChatMessage;
+    use crate::testing::MockTransport;
+    use crate::transport::HttpResponse;
+    use crate::BedrockOptions;
+
+    fn config(options: Option<BedrockOptions>) -> ProviderConfig {
+        ProviderConfig {
+            name: "bedrock".to_string(),
+            model: "claude-3-5-sonnet".to_string(),
+            bedrock: options,
+            ..ProviderConfig::default()
+        }
+    }
+
+    fn env(vars: &[(&str, &str)]) -> HashMap<String, String> {
+        vars.iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn text(body: &str) -> HttpResponse {
+        HttpResponse {
+            status: 200,
+            headers: vec![],
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    fn header<'a>(request: &'a HttpRequest, name: &str) -> Option<&'a str> {
+        request
+            .headers
+            .iter()
+            .find(|(header, _)| header == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    #[test]
+    fn test_sign_matches_aws_test_vector() {
+        // `get-vanilla` of the AWS Signature Version 4 test suite
+        let credentials = Credentials {
+            access_key_id: "AKIDEXAMPLE".to_string(),
+            secret_access_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".to_string(),
+            session_token: None,
+            expires: None,
+        };
+        let now = "2015-08-30T12:36:00Z".parse().unwrap();
+        let request = HttpRequest::get("https://example.amazonaws.com/");
+        let signed = sign(request, &credentials, "us-east-1", "service", now).unwrap();
+        assert_eq!(header(&signed, "x-amz-date"), Some("20150830T123600Z"));
+        assert_eq!(
+            header(&signed, "authorization"),
+            Some(
+                "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+                 SignedHeaders=host;x-amz-date, \
+                 Signature=5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31"
+            )
+        );
+    }
+
+    #[test]
+    fn test_model_names_map_to_ids() {
+        let endpoint = Endpoint {
+            base_url: "https://bedrock-runtime.us-east-1.amazonaws.com".to_string(),
+            api_key: None,
+            api_version: None,
+        };
+        let request = ChatRequest {
+            messages: vec![ChatMessage::user("hi")],
+            ..ChatRequest::default()
+        };
+        assert_eq!(
+            Bedrock.encode(&endpoint, "claude-3-5-sonnet", &request).url,
+            "https://bedrock-runtime.us-east-1.amazonaws.com/model/\
+             anthropic.claude-3-5-sonnet-20240620-v1%3A0/converse"
+        );
+        assert_eq!(
+            Bedrock
+                .encode(&endpoint, "us.amazon.nova-pro-v1:0", &request)
+                .url,
+            "https://bedrock-runtime.us-east-1.amazonaws.com/model/\
+             us.amazon.nova-pro-v1%3A0/converse"
+        );
+        assert!(Bedrock.supports_reasoning("claude-3-7-sonnet"));
+        assert!(Bedrock.supports_reasoning("us.anthropic.claude-sonnet-4-20250514-v1:0"));
+        assert!(!Bedrock.supports_reasoning("claude-3-5-sonnet"));
+        assert!(!Bedrock.supports_reasoning("titan-text-express"));
+        assert_eq!(Bedrock.dimensions("titan-embed-text-v2"), Some(1024));
+        assert_eq!(
+            region_of_url("https://bedrock-runtime.eu-west-1.amazonaws.com"),
+            Some("eu-west-1".to_string())
+        );
+    }
+
+    #[test]
+    fn test_normalize_converse_response() {
+        let response = Bedrock
+            .normalize(json!({
+                "output": {"message": {"role": "assistant", "content": [
+                    {"reasoningContent": {"reasoningText": {"text": "hmm", "signature": "x"}}},
+                    {"text": "Let me check."},
+                    {"toolUse": {"toolUseId": "tooluse_1", "name": "echo", "input": {"text": "hi"}}}
+                ]}},
+                "stopReason": "tool_use",
+                "usage": {"inputTokens": 20, "outputTokens": 9, "totalTokens": 29},
+                "metrics": {"latencyMs": 512}
+            }))
+            .unwrap();
+        assert_eq!(response.content, "Let me check.");
+        assert_eq!(response.tool_calls[0].id, "tooluse_1");
+        assert_eq!(response.tool_calls[0].arguments, json!({"text": "hi"}));
+        assert_eq!(response.reasoning.as_deref(), Some("hmm"));
+        assert_eq!(response.usage.total_tokens, 29);
+        assert_eq!(response.finish_reason, FinishReason::ToolCalls);
+    }
+
+    #[tokio::test]
+    async fn test_missing_credentials_name_every_source() {
+        let home = tempfile::tempdir().unwrap();
+        let home = home.path().to_str().unwrap();
+        let signer = Signer::with_env(
+            &config(None),
+            env(&[("HOME", home), ("AWS_REGION", "us-west-2")]),
+        )
+        .unwrap();
+        assert_eq!(
+            signer.base_url(),
+            "https://bedrock-runtime.us-west-2.amazonaws.com"
+        );
+        let transport = MockTransport::new();
+        let request = HttpRequest::get(signer.base_url());
+        let err = signer.sign(&transport, request).await.unwrap_err();
+        let missing = err.downcast_ref::<NoAwsCredentials>().unwrap();
+        assert_eq!(missing.attempts.len(), 3);
+        let message = err.to_string();
+        assert!(
+            message.contains("environment: AWS_ACCESS_KEY_ID is not set"),
+            "{}",
+            message
+        );
+        assert!(
+            message.contains("profile 'default': no aws_access_key_id"),
+            "{}",
+            message
+        );
+        assert!(
+            message.contains(".aws/credentials (missing)"),
+            "{}",
+            message
+        );
+        assert!(
+            message.contains("instance metadata: http://169.254.169.254"),
+            "{}",
+            message
+        );
+
+        let err = Signer::with_env(&config(None), env(&[("HOME", home)]))
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("needs an AWS region"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_named_profile_is_the_only_source() {
+        let home = tempfile::tempdir().unwrap();
+        std::fs::create_dir(home.path().join(".aws")).unwrap();
+        std::fs::write(
+            home.path().join(".aws/credentials"),
+            "[default]\naws_access_key_id = AKIDDEFAULT\naws_secret_access_key = s\n\n\
+             [bedrock]\naws_access_key_id = AKIDBEDROCK\naws_secret_access_key = secret\n",
+        )
+        .unwrap();
+        std::fs::write(
+            home.path().join(".aws/config"),
+            "[profile bedrock]\nregion = eu-central-1\n",
+        )
+        .unwrap();
+        let options = BedrockOptions {
+            region: None,
+            profile: Some("bedrock".to_string()),
+        };
+        let vars = env(&[
+            ("HOME", home.path().to_str().unwrap()),
+            ("AWS_ACCESS_KEY_ID", "AKIDENV"),
+            ("AWS_SECRET_ACCESS_KEY", "secret"),
+        ]);
+        let signer = Signer::with_env(&config(Some(options)), vars).unwrap();
+        assert_eq!(signer.region, "eu-central-1");
+        let request = HttpRequest::get(signer.base_url());
+        let signed = signer.sign(&MockTransport::new(), request).await.unwrap();
+        let authorization = header(&signed, "authorization").unwrap();
+        assert!(
+            authorization.contains("Credential=AKIDBEDROCK/"),
+            "{}",
+            authorization
+        );
+
+        let options = BedrockOptions {
+            region: Some("us-east-1".to_string()),
+            profile: Some("absent".to_string()),
+        };
+        let vars = env(&[("HOME", home.path().to_str().unwrap())]);
+        let signer = Signer::with_env(&config(Some(options)), vars).unwrap();
+        let request = HttpRequest::get(signer.base_url());
+        let err = signer
+            .sign(&MockTransport::new(), request)
+            .await
+            .unwrap_err();
+        let missing = err.downcast_ref::<NoAwsCredentials>().unwrap();
+        assert_eq!(missing.attempts.len(), 1);
+        assert!(
+            missing.attempts[0].starts_with("profile 'absent': "),
+            "{:?}",
+            missing
+        );
+    }
+
+    #[tokio::test]
+    async fn test_instance_credentials_are_cached() {
+        let transport = MockTransport::new();
+        transport.push_response(text("token-1"));
+        transport.push_response(text("bedrock-role\n"));
+        transport.push_json(
+            200,
+            json!({
+                "Code": "Success",
+                "AccessKeyId": "ASIAINSTANCE",
+                "SecretAccessKey": "secret",
+                "Token": "session",
+                "Expiration": (Utc::now() + chrono::Duration::hours(6)).to_rfc3339()
+            }),
+        );
+        let options = BedrockOptions {
+            region: Some("us-east-1".to_string()),
+            profile: None,
+        };
+        let signer = Signer::with_env(&config(Some(options)), HashMap::new()).unwrap();
+        for _ in 0..2 {
+            let request = HttpRequest::get(signer.base_url());
+            let signed = signer.sign(&transport, request).await.unwrap();
+            assert_eq!(header(&signed, "x-amz-security-token"), Some("session"));
+            assert!(header(&signed, "authorization")
+                .unwrap()
+                .contains("SignedHeaders=host;x-amz-date;x-amz-security-token"));
+        }
+
+        let requests = transport.requests();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[0].method, Method::PUT);
+        assert_eq!(requests[0].url, "http://169.254.169.254/latest/api/token");
+        assert_eq!(header(&requests[1], IMDS_TOKEN_HEADER), Some("token-1"));
+        assert_eq!(
+            requests[2].url,
+            "http://169.254.169.254/latest/meta-data/iam/security-credentials/bedrock-role"
+        );
+    }
+}
@@ -0,0 +1,288 @@
+//! Cohere v2 chat
+
+use anyhow::Result;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use super::{
+    parse_arguments, usage, Dialect, EmbeddingDialect, Endpoint, Normalizer, RerankDialect,
+};
+use crate::provider::{ChatRequest, FinishReason, NormalizedResponse, Role, ToolCall};
+use crate::rerank::Relevance;
+use crate::transport::HttpRequest;
+
+pub(crate) struct Cohere;
+
+impl Dialect for Cohere {
+    fn default_base_url(&self) -> &'static str {
+        "https://api.cohere.com"
+    }
+
+    fn encode(&self, endpoint: &Endpoint, model: &str, request: &ChatRequest) -> HttpRequest {
+        let mut messages = Vec::new();
+        if let Some(system) = &request.system {
+            messages.push(json!({ "role": "system", "content": system }));
+        }
+        for message in &request.messages {
+            messages.push(match message.role {
+                Role::Assistant if !message.tool_calls.is_empty() => {
+                    let calls: Vec<Value> = message
+                        .tool_calls
+                        .iter()
+                        .map(|call| {
+                            json!({
+                                "id": call.id,
+                                "type": "function",
+                                "function": {
+                                    "name": call.name,
+                                    "arguments": call.arguments.to_string(),
+                                }
+                            })
+                        })
+                        .collect();
+                    // Text accompanying tool calls is the model's plan
+                    json!({
+                        "role": "assistant",
+                        "tool_plan": message.content,
+                        "tool_calls": calls,
+                    })
+                }
+                Role::Tool => json!({
+                    "role": "tool",
+                    "tool_call_id": message.tool_call_id,
+                    "content": message.content,
+                }),
+                role => json!({ "role": role, "content": message.content }),
+            });
+        }
+
+        let mut body = json!({ "model": model, "messages": messages });
+        if !request.tools.is_empty() {
+            let tools: Vec<Value> = request
+                .tools
+                .iter()
+                .map(|tool| {
+                    json!({
+                        "type": "function",
+                        "function": {
+                            "name": tool.name,
+                            "description": tool.description,
+                            "parameters": tool.parameters,
+                        }
+                    })
+                })
+                .collect();
+            body["tools"] = tools.into();
+        }
+        if let Some(max_tokens) = request.max_tokens {
+            body["max_tokens"] = max_tokens.into();
+        }
+        if let Some(temperature) = request.temperature {
+            body["temperature"] = temperature.into();
+        }
+        if let Some(seed) = request.seed {
+            body["seed"] = seed.into();
+        }
+        HttpRequest::post_json(endpoint.url("v2/chat"), &body).bearer(endpoint.api_key.as_deref())
+    }
+}
+
+impl Normalizer for Cohere {
+    fn parse(&self, raw: &Value) -> Result<NormalizedResponse> {
+        let chat = Chat::deserialize(raw)?;
+        Ok(NormalizedResponse {
+            content: chat
+                .message
+                .content
+                .into_iter()
+                .filter_map(|block| block.text)
+                .collect(),
+            reasoning: None,
+            tool_calls: chat
+                .message
+                .tool_calls
+                .into_iter()
+                .map(|call| ToolCall {
+                    id: call.id,
+                    name: call.function.name,
+                    arguments: parse_arguments(&call.function.arguments),
+                })
+                .collect(),
+            usage: chat
+                .usage
+                .and_then(|u| u.tokens)
+                .map(|t| usage(t.input_tokens as u64, t.output_tokens as u64, None))
+                .unwrap_or_default(),
+            finish_reason: match chat.finish_reason.as_deref() {
+                None | Some("COMPLETE" | "STOP_SEQUENCE") => FinishReason::Stop,
+                Some("MAX_TOKENS") => FinishReason::Length,
+                Some("TOOL_CALL") => FinishReason::ToolCalls,
+                Some(other) => FinishReason::Other(other.to_string()),
+            },
+            ..NormalizedResponse::default()
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct Chat {
+    message: Message,
+    finish_reason: Option<String>,
+    usage: Option<ChatUsage>,
+}
+
+#[derive(Deserialize)]
+struct Message {
+    #[serde(default)]
+    content: Vec<ContentBlock>,
+    #[serde(default)]
+    tool_calls: Vec<WireToolCall>,
+}
+
+#[derive(Deserialize)]
+struct ContentBlock {
+    text: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct WireToolCall {
+    id: String,
+    function: Function,
+}
+
+#[derive(Deserialize)]
+struct Function {
+    name: String,
+    #[serde(default)]
+    arguments: String,
+}
+
+#[derive(Deserialize)]
+struct ChatUsage {
+    tokens: Option<Tokens>,
+}
+
+/// Cohere reports token counts as floats
+#[derive(Deserialize)]
+struct Tokens {
+    #[serde(default)]
+    input_tokens: f64,
+    #[serde(default)]
+    output_tokens: f64,
+}
+
+impl EmbeddingDialect for Cohere {
+    fn encode_embeddings(&self, endpoint: &Endpoint, model: &str, texts: &[String]) -> HttpRequest {
+        // v3 models require an input type; queries and tool descriptions are
+        // embedded alike, so both use the document type
+        let body = json!({
+            "model": model,
+            "texts": texts,
+            "input_type": "search_document",
+            "embedding_types": ["float"],
+        });
+        HttpRequest::post_json(endpoint.url("v2/embed"), &body).bearer(endpoint.api_key.as_deref())
+    }
+
+    fn decode_embeddings(&self, raw: &Value) -> Result<Vec<Vec<f32>>> {
+        Ok(Embed::deserialize(raw)?.embeddings.float)
+    }
+}
+
+#[derive(Deserialize)]
+struct Embed {
+    embeddings: EmbeddingsByType,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingsByType {
+    float: Vec<Vec<f32>>,
+}
+
+impl RerankDialect for Cohere {
+    fn encode_rerank(
+        &self, endpoint: &Endpoint, model: &str, query: &str, documents: &[String], top_n: usize,
+    ) -> HttpRequest {
+        let body = json!({
+            "model": model,
+            "query": query,
+            "documents": documents,
+            "top_n": top_n,
+        });
+        HttpRequest::post_json(endpoint.url("v2/rerank"), &body).bearer(endpoint.api_key.as_deref())
+    }
+
+    fn decode_rerank(&self, raw: &Value) -> Result<Vec<Relevance>> {
+        Ok(Rerank::deserialize(raw)?
+            .results
+            .into_iter()
+            .map(|result| Relevance {
+                index: result.index,
+                score: result.relevance_score,
+            })
+            .collect())
+    }
+}
+
+#[derive(Deserialize)]
+struct Rerank {
+    results: Vec<RerankResult>,
+}
+
+#[derive(Deserialize)]
+struct RerankResult {
+    index: usize,
+    relevance_score: f32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::ChatMessage;
+
+    #[test]
+    fn test_encode_and_normalize() {
+        let endpoint = Endpoint {
+            base_url: Cohere.default_base_url().to_string(),
+            api_key: Some("co-key".to_string()),
+            api_version: None,
+        };
+        let mut assistant = ChatMessage::assistant("I will echo");
+        assistant.tool_calls.push(ToolCall {
+            id: "tc_1".to_string(),
+            name: "echo".to_string(),
+            arguments: json!({"text": "hi"}),
+        });
+        let request = ChatRequest {
+            messages: vec![
+                ChatMessage::user("echo hi"),
+                assistant,
+                ChatMessage::tool("tc_1", "hi"),
+            ],
+            ..ChatRequest::default()
+        };
+        let http = Cohere.encode(&endpoint, "command-r-plus", &request);
+        assert_eq!(http.url, "https://api.cohere.com/v2/chat");
+        let body: Value = serde_json::from_slice(&http.body).unwrap();
+        assert_eq!(body["messages"][1]["tool_plan"], "I will echo");
+        assert_eq!(body["messages"][2]["tool_call_id"], "tc_1");
+
+        let response = Cohere
+            .normalize(json!({
+                "id": "c1",
+                "finish_reason": "COMPLETE",
+                "message": {
+                    "role": "assistant",
+                    "content": [{"type": "text", "text": "hi back"}]
+                },
+                "usage": {
+                    "billed_units": {"input_tokens": 5, "output_tokens": 2},
+                    "tokens": {"input_tokens": 71.0, "output_tokens": 2.0}
+                }
+            }))
+            .unwrap();
+        assert_eq!(response.content, "hi back");
+        assert_eq!(response.usage.prompt_tokens, 71);
+        assert_eq!(response.finish_reason, FinishReason::Stop);
+    }
+}
@@ -0,0 +1,188 @@
+//! Servers speaking the OpenAI API: vLLM, LM Studio, llama.cpp's server,
+//! LiteLLM and the like
+//!
+//! There is no common endpoint, so the provider's `base_url` is required. A
+//! URL naming only the server, e.g. `http://localhost:8000`, gets OpenAI's
+//! `/v1` prefix; one with a path, e.g. `http://localhost:1234/v1` or
+//! `https://llm.example.com/openai`, is used as it is. Local servers usually
+//! take no key, so the `api_key` is optional, and responses without a usage
+//! report zero tokens.
+
+use anyhow::Result;
+use serde_json::{json, Value};
+
+use super::openai::{OpenAiStream, COMPATIBLE, OPENAI};
+use super::{Dialect, EmbeddingDialect, Endpoint, Normalizer, StreamDecoder};
+use crate::provider::{ChatRequest, NormalizedResponse};
+use crate::transport::HttpRequest;
+
+pub(crate) struct OpenAiCompatible;
+
+impl OpenAiCompatible {
+    /// URL of `path` on the endpoint's server
+    fn url(endpoint: &Endpoint, path: &str) -> String {
+        let base = endpoint.base_url.trim_end_matches('/');
+        let host_only = base
+            .split_once("://")
+            .map_or(base, |(_, rest)| rest)
+            .find('/')
+            .is_none();
+        if host_only {
+            format!("{}/v1/{}", base, path)
+        } else {
+            endpoint.url(path)
+        }
+    }
+}
+
+impl Dialect for OpenAiCompatible {
+    /// Servers have no common endpoint; see [`Dialect::requires_base_url`]
+    fn default_base_url(&self) -> &'static str {
+        ""
+    }
+
+    fn requires_api_key(&self) -> bool {
+        false
+    }
+
+    fn requires_base_url(&self) -> bool {
+        true
+    }
+
+    fn encode(&self, endpoint: &Endpoint, model: &str, request: &ChatRequest) -> HttpRequest {
+        let url = Self::url(endpoint, "chat/completions");
+        HttpRequest::post_json(url, &COMPATIBLE.body(model, request))
+            .bearer(endpoint.api_key.as_deref())
+    }
+
+    fn list_models(&self, endpoint: &Endpoint) -> Option<HttpRequest> {
+        let url = Self::url(endpoint, "models");
+        Some(HttpRequest::get(url).bearer(endpoint.api_key.as_deref()))
+    }
+
+    /// Asks for the usage in a last chunk like OpenAI; servers that don't
+    /// send one leave it to be estimated
+    fn encode_stream(
+        &self, endpoint: &Endpoint, model: &str, request: &ChatRequest,
+    ) -> Option<(HttpRequest, Box<dyn StreamDecoder>)> {
+        let mut body = COMPATIBLE.body(model, request);
+        body["stream"] = true.into();
+        body["stream_options"] = json!({ "include_usage": true });
+        let url = Self::url(endpoint, "chat/completions");
+        let request = HttpRequest::post_json(url, &body).bearer(endpoint.api_key.as_deref());
+        Some((request, Box::<OpenAiStream>::default()))
+    }
+}
+
+impl Normalizer for OpenAiCompatible {
+    fn parse(&self, raw: &Value) -> Result<NormalizedResponse> {
+        OPENAI.parse(raw)
+    }
+}
+
+impl EmbeddingDialect for OpenAiCompatible {
+    fn encode_embeddings(&self, endpoint: &Endpoint, model: &str, texts: &[String]) -> HttpRequest {
+        let body = json!({ "model": model, "input": texts });
+        HttpRequest::post_json(Self::url(endpoint, "embeddings"), &body)
+            .bearer(endpoint.api_key.as_deref())
+    }
+
+    fn decode_embeddings(&self, raw: &Value) -> Result<Vec<Vec<f32>>> {
+        OPENAI.decode_embeddings(raw)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::ChatMessage;
+
+    fn endpoint(base_url: &str, api_key: Option<&str>) -> Endpoint {
+        Endpoint {
+            base_url: base_url.to_string(),
+            api_key: api_key.map(str::to_string),
+            api_version: None,
+        }
+    }
+
+    #[test]
+    fn test_base_url_with_and_without_v1() {
+        let request = ChatRequest {
+            messages: vec![ChatMessage::user("hi")],
+            ..ChatRequest::default()
+        };
+        for (base_url, url) in [
+            (
+                "http://localhost:8000",
+                "http://localhost:8000/v1/chat/completions",
+            ),
+            (
+                "http://localhost:8000/",
+                "http://localhost:8000/v1/chat/completions",
+            ),
+            (
+                "http://localhost:1234/v1",
+                "http://localhost:1234/v1/chat/completions",
+            ),
+            (
+                "http://localhost:1234/v1/",
+                "http://localhost:1234/v1/chat/completions",
+            ),
+            (
+                "https://llm.example.com/openai",
+                "https://llm.example.com/openai/chat/completions",
+            ),
+        ] {
+            let http = OpenAiCompatible.encode(&endpoint(base_url, None), "qwen2.5", &request);
+            assert_eq!(http.url, url);
+        }
+    }
+
+    #[test]
+    fn test_api_key_is_optional() {
+        let request = ChatRequest::default();
+        let http = OpenAiCompatible.encode(&endpoint("http://localhost:8000", None), "m", &request);
+        assert!(!http.headers.iter().any(|(name, _)| name == "authorization"));
+
+        let keyed = endpoint("http://localhost:4000", Some("sk-litellm"));
+        let http = OpenAiCompatible.encode(&keyed, "m", &request);
+        assert!(http
+            .headers
+            .contains(&("authorization".to_string(), "Bearer sk-litellm".to_string())));
+    }
+
+    #[test]
+    fn test_response_without_usage() {
+        let response = OpenAiCompatible
+            .parse(&json!({
+                "choices": [{"message": {"role": "assistant", "content": "hi"}}]
+            }))
+            .unwrap();
+        assert_eq!(response.content, "hi");
+        assert_eq!(response.usage.total_tokens, 0);
+
+        // Some servers send the usage with only some of its counts
+        let response = OpenAiCompatible
+            .parse(&json!({
+                "choices": [{"message": {"role": "assistant", "content": "hi"}}],
+                "usage": {"total_tokens": 12}
+            }))
+            .unwrap();
+        assert_eq!(response.usage.total_tokens, 12);
+        let response = OpenAiCompatible
+            .parse(&json!({
+                "choices": [{"message": {"role": "assistant", "content": "hi"}}],
+                "usage": null
+            }))
+            .unwrap();
+        assert_eq!(response.usage.total_tokens, 0);
+    }
+
+    #[test]
+    fn test_embeddings() {
+        let texts = vec!["a".to_string()];
+        let endpoint = endpoint("http://localhost:8080", None);
+        let http = OpenAiCompatible.encode_embeddings(&endpoint, "nomic-embed-text", &texts);
+        assert_eq!(http.url, "http://localhost:8080/v1/embeddings");
+    }
+}
@@ -0,0 +1,678 @@
+//! Gemini `generateContent`
+//!
+//! Providers configured with [`GeminiOptions`] send safety settings with
+//! every request, and may keep the system prompt and tools in Gemini's
+//! context cache. The cache entry is created on first use, renewed during
+//! the last tenth of its lifetime and replaced when the prompt changes;
+//! requests then refer to it as `cachedContent` instead of repeating it.
+//! When the cache can't be used, e.g. because the prompt is shorter than the
+//! minimum Gemini caches, requests carry the prompt as usual.
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+
+use super::{push_part, reasoning, system_text, usage, Dialect, Endpoint, Normalizer};
+use crate::provider::{
+    ChatMessage, ChatRequest, FinishReason, NormalizedResponse, NormalizedUsage, Role, ToolCall,
+};
+use crate::transport::{HttpRequest, HttpStatusError, HttpTransport, Method, Unreachable};
+use crate::GeminiOptions;
+
+pub(crate) struct Gemini;
+
+impl Dialect for Gemini {
+    fn default_base_url(&self) -> &'static str {
+        "https://generativelanguage.googleapis.com/v1beta"
+    }
+
+    /// Thinking arrived with Gemini 2.5
+    fn supports_reasoning(&self, model: &str) -> bool {
+        !["gemini-1", "gemini-2.0", "gemini-pro"]
+            .iter()
+            .any(|family| model.starts_with(family))
+    }
+
+    fn encode(&self, endpoint: &Endpoint, model: &str, request: &ChatRequest) -> HttpRequest {
+        post(endpoint, &generate_path(model), &body(request))
+    }
+}
+
+fn generate_path(model: &str) -> String {
+    format!("models/{}:generateContent", model)
+}
+
+/// `generateContent` body for `request`
+pub(super) fn body(request: &ChatRequest) -> Value {
+    let mut contents = Vec::new();
+    for (index, message) in request.messages.iter().enumerate() {
+        match message.role {
+            Role::System => {}
+            Role::User => push_part(
+                &mut contents,
+                "user",
+                "parts",
+                json!({ "text": message.content }),
+            ),
+            Role::Assistant => {
+                if !message.content.is_empty() {
+                    push_part(
+                        &mut contents,
+                        "model",
+                        "parts",
+                        json!({ "text": message.content }),
+                    );
+                }
+                for call in &message.tool_calls {
+                    let call = json!({ "name": call.name, "args": call.arguments });
+                    push_part(
+                        &mut contents,
+                        "model",
+                        "parts",
+                        json!({ "functionCall": call }),
+                    );
+                }
+            }
+            Role::Tool => {
+                // Results are matched to calls by function name
+                let name = called_function(&request.messages[..index], message);
+                push_part(
+                    &mut contents,
+                    "user",
+                    "parts",
+                    json!({
+                        "functionResponse": {
+                            "name": name,
+                            "response": { "content": message.content },
+                        }
+                    }),
+                );
+            }
+        }
+    }
+
+    let mut body = json!({ "contents": contents });
+    if let Some(system) = system_text(request) {
+        body["systemInstruction"] = json!({ "parts": [{ "text": system }] });
+    }
+    if !request.tools.is_empty() {
+        let declarations: Vec<Value> = request
+            .tools
+            .iter()
+            .map(|tool| {
+                json!({
+                    "name": tool.name,
+                    "description": tool.description,
+                    "parameters": tool.parameters,
+                })
+            })
+            .collect();
+        body["tools"] = json!([{ "functionDeclarations": declarations }]);
+    }
+    let mut generation = serde_json::Map::new();
+    if let Some(max_tokens) = request.max_tokens {
+        generation.insert("maxOutputTokens".to_string(), max_tokens.into());
+    }
+    if let Some(temperature) = request.temperature {
+        generation.insert("temperature".to_string(), temperature.into());
+    }
+    if let Some(seed) = request.seed {
+        generation.insert("seed".to_string(), seed.into());
+    }
+    // Thought summaries come back as parts flagged `thought`
+    if let Some(reasoning) = &request.reasoning {
+        generation.insert(
+            "thinkingConfig".to_string(),
+            json!({ "thinkingBudget": reasoning.thinking_budget(), "includeThoughts": true }),
+        );
+    }
+    if !generation.is_empty() {
+        body["generationConfig"] = generation.into();
+    }
+    body
+}
+
+fn post(endpoint: &Endpoint, path: &str, body: &Value) -> HttpRequest {
+    authorized(HttpRequest::post_json(endpoint.url(path), body), endpoint)
+}
+
+fn authorized(request: HttpRequest, endpoint: &Endpoint) -> HttpRequest {
+    match &endpoint.api_key {
+        Some(key) => request.header("x-goog-api-key", key),
+        None => request,
+    }
+}
+
+/// Safety settings and context cache of one configured provider
+pub(crate) struct Extras {
+    safety_settings: Vec<Value>,
+    cache: Option<ContextCache>,
+}
+
+impl Extras {
+    pub fn new(options: &GeminiOptions) -> Self {
+        Self {
+            safety_settings: options
+                .safety_settings
+                .iter()
+                .map(|(category, threshold)| json!({"category": category, "threshold": threshold}))
+                .collect(),
+            cache: options.cache_ttl.map(|ttl| ContextCache {
+                ttl,
+                entry: Mutex::new(None),
+            }),
+        }
+    }
+
+    /// Like [`Dialect::encode`], creating or renewing the cache entry first
+    /// when caching is on
+    pub async fn encode(
+        &self, transport: &dyn HttpTransport, endpoint: &Endpoint, model: &str,
+        request: &ChatRequest, service: &str,
+    ) -> HttpRequest {
+        let mut body = body(request);
+        if !self.safety_settings.is_empty() {
+            body["safetySettings"] = self.safety_settings.clone().into();
+        }
+        if let (Some(cache), Some(body)) = (&self.cache, body.as_object_mut()) {
+            if let Some(system) = body.get("systemInstruction").cloned() {
+                let tools = body.get("tools").cloned();
+                match cache
+                    .content(transport, endpoint, model, system, tools, service)
+                    .await
+                {
+                    Ok(name) => {
+                        // Gemini rejects requests repeating what is cached
+                        body.remove("systemInstruction");
+                        body.remove("tools");
+                        body.insert("cachedContent".to_string(), name.into());
+                    }
+                    Err(err) => {
+                        tracing::warn!("{} sends its system prompt uncached: {:#}", service, err)
+                    }
+                }
+            }
+        }
+        post(endpoint, &generate_path(model), &body)
+    }
+}
+
+/// The cache entry holding a provider's system prompt and tools
+struct ContextCache {
+    ttl: Duration,
+    /// Held while the entry is created or renewed, so concurrent requests
+    /// share one
+    entry: Mutex<Option<CachedContent>>,
+}
+
+struct CachedContent {
+    /// Hash of what is cached
+    key: String,
+    /// e.g. `cachedContents/abc123`
+    name: String,
+    expires: Instant,
+}
+
+impl ContextCache {
+    /// Name of a live entry for `system` and `tools`
+    async fn content(
+        &self, transport: &dyn HttpTransport, endpoint: &Endpoint, model: &str, system: Value,
+        tools: Option<Value>, service: &str,
+    ) -> Result<String> {
+        let key = hex(&Sha256::digest(
+            json!({"model": model, "system": system, "tools": tools}).to_string(),
+        ));
+        let mut entry = self.entry.lock().await;
+        let now = Instant::now();
+        if let Some(cached) = entry.as_mut().filter(|cached| cached.key == key) {
+            if now + self.ttl / 10 < cached.expires {
+                return Ok(cached.name.clone());
+            }
+            if now < cached.expires {
+                let request = HttpRequest::post_json(
+                    endpoint.url(&format!("{}?updateMask=ttl", cached.name)),
+                    &json!({ "ttl": self.ttl_text() }),
+                );
+                let request = HttpRequest {
+                    method: Method::PATCH,
+                    ..authorized(request, endpoint)
+                };
+                send(transport, request, service).await?;
+                cached.expires = now + self.ttl;
+                return Ok(cached.name.clone());
+            }
+        }
+
+        let mut content = json!({
+            "model": format!("models/{}", model),
+            "systemInstruction": system,
+            "ttl": self.ttl_text(),
+        });
+        if let Some(tools) = tools {
+            content["tools"] = tools;
+        }
+        let created = send(
+            transport,
+            post(endpoint, "cachedContents", &content),
+            service,
+        )
+        .await?;
+        let name = created["name"]
+            .as_str()
+            .context("Cached content has no name")?
+            .to_string();
+        *entry = Some(CachedContent {
+            key,
+            name: name.clone(),
+            expires: now + self.ttl,
+        });
+        Ok(name)
+    }
+
+    /// Gemini's duration format
+    fn ttl_text(&self) -> String {
+        format!("{}s", self.ttl.as_secs())
+    }
+}
+
+pub(super) async fn send(
+    transport: &dyn HttpTransport, request: HttpRequest, service: &str,
+) -> Result<Value> {
+    let response = transport.send(request).await.map_err(|err| {
+        err.context(Unreachable {
+            service: service.to_string(),
+        })
+    })?;
+    if !response.is_success() {
+        return Err(HttpStatusError::from_response(service, &response).into());
+    }
+    serde_json::from_slice(&response.body)
+        .with_context(|| format!("{} returned invalid JSON", service))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+impl Normalizer for Gemini {
+    fn parse(&self, raw: &Value) -> Result<NormalizedResponse> {
+        let generated = Generated::deserialize(raw)?;
+        let candidate = generated
+            .candidates
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("Response has no candidates"))?;
+        let mut response = NormalizedResponse {
+            usage: generated
+                .usage_metadata
+                .map(|u| NormalizedUsage {
+                    cached_tokens: u.cached_content_token_count,
+                    reasoning_tokens: u.thoughts_token_count,
+                    // Thoughts aren't counted among the candidates' tokens
+                    ..usage(
+                        u.prompt_token_count,
+                        u.candidates_token_count + u.thoughts_token_count,
+                        u.total_token_count,
+                    )
+                })
+                .unwrap_or_default(),
+            finish_reason: match candidate.finish_reason.as_deref() {
+                None | Some("STOP") => FinishReason::Stop,
+                Some("MAX_TOKENS") => FinishReason::Length,
+                Some(
+                    "SAFETY" | "RECITATION" | "BLOCKLIST" | "PROHIBITED_CONTENT" | "SPII"
+                    | "IMAGE_SAFETY",
+                ) => FinishReason::ContentFilter,
+                Some(other) => FinishReason::Other(other.to_string()),
+            },
+            ..NormalizedResponse::default()
+        };
+        let mut thoughts = Vec::new();
+        for part in candidate.content.map(|c| c.parts).unwrap_or_default() {
+            match part.text {
+                Some(text) if part.thought => thoughts.push(text),
+                Some(text) => response.content.push_str(&text),
+                None => {}
+            }
+            if let Some(call) = part.function_call {
+                // Gemini doesn't identify calls; ids only need to be unique per turn
+                response.tool_calls.push(ToolCall {
+                    id: format!("call_{}", response.tool_calls.len()),
+                    name: call.name,
+                    arguments: call.args,
+                });
+            }
+        }
+        response.reasoning = reasoning(thoughts);
+        Ok(response)
+    }
+}
+
+/// Name of the function a tool message answers, looking at the latest calls first
+fn called_function<'a>(earlier: &'a [ChatMessage], result: &ChatMessage) -> &'a str {
+    earlier
+        .iter()
+        .rev()
+        .flat_map(|m| &m.tool_calls)
+        .find(|call| Some(&call.id) == result.tool_call_id.as_ref())
+        .map_or("", |call| call.name.as_str())
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Generated {
+    #[serde(default)]
+    candidates: Vec<Candidate>,
+    usage_metadata: Option<UsageMetadata>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Candidate {
+    content: Option<Content>,
+    finish_reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Content {
+    #[serde(default)]
+    parts: Vec<Part>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Part {
+    text: Option<String>,
+    /// Set on thought summaries
+    #[serde(default)]
+    thought: bool,
+    function_call: Option<FunctionCall>,
+}
+
+#[derive(Deserialize)]
+struct FunctionCall {
+    name: String,
+    #[serde(default)]
+    args: Value,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UsageMetadata {
+    #[serde(default)]
+    prompt_token_count: u64,
+    #[serde(default)]
+    candidates_token_count: u64,
+    total_token_count: Option<u64>,
+    /// Part of the prompt read from cached content
+    #[serde(default)]
+    cached_content_token_count: u64,
+    /// Tokens spent thinking
+    #[serde(default)]
+    thoughts_token_count: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockTransport;
+    use crate::wire::HttpProvider;
+    use crate::Provider;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_encode_function_response_uses_call_name() {
+        let endpoint = Endpoint {
+            base_url: Gemini.default_base_url().to_string(),
+            api_key: Some("AIza".to_string()),
+            api_version: None,
+        };
+        let mut assistant = ChatMessage::assistant("");
+        assistant.tool_calls.push(ToolCall {
+            id: "call_0".to_string(),
+            name: "word_count".to_string(),
+            arguments: json!({"text": "a b"}),
+        });
+        let request = ChatRequest {
+            system: Some("Be brief".to_string()),
+            messages: vec![
+                ChatMessage::user("count a b"),
+                assistant,
+                ChatMessage::tool("call_0", "2"),
+            ],
+            temperature: Some(0.5),
+            ..ChatRequest::default()
+        };
+
+        let http = Gemini.encode(&endpoint, "gemini-1.5-pro", &request);
+        assert_eq!(
+            http.url,
+            "https://generativelanguage.googleapis.com/v1beta/models/gemini-1.5-pro:generateContent"
+        );
+        assert!(!http.url.contains("AIza"));
+        let body: Value = serde_json::from_slice(&http.body).unwrap();
+        assert_eq!(body["systemInstruction"]["parts"][0]["text"], "Be brief");
+        assert_eq!(body["contents"][1]["role"], "model");
+        assert_eq!(
+            body["contents"][2]["parts"][0]["functionResponse"]["name"],
+            "word_count"
+        );
+        assert_eq!(body["generationConfig"], json!({"temperature": 0.5}));
+    }
+
+    #[test]
+    fn test_normalize_parts() {
+        let response = Gemini
+            .normalize(json!({
+                "candidates": [{
+                    "content": {
+                        "role": "model",
+                        "parts": [
+                            {"text": "The user wants a count.", "thought": true},
+                            {"text": "Counting."},
+                            {"functionCall": {"name": "word_count", "args": {"text": "a b"}}}
+                        ]
+                    },
+                    "finishReason": "STOP"
+                }],
+                "usageMetadata": {
+                    "promptTokenCount": 7, "candidatesTokenCount": 4,
+                    "thoughtsTokenCount": 6, "totalTokenCount": 17
+                }
+            }))
+            .unwrap();
+        assert_eq!(response.content, "Counting.");
+        assert_eq!(response.tool_calls[0].id, "call_0");
+        assert_eq!(response.tool_calls[0].arguments, json!({"text": "a b"}));
+        assert_eq!(
+            response.reasoning.as_deref(),
+            Some("The user wants a count.")
+        );
+        assert_eq!(response.usage.completion_tokens, 10);
+        assert_eq!(response.usage.reasoning_tokens, 6);
+        assert_eq!(response.usage.total_tokens, 17);
+        assert_eq!(response.finish_reason, FinishReason::ToolCalls);
+    }
+
+    fn provider_config(name: &str, options: GeminiOptions) -> crate::ProviderConfig {
+        crate::ProviderConfig {
+            name: name.to_string(),
+            model: "gemini-1.5-pro".to_string(),
+            api_key: Some("AIza".to_string()),
+            base_url: Some("http://gemini.test/v1beta".to_string()),
+            gemini: Some(options),
+            ..crate::ProviderConfig::default()
+        }
+    }
+
+    fn options() -> GeminiOptions {
+        GeminiOptions {
+            safety_settings: [(
+                "HARM_CATEGORY_DANGEROUS_CONTENT".to_string(),
+                "BLOCK_ONLY_HIGH".to_string(),
+            )]
+            .into(),
+            cache_ttl: Some(Duration::from_secs(3600)),
+        }
+    }
+
+    fn generated(cached: u64) -> Value {
+        json!({
+            "candidates": [{"content": {"parts": [{"text": "ok"}]}, "finishReason": "STOP"}],
+            "usageMetadata": {
+                "promptTokenCount": 2000,
+                "candidatesTokenCount": 1,
+                "cachedContentTokenCount": cached
+            }
+        })
+    }
+
+    fn request(system: &str) -> ChatRequest {
+        ChatRequest {
+            system: Some(system.to_string()),
+            messages: vec![ChatMessage::user("hi")],
+            tools: vec![crate::provider::ToolDefinition {
+                name: "word_count".to_string(),
+                description: "Count words".to_string(),
+                parameters: json!({"type": "object"}),
+            }],
+            ..ChatRequest::default()
+        }
+    }
+
+    fn json_body(request: &HttpRequest) -> Value {
+        serde_json::from_slice(&request.body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_cache_is_created_lazily_then_reused() {
+        let transport = Arc::new(MockTransport::new());
+        transport.push_json(200, json!({"name": "cachedContents/c1"}));
+        transport.push_json(200, generated(1990));
+        transport.push_json(200, generated(1990));
+        transport.push_json(200, json!({"name": "cachedContents/c2"}));
+        transport.push_json(200, generated(0));
+        let provider =
+            HttpProvider::new(&provider_config("gemini", options()), transport.clone()).unwrap();
+        assert!(transport.requests().is_empty());
+
+        let response = provider.complete(request("Long preamble")).await.unwrap();
+        assert_eq!(response.usage.cached_tokens, 1990);
+        provider.complete(request("Long preamble")).await.unwrap();
+        // Another prompt needs an entry of its own
+        provider.complete(request("Other preamble")).await.unwrap();
+
+        let requests = transport.requests();
+        let urls: Vec<&str> = requests.iter().map(|r| r.url.as_str()).collect();
+        assert_eq!(
+            urls,
+            [
+                "http://gemini.test/v1beta/cachedContents",
+                "http://gemini.test/v1beta/models/gemini-1.5-pro:generateContent",
+                "http://gemini.test/v1beta/models/gemini-1.5-pro:generateContent",
+                "http://gemini.test/v1beta/cachedContents",
+                "http://gemini.test/v1beta/models/gemini-1.5-pro:generateContent",
+            ]
+        );
+        let created = json_body(&requests[0]);
+        assert_eq!(created["model"], "models/gemini-1.5-pro");
+        assert_eq!(created["ttl"], "3600s");
+        assert_eq!(
+            created["systemInstruction"]["parts"][0]["text"],
+            "Long preamble"
+        );
+        assert_eq!(
+            created["tools"][0]["functionDeclarations"][0]["name"],
+            "word_count"
+        );
+
+        for (request, cached) in [
+            (&requests[1], "cachedContents/c1"),
+            (&requests[4], "cachedContents/c2"),
+        ] {
+            let sent = json_body(request);
+            assert_eq!(sent["cachedContent"], cached);
+            assert_eq!(
+                sent["safetySettings"],
+                json!([{"category": "HARM_CATEGORY_DANGEROUS_CONTENT", "threshold": "BLOCK_ONLY_HIGH"}])
+            );
+            assert!(sent.get("systemInstruction").is_none());
+            assert!(sent.get("tools").is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cache_is_renewed_before_it_expires() {
+        let transport = Arc::new(MockTransport::new());
+        transport.push_json(200, json!({"name": "cachedContents/c1"}));
+        transport.push_json(200, json!({"name": "cachedContents/c1"}));
+        let extras = Extras::new(&options());
+        let endpoint = Endpoint {
+            base_url: "http://gemini.test/v1beta".to_string(),
+            api_key: Some("AIza".to_string()),
+            api_version: None,
+        };
+        let chat = request("Long preamble");
+        let encode = || {
+            extras.encode(
+                &*transport,
+                &endpoint,
+                "gemini-1.5-pro",
+                &chat,
+                "Provider 'gemini'",
+            )
+        };
+
+        encode().await;
+        let cache = extras.cache.as_ref().unwrap();
+        cache.entry.lock().await.as_mut().unwrap().expires =
+            Instant::now() + Duration::from_secs(60);
+        let sent = json_body(&encode().await);
+        assert_eq!(sent["cachedContent"], "cachedContents/c1");
+
+        let requests = transport.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[1].method, Method::PATCH);
+        assert_eq!(
+            requests[1].url,
+            "http://gemini.test/v1beta/cachedContents/c1?updateMask=ttl"
+        );
+        assert_eq!(json_body(&requests[1]), json!({"ttl": "3600s"}));
+    }
+
+    #[tokio::test]
+    async fn test_cache_failure_sends_the_prompt_inline() {
+        let transport = Arc::new(MockTransport::new());
+        transport.push_json(
+            400,
+            json!({"error": {"message": "Cached content is too small"}}),
+        );
+        transport.push_json(200, generated(0));
+        let provider =
+            HttpProvider::new(&provider_config("gemini", options()), transport.clone()).unwrap();
+        provider.complete(request("Short")).await.unwrap();
+
+        let sent = json_body(&transport.requests()[1]);
+        assert!(sent.get("cachedContent").is_none());
+        assert_eq!(sent["systemInstruction"]["parts"][0]["text"], "Short");
+    }
+
+    #[tokio::test]
+    async fn test_other_providers_ignore_gemini_options() {
+        let transport = Arc::new(MockTransport::new());
+        transport.push_json(200, json!({"choices": [{"message": {"content": "ok"}}]}));
+        let provider =
+            HttpProvider::new(&provider_config("openai", options()), transport.clone()).unwrap();
+        provider.complete(request("Long preamble")).await.unwrap();
+
+        let requests = transport.requests();
+        assert_eq!(requests.len(), 1);
+        let sent = json_body(&requests[0]);
+        assert!(sent.get("safetySettings").is_none());
+        assert_eq!(sent["messages"][0]["content"], "Long preamble");
+    }
+}
@@ -0,0 +1,122 @@
+//! Mistral's La Plateforme, which speaks OpenAI chat completions with a few
+//! differences
+//!
+//! The sampling seed is called `random_seed`, and streams end with the usage
+//! without being asked. A self-hosted deployment of the same API is reached
+//! by setting the provider's `base_url`.
+
+use anyhow::Result;
+use serde_json::{json, Value};
+
+use super::openai::{OpenAiStream, OPENAI};
+use super::{Dialect, EmbeddingDialect, Endpoint, Normalizer, StreamDecoder};
+use crate::provider::{ChatRequest, NormalizedResponse};
+use crate::transport::HttpRequest;
+
+pub(crate) struct Mistral;
+
+/// OpenAI's body, with the seed under Mistral's name
+fn body(model: &str, request: &ChatRequest) -> Value {
+    let mut body = OPENAI.body(model, request);
+    if let Some(seed) = body.as_object_mut().and_then(|body| body.remove("seed")) {
+        body["random_seed"] = seed;
+    }
+    body
+}
+
+impl Dialect for Mistral {
+    fn default_base_url(&self) -> &'static str {
+        "https://api.mistral.ai/v1"
+    }
+
+    fn encode(&self, endpoint: &Endpoint, model: &str, request: &ChatRequest) -> HttpRequest {
+        HttpRequest::post_json(endpoint.url("chat/completions"), &body(model, request))
+            .bearer(endpoint.api_key.as_deref())
+    }
+
+    fn list_models(&self, endpoint: &Endpoint) -> Option<HttpRequest> {
+        Some(HttpRequest::get(endpoint.url("models")).bearer(endpoint.api_key.as_deref()))
+    }
+
+    fn encode_stream(
+        &self, endpoint: &Endpoint, model: &str, request: &ChatRequest,
+    ) -> Option<(HttpRequest, Box<dyn StreamDecoder>)> {
+        let mut body = body(model, request);
+        body["stream"] = true.into();
+        let request = HttpRequest::post_json(endpoint.url("chat/completions"), &body)
+            .bearer(endpoint.api_key.as_deref());
+        Some((request, Box::<OpenAiStream>::default()))
+    }
+}
+
+impl Normalizer for Mistral {
+    fn parse(&self, raw: &Value) -> Result<NormalizedResponse> {
+        OPENAI.parse(raw)
+    }
+}
+
+impl EmbeddingDialect for Mistral {
+    fn encode_embeddings(&self, endpoint: &Endpoint, model: &str, texts: &[String]) -> HttpRequest {
+        let body = json!({ "model": model, "input": texts });
+        HttpRequest::post_json(endpoint.url("embeddings"), &body)
+            .bearer(endpoint.api_key.as_deref())
+    }
+
+    fn decode_embeddings(&self, raw: &Value) -> Result<Vec<Vec<f32>>> {
+        OPENAI.decode_embeddings(raw)
+    }
+
+    fn dimensions(&self, model: &str) -> Option<usize> {
+        match model {
+            "mistral-embed" => Some(1024),
+            "codestral-embed" => Some(1536),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::ChatMessage;
+
+    fn endpoint() -> Endpoint {
+        Endpoint {
+            base_url: Mistral.default_base_url().to_string(),
+            api_key: Some("test-key".to_string()),
+            api_version: None,
+        }
+    }
+
+    #[test]
+    fn test_encode_renames_seed() {
+        let request = ChatRequest {
+            messages: vec![ChatMessage::user("hi")],
+            seed: Some(7),
+            ..ChatRequest::default()
+        };
+        let http = Mistral.encode(&endpoint(), "mistral-large-latest", &request);
+        assert_eq!(http.url, "https://api.mistral.ai/v1/chat/completions");
+        let body: Value = serde_json::from_slice(&http.body).unwrap();
+        assert_eq!(body["random_seed"], 7);
+        assert!(body.get("seed").is_none());
+
+        let (http, _) = Mistral
+            .encode_stream(&endpoint(), "codestral-latest", &request)
+            .unwrap();
+        let body: Value = serde_json::from_slice(&http.body).unwrap();
+        assert_eq!(body["stream"], true);
+        assert!(body.get("stream_options").is_none());
+    }
+
+    #[test]
+    fn test_embeddings() {
+        let texts = vec!["a".to_string()];
+        let http = Mistral.encode_embeddings(&endpoint(), "mistral-embed", &texts);
+        assert_eq!(http.url, "https://api.mistral.ai/v1/embeddings");
+        let body: Value = serde_json::from_slice(&http.body).unwrap();
+        assert_eq!(body, json!({"model": "mistral-embed", "input": ["a"]}));
+        assert_eq!(Mistral.dimensions("mistral-embed"), Some(1024));
+        assert_eq!(Mistral.dimensions("mistral-embed-next"), None);
+    }
+}
@@ -0,0 +1,1429 @@
+//! Provider wire formats
+//!
+//! [`HttpProvider`] talks to a provider's HTTP API directly. Each supported
+//! API has a dialect that encodes a [`ChatRequest`] into the provider's JSON
+//! and a [`Normalizer`] that turns the reply into a [`NormalizedResponse`];
+//! requests go through an [`HttpTransport`]. [`HttpEmbedder`] does the same
+//! for embedding APIs, and [`HttpReranker`] for rerank APIs.
+//!
+//! With `streaming` enabled, providers whose APIs stream natively receive
+//! responses as server-sent events, decoded into [`StreamChunk`]s as they
+//! arrive, including the usage some APIs only send at the end.
+//!
+//! Bedrock and Vertex AI take no API key: requests are signed with AWS
+//! credentials, or carry a Google Cloud access token, just before they are
+//! sent.
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use futures::StreamExt;
+use serde_json::Value;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::task::JoinHandle;
+use tracing::Instrument;
+
+use crate::deterministic::RequestIds;
+use crate::embedding::EmbeddingModel;
+use crate::provider::{
+    self, ChatRequest, ChatStream, FinishReason, NormalizedResponse, NormalizedUsage, Provider,
+    Role, StreamChunk,
+};
+use crate::rerank::{Relevance, Reranker};
+use crate::transport::{HttpRequest, HttpStatusError, HttpTransport, RateLimits, Unreachable};
+use crate::ProviderConfig;
+
+pub use bedrock::NoAwsCredentials;
+pub use vertex::NoGoogleCredentials;
+
+mod anthropic;
+mod azure;
+mod bedrock;
+mod cohere;
+mod compatible;
+mod gemini;
+mod mistral;
+mod ollama;
+mod openai;
+mod vertex;
+
+/// Turns one provider API's response body into a [`NormalizedResponse`]
+pub trait Normalizer: Send + Sync {
+    /// Extract everything but `provider_raw`
+    fn parse(&self, raw: &Value) -> Result<NormalizedResponse>;
+
+    /// Normalize a response body, keeping it as `provider_raw`
+    ///
+    /// A plain stop is reported as [`FinishReason::ToolCalls`] when the model
+    /// asked for tools, since some APIs don't distinguish the two.
+    fn normalize(&self, raw: Value) -> Result<NormalizedResponse> {
+        let mut response = self.parse(&raw)?;
+        if response.finish_reason == FinishReason::Stop && !response.tool_calls.is_empty() {
+            response.finish_reason = FinishReason::ToolCalls;
+        }
+        response.provider_raw = raw;
+        Ok(response)
+    }
+}
+
+/// Normalizer for the API of a configured provider, e.g. `"anthropic"`
+pub fn normalizer(provider: &str) -> Option<&'static dyn Normalizer> {
+    Some(match provider {
+        "openai" | "deepseek" | "groq" => &openai::OPENAI,
+        "openai-compatible" => &compatible::OpenAiCompatible,
+        "azure" => &azure::Azure,
+        "anthropic" => &anthropic::Anthropic,
+        "gemini" => &gemini::Gemini,
+        "mistral" => &mistral::Mistral,
+        "ollama" => &ollama::Ollama,
+        "cohere" => &cohere::Cohere,
+        "bedrock" => &bedrock::Bedrock,
+        "vertex" => &vertex::Vertex,
+        _ => return None,
+    })
+}
+
+/// Request encoding for one provider API
+pub(crate) trait Dialect: Normalizer {
+    /// Base URL used when the provider config names none
+    fn default_base_url(&self) -> &'static str;
+
+    /// Whether requests fail without an API key
+    fn requires_api_key(&self) -> bool {
+        true
+    }
+
+    /// Whether the provider config must name the URL, for APIs served from
+    /// the user's own resource
+    fn requires_base_url(&self) -> bool {
+        false
+    }
+
+    /// Whether `model` takes [`ChatRequest::reasoning`]
+    fn supports_reasoning(&self, _model: &str) -> bool {
+        false
+    }
+
+    fn encode(&self, endpoint: &Endpoint, model: &str, request: &ChatRequest) -> HttpRequest;
+
+    /// A request listing the available models, for APIs that have one; the
+    /// cheapest way to find out whether a key is accepted
+    fn list_models(&self, _endpoint: &Endpoint) -> Option<HttpRequest> {
+        None
+    }
+
+    /// A streaming request and the decoder for its events, for APIs that
+    /// stream
+    fn encode_stream(
+        &self, _endpoint: &Endpoint, _model: &str, _request: &ChatRequest,
+    ) -> Option<(HttpRequest, Box<dyn StreamDecoder>)> {
+        None
+    }
+}
+
+/// Turns the events of one streamed response into chunks
+pub(crate) trait StreamDecoder: Send {
+    /// Chunks carried by the data of one event
+    fn event(&mut self, data: &Value) -> Result<Vec<StreamChunk>>;
+
+    /// Chunks still pending when the stream ends
+    fn finish(&mut self) -> Vec<StreamChunk>;
+}
+
+/// Splits a server-sent event stream into the data of its events
+#[derive(Default)]
+struct EventBuffer {
+    pending: Vec<u8>,
+    data: String,
+}
+
+impl EventBuffer {
+    /// Data of every event completed by `bytes`; lines and events may be
+    /// split across pushes
+    fn push(&mut self, bytes: &[u8]) -> Vec<String> {
+        self.pending.extend_from_slice(bytes);
+        let mut events = Vec::new();
+        while let Some(end) = self.pending.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.pending.drain(..=end).collect();
+            events.extend(self.line(&String::from_utf8_lossy(&line)));
+        }
+        events
+    }
+
+    /// Data of an event the stream ended without terminating
+    fn finish(&mut self) -> Option<String> {
+        let line = std::mem::take(&mut self.pending);
+        self.line(&String::from_utf8_lossy(&line));
+        Some(std::mem::take(&mut self.data)).filter(|data| !data.is_empty())
+    }
+
+    fn line(&mut self, line: &str) -> Option<String> {
+        let line = line.trim_end_matches(['\r', '\n']);
+        if line.is_empty() {
+            return Some(std::mem::take(&mut self.data)).filter(|data| !data.is_empty());
+        }
+        // Event names, ids and comments aren't needed; the data says it all
+        if let Some(data) = line.strip_prefix("data:") {
+            if !self.data.is_empty() {
+                self.data.push('\n');
+            }
+            self.data.push_str(data.strip_prefix(' ').unwrap_or(data));
+        }
+        None
+    }
+}
+
+/// Where and as whom requests are sent
+#[derive(Debug, Clone)]
+pub(crate) struct Endpoint {
+    pub base_url: String,
+    pub api_key: Option<String>,
+    /// `api-version` of Azure OpenAI requests
+    pub api_version: Option<String>,
+}
+
+impl Endpoint {
+    /// Where `config` points, or `default_base_url` when it names no URL
+    fn of(config: &ProviderConfig, default_base_url: &str) -> Self {
+        Self {
+            base_url: config
+                .base_url
+                .clone()
+                .unwrap_or_else(|| default_base_url.to_string()),
+            api_key: config.api_key.clone(),
+            api_version: config.api_version.clone(),
+        }
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("{}/{}", self.base_url.trim_end_matches('/'), path)
+    }
+}
+
+/// Chat APIs [`HttpProvider`] speaks, by [`ProviderConfig::name`]
+///
+/// Each has request snapshots in `tests/wire_conformance.rs`.
+pub const PROVIDERS: &[&str] = &[
+    "openai",
+    "deepseek",
+    "azure",
+    "groq",
+    "openai-compatible",
+    "anthropic",
+    "gemini",
+    "mistral",
+    "ollama",
+    "cohere",
+    "bedrock",
+    "vertex",
+];
+
+/// Kept in step with [`normalizer`] and [`PROVIDERS`]
+fn dialect(provider: &str) -> Option<&'static dyn Dialect> {
+    Some(match provider {
+        "openai" => &openai::OPENAI,
+        "deepseek" => &openai::DEEPSEEK,
+        "groq" => &openai::GROQ,
+        "openai-compatible" => &compatible::OpenAiCompatible,
+        "azure" => &azure::Azure,
+        "anthropic" => &anthropic::Anthropic,
+        "gemini" => &gemini::Gemini,
+        "mistral" => &mistral::Mistral,
+        "ollama" => &ollama::Ollama,
+        "cohere" => &cohere::Cohere,
+        "bedrock" => &bedrock::Bedrock,
+        "vertex" => &vertex::Vertex,
+        _ => return None,
+    })
+}
+
+/// The model listing of `config`'s API, if it has one
+pub(crate) fn list_models_request(config: &ProviderConfig) -> Option<HttpRequest> {
+    let dialect = dialect(&config.name)?;
+    dialect.list_models(&Endpoint::of(config, dialect.default_base_url()))
+}
+
+/// Endpoint of an Ollama provider, whose model management API is served
+/// next to the chat API
+pub(crate) fn ollama_endpoint(config: &ProviderConfig) -> Endpoint {
+    Endpoint::of(config, ollama::Ollama.default_base_url())
+}
+
+/// Embedding request encoding for one provider API
+pub(crate) trait EmbeddingDialect: Dialect {
+    fn encode_embeddings(&self, endpoint: &Endpoint, model: &str, texts: &[String]) -> HttpRequest;
+
+    /// One vector per input text, in input order
+    fn decode_embeddings(&self, raw: &Value) -> Result<Vec<Vec<f32>>>;
+
+    /// Length of `model`'s vectors, for models known to have one
+    fn dimensions(&self, _model: &str) -> Option<usize> {
+        None
+    }
+
+    /// Most texts one request may carry, for APIs that limit them
+    fn max_batch(&self, _model: &str) -> Option<usize> {
+        None
+    }
+}
+
+fn embedding_dialect(provider: &str) -> Option<&'static dyn EmbeddingDialect> {
+    Some(match provider {
+        "openai" => &openai::OPENAI,
+        "openai-compatible" => &compatible::OpenAiCompatible,
+        "azure" => &azure::Azure,
+        "mistral" => &mistral::Mistral,
+        "ollama" => &ollama::Ollama,
+        "cohere" => &cohere::Cohere,
+        "bedrock" => &bedrock::Bedrock,
+        "vertex" => &vertex::Vertex,
+        _ => return None,
+    })
+}
+
+/// Authorizes the requests of providers that take no API key
+pub(crate) enum Signer {
+    /// Signature Version 4 with AWS credentials
+    Aws(bedrock::Signer),
+    /// A Google Cloud access token
+    Google(vertex::TokenSource),
+}
+
+impl Signer {
+    async fn sign(
+        &self, transport: &dyn HttpTransport, request: HttpRequest,
+    ) -> Result<HttpRequest> {
+        match self {
+            Self::Aws(signer) => signer.sign(transport, request).await,
+            Self::Google(tokens) => tokens.authorize(transport, request).await,
+        }
+    }
+}
+
+/// Where `config` points, and the signer of providers that authorize their
+/// requests with cloud credentials instead of an API key
+fn endpoint_of(
+    config: &ProviderConfig, default_base_url: &str,
+) -> Result<(Endpoint, Option<Signer>)> {
+    let mut endpoint = Endpoint::of(config, default_base_url);
+    for (provider, configured) in [
+        ("bedrock", config.bedrock.is_some()),
+        ("vertex", config.vertex.is_some()),
+    ] {
+        if configured && config.name != provider {
+            tracing::warn!(
+                provider = %config.name,
+                "Ignoring {} options, which only apply to the {} provider",
+                provider,
+                provider
+            );
+        }
+    }
+    match config.name.as_str() {
+        "bedrock" => {
+            let signer = bedrock::Signer::new(config)?;
+            if config.base_url.is_none() {
+                endpoint.base_url = signer.base_url();
+            }
+            Ok((endpoint, Some(Signer::Aws(signer))))
+        }
+        "vertex" => {
+            let tokens = vertex::TokenSource::new(config)?;
+            endpoint.base_url = tokens.base_url().to_string();
+            // A configured key is an access token already
+            let signer = config.api_key.is_none().then_some(Signer::Google(tokens));
+            Ok((endpoint, signer))
+        }
+        _ => Ok((endpoint, None)),
+    }
+}
+
+/// Rerank request encoding for one provider API
+pub(crate) trait RerankDialect: Dialect {
+    fn encode_rerank(
+        &self, endpoint: &Endpoint, model: &str, query: &str, documents: &[String], top_n: usize,
+    ) -> HttpRequest;
+
+    /// Best first
+    fn decode_rerank(&self, raw: &Value) -> Result<Vec<Relevance>>;
+}
+
+fn rerank_dialect(provider: &str) -> Option<&'static dyn RerankDialect> {
+    Some(match provider {
+        "cohere" => &cohere::Cohere,
+        _ => return None,
+    })
+}
+
+/// [`Provider`] speaking a provider's HTTP API
+pub struct HttpProvider {
+    name: String,
+    model: String,
+    endpoint: Endpoint,
+    dialect: &'static dyn Dialect,
+    transport: Arc<dyn HttpTransport>,
+    request_ids: Arc<RequestIds>,
+    seed: Option<u64>,
+    streaming: bool,
+    gemini: Option<gemini::Extras>,
+    signer: Option<Signer>,
+    /// Whether a request asked a model that doesn't reason to think
+    warned_reasoning: AtomicBool,
+}
+
+impl HttpProvider {
+    /// Provider for `config.name`, which must be one of the supported APIs
+    pub fn new(config: &ProviderConfig, transport: Arc<dyn HttpTransport>) -> Result<Self> {
+        let dialect = dialect(&config.name)
+            .ok_or_else(|| anyhow::anyhow!("Unknown provider: {}", config.name))?;
+        if dialect.requires_api_key() && config.api_key.is_none() {
+            match crate::secrets::default_api_key_var(&config.name) {
+                Some(var) => anyhow::bail!(
+                    "Provider '{}' requires an api_key (set {} or the provider's api_key)",
+                    config.name,
+                    var
+                ),
+                None => anyhow::bail!("Provider '{}' requires an api_key", config.name),
+            }
+        }
+        if dialect.requires_base_url() && config.base_url.is_none() {
+            anyhow::bail!(
+                "Provider '{}' requires a base_url (the endpoint it is served from)",
+                config.name
+            );
+        }
+        let gemini = match &config.gemini {
+            Some(options) if config.name == "gemini" => Some(gemini::Extras::new(options)),
+            Some(_) => {
+                tracing::warn!(
+                    provider = %config.name,
+                    "Ignoring gemini options, which only apply to the gemini provider"
+                );
+                None
+            }
+            None => None,
+        };
+        let (endpoint, signer) = endpoint_of(config, dialect.default_base_url())?;
+        Ok(Self {
+            name: config.name.clone(),
+            model: config.model.clone(),
+            endpoint,
+            dialect,
+            transport,
+            request_ids: Arc::default(),
+            seed: None,
+            streaming: config.streaming,
+            gemini,
+            signer,
+            warned_reasoning: AtomicBool::new(false),
+        })
+    }
+
+    /// Take request ids from `request_ids`, e.g. one shared by a client
+    pub fn with_request_ids(mut self, request_ids: Arc<RequestIds>) -> Self {
+        self.request_ids = request_ids;
+        self
+    }
+
+    /// Seed for requests that don't carry their own
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Fill in the provider's seed and drop reasoning settings the model
+    /// can't take, warning the first time
+    fn prepare(&self, request: &mut ChatRequest) {
+        request.seed = request.seed.or(self.seed);
+        if request.reasoning.is_some() && !self.dialect.supports_reasoning(&self.model) {
+            request.reasoning = None;
+            if !self.warned_reasoning.swap(true, Ordering::Relaxed) {
+                tracing::warn!(
+                    provider = %self.name,
+                    model = %self.model,
+                    "model doesn't take reasoning settings; ignoring them"
+                );
+            }
+        }
+    }
+
+    async fn send(&self, mut request: ChatRequest) -> Result<NormalizedResponse> {
+        let span = tracing::Span::current();
+        self.prepare(&mut request);
+        let request_id = self.request_ids.next_id();
+        span.record("request_id", request_id.as_str());
+        let service = format!("Provider '{}'", self.name);
+        let request = match &self.gemini {
+            Some(gemini) => {
+                gemini
+                    .encode(
+                        &*self.transport,
+                        &self.endpoint,
+                        &self.model,
+                        &request,
+                        &service,
+                    )
+                    .await
+            }
+            None => self.dialect.encode(&self.endpoint, &self.model, &request),
+        }
+        .header("x-request-id", request_id);
+        let request = match &self.signer {
+            Some(signer) => signer.sign(&*self.transport, request).await?,
+            None => request,
+        };
+        let response = self.transport.send(request).await.map_err(|err| {
+            err.context(Unreachable {
+                service: service.clone(),
+            })
+        })?;
+        span.record("http.status_code", i64::from(response.status));
+        if !response.is_success() {
+            return Err(HttpStatusError::from_response(service, &response).into());
+        }
+        let rate_limits = RateLimits::from_response(&response);
+        let body: Value = serde_json::from_slice(&response.body)
+            .with_context(|| format!("Provider '{}' returned invalid JSON", self.name))?;
+        let mut response = self
+            .dialect
+            .normalize(body)
+            .with_context(|| format!("Unexpected response from provider '{}'", self.name))?;
+        response.rate_limits = rate_limits;
+        // Unsigned values are exported as strings
+        span.record("prompt_tokens", response.usage.prompt_tokens as i64);
+        span.record("completion_tokens", response.usage.completion_tokens as i64);
+        span.record(
+            "finish_reason",
+            tracing::field::debug(&response.finish_reason),
+        );
+        Ok(response)
+    }
+}
+
+/// Send a streaming request, decoding its events into `chunks` as they
+/// arrive
+async fn receive_stream(
+    transport: &dyn HttpTransport, request: HttpRequest, mut decoder: Box<dyn StreamDecoder>,
+    name: &str, chunks: &UnboundedSender<Result<StreamChunk>>,
+) -> Result<()> {
+    let service = format!("Provider '{}'", name);
+    let mut events = EventBuffer::default();
+    let mut failure = None;
+    let mut on_event = |data: String, decoder: &mut dyn StreamDecoder| {
+        // OpenAI ends streams with a `[DONE]` event that isn't JSON
+        if failure.is_some() || data == "[DONE]" {
+            return;
+        }
+        let decoded = serde_json::from_str(&data)
+            .with_context(|| format!("Provider '{}' returned invalid JSON", name))
+            .and_then(|event| decoder.event(&event));
+        match decoded {
+            Ok(decoded) => {
+                for chunk in decoded {
+                    // The stream was dropped; the task is about to be aborted
+                    let _ = chunks.send(Ok(chunk));
+                }
+            }
+            Err(err) => failure = Some(err),
+        }
+    };
+    let response = transport
+        .send_streaming(request, &mut |bytes| {
+            for data in events.push(bytes) {
+                on_event(data, decoder.as_mut());
+            }
+        })
+        .await
+        .map_err(|err| {
+            err.context(Unreachable {
+                service: service.clone(),
+            })
+        })?;
+    if !response.is_success() {
+        return Err(HttpStatusError::from_response(service, &response).into());
+    }
+    if let Some(data) = events.finish() {
+        on_event(data, decoder.as_mut());
+    }
+    if let Some(err) = failure {
+        return Err(err.context(format!("Unexpected response from provider '{}'", name)));
+    }
+    for chunk in decoder.finish() {
+        let _ = chunks.send(Ok(chunk));
+    }
+    Ok(())
+}
+
+/// Chunks sent by a streaming task, which is aborted when the stream is
+/// dropped
+fn chunk_stream(
+    receiver: UnboundedReceiver<Result<StreamChunk>>, task: JoinHandle<()>,
+) -> ChatStream {
+    struct AbortOnDrop(JoinHandle<()>);
+
+    impl Drop for AbortOnDrop {
+        fn drop(&mut self) {
+            self.0.abort();
+        }
+    }
+
+    futures::stream::unfold(
+        (receiver, AbortOnDrop(task)),
+        |(mut receiver, task)| async move {
+            let chunk = receiver.recv().await?;
+            Some((chunk, (receiver, task)))
+        },
+    )
+    .boxed()
+}
+
+#[async_trait]
+impl Provider for HttpProvider {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    async fn complete(&self, request: ChatRequest) -> Result<NormalizedResponse> {
+        // Exported as span attributes when telemetry is enabled
+        let span = tracing::info_span!(
+            "provider.complete",
+            provider = %self.name,
+            model = %self.model,
+            request_id = tracing::field::Empty,
+            http.status_code = tracing::field::Empty,
+            prompt_tokens = tracing::field::Empty,
+            completion_tokens = tracing::field::Empty,
+            finish_reason = tracing::field::Empty,
+        );
+        self.send(request).instrument(span).await
+    }
+
+    async fn stream(&self, mut request: ChatRequest) -> Result<ChatStream> {
+        self.prepare(&mut request);
+        let encoded = self
+            .dialect
+            .encode_stream(&self.endpoint, &self.model, &request)
+            .filter(|_| self.streaming);
+        let Some((http, decoder)) = encoded else {
+            return Ok(provider::response_stream(self.complete(request).await?));
+        };
+        let request_id = self.request_ids.next_id();
+        let span = tracing::info_span!(
+            "provider.stream",
+            provider = %self.name,
+            model = %self.model,
+            request_id = %request_id,
+        );
+        let http = http.header("x-request-id", request_id);
+        let transport = self.transport.clone();
+        let name = self.name.clone();
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let task = tokio::spawn(
+            async move {
+                let received =
+                    receive_stream(transport.as_ref(), http, decoder, &name, &sender).await;
+                if let Err(err) = received {
+                    let _ = sender.send(Err(err));
+                }
+            }
+            .instrument(span),
+        );
+        Ok(chunk_stream(receiver, task))
+    }
+}
+
+/// [`EmbeddingModel`] speaking a provider's HTTP embedding API
+pub struct HttpEmbedder {
+    provider: String,
+    model: String,
+    endpoint: Endpoint,
+    dialect: &'static dyn EmbeddingDialect,
+    transport: Arc<dyn HttpTransport>,
+    request_ids: Arc<RequestIds>,
+    signer: Option<Signer>,
+}
+
+impl HttpEmbedder {
+    /// Embedder for `config.name`, which must offer an embedding API
+    pub fn new(config: &ProviderConfig, transport: Arc<dyn HttpTransport>) -> Result<Self> {
+        let dialect = embedding_dialect(&config.name)
+            .ok_or_else(|| anyhow::anyhow!("Unknown embedding provider: {}", config.name))?;
+        if dialect.requires_api_key() && config.api_key.is_none() {
+            anyhow::bail!("Embedding provider '{}' requires an api_key", config.name);
+        }
+        if dialect.requires_base_url() && config.base_url.is_none() {
+            anyhow::bail!("Embedding provider '{}' requires a base_url", config.name);
+        }
+        let (endpoint, signer) = endpoint_of(config, dialect.default_base_url())?;
+        Ok(Self {
+            provider: config.name.clone(),
+            model: config.model.clone(),
+            endpoint,
+            dialect,
+            transport,
+            request_ids: Arc::default(),
+            signer,
+        })
+    }
+
+    /// Take request ids from `request_ids`, e.g. one shared by a client
+    pub fn with_request_ids(mut self, request_ids: Arc<RequestIds>) -> Self {
+        self.request_ids = request_ids;
+        self
+    }
+
+    /// Vectors of texts sent in one request
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let request = self
+            .dialect
+            .encode_embeddings(&self.endpoint, &self.model, texts)
+            .header("x-request-id", self.request_ids.next_id());
+        let request = match &self.signer {
+            Some(signer) => signer.sign(&*self.transport, request).await?,
+            None => request,
+        };
+        let service = format!("Embedding provider '{}'", self.provider);
+        let response = self.transport.send(request).await.map_err(|err| {
+            err.context(Unreachable {
+                service: service.clone(),
+            })
+        })?;
+        if !response.is_success() {
+            return Err(HttpStatusError::from_response(service, &response).into());
+        }
+        let body: Value = serde_json::from_slice(&response.body)
+            .with_context(|| format!("Provider '{}' returned invalid JSON", self.provider))?;
+        let vectors = self
+            .dialect
+            .decode_embeddings(&body)
+            .with_context(|| format!("Unexpected response from provider '{}'", self.provider))?;
+        if vectors.len() != texts.len() {
+            anyhow::bail!(
+                "Embedding provider '{}' returned {} vectors for {} texts",
+                self.provider,
+                vectors.len(),
+                texts.len()
+            );
+        }
+        Ok(vectors)
+    }
+}
+
+#[async_trait]
+impl EmbeddingModel for HttpEmbedder {
+    fn provider(&self) -> &str {
+        &self.provider
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn dimensions(&self) -> Option<usize> {
+        self.dialect.dimensions(&self.model)
+    }
+
+    /// In batches as large as the API takes
+    async fn embed_texts(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let batch = self.dialect.max_batch(&self.model).unwrap_or(texts.len());
+        let mut vectors = Vec::with_capacity(texts.len());
+        for texts in texts.chunks(batch.max(1)) {
+            vectors.extend(self.embed_batch(texts).await?);
+        }
+        Ok(vectors)
+    }
+}
+
+/// [`Reranker`] speaking a provider's HTTP rerank API
+pub struct HttpReranker {
+    provider: String,
+    model: String,
+    endpoint: Endpoint,
+    dialect: &'static dyn RerankDialect,
+    transport: Arc<dyn HttpTransport>,
+    request_ids: Arc<RequestIds>,
+}
+
+impl HttpReranker {
+    /// Reranker for `config.name`, which must offer a rerank API
+    pub fn new(config: &ProviderConfig, transport: Arc<dyn HttpTransport>) -> Result<Self> {
+        let dialect = rerank_dialect(&config.name)
+            .ok_or_else(|| anyhow::anyhow!("Unknown rerank provider: {}", config.name))?;
+        if dialect.requires_api_key() && config.api_key.is_none() {
+            anyhow::bail!("Rerank provider '{}' requires an api_key", config.name);
+        }
+        Ok(Self {
+            provider: config.name.clone(),
+            model: config.model.clone(),
+            endpoint: Endpoint::of(config, dialect.default_base_url()),
+            dialect,
+            transport,
+            request_ids: Arc::default(),
+        })
+    }
+
+    /// Take request ids from `request_ids`, e.g. one shared by a client
+    pub fn with_request_ids(mut self, request_ids: Arc<RequestIds>) -> Self {
+        self.request_ids = request_ids;
+        self
+    }
+}
+
+#[async_trait]
+impl Reranker for HttpReranker {
+    fn provider(&self) -> &str {
+        &self.provider
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    async fn rerank(
+        &self, query: &str, documents: &[String], top_n: usize,
+    ) -> Result<Vec<Relevance>> {
+        let request = self
+            .dialect
+            .encode_rerank(&self.endpoint, &self.model, query, documents, top_n)
+            .header("x-request-id", self.request_ids.next_id());
+        let service = format!("Rerank provider '{}'", self.provider);
+        let response = self.transport.send(request).await.map_err(|err| {
+            err.context(Unreachable {
+                service: service.clone(),
+            })
+        })?;
+        if !response.is_success() {
+            return Err(HttpStatusError::from_response(service, &response).into());
+        }
+        let body: Value = serde_json::from_slice(&response.body)
+            .with_context(|| format!("Provider '{}' returned invalid JSON", self.provider))?;
+        self.dialect
+            .decode_rerank(&body)
+            .with_context(|| format!("Unexpected response from provider '{}'", self.provider))
+    }
+}
+
+/// The preamble plus any system messages, for APIs taking the system prompt
+/// outside the message list
+fn system_text(request: &ChatRequest) -> Option<String> {
+    let parts: Vec<&str> = request
+        .system
+        .iter()
+        .map(String::as_str)
+        .chain(
+            request
+                .messages
+                .iter()
+                .filter(|m| m.role == Role::System)
+                .map(|m| m.content.as_str()),
+        )
+        .collect();
+    (!parts.is_empty()).then(|| parts.join("\n\n"))
+}
+
+/// Append `part` to `messages`, merging into the last message when it has the
+/// same role; for APIs that expect roles to alternate
+fn push_part(messages: &mut Vec<Value>, role: &str, key: &str, part: Value) {
+    if let Some(last) = messages.last_mut().filter(|last| last["role"] == role) {
+        if let Some(parts) = last[key].as_array_mut() {
+            parts.push(part);
+            return;
+        }
+    }
+    messages.push(serde_json::json!({ "role": role, key: [part] }));
+}
+
+/// Usage with the provider's total when it reports one
+fn usage(prompt_tokens: u64, completion_tokens: u64, total_tokens: Option<u64>) -> NormalizedUsage {
+    let mut usage = NormalizedUsage::new(prompt_tokens, completion_tokens);
+    if let Some(total_tokens) = total_tokens {
+        usage.total_tokens = total_tokens;
+    }
+    usage
+}
+
+/// Reasoning text, treating empty text as absent
+fn reasoning(parts: Vec<String>) -> Option<String> {
+    Some(parts.concat()).filter(|text| !text.is_empty())
+}
+
+/// Tool arguments sent as a JSON string; malformed input is passed on as a string
+fn parse_arguments(arguments: &str) -> Value {
+    if arguments.trim().is_empty() {
+        return Value::Object(Default::default());
+    }
+    serde_json::from_str(arguments).unwrap_or_else(|_| Value::String(arguments.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::ToolCall;
+    use crate::testing::MockTransport;
+    use serde_json::json;
+
+    fn config(name: &str, api_key: Option<&str>) -> ProviderConfig {
+        ProviderConfig {
+            name: name.to_string(),
+            model: "some-model".to_string(),
+            api_key: api_key.map(String::from),
+            base_url: Some("http://localhost:9999/v1/".to_string()),
+            ..ProviderConfig::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_http_provider_round_trip() {
+        let transport = Arc::new(MockTransport::new());
+        transport.push_json(
+            200,
+            json!({
+                "choices": [{"message": {"role": "assistant", "content": "hello"}}],
+                "usage": {"prompt_tokens": 3, "completion_tokens": 1}
+            }),
+        );
+        transport.push_json(429, json!({"error": {"message": "slow down"}}));
+        let provider =
+            HttpProvider::new(&config("openai", Some("sk-1")), transport.clone()).unwrap();
+
+        let request = ChatRequest {
+            messages: vec![crate::ChatMessage::user("hi")],
+            ..ChatRequest::default()
+        };
+        let response = provider.complete(request.clone()).await.unwrap();
+        assert_eq!(response.content, "hello");
+        assert_eq!(response.usage.prompt_tokens, 3);
+        let sent = &transport.requests()[0];
+        assert_eq!(sent.url, "http://localhost:9999/v1/chat/completions");
+
+        let err = provider.complete(request).await.unwrap_err();
+        assert!(crate::transport::is_retriable(&err));
+        let err = err.to_string();
+        assert!(
+            err.starts_with("Provider 'openai' returned HTTP 429"),
+            "{}",
+            err
+        );
+    }
+
+    #[tokio::test]
+    async fn test_azure_deployment_urls() {
+        let transport = Arc::new(MockTransport::new());
+        transport.push_json(
+            200,
+            json!({"choices": [{"message": {"role": "assistant", "content": "hello"}}]}),
+        );
+        transport.push_json(200, json!({"data": [{"index": 0, "embedding": [0.5]}]}));
+        let mut azure = config("azure", Some("0123abcd"));
+        azure.model = "gpt-4o-prod".to_string();
+        azure.base_url = Some("https://contoso.openai.azure.com".to_string());
+        azure.api_version = Some("2025-01-01-preview".to_string());
+        let provider = HttpProvider::new(&azure, transport.clone()).unwrap();
+        let request = ChatRequest {
+            messages: vec![crate::ChatMessage::user("hi")],
+            ..ChatRequest::default()
+        };
+        assert_eq!(provider.complete(request).await.unwrap().content, "hello");
+        let embedder = HttpEmbedder::new(&azure, transport.clone()).unwrap();
+        embedder.embed_texts(&["hi".to_string()]).await.unwrap();
+
+        let sent = transport.requests();
+        assert_eq!(
+            sent[0].url,
+            "https://contoso.openai.azure.com/openai/deployments/gpt-4o-prod/chat/completions\
+             ?api-version=2025-01-01-preview"
+        );
+        assert_eq!(
+            sent[1].url,
+            "https://contoso.openai.azure.com/openai/deployments/gpt-4o-prod/embeddings\
+             ?api-version=2025-01-01-preview"
+        );
+        assert!(sent[0]
+            .headers
+            .contains(&("api-key".to_string(), "0123abcd".to_string())));
+
+        azure.base_url = None;
+        let err = HttpProvider::new(&azure, transport).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Provider 'azure' requires a base_url (the endpoint it is served from)"
+        );
+    }
+
+    /// Log output, shared with the subscriber writing it
+    #[derive(Clone, Default)]
+    struct Logs(Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Logs {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reasoning_dropped_for_models_that_dont_reason() {
+        let logs = Logs::default();
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(move || writer.clone())
+            .with_ansi(false)
+            .finish();
+        let _subscriber = tracing::subscriber::set_default(subscriber);
+        let transport = Arc::new(MockTransport::new());
+        for _ in 0..2 {
+            transport.push_json(
+                200,
+                json!({"choices": [{"message": {"role": "assistant", "content": "Paris"}}]}),
+            );
+        }
+        let mut openai = config("openai", Some("sk-test"));
+        openai.model = "gpt-4o".to_string();
+        let provider = HttpProvider::new(&openai, transport.clone()).unwrap();
+        let request = ChatRequest {
+            messages: vec![crate::ChatMessage::user("What is the capital of France?")],
+            temperature: Some(0.5),
+            reasoning: Some(crate::ReasoningConfig::default()),
+            ..ChatRequest::default()
+        };
+        provider.complete(request.clone()).await.unwrap();
+        provider.complete(request).await.unwrap();
+
+        for sent in transport.requests() {
+            let body: Value = serde_json::from_slice(&sent.body).unwrap();
+            assert!(body.get("reasoning_effort").is_none(), "{}", body);
+            assert_eq!(body["temperature"], 0.5);
+        }
+        let logs = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+        let warnings = logs.matches("model doesn't take reasoning settings");
+        assert_eq!(warnings.count(), 1, "{}", logs);
+        assert!(logs.contains("model=gpt-4o"), "{}", logs);
+    }
+
+    #[tokio::test]
+    async fn test_groq_reports_rate_limits() {
+        use crate::transport::HttpResponse;
+        use std::time::Duration;
+
+        let header = |name: &str, value: &str| (name.to_string(), value.to_string());
+        let transport = Arc::new(MockTransport::new());
+        let mut answered = HttpResponse::json(
+            200,
+            &json!({"choices": [{"message": {"role": "assistant", "content": "hi"}}]}),
+        );
+        answered
+            .headers
+            .push(header("x-ratelimit-remaining-requests", "14399"));
+        answered
+            .headers
+            .push(header("x-ratelimit-reset-tokens", "7.66s"));
+        transport.push_response(answered);
+        let mut refused = HttpResponse::json(429, &json!({"error": {"message": "Slow down"}}));
+        refused.headers.push(header("retry-after", "2"));
+        transport.push_response(refused);
+
+        let mut groq = config("groq", Some("gsk-test"));
+        groq.base_url = None;
+        let provider = HttpProvider::new(&groq, transport.clone()).unwrap();
+        let request = ChatRequest {
+            messages: vec![crate::ChatMessage::user("hi")],
+            ..ChatRequest::default()
+        };
+        let response = provider.complete(request.clone()).await.unwrap();
+        let limits = response.rate_limits.unwrap();
+        assert_eq!(limits.remaining_requests, Some(14399));
+        assert_eq!(limits.reset_tokens, Some(Duration::from_millis(7660)));
+
+        let err = provider.complete(request).await.unwrap_err();
+        let refused = err.downcast_ref::<HttpStatusError>().unwrap();
+        assert_eq!(refused.status, 429);
+        let limits = refused.rate_limits.as_ref().unwrap();
+        assert_eq!(limits.retry_after, Some(Duration::from_secs(2)));
+
+        let sent = transport.requests();
+        assert_eq!(
+            sent[0].url,
+            "https://api.groq.com/openai/v1/chat/completions"
+        );
+        assert!(sent[0]
+            .headers
+            .contains(&header("authorization", "Bearer gsk-test")));
+    }
+
+    #[test]
+    fn test_event_buffer_joins_split_events() {
+        let mut events = EventBuffer::default();
+        assert!(events
+            .push(b"event: message_start\ndata: {\"a\"")
+            .is_empty());
+        assert_eq!(
+            events.push(b": 1}\r\n\r\ndata: 2\n\n:keep-alive\n\ndata: x\ndata:"),
+            [r#"{"a": 1}"#, "2"]
+        );
+        assert_eq!(events.push(b" y\n\ndata: 3"), ["x\ny"]);
+        assert_eq!(events.finish().as_deref(), Some("3"));
+    }
+
+    fn event_stream(events: &[Value]) -> crate::transport::HttpResponse {
+        let mut body = String::new();
+        for event in events {
+            body.push_str(&format!("data: {}\n\n", event));
+        }
+        body.push_str("data: [DONE]\n\n");
+        crate::transport::HttpResponse {
+            status: 200,
+            headers: vec![("content-type".to_string(), "text/event-stream".to_string())],
+            body: body.into_bytes(),
+        }
+    }
+
+    async fn collect(stream: ChatStream) -> Result<Vec<StreamChunk>> {
+        stream.collect::<Vec<_>>().await.into_iter().collect()
+    }
+
+    #[tokio::test]
+    async fn test_http_provider_streams_with_usage() {
+        let transport = Arc::new(MockTransport::new());
+        transport.push_response(event_stream(&[
+            json!({"choices": [{"delta": {"content": "hel"}}]}),
+            json!({"choices": [{"delta": {"content": "lo"}, "finish_reason": "stop"}]}),
+            json!({"choices": [], "usage": {"prompt_tokens": 3, "completion_tokens": 2}}),
+        ]));
+        transport.push_json(503, json!({"error": {"message": "busy"}}));
+        let config = ProviderConfig {
+            streaming: true,
+            ..config("openai", Some("sk-1"))
+        };
+        let provider = HttpProvider::new(&config, transport.clone()).unwrap();
+        let request = ChatRequest {
+            messages: vec![crate::ChatMessage::user("hi")],
+            ..ChatRequest::default()
+        };
+
+        let chunks = collect(provider.stream(request.clone()).await.unwrap())
+            .await
+            .unwrap();
+        assert_eq!(
+            chunks,
+            vec![
+                StreamChunk::Text("hel".to_string()),
+                StreamChunk::Text("lo".to_string()),
+                StreamChunk::Usage(NormalizedUsage::new(3, 2)),
+            ]
+        );
+        let body: Value = serde_json::from_slice(&transport.requests()[0].body).unwrap();
+        assert_eq!(body["stream"], true);
+        assert_eq!(body["stream_options"], json!({"include_usage": true}));
+
+        let err = collect(provider.stream(request).await.unwrap())
+            .await
+            .unwrap_err();
+        assert!(crate::transport::is_retriable(&err));
+    }
+
+    #[tokio::test]
+    async fn test_streaming_is_opt_in() {
+        let transport = Arc::new(MockTransport::new());
+        transport.push_json(
+            200,
+            json!({
+                "choices": [{"message": {"role": "assistant", "content": "hello"}}],
+                "usage": {"prompt_tokens": 3, "completion_tokens": 1}
+            }),
+        );
+        let provider =
+            HttpProvider::new(&config("openai", Some("sk-1")), transport.clone()).unwrap();
+
+        let chunks = collect(provider.stream(ChatRequest::default()).await.unwrap())
+            .await
+            .unwrap();
+        assert_eq!(chunks[0], StreamChunk::Text("hello".to_string()));
+        let body: Value = serde_json::from_slice(&transport.requests()[0].body).unwrap();
+        assert!(body.get("stream").is_none());
+    }
+
+    #[test]
+    fn test_provider_config_errors() {
+        let transport = Arc::new(MockTransport::new());
+        let err = HttpProvider::new(&config("nope", None), transport.clone())
+            .err()
+            .unwrap();
+        assert_eq!(err.to_string(), "Unknown provider: nope");
+        let err = HttpProvider::new(&config("anthropic", None), transport.clone())
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Provider 'anthropic' requires an api_key \
+             (set ANTHROPIC_API_KEY or the provider's api_key)"
+        );
+        assert!(HttpProvider::new(&config("ollama", None), transport).is_ok());
+        for provider in PROVIDERS {
+            assert!(dialect(provider).is_some(), "{}", provider);
+        }
+    }
+
+    /// Recorded text and tool-call responses of the same exchange
+    const FIXTURES: &[(&str, &str, &str)] = &[
+        (
+            "openai",
+            include_str!("../../tests/fixtures/responses/openai-text.json"),
+            include_str!("../../tests/fixtures/responses/openai-tool-call.json"),
+        ),
+        (
+            "anthropic",
+            include_str!("../../tests/fixtures/responses/anthropic-text.json"),
+            include_str!("../../tests/fixtures/responses/anthropic-tool-call.json"),
+        ),
+        (
+            "gemini",
+            include_str!("../../tests/fixtures/responses/gemini-text.json"),
+            include_str!("../../tests/fixtures/responses/gemini-tool-call.json"),
+        ),
+        (
+            "ollama",
+            include_str!("../../tests/fixtures/responses/ollama-text.json"),
+            include_str!("../../tests/fixtures/responses/ollama-tool-call.json"),
+        ),
+    ];
+
+    /// Normalize a fixture, blanking what differs between providers by design
+    fn normalized(provider: &str, fixture: &str) -> NormalizedResponse {
+        let raw: Value = serde_json::from_str(fixture).unwrap();
+        let response = normalizer(provider)
+            .unwrap()
+            .normalize(raw.clone())
+            .unwrap();
+        assert_eq!(
+            response.provider_raw, raw,
+            "{} keeps the raw body",
+            provider
+        );
+        NormalizedResponse {
+            tool_calls: response
+                .tool_calls
+                .into_iter()
+                .map(|call| ToolCall {
+                    id: String::new(),
+                    ..call
+                })
+                .collect(),
+            provider_raw: Value::Null,
+            ..response
+        }
+    }
+
+    #[test]
+    fn test_normalizers_agree_on_recorded_responses() {
+        let text = NormalizedResponse {
+            content: "Paris is the capital of France.".to_string(),
+            usage: NormalizedUsage::new(14, 8),
+            ..NormalizedResponse::default()
+        };
+        let tool_call = NormalizedResponse {
+            content: "I'll count the words.".to_string(),
+            tool_calls: vec![ToolCall {
+                id: String::new(),
+                name: "word_count".to_string(),
+                arguments: json!({"text": "the quick brown fox"}),
+            }],
+            usage: NormalizedUsage::new(120, 25),
+            finish_reason: FinishReason::ToolCalls,
+            ..NormalizedResponse::default()
+        };
+        for (provider, text_fixture, tool_fixture) in FIXTURES {
+            assert_eq!(normalized(provider, text_fixture), text, "{}", provider);
+            assert_eq!(
+                normalized(provider, tool_fixture),
+                tool_call,
+                "{}",
+                provider
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_arguments() {
+        assert_eq!(parse_arguments(""), json!({}));
+        assert_eq!(parse_arguments(r#"{"a":1}"#), json!({"a": 1}));
+        assert_eq!(parse_arguments("{oops"), json!("{oops"));
+    }
+
+    #[tokio::test]
+    async fn test_embedder_orders_vectors_by_index() {
+        let transport = Arc::new(MockTransport::new());
+        transport.push_json(
+            200,
+            json!({
+                "object": "list",
+                "data": [
+                    {"object": "embedding", "index": 1, "embedding": [0.0, 1.0]},
+                    {"object": "embedding", "index": 0, "embedding": [1.0, 0.0]}
+                ],
+                "model": "text-embedding-3-small"
+            }),
+        );
+        let config = ProviderConfig {
+            model: "text-embedding-3-small".to_string(),
+            base_url: None,
+            ..config("openai", Some("sk-test"))
+        };
+        let embedder = HttpEmbedder::new(&config, transport.clone()).unwrap();
+        let texts = vec!["first".to_string(), "second".to_string()];
+        let vectors = embedder.embed_texts(&texts).await.unwrap();
+        assert_eq!(vectors, vec![vec![1.0, 0.0], vec![0.0, 1.0]]);
+
+        let request = &transport.requests()[0];
+        assert_eq!(request.url, "https://api.openai.com/v1/embeddings");
+        let body: Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(body["input"], json!(["first", "second"]));
+
+        transport.push_json(200, json!({"data": []}));
+        let err = embedder.embed_texts(&texts).await.unwrap_err().to_string();
+        assert!(err.contains("returned 0 vectors for 2 texts"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_ollama_embedding_dimensions_come_from_the_first_response() {
+        let transport = Arc::new(MockTransport::new());
+        transport.push_json(
+            200,
+            json!({"model": "nomic-embed-text", "embeddings": [[0.1, 0.2, 0.3]]}),
+        );
+        let config = ProviderConfig {
+            model: "nomic-embed-text".to_string(),
+            base_url: None,
+            ..config("ollama", None)
+        };
+        let embedder = HttpEmbedder::new(&config, transport.clone()).unwrap();
+        let embeddings = crate::Embeddings::new(Arc::new(embedder), Default::default());
+        assert_eq!(embeddings.info().dimensions, None);
+
+        embeddings.embed_text("list files").await.unwrap();
+        assert_eq!(embeddings.info().dimensions, Some(3));
+        assert_eq!(
+            transport.requests()[0].url,
+            "http://localhost:11434/api/embed"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bedrock_embeds_one_text_per_request() {
+        let transport = Arc::new(MockTransport::new());
+        for vector in [[1.0, 0.0], [0.0, 1.0]] {
+            transport.push_json(200, json!({"embedding": vector, "inputTextTokenCount": 2}));
+        }
+        let config = ProviderConfig {
+            model: "titan-embed-text-v2".to_string(),
+            base_url: None,
+            bedrock: Some(crate::BedrockOptions {
+                region: Some("us-east-1".to_string()),
+                profile: None,
+            }),
+            ..config("bedrock", None)
+        };
+        let env = std::collections::HashMap::from([
+            ("AWS_ACCESS_KEY_ID".to_string(), "AKIDEXAMPLE".to_string()),
+            ("AWS_SECRET_ACCESS_KEY".to_string(), "secret".to_string()),
+        ]);
+        let embedder = HttpEmbedder {
+            signer: Some(Signer::Aws(
+                bedrock::Signer::with_env(&config, env).unwrap(),
+            )),
+            ..HttpEmbedder::new(&config, transport.clone()).unwrap()
+        };
+        assert_eq!(embedder.dimensions(), Some(1024));
+        let texts = vec!["first".to_string(), "second".to_string()];
+        let vectors = embedder.embed_texts(&texts).await.unwrap();
+        assert_eq!(vectors, vec![vec![1.0, 0.0], vec![0.0, 1.0]]);
+
+        let requests = transport.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(
+            requests[1].url,
+            "https://bedrock-runtime.us-east-1.amazonaws.com/model/\
+             amazon.titan-embed-text-v2%3A0/invoke"
+        );
+        let body: Value = serde_json::from_slice(&requests[1].body).unwrap();
+        assert_eq!(body, json!({"inputText": "second"}));
+        let authorization = requests[1]
+            .headers
+            .iter()
+            .find(|(name, _)| name == "authorization")
+            .map(|(_, value)| value.as_str());
+        assert!(authorization
+            .unwrap()
+            .starts_with("AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/"));
+    }
+    #[tokio::test]
+    async fn test_vertex_token_is_refreshed_between_requests() {
+        let transport = Arc::new(MockTransport::new());
+        // The first token is due for renewal as soon as it is used
+        transport.push_json(200, json!({"access_token": "ya29.a", "expires_in": 60}));
+        transport.push_json(
+            200,
+            json!({"predictions": [{"embeddings": {"values": [1.0]}}]}),
+        );
+        transport.push_json(200, json!({"access_token": "ya29.b", "expires_in": 3600}));
+        transport.push_json(
+            200,
+            json!({"predictions": [{"embeddings": {"values": [0.5]}}]}),
+        );
+        let config = ProviderConfig {
+            model: "text-embedding-004".to_string(),
+            base_url: None,
+            vertex: Some(crate::VertexOptions {
+                project: Some("my-project".to_string()),
+                location: Some("europe-west4".to_string()),
+                credentials: None,
+            }),
+            ..config("vertex", None)
+        };
+        let tokens = vertex::TokenSource::with_env(&config, Default::default()).unwrap();
+        let embedder = HttpEmbedder {
+            signer: Some(Signer::Google(tokens)),
+            ..HttpEmbedder::new(&config, transport.clone()).unwrap()
+        };
+        assert_eq!(embedder.dimensions(), Some(768));
+        for text in ["first", "second"] {
+            embedder.embed_texts(&[text.to_string()]).await.unwrap();
+        }
+
+        let requests = transport.requests();
+        assert_eq!(requests.len(), 4);
+        assert_eq!(
+            requests[0].url,
+            "http://metadata.google.internal/computeMetadata/v1/instance/service-accounts/\
+             default/token"
+        );
+        assert_eq!(
+            requests[1].url,
+            "https://europe-west4-aiplatform.googleapis.com/v1/projects/my-project/\
+             locations/europe-west4/publishers/google/models/text-embedding-004:predict"
+        );
+        let authorization = |request: &HttpRequest| {
+            request
+                .headers
+                .iter()
+                .find(|(name, _)| name == "authorization")
+                .map(|(_, value)| value.clone())
+        };
+        assert_eq!(
+            authorization(&requests[1]).as_deref(),
+            Some("Bearer ya29.a")
+        );
+        assert_eq!(
+            authorization(&requests[3]).as_deref(),
+            Some("Bearer ya29.b")
+        );
+    }
+}
@@ -0,0 +1,232 @@
+//! Ollama `/api/chat` and `/api/embed`
+
+use anyhow::Result;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use super::{reasoning, usage, Dialect, EmbeddingDialect, Endpoint, Normalizer};
+use crate::provider::{ChatRequest, FinishReason, NormalizedResponse, Role, ToolCall};
+use crate::transport::HttpRequest;
+
+pub(crate) struct Ollama;
+
+impl Dialect for Ollama {
+    fn default_base_url(&self) -> &'static str {
+        "http://localhost:11434"
+    }
+
+    fn requires_api_key(&self) -> bool {
+        false
+    }
+
+    fn list_models(&self, endpoint: &Endpoint) -> Option<HttpRequest> {
+        Some(HttpRequest::get(endpoint.url("api/tags")).bearer(endpoint.api_key.as_deref()))
+    }
+
+    fn encode(&self, endpoint: &Endpoint, model: &str, request: &ChatRequest) -> HttpRequest {
+        let mut messages = Vec::new();
+        if let Some(system) = &request.system {
+            messages.push(json!({ "role": "system", "content": system }));
+        }
+        for message in &request.messages {
+            let mut wire = json!({ "role": message.role, "content": message.content });
+            if message.role == Role::Assistant && !message.tool_calls.is_empty() {
+                let calls: Vec<Value> = message
+                    .tool_calls
+                    .iter()
+                    .map(|call| {
+                        json!({ "function": { "name": call.name, "arguments": call.arguments } })
+                    })
+                    .collect();
+                wire["tool_calls"] = calls.into();
+            }
+            messages.push(wire);
+        }
+
+        let mut body = json!({ "model": model, "messages": messages, "stream": false });
+        if !request.tools.is_empty() {
+            let tools: Vec<Value> = request
+                .tools
+                .iter()
+                .map(|tool| {
+                    json!({
+                        "type": "function",
+                        "function": {
+                            "name": tool.name,
+                            "description": tool.description,
+                            "parameters": tool.parameters,
+                        }
+                    })
+                })
+                .collect();
+            body["tools"] = tools.into();
+        }
+        let mut options = serde_json::Map::new();
+        if let Some(max_tokens) = request.max_tokens {
+            options.insert("num_predict".to_string(), max_tokens.into());
+        }
+        if let Some(temperature) = request.temperature {
+            options.insert("temperature".to_string(), temperature.into());
+        }
+        if let Some(seed) = request.seed {
+            options.insert("seed".to_string(), seed.into());
+        }
+        if !options.is_empty() {
+            body["options"] = options.into();
+        }
+        // Ollama has no auth of its own, but is often put behind a proxy that does
+        HttpRequest::post_json(endpoint.url("api/chat"), &body).bearer(endpoint.api_key.as_deref())
+    }
+}
+
+impl Normalizer for Ollama {
+    fn parse(&self, raw: &Value) -> Result<NormalizedResponse> {
+        let chat = Chat::deserialize(raw)?;
+        Ok(NormalizedResponse {
+            content: chat.message.content,
+            reasoning: reasoning(chat.message.thinking.into_iter().collect()),
+            tool_calls: chat
+                .message
+                .tool_calls
+                .into_iter()
+                .enumerate()
+                .map(|(index, call)| ToolCall {
+                    id: format!("call_{}", index),
+                    name: call.function.name,
+                    arguments: call.function.arguments,
+                })
+                .collect(),
+            usage: usage(
+                chat.prompt_eval_count.unwrap_or_default(),
+                chat.eval_count.unwrap_or_default(),
+                None,
+            ),
+            finish_reason: match chat.done_reason.as_deref() {
+                None | Some("stop") => FinishReason::Stop,
+                Some("length") => FinishReason::Length,
+                Some(other) => FinishReason::Other(other.to_string()),
+            },
+            ..NormalizedResponse::default()
+        })
+    }
+}
+
+/// Vector lengths differ by model, e.g. 768 for `nomic-embed-text` and 1024
+/// for `mxbai-embed-large`, so they are learned from the first response
+impl EmbeddingDialect for Ollama {
+    fn encode_embeddings(&self, endpoint: &Endpoint, model: &str, texts: &[String]) -> HttpRequest {
+        let body = json!({ "model": model, "input": texts });
+        HttpRequest::post_json(endpoint.url("api/embed"), &body).bearer(endpoint.api_key.as_deref())
+    }
+
+    fn decode_embeddings(&self, raw: &Value) -> Result<Vec<Vec<f32>>> {
+        Ok(Embed::deserialize(raw)?.embeddings)
+    }
+}
+
+#[derive(Deserialize)]
+struct Embed {
+    embeddings: Vec<Vec<f32>>,
+}
+
+#[derive(Deserialize)]
+struct Chat {
+    message: Message,
+    done_reason: Option<String>,
+    prompt_eval_count: Option<u64>,
+    eval_count: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct Message {
+    #[serde(default)]
+    content: String,
+    /// Set by thinking models
+    thinking: Option<String>,
+    #[serde(default)]
+    tool_calls: Vec<WireToolCall>,
+}
+
+#[derive(Deserialize)]
+struct WireToolCall {
+    function: Function,
+}
+
+#[derive(Deserialize)]
+struct Function {
+    name: String,
+    #[serde(default)]
+    arguments: Value,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::ChatMessage;
+
+    #[test]
+    fn test_encode_and_normalize() {
+        let endpoint = Endpoint {
+            base_url: Ollama.default_base_url().to_string(),
+            api_key: None,
+            api_version: None,
+        };
+        let request = ChatRequest {
+            messages: vec![ChatMessage::user("hi"), ChatMessage::tool("call_0", "ok")],
+            max_tokens: Some(64),
+            ..ChatRequest::default()
+        };
+        let http = Ollama.encode(&endpoint, "llama3.1", &request);
+        assert_eq!(http.url, "http://localhost:11434/api/chat");
+        assert!(http.headers.iter().all(|(name, _)| name != "authorization"));
+        let body: Value = serde_json::from_slice(&http.body).unwrap();
+        assert_eq!(body["stream"], false);
+        assert_eq!(body["messages"][1]["role"], "tool");
+        assert_eq!(body["options"], json!({"num_predict": 64}));
+
+        let response = Ollama
+            .normalize(json!({
+                "model": "llama3.1",
+                "message": {
+                    "role": "assistant",
+                    "content": "",
+                    "tool_calls": [{"function": {"name": "echo", "arguments": {"text": "hi"}}}]
+                },
+                "done": true,
+                "prompt_eval_count": 26,
+                "eval_count": 11
+            }))
+            .unwrap();
+        assert_eq!(response.tool_calls[0].name, "echo");
+        assert_eq!(response.tool_calls[0].arguments, json!({"text": "hi"}));
+        assert_eq!(response.usage.completion_tokens, 11);
+        assert_eq!(response.finish_reason, FinishReason::ToolCalls);
+    }
+
+    #[test]
+    fn test_encode_and_decode_embeddings() {
+        let endpoint = Endpoint {
+            base_url: Ollama.default_base_url().to_string(),
+            api_key: None,
+            api_version: None,
+        };
+        let texts = vec!["first".to_string(), "second".to_string()];
+        let http = Ollama.encode_embeddings(&endpoint, "nomic-embed-text", &texts);
+        assert_eq!(http.url, "http://localhost:11434/api/embed");
+        let body: Value = serde_json::from_slice(&http.body).unwrap();
+        assert_eq!(
+            body,
+            json!({"model": "nomic-embed-text", "input": ["first", "second"]})
+        );
+
+        let vectors = Ollama
+            .decode_embeddings(&json!({
+                "model": "nomic-embed-text",
+                "embeddings": [[0.1, 0.2, 0.3], [0.4, 0.5, 0.6]],
+                "prompt_eval_count": 4
+            }))
+            .unwrap();
+        assert_eq!(vectors, vec![vec![0.1, 0.2, 0.3], vec![0.4, 0.5, 0.6]]);
+        assert_eq!(Ollama.dimensions("nomic-embed-text"), None);
+    }
+}
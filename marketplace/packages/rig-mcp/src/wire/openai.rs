@@ -0,0 +1,545 @@
+//! OpenAI chat completions, also spoken by DeepSeek, Groq, Azure OpenAI and
+//! self-hosted servers
+
+use anyhow::Result;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use super::{
+    parse_arguments, reasoning, usage, Dialect, EmbeddingDialect, Endpoint, Normalizer,
+    StreamDecoder,
+};
+use crate::provider::{
+    ChatRequest, FinishReason, NormalizedResponse, NormalizedUsage, Role, StreamChunk, ToolCall,
+};
+use crate::transport::HttpRequest;
+
+pub(crate) struct OpenAi {
+    base_url: &'static str,
+    /// Whether the API takes a sampling `seed`
+    seed: bool,
+    /// Whether the API takes a `reasoning_effort`; DeepSeek's reasoner
+    /// always thinks as much as it likes
+    reasoning_effort: bool,
+}
+
+pub(crate) const OPENAI: OpenAi = OpenAi {
+    base_url: "https://api.openai.com/v1",
+    seed: true,
+    reasoning_effort: true,
+};
+
+pub(crate) const DEEPSEEK: OpenAi = OpenAi {
+    base_url: "https://api.deepseek.com",
+    seed: false,
+    reasoning_effort: false,
+};
+
+/// Llama, Mixtral and other open models on Groq's LPUs
+pub(crate) const GROQ: OpenAi = OpenAi {
+    base_url: "https://api.groq.com/openai/v1",
+    seed: true,
+    reasoning_effort: false,
+};
+
+/// Self-hosted servers; their models are named by whoever runs them, so
+/// which of them reason isn't known
+pub(crate) const COMPATIBLE: OpenAi = OpenAi {
+    base_url: "",
+    seed: true,
+    reasoning_effort: false,
+};
+
+/// Whether `model` is one of OpenAI's reasoning models: the o-series and
+/// GPT-5
+pub(super) fn reasons(model: &str) -> bool {
+    ["o1", "o3", "o4", "gpt-5"]
+        .iter()
+        .any(|family| model.starts_with(family))
+}
+
+impl Dialect for OpenAi {
+    fn default_base_url(&self) -> &'static str {
+        self.base_url
+    }
+
+    fn supports_reasoning(&self, model: &str) -> bool {
+        self.reasoning_effort && reasons(model)
+    }
+
+    fn encode(&self, endpoint: &Endpoint, model: &str, request: &ChatRequest) -> HttpRequest {
+        HttpRequest::post_json(endpoint.url("chat/completions"), &self.body(model, request))
+            .bearer(endpoint.api_key.as_deref())
+    }
+
+    fn list_models(&self, endpoint: &Endpoint) -> Option<HttpRequest> {
+        Some(HttpRequest::get(endpoint.url("models")).bearer(endpoint.api_key.as_deref()))
+    }
+
+    /// Asks for the usage in a last chunk, which the API otherwise leaves out
+    /// of streams
+    fn encode_stream(
+        &self, endpoint: &Endpoint, model: &str, request: &ChatRequest,
+    ) -> Option<(HttpRequest, Box<dyn StreamDecoder>)> {
+        let mut body = self.body(model, request);
+        body["stream"] = true.into();
+        body["stream_options"] = json!({ "include_usage": true });
+        let request = HttpRequest::post_json(endpoint.url("chat/completions"), &body)
+            .bearer(endpoint.api_key.as_deref());
+        Some((request, Box::<OpenAiStream>::default()))
+    }
+}
+
+impl OpenAi {
+    pub(super) fn body(&self, model: &str, request: &ChatRequest) -> Value {
+        let mut messages = Vec::new();
+        if let Some(system) = &request.system {
+            messages.push(json!({ "role": "system", "content": system }));
+        }
+        for message in &request.messages {
+            messages.push(match message.role {
+                Role::System => json!({ "role": "system", "content": message.content }),
+                Role::User => json!({ "role": "user", "content": message.content }),
+                Role::Assistant if message.tool_calls.is_empty() => {
+                    json!({ "role": "assistant", "content": message.content })
+                }
+                Role::Assistant => {
+                    let calls: Vec<Value> = message
+                        .tool_calls
+                        .iter()
+                        .map(|call| {
+                            json!({
+                                "id": call.id,
+                                "type": "function",
+                                "function": {
+                                    "name": call.name,
+                                    "arguments": call.arguments.to_string(),
+                                }
+                            })
+                        })
+                        .collect();
+                    let content = Some(&message.content).filter(|text| !text.is_empty());
+                    json!({ "role": "assistant", "content": content, "tool_calls": calls })
+                }
+                Role::Tool => json!({
+                    "role": "tool",
+                    "tool_call_id": message.tool_call_id,
+                    "content": message.content,
+                }),
+            });
+        }
+
+        let mut body = json!({ "model": model, "messages": messages });
+        if !request.tools.is_empty() {
+            let tools: Vec<Value> = request
+                .tools
+                .iter()
+                .map(|tool| {
+                    json!({
+                        "type": "function",
+                        "function": {
+                            "name": tool.name,
+                            "description": tool.description,
+                            "parameters": tool.parameters,
+                        }
+                    })
+                })
+                .collect();
+            body["tools"] = tools.into();
+        }
+        match &request.reasoning {
+            // Reasoning models only take the default temperature, and count
+            // their thinking towards `max_completion_tokens`
+            Some(reasoning) => {
+                body["reasoning_effort"] = json!(reasoning.effort);
+                if let Some(max_tokens) = request.max_tokens {
+                    body["max_completion_tokens"] = max_tokens.into();
+                }
+            }
+            None => {
+                if let Some(max_tokens) = request.max_tokens {
+                    body["max_tokens"] = max_tokens.into();
+                }
+                if let Some(temperature) = request.temperature {
+                    body["temperature"] = temperature.into();
+                }
+            }
+        }
+        if let Some(seed) = request.seed.filter(|_| self.seed) {
+            body["seed"] = seed.into();
+        }
+        body
+    }
+}
+
+impl Normalizer for OpenAi {
+    fn parse(&self, raw: &Value) -> Result<NormalizedResponse> {
+        let completion = Completion::deserialize(raw)?;
+        let choice = completion
+            .choices
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("Completion has no choices"))?;
+        let message = choice.message;
+        Ok(NormalizedResponse {
+            content: message.content.unwrap_or_default(),
+            reasoning: reasoning(message.reasoning_content.into_iter().collect()),
+            tool_calls: message
+                .tool_calls
+                .into_iter()
+                .map(|call| ToolCall {
+                    id: call.id,
+                    name: call.function.name,
+                    arguments: parse_arguments(&call.function.arguments),
+                })
+                .collect(),
+            usage: completion
+                .usage
+                .map(CompletionUsage::normalized)
+                .unwrap_or_default(),
+            finish_reason: match choice.finish_reason.as_deref() {
+                None | Some("stop") => FinishReason::Stop,
+                Some("length") => FinishReason::Length,
+                Some("tool_calls" | "function_call") => FinishReason::ToolCalls,
+                Some("content_filter") => FinishReason::ContentFilter,
+                Some(other) => FinishReason::Other(other.to_string()),
+            },
+            ..NormalizedResponse::default()
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct Completion {
+    choices: Vec<Choice>,
+    usage: Option<CompletionUsage>,
+}
+
+#[derive(Deserialize)]
+struct Choice {
+    message: Message,
+    finish_reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Message {
+    content: Option<String>,
+    /// DeepSeek's reasoning models
+    reasoning_content: Option<String>,
+    #[serde(default)]
+    tool_calls: Vec<WireToolCall>,
+}
+
+#[derive(Deserialize)]
+struct WireToolCall {
+    id: String,
+    function: Function,
+}
+
+#[derive(Deserialize)]
+struct Function {
+    name: String,
+    arguments: String,
+}
+
+#[derive(Deserialize)]
+struct CompletionUsage {
+    /// Left out by some compatible servers
+    #[serde(default)]
+    prompt_tokens: u64,
+    #[serde(default)]
+    completion_tokens: u64,
+    total_tokens: Option<u64>,
+    prompt_tokens_details: Option<PromptTokensDetails>,
+    completion_tokens_details: Option<CompletionTokensDetails>,
+}
+
+#[derive(Deserialize)]
+struct PromptTokensDetails {
+    /// Part of the prompt served from OpenAI's prompt cache
+    #[serde(default)]
+    cached_tokens: u64,
+}
+
+#[derive(Deserialize)]
+struct CompletionTokensDetails {
+    /// Part of the completion a reasoning model spent thinking
+    #[serde(default)]
+    reasoning_tokens: u64,
+}
+
+impl CompletionUsage {
+    fn normalized(self) -> NormalizedUsage {
+        NormalizedUsage {
+            cached_tokens: self.prompt_tokens_details.map_or(0, |d| d.cached_tokens),
+            reasoning_tokens: self
+                .completion_tokens_details
+                .map_or(0, |d| d.reasoning_tokens),
+            ..usage(
+                self.prompt_tokens,
+                self.completion_tokens,
+                self.total_tokens,
+            )
+        }
+    }
+}
+
+/// Tool calls arrive in pieces, keyed by their index, and are complete once
+/// the choice has a finish reason
+#[derive(Default)]
+pub(super) struct OpenAiStream {
+    calls: Vec<(String, String, String)>,
+}
+
+impl StreamDecoder for OpenAiStream {
+    fn event(&mut self, data: &Value) -> Result<Vec<StreamChunk>> {
+        let chunk = CompletionChunk::deserialize(data)?;
+        let mut chunks = Vec::new();
+        for choice in chunk.choices {
+            if let Some(text) = choice.delta.content.filter(|text| !text.is_empty()) {
+                chunks.push(StreamChunk::Text(text));
+            }
+            for delta in choice.delta.tool_calls {
+                if self.calls.len() <= delta.index {
+                    self.calls.resize_with(delta.index + 1, Default::default);
+                }
+                let (id, name, arguments) = &mut self.calls[delta.index];
+                id.push_str(delta.id.as_deref().unwrap_or_default());
+                if let Some(function) = delta.function {
+                    name.push_str(function.name.as_deref().unwrap_or_default());
+                    arguments.push_str(function.arguments.as_deref().unwrap_or_default());
+                }
+            }
+            if choice.finish_reason.is_some() {
+                chunks.extend(self.finish());
+            }
+        }
+        if let Some(u) = chunk.usage {
+            chunks.push(StreamChunk::Usage(u.normalized()));
+        }
+        Ok(chunks)
+    }
+
+    fn finish(&mut self) -> Vec<StreamChunk> {
+        self.calls
+            .drain(..)
+            .map(|(id, name, arguments)| {
+                StreamChunk::ToolCall(ToolCall {
+                    id,
+                    name,
+                    arguments: parse_arguments(&arguments),
+                })
+            })
+            .collect()
+    }
+}
+
+#[derive(Deserialize)]
+struct CompletionChunk {
+    #[serde(default)]
+    choices: Vec<ChunkChoice>,
+    usage: Option<CompletionUsage>,
+}
+
+#[derive(Deserialize)]
+struct ChunkChoice {
+    #[serde(default)]
+    delta: Delta,
+    finish_reason: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+struct Delta {
+    content: Option<String>,
+    #[serde(default)]
+    tool_calls: Vec<ToolCallDelta>,
+}
+
+#[derive(Deserialize)]
+struct ToolCallDelta {
+    #[serde(default)]
+    index: usize,
+    id: Option<String>,
+    function: Option<FunctionDelta>,
+}
+
+#[derive(Deserialize)]
+struct FunctionDelta {
+    name: Option<String>,
+    arguments: Option<String>,
+}
+
+impl EmbeddingDialect for OpenAi {
+    fn encode_embeddings(&self, endpoint: &Endpoint, model: &str, texts: &[String]) -> HttpRequest {
+        let body = json!({ "model": model, "input": texts });
+        HttpRequest::post_json(endpoint.url("embeddings"), &body)
+            .bearer(endpoint.api_key.as_deref())
+    }
+
+    fn decode_embeddings(&self, raw: &Value) -> Result<Vec<Vec<f32>>> {
+        let mut data = Embeddings::deserialize(raw)?.data;
+        data.sort_by_key(|item| item.index);
+        Ok(data.into_iter().map(|item| item.embedding).collect())
+    }
+
+    fn dimensions(&self, model: &str) -> Option<usize> {
+        match model {
+            "text-embedding-3-small" | "text-embedding-ada-002" => Some(1536),
+            "text-embedding-3-large" => Some(3072),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Embeddings {
+    data: Vec<EmbeddingItem>,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingItem {
+    #[serde(default)]
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::{ChatMessage, NormalizedUsage, ToolDefinition};
+
+    #[test]
+    fn test_encode_tool_conversation() {
+        let endpoint = Endpoint {
+            base_url: OPENAI.base_url.to_string(),
+            api_key: Some("sk-test".to_string()),
+            api_version: None,
+        };
+        let mut assistant = ChatMessage::assistant("");
+        assistant.tool_calls.push(ToolCall {
+            id: "call_1".to_string(),
+            name: "echo".to_string(),
+            arguments: json!({"text": "hi"}),
+        });
+        let request = ChatRequest {
+            system: Some("Be brief".to_string()),
+            messages: vec![
+                ChatMessage::user("echo hi"),
+                assistant,
+                ChatMessage::tool("call_1", "hi"),
+            ],
+            tools: vec![ToolDefinition {
+                name: "echo".to_string(),
+                description: "Echo".to_string(),
+                parameters: json!({"type": "object"}),
+            }],
+            max_tokens: Some(100),
+            temperature: None,
+            seed: Some(7),
+            reasoning: None,
+        };
+
+        let http = OPENAI.encode(&endpoint, "gpt-4o", &request);
+        assert_eq!(http.url, "https://api.openai.com/v1/chat/completions");
+        assert!(http
+            .headers
+            .contains(&("authorization".to_string(), "Bearer sk-test".to_string())));
+        let body: Value = serde_json::from_slice(&http.body).unwrap();
+        assert_eq!(
+            body["messages"][0],
+            json!({"role": "system", "content": "Be brief"})
+        );
+        assert_eq!(body["messages"][2]["content"], Value::Null);
+        assert_eq!(
+            body["messages"][2]["tool_calls"][0]["function"]["arguments"],
+            r#"{"text":"hi"}"#
+        );
+        assert_eq!(body["messages"][3]["tool_call_id"], "call_1");
+        assert_eq!(body["tools"][0]["function"]["name"], "echo");
+        assert_eq!(body["max_tokens"], 100);
+        assert!(body.get("temperature").is_none());
+        assert_eq!(body["seed"], 7);
+        // DeepSeek takes no seed
+        let body: Value =
+            serde_json::from_slice(&DEEPSEEK.encode(&endpoint, "deepseek-chat", &request).body)
+                .unwrap();
+        assert!(body.get("seed").is_none());
+    }
+
+    #[test]
+    fn test_normalize_tool_call() {
+        let response = OPENAI
+            .normalize(json!({
+                "id": "chatcmpl-1",
+                "choices": [{
+                    "index": 0,
+                    "message": {
+                        "role": "assistant",
+                        "content": null,
+                        "tool_calls": [{
+                            "id": "call_9",
+                            "type": "function",
+                            "function": {"name": "echo", "arguments": "{\"text\":\"hi\"}"}
+                        }]
+                    },
+                    "finish_reason": "tool_calls"
+                }],
+                "usage": {
+                    "prompt_tokens": 12, "completion_tokens": 5, "total_tokens": 17,
+                    "prompt_tokens_details": {"cached_tokens": 8},
+                    "completion_tokens_details": {"reasoning_tokens": 3}
+                }
+            }))
+            .unwrap();
+        assert_eq!(response.content, "");
+        assert_eq!(response.tool_calls[0].id, "call_9");
+        assert_eq!(response.tool_calls[0].arguments, json!({"text": "hi"}));
+        assert_eq!(response.usage.total_tokens, 17);
+        assert_eq!(response.usage.cached_tokens, 8);
+        assert_eq!(response.usage.reasoning_tokens, 3);
+        assert_eq!(response.finish_reason, FinishReason::ToolCalls);
+        assert_eq!(response.provider_raw["id"], "chatcmpl-1");
+    }
+
+    #[test]
+    fn test_stream_assembles_tool_calls_and_reads_usage() {
+        let events = [
+            json!({"choices": [{"index": 0, "delta": {"role": "assistant", "content": ""}}]}),
+            json!({"choices": [{"index": 0, "delta": {"content": "Let me"}}]}),
+            json!({"choices": [{"index": 0, "delta": {"content": " check."}}]}),
+            json!({"choices": [{"index": 0, "delta": {"tool_calls": [{
+                "index": 0, "id": "call_1", "type": "function",
+                "function": {"name": "echo", "arguments": ""}
+            }]}}]}),
+            json!({"choices": [{"index": 0, "delta": {"tool_calls": [{
+                "index": 0, "function": {"arguments": "{\"text\":"}
+            }]}}]}),
+            json!({"choices": [{"index": 0, "delta": {"tool_calls": [{
+                "index": 0, "function": {"arguments": "\"hi\"}"}
+            }]}}]}),
+            json!({"choices": [{"index": 0, "delta": {}, "finish_reason": "tool_calls"}]}),
+            json!({"choices": [], "usage": {
+                "prompt_tokens": 20, "completion_tokens": 9, "total_tokens": 29
+            }}),
+        ];
+        let mut decoder = OpenAiStream::default();
+        let mut chunks = Vec::new();
+        for event in &events {
+            chunks.extend(decoder.event(event).unwrap());
+        }
+        chunks.extend(decoder.finish());
+        assert_eq!(
+            chunks,
+            vec![
+                StreamChunk::Text("Let me".to_string()),
+                StreamChunk::Text(" check.".to_string()),
+                StreamChunk::ToolCall(ToolCall {
+                    id: "call_1".to_string(),
+                    name: "echo".to_string(),
+                    arguments: json!({"text": "hi"}),
+                }),
+                StreamChunk::Usage(NormalizedUsage::new(20, 9)),
+            ]
+        );
+    }
+}
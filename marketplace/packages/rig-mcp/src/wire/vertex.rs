@@ -0,0 +1,829 @@
+//! Vertex AI, Google Cloud's Gemini, authorized with Google Cloud
+//! credentials instead of an API key
+//!
+//! Chat goes through `generateContent`, which takes the same body as the
+//! Gemini API; embeddings through the `predict` method of Google's text
+//! embedding models, e.g. `text-embedding-004`. Requests go to one project
+//! and location, taken from the provider's [`crate::VertexOptions`], its
+//! `base_url` or the environment.
+//!
+//! Requests carry an OAuth access token from Application Default
+//! Credentials, first match wins: the file `GOOGLE_APPLICATION_CREDENTIALS`
+//! names, the file `gcloud auth application-default login` writes, then the
+//! metadata server of Compute Engine, GKE and Cloud Run. A `credentials`
+//! file in the options, like `GOOGLE_APPLICATION_CREDENTIALS`, is the only
+//! source tried. Files may hold a service account key or a user's refresh
+//! token; workload identity federation isn't supported. Tokens are fetched
+//! again shortly before they expire, so long-lived clients never see one
+//! lapse. A configured `api_key` is sent as the access token as it is, e.g.
+//! one printed by `gcloud auth print-access-token`.
+
+use anyhow::{Context, Result};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use rsa::pkcs1v15::SigningKey;
+use rsa::pkcs8::DecodePrivateKey;
+use rsa::signature::{SignatureEncoding, Signer as _};
+use rsa::RsaPrivateKey;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::Mutex;
+
+use super::bedrock::uri_encode;
+use super::gemini::{self, Gemini};
+use super::{Dialect, EmbeddingDialect, Endpoint, Normalizer};
+use crate::provider::{ChatRequest, NormalizedResponse};
+use crate::transport::{HttpRequest, HttpTransport, Method};
+use crate::ProviderConfig;
+
+/// Location of requests when none is configured
+const DEFAULT_LOCATION: &str = "us-central1";
+
+/// Where refresh tokens are exchanged, and service accounts' default
+const TOKEN_URI: &str = "https://oauth2.googleapis.com/token";
+
+/// The scope Vertex AI accepts tokens of
+const SCOPE: &str = "https://www.googleapis.com/auth/cloud-platform";
+
+const METADATA_HOST: &str = "metadata.google.internal";
+
+/// Off Google Cloud nothing answers the metadata host, so don't wait long
+/// for it
+const METADATA_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Tokens are fetched again this many seconds before they expire
+const REFRESH_MARGIN_SECS: i64 = 300;
+
+/// How long the tokens asked for with a service account's key last, the
+/// most Google grants
+const ASSERTION_LIFETIME_SECS: i64 = 3600;
+
+pub(crate) struct Vertex;
+
+impl Dialect for Vertex {
+    /// Vertex is served per project and location; see
+    /// [`TokenSource::base_url`]
+    fn default_base_url(&self) -> &'static str {
+        ""
+    }
+
+    fn requires_api_key(&self) -> bool {
+        false
+    }
+
+    fn supports_reasoning(&self, model: &str) -> bool {
+        Gemini.supports_reasoning(model)
+    }
+
+    fn encode(&self, endpoint: &Endpoint, model: &str, request: &ChatRequest) -> HttpRequest {
+        HttpRequest::post_json(
+            model_url(endpoint, model, "generateContent"),
+            &gemini::body(request),
+        )
+        .bearer(endpoint.api_key.as_deref())
+    }
+}
+
+/// URL of `method` on one of Google's models
+fn model_url(endpoint: &Endpoint, model: &str, method: &str) -> String {
+    endpoint.url(&format!("publishers/google/models/{}:{}", model, method))
+}
+
+impl Normalizer for Vertex {
+    fn parse(&self, raw: &Value) -> Result<NormalizedResponse> {
+        Gemini.parse(raw)
+    }
+}
+
+impl EmbeddingDialect for Vertex {
+    fn encode_embeddings(&self, endpoint: &Endpoint, model: &str, texts: &[String]) -> HttpRequest {
+        let instances: Vec<Value> = texts
+            .iter()
+            .map(|text| json!({ "content": text }))
+            .collect();
+        HttpRequest::post_json(
+            model_url(endpoint, model, "predict"),
+            &json!({ "instances": instances }),
+        )
+        .bearer(endpoint.api_key.as_deref())
+    }
+
+    fn decode_embeddings(&self, raw: &Value) -> Result<Vec<Vec<f32>>> {
+        #[derive(Deserialize)]
+        struct Predictions {
+            predictions: Vec<Prediction>,
+        }
+        #[derive(Deserialize)]
+        struct Prediction {
+            embeddings: Embeddings,
+        }
+        #[derive(Deserialize)]
+        struct Embeddings {
+            values: Vec<f32>,
+        }
+        Ok(Predictions::deserialize(raw)?
+            .predictions
+            .into_iter()
+            .map(|prediction| prediction.embeddings.values)
+            .collect())
+    }
+
+    fn dimensions(&self, model: &str) -> Option<usize> {
+        match model {
+            "text-embedding-004" | "text-embedding-005" | "text-multilingual-embedding-002" => {
+                Some(768)
+            }
+            "gemini-embedding-001" => Some(3072),
+            _ => None,
+        }
+    }
+
+    /// Outside `us-central1` the text embedding models take five texts a
+    /// request, and Gemini's embedding model one everywhere
+    fn max_batch(&self, model: &str) -> Option<usize> {
+        match model {
+            "gemini-embedding-001" => Some(1),
+            _ => Some(5),
+        }
+    }
+}
+
+/// Every source of Application Default Credentials came up empty
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error(
+    "No Google Cloud credentials for provider '{}'; tried {}",
+    .provider,
+    .attempts.join("; ")
+)]
+pub struct NoGoogleCredentials {
+    pub provider: String,
+    /// Each source tried, with why it had no credentials
+    pub attempts: Vec<String>,
+}
+
+/// An access token and when it stops working
+#[derive(Clone)]
+struct Token {
+    access_token: String,
+    expires: DateTime<Utc>,
+}
+
+/// Token endpoint and metadata server replies
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: i64,
+}
+
+impl From<TokenResponse> for Token {
+    fn from(response: TokenResponse) -> Self {
+        Self {
+            access_token: response.access_token,
+            expires: Utc::now() + chrono::Duration::seconds(response.expires_in),
+        }
+    }
+}
+
+/// A credentials file, as `gcloud` and the Cloud console write them
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum CredentialsFile {
+    ServiceAccount(ServiceAccount),
+    AuthorizedUser(AuthorizedUser),
+}
+
+#[derive(Deserialize)]
+struct ServiceAccount {
+    client_email: String,
+    /// PKCS #8 PEM
+    private_key: String,
+    #[serde(default)]
+    private_key_id: Option<String>,
+    #[serde(default = "default_token_uri")]
+    token_uri: String,
+    #[serde(default)]
+    project_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct AuthorizedUser {
+    client_id: String,
+    client_secret: String,
+    refresh_token: String,
+    #[serde(default)]
+    quota_project_id: Option<String>,
+}
+
+fn default_token_uri() -> String {
+    TOKEN_URI.to_string()
+}
+
+impl CredentialsFile {
+    /// Project the credentials belong to, if the file names one
+    fn project(&self) -> Option<&str> {
+        match self {
+            Self::ServiceAccount(account) => account.project_id.as_deref(),
+            Self::AuthorizedUser(user) => user.quota_project_id.as_deref(),
+        }
+    }
+
+    /// A fresh access token, from the token endpoint
+    async fn token(&self, transport: &dyn HttpTransport, provider: &str) -> Result<Token> {
+        let (url, request) = match self {
+            Self::ServiceAccount(account) => {
+                let assertion = assertion(account, Utc::now())?;
+                let request = form_post(
+                    &account.token_uri,
+                    &[
+                        ("grant_type", "urn:ietf:params:oauth:grant-type:jwt-bearer"),
+                        ("assertion", &assertion),
+                    ],
+                );
+                (account.token_uri.as_str(), request)
+            }
+            Self::AuthorizedUser(user) => {
+                let request = form_post(
+                    TOKEN_URI,
+                    &[
+                        ("grant_type", "refresh_token"),
+                        ("client_id", &user.client_id),
+                        ("client_secret", &user.client_secret),
+                        ("refresh_token", &user.refresh_token),
+                    ],
+                );
+                (TOKEN_URI, request)
+            }
+        };
+        let service = format!("Token endpoint {} of provider '{}'", url, provider);
+        let raw = gemini::send(transport, request, &service).await?;
+        let response = TokenResponse::deserialize(&raw)
+            .with_context(|| format!("{} returned no access token", service))?;
+        Ok(response.into())
+    }
+}
+
+/// A service account's signed request for a token, a JWT signed with RS256
+fn assertion(account: &ServiceAccount, now: DateTime<Utc>) -> Result<String> {
+    let key = RsaPrivateKey::from_pkcs8_pem(&account.private_key).with_context(|| {
+        format!(
+            "Invalid private_key of service account {}",
+            account.client_email
+        )
+    })?;
+    let mut header = json!({ "alg": "RS256", "typ": "JWT" });
+    if let Some(id) = &account.private_key_id {
+        header["kid"] = id.as_str().into();
+    }
+    let claims = json!({
+        "iss": account.client_email,
+        "scope": SCOPE,
+        "aud": account.token_uri,
+        "iat": now.timestamp(),
+        "exp": now.timestamp() + ASSERTION_LIFETIME_SECS,
+    });
+    let signed = format!(
+        "{}.{}",
+        URL_SAFE_NO_PAD.encode(header.to_string()),
+        URL_SAFE_NO_PAD.encode(claims.to_string())
+    );
+    let signature = SigningKey::<Sha256>::new(key).sign(signed.as_bytes());
+    Ok(format!(
+        "{}.{}",
+        signed,
+        URL_SAFE_NO_PAD.encode(signature.to_bytes())
+    ))
+}
+
+/// A POST of `fields` as an HTML form
+fn form_post(url: &str, fields: &[(&str, &str)]) -> HttpRequest {
+    let body = fields
+        .iter()
+        .map(|(name, value)| format!("{}={}", uri_encode(name), uri_encode(value)))
+        .collect::<Vec<_>>()
+        .join("&");
+    HttpRequest {
+        method: Method::POST,
+        url: url.to_string(),
+        headers: vec![(
+            "content-type".to_string(),
+            "application/x-www-form-urlencoded".to_string(),
+        )],
+        body: body.into_bytes(),
+    }
+}
+
+/// Authorizes a Vertex provider's requests with the tokens Application
+/// Default Credentials grant
+pub(crate) struct TokenSource {
+    provider: String,
+    base_url: String,
+    /// The only source tried when set
+    credentials: Option<PathBuf>,
+    /// The variables of the chain, as they were when the provider was created
+    env: HashMap<String, String>,
+    /// Fetched on the first request
+    token: Mutex<Option<Token>>,
+}
+
+impl TokenSource {
+    pub fn new(config: &ProviderConfig) -> Result<Self> {
+        let env = std::env::vars()
+            .filter(|(name, _)| {
+                name.starts_with("GOOGLE_")
+                    || name.starts_with("CLOUDSDK_")
+                    || ["GCE_METADATA_HOST", "HOME", "APPDATA"].contains(&name.as_str())
+            })
+            .collect();
+        Self::with_env(config, env)
+    }
+
+    /// The location is the first of `vertex.location`, the `base_url` path,
+    /// `GOOGLE_CLOUD_LOCATION` and `us-central1`; the project the first of
+    /// `vertex.project`, the `base_url` path, `GOOGLE_CLOUD_PROJECT` and the
+    /// credentials file's project. `env` stands in for the process
+    /// environment.
+    pub(crate) fn with_env(config: &ProviderConfig, env: HashMap<String, String>) -> Result<Self> {
+        let options = config.vertex.clone().unwrap_or_default();
+        let mut source = Self {
+            provider: config.name.clone(),
+            base_url: String::new(),
+            credentials: options.credentials,
+            env,
+            token: Mutex::new(None),
+        };
+        let base_url = config.base_url.as_deref();
+        let location = options
+            .location
+            .or_else(|| base_url.and_then(|url| path_segment(url, "locations")))
+            .or_else(|| source.var("GOOGLE_CLOUD_LOCATION"))
+            .unwrap_or_else(|| DEFAULT_LOCATION.to_string());
+        let project = options
+            .project
+            .or_else(|| base_url.and_then(|url| path_segment(url, "projects")))
+            .or_else(|| source.var("GOOGLE_CLOUD_PROJECT"))
+            .or_else(|| source.file_project())
+            .with_context(|| {
+                format!(
+                    "Provider '{}' needs a Google Cloud project (set vertex.project, \
+                     GOOGLE_CLOUD_PROJECT or a base_url ending in \
+                     /projects/<project>/locations/<location>)",
+                    config.name
+                )
+            })?;
+        source.base_url = match base_url {
+            Some(url) if url.contains("/projects/") => url.to_string(),
+            _ => {
+                let host = match (base_url, location.as_str()) {
+                    (Some(url), _) => url.trim_end_matches('/').to_string(),
+                    (None, "global") => "https://aiplatform.googleapis.com".to_string(),
+                    (None, region) => format!("https://{}-aiplatform.googleapis.com", region),
+                };
+                format!("{}/v1/projects/{}/locations/{}", host, project, location)
+            }
+        };
+        Ok(source)
+    }
+
+    /// Where the project's models are served in its location
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// `request` with a bearer token, fetching one on first use and again
+    /// shortly before it expires
+    pub async fn authorize(
+        &self, transport: &dyn HttpTransport, request: HttpRequest,
+    ) -> Result<HttpRequest> {
+        let mut cached = self.token.lock().await;
+        let now = Utc::now();
+        let fresh = cached
+            .as_ref()
+            .filter(|token| token.expires - now > chrono::Duration::seconds(REFRESH_MARGIN_SECS));
+        let token = match fresh {
+            Some(token) => token.clone(),
+            None => {
+                let token = self.fetch(transport).await?;
+                *cached = Some(token.clone());
+                token
+            }
+        };
+        Ok(request.bearer(Some(&token.access_token)))
+    }
+
+    /// A token from the first source of the chain with credentials
+    async fn fetch(&self, transport: &dyn HttpTransport) -> Result<Token> {
+        let mut attempts = Vec::new();
+        if let Some(path) = self.explicit_file() {
+            match read_credentials(&path) {
+                Ok(file) => return file.token(transport, &self.provider).await,
+                Err(why) => attempts.push(why),
+            }
+            return Err(self.missing(attempts).into());
+        }
+        match self.gcloud_file() {
+            Some(path) => match read_credentials(&path) {
+                Ok(file) => return file.token(transport, &self.provider).await,
+                Err(why) => attempts.push(why),
+            },
+            None => attempts.push("no home directory to find gcloud's credentials in".to_string()),
+        }
+        match self.metadata_token(transport).await {
+            Ok(token) => return Ok(token),
+            Err(why) => attempts.push(format!("metadata server: {}", why)),
+        }
+        Err(self.missing(attempts).into())
+    }
+
+    fn missing(&self, attempts: Vec<String>) -> NoGoogleCredentials {
+        NoGoogleCredentials {
+            provider: self.provider.clone(),
+            attempts,
+        }
+    }
+
+    /// A token of the service account attached to the machine
+    async fn metadata_token(&self, transport: &dyn HttpTransport) -> Result<Token, String> {
+        let host = self
+            .var("GCE_METADATA_HOST")
+            .unwrap_or_else(|| METADATA_HOST.to_string());
+        let url = format!(
+            "http://{}/computeMetadata/v1/instance/service-accounts/default/token",
+            host
+        );
+        let request = HttpRequest::get(&url).header("Metadata-Flavor", "Google");
+        let response = match tokio::time::timeout(METADATA_TIMEOUT, transport.send(request)).await {
+            Ok(Ok(response)) => response,
+            Ok(Err(err)) => return Err(format!("{} unreachable: {:#}", url, err)),
+            Err(_) => return Err(format!("{} timed out", url)),
+        };
+        if !response.is_success() {
+            return Err(format!("{} returned HTTP {}", url, response.status));
+        }
+        let token: TokenResponse = serde_json::from_slice(&response.body)
+            .map_err(|err| format!("invalid token from {}: {}", url, err))?;
+        Ok(token.into())
+    }
+
+    /// Project of the credentials file the chain would read
+    fn file_project(&self) -> Option<String> {
+        let path = self.explicit_file().or_else(|| self.gcloud_file())?;
+        read_credentials(&path).ok()?.project().map(String::from)
+    }
+
+    fn explicit_file(&self) -> Option<PathBuf> {
+        self.credentials.clone().or_else(|| {
+            self.var("GOOGLE_APPLICATION_CREDENTIALS")
+                .map(PathBuf::from)
+        })
+    }
+
+    /// The credentials `gcloud auth application-default login` writes
+    fn gcloud_file(&self) -> Option<PathBuf> {
+        let dir = self
+            .var("CLOUDSDK_CONFIG")
+            .map(PathBuf::from)
+            .or_else(|| Some(PathBuf::from(self.var("HOME")?).join(".config/gcloud")))
+            .or_else(|| Some(PathBuf::from(self.var("APPDATA")?).join("gcloud")))?;
+        Some(dir.join("application_default_credentials.json"))
+    }
+
+    /// A variable of the chain, treating empty as unset
+    fn var(&self, name: &str) -> Option<String> {
+        self.env
+            .get(name)
+            .filter(|value| !value.is_empty())
+            .cloned()
+    }
+}
+
+/// The credentials in `path`, or why there are none
+fn read_credentials(path: &Path) -> Result<CredentialsFile, String> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            return Err(format!("{} (missing)", path.display()));
+        }
+        Err(err) => return Err(format!("{}: {}", path.display(), err)),
+    };
+    serde_json::from_str(&text).map_err(|err| format!("{}: {}", path.display(), err))
+}
+
+/// The path segment after `/<name>/` in `url`, e.g. the project of
+/// `…/projects/my-project/locations/us-central1`
+fn path_segment(url: &str, name: &str) -> Option<String> {
+    let (_, rest) = url.split_once(&format!("/{}/", name))?;
+    rest.split('/')
+        .next()
+        .filter(|segment| !segment.is_empty())
+        .map(String::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::ChatMessage;
+    use crate::testing::MockTransport;
+    use crate::VertexOptions;
+    use rsa::pkcs1v15::{Signature, VerifyingKey};
+    use rsa::pkcs8::{EncodePrivateKey, LineEnding};
+    use rsa::rand_core::OsRng;
+    use rsa::signature::Verifier;
+
+    fn config(options: Option<VertexOptions>, base_url: Option<&str>) -> ProviderConfig {
+        ProviderConfig {
+            name: "vertex".to_string(),
+            model: "gemini-1.5-pro".to_string(),
+            base_url: base_url.map(String::from),
+            vertex: options,
+            ..ProviderConfig::default()
+        }
+    }
+
+    fn project(project: &str, location: Option<&str>) -> Option<VertexOptions> {
+        Some(VertexOptions {
+            project: Some(project.to_string()),
+            location: location.map(String::from),
+            credentials: None,
+        })
+    }
+
+    fn env(vars: &[(&str, &str)]) -> HashMap<String, String> {
+        vars.iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn header<'a>(request: &'a HttpRequest, name: &str) -> Option<&'a str> {
+        request
+            .headers
+            .iter()
+            .find(|(header, _)| header == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The value of field `name` of a form body, still encoded
+    fn form_field(request: &HttpRequest, name: &str) -> Option<String> {
+        request.body_text().split('&').find_map(|field| {
+            let (key, value) = field.split_once('=')?;
+            (key == name).then(|| value.to_string())
+        })
+    }
+
+    #[test]
+    fn test_project_and_location_make_the_base_url() {
+        let source = TokenSource::with_env(&config(project("p1", None), None), env(&[])).unwrap();
+        assert_eq!(
+            source.base_url(),
+            "https://us-central1-aiplatform.googleapis.com/v1/projects/p1/locations/us-central1"
+        );
+        let source =
+            TokenSource::with_env(&config(project("p1", Some("global")), None), env(&[])).unwrap();
+        assert_eq!(
+            source.base_url(),
+            "https://aiplatform.googleapis.com/v1/projects/p1/locations/global"
+        );
+        let vars = env(&[
+            ("GOOGLE_CLOUD_PROJECT", "p2"),
+            ("GOOGLE_CLOUD_LOCATION", "europe-west4"),
+        ]);
+        let source = TokenSource::with_env(&config(None, None), vars).unwrap();
+        assert_eq!(
+            source.base_url(),
+            "https://europe-west4-aiplatform.googleapis.com/v1/projects/p2/locations/europe-west4"
+        );
+
+        let url = "https://vertex.internal/v1/projects/p3/locations/asia-northeast1";
+        let source = TokenSource::with_env(&config(None, Some(url)), env(&[])).unwrap();
+        assert_eq!(source.base_url(), url);
+        let endpoint = Endpoint {
+            base_url: url.to_string(),
+            api_key: Some("ya29.token".to_string()),
+            api_version: None,
+        };
+        let request = ChatRequest {
+            messages: vec![ChatMessage::user("hi")],
+            ..ChatRequest::default()
+        };
+        let encoded = Vertex.encode(&endpoint, "gemini-2.5-flash", &request);
+        assert_eq!(
+            encoded.url,
+            format!(
+                "{}/publishers/google/models/gemini-2.5-flash:generateContent",
+                url
+            )
+        );
+        assert_eq!(header(&encoded, "authorization"), Some("Bearer ya29.token"));
+        assert!(Vertex.supports_reasoning("gemini-2.5-flash"));
+
+        let home = tempfile::tempdir().unwrap();
+        let vars = env(&[("HOME", home.path().to_str().unwrap())]);
+        let err = TokenSource::with_env(&config(None, None), vars)
+            .err()
+            .unwrap();
+        assert!(
+            err.to_string().contains("needs a Google Cloud project"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_embeddings_are_predictions() {
+        let endpoint = Endpoint {
+            base_url: "https://x/v1/projects/p/locations/us-central1".to_string(),
+            api_key: None,
+            api_version: None,
+        };
+        let texts = vec!["a".to_string(), "b".to_string()];
+        let request = Vertex.encode_embeddings(&endpoint, "text-embedding-004", &texts);
+        assert_eq!(
+            request.url,
+            "https://x/v1/projects/p/locations/us-central1/publishers/google/models/\
+             text-embedding-004:predict"
+        );
+        let body: Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(
+            body,
+            json!({"instances": [{"content": "a"}, {"content": "b"}]})
+        );
+        let vectors = Vertex
+            .decode_embeddings(&json!({
+                "predictions": [
+                    {"embeddings": {"values": [0.5, 0.25], "statistics": {"token_count": 1}}},
+                    {"embeddings": {"values": [1.0, 0.0], "statistics": {"token_count": 1}}}
+                ]
+            }))
+            .unwrap();
+        assert_eq!(vectors, vec![vec![0.5, 0.25], vec![1.0, 0.0]]);
+        assert_eq!(Vertex.dimensions("text-embedding-004"), Some(768));
+        assert_eq!(Vertex.max_batch("gemini-embedding-001"), Some(1));
+    }
+
+    #[tokio::test]
+    async fn test_service_account_tokens_are_refreshed_before_expiry() {
+        let dir = tempfile::tempdir().unwrap();
+        let key_file = dir.path().join("key.json");
+        // Generated per run rather than checked in; small, since it only
+        // signs assertions for the mock token endpoint
+        let private_key = RsaPrivateKey::new(&mut OsRng, 1024).unwrap();
+        let pem = private_key.to_pkcs8_pem(LineEnding::LF).unwrap();
+        let key = json!({
+            "type": "service_account",
+            "project_id": "sa-project",
+            "private_key_id": "key-1",
+            "private_key": pem.as_str(),
+            "client_email": "rig@sa-project.iam.gserviceaccount.com",
+            "token_uri": "https://oauth2.googleapis.com/token"
+        });
+        std::fs::write(&key_file, key.to_string()).unwrap();
+        let vars = env(&[("GOOGLE_APPLICATION_CREDENTIALS", key_file.to_str().unwrap())]);
+        let source = TokenSource::with_env(&config(None, None), vars).unwrap();
+        assert!(source.base_url().contains("/projects/sa-project/"));
+
+        let transport = MockTransport::new();
+        // Already inside the refresh margin, so the next request fetches
+        // another
+        transport.push_json(
+            200,
+            json!({"access_token": "ya29.first", "expires_in": 100}),
+        );
+        transport.push_json(
+            200,
+            json!({"access_token": "ya29.second", "expires_in": 3600}),
+        );
+        let mut tokens = Vec::new();
+        for _ in 0..3 {
+            let request = HttpRequest::get(source.base_url());
+            let authorized = source.authorize(&transport, request).await.unwrap();
+            tokens.push(header(&authorized, "authorization").unwrap().to_string());
+        }
+        assert_eq!(
+            tokens,
+            [
+                "Bearer ya29.first",
+                "Bearer ya29.second",
+                "Bearer ya29.second"
+            ]
+        );
+
+        let requests = transport.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].url, "https://oauth2.googleapis.com/token");
+        assert_eq!(
+            form_field(&requests[0], "grant_type").as_deref(),
+            Some("urn%3Aietf%3Aparams%3Aoauth%3Agrant-type%3Ajwt-bearer")
+        );
+        let assertion = form_field(&requests[0], "assertion").unwrap();
+        let (signed, signature) = assertion.rsplit_once('.').unwrap();
+        let signature = Signature::try_from(URL_SAFE_NO_PAD.decode(signature).unwrap().as_slice());
+        VerifyingKey::<Sha256>::new(private_key.to_public_key())
+            .verify(signed.as_bytes(), &signature.unwrap())
+            .unwrap();
+        let decode = |part: &str| -> Value {
+            serde_json::from_slice(&URL_SAFE_NO_PAD.decode(part).unwrap()).unwrap()
+        };
+        let (jose, claims) = signed.split_once('.').unwrap();
+        let claims = decode(claims);
+        assert_eq!(decode(jose)["kid"], "key-1");
+        assert_eq!(claims["iss"], "rig@sa-project.iam.gserviceaccount.com");
+        assert_eq!(claims["aud"], "https://oauth2.googleapis.com/token");
+        assert_eq!(claims["scope"], SCOPE);
+        assert_eq!(
+            claims["exp"].as_i64().unwrap() - claims["iat"].as_i64().unwrap(),
+            3600
+        );
+    }
+
+    #[tokio::test]
+    async fn test_gcloud_user_credentials_are_exchanged() {
+        let home = tempfile::tempdir().unwrap();
+        let gcloud = home.path().join(".config/gcloud");
+        std::fs::create_dir_all(&gcloud).unwrap();
+        let user = json!({
+            "type": "authorized_user",
+            "client_id": "client.apps.googleusercontent.com",
+            "client_secret": "shh",
+            "refresh_token": "1//refresh",
+            "quota_project_id": "user-project"
+        });
+        std::fs::write(
+            gcloud.join("application_default_credentials.json"),
+            user.to_string(),
+        )
+        .unwrap();
+        let vars = env(&[("HOME", home.path().to_str().unwrap())]);
+        let source = TokenSource::with_env(&config(None, None), vars).unwrap();
+        assert!(source.base_url().contains("/projects/user-project/"));
+
+        let transport = MockTransport::new();
+        transport.push_json(
+            200,
+            json!({"access_token": "ya29.user", "expires_in": 3599}),
+        );
+        let request = HttpRequest::get(source.base_url());
+        let authorized = source.authorize(&transport, request).await.unwrap();
+        assert_eq!(
+            header(&authorized, "authorization"),
+            Some("Bearer ya29.user")
+        );
+        let exchange = &transport.requests()[0];
+        assert_eq!(exchange.url, TOKEN_URI);
+        assert_eq!(
+            form_field(exchange, "refresh_token").as_deref(),
+            Some("1%2F%2Frefresh")
+        );
+        assert_eq!(
+            header(exchange, "content-type"),
+            Some("application/x-www-form-urlencoded")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_missing_credentials_name_every_source() {
+        let home = tempfile::tempdir().unwrap();
+        let vars = env(&[("HOME", home.path().to_str().unwrap())]);
+        let source = TokenSource::with_env(&config(project("p1", None), None), vars).unwrap();
+        let request = HttpRequest::get(source.base_url());
+        let err = source
+            .authorize(&MockTransport::new(), request)
+            .await
+            .unwrap_err();
+        let missing = err.downcast_ref::<NoGoogleCredentials>().unwrap();
+        assert_eq!(missing.attempts.len(), 2);
+        let message = err.to_string();
+        assert!(
+            message.contains("gcloud/application_default_credentials.json (missing)"),
+            "{}",
+            message
+        );
+        assert!(
+            message.contains("metadata server: http://metadata.google.internal/"),
+            "{}",
+            message
+        );
+
+        let options = VertexOptions {
+            credentials: Some(home.path().join("absent.json")),
+            ..project("p1", None).unwrap()
+        };
+        let source = TokenSource::with_env(&config(Some(options), None), env(&[])).unwrap();
+        let request = HttpRequest::get(source.base_url());
+        let err = source
+            .authorize(&MockTransport::new(), request)
+            .await
+            .unwrap_err();
+        let missing = err.downcast_ref::<NoGoogleCredentials>().unwrap();
+        assert_eq!(missing.attempts.len(), 1);
+        assert!(
+            missing.attempts[0].ends_with("absent.json (missing)"),
+            "{:?}",
+            missing
+        );
+    }
+}
@@ -0,0 +1,771 @@
+//! Structured fuzzing of `Config` deserialization
+//!
+//! Config files are written by hand or templated in CI, so anything may
+//! arrive. Deserializing must never panic: junk is rejected with an error,
+//! and every valid config survives a round trip through each format.
+
+use proptest::prelude::*;
+use rig_mcp_integration::{
+    AgentConfig, ArgumentMode, AuditConfig, BedrockOptions, Config, DebugLogging, Deterministic,
+    EmbeddingConfig, EmbeddingFallback, GeminiOptions, ModelChangePolicy, ModerationConfig,
+    PiiKind, Price, ProviderConfig, ProviderGroupConfig, RaceConfig, RateLimitConfig,
+    ReasoningConfig, ReasoningEffort, RedactionConfig, RedactionRule, RerankConfig, RootConfig,
+    RoutingStrategy, RoutingWeights, RunBudget, ScoreNormalization, ServerConfig, SessionConfig,
+    Transport, Truncation, VertexOptions,
+};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+const PROVIDERS: &[&str] = &[
+    "openai",
+    "anthropic",
+    "azure",
+    "cohere",
+    "deepseek",
+    "gemini",
+    "groq",
+    "mistral",
+    "ollama",
+    "openai-compatible",
+    "bedrock",
+    "vertex",
+];
+
+fn text() -> impl Strategy<Value = String> {
+    "[a-zA-Z0-9 ./:_-]{0,16}"
+}
+
+fn provider() -> impl Strategy<Value = ProviderConfig> {
+    (
+        prop::sample::select(PROVIDERS),
+        text(),
+        prop::option::of(text()),
+        prop::option::of(text()),
+        prop::collection::vec(text(), 0..3),
+        any::<bool>(),
+        any::<bool>(),
+        any::<bool>(),
+        prop::option::of(text()),
+        prop::option::of(gemini()),
+        prop::option::of(bedrock()),
+        (
+            prop::option::of(vertex()),
+            prop::collection::vec(text(), 0..3),
+            prop::option::of(0..=i64::MAX as u64),
+            prop::option::of(1..=i64::MAX as u64),
+            any::<u32>(),
+            prop::option::of(0..=i64::MAX as u64),
+            prop::option::of(rate_limit()),
+        ),
+    )
+        .prop_map(
+            |(
+                name,
+                model,
+                api_key,
+                base_url,
+                features,
+                ensure_model,
+                streaming,
+                skip_verify,
+                api_version,
+                gemini,
+                bedrock,
+                (
+                    vertex,
+                    fallbacks,
+                    context_window,
+                    timeout_ms,
+                    max_retries,
+                    retry_backoff_ms,
+                    rate_limit,
+                ),
+            )| {
+                ProviderConfig {
+                    name: name.to_string(),
+                    model,
+                    api_key,
+                    base_url,
+                    features,
+                    ensure_model,
+                    streaming,
+                    skip_verify,
+                    api_version,
+                    gemini,
+                    bedrock,
+                    vertex,
+                    fallbacks,
+                    context_window,
+                    timeout_ms,
+                    max_retries,
+                    retry_backoff_ms,
+                    rate_limit,
+                }
+            },
+        )
+}
+
+fn rate_limit() -> impl Strategy<Value = RateLimitConfig> {
+    (
+        prop::option::of(any::<u32>()),
+        prop::option::of(0..=i64::MAX as u64),
+        prop::option::of(0..=i64::MAX as u64),
+    )
+        .prop_map(
+            |(requests_per_minute, tokens_per_minute, max_wait_ms)| RateLimitConfig {
+                requests_per_minute,
+                tokens_per_minute,
+                max_wait_ms,
+            },
+        )
+}
+
+fn gemini() -> impl Strategy<Value = GeminiOptions> {
+    (
+        prop::collection::btree_map(text(), text(), 0..3),
+        prop::option::of(any::<u32>()),
+    )
+        .prop_map(|(safety_settings, cache_ttl)| GeminiOptions {
+            safety_settings,
+            cache_ttl: cache_ttl.map(|s| Duration::from_secs(s.into())),
+        })
+}
+
+fn bedrock() -> impl Strategy<Value = BedrockOptions> {
+    (prop::option::of(text()), prop::option::of(text()))
+        .prop_map(|(region, profile)| BedrockOptions { region, profile })
+}
+
+fn vertex() -> impl Strategy<Value = VertexOptions> {
+    (
+        prop::option::of(text()),
+        prop::option::of(text()),
+        prop::option::of(text()),
+    )
+        .prop_map(|(project, location, credentials)| VertexOptions {
+            project,
+            location,
+            credentials: credentials.map(PathBuf::from),
+        })
+}
+
+fn server() -> impl Strategy<Value = ServerConfig> {
+    let transport = prop_oneof![
+        (
+            text(),
+            prop::collection::vec(text(), 0..3),
+            prop::collection::hash_map(text(), text(), 0..3),
+        )
+            .prop_map(|(command, args, env)| Transport::Stdio { command, args, env }),
+        (text(), prop::option::of(text()))
+            .prop_map(|(url, bearer_token)| Transport::Sse { url, bearer_token }),
+        (text(), prop::option::of(text()), any::<bool>()).prop_map(
+            |(url, bearer_token, reinitialize)| Transport::Http {
+                url,
+                bearer_token,
+                reinitialize
+            }
+        ),
+    ];
+    (
+        text(),
+        transport,
+        prop::option::of(prop::collection::vec(root(), 0..3)),
+        prop::option::of(embeddings()),
+    )
+        .prop_map(|(name, transport, roots, embeddings)| ServerConfig {
+            name,
+            transport,
+            roots,
+            embeddings,
+        })
+}
+
+fn root() -> impl Strategy<Value = RootConfig> {
+    (text(), prop::option::of(text())).prop_map(|(path, name)| RootConfig {
+        path: path.into(),
+        name,
+    })
+}
+
+fn embedding_fallback() -> impl Strategy<Value = EmbeddingFallback> {
+    (
+        text(),
+        prop::sample::select(PROVIDERS),
+        prop::option::of(text()),
+        prop::option::of(text()),
+        prop::option::of(text()),
+        prop::option::of(1..4096usize),
+    )
+        .prop_map(
+            |(model, provider, api_key, base_url, api_version, dimensions)| EmbeddingFallback {
+                provider: provider.to_string(),
+                model,
+                api_key,
+                base_url,
+                api_version,
+                dimensions,
+            },
+        )
+}
+
+fn embeddings() -> impl Strategy<Value = EmbeddingConfig> {
+    (
+        text(),
+        prop::sample::select(PROVIDERS),
+        prop::option::of(text()),
+        prop::option::of(text()),
+        prop::option::of(text()),
+        prop::option::of(text()),
+        prop::option::of(text()),
+        any::<bool>(),
+        prop::collection::vec(embedding_fallback(), 0..3),
+        (any::<bool>(), any::<bool>()),
+        any::<bool>(),
+        prop::option::of(1usize..4),
+    )
+        .prop_map(
+            |(
+                model,
+                provider,
+                api_key,
+                base_url,
+                api_version,
+                cache_path,
+                index_path,
+                error,
+                fallbacks,
+                (allow_reindex, allow_download),
+                min_max,
+                max_per_description,
+            )| {
+                EmbeddingConfig {
+                    model,
+                    provider: provider.to_string(),
+                    api_key,
+                    base_url,
+                    api_version,
+                    cache_path: cache_path.map(Into::into),
+                    index_path: index_path.map(Into::into),
+                    on_model_change: if error {
+                        ModelChangePolicy::Error
+                    } else {
+                        ModelChangePolicy::Reindex
+                    },
+                    fallbacks,
+                    allow_dimension_change_reindex: allow_reindex,
+                    score_normalization: if min_max {
+                        ScoreNormalization::MinMax
+                    } else {
+                        ScoreNormalization::ZScore
+                    },
+                    max_per_description,
+                    allow_download,
+                }
+            },
+        )
+}
+
+fn agent() -> impl Strategy<Value = AgentConfig> {
+    (
+        1..=u32::MAX as usize,
+        0.0f32..=2.0,
+        prop::option::of(text()),
+        prop::collection::vec(text(), 0..3),
+        budget(),
+        prop::option::of(any::<u32>()),
+        any::<bool>(),
+        any::<bool>(),
+        prop::option::of(reasoning()),
+        prop_oneof![Just(ArgumentMode::Strict), Just(ArgumentMode::Lenient)],
+    )
+        .prop_map(
+            |(
+                max_tokens,
+                temperature,
+                system_prompt,
+                tools,
+                budget,
+                tool_timeout,
+                progress_resets_timeout,
+                compact_tools,
+                reasoning,
+                tool_arguments,
+            )| AgentConfig {
+                max_tokens,
+                temperature,
+                system_prompt,
+                tools,
+                budget,
+                tool_timeout: tool_timeout.map(|s| Duration::from_secs(s.into())),
+                progress_resets_timeout,
+                compact_tools,
+                reasoning,
+                tool_arguments,
+            },
+        )
+}
+
+fn reasoning() -> impl Strategy<Value = ReasoningConfig> {
+    let effort = prop_oneof![
+        Just(ReasoningEffort::Low),
+        Just(ReasoningEffort::Medium),
+        Just(ReasoningEffort::High),
+    ];
+    (effort, prop::option::of(any::<u32>())).prop_map(|(effort, max_thinking_tokens)| {
+        ReasoningConfig {
+            effort,
+            max_thinking_tokens,
+        }
+    })
+}
+
+fn budget() -> impl Strategy<Value = RunBudget> {
+    (
+        // TOML integers are signed
+        prop::option::of(0..=i64::MAX as u64),
+        prop::option::of(0..=i64::MAX as usize),
+        prop::option::of(any::<u32>()),
+    )
+        .prop_map(|(max_total_tokens, max_tool_calls, seconds)| RunBudget {
+            max_total_tokens,
+            max_tool_calls,
+            max_wall_time: seconds.map(|s| Duration::from_secs(s.into())),
+        })
+}
+
+fn debug_logging() -> impl Strategy<Value = DebugLogging> {
+    (
+        any::<bool>(),
+        0..1_000_000usize,
+        prop::collection::vec(text(), 0..3),
+        prop::option::of(text()),
+        prop::option::of(prop::sample::select(PROVIDERS)),
+    )
+        .prop_map(
+            |(enabled, max_body_bytes, redact_fields, dump_dir, dump_provider)| DebugLogging {
+                enabled,
+                max_body_bytes,
+                redact_fields,
+                dump_dir: dump_dir.map(Into::into),
+                dump_provider: dump_provider.map(str::to_string),
+            },
+        )
+}
+
+fn reranker() -> impl Strategy<Value = RerankConfig> {
+    (text(), prop::option::of(text()), 0..=i64::MAX as usize).prop_map(
+        |(model, api_key, candidate_factor)| RerankConfig {
+            provider: "cohere".to_string(),
+            model,
+            api_key,
+            candidate_factor,
+        },
+    )
+}
+
+fn audit() -> impl Strategy<Value = AuditConfig> {
+    (text(), prop::collection::vec(text(), 0..3)).prop_map(|(path, tags)| AuditConfig {
+        path: path.into(),
+        tags,
+    })
+}
+
+fn moderation() -> impl Strategy<Value = ModerationConfig> {
+    (
+        prop::collection::vec(
+            prop::sample::select(&[PiiKind::Email, PiiKind::Phone][..]),
+            0..3,
+        ),
+        prop::collection::vec(text(), 0..3),
+    )
+        .prop_map(|(redact, redact_terms)| ModerationConfig {
+            redact,
+            redact_terms,
+        })
+}
+
+fn redaction() -> impl Strategy<Value = RedactionConfig> {
+    let rule = (
+        text(),
+        prop_oneof![
+            text().prop_map(|pattern| RedactionRule::pattern("", pattern)),
+            prop::sample::select(&[PiiKind::Email, PiiKind::Phone][..])
+                .prop_map(|shape| RedactionRule::shape("", shape)),
+            prop::collection::vec(text(), 1..3).prop_map(|fields| RedactionRule {
+                fields,
+                ..RedactionRule::fields("", &[])
+            }),
+        ],
+        prop::option::of(text()),
+    )
+        .prop_map(|(name, rule, replacement)| RedactionRule {
+            name,
+            replacement,
+            ..rule
+        });
+    (
+        prop::collection::vec(rule, 0..3),
+        prop::collection::vec(any::<bool>(), 6),
+    )
+        .prop_map(|(rules, flags)| RedactionConfig {
+            rules,
+            prompts: flags[0],
+            audit: flags[1],
+            cache: flags[2],
+            debug_logging: flags[3],
+            transcripts: flags[4],
+            finetune: flags[5],
+        })
+}
+
+fn prices() -> impl Strategy<Value = HashMap<String, Price>> {
+    prop::collection::hash_map(
+        text(),
+        (0.0..100.0f64, 0.0..100.0f64).prop_map(|(input, output)| Price { input, output }),
+        0..3,
+    )
+}
+
+fn races() -> impl Strategy<Value = HashMap<String, RaceConfig>> {
+    prop::collection::hash_map(
+        text(),
+        (
+            prop::collection::vec(text(), 0..4),
+            prop::option::of(0..=i64::MAX as u64),
+        )
+            .prop_map(|(targets, stagger_ms)| RaceConfig {
+                targets,
+                stagger_ms,
+            }),
+        0..3,
+    )
+}
+
+fn provider_groups() -> impl Strategy<Value = HashMap<String, ProviderGroupConfig>> {
+    prop::collection::hash_map(
+        text(),
+        (
+            prop::collection::vec(text(), 0..4),
+            any::<bool>(),
+            (0.0..10.0f64, 0.0..10.0f64),
+            0.0..=1.0f64,
+        )
+            .prop_map(
+                |(targets, adaptive, (success_rate, latency), exploration)| ProviderGroupConfig {
+                    targets,
+                    strategy: if adaptive {
+                        RoutingStrategy::Adaptive
+                    } else {
+                        RoutingStrategy::Ordered
+                    },
+                    weights: RoutingWeights {
+                        success_rate,
+                        latency,
+                    },
+                    exploration,
+                },
+            ),
+        0..3,
+    )
+}
+
+fn session() -> impl Strategy<Value = SessionConfig> {
+    let truncation = prop_oneof![
+        Just(Truncation::KeepAll),
+        (0..64usize).prop_map(|keep_recent| Truncation::SummarizeOldest { keep_recent }),
+    ];
+    (
+        any::<bool>(),
+        prop::option::of(0..64usize),
+        prop::option::of(text()),
+        truncation,
+    )
+        .prop_map(
+            |(auto_title, summary_every, summarizer, truncation)| SessionConfig {
+                auto_title,
+                summary_every,
+                summarizer,
+                truncation,
+            },
+        )
+}
+
+fn config() -> impl Strategy<Value = Config> {
+    (
+        prop::collection::vec(provider(), 0..4),
+        prop::collection::vec(server(), 0..3),
+        prop::collection::vec(root(), 0..3),
+        embeddings(),
+        agent(),
+        prop::collection::hash_map(text(), text(), 0..3),
+        debug_logging(),
+        prop::option::of(0..=i64::MAX as u64),
+        prop::option::of(reranker()),
+        prop::option::of(audit()),
+        moderation(),
+        // Tuples stop at twelve elements
+        (
+            any::<bool>(),
+            session(),
+            prices(),
+            races(),
+            redaction(),
+            provider_groups(),
+        ),
+    )
+        .prop_map(
+            |(
+                providers,
+                mcp_servers,
+                roots,
+                embeddings,
+                agent,
+                model_aliases,
+                debug_logging,
+                seed,
+                reranker,
+                audit,
+                moderation,
+                (verify_on_startup, session, prices, races, redaction, provider_groups),
+            )| Config {
+                providers,
+                mcp_servers,
+                roots,
+                embeddings,
+                agent,
+                model_aliases,
+                races,
+                provider_groups,
+                prices,
+                debug_logging,
+                deterministic: seed.map(|seed| Deterministic { seed }),
+                reranker,
+                audit,
+                moderation,
+                redaction,
+                verify_on_startup,
+                session,
+                ..Config::default()
+            },
+        )
+}
+
+/// Arbitrary JSON, nested a few levels deep
+fn junk() -> impl Strategy<Value = Value> {
+    let leaf = prop_oneof![
+        Just(Value::Null),
+        any::<bool>().prop_map(Value::from),
+        any::<i64>().prop_map(Value::from),
+        any::<u64>().prop_map(Value::from),
+        any::<f64>().prop_map(Value::from),
+        ".{0,8}".prop_map(Value::from),
+    ];
+    leaf.prop_recursive(4, 32, 6, |inner| {
+        prop_oneof![
+            prop::collection::vec(inner.clone(), 0..6).prop_map(Value::from),
+            prop::collection::btree_map("[a-z_]{1,12}", inner, 0..6)
+                .prop_map(|map| Value::Object(map.into_iter().collect())),
+        ]
+    })
+}
+
+/// Replace the value at a pseudo-random path of `value` with `replacement`
+fn splice(value: &mut Value, mut choice: usize, replacement: Value) {
+    let child = match value {
+        Value::Object(map) if !map.is_empty() => {
+            let key = map.keys().nth(choice % map.len()).cloned().unwrap();
+            map.get_mut(&key)
+        }
+        Value::Array(items) if !items.is_empty() => {
+            let len = items.len();
+            items.get_mut(choice % len)
+        }
+        _ => None,
+    };
+    choice /= 7;
+    match child {
+        Some(child) if !choice.is_multiple_of(3) => splice(child, choice, replacement),
+        _ => *value = replacement,
+    }
+}
+
+fn as_json(config: &Config) -> Value {
+    serde_json::to_value(config).unwrap()
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(256))]
+
+    #[test]
+    fn roundtrips_through_json(config in config()) {
+        let json = serde_json::to_string(&config).unwrap();
+        let parsed: Config = serde_json::from_str(&json).unwrap();
+        prop_assert_eq!(as_json(&parsed), as_json(&config));
+    }
+
+    #[test]
+    fn roundtrips_through_toml(config in config()) {
+        let toml = toml::to_string(&config).unwrap();
+        let parsed: Config = toml::from_str(&toml).unwrap();
+        prop_assert_eq!(as_json(&parsed), as_json(&config));
+    }
+
+    #[test]
+    fn roundtrips_through_yaml(config in config()) {
+        let yaml = serde_yaml::to_string(&config).unwrap();
+        let parsed: Config = serde_yaml::from_str(&yaml).unwrap();
+        prop_assert_eq!(as_json(&parsed), as_json(&config));
+    }
+
+    #[test]
+    fn rejects_junk_values_without_panicking(
+        config in config(),
+        junk in junk(),
+        choice in any::<usize>(),
+    ) {
+        let mut value = as_json(&config);
+        splice(&mut value, choice, junk);
+        if let Err(err) = serde_json::from_value::<Config>(value.clone()) {
+            prop_assert!(!err.to_string().is_empty());
+        }
+        // The same document in the other formats, where it can be written
+        if let Ok(toml) = toml::to_string(&value) {
+            if let Err(err) = toml::from_str::<Config>(&toml) {
+                prop_assert!(!err.to_string().is_empty());
+            }
+        }
+        let yaml = serde_yaml::to_string(&value).unwrap();
+        if let Err(err) = serde_yaml::from_str::<Config>(&yaml) {
+            prop_assert!(!err.to_string().is_empty());
+        }
+    }
+
+    #[test]
+    fn rejects_corrupted_documents_without_panicking(
+        config in config(),
+        at in any::<prop::sample::Index>(),
+        garbage in ".{0,8}",
+    ) {
+        for document in [
+            serde_json::to_string(&config).unwrap(),
+            toml::to_string(&config).unwrap(),
+            serde_yaml::to_string(&config).unwrap(),
+        ] {
+            let mut cut = at.index(document.len() + 1);
+            while !document.is_char_boundary(cut) {
+                cut -= 1;
+            }
+            let corrupted = format!("{}{}{}", &document[..cut], garbage, &document[cut..]);
+            let _ = serde_json::from_str::<Config>(&corrupted);
+            let _ = toml::from_str::<Config>(&corrupted);
+            let _ = serde_yaml::from_str::<Config>(&corrupted);
+        }
+    }
+}
+
+fn base() -> Value {
+    as_json(&Config::default())
+}
+
+fn agent_error(agent: Value) -> String {
+    let mut value = base();
+    value["agent"] = agent;
+    serde_json::from_value::<Config>(value)
+        .unwrap_err()
+        .to_string()
+}
+
+#[test]
+fn test_out_of_range_max_tokens_is_rejected() {
+    let err = agent_error(serde_json::json!({"max_tokens": 0, "temperature": 0.7, "tools": []}));
+    assert!(err.contains("max_tokens must be between 1 and"), "{}", err);
+
+    let too_many = u64::from(u32::MAX) + 1;
+    let err = agent_error(serde_json::json!({
+        "max_tokens": too_many, "temperature": 0.7, "tools": []
+    }));
+    assert!(err.contains("got 4294967296"), "{}", err);
+
+    let err = agent_error(serde_json::json!({"max_tokens": -1, "temperature": 0.7, "tools": []}));
+    assert!(err.contains("invalid value"), "{}", err);
+}
+
+#[test]
+fn test_non_finite_temperature_is_rejected() {
+    let toml = r#"
+        providers = []
+        mcp_servers = []
+        embeddings = { model = "m", provider = "openai" }
+        agent = { max_tokens = 100, temperature = nan, tools = [] }
+    "#;
+    let err = toml::from_str::<Config>(toml).unwrap_err().to_string();
+    assert!(
+        err.contains("temperature must be between 0 and 2, got NaN"),
+        "{}",
+        err
+    );
+
+    let yaml = r#"
+        providers: []
+        mcp_servers: []
+        embeddings: {model: m, provider: openai}
+        agent: {max_tokens: 100, temperature: .inf, tools: []}
+    "#;
+    let err = serde_yaml::from_str::<Config>(yaml)
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("got inf"), "{}", err);
+
+    // Finite as an f64 but not as an f32
+    let err = agent_error(serde_json::json!({"max_tokens": 1, "temperature": 1e39, "tools": []}));
+    assert!(err.contains("got inf"), "{}", err);
+
+    let err = agent_error(serde_json::json!({"max_tokens": 1, "temperature": -0.5, "tools": []}));
+    assert!(err.contains("got -0.5"), "{}", err);
+}
+
+#[test]
+fn test_type_mismatch_is_an_error() {
+    let mut value = base();
+    value["agent"]["max_tokens"] = Value::from("4000");
+    let err = serde_json::from_value::<Config>(value)
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("expected usize"), "{}", err);
+
+    let yaml = r#"
+        providers: {openai: gpt-4}
+        mcp_servers: []
+        embeddings: {model: m, provider: openai}
+        agent: {max_tokens: 1, temperature: 0, tools: []}
+    "#;
+    assert!(serde_yaml::from_str::<Config>(yaml).is_err());
+}
+
+#[test]
+fn test_deeply_nested_input_is_an_error() {
+    let depth = 10_000;
+    let json = format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+    assert!(serde_json::from_str::<Config>(&json).is_err());
+
+    let toml = format!("providers = {}{}", "[".repeat(depth), "]".repeat(depth));
+    assert!(toml::from_str::<Config>(&toml).is_err());
+
+    let yaml = format!("providers: {}{}", "[".repeat(depth), "]".repeat(depth));
+    assert!(serde_yaml::from_str::<Config>(&yaml).is_err());
+}
+
+#[test]
+fn test_defaults_survive_every_format() {
+    let config = Config {
+        model_aliases: HashMap::from([("smart".to_string(), "openai/gpt-4o".to_string())]),
+        ..Config::default()
+    };
+    let toml: Config = toml::from_str(&toml::to_string(&config).unwrap()).unwrap();
+    let yaml: Config = serde_yaml::from_str(&serde_yaml::to_string(&config).unwrap()).unwrap();
+    assert_eq!(as_json(&toml), as_json(&config));
+    assert_eq!(as_json(&yaml), as_json(&config));
+}
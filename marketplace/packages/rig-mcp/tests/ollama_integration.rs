@@ -0,0 +1,57 @@
+//! Embeddings from a local Ollama server
+//!
+//! Needs `ollama serve` with the model pulled (`ollama pull nomic-embed-text`):
+//!
+//! ```bash
+//! cargo test --features ollama-integration --test ollama_integration
+//! ```
+//!
+//! `OLLAMA_BASE_URL` and `OLLAMA_EMBED_MODEL` point the tests at another
+//! server or model.
+
+#![cfg(feature = "ollama-integration")]
+
+use rig_mcp_integration::transport::ReqwestTransport;
+use rig_mcp_integration::wire::HttpEmbedder;
+use rig_mcp_integration::{Embeddings, ModelChangePolicy, ProviderConfig};
+use std::sync::Arc;
+
+fn embeddings() -> Embeddings {
+    let config = ProviderConfig {
+        name: "ollama".to_string(),
+        model: std::env::var("OLLAMA_EMBED_MODEL")
+            .unwrap_or_else(|_| "nomic-embed-text".to_string()),
+        base_url: std::env::var("OLLAMA_BASE_URL").ok(),
+        ..ProviderConfig::default()
+    };
+    let transport = Arc::new(ReqwestTransport::new(reqwest::Client::new()));
+    let embedder = HttpEmbedder::new(&config, transport).unwrap();
+    Embeddings::new(Arc::new(embedder), ModelChangePolicy::default())
+}
+
+fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    dot / (norm(a) * norm(b))
+}
+
+#[tokio::test]
+async fn test_local_model_embeds_and_reports_its_dimensions() {
+    let embeddings = embeddings();
+    assert_eq!(embeddings.info().dimensions, None);
+
+    let texts = [
+        "List the files in a directory",
+        "Show what a folder contains",
+        "Convert an amount between currencies",
+    ]
+    .map(String::from);
+    let vectors = embeddings.embed(&texts).await.unwrap();
+
+    assert_eq!(vectors.len(), 3);
+    let dimensions = vectors[0].len();
+    assert!(dimensions > 0);
+    assert!(vectors.iter().all(|vector| vector.len() == dimensions));
+    assert_eq!(embeddings.info().dimensions, Some(dimensions));
+    assert!(cosine(&vectors[0], &vectors[1]) > cosine(&vectors[0], &vectors[2]));
+}
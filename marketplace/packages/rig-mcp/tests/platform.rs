@@ -0,0 +1,175 @@
+//! Stdio processes and paths on unix and Windows
+//!
+//! Fixture commands run through the platform's shell, `sh -c` or `cmd /C`,
+//! so the same tests spawn, stop and kill real processes on both families.
+//! Program lookup, stop commands and root URIs don't depend on the host and
+//! are checked for both families wherever the suite runs.
+
+use rig_mcp_integration::platform::{
+    self, file_uri, resolve_program, stdio_command, stop_command, Family,
+};
+use rig_mcp_integration::{RmcpServer, ServerConfig, Transport};
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tokio::io::AsyncReadExt;
+
+const GRACE: Duration = Duration::from_millis(200);
+
+/// `unix` under `sh -c`, or `windows` under `cmd /C`
+fn shell(unix: &str, windows: &str) -> tokio::process::Command {
+    if cfg!(windows) {
+        stdio_command("cmd", &["/C".to_string(), windows.to_string()])
+    } else {
+        stdio_command("sh", &["-c".to_string(), unix.to_string()])
+    }
+}
+
+#[tokio::test]
+async fn test_fixture_spawns_with_piped_stdio() {
+    let mut child = shell("echo ready", "echo ready").spawn().unwrap();
+    let mut stdout = String::new();
+    let mut pipe = child.stdout.take().unwrap();
+    pipe.read_to_string(&mut stdout).await.unwrap();
+    assert_eq!(stdout.trim(), "ready");
+    assert!(child.wait().await.unwrap().success());
+}
+
+#[tokio::test]
+async fn test_server_exits_when_stdin_closes() {
+    let mut child = shell("cat > /dev/null", "more > NUL").spawn().unwrap();
+    drop(child.stdin.take());
+    let status = platform::terminate(&mut child, Duration::from_secs(10))
+        .await
+        .unwrap();
+    assert!(status.success(), "{}", status);
+}
+
+#[tokio::test]
+async fn test_server_ignoring_stdin_is_stopped() {
+    let mut child = shell("exec sleep 30", "ping -n 30 127.0.0.1 > NUL")
+        .spawn()
+        .unwrap();
+    drop(child.stdin.take());
+    let started = Instant::now();
+    let status = platform::terminate(&mut child, GRACE).await.unwrap();
+    assert!(!status.success());
+    assert!(started.elapsed() < Duration::from_secs(10));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_sigterm_comes_before_kill() {
+    use std::os::unix::process::ExitStatusExt;
+
+    let mut polite = shell("trap 'exit 3' TERM; sleep 30 & wait", "")
+        .spawn()
+        .unwrap();
+    let status = platform::terminate(&mut polite, GRACE).await.unwrap();
+    assert_eq!(status.code(), Some(3));
+
+    let mut stubborn = shell("trap '' TERM; exec sleep 30", "").spawn().unwrap();
+    let status = platform::terminate(&mut stubborn, GRACE).await.unwrap();
+    assert_eq!(status.signal(), Some(9));
+}
+
+#[cfg(windows)]
+#[tokio::test]
+async fn test_cmd_scripts_are_found_on_path() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("hello.cmd"), "@echo hello %1\r\n").unwrap();
+    let program = resolve_program("hello", Some(dir.path().as_os_str()), None).unwrap();
+    let output = tokio::process::Command::new(program)
+        .arg("world")
+        .output()
+        .await
+        .unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout).trim(),
+        "hello world"
+    );
+}
+
+#[test]
+fn test_programs_resolve_with_pathext() {
+    let dir = tempfile::tempdir().unwrap();
+    for file in ["npx.cmd", "node.exe", "README"] {
+        std::fs::write(dir.path().join(file), "").unwrap();
+    }
+    let path = format!(r#"C:\missing;"{}""#, dir.path().display());
+    let path = Some(OsStr::new(&path));
+    let resolve = |command: &str| resolve_program(command, path, None);
+
+    assert_eq!(resolve("npx"), Some(dir.path().join("npx.cmd")));
+    assert_eq!(resolve("node"), Some(dir.path().join("node.exe")));
+    assert_eq!(resolve("node.exe"), Some(dir.path().join("node.exe")));
+    assert_eq!(resolve("README"), None);
+    assert_eq!(resolve("python"), None);
+    // Commands with a directory are only looked for there
+    let npx = dir.path().join("npx");
+    assert_eq!(
+        resolve_program(npx.to_str().unwrap(), None, None),
+        Some(dir.path().join("npx.cmd"))
+    );
+    assert_eq!(resolve_program("npx", path, Some(OsStr::new(".PS1"))), None);
+}
+
+#[test]
+fn test_stop_commands() {
+    assert_eq!(
+        stop_command(42, false, Family::Unix),
+        Some(("kill", vec!["-TERM".to_string(), "42".to_string()]))
+    );
+    assert_eq!(stop_command(42, true, Family::Unix), None);
+    let taskkill = |args: &[&str]| {
+        Some((
+            "taskkill",
+            args.iter().map(|a| a.to_string()).collect::<Vec<_>>(),
+        ))
+    };
+    assert_eq!(
+        stop_command(42, false, Family::Windows),
+        taskkill(&["/PID", "42", "/T"])
+    );
+    assert_eq!(
+        stop_command(42, true, Family::Windows),
+        taskkill(&["/PID", "42", "/T", "/F"])
+    );
+}
+
+#[test]
+fn test_root_uris() {
+    let uri = |path: &str| file_uri(Path::new(path));
+    assert_eq!(uri("/home/ada/my project"), "file:///home/ada/my%20project");
+    assert_eq!(
+        uri(r"\\?\C:\Users\Ada\my project"),
+        "file:///C:/Users/Ada/my%20project"
+    );
+    assert_eq!(uri(r"C:\Users\Ada"), "file:///C:/Users/Ada");
+    assert_eq!(uri(r"\\?\UNC\fs\share\docs"), "file://fs/share/docs");
+    assert_eq!(uri(r"\\fs\share\docs"), "file://fs/share/docs");
+}
+
+#[tokio::test]
+async fn test_missing_stdio_server_fails_to_connect() {
+    let config = ServerConfig {
+        name: "docs".to_string(),
+        transport: Transport::Stdio {
+            command: "no-such-mcp-server".to_string(),
+            args: Vec::new(),
+            env: HashMap::new(),
+        },
+        roots: None,
+        embeddings: None,
+    };
+    let err = RmcpServer::connect(&config).await.err().unwrap();
+    let message = format!("{:#}", err);
+    assert!(
+        message.starts_with(
+            "Failed to connect to MCP server 'docs': Failed to start `no-such-mcp-server`"
+        ),
+        "{}",
+        message
+    );
+}
@@ -0,0 +1,287 @@
+//! Resilience under injected faults
+//!
+//! Providers, MCP servers and embedding models fail on a [`FaultPlan`]:
+//! scripted where a test needs an exact sequence, seeded chaos where it
+//! needs volume. The client must fall over to embedding fallbacks on
+//! retriable errors only, give up on hung tools, keep working once a
+//! dropped connection comes back, and report all of it in health checks.
+//! Every plan is seeded, so a failure replays exactly.
+
+use rig_mcp_integration::testing::{
+    FakeMcpServer, Fault, FaultEvent, FaultPlan, Latency, MockEmbeddingModel, MockProvider,
+};
+use rig_mcp_integration::transport::is_retriable;
+use rig_mcp_integration::{
+    AgentBuilder, AgentConfig, ChatMessage, ChatRequest, Config, Embeddings, HealthReport,
+    HealthStatus, ModelChangePolicy, NormalizedResponse, Provider, RigMcpClient,
+    ToolInvocationError,
+};
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+
+const TOOL_TIMEOUT: Duration = Duration::from_millis(50);
+
+/// Retriable failures on about a third of the calls, and a few milliseconds
+/// of jitter on every call
+fn chaos(seed: u64) -> FaultPlan {
+    FaultPlan::seeded(seed)
+        .with_chance(Fault::FailWithStatus(503), 0.2)
+        .with_chance(Fault::FailWithStatus(429), 0.05)
+        .with_chance(Fault::Disconnect, 0.05)
+        .with_chance(Fault::TimeoutAfter(Duration::from_millis(2)), 0.05)
+        .latency(Latency::Uniform {
+            min: Duration::ZERO,
+            max: Duration::from_millis(3),
+        })
+}
+
+fn echo_server(plan: FaultPlan) -> Arc<FakeMcpServer> {
+    Arc::new(
+        FakeMcpServer::new("demo")
+            .with_tool(
+                "echo",
+                "Echo the given text",
+                json!({"type": "object", "properties": {"text": {"type": "string"}}}),
+                |args| Ok(args["text"].as_str().unwrap_or_default().to_string()),
+            )
+            .with_faults(plan),
+    )
+}
+
+fn outcomes(timeline: &[FaultEvent]) -> Vec<(Fault, Duration)> {
+    timeline
+        .iter()
+        .map(|event| (event.fault.clone(), event.latency))
+        .collect()
+}
+
+fn failures(timeline: &[FaultEvent]) -> usize {
+    timeline
+        .iter()
+        .filter(|event| event.fault.is_failure())
+        .count()
+}
+
+fn ping() -> ChatRequest {
+    ChatRequest {
+        messages: vec![ChatMessage::user("ping")],
+        ..ChatRequest::default()
+    }
+}
+
+#[tokio::test]
+async fn seeded_chaos_replays_exactly() {
+    async fn run(seed: u64) -> Vec<(Fault, Duration)> {
+        let provider = MockProvider::new("mock").with_faults(chaos(seed));
+        for _ in 0..40 {
+            let _ = provider.complete(ping()).await;
+        }
+        outcomes(&provider.timeline())
+    }
+
+    let first = run(7).await;
+    assert_eq!(first.len(), 40);
+    assert_eq!(first, run(7).await);
+    assert_ne!(first, run(8).await);
+}
+
+#[tokio::test]
+async fn provider_faults_fail_like_real_providers() {
+    let provider = Arc::new(MockProvider::new("mock").with_faults(FaultPlan::script([
+        Fault::FailWithStatus(503),
+        Fault::Disconnect,
+        Fault::TimeoutAfter(Duration::from_millis(5)),
+        Fault::CorruptJson,
+        Fault::FailWithStatus(400),
+    ])));
+    provider.push_response(NormalizedResponse::text("pong"));
+    let client = RigMcpClient::from_parts(Config::default(), vec![provider.clone()], vec![]);
+
+    let mut retriable = Vec::new();
+    for _ in 0..5 {
+        let err = client.complete("mock", ping()).await.unwrap_err();
+        retriable.push(is_retriable(&err));
+    }
+    assert_eq!(retriable, [true, true, true, false, false]);
+
+    // Failed calls leave the queued response for the first one that succeeds
+    let response = client.complete("mock", ping()).await.unwrap();
+    assert_eq!(response.content, "pong");
+    assert_eq!(provider.requests().len(), 6);
+    assert_eq!(failures(&provider.timeline()), 5);
+}
+
+#[tokio::test]
+async fn embedding_fallback_rides_out_a_chaotic_primary() {
+    // Two guaranteed outages, then chaos
+    let primary = Arc::new(
+        MockEmbeddingModel::new("primary", 16).with_faults(
+            chaos(42)
+                .repeat(Fault::FailWithStatus(503), 2)
+                .latency(Latency::None),
+        ),
+    );
+    let secondary = Arc::new(MockEmbeddingModel::new("secondary", 16));
+    let embeddings = Embeddings::new(primary.clone(), ModelChangePolicy::Error)
+        .with_fallback(secondary.clone(), None);
+
+    for i in 0..30 {
+        let vectors = embeddings
+            .embed(&[format!("tool number {}", i)])
+            .await
+            .unwrap();
+        assert_eq!(vectors[0].len(), 16);
+        let served_by_fallback = primary.timeline()[i].fault.is_failure();
+        assert_eq!(
+            embeddings.is_failed_over(),
+            served_by_fallback,
+            "call {}",
+            i
+        );
+    }
+
+    let timeline = primary.timeline();
+    assert_eq!(timeline.len(), 30);
+    assert!(failures(&timeline) >= 2);
+    // Every call the primary dropped was served by the fallback, no others
+    assert_eq!(secondary.embedded(), failures(&timeline));
+    assert_eq!(primary.embedded() + secondary.embedded(), 30);
+}
+
+#[tokio::test]
+async fn corrupt_responses_are_not_failed_over() {
+    let primary = Arc::new(
+        MockEmbeddingModel::new("primary", 8).with_faults(FaultPlan::script([Fault::CorruptJson])),
+    );
+    let secondary = Arc::new(MockEmbeddingModel::new("secondary", 8));
+    let embeddings = Embeddings::new(primary.clone(), ModelChangePolicy::Error)
+        .with_fallback(secondary.clone(), None);
+
+    let err = embeddings
+        .embed(&["read_file".to_string()])
+        .await
+        .unwrap_err();
+    assert!(format!("{:#}", err).contains("invalid JSON"), "{:#}", err);
+    assert_eq!(secondary.embedded(), 0);
+
+    embeddings.embed(&["read_file".to_string()]).await.unwrap();
+    assert_eq!(primary.embedded(), 1);
+}
+
+#[tokio::test]
+async fn tool_calls_recover_after_hangs_and_dropped_connections() {
+    let server = echo_server(FaultPlan::script([
+        Fault::Hang,
+        Fault::Disconnect,
+        Fault::TimeoutAfter(Duration::from_millis(5)),
+    ]));
+    let config = Config {
+        agent: AgentConfig {
+            tool_timeout: Some(TOOL_TIMEOUT),
+            ..AgentConfig::default()
+        },
+        ..Config::default()
+    };
+    let client = RigMcpClient::from_parts(config, vec![], vec![server.clone()]);
+    let call = || client.invoke_tool("demo", "echo", json!({"text": "ping"}), false);
+
+    let err = call().await.unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<ToolInvocationError>(),
+        Some(&ToolInvocationError::TimedOut {
+            tool: "echo".to_string(),
+            after: TOOL_TIMEOUT,
+        })
+    );
+    assert!(is_retriable(&call().await.unwrap_err()));
+    assert!(is_retriable(&call().await.unwrap_err()));
+    assert_eq!(call().await.unwrap().content, "ping");
+
+    let faults: Vec<Fault> = server.timeline().into_iter().map(|e| e.fault).collect();
+    assert_eq!(
+        faults,
+        [
+            Fault::Hang,
+            Fault::Disconnect,
+            Fault::TimeoutAfter(Duration::from_millis(5)),
+            Fault::Succeed,
+        ]
+    );
+    assert_eq!(server.calls().len(), 4);
+}
+
+#[tokio::test]
+async fn agent_runs_through_tool_chaos() {
+    let provider = Arc::new(MockProvider::new("mock"));
+    for _ in 0..3 {
+        provider.push_tool_call("echo", json!({"text": "ping"}));
+    }
+    provider.push_response(NormalizedResponse::text("done"));
+    let server = echo_server(FaultPlan::script([Fault::Hang, Fault::Disconnect]));
+    let agent = AgentBuilder::new(provider.clone())
+        .tool_server(server.clone())
+        .tool_timeout(TOOL_TIMEOUT)
+        .build();
+
+    let mut history = Vec::new();
+    let answer = agent.chat(&mut history, "echo ping").await.unwrap();
+    assert_eq!(answer, "done");
+    assert_eq!(history[2].content, "Tool 'echo' timed out after 50ms");
+    assert!(
+        history[4].content.contains("could not be reached"),
+        "{}",
+        history[4].content
+    );
+    assert_eq!(history[6].content, "ping");
+    // The model saw every failure and carried on
+    assert_eq!(provider.requests().len(), 4);
+    assert_eq!(server.timeline().len(), 3);
+}
+
+#[tokio::test]
+async fn health_checks_follow_outages_and_recovery() {
+    let provider = Arc::new(
+        MockProvider::new("mock")
+            .with_faults(FaultPlan::script([Fault::Hang, Fault::FailWithStatus(503)])),
+    );
+    let primary = Arc::new(
+        MockEmbeddingModel::new("primary", 8)
+            .with_faults(FaultPlan::script([Fault::Disconnect, Fault::Disconnect])),
+    );
+    let secondary = Arc::new(MockEmbeddingModel::new("secondary", 8));
+    let embeddings =
+        Embeddings::new(primary, ModelChangePolicy::Error).with_fallback(secondary, None);
+    let client = RigMcpClient::from_parts(
+        Config::default(),
+        vec![provider],
+        vec![echo_server(FaultPlan::new())],
+    )
+    .with_embeddings(embeddings);
+    let registry = client.health_checks().await.timeout(TOOL_TIMEOUT);
+
+    let statuses = |report: &HealthReport| -> Vec<HealthStatus> {
+        report.components.iter().map(|c| c.status).collect()
+    };
+
+    let report = registry.check_all().await;
+    assert_eq!(report.status, HealthStatus::Down);
+    // provider:mock, mcp:demo, embeddings:primary
+    assert_eq!(
+        statuses(&report),
+        [HealthStatus::Down, HealthStatus::Up, HealthStatus::Degraded]
+    );
+    let detail = report.components[0].detail.as_deref().unwrap();
+    assert!(detail.contains("timed out"), "{}", detail);
+
+    let report = registry.check_all().await;
+    assert_eq!(
+        statuses(&report),
+        [HealthStatus::Down, HealthStatus::Up, HealthStatus::Degraded]
+    );
+    let detail = report.components[0].detail.as_deref().unwrap();
+    assert!(detail.contains("HTTP 503"), "{}", detail);
+
+    let report = registry.check_all().await;
+    assert_eq!(report.status, HealthStatus::Up);
+}
@@ -0,0 +1,275 @@
+//! MCP over streamable HTTP against an in-process axum server
+//!
+//! The fake speaks just enough of the transport: it assigns a session id
+//! while initializing, answers 404 for unknown sessions, lists tools as
+//! JSON, streams tool results as server-sent events, and replays the result
+//! of a cut stream to a `GET` with `Last-Event-ID`.
+
+use axum::body::Body;
+use axum::extract::State;
+use axum::http::header::CONTENT_TYPE;
+use axum::http::{HeaderMap, HeaderValue, Method, StatusCode};
+use axum::response::{IntoResponse, Response};
+use rig_mcp_integration::{RmcpServer, ServerConfig, ToolServer, Transport};
+use serde_json::{json, Value};
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+
+const VERSION: &str = "2025-03-26";
+
+#[derive(Default)]
+struct Fake {
+    state: Mutex<FakeState>,
+}
+
+#[derive(Default)]
+struct FakeState {
+    initializes: usize,
+    /// Sessions the server still knows
+    sessions: Vec<String>,
+    /// Method, session id and protocol version of every POST but
+    /// `initialize`
+    requests: Vec<(String, Option<String>, Option<String>)>,
+    /// Event id and result of a call whose stream was cut
+    interrupted: Option<(String, Value)>,
+    resumed_after: Vec<String>,
+}
+
+impl Fake {
+    fn expire_sessions(&self) {
+        self.state.lock().unwrap().sessions.clear();
+    }
+
+    fn requests(&self) -> Vec<(String, Option<String>)> {
+        self.state
+            .lock()
+            .unwrap()
+            .requests
+            .iter()
+            .map(|(method, session, _)| (method.clone(), session.clone()))
+            .collect()
+    }
+}
+
+fn json_response(message: Value) -> Response {
+    ([(CONTENT_TYPE, "application/json")], message.to_string()).into_response()
+}
+
+/// Events sent as separate chunks, the last one split in two
+fn event_stream(events: Vec<(&str, Value)>) -> Response {
+    let mut chunks: Vec<String> = events
+        .into_iter()
+        .map(|(id, message)| format!("id: {}\nevent: message\ndata: {}\n\n", id, message))
+        .collect();
+    if let Some(last) = chunks.pop() {
+        let (head, tail) = last.split_at(last.len() / 2);
+        chunks.extend([head.to_string(), tail.to_string()]);
+    }
+    let body = Body::from_stream(futures::stream::iter(
+        chunks.into_iter().map(Ok::<_, Infallible>),
+    ));
+    ([(CONTENT_TYPE, "text/event-stream")], body).into_response()
+}
+
+fn log_message(text: &str) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "notifications/message",
+        "params": { "level": "info", "data": text },
+    })
+}
+
+async fn handle(
+    State(fake): State<Arc<Fake>>, method: Method, headers: HeaderMap, body: String,
+) -> Response {
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string)
+    };
+    let session = header("mcp-session-id");
+    let mut state = fake.state.lock().unwrap();
+    match method {
+        Method::DELETE => return StatusCode::OK.into_response(),
+        Method::GET => {
+            let last = header("last-event-id");
+            return match (last, state.interrupted.take()) {
+                (Some(last), Some((id, result))) if last == id => {
+                    state.resumed_after.push(last);
+                    event_stream(vec![("cut-2", result)])
+                }
+                _ => StatusCode::METHOD_NOT_ALLOWED.into_response(),
+            };
+        }
+        _ => {}
+    }
+
+    let message: Value = serde_json::from_str(&body).unwrap();
+    let id = message["id"].clone();
+    let name = message["method"].as_str().unwrap_or_default().to_string();
+    if name == "initialize" {
+        state.initializes += 1;
+        let session = format!("session-{}", state.initializes);
+        state.sessions.push(session.clone());
+        let mut response = json_response(json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "result": {
+                "protocolVersion": VERSION,
+                "capabilities": { "tools": {} },
+                "serverInfo": { "name": "fake", "version": "1.0.0" },
+            },
+        }));
+        response
+            .headers_mut()
+            .insert("mcp-session-id", HeaderValue::from_str(&session).unwrap());
+        return response;
+    }
+
+    state.requests.push((
+        name.clone(),
+        session.clone(),
+        header("mcp-protocol-version"),
+    ));
+    if !session.is_some_and(|session| state.sessions.contains(&session)) {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    let result = |result: Value| json!({ "jsonrpc": "2.0", "id": id, "result": result });
+    match name.as_str() {
+        "tools/list" => {
+            let tool = |name: &str| {
+                json!({
+                    "name": name,
+                    "description": "Counts",
+                    "inputSchema": { "type": "object" },
+                })
+            };
+            json_response(result(json!({ "tools": [tool("count"), tool("cut")] })))
+        }
+        "tools/call" => {
+            let tool = message["params"]["name"].as_str().unwrap_or_default();
+            let done = result(json!({
+                "content": [{ "type": "text", "text": format!("{} done", tool) }],
+                "isError": false,
+            }));
+            if tool == "cut" {
+                state.interrupted = Some(("cut-1".to_string(), done));
+                return event_stream(vec![("cut-1", log_message("cutting"))]);
+            }
+            event_stream(vec![
+                ("count-1", log_message("counting")),
+                ("count-2", done),
+            ])
+        }
+        _ if id.is_null() => StatusCode::ACCEPTED.into_response(),
+        _ => json_response(json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": { "code": -32601, "message": "Method not found" },
+        })),
+    }
+}
+
+async fn start(fake: Arc<Fake>) -> String {
+    let app = axum::Router::new()
+        .route("/mcp", axum::routing::any(handle))
+        .with_state(fake);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/mcp", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    url
+}
+
+async fn connect(fake: &Arc<Fake>, reinitialize: bool) -> RmcpServer {
+    let config = ServerConfig {
+        name: "fake".to_string(),
+        transport: Transport::Http {
+            url: start(fake.clone()).await,
+            bearer_token: None,
+            reinitialize,
+        },
+        roots: None,
+        embeddings: None,
+    };
+    RmcpServer::connect(&config).await.unwrap()
+}
+
+fn seen(method: &str, session: &str) -> (String, Option<String>) {
+    (method.to_string(), Some(session.to_string()))
+}
+
+#[tokio::test]
+async fn test_session_is_reused() {
+    let fake = Arc::new(Fake::default());
+    let server = connect(&fake, true).await;
+    assert_eq!(server.protocol_version().as_deref(), Some(VERSION));
+
+    server.list_tools().await.unwrap();
+    let tools = server.list_tools().await.unwrap();
+    assert_eq!(tools.len(), 2);
+
+    assert_eq!(
+        fake.requests(),
+        [
+            seen("notifications/initialized", "session-1"),
+            seen("tools/list", "session-1"),
+            seen("tools/list", "session-1"),
+        ]
+    );
+    let state = fake.state.lock().unwrap();
+    assert_eq!(state.initializes, 1);
+    assert!(state
+        .requests
+        .iter()
+        .all(|(_, _, version)| version.as_deref() == Some(VERSION)));
+}
+
+#[tokio::test]
+async fn test_expired_session_is_reinitialized() {
+    let fake = Arc::new(Fake::default());
+    let server = connect(&fake, true).await;
+    server.list_tools().await.unwrap();
+
+    fake.expire_sessions();
+    let tools = server.list_tools().await.unwrap();
+    assert_eq!(tools.len(), 2);
+
+    assert_eq!(
+        fake.requests(),
+        [
+            seen("notifications/initialized", "session-1"),
+            seen("tools/list", "session-1"),
+            seen("tools/list", "session-1"),
+            seen("notifications/initialized", "session-2"),
+            seen("tools/list", "session-2"),
+        ]
+    );
+    assert_eq!(fake.state.lock().unwrap().initializes, 2);
+}
+
+#[tokio::test]
+async fn test_expired_session_fails_without_reinitialize() {
+    let fake = Arc::new(Fake::default());
+    let server = connect(&fake, false).await;
+
+    fake.expire_sessions();
+    let err = server.list_tools().await.unwrap_err();
+    assert!(format!("{:#}", err).contains("has expired"), "{:#}", err);
+    assert_eq!(fake.state.lock().unwrap().initializes, 1);
+}
+
+#[tokio::test]
+async fn test_tool_result_streams_and_resumes() {
+    let fake = Arc::new(Fake::default());
+    let server = connect(&fake, true).await;
+
+    let output = server.call_tool("count", json!({})).await.unwrap();
+    assert_eq!(output.content, "count done");
+    assert!(!output.is_error);
+
+    // The stream ends after one event; the result comes from resuming it
+    let output = server.call_tool("cut", json!({})).await.unwrap();
+    assert_eq!(output.content, "cut done");
+    assert_eq!(fake.state.lock().unwrap().resumed_after, ["cut-1"]);
+}
@@ -0,0 +1,255 @@
+//! Wire-format conformance of provider requests
+//!
+//! Every supported provider encodes the same canonical scenarios through the
+//! client, and the requests that reach the transport are compared with
+//! snapshots in `tests/snapshots`. A change to a provider's wire format shows
+//! up as a snapshot diff; review it with `cargo insta review`. A provider
+//! added to [`PROVIDERS`] fails here until its snapshot is accepted.
+//!
+//! Reasoning settings go to a model of the provider that reasons; providers
+//! without one show them dropped. Bedrock's signatures change with the time
+//! of the request, so only their scope is compared.
+
+use futures::StreamExt;
+use insta::internals::{Content, ContentPath};
+use regex::Regex;
+use rig_mcp_integration::provider::{ToolCall, ToolDefinition};
+use rig_mcp_integration::testing::MockTransport;
+use rig_mcp_integration::transport::HttpRequest;
+use rig_mcp_integration::wire::PROVIDERS;
+use rig_mcp_integration::{
+    BedrockOptions, ChatMessage, ChatRequest, Config, HttpProvider, Provider, ProviderConfig,
+    ReasoningConfig, ReasoningEffort, RigMcpClient, VertexOptions,
+};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+fn provider_config(name: &str, streaming: bool) -> ProviderConfig {
+    let (model, api_key) = match name {
+        "openai" => ("gpt-4o", Some("test-key")),
+        "deepseek" => ("deepseek-chat", Some("test-key")),
+        "azure" => ("gpt-4o", Some("test-key")),
+        "groq" => ("llama-3.3-70b-versatile", Some("test-key")),
+        "openai-compatible" => ("Qwen/Qwen2.5-7B-Instruct", None),
+        "anthropic" => ("claude-3-5-sonnet-latest", Some("test-key")),
+        "gemini" => ("gemini-1.5-pro", Some("test-key")),
+        "mistral" => ("mistral-large-latest", Some("test-key")),
+        "ollama" => ("llama3.1", None),
+        "cohere" => ("command-r-plus", Some("test-key")),
+        "bedrock" => ("claude-3-5-sonnet", None),
+        "vertex" => ("gemini-1.5-pro", Some("test-key")),
+        other => panic!("No conformance model for provider '{}'", other),
+    };
+    ProviderConfig {
+        name: name.to_string(),
+        model: model.to_string(),
+        api_key: api_key.map(String::from),
+        // Azure and self-hosted servers have no common endpoint
+        base_url: match name {
+            "azure" => Some("https://contoso.openai.azure.com".to_string()),
+            "openai-compatible" => Some("http://localhost:8000".to_string()),
+            _ => None,
+        },
+        streaming,
+        bedrock: (name == "bedrock").then(|| BedrockOptions {
+            region: Some("us-east-1".to_string()),
+            profile: None,
+        }),
+        vertex: (name == "vertex").then(|| VertexOptions {
+            project: Some("my-project".to_string()),
+            location: Some("us-central1".to_string()),
+            credentials: None,
+        }),
+        ..ProviderConfig::default()
+    }
+}
+
+/// A model of `provider` that reasons, if it has one
+fn reasoning_model(provider: &str) -> Option<&'static str> {
+    match provider {
+        "openai" | "azure" => Some("o3-mini"),
+        "anthropic" => Some("claude-3-7-sonnet-latest"),
+        "gemini" | "vertex" => Some("gemini-2.5-pro"),
+        "bedrock" => Some("claude-3-7-sonnet"),
+        _ => None,
+    }
+}
+
+fn question() -> ChatMessage {
+    ChatMessage::user("What is the capital of France?")
+}
+
+fn plain() -> ChatRequest {
+    ChatRequest {
+        messages: vec![question()],
+        max_tokens: Some(256),
+        temperature: Some(0.5),
+        seed: Some(7),
+        ..ChatRequest::default()
+    }
+}
+
+fn word_count() -> ToolDefinition {
+    ToolDefinition {
+        name: "word_count".to_string(),
+        description: "Count the words in a text".to_string(),
+        parameters: json!({
+            "type": "object",
+            "properties": {"text": {"type": "string"}},
+            "required": ["text"]
+        }),
+    }
+}
+
+/// Requests sent with `complete`, by scenario
+fn scenarios() -> Vec<(&'static str, ChatRequest)> {
+    let count = ChatMessage::user("How many words are in 'the quick brown fox'?");
+    vec![
+        ("plain", plain()),
+        (
+            "system_prompt",
+            ChatRequest {
+                system: Some("Answer in one word.".to_string()),
+                messages: vec![
+                    ChatMessage::system("Use British spelling."),
+                    ChatMessage::user("What colour is the sky?"),
+                ],
+                ..ChatRequest::default()
+            },
+        ),
+        (
+            "tool_definitions",
+            ChatRequest {
+                messages: vec![count.clone()],
+                tools: vec![word_count()],
+                ..ChatRequest::default()
+            },
+        ),
+        (
+            "tool_results",
+            ChatRequest {
+                messages: vec![
+                    count,
+                    ChatMessage {
+                        tool_calls: vec![ToolCall {
+                            id: "call_1".to_string(),
+                            name: "word_count".to_string(),
+                            arguments: json!({"text": "the quick brown fox"}),
+                        }],
+                        ..ChatMessage::assistant("I'll count the words.")
+                    },
+                    ChatMessage::tool("call_1", "4"),
+                ],
+                tools: vec![word_count()],
+                ..ChatRequest::default()
+            },
+        ),
+    ]
+}
+
+/// A SigV4 `authorization` header with its date and signature redacted;
+/// other schemes as they are
+fn redact_signature(value: Content, _: ContentPath<'_>) -> Content {
+    let header = value.as_str().unwrap_or_default().to_string();
+    if !header.starts_with("AWS4-HMAC-SHA256 ") {
+        return value;
+    }
+    let header = Regex::new(r"/\d{8}/").unwrap().replace(&header, "/[date]/");
+    let header = Regex::new(r"Signature=[0-9a-f]{64}")
+        .unwrap()
+        .replace(&header, "Signature=[signature]");
+    header.into_owned().into()
+}
+
+/// `request` as JSON, with headers by name and the body parsed
+fn captured(request: &HttpRequest) -> Value {
+    let headers: BTreeMap<&str, &str> = request
+        .headers
+        .iter()
+        .map(|(name, value)| (name.as_str(), value.as_str()))
+        .collect();
+    json!({
+        "method": request.method.as_str(),
+        "url": request.url,
+        "headers": headers,
+        "body": serde_json::from_slice::<Value>(&request.body).unwrap(),
+    })
+}
+
+/// The only request `transport` received
+fn sent(transport: &MockTransport) -> HttpRequest {
+    let mut requests = transport.requests();
+    assert_eq!(requests.len(), 1, "{:?}", requests);
+    requests.remove(0)
+}
+
+/// Every scenario as `provider` sends it; nothing answers, so every call
+/// fails after the request is captured
+async fn conversation(provider: &str) -> Vec<Value> {
+    let mut out = Vec::new();
+    for (scenario, request) in scenarios() {
+        let transport = Arc::new(MockTransport::new());
+        let config = Config {
+            providers: vec![provider_config(provider, false)],
+            ..Config::default()
+        };
+        let client = RigMcpClient::with_transport(config, transport.clone())
+            .await
+            .unwrap();
+        assert!(client.complete(provider, request).await.is_err());
+        out.push(json!({ "scenario": scenario, "request": captured(&sent(&transport)) }));
+    }
+
+    let mut config = provider_config(provider, false);
+    if let Some(model) = reasoning_model(provider) {
+        config.model = model.to_string();
+    }
+    let transport = Arc::new(MockTransport::new());
+    let reasoning = HttpProvider::new(&config, transport.clone()).unwrap();
+    let request = ChatRequest {
+        reasoning: Some(ReasoningConfig {
+            effort: ReasoningEffort::High,
+            max_thinking_tokens: None,
+        }),
+        ..plain()
+    };
+    assert!(reasoning.complete(request).await.is_err());
+    out.push(json!({ "scenario": "reasoning", "request": captured(&sent(&transport)) }));
+
+    // Providers without native streaming send a plain request
+    let transport = Arc::new(MockTransport::new());
+    let streaming = HttpProvider::new(&provider_config(provider, true), transport.clone()).unwrap();
+    let request = ChatRequest {
+        messages: vec![question()],
+        ..ChatRequest::default()
+    };
+    if let Ok(stream) = streaming.stream(request).await {
+        let chunks: Vec<_> = stream.collect().await;
+        assert!(chunks.iter().any(Result::is_err));
+    }
+    out.push(json!({ "scenario": "streaming", "request": captured(&sent(&transport)) }));
+    out
+}
+
+#[tokio::test]
+async fn provider_requests_match_snapshots() {
+    let mut settings = insta::Settings::clone_current();
+    settings.set_sort_maps(true);
+    let _settings = settings.bind_to_scope();
+    // Bedrock signs with the first credentials of the AWS chain
+    std::env::set_var("AWS_ACCESS_KEY_ID", "AKIDEXAMPLE");
+    std::env::set_var(
+        "AWS_SECRET_ACCESS_KEY",
+        "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+    );
+    std::env::remove_var("AWS_SESSION_TOKEN");
+    for provider in PROVIDERS {
+        let requests = conversation(provider).await;
+        insta::assert_json_snapshot!(*provider, requests, {
+            "[].request.headers[\"x-request-id\"]" => "[request-id]",
+            "[].request.headers[\"x-amz-date\"]" => "[date]",
+            "[].request.headers.authorization" => insta::dynamic_redaction(redact_signature)
+        });
+    }
+}